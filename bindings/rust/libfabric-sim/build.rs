@@ -0,0 +1,19 @@
+use std::env;
+
+fn main() {
+    // Forward the `libfabric_ge_{major}_{minor}` cfgs detected by ofi-libfabric-sys (see its
+    // build.rs), such that the provider reads the attributes of the headers built against.
+    let list = |key: &str| env::var(key).unwrap_or_default();
+    for cfg in list("DEP_LIBFABRIC_CHECK_CFGS")
+        .split(',')
+        .filter(|c| !c.is_empty())
+    {
+        println!("cargo:rustc-check-cfg=cfg({cfg})");
+    }
+    for cfg in list("DEP_LIBFABRIC_CFGS")
+        .split(',')
+        .filter(|c| !c.is_empty())
+    {
+        println!("cargo:rustc-cfg={cfg}");
+    }
+}
@@ -0,0 +1,235 @@
+// Address vectors of the provider: tables of the names of endpoints, which the network
+// resolves, and which the endpoints bound to them translate the addresses of operations with.
+
+use crate::{NETWORK, enosys};
+use libfabric::{Addr, Av, EndpointAddress, Error, Result};
+use ofi_libfabric_sys::bindgen as ffi;
+use std::collections::HashMap;
+use std::ffi::{c_char, c_int, c_void};
+use std::slice;
+use std::sync::{Arc, LazyLock, Mutex};
+
+// The length of the names of endpoints, their index in the network.
+pub(crate) const ADDR_LEN: usize = 8;
+
+#[repr(C)]
+pub(crate) struct SimAv {
+    fid: ffi::fid_av,
+    pub(crate) table: Arc<Table>,
+}
+
+// The endpoints inserted, at their index in the table, which removals leave empty.
+#[derive(Default)]
+pub(crate) struct Table {
+    entries: Mutex<Entries>,
+}
+
+#[derive(Default)]
+struct Entries {
+    addrs: Vec<Option<Addr>>,
+    // The first index each endpoint was inserted at, reported as the source of completions.
+    indices: HashMap<Addr, u64>,
+}
+
+impl Table {
+    fn insert(&self, addr: Addr) -> u64 {
+        let mut entries = self.entries.lock().unwrap();
+        let index = entries.addrs.len() as u64;
+        entries.addrs.push(Some(addr));
+        entries.indices.entry(addr).or_insert(index);
+        index
+    }
+
+    fn remove(&self, index: u64) -> bool {
+        let mut entries = self.entries.lock().unwrap();
+        let Some(addr) = entries.addrs.get_mut(index as usize).and_then(Option::take) else {
+            return false;
+        };
+        if entries.indices.get(&addr) == Some(&index) {
+            entries.indices.remove(&addr);
+            // Any other index the endpoint is still inserted at takes over.
+            if let Some(other) = entries.addrs.iter().position(|a| *a == Some(addr)) {
+                entries.indices.insert(addr, other as u64);
+            }
+        }
+        true
+    }
+
+    // The endpoint inserted at `index`, in the network.
+    fn get(&self, index: u64) -> Option<Addr> {
+        let entries = self.entries.lock().unwrap();
+        entries.addrs.get(index as usize).copied().flatten()
+    }
+
+    // The endpoint `fi_addr` stands for, `Addr::UNSPEC` standing for any.
+    pub(crate) fn resolve(&self, op: &'static str, fi_addr: ffi::fi_addr_t) -> Result<Addr> {
+        if fi_addr == ffi::FI_ADDR_UNSPEC {
+            return Ok(Addr::UNSPEC);
+        }
+        self.get(fi_addr).ok_or(Error::Fabric {
+            op,
+            code: ffi::FI_EADDRNOTAVAIL as i32,
+        })
+    }
+
+    // The index of the endpoint at `addr` in the network, `FI_ADDR_NOTAVAIL` if it was not
+    // inserted.
+    pub(crate) fn source(&self, addr: Addr) -> ffi::fi_addr_t {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .indices
+            .get(&addr)
+            .copied()
+            .unwrap_or(ffi::FI_ADDR_NOTAVAIL)
+    }
+}
+
+pub(crate) static FID_OPS: LazyLock<ffi::fi_ops> =
+    LazyLock::new(|| crate::fid_ops(crate::close::<SimAv>, crate::bind, crate::control));
+
+static OPS: LazyLock<ffi::fi_ops_av> = LazyLock::new(|| ffi::fi_ops_av {
+    size: size_of::<ffi::fi_ops_av>(),
+    insert: Some(insert),
+    insertsvc: Some(insertsvc),
+    insertsym: Some(insertsym),
+    remove: Some(remove),
+    lookup: Some(lookup),
+    straddr: Some(straddr),
+    ..Default::default()
+});
+
+// Open an address vector, of the table type whichever is asked for: its addresses are valid
+// as those of a map too. Named, shared vectors and insertion events are not supported.
+pub(crate) unsafe extern "C" fn open(
+    _domain: *mut ffi::fid_domain,
+    attr: *mut ffi::fi_av_attr,
+    av: *mut *mut ffi::fid_av,
+    context: *mut c_void,
+) -> c_int {
+    if let Some(attr) = unsafe { attr.as_mut() } {
+        if !attr.name.is_null() || attr.flags & ffi::FI_EVENT as u64 != 0 {
+            return -(ffi::FI_ENOSYS as c_int);
+        }
+        if unsafe { crate::raw_enum(&attr.type_) } == ffi::fi_av_type::FI_AV_UNSPEC as u32 {
+            attr.type_ = ffi::fi_av_type::FI_AV_TABLE;
+        }
+    }
+    let object = crate::open(SimAv {
+        fid: ffi::fid_av {
+            fid: crate::fid(ffi::FI_CLASS_AV, context, &FID_OPS),
+            ops: crate::table(&OPS),
+        },
+        table: Arc::default(),
+    });
+    unsafe { *av = object.cast() };
+    0
+}
+
+// Insert `count` names, writing their index to `fi_addr`, and with `FI_SYNC_ERR` the status
+// of each to the array of `context`.
+unsafe extern "C" fn insert(
+    av: *mut ffi::fid_av,
+    addr: *const c_void,
+    count: usize,
+    fi_addr: *mut ffi::fi_addr_t,
+    flags: u64,
+    context: *mut c_void,
+) -> c_int {
+    if flags & !(ffi::FI_SYNC_ERR | ffi::FI_MORE as u64) != 0 {
+        return -(ffi::FI_EBADFLAGS as c_int);
+    }
+    if count == 0 {
+        return 0;
+    }
+    let table = unsafe { &(*av.cast::<SimAv>()).table };
+    let names = unsafe { slice::from_raw_parts(addr.cast::<u8>(), count * ADDR_LEN) };
+    let network = NETWORK.av();
+    let mut inserted = 0;
+    for (i, name) in names.chunks(ADDR_LEN).enumerate() {
+        let (index, err) = match network.insert(&EndpointAddress::from_bytes(name)) {
+            Ok(addr) => {
+                inserted += 1;
+                (table.insert(addr), 0)
+            }
+            Err(err) => (ffi::FI_ADDR_NOTAVAIL, err.code()),
+        };
+        if !fi_addr.is_null() {
+            unsafe { *fi_addr.add(i) = index };
+        }
+        if flags & ffi::FI_SYNC_ERR != 0 {
+            unsafe { *context.cast::<c_int>().add(i) = err };
+        }
+    }
+    inserted
+}
+
+unsafe extern "C" fn remove(
+    av: *mut ffi::fid_av,
+    fi_addr: *mut ffi::fi_addr_t,
+    count: usize,
+    _flags: u64,
+) -> c_int {
+    if count == 0 {
+        return 0;
+    }
+    let table = unsafe { &(*av.cast::<SimAv>()).table };
+    let indices = unsafe { slice::from_raw_parts(fi_addr, count) };
+    // Each index is removed, even once others are found missing.
+    let missing = indices
+        .iter()
+        .filter(|&&index| !table.remove(index))
+        .count();
+    match missing {
+        0 => 0,
+        _ => -(ffi::FI_EINVAL as c_int),
+    }
+}
+
+// The name inserted at `fi_addr`, truncated to the `addrlen` bytes of `addr`, which is set
+// to the full length.
+unsafe extern "C" fn lookup(
+    av: *mut ffi::fid_av,
+    fi_addr: ffi::fi_addr_t,
+    addr: *mut c_void,
+    addrlen: *mut usize,
+) -> c_int {
+    let table = unsafe { &(*av.cast::<SimAv>()).table };
+    let Some(found) = table.get(fi_addr) else {
+        return -(ffi::FI_EINVAL as c_int);
+    };
+    let name = found.as_raw().to_le_bytes();
+    unsafe {
+        let len = (*addrlen).min(ADDR_LEN);
+        addr.cast::<u8>()
+            .copy_from_nonoverlapping(name.as_ptr(), len);
+        *addrlen = ADDR_LEN;
+    }
+    0
+}
+
+// Format a name as `fi_sim://<index>`, truncated to the `len` bytes of `buf`, which is set to
+// the full length, with the terminating NUL.
+unsafe extern "C" fn straddr(
+    _av: *mut ffi::fid_av,
+    addr: *const c_void,
+    buf: *mut c_char,
+    len: *mut usize,
+) -> *const c_char {
+    let name = unsafe { slice::from_raw_parts(addr.cast::<u8>(), ADDR_LEN) };
+    let index = u64::from_le_bytes(name.try_into().unwrap());
+    let text = format!("fi_sim://{index}\0");
+    unsafe {
+        if *len > 0 {
+            let n = (*len - 1).min(text.len() - 1);
+            buf.cast::<u8>().copy_from_nonoverlapping(text.as_ptr(), n);
+            *buf.add(n) = 0;
+        }
+        *len = text.len();
+    }
+    buf
+}
+
+enosys! {
+    insertsvc(*mut ffi::fid_av, *const c_char, *const c_char, *mut ffi::fi_addr_t, u64, *mut c_void) -> c_int;
+    insertsym(*mut ffi::fid_av, *const c_char, usize, *const c_char, usize, *mut ffi::fi_addr_t, u64, *mut c_void) -> c_int;
+}
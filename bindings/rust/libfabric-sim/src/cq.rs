@@ -0,0 +1,317 @@
+// Completion queues of the provider, which the endpoints bound to them hand their completions
+// on to whenever one of them is read.
+
+use crate::QUEUE_SIZE;
+use crate::ep::Shared;
+use libfabric::{Completion, CqErrEntry};
+use ofi_libfabric_sys::bindgen as ffi;
+use std::collections::VecDeque;
+use std::ffi::{CString, c_char, c_int, c_void};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock, Mutex, Weak};
+use std::time::{Duration, Instant};
+use std::{ptr, thread};
+
+#[repr(C)]
+pub(crate) struct SimCq {
+    fid: ffi::fid_cq,
+    pub(crate) queue: Arc<Queue>,
+}
+
+pub(crate) enum Entry {
+    // A completion, and the index of its source in the address vector of the endpoint.
+    Done(Completion, ffi::fi_addr_t),
+    Failed(CqErrEntry),
+}
+
+pub(crate) struct Queue {
+    format: u32,
+    entries: Mutex<VecDeque<Entry>>,
+    // Polled when the queue is read.
+    endpoints: Mutex<Vec<Weak<Shared>>>,
+    // That of the last error entry read, which its `err_data` points to until the next.
+    message: Mutex<CString>,
+    signaled: AtomicBool,
+}
+
+impl Queue {
+    pub(crate) fn attach(&self, endpoint: Weak<Shared>) {
+        self.endpoints.lock().unwrap().push(endpoint);
+    }
+
+    pub(crate) fn push(&self, entry: Entry) {
+        self.entries.lock().unwrap().push_back(entry);
+    }
+
+    // Make progress on the endpoints bound to the queue, dropping those since closed.
+    fn progress(&self) {
+        let endpoints: Vec<_> = {
+            let mut endpoints = self.endpoints.lock().unwrap();
+            endpoints.retain(|ep| ep.strong_count() > 0);
+            endpoints.iter().filter_map(Weak::upgrade).collect()
+        };
+        for ep in endpoints {
+            ep.progress();
+        }
+    }
+
+    // Read up to `count` completions in the format of the queue, up to the first error.
+    //
+    // SAFETY: `buf` must have room for `count` entries, and `src`, if not null, for `count`
+    // addresses.
+    unsafe fn read(&self, buf: *mut c_void, count: usize, src: *mut ffi::fi_addr_t) -> isize {
+        self.progress();
+        let size = entry_size(self.format);
+        let mut entries = self.entries.lock().unwrap();
+        let mut n = 0;
+        while n < count
+            && let Some(Entry::Done(completion, addr)) = entries.front()
+        {
+            // The formats are all prefixes of the tagged one.
+            unsafe {
+                let out = buf.cast::<u8>().add(n * size);
+                let raw = ptr::from_ref(completion.as_raw()).cast::<u8>();
+                out.copy_from_nonoverlapping(raw, size);
+                if !src.is_null() {
+                    *src.add(n) = *addr;
+                }
+            }
+            entries.pop_front();
+            n += 1;
+        }
+        match (n, entries.front()) {
+            (0, Some(Entry::Failed(_))) => -(ffi::FI_EAVAIL as isize),
+            (0, _) => -(ffi::FI_EAGAIN as isize),
+            _ => n as isize,
+        }
+    }
+
+    // Poll the queue until a completion or an error is read, the timeout in milliseconds
+    // expires, or the queue is signaled.
+    //
+    // SAFETY: As for `read()`.
+    unsafe fn sread(
+        &self,
+        buf: *mut c_void,
+        count: usize,
+        src: *mut ffi::fi_addr_t,
+        timeout: c_int,
+    ) -> isize {
+        let deadline =
+            (timeout >= 0).then(|| Instant::now() + Duration::from_millis(timeout as u64));
+        loop {
+            let ret = unsafe { self.read(buf, count, src) };
+            if ret != -(ffi::FI_EAGAIN as isize)
+                || self.signaled.swap(false, Ordering::AcqRel)
+                || deadline.is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                return ret;
+            }
+            thread::yield_now();
+        }
+    }
+}
+
+// The size of the entries of `format`.
+fn entry_size(format: u32) -> usize {
+    match format {
+        f if f == ffi::fi_cq_format::FI_CQ_FORMAT_MSG as u32 => size_of::<ffi::fi_cq_msg_entry>(),
+        f if f == ffi::fi_cq_format::FI_CQ_FORMAT_DATA as u32 => size_of::<ffi::fi_cq_data_entry>(),
+        f if f == ffi::fi_cq_format::FI_CQ_FORMAT_TAGGED as u32 => {
+            size_of::<ffi::fi_cq_tagged_entry>()
+        }
+        _ => size_of::<ffi::fi_cq_entry>(),
+    }
+}
+
+pub(crate) static FID_OPS: LazyLock<ffi::fi_ops> =
+    LazyLock::new(|| crate::fid_ops(crate::close::<SimCq>, crate::bind, crate::control));
+
+static OPS: LazyLock<ffi::fi_ops_cq> = LazyLock::new(|| ffi::fi_ops_cq {
+    size: size_of::<ffi::fi_ops_cq>(),
+    read: Some(read),
+    readfrom: Some(readfrom),
+    readerr: Some(readerr),
+    sread: Some(sread),
+    sreadfrom: Some(sreadfrom),
+    signal: Some(signal),
+    strerror: Some(strerror),
+});
+
+// Open a completion queue of any format, the context one if unspecified, of `QUEUE_SIZE`
+// entries if unspecified, and without a wait object of its own, polled by `fi_cq_sread()` for
+// `FI_WAIT_UNSPEC` and `FI_WAIT_YIELD`. Peer queues are not supported.
+pub(crate) unsafe extern "C" fn open(
+    _domain: *mut ffi::fid_domain,
+    attr: *mut ffi::fi_cq_attr,
+    cq: *mut *mut ffi::fid_cq,
+    context: *mut c_void,
+) -> c_int {
+    let attr = unsafe { &mut *attr };
+    let format = unsafe { crate::raw_enum(&attr.format) };
+    let wait = unsafe { crate::raw_enum(&attr.wait_obj) };
+    if format > ffi::fi_cq_format::FI_CQ_FORMAT_TAGGED as u32
+        || ![
+            ffi::fi_wait_obj::FI_WAIT_NONE as u32,
+            ffi::fi_wait_obj::FI_WAIT_UNSPEC as u32,
+            ffi::fi_wait_obj::FI_WAIT_YIELD as u32,
+        ]
+        .contains(&wait)
+        || attr.flags & !(ffi::FI_AFFINITY as u64) != 0
+    {
+        return -(ffi::FI_ENOSYS as c_int);
+    }
+    if format == ffi::fi_cq_format::FI_CQ_FORMAT_UNSPEC as u32 {
+        attr.format = ffi::fi_cq_format::FI_CQ_FORMAT_CONTEXT;
+    }
+    if wait == ffi::fi_wait_obj::FI_WAIT_UNSPEC as u32 {
+        attr.wait_obj = ffi::fi_wait_obj::FI_WAIT_YIELD;
+    }
+    if attr.size == 0 {
+        attr.size = QUEUE_SIZE;
+    }
+    let object = crate::open(SimCq {
+        fid: ffi::fid_cq {
+            fid: crate::fid(ffi::FI_CLASS_CQ, context, &FID_OPS),
+            ops: crate::table(&OPS),
+        },
+        queue: Arc::new(Queue {
+            format: unsafe { crate::raw_enum(&attr.format) },
+            entries: Mutex::default(),
+            endpoints: Mutex::default(),
+            message: Mutex::default(),
+            signaled: AtomicBool::new(false),
+        }),
+    });
+    unsafe { *cq = object.cast() };
+    0
+}
+
+// The queue of `cq`.
+//
+// SAFETY: `cq` must be a queue opened by `open()`.
+unsafe fn queue<'a>(cq: *mut ffi::fid_cq) -> &'a Queue {
+    unsafe { &(*cq.cast::<SimCq>()).queue }
+}
+
+unsafe extern "C" fn read(cq: *mut ffi::fid_cq, buf: *mut c_void, count: usize) -> isize {
+    unsafe { queue(cq).read(buf, count, ptr::null_mut()) }
+}
+
+unsafe extern "C" fn readfrom(
+    cq: *mut ffi::fid_cq,
+    buf: *mut c_void,
+    count: usize,
+    src_addr: *mut ffi::fi_addr_t,
+) -> isize {
+    unsafe { queue(cq).read(buf, count, src_addr) }
+}
+
+unsafe extern "C" fn sread(
+    cq: *mut ffi::fid_cq,
+    buf: *mut c_void,
+    count: usize,
+    _cond: *const c_void,
+    timeout: c_int,
+) -> isize {
+    unsafe { queue(cq).sread(buf, count, ptr::null_mut(), timeout) }
+}
+
+unsafe extern "C" fn sreadfrom(
+    cq: *mut ffi::fid_cq,
+    buf: *mut c_void,
+    count: usize,
+    src_addr: *mut ffi::fi_addr_t,
+    _cond: *const c_void,
+    timeout: c_int,
+) -> isize {
+    unsafe { queue(cq).sread(buf, count, src_addr, timeout) }
+}
+
+// Read the error entry at the head of the queue. Its message is the error data, copied to
+// the buffer of the caller if it has one (`err_data_size`), or else kept by the queue until
+// the next is read. Entries failed with `FI_EADDRNOTAVAIL` carry none, as error data is the
+// address of the sender then (`FI_SOURCE_ERR`).
+unsafe extern "C" fn readerr(
+    cq: *mut ffi::fid_cq,
+    buf: *mut ffi::fi_cq_err_entry,
+    _flags: u64,
+) -> isize {
+    let queue = unsafe { queue(cq) };
+    let entry = {
+        let mut entries = queue.entries.lock().unwrap();
+        match entries.front() {
+            Some(Entry::Failed(_)) => match entries.pop_front() {
+                Some(Entry::Failed(entry)) => entry,
+                _ => unreachable!(),
+            },
+            _ => return -(ffi::FI_EAGAIN as isize),
+        }
+    };
+    let out = unsafe { &mut *buf };
+    let err = entry.error.code();
+    let message = CString::new(entry.message).unwrap_or_default();
+    let (err_data, err_data_size) = match (err == ffi::FI_EADDRNOTAVAIL as i32, out.err_data_size) {
+        (true, _) => (ptr::null_mut(), 0),
+        (false, 0) => {
+            let mut kept = queue.message.lock().unwrap();
+            *kept = message;
+            (
+                kept.as_ptr().cast_mut().cast(),
+                kept.as_bytes_with_nul().len(),
+            )
+        }
+        (false, size) => {
+            let bytes = message.as_bytes_with_nul();
+            let len = size.min(bytes.len());
+            unsafe {
+                out.err_data
+                    .cast::<u8>()
+                    .copy_from_nonoverlapping(bytes.as_ptr(), len)
+            };
+            (out.err_data, len)
+        }
+    };
+    out.op_context = entry.context as *mut c_void;
+    out.flags = entry.flags;
+    out.len = entry.len;
+    out.buf = ptr::null_mut();
+    out.data = entry.data;
+    out.tag = entry.tag;
+    out.olen = entry.olen;
+    out.err = err;
+    out.prov_errno = entry.prov_errno;
+    out.err_data = err_data;
+    out.err_data_size = err_data_size;
+    out.src_addr = ffi::FI_ADDR_NOTAVAIL;
+    1
+}
+
+unsafe extern "C" fn signal(cq: *mut ffi::fid_cq) -> c_int {
+    unsafe { queue(cq) }.signaled.store(true, Ordering::Release);
+    0
+}
+
+// The message of an error entry, its error data, or else that of `prov_errno`, copied to `buf`
+// if given.
+unsafe extern "C" fn strerror(
+    _cq: *mut ffi::fid_cq,
+    prov_errno: c_int,
+    err_data: *const c_void,
+    buf: *mut c_char,
+    len: usize,
+) -> *const c_char {
+    let message = match err_data.is_null() {
+        true => unsafe { ffi::fi_strerror(prov_errno) },
+        false => err_data.cast::<c_char>(),
+    };
+    if buf.is_null() || len == 0 {
+        return message;
+    }
+    unsafe {
+        let n = std::ffi::CStr::from_ptr(message).count_bytes().min(len - 1);
+        buf.copy_from_nonoverlapping(message, n);
+        *buf.add(n) = 0;
+    }
+    buf
+}
@@ -0,0 +1,870 @@
+// Endpoints of the provider, each an endpoint of the network, which translates the addresses
+// of its operations through the address vector bound, and hands its completions on to the
+// queues bound.
+
+use crate::av::{ADDR_LEN, SimAv, Table};
+use crate::cq::{Entry, Queue, SimCq};
+use crate::{INJECT_SIZE, NETWORK, QUEUE_SIZE, enosys};
+use libfabric::mock::MockEndpoint;
+use libfabric::{Addr, Completion, Cq, Error, Result, Transport};
+use ofi_libfabric_sys::bindgen as ffi;
+use std::ffi::{c_int, c_void};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::{ptr, slice};
+
+#[repr(C)]
+struct SimEp {
+    fid: ffi::fid_ep,
+    shared: Arc<Shared>,
+}
+
+// The endpoint, shared with the queues bound to it, which make progress on it.
+pub(crate) struct Shared {
+    ep: MockEndpoint,
+    bound: Mutex<Bound>,
+}
+
+#[derive(Clone, Default)]
+struct Bound {
+    tx: Option<Arc<Queue>>,
+    rx: Option<Arc<Queue>>,
+    av: Option<Arc<Table>>,
+}
+
+// The context of the operations posted to complete silently, as injected ones do.
+static INJECTED: u8 = 0;
+
+fn injected() -> usize {
+    (&raw const INJECTED) as usize
+}
+
+impl Shared {
+    // Hand the completions of the endpoint on to the queues bound to it: those of receives and
+    // remote writes to the receive queue, the others to the transmit queue.
+    pub(crate) fn progress(&self) {
+        let bound = self.bound.lock().unwrap().clone();
+        let cq = self.ep.cq();
+        let mut completions = [Completion::default(); 16];
+        let mut src = [Addr::NOTAVAIL; 16];
+        loop {
+            match cq.read_from(&mut completions, &mut src) {
+                Ok(n) => {
+                    for (completion, src) in completions[..n].iter().zip(&src[..n]) {
+                        let src = match &bound.av {
+                            Some(av) if *src != Addr::NOTAVAIL => av.source(*src),
+                            _ => ffi::FI_ADDR_NOTAVAIL,
+                        };
+                        let (flags, context) = (completion.as_raw().flags, completion.context());
+                        bound.route(flags, context, Entry::Done(*completion, src));
+                    }
+                }
+                Err(err) if err.is_avail() => match cq.read_err() {
+                    Ok(Some(entry)) => {
+                        bound.route(entry.flags, entry.context, Entry::Failed(entry))
+                    }
+                    _ => break,
+                },
+                Err(_) => break,
+            }
+        }
+    }
+
+    // The endpoint of the network `fi_addr` stands for in the address vector bound.
+    fn peer(&self, op: &'static str, fi_addr: ffi::fi_addr_t) -> Result<Addr> {
+        match &self.bound.lock().unwrap().av {
+            Some(av) => av.resolve(op, fi_addr),
+            None => Err(Error::Fabric {
+                op,
+                code: ffi::FI_ENOAV as i32,
+            }),
+        }
+    }
+}
+
+impl Bound {
+    fn route(&self, flags: u64, context: usize, entry: Entry) {
+        if context == injected() {
+            return;
+        }
+        let queue = match flags & (ffi::FI_RECV | ffi::FI_REMOTE_WRITE) as u64 {
+            0 => &self.tx,
+            _ => &self.rx,
+        };
+        if let Some(queue) = queue {
+            queue.push(entry);
+        }
+    }
+}
+
+static FID_OPS: LazyLock<ffi::fi_ops> =
+    LazyLock::new(|| crate::fid_ops(crate::close::<SimEp>, bind, control));
+
+static OPS: LazyLock<ffi::fi_ops_ep> = LazyLock::new(|| ffi::fi_ops_ep {
+    size: size_of::<ffi::fi_ops_ep>(),
+    cancel: Some(cancel),
+    getopt: Some(getopt),
+    setopt: Some(setopt),
+    tx_ctx: Some(tx_ctx),
+    rx_ctx: Some(rx_ctx),
+    rx_size_left: Some(size_left),
+    tx_size_left: Some(size_left),
+});
+
+static CM_OPS: LazyLock<ffi::fi_ops_cm> = LazyLock::new(|| ffi::fi_ops_cm {
+    size: size_of::<ffi::fi_ops_cm>(),
+    setname: Some(setname),
+    getname: Some(getname),
+    getpeer: Some(getpeer),
+    connect: Some(connect),
+    listen: Some(listen),
+    accept: Some(accept),
+    reject: Some(reject),
+    shutdown: Some(shutdown),
+    ..Default::default()
+});
+
+static MSG_OPS: LazyLock<ffi::fi_ops_msg> = LazyLock::new(|| ffi::fi_ops_msg {
+    size: size_of::<ffi::fi_ops_msg>(),
+    recv: Some(recv),
+    recvv: Some(recvv),
+    recvmsg: Some(recvmsg),
+    send: Some(send),
+    sendv: Some(sendv),
+    sendmsg: Some(sendmsg),
+    inject: Some(inject),
+    senddata: Some(senddata),
+    injectdata: Some(injectdata),
+});
+
+static TAGGED_OPS: LazyLock<ffi::fi_ops_tagged> = LazyLock::new(|| ffi::fi_ops_tagged {
+    size: size_of::<ffi::fi_ops_tagged>(),
+    recv: Some(trecv),
+    recvv: Some(trecvv),
+    recvmsg: Some(trecvmsg),
+    send: Some(tsend),
+    sendv: Some(tsendv),
+    sendmsg: Some(tsendmsg),
+    inject: Some(tinject),
+    senddata: Some(tsenddata),
+    injectdata: Some(tinjectdata),
+});
+
+static RMA_OPS: LazyLock<ffi::fi_ops_rma> = LazyLock::new(|| ffi::fi_ops_rma {
+    size: size_of::<ffi::fi_ops_rma>(),
+    read: Some(read),
+    readv: Some(readv),
+    readmsg: Some(readmsg),
+    write: Some(write),
+    writev: Some(writev),
+    writemsg: Some(writemsg),
+    inject: Some(inject_write),
+    writedata: Some(writedata),
+    injectdata: Some(inject_writedata),
+});
+
+// Open an endpoint of the network, of the RDM type only.
+pub(crate) unsafe extern "C" fn open(
+    _domain: *mut ffi::fid_domain,
+    info: *mut ffi::fi_info,
+    ep: *mut *mut ffi::fid_ep,
+    context: *mut c_void,
+) -> c_int {
+    if let Some(attr) = unsafe { info.as_ref().and_then(|info| info.ep_attr.as_ref()) } {
+        let ep_type = unsafe { crate::raw_enum(&attr.type_) };
+        if ep_type != ffi::fi_ep_type::FI_EP_RDM as u32
+            && ep_type != ffi::fi_ep_type::FI_EP_UNSPEC as u32
+        {
+            return -(ffi::FI_EINVAL as c_int);
+        }
+    }
+    let object = crate::open(SimEp {
+        fid: ffi::fid_ep {
+            fid: crate::fid(ffi::FI_CLASS_EP, context, &FID_OPS),
+            ops: crate::table(&OPS),
+            cm: crate::table(&CM_OPS),
+            msg: crate::table(&MSG_OPS),
+            rma: crate::table(&RMA_OPS),
+            tagged: crate::table(&TAGGED_OPS),
+            ..Default::default()
+        },
+        shared: Arc::new(Shared {
+            ep: NETWORK.endpoint(),
+            bound: Mutex::default(),
+        }),
+    });
+    unsafe { *ep = object.cast() };
+    0
+}
+
+// The endpoint of `ep`.
+//
+// SAFETY: `ep` must be an endpoint opened by `open()`.
+unsafe fn endpoint<'a>(ep: *mut ffi::fid_ep) -> &'a Arc<Shared> {
+    unsafe { &(*ep.cast::<SimEp>()).shared }
+}
+
+// Bind a completion queue of the provider, for all its operations or none, or an address
+// vector of the provider.
+unsafe extern "C" fn bind(fid: *mut ffi::fid, bfid: *mut ffi::fid, flags: u64) -> c_int {
+    let shared = unsafe { endpoint(fid.cast()) };
+    let mut bound = shared.bound.lock().unwrap();
+    if let Some(cq) = unsafe { crate::object::<SimCq>(bfid, &crate::cq::FID_OPS) } {
+        if flags & ffi::FI_SELECTIVE_COMPLETION != 0 {
+            return -(ffi::FI_ENOSYS as c_int);
+        }
+        if flags & ffi::FI_TRANSMIT as u64 != 0 {
+            bound.tx = Some(cq.queue.clone());
+        }
+        if flags & ffi::FI_RECV as u64 != 0 {
+            bound.rx = Some(cq.queue.clone());
+        }
+        cq.queue.attach(Arc::downgrade(shared));
+        0
+    } else if let Some(av) = unsafe { crate::object::<SimAv>(bfid, &crate::av::FID_OPS) } {
+        bound.av = Some(av.table.clone());
+        0
+    } else {
+        -(ffi::FI_ENOSYS as c_int)
+    }
+}
+
+unsafe extern "C" fn control(_fid: *mut ffi::fid, command: c_int, _arg: *mut c_void) -> c_int {
+    match command {
+        c if c == ffi::FI_ENABLE as c_int => 0,
+        _ => -(ffi::FI_ENOSYS as c_int),
+    }
+}
+
+// Cancel the receives posted with `context`.
+unsafe extern "C" fn cancel(fid: ffi::fid_t, context: *mut c_void) -> isize {
+    let shared = unsafe { endpoint(fid.cast()) };
+    crate::status(shared.ep.cancel(context as usize)) as isize
+}
+
+unsafe extern "C" fn getopt(
+    _fid: ffi::fid_t,
+    _level: c_int,
+    _optname: c_int,
+    _optval: *mut c_void,
+    _optlen: *mut usize,
+) -> c_int {
+    -(ffi::FI_ENOPROTOOPT as c_int)
+}
+
+unsafe extern "C" fn setopt(
+    _fid: ffi::fid_t,
+    _level: c_int,
+    _optname: c_int,
+    _optval: *const c_void,
+    _optlen: usize,
+) -> c_int {
+    -(ffi::FI_ENOPROTOOPT as c_int)
+}
+
+// The network queues operations without bounds, the size of the queues is reported.
+unsafe extern "C" fn size_left(_ep: *mut ffi::fid_ep) -> isize {
+    QUEUE_SIZE as isize
+}
+
+// The name of the endpoint, its index in the network, which needs `ADDR_LEN` bytes at `addr`.
+unsafe extern "C" fn getname(fid: ffi::fid_t, addr: *mut c_void, addrlen: *mut usize) -> c_int {
+    let shared = unsafe { endpoint(fid.cast()) };
+    let name = match shared.ep.name() {
+        Ok(name) => name,
+        Err(err) => return -err.code(),
+    };
+    unsafe {
+        let room = *addrlen;
+        *addrlen = ADDR_LEN;
+        if room < ADDR_LEN {
+            return -(ffi::FI_ETOOSMALL as c_int);
+        }
+        addr.cast::<u8>()
+            .copy_from_nonoverlapping(name.as_bytes().as_ptr(), ADDR_LEN);
+    }
+    0
+}
+
+// The buffer of an array of `count` iovs, empty or of one iov.
+//
+// SAFETY: `iov` must point to `count` iovs.
+pub(crate) unsafe fn iov(iov: *const ffi::iovec, count: usize) -> Option<(*mut c_void, usize)> {
+    match count {
+        0 => Some((ptr::null_mut(), 0)),
+        1 => {
+            let iov = unsafe { *iov };
+            Some((iov.iov_base, iov.iov_len))
+        }
+        _ => None,
+    }
+}
+
+// The `len` bytes at `buf`, which may be null when empty.
+//
+// SAFETY: The bytes must be valid for reads while in use.
+unsafe fn bytes<'a>(buf: *const c_void, len: usize) -> &'a [u8] {
+    match len {
+        0 => &[],
+        _ => unsafe { slice::from_raw_parts(buf.cast(), len) },
+    }
+}
+
+// The `len` bytes at `buf`, which may be null when empty.
+//
+// SAFETY: The bytes must be valid for writes until the operation completes.
+unsafe fn bytes_mut<'a>(buf: *mut c_void, len: usize) -> &'a mut [u8] {
+    match len {
+        0 => &mut [],
+        _ => unsafe { slice::from_raw_parts_mut(buf.cast(), len) },
+    }
+}
+
+// The status of an operation posted, `0` or `-FI_E*`.
+fn posted(result: Result<()>) -> isize {
+    crate::status(result) as isize
+}
+
+// `-FI_EINVAL`, for more than one iov.
+const EINVAL: isize = -(ffi::FI_EINVAL as isize);
+
+unsafe extern "C" fn recv(
+    ep: *mut ffi::fid_ep,
+    buf: *mut c_void,
+    len: usize,
+    _desc: *mut c_void,
+    src_addr: ffi::fi_addr_t,
+    context: *mut c_void,
+) -> isize {
+    let shared = unsafe { endpoint(ep) };
+    posted(shared.peer("fi_recv", src_addr).and_then(|src| unsafe {
+        shared
+            .ep
+            .recv(bytes_mut(buf, len), None, src, context as usize)
+    }))
+}
+
+unsafe extern "C" fn recvv(
+    ep: *mut ffi::fid_ep,
+    iov: *const ffi::iovec,
+    _desc: *mut *mut c_void,
+    count: usize,
+    src_addr: ffi::fi_addr_t,
+    context: *mut c_void,
+) -> isize {
+    match unsafe { self::iov(iov, count) } {
+        Some((buf, len)) => unsafe { recv(ep, buf, len, ptr::null_mut(), src_addr, context) },
+        None => EINVAL,
+    }
+}
+
+// Multi-receive buffers are not supported.
+unsafe extern "C" fn recvmsg(ep: *mut ffi::fid_ep, msg: *const ffi::fi_msg, flags: u64) -> isize {
+    if flags & ffi::FI_MULTI_RECV as u64 != 0 {
+        return -(ffi::FI_ENOSYS as isize);
+    }
+    let msg = unsafe { &*msg };
+    unsafe {
+        recvv(
+            ep,
+            msg.msg_iov,
+            msg.desc,
+            msg.iov_count,
+            msg.addr,
+            msg.context,
+        )
+    }
+}
+
+unsafe extern "C" fn send(
+    ep: *mut ffi::fid_ep,
+    buf: *const c_void,
+    len: usize,
+    _desc: *mut c_void,
+    dest_addr: ffi::fi_addr_t,
+    context: *mut c_void,
+) -> isize {
+    let shared = unsafe { endpoint(ep) };
+    posted(shared.peer("fi_send", dest_addr).and_then(|dest| unsafe {
+        shared
+            .ep
+            .send(bytes(buf, len), None, dest, context as usize)
+    }))
+}
+
+unsafe extern "C" fn sendv(
+    ep: *mut ffi::fid_ep,
+    iov: *const ffi::iovec,
+    _desc: *mut *mut c_void,
+    count: usize,
+    dest_addr: ffi::fi_addr_t,
+    context: *mut c_void,
+) -> isize {
+    match unsafe { self::iov(iov, count) } {
+        Some((buf, len)) => unsafe { send(ep, buf, len, ptr::null_mut(), dest_addr, context) },
+        None => EINVAL,
+    }
+}
+
+// Send with remote CQ data for `FI_REMOTE_CQ_DATA`. The other flags are met by all
+// operations, which complete once delivered.
+unsafe extern "C" fn sendmsg(ep: *mut ffi::fid_ep, msg: *const ffi::fi_msg, flags: u64) -> isize {
+    let msg = unsafe { &*msg };
+    let Some((buf, len)) = (unsafe { iov(msg.msg_iov, msg.iov_count) }) else {
+        return EINVAL;
+    };
+    match flags & ffi::FI_REMOTE_CQ_DATA as u64 {
+        0 => unsafe { send(ep, buf, len, ptr::null_mut(), msg.addr, msg.context) },
+        _ => unsafe {
+            senddata(
+                ep,
+                buf,
+                len,
+                ptr::null_mut(),
+                msg.data,
+                msg.addr,
+                msg.context,
+            )
+        },
+    }
+}
+
+unsafe extern "C" fn inject(
+    ep: *mut ffi::fid_ep,
+    buf: *const c_void,
+    len: usize,
+    dest_addr: ffi::fi_addr_t,
+) -> isize {
+    if len > INJECT_SIZE {
+        return EINVAL;
+    }
+    let shared = unsafe { endpoint(ep) };
+    posted(
+        shared
+            .peer("fi_inject", dest_addr)
+            .and_then(|dest| shared.ep.inject(unsafe { bytes(buf, len) }, dest)),
+    )
+}
+
+unsafe extern "C" fn senddata(
+    ep: *mut ffi::fid_ep,
+    buf: *const c_void,
+    len: usize,
+    _desc: *mut c_void,
+    data: u64,
+    dest_addr: ffi::fi_addr_t,
+    context: *mut c_void,
+) -> isize {
+    let shared = unsafe { endpoint(ep) };
+    posted(
+        shared
+            .peer("fi_senddata", dest_addr)
+            .and_then(|dest| unsafe {
+                shared
+                    .ep
+                    .senddata(bytes(buf, len), None, data, dest, context as usize)
+            }),
+    )
+}
+
+unsafe extern "C" fn injectdata(
+    ep: *mut ffi::fid_ep,
+    buf: *const c_void,
+    len: usize,
+    data: u64,
+    dest_addr: ffi::fi_addr_t,
+) -> isize {
+    if len > INJECT_SIZE {
+        return EINVAL;
+    }
+    let context = injected() as *mut c_void;
+    unsafe { senddata(ep, buf, len, ptr::null_mut(), data, dest_addr, context) }
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe extern "C" fn trecv(
+    ep: *mut ffi::fid_ep,
+    buf: *mut c_void,
+    len: usize,
+    _desc: *mut c_void,
+    src_addr: ffi::fi_addr_t,
+    tag: u64,
+    ignore: u64,
+    context: *mut c_void,
+) -> isize {
+    let shared = unsafe { endpoint(ep) };
+    posted(shared.peer("fi_trecv", src_addr).and_then(|src| unsafe {
+        shared.ep.trecv(
+            bytes_mut(buf, len),
+            None,
+            src,
+            tag,
+            ignore,
+            context as usize,
+        )
+    }))
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe extern "C" fn trecvv(
+    ep: *mut ffi::fid_ep,
+    iov: *const ffi::iovec,
+    _desc: *mut *mut c_void,
+    count: usize,
+    src_addr: ffi::fi_addr_t,
+    tag: u64,
+    ignore: u64,
+    context: *mut c_void,
+) -> isize {
+    match unsafe { self::iov(iov, count) } {
+        Some((buf, len)) => unsafe {
+            trecv(
+                ep,
+                buf,
+                len,
+                ptr::null_mut(),
+                src_addr,
+                tag,
+                ignore,
+                context,
+            )
+        },
+        None => EINVAL,
+    }
+}
+
+// Peeking at, claiming and discarding messages, and multi-receive buffers, are not supported.
+unsafe extern "C" fn trecvmsg(
+    ep: *mut ffi::fid_ep,
+    msg: *const ffi::fi_msg_tagged,
+    flags: u64,
+) -> isize {
+    if flags & (ffi::FI_PEEK as u64 | ffi::FI_CLAIM | ffi::FI_DISCARD | ffi::FI_MULTI_RECV as u64)
+        != 0
+    {
+        return -(ffi::FI_ENOSYS as isize);
+    }
+    let msg = unsafe { &*msg };
+    unsafe {
+        trecvv(
+            ep,
+            msg.msg_iov,
+            msg.desc,
+            msg.iov_count,
+            msg.addr,
+            msg.tag,
+            msg.ignore,
+            msg.context,
+        )
+    }
+}
+
+unsafe extern "C" fn tsend(
+    ep: *mut ffi::fid_ep,
+    buf: *const c_void,
+    len: usize,
+    _desc: *mut c_void,
+    dest_addr: ffi::fi_addr_t,
+    tag: u64,
+    context: *mut c_void,
+) -> isize {
+    let shared = unsafe { endpoint(ep) };
+    posted(shared.peer("fi_tsend", dest_addr).and_then(|dest| unsafe {
+        shared
+            .ep
+            .tsend(bytes(buf, len), None, dest, tag, context as usize)
+    }))
+}
+
+unsafe extern "C" fn tsendv(
+    ep: *mut ffi::fid_ep,
+    iov: *const ffi::iovec,
+    _desc: *mut *mut c_void,
+    count: usize,
+    dest_addr: ffi::fi_addr_t,
+    tag: u64,
+    context: *mut c_void,
+) -> isize {
+    match unsafe { self::iov(iov, count) } {
+        Some((buf, len)) => unsafe {
+            tsend(ep, buf, len, ptr::null_mut(), dest_addr, tag, context)
+        },
+        None => EINVAL,
+    }
+}
+
+// As `sendmsg()`, for tagged messages.
+unsafe extern "C" fn tsendmsg(
+    ep: *mut ffi::fid_ep,
+    msg: *const ffi::fi_msg_tagged,
+    flags: u64,
+) -> isize {
+    let msg = unsafe { &*msg };
+    let Some((buf, len)) = (unsafe { iov(msg.msg_iov, msg.iov_count) }) else {
+        return EINVAL;
+    };
+    let desc = ptr::null_mut();
+    match flags & ffi::FI_REMOTE_CQ_DATA as u64 {
+        0 => unsafe { tsend(ep, buf, len, desc, msg.addr, msg.tag, msg.context) },
+        _ => unsafe { tsenddata(ep, buf, len, desc, msg.data, msg.addr, msg.tag, msg.context) },
+    }
+}
+
+unsafe extern "C" fn tinject(
+    ep: *mut ffi::fid_ep,
+    buf: *const c_void,
+    len: usize,
+    dest_addr: ffi::fi_addr_t,
+    tag: u64,
+) -> isize {
+    if len > INJECT_SIZE {
+        return EINVAL;
+    }
+    let shared = unsafe { endpoint(ep) };
+    posted(
+        shared
+            .peer("fi_tinject", dest_addr)
+            .and_then(|dest| shared.ep.tinject(unsafe { bytes(buf, len) }, dest, tag)),
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe extern "C" fn tsenddata(
+    ep: *mut ffi::fid_ep,
+    buf: *const c_void,
+    len: usize,
+    _desc: *mut c_void,
+    data: u64,
+    dest_addr: ffi::fi_addr_t,
+    tag: u64,
+    context: *mut c_void,
+) -> isize {
+    let shared = unsafe { endpoint(ep) };
+    posted(
+        shared
+            .peer("fi_tsenddata", dest_addr)
+            .and_then(|dest| unsafe {
+                shared
+                    .ep
+                    .tsenddata(bytes(buf, len), None, data, dest, tag, context as usize)
+            }),
+    )
+}
+
+unsafe extern "C" fn tinjectdata(
+    ep: *mut ffi::fid_ep,
+    buf: *const c_void,
+    len: usize,
+    data: u64,
+    dest_addr: ffi::fi_addr_t,
+    tag: u64,
+) -> isize {
+    if len > INJECT_SIZE {
+        return EINVAL;
+    }
+    let context = injected() as *mut c_void;
+    unsafe { tsenddata(ep, buf, len, ptr::null_mut(), data, dest_addr, tag, context) }
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe extern "C" fn read(
+    ep: *mut ffi::fid_ep,
+    buf: *mut c_void,
+    len: usize,
+    _desc: *mut c_void,
+    src_addr: ffi::fi_addr_t,
+    addr: u64,
+    key: u64,
+    context: *mut c_void,
+) -> isize {
+    let shared = unsafe { endpoint(ep) };
+    posted(shared.peer("fi_read", src_addr).and_then(|src| unsafe {
+        shared
+            .ep
+            .read(bytes_mut(buf, len), None, src, addr, key, context as usize)
+    }))
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe extern "C" fn readv(
+    ep: *mut ffi::fid_ep,
+    iov: *const ffi::iovec,
+    _desc: *mut *mut c_void,
+    count: usize,
+    src_addr: ffi::fi_addr_t,
+    addr: u64,
+    key: u64,
+    context: *mut c_void,
+) -> isize {
+    match unsafe { self::iov(iov, count) } {
+        Some((buf, len)) => unsafe {
+            read(ep, buf, len, ptr::null_mut(), src_addr, addr, key, context)
+        },
+        None => EINVAL,
+    }
+}
+
+// The remote buffer of an RMA message, which takes one.
+//
+// SAFETY: `msg` must be valid, and its iovs too.
+unsafe fn rma_msg(msg: &ffi::fi_msg_rma) -> Option<((*mut c_void, usize), ffi::fi_rma_iov)> {
+    let local = unsafe { iov(msg.msg_iov, msg.iov_count) }?;
+    (msg.rma_iov_count == 1).then(|| (local, unsafe { *msg.rma_iov }))
+}
+
+unsafe extern "C" fn readmsg(
+    ep: *mut ffi::fid_ep,
+    msg: *const ffi::fi_msg_rma,
+    _flags: u64,
+) -> isize {
+    let msg = unsafe { &*msg };
+    let Some(((buf, len), rma)) = (unsafe { rma_msg(msg) }) else {
+        return EINVAL;
+    };
+    let desc = ptr::null_mut();
+    unsafe { read(ep, buf, len, desc, msg.addr, rma.addr, rma.key, msg.context) }
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe extern "C" fn write(
+    ep: *mut ffi::fid_ep,
+    buf: *const c_void,
+    len: usize,
+    _desc: *mut c_void,
+    dest_addr: ffi::fi_addr_t,
+    addr: u64,
+    key: u64,
+    context: *mut c_void,
+) -> isize {
+    let shared = unsafe { endpoint(ep) };
+    posted(shared.peer("fi_write", dest_addr).and_then(|dest| unsafe {
+        shared
+            .ep
+            .write(bytes(buf, len), None, dest, addr, key, context as usize)
+    }))
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe extern "C" fn writev(
+    ep: *mut ffi::fid_ep,
+    iov: *const ffi::iovec,
+    _desc: *mut *mut c_void,
+    count: usize,
+    dest_addr: ffi::fi_addr_t,
+    addr: u64,
+    key: u64,
+    context: *mut c_void,
+) -> isize {
+    match unsafe { self::iov(iov, count) } {
+        Some((buf, len)) => unsafe {
+            write(ep, buf, len, ptr::null_mut(), dest_addr, addr, key, context)
+        },
+        None => EINVAL,
+    }
+}
+
+// As `sendmsg()`, for RMA writes.
+unsafe extern "C" fn writemsg(
+    ep: *mut ffi::fid_ep,
+    msg: *const ffi::fi_msg_rma,
+    flags: u64,
+) -> isize {
+    let msg = unsafe { &*msg };
+    let Some(((buf, len), rma)) = (unsafe { rma_msg(msg) }) else {
+        return EINVAL;
+    };
+    let (desc, dest, context) = (ptr::null_mut(), msg.addr, msg.context);
+    match flags & ffi::FI_REMOTE_CQ_DATA as u64 {
+        0 => unsafe { write(ep, buf, len, desc, dest, rma.addr, rma.key, context) },
+        _ => unsafe {
+            writedata(
+                ep, buf, len, desc, msg.data, dest, rma.addr, rma.key, context,
+            )
+        },
+    }
+}
+
+unsafe extern "C" fn inject_write(
+    ep: *mut ffi::fid_ep,
+    buf: *const c_void,
+    len: usize,
+    dest_addr: ffi::fi_addr_t,
+    addr: u64,
+    key: u64,
+) -> isize {
+    if len > INJECT_SIZE {
+        return EINVAL;
+    }
+    let context = injected() as *mut c_void;
+    unsafe { write(ep, buf, len, ptr::null_mut(), dest_addr, addr, key, context) }
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe extern "C" fn writedata(
+    ep: *mut ffi::fid_ep,
+    buf: *const c_void,
+    len: usize,
+    _desc: *mut c_void,
+    data: u64,
+    dest_addr: ffi::fi_addr_t,
+    addr: u64,
+    key: u64,
+    context: *mut c_void,
+) -> isize {
+    let shared = unsafe { endpoint(ep) };
+    posted(
+        shared
+            .peer("fi_writedata", dest_addr)
+            .and_then(|dest| unsafe {
+                shared.ep.writedata(
+                    bytes(buf, len),
+                    None,
+                    data,
+                    dest,
+                    addr,
+                    key,
+                    context as usize,
+                )
+            }),
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe extern "C" fn inject_writedata(
+    ep: *mut ffi::fid_ep,
+    buf: *const c_void,
+    len: usize,
+    data: u64,
+    dest_addr: ffi::fi_addr_t,
+    addr: u64,
+    key: u64,
+) -> isize {
+    if len > INJECT_SIZE {
+        return EINVAL;
+    }
+    let context = injected() as *mut c_void;
+    unsafe {
+        writedata(
+            ep,
+            buf,
+            len,
+            ptr::null_mut(),
+            data,
+            dest_addr,
+            addr,
+            key,
+            context,
+        )
+    }
+}
+
+enosys! {
+    tx_ctx(*mut ffi::fid_ep, c_int, *mut ffi::fi_tx_attr, *mut *mut ffi::fid_ep, *mut c_void) -> c_int;
+    rx_ctx(*mut ffi::fid_ep, c_int, *mut ffi::fi_rx_attr, *mut *mut ffi::fid_ep, *mut c_void) -> c_int;
+    setname(ffi::fid_t, *mut c_void, usize) -> c_int;
+    getpeer(*mut ffi::fid_ep, *mut c_void, *mut usize) -> c_int;
+    connect(*mut ffi::fid_ep, *const c_void, *const c_void, usize) -> c_int;
+    listen(*mut ffi::fid_pep) -> c_int;
+    accept(*mut ffi::fid_ep, *const c_void, usize) -> c_int;
+    reject(*mut ffi::fid_pep, ffi::fid_t, *const c_void, usize) -> c_int;
+    shutdown(*mut ffi::fid_ep, u64) -> c_int;
+}
@@ -0,0 +1,197 @@
+// The fabric and domain of the provider, and the memory regions registered in the domain,
+// which all share the network of the process.
+
+use crate::{NETWORK, enosys};
+use libfabric::mock::MockMr;
+use libfabric::{Access, Mr};
+use ofi_libfabric_sys::bindgen as ffi;
+use std::ffi::{c_int, c_void};
+use std::ptr;
+use std::sync::LazyLock;
+
+#[repr(C)]
+struct SimFabric {
+    fid: ffi::fid_fabric,
+}
+
+#[repr(C)]
+struct SimDomain {
+    fid: ffi::fid_domain,
+}
+
+#[repr(C)]
+struct SimMr {
+    fid: ffi::fid_mr,
+    // Deregistered once dropped, when the region is closed.
+    _mr: MockMr,
+}
+
+static FABRIC_FID_OPS: LazyLock<ffi::fi_ops> =
+    LazyLock::new(|| crate::fid_ops(crate::close::<SimFabric>, crate::bind, crate::control));
+
+static FABRIC_OPS: LazyLock<ffi::fi_ops_fabric> = LazyLock::new(|| ffi::fi_ops_fabric {
+    size: size_of::<ffi::fi_ops_fabric>(),
+    domain: Some(domain),
+    passive_ep: Some(passive_ep),
+    eq_open: Some(eq_open),
+    wait_open: Some(wait_open),
+    trywait: Some(trywait),
+    ..Default::default()
+});
+
+static DOMAIN_FID_OPS: LazyLock<ffi::fi_ops> =
+    LazyLock::new(|| crate::fid_ops(crate::close::<SimDomain>, crate::bind, crate::control));
+
+static DOMAIN_OPS: LazyLock<ffi::fi_ops_domain> = LazyLock::new(|| ffi::fi_ops_domain {
+    size: size_of::<ffi::fi_ops_domain>(),
+    av_open: Some(crate::av::open),
+    cq_open: Some(crate::cq::open),
+    endpoint: Some(crate::ep::open),
+    scalable_ep: Some(scalable_ep),
+    cntr_open: Some(cntr_open),
+    poll_open: Some(poll_open),
+    stx_ctx: Some(stx_ctx),
+    srx_ctx: Some(srx_ctx),
+    ..Default::default()
+});
+
+static MR_FID_OPS: LazyLock<ffi::fi_ops> =
+    LazyLock::new(|| crate::fid_ops(crate::close::<SimMr>, crate::bind, crate::control));
+
+static MR_OPS: LazyLock<ffi::fi_ops_mr> = LazyLock::new(|| ffi::fi_ops_mr {
+    size: size_of::<ffi::fi_ops_mr>(),
+    reg: Some(reg),
+    regv: Some(regv),
+    regattr: Some(regattr),
+});
+
+// Open the fabric, the `fabric` callback of the provider.
+pub(crate) unsafe extern "C" fn fabric(
+    _attr: *mut ffi::fi_fabric_attr,
+    fabric: *mut *mut ffi::fid_fabric,
+    context: *mut c_void,
+) -> c_int {
+    let object = crate::open(SimFabric {
+        fid: ffi::fid_fabric {
+            fid: crate::fid(ffi::FI_CLASS_FABRIC, context, &FABRIC_FID_OPS),
+            ops: crate::table(&FABRIC_OPS),
+            api_version: 0,
+        },
+    });
+    unsafe { *fabric = object.cast() };
+    0
+}
+
+unsafe extern "C" fn domain(
+    _fabric: *mut ffi::fid_fabric,
+    _info: *mut ffi::fi_info,
+    domain: *mut *mut ffi::fid_domain,
+    context: *mut c_void,
+) -> c_int {
+    let object = crate::open(SimDomain {
+        fid: ffi::fid_domain {
+            fid: crate::fid(ffi::FI_CLASS_DOMAIN, context, &DOMAIN_FID_OPS),
+            ops: crate::table(&DOMAIN_OPS),
+            mr: crate::table(&MR_OPS),
+        },
+    });
+    unsafe { *domain = object.cast() };
+    0
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe extern "C" fn reg(
+    _fid: *mut ffi::fid,
+    buf: *const c_void,
+    len: usize,
+    access: u64,
+    _offset: u64,
+    _requested_key: u64,
+    flags: u64,
+    mr: *mut *mut ffi::fid_mr,
+    context: *mut c_void,
+) -> c_int {
+    unsafe { register(buf.cast_mut().cast(), len, access, flags, mr, context) }
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe extern "C" fn regv(
+    _fid: *mut ffi::fid,
+    iov: *const ffi::iovec,
+    count: usize,
+    access: u64,
+    _offset: u64,
+    _requested_key: u64,
+    flags: u64,
+    mr: *mut *mut ffi::fid_mr,
+    context: *mut c_void,
+) -> c_int {
+    match unsafe { crate::ep::iov(iov, count) } {
+        Some((buf, len)) => unsafe { register(buf.cast(), len, access, flags, mr, context) },
+        None => -(ffi::FI_EINVAL as c_int),
+    }
+}
+
+unsafe extern "C" fn regattr(
+    _fid: *mut ffi::fid,
+    attr: *const ffi::fi_mr_attr,
+    flags: u64,
+    mr: *mut *mut ffi::fid_mr,
+) -> c_int {
+    let attr = unsafe { &*attr };
+    if unsafe { crate::raw_enum(&attr.iface) } != ffi::fi_hmem_iface::FI_HMEM_SYSTEM as u32 {
+        return -(ffi::FI_ENOSYS as c_int);
+    }
+    // The iov leads the attributes, in a union with the dma-buf of newer headers.
+    let iov = unsafe { ptr::from_ref(attr).cast::<*const ffi::iovec>().read() };
+    match unsafe { crate::ep::iov(iov, attr.iov_count) } {
+        Some((buf, len)) => unsafe {
+            register(buf.cast(), len, attr.access, flags, mr, attr.context)
+        },
+        None => -(ffi::FI_EINVAL as c_int),
+    }
+}
+
+// Register the `len` bytes at `buf` with the network. Keys are picked by the network, and
+// regions of memory other than the host's are not supported.
+//
+// SAFETY: The memory must stay valid until the region is closed.
+unsafe fn register(
+    buf: *mut u8,
+    len: usize,
+    access: u64,
+    flags: u64,
+    mr: *mut *mut ffi::fid_mr,
+    context: *mut c_void,
+) -> c_int {
+    if flags != 0 {
+        return -(ffi::FI_EBADFLAGS as c_int);
+    }
+    match unsafe { NETWORK.register(buf, len, Access::from_bits_retain(access)) } {
+        Ok(region) => {
+            let object = crate::open(SimMr {
+                fid: ffi::fid_mr {
+                    fid: crate::fid(ffi::FI_CLASS_MR, context, &MR_FID_OPS),
+                    mem_desc: ptr::null_mut(),
+                    key: region.key(),
+                },
+                _mr: region,
+            });
+            unsafe { *mr = object.cast() };
+            0
+        }
+        Err(err) => -err.code(),
+    }
+}
+
+enosys! {
+    passive_ep(*mut ffi::fid_fabric, *mut ffi::fi_info, *mut *mut ffi::fid_pep, *mut c_void) -> c_int;
+    eq_open(*mut ffi::fid_fabric, *mut ffi::fi_eq_attr, *mut *mut ffi::fid_eq, *mut c_void) -> c_int;
+    wait_open(*mut ffi::fid_fabric, *mut ffi::fi_wait_attr, *mut *mut ffi::fid_wait) -> c_int;
+    trywait(*mut ffi::fid_fabric, *mut *mut ffi::fid, c_int) -> c_int;
+    scalable_ep(*mut ffi::fid_domain, *mut ffi::fi_info, *mut *mut ffi::fid_ep, *mut c_void) -> c_int;
+    cntr_open(*mut ffi::fid_domain, *mut ffi::fi_cntr_attr, *mut *mut ffi::fid_cntr, *mut c_void) -> c_int;
+    poll_open(*mut ffi::fid_domain, *mut ffi::fi_poll_attr, *mut *mut ffi::fid_poll) -> c_int;
+    stx_ctx(*mut ffi::fid_domain, *mut ffi::fi_tx_attr, *mut *mut ffi::fid_stx, *mut c_void) -> c_int;
+    srx_ctx(*mut ffi::fid_domain, *mut ffi::fi_rx_attr, *mut *mut ffi::fid_ep, *mut c_void) -> c_int;
+}
@@ -0,0 +1,301 @@
+//! The `sim` provider: the in-memory network of `libfabric::mock`, served to libfabric as a
+//! dynamically loaded provider written in Rust.
+//!
+//! Built as `libsim_fi.so`, the provider is loaded from the directories of `FI_PROVIDER_PATH`
+//! (ex: `FI_PROVIDER_PATH=target/debug`), so that the `libfabric` crate and the applications
+//! built on it run end to end, through `fi_getinfo()` and the operation tables of each object,
+//! on machines with no usable provider. The fabrics opened in a process share one network,
+//! over which all of their endpoints reach each other.
+//!
+//! Endpoints are reliable and connectionless (`FI_EP_RDM`), with messages, tagged messages
+//! and RMA, and with these limits:
+//!
+//! - Progress is manual (`FI_PROGRESS_MANUAL`): operations are delivered when a completion
+//!   queue is read.
+//! - Operations take a single buffer, and a single remote buffer for RMA.
+//! - Address vectors are tables (`FI_AV_TABLE`) of the 8 byte names of the endpoints.
+//! - Completion queues have no wait object of their own: `fi_cq_sread()` polls them.
+//! - Counters, event queues, wait sets, poll sets, connection management, atomics, shared
+//!   and scalable contexts are not supported (`-FI_ENOSYS`).
+
+// bindgen types the FI_* macros u32 or u64 by value.
+#![allow(clippy::unnecessary_cast)]
+
+mod av;
+mod cq;
+mod ep;
+mod fabric;
+
+use libfabric::mock::MockFabric;
+use libfabric::{
+    Caps, DomainConfig, EndpointType, Info, MrMode, MsgOrder, Progress, ResourceMgmt, Result,
+    RxQueueAttr, ThreadSafe, TxQueueAttr,
+};
+use ofi_libfabric_sys::bindgen as ffi;
+use ofi_libfabric_sys::{FI_VERSION, fi_ext_ini};
+use std::ffi::{CStr, c_char, c_int, c_void};
+use std::ptr;
+use std::sync::LazyLock;
+
+/// The name of the provider, and of its fabric and domain.
+pub const NAME: &CStr = c"sim";
+
+/// The version of the provider.
+pub const VERSION: u32 = FI_VERSION(0, 1);
+
+// The capabilities of the endpoints, which only reach those of the process.
+const CAPS: u64 = (ffi::FI_MSG
+    | ffi::FI_TAGGED
+    | ffi::FI_RMA
+    | ffi::FI_SEND
+    | ffi::FI_RECV
+    | ffi::FI_READ
+    | ffi::FI_WRITE
+    | ffi::FI_REMOTE_READ
+    | ffi::FI_REMOTE_WRITE) as u64
+    | ffi::FI_DIRECTED_RECV as u64
+    | ffi::FI_SOURCE as u64
+    | ffi::FI_LOCAL_COMM as u64;
+
+// The operations are queued in order, and delivered in order.
+const ORDER: MsgOrder = MsgOrder::RAR
+    .union(MsgOrder::RAW)
+    .union(MsgOrder::RAS)
+    .union(MsgOrder::WAR)
+    .union(MsgOrder::WAW)
+    .union(MsgOrder::WAS)
+    .union(MsgOrder::SAR)
+    .union(MsgOrder::SAW)
+    .union(MsgOrder::SAS);
+
+// The limits reported, none of which the network needs.
+const MAX_MSG_SIZE: usize = 1 << 30;
+const INJECT_SIZE: usize = 8192;
+const QUEUE_SIZE: usize = 1024;
+const MAX_OBJECTS: usize = 1 << 16;
+
+// The legacy memory registration mode of FI_VIRT_ADDR and FI_PROV_KEY, whose deprecated
+// macro bindgen skips.
+const MR_BASIC: i32 = 1 << 0;
+
+// The network of the fabrics opened in the process.
+static NETWORK: LazyLock<MockFabric> = LazyLock::new(MockFabric::new);
+
+fi_ext_ini!(Box::into_raw(Box::new(ffi::fi_provider {
+    version: VERSION,
+    fi_version: FI_VERSION(ffi::FI_MAJOR_VERSION, ffi::FI_MINOR_VERSION),
+    name: NAME.as_ptr(),
+    getinfo: Some(getinfo),
+    fabric: Some(fabric::fabric),
+    cleanup: Some(cleanup),
+    ..Default::default()
+})));
+
+// The single entry of the provider, unless `hints` ask for what it does not support. The
+// node and service are not resolved, as the endpoints have no addresses to bind to.
+unsafe extern "C" fn getinfo(
+    _version: u32,
+    _node: *const c_char,
+    _service: *const c_char,
+    _flags: u64,
+    hints: *const ffi::fi_info,
+    info: *mut *mut ffi::fi_info,
+) -> c_int {
+    if let Some(hints) = unsafe { hints.as_ref() }
+        && !unsafe { supports(hints) }
+    {
+        return -(ffi::FI_ENODATA as c_int);
+    }
+    let mut entry = entry();
+    let dup = unsafe { ffi::fi_dupinfo(entry.as_raw_mut()) };
+    if dup.is_null() {
+        return -(ffi::FI_ENOMEM as c_int);
+    }
+    unsafe { *info = dup };
+    0
+}
+
+unsafe extern "C" fn cleanup() {}
+
+// Whether the entry of the provider meets `hints`.
+//
+// SAFETY: The attributes of `hints` must be null or valid.
+unsafe fn supports(hints: &ffi::fi_info) -> bool {
+    let named = |name: *const c_char| name.is_null() || unsafe { CStr::from_ptr(name) } == NAME;
+    let ep = unsafe { hints.ep_attr.as_ref() }.is_none_or(|attr| {
+        let ep_type = unsafe { raw_enum(&attr.type_) };
+        (ep_type == ffi::fi_ep_type::FI_EP_UNSPEC as u32
+            || ep_type == ffi::fi_ep_type::FI_EP_RDM as u32)
+            && attr.protocol == ffi::FI_PROTO_UNSPEC as u32
+            && attr.tx_ctx_cnt <= 1
+            && attr.rx_ctx_cnt <= 1
+    });
+    let domain = unsafe { hints.domain_attr.as_ref() }.is_none_or(|attr| {
+        let auto = ffi::fi_progress::FI_PROGRESS_AUTO as u32;
+        let mr_mode = attr.mr_mode;
+        let mr_required = (ffi::FI_MR_VIRT_ADDR | ffi::FI_MR_PROV_KEY) as i32;
+        named(attr.name)
+            && unsafe { raw_enum(&attr.control_progress) } != auto
+            && data_progress(attr) != auto
+            && (mr_mode == 0 || mr_mode == MR_BASIC || mr_mode & mr_required == mr_required)
+    });
+    let fabric = unsafe { hints.fabric_attr.as_ref() }.is_none_or(|attr| named(attr.name));
+    let tx = unsafe { hints.tx_attr.as_ref() }.is_none_or(|attr| {
+        attr.iov_limit <= 1 && attr.rma_iov_limit <= 1 && attr.inject_size <= INJECT_SIZE
+    });
+    let rx = unsafe { hints.rx_attr.as_ref() }.is_none_or(|attr| attr.iov_limit <= 1);
+    hints.caps & !CAPS == 0
+        && hints.addr_format == ffi::FI_FORMAT_UNSPEC as u32
+        && ep
+        && domain
+        && fabric
+        && tx
+        && rx
+}
+
+// The entry of the provider, built as hints are, then duplicated by libfabric.
+fn entry() -> Info {
+    let config = DomainConfig::<ThreadSafe>::new()
+        .control_progress(Progress::Manual)
+        .data_progress(Progress::Manual)
+        .resource_mgmt(ResourceMgmt::Enabled);
+    let tx = TxQueueAttr::new()
+        .size(QUEUE_SIZE)
+        .iov_limit(1)
+        .rma_iov_limit(1)
+        .inject_size(INJECT_SIZE)
+        .msg_order(ORDER);
+    let rx = RxQueueAttr::new()
+        .size(QUEUE_SIZE)
+        .iov_limit(1)
+        .msg_order(ORDER);
+    let mut info = Info::new()
+        .caps(Caps::from_bits_retain(CAPS))
+        .ep_type(EndpointType::Rdm)
+        .mr_mode(MrMode::VIRT_ADDR | MrMode::PROV_KEY)
+        .domain_config(&config)
+        .tx_attr(&tx)
+        .rx_attr(&rx)
+        .fabric_name("sim")
+        .domain_name("sim");
+    // The maximums, which hints have no setters for.
+    let raw = unsafe { &mut *info.as_raw_mut() };
+    let (tx, rx) = unsafe { (&mut *raw.tx_attr, &mut *raw.rx_attr) };
+    tx.caps = CAPS;
+    rx.caps = CAPS;
+    let ep = unsafe { &mut *raw.ep_attr };
+    ep.max_msg_size = MAX_MSG_SIZE;
+    ep.mem_tag_format = u64::MAX;
+    ep.max_order_raw_size = MAX_MSG_SIZE;
+    ep.max_order_war_size = MAX_MSG_SIZE;
+    ep.max_order_waw_size = MAX_MSG_SIZE;
+    ep.tx_ctx_cnt = 1;
+    ep.rx_ctx_cnt = 1;
+    let domain = unsafe { &mut *raw.domain_attr };
+    unsafe {
+        (&raw mut domain.av_type)
+            .cast::<u32>()
+            .write(ffi::fi_av_type::FI_AV_TABLE as u32)
+    };
+    domain.caps = ffi::FI_LOCAL_COMM as u64;
+    domain.mr_key_size = 8;
+    domain.cq_data_size = 8;
+    domain.cq_cnt = MAX_OBJECTS;
+    domain.ep_cnt = MAX_OBJECTS;
+    domain.tx_ctx_cnt = MAX_OBJECTS;
+    domain.rx_ctx_cnt = MAX_OBJECTS;
+    domain.max_ep_tx_ctx = 1;
+    domain.max_ep_rx_ctx = 1;
+    domain.mr_iov_limit = 1;
+    domain.mr_cnt = MAX_OBJECTS;
+    info
+}
+
+// The raw value of an enum field, which may have no variant in the generated Rust enum.
+//
+// SAFETY: `field` must point to a C enum.
+unsafe fn raw_enum<T>(field: *const T) -> u32 {
+    unsafe { field.cast::<u32>().read() }
+}
+
+// Libfabric 2.0 shares the data progress field with its new name, `progress`, in a union.
+fn data_progress(attr: &ffi::fi_domain_attr) -> u32 {
+    #[cfg(libfabric_ge_2_0)]
+    let raw = unsafe { raw_enum(&raw const attr.__bindgen_anon_1.data_progress) };
+    #[cfg(not(libfabric_ge_2_0))]
+    let raw = unsafe { raw_enum(&raw const attr.data_progress) };
+    raw
+}
+
+// The status of a call, `0` or `-FI_E*`.
+fn status(result: Result<()>) -> c_int {
+    match result {
+        Ok(()) => 0,
+        Err(err) => -err.code(),
+    }
+}
+
+// The fid heading an object of `class`, closed by `ops`.
+fn fid(class: u32, context: *mut c_void, ops: &'static ffi::fi_ops) -> ffi::fid {
+    ffi::fid {
+        fclass: class as usize,
+        context,
+        ops: table(ops),
+    }
+}
+
+// The operations of a fid, with `close`, `bind` and `control`.
+fn fid_ops(
+    close: unsafe extern "C" fn(*mut ffi::fid) -> c_int,
+    bind: unsafe extern "C" fn(*mut ffi::fid, *mut ffi::fid, u64) -> c_int,
+    control: unsafe extern "C" fn(*mut ffi::fid, c_int, *mut c_void) -> c_int,
+) -> ffi::fi_ops {
+    ffi::fi_ops {
+        size: size_of::<ffi::fi_ops>(),
+        close: Some(close),
+        bind: Some(bind),
+        control: Some(control),
+        ops_open: Some(ops_open),
+        ..Default::default()
+    }
+}
+
+// An operation table, which libfabric takes as mutable but only reads.
+fn table<T>(ops: &'static T) -> *mut T {
+    ptr::from_ref(ops).cast_mut()
+}
+
+// Move an object, starting with its fid, to the heap, until `close::<T>()`.
+fn open<T>(object: T) -> *mut T {
+    Box::into_raw(Box::new(object))
+}
+
+// Close the object opened at `fid` by `open::<T>()`.
+unsafe extern "C" fn close<T>(fid: *mut ffi::fid) -> c_int {
+    drop(unsafe { Box::from_raw(fid.cast::<T>()) });
+    0
+}
+
+// The object of `fid`, if it is one of the provider's with `ops`.
+//
+// SAFETY: `fid` must be an open fid.
+unsafe fn object<'a, T>(fid: *mut ffi::fid, ops: &'static ffi::fi_ops) -> Option<&'a T> {
+    (unsafe { (*fid).ops } == table(ops)).then(|| unsafe { &*fid.cast::<T>() })
+}
+
+macro_rules! enosys {
+    ($($name:ident($($arg:ty),*) -> $ret:ty;)*) => {
+        $(
+            unsafe extern "C" fn $name($(_: $arg),*) -> $ret {
+                -(ffi::FI_ENOSYS as $ret)
+            }
+        )*
+    };
+}
+pub(crate) use enosys;
+
+enosys! {
+    bind(*mut ffi::fid, *mut ffi::fid, u64) -> c_int;
+    control(*mut ffi::fid, c_int, *mut c_void) -> c_int;
+    ops_open(*mut ffi::fid, *const c_char, u64, *mut *mut c_void, *mut c_void) -> c_int;
+}
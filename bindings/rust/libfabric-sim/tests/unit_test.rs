@@ -0,0 +1,210 @@
+#[cfg(test)]
+mod unit_tests {
+    use libfabric::*;
+    use ofi_libfabric_sys::bindgen as ffi;
+    use std::sync::Once;
+
+    /// Hints for the sim provider, loaded from the target directory of the test, where cargo
+    /// builds `libsim_fi.so`.
+    fn sim_hints() -> Info {
+        static LOAD: Once = Once::new();
+        LOAD.call_once(|| {
+            let exe = std::env::current_exe().unwrap();
+            let dir = exe.parent().and_then(|deps| deps.parent()).unwrap();
+            // SAFETY: Set before libfabric is initialized, by the first test to get entries,
+            // and never read by the tests themselves.
+            unsafe { std::env::set_var("FI_PROVIDER_PATH", dir) };
+        });
+        Info::new()
+            .caps(Caps::MSG | Caps::TAGGED | Caps::RMA)
+            .ep_type(EndpointType::Rdm)
+            .provider("sim")
+    }
+
+    // An endpoint of the domain, with its transmit and receive completion queues.
+    struct Peer {
+        ep: Endpoint,
+        tx: CompletionQueue,
+        rx: CompletionQueue,
+    }
+
+    impl Peer {
+        fn open(domain: &Domain, av: &AddressVector, entry: &InfoEntry) -> Peer {
+            let tx = domain.cq(&CqAttr::new()).unwrap();
+            let rx = domain.cq(&CqAttr::new()).unwrap();
+            let ep = domain
+                .endpoint(entry)
+                .unwrap()
+                .bind_cq(&tx, BindFlags::TRANSMIT)
+                .unwrap()
+                .bind_cq(&rx, BindFlags::RECV)
+                .unwrap()
+                .bind_av(av)
+                .unwrap()
+                .enable()
+                .unwrap();
+            Peer { ep, tx, rx }
+        }
+    }
+
+    // The next completion of `cq`, with its source, or else its error entry.
+    fn next(cq: &CompletionQueue) -> std::result::Result<(Completion, Addr), Box<CqErrEntry>> {
+        let (mut completion, mut src) = ([Completion::default()], [Addr::NOTAVAIL]);
+        loop {
+            match cq.read_from(&mut completion, &mut src) {
+                Ok(1) => return Ok((completion[0], src[0])),
+                Ok(_) => std::thread::yield_now(),
+                Err(err) if err.is_avail() => {
+                    return Err(Box::new(cq.read_err().unwrap().unwrap()));
+                }
+                Err(err) => panic!("{err}"),
+            }
+        }
+    }
+
+    /// The provider is found under its name, with its fabric and domain, and none of its
+    /// entries is given for what it does not support.
+    #[test]
+    fn test_get_info() {
+        let entries = sim_hints().get().unwrap();
+        assert_eq!(entries.len(), 1);
+        let entry = &entries[0];
+        assert_eq!(entry.provider_name(), "sim");
+        assert_eq!(entry.fabric_name(), "sim");
+        assert_eq!(entry.domain_name(), "sim");
+        assert_eq!(entry.ep_type(), EndpointType::Rdm);
+        assert!(entry.caps().contains(Caps::MSG | Caps::TAGGED | Caps::RMA));
+
+        let err = sim_hints().ep_type(EndpointType::Msg).get().unwrap_err();
+        assert_eq!(err.code(), ffi::FI_ENODATA as i32);
+    }
+
+    /// Messages, tagged or not, are received from the source inserted in the address vector,
+    /// with their remote CQ data, each completion going to the queue of its direction.
+    #[test]
+    fn test_msg() {
+        let entries = sim_hints().get().unwrap();
+        let entry = &entries[0];
+        let fabric = Fabric::open(entry).unwrap();
+        let domain = fabric.domain(entry).unwrap();
+        let av = domain.av(&AvAttr::new()).unwrap();
+        let (a, b) = (
+            Peer::open(&domain, &av, entry),
+            Peer::open(&domain, &av, entry),
+        );
+        let to_a = av.insert(&a.ep.name().unwrap()).unwrap();
+        let to_b = av.insert(&b.ep.name().unwrap()).unwrap();
+
+        let mut buf = [0u8; 16];
+        unsafe {
+            b.ep.recv(&mut buf, None, Addr::UNSPEC, 1).unwrap();
+            a.ep.senddata(b"hello", None, 42, to_b, 2).unwrap();
+        }
+        let (completion, src) = next(&b.rx).unwrap();
+        assert_eq!((completion.context(), completion.len()), (1, 5));
+        assert!(completion.is_recv() && completion.has_data());
+        assert_eq!((completion.data(), src), (42, to_a));
+        assert_eq!(&buf[..5], b"hello");
+        let (completion, _) = next(&a.tx).unwrap();
+        assert!(completion.is_send());
+        assert_eq!(completion.context(), 2);
+
+        unsafe {
+            b.ep.trecv(&mut buf, None, to_a, 0x10, 0x0f, 3).unwrap();
+            a.ep.tsend(b"tagged", None, to_b, 0x17, 4).unwrap();
+        }
+        let (completion, src) = next(&b.rx).unwrap();
+        assert!(completion.is_tagged());
+        assert_eq!(
+            (completion.context(), completion.tag(), src),
+            (3, 0x17, to_a)
+        );
+        assert_eq!(&buf[..6], b"tagged");
+        assert_eq!(next(&a.tx).unwrap().0.context(), 4);
+
+        // Injected messages complete at the receiver only.
+        unsafe { b.ep.recv(&mut buf, None, Addr::UNSPEC, 5).unwrap() };
+        a.ep.inject(b"inject", to_b).unwrap();
+        assert_eq!(next(&b.rx).unwrap().0.context(), 5);
+        assert_eq!(&buf[..6], b"inject");
+        let mut completions = [Completion::default(); 4];
+        assert_eq!(a.tx.read(&mut completions).unwrap(), 0);
+    }
+
+    /// Writes and reads reach the region registered by the peer, at its address and key, and
+    /// writes with remote CQ data complete at the peer.
+    #[test]
+    fn test_rma() {
+        let entries = sim_hints().get().unwrap();
+        let entry = &entries[0];
+        let fabric = Fabric::open(entry).unwrap();
+        let domain = fabric.domain(entry).unwrap();
+        let av = domain.av(&AvAttr::new()).unwrap();
+        let (a, b) = (
+            Peer::open(&domain, &av, entry),
+            Peer::open(&domain, &av, entry),
+        );
+        let to_b = av.insert(&b.ep.name().unwrap()).unwrap();
+
+        let mut region = [0u8; 32];
+        let access = Access::REMOTE_READ | Access::REMOTE_WRITE;
+        let mr = unsafe { domain.register(region.as_mut_ptr(), region.len(), access) }.unwrap();
+        let (addr, key) = (mr.addr() as u64, mr.key());
+
+        unsafe {
+            a.ep.write(b"written", None, to_b, addr + 4, key, 1)
+                .unwrap()
+        };
+        assert_eq!(next(&a.tx).unwrap().0.context(), 1);
+        let mut buf = [0u8; 11];
+        unsafe { a.ep.read(&mut buf, None, to_b, addr, key, 2).unwrap() };
+        assert_eq!(next(&a.tx).unwrap().0.context(), 2);
+        assert_eq!(&buf, b"\0\0\0\0written");
+
+        unsafe {
+            a.ep.writedata(b"data", None, 7, to_b, addr, key, 3)
+                .unwrap()
+        };
+        assert_eq!(next(&a.tx).unwrap().0.context(), 3);
+        let (completion, _) = next(&b.rx).unwrap();
+        assert_eq!((completion.has_data(), completion.data()), (true, 7));
+        drop(mr);
+        assert_eq!(&region[..11], b"datawritten");
+    }
+
+    /// Truncated and cancelled receives complete in error, and addresses removed from the
+    /// address vector can no longer be sent to.
+    #[test]
+    fn test_errors() {
+        let entries = sim_hints().get().unwrap();
+        let entry = &entries[0];
+        let fabric = Fabric::open(entry).unwrap();
+        let domain = fabric.domain(entry).unwrap();
+        let av = domain.av(&AvAttr::new()).unwrap();
+        let (a, b) = (
+            Peer::open(&domain, &av, entry),
+            Peer::open(&domain, &av, entry),
+        );
+        let to_b = av.insert(&b.ep.name().unwrap()).unwrap();
+
+        let mut buf = [0u8; 4];
+        unsafe {
+            b.ep.recv(&mut buf, None, Addr::UNSPEC, 1).unwrap();
+            a.ep.send(b"too long", None, to_b, 2).unwrap();
+        }
+        let entry = next(&b.rx).unwrap_err();
+        assert_eq!((entry.context, entry.olen), (1, 4));
+        assert_eq!(entry.error.code(), ffi::FI_ETRUNC as i32);
+        assert_eq!(next(&a.tx).unwrap().0.context(), 2);
+
+        unsafe { b.ep.recv(&mut buf, None, Addr::UNSPEC, 3).unwrap() };
+        b.ep.cancel(3).unwrap();
+        let entry = next(&b.rx).unwrap_err();
+        assert_eq!(entry.context, 3);
+        assert_eq!(entry.error.code(), ffi::FI_ECANCELED as i32);
+
+        av.remove(to_b).unwrap();
+        let err = unsafe { a.ep.send(b"gone", None, to_b, 4) }.unwrap_err();
+        assert_eq!(err.code(), ffi::FI_EADDRNOTAVAIL as i32);
+    }
+}
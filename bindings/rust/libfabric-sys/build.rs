@@ -0,0 +1,1296 @@
+use bindgen::callbacks::ItemInfo;
+use bindgen::callbacks::ItemKind;
+use bindgen::callbacks::ParseCallbacks;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+
+// Global variables for common directories.
+static CARGO_MANIFEST_DIR: OnceLock<PathBuf> = OnceLock::new();
+static CARGO_WORKSPACE_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+fn get_cargo_manifest_dir() -> &'static PathBuf {
+    CARGO_MANIFEST_DIR.get_or_init(|| PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap()))
+}
+
+// Use CARGO_MANIFEST_DIR to get absolute path to the crate, then navigate to libfabric root.
+// The conditional directory walking supports for both `cargo build` and `cargo publish` scenarios.
+//
+// For the case of cargo build:
+// manifest_dir = {your_libfabric_directory}/bindings/rust/libfabric-sys
+//
+// For the case of cargo publish:
+// manifest_dir = {your_libfabric_directory}/target/package/ofi-libfabric-sys-x.y.z
+fn get_cargo_workspace_dir() -> &'static PathBuf {
+    CARGO_WORKSPACE_DIR.get_or_init(|| {
+        let manifest_dir = get_cargo_manifest_dir();
+        manifest_dir
+            .ancestors()
+            .skip(2) // Skip the 0th (myself) and 1st ancestor directories.
+            .take(2) // Check for both 2nd and 3rd ancestor directories.
+            .find(|dir| dir.join("Cargo.toml").exists())
+            .unwrap_or_else(|| panic!("Could not find parent directory with Cargo.toml"))
+            .to_path_buf()
+    })
+}
+
+#[derive(Debug)]
+struct RenameFunctions;
+
+// Rename function callback, such that those static inline functions are replaced without the "wrap_" prefix.
+// This way, the library is able to export such functions under `fi_xyz()`, rather than `wrap_fi_xyz()`.
+impl ParseCallbacks for RenameFunctions {
+    // This is to remove the prefix, from `wrap_fi_send()` --> `fi_send()` for Rust function.
+    fn item_name(&self, original_name: ItemInfo<'_>) -> Option<String> {
+        original_name.name.strip_prefix("wrap_").map(String::from)
+    }
+
+    // Explicitly use link_name for those marked with "wrap_" prefix, which indicates for static inline wrapper.
+    fn generated_link_name_override(&self, item_info: ItemInfo<'_>) -> Option<String> {
+        match item_info.kind {
+            ItemKind::Function => {
+                if item_info.name.starts_with("wrap_") {
+                    return Some(String::from(item_info.name));
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct CleanComments;
+
+// Header comments are plain C comments rather than markdown, so strip the indentation which
+// rustdoc would otherwise render (and run!) as code blocks, and escape the characters which it
+// would otherwise treat as intra-doc links or HTML tags.
+impl ParseCallbacks for CleanComments {
+    fn process_comment(&self, comment: &str) -> Option<String> {
+        let cleaned = comment
+            .lines()
+            .map(|line| {
+                line.trim()
+                    .replace('[', "\\[")
+                    .replace(']', "\\]")
+                    .replace('<', "\\<")
+                    .replace('>', "\\>")
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        Some(cleaned)
+    }
+}
+
+// Locate the markdown man pages (ex: man/fi_msg.3.md) of the libfabric source tree.
+// These are only available when building from the source tree, in which case None is returned.
+fn find_man_dir() -> Option<PathBuf> {
+    get_cargo_manifest_dir()
+        .ancestors()
+        .skip(2)
+        .take(2)
+        .map(|dir| dir.join("man"))
+        .find(|dir| dir.is_dir())
+}
+
+// Collect a one line summary per function, from the NAME section of each section 3 man page.
+//
+// The NAME section comes in two flavours, either:
+//   fi_getinfo, fi_freeinfo \- Obtain / free fabric interface information
+// or:
+//   fi_cq_read / fi_cq_readfrom / fi_cq_readerr
+//   : Read a completion from a completion queue
+//
+// Functions documented by several pages (ex: fi_close) are ambiguous, and thus skipped.
+fn parse_man_docs(man_dir: &Path) -> HashMap<String, String> {
+    let mut docs: HashMap<String, Option<String>> = HashMap::new();
+    let mut pages: Vec<_> = fs::read_dir(man_dir)
+        .map(|entries| entries.filter_map(|e| e.ok()).map(|e| e.path()).collect())
+        .unwrap_or_default();
+    pages.retain(|p| p.to_string_lossy().ends_with(".3.md"));
+    pages.sort();
+
+    let is_ident = |name: &&str| {
+        !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+
+    for path in pages {
+        let Ok(content) = fs::read_to_string(&path) else {
+            continue;
+        };
+        let page = path
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .trim_end_matches(".md")
+            .replacen(".3", "(3)", 1);
+
+        let mut entries: Vec<(Vec<String>, String)> = Vec::new();
+        let mut names: Vec<String> = Vec::new();
+        for line in content
+            .lines()
+            .skip_while(|line| *line != "# NAME")
+            .skip(1)
+            .take_while(|line| !line.starts_with("# "))
+        {
+            if let Some(desc) = line.strip_prefix(':') {
+                entries.push((std::mem::take(&mut names), desc.trim().to_string()));
+            } else if line.starts_with(char::is_whitespace) && !line.trim().is_empty() {
+                // Continuation of the previous description.
+                if let Some((_, desc)) = entries.last_mut() {
+                    desc.push(' ');
+                    desc.push_str(line.trim());
+                }
+            } else if let Some((lhs, desc)) = line.split_once(" \\- ") {
+                let names = lhs.split(',').map(str::trim).filter(is_ident);
+                entries.push((names.map(String::from).collect(), desc.trim().to_string()));
+            } else {
+                names.extend(
+                    line.split('/')
+                        .map(str::trim)
+                        .filter(is_ident)
+                        .map(String::from),
+                );
+            }
+        }
+
+        for (names, desc) in entries {
+            let mut desc = desc.replace("\\-", "-");
+            if !desc.ends_with('.') {
+                desc.push('.');
+            }
+            let doc = format!("{desc}\n\nSee the `{page}` man page.");
+            for name in names {
+                docs.entry(name)
+                    .and_modify(|doc| *doc = None)
+                    .or_insert_with(|| Some(doc.clone()));
+            }
+        }
+    }
+
+    docs.into_iter()
+        .filter_map(|(name, doc)| Some((name, doc?)))
+        .collect()
+}
+
+// bindgen offers no per-item hook for extra documentation, thus attach the man page summaries
+// by inserting a doc attribute ahead of each matching `pub fn fi_xyz(` declaration.
+fn add_man_docs(bindings: &str, docs: &HashMap<String, String>) -> String {
+    let mut out = String::with_capacity(bindings.len());
+    for line in bindings.lines() {
+        let trimmed = line.trim_start();
+        let name = trimmed
+            .strip_prefix("pub fn ")
+            .and_then(|rest| rest.split('(').next());
+        if let Some(doc) = name.and_then(|name| docs.get(name)) {
+            let indent = &line[..line.len() - trimmed.len()];
+            for doc_line in doc.lines() {
+                out.push_str(&format!("{indent}#[doc = {:?}]\n", format!(" {doc_line}")));
+            }
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
+
+// Mark the `fi_*` functions returning a value `#[must_use]`, as bindgen offers no attributes
+// for functions. Most return a status (`-FI_E*`), or a pointer which is null on failure, a call
+// ignoring it thus warns rather than losing the error.
+fn add_must_use(bindings: &str) -> String {
+    let lines: Vec<&str> = bindings.lines().collect();
+    let mut out = String::with_capacity(bindings.len());
+    for (i, line) in lines.iter().enumerate() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("pub fn fi_") {
+            // The return type follows the parenthesis closing the parameters, which may
+            // span several lines.
+            let end = lines[i..]
+                .iter()
+                .position(|line| line.trim_end().ends_with(';'))
+                .map_or(i, |end| i + end);
+            let declaration = lines[i..=end].join(" ");
+            let returns = declaration
+                .rfind(')')
+                .is_some_and(|close| declaration[close + 1..].trim_start().starts_with("->"));
+            if returns {
+                let indent = &line[..line.len() - trimmed.len()];
+                out.push_str(&format!("{indent}#[must_use]\n"));
+            }
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
+
+// Enums generated as non-exhaustive Rust enums, rather than bare integer constants.
+//
+// Only enums whose values are exclusively produced by the enum itself qualify, as receiving
+// a value without a matching variant from C would be undefined behaviour. A TryFrom impl from
+// the underlying integer is generated for each, to convert raw values safely.
+const RUSTIFIED_ENUMS: &[&str] = &[
+    "fi_av_type",
+    "fi_progress",
+    "fi_threading",
+    "fi_resource_mgmt",
+    "fi_ep_type",
+    "fi_hmem_iface",
+    "fi_datatype",
+    "fi_op",
+    "fi_collective_op",
+    "fi_wait_obj",
+    "fi_cq_format",
+    "fi_cq_wait_cond",
+    "fi_cntr_events",
+];
+
+// Append `impl TryFrom<repr> for fi_xyz` for each of the RUSTIFIED_ENUMS, by reading back the
+// variants from the generated code, such that the impls always match the headers in use.
+fn add_try_from_impls(bindings: &str) -> String {
+    let lines: Vec<&str> = bindings.lines().collect();
+    let mut out = String::from(bindings);
+    for name in RUSTIFIED_ENUMS {
+        let header = format!("pub enum {name} {{");
+        let Some(start) = lines.iter().position(|line| line.trim() == header) else {
+            panic!("Rustified enum {name} not found in the generated bindings");
+        };
+        let repr = lines[..start]
+            .iter()
+            .rev()
+            .take_while(|line| line.trim_start().starts_with("#["))
+            .find_map(|line| {
+                let line = line.trim();
+                line.strip_prefix("#[repr(")?.strip_suffix(")]")
+            })
+            .unwrap_or("u32");
+        let arms: String = lines[start + 1..]
+            .iter()
+            .map(|line| line.trim())
+            .take_while(|line| *line != "}")
+            .filter(|line| !line.starts_with("#["))
+            .filter_map(|line| line.trim_end_matches(',').split_once(" = "))
+            .map(|(variant, value)| format!("            {value} => Ok({name}::{variant}),\n"))
+            .collect();
+        out.push_str(&format!(
+            "impl ::core::convert::TryFrom<{repr}> for {name} {{\n    \
+                type Error = {repr};\n    \
+                fn try_from(value: {repr}) -> ::core::result::Result<Self, Self::Error> {{\n        \
+                    match value {{\n{arms}            _ => Err(value),\n        }}\n    }}\n}}\n"
+        ));
+    }
+    out
+}
+
+// Structs whose size and alignment are checked by tests/abi.rs, against what the C compiler
+// building wrapper.c comes up with. A mismatch means bindgen parsed the headers differently
+// (ex: host headers in a cross build), and every call passing these structs would be broken.
+const ABI_STRUCTS: &[&str] = &[
+    "fi_info",
+    "fi_fabric_attr",
+    "fi_domain_attr",
+    "fi_ep_attr",
+    "fi_tx_attr",
+    "fi_rx_attr",
+    "fi_av_attr",
+    "fi_cq_attr",
+    "fi_eq_attr",
+    "fi_cntr_attr",
+    "fi_mr_attr",
+    "fi_msg",
+    "fi_msg_tagged",
+    "fi_msg_rma",
+    "fi_cq_entry",
+    "fi_cq_msg_entry",
+    "fi_cq_data_entry",
+    "fi_cq_tagged_entry",
+    "fi_cq_err_entry",
+    "fi_eq_entry",
+    "fi_eq_cm_entry",
+    "fi_eq_err_entry",
+];
+
+// Structs of the headers only bound with the full feature, checked along with ABI_STRUCTS.
+const ABI_FULL_STRUCTS: &[&str] = &["fi_msg_atomic"];
+
+fn abi_structs(full: bool) -> impl Iterator<Item = &'static str> {
+    let full_structs = if full { ABI_FULL_STRUCTS } else { &[] };
+    ABI_STRUCTS.iter().chain(full_structs).copied()
+}
+
+// Socket address definitions of src/sockaddr.rs, checked by tests/abi.rs as well: the struct,
+// its family field, and its port field.
+const ABI_SOCKADDRS: &[(&str, &str, &str)] = &[
+    ("sockaddr_in", "sin_family", "sin_port"),
+    ("sockaddr_in6", "sin6_family", "sin6_port"),
+];
+
+// C side of the ABI checks, compiled along with wrapper.c. The alignment is taken through
+// offsetof() rather than _Alignof, which older MSVC releases lack.
+fn abi_checks_c(full: bool) -> String {
+    let mut out = String::from(
+        "/* Generated by build.rs, see ABI_STRUCTS. */\n#include <stddef.h>\n#include \"wrapper.h\"\n\n",
+    );
+    for name in abi_structs(full) {
+        out.push_str(&format!(
+            "struct wrap_abi_{name} {{ char c; struct {name} s; }};\n\
+             const size_t wrap_abi_size_{name} = sizeof(struct {name});\n\
+             const size_t wrap_abi_align_{name} = offsetof(struct wrap_abi_{name}, s);\n"
+        ));
+    }
+    out.push_str(
+        "\n#ifdef _WIN32\n#include <ws2tcpip.h>\n#else\n#include <netinet/in.h>\n#endif\n\n",
+    );
+    out.push_str(
+        "const int wrap_abi_AF_INET = AF_INET;\nconst int wrap_abi_AF_INET6 = AF_INET6;\n",
+    );
+    for (name, family, port) in ABI_SOCKADDRS {
+        out.push_str(&format!(
+            "struct wrap_abi_{name} {{ char c; struct {name} s; }};\n\
+             const size_t wrap_abi_size_{name} = sizeof(struct {name});\n\
+             const size_t wrap_abi_align_{name} = offsetof(struct wrap_abi_{name}, s);\n\
+             const size_t wrap_abi_family_{name} = offsetof(struct {name}, {family});\n\
+             const size_t wrap_abi_port_{name} = offsetof(struct {name}, {port});\n"
+        ));
+    }
+    out
+}
+
+// Rust side of the ABI checks, one test per struct, included by tests/abi.rs.
+fn abi_checks_rs(full: bool) -> String {
+    let mut out = String::from("// Generated by build.rs, see ABI_STRUCTS.\n");
+    for name in abi_structs(full) {
+        out.push_str(&format!(
+            "unsafe extern \"C\" {{\n    \
+                 static wrap_abi_size_{name}: usize;\n    \
+                 static wrap_abi_align_{name}: usize;\n\
+             }}\n\
+             #[test]\n\
+             fn test_{name}_layout() {{\n    \
+                 assert_eq!(::std::mem::size_of::<{name}>(), unsafe {{ wrap_abi_size_{name} }}, \"size of struct {name}\");\n    \
+                 assert_eq!(::std::mem::align_of::<{name}>(), unsafe {{ wrap_abi_align_{name} }}, \"alignment of struct {name}\");\n\
+             }}\n"
+        ));
+    }
+    out.push_str(
+        "unsafe extern \"C\" {\n    \
+             static wrap_abi_AF_INET: ::core::ffi::c_int;\n    \
+             static wrap_abi_AF_INET6: ::core::ffi::c_int;\n\
+         }\n\
+         #[test]\n\
+         fn test_address_families() {\n    \
+             assert_eq!(::ofi_libfabric_sys::sockaddr::AF_INET as i32, unsafe { wrap_abi_AF_INET });\n    \
+             assert_eq!(::ofi_libfabric_sys::sockaddr::AF_INET6 as i32, unsafe { wrap_abi_AF_INET6 });\n\
+         }\n",
+    );
+    for (name, family, port) in ABI_SOCKADDRS {
+        out.push_str(&format!(
+            "unsafe extern \"C\" {{\n    \
+                 static wrap_abi_size_{name}: usize;\n    \
+                 static wrap_abi_align_{name}: usize;\n    \
+                 static wrap_abi_family_{name}: usize;\n    \
+                 static wrap_abi_port_{name}: usize;\n\
+             }}\n\
+             #[test]\n\
+             fn test_{name}_layout() {{\n    \
+                 use ::ofi_libfabric_sys::sockaddr::{name};\n    \
+                 assert_eq!(::std::mem::size_of::<{name}>(), unsafe {{ wrap_abi_size_{name} }}, \"size of struct {name}\");\n    \
+                 assert_eq!(::std::mem::align_of::<{name}>(), unsafe {{ wrap_abi_align_{name} }}, \"alignment of struct {name}\");\n    \
+                 assert_eq!(::std::mem::offset_of!({name}, {family}), unsafe {{ wrap_abi_family_{name} }}, \"offset of {name}.{family}\");\n    \
+                 assert_eq!(::std::mem::offset_of!({name}, {port}), unsafe {{ wrap_abi_port_{name} }}, \"offset of {name}.{port}\");\n\
+             }}\n"
+        ));
+    }
+    out
+}
+
+// Libfabric releases for which a `libfabric_ge_{major}_{minor}` cfg is emitted, oldest first.
+//
+// APIs introduced after the oldest supported release are gated behind these (ex: the profiling
+// interface behind `libfabric_ge_1_20`), so the bindings still compile against older installs.
+const KNOWN_VERSIONS: &[(u32, u32)] = &[
+    (1, 18),
+    (1, 19),
+    (1, 20),
+    (1, 21),
+    (1, 22),
+    (2, 0),
+    (2, 1),
+    (2, 2),
+    (2, 3),
+];
+
+// Read FI_MAJOR_VERSION and FI_MINOR_VERSION from the fabric.h found under the include paths,
+// as the headers are what the bindings are generated from.
+fn detect_header_version(include_paths: &[PathBuf]) -> (u32, u32) {
+    let fabric_h = include_paths
+        .iter()
+        .flat_map(|dir| [dir.join("rdma").join("fabric.h"), dir.join("fabric.h")])
+        .find(|path| path.is_file())
+        .unwrap_or_else(|| panic!("Could not find rdma/fabric.h under {include_paths:?}"));
+    let content = fs::read_to_string(&fabric_h).unwrap();
+    let define = |name: &str| -> u32 {
+        content
+            .lines()
+            .find_map(|line| {
+                let mut tokens = line.split_whitespace();
+                match (tokens.next(), tokens.next(), tokens.next()) {
+                    (Some("#define"), Some(n), Some(value)) if n == name => value.parse().ok(),
+                    _ => None,
+                }
+            })
+            .unwrap_or_else(|| panic!("Could not find {name} in {}", fabric_h.display()))
+    };
+    (define("FI_MAJOR_VERSION"), define("FI_MINOR_VERSION"))
+}
+
+// The manifest of the wrapped inline functions, see wrapper.manifest.
+struct Manifest {
+    headers: Vec<ManifestHeader>,
+    skip: HashSet<String>,
+}
+
+struct ManifestHeader {
+    name: String,
+    // The release which introduced the header, if later than 1.18.
+    since: Option<(u32, u32)>,
+    // Whether the header is only bound with the full feature.
+    full: bool,
+}
+
+fn parse_manifest(path: &Path) -> Manifest {
+    let content = fs::read_to_string(path)
+        .unwrap_or_else(|err| panic!("Could not read {}: {err}", path.display()));
+    let mut manifest = Manifest {
+        headers: Vec::new(),
+        skip: HashSet::new(),
+    };
+    for (i, line) in content.lines().enumerate() {
+        let malformed = || panic!("{}:{}: malformed line: {line}", path.display(), i + 1);
+        let mut words: Vec<&str> = line.split_whitespace().collect();
+        let full = words.len() > 2 && words[0] == "header" && words.last() == Some(&"full");
+        if full {
+            words.pop();
+        }
+        let mut header = |name: &str, since| {
+            manifest.headers.push(ManifestHeader {
+                name: name.to_string(),
+                since,
+                full,
+            })
+        };
+        match words[..] {
+            [] => {}
+            [comment, ..] if comment.starts_with('#') => {}
+            ["header", name] => header(name, None),
+            ["header", name, ">=", version] => {
+                let Some((major, minor)) = version.split_once('.') else {
+                    malformed()
+                };
+                let (Ok(major), Ok(minor)) = (major.parse(), minor.parse()) else {
+                    malformed()
+                };
+                header(name, Some((major, minor)));
+            }
+            ["skip", name] => {
+                manifest.skip.insert(name.to_string());
+            }
+            _ => malformed(),
+        }
+    }
+    manifest
+}
+
+// A static inline function of the headers.
+struct InlineFn {
+    name: String,
+    ret: String,
+    // The parameters as declared, and their names, to forward them.
+    params: String,
+    args: Vec<String>,
+}
+
+// Split `text` at the commas outside of parentheses, ex: those between the parameters of a
+// function taking a callback.
+fn split_top_level(text: &str) -> Vec<&str> {
+    let (mut parts, mut depth, mut start) = (Vec::new(), 0, 0);
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(&text[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+// The name of a parameter: the identifier after `(*` for function pointers, otherwise the last
+// one, after any array brackets.
+fn param_name(param: &str) -> Option<String> {
+    let param = match param.split_once("(*") {
+        Some((_, pointer)) => pointer.split_once(')')?.0,
+        None => param.split('[').next()?,
+    };
+    param
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .rfind(|token| !token.is_empty())
+        .map(String::from)
+}
+
+// The static inline `fi_*` functions defined in the `content` of a header. The name is the
+// last identifier before the opening parenthesis, the return type coming before it, on the
+// same line or the one before, and the parameters run to the matching parenthesis.
+fn parse_inline_fns(content: &str) -> Vec<InlineFn> {
+    let collapse = |text: &str| text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let mut fns = Vec::new();
+    for definition in content.split("static inline").skip(1) {
+        let Some((signature, rest)) = definition.split_once('(') else {
+            continue;
+        };
+        let Some(name) = signature
+            .split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .rfind(|token| !token.is_empty())
+        else {
+            continue;
+        };
+        if !name.starts_with("fi_") {
+            continue;
+        }
+        let mut depth = 1;
+        let Some(end) = rest.find(|c| {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                _ => {}
+            }
+            depth == 0
+        }) else {
+            continue;
+        };
+        let params = collapse(&rest[..end]);
+        let args = match params.as_str() {
+            "" | "void" => Vec::new(),
+            _ => split_top_level(&params)
+                .into_iter()
+                .map(|param| param_name(param).unwrap_or_default())
+                .collect(),
+        };
+        // Without the attribute macros of the headers, ex: FI_DEPRECATED_FUNC, whose warnings
+        // the calls of the wrappers raise instead.
+        let ret = signature.trim_end().strip_suffix(name).unwrap();
+        let ret = ret
+            .split_whitespace()
+            .filter(|token| !token.starts_with("FI_"));
+        fns.push(InlineFn {
+            name: name.to_string(),
+            ret: ret.collect::<Vec<_>>().join(" "),
+            params,
+            args,
+        });
+    }
+    fns
+}
+
+// The `wrap_` functions of the inline functions of the headers listed in wrapper.manifest, as
+// the declarations of wrappers.h and the definitions of wrappers.c, along with the names of
+// the functions wrapped or skipped. Without `full`, the functions of the headers tagged so
+// are left out, and only count as skipped.
+fn generate_wrappers(
+    include_paths: &[PathBuf],
+    version: (u32, u32),
+    full: bool,
+) -> (String, String, HashSet<String>) {
+    let manifest_path = get_cargo_manifest_dir().join("wrapper.manifest");
+    println!("cargo:rerun-if-changed={}", manifest_path.display());
+    let manifest = parse_manifest(&manifest_path);
+    let mut h = String::from(
+        "/* Generated by build.rs from wrapper.manifest. */\n#ifndef __WRAPPERS_H__\n#define __WRAPPERS_H__\n\n#include \"wrapper.h\"\n",
+    );
+    let mut c = String::from(
+        "/* Generated by build.rs from wrapper.manifest. */\n#include \"wrappers.h\"\n",
+    );
+    let mut covered = manifest.skip.clone();
+    for ManifestHeader {
+        name: header,
+        since,
+        full: full_only,
+    } in &manifest.headers
+    {
+        if since.is_some_and(|since| version < since) {
+            continue;
+        }
+        let path = include_paths
+            .iter()
+            .map(|dir| dir.join(header))
+            .find(|path| path.is_file())
+            .unwrap_or_else(|| panic!("Could not find {header}, listed in wrapper.manifest"));
+        let content = fs::read_to_string(&path).unwrap();
+        let fns = parse_inline_fns(&content);
+        if *full_only && !full {
+            covered.extend(fns.into_iter().map(|f| f.name));
+            continue;
+        }
+        if fns.is_empty() {
+            continue;
+        }
+        h.push_str(&format!("\n/* Static inline functions of {header}. */\n"));
+        c.push_str(&format!("\n/* Static inline functions of {header}. */\n"));
+        for f in fns {
+            if !covered.insert(f.name.clone()) {
+                continue;
+            }
+            let (name, ret, params) = (&f.name, &f.ret, &f.params);
+            let call = format!("{name}({})", f.args.join(", "));
+            let body = match ret.as_str() {
+                "void" => call,
+                _ => format!("return {call}"),
+            };
+            // Pointers bind to the name, ex: `struct fi_info *wrap_fi_allocinfo(void)`.
+            let sep = if ret.ends_with('*') { "" } else { " " };
+            h.push_str(&format!("{ret}{sep}wrap_{name}({params});\n"));
+            c.push_str(&format!(
+                "\n{ret}{sep}wrap_{name}({params})\n{{\n\t{body};\n}}\n"
+            ));
+        }
+    }
+    h.push_str("\n#endif /* __WRAPPERS_H__ */\n");
+    (h, c, covered)
+}
+
+// Static inline functions of the headers with no `wrap_` counterpart, which are thus missing
+// from the bindings (bindgen only sees their declarations), as their header is not listed in
+// wrapper.manifest. Reported by main() as build warnings, so a new header of a libfabric
+// update does not go unnoticed.
+fn missing_wrappers(include_paths: &[PathBuf], covered: &HashSet<String>) -> Vec<String> {
+    let Some(rdma_dir) = include_paths
+        .iter()
+        .flat_map(|dir| [dir.join("rdma"), dir.clone()])
+        .find(|dir| dir.join("fabric.h").is_file())
+    else {
+        return Vec::new();
+    };
+    let headers = [rdma_dir.clone(), rdma_dir.join("providers")]
+        .into_iter()
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flatten()
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "h"));
+    let mut missing = Vec::new();
+    for header in headers {
+        let content = fs::read_to_string(&header).unwrap_or_default();
+        for f in parse_inline_fns(&content) {
+            if !covered.contains(&f.name) {
+                missing.push(format!("{} ({})", f.name, header.display()));
+            }
+        }
+    }
+    missing.sort();
+    missing
+}
+
+// Providers libfabric can be configured with, as listed by FI_PROVIDER_SETUP() in configure.ac.
+const KNOWN_PROVIDERS: &[&str] = &[
+    "psm2",
+    "psm3",
+    "sockets",
+    "verbs",
+    "efa",
+    "cxi",
+    "usnic",
+    "udp",
+    "tcp",
+    "rxm",
+    "mrail",
+    "rxd",
+    "shm",
+    "sm2",
+    "ucx",
+    "lpp",
+    "perf",
+    "trace",
+    "profile",
+    "monitor",
+    "hook_debug",
+    "hook_hmem",
+    "dmabuf_peer_mem",
+    "opx",
+    "lnx",
+];
+
+// Emit the cfgs for this crate, and forward them to dependents through the `links` metadata, as
+// DEP_LIBFABRIC_VERSION, DEP_LIBFABRIC_CFGS and DEP_LIBFABRIC_CHECK_CFGS.
+//
+// These are `libfabric_ge_{major}_{minor}` for the version of the headers, and
+// `libfabric_provider_{name}` for the providers a vendored build was configured with. Nothing is
+// known of the providers of an installed library until it is loaded, see fi_getinfo().
+fn emit_cfgs(version: (u32, u32), providers: &[String]) {
+    let cfg_name = |(major, minor): (u32, u32)| format!("libfabric_ge_{major}_{minor}");
+    let provider_cfg = |name: &str| format!("libfabric_provider_{name}");
+    let all: Vec<String> = KNOWN_VERSIONS
+        .iter()
+        .copied()
+        .map(cfg_name)
+        .chain(KNOWN_PROVIDERS.iter().map(|name| provider_cfg(name)))
+        .collect();
+    let enabled: Vec<String> = KNOWN_VERSIONS
+        .iter()
+        .copied()
+        .filter(|known| *known <= version)
+        .map(cfg_name)
+        .chain(providers.iter().map(|name| provider_cfg(name)))
+        .collect();
+
+    if version < KNOWN_VERSIONS[0] {
+        println!(
+            "cargo:warning=Libfabric {}.{} is older than the oldest supported release {}.{}",
+            version.0, version.1, KNOWN_VERSIONS[0].0, KNOWN_VERSIONS[0].1
+        );
+    }
+    for cfg in &all {
+        println!("cargo:rustc-check-cfg=cfg({cfg})");
+    }
+    for cfg in &enabled {
+        println!("cargo:rustc-cfg={cfg}");
+    }
+    println!("cargo:version={}.{}", version.0, version.1);
+    println!("cargo:cfgs={}", enabled.join(","));
+    println!("cargo:check_cfgs={}", all.join(","));
+}
+
+// The providers enabled in the config.h written by configure, as `#define HAVE_{NAME} 1`. Those
+// built as a DSO (HAVE_{NAME}_DL) are enabled as well.
+fn configured_providers(config_h: &Path) -> Vec<String> {
+    let config = fs::read_to_string(config_h)
+        .unwrap_or_else(|e| panic!("Could not read {}: {e}", config_h.display()));
+    let defines: HashMap<&str, &str> = config
+        .lines()
+        .filter_map(|line| {
+            let mut words = line.strip_prefix("#define ")?.split_whitespace();
+            Some((words.next()?, words.next()?))
+        })
+        .collect();
+    KNOWN_PROVIDERS
+        .iter()
+        .filter(|name| defines.get(format!("HAVE_{}", name.to_uppercase()).as_str()) == Some(&"1"))
+        .map(|name| name.to_string())
+        .collect()
+}
+
+// Returns the providers configure enabled.
+fn build_libfabric(install_dir: &Path, cross: Option<&CrossTarget>) -> Vec<String> {
+    // Build the libfabric.so on the fly, such that its symbols can be accessed during the Rust binding compilation.
+    // This way, the libfabric.so library can later be dynamically linked during run-time.
+    // Else, you will receive the following error; = note: ld: cannot find -lfabric
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let libfabric_rsync_dir = out_dir.join("vendored");
+    let cargo_manifest_dir = get_cargo_manifest_dir();
+    let cargo_workspace_dir = get_cargo_workspace_dir();
+
+    println!(
+        "cargo:warning=cargo_manifest_dir {}",
+        cargo_manifest_dir.display()
+    );
+    println!(
+        "cargo:warning=cargo_workspace_dir {}",
+        cargo_workspace_dir.display()
+    );
+    println!(
+        "cargo:warning=libfabric_rsync_dir {}",
+        libfabric_rsync_dir.display()
+    );
+    println!("cargo:warning=install_dir {}", install_dir.display());
+    println!("cargo:warning=out_dir {}", out_dir.display());
+
+    // Rsync the libfabric codebase into OUT_DIR, as the compilation process should never modify the source directory.
+    if !libfabric_rsync_dir.exists() {
+        println!("cargo:warning=Running rsync as part of libfabric compilation.");
+        Command::new("rsync")
+            .arg("-av")
+            .arg("--exclude=vendored")
+            .arg(format!("{}/", cargo_workspace_dir.display()))
+            .arg(&libfabric_rsync_dir)
+            .status()
+            .expect("Failed to copy vendor directory");
+    }
+
+    // Run autogen.
+    println!("cargo:warning=Running autogen.sh as part of libfabric compilation.");
+    assert!(
+        Command::new("sh")
+            .current_dir(&libfabric_rsync_dir)
+            .arg("autogen.sh")
+            .status()
+            .unwrap()
+            .success()
+    );
+
+    // Run configure.
+    //
+    // Note that the binary is compiled on-the-fly to extract relevant header files under the compilation folder.
+    // The arguments provided for such compilation is not important, as the Libfabric API interface stays the same.
+    // During run-time, a separate user compiled library should be dynamically loaded via exporting the 'LD_LIBRARY_PATH' environment variable.
+    //
+    // Cross builds configure for the target, using the same C compiler as the wrapper build.
+    println!("cargo:warning=Running configure as part of libfabric compilation.");
+    let mut configure = Command::new("sh");
+    configure
+        .current_dir(&libfabric_rsync_dir)
+        .arg("configure")
+        .arg(format!("--prefix={}", install_dir.display()));
+    if let Some(cross) = cross {
+        let compiler = cc::Build::new().get_compiler();
+        configure
+            .arg(format!("--host={}", cross.target))
+            .env("CC", compiler.path())
+            .env("CFLAGS", compiler.cflags_env());
+        if let Some(sysroot) = &cross.sysroot {
+            configure.env("CPPFLAGS", format!("--sysroot={}", sysroot.display()));
+            configure.env("LDFLAGS", format!("--sysroot={}", sysroot.display()));
+        }
+    }
+    assert!(configure.status().unwrap().success());
+
+    // Run make install.
+    println!("cargo:warning=Running make install as part of libfabric compilation.");
+    assert!(
+        Command::new("make")
+            .current_dir(&libfabric_rsync_dir)
+            .arg("-j")
+            .arg("install")
+            .status()
+            .unwrap()
+            .success()
+    );
+
+    println!("cargo:warning=Libfabric successfully compiled.");
+    configured_providers(&libfabric_rsync_dir.join("config.h"))
+}
+
+// Provider extension headers, bound when the matching feature is enabled (ex: --features efa).
+//
+// Each entry is the feature, the header, and where the header lives in the source tree. Installs
+// carry the headers of the providers they were built with next to the core ones, under rdma/.
+const PROVIDER_EXTENSIONS: &[(&str, &str, &str)] = &[
+    ("cxi", "fi_cxi_ext.h", "prov/cxi/include"),
+    ("efa", "fi_ext_efa.h", "prov/efa/src"),
+    ("psm2", "fi_ext_psm2.h", "prov/psm2/include"),
+    ("usnic", "fi_ext_usnic.h", "prov/usnic/src"),
+];
+
+// Locate the extension headers of the enabled provider features, in the include paths first,
+// then in the source tree. Their items are covered by the existing fi_/FI_ allowlist, but for the
+// cxi_/cxip_ types of fi_cxi_ext.h.
+fn find_provider_extensions(include_paths: &[PathBuf]) -> Vec<PathBuf> {
+    PROVIDER_EXTENSIONS
+        .iter()
+        .filter(|(feature, _, _)| {
+            env::var_os(format!("CARGO_FEATURE_{}", feature.to_uppercase())).is_some()
+        })
+        .map(|(feature, header, source_dir)| {
+            include_paths
+                .iter()
+                .map(|dir| dir.join(header))
+                .chain([get_cargo_workspace_dir().join(source_dir).join(header)])
+                .find(|path| path.exists())
+                .unwrap_or_else(|| {
+                    panic!("{header} not found, which the {feature} feature requires")
+                })
+        })
+        .collect()
+}
+
+// Target of a cross build, see cross_target().
+struct CrossTarget {
+    target: String,
+    sysroot: Option<PathBuf>,
+}
+
+// Detect a cross build (ex: cargo build --target aarch64-unknown-linux-gnu on an x86_64 host).
+//
+// The sysroot holding the target headers and libraries is looked up the same way the pkg-config
+// crate does, such that one setting serves both: PKG_CONFIG_SYSROOT_DIR_<target>, then
+// PKG_CONFIG_SYSROOT_DIR_<target_with_underscores>, TARGET_PKG_CONFIG_SYSROOT_DIR and finally
+// PKG_CONFIG_SYSROOT_DIR.
+fn cross_target() -> Option<CrossTarget> {
+    let target = env::var("TARGET").unwrap();
+    if target == env::var("HOST").unwrap() {
+        return None;
+    }
+    let sysroot = [
+        format!("PKG_CONFIG_SYSROOT_DIR_{target}"),
+        format!("PKG_CONFIG_SYSROOT_DIR_{}", target.replace('-', "_")),
+        "TARGET_PKG_CONFIG_SYSROOT_DIR".to_string(),
+        "PKG_CONFIG_SYSROOT_DIR".to_string(),
+    ]
+    .iter()
+    .find_map(|var| {
+        println!("cargo:rerun-if-env-changed={var}");
+        env::var_os(var)
+    })
+    .map(PathBuf::from);
+    Some(CrossTarget { target, sysroot })
+}
+
+// Preprocessor definitions libfabric.vcxproj builds with, which its headers under
+// include/windows rely on (ex: _WINSOCKAPI_ keeps windows.h from pulling in the legacy winsock.h,
+// which conflicts with winsock2.h).
+const WINDOWS_DEFINES: &[(&str, &str)] = &[
+    ("WIN32", "1"),
+    ("_WINSOCKAPI_", ""),
+    ("_CRT_SECURE_NO_WARNINGS", "1"),
+    ("_WINSOCK_DEPRECATED_NO_WARNINGS", "1"),
+];
+
+// Locate a Windows build of libfabric, for which there is no pkg-config file.
+//
+// LIBFABRIC_DIR points to the libfabric source tree the library was built from (defaults to
+// the tree this crate lives in), and LIBFABRIC_LIB_DIR to the directory holding the
+// libfabric.lib import library produced by libfabric.sln (ex: x64\Release-v142).
+fn find_windows_libfabric() -> (PathBuf, Vec<PathBuf>) {
+    println!("cargo:rerun-if-env-changed=LIBFABRIC_DIR");
+    println!("cargo:rerun-if-env-changed=LIBFABRIC_LIB_DIR");
+    let libfabric_dir = env::var_os("LIBFABRIC_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| get_cargo_workspace_dir().clone());
+    let lib_dir = env::var_os("LIBFABRIC_LIB_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| {
+            panic!("LIBFABRIC_LIB_DIR must point to the directory holding libfabric.lib")
+        });
+    let include_dir = libfabric_dir.join("include");
+    (
+        lib_dir,
+        vec![
+            libfabric_dir.clone(),
+            include_dir.clone(),
+            // POSIX compatibility headers (ex: sys/uio.h), on top of winsock.
+            include_dir.join("windows"),
+            include_dir.join("rdma"),
+            include_dir.join("rdma").join("providers"),
+        ],
+    )
+}
+
+// Drop repeated paths, keeping the first occurrence so the search order is preserved.
+fn dedup_paths(paths: impl IntoIterator<Item = PathBuf>) -> Vec<PathBuf> {
+    let mut seen = HashSet::new();
+    paths
+        .into_iter()
+        .filter(|path| seen.insert(path.clone()))
+        .collect()
+}
+
+fn main() {
+    // Note that cfg!(target_os) would describe the host running this build script, rather than
+    // the target the binding is compiled for.
+    let target_os = env::var("CARGO_CFG_TARGET_OS").unwrap();
+    let windows = target_os == "windows";
+    let macos = target_os == "macos";
+    assert!(
+        matches!(target_os.as_str(), "linux" | "macos" | "windows"),
+        "This binding is only compatible with Linux, macOS and Windows, not {target_os}."
+    );
+
+    // Link asan library.
+    let asan = cfg!(feature = "asan");
+    assert!(
+        !(asan && windows),
+        "The asan feature is not supported on Windows."
+    );
+    // Apple clang ships the runtime as clang_rt.asan_osx_dynamic instead, which the driver links
+    // in by itself given the sanitizer flag.
+    if asan && macos {
+        println!("cargo:rustc-link-arg=-fsanitize=address");
+    } else if asan {
+        println!("cargo:rustc-link-lib=asan");
+    }
+
+    // Link libfabric library, built as libfabric.dll (with its libfabric.lib import library) on Windows.
+    // With the dlopen feature, it is loaded at runtime instead, see src/dlopen.rs.
+    let dlopen = cfg!(feature = "dlopen");
+    match windows {
+        _ if dlopen => {}
+        true => println!("cargo:rustc-link-lib=libfabric"),
+        false => println!("cargo:rustc-link-lib=fabric"),
+    }
+
+    // Conditional reference of header files from the source code, versus from the already installed library,
+    // based on the vendor feature flag (ex: cargo build --features vendored).
+    let vendored = cfg!(feature = "vendored");
+    println!(
+        "cargo:warning=Building the binding with vendored: {}",
+        vendored
+    );
+
+    assert!(
+        !(vendored && windows),
+        "The vendored feature is not supported on Windows, build libfabric.sln instead and set LIBFABRIC_LIB_DIR."
+    );
+    assert!(
+        !(vendored && dlopen),
+        "The vendored and dlopen features are exclusive, the library being linked to in the former."
+    );
+
+    let cross = cross_target();
+    if let Some(cross) = &cross {
+        println!(
+            "cargo:warning=Cross-compiling for {}, sysroot: {:?}",
+            cross.target, cross.sysroot
+        );
+    }
+    let sysroot = cross.as_ref().and_then(|cross| cross.sysroot.as_deref());
+
+    let mut providers = Vec::new();
+    let (lib_paths, include_paths) = match vendored {
+        // Headers only, those of an install if pkg-config finds one, otherwise those of this
+        // source tree, such that hosts without libfabric build the bindings as well.
+        false if dlopen => {
+            let lib = pkg_config::Config::new()
+                .cargo_metadata(false)
+                .probe("libfabric");
+            let include_roots = match lib {
+                Ok(lib) if !lib.include_paths.is_empty() => lib.include_paths,
+                _ => vec![get_cargo_workspace_dir().join("include")],
+            };
+            (
+                Vec::new(),
+                dedup_paths(include_roots.iter().flat_map(|dir| {
+                    [
+                        dir.clone(),
+                        dir.join("rdma"),
+                        dir.join("rdma").join("providers"),
+                    ]
+                })),
+            )
+        }
+        false if windows => {
+            let (lib_path, include_paths) = find_windows_libfabric();
+            (vec![lib_path], include_paths)
+        }
+        true => {
+            // Vendored option, build the libfabric based on the available source code.
+            let install_dir = PathBuf::from(env::var("OUT_DIR").unwrap()).join("install");
+            providers = build_libfabric(&install_dir, cross.as_ref());
+            println!("cargo:warning=Vendored providers: {}", providers.join(", "));
+
+            // Return relevant paths using global variables.
+            let libfabric_dir = get_cargo_workspace_dir();
+
+            (
+                // Provide static link search path.
+                // Vendored option, thus should refer to the compiled library's installation path.
+                vec![install_dir.join("lib")],
+                vec![
+                    libfabric_dir.clone(),
+                    libfabric_dir.join("include"),
+                    libfabric_dir.join("include").join("rdma"),
+                    libfabric_dir.join("include").join("rdma").join("providers"),
+                ],
+            )
+        }
+        false => {
+            // Non-vendored option, and thus should refer to the already installed library's path.
+            //
+            // Paths in the sysroot are already prefixed by pkg-config, but system directories
+            // (ex: /usr/include) are left out of its output altogether, in which case they must
+            // be resolved against the sysroot rather than the host.
+            //
+            // pkg-config may report several directories (ex: spack, or distributions splitting
+            // the headers of dependencies out), all of which are searched, in order.
+            let lib = pkg_config::Config::new().probe("libfabric").unwrap();
+            let system_dir = |dir: &str| vec![sysroot.unwrap_or(Path::new("/")).join(dir)];
+            let include_roots = match lib.include_paths.is_empty() {
+                true => system_dir("usr/include"),
+                false => lib.include_paths,
+            };
+            let link_paths = match lib.link_paths.is_empty() {
+                true => system_dir("usr/lib"),
+                false => lib.link_paths,
+            };
+
+            // Return relevant paths.
+            (
+                // Provide static link search path.
+                // Non-vendored option, and thus should refer to the already installed library's path.
+                dedup_paths(link_paths),
+                dedup_paths(include_roots.iter().flat_map(|dir| {
+                    [
+                        dir.clone(),
+                        dir.join("rdma"),
+                        dir.join("rdma").join("providers"),
+                    ]
+                })),
+            )
+        }
+    };
+
+    // The first link path is the one holding libfabric, the others are for its dependencies.
+    for path in &lib_paths {
+        println!("cargo:rustc-link-search=native={}", path.display());
+    }
+    if let Some(lib_path) = lib_paths.first() {
+        println!("cargo:lib_dir={}", lib_path.display());
+        // System Integrity Protection strips DYLD_LIBRARY_PATH from processes spawned through
+        // system binaries, so libfabric.dylib is located through an rpath instead. Link args do
+        // not carry over to dependent crates, which can add the same from DEP_LIBFABRIC_LIB_DIR.
+        if macos {
+            println!("cargo:rustc-link-arg=-Wl,-rpath,{}", lib_path.display());
+        }
+    }
+    lib_paths
+        .iter()
+        .enumerate()
+        .for_each(|(i, x)| println!("cargo:warning=lib_paths[{}]: {}", i, x.display()));
+    include_paths
+        .iter()
+        .enumerate()
+        .for_each(|(i, x)| println!("cargo:warning=include_paths[{}]: {}", i, x.display()));
+
+    // Detect the Libfabric version of the headers, and gate the newer APIs accordingly.
+    let version = detect_header_version(&include_paths);
+    println!(
+        "cargo:warning=Libfabric header version: {}.{}",
+        version.0, version.1
+    );
+    emit_cfgs(version, &providers);
+    let out_path = PathBuf::from(env::var("OUT_DIR").unwrap());
+    // The headers bound, see wrapper.manifest, whose `full` ones wrapper.h includes with
+    // WRAP_FI_FULL defined.
+    let full = cfg!(feature = "full");
+    let (wrappers_h, wrappers_c, covered) = generate_wrappers(&include_paths, version, full);
+    fs::write(out_path.join("wrappers.h"), wrappers_h).expect("Couldn't write wrappers.h!");
+    fs::write(out_path.join("wrappers.c"), wrappers_c).expect("Couldn't write wrappers.c!");
+    for missing in missing_wrappers(&include_paths, &covered) {
+        println!("cargo:warning=Static inline function without a wrapper: {missing}");
+    }
+
+    // Compiles the wrapper.[ch], along with the wrappers.[ch] generated from wrapper.manifest.
+    //
+    // This generates a libwrapper.a, which is statically linked against your Rust application code.
+    // Then, from the statically linked single executable, libfabric.so is dynamically called via libwrapper.
+    //
+    // The goal of the wrappers.[ch] is to create translation unit for "static inline" functions, such that they can be properly FFI'ed.
+    // TODO: https://github.com/rust-lang/rust-bindgen/discussions/2405
+    //
+    // The C side of the ABI checks is compiled in as well, and only pulled out of the archive by
+    // tests/abi.rs, which references it.
+    fs::write(out_path.join("abi.c"), abi_checks_c(full)).expect("Couldn't write abi.c!");
+    fs::write(out_path.join("abi.rs"), abi_checks_rs(full)).expect("Couldn't write abi.rs!");
+    let mut builder = cc::Build::new();
+    let cargo_manifest_dir = get_cargo_manifest_dir().display();
+    // Listed, as the rerun-if-changed of wrapper.manifest disables the default of rerunning on
+    // changes to any file of the crate.
+    for file in ["wrapper.h", "wrapper.c", "dlopen.c"] {
+        println!("cargo:rerun-if-changed={cargo_manifest_dir}/{file}");
+    }
+    builder.file(format!("{cargo_manifest_dir}/wrapper.c"));
+    builder.file(out_path.join("wrappers.c"));
+    builder.file(out_path.join("abi.c"));
+    if dlopen {
+        builder.file(format!("{cargo_manifest_dir}/dlopen.c"));
+    }
+    builder.include(get_cargo_manifest_dir());
+    builder.include(&out_path);
+    for path in &include_paths {
+        builder.include(format!("{}", path.display()));
+    }
+    if asan {
+        builder.flag("-fsanitize=address");
+        builder.flag("-fsanitize-recover=address");
+    }
+    if windows {
+        for (name, value) in WINDOWS_DEFINES {
+            builder.define(name, *value);
+        }
+    }
+    if full {
+        builder.define("WRAP_FI_FULL", None);
+    }
+    // cc already picks the target's compiler, but not where its system headers live.
+    if let Some(sysroot) = sysroot {
+        builder.flag(format!("--sysroot={}", sysroot.display()));
+    }
+    builder.compile("wrapper");
+
+    // Finally, build the Rust binding.
+    let mut builder = bindgen::Builder::default()
+        .header(out_path.join("wrappers.h").display().to_string())
+        .clang_arg(format!("-I{cargo_manifest_dir}"))
+        .clang_args(
+            include_paths
+                .iter()
+                .map(|dir| format!("-I{}", dir.display())),
+        );
+    for header in find_provider_extensions(&include_paths) {
+        println!(
+            "cargo:warning=Provider extension header: {}",
+            header.display()
+        );
+        builder = builder.header(header.display().to_string());
+    }
+    // bindgen passes the Rust target on to clang by itself (ex: --target=aarch64-unknown-linux-gnu),
+    // the sysroot keeps clang from parsing the host's system headers, and thus their layouts.
+    if let Some(sysroot) = sysroot {
+        builder = builder.clang_arg(format!("--sysroot={}", sysroot.display()));
+    }
+    if windows {
+        builder = builder.clang_args(
+            WINDOWS_DEFINES
+                .iter()
+                .map(|(name, value)| format!("-D{name}={value}")),
+        );
+    }
+    if full {
+        builder = builder.clang_arg("-DWRAP_FI_FULL");
+    }
+    let bindings = builder
+        .clang_arg("-fno-inline-functions")
+        .clang_arg("-Wno-error=implicit-function-declaration")
+        .clang_arg("-Wno-error=int-conversion")
+        // Libfabric headers use regular comments rather than doxygen ones, include them all.
+        .clang_arg("-fparse-all-comments")
+        .generate_comments(true)
+        .parse_callbacks(Box::new(RenameFunctions))
+        .parse_callbacks(Box::new(CleanComments))
+        .rustified_non_exhaustive_enum(RUSTIFIED_ENUMS.join("|"))
+        // Only generate the Libfabric API, plus whatever it references (ex: iovec, sockaddr),
+        // rather than everything reachable from the system headers.
+        //
+        // Note that the allowlist is matched against the C names, before the "wrap_" prefix is
+        // stripped by RenameFunctions.
+        .allowlist_function("fi_.*")
+        .allowlist_function("wrap_fi_.*")
+        .allowlist_function("get_fid_ptr")
+        .allowlist_type("fi_.*")
+        .allowlist_type("fid.*")
+        .allowlist_type("cxip?_.*")
+        .allowlist_var("FI_.*")
+        // Plain C types from core::ffi, rather than std::os::raw, and no bindings of the socket
+        // types (see src/sockaddr.rs), such that nothing ties the bindings to libc.
+        .use_core()
+        .ctypes_prefix("::core::ffi")
+        .generate_inline_functions(false)
+        .wrap_static_fns(false)
+        .derive_default(true)
+        .derive_debug(true)
+        .generate()
+        .expect("Unable to generate bindings");
+
+    // Attach the TryFrom impls, and the man page summaries when building from the source tree.
+    let mut bindings = add_must_use(&add_try_from_impls(&bindings.to_string()));
+    if let Some(man_dir) = find_man_dir() {
+        println!("cargo:warning=Man page directory: {}", man_dir.display());
+        bindings = add_man_docs(&bindings, &parse_man_docs(&man_dir));
+    }
+
+    // Write the bindings to the $OUT_DIR/bindings.rs file.
+    fs::write(out_path.join("bindings.rs"), bindings).expect("Couldn't write bindings!");
+}
@@ -0,0 +1,182 @@
+#include <stdarg.h>
+#include "wrapper.h"
+
+/*
+ * The functions libfabric exports, for the dlopen feature, with which nothing links against
+ * the library. Each looks its symbol up in the library loaded at runtime by src/dlopen.rs,
+ * and fails as the function itself would (ex: -FI_ENOSYS) when either is missing.
+ */
+
+/* Defined by src/dlopen.rs: the symbol of the loaded library, or NULL. */
+void *wrap_fi_dlsym(const char *name);
+
+/* The longest message of fi_log() and fi_param_define(), formatted here as variadic calls
+ * cannot be forwarded. */
+#define WRAP_FI_DLOPEN_MSG 1024
+
+int fi_getinfo(uint32_t version, const char *node, const char *service,
+	       uint64_t flags, const struct fi_info *hints,
+	       struct fi_info **info)
+{
+	int (*real)(uint32_t, const char *, const char *, uint64_t,
+		    const struct fi_info *, struct fi_info **) =
+		wrap_fi_dlsym("fi_getinfo");
+
+	return real ? real(version, node, service, flags, hints, info) :
+		      -FI_ENOSYS;
+}
+
+void fi_freeinfo(struct fi_info *info)
+{
+	void (*real)(struct fi_info *) = wrap_fi_dlsym("fi_freeinfo");
+
+	if (real)
+		real(info);
+}
+
+struct fi_info *fi_dupinfo(const struct fi_info *info)
+{
+	struct fi_info *(*real)(const struct fi_info *) =
+		wrap_fi_dlsym("fi_dupinfo");
+
+	return real ? real(info) : NULL;
+}
+
+int fi_fabric(struct fi_fabric_attr *attr, struct fid_fabric **fabric,
+	      void *context)
+{
+	int (*real)(struct fi_fabric_attr *, struct fid_fabric **, void *) =
+		wrap_fi_dlsym("fi_fabric");
+
+	return real ? real(attr, fabric, context) : -FI_ENOSYS;
+}
+
+int fi_fabric2(struct fi_info *info, struct fid_fabric **fabric,
+	       uint64_t flags, void *context)
+{
+	int (*real)(struct fi_info *, struct fid_fabric **, uint64_t, void *) =
+		wrap_fi_dlsym("fi_fabric2");
+
+	return real ? real(info, fabric, flags, context) : -FI_ENOSYS;
+}
+
+int fi_open(uint32_t version, const char *name, void *attr, size_t attr_len,
+	    uint64_t flags, struct fid **fid, void *context)
+{
+	int (*real)(uint32_t, const char *, void *, size_t, uint64_t,
+		    struct fid **, void *) = wrap_fi_dlsym("fi_open");
+
+	return real ? real(version, name, attr, attr_len, flags, fid, context) :
+		      -FI_ENOSYS;
+}
+
+/* 0, which no release reports, without a library. */
+uint32_t fi_version(void)
+{
+	uint32_t (*real)(void) = wrap_fi_dlsym("fi_version");
+
+	return real ? real() : 0;
+}
+
+const char *fi_strerror(int errnum)
+{
+	const char *(*real)(int) = wrap_fi_dlsym("fi_strerror");
+
+	return real ? real(errnum) : "libfabric could not be loaded";
+}
+
+char *fi_tostr(const void *data, enum fi_type datatype)
+{
+	char *(*real)(const void *, enum fi_type) = wrap_fi_dlsym("fi_tostr");
+
+	return real ? real(data, datatype) : NULL;
+}
+
+char *fi_tostr_r(char *buf, size_t len, const void *data,
+		 enum fi_type datatype)
+{
+	char *(*real)(char *, size_t, const void *, enum fi_type) =
+		wrap_fi_dlsym("fi_tostr_r");
+
+	return real ? real(buf, len, data, datatype) : NULL;
+}
+
+int fi_getparams(struct fi_param **params, int *count)
+{
+	int (*real)(struct fi_param **, int *) = wrap_fi_dlsym("fi_getparams");
+
+	return real ? real(params, count) : -FI_ENOSYS;
+}
+
+void fi_freeparams(struct fi_param *params)
+{
+	void (*real)(struct fi_param *) = wrap_fi_dlsym("fi_freeparams");
+
+	if (real)
+		real(params);
+}
+
+#ifdef WRAP_FI_FULL
+int fi_param_define(const struct fi_provider *provider, const char *param_name,
+		    enum fi_param_type type, const char *help_string_fmt, ...)
+{
+	int (*real)(const struct fi_provider *, const char *,
+		    enum fi_param_type, const char *, ...) =
+		wrap_fi_dlsym("fi_param_define");
+	char help[WRAP_FI_DLOPEN_MSG];
+	va_list ap;
+
+	if (!real)
+		return -FI_ENOSYS;
+	va_start(ap, help_string_fmt);
+	vsnprintf(help, sizeof(help), help_string_fmt, ap);
+	va_end(ap);
+	return real(provider, param_name, type, "%s", help);
+}
+
+int fi_param_get(struct fi_provider *provider, const char *param_name,
+		 void *value)
+{
+	int (*real)(struct fi_provider *, const char *, void *) =
+		wrap_fi_dlsym("fi_param_get");
+
+	return real ? real(provider, param_name, value) : -FI_ENOSYS;
+}
+
+int fi_log_enabled(const struct fi_provider *prov, enum fi_log_level level,
+		   enum fi_log_subsys subsys)
+{
+	int (*real)(const struct fi_provider *, enum fi_log_level,
+		    enum fi_log_subsys) = wrap_fi_dlsym("fi_log_enabled");
+
+	return real ? real(prov, level, subsys) : 0;
+}
+
+int fi_log_ready(const struct fi_provider *prov, enum fi_log_level level,
+		 enum fi_log_subsys subsys, uint64_t *showtime)
+{
+	int (*real)(const struct fi_provider *, enum fi_log_level,
+		    enum fi_log_subsys, uint64_t *) =
+		wrap_fi_dlsym("fi_log_ready");
+
+	return real ? real(prov, level, subsys, showtime) : 0;
+}
+
+void fi_log(const struct fi_provider *prov, enum fi_log_level level,
+	    enum fi_log_subsys subsys, const char *func, int line,
+	    const char *fmt, ...)
+{
+	void (*real)(const struct fi_provider *, enum fi_log_level,
+		     enum fi_log_subsys, const char *, int, const char *, ...) =
+		wrap_fi_dlsym("fi_log");
+	char msg[WRAP_FI_DLOPEN_MSG];
+	va_list ap;
+
+	if (!real)
+		return;
+	va_start(ap, fmt);
+	vsnprintf(msg, sizeof(msg), fmt, ap);
+	va_end(ap);
+	real(prov, level, subsys, func, line, "%s", msg);
+}
+#endif /* WRAP_FI_FULL */
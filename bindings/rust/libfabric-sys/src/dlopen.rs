@@ -0,0 +1,171 @@
+// Loading of libfabric at runtime, with the dlopen feature, for tools which run on hosts with
+// and without a fabric stack.
+//
+// Nothing links against libfabric then: the functions it exports (ex: fi_getinfo()) are
+// defined by dlopen.c, which looks each up in the library loaded here on every call. The
+// static inline functions of the headers only call through the ops of the objects, or into
+// those exported functions (ex: fi_allocinfo() into fi_dupinfo()), so all of the bindings work
+// unchanged once a library is loaded, and fail with -FI_ENOSYS before.
+
+use libloading::Library;
+use std::env;
+use std::error::Error;
+use std::ffi::{CStr, c_char, c_void};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+/// Environment variable naming the library [`load()`] loads, ex:
+/// `/opt/libfabric-2.1/lib/libfabric.so.1`, rather than the one of the search path of the
+/// dynamic loader.
+pub const LIBRARY_ENV: &str = "LIBFABRIC_LIBRARY";
+
+// Names of the library, looked up in the search path of the dynamic loader.
+#[cfg(target_os = "linux")]
+const DEFAULT_NAMES: &[&str] = &["libfabric.so.1", "libfabric.so"];
+#[cfg(target_os = "macos")]
+const DEFAULT_NAMES: &[&str] = &["libfabric.1.dylib", "libfabric.dylib"];
+#[cfg(windows)]
+const DEFAULT_NAMES: &[&str] = &["libfabric.dll"];
+
+// Symbols which every release exports, checked upon loading such that a library which is no
+// libfabric is refused then. The others (ex: fi_fabric2()) are only looked up when called.
+const REQUIRED_SYMBOLS: &[&CStr] = &[
+    c"fi_getinfo",
+    c"fi_freeinfo",
+    c"fi_dupinfo",
+    c"fi_fabric",
+    c"fi_version",
+    c"fi_strerror",
+];
+
+/// Why libfabric could not be loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadError {
+    /// None of the libraries tried could be opened, each given with the error of the loader.
+    NotFound(Vec<(String, String)>),
+    /// The library lacks a function which every libfabric release exports.
+    MissingSymbol { path: PathBuf, symbol: String },
+    /// Another library is loaded already, which stays in use for the rest of the process.
+    AlreadyLoaded(PathBuf),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::NotFound(tried) => {
+                write!(f, "libfabric could not be loaded")?;
+                for (name, error) in tried {
+                    write!(f, "; {name}: {error}")?;
+                }
+                Ok(())
+            }
+            LoadError::MissingSymbol { path, symbol } => {
+                write!(f, "{} does not export {symbol}", path.display())
+            }
+            LoadError::AlreadyLoaded(path) => {
+                write!(f, "{} is already loaded", path.display())
+            }
+        }
+    }
+}
+
+impl Error for LoadError {}
+
+struct Loaded {
+    library: Library,
+    path: PathBuf,
+}
+
+static LOADED: OnceLock<Loaded> = OnceLock::new();
+// Serializes the loads, such that two threads do not both open a library.
+static LOADING: Mutex<()> = Mutex::new(());
+
+/// Load libfabric, the library named by [`LIBRARY_ENV`] if set, otherwise the one the search
+/// path of the dynamic loader holds, returning its path. Returns the library loaded already, if
+/// any.
+///
+/// The first call into libfabric loads it as well, but only reports a failure as the
+/// `-FI_ENOSYS` of that call, thus tools call this first to tell why.
+pub fn load() -> Result<&'static Path, LoadError> {
+    let _loading = LOADING.lock().unwrap();
+    if let Some(loaded) = LOADED.get() {
+        return Ok(&loaded.path);
+    }
+    let names = match env::var(LIBRARY_ENV) {
+        Ok(path) => vec![path],
+        Err(_) => DEFAULT_NAMES.iter().map(|name| name.to_string()).collect(),
+    };
+    let mut tried = Vec::new();
+    for name in names {
+        match open(Path::new(&name)) {
+            Ok(loaded) => return Ok(&LOADED.get_or_init(|| loaded).path),
+            Err(LoadError::NotFound(error)) => tried.extend(error),
+            Err(err) => return Err(err),
+        }
+    }
+    Err(LoadError::NotFound(tried))
+}
+
+/// Load the libfabric at `path`, ex: one of several releases installed side by side, returning
+/// its path. Must come before any call into libfabric, which loads the default library
+/// otherwise, and fails with [`LoadError::AlreadyLoaded`] if another one is loaded.
+pub fn load_from(path: impl AsRef<Path>) -> Result<&'static Path, LoadError> {
+    let path = path.as_ref();
+    let _loading = LOADING.lock().unwrap();
+    match LOADED.get() {
+        Some(loaded) if loaded.path == path => Ok(&loaded.path),
+        Some(loaded) => Err(LoadError::AlreadyLoaded(loaded.path.clone())),
+        None => {
+            let loaded = open(path)?;
+            Ok(&LOADED.get_or_init(|| loaded).path)
+        }
+    }
+}
+
+/// The path of the library loaded, if any.
+pub fn loaded() -> Option<&'static Path> {
+    LOADED.get().map(|loaded| loaded.path.as_path())
+}
+
+fn open(path: &Path) -> Result<Loaded, LoadError> {
+    // SAFETY: The initialization of libfabric, run on loading, has no requirement.
+    let library = unsafe { Library::new(path) }
+        .map_err(|err| LoadError::NotFound(vec![(path.display().to_string(), err.to_string())]))?;
+    for symbol in REQUIRED_SYMBOLS {
+        // SAFETY: The symbol is only checked for, not called.
+        if unsafe { library.get::<*mut c_void>(symbol.to_bytes_with_nul()) }.is_err() {
+            return Err(LoadError::MissingSymbol {
+                path: path.to_path_buf(),
+                symbol: symbol.to_string_lossy().into_owned(),
+            });
+        }
+    }
+    Ok(Loaded {
+        library,
+        path: path.to_path_buf(),
+    })
+}
+
+/// The address of the function `name` of the library, loading the default one first if none
+/// is, or null if either fails. Called by the functions of dlopen.c.
+///
+/// # Safety
+///
+/// `name` must be a nul terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wrap_fi_dlsym(name: *const c_char) -> *mut c_void {
+    let Some(loaded) = LOADED
+        .get()
+        .or_else(|| load().ok().and_then(|_| LOADED.get()))
+    else {
+        return std::ptr::null_mut();
+    };
+    let name = unsafe { CStr::from_ptr(name) };
+    // SAFETY: The library stays loaded for the rest of the process, and the caller casts the
+    // address to the prototype of the headers.
+    match unsafe { loaded.library.get::<*mut c_void>(name.to_bytes_with_nul()) } {
+        Ok(symbol) => *symbol,
+        Err(_) => std::ptr::null_mut(),
+    }
+}
@@ -0,0 +1,28 @@
+// Suppress expected warnings from bindgen-generated code.
+#![allow(non_upper_case_globals)]
+#![allow(non_camel_case_types)]
+#![allow(non_snake_case)]
+#![allow(missing_docs)]
+#![allow(deref_nullptr)]
+#![allow(improper_ctypes)]
+#![allow(unnecessary_transmutes)]
+#![allow(unsafe_op_in_unsafe_fn)]
+
+// Dump the bindgen generated code, and export as public module named "bindings".
+pub mod bindgen {
+    include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+}
+
+// Hand written counterparts of the fi_prov.h macros, for providers written in Rust. fi_prov.h
+// is only bound with the full feature, as is the entry point of the providers.
+mod prov;
+#[cfg(feature = "full")]
+pub use prov::FI_LIB_SUFFIX;
+pub use prov::FI_VERSION;
+
+// Socket addresses, which the bindings do not pull from the system headers.
+pub mod sockaddr;
+
+// Loading of libfabric at runtime, rather than linking against it.
+#[cfg(feature = "dlopen")]
+pub mod dlopen;
@@ -0,0 +1,50 @@
+// Counterparts of the fi_prov.h macros bindgen cannot translate, for providers written in Rust.
+//
+// A dynamically loaded provider is a shared library named `lib<name>-fi.so`, found in the
+// library search path or under FI_PROVIDER_PATH, which exports `fi_prov_ini()` (see
+// `fi_provider(7)`). With cargo, that is a `cdylib` crate whose output is renamed or linked to
+// that name, and which declares its entry point with `fi_ext_ini!`.
+
+#[cfg(feature = "full")]
+use std::ffi::CStr;
+
+/// Suffix of the file names of dynamically loaded providers (`FI_LIB_SUFFIX`).
+#[cfg(feature = "full")]
+pub const FI_LIB_SUFFIX: &CStr = c"fi.so";
+
+/// Encode an API version as `FI_VERSION(major, minor)` does.
+///
+/// Providers report the version of the headers they were built against in
+/// `fi_provider::fi_version`, as `FI_VERSION(FI_MAJOR_VERSION, FI_MINOR_VERSION)`.
+pub const fn FI_VERSION(major: u32, minor: u32) -> u32 {
+    (major << 16) | minor
+}
+
+/// Declare the `fi_prov_ini()` entry point of a dynamically loaded provider (`FI_EXT_INI`).
+///
+/// The expression is evaluated once, when libfabric loads the library, and must yield a
+/// `*mut fi_provider` which stays valid until its `cleanup` callback is called.
+///
+/// ```no_run
+/// use ofi_libfabric_sys::bindgen::{FI_MAJOR_VERSION, FI_MINOR_VERSION, fi_provider};
+/// use ofi_libfabric_sys::{FI_VERSION, fi_ext_ini};
+///
+/// fi_ext_ini!(Box::into_raw(Box::new(fi_provider {
+///     version: FI_VERSION(0, 1),
+///     fi_version: FI_VERSION(FI_MAJOR_VERSION, FI_MINOR_VERSION),
+///     name: c"sim".as_ptr(),
+///     // getinfo, fabric and cleanup callbacks.
+///     ..Default::default()
+/// })));
+/// # fn main() {}
+/// ```
+#[cfg(feature = "full")]
+#[macro_export]
+macro_rules! fi_ext_ini {
+    ($provider:expr) => {
+        #[unsafe(no_mangle)]
+        pub extern "C" fn fi_prov_ini() -> *mut $crate::bindgen::fi_provider {
+            $provider
+        }
+    };
+}
@@ -0,0 +1,57 @@
+// Minimal IPv4 and IPv6 socket addresses, as used by FI_SOCKADDR_IN(6) addresses (ex: the names
+// of tcp endpoints), without depending on libc for them. tests/abi.rs checks them against the
+// system headers.
+
+/// Address family (`sa_family_t`). BSD derived systems store the length of the address in the
+/// first byte, and the family in the second.
+#[cfg(not(target_os = "macos"))]
+pub type sa_family_t = u16;
+#[cfg(target_os = "macos")]
+pub type sa_family_t = u8;
+
+pub const AF_INET: sa_family_t = 2;
+#[cfg(target_os = "linux")]
+pub const AF_INET6: sa_family_t = 10;
+#[cfg(target_os = "macos")]
+pub const AF_INET6: sa_family_t = 30;
+#[cfg(windows)]
+pub const AF_INET6: sa_family_t = 23;
+
+/// IPv4 address, in network byte order.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct in_addr {
+    pub s_addr: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct sockaddr_in {
+    #[cfg(target_os = "macos")]
+    pub sin_len: u8,
+    pub sin_family: sa_family_t,
+    /// Port, in network byte order.
+    pub sin_port: u16,
+    pub sin_addr: in_addr,
+    pub sin_zero: [u8; 8],
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct in6_addr {
+    pub s6_addr: [u8; 16],
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct sockaddr_in6 {
+    #[cfg(target_os = "macos")]
+    pub sin6_len: u8,
+    pub sin6_family: sa_family_t,
+    /// Port, in network byte order.
+    pub sin6_port: u16,
+    /// Flow information, in network byte order.
+    pub sin6_flowinfo: u32,
+    pub sin6_addr: in6_addr,
+    pub sin6_scope_id: u32,
+}
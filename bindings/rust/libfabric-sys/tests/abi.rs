@@ -0,0 +1,29 @@
+#[cfg(test)]
+mod abi_tests {
+    use ofi_libfabric_sys::bindgen::*;
+
+    /// Size and alignment of the ABI_STRUCTS listed in build.rs, as laid out by bindgen versus
+    /// the C compiler building wrapper.c, and likewise for the socket address definitions of
+    /// src/sockaddr.rs (ABI_SOCKADDRS).
+    mod layout {
+        use super::*;
+
+        include!(concat!(env!("OUT_DIR"), "/abi.rs"));
+    }
+
+    /// The linked library must be at least as recent as the headers the bindings were generated
+    /// from, else the structs it fills may be smaller than declared.
+    #[test]
+    fn test_linked_version() {
+        let version = unsafe { fi_version() };
+        let (major, minor) = (version >> 16, version & 0xffff);
+        assert_eq!(
+            major, FI_MAJOR_VERSION,
+            "linked libfabric {major}.{minor}, headers {FI_MAJOR_VERSION}.{FI_MINOR_VERSION}"
+        );
+        assert!(
+            minor >= FI_MINOR_VERSION,
+            "linked libfabric {major}.{minor} is older than the {FI_MAJOR_VERSION}.{FI_MINOR_VERSION} headers"
+        );
+    }
+}
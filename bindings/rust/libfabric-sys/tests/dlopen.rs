@@ -0,0 +1,32 @@
+// A test binary of its own, as the library loaded is that of the whole process.
+#![cfg(feature = "dlopen")]
+
+#[cfg(test)]
+mod dlopen_tests {
+    use ofi_libfabric_sys::bindgen::*;
+    use ofi_libfabric_sys::dlopen::{self, LoadError};
+
+    /// A library which cannot be opened is reported with the error of the loader, and leaves
+    /// the default one to be loaded, through which calls then go.
+    #[test]
+    fn test_load() {
+        let missing = "/nonexistent/libfabric.so.1";
+        match dlopen::load_from(missing) {
+            Err(LoadError::NotFound(tried)) => assert_eq!(tried[0].0, missing),
+            other => panic!("loading {missing}: {other:?}"),
+        }
+        assert_eq!(dlopen::loaded(), None);
+
+        // Hosts without libfabric have no default library either.
+        let Ok(path) = dlopen::load() else {
+            assert_eq!(unsafe { fi_version() }, 0);
+            return;
+        };
+        assert_eq!(dlopen::loaded(), Some(path));
+        assert_eq!(unsafe { fi_version() } >> 16, FI_MAJOR_VERSION);
+        assert_eq!(
+            dlopen::load_from(missing),
+            Err(LoadError::AlreadyLoaded(path.to_path_buf()))
+        );
+    }
+}
@@ -32,10 +32,6 @@ mod unit_tests {
         fi_getinfo,
         fi_freeinfo,
         fi_dupinfo,
-        fi_param_get_str,
-        fi_param_get_int,
-        fi_param_get_bool,
-        fi_param_get_size_t,
         fi_allocinfo,
         fi_close,
         fi_control,
@@ -44,6 +40,8 @@ mod unit_tests {
         fi_set_val,
         fi_open_ops,
         fi_set_ops,
+        fi_tc_dscp_set,
+        fi_tc_dscp_get,
         fi_passive_ep,
         fi_endpoint,
         fi_endpoint2,
@@ -71,20 +69,7 @@ mod unit_tests {
         fi_inject,
         fi_senddata,
         fi_injectdata,
-        fi_atomic,
-        fi_atomicv,
-        fi_atomicmsg,
-        fi_inject_atomic,
-        fi_fetch_atomic,
-        fi_fetch_atomicv,
-        fi_fetch_atomicmsg,
-        fi_compare_atomic,
-        fi_compare_atomicv,
-        fi_compare_atomicmsg,
-        fi_atomicvalid,
-        fi_fetch_atomicvalid,
-        fi_compare_atomicvalid,
-        fi_query_atomic,
+        fi_hmem_ze_device,
         fi_domain,
         fi_domain2,
         fi_domain_bind,
@@ -111,9 +96,6 @@ mod unit_tests {
         fi_av_remove,
         fi_av_lookup,
         fi_av_straddr,
-        fi_av_insert_auth_key,
-        fi_av_lookup_auth_key,
-        fi_av_set_user_id,
         fi_rx_addr,
         fi_group_addr,
         fi_setname,
@@ -126,25 +108,6 @@ mod unit_tests {
         fi_shutdown,
         fi_join,
         fi_mc_addr,
-        fi_av_set,
-        fi_av_set_union,
-        fi_av_set_intersect,
-        fi_av_set_diff,
-        fi_av_set_insert,
-        fi_av_set_remove,
-        fi_av_set_addr,
-        fi_join_collective,
-        fi_barrier,
-        fi_barrier2,
-        fi_broadcast,
-        fi_alltoall,
-        fi_allreduce,
-        fi_allgather,
-        fi_reduce_scatter,
-        fi_reduce,
-        fi_scatter,
-        fi_gather,
-        fi_query_collective,
         fi_trywait,
         fi_wait,
         fi_poll,
@@ -170,19 +133,6 @@ mod unit_tests {
         fi_cntr_set,
         fi_cntr_seterr,
         fi_cntr_wait,
-        fi_export_fid,
-        fi_import_fid,
-        fi_import,
-        fi_import_log,
-        fi_profile_reset,
-        fi_profile_query_vars,
-        fi_profile_query_events,
-        fi_profile_read_u64,
-        fi_profile_register_callback,
-        fi_profile_start_reads,
-        fi_profile_end_reads,
-        fi_profile_open,
-        fi_profile_close,
         fi_read,
         fi_readv,
         fi_readmsg,
@@ -203,6 +153,92 @@ mod unit_tests {
         fi_tinjectdata,
     );
 
+    /// Test linkage of the functions of the headers bound with the full feature.
+    #[cfg(feature = "full")]
+    test_function_linkage!(
+        fi_param_get_str,
+        fi_param_get_int,
+        fi_param_get_bool,
+        fi_param_get_size_t,
+        fi_atomic,
+        fi_atomicv,
+        fi_atomicmsg,
+        fi_inject_atomic,
+        fi_fetch_atomic,
+        fi_fetch_atomicv,
+        fi_fetch_atomicmsg,
+        fi_compare_atomic,
+        fi_compare_atomicv,
+        fi_compare_atomicmsg,
+        fi_atomicvalid,
+        fi_fetch_atomicvalid,
+        fi_compare_atomicvalid,
+        fi_query_atomic,
+        fi_av_set,
+        fi_av_set_union,
+        fi_av_set_intersect,
+        fi_av_set_diff,
+        fi_av_set_insert,
+        fi_av_set_remove,
+        fi_av_set_addr,
+        fi_join_collective,
+        fi_barrier,
+        fi_barrier2,
+        fi_broadcast,
+        fi_alltoall,
+        fi_allreduce,
+        fi_allgather,
+        fi_reduce_scatter,
+        fi_reduce,
+        fi_scatter,
+        fi_gather,
+        fi_query_collective,
+        fi_export_fid,
+        fi_import_fid,
+        fi_import,
+        fi_import_log,
+    );
+
+    /// Test linkage of the functions introduced in libfabric 1.20.
+    #[cfg(libfabric_ge_1_20)]
+    test_function_linkage!(
+        fi_av_insert_auth_key,
+        fi_av_lookup_auth_key,
+        fi_av_set_user_id,
+    );
+
+    /// Test linkage of the profiling functions, introduced in libfabric 1.20.
+    #[cfg(all(libfabric_ge_1_20, feature = "full"))]
+    test_function_linkage!(
+        fi_profile_reset,
+        fi_profile_query_vars,
+        fi_profile_query_events,
+        fi_profile_read_u64,
+        fi_profile_register_callback,
+        fi_profile_start_reads,
+        fi_profile_end_reads,
+        fi_profile_open,
+        fi_profile_close,
+    );
+
+    /// Test linkage of the functions introduced in libfabric 2.0.
+    #[cfg(libfabric_ge_2_0)]
+    test_function_linkage!(fi_tag_mpi, fi_fabric2);
+
+    /// Test the inline helpers computing values, which do not call into the library.
+    #[test]
+    fn test_inline_helpers() {
+        unsafe {
+            let tclass = fi_tc_dscp_set(46);
+            assert_eq!(tclass & FI_TC_DSCP, FI_TC_DSCP);
+            assert_eq!(fi_tc_dscp_get(tclass), 46);
+            assert_eq!(fi_tc_dscp_get(0), 0);
+            assert_eq!(fi_hmem_ze_device(1, 2), 1 << 16 | 2);
+        }
+        #[cfg(libfabric_ge_2_0)]
+        assert_eq!(unsafe { fi_tag_mpi(7, 3) }, 3 << 32 | 7);
+    }
+
     /// Test successful instantiation of core Libfabric structures.
     #[test]
     fn test_struct_definitions() {
@@ -226,6 +262,34 @@ mod unit_tests {
         let _fi_mr_attr: fi_mr_attr = unsafe { std::mem::zeroed() };
     }
 
+    /// Test conversion of raw values into the rustified enums.
+    #[test]
+    fn test_enum_try_from() {
+        assert_eq!(fi_ep_type::try_from(3), Ok(fi_ep_type::FI_EP_RDM));
+        assert_eq!(fi_op::try_from(fi_op::FI_NOOP as u32), Ok(fi_op::FI_NOOP));
+        assert_eq!(fi_datatype::try_from(9999), Err(9999));
+    }
+
+    // Entry point of a provider, as a dynamically loaded one would declare it.
+    #[cfg(feature = "full")]
+    ofi_libfabric_sys::fi_ext_ini!(Box::into_raw(Box::new(fi_provider {
+        version: ofi_libfabric_sys::FI_VERSION(0, 1),
+        fi_version: ofi_libfabric_sys::FI_VERSION(FI_MAJOR_VERSION, FI_MINOR_VERSION),
+        name: c"rust".as_ptr(),
+        ..Default::default()
+    })));
+
+    /// Test the provider entry point, and the version encoding it relies on.
+    #[cfg(feature = "full")]
+    #[test]
+    fn test_prov_ini() {
+        let provider = unsafe { Box::from_raw(fi_prov_ini()) };
+        assert_eq!(unsafe { std::ffi::CStr::from_ptr(provider.name) }, c"rust");
+        assert_eq!(provider.fi_version >> 16, FI_MAJOR_VERSION);
+        assert_eq!(provider.fi_version & 0xFFFF, FI_MINOR_VERSION);
+        assert_eq!(ofi_libfabric_sys::FI_LIB_SUFFIX, c"fi.so");
+    }
+
     /// Example use case of the library.
     #[test]
     fn test_get_info() {
@@ -236,7 +300,7 @@ mod unit_tests {
 
             (*hints).caps = FI_MSG as u64;
             (*hints).mode = FI_CONTEXT;
-            (*(*hints).ep_attr).type_ = fi_ep_type_FI_EP_RDM;
+            (*(*hints).ep_attr).type_ = fi_ep_type::FI_EP_RDM;
             (*(*hints).domain_attr).mr_mode = FI_MR_LOCAL as i32;
             let prov_name = CString::new("tcp").unwrap();
             (*(*hints).fabric_attr).prov_name = prov_name.into_raw() as *mut i8;
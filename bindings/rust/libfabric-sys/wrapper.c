@@ -0,0 +1,8 @@
+#include "wrapper.h"
+
+/* Proprietary helper function declarations. */
+
+fid_t get_fid_ptr(void *ptr)
+{
+	return (fid_t) ptr;
+}
@@ -0,0 +1,46 @@
+#ifndef __WRAPPER_H__
+#define __WRAPPER_H__
+
+#ifndef container_of
+#define container_of(ptr, type, field) \
+	((type *) ((char *) ptr - offsetof(type, field)))
+#endif
+
+#include <stdio.h>
+
+#include "fi_cm.h"
+#include "fi_domain.h"
+#include "fi_endpoint.h"
+#include "fi_eq.h"
+#include "fi_errno.h"
+#include "fi_rma.h"
+#include "fi_tagged.h"
+
+/* Whether the headers in use are of the given libfabric release or newer. */
+#define WRAP_FI_HEADER_GE(major, minor)                                  \
+	FI_VERSION_GE(FI_VERSION(FI_MAJOR_VERSION, FI_MINOR_VERSION),    \
+		      FI_VERSION(major, minor))
+
+/* Defined by build.rs with the full feature, see wrapper.manifest. */
+#ifdef WRAP_FI_FULL
+#include "fi_atomic.h"
+#include "fi_collective.h"
+#include "fi_ext.h"
+#include "fi_peer.h"
+#include "fi_prov.h"
+#include "fi_trigger.h"
+
+#if WRAP_FI_HEADER_GE(1, 20)
+#include "fi_profile.h"
+#endif
+#endif /* WRAP_FI_FULL */
+
+/* Proprietary helper function declarations. */
+fid_t get_fid_ptr(void *ptr);
+
+/*
+ * The static inline functions of the headers are wrapped by the wrap_ functions of
+ * wrappers.[ch], which build.rs generates from wrapper.manifest.
+ */
+
+#endif /* __WRAPPER_H */
@@ -0,0 +1,55 @@
+// Round trip latency and streaming rate through the safe wrappers and through the raw bindings,
+// between two tcp endpoints of the same process. Run with:
+//
+//     cargo bench --features bench
+//
+// A regression of the safe numbers which the raw ones do not show is overhead added to the
+// wrappers.
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use libfabric::bench::{Api, Loopback};
+
+const SIZES: &[usize] = &[8, 256, 4096, 65536];
+const WINDOW: usize = 64;
+
+fn apis() -> [(&'static str, Api); 2] {
+    [("safe", Api::Safe), ("raw", Api::Raw)]
+}
+
+fn round_trip(c: &mut Criterion) {
+    let mut loopback = Loopback::open("tcp", SIZES[SIZES.len() - 1]).unwrap();
+    let mut group = c.benchmark_group("round_trip");
+    for &size in SIZES {
+        for (name, api) in apis() {
+            group.bench_with_input(BenchmarkId::new(name, size), &size, |b, &size| {
+                b.iter_custom(|iters| {
+                    (0..iters)
+                        .map(|_| loopback.round_trip(size, api).unwrap())
+                        .sum()
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+fn stream(c: &mut Criterion) {
+    let mut loopback = Loopback::open("tcp", SIZES[SIZES.len() - 1]).unwrap();
+    let mut group = c.benchmark_group("stream");
+    for &size in SIZES {
+        group.throughput(Throughput::Bytes((size * WINDOW) as u64));
+        for (name, api) in apis() {
+            group.bench_with_input(BenchmarkId::new(name, size), &size, |b, &size| {
+                b.iter_custom(|iters| {
+                    (0..iters)
+                        .map(|_| loopback.stream(size, WINDOW, api).unwrap())
+                        .sum()
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, round_trip, stream);
+criterion_main!(benches);
@@ -0,0 +1,35 @@
+use std::env;
+
+fn main() {
+    // Forward the `libfabric_ge_{major}_{minor}` and `libfabric_provider_{name}` cfgs detected by
+    // ofi-libfabric-sys (see its build.rs), such that the safe wrappers gate newer APIs the same
+    // way the bindings do.
+    let list = |key: &str| env::var(key).unwrap_or_default();
+    for cfg in list("DEP_LIBFABRIC_CHECK_CFGS")
+        .split(',')
+        .filter(|c| !c.is_empty())
+    {
+        println!("cargo:rustc-check-cfg=cfg({cfg})");
+    }
+    for cfg in list("DEP_LIBFABRIC_CFGS")
+        .split(',')
+        .filter(|c| !c.is_empty())
+    {
+        println!("cargo:rustc-cfg={cfg}");
+    }
+
+    if env::var_os("CARGO_FEATURE_PMI").is_some() {
+        println!("cargo:rerun-if-env-changed=PMI_LIB_DIR");
+        if let Ok(dir) = env::var("PMI_LIB_DIR") {
+            println!("cargo:rustc-link-search=native={dir}");
+        }
+        println!("cargo:rustc-link-lib=pmi2");
+    }
+
+    // Same rpath as ofi-libfabric-sys sets for its own targets, see its build.rs.
+    if env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("macos")
+        && let Ok(lib_dir) = env::var("DEP_LIBFABRIC_LIB_DIR")
+    {
+        println!("cargo:rustc-link-arg=-Wl,-rpath,{lib_dir}");
+    }
+}
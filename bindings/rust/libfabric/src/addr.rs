@@ -0,0 +1,324 @@
+use crate::av::{AddrFormat, EndpointAddress};
+use crate::verbs::IbAddr;
+use std::fmt;
+use std::net::{Ipv6Addr, SocketAddr};
+
+/// A format of endpoint addresses, between the raw bytes of an [`EndpointAddress`] and a
+/// typed address, ex: the GID and QPN of an efa endpoint.
+///
+/// The address vectors, endpoints and exchanged names decode and encode addresses through it,
+/// with [`EndpointAddress::decode()`], [`AddressVector::insert_addr()`],
+/// [`AddressVector::lookup_as()`] and [`Endpoint::name_as()`], so that a format these
+/// bindings do not know is added by implementing it, without touching those calls.
+///
+/// [`AddressVector::insert_addr()`]: crate::AddressVector::insert_addr
+/// [`AddressVector::lookup_as()`]: crate::AddressVector::lookup_as
+/// [`Endpoint::name_as()`]: crate::Endpoint::name_as
+///
+/// ```no_run
+/// use libfabric::{AddrFormat, AddressFormat, Endpoint};
+///
+/// /// The NIC and PID of a cxi endpoint.
+/// struct CxiAddr {
+///     nic: u32,
+///     pid: u16,
+/// }
+///
+/// impl AddressFormat for CxiAddr {
+///     fn decode(format: AddrFormat, bytes: &[u8]) -> Option<Self> {
+///         let raw = u32::from_ne_bytes(bytes.get(..4)?.try_into().ok()?);
+///         (format == AddrFormat::Cxi).then_some(CxiAddr {
+///             nic: raw >> 9 & 0xf_ffff,
+///             pid: (raw & 0x1ff) as u16,
+///         })
+///     }
+///
+///     fn format(&self) -> AddrFormat {
+///         AddrFormat::Cxi
+///     }
+///
+///     fn encode(&self) -> Vec<u8> {
+///         (self.nic << 9 | self.pid as u32).to_ne_bytes().to_vec()
+///     }
+/// }
+///
+/// # fn run(ep: &Endpoint) -> libfabric::Result<()> {
+/// if let Some(addr) = ep.name_as::<CxiAddr>()? {
+///     println!("NIC {:#x}, PID {}", addr.nic, addr.pid);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub trait AddressFormat: Sized {
+    /// Decode the `bytes` of an address in `format`, or `None` if the type holds no addresses
+    /// of that format or they are too short.
+    fn decode(format: AddrFormat, bytes: &[u8]) -> Option<Self>;
+
+    /// The format of the address, that of the providers it is inserted with.
+    fn format(&self) -> AddrFormat;
+
+    /// The raw bytes of the address, as `fi_getname()` returns them.
+    fn encode(&self) -> Vec<u8>;
+}
+
+impl EndpointAddress {
+    /// Decode the address as an `F`, given the `format` of the provider, ex: from
+    /// [`InfoEntry::addr_format()`](crate::InfoEntry::addr_format).
+    pub fn decode<F: AddressFormat>(&self, format: AddrFormat) -> Option<F> {
+        F::decode(format, self.as_bytes())
+    }
+
+    pub fn encode<F: AddressFormat>(addr: &F) -> Self {
+        EndpointAddress::from_bytes(addr.encode())
+    }
+}
+
+fn words<const N: usize>(bytes: &[u8]) -> Option<[u64; N]> {
+    let mut words = [0; N];
+    for (word, bytes) in words.iter_mut().zip(bytes.get(..N * 8)?.chunks_exact(8)) {
+        *word = u64::from_ne_bytes(bytes.try_into().unwrap());
+    }
+    Some(words)
+}
+
+fn gid(bytes: &[u8]) -> Ipv6Addr {
+    Ipv6Addr::from(<[u8; 16]>::try_from(&bytes[..16]).unwrap())
+}
+
+/// `sockaddr_in` and `sockaddr_in6` addresses, of IP based providers (ex: tcp, udp).
+impl AddressFormat for SocketAddr {
+    fn decode(format: AddrFormat, bytes: &[u8]) -> Option<Self> {
+        match format {
+            AddrFormat::Unspec
+            | AddrFormat::SockAddr
+            | AddrFormat::SockAddrIn
+            | AddrFormat::SockAddrIn6
+            | AddrFormat::SockAddrIp => EndpointAddress::from_bytes(bytes).to_socket_addr(),
+            _ => None,
+        }
+    }
+
+    fn format(&self) -> AddrFormat {
+        match self {
+            SocketAddr::V4(_) => AddrFormat::SockAddrIn,
+            SocketAddr::V6(_) => AddrFormat::SockAddrIn6,
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        EndpointAddress::from(*self).into_bytes()
+    }
+}
+
+/// `sockaddr_ib` addresses, of verbs entries bound to InfiniBand addresses.
+impl AddressFormat for IbAddr {
+    fn decode(format: AddrFormat, bytes: &[u8]) -> Option<Self> {
+        match format {
+            AddrFormat::SockAddr | AddrFormat::SockAddrIb => IbAddr::from_bytes(bytes),
+            _ => None,
+        }
+    }
+
+    fn format(&self) -> AddrFormat {
+        AddrFormat::SockAddrIb
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        self.to_bytes()
+    }
+}
+
+// The length of `struct ofi_ib_ud_ep_name`.
+const IB_UD_LEN: usize = 32;
+
+/// The address of an InfiniBand unreliable datagram queue pair (`FI_ADDR_IB_UD`), that of
+/// verbs datagram endpoints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IbUdAddr {
+    pub gid: Ipv6Addr,
+    pub qpn: u32,
+    pub lid: u16,
+    /// The partition key.
+    pub pkey: u16,
+    /// The service of the name server, 0 for any.
+    pub service: u16,
+    /// The service level.
+    pub sl: u8,
+}
+
+impl AddressFormat for IbUdAddr {
+    fn decode(format: AddrFormat, bytes: &[u8]) -> Option<Self> {
+        if format != AddrFormat::IbUd || bytes.len() < IB_UD_LEN {
+            return None;
+        }
+        let u16_at = |at: usize| u16::from_ne_bytes([bytes[at], bytes[at + 1]]);
+        Some(IbUdAddr {
+            gid: gid(bytes),
+            qpn: u32::from_ne_bytes(bytes[16..20].try_into().unwrap()),
+            lid: u16_at(20),
+            pkey: u16_at(22),
+            service: u16_at(24),
+            sl: bytes[26],
+        })
+    }
+
+    fn format(&self) -> AddrFormat {
+        AddrFormat::IbUd
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(IB_UD_LEN);
+        bytes.extend(self.gid.octets());
+        bytes.extend(self.qpn.to_ne_bytes());
+        for field in [self.lid, self.pkey, self.service] {
+            bytes.extend(field.to_ne_bytes());
+        }
+        bytes.push(self.sl);
+        bytes.resize(IB_UD_LEN, 0);
+        bytes
+    }
+}
+
+/// Displayed as by `fi_av_straddr()`, ex: `fi_addr_ib_ud://fe80::1:12/3/ffff/0`.
+impl fmt::Display for IbUdAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "fi_addr_ib_ud://{}:{:x}/{:x}/{:x}/{:x}",
+            self.gid, self.qpn, self.lid, self.pkey, self.sl
+        )
+    }
+}
+
+/// The address of an endpoint of the psm providers of Omni-Path and Ethernet, opaque words
+/// of a length which depends on the provider.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PsmxAddr {
+    /// `FI_ADDR_PSMX`, of the psm provider.
+    Psmx(u64),
+    /// `FI_ADDR_PSMX2`, of the psm2 provider.
+    Psmx2([u64; 2]),
+    /// `FI_ADDR_PSMX3`, of the psm3 provider.
+    Psmx3([u64; 4]),
+}
+
+impl PsmxAddr {
+    pub fn words(&self) -> &[u64] {
+        match self {
+            PsmxAddr::Psmx(word) => std::slice::from_ref(word),
+            PsmxAddr::Psmx2(words) => words,
+            PsmxAddr::Psmx3(words) => words,
+        }
+    }
+}
+
+impl AddressFormat for PsmxAddr {
+    fn decode(format: AddrFormat, bytes: &[u8]) -> Option<Self> {
+        match format {
+            AddrFormat::Psmx => words::<1>(bytes).map(|[word]| PsmxAddr::Psmx(word)),
+            AddrFormat::Psmx2 => words(bytes).map(PsmxAddr::Psmx2),
+            AddrFormat::Psmx3 => words(bytes).map(PsmxAddr::Psmx3),
+            _ => None,
+        }
+    }
+
+    fn format(&self) -> AddrFormat {
+        match self {
+            PsmxAddr::Psmx(_) => AddrFormat::Psmx,
+            PsmxAddr::Psmx2(_) => AddrFormat::Psmx2,
+            PsmxAddr::Psmx3(_) => AddrFormat::Psmx3,
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        self.words()
+            .iter()
+            .flat_map(|word| word.to_ne_bytes())
+            .collect()
+    }
+}
+
+/// Displayed as by `fi_av_straddr()`, ex: `fi_addr_psmx2://1a:2b`.
+impl fmt::Display for PsmxAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scheme = match self {
+            PsmxAddr::Psmx(_) => "fi_addr_psmx",
+            PsmxAddr::Psmx2(_) => "fi_addr_psmx2",
+            PsmxAddr::Psmx3(_) => "fi_addr_psmx3",
+        };
+        write!(f, "{scheme}://")?;
+        for (i, word) in self.words().iter().enumerate() {
+            let sep = if i == 0 { "" } else { ":" };
+            write!(f, "{sep}{word:x}")?;
+        }
+        Ok(())
+    }
+}
+
+// The length of `struct efa_ep_addr`, which ends with a pointer of the provider.
+const EFA_LEN: usize = 32;
+
+/// The address of an efa endpoint (`FI_ADDR_EFA`): the GID of its device, and its queue
+/// pair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EfaAddr {
+    pub gid: Ipv6Addr,
+    pub qpn: u16,
+    pub qkey: u32,
+}
+
+impl AddressFormat for EfaAddr {
+    fn decode(format: AddrFormat, bytes: &[u8]) -> Option<Self> {
+        if format != AddrFormat::Efa || bytes.len() < 24 {
+            return None;
+        }
+        Some(EfaAddr {
+            gid: gid(bytes),
+            qpn: u16::from_ne_bytes([bytes[16], bytes[17]]),
+            qkey: u32::from_ne_bytes(bytes[20..24].try_into().unwrap()),
+        })
+    }
+
+    fn format(&self) -> AddrFormat {
+        AddrFormat::Efa
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(EFA_LEN);
+        bytes.extend(self.gid.octets());
+        bytes.extend(self.qpn.to_ne_bytes());
+        bytes.extend([0; 2]);
+        bytes.extend(self.qkey.to_ne_bytes());
+        bytes.resize(EFA_LEN, 0);
+        bytes
+    }
+}
+
+/// Displayed as by `fi_av_straddr()`, ex: `fi_addr_efa://[fe80::1]:12:3`.
+impl fmt::Display for EfaAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "fi_addr_efa://[{}]:{}:{}", self.gid, self.qpn, self.qkey)
+    }
+}
+
+/// Addresses formatted as strings (`FI_ADDR_STR`), nul terminated, ex:
+/// `fi_sockaddr_in://10.0.0.1:4000`.
+impl AddressFormat for String {
+    fn decode(format: AddrFormat, bytes: &[u8]) -> Option<Self> {
+        if format != AddrFormat::Str {
+            return None;
+        }
+        let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        String::from_utf8(bytes[..len].to_vec()).ok()
+    }
+
+    fn format(&self) -> AddrFormat {
+        AddrFormat::Str
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = self.as_bytes().to_vec();
+        bytes.push(0);
+        bytes
+    }
+}
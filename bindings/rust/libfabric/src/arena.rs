@@ -0,0 +1,530 @@
+use crate::av::Addr;
+use crate::cq::{CqEntry, CqErrEntry};
+use crate::ep::Endpoint;
+use crate::error::{Error, Result, check_len};
+use crate::flags::BindFlags;
+use crate::mr::MemoryRegion;
+use crate::trace;
+use crate::transport::Transport;
+use ofi_libfabric_sys::bindgen as ffi;
+use std::ffi::c_void;
+use std::{mem, ptr};
+
+/// The buffers an operation of an [`OpArena`] gathers or scatters, at most.
+pub const ARENA_IOV_LIMIT: usize = 8;
+
+// The descriptor of an operation: the context of the application, and the arrays of the
+// buffers of vectored operations.
+struct Slot {
+    context: usize,
+    busy: bool,
+    iov: [ffi::iovec; ARENA_IOV_LIMIT],
+    desc: [*mut c_void; ARENA_IOV_LIMIT],
+}
+
+impl Slot {
+    fn new() -> Self {
+        Slot {
+            context: 0,
+            busy: false,
+            iov: [ffi::iovec {
+                iov_base: ptr::null_mut(),
+                iov_len: 0,
+            }; ARENA_IOV_LIMIT],
+            desc: [ptr::null_mut(); ARENA_IOV_LIMIT],
+        }
+    }
+}
+
+/// Operations posted on an endpoint without allocating: their descriptors come from a fixed
+/// number of slots, allocated once by [`new()`](Self::new), for applications bound by their
+/// message rate.
+///
+/// The context the provider is given is the address of the slot, and the completion of the
+/// operation hands the one of the application back through [`complete()`](Self::complete),
+/// which frees the slot. Posts fail with `FI_EAGAIN` while every slot is in flight, as they
+/// do when the queues of the provider are full. Vectored operations, over an [`Endpoint`],
+/// take their arrays of buffers and descriptors from the slot as well, up to
+/// [`ARENA_IOV_LIMIT`] buffers.
+///
+/// ```no_run
+/// # use libfabric::{Addr, CompletionQueue, Endpoint};
+/// # fn run(ep: Endpoint, cq: &CompletionQueue, dest: Addr) -> libfabric::Result<()> {
+/// use libfabric::{Completion, OpArena};
+///
+/// let mut arena = OpArena::new(ep, 64);
+/// let (header, payload) = (*b"head", vec![0u8; 4096]);
+/// unsafe { arena.sendv(&[&header, &payload], &[], dest, 7) }?;
+/// let mut completions = [Completion::default(); 16];
+/// loop {
+///     let n = cq.read(&mut completions)?;
+///     if completions[..n].iter().any(|c| arena.complete(c) == Some(7)) {
+///         break;
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct OpArena<T: Transport> {
+    ep: T,
+    slots: Box<[Slot]>,
+    // The free slots, with room for all of them so that freeing one never allocates.
+    free: Vec<usize>,
+}
+
+// SAFETY: The pointers of the slots are only handed to the provider during a post, never
+// dereferenced.
+unsafe impl<T: Transport + Send> Send for OpArena<T> {}
+
+impl<T: Transport> OpArena<T> {
+    /// Post the operations of `ep` with `depth` descriptors, as many operations in flight.
+    pub fn new(ep: T, depth: usize) -> Self {
+        OpArena {
+            ep,
+            slots: (0..depth).map(|_| Slot::new()).collect(),
+            free: (0..depth).rev().collect(),
+        }
+    }
+
+    pub fn endpoint(&self) -> &T {
+        &self.ep
+    }
+
+    /// The operations the arena holds the descriptors of.
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// The operations posted whose completion was not handed to the arena yet.
+    pub fn in_flight(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    /// The context of the application the completion of an operation of the arena was posted
+    /// with, freeing its descriptor, or `None` for the operations of others.
+    pub fn complete<E: CqEntry>(&mut self, completion: &E) -> Option<usize> {
+        self.complete_context(completion.context())
+    }
+
+    /// Like [`complete()`](Self::complete), for an operation which failed.
+    pub fn complete_err(&mut self, entry: &CqErrEntry) -> Option<usize> {
+        self.complete_context(entry.context)
+    }
+
+    /// Like [`complete()`](Self::complete), from the context the provider reported, ex: in
+    /// a completion read through the raw bindings.
+    pub fn complete_context(&mut self, op_context: usize) -> Option<usize> {
+        let offset = op_context.checked_sub(self.slots.as_ptr() as usize)?;
+        let slot = offset / mem::size_of::<Slot>();
+        if offset % mem::size_of::<Slot>() != 0 || !self.slots.get(slot)?.busy {
+            return None;
+        }
+        self.free(slot);
+        Some(self.slots[slot].context)
+    }
+
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    pub unsafe fn send(
+        &mut self,
+        buf: &[u8],
+        mr: Option<&T::Mr>,
+        dest: Addr,
+        context: usize,
+    ) -> Result<()> {
+        self.post("fi_send", context, |ep, slot| unsafe {
+            ep.send(buf, mr, dest, slot)
+        })
+    }
+
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    pub unsafe fn recv(
+        &mut self,
+        buf: &mut [u8],
+        mr: Option<&T::Mr>,
+        src: Addr,
+        context: usize,
+    ) -> Result<()> {
+        self.post("fi_recv", context, |ep, slot| unsafe {
+            ep.recv(buf, mr, src, slot)
+        })
+    }
+
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    pub unsafe fn tsend(
+        &mut self,
+        buf: &[u8],
+        mr: Option<&T::Mr>,
+        dest: Addr,
+        tag: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.post("fi_tsend", context, |ep, slot| unsafe {
+            ep.tsend(buf, mr, dest, tag, slot)
+        })
+    }
+
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn trecv(
+        &mut self,
+        buf: &mut [u8],
+        mr: Option<&T::Mr>,
+        src: Addr,
+        tag: u64,
+        ignore: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.post("fi_trecv", context, |ep, slot| unsafe {
+            ep.trecv(buf, mr, src, tag, ignore, slot)
+        })
+    }
+
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn write(
+        &mut self,
+        buf: &[u8],
+        mr: Option<&T::Mr>,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.post("fi_write", context, |ep, slot| unsafe {
+            ep.write(buf, mr, dest, addr, key, slot)
+        })
+    }
+
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn read(
+        &mut self,
+        buf: &mut [u8],
+        mr: Option<&T::Mr>,
+        src: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.post("fi_read", context, |ep, slot| unsafe {
+            ep.read(buf, mr, src, addr, key, slot)
+        })
+    }
+
+    // Post with the address of a free slot as the context, freeing it again if the post
+    // fails.
+    fn post(
+        &mut self,
+        op: &'static str,
+        context: usize,
+        post: impl FnOnce(&T, usize) -> Result<()>,
+    ) -> Result<()> {
+        let slot = self.acquire(op, context)?;
+        let result = post(&self.ep, self.op_context(slot));
+        if result.is_err() {
+            self.free(slot);
+        }
+        result
+    }
+
+    fn acquire(&mut self, op: &'static str, context: usize) -> Result<usize> {
+        let slot = self
+            .free
+            .pop()
+            .ok_or_else(|| Error::fabric(op, ffi::FI_EAGAIN as i64))?;
+        self.slots[slot].context = context;
+        self.slots[slot].busy = true;
+        Ok(slot)
+    }
+
+    fn free(&mut self, slot: usize) {
+        self.slots[slot].busy = false;
+        self.free.push(slot);
+    }
+
+    fn op_context(&self, slot: usize) -> usize {
+        ptr::from_ref(&self.slots[slot]) as usize
+    }
+}
+
+/// Vectored operations, whose arrays of buffers and descriptors are those of the slot. The
+/// memory regions of the buffers are given as `mrs`, either one for each buffer or none at
+/// all.
+impl OpArena<Endpoint> {
+    /// Send `bufs` as one message, via `fi_sendv()`.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    pub unsafe fn sendv(
+        &mut self,
+        bufs: &[&[u8]],
+        mrs: &[&MemoryRegion],
+        dest: Addr,
+        context: usize,
+    ) -> Result<()> {
+        let bufs = bufs.iter().map(|buf| (buf.as_ptr(), buf.len()));
+        let (index, count) = self.gather("fi_sendv", bufs, mrs, context)?;
+        let slot = &mut self.slots[index];
+        trace::data_op!(
+            &self.ep,
+            "fi_sendv",
+            size = crate::rma::total(&slot.iov[..count])
+        );
+        let ret = self.ep.post_op(BindFlags::TRANSMIT, || unsafe {
+            ffi::fi_sendv(
+                self.ep.as_raw(),
+                slot.iov.as_ptr(),
+                slot.desc.as_mut_ptr(),
+                count,
+                dest.as_raw(),
+                ptr::from_mut(slot).cast(),
+            )
+        });
+        self.posted(index, "fi_sendv", ret)
+    }
+
+    /// Receive a message into `bufs`, filled one after the other, via `fi_recvv()`.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    pub unsafe fn recvv(
+        &mut self,
+        bufs: &mut [&mut [u8]],
+        mrs: &[&MemoryRegion],
+        src: Addr,
+        context: usize,
+    ) -> Result<()> {
+        let bufs = bufs
+            .iter_mut()
+            .map(|buf| (buf.as_mut_ptr().cast_const(), buf.len()));
+        let (index, count) = self.gather("fi_recvv", bufs, mrs, context)?;
+        let slot = &mut self.slots[index];
+        trace::data_op!(
+            &self.ep,
+            "fi_recvv",
+            size = crate::rma::total(&slot.iov[..count])
+        );
+        let ret = self.ep.post_op(BindFlags::RECV, || unsafe {
+            ffi::fi_recvv(
+                self.ep.as_raw(),
+                slot.iov.as_ptr(),
+                slot.desc.as_mut_ptr(),
+                count,
+                src.as_raw(),
+                ptr::from_mut(slot).cast(),
+            )
+        });
+        self.posted(index, "fi_recvv", ret)
+    }
+
+    /// Send `bufs` as one tagged message, via `fi_tsendv()`.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    pub unsafe fn tsendv(
+        &mut self,
+        bufs: &[&[u8]],
+        mrs: &[&MemoryRegion],
+        dest: Addr,
+        tag: u64,
+        context: usize,
+    ) -> Result<()> {
+        let bufs = bufs.iter().map(|buf| (buf.as_ptr(), buf.len()));
+        let (index, count) = self.gather("fi_tsendv", bufs, mrs, context)?;
+        let slot = &mut self.slots[index];
+        trace::data_op!(
+            &self.ep,
+            "fi_tsendv",
+            size = crate::rma::total(&slot.iov[..count]),
+            tag
+        );
+        let ret = self.ep.post_op(BindFlags::TRANSMIT, || unsafe {
+            ffi::fi_tsendv(
+                self.ep.as_raw(),
+                slot.iov.as_ptr(),
+                slot.desc.as_mut_ptr(),
+                count,
+                dest.as_raw(),
+                tag,
+                ptr::from_mut(slot).cast(),
+            )
+        });
+        self.posted(index, "fi_tsendv", ret)
+    }
+
+    /// Receive a tagged message into `bufs`, via `fi_trecvv()`.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn trecvv(
+        &mut self,
+        bufs: &mut [&mut [u8]],
+        mrs: &[&MemoryRegion],
+        src: Addr,
+        tag: u64,
+        ignore: u64,
+        context: usize,
+    ) -> Result<()> {
+        let bufs = bufs
+            .iter_mut()
+            .map(|buf| (buf.as_mut_ptr().cast_const(), buf.len()));
+        let (index, count) = self.gather("fi_trecvv", bufs, mrs, context)?;
+        let slot = &mut self.slots[index];
+        trace::data_op!(
+            &self.ep,
+            "fi_trecvv",
+            size = crate::rma::total(&slot.iov[..count]),
+            tag
+        );
+        let ret = self.ep.post_op(BindFlags::RECV, || unsafe {
+            ffi::fi_trecvv(
+                self.ep.as_raw(),
+                slot.iov.as_ptr(),
+                slot.desc.as_mut_ptr(),
+                count,
+                src.as_raw(),
+                tag,
+                ignore,
+                ptr::from_mut(slot).cast(),
+            )
+        });
+        self.posted(index, "fi_trecvv", ret)
+    }
+
+    /// Write `bufs` one after the other to remote memory at `addr`, via `fi_writev()`.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn writev(
+        &mut self,
+        bufs: &[&[u8]],
+        mrs: &[&MemoryRegion],
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()> {
+        let bufs = bufs.iter().map(|buf| (buf.as_ptr(), buf.len()));
+        let (index, count) = self.gather("fi_writev", bufs, mrs, context)?;
+        let slot = &mut self.slots[index];
+        trace::data_op!(
+            &self.ep,
+            "fi_writev",
+            size = crate::rma::total(&slot.iov[..count])
+        );
+        let ret = self.ep.post_op(BindFlags::TRANSMIT, || unsafe {
+            ffi::fi_writev(
+                self.ep.as_raw(),
+                slot.iov.as_ptr(),
+                slot.desc.as_mut_ptr(),
+                count,
+                dest.as_raw(),
+                addr,
+                key,
+                ptr::from_mut(slot).cast(),
+            )
+        });
+        self.posted(index, "fi_writev", ret)
+    }
+
+    /// Read remote memory at `addr` into `bufs`, filled one after the other, via
+    /// `fi_readv()`.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn readv(
+        &mut self,
+        bufs: &mut [&mut [u8]],
+        mrs: &[&MemoryRegion],
+        src: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()> {
+        let bufs = bufs
+            .iter_mut()
+            .map(|buf| (buf.as_mut_ptr().cast_const(), buf.len()));
+        let (index, count) = self.gather("fi_readv", bufs, mrs, context)?;
+        let slot = &mut self.slots[index];
+        trace::data_op!(
+            &self.ep,
+            "fi_readv",
+            size = crate::rma::total(&slot.iov[..count])
+        );
+        let ret = self.ep.post_op(BindFlags::TRANSMIT, || unsafe {
+            ffi::fi_readv(
+                self.ep.as_raw(),
+                slot.iov.as_ptr(),
+                slot.desc.as_mut_ptr(),
+                count,
+                src.as_raw(),
+                addr,
+                key,
+                ptr::from_mut(slot).cast(),
+            )
+        });
+        self.posted(index, "fi_readv", ret)
+    }
+
+    // Take a free slot for `context`, filling its arrays with the buffers and descriptors.
+    fn gather(
+        &mut self,
+        op: &'static str,
+        bufs: impl ExactSizeIterator<Item = (*const u8, usize)>,
+        mrs: &[&MemoryRegion],
+        context: usize,
+    ) -> Result<(usize, usize)> {
+        let count = bufs.len();
+        if count > ARENA_IOV_LIMIT {
+            return Err(Error::invalid(format!(
+                "{count} buffers exceed the {ARENA_IOV_LIMIT} of an operation arena"
+            )));
+        }
+        if !mrs.is_empty() && mrs.len() != count {
+            return Err(Error::invalid(format!(
+                "{} memory regions for {count} buffers",
+                mrs.len()
+            )));
+        }
+        let slot = self.acquire(op, context)?;
+        let entry = &mut self.slots[slot];
+        for (i, (base, len)) in bufs.enumerate() {
+            entry.iov[i] = ffi::iovec {
+                iov_base: base as *mut _,
+                iov_len: len,
+            };
+            entry.desc[i] = mrs.get(i).map_or(ptr::null_mut(), |mr| mr.desc());
+        }
+        Ok((slot, count))
+    }
+
+    // Free the slot of an operation if its post failed.
+    fn posted(&mut self, slot: usize, op: &'static str, ret: isize) -> Result<()> {
+        let result = check_len(op, ret).map(|_| ());
+        if result.is_err() {
+            self.free(slot);
+        }
+        result
+    }
+}
@@ -0,0 +1,733 @@
+use crate::av::Addr;
+use crate::caps::HasAtomic;
+use crate::domain::Domain;
+use crate::ep::{Enabled, Endpoint};
+use crate::error::{Error, Result, check, check_len};
+use crate::flags::{BindFlags, OpFlags};
+use crate::mr::{MemoryRegion, desc};
+use crate::threading::{ThreadSafe, ThreadingModel};
+use crate::trace;
+use ofi_libfabric_sys::bindgen as ffi;
+use std::ffi::c_void;
+use std::marker::PhantomData;
+use std::mem::size_of;
+use std::os::raw::c_int;
+
+/// Element types libfabric can operate on atomically (`enum fi_datatype`).
+///
+/// # Safety
+///
+/// `DATATYPE` must describe the exact size and representation of the implementing type.
+pub unsafe trait AtomicDatatype: Copy {
+    const DATATYPE: ffi::fi_datatype;
+}
+
+macro_rules! atomic_datatype {
+    ($($ty:ty => $dt:ident),* $(,)?) => {
+        $(unsafe impl AtomicDatatype for $ty {
+            const DATATYPE: ffi::fi_datatype = ffi::fi_datatype::$dt;
+        })*
+    };
+}
+
+atomic_datatype! {
+    i8 => FI_INT8,
+    u8 => FI_UINT8,
+    i16 => FI_INT16,
+    u16 => FI_UINT16,
+    i32 => FI_INT32,
+    u32 => FI_UINT32,
+    i64 => FI_INT64,
+    u64 => FI_UINT64,
+    i128 => FI_INT128,
+    u128 => FI_UINT128,
+    f32 => FI_FLOAT,
+    f64 => FI_DOUBLE,
+}
+
+/// A C `long double`, passed through as its bytes: Rust has no such type, and its size and
+/// representation depend on the platform, ex: 80 bit extended precision padded to 16 bytes on
+/// x86-64, quadruple precision on aarch64. [`Domain::query_atomic()`] fails for providers whose
+/// `long double` is not this size.
+#[repr(C, align(16))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct LongDouble(pub [u8; 16]);
+
+/// A complex number, laid out as C `float complex`, `double complex` and `long double complex`
+/// are.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Complex<T> {
+    pub re: T,
+    pub im: T,
+}
+
+atomic_datatype! {
+    LongDouble => FI_LONG_DOUBLE,
+    Complex<f32> => FI_FLOAT_COMPLEX,
+    Complex<f64> => FI_DOUBLE_COMPLEX,
+    Complex<LongDouble> => FI_LONG_DOUBLE_COMPLEX,
+}
+
+/// The kind of atomic operation a capability is queried for, see
+/// [`Domain::query_atomic()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AtomicKind {
+    /// [`Endpoint::atomic()`] and its variants.
+    Atomic,
+    /// [`Endpoint::fetch_atomic()`] and its variants.
+    Fetch,
+    /// [`Endpoint::compare_atomic()`] and its variants.
+    Compare,
+}
+
+/// Atomic operations (`enum fi_op`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AtomicOp {
+    Min,
+    Max,
+    Sum,
+    Prod,
+    Lor,
+    Land,
+    Bor,
+    Band,
+    Lxor,
+    Bxor,
+    /// Fetch only, the source buffer is ignored.
+    Read,
+    Write,
+    /// Compare and swap if equal.
+    Cswap,
+    CswapNe,
+    CswapLe,
+    CswapLt,
+    CswapGe,
+    CswapGt,
+    /// Masked swap, the compare buffer holds the mask.
+    Mswap,
+}
+
+impl AtomicOp {
+    pub(crate) fn as_raw(self) -> ffi::fi_op {
+        match self {
+            AtomicOp::Min => ffi::fi_op::FI_MIN,
+            AtomicOp::Max => ffi::fi_op::FI_MAX,
+            AtomicOp::Sum => ffi::fi_op::FI_SUM,
+            AtomicOp::Prod => ffi::fi_op::FI_PROD,
+            AtomicOp::Lor => ffi::fi_op::FI_LOR,
+            AtomicOp::Land => ffi::fi_op::FI_LAND,
+            AtomicOp::Bor => ffi::fi_op::FI_BOR,
+            AtomicOp::Band => ffi::fi_op::FI_BAND,
+            AtomicOp::Lxor => ffi::fi_op::FI_LXOR,
+            AtomicOp::Bxor => ffi::fi_op::FI_BXOR,
+            AtomicOp::Read => ffi::fi_op::FI_ATOMIC_READ,
+            AtomicOp::Write => ffi::fi_op::FI_ATOMIC_WRITE,
+            AtomicOp::Cswap => ffi::fi_op::FI_CSWAP,
+            AtomicOp::CswapNe => ffi::fi_op::FI_CSWAP_NE,
+            AtomicOp::CswapLe => ffi::fi_op::FI_CSWAP_LE,
+            AtomicOp::CswapLt => ffi::fi_op::FI_CSWAP_LT,
+            AtomicOp::CswapGe => ffi::fi_op::FI_CSWAP_GE,
+            AtomicOp::CswapGt => ffi::fi_op::FI_CSWAP_GT,
+            AtomicOp::Mswap => ffi::fi_op::FI_MSWAP,
+        }
+    }
+}
+
+/// The arrays of a vectored or message atomic operation, built buffer by buffer: the source
+/// elements, those compared with by compare operations, those fetched into by fetch and compare
+/// operations, and the remote segments of messages. The buffers stay borrowed for as long as
+/// the builder, which must outlive the operation.
+///
+/// ```no_run
+/// # use libfabric::{Addr, Endpoint};
+/// # fn run(ep: &Endpoint, dest: Addr, addr: u64, key: u64) -> libfabric::Result<()> {
+/// use libfabric::{AtomicMsg, AtomicOp, OpFlags};
+///
+/// // Add to two strided elements, fetching their previous values.
+/// let (ones, mut previous) = ([1u64; 2], [0u64; 2]);
+/// let (first, second) = previous.split_at_mut(1);
+/// let msg = AtomicMsg::new()
+///     .buf(&ones, None)
+///     .result(first, None)
+///     .result(second, None)
+///     .remote(addr, 1, key)
+///     .remote(addr + 64, 1, key);
+/// unsafe { ep.fetch_atomicmsg(&msg, dest, AtomicOp::Sum, 1, OpFlags::COMPLETION) }?;
+/// # Ok(())
+/// # }
+/// ```
+#[must_use]
+pub struct AtomicMsg<'a, T: AtomicDatatype, M: ThreadingModel = ThreadSafe> {
+    bufs: Iocs,
+    compare: Iocs,
+    result: Iocs,
+    remote: Vec<ffi::fi_rma_ioc>,
+    data: u64,
+    borrows: PhantomData<(&'a mut [T], &'a MemoryRegion<M>)>,
+}
+
+// Buffers of elements, with their descriptors.
+#[derive(Default)]
+struct Iocs {
+    ioc: Vec<ffi::fi_ioc>,
+    desc: Vec<*mut c_void>,
+}
+
+impl Iocs {
+    fn push<T, M: ThreadingModel>(
+        &mut self,
+        buf: *const T,
+        count: usize,
+        mr: Option<&MemoryRegion<M>>,
+    ) {
+        self.ioc.push(ffi::fi_ioc {
+            addr: buf as *mut _,
+            count,
+        });
+        self.desc.push(desc(mr));
+    }
+
+    fn count(&self) -> usize {
+        self.ioc.iter().map(|ioc| ioc.count).sum()
+    }
+}
+
+impl<T: AtomicDatatype, M: ThreadingModel> Default for AtomicMsg<'_, T, M> {
+    fn default() -> Self {
+        AtomicMsg {
+            bufs: Iocs::default(),
+            compare: Iocs::default(),
+            result: Iocs::default(),
+            remote: Vec::new(),
+            data: 0,
+            borrows: PhantomData,
+        }
+    }
+}
+
+impl<'a, T: AtomicDatatype, M: ThreadingModel> AtomicMsg<'a, T, M> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a buffer of source elements.
+    pub fn buf(mut self, buf: &'a [T], mr: Option<&'a MemoryRegion<M>>) -> Self {
+        self.bufs.push(buf.as_ptr(), buf.len(), mr);
+        self
+    }
+
+    /// Append a buffer of elements to compare with, or of masks for [`AtomicOp::Mswap`].
+    pub fn compare(mut self, buf: &'a [T], mr: Option<&'a MemoryRegion<M>>) -> Self {
+        self.compare.push(buf.as_ptr(), buf.len(), mr);
+        self
+    }
+
+    /// Append a buffer the previous remote elements are fetched into.
+    pub fn result(mut self, buf: &'a mut [T], mr: Option<&'a MemoryRegion<M>>) -> Self {
+        self.result.push(buf.as_mut_ptr(), buf.len(), mr);
+        self
+    }
+
+    /// Append a remote segment of `count` elements at `addr`, accessed through `key`, for the
+    /// message operations.
+    pub fn remote(mut self, addr: u64, count: usize, key: u64) -> Self {
+        self.remote.push(ffi::fi_rma_ioc { addr, count, key });
+        self
+    }
+
+    /// Remote CQ data of the message operations, sent with `FI_REMOTE_CQ_DATA`.
+    pub fn data(mut self, data: u64) -> Self {
+        self.data = data;
+        self
+    }
+
+    // The elements fetched or compared with must match the source elements one to one.
+    fn check(&self, iocs: &Iocs, name: &str) -> Result<()> {
+        let (count, expected) = (iocs.count(), self.bufs.count());
+        if count == expected {
+            return Ok(());
+        }
+        Err(Error::invalid(format!(
+            "{count} {name} elements for {expected} source elements"
+        )))
+    }
+
+    fn raw(&self, dest: Addr, op: AtomicOp, context: usize) -> Result<ffi::fi_msg_atomic> {
+        if self.remote.is_empty() {
+            return Err(Error::invalid("atomic message without remote segments"));
+        }
+        Ok(ffi::fi_msg_atomic {
+            msg_iov: self.bufs.ioc.as_ptr(),
+            desc: self.bufs.desc.as_ptr() as *mut _,
+            iov_count: self.bufs.ioc.len(),
+            addr: dest.as_raw(),
+            rma_iov: self.remote.as_ptr(),
+            rma_iov_count: self.remote.len(),
+            datatype: T::DATATYPE,
+            op: op.as_raw(),
+            context: context as *mut _,
+            data: self.data,
+        })
+    }
+}
+
+/// Atomic operations on remote memory (`fi_atomic(3)`), element wise over `buf`.
+///
+/// As with RMA, the target is the peer's region at `addr`, accessed through `key`.
+impl<M: ThreadingModel, P: HasAtomic> Endpoint<M, Enabled, P> {
+    /// Apply `op` with the elements of `buf` to remote memory.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn atomic<T: AtomicDatatype>(
+        &self,
+        buf: &[T],
+        mr: Option<&MemoryRegion<M>>,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        op: AtomicOp,
+        context: usize,
+    ) -> Result<()> {
+        trace::data_op!(self, "fi_atomic", size = std::mem::size_of_val(buf));
+        let ret = self.post_op(BindFlags::TRANSMIT, || {
+            trace::tracked!(self, "fi_atomic", context, None, [reads(buf)], unsafe {
+                ffi::fi_atomic(
+                    self.as_raw(),
+                    buf.as_ptr().cast(),
+                    buf.len(),
+                    desc(mr),
+                    dest.as_raw(),
+                    addr,
+                    key,
+                    T::DATATYPE,
+                    op.as_raw(),
+                    context as *mut _,
+                )
+            })
+        });
+        check_len("fi_atomic", ret).map(|_| ())
+    }
+
+    /// Like [`atomic()`](Self::atomic) for small buffers, without a completion.
+    pub fn inject_atomic<T: AtomicDatatype>(
+        &self,
+        buf: &[T],
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        op: AtomicOp,
+    ) -> Result<()> {
+        trace::data_op!(self, "fi_inject_atomic", size = std::mem::size_of_val(buf));
+        let ret = unsafe {
+            ffi::fi_inject_atomic(
+                self.as_raw(),
+                buf.as_ptr().cast(),
+                buf.len(),
+                dest.as_raw(),
+                addr,
+                key,
+                T::DATATYPE,
+                op.as_raw(),
+            )
+        };
+        check_len("fi_inject_atomic", ret).map(|_| ())
+    }
+
+    /// Apply `op` to remote memory, returning the previous values in `result`.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation; `result` is written when the operation completes.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn fetch_atomic<T: AtomicDatatype>(
+        &self,
+        buf: &[T],
+        mr: Option<&MemoryRegion<M>>,
+        result: &mut [T],
+        result_mr: Option<&MemoryRegion<M>>,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        op: AtomicOp,
+        context: usize,
+    ) -> Result<()> {
+        trace::data_op!(self, "fi_fetch_atomic", size = std::mem::size_of_val(buf));
+        let ret = self.post_op(BindFlags::TRANSMIT, || {
+            trace::tracked!(
+                self,
+                "fi_fetch_atomic",
+                context,
+                None,
+                [reads(buf), writes(result)],
+                unsafe {
+                    ffi::fi_fetch_atomic(
+                        self.as_raw(),
+                        buf.as_ptr().cast(),
+                        buf.len().min(result.len()),
+                        desc(mr),
+                        result.as_mut_ptr().cast(),
+                        desc(result_mr),
+                        dest.as_raw(),
+                        addr,
+                        key,
+                        T::DATATYPE,
+                        op.as_raw(),
+                        context as *mut _,
+                    )
+                }
+            )
+        });
+        check_len("fi_fetch_atomic", ret).map(|_| ())
+    }
+
+    /// Apply a compare operation to remote memory, returning the previous values in `result`.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation; `result` is written when the operation completes.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn compare_atomic<T: AtomicDatatype>(
+        &self,
+        buf: &[T],
+        mr: Option<&MemoryRegion<M>>,
+        compare: &[T],
+        compare_mr: Option<&MemoryRegion<M>>,
+        result: &mut [T],
+        result_mr: Option<&MemoryRegion<M>>,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        op: AtomicOp,
+        context: usize,
+    ) -> Result<()> {
+        trace::data_op!(self, "fi_compare_atomic", size = std::mem::size_of_val(buf));
+        let ret = self.post_op(BindFlags::TRANSMIT, || {
+            trace::tracked!(
+                self,
+                "fi_compare_atomic",
+                context,
+                None,
+                [reads(buf), reads(compare), writes(result)],
+                unsafe {
+                    ffi::fi_compare_atomic(
+                        self.as_raw(),
+                        buf.as_ptr().cast(),
+                        buf.len().min(compare.len()).min(result.len()),
+                        desc(mr),
+                        compare.as_ptr().cast(),
+                        desc(compare_mr),
+                        result.as_mut_ptr().cast(),
+                        desc(result_mr),
+                        dest.as_raw(),
+                        addr,
+                        key,
+                        T::DATATYPE,
+                        op.as_raw(),
+                        context as *mut _,
+                    )
+                }
+            )
+        });
+        check_len("fi_compare_atomic", ret).map(|_| ())
+    }
+
+    /// Like [`atomic()`](Self::atomic), with the source buffers of `msg`, via `fi_atomicv()`.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn atomicv<T: AtomicDatatype>(
+        &self,
+        msg: &AtomicMsg<'_, T, M>,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        op: AtomicOp,
+        context: usize,
+    ) -> Result<()> {
+        trace::data_op!(
+            self,
+            "fi_atomicv",
+            size = msg.bufs.count() * std::mem::size_of::<T>()
+        );
+        let ret = self.post_op(BindFlags::TRANSMIT, || unsafe {
+            ffi::fi_atomicv(
+                self.as_raw(),
+                msg.bufs.ioc.as_ptr(),
+                msg.bufs.desc.as_ptr() as *mut _,
+                msg.bufs.ioc.len(),
+                dest.as_raw(),
+                addr,
+                key,
+                T::DATATYPE,
+                op.as_raw(),
+                context as *mut _,
+            )
+        });
+        check_len("fi_atomicv", ret).map(|_| ())
+    }
+
+    /// Like [`fetch_atomic()`](Self::fetch_atomic), with the source and result buffers of
+    /// `msg`, via `fi_fetch_atomicv()`.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation; the results are written when the operation
+    /// completes.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn fetch_atomicv<T: AtomicDatatype>(
+        &self,
+        msg: &AtomicMsg<'_, T, M>,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        op: AtomicOp,
+        context: usize,
+    ) -> Result<()> {
+        msg.check(&msg.result, "result")?;
+        trace::data_op!(
+            self,
+            "fi_fetch_atomicv",
+            size = msg.bufs.count() * std::mem::size_of::<T>()
+        );
+        let ret = self.post_op(BindFlags::TRANSMIT, || unsafe {
+            ffi::fi_fetch_atomicv(
+                self.as_raw(),
+                msg.bufs.ioc.as_ptr(),
+                msg.bufs.desc.as_ptr() as *mut _,
+                msg.bufs.ioc.len(),
+                msg.result.ioc.as_ptr() as *mut _,
+                msg.result.desc.as_ptr() as *mut _,
+                msg.result.ioc.len(),
+                dest.as_raw(),
+                addr,
+                key,
+                T::DATATYPE,
+                op.as_raw(),
+                context as *mut _,
+            )
+        });
+        check_len("fi_fetch_atomicv", ret).map(|_| ())
+    }
+
+    /// Like [`compare_atomic()`](Self::compare_atomic), with the source, compare and result
+    /// buffers of `msg`, via `fi_compare_atomicv()`.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation; the results are written when the operation
+    /// completes.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn compare_atomicv<T: AtomicDatatype>(
+        &self,
+        msg: &AtomicMsg<'_, T, M>,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        op: AtomicOp,
+        context: usize,
+    ) -> Result<()> {
+        msg.check(&msg.compare, "compare")?;
+        msg.check(&msg.result, "result")?;
+        trace::data_op!(
+            self,
+            "fi_compare_atomicv",
+            size = msg.bufs.count() * std::mem::size_of::<T>()
+        );
+        let ret = self.post_op(BindFlags::TRANSMIT, || unsafe {
+            ffi::fi_compare_atomicv(
+                self.as_raw(),
+                msg.bufs.ioc.as_ptr(),
+                msg.bufs.desc.as_ptr() as *mut _,
+                msg.bufs.ioc.len(),
+                msg.compare.ioc.as_ptr(),
+                msg.compare.desc.as_ptr() as *mut _,
+                msg.compare.ioc.len(),
+                msg.result.ioc.as_ptr() as *mut _,
+                msg.result.desc.as_ptr() as *mut _,
+                msg.result.ioc.len(),
+                dest.as_raw(),
+                addr,
+                key,
+                T::DATATYPE,
+                op.as_raw(),
+                context as *mut _,
+            )
+        });
+        check_len("fi_compare_atomicv", ret).map(|_| ())
+    }
+
+    /// Apply `op` with the source buffers of `msg` to its remote segments, with the flags of
+    /// the operation, via `fi_atomicmsg()`.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    pub unsafe fn atomicmsg<T: AtomicDatatype>(
+        &self,
+        msg: &AtomicMsg<'_, T, M>,
+        dest: Addr,
+        op: AtomicOp,
+        context: usize,
+        flags: OpFlags,
+    ) -> Result<()> {
+        let raw = msg.raw(dest, op, context)?;
+        trace::data_op!(
+            self,
+            "fi_atomicmsg",
+            size = msg.bufs.count() * std::mem::size_of::<T>()
+        );
+        let ret = self.post_op(BindFlags::TRANSMIT, || unsafe {
+            ffi::fi_atomicmsg(self.as_raw(), &raw, flags.bits())
+        });
+        check_len("fi_atomicmsg", ret).map(|_| ())
+    }
+
+    /// Like [`atomicmsg()`](Self::atomicmsg), fetching the previous values into the result
+    /// buffers of `msg`, via `fi_fetch_atomicmsg()`.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation; the results are written when the operation
+    /// completes.
+    pub unsafe fn fetch_atomicmsg<T: AtomicDatatype>(
+        &self,
+        msg: &AtomicMsg<'_, T, M>,
+        dest: Addr,
+        op: AtomicOp,
+        context: usize,
+        flags: OpFlags,
+    ) -> Result<()> {
+        msg.check(&msg.result, "result")?;
+        let raw = msg.raw(dest, op, context)?;
+        trace::data_op!(
+            self,
+            "fi_fetch_atomicmsg",
+            size = msg.bufs.count() * std::mem::size_of::<T>()
+        );
+        let ret = self.post_op(BindFlags::TRANSMIT, || unsafe {
+            ffi::fi_fetch_atomicmsg(
+                self.as_raw(),
+                &raw,
+                msg.result.ioc.as_ptr() as *mut _,
+                msg.result.desc.as_ptr() as *mut _,
+                msg.result.ioc.len(),
+                flags.bits(),
+            )
+        });
+        check_len("fi_fetch_atomicmsg", ret).map(|_| ())
+    }
+
+    /// Like [`fetch_atomicmsg()`](Self::fetch_atomicmsg), for a compare operation with the
+    /// compare buffers of `msg`, via `fi_compare_atomicmsg()`.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation; the results are written when the operation
+    /// completes.
+    pub unsafe fn compare_atomicmsg<T: AtomicDatatype>(
+        &self,
+        msg: &AtomicMsg<'_, T, M>,
+        dest: Addr,
+        op: AtomicOp,
+        context: usize,
+        flags: OpFlags,
+    ) -> Result<()> {
+        msg.check(&msg.compare, "compare")?;
+        msg.check(&msg.result, "result")?;
+        let raw = msg.raw(dest, op, context)?;
+        trace::data_op!(
+            self,
+            "fi_compare_atomicmsg",
+            size = msg.bufs.count() * std::mem::size_of::<T>()
+        );
+        let ret = self.post_op(BindFlags::TRANSMIT, || unsafe {
+            ffi::fi_compare_atomicmsg(
+                self.as_raw(),
+                &raw,
+                msg.compare.ioc.as_ptr(),
+                msg.compare.desc.as_ptr() as *mut _,
+                msg.compare.ioc.len(),
+                msg.result.ioc.as_ptr() as *mut _,
+                msg.result.desc.as_ptr() as *mut _,
+                msg.result.ioc.len(),
+                flags.bits(),
+            )
+        });
+        check_len("fi_compare_atomicmsg", ret).map(|_| ())
+    }
+
+    /// Maximum number of `T` elements [`atomic()`](Self::atomic) accepts for `op`, failing if
+    /// the combination is not supported.
+    pub fn atomic_valid<T: AtomicDatatype>(&self, op: AtomicOp) -> Result<usize> {
+        self.valid("fi_atomicvalid", T::DATATYPE, op, ffi::fi_atomicvalid)
+    }
+
+    /// Like [`atomic_valid()`](Self::atomic_valid), for [`fetch_atomic()`](Self::fetch_atomic).
+    pub fn fetch_atomic_valid<T: AtomicDatatype>(&self, op: AtomicOp) -> Result<usize> {
+        self.valid(
+            "fi_fetch_atomicvalid",
+            T::DATATYPE,
+            op,
+            ffi::fi_fetch_atomicvalid,
+        )
+    }
+
+    /// Like [`atomic_valid()`](Self::atomic_valid), for [`compare_atomic()`](Self::compare_atomic).
+    pub fn compare_atomic_valid<T: AtomicDatatype>(&self, op: AtomicOp) -> Result<usize> {
+        self.valid(
+            "fi_compare_atomicvalid",
+            T::DATATYPE,
+            op,
+            ffi::fi_compare_atomicvalid,
+        )
+    }
+
+    fn valid(
+        &self,
+        op_name: &'static str,
+        datatype: ffi::fi_datatype,
+        op: AtomicOp,
+        valid: unsafe extern "C" fn(
+            *mut ffi::fid_ep,
+            ffi::fi_datatype,
+            ffi::fi_op,
+            *mut usize,
+        ) -> c_int,
+    ) -> Result<usize> {
+        let mut count = 0;
+        check(op_name, unsafe {
+            valid(self.as_raw(), datatype, op.as_raw(), &mut count)
+        })?;
+        Ok(count)
+    }
+}
+
+impl<M: ThreadingModel> Domain<M> {
+    /// Maximum number of `T` elements an operation of `kind` accepts for `op`, on any endpoint
+    /// of the domain (`fi_query_atomic()`).
+    ///
+    /// Fails with `FI_EOPNOTSUPP`, see [`Error::is_unsupported()`], if the provider does not
+    /// support the combination, or if its elements of the datatype are not the size of `T`, as
+    /// happens for [`LongDouble`] where the C `long double` is not 16 bytes.
+    pub fn query_atomic<T: AtomicDatatype>(&self, op: AtomicOp, kind: AtomicKind) -> Result<usize> {
+        let flags = match kind {
+            AtomicKind::Atomic => 0,
+            AtomicKind::Fetch => ffi::FI_FETCH_ATOMIC,
+            AtomicKind::Compare => ffi::FI_COMPARE_ATOMIC,
+        };
+        let mut attr = ffi::fi_atomic_attr::default();
+        check("fi_query_atomic", unsafe {
+            ffi::fi_query_atomic(self.as_raw(), T::DATATYPE, op.as_raw(), &mut attr, flags)
+        })?;
+        if attr.size != size_of::<T>() {
+            return Err(Error::fabric("fi_query_atomic", ffi::FI_EOPNOTSUPP as i64));
+        }
+        Ok(attr.count)
+    }
+}
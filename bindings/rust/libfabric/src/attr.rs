@@ -0,0 +1,836 @@
+use crate::av::AvType;
+use crate::checks::ValidationLevel;
+use crate::error::{Error, Result};
+use crate::flags::{Caps, Mode, MrMode, MsgOrder, OpFlags};
+use crate::info::{EndpointType, Version};
+use crate::threading::{ThreadSafe, Threading, ThreadingModel};
+use crate::util::{cstr, read_enum, write_enum};
+use ofi_libfabric_sys::bindgen as ffi;
+use std::marker::PhantomData;
+
+macro_rules! protocols {
+    ($($(#[$meta:meta])* $name:ident => $raw:ident),* $(,)?) => {
+        /// Wire protocols of endpoints (`FI_PROTO_*`), as reported by providers in
+        /// [`EpAttr::protocol`], or required in hints with
+        /// [`Info::protocol()`](crate::Info::protocol).
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+        pub enum Protocol {
+            /// Any protocol, in hints.
+            #[default]
+            Unspec,
+            $($(#[$meta])* $name,)*
+            /// A raw value these bindings do not know about, ex: from a newer libfabric.
+            Other(u32),
+        }
+
+        impl Protocol {
+            pub(crate) fn from_raw(raw: u32) -> Self {
+                match raw {
+                    ffi::FI_PROTO_UNSPEC => Protocol::Unspec,
+                    $(ffi::$raw => Protocol::$name,)*
+                    _ => Protocol::Other(raw),
+                }
+            }
+
+            pub(crate) fn as_raw(self) -> u32 {
+                match self {
+                    Protocol::Unspec => ffi::FI_PROTO_UNSPEC,
+                    $(Protocol::$name => ffi::$raw,)*
+                    Protocol::Other(raw) => raw,
+                }
+            }
+        }
+    };
+}
+
+protocols! {
+    /// InfiniBand reliable connections, through the RDMA connection manager.
+    RdmaCmIbRc => FI_PROTO_RDMA_CM_IB_RC,
+    Iwarp => FI_PROTO_IWARP,
+    /// InfiniBand unreliable datagrams.
+    IbUd => FI_PROTO_IB_UD,
+    Udp => FI_PROTO_UDP,
+    SockTcp => FI_PROTO_SOCK_TCP,
+    IwarpRdm => FI_PROTO_IWARP_RDM,
+    IbRdm => FI_PROTO_IB_RDM,
+    /// The reliable datagrams of the rxm utility provider, over MSG endpoints.
+    Rxm => FI_PROTO_RXM,
+    /// The reliable datagrams of the rxd utility provider, over datagram endpoints.
+    Rxd => FI_PROTO_RXD,
+    Mlx => FI_PROTO_MLX,
+    NetworkDirect => FI_PROTO_NETWORKDIRECT,
+    Psmx2 => FI_PROTO_PSMX2,
+    Shm => FI_PROTO_SHM,
+    Mrail => FI_PROTO_MRAIL,
+    Rstream => FI_PROTO_RSTREAM,
+    /// InfiniBand extended reliable connections.
+    RdmaCmIbXrc => FI_PROTO_RDMA_CM_IB_XRC,
+    Efa => FI_PROTO_EFA,
+    Psmx3 => FI_PROTO_PSMX3,
+    RxmTcp => FI_PROTO_RXM_TCP,
+    Opx => FI_PROTO_OPX,
+    Cxi => FI_PROTO_CXI,
+    /// The protocol of the tcp provider.
+    Xnet => FI_PROTO_XNET,
+    Coll => FI_PROTO_COLL,
+    Ucx => FI_PROTO_UCX,
+    Sm2 => FI_PROTO_SM2,
+    /// CXI, with receiver-not-ready retries rather than hardware matching.
+    CxiRnr => FI_PROTO_CXI_RNR,
+    Lpp => FI_PROTO_LPP,
+    Lnx => FI_PROTO_LNX,
+}
+
+/// Traffic classes (`FI_TC_*`): the service messages get from the network, in hints with
+/// [`Info::tclass()`](crate::Info::tclass), or per endpoint with [`TxQueueAttr::tclass()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TrafficClass {
+    /// The provider's default.
+    #[default]
+    Unspec,
+    BestEffort,
+    /// Small messages, which should arrive soon.
+    LowLatency,
+    /// Traffic with a reserved share of the network.
+    DedicatedAccess,
+    /// Large transfers, which favor bandwidth over latency.
+    BulkData,
+    /// Traffic using what the other classes leave.
+    Scavenger,
+    /// Control messages of the network itself.
+    NetworkCtrl,
+    /// An IP differentiated services code point, from 0 to 63.
+    Dscp(u8),
+    /// A raw value these bindings do not know about, ex: a provider specific class.
+    Other(u32),
+}
+
+impl TrafficClass {
+    pub(crate) fn from_raw(raw: u32) -> Self {
+        match raw {
+            ffi::FI_TC_UNSPEC => TrafficClass::Unspec,
+            ffi::FI_TC_BEST_EFFORT => TrafficClass::BestEffort,
+            ffi::FI_TC_LOW_LATENCY => TrafficClass::LowLatency,
+            ffi::FI_TC_DEDICATED_ACCESS => TrafficClass::DedicatedAccess,
+            ffi::FI_TC_BULK_DATA => TrafficClass::BulkData,
+            ffi::FI_TC_SCAVENGER => TrafficClass::Scavenger,
+            ffi::FI_TC_NETWORK_CTRL => TrafficClass::NetworkCtrl,
+            raw if raw & !0xff == ffi::FI_TC_DSCP => TrafficClass::Dscp(raw as u8),
+            _ => TrafficClass::Other(raw),
+        }
+    }
+
+    pub(crate) fn as_raw(self) -> u32 {
+        match self {
+            TrafficClass::Unspec => ffi::FI_TC_UNSPEC,
+            TrafficClass::BestEffort => ffi::FI_TC_BEST_EFFORT,
+            TrafficClass::LowLatency => ffi::FI_TC_LOW_LATENCY,
+            TrafficClass::DedicatedAccess => ffi::FI_TC_DEDICATED_ACCESS,
+            TrafficClass::BulkData => ffi::FI_TC_BULK_DATA,
+            TrafficClass::Scavenger => ffi::FI_TC_SCAVENGER,
+            TrafficClass::NetworkCtrl => ffi::FI_TC_NETWORK_CTRL,
+            // As fi_tc_dscp_set().
+            TrafficClass::Dscp(dscp) => ffi::FI_TC_DSCP | dscp as u32,
+            TrafficClass::Other(raw) => raw,
+        }
+    }
+}
+
+/// The attributes of a fabric (`struct fi_fabric_attr`), from [`InfoEntry::fabric_attr()`].
+///
+/// [`InfoEntry::fabric_attr()`]: crate::InfoEntry::fabric_attr
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FabricAttr {
+    pub name: String,
+    /// The provider, with its layers, ex: `tcp;ofi_rxm`.
+    pub provider: String,
+    pub provider_version: Version,
+    /// The version of the libfabric API the provider implements.
+    pub api_version: Version,
+}
+
+impl FabricAttr {
+    // SAFETY: The strings of `attr` must be NULL or valid.
+    pub(crate) unsafe fn from_raw(attr: &ffi::fi_fabric_attr) -> Self {
+        FabricAttr {
+            name: unsafe { cstr(attr.name) }.to_owned(),
+            provider: unsafe { cstr(attr.prov_name) }.to_owned(),
+            provider_version: Version::from_raw(attr.prov_version),
+            api_version: Version::from_raw(attr.api_version),
+        }
+    }
+}
+
+/// How a provider makes progress on operations or connections (`enum fi_progress`), as reported
+/// in [`DomainAttr::control_progress`] and [`DomainAttr::data_progress`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Progress {
+    /// Unknown, or any mode in hints.
+    #[default]
+    Unspec,
+    /// The provider progresses on its own, ex: in hardware or from a thread of its own.
+    Auto,
+    /// Progress is only made from within calls into the provider, such as reading completion
+    /// queues, which the application must keep making.
+    Manual,
+    /// Control operations progress along with data ones, the same way.
+    ControlUnified,
+    /// A raw value these bindings do not know about, ex: from a newer libfabric.
+    Other(u32),
+}
+
+impl Progress {
+    pub(crate) fn from_raw(raw: u32) -> Self {
+        match ffi::fi_progress::try_from(raw) {
+            Ok(ffi::fi_progress::FI_PROGRESS_UNSPEC) => Progress::Unspec,
+            Ok(ffi::fi_progress::FI_PROGRESS_AUTO) => Progress::Auto,
+            Ok(ffi::fi_progress::FI_PROGRESS_MANUAL) => Progress::Manual,
+            Ok(ffi::fi_progress::FI_PROGRESS_CONTROL_UNIFIED) => Progress::ControlUnified,
+            _ => Progress::Other(raw),
+        }
+    }
+
+    pub(crate) fn as_raw(self) -> u32 {
+        match self {
+            Progress::Unspec => ffi::fi_progress::FI_PROGRESS_UNSPEC as u32,
+            Progress::Auto => ffi::fi_progress::FI_PROGRESS_AUTO as u32,
+            Progress::Manual => ffi::fi_progress::FI_PROGRESS_MANUAL as u32,
+            Progress::ControlUnified => ffi::fi_progress::FI_PROGRESS_CONTROL_UNIFIED as u32,
+            Progress::Other(raw) => raw,
+        }
+    }
+}
+
+/// Whether a provider protects the application from overrunning queues and peers
+/// (`enum fi_resource_mgmt`), as reported in [`DomainAttr::resource_mgmt`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ResourceMgmt {
+    /// Unknown, or either in hints.
+    #[default]
+    Unspec,
+    /// Operations overrunning a queue or a peer are undefined behavior, which the application
+    /// must prevent, ex: with credits.
+    Disabled,
+    /// Operations which would overrun a queue or a peer fail with `FI_EAGAIN`, or are retried
+    /// by the provider.
+    Enabled,
+    /// A raw value these bindings do not know about, ex: from a newer libfabric.
+    Other(u32),
+}
+
+impl ResourceMgmt {
+    pub(crate) fn from_raw(raw: u32) -> Self {
+        match ffi::fi_resource_mgmt::try_from(raw) {
+            Ok(ffi::fi_resource_mgmt::FI_RM_UNSPEC) => ResourceMgmt::Unspec,
+            Ok(ffi::fi_resource_mgmt::FI_RM_DISABLED) => ResourceMgmt::Disabled,
+            Ok(ffi::fi_resource_mgmt::FI_RM_ENABLED) => ResourceMgmt::Enabled,
+            _ => ResourceMgmt::Other(raw),
+        }
+    }
+
+    pub(crate) fn as_raw(self) -> u32 {
+        match self {
+            ResourceMgmt::Unspec => ffi::fi_resource_mgmt::FI_RM_UNSPEC as u32,
+            ResourceMgmt::Disabled => ffi::fi_resource_mgmt::FI_RM_DISABLED as u32,
+            ResourceMgmt::Enabled => ffi::fi_resource_mgmt::FI_RM_ENABLED as u32,
+            ResourceMgmt::Other(raw) => raw,
+        }
+    }
+}
+
+/// The attributes of a domain (`struct fi_domain_attr`), from [`InfoEntry::domain_attr()`]:
+/// the sizes of keys and data, and the numbers of objects it is optimized for.
+///
+/// [`InfoEntry::domain_attr()`]: crate::InfoEntry::domain_attr
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DomainAttr {
+    pub name: String,
+    pub caps: Caps,
+    pub mode: Mode,
+    pub mr_mode: MrMode,
+    /// The size of the keys of memory regions, in bytes.
+    pub mr_key_size: usize,
+    /// The size of the remote CQ data carried by operations, in bytes.
+    pub cq_data_size: usize,
+    pub cq_cnt: usize,
+    pub ep_cnt: usize,
+    pub tx_ctx_cnt: usize,
+    pub rx_ctx_cnt: usize,
+    /// The transmit and receive contexts of a scalable endpoint.
+    pub max_ep_tx_ctx: usize,
+    pub max_ep_rx_ctx: usize,
+    /// The shared transmit and receive contexts an endpoint may be bound to.
+    pub max_ep_stx_ctx: usize,
+    pub max_ep_srx_ctx: usize,
+    pub cntr_cnt: usize,
+    /// The buffers a memory region may span.
+    pub mr_iov_limit: usize,
+    pub mr_cnt: usize,
+    /// The size of the provider specific data of error completions.
+    pub max_err_data: usize,
+    /// The traffic class endpoints default to.
+    pub tclass: TrafficClass,
+    /// The thread safety the provider guarantees for the objects of the domain.
+    pub threading: Threading,
+    /// The type of the address vectors opened with [`AvType::Unspec`].
+    pub av_type: AvType,
+    /// How connection management and address vector operations progress.
+    pub control_progress: Progress,
+    /// How data transfers progress.
+    pub data_progress: Progress,
+    /// Whether the provider protects queues and peers from being overrun.
+    pub resource_mgmt: ResourceMgmt,
+}
+
+impl DomainAttr {
+    // SAFETY: The name of `attr` must be NULL or valid.
+    pub(crate) unsafe fn from_raw(attr: &ffi::fi_domain_attr) -> Self {
+        DomainAttr {
+            name: unsafe { cstr(attr.name) }.to_owned(),
+            caps: Caps::from_bits_retain(attr.caps),
+            mode: Mode::from_bits_retain(attr.mode),
+            mr_mode: MrMode::from_bits_retain(attr.mr_mode as u32),
+            mr_key_size: attr.mr_key_size,
+            cq_data_size: attr.cq_data_size,
+            cq_cnt: attr.cq_cnt,
+            ep_cnt: attr.ep_cnt,
+            tx_ctx_cnt: attr.tx_ctx_cnt,
+            rx_ctx_cnt: attr.rx_ctx_cnt,
+            max_ep_tx_ctx: attr.max_ep_tx_ctx,
+            max_ep_rx_ctx: attr.max_ep_rx_ctx,
+            max_ep_stx_ctx: attr.max_ep_stx_ctx,
+            max_ep_srx_ctx: attr.max_ep_srx_ctx,
+            cntr_cnt: attr.cntr_cnt,
+            mr_iov_limit: attr.mr_iov_limit,
+            mr_cnt: attr.mr_cnt,
+            max_err_data: attr.max_err_data,
+            tclass: TrafficClass::from_raw(attr.tclass),
+            threading: Threading::from_raw(unsafe { read_enum(&raw const attr.threading) }),
+            av_type: AvType::from_raw(unsafe { read_enum(&raw const attr.av_type) }),
+            control_progress: Progress::from_raw(unsafe {
+                read_enum(&raw const attr.control_progress)
+            }),
+            data_progress: Progress::from_raw(data_progress(attr)),
+            resource_mgmt: ResourceMgmt::from_raw(unsafe {
+                read_enum(&raw const attr.resource_mgmt)
+            }),
+        }
+    }
+}
+
+// Libfabric 2.0 shares the data progress field with its new name, `progress`, in a union.
+fn data_progress(attr: &ffi::fi_domain_attr) -> u32 {
+    #[cfg(libfabric_ge_2_0)]
+    let raw = unsafe { read_enum(&raw const attr.__bindgen_anon_1.data_progress) };
+    #[cfg(not(libfabric_ge_2_0))]
+    let raw = unsafe { read_enum(&raw const attr.data_progress) };
+    raw
+}
+
+fn set_data_progress(attr: &mut ffi::fi_domain_attr, raw: u32) {
+    #[cfg(libfabric_ge_2_0)]
+    unsafe {
+        write_enum(&raw mut attr.__bindgen_anon_1.data_progress, raw)
+    };
+    #[cfg(not(libfabric_ge_2_0))]
+    unsafe {
+        write_enum(&raw mut attr.data_progress, raw)
+    };
+}
+
+/// The attributes of an endpoint (`struct fi_ep_attr`), from [`InfoEntry::ep_attr()`].
+///
+/// [`InfoEntry::ep_attr()`]: crate::InfoEntry::ep_attr
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EpAttr {
+    pub ep_type: EndpointType,
+    /// The wire protocol, and its version.
+    pub protocol: Protocol,
+    pub protocol_version: u32,
+    pub max_msg_size: usize,
+    /// The space reserved ahead of the buffers of messages, with [`Mode::MSG_PREFIX`].
+    pub msg_prefix_size: usize,
+    /// The largest messages ordered read after write, write after read and write after write.
+    pub max_order_raw_size: usize,
+    pub max_order_war_size: usize,
+    pub max_order_waw_size: usize,
+    /// The tag bits the provider matches on, and those it ignores.
+    pub mem_tag_format: u64,
+    pub tx_ctx_cnt: usize,
+    pub rx_ctx_cnt: usize,
+    pub auth_key_size: usize,
+}
+
+impl EpAttr {
+    pub(crate) fn from_raw(attr: &ffi::fi_ep_attr) -> Self {
+        EpAttr {
+            ep_type: EndpointType::from_raw(unsafe { read_enum(&raw const attr.type_) }),
+            protocol: Protocol::from_raw(attr.protocol),
+            protocol_version: attr.protocol_version,
+            max_msg_size: attr.max_msg_size,
+            msg_prefix_size: attr.msg_prefix_size,
+            max_order_raw_size: attr.max_order_raw_size,
+            max_order_war_size: attr.max_order_war_size,
+            max_order_waw_size: attr.max_order_waw_size,
+            mem_tag_format: attr.mem_tag_format,
+            tx_ctx_cnt: attr.tx_ctx_cnt,
+            rx_ctx_cnt: attr.rx_ctx_cnt,
+            auth_key_size: attr.auth_key_size,
+        }
+    }
+}
+
+/// The attributes of the transmit context of an endpoint (`struct fi_tx_attr`), from
+/// [`InfoEntry::tx_attr()`].
+///
+/// [`InfoEntry::tx_attr()`]: crate::InfoEntry::tx_attr
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TxAttr {
+    pub caps: Caps,
+    pub mode: Mode,
+    /// The default flags of operations.
+    pub op_flags: OpFlags,
+    /// The orderings of messages and of their completions.
+    pub msg_order: MsgOrder,
+    pub comp_order: MsgOrder,
+    /// The largest buffer sent with `FI_INJECT`, reusable as soon as the call returns.
+    pub inject_size: usize,
+    /// The operations the context queues.
+    pub size: usize,
+    pub iov_limit: usize,
+    pub rma_iov_limit: usize,
+    pub tclass: TrafficClass,
+}
+
+impl TxAttr {
+    pub(crate) fn from_raw(attr: &ffi::fi_tx_attr) -> Self {
+        TxAttr {
+            caps: Caps::from_bits_retain(attr.caps),
+            mode: Mode::from_bits_retain(attr.mode),
+            op_flags: OpFlags::from_bits_retain(attr.op_flags),
+            msg_order: MsgOrder::from_bits_retain(attr.msg_order),
+            comp_order: MsgOrder::from_bits_retain(attr.comp_order),
+            inject_size: attr.inject_size,
+            size: attr.size,
+            iov_limit: attr.iov_limit,
+            rma_iov_limit: attr.rma_iov_limit,
+            tclass: TrafficClass::from_raw(attr.tclass),
+        }
+    }
+}
+
+/// The attributes of the receive context of an endpoint (`struct fi_rx_attr`), from
+/// [`InfoEntry::rx_attr()`].
+///
+/// [`InfoEntry::rx_attr()`]: crate::InfoEntry::rx_attr
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RxAttr {
+    pub caps: Caps,
+    pub mode: Mode,
+    pub op_flags: OpFlags,
+    pub msg_order: MsgOrder,
+    pub comp_order: MsgOrder,
+    /// The bytes the provider buffers for messages arriving before their receive is posted.
+    pub total_buffered_recv: usize,
+    /// The receives the context queues.
+    pub size: usize,
+    pub iov_limit: usize,
+}
+
+impl RxAttr {
+    pub(crate) fn from_raw(attr: &ffi::fi_rx_attr) -> Self {
+        RxAttr {
+            caps: Caps::from_bits_retain(attr.caps),
+            mode: Mode::from_bits_retain(attr.mode),
+            op_flags: OpFlags::from_bits_retain(attr.op_flags),
+            msg_order: MsgOrder::from_bits_retain(attr.msg_order),
+            comp_order: MsgOrder::from_bits_retain(attr.comp_order),
+            total_buffered_recv: attr.total_buffered_recv,
+            size: attr.size,
+            iov_limit: attr.iov_limit,
+        }
+    }
+}
+
+/// Settings of the transmit context of an endpoint, applied to hints with
+/// [`Info::tx_attr()`], or to an entry before opening its endpoint with
+/// [`InfoEntry::with_tx_attr()`]. Settings left at 0, or empty, keep those of the provider.
+///
+/// ```no_run
+/// use libfabric::{MsgOrder, OpFlags, TxQueueAttr};
+///
+/// # fn run(domain: &libfabric::Domain, entry: &libfabric::InfoEntry) -> libfabric::Result<()> {
+/// let tx = TxQueueAttr::new()
+///     .size(4096)
+///     .iov_limit(4)
+///     .msg_order(MsgOrder::SAS)
+///     .op_flags(OpFlags::DELIVERY_COMPLETE);
+/// let ep = domain.endpoint(&entry.with_tx_attr(&tx)?)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`Info::tx_attr()`]: crate::Info::tx_attr
+/// [`InfoEntry::with_tx_attr()`]: crate::InfoEntry::with_tx_attr
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[must_use]
+pub struct TxQueueAttr {
+    size: usize,
+    iov_limit: usize,
+    rma_iov_limit: usize,
+    inject_size: usize,
+    op_flags: OpFlags,
+    msg_order: MsgOrder,
+    comp_order: MsgOrder,
+    tclass: TrafficClass,
+}
+
+impl TxQueueAttr {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The operations the context queues.
+    pub fn size(mut self, size: usize) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// The buffers of a message.
+    pub fn iov_limit(mut self, limit: usize) -> Self {
+        self.iov_limit = limit;
+        self
+    }
+
+    /// The remote buffers of an RMA operation.
+    pub fn rma_iov_limit(mut self, limit: usize) -> Self {
+        self.rma_iov_limit = limit;
+        self
+    }
+
+    /// The largest buffer sent with `FI_INJECT`.
+    pub fn inject_size(mut self, size: usize) -> Self {
+        self.inject_size = size;
+        self
+    }
+
+    /// The default flags of the operations.
+    pub fn op_flags(mut self, flags: OpFlags) -> Self {
+        self.op_flags = flags;
+        self
+    }
+
+    /// The orderings of the messages the context must keep.
+    pub fn msg_order(mut self, order: MsgOrder) -> Self {
+        self.msg_order = order;
+        self
+    }
+
+    /// The orderings of the completions the context must keep.
+    pub fn comp_order(mut self, order: MsgOrder) -> Self {
+        self.comp_order = order;
+        self
+    }
+
+    /// The traffic class of the messages, overriding that of the domain.
+    pub fn tclass(mut self, tclass: TrafficClass) -> Self {
+        self.tclass = tclass;
+        self
+    }
+
+    /// Check the settings against the maxima of a provider, ex: the
+    /// [`tx_attr()`](crate::InfoEntry::tx_attr) of an entry.
+    pub fn validate(&self, max: &TxAttr) -> Result<()> {
+        within("tx size", self.size, max.size)?;
+        within("tx iov limit", self.iov_limit, max.iov_limit)?;
+        within("tx rma iov limit", self.rma_iov_limit, max.rma_iov_limit)?;
+        within("inject size", self.inject_size, max.inject_size)?;
+        ordered("tx msg order", self.msg_order, max.msg_order)?;
+        ordered("tx comp order", self.comp_order, max.comp_order)
+    }
+
+    pub(crate) fn apply(&self, attr: &mut ffi::fi_tx_attr) {
+        set(&mut attr.size, self.size);
+        set(&mut attr.iov_limit, self.iov_limit);
+        set(&mut attr.rma_iov_limit, self.rma_iov_limit);
+        set(&mut attr.inject_size, self.inject_size);
+        set(&mut attr.op_flags, self.op_flags.bits());
+        set(&mut attr.msg_order, self.msg_order.bits());
+        set(&mut attr.comp_order, self.comp_order.bits());
+        set(&mut attr.tclass, self.tclass.as_raw());
+    }
+}
+
+/// Settings of the receive context of an endpoint, applied to hints with
+/// [`Info::rx_attr()`], or to an entry before opening its endpoint with
+/// [`InfoEntry::with_rx_attr()`]. Settings left at 0, or empty, keep those of the provider.
+///
+/// [`Info::rx_attr()`]: crate::Info::rx_attr
+/// [`InfoEntry::with_rx_attr()`]: crate::InfoEntry::with_rx_attr
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[must_use]
+pub struct RxQueueAttr {
+    size: usize,
+    iov_limit: usize,
+    total_buffered_recv: usize,
+    op_flags: OpFlags,
+    msg_order: MsgOrder,
+    comp_order: MsgOrder,
+}
+
+impl RxQueueAttr {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The receives the context queues.
+    pub fn size(mut self, size: usize) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// The buffers of a receive.
+    pub fn iov_limit(mut self, limit: usize) -> Self {
+        self.iov_limit = limit;
+        self
+    }
+
+    /// The bytes buffered for messages arriving before their receive is posted.
+    pub fn total_buffered_recv(mut self, size: usize) -> Self {
+        self.total_buffered_recv = size;
+        self
+    }
+
+    /// The default flags of the receives.
+    pub fn op_flags(mut self, flags: OpFlags) -> Self {
+        self.op_flags = flags;
+        self
+    }
+
+    /// The orderings of the messages the context must keep.
+    pub fn msg_order(mut self, order: MsgOrder) -> Self {
+        self.msg_order = order;
+        self
+    }
+
+    /// The orderings of the completions the context must keep.
+    pub fn comp_order(mut self, order: MsgOrder) -> Self {
+        self.comp_order = order;
+        self
+    }
+
+    /// Check the settings against the maxima of a provider, ex: the
+    /// [`rx_attr()`](crate::InfoEntry::rx_attr) of an entry.
+    pub fn validate(&self, max: &RxAttr) -> Result<()> {
+        within("rx size", self.size, max.size)?;
+        within("rx iov limit", self.iov_limit, max.iov_limit)?;
+        within(
+            "total buffered recv",
+            self.total_buffered_recv,
+            max.total_buffered_recv,
+        )?;
+        ordered("rx msg order", self.msg_order, max.msg_order)?;
+        ordered("rx comp order", self.comp_order, max.comp_order)
+    }
+
+    pub(crate) fn apply(&self, attr: &mut ffi::fi_rx_attr) {
+        set(&mut attr.size, self.size);
+        set(&mut attr.iov_limit, self.iov_limit);
+        set(&mut attr.total_buffered_recv, self.total_buffered_recv);
+        set(&mut attr.op_flags, self.op_flags.bits());
+        set(&mut attr.msg_order, self.msg_order.bits());
+        set(&mut attr.comp_order, self.comp_order.bits());
+    }
+}
+
+/// The threading, progress and resource management of a domain, applied to hints with
+/// [`Info::domain_config()`], then checked against the domain opened with
+/// [`Domain::open_with_config()`]. The threading level is that of the model `M` the domain is
+/// opened under: `FI_THREAD_SAFE` for [`ThreadSafe`], the default, and `FI_THREAD_DOMAIN` for
+/// [`ThreadDomain`]. Settings left unspecified keep those of the provider.
+///
+/// ```no_run
+/// use libfabric::{Domain, DomainConfig, Fabric, Info, Progress, ResourceMgmt, ThreadDomain};
+///
+/// # fn run() -> libfabric::Result<()> {
+/// let config = DomainConfig::<ThreadDomain>::new()
+///     .data_progress(Progress::Manual)
+///     .resource_mgmt(ResourceMgmt::Enabled);
+/// let entries = Info::new().domain_config(&config).get()?;
+/// let fabric = Fabric::open(&entries[0])?;
+/// let domain = Domain::open_with_config(&fabric, &entries[0], &config)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`Info::domain_config()`]: crate::Info::domain_config
+/// [`Domain::open_with_config()`]: crate::Domain::open_with_config
+/// [`ThreadDomain`]: crate::ThreadDomain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[must_use]
+pub struct DomainConfig<M: ThreadingModel = ThreadSafe> {
+    control_progress: Progress,
+    data_progress: Progress,
+    resource_mgmt: ResourceMgmt,
+    validation: Option<ValidationLevel>,
+    enforce_limits: bool,
+    // Configs of any model are Send and Sync, unlike the objects of its domains.
+    model: PhantomData<fn() -> M>,
+}
+
+impl<M: ThreadingModel> Default for DomainConfig<M> {
+    fn default() -> Self {
+        DomainConfig {
+            control_progress: Progress::Unspec,
+            data_progress: Progress::Unspec,
+            resource_mgmt: ResourceMgmt::Unspec,
+            validation: None,
+            enforce_limits: false,
+            model: PhantomData,
+        }
+    }
+}
+
+impl<M: ThreadingModel> DomainConfig<M> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The threading level requested, that of the model.
+    pub fn threading(&self) -> Threading {
+        M::requested()
+    }
+
+    /// How connection management and address vector operations must progress.
+    pub fn control_progress(mut self, progress: Progress) -> Self {
+        self.control_progress = progress;
+        self
+    }
+
+    /// How data transfers must progress: [`Progress::Manual`] accepts providers progressing
+    /// on their own as well, while [`Progress::Auto`] requires them to.
+    pub fn data_progress(mut self, progress: Progress) -> Self {
+        self.data_progress = progress;
+        self
+    }
+
+    /// Whether the provider must protect queues and peers from being overrun:
+    /// [`ResourceMgmt::Disabled`] accepts providers which do as well.
+    pub fn resource_mgmt(mut self, resource_mgmt: ResourceMgmt) -> Self {
+        self.resource_mgmt = resource_mgmt;
+        self
+    }
+
+    /// How much the operations of the endpoints of the domain check their arguments, the
+    /// [default](ValidationLevel::default) of the build if unspecified. Not a setting of the
+    /// provider, so left out of the hints and of [`validate()`](Self::validate).
+    pub fn validation(mut self, level: ValidationLevel) -> Self {
+        self.validation = Some(level);
+        self
+    }
+
+    pub(crate) fn validation_level(&self) -> Option<ValidationLevel> {
+        self.validation
+    }
+
+    /// Fail to open objects past the counts of the attributes of the domain, see
+    /// [`Domain::set_limits_enforced()`](crate::Domain::set_limits_enforced). Not a setting of
+    /// the provider either.
+    pub fn enforce_limits(mut self, enforce: bool) -> Self {
+        self.enforce_limits = enforce;
+        self
+    }
+
+    pub(crate) fn limits_enforced(&self) -> bool {
+        self.enforce_limits
+    }
+
+    /// Check the attributes of a domain, ex: the
+    /// [`domain_attr()`](crate::InfoEntry::domain_attr) of an entry, against the settings.
+    pub fn validate(&self, attr: &DomainAttr) -> Result<()> {
+        if !M::allows(attr.threading) {
+            return Err(Error::invalid(format!(
+                "domain {} has threading {:?}, which {} does not allow",
+                attr.name,
+                attr.threading,
+                std::any::type_name::<M>()
+            )));
+        }
+        progressed("control", self.control_progress, attr.control_progress)?;
+        progressed("data", self.data_progress, attr.data_progress)?;
+        if self.resource_mgmt == ResourceMgmt::Enabled
+            && attr.resource_mgmt != ResourceMgmt::Enabled
+        {
+            return Err(Error::invalid(format!(
+                "domain {} has resource management {:?}, not enabled",
+                attr.name, attr.resource_mgmt
+            )));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn apply(&self, attr: &mut ffi::fi_domain_attr) {
+        unsafe { write_enum(&raw mut attr.threading, M::requested().as_raw()) };
+        if self.control_progress != Progress::Unspec {
+            unsafe {
+                write_enum(
+                    &raw mut attr.control_progress,
+                    self.control_progress.as_raw(),
+                )
+            };
+        }
+        if self.data_progress != Progress::Unspec {
+            set_data_progress(attr, self.data_progress.as_raw());
+        }
+        if self.resource_mgmt != ResourceMgmt::Unspec {
+            unsafe { write_enum(&raw mut attr.resource_mgmt, self.resource_mgmt.as_raw()) };
+        }
+    }
+}
+
+// Manual progress is met by any provider, since the application progresses it anyway.
+fn progressed(what: &str, wanted: Progress, progress: Progress) -> Result<()> {
+    if matches!(wanted, Progress::Unspec | Progress::Manual) || wanted == progress {
+        return Ok(());
+    }
+    Err(Error::invalid(format!(
+        "{what} progress {progress:?}, not {wanted:?}"
+    )))
+}
+
+fn within(what: &str, value: usize, max: usize) -> Result<()> {
+    if value > max {
+        return Err(Error::invalid(format!(
+            "{what} {value} exceeds the maximum of {max}"
+        )));
+    }
+    Ok(())
+}
+
+fn ordered(what: &str, order: MsgOrder, max: MsgOrder) -> Result<()> {
+    if !max.contains(order) {
+        return Err(Error::invalid(format!(
+            "{what} {:?} not supported",
+            order.difference(max)
+        )));
+    }
+    Ok(())
+}
+
+// Settings left at 0 keep the value of the provider.
+fn set<T: Default + PartialEq>(field: &mut T, value: T) {
+    if value != T::default() {
+        *field = value;
+    }
+}
@@ -0,0 +1,884 @@
+use crate::addr::AddressFormat;
+use crate::domain::Domain;
+use crate::error::{Error, Result, check};
+use crate::fid::{AsRawFid, OwnedFid};
+use crate::stats::StatsTracker;
+use crate::threading::{ThreadSafe, ThreadingModel};
+use crate::trace;
+use crate::util::{cstr, cstring};
+use ofi_libfabric_sys::bindgen as ffi;
+use ofi_libfabric_sys::sockaddr;
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+use std::sync::{Arc, Mutex};
+use std::{mem, slice};
+
+/// A peer address resolved by an address vector (`fi_addr_t`).
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Addr(ffi::fi_addr_t);
+
+impl Addr {
+    /// No address, used for connected endpoints and to receive from any peer.
+    pub const UNSPEC: Addr = Addr(ffi::FI_ADDR_UNSPEC);
+    /// Source address not available, as reported for unknown peers.
+    pub const NOTAVAIL: Addr = Addr(ffi::FI_ADDR_NOTAVAIL);
+
+    pub const fn from_raw(raw: ffi::fi_addr_t) -> Self {
+        Addr(raw)
+    }
+
+    pub const fn as_raw(self) -> ffi::fi_addr_t {
+        self.0
+    }
+}
+
+/// The raw, provider specific address of an endpoint, as returned by `fi_getname()`.
+///
+/// This is what applications exchange out of band, and insert into address vectors.
+#[derive(Clone, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EndpointAddress(Vec<u8>);
+
+impl EndpointAddress {
+    pub fn from_bytes(bytes: impl Into<Vec<u8>>) -> Self {
+        EndpointAddress(bytes.into())
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+// Fetch an address through `fi_getname()`, `fi_getpeer()` or `fi_av_lookup()`, growing the
+// buffer when the provider reports the larger size it needs, along with FI_ETOOSMALL or, for
+// lookups, a truncated address.
+pub(crate) fn read_addr(
+    op: &'static str,
+    get: impl Fn(*mut c_void, *mut usize) -> c_int,
+) -> Result<EndpointAddress> {
+    let mut buf = vec![0u8; 64];
+    loop {
+        let mut len = buf.len();
+        let ret = get(buf.as_mut_ptr().cast(), &mut len);
+        if (ret == 0 || ret == -(ffi::FI_ETOOSMALL as c_int)) && len > buf.len() {
+            buf.resize(len, 0);
+            continue;
+        }
+        check(op, ret)?;
+        buf.truncate(len);
+        return Ok(EndpointAddress::from_bytes(buf));
+    }
+}
+
+impl fmt::Debug for EndpointAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EndpointAddress(")?;
+        for b in &self.0 {
+            write!(f, "{b:02x}")?;
+        }
+        write!(f, ")")
+    }
+}
+
+// View the raw bytes of an address as a socket address struct, if long enough.
+fn read_sockaddr<T: Copy>(bytes: &[u8]) -> Option<T> {
+    (bytes.len() >= mem::size_of::<T>())
+        .then(|| unsafe { ptr::read_unaligned(bytes.as_ptr().cast::<T>()) })
+}
+
+fn sockaddr_bytes<T: Copy>(addr: &T) -> Vec<u8> {
+    unsafe { slice::from_raw_parts((addr as *const T).cast::<u8>(), mem::size_of::<T>()) }.to_vec()
+}
+
+impl EndpointAddress {
+    /// Decode a `sockaddr_in` or `sockaddr_in6` address, as returned by IP based providers
+    /// (ex: tcp, udp, netdir). Returns `None` for any other address format.
+    pub fn to_socket_addr(&self) -> Option<SocketAddr> {
+        // Both start with the family, at the same offset.
+        let sin = read_sockaddr::<sockaddr::sockaddr_in>(&self.0);
+        match sin.map(|sin| sin.sin_family) {
+            Some(sockaddr::AF_INET) => {
+                let sin = sin?;
+                let ip = Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr));
+                Some(SocketAddrV4::new(ip, u16::from_be(sin.sin_port)).into())
+            }
+            Some(sockaddr::AF_INET6) => {
+                let sin6 = read_sockaddr::<sockaddr::sockaddr_in6>(&self.0)?;
+                Some(
+                    SocketAddrV6::new(
+                        Ipv6Addr::from(sin6.sin6_addr.s6_addr),
+                        u16::from_be(sin6.sin6_port),
+                        u32::from_be(sin6.sin6_flowinfo),
+                        sin6.sin6_scope_id,
+                    )
+                    .into(),
+                )
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Encode as a `sockaddr_in` or `sockaddr_in6`, for use as a node address or with
+/// [`AddressVector::insert()`] on IP based providers.
+impl From<SocketAddr> for EndpointAddress {
+    fn from(addr: SocketAddr) -> Self {
+        EndpointAddress(match addr {
+            SocketAddr::V4(addr) => sockaddr_bytes(&sockaddr::sockaddr_in {
+                #[cfg(target_os = "macos")]
+                sin_len: mem::size_of::<sockaddr::sockaddr_in>() as u8,
+                sin_family: sockaddr::AF_INET,
+                sin_port: addr.port().to_be(),
+                sin_addr: sockaddr::in_addr {
+                    s_addr: u32::from(*addr.ip()).to_be(),
+                },
+                ..Default::default()
+            }),
+            SocketAddr::V6(addr) => sockaddr_bytes(&sockaddr::sockaddr_in6 {
+                #[cfg(target_os = "macos")]
+                sin6_len: mem::size_of::<sockaddr::sockaddr_in6>() as u8,
+                sin6_family: sockaddr::AF_INET6,
+                sin6_port: addr.port().to_be(),
+                sin6_flowinfo: addr.flowinfo().to_be(),
+                sin6_addr: sockaddr::in6_addr {
+                    s6_addr: addr.ip().octets(),
+                },
+                sin6_scope_id: addr.scope_id(),
+            }),
+        })
+    }
+}
+
+/// Address vector types (`enum fi_av_type`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AvType {
+    /// Let the provider pick.
+    #[default]
+    Unspec,
+    /// Addresses are provider defined values, e.g. pointers.
+    Map,
+    /// Addresses are consecutive indices, in insertion order.
+    Table,
+}
+
+impl AvType {
+    pub(crate) fn from_raw(raw: u32) -> Self {
+        match ffi::fi_av_type::try_from(raw) {
+            Ok(ffi::fi_av_type::FI_AV_MAP) => AvType::Map,
+            Ok(ffi::fi_av_type::FI_AV_TABLE) => AvType::Table,
+            _ => AvType::Unspec,
+        }
+    }
+
+    pub(crate) fn as_raw(self) -> ffi::fi_av_type {
+        match self {
+            AvType::Unspec => ffi::fi_av_type::FI_AV_UNSPEC,
+            AvType::Map => ffi::fi_av_type::FI_AV_MAP,
+            AvType::Table => ffi::fi_av_type::FI_AV_TABLE,
+        }
+    }
+}
+
+/// The format of the endpoint addresses of a provider (`FI_SOCKADDR_IN`, `FI_ADDR_EFA`, ...),
+/// from [`InfoEntry::addr_format()`](crate::InfoEntry::addr_format).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AddrFormat {
+    /// Unknown, or any format in hints.
+    #[default]
+    Unspec,
+    /// A `struct sockaddr` of any family.
+    SockAddr,
+    /// A `struct sockaddr_in`.
+    SockAddrIn,
+    /// A `struct sockaddr_in6`.
+    SockAddrIn6,
+    /// A `struct sockaddr_ib`.
+    SockAddrIb,
+    /// A `struct sockaddr_in` or `sockaddr_in6`.
+    SockAddrIp,
+    /// A nul terminated string, as formatted by [`AddressVector::straddr()`].
+    Str,
+    /// The GID, QPN, LID and partition of an InfiniBand datagram queue pair.
+    IbUd,
+    /// The words of a psm endpoint.
+    Psmx,
+    /// The words of a psm2 endpoint.
+    Psmx2,
+    /// The words of a psm3 endpoint.
+    Psmx3,
+    /// The GID, QPN and QKey of an efa endpoint.
+    Efa,
+    /// The NIC and PID of a cxi endpoint.
+    Cxi,
+    /// A raw value these bindings do not know about, ex: from a newer libfabric or another
+    /// vendor format.
+    Other(u32),
+}
+
+impl AddrFormat {
+    pub(crate) fn from_raw(raw: u32) -> Self {
+        match raw {
+            ffi::FI_FORMAT_UNSPEC => AddrFormat::Unspec,
+            ffi::FI_SOCKADDR => AddrFormat::SockAddr,
+            ffi::FI_SOCKADDR_IN => AddrFormat::SockAddrIn,
+            ffi::FI_SOCKADDR_IN6 => AddrFormat::SockAddrIn6,
+            ffi::FI_SOCKADDR_IB => AddrFormat::SockAddrIb,
+            ffi::FI_SOCKADDR_IP => AddrFormat::SockAddrIp,
+            ffi::FI_ADDR_STR => AddrFormat::Str,
+            ffi::FI_ADDR_IB_UD => AddrFormat::IbUd,
+            ffi::FI_ADDR_PSMX => AddrFormat::Psmx,
+            ffi::FI_ADDR_PSMX2 => AddrFormat::Psmx2,
+            ffi::FI_ADDR_PSMX3 => AddrFormat::Psmx3,
+            ffi::FI_ADDR_EFA => AddrFormat::Efa,
+            ffi::FI_ADDR_CXI => AddrFormat::Cxi,
+            _ => AddrFormat::Other(raw),
+        }
+    }
+}
+
+/// Attributes for opening an address vector.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[must_use]
+pub struct AvAttr {
+    av_type: AvType,
+    count: usize,
+    user_id: bool,
+    ep_per_node: usize,
+    name: Option<String>,
+    map_addr: usize,
+    read_only: bool,
+}
+
+impl AvAttr {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn av_type(mut self, av_type: AvType) -> Self {
+        self.av_type = av_type;
+        self
+    }
+
+    /// Expected number of addresses, 0 lets the provider pick.
+    pub fn count(mut self, count: usize) -> Self {
+        self.count = count;
+        self
+    }
+
+    /// Report the IDs set with [`AddressVector::set_user_id()`] as the source of completions,
+    /// rather than the addresses of the peers (`FI_AV_USER_ID`). This requires the domain to
+    /// have the [`Caps::AV_USER_ID`](crate::Caps::AV_USER_ID) capability.
+    pub fn user_id(mut self, enable: bool) -> Self {
+        self.user_id = enable;
+        self
+    }
+
+    /// Expected number of endpoints per node, 0 when unknown, which lets providers size
+    /// vectors shared by the processes of a node.
+    pub fn ep_per_node(mut self, count: usize) -> Self {
+        self.ep_per_node = count;
+        self
+    }
+
+    /// Share the vector across the processes of the node as `name`, a system wide name, ex:
+    /// `/job-1234-av`, rather than each holding a copy of the same addresses. See
+    /// [`Domain::shared_av()`](crate::Domain::shared_av) to create or attach to it.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// The base address to map a shared `FI_AV_MAP` vector at, so that its handles are the
+    /// same in every process sharing it, 0 to let the provider pick.
+    pub fn map_addr(mut self, addr: usize) -> Self {
+        self.map_addr = addr;
+        self
+    }
+
+    /// Attach to a shared vector created by another process, read-only (`FI_READ`): its
+    /// addresses are those the owner inserts, and inserting into it fails.
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+}
+
+/// An address vector (`fid_av`), mapping endpoint addresses to [`Addr`] handles.
+///
+/// Whichever the [`AvType`], the addresses inserted are also kept in insertion order, so that
+/// [`get()`](Self::get) finds peers by index with either type: with `FI_AV_TABLE`, the handles
+/// are these indices, while `FI_AV_MAP` providers return values of their own.
+#[derive(Clone)]
+pub struct AddressVector<M: ThreadingModel = ThreadSafe> {
+    inner: Arc<AvInner<M>>,
+}
+
+struct AvInner<M: ThreadingModel> {
+    fid: OwnedFid<ffi::fid_av>,
+    domain: Domain<M>,
+    av_type: AvType,
+    read_only: bool,
+    // The handles of the addresses inserted, by index.
+    addrs: Mutex<Vec<Addr>>,
+}
+
+impl<M: ThreadingModel> AddressVector<M> {
+    // The handles to the address vector, this one included.
+    pub(crate) fn handles(&self) -> usize {
+        Arc::strong_count(&self.inner)
+    }
+
+    // Close the address vector, via `fi_close()`, failing unless this is the last handle to it.
+    pub(crate) fn close(self) -> Result<()> {
+        crate::fid::close_last(self.inner, |inner| &mut inner.fid)
+    }
+
+    pub(crate) fn open(domain: &Domain<M>, attr: &AvAttr) -> Result<Self> {
+        let name = attr.name.as_deref().map(cstring).transpose()?;
+        let mut raw = ffi::fi_av_attr {
+            type_: attr.av_type.as_raw(),
+            count: attr.count,
+            ep_per_node: attr.ep_per_node,
+            name: name.as_ref().map_or(ptr::null(), |name| name.as_ptr()),
+            map_addr: attr.map_addr as *mut c_void,
+            flags: if attr.user_id { ffi::FI_AV_USER_ID } else { 0 }
+                | if attr.read_only {
+                    ffi::FI_READ as u64
+                } else {
+                    0
+                },
+            ..Default::default()
+        };
+        let fid = OwnedFid::open("fi_av_open", |av| unsafe {
+            ffi::fi_av_open(domain.as_raw(), &mut raw, av, ptr::null_mut())
+        })?;
+        // Providers open FI_AV_UNSPEC vectors with the type they report for their domain.
+        let av_type = match attr.av_type {
+            AvType::Unspec => domain.info().domain_attr().av_type,
+            av_type => av_type,
+        };
+        Ok(AddressVector {
+            inner: Arc::new(AvInner {
+                fid,
+                domain: domain.clone(),
+                av_type,
+                read_only: attr.read_only,
+                addrs: Mutex::new(Vec::new()),
+            }),
+        })
+    }
+
+    pub(crate) fn open_shared(domain: &Domain<M>, attr: &AvAttr) -> Result<Self> {
+        if attr.name.is_none() {
+            return Err(Error::invalid("shared address vectors require a name"));
+        }
+        match Self::open(domain, &attr.clone().read_only(true)) {
+            // Nobody created it yet.
+            Err(err) if err.code() == ffi::FI_ENOENT as i32 => {
+                Self::open(domain, &attr.clone().read_only(false))
+            }
+            av => av,
+        }
+    }
+
+    /// Take ownership of an address vector of `domain` opened elsewhere, ex: by C code, with
+    /// the type and access of `attr`. The addresses inserted before are not known to
+    /// [`get()`](Self::get), which finds those inserted through the returned value only. Fails
+    /// with an invalid argument error if `av` is null.
+    ///
+    /// # Safety
+    ///
+    /// `av` must be an open address vector of `domain`, which is closed by the returned value
+    /// only: once all handles to it are dropped, or released by [`into_raw()`](Self::into_raw).
+    pub unsafe fn from_raw(
+        domain: &Domain<M>,
+        av: *mut ffi::fid_av,
+        attr: &AvAttr,
+    ) -> Result<Self> {
+        let av_type = match attr.av_type {
+            AvType::Unspec => domain.info().domain_attr().av_type,
+            av_type => av_type,
+        };
+        Ok(AddressVector {
+            inner: Arc::new(AvInner {
+                fid: unsafe { OwnedFid::from_raw(av) }?,
+                domain: domain.clone(),
+                av_type,
+                read_only: attr.read_only,
+                addrs: Mutex::new(Vec::new()),
+            }),
+        })
+    }
+
+    /// Hand the address vector over, ex: to C code, whose `fi_close()` then closes it. Fails
+    /// unless this is the last handle to it, which the endpoints bound to it hold too. The
+    /// domain is released: it must be kept open until the address vector is closed.
+    pub fn into_raw(self) -> Result<*mut ffi::fid_av> {
+        crate::fid::release_last(self.inner, |inner| &mut inner.fid)
+    }
+
+    pub fn domain(&self) -> &Domain<M> {
+        &self.inner.domain
+    }
+
+    /// The type of the vector, as requested or else as the provider reports for the domain,
+    /// [`AvType::Unspec`] when it does not say.
+    pub fn av_type(&self) -> AvType {
+        self.inner.av_type
+    }
+
+    /// The handle of the address inserted at `index`, counting every address inserted, in
+    /// order, including those which failed to insert with [`insert_all()`](Self::insert_all).
+    pub fn get(&self, index: usize) -> Option<Addr> {
+        self.inner.addrs.lock().unwrap().get(index).copied()
+    }
+
+    /// The number of addresses inserted.
+    pub fn len(&self) -> usize {
+        self.inner.addrs.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the vector was attached to read-only, in which case it is not the owner of its
+    /// addresses.
+    pub fn is_read_only(&self) -> bool {
+        self.inner.read_only
+    }
+
+    // Fail inserting into a vector attached read-only, before the provider does.
+    fn check_writable(&self) -> Result<()> {
+        match self.inner.read_only {
+            true => Err(Error::invalid("the address vector is attached read-only")),
+            false => Ok(()),
+        }
+    }
+
+    /// Insert one peer address, via `fi_av_insert()`.
+    pub fn insert(&self, addr: &EndpointAddress) -> Result<Addr> {
+        self.check_writable()?;
+        let _span = trace::span!(
+            "fi_av_insert",
+            provider = self.domain().info().provider_name(),
+            peer = ?addr.to_socket_addr()
+        );
+        let mut fi_addr = Addr::NOTAVAIL;
+        let ret = unsafe {
+            ffi::fi_av_insert(
+                self.as_raw(),
+                addr.as_bytes().as_ptr().cast(),
+                1,
+                &mut fi_addr.0,
+                0,
+                ptr::null_mut(),
+            )
+        };
+        check("fi_av_insert", ret)?;
+        if ret != 1 {
+            return Err(Error::fabric("fi_av_insert", ffi::FI_EADDRNOTAVAIL as i64));
+        }
+        self.inner.addrs.lock().unwrap().push(fi_addr);
+        Ok(fi_addr)
+    }
+
+    /// Insert a typed peer address, encoded by its [`AddressFormat`], which must be that of
+    /// the domain.
+    pub fn insert_addr<F: AddressFormat>(&self, addr: &F) -> Result<Addr> {
+        self.insert(&EndpointAddress::encode(addr))
+    }
+
+    /// Insert several peer addresses at once, via `fi_av_insert()` with `FI_SYNC_ERR`, which
+    /// reports whether each of them was inserted, rather than failing them all with the first.
+    ///
+    /// Returns the handle or error of each address, in order. The call fails as a whole for
+    /// addresses of different lengths, which the provider could not tell apart.
+    pub fn insert_all(&self, addrs: &[EndpointAddress]) -> Result<Vec<Result<Addr>>> {
+        self.check_writable()?;
+        let Some(len) = addrs.first().map(|addr| addr.as_bytes().len()) else {
+            return Ok(Vec::new());
+        };
+        if addrs.iter().any(|addr| addr.as_bytes().len() != len) {
+            return Err(Error::invalid("addresses of different lengths"));
+        }
+        let _span = trace::span!(
+            "fi_av_insert",
+            provider = self.domain().info().provider_name(),
+            count = addrs.len()
+        );
+        let buf: Vec<u8> = addrs
+            .iter()
+            .flat_map(|addr| addr.as_bytes())
+            .copied()
+            .collect();
+        let mut fi_addrs = vec![Addr::NOTAVAIL; addrs.len()];
+        let mut errors = vec![0 as c_int; addrs.len()];
+        let ret = unsafe {
+            ffi::fi_av_insert(
+                self.as_raw(),
+                buf.as_ptr().cast(),
+                addrs.len(),
+                fi_addrs.as_mut_ptr().cast(),
+                ffi::FI_SYNC_ERR,
+                errors.as_mut_ptr().cast(),
+            )
+        };
+        check("fi_av_insert", ret)?;
+        Ok(self.inserted("fi_av_insert", fi_addrs, errors))
+    }
+
+    /// Like [`insert_all()`](Self::insert_all), inserting either every address or none: once
+    /// any of them fails to insert, those inserted are removed again, and the call fails with
+    /// the error of the first which failed, even if some fail to be removed. Jobs wire up this
+    /// way to the same vector on every member, or to none, rather than to whichever peers
+    /// happened to insert.
+    ///
+    /// The slots of the addresses in [`get()`](Self::get) are all [`Addr::NOTAVAIL`] once
+    /// rolled back.
+    pub fn insert_all_or_nothing(&self, addrs: &[EndpointAddress]) -> Result<Vec<Addr>> {
+        let outcomes = self.insert_all(addrs)?;
+        let Some(index) = outcomes.iter().position(|outcome| outcome.is_err()) else {
+            return Ok(outcomes
+                .into_iter()
+                .map(|outcome| outcome.unwrap())
+                .collect());
+        };
+        // Whichever fail to be removed, the others still are, and the insert error is the
+        // one which tells why.
+        for addr in outcomes.iter().filter_map(|outcome| outcome.as_ref().ok()) {
+            let _ = self.remove(*addr);
+        }
+        Err(outcomes.into_iter().nth(index).unwrap().unwrap_err())
+    }
+
+    // Record the handles of addresses inserted with FI_SYNC_ERR, failed ones included, and
+    // return their outcomes.
+    fn inserted(
+        &self,
+        op: &'static str,
+        fi_addrs: Vec<Addr>,
+        errors: Vec<c_int>,
+    ) -> Vec<Result<Addr>> {
+        self.inner.addrs.lock().unwrap().extend(&fi_addrs);
+        fi_addrs
+            .into_iter()
+            .zip(errors)
+            .map(|(addr, err)| match err {
+                0 if addr != Addr::NOTAVAIL => Ok(addr),
+                0 => Err(Error::fabric(op, ffi::FI_EADDRNOTAVAIL as i64)),
+                err => Err(Error::fabric(op, err as i64)),
+            })
+            .collect()
+    }
+
+    /// Insert the peer at `node`, a host name or numeric address, and `service`, ex: a port,
+    /// resolved by the provider, via `fi_av_insertsvc()`.
+    pub fn insert_service(&self, node: &str, service: &str) -> Result<Addr> {
+        self.check_writable()?;
+        let _span = trace::span!(
+            "fi_av_insertsvc",
+            provider = self.domain().info().provider_name(),
+            node,
+            service
+        );
+        let (node, service) = (cstring(node)?, cstring(service)?);
+        let mut fi_addr = Addr::NOTAVAIL;
+        let ret = unsafe {
+            ffi::fi_av_insertsvc(
+                self.as_raw(),
+                node.as_ptr(),
+                service.as_ptr(),
+                &mut fi_addr.0,
+                0,
+                ptr::null_mut(),
+            )
+        };
+        check("fi_av_insertsvc", ret)?;
+        if ret != 1 {
+            return Err(Error::fabric(
+                "fi_av_insertsvc",
+                ffi::FI_EADDRNOTAVAIL as i64,
+            ));
+        }
+        self.inner.addrs.lock().unwrap().push(fi_addr);
+        Ok(fi_addr)
+    }
+
+    /// Insert the peers of a job laid out symmetrically, `services` of them on each of `nodes`
+    /// hosts, via `fi_av_insertsym()` with `FI_SYNC_ERR`: the hosts follow `node` by
+    /// incrementing its trailing number, ex: `node07`, `node08`..., or its last byte for
+    /// numeric addresses, and the services follow `service` as numbers.
+    ///
+    /// Returns the handle or error of each peer, node by node, so that the peer of rank
+    /// `node * services + service` is at that index, here and with [`get()`](Self::get) when
+    /// the vector was empty.
+    pub fn insert_symmetric(
+        &self,
+        node: &str,
+        nodes: usize,
+        service: &str,
+        services: usize,
+    ) -> Result<Vec<Result<Addr>>> {
+        self.check_writable()?;
+        let _span = trace::span!(
+            "fi_av_insertsym",
+            provider = self.domain().info().provider_name(),
+            node,
+            nodes,
+            service,
+            services
+        );
+        let count = nodes
+            .checked_mul(services)
+            .ok_or_else(|| Error::invalid("too many peers"))?;
+        let (node, service) = (cstring(node)?, cstring(service)?);
+        let mut fi_addrs = vec![Addr::NOTAVAIL; count];
+        let mut errors = vec![0 as c_int; count];
+        let ret = unsafe {
+            ffi::fi_av_insertsym(
+                self.as_raw(),
+                node.as_ptr(),
+                nodes,
+                service.as_ptr(),
+                services,
+                fi_addrs.as_mut_ptr().cast(),
+                ffi::FI_SYNC_ERR,
+                errors.as_mut_ptr().cast(),
+            )
+        };
+        check("fi_av_insertsym", ret)?;
+        Ok(self.inserted("fi_av_insertsym", fi_addrs, errors))
+    }
+
+    /// Insert one peer address, whose completions report `id` as their source rather than the
+    /// returned address, via `fi_av_insert()` with `FI_AV_USER_ID`. IDs are the application's,
+    /// ex: the rank of the peer.
+    ///
+    /// Address vectors opened with [`AvAttr::user_id()`] take IDs from
+    /// [`set_user_id()`](Self::set_user_id) instead, and fail this call.
+    pub fn insert_with_id(&self, addr: &EndpointAddress, id: u64) -> Result<Addr> {
+        self.check_writable()?;
+        let _span = trace::span!(
+            "fi_av_insert",
+            provider = self.domain().info().provider_name(),
+            peer = ?addr.to_socket_addr(),
+            id
+        );
+        // The ID goes in, and the address comes out.
+        let mut fi_addr = id;
+        let ret = unsafe {
+            ffi::fi_av_insert(
+                self.as_raw(),
+                addr.as_bytes().as_ptr().cast(),
+                1,
+                &mut fi_addr,
+                ffi::FI_AV_USER_ID,
+                ptr::null_mut(),
+            )
+        };
+        check("fi_av_insert", ret)?;
+        if ret != 1 {
+            return Err(Error::fabric("fi_av_insert", ffi::FI_EADDRNOTAVAIL as i64));
+        }
+        self.inner.addrs.lock().unwrap().push(Addr(fi_addr));
+        Ok(Addr(fi_addr))
+    }
+
+    /// Remove the peer at `addr`, via `fi_av_remove()`, ex: once it is known to be gone.
+    /// Operations still in flight to or from it may fail, and its slot in
+    /// [`get()`](Self::get) becomes [`Addr::NOTAVAIL`].
+    pub fn remove(&self, addr: Addr) -> Result<()> {
+        self.remove_many(&[addr])
+    }
+
+    /// Remove the peers at `addrs` in one call to `fi_av_remove()`, as
+    /// [`remove()`](Self::remove) does.
+    pub fn remove_many(&self, addrs: &[Addr]) -> Result<()> {
+        self.check_writable()?;
+        let mut fi_addrs: Vec<_> = addrs.iter().map(|addr| addr.0).collect();
+        check("fi_av_remove", unsafe {
+            ffi::fi_av_remove(self.as_raw(), fi_addrs.as_mut_ptr(), fi_addrs.len(), 0)
+        })?;
+        for slot in self.inner.addrs.lock().unwrap().iter_mut() {
+            if addrs.contains(slot) {
+                *slot = Addr::NOTAVAIL;
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove the peers at `addrs` as [`remove_many()`](Self::remove_many) does, unless the
+    /// endpoints of `tracker` have operations in flight to or from any of them, whose
+    /// completion is not read yet: removal then fails with `FI_EBUSY`, and is to be tried
+    /// again once they completed, rather than leaving them to target a removed address.
+    ///
+    /// The endpoints of `tracker` cannot post operations meanwhile, so none targets the peers
+    /// once they are removed, but those of the address vector posted on other endpoints are
+    /// not seen.
+    ///
+    /// ```no_run
+    /// # use libfabric::{Addr, AddressVector, StatsTracker};
+    /// # fn run(av: &AddressVector, tracker: &StatsTracker, gone: &[Addr]) -> libfabric::Result<()> {
+    /// use libfabric::sys::bindgen::FI_EBUSY;
+    ///
+    /// while let Err(err) = av.remove_idle(gone, tracker) {
+    ///     if err.code() != FI_EBUSY as i32 {
+    ///         return Err(err);
+    ///     }
+    ///     // Read the completions of the operations in flight to the peers, then retry.
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn remove_idle(&self, addrs: &[Addr], tracker: &StatsTracker) -> Result<()> {
+        tracker.while_idle(addrs, || self.remove_many(addrs))
+    }
+
+    /// Report `id` as the source of the completions of the peer at `addr`, via
+    /// `fi_av_set_user_id()`, on address vectors opened with [`AvAttr::user_id()`]. Until then,
+    /// their source is [`Addr::NOTAVAIL`].
+    pub fn set_user_id(&self, addr: Addr, id: u64) -> Result<()> {
+        self.check_writable()?;
+        check("fi_av_set_user_id", unsafe {
+            ffi::fi_av_set_user_id(self.as_raw(), addr.0, id, 0)
+        })
+    }
+
+    /// The endpoint address behind `addr`, via `fi_av_lookup()`: which peer a handle, ex: the
+    /// source of a completion, stands for.
+    pub fn lookup(&self, addr: Addr) -> Result<EndpointAddress> {
+        read_addr("fi_av_lookup", |buf, len| unsafe {
+            ffi::fi_av_lookup(self.as_raw(), addr.0, buf, len)
+        })
+    }
+
+    /// The IP address and port behind `addr`, from [`lookup()`](Self::lookup), or `None` with
+    /// addresses which are not socket addresses.
+    pub fn lookup_socket_addr(&self, addr: Addr) -> Result<Option<SocketAddr>> {
+        Ok(self.lookup(addr)?.to_socket_addr())
+    }
+
+    /// The address behind `addr` decoded as an `F`, in the format of the domain, or `None`
+    /// with addresses of other formats.
+    pub fn lookup_as<F: AddressFormat>(&self, addr: Addr) -> Result<Option<F>> {
+        let format = self.domain().info().addr_format();
+        Ok(self.lookup(addr)?.decode(format))
+    }
+
+    /// Format `addr` as the provider displays its addresses, via `fi_av_straddr()`, ex:
+    /// `fi_sockaddr_in://10.0.0.1:4000`, for those which are not socket addresses too.
+    pub fn straddr(&self, addr: &EndpointAddress) -> String {
+        let mut buf = vec![0 as c_char; 128];
+        loop {
+            let mut len = buf.len();
+            let ret = unsafe {
+                ffi::fi_av_straddr(
+                    self.as_raw(),
+                    addr.as_bytes().as_ptr().cast(),
+                    buf.as_mut_ptr(),
+                    &mut len,
+                )
+            };
+            // The length reported includes the terminating NUL.
+            if len > buf.len() {
+                buf.resize(len, 0);
+                continue;
+            }
+            return unsafe { cstr(ret) }.to_owned();
+        }
+    }
+
+    pub fn as_raw(&self) -> *mut ffi::fid_av {
+        self.inner.fid.as_ptr()
+    }
+}
+
+impl<M: ThreadingModel> AsRawFid for AddressVector<M> {
+    fn as_raw_fid(&self) -> *mut ffi::fid {
+        self.inner.fid.as_fid()
+    }
+}
+
+/// Name resolution off the calling thread, enabled by the `async` feature.
+#[cfg(feature = "async")]
+impl<M: ThreadingModel> AddressVector<M> {
+    /// Resolve `host` on a thread of its own, so as not to block the executor on DNS, then
+    /// insert the first of its socket addresses with `port`, on IP based providers (ex: tcp,
+    /// verbs;ofi_rxm). See [`insert_service()`](Self::insert_service) to let the provider
+    /// resolve names, blocking.
+    pub async fn insert_host_async(&self, host: &str, port: u16) -> Result<Addr> {
+        let addrs = Resolve::spawn(host.to_owned(), port).await?;
+        let addr = addrs.first().ok_or_else(|| Error::Io {
+            kind: std::io::ErrorKind::NotFound,
+            message: format!("no address for {host}"),
+        })?;
+        self.insert(&EndpointAddress::from(*addr))
+    }
+}
+
+// The resolution of a host name by a thread, which wakes up the task awaiting it once done.
+#[cfg(feature = "async")]
+struct Resolve {
+    state: Arc<Mutex<ResolveState>>,
+}
+
+#[cfg(feature = "async")]
+#[derive(Default)]
+struct ResolveState {
+    addrs: Option<std::io::Result<Vec<SocketAddr>>>,
+    waker: Option<std::task::Waker>,
+}
+
+#[cfg(feature = "async")]
+impl Resolve {
+    fn spawn(host: String, port: u16) -> Self {
+        use std::net::ToSocketAddrs;
+
+        let state = Arc::new(Mutex::new(ResolveState::default()));
+        std::thread::spawn({
+            let state = state.clone();
+            move || {
+                let addrs = (host.as_str(), port)
+                    .to_socket_addrs()
+                    .map(Iterator::collect);
+                let mut state = state.lock().unwrap();
+                state.addrs = Some(addrs);
+                if let Some(waker) = state.waker.take() {
+                    waker.wake();
+                }
+            }
+        });
+        Resolve { state }
+    }
+}
+
+#[cfg(feature = "async")]
+impl std::future::Future for Resolve {
+    type Output = Result<Vec<SocketAddr>>;
+
+    fn poll(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Self::Output> {
+        let mut state = self.state.lock().unwrap();
+        match state.addrs.take() {
+            Some(addrs) => std::task::Poll::Ready(addrs.map_err(Error::from)),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                std::task::Poll::Pending
+            }
+        }
+    }
+}
@@ -0,0 +1,326 @@
+//! Latency, message rate and bandwidth measurements between two endpoints of the same process,
+//! enabled by the `bench` feature.
+//!
+//! Each measurement runs either through the safe wrappers ([`Api::Safe`]) or through direct
+//! calls into the bindings ([`Api::Raw`]), as a C application would make them, such that the
+//! difference between the two is the overhead of the wrappers. They back both the `fi-bench`
+//! binary and the criterion benchmarks of this crate.
+
+use crate::av::{Addr, AddressVector, AvAttr};
+use crate::cq::{Completion, CompletionQueue, CqAttr};
+use crate::domain::Domain;
+use crate::ep::Endpoint;
+use crate::error::{Error, Result, check_len};
+use crate::fabric::Fabric;
+use crate::flags::{Access, BindFlags, Caps, MrMode};
+use crate::info::{EndpointType, Info};
+use crate::mr::MemoryRegion;
+use ofi_libfabric_sys::bindgen as ffi;
+use std::time::{Duration, Instant};
+
+/// Which layer the transfers go through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Api {
+    /// The safe wrappers, ex: [`Endpoint::send()`] and [`CompletionQueue::read()`].
+    Safe,
+    /// `fi_send()`, `fi_recv()` and `fi_cq_read()` from the bindings.
+    Raw,
+}
+
+const TX_CONTEXT: usize = 1;
+const RX_CONTEXT: usize = 2;
+
+struct Side {
+    ep: Endpoint,
+    cq: CompletionQueue,
+    // The address of the other side.
+    peer: Addr,
+    tx: usize,
+    rx: usize,
+    // Completions read, not yet waited for.
+    done: usize,
+}
+
+/// Two RDM endpoints of one domain, sending to each other, along with their registered buffers.
+pub struct Loopback {
+    sides: [Side; 2],
+    // Closed before the buffer below is freed.
+    mr: MemoryRegion,
+    buf: Vec<u8>,
+    max_size: usize,
+    // Kept alive along with the endpoints bound to it.
+    #[allow(dead_code)]
+    av: AddressVector,
+}
+
+impl Loopback {
+    /// Open both endpoints on `provider`, with room for messages of up to `max_size` bytes.
+    pub fn open(provider: &str, max_size: usize) -> Result<Self> {
+        let entries = Info::new()
+            .provider(provider)
+            .ep_type(EndpointType::Rdm)
+            .caps(Caps::MSG)
+            .mr_mode(MrMode::LOCAL | MrMode::VIRT_ADDR | MrMode::ALLOCATED | MrMode::PROV_KEY)
+            .get()?;
+        let entry = &entries[0];
+        let fabric = Fabric::open(entry)?;
+        let domain = Domain::open(&fabric, entry)?;
+        let av = domain.av(&AvAttr::new())?;
+
+        let open = |tx: usize, rx: usize| -> Result<Side> {
+            let cq = domain.cq(&CqAttr::new())?;
+            let ep = domain
+                .endpoint(entry)?
+                .bind_cq(&cq, BindFlags::TRANSMIT | BindFlags::RECV)?
+                .bind_av(&av)?
+                .enable()?;
+            Ok(Side {
+                ep,
+                cq,
+                peer: Addr::UNSPEC,
+                tx,
+                rx,
+                done: 0,
+            })
+        };
+        // The buffer holds, in order, the send and receive areas of the first side, then of the
+        // second.
+        let mut sides = [open(0, max_size)?, open(2 * max_size, 3 * max_size)?];
+        let names = [sides[0].ep.name()?, sides[1].ep.name()?];
+        sides[0].peer = av.insert(&names[1])?;
+        sides[1].peer = av.insert(&names[0])?;
+
+        let mut buf = vec![0u8; 4 * max_size];
+        let mr =
+            unsafe { domain.register(buf.as_mut_ptr(), buf.len(), Access::SEND | Access::RECV)? };
+        Ok(Loopback {
+            sides,
+            mr,
+            buf,
+            max_size,
+            av,
+        })
+    }
+
+    fn check_size(&self, size: usize) -> Result<()> {
+        match size <= self.max_size {
+            true => Ok(()),
+            false => Err(Error::invalid(format!(
+                "{size} bytes messages do not fit in {} bytes buffers",
+                self.max_size
+            ))),
+        }
+    }
+
+    /// Time one round trip of a `size` bytes message from the first side to the second, and
+    /// back.
+    pub fn round_trip(&mut self, size: usize, api: Api) -> Result<Duration> {
+        self.check_size(size)?;
+        self.post_recv(0, size, api)?;
+        self.post_recv(1, size, api)?;
+        let start = Instant::now();
+        self.send(0, size, api)?;
+        wait(&mut self.sides, 1, 1, api)?;
+        self.send(1, size, api)?;
+        // Each side waits for its send and its receive.
+        wait(&mut self.sides, 0, 2, api)?;
+        let elapsed = start.elapsed();
+        wait(&mut self.sides, 1, 1, api)?;
+        Ok(elapsed)
+    }
+
+    /// Time the transfer of `window` messages of `size` bytes from the first side to the
+    /// second, sent back to back.
+    pub fn stream(&mut self, size: usize, window: usize, api: Api) -> Result<Duration> {
+        self.check_size(size)?;
+        for _ in 0..window {
+            self.post_recv(1, size, api)?;
+        }
+        let start = Instant::now();
+        for _ in 0..window {
+            self.send(0, size, api)?;
+        }
+        wait(&mut self.sides, 0, window, api)?;
+        wait(&mut self.sides, 1, window, api)?;
+        Ok(start.elapsed())
+    }
+
+    // All receives of a side land in the same area, which is fine as the contents are not
+    // looked at.
+    //
+    // SAFETY (of the calls below): `buf` outlives the endpoints, and is only accessed by the
+    // provider while transfers are in flight.
+    fn post_recv(&mut self, side: usize, size: usize, api: Api) -> Result<()> {
+        let Loopback { sides, mr, buf, .. } = self;
+        let rx = sides[side].rx;
+        let rx = &mut buf[rx..rx + size];
+        retry(sides, side, api, |side| match api {
+            Api::Safe => unsafe { side.ep.recv(rx, Some(mr), side.peer, RX_CONTEXT) },
+            Api::Raw => check_len("fi_recv", unsafe {
+                ffi::fi_recv(
+                    side.ep.as_raw(),
+                    rx.as_mut_ptr().cast(),
+                    rx.len(),
+                    mr.desc(),
+                    side.peer.as_raw(),
+                    RX_CONTEXT as *mut _,
+                )
+            })
+            .map(|_| ()),
+        })
+    }
+
+    fn send(&mut self, side: usize, size: usize, api: Api) -> Result<()> {
+        let Loopback { sides, mr, buf, .. } = self;
+        let tx = sides[side].tx;
+        let tx = &buf[tx..tx + size];
+        retry(sides, side, api, |side| match api {
+            Api::Safe => unsafe { side.ep.send(tx, Some(mr), side.peer, TX_CONTEXT) },
+            Api::Raw => check_len("fi_send", unsafe {
+                ffi::fi_send(
+                    side.ep.as_raw(),
+                    tx.as_ptr().cast(),
+                    tx.len(),
+                    mr.desc(),
+                    side.peer.as_raw(),
+                    TX_CONTEXT as *mut _,
+                )
+            })
+            .map(|_| ()),
+        })
+    }
+}
+
+// Run `op` on a side until the provider has room for it, making progress on both sides in
+// between, as the peer may need to be progressed for room to free up.
+fn retry(
+    sides: &mut [Side; 2],
+    side: usize,
+    api: Api,
+    mut op: impl FnMut(&Side) -> Result<()>,
+) -> Result<()> {
+    loop {
+        match op(&sides[side]) {
+            Err(err) if err.is_again() => {
+                progress(&mut sides[0], api)?;
+                progress(&mut sides[1], api)?;
+            }
+            other => return other,
+        }
+    }
+}
+
+// Wait for `count` completions of a side, progressing the other one as well.
+fn wait(sides: &mut [Side; 2], side: usize, count: usize, api: Api) -> Result<()> {
+    while sides[side].done < count {
+        progress(&mut sides[side], api)?;
+        progress(&mut sides[1 - side], api)?;
+    }
+    sides[side].done -= count;
+    Ok(())
+}
+
+fn progress(side: &mut Side, api: Api) -> Result<()> {
+    let mut completions = [Completion::default(); 16];
+    let read = match api {
+        Api::Safe => side.cq.read(&mut completions),
+        Api::Raw => match unsafe {
+            ffi::fi_cq_read(
+                side.cq.as_raw(),
+                completions.as_mut_ptr().cast(),
+                completions.len(),
+            )
+        } {
+            ret if ret == -(ffi::FI_EAGAIN as isize) => Ok(0),
+            ret => check_len("fi_cq_read", ret),
+        },
+    };
+    side.done += match read {
+        Err(err) if err.is_avail() => {
+            return Err(side.cq.read_err()?.map_or(err, |entry| entry.error));
+        }
+        other => other?,
+    };
+    Ok(())
+}
+
+/// Latency samples, each half of a round trip.
+#[derive(Debug, Clone)]
+pub struct Latencies {
+    sorted: Vec<Duration>,
+}
+
+impl Latencies {
+    /// The sample below which `percentile` percent of them fall, ex: 99.0.
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        if self.sorted.is_empty() {
+            return Duration::ZERO;
+        }
+        let rank = (percentile / 100.0 * (self.sorted.len() - 1) as f64).round() as usize;
+        self.sorted[rank.min(self.sorted.len() - 1)]
+    }
+
+    pub fn mean(&self) -> Duration {
+        let total: Duration = self.sorted.iter().sum();
+        total / self.sorted.len().max(1) as u32
+    }
+
+    pub fn min(&self) -> Duration {
+        self.sorted.first().copied().unwrap_or_default()
+    }
+
+    pub fn max(&self) -> Duration {
+        self.sorted.last().copied().unwrap_or_default()
+    }
+}
+
+/// Measure the one way latency of `size` bytes messages, over `iterations` round trips after
+/// `warmup` untimed ones.
+pub fn latency(
+    loopback: &mut Loopback,
+    size: usize,
+    warmup: usize,
+    iterations: usize,
+    api: Api,
+) -> Result<Latencies> {
+    for _ in 0..warmup {
+        loopback.round_trip(size, api)?;
+    }
+    let mut sorted = (0..iterations)
+        .map(|_| loopback.round_trip(size, api).map(|rtt| rtt / 2))
+        .collect::<Result<Vec<_>>>()?;
+    sorted.sort();
+    Ok(Latencies { sorted })
+}
+
+/// Message rate and bandwidth of a stream of messages.
+#[derive(Debug, Clone, Copy)]
+pub struct Throughput {
+    pub messages_per_sec: f64,
+    pub bytes_per_sec: f64,
+}
+
+/// Measure the throughput of `size` bytes messages, sent `window` at a time, over `iterations`
+/// windows after `warmup` untimed ones.
+pub fn throughput(
+    loopback: &mut Loopback,
+    size: usize,
+    window: usize,
+    warmup: usize,
+    iterations: usize,
+    api: Api,
+) -> Result<Throughput> {
+    for _ in 0..warmup {
+        loopback.stream(size, window, api)?;
+    }
+    let mut elapsed = Duration::ZERO;
+    for _ in 0..iterations {
+        elapsed += loopback.stream(size, window, api)?;
+    }
+    let messages = (window * iterations) as f64;
+    let secs = elapsed.as_secs_f64().max(f64::MIN_POSITIVE);
+    Ok(Throughput {
+        messages_per_sec: messages / secs,
+        bytes_per_sec: messages * size as f64 / secs,
+    })
+}
@@ -0,0 +1,153 @@
+// fi-bench: latency, message rate and bandwidth of the safe wrappers against the raw bindings,
+// between two endpoints of the same process. Built with the `bench` feature:
+//
+//     cargo run --release --features bench --bin fi-bench -- -p tcp -S 8,4096
+//
+// The gap between the two APIs is the overhead of the wrappers, which is what this tracks; see
+// the pingpong binary for measurements across nodes.
+
+use libfabric::bench::{self, Api, Loopback};
+use std::process::ExitCode;
+use std::time::Duration;
+
+const USAGE: &str = "\
+Usage: fi-bench [OPTIONS]
+
+Options:
+  -p, --provider <NAME>    Provider to use [default: tcp]
+  -S, --sizes <BYTES,...>  Message sizes, or \"all\" for powers of two up to 64 KiB [default: all]
+  -I, --iterations <N>     Timed round trips, or windows, per size [default: 1000]
+  -W, --warmup <N>         Untimed ones before [default: 100]
+  -w, --window <N>         Messages in flight for the throughput test [default: 64]
+  -a, --api <API>          safe, raw or both [default: both]
+  -h, --help               Print this help";
+
+struct Args {
+    provider: String,
+    sizes: Vec<usize>,
+    iterations: usize,
+    warmup: usize,
+    window: usize,
+    apis: Vec<Api>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let all: Vec<usize> = (0..=16).map(|shift| 1 << shift).collect();
+    let mut args = Args {
+        provider: "tcp".to_owned(),
+        sizes: all.clone(),
+        iterations: 1000,
+        warmup: 100,
+        window: 64,
+        apis: vec![Api::Safe, Api::Raw],
+    };
+    let mut argv = std::env::args().skip(1);
+    while let Some(arg) = argv.next() {
+        if arg == "-h" || arg == "--help" {
+            println!("{USAGE}");
+            std::process::exit(0);
+        }
+        let value = argv.next().ok_or(format!("{arg} requires a value"))?;
+        let number = |value: &str| {
+            value
+                .parse::<usize>()
+                .map_err(|_| format!("invalid {arg} {value}"))
+        };
+        match arg.as_str() {
+            "-p" | "--provider" => args.provider = value,
+            "-S" | "--sizes" if value == "all" => args.sizes = all.clone(),
+            "-S" | "--sizes" => {
+                args.sizes = value.split(',').map(number).collect::<Result<_, _>>()?
+            }
+            "-I" | "--iterations" => args.iterations = number(&value)?,
+            "-W" | "--warmup" => args.warmup = number(&value)?,
+            "-w" | "--window" => args.window = number(&value)?.max(1),
+            "-a" | "--api" => {
+                args.apis = match value.as_str() {
+                    "safe" => vec![Api::Safe],
+                    "raw" => vec![Api::Raw],
+                    "both" => vec![Api::Safe, Api::Raw],
+                    _ => return Err(format!("unknown api {value}")),
+                }
+            }
+            _ => return Err(format!("unknown option {arg}")),
+        }
+    }
+    Ok(args)
+}
+
+fn usec(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1e6
+}
+
+fn run(args: &Args) -> libfabric::Result<()> {
+    let max_size = args.sizes.iter().copied().max().unwrap_or(0);
+    let mut loopback = Loopback::open(&args.provider, max_size)?;
+
+    println!("# Latency, one way, in usec ({})", args.provider);
+    println!(
+        "{:<10} {:<6} {:>9} {:>9} {:>9} {:>9} {:>9} {:>9}",
+        "bytes", "api", "mean", "p50", "p90", "p99", "p99.9", "max"
+    );
+    for &size in &args.sizes {
+        for &api in &args.apis {
+            let latencies = bench::latency(&mut loopback, size, args.warmup, args.iterations, api)?;
+            println!(
+                "{:<10} {:<6} {:>9.2} {:>9.2} {:>9.2} {:>9.2} {:>9.2} {:>9.2}",
+                size,
+                format!("{api:?}").to_lowercase(),
+                usec(latencies.mean()),
+                usec(latencies.percentile(50.0)),
+                usec(latencies.percentile(90.0)),
+                usec(latencies.percentile(99.0)),
+                usec(latencies.percentile(99.9)),
+                usec(latencies.max()),
+            );
+        }
+    }
+
+    println!();
+    println!("# Throughput, {} messages in flight", args.window);
+    println!(
+        "{:<10} {:<6} {:>12} {:>12}",
+        "bytes", "api", "Mmsgs/sec", "MB/sec"
+    );
+    for &size in &args.sizes {
+        for &api in &args.apis {
+            let throughput = bench::throughput(
+                &mut loopback,
+                size,
+                args.window,
+                // As many untimed messages as untimed round trips above.
+                (args.warmup / args.window).max(1),
+                args.iterations,
+                api,
+            )?;
+            println!(
+                "{:<10} {:<6} {:>12.3} {:>12.2}",
+                size,
+                format!("{api:?}").to_lowercase(),
+                throughput.messages_per_sec / 1e6,
+                throughput.bytes_per_sec / 1e6,
+            );
+        }
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("fi-bench: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("fi-bench: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,173 @@
+// fi-info-rs: list the fabric configurations libfabric discovers, like the fi_info utility.
+//
+// Built with the `cli` feature:
+//
+//     cargo install ofi-libfabric --features cli
+//     fi-info-rs -p tcp -e rdm --json
+//
+// It goes through the safe API only, which makes it an end-to-end check of the bindings on a
+// given node.
+
+use libfabric::{Caps, EndpointType, Info, InfoEntry, Nic, Version, available_providers};
+use serde::Serialize;
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage: fi-info-rs [OPTIONS]
+
+Options:
+  -p, --provider <NAME>  Only list entries of this provider (ex: tcp, verbs)
+  -f, --fabric <NAME>    Only list entries of this fabric
+  -d, --domain <NAME>    Only list entries of this domain
+  -e, --ep-type <TYPE>   Endpoint type: msg, rdm or dgram
+  -c, --caps <CAPS>      Required capabilities, ex: \"MSG | TAGGED\"
+  -n, --node <NODE>      Node to resolve
+  -s, --service <PORT>   Service to resolve
+  -l, --list             List the available providers only
+  -v, --verbose          Include the NIC of each entry
+      --json             Print JSON instead of text
+  -h, --help             Print this help";
+
+#[derive(Default)]
+struct Args {
+    hints: Vec<(String, String)>,
+    list: bool,
+    verbose: bool,
+    json: bool,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args::default();
+    let mut argv = std::env::args().skip(1);
+    while let Some(arg) = argv.next() {
+        match arg.as_str() {
+            "-l" | "--list" => args.list = true,
+            "-v" | "--verbose" => args.verbose = true,
+            "--json" => args.json = true,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                std::process::exit(0);
+            }
+            "-p" | "--provider" | "-f" | "--fabric" | "-d" | "--domain" | "-e" | "--ep-type"
+            | "-c" | "--caps" | "-n" | "--node" | "-s" | "--service" => {
+                let value = argv.next().ok_or(format!("{arg} requires a value"))?;
+                args.hints.push((arg, value));
+            }
+            _ => return Err(format!("unknown option {arg}")),
+        }
+    }
+    Ok(args)
+}
+
+fn hints(args: &Args) -> Result<Info, String> {
+    let mut info = Info::new();
+    for (option, value) in &args.hints {
+        info = match option.as_str() {
+            "-p" | "--provider" => info.provider(value),
+            "-f" | "--fabric" => info.fabric_name(value),
+            "-d" | "--domain" => info.domain_name(value),
+            "-e" | "--ep-type" => info.ep_type(match value.to_lowercase().as_str() {
+                "msg" => EndpointType::Msg,
+                "rdm" => EndpointType::Rdm,
+                "dgram" => EndpointType::Dgram,
+                _ => return Err(format!("unknown endpoint type {value}")),
+            }),
+            "-c" | "--caps" => info.caps(
+                bitflags::parser::from_str::<Caps>(value)
+                    .map_err(|err| format!("invalid capabilities {value}: {err}"))?,
+            ),
+            "-n" | "--node" => info.node(value),
+            "-s" | "--service" => info.service(value),
+            _ => unreachable!(),
+        };
+    }
+    Ok(info)
+}
+
+/// An entry as printed with --json.
+#[derive(Serialize)]
+struct EntryOutput<'a> {
+    #[serde(flatten)]
+    entry: &'a InfoEntry,
+    provider_version: Version,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nic: Option<Nic>,
+}
+
+fn flags<B: bitflags::Flags>(flags: &B) -> String
+where
+    B::Bits: bitflags::parser::WriteHex,
+{
+    let mut out = String::new();
+    bitflags::parser::to_writer(flags, &mut out).unwrap();
+    out
+}
+
+fn print_entry(entry: &InfoEntry, verbose: bool) {
+    println!("provider: {}", entry.provider_name());
+    println!("    fabric: {}", entry.fabric_name());
+    println!("    domain: {}", entry.domain_name());
+    println!("    version: {}", entry.provider_version());
+    println!("    type: {:?}", entry.ep_type());
+    println!("    caps: {}", flags(&entry.caps()));
+    println!("    mode: {}", flags(&entry.mode()));
+    println!("    mr_mode: {}", flags(&entry.mr_mode()));
+    if let (true, Some(nic)) = (verbose, entry.nic()) {
+        println!("    nic:");
+        println!("        name: {}", nic.name);
+        println!("        device: {} {}", nic.vendor_id, nic.device_id);
+        println!("        driver: {} {}", nic.driver, nic.firmware);
+        if let Some(pci) = nic.pci {
+            println!("        pci: {pci}");
+        }
+        println!("        address: {}", nic.link_address);
+        println!("        mtu: {}", nic.mtu);
+        println!("        speed: {}", nic.speed);
+        let state = match nic.link_up {
+            Some(true) => "up",
+            Some(false) => "down",
+            None => "unknown",
+        };
+        println!("        state: {state}");
+        println!("        network_type: {}", nic.network_type);
+    }
+}
+
+fn run(args: &Args) -> Result<(), String> {
+    if args.list {
+        let providers = available_providers().map_err(|err| err.to_string())?;
+        match args.json {
+            true => println!("{}", serde_json::to_string_pretty(&providers).unwrap()),
+            false => providers.iter().for_each(|name| println!("{name}")),
+        }
+        return Ok(());
+    }
+
+    let entries = hints(args)?.get().map_err(|err| err.to_string())?;
+    if args.json {
+        let output: Vec<EntryOutput<'_>> = entries
+            .iter()
+            .map(|entry| EntryOutput {
+                entry,
+                provider_version: entry.provider_version(),
+                nic: entry.nic().filter(|_| args.verbose),
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&output).unwrap());
+    } else {
+        entries
+            .iter()
+            .for_each(|entry| print_entry(entry, args.verbose));
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match parse_args().and_then(|args| run(&args)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("fi-info-rs: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
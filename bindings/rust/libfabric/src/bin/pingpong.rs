@@ -0,0 +1,633 @@
+// pingpong: bounce a message between two nodes and report the latency, like fabtests' fi_pingpong,
+// or stream messages from the client to the server and report the bandwidth.
+//
+//     pingpong [OPTIONS]           # server, waits for a client
+//     pingpong [OPTIONS] <SERVER>  # client
+//
+// Both sides must be given the same options. Endpoint addresses and memory keys are exchanged
+// out of band over a TCP socket, after which either side in turn sends one message and waits
+// for the next, or the client sends windows of messages, each acknowledged by the server. It is
+// meant as a reference for putting the safe API together, rather than as a tuned benchmark.
+//
+// With --fabtests, it speaks the protocol of the C fabtests run with -E instead, so that either
+// side may be fi_rdm_pingpong, or fi_rdm_bw for the bandwidth test: addresses are exchanged out
+// of band in frames of FT_MAX_CTRL_MSG bytes, and both sides sync in band before the test and
+// after it, with messages of 0 and 4 bytes.
+
+use libfabric::{
+    Access, Addr, AddressVector, AvAttr, BindFlags, Caps, Completion, CompletionQueue, CqAttr,
+    Endpoint, EndpointAddress, EndpointType, EqAttr, EqEvent, EventQueue, Fabric, Info,
+    MemoryRegion, MrMode, PassiveEndpoint,
+};
+use std::error::Error;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "\
+Usage: pingpong [OPTIONS] [SERVER]
+
+Runs as the server without SERVER, as the client otherwise.
+
+Options:
+  -p, --provider <NAME>   Provider to use [default: tcp]
+  -e, --ep-type <TYPE>    Endpoint type: rdm or msg [default: rdm]
+  -m, --mode <MODE>       Transfers: msg, tagged or rma [default: msg]
+  -t, --test <TEST>       pingpong or bw [default: pingpong]
+  -w, --window <N>        Messages per acknowledgement in the bw test [default: 64]
+  -S, --size <BYTES>      Message size [default: 64]
+  -I, --iterations <N>    Number of round trips [default: 1000]
+  -W, --warmup <N>        Round trips before timing starts [default: 10]
+  -B, --oob-port <PORT>   Port of the out of band exchange [default: 47592, 3000 with -E]
+  -E, --fabtests          Interoperate with the fabtests run with -E, over rdm endpoints
+                          and msg transfers
+  -h, --help              Print this help";
+
+// Completion contexts, telling local send completions apart from arrivals.
+const TX_CONTEXT: usize = 1;
+const RX_CONTEXT: usize = 2;
+const TAG: u64 = 0x7070;
+
+// The frames of the addresses fabtests exchange out of band, and the size of the messages
+// acknowledging windows in its bandwidth tests, or ending its tests.
+const FT_MAX_CTRL_MSG: usize = 1024;
+const FT_SYNC_MSG_BYTES: usize = 4;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Msg,
+    Tagged,
+    Rma,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Test {
+    Pingpong,
+    Bandwidth,
+}
+
+struct Args {
+    provider: String,
+    ep_type: EndpointType,
+    mode: Mode,
+    test: Test,
+    window: usize,
+    size: usize,
+    iterations: usize,
+    warmup: usize,
+    oob_port: Option<u16>,
+    fabtests: bool,
+    server: Option<String>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args {
+        provider: "tcp".to_owned(),
+        ep_type: EndpointType::Rdm,
+        mode: Mode::Msg,
+        test: Test::Pingpong,
+        window: 64,
+        size: 64,
+        iterations: 1000,
+        warmup: 10,
+        oob_port: None,
+        fabtests: false,
+        server: None,
+    };
+    let mut argv = std::env::args().skip(1);
+    while let Some(arg) = argv.next() {
+        if arg == "-h" || arg == "--help" {
+            println!("{USAGE}");
+            std::process::exit(0);
+        }
+        if arg == "-E" || arg == "--fabtests" {
+            args.fabtests = true;
+            continue;
+        }
+        if !arg.starts_with('-') {
+            args.server = Some(arg);
+            continue;
+        }
+        let value = argv.next().ok_or(format!("{arg} requires a value"))?;
+        let number = |value: &str| {
+            value
+                .parse::<usize>()
+                .map_err(|_| format!("invalid {arg} {value}"))
+        };
+        match arg.as_str() {
+            "-p" | "--provider" => args.provider = value,
+            "-e" | "--ep-type" => {
+                args.ep_type = match value.as_str() {
+                    "rdm" => EndpointType::Rdm,
+                    "msg" => EndpointType::Msg,
+                    _ => return Err(format!("unknown endpoint type {value}")),
+                }
+            }
+            "-m" | "--mode" => {
+                args.mode = match value.as_str() {
+                    "msg" => Mode::Msg,
+                    "tagged" => Mode::Tagged,
+                    "rma" => Mode::Rma,
+                    _ => return Err(format!("unknown mode {value}")),
+                }
+            }
+            "-t" | "--test" => {
+                args.test = match value.as_str() {
+                    "pingpong" => Test::Pingpong,
+                    "bw" => Test::Bandwidth,
+                    _ => return Err(format!("unknown test {value}")),
+                }
+            }
+            "-w" | "--window" => args.window = number(&value)?.max(1),
+            "-S" | "--size" => args.size = number(&value)?,
+            "-I" | "--iterations" => args.iterations = number(&value)?,
+            "-W" | "--warmup" => args.warmup = number(&value)?,
+            "-B" | "--oob-port" => {
+                args.oob_port = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("invalid {arg} {value}"))?,
+                )
+            }
+            _ => return Err(format!("unknown option {arg}")),
+        }
+    }
+    if args.fabtests && (args.ep_type != EndpointType::Rdm || args.mode != Mode::Msg) {
+        return Err("--fabtests supports rdm endpoints and msg transfers".to_owned());
+    }
+    Ok(args)
+}
+
+impl Args {
+    fn oob_port(&self) -> u16 {
+        match (self.oob_port, self.fabtests) {
+            (Some(port), _) => port,
+            // The default of fabtests' -E.
+            (None, true) => 3000,
+            (None, false) => 47592,
+        }
+    }
+}
+
+/// The out of band channel, over which both sides send and receive length prefixed messages,
+/// or the fixed size frames of fabtests.
+struct Oob(TcpStream);
+
+impl Oob {
+    fn connect(args: &Args) -> std::io::Result<Self> {
+        let stream = match &args.server {
+            // Give the server some time to come up.
+            Some(server) => {
+                let mut attempts = 0;
+                loop {
+                    match TcpStream::connect((server.as_str(), args.oob_port())) {
+                        Err(err) if err.kind() == ErrorKind::ConnectionRefused && attempts < 50 => {
+                            attempts += 1;
+                            std::thread::sleep(Duration::from_millis(100));
+                        }
+                        other => break other?,
+                    }
+                }
+            }
+            None => TcpListener::bind(("0.0.0.0", args.oob_port()))?.accept()?.0,
+        };
+        stream.set_nodelay(true)?;
+        Ok(Oob(stream))
+    }
+
+    /// Send `mine`, and return what the peer sent.
+    fn exchange(&mut self, mine: &[u8]) -> std::io::Result<Vec<u8>> {
+        self.0.write_all(&(mine.len() as u32).to_le_bytes())?;
+        self.0.write_all(mine)?;
+        let mut len = [0; 4];
+        self.0.read_exact(&mut len)?;
+        let mut theirs = vec![0; u32::from_le_bytes(len) as usize];
+        self.0.read_exact(&mut theirs)?;
+        Ok(theirs)
+    }
+
+    /// Exchange the word fabtests syncs with: the client sends it once connected, and the
+    /// server once its endpoint is enabled.
+    fn sync_word(&mut self) -> std::io::Result<()> {
+        self.0.write_all(&0i32.to_ne_bytes())?;
+        self.0.read_exact(&mut [0; 4])
+    }
+
+    /// Send `mine` in a frame of fabtests, and return the frame the peer sent.
+    fn exchange_frame(&mut self, mine: &[u8]) -> std::io::Result<Vec<u8>> {
+        let mut frame = vec![0; FT_MAX_CTRL_MSG];
+        frame[..mine.len()].copy_from_slice(mine);
+        self.0.write_all(&frame)?;
+        self.0.read_exact(&mut frame)?;
+        Ok(frame)
+    }
+}
+
+// Completions read so far, of each kind, which have not been waited for yet.
+#[derive(Default)]
+struct Counts {
+    tx: usize,
+    rx: usize,
+}
+
+/// An endpoint connected to its peer, along with the buffer it transfers from and to.
+struct Pingpong {
+    mode: Mode,
+    size: usize,
+    // The offset of the receive buffer, which also takes the messages of the syncs.
+    half: usize,
+    ep: Endpoint,
+    cq: CompletionQueue,
+    peer: Addr,
+    // Closed before the buffer below is freed.
+    mr: MemoryRegion,
+    // The first half is sent from, the second half is received into.
+    buf: Vec<u8>,
+    // Where to write in the peer's receive buffer, in rma mode.
+    remote_addr: u64,
+    remote_key: u64,
+    counts: Counts,
+    // Objects the endpoint was bound to, kept alive along with it.
+    _eq: EventQueue,
+    _av: Option<AddressVector>,
+    _pep: Option<PassiveEndpoint>,
+}
+
+fn hints(args: &Args) -> Info {
+    let caps = match args.mode {
+        Mode::Msg => Caps::MSG,
+        Mode::Tagged => Caps::TAGGED,
+        Mode::Rma => Caps::RMA | Caps::REMOTE_CQ_DATA,
+    };
+    Info::new()
+        .provider(&args.provider)
+        .ep_type(args.ep_type)
+        .caps(caps)
+        .mr_mode(
+            MrMode::LOCAL
+                | MrMode::VIRT_ADDR
+                | MrMode::ALLOCATED
+                | MrMode::PROV_KEY
+                | MrMode::ENDPOINT,
+        )
+}
+
+impl Pingpong {
+    fn setup(args: &Args, oob: &mut Oob) -> Result<Self, Box<dyn Error>> {
+        let client = args.server.is_some();
+        if args.fabtests && client {
+            oob.sync_word()?;
+        }
+        let entries = hints(args).get()?;
+        let fabric = Fabric::open(&entries[0])?;
+        let eq = fabric.eq(&EqAttr::new().blocking(true))?;
+        let connected = args.ep_type == EndpointType::Msg;
+
+        // On connected endpoints, the server listens on a passive endpoint whose address it
+        // hands out, then opens its endpoint from the connection request.
+        let mut pep = None;
+        let mut server = None;
+        let entry = match (connected, &args.server) {
+            (true, None) => {
+                let listener = fabric.passive_endpoint(&entries[0])?;
+                listener.bind_eq(&eq)?;
+                listener.listen()?;
+                oob.exchange(listener.name()?.as_bytes())?;
+                pep = Some(listener);
+                match next_event(&eq)? {
+                    EqEvent::ConnReq { info, .. } => info,
+                    event => return Err(format!("unexpected event {event:?}").into()),
+                }
+            }
+            (true, Some(_)) => {
+                server = Some(EndpointAddress::from_bytes(oob.exchange(&[])?));
+                entries[0].clone()
+            }
+            (false, _) => entries[0].clone(),
+        };
+
+        let domain = fabric.domain(&entry)?;
+        let cq = domain.cq(&CqAttr::new())?;
+        let ep = domain
+            .endpoint(&entry)?
+            .bind_cq(&cq, BindFlags::TRANSMIT | BindFlags::RECV)?;
+        let (ep, av) = match connected {
+            true => (ep.bind_eq(&eq)?, None),
+            false => {
+                let av = domain.av(&AvAttr::new())?;
+                (ep.bind_av(&av)?, Some(av))
+            }
+        };
+        let ep = ep.enable()?;
+
+        let peer = match (&av, server) {
+            (Some(av), _) => {
+                if args.fabtests && !client {
+                    oob.sync_word()?;
+                }
+                let mine = ep.name()?;
+                let theirs = match args.fabtests {
+                    true => oob.exchange_frame(mine.as_bytes())?,
+                    false => oob.exchange(mine.as_bytes())?,
+                };
+                av.insert(&EndpointAddress::from_bytes(theirs))?
+            }
+            (None, server) => {
+                match server {
+                    Some(server) => ep.connect(&server, &[])?,
+                    None => ep.accept(&[])?,
+                }
+                match next_event(&eq)? {
+                    EqEvent::Connected { .. } => Addr::UNSPEC,
+                    event => return Err(format!("unexpected event {event:?}").into()),
+                }
+            }
+        };
+
+        // Register the buffers, and tell the peer where to write in rma mode.
+        let size = args.size;
+        let half = size.max(FT_SYNC_MSG_BYTES);
+        let mut buf = vec![0u8; 2 * half];
+        let access = Access::SEND
+            | Access::RECV
+            | Access::READ
+            | Access::WRITE
+            | Access::REMOTE_READ
+            | Access::REMOTE_WRITE;
+        let mr = unsafe { domain.register(buf.as_mut_ptr(), buf.len(), access)? };
+        if entry.mr_mode().contains(MrMode::ENDPOINT) {
+            mr.bind_endpoint(&ep)?;
+            mr.enable()?;
+        }
+        let rx_addr = match entry.mr_mode().contains(MrMode::VIRT_ADDR) {
+            true => buf[half..].as_ptr() as u64,
+            false => half as u64,
+        };
+        // Fabtests exchanges no keys for message transfers.
+        let mine = [rx_addr.to_le_bytes(), mr.key().to_le_bytes()].concat();
+        let theirs = match args.fabtests {
+            true => vec![0; mine.len()],
+            false => oob.exchange(&mine)?,
+        };
+        let word = |i: usize| {
+            theirs
+                .get(i * 8..i * 8 + 8)
+                .map(|w| u64::from_le_bytes(w.try_into().unwrap()))
+        };
+        let (Some(remote_addr), Some(remote_key)) = (word(0), word(1)) else {
+            return Err("truncated memory key exchange".into());
+        };
+
+        Ok(Pingpong {
+            mode: args.mode,
+            size,
+            half,
+            ep,
+            cq,
+            peer,
+            mr,
+            buf,
+            remote_addr,
+            remote_key,
+            counts: Counts::default(),
+            _eq: eq,
+            _av: av,
+            _pep: pep,
+        })
+    }
+
+    // Post the receive the next message lands in. Remote writes need none.
+    //
+    // SAFETY: The buffers outlive the endpoint, and are not otherwise accessed during transfers.
+    fn post_recv(&mut self) -> libfabric::Result<()> {
+        let Pingpong {
+            ep,
+            cq,
+            peer,
+            mr,
+            buf,
+            counts,
+            ..
+        } = self;
+        let rx = &mut buf[self.half..];
+        match self.mode {
+            Mode::Msg => retry(cq, counts, || unsafe {
+                ep.recv(rx, Some(mr), *peer, RX_CONTEXT)
+            }),
+            Mode::Tagged => retry(cq, counts, || unsafe {
+                ep.trecv(rx, Some(mr), *peer, TAG, 0, RX_CONTEXT)
+            }),
+            Mode::Rma => Ok(()),
+        }
+    }
+
+    // Send one message of `len` bytes, and wait for its completion.
+    fn send(&mut self, len: usize) -> libfabric::Result<()> {
+        self.post_send(len)?;
+        wait(&self.cq, &mut self.counts, |counts| &mut counts.tx)
+    }
+
+    // Post one message of `len` bytes.
+    fn post_send(&mut self, len: usize) -> libfabric::Result<()> {
+        let Pingpong {
+            ep,
+            cq,
+            peer,
+            mr,
+            buf,
+            counts,
+            ..
+        } = self;
+        let tx = &buf[..len];
+        let (addr, key) = (self.remote_addr, self.remote_key);
+        retry(cq, counts, || unsafe {
+            match self.mode {
+                Mode::Msg => ep.send(tx, Some(mr), *peer, TX_CONTEXT),
+                Mode::Tagged => ep.tsend(tx, Some(mr), *peer, TAG, TX_CONTEXT),
+                Mode::Rma => ep.writedata(tx, Some(mr), 0, *peer, addr, key, TX_CONTEXT),
+            }
+        })
+    }
+
+    // Wait for the peer's message, and post the receive for the next one.
+    fn recv(&mut self) -> libfabric::Result<()> {
+        wait(&self.cq, &mut self.counts, |counts| &mut counts.rx)?;
+        self.post_recv()
+    }
+
+    // Wait for the peer to be at the same point, out of band, or in band with a message of
+    // `len` bytes each way, the client's first, as fabtests does.
+    fn sync(&mut self, oob: &mut Oob, args: &Args, len: usize) -> Result<(), Box<dyn Error>> {
+        if !args.fabtests {
+            oob.exchange(&[])?;
+        } else if args.server.is_some() {
+            self.send(len)?;
+            self.recv()?;
+        } else {
+            self.recv()?;
+            self.send(len)?;
+        }
+        Ok(())
+    }
+
+    // Bounce messages with the peer, returning how long the timed round trips took.
+    fn pingpong(&mut self, args: &Args) -> libfabric::Result<Duration> {
+        let client = args.server.is_some();
+        let mut start = Instant::now();
+        for i in 0..args.warmup + args.iterations {
+            if i == args.warmup {
+                start = Instant::now();
+            }
+            if client {
+                self.send(self.size)?;
+                self.recv()?;
+            } else {
+                self.recv()?;
+                self.send(self.size)?;
+            }
+        }
+        Ok(start.elapsed())
+    }
+
+    // Stream messages from the client, acknowledged by the server once per window and at the
+    // end, returning how long the timed ones took.
+    fn bandwidth(&mut self, args: &Args) -> libfabric::Result<Duration> {
+        let client = args.server.is_some();
+        let mut start = Instant::now();
+        let mut window = 0;
+        for i in 0..args.warmup + args.iterations {
+            if i == args.warmup {
+                start = Instant::now();
+            }
+            match client {
+                true => self.post_send(self.size)?,
+                false => self.recv()?,
+            }
+            window += 1;
+            if window == args.window {
+                self.ack(client, window)?;
+                window = 0;
+            }
+        }
+        self.ack(client, window)?;
+        Ok(start.elapsed())
+    }
+
+    // The client waits for the `sends` of the window, then for the server to acknowledge them.
+    fn ack(&mut self, client: bool, sends: usize) -> libfabric::Result<()> {
+        if !client {
+            return self.send(FT_SYNC_MSG_BYTES);
+        }
+        for _ in 0..sends {
+            wait(&self.cq, &mut self.counts, |counts| &mut counts.tx)?;
+        }
+        self.recv()
+    }
+}
+
+// Read the pending completions. Local sends complete with TX_CONTEXT, while arrivals complete
+// with RX_CONTEXT, or no context at all for remote writes.
+fn progress(cq: &CompletionQueue, counts: &mut Counts) -> libfabric::Result<()> {
+    let mut completions = [Completion::default(); 8];
+    let n = match cq.read(&mut completions) {
+        Err(err) if err.is_avail() => {
+            return Err(cq.read_err()?.map_or(err, |entry| entry.error));
+        }
+        other => other?,
+    };
+    for completion in &completions[..n] {
+        match completion.context() {
+            TX_CONTEXT => counts.tx += 1,
+            _ => counts.rx += 1,
+        }
+    }
+    Ok(())
+}
+
+fn wait(
+    cq: &CompletionQueue,
+    counts: &mut Counts,
+    which: fn(&mut Counts) -> &mut usize,
+) -> libfabric::Result<()> {
+    loop {
+        let count = which(counts);
+        if *count > 0 {
+            *count -= 1;
+            return Ok(());
+        }
+        progress(cq, counts)?;
+    }
+}
+
+// Run `op` until the provider has room for it, making progress in between.
+fn retry(
+    cq: &CompletionQueue,
+    counts: &mut Counts,
+    mut op: impl FnMut() -> libfabric::Result<()>,
+) -> libfabric::Result<()> {
+    loop {
+        match op() {
+            Err(err) if err.is_again() => progress(cq, counts)?,
+            other => return other,
+        }
+    }
+}
+
+fn next_event(eq: &EventQueue) -> Result<EqEvent, Box<dyn Error>> {
+    match eq.sread(None) {
+        Ok(Some(event)) => Ok(event),
+        Ok(None) => Err("no event".into()),
+        Err(err) if err.is_avail() => match eq.read_err()? {
+            Some(entry) => Err(format!("{}: {}", entry.error, entry.message).into()),
+            None => Err(err.into()),
+        },
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn run(args: &Args) -> Result<(), Box<dyn Error>> {
+    let mut oob = Oob::connect(args)?;
+    let mut pingpong = Pingpong::setup(args, &mut oob)?;
+    pingpong.post_recv()?;
+    // Both sides have their receive posted before the first message.
+    pingpong.sync(&mut oob, args, 0)?;
+    let elapsed = match args.test {
+        Test::Pingpong => pingpong.pingpong(args)?,
+        Test::Bandwidth => pingpong.bandwidth(args)?,
+    }
+    .as_secs_f64();
+    // Neither side tears down before the other is done.
+    pingpong.sync(&mut oob, args, FT_SYNC_MSG_BYTES)?;
+
+    let transfers = match args.test {
+        Test::Pingpong => 2 * args.iterations,
+        Test::Bandwidth => args.iterations,
+    };
+    let bytes = (transfers * args.size) as f64;
+    println!(
+        "{:<10} {:<10} {:<12} {:<10} {:<12} {:<12} {:<10}",
+        "bytes", "iters", "total", "time", "MB/sec", "usec/xfer", "Mxfers/sec"
+    );
+    println!(
+        "{:<10} {:<10} {:<12} {:<10} {:<12.2} {:<12.2} {:<10.2}",
+        args.size,
+        args.iterations,
+        bytes,
+        format!("{elapsed:.2}s"),
+        bytes / elapsed / 1e6,
+        elapsed * 1e6 / transfers as f64,
+        transfers as f64 / elapsed / 1e6,
+    );
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match parse_args().map_err(Into::into).and_then(|args| run(&args)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("pingpong: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,339 @@
+//! Out of band bootstrap, exchanging what the members of a job need to reach each other before
+//! the fabric itself is usable: endpoint names, the keys of exposed memory regions, and job
+//! metadata.
+//!
+//! A [`Bootstrap`] gathers one contribution from every member, by rank. [`Tcp`] does so over
+//! plain TCP connections to a rendezvous point, which is either a member acting as the root of
+//! the job, or a separate server run with [`serve()`]. With the `pmi` feature, `Pmi` does so
+//! through the key-value store of the job launcher instead.
+//!
+//! ```no_run
+//! use libfabric::bootstrap::{Bootstrap, PeerInfo, Tcp};
+//! use std::time::Duration;
+//!
+//! # fn run(ep: libfabric::Endpoint, av: libfabric::AddressVector, root: bool) -> libfabric::Result<()> {
+//! let mut job = match root {
+//!     true => Tcp::root("0.0.0.0:4000", 4)?,
+//!     false => Tcp::connect("node0:4000", Duration::from_secs(30))?,
+//! };
+//! let local = PeerInfo {
+//!     name: ep.name()?,
+//!     ..Default::default()
+//! };
+//! // Every member's name is now in the address vector, by rank.
+//! let peers = job.exchange(&av, &local)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::av::{Addr, EndpointAddress};
+use crate::error::{Error, Result};
+use crate::transport::{Av, Mr};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "pmi")]
+pub use crate::pmi::Pmi;
+
+/// A memory region exposed to peers, with what they need to target it with RMA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RemoteRegion {
+    /// The address RMA operations target the start of the region with: its virtual address
+    /// when the provider uses [`MrMode::VIRT_ADDR`](crate::MrMode::VIRT_ADDR), 0 otherwise.
+    pub addr: u64,
+    pub len: u64,
+    pub key: u64,
+}
+
+impl RemoteRegion {
+    pub fn new(mr: &impl Mr, virt_addr: bool) -> Self {
+        RemoteRegion {
+            addr: if virt_addr { mr.addr() as u64 } else { 0 },
+            len: mr.len() as u64,
+            key: mr.key(),
+        }
+    }
+}
+
+/// What a member contributes to the exchange.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PeerInfo {
+    pub name: EndpointAddress,
+    pub regions: Vec<RemoteRegion>,
+    /// Application defined metadata.
+    pub data: Vec<u8>,
+}
+
+impl PeerInfo {
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        put_bytes(&mut buf, self.name.as_bytes());
+        buf.extend((self.regions.len() as u32).to_le_bytes());
+        for region in &self.regions {
+            for field in [region.addr, region.len, region.key] {
+                buf.extend(field.to_le_bytes());
+            }
+        }
+        put_bytes(&mut buf, &self.data);
+        buf
+    }
+
+    fn decode(mut buf: &[u8]) -> Result<Self> {
+        let name = EndpointAddress::from_bytes(take_bytes(&mut buf)?);
+        let count = u32::from_le_bytes(take(&mut buf)?);
+        let regions = (0..count)
+            .map(|_| {
+                Ok(RemoteRegion {
+                    addr: u64::from_le_bytes(take(&mut buf)?),
+                    len: u64::from_le_bytes(take(&mut buf)?),
+                    key: u64::from_le_bytes(take(&mut buf)?),
+                })
+            })
+            .collect::<Result<_>>()?;
+        let data = take_bytes(&mut buf)?.to_vec();
+        Ok(PeerInfo {
+            name,
+            regions,
+            data,
+        })
+    }
+}
+
+/// A member of the job, as found by [`Bootstrap::exchange()`].
+#[derive(Debug, Clone)]
+pub struct Peer {
+    /// Where the address vector maps the name of the member.
+    pub addr: Addr,
+    pub info: PeerInfo,
+}
+
+/// An out of band channel between the members of a job, implemented by [`Tcp`] and, with the
+/// `pmi` feature, by `Pmi`.
+pub trait Bootstrap {
+    /// The rank of this member, from 0 to [`size()`](Self::size).
+    fn rank(&self) -> usize;
+
+    fn size(&self) -> usize;
+
+    /// Send `local` to every member, returning what each member sent, by rank. Every member
+    /// must call it the same number of times.
+    fn allgather(&mut self, local: &[u8]) -> Result<Vec<Vec<u8>>>;
+
+    /// Return the `data` of the member `root` on every member; that of the others is ignored.
+    fn broadcast(&mut self, data: &[u8], root: usize) -> Result<Vec<u8>> {
+        let local = if self.rank() == root { data } else { &[] };
+        let mut all = self.allgather(local)?;
+        match root < all.len() {
+            true => Ok(all.swap_remove(root)),
+            false => Err(Error::invalid(format!("no member of rank {root}"))),
+        }
+    }
+
+    /// Wait for every member to reach the barrier.
+    fn barrier(&mut self) -> Result<()> {
+        self.allgather(&[]).map(|_| ())
+    }
+
+    /// Exchange `local` with every member, inserting the names of all members, this one
+    /// included, into `av`. Returns the members by rank.
+    fn exchange<A: Av>(&mut self, av: &A, local: &PeerInfo) -> Result<Vec<Peer>>
+    where
+        Self: Sized,
+    {
+        self.allgather(&local.encode())?
+            .iter()
+            .map(|buf| {
+                let info = PeerInfo::decode(buf)?;
+                Ok(Peer {
+                    addr: av.insert(&info.name)?,
+                    info,
+                })
+            })
+            .collect()
+    }
+}
+
+/// A [`Bootstrap`] over TCP connections to the root of the job, or to a rendezvous server.
+///
+/// Members are given ranks in the order they connect. Every message is a 4 byte little endian
+/// length followed by as many bytes: the hub first sends each member its rank and the size of
+/// the job, then gathers one message from every member for each round, and sends back their
+/// concatenation as a sequence of such messages.
+pub struct Tcp {
+    rank: usize,
+    size: usize,
+    link: Link,
+}
+
+enum Link {
+    // The connections of members 1 and up.
+    Root(Hub),
+    Member(TcpStream),
+}
+
+impl Tcp {
+    /// Act as the root of a job of `size` members, with rank 0, waiting at `addr` for the
+    /// others to connect.
+    pub fn root(addr: impl ToSocketAddrs, size: usize) -> Result<Self> {
+        if size == 0 {
+            return Err(Error::invalid("a job has at least one member"));
+        }
+        let hub = Hub::accept(&TcpListener::bind(addr)?, size, 1)?;
+        Ok(Tcp {
+            rank: 0,
+            size,
+            link: Link::Root(hub),
+        })
+    }
+
+    /// Join the job whose root, or server, is at `addr`, retrying for up to `timeout` while
+    /// they are not up yet.
+    pub fn connect(addr: impl ToSocketAddrs, timeout: Duration) -> Result<Self> {
+        let deadline = Instant::now() + timeout;
+        let mut stream = loop {
+            match TcpStream::connect(&addr) {
+                Err(err) if err.kind() == ErrorKind::ConnectionRefused => {
+                    if Instant::now() > deadline {
+                        return Err(err.into());
+                    }
+                    thread::sleep(Duration::from_millis(100));
+                }
+                other => break other?,
+            }
+        };
+        stream.set_nodelay(true)?;
+        let hello = recv(&mut stream)?;
+        let mut hello = hello.as_slice();
+        let rank = u32::from_le_bytes(take(&mut hello)?) as usize;
+        let size = u32::from_le_bytes(take(&mut hello)?) as usize;
+        Ok(Tcp {
+            rank,
+            size,
+            link: Link::Member(stream),
+        })
+    }
+}
+
+impl Bootstrap for Tcp {
+    fn rank(&self) -> usize {
+        self.rank
+    }
+
+    fn size(&self) -> usize {
+        self.size
+    }
+
+    fn allgather(&mut self, local: &[u8]) -> Result<Vec<Vec<u8>>> {
+        match &mut self.link {
+            Link::Root(hub) => hub.round(Some(local))?.ok_or_else(closed),
+            Link::Member(stream) => {
+                send(stream, local)?;
+                let all = recv(stream)?;
+                let mut all = all.as_slice();
+                let all = (0..self.size)
+                    .map(|_| take_bytes(&mut all).map(<[u8]>::to_vec))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(all)
+            }
+        }
+    }
+}
+
+/// Run a rendezvous server for a job of `size` members at `addr`, which join with
+/// [`Tcp::connect()`] and get ranks 0 and up. Returns once the members disconnected.
+pub fn serve(addr: impl ToSocketAddrs, size: usize) -> Result<()> {
+    if size == 0 {
+        return Err(Error::invalid("a job has at least one member"));
+    }
+    let mut hub = Hub::accept(&TcpListener::bind(addr)?, size, 0)?;
+    while hub.round(None)?.is_some() {}
+    Ok(())
+}
+
+// The connections of the members to the root or server, in rank order.
+struct Hub {
+    members: Vec<TcpStream>,
+}
+
+impl Hub {
+    // Accept the members of ranks `first` to `size`.
+    fn accept(listener: &TcpListener, size: usize, first: usize) -> Result<Self> {
+        let members = (first..size)
+            .map(|rank| {
+                let (mut stream, _) = listener.accept()?;
+                stream.set_nodelay(true)?;
+                let mut hello = (rank as u32).to_le_bytes().to_vec();
+                hello.extend((size as u32).to_le_bytes());
+                send(&mut stream, &hello)?;
+                Ok(stream)
+            })
+            .collect::<Result<_>>()?;
+        Ok(Hub { members })
+    }
+
+    // Gather a message from every member, preceded by `local` at the root, and send all of
+    // them back. Returns None once the members disconnected.
+    fn round(&mut self, local: Option<&[u8]>) -> Result<Option<Vec<Vec<u8>>>> {
+        let mut all: Vec<Vec<u8>> = local.into_iter().map(<[u8]>::to_vec).collect();
+        for stream in &mut self.members {
+            match recv(stream) {
+                Err(Error::Io {
+                    kind: ErrorKind::UnexpectedEof,
+                    ..
+                }) => return Ok(None),
+                other => all.push(other?),
+            }
+        }
+        let mut reply = Vec::new();
+        for buf in &all {
+            put_bytes(&mut reply, buf);
+        }
+        for stream in &mut self.members {
+            send(stream, &reply)?;
+        }
+        Ok(Some(all))
+    }
+}
+
+fn closed() -> Error {
+    Error::from(std::io::Error::from(ErrorKind::UnexpectedEof))
+}
+
+fn send(stream: &mut TcpStream, buf: &[u8]) -> Result<()> {
+    stream.write_all(&(buf.len() as u32).to_le_bytes())?;
+    stream.write_all(buf)?;
+    Ok(())
+}
+
+fn recv(stream: &mut TcpStream) -> Result<Vec<u8>> {
+    let mut len = [0; 4];
+    stream.read_exact(&mut len)?;
+    let mut buf = vec![0; u32::from_le_bytes(len) as usize];
+    stream.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+pub(crate) fn put_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend((bytes.len() as u32).to_le_bytes());
+    buf.extend(bytes);
+}
+
+pub(crate) fn take<const N: usize>(buf: &mut &[u8]) -> Result<[u8; N]> {
+    let (head, tail) = buf
+        .split_first_chunk()
+        .ok_or_else(|| Error::invalid("truncated bootstrap message"))?;
+    *buf = tail;
+    Ok(*head)
+}
+
+pub(crate) fn take_bytes<'a>(buf: &mut &'a [u8]) -> Result<&'a [u8]> {
+    let len = u32::from_le_bytes(take(buf)?) as usize;
+    if buf.len() < len {
+        return Err(Error::invalid("truncated bootstrap message"));
+    }
+    let (head, tail) = buf.split_at(len);
+    *buf = tail;
+    Ok(head)
+}
@@ -0,0 +1,226 @@
+use crate::av::Addr;
+use crate::caps::CapProfile;
+use crate::cq::Completion;
+use crate::ep::{Enabled, Endpoint};
+use crate::error::{Error, Result, check_len};
+use crate::mr::{MemoryRegion, desc};
+use crate::threading::ThreadingModel;
+use crate::trace;
+use ofi_libfabric_sys::bindgen as ffi;
+use std::ffi::c_void;
+use std::ptr::{self, NonNull};
+use std::slice;
+
+/// Buffered receives, with which the provider takes in messages no receive was posted for, and
+/// reports them as [`BufferedRecv`]s: up to [`buffered_limit()`](Self::buffered_limit) bytes
+/// of each are buffered, the rest being left at the sender until the message is claimed.
+///
+/// Before libfabric 2.0, the endpoint is opened with
+/// [`Mode::BUFFERED_RECV`](crate::Mode::BUFFERED_RECV) for all of its receives to be buffered.
+impl<M: ThreadingModel, P: CapProfile> Endpoint<M, Enabled, P> {
+    /// The most bytes of a message buffered by the provider and reported in its completion,
+    /// via `fi_getopt(FI_OPT_BUFFERED_LIMIT)`.
+    pub fn buffered_limit(&self) -> Result<usize> {
+        self.getopt(ffi::FI_OPT_BUFFERED_LIMIT as i32)
+    }
+
+    /// Set the most bytes of a message buffered, via `fi_setopt(FI_OPT_BUFFERED_LIMIT)`, at
+    /// least [`buffered_min()`](Self::buffered_min).
+    pub fn set_buffered_limit(&self, len: usize) -> Result<()> {
+        self.setopt(ffi::FI_OPT_BUFFERED_LIMIT as i32, &len)
+    }
+
+    /// The fewest bytes of a message buffered before it is reported, unless shorter, via
+    /// `fi_getopt(FI_OPT_BUFFERED_MIN)`.
+    pub fn buffered_min(&self) -> Result<usize> {
+        self.getopt(ffi::FI_OPT_BUFFERED_MIN as i32)
+    }
+
+    /// Set the fewest bytes of a message buffered, via `fi_setopt(FI_OPT_BUFFERED_MIN)`: enough
+    /// to decide from its header whether to claim a message, as senders of rendezvous
+    /// protocols send that much along with their request.
+    pub fn set_buffered_min(&self, len: usize) -> Result<()> {
+        self.setopt(ffi::FI_OPT_BUFFERED_MIN as i32, &len)
+    }
+}
+
+/// A message buffered by the provider, from a completion flagged with `FI_CLAIM`, which must
+/// be claimed into a buffer of the application or discarded for the provider to release its
+/// buffer.
+///
+/// The completion context is then a `struct fi_recv_context` of the provider, naming the
+/// endpoint, which [`claim()`](Self::claim) and [`discard()`](Self::discard) pass back, each
+/// once: the message is left buffered when they fail, ex: with `FI_EAGAIN`, to be tried again.
+///
+/// ```no_run
+/// use libfabric::{BufferedRecv, Completion, CompletionQueue, Endpoint};
+///
+/// # fn run(ep: Endpoint, cq: CompletionQueue) -> libfabric::Result<()> {
+/// ep.set_buffered_min(64)?;
+/// let mut entries = [Completion::default(); 1];
+/// let mut buf = vec![0u8; 1 << 20];
+/// if cq.read(&mut entries)? == 1 {
+///     if let Some(mut msg) = unsafe { BufferedRecv::from_completion(&entries[0]) } {
+///         if msg.len() > buf.len() {
+///             msg.discard(&ep)?;
+///         } else {
+///             unsafe { msg.claim(&ep, &mut buf, None, 1)? };
+///         }
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[must_use = "the provider holds the message until it is claimed or discarded"]
+pub struct BufferedRecv {
+    recv: NonNull<ffi::fi_recv_context>,
+    completion: Completion,
+    done: bool,
+}
+
+// SAFETY: The receive context is only passed back to the provider, from any thread.
+unsafe impl Send for BufferedRecv {}
+
+impl BufferedRecv {
+    /// The buffered message of `completion`, if its flags hold `FI_CLAIM`.
+    ///
+    /// # Safety
+    ///
+    /// `completion` must have been read from a completion queue of a receive context, and not
+    /// be claimed or discarded already.
+    pub unsafe fn from_completion(completion: &Completion) -> Option<Self> {
+        if completion.flags() & ffi::FI_CLAIM == 0 {
+            return None;
+        }
+        Some(BufferedRecv {
+            recv: NonNull::new(completion.context() as *mut ffi::fi_recv_context)?,
+            completion: *completion,
+            done: false,
+        })
+    }
+
+    /// The completion reporting the message, with its length, tag and remote CQ data.
+    pub fn completion(&self) -> &Completion {
+        &self.completion
+    }
+
+    /// The length of the message.
+    pub fn len(&self) -> usize {
+        self.completion.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the whole message was buffered, rather than the first bytes of it, the rest
+    /// being transferred once claimed (`FI_MORE`).
+    pub fn is_complete(&self) -> bool {
+        self.completion.flags() & ffi::FI_MORE as u64 == 0
+    }
+
+    /// The message, when buffered whole, read from the buffer of the provider.
+    pub fn data(&self) -> Option<&[u8]> {
+        let buf = self.completion.buf();
+        if !self.is_complete() || self.done || buf.is_null() {
+            return None;
+        }
+        // SAFETY: The provider keeps the buffer until the message is claimed or discarded.
+        Some(unsafe { slice::from_raw_parts(buf, self.len()) })
+    }
+
+    /// Receive the message into `buf`, via `fi_recvmsg()`, or `fi_trecvmsg()` for tagged
+    /// messages, with `FI_CLAIM`. Completes as a receive, `context` being kept in the
+    /// `fi_recv_context` passed as the context of the operation. Fails if the message was
+    /// buffered by another endpoint.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation, for `buf`.
+    pub unsafe fn claim<M: ThreadingModel>(
+        &mut self,
+        ep: &Endpoint<M>,
+        buf: &mut [u8],
+        mr: Option<&MemoryRegion<M>>,
+        context: usize,
+    ) -> Result<()> {
+        let iov = ffi::iovec {
+            iov_base: buf.as_mut_ptr().cast(),
+            iov_len: buf.len(),
+        };
+        let mut desc = desc(mr);
+        unsafe { self.post(ep, &[iov], &mut desc, ffi::FI_CLAIM, context) }
+    }
+
+    /// Drop the message, via `fi_recvmsg()`, or `fi_trecvmsg()` for tagged messages, with
+    /// `FI_CLAIM | FI_DISCARD`. Fails if the message was buffered by another endpoint.
+    pub fn discard<M: ThreadingModel>(&mut self, ep: &Endpoint<M>) -> Result<()> {
+        let mut desc = ptr::null_mut();
+        let flags = ffi::FI_CLAIM | ffi::FI_DISCARD;
+        // SAFETY: No buffer is passed, the provider releasing its own.
+        unsafe { self.post(ep, &[], &mut desc, flags, 0) }
+    }
+
+    unsafe fn post<M: ThreadingModel>(
+        &mut self,
+        ep: &Endpoint<M>,
+        iov: &[ffi::iovec],
+        desc: &mut *mut c_void,
+        flags: u64,
+        user_context: usize,
+    ) -> Result<()> {
+        if self.done {
+            return Err(Error::invalid(
+                "buffered receive already claimed or discarded",
+            ));
+        }
+        if unsafe { (*self.recv.as_ptr()).ep } != ep.as_raw() {
+            return Err(Error::invalid("buffered receive of another endpoint"));
+        }
+        unsafe { (*self.recv.as_ptr()).context = user_context as *mut _ };
+        let context = self.recv.as_ptr() as usize;
+        let (msg_iov, iov_count) = match iov {
+            [] => (ptr::null(), 0),
+            iov => (iov.as_ptr(), iov.len()),
+        };
+        let (op, ret) = if self.completion.is_tagged() {
+            let msg = ffi::fi_msg_tagged {
+                msg_iov,
+                desc,
+                iov_count,
+                addr: Addr::UNSPEC.as_raw(),
+                tag: self.completion.tag(),
+                ignore: 0,
+                context: context as *mut _,
+                data: 0,
+            };
+            trace::data_op!(ep, "fi_trecvmsg", size = self.len());
+            let ret = trace::tracked!(
+                ep,
+                "fi_trecvmsg",
+                context,
+                None,
+                [writes_iov(iov)],
+                unsafe { ffi::fi_trecvmsg(ep.as_raw(), &msg, flags) }
+            );
+            ("fi_trecvmsg", ret)
+        } else {
+            let msg = ffi::fi_msg {
+                msg_iov,
+                desc,
+                iov_count,
+                addr: Addr::UNSPEC.as_raw(),
+                context: context as *mut _,
+                data: 0,
+            };
+            trace::data_op!(ep, "fi_recvmsg", size = self.len());
+            let ret = trace::tracked!(ep, "fi_recvmsg", context, None, [writes_iov(iov)], unsafe {
+                ffi::fi_recvmsg(ep.as_raw(), &msg, flags)
+            });
+            ("fi_recvmsg", ret)
+        };
+        check_len(op, ret)?;
+        self.done = true;
+        Ok(())
+    }
+}
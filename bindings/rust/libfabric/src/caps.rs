@@ -0,0 +1,184 @@
+//! Capability profiles: the capabilities an [`Endpoint`](crate::Endpoint) is meant to use, as
+//! its last type parameter, so that the operations of the others do not compile on it rather
+//! than fail with `FI_EOPNOTSUPP`.
+//!
+//! The profiles are [`Msg`], [`Tagged`], [`Rma`] and [`Atomic`], and tuples of them, ex:
+//! `(Msg, Rma)`, which have the capabilities of each. An endpoint takes a profile with
+//! [`with_profile()`](crate::Endpoint::with_profile), which checks that it was opened with
+//! the capabilities of the profile, and the methods of the capabilities it lacks are left out:
+//! the message operations of [`HasMsg`] profiles, the tagged ones of [`HasTagged`], and so on.
+//! Endpoints are of [`AnyCaps`] by default, on which every operation compiles, and the
+//! provider checks them.
+//!
+//! ```no_run
+//! use libfabric::caps::{Msg, Rma};
+//! use libfabric::{Endpoint, ProfiledEndpoint};
+//!
+//! # fn run(ep: Endpoint, buf: &[u8], dest: libfabric::Addr) -> libfabric::Result<()> {
+//! let ep: ProfiledEndpoint<(Msg, Rma)> = ep.with_profile()?;
+//! unsafe { ep.write(buf, None, dest, 0, 0, 1)? };
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ```compile_fail
+//! use libfabric::caps::Msg;
+//! use libfabric::{Endpoint, ProfiledEndpoint};
+//!
+//! # fn run(ep: Endpoint, buf: &[u8], dest: libfabric::Addr) -> libfabric::Result<()> {
+//! // Endpoints of messages only do not write remote memory.
+//! let ep: ProfiledEndpoint<Msg> = ep.with_profile()?;
+//! unsafe { ep.write(buf, None, dest, 0, 0, 1)? };
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::flags::Caps;
+
+mod sealed {
+    pub trait Sealed {}
+
+    impl Sealed for super::Yes {}
+    impl Sealed for super::No {}
+    impl Sealed for super::AnyCaps {}
+    impl Sealed for super::Msg {}
+    impl Sealed for super::Tagged {}
+    impl Sealed for super::Rma {}
+    impl Sealed for super::Atomic {}
+    impl<A: Sealed, B: Sealed> Sealed for (A, B) {}
+    impl<A: Sealed, B: Sealed, C: Sealed> Sealed for (A, B, C) {}
+}
+
+/// Whether a profile has a capability, [`Yes`] or [`No`].
+pub trait Bit: sealed::Sealed + 'static {
+    /// [`Yes`] if either bit is.
+    type Or<B: Bit>: Bit;
+}
+
+pub enum Yes {}
+
+pub enum No {}
+
+impl Bit for Yes {
+    type Or<B: Bit> = Yes;
+}
+
+impl Bit for No {
+    type Or<B: Bit> = B;
+}
+
+/// The capabilities of the operations an endpoint compiles, see the
+/// [module documentation](self).
+pub trait CapProfile: sealed::Sealed + 'static {
+    type Msg: Bit;
+    type Tagged: Bit;
+    type Rma: Bit;
+    type Atomic: Bit;
+    /// What the entry of the endpoint must have, checked by
+    /// [`with_profile()`](crate::Endpoint::with_profile).
+    const CAPS: Caps;
+}
+
+/// Every operation, checked by the provider rather than at compile time, the default.
+pub enum AnyCaps {}
+
+/// Messages (`Caps::MSG`).
+pub enum Msg {}
+
+/// Tagged messages (`Caps::TAGGED`).
+pub enum Tagged {}
+
+/// Remote memory access (`Caps::RMA`).
+pub enum Rma {}
+
+/// Atomic operations (`Caps::ATOMIC`).
+pub enum Atomic {}
+
+impl CapProfile for AnyCaps {
+    type Msg = Yes;
+    type Tagged = Yes;
+    type Rma = Yes;
+    type Atomic = Yes;
+    const CAPS: Caps = Caps::empty();
+}
+
+impl CapProfile for Msg {
+    type Msg = Yes;
+    type Tagged = No;
+    type Rma = No;
+    type Atomic = No;
+    const CAPS: Caps = Caps::MSG;
+}
+
+impl CapProfile for Tagged {
+    type Msg = No;
+    type Tagged = Yes;
+    type Rma = No;
+    type Atomic = No;
+    const CAPS: Caps = Caps::TAGGED;
+}
+
+impl CapProfile for Rma {
+    type Msg = No;
+    type Tagged = No;
+    type Rma = Yes;
+    type Atomic = No;
+    const CAPS: Caps = Caps::RMA;
+}
+
+impl CapProfile for Atomic {
+    type Msg = No;
+    type Tagged = No;
+    type Rma = No;
+    type Atomic = Yes;
+    const CAPS: Caps = Caps::ATOMIC;
+}
+
+impl<A: CapProfile, B: CapProfile> CapProfile for (A, B) {
+    type Msg = <A::Msg as Bit>::Or<B::Msg>;
+    type Tagged = <A::Tagged as Bit>::Or<B::Tagged>;
+    type Rma = <A::Rma as Bit>::Or<B::Rma>;
+    type Atomic = <A::Atomic as Bit>::Or<B::Atomic>;
+    const CAPS: Caps = A::CAPS.union(B::CAPS);
+}
+
+impl<A: CapProfile, B: CapProfile, C: CapProfile> CapProfile for (A, B, C) {
+    type Msg = <<(A, B) as CapProfile>::Msg as Bit>::Or<C::Msg>;
+    type Tagged = <<(A, B) as CapProfile>::Tagged as Bit>::Or<C::Tagged>;
+    type Rma = <<(A, B) as CapProfile>::Rma as Bit>::Or<C::Rma>;
+    type Atomic = <<(A, B) as CapProfile>::Atomic as Bit>::Or<C::Atomic>;
+    const CAPS: Caps = A::CAPS.union(B::CAPS).union(C::CAPS);
+}
+
+/// Profiles of the message operations, `send()`, `recv()` and the like.
+#[diagnostic::on_unimplemented(
+    message = "endpoints of the profile `{Self}` do not send messages",
+    note = "add `Msg` to the profile, ex: `(Msg, Rma)`"
+)]
+pub trait HasMsg: CapProfile {}
+
+/// Profiles of the tagged operations, `tsend()`, `trecv()` and the like.
+#[diagnostic::on_unimplemented(
+    message = "endpoints of the profile `{Self}` do not send tagged messages",
+    note = "add `Tagged` to the profile, ex: `(Msg, Tagged)`"
+)]
+pub trait HasTagged: CapProfile {}
+
+/// Profiles of the RMA operations, `read()`, `write()` and the like.
+#[diagnostic::on_unimplemented(
+    message = "endpoints of the profile `{Self}` do not access remote memory",
+    note = "add `Rma` to the profile, ex: `(Msg, Rma)`"
+)]
+pub trait HasRma: CapProfile {}
+
+/// Profiles of the atomic operations, `atomic()`, `fetch_atomic()` and the like.
+#[diagnostic::on_unimplemented(
+    message = "endpoints of the profile `{Self}` do not apply atomic operations",
+    note = "add `Atomic` to the profile, ex: `(Rma, Atomic)`"
+)]
+pub trait HasAtomic: CapProfile {}
+
+impl<P: CapProfile<Msg = Yes>> HasMsg for P {}
+impl<P: CapProfile<Tagged = Yes>> HasTagged for P {}
+impl<P: CapProfile<Rma = Yes>> HasRma for P {}
+impl<P: CapProfile<Atomic = Yes>> HasAtomic for P {}
@@ -0,0 +1,286 @@
+//! Channels of values between nodes, enabled by the `channel` feature.
+//!
+//! [`fabric_channel()`] turns an endpoint and its completion queue into a [`Sender`] and a
+//! [`Receiver`], in the manner of `std::sync::mpsc`: the senders of any number of nodes send
+//! values to the receiver of a node, which receives them in the order each sender sent them.
+//! Values are encoded as JSON, or by the [`Codec`] of a [`fabric_channel_with()`], each in a
+//! tagged message of at most [`CreditAttr::max_size()`] bytes, under the credit based flow
+//! control of a [`FlowControl`].
+//!
+//! Like a `sync_channel()`, whose bound would be the [window](CreditAttr::window) of credits,
+//! sends block once the window towards a peer is spent, until the peer receives values, which
+//! grants the credits back.
+//!
+//! ```no_run
+//! # use libfabric::{Addr, CompletionQueue, Endpoint};
+//! # fn run(ep: Endpoint, cq: CompletionQueue, peer: Addr) -> libfabric::Result<()> {
+//! use libfabric::CreditAttr;
+//! use libfabric::channel::fabric_channel;
+//!
+//! let (tx, rx) = unsafe { fabric_channel::<(u32, String), _, _>(ep, cq, &CreditAttr::new()) }?;
+//! let worker = tx.to(peer);
+//! std::thread::spawn(move || worker.send(&(1, "hello".to_string())));
+//! let (src, (id, text)) = rx.recv_from()?;
+//! println!("{id} {text} from {src:?}");
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::av::Addr;
+use crate::codec::{Codec, Json};
+use crate::credit::{CreditAttr, FlowControl};
+use crate::error::{Error, Result};
+use crate::transport::{Cq, Transport};
+use ofi_libfabric_sys::bindgen as ffi;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// The flow control of the channel, whatever its endpoint and queue.
+trait Link: Send {
+    fn send(&mut self, dest: Addr, buf: &[u8]) -> Result<()>;
+    fn recv(&mut self) -> Result<Option<(Addr, Vec<u8>)>>;
+    fn poll(&mut self) -> Result<()>;
+    fn backlog(&self) -> usize;
+}
+
+impl<T: Transport + Send, C: Cq + Send> Link for FlowControl<T, C> {
+    fn send(&mut self, dest: Addr, buf: &[u8]) -> Result<()> {
+        FlowControl::send(self, dest, buf)
+    }
+
+    fn recv(&mut self) -> Result<Option<(Addr, Vec<u8>)>> {
+        FlowControl::recv(self)
+    }
+
+    fn poll(&mut self) -> Result<()> {
+        FlowControl::poll(self)
+    }
+
+    fn backlog(&self) -> usize {
+        FlowControl::backlog(self)
+    }
+}
+
+type Shared = Arc<Mutex<Box<dyn Link>>>;
+
+/// A channel of values of `M` over `ep`, whose completions are read from `cq`, encoded as
+/// JSON. The sender has no destination yet, see [`Sender::to()`].
+///
+/// # Safety
+///
+/// As for [`FlowControl::new()`]: no other handle of `ep` may outlive the sender and receiver,
+/// and other operations of `ep`, and completions of `cq`, must not be used elsewhere.
+pub unsafe fn fabric_channel<M, T, C>(
+    ep: T,
+    cq: C,
+    attr: &CreditAttr,
+) -> Result<(Sender<M>, Receiver<M>)>
+where
+    Json: Codec<M>,
+    T: Transport + Send + 'static,
+    C: Cq + Send + 'static,
+{
+    unsafe { fabric_channel_with::<Json, M, T, C>(ep, cq, attr) }
+}
+
+/// Like [`fabric_channel()`], with values encoded by `K`, ex:
+/// [`Bincode`](crate::codec::Bincode). Both ends of the channel must use the same codec.
+///
+/// # Safety
+///
+/// See [`fabric_channel()`].
+pub unsafe fn fabric_channel_with<K, M, T, C>(
+    ep: T,
+    cq: C,
+    attr: &CreditAttr,
+) -> Result<(Sender<M, K>, Receiver<M, K>)>
+where
+    K: Codec<M>,
+    T: Transport + Send + 'static,
+    C: Cq + Send + 'static,
+{
+    let flow = unsafe { FlowControl::new(ep, cq, attr) }?;
+    let link: Shared = Arc::new(Mutex::new(Box::new(flow)));
+    let sender = Sender {
+        link: link.clone(),
+        dest: Addr::UNSPEC,
+        _values: PhantomData,
+    };
+    let receiver = Receiver {
+        link,
+        _values: PhantomData,
+    };
+    Ok((sender, receiver))
+}
+
+/// The sending half of a [`fabric_channel()`], sending values to the receiver of a peer.
+/// Clones send over the same endpoint, from any thread.
+pub struct Sender<M, K = Json> {
+    link: Shared,
+    dest: Addr,
+    _values: PhantomData<fn(&M) -> K>,
+}
+
+impl<M, K> Clone for Sender<M, K> {
+    fn clone(&self) -> Self {
+        Sender {
+            link: self.link.clone(),
+            dest: self.dest,
+            _values: PhantomData,
+        }
+    }
+}
+
+impl<M, K: Codec<M>> Sender<M, K> {
+    /// A sender to the channel of the peer at `dest`.
+    #[must_use]
+    pub fn to(&self, dest: Addr) -> Sender<M, K> {
+        Sender {
+            dest,
+            ..self.clone()
+        }
+    }
+
+    /// The peer values are sent to.
+    pub fn dest(&self) -> Addr {
+        self.dest
+    }
+
+    /// Send `value`, once the values sent before it, to any peer, are on their way. Blocks
+    /// until then, while no credits towards their peer are left.
+    pub fn send(&self, value: &M) -> Result<()> {
+        if self.dest == Addr::UNSPEC {
+            return Err(Error::invalid("send on a channel without destination"));
+        }
+        let buf = K::encode(value)?;
+        loop {
+            let mut link = self.link.lock().unwrap();
+            if link.backlog() == 0 {
+                return link.send(self.dest, &buf);
+            }
+            link.poll()?;
+        }
+    }
+
+    /// Send the values waiting for credits that those granted since allow. Credits are only
+    /// taken in while sending or receiving, so nodes which stop doing either call it to let
+    /// their last values out.
+    pub fn flush(&self) -> Result<()> {
+        self.link.lock().unwrap().poll()
+    }
+
+    /// Values waiting for credits, or for room to be sent.
+    pub fn backlog(&self) -> usize {
+        self.link.lock().unwrap().backlog()
+    }
+}
+
+/// With the `sink` feature, senders are sinks of values, ready while no values wait for
+/// credits or for room to be sent, as before a [`send()`](Sender::send), and flushed once none
+/// do, so that `SinkExt::send_all()` follows the window of the receiver. As the futures of the
+/// `async` feature, they do not depend on a runtime, and wake themselves up while they wait.
+#[cfg(feature = "sink")]
+impl<M, K: Codec<M>> futures_sink::Sink<M> for Sender<M, K> {
+    type Error = Error;
+
+    fn poll_ready(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<()>> {
+        self.poll_backlog(cx)
+    }
+
+    fn start_send(self: std::pin::Pin<&mut Self>, value: M) -> Result<()> {
+        if self.dest == Addr::UNSPEC {
+            return Err(Error::invalid("send on a channel without destination"));
+        }
+        let buf = K::encode(&value)?;
+        self.link.lock().unwrap().send(self.dest, &buf)
+    }
+
+    fn poll_flush(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<()>> {
+        self.poll_backlog(cx)
+    }
+
+    fn poll_close(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<()>> {
+        self.poll_backlog(cx)
+    }
+}
+
+#[cfg(feature = "sink")]
+impl<M, K> Sender<M, K> {
+    // Ready once no values wait, taking in the credits granted since.
+    fn poll_backlog(&self, cx: &mut std::task::Context<'_>) -> std::task::Poll<Result<()>> {
+        use std::task::Poll;
+
+        let mut link = self.link.lock().unwrap();
+        if link.backlog() > 0 {
+            if let Err(err) = link.poll() {
+                return Poll::Ready(Err(err));
+            }
+            if link.backlog() > 0 {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// The receiving half of a [`fabric_channel()`], receiving the values sent to this endpoint.
+pub struct Receiver<M, K = Json> {
+    link: Shared,
+    _values: PhantomData<fn() -> (M, K)>,
+}
+
+impl<M, K: Codec<M>> Receiver<M, K> {
+    /// The next value, waiting for it.
+    pub fn recv(&self) -> Result<M> {
+        self.recv_from().map(|(_, value)| value)
+    }
+
+    /// The next value and the address of its sender, waiting for it.
+    pub fn recv_from(&self) -> Result<(Addr, M)> {
+        loop {
+            if let Some(received) = self.try_recv_from()? {
+                return Ok(received);
+            }
+        }
+    }
+
+    /// The next value, failing with `FI_ETIMEDOUT` if none arrives within `timeout`.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<M> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some((_, value)) = self.try_recv_from()? {
+                return Ok(value);
+            }
+            if Instant::now() >= deadline {
+                return Err(Error::fabric("channel recv", ffi::FI_ETIMEDOUT as i64));
+            }
+        }
+    }
+
+    /// The next value, if one arrived.
+    pub fn try_recv(&self) -> Result<Option<M>> {
+        Ok(self.try_recv_from()?.map(|(_, value)| value))
+    }
+
+    /// The next value and the address of its sender, if one arrived. Values which fail to
+    /// decode, sent as another type, fail with `FI_EINVAL`, and are dropped.
+    pub fn try_recv_from(&self) -> Result<Option<(Addr, M)>> {
+        // Unlocked between polls, so that senders of other threads get in.
+        let Some((src, buf)) = self.link.lock().unwrap().recv()? else {
+            return Ok(None);
+        };
+        let value = K::decode(&buf)?;
+        Ok(Some((src, value)))
+    }
+}
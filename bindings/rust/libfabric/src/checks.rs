@@ -0,0 +1,239 @@
+use crate::error::{Error, Result};
+use crate::flags::{Caps, OpFlags};
+use crate::info::InfoEntry;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// How much the data transfers of the endpoints of a domain check their arguments before they
+/// are posted, set with [`DomainConfig::validation()`](crate::DomainConfig::validation) or, at
+/// runtime, with [`Domain::set_validation()`](crate::Domain::set_validation).
+///
+/// Providers check arguments unevenly: some fail a message past their `max_msg_size` with
+/// `FI_EINVAL`, others truncate it, or fail its completion. Checks fail early instead, with an
+/// invalid argument error naming the operation, the limit and the provider. They cover the
+/// message, tagged and RMA operations of [`Endpoint`](crate::Endpoint); the default is
+/// [`Full`](Self::Full) in debug builds and [`Off`](Self::Off) otherwise, so that production
+/// builds only pay for a load of the level per operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ValidationLevel {
+    /// Arguments are handed to the provider as they are.
+    Off,
+    /// Lengths are checked against the `max_msg_size` of the endpoint, injects against its
+    /// `inject_size`, and vectored operations against its `iov_limit` and `rma_iov_limit`.
+    Cheap,
+    /// The checks of [`Cheap`](Self::Cheap), and operations against the capabilities of the
+    /// endpoint, remote CQ data against the `cq_data_size` of the domain, and flags against
+    /// the direction of the operation, ex: `FI_MULTI_RECV` on a send.
+    Full,
+}
+
+impl Default for ValidationLevel {
+    fn default() -> Self {
+        match cfg!(debug_assertions) {
+            true => ValidationLevel::Full,
+            false => ValidationLevel::Off,
+        }
+    }
+}
+
+// The level of a domain, changed while its endpoints post operations.
+pub(crate) struct Level(AtomicU8);
+
+impl Level {
+    pub(crate) fn new(level: ValidationLevel) -> Self {
+        Level(AtomicU8::new(level as u8))
+    }
+
+    pub(crate) fn get(&self) -> ValidationLevel {
+        match self.0.load(Ordering::Relaxed) {
+            0 => ValidationLevel::Off,
+            1 => ValidationLevel::Cheap,
+            _ => ValidationLevel::Full,
+        }
+    }
+
+    pub(crate) fn set(&self, level: ValidationLevel) {
+        self.0.store(level as u8, Ordering::Relaxed);
+    }
+}
+
+// The attributes of an endpoint its operations are checked against, read from its entry on
+// the first check. Limits of 0 are unknown, and not checked.
+pub(crate) struct Limits {
+    provider: String,
+    caps: Caps,
+    max_msg_size: usize,
+    inject_size: usize,
+    tx_iov_limit: usize,
+    rx_iov_limit: usize,
+    rma_iov_limit: usize,
+    cq_data_size: usize,
+}
+
+impl Limits {
+    pub(crate) fn of(info: &InfoEntry) -> Self {
+        let tx = info.tx_attr();
+        Limits {
+            provider: info.provider_name().to_string(),
+            caps: info.caps(),
+            max_msg_size: info.ep_attr().max_msg_size,
+            inject_size: tx.inject_size,
+            tx_iov_limit: tx.iov_limit,
+            rx_iov_limit: info.rx_attr().iov_limit,
+            rma_iov_limit: tx.rma_iov_limit,
+            cq_data_size: info.domain_attr().cq_data_size,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Kind {
+    Recv,
+    Send,
+    Trecv,
+    Tsend,
+    Read,
+    Write,
+}
+
+impl Kind {
+    fn is_rx(self) -> bool {
+        matches!(self, Kind::Recv | Kind::Trecv)
+    }
+
+    fn caps(self) -> Caps {
+        match self {
+            Kind::Recv | Kind::Send => Caps::MSG,
+            Kind::Trecv | Kind::Tsend => Caps::TAGGED,
+            Kind::Read | Kind::Write => Caps::RMA,
+        }
+    }
+}
+
+// The arguments of an operation to check.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Op {
+    name: &'static str,
+    kind: Kind,
+    len: usize,
+    iov: usize,
+    rma_iov: usize,
+    inject: bool,
+    data: bool,
+    flags: OpFlags,
+}
+
+impl Op {
+    pub(crate) const fn new(name: &'static str, kind: Kind, len: usize) -> Self {
+        Op {
+            name,
+            kind,
+            len,
+            iov: 1,
+            rma_iov: 0,
+            inject: false,
+            data: false,
+            flags: OpFlags::empty(),
+        }
+    }
+
+    // Of `iov` local buffers.
+    pub(crate) const fn iov(mut self, iov: usize) -> Self {
+        self.iov = iov;
+        self
+    }
+
+    // Of `rma_iov` remote segments.
+    pub(crate) const fn rma_iov(mut self, rma_iov: usize) -> Self {
+        self.rma_iov = rma_iov;
+        self
+    }
+
+    pub(crate) const fn inject(mut self) -> Self {
+        self.inject = true;
+        self
+    }
+
+    // With remote CQ data.
+    pub(crate) const fn data(mut self) -> Self {
+        self.data = true;
+        self
+    }
+
+    pub(crate) const fn flags(mut self, flags: OpFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    pub(crate) fn check(&self, level: ValidationLevel, limits: &Limits) -> Result<()> {
+        if level == ValidationLevel::Off {
+            return Ok(());
+        }
+        let (name, provider) = (self.name, &limits.provider);
+        let past = |what: &str, value: usize, limit: usize| {
+            Error::invalid(format!(
+                "{name} of {value} {what}, past the limit of {limit} of the {provider} endpoint"
+            ))
+        };
+        if exceeds(self.len, limits.max_msg_size) {
+            return Err(past("bytes", self.len, limits.max_msg_size));
+        }
+        let injected = self.inject || self.flags.contains(OpFlags::INJECT);
+        if injected && exceeds(self.len, limits.inject_size) {
+            return Err(past("bytes injected", self.len, limits.inject_size));
+        }
+        let iov_limit = match self.kind.is_rx() {
+            true => limits.rx_iov_limit,
+            false => limits.tx_iov_limit,
+        };
+        if exceeds(self.iov, iov_limit) {
+            return Err(past("buffers", self.iov, iov_limit));
+        }
+        if exceeds(self.rma_iov, limits.rma_iov_limit) {
+            return Err(past("remote segments", self.rma_iov, limits.rma_iov_limit));
+        }
+        if level < ValidationLevel::Full {
+            return Ok(());
+        }
+
+        let caps = self.kind.caps();
+        if !limits.caps.contains(caps) {
+            return Err(Error::invalid(format!(
+                "{name} on a {provider} endpoint without {caps:?}, only {:?}",
+                limits.caps
+            )));
+        }
+        if self.data && limits.cq_data_size == 0 {
+            return Err(Error::invalid(format!(
+                "{name} with remote CQ data, on a {provider} domain whose cq_data_size is 0"
+            )));
+        }
+        let misplaced = match self.kind.is_rx() {
+            true => {
+                OpFlags::INJECT
+                    | OpFlags::INJECT_COMPLETE
+                    | OpFlags::TRANSMIT_COMPLETE
+                    | OpFlags::DELIVERY_COMPLETE
+                    | OpFlags::COMMIT_COMPLETE
+                    | OpFlags::FENCE
+            }
+            false => OpFlags::MULTI_RECV,
+        };
+        if self.flags.intersects(misplaced) {
+            return Err(Error::invalid(format!(
+                "{name} with {:?}, which does not apply to it",
+                self.flags & misplaced
+            )));
+        }
+        if self.flags.contains(OpFlags::MULTI_RECV) && !limits.caps.contains(Caps::MULTI_RECV) {
+            return Err(Error::invalid(format!(
+                "{name} with FI_MULTI_RECV, on a {provider} endpoint without Caps::MULTI_RECV"
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn exceeds(value: usize, limit: usize) -> bool {
+    limit != 0 && value > limit
+}
@@ -0,0 +1,107 @@
+//! The client side of sessions: a [`Client`] connects to a [`Server`](crate::server::Server),
+//! and hands the connection, the messages of the server and its RMA events to a [`Handler`],
+//! as the server does for its peers.
+//!
+//! ```no_run
+//! use libfabric::client::{Client, Handler, PeerId, Session, SessionAttr};
+//!
+//! // Keeps the last reply of the server.
+//! struct Last(Vec<u8>);
+//!
+//! impl Handler for Last {
+//!     type Peer = ();
+//!
+//!     fn on_connect(&mut self, session: &mut Session, server: PeerId) -> libfabric::Result<()> {
+//!         session.send(server, b"hello")
+//!     }
+//!
+//!     fn on_message(
+//!         &mut self,
+//!         _: &mut Session,
+//!         _: PeerId,
+//!         _: &mut (),
+//!         msg: &[u8],
+//!     ) -> libfabric::Result<()> {
+//!         self.0 = msg.to_vec();
+//!         Ok(())
+//!     }
+//! }
+//!
+//! # fn run() -> libfabric::Result<()> {
+//! let attr = SessionAttr::new().provider("tcp");
+//! let mut client = Client::connect("server-host", "9228", &attr, Last(Vec::new()))?;
+//! while client.handler().0.is_empty() {
+//!     client.poll()?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::error::Result;
+use crate::session::dispatch;
+pub use crate::session::{Handler, PeerId, RmaEvent, Session, SessionAttr};
+use std::collections::HashMap;
+
+/// A client of a session, see the [module](self) documentation.
+pub struct Client<H: Handler> {
+    session: Session,
+    handler: H,
+    server: PeerId,
+    states: HashMap<PeerId, H::Peer>,
+}
+
+impl<H: Handler> Client<H> {
+    /// Start connecting to the server at `node` and `service`, with the attributes of the
+    /// server. The handler is told once connected, from [`poll()`](Self::poll).
+    pub fn connect(node: &str, service: &str, attr: &SessionAttr, handler: H) -> Result<Self> {
+        let (session, server) = Session::connect(attr.hints(node, service), attr)?;
+        Ok(Client {
+            session,
+            handler,
+            server,
+            states: HashMap::new(),
+        })
+    }
+
+    /// The id of the server, the only peer of the session.
+    pub fn server(&self) -> PeerId {
+        self.server
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.session.is_connected(self.server)
+    }
+
+    /// Send `msg` to the server, see [`Session::send()`].
+    pub fn send(&mut self, msg: &[u8]) -> Result<()> {
+        self.session.send(self.server, msg)
+    }
+
+    /// Disconnect from the server.
+    pub fn disconnect(&mut self) -> Result<()> {
+        self.session.disconnect(self.server)
+    }
+
+    pub fn session(&mut self) -> &mut Session {
+        &mut self.session
+    }
+
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    pub fn handler_mut(&mut self) -> &mut H {
+        &mut self.handler
+    }
+
+    /// The state of the connection, while connected.
+    pub fn state(&self) -> Option<&H::Peer> {
+        self.states.get(&self.server)
+    }
+
+    /// Make progress, calling the handler for what happened, and returning how many events
+    /// it was called for.
+    pub fn poll(&mut self) -> Result<usize> {
+        dispatch(&mut self.session, &mut self.handler, &mut self.states)
+    }
+}
@@ -0,0 +1,347 @@
+use crate::addr::AddressFormat;
+use crate::av::{AddrFormat, EndpointAddress, read_addr};
+use crate::caps::CapProfile;
+use crate::cq::{Completion, CqErrEntry};
+use crate::ep::{Enabled, Endpoint, PassiveEndpoint, ScalableEndpoint};
+use crate::eq::{EqEvent, EventQueue};
+use crate::error::{Error, Result, check};
+use crate::fid::AsRawFid;
+use crate::info::InfoEntry;
+use crate::threading::ThreadingModel;
+use crate::trace;
+use ofi_libfabric_sys::bindgen as ffi;
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::os::raw::{c_int, c_void};
+use std::ptr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Connection private data, as the pointer and length pair the CM calls take.
+fn param(data: &[u8]) -> (*const c_void, usize) {
+    if data.is_empty() {
+        (ptr::null(), 0)
+    } else {
+        (data.as_ptr().cast(), data.len())
+    }
+}
+
+/// The address of the peer of a connected endpoint, from [`Endpoint::peer_address()`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PeerAddress {
+    /// The format of the address, which is that of the endpoint.
+    pub format: AddrFormat,
+    /// The address, in that format.
+    pub addr: EndpointAddress,
+}
+
+impl PeerAddress {
+    /// The address as a socket address, with IP based providers (ex: tcp, verbs over RoCE).
+    pub fn socket_addr(&self) -> Option<SocketAddr> {
+        self.decode()
+    }
+
+    /// The address decoded as an `F`, or `None` if it is of another format.
+    pub fn decode<F: AddressFormat>(&self) -> Option<F> {
+        self.addr.decode(self.format)
+    }
+}
+
+impl<M: ThreadingModel, P: CapProfile> Endpoint<M, Enabled, P> {
+    /// The local address of the endpoint, to hand to peers out of band.
+    pub fn name(&self) -> Result<EndpointAddress> {
+        read_addr("fi_getname", |addr, len| unsafe {
+            ffi::fi_getname(self.as_raw_fid(), addr, len)
+        })
+    }
+
+    /// The local address decoded as an `F`, in the format of the endpoint, or `None` if it
+    /// is of another format.
+    pub fn name_as<F: AddressFormat>(&self) -> Result<Option<F>> {
+        Ok(self.name()?.decode(self.info().addr_format()))
+    }
+
+    /// The address of the connected peer, via `fi_getpeer()`.
+    pub fn peer(&self) -> Result<EndpointAddress> {
+        read_addr("fi_getpeer", |addr, len| unsafe {
+            ffi::fi_getpeer(self.as_raw(), addr, len)
+        })
+    }
+
+    /// The address of the connected peer along with its format, ex: to tell the clients of a
+    /// server apart once accepted, or to log where they connect from.
+    pub fn peer_address(&self) -> Result<PeerAddress> {
+        Ok(PeerAddress {
+            format: self.info().addr_format(),
+            addr: self.peer()?,
+        })
+    }
+
+    /// Start connecting a MSG endpoint to `addr`, sending `data` as private data.
+    ///
+    /// Completion is reported as [`EqEvent::Connected`](crate::EqEvent::Connected) on the bound
+    /// event queue.
+    pub fn connect(&self, addr: &EndpointAddress, data: &[u8]) -> Result<()> {
+        trace::event!(
+            provider = self.info().provider_name(),
+            endpoint = ?self.id(),
+            peer = ?addr.to_socket_addr(),
+            "fi_connect"
+        );
+        let (p, n) = param(data);
+        check("fi_connect", unsafe {
+            ffi::fi_connect(self.as_raw(), addr.as_bytes().as_ptr().cast(), p, n)
+        })
+    }
+
+    /// Accept the connection request the endpoint was opened from.
+    pub fn accept(&self, data: &[u8]) -> Result<()> {
+        trace::event!(
+            provider = self.info().provider_name(),
+            endpoint = ?self.id(),
+            "fi_accept"
+        );
+        let (p, n) = param(data);
+        check("fi_accept", unsafe { ffi::fi_accept(self.as_raw(), p, n) })
+    }
+
+    /// Shut the connection down, via `fi_shutdown()`.
+    pub fn shutdown(&self) -> Result<()> {
+        trace::event!(
+            provider = self.info().provider_name(),
+            endpoint = ?self.id(),
+            "fi_shutdown"
+        );
+        check("fi_shutdown", unsafe { ffi::fi_shutdown(self.as_raw(), 0) })
+    }
+
+    /// Shut the connection down, then read the completions of the bound queues until the
+    /// bound event queue reports the end of the connection with
+    /// [`EqEvent::Shutdown`](crate::EqEvent::Shutdown), or `timeout` expires, and close the
+    /// endpoint, unless it has other handles.
+    ///
+    /// The completions of the operations still in flight are returned, so none of them is
+    /// lost, along with the events of other endpoints sharing the event queue. Error events
+    /// fail the call.
+    pub fn shutdown_graceful(self, timeout: Duration) -> Result<ShutdownReport> {
+        let eq = self
+            .bound_eq()
+            .ok_or_else(|| Error::invalid("endpoint has no event queue bound"))?;
+        let cqs = self.bound_cqs();
+        self.shutdown()?;
+        let mut report = ShutdownReport::default();
+        let deadline = Instant::now() + timeout;
+        let mut buf = [Completion::default(); 16];
+        loop {
+            for cq in &cqs {
+                loop {
+                    match cq.read(&mut buf) {
+                        Ok(0) => break,
+                        Ok(n) => report.completions.extend_from_slice(&buf[..n]),
+                        Err(err) if err.is_avail() => report.errors.extend(cq.read_err()?),
+                        Err(err) => return Err(err),
+                    }
+                }
+            }
+            if report.peer_closed || Instant::now() >= deadline {
+                return Ok(report);
+            }
+            match eq.read() {
+                Ok(Some(EqEvent::Shutdown { fid })) if fid == self.id() => {
+                    // Read the completions once more, as the provider flushes the last ones.
+                    report.peer_closed = true;
+                }
+                Ok(Some(event)) => report.events.push(event),
+                Ok(None) => thread::yield_now(),
+                Err(err) if err.is_avail() => {
+                    return Err(eq.read_err()?.map_or(err, |entry| entry.error));
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+/// What [`Endpoint::shutdown_graceful()`] read from the queues of the endpoint.
+#[derive(Debug, Default)]
+pub struct ShutdownReport {
+    /// Whether the end of the connection was reported before the timeout expired.
+    pub peer_closed: bool,
+    /// The completions of the operations still in flight, and the errors of those which failed,
+    /// ex: the receives the shutdown cancelled.
+    pub completions: Vec<Completion>,
+    pub errors: Vec<CqErrEntry>,
+    /// The events of other endpoints, read from the shared event queue meanwhile.
+    pub events: Vec<EqEvent>,
+}
+
+impl<M: ThreadingModel> ScalableEndpoint<M> {
+    /// The address of the endpoint, at which peers reach all of its contexts.
+    pub fn name(&self) -> Result<EndpointAddress> {
+        read_addr("fi_getname", |addr, len| unsafe {
+            ffi::fi_getname(self.as_raw_fid(), addr, len)
+        })
+    }
+}
+
+impl PassiveEndpoint {
+    /// The address the endpoint listens on.
+    pub fn name(&self) -> Result<EndpointAddress> {
+        read_addr("fi_getname", |addr, len| unsafe {
+            ffi::fi_getname(self.as_raw_fid(), addr, len)
+        })
+    }
+
+    /// Start listening for connection requests, reported on the bound event queue.
+    pub fn listen(&self) -> Result<()> {
+        trace::event!(endpoint = ?self.id(), "fi_listen");
+        check("fi_listen", unsafe { ffi::fi_listen(self.as_raw()) })
+    }
+
+    /// Set how many connection requests the provider queues before they are read from the
+    /// event queue, via `fi_control(FI_BACKLOG)`. Requests past it are refused by the provider,
+    /// or, over sockets, by the kernel.
+    pub fn set_backlog(&self, backlog: usize) -> Result<()> {
+        let mut backlog = c_int::try_from(backlog)
+            .map_err(|_| Error::invalid(format!("backlog {backlog} is too large")))?;
+        check("fi_control", unsafe {
+            ffi::fi_control(
+                self.as_raw_fid(),
+                ffi::FI_BACKLOG as c_int,
+                (&raw mut backlog).cast(),
+            )
+        })
+    }
+
+    /// Reject the connection request described by `info`, sending `data` as private data.
+    pub fn reject(&self, info: &InfoEntry, data: &[u8]) -> Result<()> {
+        trace::event!(
+            provider = info.provider_name(),
+            endpoint = ?self.id(),
+            "fi_reject"
+        );
+        let handle = info.handle();
+        if handle.is_null() {
+            return Err(Error::invalid(
+                "entry does not belong to a connection request",
+            ));
+        }
+        let (p, n) = param(data);
+        check("fi_reject", unsafe {
+            ffi::fi_reject(self.as_raw(), handle, p, n)
+        })
+    }
+}
+
+/// A connection request waiting in an [`AcceptQueue`].
+#[derive(Debug)]
+pub struct ConnRequest {
+    /// The entry to open the accepting endpoint from.
+    pub info: InfoEntry,
+    /// Private data sent by the peer.
+    pub data: Vec<u8>,
+}
+
+/// What an [`AcceptQueue`] does with connection requests once it is full.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// Reject them at once, sending the given private data.
+    Reject(Vec<u8>),
+    /// Stop reading the event queue until requests are taken, such that new ones wait in the
+    /// provider's [backlog](PassiveEndpoint::set_backlog). Other events wait along with them.
+    #[default]
+    Wait,
+}
+
+/// A bounded queue of the connection requests of a listening passive endpoint.
+///
+/// [`progress()`](Self::progress) moves requests from the event queue into the queue, up to
+/// its capacity, past which the [`Overflow`] policy applies. Servers take requests with
+/// [`pop()`](Self::pop) as they are ready to serve them, then accept or
+/// [`reject()`](Self::reject) them. The other events of the queue, such as those of the
+/// accepted endpoints, are set aside in order for [`next_event()`](Self::next_event).
+pub struct AcceptQueue {
+    pep: PassiveEndpoint,
+    eq: EventQueue,
+    capacity: usize,
+    overflow: Overflow,
+    pending: VecDeque<ConnRequest>,
+    events: VecDeque<EqEvent>,
+    rejected: u64,
+}
+
+impl AcceptQueue {
+    /// Queue up to `capacity` requests of `pep`, whose events are read from `eq`.
+    pub fn new(pep: PassiveEndpoint, eq: EventQueue, capacity: usize, overflow: Overflow) -> Self {
+        AcceptQueue {
+            pep,
+            eq,
+            capacity,
+            overflow,
+            pending: VecDeque::with_capacity(capacity),
+            events: VecDeque::new(),
+            rejected: 0,
+        }
+    }
+
+    pub fn passive_endpoint(&self) -> &PassiveEndpoint {
+        &self.pep
+    }
+
+    /// Read the available events, returning how many requests were queued.
+    ///
+    /// Fails with an error for which [`Error::is_avail()`] holds when an error event is pending,
+    /// which must then be consumed with [`EventQueue::read_err()`].
+    pub fn progress(&mut self) -> Result<usize> {
+        let mut queued = 0;
+        while !(self.is_full() && self.overflow == Overflow::Wait) {
+            match self.eq.read()? {
+                Some(EqEvent::ConnReq { info, data, .. }) => match &self.overflow {
+                    Overflow::Reject(reply) if self.is_full() => {
+                        self.pep.reject(&info, reply)?;
+                        self.rejected += 1;
+                    }
+                    _ => {
+                        self.pending.push_back(ConnRequest { info, data });
+                        queued += 1;
+                    }
+                },
+                Some(event) => self.events.push_back(event),
+                None => break,
+            }
+        }
+        Ok(queued)
+    }
+
+    /// Take the oldest request.
+    pub fn pop(&mut self) -> Option<ConnRequest> {
+        self.pending.pop_front()
+    }
+
+    /// Reject a request taken from the queue, sending `data` as private data.
+    pub fn reject(&self, request: &ConnRequest, data: &[u8]) -> Result<()> {
+        self.pep.reject(&request.info, data)
+    }
+
+    /// Take the oldest event other than a connection request.
+    pub fn next_event(&mut self) -> Option<EqEvent> {
+        self.events.pop_front()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.pending.len() >= self.capacity
+    }
+
+    /// How many requests were rejected because the queue was full.
+    pub fn rejected(&self) -> u64 {
+        self.rejected
+    }
+}
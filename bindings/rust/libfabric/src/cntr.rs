@@ -0,0 +1,266 @@
+use crate::domain::Domain;
+use crate::error::{Result, check};
+use crate::fid::{AsRawFid, OwnedFid};
+use crate::peer::PeerCounter;
+use crate::resources::{Claim, Resource};
+use crate::threading::{ThreadSafe, ThreadingModel};
+use crate::util::timeout_ms;
+use crate::wait::{WaitObj, WaitTarget};
+use ofi_libfabric_sys::bindgen as ffi;
+use std::mem;
+use std::ptr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// What a counter counts (`enum fi_cntr_events`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CntrEvents {
+    /// Completed operations.
+    #[default]
+    Completions,
+    /// Transferred bytes.
+    Bytes,
+}
+
+/// Attributes for opening a counter.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[must_use]
+pub struct CntrAttr {
+    events: CntrEvents,
+    blocking: bool,
+    pollable: bool,
+    spin: bool,
+    wait: Option<WaitObj>,
+}
+
+impl CntrAttr {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn events(mut self, events: CntrEvents) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Open the counter with a wait object, so [`Counter::wait()`] can block.
+    pub fn blocking(mut self, blocking: bool) -> Self {
+        self.blocking = blocking;
+        self
+    }
+
+    /// Open the counter with a file descriptor wait object, so it can be polled along with other
+    /// descriptors through [`Counter::wait_fd()`]. This also allows blocking as above.
+    #[cfg(unix)]
+    pub fn pollable(mut self, pollable: bool) -> Self {
+        self.pollable = pollable;
+        self
+    }
+
+    /// Block in [`Counter::wait()`] by yielding the thread in a loop (`FI_WAIT_YIELD`), rather
+    /// than sleeping on a wait object: lower wakeup latency, for a busy core.
+    pub fn spin(mut self, spin: bool) -> Self {
+        self.spin = spin;
+        self
+    }
+
+    /// Open the counter with `obj` as its wait object, rather than the one picked from
+    /// [`blocking()`](Self::blocking), [`pollable()`](Self::pollable) and
+    /// [`spin()`](Self::spin). Opening fails with an argument error if the provider is known not
+    /// to support it, see [`WaitObj::supported()`].
+    pub fn wait_obj(mut self, obj: WaitObj) -> Self {
+        self.wait = Some(obj);
+        self
+    }
+
+    // The wait object to open the counter with.
+    pub(crate) fn wait_object(&self) -> WaitObj {
+        self.wait
+            .unwrap_or_else(|| WaitObj::pick(self.blocking, self.pollable, self.spin))
+    }
+}
+
+/// A completion counter (`fid_cntr`).
+#[derive(Clone)]
+pub struct Counter<M: ThreadingModel = ThreadSafe> {
+    inner: Arc<CntrInner<M>>,
+}
+
+struct CntrInner<M: ThreadingModel> {
+    fid: OwnedFid<ffi::fid_cntr>,
+    domain: Domain<M>,
+    wait: WaitObj,
+    // The owner of a peer counter, kept alive until the counter is closed.
+    #[allow(dead_code)]
+    owner: Option<PeerCounter>,
+    #[allow(dead_code)]
+    claim: Claim,
+}
+
+impl<M: ThreadingModel> Counter<M> {
+    // The handles to the counter, this one included.
+    pub(crate) fn handles(&self) -> usize {
+        Arc::strong_count(&self.inner)
+    }
+
+    // Close the counter, via `fi_close()`, failing unless this is the last handle to it.
+    pub(crate) fn close(self) -> Result<()> {
+        crate::fid::close_last(self.inner, |inner| &mut inner.fid)
+    }
+
+    // Open a counter, forwarding its updates to `owner` instead when given (FI_PEER).
+    pub(crate) fn open(
+        domain: &Domain<M>,
+        attr: &CntrAttr,
+        owner: Option<&PeerCounter>,
+    ) -> Result<Self> {
+        let wait = attr.wait_object();
+        let mut raw = ffi::fi_cntr_attr {
+            events: match attr.events {
+                CntrEvents::Completions => ffi::fi_cntr_events::FI_CNTR_EVENTS_COMP,
+                CntrEvents::Bytes => ffi::fi_cntr_events::FI_CNTR_EVENTS_BYTES,
+            },
+            wait_obj: wait.check(domain.info().provider_name(), WaitTarget::Cntr)?,
+            flags: owner.map_or(0, |_| ffi::FI_PEER),
+            ..Default::default()
+        };
+        let mut context = owner.map(|owner| ffi::fi_peer_cntr_context {
+            size: mem::size_of::<ffi::fi_peer_cntr_context>(),
+            cntr: owner.as_raw(),
+        });
+        let context = context
+            .as_mut()
+            .map_or(ptr::null_mut(), |c| ptr::from_mut(c).cast());
+        let claim = domain.claim(&[Resource::Counter])?;
+        let fid = OwnedFid::open("fi_cntr_open", |cntr| unsafe {
+            ffi::fi_cntr_open(domain.as_raw(), &mut raw, cntr, context)
+        })?;
+        Ok(Counter {
+            inner: Arc::new(CntrInner {
+                fid,
+                domain: domain.clone(),
+                wait: WaitObj::from_raw(raw.wait_obj).unwrap_or(wait),
+                owner: owner.cloned(),
+                claim,
+            }),
+        })
+    }
+
+    /// Take ownership of a counter of `domain` opened elsewhere, ex: by C code. Fails with an
+    /// invalid argument error if `cntr` is null.
+    ///
+    /// # Safety
+    ///
+    /// `cntr` must be an open counter of `domain`, which is closed by the returned value only:
+    /// once all handles to it are dropped, or released by [`into_raw()`](Self::into_raw).
+    pub unsafe fn from_raw(domain: &Domain<M>, cntr: *mut ffi::fid_cntr) -> Result<Self> {
+        let fid = unsafe { OwnedFid::from_raw(cntr) }?;
+        Ok(Counter {
+            inner: Arc::new(CntrInner {
+                wait: crate::wait::wait_obj_of(fid.as_fid()),
+                fid,
+                domain: domain.clone(),
+                owner: None,
+                claim: domain.adopt(&[Resource::Counter]),
+            }),
+        })
+    }
+
+    /// Hand the counter over, ex: to C code, whose `fi_close()` then closes it. Fails unless
+    /// this is the last handle to it, which the endpoints and regions bound to it hold too. The
+    /// domain is released: it must be kept open until the counter is closed.
+    pub fn into_raw(self) -> Result<*mut ffi::fid_cntr> {
+        crate::fid::release_last(self.inner, |inner| &mut inner.fid)
+    }
+
+    pub fn domain(&self) -> &Domain<M> {
+        &self.inner.domain
+    }
+
+    /// The wait object of the counter, as the provider resolved [`WaitObj::Unspec`] if it did.
+    pub fn wait_obj(&self) -> WaitObj {
+        self.inner.wait
+    }
+
+    /// Number of successfully completed events.
+    pub fn read(&self) -> u64 {
+        unsafe { ffi::fi_cntr_read(self.as_raw()) }
+    }
+
+    /// Number of events completed in error.
+    pub fn read_err(&self) -> u64 {
+        unsafe { ffi::fi_cntr_readerr(self.as_raw()) }
+    }
+
+    pub fn add(&self, value: u64) -> Result<()> {
+        check("fi_cntr_add", unsafe {
+            ffi::fi_cntr_add(self.as_raw(), value)
+        })
+    }
+
+    pub fn set(&self, value: u64) -> Result<()> {
+        check("fi_cntr_set", unsafe {
+            ffi::fi_cntr_set(self.as_raw(), value)
+        })
+    }
+
+    pub fn add_err(&self, value: u64) -> Result<()> {
+        check("fi_cntr_adderr", unsafe {
+            ffi::fi_cntr_adderr(self.as_raw(), value)
+        })
+    }
+
+    pub fn set_err(&self, value: u64) -> Result<()> {
+        check("fi_cntr_seterr", unsafe {
+            ffi::fi_cntr_seterr(self.as_raw(), value)
+        })
+    }
+
+    /// Block until the counter reaches `threshold`, an error is counted, or the timeout expires.
+    ///
+    /// Requires a counter opened with [`CntrAttr::blocking()`].
+    pub fn wait(&self, threshold: u64, timeout: Option<Duration>) -> Result<()> {
+        check("fi_cntr_wait", unsafe {
+            ffi::fi_cntr_wait(self.as_raw(), threshold, timeout_ms(timeout))
+        })
+    }
+
+    /// The descriptor of a [pollable](CntrAttr::pollable) counter, readable once it may have
+    /// been updated. Call [`Fabric::trywait()`](crate::Fabric::trywait) before blocking on it.
+    #[cfg(unix)]
+    pub fn wait_fd(&self) -> Result<std::os::fd::RawFd> {
+        crate::wait::wait_fd(self.as_raw_fid(), self.inner.wait)
+    }
+
+    /// The descriptors of a counter opened with [`WaitObj::PollFd`], any of which is readable
+    /// once it may have been updated, see [`PollFdSet::refresh()`](crate::PollFdSet::refresh).
+    #[cfg(unix)]
+    pub fn wait_pollfd(&self) -> Result<crate::PollFdSet> {
+        crate::PollFdSet::fetch(self.as_raw_fid(), self.inner.wait)
+    }
+
+    /// Wait until the counter may have been updated, returning `false` if the timeout expired
+    /// first, for counters opened with a [pollable](WaitObj::is_pollable) wait object.
+    /// [`Fabric::trywait()`](crate::Fabric::trywait) is called first: the future is ready at
+    /// once if the counter has been updated already.
+    ///
+    /// Like the other futures of the `async` feature, it does not depend on a runtime: a
+    /// thread polls the descriptors of the wait object, which are fetched again by each
+    /// call for [`WaitObj::PollFd`], and wakes up the task.
+    #[cfg(all(unix, feature = "async"))]
+    pub async fn readable_async(&self, timeout: Option<Duration>) -> Result<bool> {
+        crate::wait::readable(self.domain().fabric(), self, self.inner.wait, timeout)?.await
+    }
+
+    pub fn as_raw(&self) -> *mut ffi::fid_cntr {
+        self.inner.fid.as_ptr()
+    }
+}
+
+impl<M: ThreadingModel> AsRawFid for Counter<M> {
+    fn as_raw_fid(&self) -> *mut ffi::fid {
+        self.inner.fid.as_fid()
+    }
+}
@@ -0,0 +1,330 @@
+use crate::av::Addr;
+use crate::cq::{Completion, CqErrEntry};
+use crate::error::{Error, Result};
+use crate::info::InfoEntry;
+use crate::transport::{Cq, CqHandler, Transport, poll_cq};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+// Each message of a batch is preceded by its length, little endian.
+const HEADER_LEN: usize = 2;
+
+/// Attributes of a [`Coalescer`], whose batch size must be the same on all peers.
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct CoalesceAttr {
+    max_size: usize,
+    delay: Duration,
+    inject_size: usize,
+    depth: usize,
+}
+
+impl Default for CoalesceAttr {
+    fn default() -> Self {
+        CoalesceAttr {
+            max_size: 4096,
+            delay: Duration::from_micros(20),
+            inject_size: 0,
+            depth: 16,
+        }
+    }
+}
+
+impl CoalesceAttr {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Defaults tuned to the endpoints opened from `entry`: batches up to the inject size of
+    /// its transmit context are injected.
+    pub fn for_entry(entry: &InfoEntry) -> Self {
+        Self::default().inject_size(entry.tx_attr().inject_size)
+    }
+
+    /// Largest batch, headers included, 4 KiB by default. Messages are at most as long, less
+    /// their 2 bytes header, and at most 64 KiB.
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size.max(HEADER_LEN + 1);
+        self
+    }
+
+    /// How long the first message of a batch waits for others to the same peer, 20 us by
+    /// default. Batches are sent once due at the next [`Coalescer::poll()`] or send, or as soon
+    /// as the next message does not fit.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Batches up to which are injected, rather than sent, none by default.
+    pub fn inject_size(mut self, inject_size: usize) -> Self {
+        self.inject_size = inject_size;
+        self
+    }
+
+    /// Number of receives kept posted, 16 by default.
+    pub fn depth(mut self, depth: usize) -> Self {
+        self.depth = depth.max(1);
+        self
+    }
+
+    fn max_message(&self) -> usize {
+        (self.max_size - HEADER_LEN).min(u16::MAX as usize)
+    }
+}
+
+// The messages to a peer packed so far, and when the first was.
+struct Batch {
+    buf: Vec<u8>,
+    since: Instant,
+}
+
+/// Small messages to the same peer packed into one send, for workloads exchanging many tiny
+/// messages, whose rate the per operation cost of the provider otherwise bounds.
+///
+/// Messages to a peer are appended to its batch, which is sent once the
+/// [delay](CoalesceAttr::delay) of its first message expired, or once the next message does
+/// not fit in the [batch](CoalesceAttr::max_size), as one message, or one inject for batches up
+/// to the [inject size](CoalesceAttr::inject_size). Receivers unpack batches into the messages
+/// they hold, in the order they were sent. [`flush()`](Self::flush) sends the batches without
+/// waiting, ex: before blocking on a reply.
+///
+/// It runs over any [`Transport`] and [`Cq`], so over an RDM [`Endpoint`] opened with
+/// `Caps::MSG | Caps::SOURCE`, or a [`MockEndpoint`](crate::mock::MockEndpoint). Batches go
+/// out in order, so messages arrive in order on endpoints ordering sends after sends.
+///
+/// [`Endpoint`]: crate::Endpoint
+pub struct Coalescer<T: Transport, C: Cq> {
+    // First, see `CqHandler`.
+    ep: T,
+    cq: C,
+    attr: CoalesceAttr,
+    slots: Vec<Box<[u8]>>,
+    unposted: Vec<usize>,
+    batches: HashMap<Addr, Batch>,
+    // Batches due, in the order they were closed, waiting for room to be sent.
+    ready: VecDeque<(Addr, Vec<u8>)>,
+    inbox: VecDeque<(Addr, Vec<u8>)>,
+    // The buffers of posted sends, by context.
+    sends: HashMap<usize, Vec<u8>>,
+    next_send: usize,
+    errors: VecDeque<Error>,
+}
+
+impl<T: Transport, C: Cq> Coalescer<T, C> {
+    /// Post the receives of the coalescer on `ep`, whose completions are read from `cq`.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Transport`] documentation.
+    pub unsafe fn new(ep: T, cq: C, attr: &CoalesceAttr) -> Result<Self> {
+        let mut coalescer = Coalescer {
+            ep,
+            cq,
+            attr: attr.clone(),
+            slots: (0..attr.depth)
+                .map(|_| vec![0u8; attr.max_size].into_boxed_slice())
+                .collect(),
+            unposted: (0..attr.depth).rev().collect(),
+            batches: HashMap::new(),
+            ready: VecDeque::new(),
+            inbox: VecDeque::new(),
+            sends: HashMap::new(),
+            next_send: 0,
+            errors: VecDeque::new(),
+        };
+        coalescer.drain()?;
+        Ok(coalescer)
+    }
+
+    pub fn endpoint(&self) -> &T {
+        &self.ep
+    }
+
+    /// Append `buf` to the batch to `dest`, sending the batches due. Never fails with
+    /// `FI_EAGAIN`: batches the provider has no room for wait for the next poll.
+    pub fn send(&mut self, dest: Addr, buf: &[u8]) -> Result<()> {
+        if buf.len() > self.attr.max_message() {
+            return Err(Error::invalid(format!(
+                "{} bytes message over the {} bytes maximum",
+                buf.len(),
+                self.attr.max_message()
+            )));
+        }
+        let fits = self
+            .batches
+            .get(&dest)
+            .is_none_or(|batch| batch.buf.len() + HEADER_LEN + buf.len() <= self.attr.max_size);
+        if !fits {
+            self.close(dest);
+        }
+        let batch = self.batches.entry(dest).or_insert_with(|| Batch {
+            buf: Vec::with_capacity(self.attr.max_size),
+            since: Instant::now(),
+        });
+        batch.buf.extend((buf.len() as u16).to_le_bytes());
+        batch.buf.extend(buf);
+        self.close_due();
+        self.drain()
+    }
+
+    /// The next message received, and its source.
+    pub fn recv(&mut self) -> Result<Option<(Addr, Vec<u8>)>> {
+        if self.inbox.is_empty() {
+            self.poll()?;
+        }
+        Ok(self.inbox.pop_front())
+    }
+
+    /// Send the batches of all peers, without waiting for their delay.
+    pub fn flush(&mut self) -> Result<()> {
+        let dests: Vec<_> = self.batches.keys().copied().collect();
+        for dest in dests {
+            self.close(dest);
+        }
+        self.drain()
+    }
+
+    /// Messages not sent yet, in their batch or in a batch waiting for room to be sent.
+    pub fn pending(&self) -> usize {
+        let count = |mut buf: &[u8]| {
+            let mut messages = 0;
+            while let Some((len, rest)) = split_message(buf) {
+                (buf, messages) = (&rest[len..], messages + 1);
+            }
+            messages
+        };
+        let batched: usize = self.batches.values().map(|batch| count(&batch.buf)).sum();
+        let ready: usize = self.ready.iter().map(|(_, buf)| count(buf)).sum();
+        batched + ready
+    }
+
+    /// Read the completions available, unpack the batches received, and send the batches
+    /// due. Fails with the error of a batch which failed to be sent, whose messages are lost,
+    /// or of a batch received malformed.
+    pub fn poll(&mut self) -> Result<()> {
+        poll_cq(self)?;
+        self.close_due();
+        self.drain()?;
+        match self.errors.pop_front() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    // Close the batch to `dest`, if any, queuing it to be sent.
+    fn close(&mut self, dest: Addr) {
+        if let Some(batch) = self.batches.remove(&dest) {
+            self.ready.push_back((dest, batch.buf));
+        }
+    }
+
+    fn close_due(&mut self) {
+        let now = Instant::now();
+        let mut due: Vec<_> = self
+            .batches
+            .iter()
+            .filter(|(_, batch)| now.duration_since(batch.since) >= self.attr.delay)
+            .map(|(dest, batch)| (batch.since, *dest))
+            .collect();
+        // In the order they were opened, as their messages were sent.
+        due.sort_by_key(|(since, _)| *since);
+        for (_, dest) in due {
+            self.close(dest);
+        }
+    }
+
+    // Repost the receives, then post the batches due, in order, until the provider runs out
+    // of room.
+    fn drain(&mut self) -> Result<()> {
+        while let Some(slot) = self.unposted.pop() {
+            // SAFETY: the slot is not read until the receive completes, and outlives the
+            // endpoint, see `new()`.
+            let posted = unsafe {
+                self.ep
+                    .recv(&mut self.slots[slot], None, Addr::UNSPEC, slot)
+            };
+            match posted {
+                Err(err) if err.is_again() => {
+                    self.unposted.push(slot);
+                    break;
+                }
+                other => other?,
+            }
+        }
+
+        while let Some((dest, buf)) = self.ready.pop_front() {
+            let posted = match buf.len() <= self.attr.inject_size {
+                true => self.ep.inject(&buf, dest).map(|()| None),
+                false => {
+                    let context = self.slots.len().wrapping_add(self.next_send);
+                    // SAFETY: the buffer is kept in `sends` until the send completes.
+                    unsafe { self.ep.send(&buf, None, dest, context) }.map(|()| Some(context))
+                }
+            };
+            match posted {
+                Ok(None) => {}
+                Ok(Some(context)) => {
+                    self.next_send = self.next_send.wrapping_add(1);
+                    self.sends.insert(context, buf);
+                }
+                Err(err) if err.is_again() => {
+                    self.ready.push_front((dest, buf));
+                    break;
+                }
+                // The batch is dropped, like a lost one.
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+}
+
+// The length of the message at the start of `buf`, and the rest of it, if it holds the whole
+// message.
+fn split_message(buf: &[u8]) -> Option<(usize, &[u8])> {
+    let (header, rest) = buf.split_first_chunk::<HEADER_LEN>()?;
+    let len = u16::from_le_bytes(*header) as usize;
+    (len <= rest.len()).then_some((len, rest))
+}
+
+impl<T: Transport, C: Cq> CqHandler for Coalescer<T, C> {
+    type Cq = C;
+
+    fn cq(&self) -> &C {
+        &self.cq
+    }
+
+    fn complete(&mut self, completion: &Completion, src: Addr) {
+        let slot = completion.context();
+        if slot >= self.slots.len() {
+            self.sends.remove(&slot);
+            return;
+        }
+        let mut buf = &self.slots[slot][..completion.len()];
+        while !buf.is_empty() {
+            let Some((len, rest)) = split_message(buf) else {
+                self.errors.push_back(Error::invalid(format!(
+                    "malformed batch of {} bytes",
+                    completion.len()
+                )));
+                break;
+            };
+            self.inbox.push_back((src, rest[..len].to_vec()));
+            buf = &rest[len..];
+        }
+        self.unposted.push(slot);
+    }
+
+    fn failed(&mut self, entry: CqErrEntry) {
+        if entry.context >= self.slots.len() {
+            if self.sends.remove(&entry.context).is_some() {
+                self.errors.push_back(entry.error);
+            }
+            return;
+        }
+        // A truncated receive, of a batch larger than those of this endpoint.
+        self.unposted.push(entry.context);
+        self.errors.push_back(entry.error);
+    }
+}
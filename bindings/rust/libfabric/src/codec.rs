@@ -0,0 +1,142 @@
+//! Encodings of the values of the [`rpc`](crate::rpc) and [`channel`](crate::channel)
+//! layers, enabled by either of their features.
+//!
+//! A [`Codec`] turns values into the payloads of messages and back. [`Raw`] passes bytes as
+//! they are, and each of the others comes with its feature: [`Json`] (`json`) and
+//! [`Bincode`] (`bincode`) encode serde values, [`Protobuf`] (`prost`) prost messages, and
+//! [`Rkyv`] (`rkyv`) the archives of rkyv, which [`Rkyv::access()`] validates and reads in
+//! place, from the receive buffer itself, as
+//! [`Rpc::register_archived()`](crate::rpc::Rpc::register_archived) does.
+//!
+//! Other encodings are added by implementing [`Codec`], which the RPC and channel layers take
+//! as a type parameter.
+
+#[cfg(any(feature = "json", feature = "bincode", feature = "prost"))]
+use crate::error::Error;
+use crate::error::Result;
+
+/// Encodes values of `T` into the payloads of messages.
+pub trait Codec<T> {
+    fn encode(value: &T) -> Result<Vec<u8>>;
+
+    /// Decode a payload, failing with `FI_EINVAL` when it is not a value of `T`.
+    fn decode(bytes: &[u8]) -> Result<T>;
+}
+
+/// Payloads as is.
+pub struct Raw;
+
+impl Codec<Vec<u8>> for Raw {
+    fn encode(value: &Vec<u8>) -> Result<Vec<u8>> {
+        Ok(value.clone())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Vec<u8>> {
+        Ok(bytes.to_vec())
+    }
+}
+
+/// Serde values, as JSON. Requires the `json` feature.
+#[cfg(feature = "json")]
+pub struct Json;
+
+#[cfg(feature = "json")]
+impl<T: serde::Serialize + serde::de::DeserializeOwned> Codec<T> for Json {
+    fn encode(value: &T) -> Result<Vec<u8>> {
+        serde_json::to_vec(value).map_err(|err| Error::invalid(err.to_string()))
+    }
+
+    fn decode(bytes: &[u8]) -> Result<T> {
+        serde_json::from_slice(bytes).map_err(|err| Error::invalid(err.to_string()))
+    }
+}
+
+/// Serde values, in the standard configuration of bincode: compact, and faster to encode and
+/// decode than JSON, for peers built from the same types. Requires the `bincode` feature.
+#[cfg(feature = "bincode")]
+pub struct Bincode;
+
+#[cfg(feature = "bincode")]
+impl<T: serde::Serialize + serde::de::DeserializeOwned> Codec<T> for Bincode {
+    fn encode(value: &T) -> Result<Vec<u8>> {
+        bincode::serde::encode_to_vec(value, bincode::config::standard())
+            .map_err(|err| Error::invalid(err.to_string()))
+    }
+
+    fn decode(bytes: &[u8]) -> Result<T> {
+        let (value, _) = bincode::serde::decode_from_slice(bytes, bincode::config::standard())
+            .map_err(|err| Error::invalid(err.to_string()))?;
+        Ok(value)
+    }
+}
+
+/// Protocol buffers messages, generated by prost, for peers written in other languages.
+/// Requires the `prost` feature.
+#[cfg(feature = "prost")]
+pub struct Protobuf;
+
+#[cfg(feature = "prost")]
+impl<T: prost::Message + Default> Codec<T> for Protobuf {
+    fn encode(value: &T) -> Result<Vec<u8>> {
+        Ok(value.encode_to_vec())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<T> {
+        T::decode(bytes).map_err(|err| Error::invalid(err.to_string()))
+    }
+}
+
+/// The archives of rkyv, which are read in place once validated, with no decoding: see
+/// [`access()`](Self::access). Requires the `rkyv` feature.
+///
+/// Archives are aligned to 16 bytes, as are the receive buffers of the RPC layer, so that
+/// requests are accessed where they were received.
+#[cfg(feature = "rkyv")]
+pub struct Rkyv;
+
+#[cfg(feature = "rkyv")]
+mod rkyv_codec {
+    use super::{Codec, Rkyv};
+    use crate::error::{Error, Result};
+    use rkyv::api::high::{HighDeserializer, HighSerializer, HighValidator};
+    use rkyv::bytecheck::CheckBytes;
+    use rkyv::rancor;
+    use rkyv::ser::allocator::ArenaHandle;
+    use rkyv::util::AlignedVec;
+    use rkyv::{Archive, Archived, Deserialize, Serialize};
+
+    impl Rkyv {
+        /// The archived value of `T` in `bytes`, validated, without copying or decoding it.
+        /// Fails with `FI_EINVAL` when `bytes` are not a valid archive of `T`, or are not
+        /// aligned as the archive requires.
+        pub fn access<T>(bytes: &[u8]) -> Result<&Archived<T>>
+        where
+            T: Archive,
+            T::Archived: for<'a> CheckBytes<HighValidator<'a, rancor::Error>>,
+        {
+            rkyv::access::<T::Archived, rancor::Error>(bytes)
+                .map_err(|err| Error::invalid(err.to_string()))
+        }
+    }
+
+    impl<T> Codec<T> for Rkyv
+    where
+        T: Archive + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rancor::Error>>,
+        T::Archived: for<'a> CheckBytes<HighValidator<'a, rancor::Error>>
+            + Deserialize<T, HighDeserializer<rancor::Error>>,
+    {
+        fn encode(value: &T) -> Result<Vec<u8>> {
+            let bytes = rkyv::to_bytes::<rancor::Error>(value)
+                .map_err(|err| Error::invalid(err.to_string()))?;
+            Ok(bytes.to_vec())
+        }
+
+        // Copied to an aligned buffer first, as payloads handed over as vectors are not.
+        fn decode(bytes: &[u8]) -> Result<T> {
+            let mut aligned: AlignedVec = AlignedVec::with_capacity(bytes.len());
+            aligned.extend_from_slice(bytes);
+            rkyv::from_bytes::<T, rancor::Error>(&aligned)
+                .map_err(|err| Error::invalid(err.to_string()))
+        }
+    }
+}
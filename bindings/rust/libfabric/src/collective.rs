@@ -0,0 +1,739 @@
+use crate::atomic::{AtomicDatatype, AtomicOp};
+use crate::av::{Addr, AddressVector};
+use crate::cntr::Counter;
+use crate::domain::Domain;
+use crate::ep::Endpoint;
+use crate::error::{Error, Result, check, check_len};
+use crate::fid::{AsRawFid, OwnedFid};
+use crate::mr::{MemoryRegion, desc};
+use crate::threading::ThreadingModel;
+use crate::topology::RankSet;
+use ofi_libfabric_sys::bindgen as ffi;
+use std::ffi::c_void;
+use std::marker::PhantomData;
+use std::mem::size_of;
+use std::ptr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Element types collectives reduce, the integer and floating point types of Rust.
+pub trait CollectiveDatatype: AtomicDatatype {
+    /// Whether the bitwise reductions apply, to integers only.
+    const BITWISE: bool;
+}
+
+macro_rules! collective_datatype {
+    ($($ty:ty => $bitwise:literal),* $(,)?) => {
+        $(impl CollectiveDatatype for $ty {
+            const BITWISE: bool = $bitwise;
+        })*
+    };
+}
+
+collective_datatype! {
+    i8 => true,
+    u8 => true,
+    i16 => true,
+    u16 => true,
+    i32 => true,
+    u32 => true,
+    i64 => true,
+    u64 => true,
+    i128 => true,
+    u128 => true,
+    f32 => false,
+    f64 => false,
+}
+
+/// The reductions of collectives, the subset of the [`AtomicOp`]s combining elements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReduceOp {
+    Sum,
+    Prod,
+    Min,
+    Max,
+    Land,
+    Lor,
+    Lxor,
+    /// Bitwise, of integers only.
+    Band,
+    Bor,
+    Bxor,
+}
+
+impl ReduceOp {
+    /// Whether the reduction is bitwise, of [integers](CollectiveDatatype::BITWISE) only.
+    pub fn is_bitwise(self) -> bool {
+        matches!(self, ReduceOp::Band | ReduceOp::Bor | ReduceOp::Bxor)
+    }
+}
+
+impl From<ReduceOp> for AtomicOp {
+    fn from(op: ReduceOp) -> Self {
+        match op {
+            ReduceOp::Sum => AtomicOp::Sum,
+            ReduceOp::Prod => AtomicOp::Prod,
+            ReduceOp::Min => AtomicOp::Min,
+            ReduceOp::Max => AtomicOp::Max,
+            ReduceOp::Land => AtomicOp::Land,
+            ReduceOp::Lor => AtomicOp::Lor,
+            ReduceOp::Lxor => AtomicOp::Lxor,
+            ReduceOp::Band => AtomicOp::Band,
+            ReduceOp::Bor => AtomicOp::Bor,
+            ReduceOp::Bxor => AtomicOp::Bxor,
+        }
+    }
+}
+
+/// The limits of a provider on a reduction of a datatype, see
+/// [`Domain::query_allreduce()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CollectiveAttr {
+    /// The most elements one operation reduces, unlimited when 0.
+    pub count: usize,
+    /// The most members of a group, unlimited when 0.
+    pub max_members: usize,
+}
+
+impl<M: ThreadingModel> Domain<M> {
+    /// The limits of the provider on allreduces of `T` with `op`
+    /// (`fi_query_collective()`).
+    ///
+    /// Fails with `FI_EOPNOTSUPP`, see [`Error::is_unsupported()`], for bitwise reductions of
+    /// floating point types, if the provider does not support the reduction, or if its
+    /// elements of the datatype are not the size of `T`.
+    pub fn query_allreduce<T: CollectiveDatatype>(&self, op: ReduceOp) -> Result<CollectiveAttr> {
+        let unsupported = || Error::fabric("fi_query_collective", ffi::FI_EOPNOTSUPP as i64);
+        if op.is_bitwise() && !T::BITWISE {
+            return Err(unsupported());
+        }
+        let mut attr = ffi::fi_collective_attr {
+            op: AtomicOp::from(op).as_raw(),
+            datatype: T::DATATYPE,
+            ..Default::default()
+        };
+        check("fi_query_collective", unsafe {
+            ffi::fi_query_collective(
+                self.as_raw(),
+                ffi::fi_collective_op::FI_ALLREDUCE,
+                &mut attr,
+                0,
+            )
+        })?;
+        if attr.datatype_attr.size != size_of::<T>() {
+            return Err(unsupported());
+        }
+        Ok(CollectiveAttr {
+            count: attr.datatype_attr.count,
+            max_members: attr.max_members,
+        })
+    }
+}
+
+/// A set of addresses of an address vector (`fid_av_set`), describing the members of a
+/// collective group.
+#[derive(Clone)]
+pub struct AvSet {
+    inner: Arc<AvSetInner>,
+}
+
+struct AvSetInner {
+    fid: OwnedFid<ffi::fid_av_set>,
+    av: AddressVector,
+}
+
+impl AddressVector {
+    /// Create a set holding `members`, via `fi_av_set()`.
+    pub fn av_set(&self, members: &[Addr]) -> Result<AvSet> {
+        // Starting from FI_ADDR_NOTAVAIL creates an empty set, the members are inserted below.
+        let mut attr = ffi::fi_av_set_attr {
+            count: members.len(),
+            start_addr: Addr::NOTAVAIL.as_raw(),
+            end_addr: Addr::NOTAVAIL.as_raw(),
+            stride: 1,
+            ..Default::default()
+        };
+        let set = self.open_set(&mut attr)?;
+        for &addr in members {
+            set.insert(addr)?;
+        }
+        Ok(set)
+    }
+
+    /// Create a set holding the addresses from `start` to `end` included, `stride` apart, as
+    /// `fi_av_set()` selects them, without inserting each. On address vectors of
+    /// [`AvType::Table`](crate::AvType::Table), whose addresses are the indices peers were
+    /// inserted at, these are the ranks of peers inserted in order.
+    ///
+    /// Fails with an invalid argument error if `stride` is 0 or `end` is before `start`.
+    pub fn av_set_strided(&self, start: Addr, end: Addr, stride: u64) -> Result<AvSet> {
+        let (first, last) = (start.as_raw(), end.as_raw());
+        if stride == 0 || last < first {
+            return Err(Error::invalid(format!(
+                "addresses from {first} to {last} by {stride}"
+            )));
+        }
+        let mut attr = ffi::fi_av_set_attr {
+            count: ((last - first) / stride + 1) as usize,
+            start_addr: first,
+            end_addr: last,
+            stride,
+            ..Default::default()
+        };
+        self.open_set(&mut attr)
+    }
+
+    /// Create a set holding the members of `ranks`, `peers` being the addresses of the group
+    /// by rank, ex: those of a [`Communicator`](crate::Communicator). Fails with an invalid
+    /// argument error if a rank is out of `peers`.
+    pub fn av_set_ranks(&self, peers: &[Addr], ranks: &RankSet) -> Result<AvSet> {
+        let members = ranks
+            .ranks()
+            .iter()
+            .map(|&rank| {
+                peers.get(rank).copied().ok_or_else(|| {
+                    Error::invalid(format!("rank {rank} is out of a group of {}", peers.len()))
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.av_set(&members)
+    }
+
+    fn open_set(&self, attr: &mut ffi::fi_av_set_attr) -> Result<AvSet> {
+        let fid = OwnedFid::open("fi_av_set", |set| unsafe {
+            ffi::fi_av_set(self.as_raw(), attr, set, ptr::null_mut())
+        })?;
+        Ok(AvSet {
+            inner: Arc::new(AvSetInner {
+                fid,
+                av: self.clone(),
+            }),
+        })
+    }
+}
+
+impl AvSet {
+    pub fn av(&self) -> &AddressVector {
+        &self.inner.av
+    }
+
+    pub fn insert(&self, addr: Addr) -> Result<()> {
+        check("fi_av_set_insert", unsafe {
+            ffi::fi_av_set_insert(self.as_raw(), addr.as_raw())
+        })
+    }
+
+    pub fn remove(&self, addr: Addr) -> Result<()> {
+        check("fi_av_set_remove", unsafe {
+            ffi::fi_av_set_remove(self.as_raw(), addr.as_raw())
+        })
+    }
+
+    /// The address designating the whole set, which [`Endpoint::join_collective()`] uses to form
+    /// the group.
+    pub fn addr(&self) -> Result<Addr> {
+        let mut addr = Addr::NOTAVAIL.as_raw();
+        check("fi_av_set_addr", unsafe {
+            ffi::fi_av_set_addr(self.as_raw(), &mut addr)
+        })?;
+        Ok(Addr::from_raw(addr))
+    }
+
+    pub fn as_raw(&self) -> *mut ffi::fid_av_set {
+        self.inner.fid.as_ptr()
+    }
+}
+
+impl AsRawFid for AvSet {
+    fn as_raw_fid(&self) -> *mut ffi::fid {
+        self.inner.fid.as_fid()
+    }
+}
+
+/// A collective group joined by an endpoint (`fid_mc`).
+#[derive(Clone)]
+pub struct Multicast {
+    inner: Arc<McInner>,
+}
+
+struct McInner {
+    fid: OwnedFid<ffi::fid_mc>,
+    // The group is left before the endpoint and the set are closed.
+    _ep: Endpoint,
+    _set: AvSet,
+}
+
+impl Multicast {
+    /// The address collective operations on the group are posted to.
+    pub fn addr(&self) -> Addr {
+        Addr::from_raw(unsafe { ffi::fi_mc_addr(self.as_raw()) })
+    }
+
+    pub fn as_raw(&self) -> *mut ffi::fid_mc {
+        self.inner.fid.as_ptr()
+    }
+}
+
+impl AsRawFid for Multicast {
+    fn as_raw_fid(&self) -> *mut ffi::fid {
+        self.inner.fid.as_fid()
+    }
+}
+
+/// Collective operations (`fi_collective(3)`) over the members of a joined group. The endpoint
+/// must have been opened with [`Caps::COLLECTIVE`](crate::Caps::COLLECTIVE), and every member
+/// must post the same operations in the same order.
+impl Endpoint {
+    /// Join the group formed by the members of `set`.
+    ///
+    /// The join completes asynchronously, with an [`EqEvent::JoinComplete`](crate::EqEvent)
+    /// for the returned group on the event queue bound to the endpoint. Collective operations
+    /// may only be posted to the group after that.
+    pub fn join_collective(&self, set: &AvSet, context: usize) -> Result<Multicast> {
+        let coll_addr = set.addr()?;
+        let fid = OwnedFid::open("fi_join_collective", |mc| unsafe {
+            ffi::fi_join_collective(
+                self.as_raw(),
+                coll_addr.as_raw(),
+                set.as_raw(),
+                0,
+                mc,
+                context as *mut _,
+            )
+        })?;
+        Ok(Multicast {
+            inner: Arc::new(McInner {
+                fid,
+                _ep: self.clone(),
+                _set: set.clone(),
+            }),
+        })
+    }
+
+    /// Complete once every member of the group at `coll_addr` reached the barrier.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    pub unsafe fn barrier(&self, coll_addr: Addr, context: usize) -> Result<()> {
+        let ret = unsafe { ffi::fi_barrier(self.as_raw(), coll_addr.as_raw(), context as *mut _) };
+        check_len("fi_barrier", ret).map(|_| ())
+    }
+
+    /// Broadcast `buf` from the member `root` to all members, overwriting their `buf`.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    pub unsafe fn broadcast<T: AtomicDatatype>(
+        &self,
+        buf: &mut [T],
+        mr: Option<&MemoryRegion>,
+        coll_addr: Addr,
+        root: Addr,
+        context: usize,
+    ) -> Result<()> {
+        let ret = unsafe {
+            ffi::fi_broadcast(
+                self.as_raw(),
+                buf.as_mut_ptr().cast(),
+                buf.len(),
+                desc(mr),
+                coll_addr.as_raw(),
+                root.as_raw(),
+                T::DATATYPE,
+                0,
+                context as *mut _,
+            )
+        };
+        check_len("fi_broadcast", ret).map(|_| ())
+    }
+
+    /// Combine the `buf` of all members element wise with `op`, storing the outcome in the
+    /// `result` of every member.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation; `result` is written when the operation completes.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn allreduce<T: AtomicDatatype>(
+        &self,
+        buf: &[T],
+        mr: Option<&MemoryRegion>,
+        result: &mut [T],
+        result_mr: Option<&MemoryRegion>,
+        coll_addr: Addr,
+        op: AtomicOp,
+        context: usize,
+    ) -> Result<()> {
+        let ret = unsafe {
+            ffi::fi_allreduce(
+                self.as_raw(),
+                buf.as_ptr().cast(),
+                buf.len().min(result.len()),
+                desc(mr),
+                result.as_mut_ptr().cast(),
+                desc(result_mr),
+                coll_addr.as_raw(),
+                T::DATATYPE,
+                op.as_raw(),
+                0,
+                context as *mut _,
+            )
+        };
+        check_len("fi_allreduce", ret).map(|_| ())
+    }
+}
+
+/// A collective operation fixed once, with its group, buffers, datatype and operation, which
+/// [`start()`](Self::start) posts again at each iteration of a loop, as iterative solvers and
+/// training loops reduce the same buffers over and over.
+///
+/// The buffers stay borrowed by the plan, which hands them out between iterations with
+/// [`buf()`](Self::buf) and [`result()`](Self::result). With a [trigger](Self::trigger), each
+/// start is deferred until a counter reaches a threshold, raised at each iteration, so that the
+/// collective chains after the operations which increment the counter.
+///
+/// With a [completion counter](Self::counter), [`issue()`](Self::issue) starts the operation
+/// and returns a [`CollectiveTicket`], done once the counter counts it, to overlap the
+/// collective with computation without reading completions.
+///
+/// ```no_run
+/// # use libfabric::{Addr, CompletionQueue, Endpoint};
+/// # fn run(ep: &Endpoint, cq: &CompletionQueue, group: Addr) -> libfabric::Result<()> {
+/// use libfabric::{AtomicOp, Completion};
+///
+/// let (mut local, mut sum) = ([0f64; 64], [0f64; 64]);
+/// let mut plan = ep.allreduce_plan(&mut local, None, &mut sum, None, group, AtomicOp::Sum)?;
+/// for step in 0..100 {
+///     plan.buf().fill(step as f64);
+///     unsafe { plan.start() }?;
+///     let mut completions = [Completion::default(); 1];
+///     while cq.read(&mut completions)? == 0 {}
+///     assert_eq!(completions[0].context(), plan.context());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[must_use]
+pub struct CollectivePlan<'a, T: AtomicDatatype> {
+    ep: Endpoint,
+    coll_addr: Addr,
+    kind: PlanKind,
+    buf: &'a mut [T],
+    result: &'a mut [T],
+    // The regions of the buffers are kept alive along with their descriptors.
+    _mrs: [Option<MemoryRegion>; 2],
+    descs: [*mut c_void; 2],
+    context: usize,
+    trigger: Option<Trigger>,
+    // The counter the operations complete on, its value and the starts when it was set.
+    counted: Option<(Counter, u64, u64)>,
+    starts: u64,
+}
+
+#[derive(Clone, Copy)]
+enum PlanKind {
+    Barrier,
+    Broadcast { root: Addr },
+    Allreduce { op: AtomicOp },
+}
+
+// The threshold of a triggered plan, in the context its operations are posted with.
+struct Trigger {
+    cntr: Counter,
+    threshold: u64,
+    step: u64,
+    raw: Box<ffi::fi_triggered_context>,
+}
+
+/// Plans of the collective operations.
+impl Endpoint {
+    /// Plan a [`barrier()`](Self::barrier) over the group at `coll_addr`.
+    pub fn barrier_plan(&self, coll_addr: Addr) -> CollectivePlan<'static, u8> {
+        CollectivePlan::new(
+            self,
+            coll_addr,
+            PlanKind::Barrier,
+            &mut [],
+            &mut [],
+            [None, None],
+        )
+    }
+
+    /// Plan a [`broadcast()`](Self::broadcast) of `buf` from the member `root`.
+    pub fn broadcast_plan<'a, T: AtomicDatatype>(
+        &self,
+        buf: &'a mut [T],
+        mr: Option<&MemoryRegion>,
+        coll_addr: Addr,
+        root: Addr,
+    ) -> CollectivePlan<'a, T> {
+        let kind = PlanKind::Broadcast { root };
+        CollectivePlan::new(self, coll_addr, kind, buf, &mut [], [mr.cloned(), None])
+    }
+
+    /// Plan an [`allreduce()`](Self::allreduce) of `buf` into `result`, of the same length.
+    #[allow(clippy::too_many_arguments)]
+    pub fn allreduce_plan<'a, T: AtomicDatatype>(
+        &self,
+        buf: &'a mut [T],
+        mr: Option<&MemoryRegion>,
+        result: &'a mut [T],
+        result_mr: Option<&MemoryRegion>,
+        coll_addr: Addr,
+        op: AtomicOp,
+    ) -> Result<CollectivePlan<'a, T>> {
+        if buf.len() != result.len() {
+            return Err(Error::invalid(format!(
+                "allreduce of {} elements into {}",
+                buf.len(),
+                result.len()
+            )));
+        }
+        let kind = PlanKind::Allreduce { op };
+        let mrs = [mr.cloned(), result_mr.cloned()];
+        Ok(CollectivePlan::new(self, coll_addr, kind, buf, result, mrs))
+    }
+}
+
+impl<'a, T: AtomicDatatype> CollectivePlan<'a, T> {
+    fn new(
+        ep: &Endpoint,
+        coll_addr: Addr,
+        kind: PlanKind,
+        buf: &'a mut [T],
+        result: &'a mut [T],
+        mrs: [Option<MemoryRegion>; 2],
+    ) -> Self {
+        let descs = [desc(mrs[0].as_ref()), desc(mrs[1].as_ref())];
+        CollectivePlan {
+            ep: ep.clone(),
+            coll_addr,
+            kind,
+            buf,
+            result,
+            _mrs: mrs,
+            descs,
+            context: 0,
+            trigger: None,
+            counted: None,
+            starts: 0,
+        }
+    }
+
+    /// The context the operations are posted with, 0 by default.
+    pub fn with_context(mut self, context: usize) -> Self {
+        self.context = context;
+        self
+    }
+
+    /// Defer each start until `cntr` reaches `threshold`, raised by `step` at each iteration,
+    /// as a triggered operation (`FI_TRIGGER`). Requires [`Caps::TRIGGER`](crate::Caps::TRIGGER).
+    ///
+    /// The operations are then posted with a `fi_triggered_context`, whose address their
+    /// completions report as [`context()`](Self::context).
+    pub fn trigger(mut self, cntr: &Counter, threshold: u64, step: u64) -> Self {
+        // The threshold of each start is set by it.
+        let raw = Box::new(ffi::fi_triggered_context {
+            event_type: ffi::fi_trigger_event_FI_TRIGGER_THRESHOLD,
+            ..Default::default()
+        });
+        self.trigger = Some(Trigger {
+            cntr: cntr.clone(),
+            threshold,
+            step,
+            raw,
+        });
+        self
+    }
+
+    /// Track the completions of the operations on `cntr`, for [`issue()`](Self::issue). The
+    /// endpoint must be bound to the counter with
+    /// [`BindFlags::TRANSMIT`](crate::BindFlags::TRANSMIT), and the counter count nothing but
+    /// the operations of the plan from now on. Binding the endpoint to its completion queue
+    /// with [`BindFlags::SELECTIVE_COMPLETION`](crate::BindFlags::SELECTIVE_COMPLETION) leaves
+    /// out their completion entries, which are otherwise still written.
+    pub fn counter(mut self, cntr: &Counter) -> Self {
+        self.counted = Some((cntr.clone(), cntr.read(), self.starts));
+        self
+    }
+
+    /// The context of the completions of the operations.
+    pub fn context(&self) -> usize {
+        match &self.trigger {
+            Some(trigger) => ptr::from_ref(&*trigger.raw) as usize,
+            None => self.context,
+        }
+    }
+
+    /// The buffer sent, or broadcast into, to update between iterations.
+    pub fn buf(&mut self) -> &mut [T] {
+        self.buf
+    }
+
+    /// The buffer an allreduce stores its outcome in, empty for other operations.
+    pub fn result(&self) -> &[T] {
+        self.result
+    }
+
+    /// How many times the plan was started.
+    pub fn starts(&self) -> u64 {
+        self.starts
+    }
+
+    /// Post the operation once more.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation: the buffers, which only the plan hands out, must not
+    /// be accessed until the completion of the operation is read, nor may the plan be started
+    /// again or dropped before that.
+    pub unsafe fn start(&mut self) -> Result<()> {
+        let (context, flags) = match &mut self.trigger {
+            Some(trigger) => {
+                let threshold = trigger.threshold + self.starts * trigger.step;
+                // The previous operation completed, it no longer reads the context.
+                trigger.raw.trigger.threshold = ffi::fi_trigger_threshold {
+                    cntr: trigger.cntr.as_raw(),
+                    threshold: threshold as usize,
+                };
+                let context = ptr::from_mut(&mut *trigger.raw).cast::<c_void>();
+                (context, ffi::FI_TRIGGER as u64)
+            }
+            None => (self.context as *mut c_void, 0),
+        };
+        let (ep, coll_addr) = (self.ep.as_raw(), self.coll_addr.as_raw());
+        let (op, ret) = match self.kind {
+            PlanKind::Barrier => ("fi_barrier2", unsafe {
+                ffi::fi_barrier2(ep, coll_addr, flags, context)
+            }),
+            PlanKind::Broadcast { root } => ("fi_broadcast", unsafe {
+                ffi::fi_broadcast(
+                    ep,
+                    self.buf.as_mut_ptr().cast(),
+                    self.buf.len(),
+                    self.descs[0],
+                    coll_addr,
+                    root.as_raw(),
+                    T::DATATYPE,
+                    flags,
+                    context,
+                )
+            }),
+            PlanKind::Allreduce { op } => ("fi_allreduce", unsafe {
+                ffi::fi_allreduce(
+                    ep,
+                    self.buf.as_ptr().cast(),
+                    self.buf.len(),
+                    self.descs[0],
+                    self.result.as_mut_ptr().cast(),
+                    self.descs[1],
+                    coll_addr,
+                    T::DATATYPE,
+                    op.as_raw(),
+                    flags,
+                    context,
+                )
+            }),
+        };
+        check_len(op, ret)?;
+        self.starts += 1;
+        Ok(())
+    }
+
+    /// Post the operation once more, returning the ticket of its completion on the
+    /// [counter](Self::counter) of the plan, which fails with `FI_EINVAL` without one.
+    ///
+    /// The ticket borrows the plan, whose buffers hold the outcome once it is done.
+    ///
+    /// # Safety
+    ///
+    /// As for [`start()`](Self::start): the ticket must not be dropped before it is done.
+    pub unsafe fn issue(&mut self) -> Result<CollectiveTicket<'_>> {
+        let Some((cntr, base, from)) = &self.counted else {
+            return Err(Error::invalid(
+                "issue of a collective plan without a counter",
+            ));
+        };
+        let ticket = CollectiveTicket {
+            cntr: cntr.clone(),
+            threshold: base + (self.starts - from) + 1,
+            errors: cntr.read_err(),
+            _plan: PhantomData,
+        };
+        unsafe { self.start() }?;
+        Ok(ticket)
+    }
+}
+
+/// The completion of a collective operation started by [`CollectivePlan::issue()`], done once
+/// the counter of the plan counts it. With the `async` feature, the ticket is also a future,
+/// which polls the counter.
+///
+/// ```no_run
+/// # use libfabric::{Addr, Counter, Endpoint};
+/// # fn run(ep: &Endpoint, cntr: &Counter, group: Addr) -> libfabric::Result<()> {
+/// use libfabric::AtomicOp;
+///
+/// let (mut local, mut sum) = ([1f64; 64], [0f64; 64]);
+/// let mut plan = ep
+///     .allreduce_plan(&mut local, None, &mut sum, None, group, AtomicOp::Sum)?
+///     .counter(cntr);
+/// let ticket = unsafe { plan.issue() }?;
+/// while !ticket.is_done()? {
+///     // Compute while the reduction is in flight.
+/// }
+/// drop(ticket);
+/// assert_eq!(plan.result()[0], 64f64);
+/// # Ok(())
+/// # }
+/// ```
+pub struct CollectiveTicket<'p> {
+    cntr: Counter,
+    threshold: u64,
+    errors: u64,
+    _plan: PhantomData<&'p mut ()>,
+}
+
+impl CollectiveTicket<'_> {
+    /// Whether the operation completed, failing with `FI_EIO` once the counter counts errors.
+    pub fn is_done(&self) -> Result<bool> {
+        if self.cntr.read_err() > self.errors {
+            return Err(Error::fabric("collective", ffi::FI_EIO as i64));
+        }
+        Ok(self.cntr.read() >= self.threshold)
+    }
+
+    /// Block until the operation completed, an error is counted, or the timeout expires.
+    ///
+    /// Requires a counter opened with [`CntrAttr::blocking()`](crate::CntrAttr::blocking).
+    pub fn wait(&self, timeout: Option<Duration>) -> Result<()> {
+        self.cntr.wait(self.threshold, timeout)?;
+        self.is_done().map(|_| ())
+    }
+
+    /// The counter value at which the operation is done.
+    pub fn threshold(&self) -> u64 {
+        self.threshold
+    }
+}
+
+// Polled again as soon as the executor gets to it, counters having no waker to register.
+#[cfg(feature = "async")]
+impl std::future::Future for CollectiveTicket<'_> {
+    type Output = Result<()>;
+
+    fn poll(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<()>> {
+        match self.is_done() {
+            Ok(false) => {
+                cx.waker().wake_by_ref();
+                std::task::Poll::Pending
+            }
+            done => std::task::Poll::Ready(done.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,391 @@
+use crate::atomic::{AtomicDatatype, AtomicOp};
+use crate::av::{Addr, AddressVector, EndpointAddress};
+use crate::collective::{AvSet, CollectiveDatatype, Multicast, ReduceOp};
+use crate::cq::{Completion, CompletionQueue};
+use crate::ep::Endpoint;
+use crate::eq::{EqEvent, EventQueue};
+use crate::error::{Error, Result};
+use crate::fid::AsRawFid;
+use crate::tag::TagSpace;
+use crate::topology::RankSet;
+use ofi_libfabric_sys::bindgen as ffi;
+use std::thread;
+
+const JOIN_CONTEXT: usize = 1;
+
+// The tag of point to point messages, then the rank of their sender, so that receives from a
+// given rank match without FI_DIRECTED_RECV.
+const TAGS: TagSpace<2> = TagSpace::new([32, 32]);
+
+/// A group of peers addressed by rank, in the manner of an MPI communicator.
+///
+/// Every member joins with the names of all members, in the same order, the index of a name
+/// being the rank of its endpoint. Collective operations then go through a collective group
+/// joined over all members, and point to point messages through tagged messages.
+///
+/// Operations block until they complete, so their buffers are plain slices, copied through
+/// buffers owned by the communicator. No memory descriptors are passed, which rules out
+/// providers requiring [`MrMode::LOCAL`](crate::MrMode::LOCAL). Should the completion queue
+/// fail to be read, the operations posted are canceled and waited for before failing, and if
+/// the queue still fails to be read, the endpoint keeps their buffers until it is closed.
+///
+/// ```no_run
+/// use libfabric::{AtomicOp, Communicator};
+/// # fn run(
+/// #     ep: libfabric::Endpoint,
+/// #     cq: libfabric::CompletionQueue,
+/// #     eq: libfabric::EventQueue,
+/// #     av: libfabric::AddressVector,
+/// #     names: Vec<libfabric::EndpointAddress>,
+/// #     rank: usize,
+/// # ) -> libfabric::Result<()> {
+/// let mut comm = Communicator::join(ep, cq, &eq, &av, &names, rank)?;
+/// let mut sum = [0u64];
+/// comm.allreduce(&[comm.rank() as u64], &mut sum, AtomicOp::Sum)?;
+/// comm.barrier()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Communicator {
+    mc: Multicast,
+    ep: Endpoint,
+    cq: CompletionQueue,
+    av: AddressVector,
+    peers: Vec<Addr>,
+    rank: usize,
+    // The context of the next operation. Each operation takes its own, so that the completions
+    // of those orphaned after a failure are not mistaken for those of later ones.
+    next_context: usize,
+}
+
+impl Communicator {
+    /// Join the group of the endpoints named by `names`, as the member of rank `rank`.
+    ///
+    /// `ep` must be enabled, opened with [`Caps::COLLECTIVE`](crate::Caps::COLLECTIVE) and
+    /// [`Caps::TAGGED`](crate::Caps::TAGGED), with `cq` bound for transmits and receives, and
+    /// `eq` and `av` bound. This blocks until every member joined.
+    pub fn join(
+        ep: Endpoint,
+        cq: CompletionQueue,
+        eq: &EventQueue,
+        av: &AddressVector,
+        names: &[EndpointAddress],
+        rank: usize,
+    ) -> Result<Self> {
+        if rank >= names.len() {
+            return Err(Error::invalid(format!(
+                "rank {rank} is out of a group of {}",
+                names.len()
+            )));
+        }
+        let peers = names
+            .iter()
+            .map(|name| av.insert(name))
+            .collect::<Result<Vec<_>>>()?;
+        let set = av.av_set(&peers)?;
+        let mc = ep.join_collective(&set, JOIN_CONTEXT)?;
+        let comm = Communicator {
+            mc,
+            ep,
+            cq,
+            av: av.clone(),
+            peers,
+            rank,
+            next_context: JOIN_CONTEXT + 1,
+        };
+        let mut done = Vec::new();
+        loop {
+            match eq.read() {
+                Ok(Some(EqEvent::JoinComplete { fid, .. })) if fid == comm.mc.id() => break,
+                Ok(Some(_)) => {}
+                Ok(None) => {
+                    // Providers may only progress the join along with the completion queue.
+                    comm.progress(&mut done)?;
+                    thread::yield_now();
+                }
+                Err(err) if err.is_avail() => {
+                    return Err(eq.read_err()?.map_or(err, |entry| entry.error));
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(comm)
+    }
+
+    /// The rank of this member, from 0 to [`size()`](Self::size).
+    pub fn rank(&self) -> usize {
+        self.rank
+    }
+
+    pub fn size(&self) -> usize {
+        self.peers.len()
+    }
+
+    /// The address of the member `rank`.
+    pub fn addr(&self, rank: usize) -> Result<Addr> {
+        self.peers.get(rank).copied().ok_or_else(|| {
+            Error::invalid(format!("rank {rank} is out of a group of {}", self.size()))
+        })
+    }
+
+    pub fn endpoint(&self) -> &Endpoint {
+        &self.ep
+    }
+
+    /// A set of the members of `ranks`, to join a collective subgroup over with
+    /// [`Endpoint::join_collective()`], ex: the members of the node of this one with
+    /// [`RankSet::node_local()`]. Fails with an invalid argument error if a rank is out of the
+    /// group.
+    pub fn av_set(&self, ranks: &RankSet) -> Result<AvSet> {
+        self.av.av_set_ranks(&self.peers, ranks)
+    }
+
+    /// The collective group of all members.
+    pub fn group(&self) -> &Multicast {
+        &self.mc
+    }
+
+    /// Wait for every member to reach the barrier.
+    pub fn barrier(&mut self) -> Result<()> {
+        let coll_addr = self.mc.addr();
+        // SAFETY: no buffer is involved.
+        self.run((), |ep, (), context| unsafe {
+            ep.barrier(coll_addr, context)
+        })
+        .map(|_| ())
+    }
+
+    /// Overwrite `buf` on every member with the `buf` of the member `root`.
+    pub fn broadcast<T: AtomicDatatype + Send + 'static>(
+        &mut self,
+        buf: &mut [T],
+        root: usize,
+    ) -> Result<()> {
+        let (coll_addr, root) = (self.mc.addr(), self.addr(root)?);
+        // SAFETY: the buffer is owned until the operation completed, see `run()`.
+        let (_, staged) = self.run(Box::<[T]>::from(&*buf), |ep, staged, context| unsafe {
+            ep.broadcast(staged, None, coll_addr, root, context)
+        })?;
+        buf.copy_from_slice(&staged);
+        Ok(())
+    }
+
+    /// Combine the `buf` of every member element wise with `op`, into the `result` of every
+    /// member.
+    pub fn allreduce<T: AtomicDatatype + Send + 'static>(
+        &mut self,
+        buf: &[T],
+        result: &mut [T],
+        op: AtomicOp,
+    ) -> Result<()> {
+        if buf.len() != result.len() {
+            return Err(Error::invalid("allreduce buffers differ in length"));
+        }
+        let coll_addr = self.mc.addr();
+        let staged = (Box::<[T]>::from(buf), Box::<[T]>::from(&*result));
+        // SAFETY: both buffers are owned until the operation completed, see `run()`.
+        let (_, (_, staged)) = self.run(staged, |ep, (buf, result), context| unsafe {
+            ep.allreduce(buf, None, result, None, coll_addr, op, context)
+        })?;
+        result.copy_from_slice(&staged);
+        Ok(())
+    }
+
+    /// Combine the `buf` of every member element wise with `op`, returning the outcome.
+    ///
+    /// The reduction is checked with [`Domain::query_allreduce()`](crate::Domain::query_allreduce)
+    /// first, failing with `FI_EOPNOTSUPP` if the provider does not support it, and buffers of
+    /// more elements than the provider reduces at once are reduced in chunks. Every member must
+    /// pass the same number of elements.
+    pub fn allreduce_vec<T: CollectiveDatatype + Default + Send + 'static>(
+        &mut self,
+        buf: &[T],
+        op: ReduceOp,
+    ) -> Result<Vec<T>> {
+        let attr = self.ep.domain().query_allreduce::<T>(op)?;
+        if attr.max_members != 0 && self.size() > attr.max_members {
+            return Err(Error::fabric(
+                "fi_query_collective",
+                ffi::FI_EOPNOTSUPP as i64,
+            ));
+        }
+        let chunk = match attr.count {
+            0 => buf.len().max(1),
+            count => count,
+        };
+        let mut result = vec![T::default(); buf.len()];
+        for (buf, result) in buf.chunks(chunk).zip(result.chunks_mut(chunk)) {
+            self.allreduce(buf, result, op.into())?;
+        }
+        Ok(result)
+    }
+
+    /// Send `buf` to the member `dest`, where a [`recv()`](Self::recv) from this rank with the
+    /// same `tag` receives it.
+    pub fn send(&mut self, buf: &[u8], dest: usize, tag: u32) -> Result<()> {
+        let (dest, tag) = (self.addr(dest)?, self.tag(self.rank, tag));
+        // SAFETY: the buffer is owned until the send completed, see `run()`.
+        self.run(Box::<[u8]>::from(buf), |ep, staged, context| unsafe {
+            ep.tsend(staged, None, dest, tag, context)
+        })
+        .map(|_| ())
+    }
+
+    /// Receive a message sent by the member `src` with `tag` into `buf`, returning its length.
+    pub fn recv(&mut self, buf: &mut [u8], src: usize, tag: u32) -> Result<usize> {
+        self.addr(src)?;
+        let tag = self.tag(src, tag);
+        let staged = vec![0; buf.len()].into_boxed_slice();
+        // SAFETY: the buffer is owned until the receive completed, see `run()`.
+        let (len, staged) = self.run(staged, |ep, staged, context| unsafe {
+            ep.trecv(staged, None, Addr::UNSPEC, tag, 0, context)
+        })?;
+        buf[..len].copy_from_slice(&staged[..len]);
+        Ok(len)
+    }
+
+    /// Send `send` to `dest` while receiving from `src` into `recv`, as exchanges between
+    /// members would deadlock with blocking sends on both sides. Returns the length received.
+    pub fn sendrecv(
+        &mut self,
+        send: &[u8],
+        dest: usize,
+        recv: &mut [u8],
+        src: usize,
+        tag: u32,
+    ) -> Result<usize> {
+        let dest = self.addr(dest)?;
+        self.addr(src)?;
+        let (send_tag, recv_tag) = (self.tag(self.rank, tag), self.tag(src, tag));
+        let (send_context, recv_context) = (self.context(), self.context());
+        let mut staged = (
+            Box::<[u8]>::from(send),
+            vec![0; recv.len()].into_boxed_slice(),
+        );
+        let mut done = Vec::new();
+        // SAFETY: both buffers are owned until both operations completed, including when
+        // posting the send fails and the receive is canceled, see `settle()`.
+        unsafe {
+            self.post(&mut done, |ep| {
+                ep.trecv(&mut staged.1, None, Addr::UNSPEC, recv_tag, 0, recv_context)
+            })?;
+            if let Err(err) = self.post(&mut done, |ep| {
+                ep.tsend(&staged.0, None, dest, send_tag, send_context)
+            }) {
+                let _ = self.ep.cancel(recv_context);
+                let _ = self.settle(&mut done, &[recv_context], staged);
+                return Err(err);
+            }
+        }
+        let (lens, (_, staged)) = self.wait(&mut done, &[send_context, recv_context], staged)?;
+        recv[..lens[1]].copy_from_slice(&staged[..lens[1]]);
+        Ok(lens[1])
+    }
+
+    fn tag(&self, rank: usize, tag: u32) -> u64 {
+        TAGS.tag([tag as u64, rank as u64])
+    }
+
+    // A context for a new operation.
+    fn context(&mut self) -> usize {
+        self.next_context += 1;
+        self.next_context - 1
+    }
+
+    // Post one operation over `bufs`, with a new context, and wait for it, returning the
+    // length of its completion along with `bufs`.
+    fn run<B: Send + 'static>(
+        &mut self,
+        mut bufs: B,
+        mut post: impl FnMut(&Endpoint, &mut B, usize) -> Result<()>,
+    ) -> Result<(usize, B)> {
+        let context = self.context();
+        let mut done = Vec::new();
+        self.post(&mut done, |ep| post(ep, &mut bufs, context))?;
+        let (lens, bufs) = self.wait(&mut done, &[context], bufs)?;
+        Ok((lens[0], bufs))
+    }
+
+    // Post an operation, progressing the queue while the endpoint is out of resources.
+    fn post(
+        &self,
+        done: &mut Vec<(usize, Result<usize>)>,
+        mut post: impl FnMut(&Endpoint) -> Result<()>,
+    ) -> Result<()> {
+        loop {
+            match post(&self.ep) {
+                Err(err) if err.is_again() => self.progress(done)?,
+                other => return other,
+            }
+        }
+    }
+
+    // Wait until each of `contexts` completed, returning their lengths in order along with
+    // `bufs`, the buffers of the operations, or the first error. Every operation is waited for
+    // even after one failed, as `bufs` is only released on return.
+    fn wait<B: Send + 'static>(
+        &self,
+        done: &mut Vec<(usize, Result<usize>)>,
+        contexts: &[usize],
+        bufs: B,
+    ) -> Result<(Vec<usize>, B)> {
+        let bufs = self.settle(done, contexts, bufs)?;
+        let lens = contexts
+            .iter()
+            .map(|context| {
+                let at = done.iter().position(|(c, _)| c == context).unwrap();
+                done.swap_remove(at).1
+            })
+            .collect::<Result<_>>()?;
+        Ok((lens, bufs))
+    }
+
+    // Wait until each of `contexts` completed, returning `bufs`. Should the queue fail to be
+    // read, the operations left are canceled and waited for all the same before failing with
+    // its error, and should it still fail to be read, `bufs` is left to the endpoint.
+    fn settle<B: Send + 'static>(
+        &self,
+        done: &mut Vec<(usize, Result<usize>)>,
+        contexts: &[usize],
+        bufs: B,
+    ) -> Result<B> {
+        let pending = |done: &Vec<(usize, Result<usize>)>| -> Vec<usize> {
+            let pending = contexts.iter().copied();
+            pending
+                .filter(|context| done.iter().all(|(c, _)| c != context))
+                .collect()
+        };
+        while !pending(done).is_empty() {
+            let Err(err) = self.progress(done) else {
+                continue;
+            };
+            for context in pending(done) {
+                let _ = self.ep.cancel(context);
+            }
+            while !pending(done).is_empty() {
+                if self.progress(done).is_err() {
+                    self.ep.orphan(bufs);
+                    break;
+                }
+            }
+            return Err(err);
+        }
+        Ok(bufs)
+    }
+
+    // Read the completions available, successful or not.
+    fn progress(&self, done: &mut Vec<(usize, Result<usize>)>) -> Result<()> {
+        let mut completions = [Completion::default(); 8];
+        match self.cq.read(&mut completions) {
+            Ok(n) => done.extend(completions[..n].iter().map(|c| (c.context(), Ok(c.len())))),
+            Err(err) if err.is_again() => {}
+            Err(err) if err.is_avail() => {
+                if let Some(entry) = self.cq.read_err()? {
+                    done.push((entry.context, Err(entry.error)));
+                }
+            }
+            Err(err) => return Err(err),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,326 @@
+use crate::av::Addr;
+use crate::cntr::Counter;
+use crate::ep::Endpoint;
+use crate::error::{Error, Result};
+use crate::flags::OpFlags;
+use crate::mr::MemoryRegion;
+use crate::threading::{ThreadSafe, ThreadingModel};
+use ofi_libfabric_sys::bindgen as ffi;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// How often a [`CountedRma`] notifies the progress of its operations.
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct CountedRmaAttr {
+    every: u64,
+    interval: Duration,
+}
+
+impl Default for CountedRmaAttr {
+    fn default() -> Self {
+        CountedRmaAttr {
+            every: 64,
+            interval: Duration::from_micros(100),
+        }
+    }
+}
+
+impl CountedRmaAttr {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Notify once this many operations completed since the previous notification, 64 by
+    /// default.
+    pub fn every(mut self, ops: u64) -> Self {
+        self.every = ops.max(1);
+        self
+    }
+
+    /// Notify the operations completed once this long passed since the previous
+    /// notification, 100 µs by default, bounding how late a ticket is done.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+}
+
+struct State {
+    issued: u64,
+    // The operations done, and those of them failed, as of the last notification.
+    done: u64,
+    failed: u64,
+    notifications: u64,
+    last: Instant,
+    // The tasks awaiting tickets, by threshold.
+    #[cfg(feature = "async")]
+    wakers: Vec<(u64, std::task::Waker)>,
+}
+
+struct Shared<M: ThreadingModel> {
+    cntr: Counter<M>,
+    attr: CountedRmaAttr,
+    // The counts of the counter when the operations started.
+    base: u64,
+    errors: u64,
+    state: Mutex<State>,
+}
+
+impl<M: ThreadingModel> Shared<M> {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    // Notify the operations completed, if one is due, or `force`d, returning whether it was.
+    fn notify(&self, force: bool) -> bool {
+        let failed = self.cntr.read_err() - self.errors;
+        let done = self.cntr.read() - self.base + failed;
+        let mut state = self.lock();
+        if done == state.done {
+            return false;
+        }
+        let due = force
+            || failed > state.failed
+            || done - state.done >= self.attr.every
+            || done >= state.issued
+            || state.last.elapsed() >= self.attr.interval;
+        if !due {
+            return false;
+        }
+        state.done = done;
+        state.failed = failed;
+        state.notifications += 1;
+        state.last = Instant::now();
+        #[cfg(feature = "async")]
+        state
+            .wakers
+            .retain(|(threshold, waker)| match *threshold <= done {
+                true => {
+                    waker.wake_by_ref();
+                    false
+                }
+                false => true,
+            });
+        true
+    }
+}
+
+/// One-sided operations completing only to a counter, for workloads posting many of them:
+/// they take no room in a completion queue, and the progress of the counter is notified to
+/// the [`RmaTicket`]s of the operations at most every [`CountedRmaAttr::every()`] operations
+/// or [`CountedRmaAttr::interval()`], as well as once none is in flight or one failed, rather
+/// than on each completion.
+///
+/// Notifications come from [`progress()`](Self::progress), to be called from the progress
+/// loop of the application, or a thread of its own, or from a ticket being waited for. With
+/// the `async` feature, tickets are futures, woken by the notification which completes them:
+/// tasks awaiting them are not polled in between.
+///
+/// As for an [`RmaEpoch`](crate::RmaEpoch), the counter must be bound to the endpoint with
+/// [`BindFlags::READ`](crate::BindFlags::READ) and
+/// [`BindFlags::WRITE`](crate::BindFlags::WRITE), and the operations go without a completion
+/// queue entry where it is bound with
+/// [`BindFlags::SELECTIVE_COMPLETION`](crate::BindFlags::SELECTIVE_COMPLETION). Counters
+/// count operations rather than name them, so a ticket fails with `FI_EIO` if any operation
+/// failed while it was in flight.
+///
+/// ```no_run
+/// use libfabric::{Addr, Counter, CountedRma, CountedRmaAttr, Endpoint};
+///
+/// # fn run(ep: Endpoint, cntr: Counter, peer: Addr, key: u64) -> libfabric::Result<()> {
+/// let rma = CountedRma::new(ep, cntr, &CountedRmaAttr::new().every(256));
+/// let pages = vec![0u8; 4096 * 1024];
+/// let mut last = None;
+/// for (i, page) in pages.chunks(4096).enumerate() {
+///     last = Some(unsafe { rma.write(page, None, peer, 4096 * i as u64, key)? });
+///     rma.progress();
+/// }
+/// last.unwrap().wait(None)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct CountedRma<M: ThreadingModel = ThreadSafe> {
+    ep: Endpoint<M>,
+    shared: Arc<Shared<M>>,
+}
+
+impl<M: ThreadingModel> CountedRma<M> {
+    /// Post the operations of `ep`, counted by `cntr`, with the notifications of `attr`.
+    pub fn new(ep: Endpoint<M>, cntr: Counter<M>, attr: &CountedRmaAttr) -> Self {
+        let shared = Shared {
+            base: cntr.read(),
+            errors: cntr.read_err(),
+            cntr,
+            attr: attr.clone(),
+            state: Mutex::new(State {
+                issued: 0,
+                done: 0,
+                failed: 0,
+                notifications: 0,
+                last: Instant::now(),
+                #[cfg(feature = "async")]
+                wakers: Vec::new(),
+            }),
+        };
+        CountedRma {
+            ep,
+            shared: Arc::new(shared),
+        }
+    }
+
+    pub fn endpoint(&self) -> &Endpoint<M> {
+        &self.ep
+    }
+
+    /// Write `buf` to remote memory, completing to the counter only.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation: `buf` must stay valid until the ticket is done.
+    pub unsafe fn write(
+        &self,
+        buf: &[u8],
+        mr: Option<&MemoryRegion<M>>,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+    ) -> Result<RmaTicket<M>> {
+        let mut state = self.shared.lock();
+        unsafe {
+            self.ep
+                .write_with_flags(buf, mr, dest, addr, key, 0, OpFlags::empty())
+        }?;
+        Ok(self.ticket(&mut state))
+    }
+
+    /// Read remote memory into `buf`, completing to the counter only.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation: `buf` must stay valid until the ticket is done.
+    pub unsafe fn read(
+        &self,
+        buf: &mut [u8],
+        mr: Option<&MemoryRegion<M>>,
+        src: Addr,
+        addr: u64,
+        key: u64,
+    ) -> Result<RmaTicket<M>> {
+        let mut state = self.shared.lock();
+        unsafe {
+            self.ep
+                .read_with_flags(buf, mr, src, addr, key, 0, OpFlags::empty())
+        }?;
+        Ok(self.ticket(&mut state))
+    }
+
+    // Posted under the lock, so that operations are numbered in the order they were posted.
+    fn ticket(&self, state: &mut State) -> RmaTicket<M> {
+        state.issued += 1;
+        RmaTicket {
+            shared: self.shared.clone(),
+            threshold: state.issued,
+            failed: self.shared.cntr.read_err() - self.shared.errors,
+        }
+    }
+
+    /// Read the counter, and notify the operations completed if a notification is due.
+    /// Returns whether one was.
+    pub fn progress(&self) -> bool {
+        self.shared.notify(false)
+    }
+
+    /// The operations posted.
+    pub fn issued(&self) -> u64 {
+        self.shared.lock().issued
+    }
+
+    /// The operations done, failed ones included, as of the last notification.
+    pub fn notified(&self) -> u64 {
+        self.shared.lock().done
+    }
+
+    /// The notifications so far.
+    pub fn notifications(&self) -> u64 {
+        self.shared.lock().notifications
+    }
+}
+
+/// The completion of an operation of a [`CountedRma`], done once a notification counts it.
+/// With the `async` feature, the ticket is also a future, woken by that notification.
+pub struct RmaTicket<M: ThreadingModel = ThreadSafe> {
+    shared: Arc<Shared<M>>,
+    // The operations done once this one is, and those failed when it was posted.
+    threshold: u64,
+    failed: u64,
+}
+
+impl<M: ThreadingModel> RmaTicket<M> {
+    /// Whether the operation completed, as of the last notification, failing with `FI_EIO` if
+    /// an operation failed while it was in flight.
+    pub fn is_done(&self) -> Result<bool> {
+        let state = self.shared.lock();
+        self.done(&state).transpose().map(|done| done.is_some())
+    }
+
+    fn done(&self, state: &State) -> Option<Result<()>> {
+        if state.done < self.threshold {
+            return None;
+        }
+        Some(match state.failed > self.failed {
+            true => Err(Error::fabric("fi_cntr_readerr", ffi::FI_EIO as i64)),
+            false => Ok(()),
+        })
+    }
+
+    /// Block until the operation completed, notifying it then, or the timeout expires,
+    /// failing with `FI_ETIMEDOUT`.
+    ///
+    /// Requires a counter opened with [`CntrAttr::blocking()`](crate::CntrAttr::blocking).
+    pub fn wait(&self, timeout: Option<Duration>) -> Result<()> {
+        let shared = &self.shared;
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            if let Some(done) = self.done(&shared.lock()) {
+                return done;
+            }
+            let errors = shared.cntr.read_err() - shared.errors;
+            let threshold = shared.base + self.threshold.saturating_sub(errors);
+            let left = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            match shared.cntr.wait(threshold, left) {
+                Ok(()) => {}
+                // Errors counted since unblock the wait, which goes on for the others.
+                Err(_) if shared.cntr.read_err() - shared.errors > errors => {}
+                Err(err) => return Err(err),
+            }
+            shared.notify(true);
+        }
+    }
+
+    /// The number of the operation, from 1, in the order of the operations posted.
+    pub fn threshold(&self) -> u64 {
+        self.threshold
+    }
+}
+
+#[cfg(feature = "async")]
+impl<M: ThreadingModel> std::future::Future for RmaTicket<M> {
+    type Output = Result<()>;
+
+    fn poll(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<()>> {
+        let mut state = self.shared.lock();
+        if let Some(done) = self.done(&state) {
+            return std::task::Poll::Ready(done);
+        }
+        let waker = cx.waker().clone();
+        match state.wakers.iter_mut().find(|(t, _)| *t == self.threshold) {
+            Some((_, registered)) => *registered = waker,
+            None => state.wakers.push((self.threshold, waker)),
+        }
+        std::task::Poll::Pending
+    }
+}
@@ -0,0 +1,946 @@
+use crate::av::{Addr, AddressVector, EndpointAddress};
+use crate::domain::Domain;
+use crate::error::{Error, Result, check, check_len};
+use crate::fid::{AsRawFid, OwnedFid};
+use crate::peer::PeerCq;
+use crate::resources::{Claim, Resource};
+use crate::threading::{ThreadSafe, ThreadingModel};
+use crate::util::{cstr, timeout_ms};
+use crate::wait::{WaitObj, WaitTarget};
+use ofi_libfabric_sys::bindgen as ffi;
+use std::fmt;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+/// Attributes for opening a completion queue.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[must_use]
+pub struct CqAttr {
+    size: usize,
+    format: CqFormat,
+    blocking: bool,
+    pollable: bool,
+    spin: bool,
+    wait: Option<WaitObj>,
+    signaling_vector: Option<i32>,
+    threshold: Option<usize>,
+    untracked: bool,
+}
+
+impl CqAttr {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Minimum number of entries, 0 lets the provider pick.
+    pub fn size(mut self, size: usize) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// The format of the entries of the queue, and so which [`CqEntry`] it reads.
+    pub fn format(mut self, format: CqFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Open the queue with a wait object, so [`CompletionQueue::sread()`] can block.
+    pub fn blocking(mut self, blocking: bool) -> Self {
+        self.blocking = blocking;
+        self
+    }
+
+    /// Open the queue with a file descriptor wait object, so it can be polled along with other
+    /// descriptors through [`CompletionQueue::wait_fd()`]. This also allows blocking as above.
+    #[cfg(unix)]
+    pub fn pollable(mut self, pollable: bool) -> Self {
+        self.pollable = pollable;
+        self
+    }
+
+    /// Block in [`CompletionQueue::sread()`] by yielding the thread in a loop (`FI_WAIT_YIELD`),
+    /// rather than sleeping on a wait object: lower wakeup latency, for a busy core.
+    pub fn spin(mut self, spin: bool) -> Self {
+        self.spin = spin;
+        self
+    }
+
+    /// Open the queue with `obj` as its wait object, rather than the one picked from
+    /// [`blocking()`](Self::blocking), [`pollable()`](Self::pollable) and
+    /// [`spin()`](Self::spin). Opening fails with an argument error if the provider is known not
+    /// to support it, see [`WaitObj::supported()`].
+    pub fn wait_obj(mut self, obj: WaitObj) -> Self {
+        self.wait = Some(obj);
+        self
+    }
+
+    // The wait object to open the queue with.
+    pub(crate) fn wait_object(&self) -> WaitObj {
+        self.wait
+            .unwrap_or_else(|| WaitObj::pick(self.blocking, self.pollable, self.spin))
+    }
+
+    /// Signal the wait object of the queue through `vector` (`FI_AFFINITY`), ex: the interrupt
+    /// vector of the core the thread reading the queue is pinned to.
+    pub fn signaling_vector(mut self, vector: i32) -> Self {
+        self.signaling_vector = Some(vector);
+        self
+    }
+
+    /// Only wake [`CompletionQueue::sread()`] up once `count` completions are available, or
+    /// on timeout (`FI_CQ_COND_THRESHOLD`), rather than at the first one.
+    pub fn wait_threshold(mut self, count: usize) -> Self {
+        self.threshold = Some(count);
+        self
+    }
+
+    /// Count the operations posted by the endpoints bound to the queue whose completions are
+    /// not read yet, and refuse a post with `FI_EAGAIN` once they would fill the queue, the
+    /// default. A queue the provider cannot write a completion to may drop it, or stall the
+    /// endpoint, which then hangs waiting for it: refused posts are to be retried once
+    /// completions are read, like those the provider has no room for.
+    ///
+    /// Only the queues opened with a [size](Self::size), or whose provider reports the size it
+    /// picked, are tracked, see [`CompletionQueue::capacity()`]. `false` opts out, ex: for a
+    /// provider which queues the completions past the size of the queue.
+    pub fn track_outstanding(mut self, track: bool) -> Self {
+        self.untracked = !track;
+        self
+    }
+}
+
+/// The format of the entries of a completion queue (`enum fi_cq_format`), from the context
+/// alone to everything a tagged receive reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CqFormat {
+    /// [`CtxCompletion`], the context only.
+    Context,
+    /// [`MsgCompletion`], with the flags and length.
+    Msg,
+    /// [`DataCompletion`], with the buffer and remote CQ data as well.
+    Data,
+    /// [`Completion`], with the tag as well, the default.
+    #[default]
+    Tagged,
+}
+
+impl CqFormat {
+    pub(crate) fn as_raw(self) -> ffi::fi_cq_format {
+        match self {
+            CqFormat::Context => ffi::fi_cq_format::FI_CQ_FORMAT_CONTEXT,
+            CqFormat::Msg => ffi::fi_cq_format::FI_CQ_FORMAT_MSG,
+            CqFormat::Data => ffi::fi_cq_format::FI_CQ_FORMAT_DATA,
+            CqFormat::Tagged => ffi::fi_cq_format::FI_CQ_FORMAT_TAGGED,
+        }
+    }
+}
+
+mod sealed {
+    pub trait Sealed {
+        // The completion flags of the entry, none for the context format.
+        #[cfg(feature = "debug-validate")]
+        fn raw_flags(&self) -> u64 {
+            0
+        }
+    }
+
+    impl Sealed for super::CtxCompletion {}
+    impl Sealed for super::MsgCompletion {
+        #[cfg(feature = "debug-validate")]
+        fn raw_flags(&self) -> u64 {
+            self.0.flags
+        }
+    }
+    impl Sealed for super::DataCompletion {
+        #[cfg(feature = "debug-validate")]
+        fn raw_flags(&self) -> u64 {
+            self.0.flags
+        }
+    }
+    impl Sealed for super::Completion {
+        #[cfg(feature = "debug-validate")]
+        fn raw_flags(&self) -> u64 {
+            self.0.flags
+        }
+    }
+}
+
+/// A completion entry of one [`CqFormat`], read from the queues opened with it. Queues of other
+/// formats refuse to read it, since their entries have another layout.
+pub trait CqEntry: sealed::Sealed + Copy + Default + Send + Sync + 'static {
+    /// The format of the queues which read the entry.
+    const FORMAT: CqFormat;
+
+    /// The context given when the operation was posted.
+    fn context(&self) -> usize;
+}
+
+// The accessors of the completion flags, which each format but the context one reports.
+macro_rules! completion_flags {
+    ($entry:ty) => {
+        impl $entry {
+            /// Raw completion flags, e.g. `FI_SEND`, `FI_RECV` or `FI_REMOTE_CQ_DATA`.
+            pub fn flags(&self) -> u64 {
+                self.0.flags
+            }
+
+            /// Number of bytes received.
+            pub fn len(&self) -> usize {
+                self.0.len
+            }
+
+            pub fn is_empty(&self) -> bool {
+                self.0.len == 0
+            }
+
+            /// Whether the operation is a send (`FI_SEND`).
+            pub fn is_send(&self) -> bool {
+                self.has(ffi::FI_SEND as u64)
+            }
+
+            /// Whether the operation is a receive (`FI_RECV`).
+            pub fn is_recv(&self) -> bool {
+                self.has(ffi::FI_RECV as u64)
+            }
+
+            /// Whether the operation is tagged (`FI_TAGGED`).
+            pub fn is_tagged(&self) -> bool {
+                self.has(ffi::FI_TAGGED as u64)
+            }
+
+            /// Whether the operation is a remote memory access (`FI_RMA`), a local read or write,
+            /// or a remote one when the queue reports those.
+            pub fn is_rma(&self) -> bool {
+                self.has(ffi::FI_RMA as u64)
+            }
+
+            /// Whether the operation is an atomic (`FI_ATOMIC`).
+            pub fn is_atomic(&self) -> bool {
+                self.has(ffi::FI_ATOMIC as u64)
+            }
+
+            /// Whether the operation reads remote memory (`FI_READ`).
+            pub fn is_read(&self) -> bool {
+                self.has(ffi::FI_READ as u64)
+            }
+
+            /// Whether the operation writes remote memory (`FI_WRITE`).
+            pub fn is_write(&self) -> bool {
+                self.has(ffi::FI_WRITE as u64)
+            }
+
+            /// Whether a peer read the local memory (`FI_REMOTE_READ`).
+            pub fn is_remote_read(&self) -> bool {
+                self.has(ffi::FI_REMOTE_READ as u64)
+            }
+
+            /// Whether a peer wrote the local memory (`FI_REMOTE_WRITE`).
+            pub fn is_remote_write(&self) -> bool {
+                self.has(ffi::FI_REMOTE_WRITE as u64)
+            }
+
+            /// Whether the provider released the multi-receive buffer of the receive
+            /// (`FI_MULTI_RECV`), this being its last completion.
+            pub fn is_multi_recv(&self) -> bool {
+                self.has(ffi::FI_MULTI_RECV as u64)
+            }
+
+            fn has(&self, flag: u64) -> bool {
+                self.0.flags & flag != 0
+            }
+        }
+    };
+}
+
+/// A completion entry, read in `FI_CQ_FORMAT_CONTEXT` format.
+#[repr(transparent)]
+#[derive(Clone, Copy, Default)]
+pub struct CtxCompletion(ffi::fi_cq_entry);
+
+/// A completion entry, read in `FI_CQ_FORMAT_MSG` format.
+#[repr(transparent)]
+#[derive(Clone, Copy, Default)]
+pub struct MsgCompletion(ffi::fi_cq_msg_entry);
+
+/// A completion entry, read in `FI_CQ_FORMAT_DATA` format.
+#[repr(transparent)]
+#[derive(Clone, Copy, Default)]
+pub struct DataCompletion(ffi::fi_cq_data_entry);
+
+/// A completion entry, read in `FI_CQ_FORMAT_TAGGED` format.
+#[repr(transparent)]
+#[derive(Clone, Copy, Default)]
+pub struct Completion(ffi::fi_cq_tagged_entry);
+
+/// The entries of `FI_CQ_FORMAT_TAGGED`, under the name of their format.
+pub type TaggedCompletion = Completion;
+
+// SAFETY: The context and buffer pointers are only reported back, never dereferenced by the
+// crate.
+unsafe impl Send for CtxCompletion {}
+unsafe impl Sync for CtxCompletion {}
+unsafe impl Send for MsgCompletion {}
+unsafe impl Sync for MsgCompletion {}
+unsafe impl Send for DataCompletion {}
+unsafe impl Sync for DataCompletion {}
+unsafe impl Send for Completion {}
+unsafe impl Sync for Completion {}
+
+impl CqEntry for CtxCompletion {
+    const FORMAT: CqFormat = CqFormat::Context;
+
+    fn context(&self) -> usize {
+        self.0.op_context as usize
+    }
+}
+
+impl CqEntry for MsgCompletion {
+    const FORMAT: CqFormat = CqFormat::Msg;
+
+    fn context(&self) -> usize {
+        self.0.op_context as usize
+    }
+}
+
+impl CqEntry for DataCompletion {
+    const FORMAT: CqFormat = CqFormat::Data;
+
+    fn context(&self) -> usize {
+        self.0.op_context as usize
+    }
+}
+
+impl CqEntry for Completion {
+    const FORMAT: CqFormat = CqFormat::Tagged;
+
+    fn context(&self) -> usize {
+        self.0.op_context as usize
+    }
+}
+
+impl CtxCompletion {
+    /// The context given when the operation was posted.
+    pub fn context(&self) -> usize {
+        self.0.op_context as usize
+    }
+}
+
+impl MsgCompletion {
+    /// The context given when the operation was posted.
+    pub fn context(&self) -> usize {
+        self.0.op_context as usize
+    }
+}
+
+completion_flags!(MsgCompletion);
+
+impl DataCompletion {
+    /// The context given when the operation was posted.
+    pub fn context(&self) -> usize {
+        self.0.op_context as usize
+    }
+
+    /// Start of the received data, for multi-receive buffers.
+    pub fn buf(&self) -> *mut u8 {
+        self.0.buf.cast()
+    }
+
+    /// Remote CQ data, valid with [`has_data()`](Self::has_data).
+    pub fn data(&self) -> u64 {
+        self.0.data
+    }
+
+    /// Whether the completion carries remote CQ data (`FI_REMOTE_CQ_DATA`).
+    pub fn has_data(&self) -> bool {
+        self.has(ffi::FI_REMOTE_CQ_DATA as u64)
+    }
+}
+
+completion_flags!(DataCompletion);
+
+impl Completion {
+    pub(crate) fn from_raw(raw: ffi::fi_cq_tagged_entry) -> Self {
+        Completion(raw)
+    }
+
+    /// The context given when the operation was posted.
+    pub fn context(&self) -> usize {
+        self.0.op_context as usize
+    }
+
+    pub(crate) fn set_context(&mut self, context: usize) {
+        self.0.op_context = context as *mut _;
+    }
+
+    /// The raw entry, ex: to hand it on in another format.
+    pub fn as_raw(&self) -> &ffi::fi_cq_tagged_entry {
+        &self.0
+    }
+
+    /// Start of the received data, for multi-receive buffers.
+    pub fn buf(&self) -> *mut u8 {
+        self.0.buf.cast()
+    }
+
+    /// Remote CQ data, valid with [`has_data()`](Self::has_data).
+    pub fn data(&self) -> u64 {
+        self.0.data
+    }
+
+    /// Whether the completion carries remote CQ data (`FI_REMOTE_CQ_DATA`).
+    pub fn has_data(&self) -> bool {
+        self.has(ffi::FI_REMOTE_CQ_DATA as u64)
+    }
+
+    /// Tag of a tagged receive.
+    pub fn tag(&self) -> u64 {
+        self.0.tag
+    }
+}
+
+completion_flags!(Completion);
+
+impl fmt::Debug for CtxCompletion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CtxCompletion")
+            .field("context", &self.context())
+            .finish()
+    }
+}
+
+impl fmt::Debug for MsgCompletion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MsgCompletion")
+            .field("context", &self.context())
+            .field("flags", &format_args!("{:#x}", self.flags()))
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl fmt::Debug for DataCompletion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DataCompletion")
+            .field("context", &self.context())
+            .field("flags", &format_args!("{:#x}", self.flags()))
+            .field("len", &self.len())
+            .field("data", &self.data())
+            .finish()
+    }
+}
+
+impl fmt::Debug for Completion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Completion")
+            .field("context", &self.context())
+            .field("flags", &format_args!("{:#x}", self.flags()))
+            .field("len", &self.len())
+            .field("data", &self.data())
+            .field("tag", &self.tag())
+            .finish()
+    }
+}
+
+/// An error completion, read with `fi_cq_readerr()`.
+#[derive(Debug, Clone)]
+pub struct CqErrEntry {
+    pub context: usize,
+    pub flags: u64,
+    pub len: usize,
+    pub data: u64,
+    pub tag: u64,
+    /// Number of bytes that did not fit in a truncated receive.
+    pub olen: usize,
+    pub error: Error,
+    pub prov_errno: i32,
+    /// Provider specific description, from `fi_cq_strerror()`.
+    pub message: String,
+    pub src_addr: Addr,
+    /// The raw address of a sender missing from the address vector, reported with
+    /// `FI_SOURCE_ERR` along with `FI_EADDRNOTAVAIL`.
+    pub src_name: Option<EndpointAddress>,
+}
+
+impl CqErrEntry {
+    pub(crate) fn from_raw(raw: &ffi::fi_cq_err_entry, message: String) -> Self {
+        // With FI_SOURCE_ERR, the error data of an unknown sender is its address.
+        let src_name = (raw.err == ffi::FI_EADDRNOTAVAIL as i32
+            && !raw.err_data.is_null()
+            && raw.err_data_size > 0)
+            .then(|| {
+                let name = unsafe {
+                    std::slice::from_raw_parts(raw.err_data.cast::<u8>(), raw.err_data_size)
+                };
+                EndpointAddress::from_bytes(name)
+            });
+        CqErrEntry {
+            context: raw.op_context as usize,
+            flags: raw.flags,
+            len: raw.len,
+            data: raw.data,
+            tag: raw.tag,
+            olen: raw.olen,
+            error: Error::fabric("fi_cq_read", raw.err as i64),
+            prov_errno: raw.prov_errno,
+            message,
+            src_addr: Addr::from_raw(raw.src_addr),
+            src_name,
+        }
+    }
+
+    /// Whether the completion failed only because its sender is missing from the address
+    /// vector, whose address [`src_name`](Self::src_name) holds.
+    pub fn is_unknown_source(&self) -> bool {
+        self.src_name.is_some() && self.error.code() == ffi::FI_EADDRNOTAVAIL as i32
+    }
+
+    /// Insert the unknown sender of the completion into `av`, then return the completion as it
+    /// would have been reported, along with the handle of its sender, which is now known: how
+    /// servers accept messages from peers they did not hear of beforehand, reading receive
+    /// queues with `FI_SOURCE` and `FI_SOURCE_ERR`.
+    ///
+    /// ```no_run
+    /// # use libfabric::{AddressVector, Completion, CompletionQueue};
+    /// # fn run(cq: &CompletionQueue, av: &AddressVector) -> libfabric::Result<()> {
+    /// let mut entries = [Completion::default(); 1];
+    /// let mut srcs = [libfabric::Addr::UNSPEC; 1];
+    /// let (completion, src) = match cq.read_from(&mut entries, &mut srcs) {
+    ///     Err(err) if err.is_avail() => match cq.read_err()? {
+    ///         Some(entry) if entry.is_unknown_source() => entry.insert_and_retry(av)?,
+    ///         Some(entry) => return Err(entry.error),
+    ///         None => return Ok(()),
+    ///     },
+    ///     read => {
+    ///         read?;
+    ///         (entries[0], srcs[0])
+    ///     }
+    /// };
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn insert_and_retry<M: ThreadingModel>(
+        self,
+        av: &AddressVector<M>,
+    ) -> Result<(Completion, Addr)> {
+        let Some(name) = self.src_name.as_ref().filter(|_| self.is_unknown_source()) else {
+            return Err(Error::invalid(
+                "the completion is not from an unknown source",
+            ));
+        };
+        let src = av.insert(name)?;
+        let completion = Completion::from_raw(ffi::fi_cq_tagged_entry {
+            op_context: self.context as *mut _,
+            flags: self.flags,
+            len: self.len,
+            data: self.data,
+            tag: self.tag,
+            ..Default::default()
+        });
+        Ok((completion, src))
+    }
+}
+
+/// A completion queue (`fid_cq`).
+#[derive(Clone)]
+pub struct CompletionQueue<M: ThreadingModel = ThreadSafe> {
+    inner: Arc<CqInner<M>>,
+}
+
+struct CqInner<M: ThreadingModel> {
+    fid: OwnedFid<ffi::fid_cq>,
+    domain: Domain<M>,
+    format: CqFormat,
+    wait: WaitObj,
+    // The completions sread() waits for, with FI_CQ_COND_THRESHOLD.
+    threshold: Option<usize>,
+    // The owner of a peer queue, kept alive until the queue is closed.
+    #[allow(dead_code)]
+    owner: Option<PeerCq>,
+    // The size the queue was opened with, 0 for that of the provider.
+    #[cfg(feature = "metrics")]
+    size: usize,
+    #[cfg(feature = "metrics")]
+    stats: crate::metrics::CqStats,
+    room: Arc<Room>,
+    #[allow(dead_code)]
+    claim: Claim,
+}
+
+// The room left for completions in a queue, shared with the endpoints bound to it, which
+// reserve a completion for each operation they post.
+#[derive(Debug, Default)]
+pub(crate) struct Room {
+    // 0 when the queue is not tracked.
+    capacity: usize,
+    outstanding: AtomicUsize,
+    refused: AtomicU64,
+}
+
+impl Room {
+    fn new(capacity: usize) -> Arc<Self> {
+        Arc::new(Room {
+            capacity,
+            ..Default::default()
+        })
+    }
+
+    fn is_tracked(&self) -> bool {
+        self.capacity != 0
+    }
+
+    // Reserve a completion, unless the queue is full.
+    pub(crate) fn reserve(&self) -> bool {
+        let reserved = self
+            .outstanding
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+                (n < self.capacity).then_some(n + 1)
+            })
+            .is_ok();
+        if !reserved {
+            self.refused.fetch_add(1, Ordering::Relaxed);
+        }
+        reserved
+    }
+
+    // Release the completions read, or those of operations which failed to post. Operations
+    // posted elsewhere, or which complete more than once like multi-receives, are not counted.
+    pub(crate) fn release(&self, count: usize) {
+        if count != 0 && self.is_tracked() {
+            let _ = self
+                .outstanding
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+                    Some(n.saturating_sub(count))
+                });
+        }
+    }
+}
+
+// The queues an endpoint reserves the completions of its operations in, set as they are bound.
+#[derive(Clone, Default)]
+pub(crate) struct Rooms {
+    pub(crate) tx: OnceLock<Arc<Room>>,
+    pub(crate) rx: OnceLock<Arc<Room>>,
+}
+
+impl<M: ThreadingModel> CompletionQueue<M> {
+    // The handles to the queue, this one included.
+    pub(crate) fn handles(&self) -> usize {
+        Arc::strong_count(&self.inner)
+    }
+
+    // Close the queue, via `fi_close()`, failing unless this is the last handle to it.
+    pub(crate) fn close(self) -> Result<()> {
+        crate::fid::close_last(self.inner, |inner| &mut inner.fid)
+    }
+
+    // Open a queue, writing its completions to `owner` instead when given (FI_PEER).
+    pub(crate) fn open(domain: &Domain<M>, attr: &CqAttr, owner: Option<&PeerCq>) -> Result<Self> {
+        let wait = attr.wait_object();
+        let mut raw = ffi::fi_cq_attr {
+            size: attr.size,
+            flags: owner.map_or(0, |_| ffi::FI_PEER)
+                | attr.signaling_vector.map_or(0, |_| ffi::FI_AFFINITY as u64),
+            format: attr.format.as_raw(),
+            wait_obj: wait.check(domain.info().provider_name(), WaitTarget::Cq)?,
+            signaling_vector: attr.signaling_vector.unwrap_or(0),
+            wait_cond: match attr.threshold {
+                Some(_) => ffi::fi_cq_wait_cond::FI_CQ_COND_THRESHOLD,
+                None => ffi::fi_cq_wait_cond::FI_CQ_COND_NONE,
+            },
+            ..Default::default()
+        };
+        let mut context = owner.map(|owner| ffi::fi_peer_cq_context {
+            size: mem::size_of::<ffi::fi_peer_cq_context>(),
+            cq: owner.as_raw(),
+        });
+        let context = context
+            .as_mut()
+            .map_or(ptr::null_mut(), |c| ptr::from_mut(c).cast());
+        let claim = domain.claim(&[Resource::Cq])?;
+        let fid = OwnedFid::open("fi_cq_open", |cq| unsafe {
+            ffi::fi_cq_open(domain.as_raw(), &mut raw, cq, context)
+        })?;
+        // The completions of a peer queue are read from its owner.
+        let tracked = !attr.untracked && owner.is_none();
+        Ok(CompletionQueue {
+            inner: Arc::new(CqInner {
+                fid,
+                domain: domain.clone(),
+                format: attr.format,
+                wait: WaitObj::from_raw(raw.wait_obj).unwrap_or(wait),
+                threshold: attr.threshold,
+                owner: owner.cloned(),
+                #[cfg(feature = "metrics")]
+                size: attr.size,
+                #[cfg(feature = "metrics")]
+                stats: Default::default(),
+                // The provider may write back the size it picked for 0.
+                room: Room::new(if tracked { raw.size } else { 0 }),
+                claim,
+            }),
+        })
+    }
+
+    /// Take ownership of a completion queue of `domain` opened elsewhere, ex: by C code, with
+    /// the format, and the threshold if any, of `attr`, which completions are read with. Fails
+    /// with an invalid argument error if `cq` is null.
+    ///
+    /// # Safety
+    ///
+    /// `cq` must be an open queue of `domain`, opened with the format of `attr`, which is
+    /// closed by the returned value only: once all handles to it are dropped, or released by
+    /// [`into_raw()`](Self::into_raw).
+    pub unsafe fn from_raw(
+        domain: &Domain<M>,
+        cq: *mut ffi::fid_cq,
+        attr: &CqAttr,
+    ) -> Result<Self> {
+        let fid = unsafe { OwnedFid::from_raw(cq) }?;
+        Ok(CompletionQueue {
+            inner: Arc::new(CqInner {
+                wait: crate::wait::wait_obj_of(fid.as_fid()),
+                fid,
+                domain: domain.clone(),
+                format: attr.format,
+                threshold: attr.threshold,
+                owner: None,
+                #[cfg(feature = "metrics")]
+                size: attr.size,
+                #[cfg(feature = "metrics")]
+                stats: Default::default(),
+                room: Room::new(if attr.untracked { 0 } else { attr.size }),
+                claim: domain.adopt(&[Resource::Cq]),
+            }),
+        })
+    }
+
+    /// Hand the queue over, ex: to C code, whose `fi_close()` then closes it. Fails unless this
+    /// is the last handle to it, which the endpoints bound to it hold too. The domain is
+    /// released: it must be kept open until the queue is closed.
+    pub fn into_raw(self) -> Result<*mut ffi::fid_cq> {
+        crate::fid::release_last(self.inner, |inner| &mut inner.fid)
+    }
+
+    pub fn domain(&self) -> &Domain<M> {
+        &self.inner.domain
+    }
+
+    /// The format the queue was opened with.
+    pub fn format(&self) -> CqFormat {
+        self.inner.format
+    }
+
+    /// The wait object of the queue, as the provider resolved [`WaitObj::Unspec`] if it did.
+    pub fn wait_obj(&self) -> WaitObj {
+        self.inner.wait
+    }
+
+    #[cfg(feature = "metrics")]
+    pub(crate) fn size(&self) -> usize {
+        self.inner.size
+    }
+
+    /// The completions the operations posted to the queue are refused past, its size, or 0 if
+    /// it is not tracked, see [`CqAttr::track_outstanding()`].
+    pub fn capacity(&self) -> usize {
+        self.inner.room.capacity
+    }
+
+    /// The operations posted by the endpoints bound to the queue whose completions are not
+    /// read yet, 0 if it is not tracked.
+    pub fn outstanding(&self) -> usize {
+        self.inner.room.outstanding.load(Ordering::Relaxed)
+    }
+
+    /// The posts refused with `FI_EAGAIN` as the queue was full, since it was opened.
+    pub fn refused(&self) -> u64 {
+        self.inner.room.refused.load(Ordering::Relaxed)
+    }
+
+    // The room of the queue, for the endpoints bound to it to reserve completions in.
+    pub(crate) fn room(&self) -> Option<Arc<Room>> {
+        let room = &self.inner.room;
+        room.is_tracked().then(|| room.clone())
+    }
+
+    #[cfg(feature = "metrics")]
+    pub(crate) fn stats(&self) -> &crate::metrics::CqStats {
+        &self.inner.stats
+    }
+
+    // Refuse entries of another layout than those the queue writes.
+    fn check_format<E: CqEntry>(&self) -> Result<()> {
+        if E::FORMAT == self.inner.format {
+            return Ok(());
+        }
+        Err(Error::invalid(format!(
+            "completion queue has format {:?}, not {:?}",
+            self.inner.format,
+            E::FORMAT
+        )))
+    }
+
+    // Treat -FI_EAGAIN as "nothing to read".
+    fn entries<E: CqEntry>(&self, op: &'static str, ret: isize, out: &[E]) -> Result<usize> {
+        let count = match check_len(op, ret) {
+            Err(err) if err.is_again() => Ok(0),
+            other => other,
+        };
+        #[cfg(feature = "debug-validate")]
+        for entry in &out[..*count.as_ref().unwrap_or(&0)] {
+            crate::validate::completed(entry.context(), sealed::Sealed::raw_flags(entry));
+        }
+        #[cfg(not(feature = "debug-validate"))]
+        let _ = out;
+        #[cfg(feature = "metrics")]
+        if let Ok(count) = count {
+            let completions = &self.inner.stats.completions;
+            completions.fetch_add(count as u64, std::sync::atomic::Ordering::Relaxed);
+        }
+        if let Ok(count) = count {
+            self.inner.room.release(count);
+        }
+        count
+    }
+
+    /// Read up to `out.len()` completions without blocking, returning how many were read.
+    ///
+    /// Fails with an error for which [`Error::is_avail()`] holds when an error completion is
+    /// pending, which must then be consumed with [`read_err()`](Self::read_err). The entries
+    /// must be of the [format](CqAttr::format) of the queue.
+    pub fn read<E: CqEntry>(&self, out: &mut [E]) -> Result<usize> {
+        self.check_format::<E>()?;
+        let ret = unsafe { ffi::fi_cq_read(self.as_raw(), out.as_mut_ptr().cast(), out.len()) };
+        self.entries("fi_cq_read", ret, out)
+    }
+
+    /// Drive the progress of the provider without reading any completion, via `fi_cq_read()`
+    /// of no entries, as queues of any format allow. A pending error completion is left for
+    /// the next read.
+    pub fn progress(&self) -> Result<()> {
+        let ret = unsafe { ffi::fi_cq_read(self.as_raw(), ptr::null_mut(), 0) };
+        match check_len("fi_cq_read", ret) {
+            Err(err) if err.is_again() || err.is_avail() => Ok(()),
+            other => other.map(|_| ()),
+        }
+    }
+
+    /// Like [`read()`](Self::read), also reporting the source address of each completion.
+    ///
+    /// Requires `FI_SOURCE`, sources which are not in the address vector read as
+    /// [`Addr::NOTAVAIL`].
+    pub fn read_from<E: CqEntry>(&self, out: &mut [E], src: &mut [Addr]) -> Result<usize> {
+        self.check_format::<E>()?;
+        let count = out.len().min(src.len());
+        let ret = unsafe {
+            ffi::fi_cq_readfrom(
+                self.as_raw(),
+                out.as_mut_ptr().cast(),
+                count,
+                src.as_mut_ptr().cast(),
+            )
+        };
+        self.entries("fi_cq_readfrom", ret, out)
+    }
+
+    /// Block until at least one completion is available, or as many as the
+    /// [`CqAttr::wait_threshold()`] of the queue, or the timeout expires.
+    ///
+    /// Requires a queue opened with [`CqAttr::blocking()`] or [`CqAttr::spin()`].
+    pub fn sread<E: CqEntry>(&self, out: &mut [E], timeout: Option<Duration>) -> Result<usize> {
+        self.check_format::<E>()?;
+        let ret = unsafe {
+            ffi::fi_cq_sread(
+                self.as_raw(),
+                out.as_mut_ptr().cast(),
+                out.len(),
+                self.inner
+                    .threshold
+                    .as_ref()
+                    .map_or(ptr::null(), |count| ptr::from_ref(count).cast()),
+                timeout_ms(timeout),
+            )
+        };
+        self.entries("fi_cq_sread", ret, out)
+    }
+
+    /// Read one error completion, if any.
+    pub fn read_err(&self) -> Result<Option<CqErrEntry>> {
+        let mut raw = ffi::fi_cq_err_entry::default();
+        match check_len("fi_cq_readerr", unsafe {
+            ffi::fi_cq_readerr(self.as_raw(), &mut raw, 0)
+        }) {
+            Err(err) if err.is_again() => return Ok(None),
+            Err(err) => return Err(err),
+            Ok(_) => {}
+        }
+        self.inner.room.release(1);
+        #[cfg(feature = "metrics")]
+        self.inner
+            .stats
+            .errors
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let mut buf = [0 as std::os::raw::c_char; 256];
+        let message = unsafe {
+            cstr(ffi::fi_cq_strerror(
+                self.as_raw(),
+                raw.prov_errno,
+                raw.err_data,
+                buf.as_mut_ptr(),
+                buf.len(),
+            ))
+        }
+        .to_owned();
+        #[cfg(feature = "debug-validate")]
+        crate::validate::completed(raw.op_context as usize, raw.flags);
+        Ok(Some(CqErrEntry::from_raw(&raw, message)))
+    }
+
+    /// Wake up a thread blocked in [`sread()`](Self::sread).
+    pub fn signal(&self) -> Result<()> {
+        check("fi_cq_signal", unsafe { ffi::fi_cq_signal(self.as_raw()) })
+    }
+
+    /// The descriptor of a [pollable](CqAttr::pollable) queue, readable once completions may be
+    /// available. Call [`Fabric::trywait()`](crate::Fabric::trywait) before blocking on it.
+    #[cfg(unix)]
+    pub fn wait_fd(&self) -> Result<std::os::fd::RawFd> {
+        crate::wait::wait_fd(self.as_raw_fid(), self.inner.wait)
+    }
+
+    /// The descriptors of a queue opened with [`WaitObj::PollFd`], any of which is readable
+    /// once completions may be available. They change as endpoints are bound to the queue, see
+    /// [`PollFdSet::refresh()`](crate::PollFdSet::refresh).
+    #[cfg(unix)]
+    pub fn wait_pollfd(&self) -> Result<crate::PollFdSet> {
+        crate::PollFdSet::fetch(self.as_raw_fid(), self.inner.wait)
+    }
+
+    /// Wait until the queue may have completions to read, returning `false` if the timeout expired
+    /// first, for queues opened with a [pollable](WaitObj::is_pollable) wait object.
+    /// [`Fabric::trywait()`](crate::Fabric::trywait) is called first: the future is ready at
+    /// once if the queue has completions to read already.
+    ///
+    /// Like the other futures of the `async` feature, it does not depend on a runtime: a
+    /// thread polls the descriptors of the wait object, which are fetched again by each
+    /// call for [`WaitObj::PollFd`], and wakes up the task.
+    #[cfg(all(unix, feature = "async"))]
+    pub async fn readable_async(&self, timeout: Option<Duration>) -> Result<bool> {
+        crate::wait::readable(self.domain().fabric(), self, self.inner.wait, timeout)?.await
+    }
+
+    pub fn as_raw(&self) -> *mut ffi::fid_cq {
+        self.inner.fid.as_ptr()
+    }
+}
+
+impl<M: ThreadingModel> AsRawFid for CompletionQueue<M> {
+    fn as_raw_fid(&self) -> *mut ffi::fid {
+        self.inner.fid.as_fid()
+    }
+}
@@ -0,0 +1,360 @@
+use crate::av::Addr;
+use crate::cq::{Completion, CqErrEntry};
+use crate::error::{Error, Result};
+use crate::transport::{Cq, CqHandler, Transport, poll_cq};
+use std::collections::{HashMap, VecDeque};
+
+// The tags of messages carrying a payload, and of those only granting credits.
+const DATA: u64 = 0;
+const GRANT: u64 = 1;
+
+/// Attributes of a [`FlowControl`], which must be the same on all peers.
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct CreditAttr {
+    window: u32,
+    threshold: Option<u32>,
+    max_size: usize,
+    peers: usize,
+}
+
+impl Default for CreditAttr {
+    fn default() -> Self {
+        CreditAttr {
+            window: 8,
+            threshold: None,
+            max_size: 4096,
+            peers: 1,
+        }
+    }
+}
+
+impl CreditAttr {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Messages a sender may have sent to a peer that the peer has not received yet, 8 by
+    /// default.
+    pub fn window(mut self, credits: u32) -> Self {
+        self.window = credits.max(1);
+        self
+    }
+
+    /// Credits a receiver owes a peer before granting them in a message of their own, rather
+    /// than along with the next message sent to the peer. Half the window by default.
+    pub fn threshold(mut self, credits: u32) -> Self {
+        self.threshold = Some(credits.max(1));
+        self
+    }
+
+    /// Largest payload, 4 KiB by default.
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Peers sending to this endpoint, for which as many windows of receives are posted, 1 by
+    /// default.
+    pub fn peers(mut self, peers: usize) -> Self {
+        self.peers = peers.max(1);
+        self
+    }
+
+    fn threshold_or_default(&self) -> u32 {
+        self.threshold
+            .unwrap_or(self.window.div_ceil(2))
+            .min(self.window)
+    }
+}
+
+/// A message waiting for a credit, or for room to be sent.
+struct Outgoing {
+    dest: Addr,
+    buf: Vec<u8>,
+    // Set once posted: `DATA` or `GRANT`, and the credits granted along with the message,
+    // which are restored should it fail.
+    tag: u64,
+    granted: u32,
+}
+
+impl Outgoing {
+    fn new(dest: Addr, buf: Vec<u8>) -> Self {
+        Outgoing {
+            dest,
+            buf,
+            tag: DATA,
+            granted: 0,
+        }
+    }
+}
+
+/// Credit based flow control of the messages exchanged over an endpoint, such that senders
+/// never overrun the receives of their peers.
+///
+/// Each sender starts with a window of credits towards each peer, and spends one per message
+/// sent to it. Messages sent without credits left are queued, and sent as the peer grants
+/// credits back: one per message the application received with [`recv()`](Self::recv), in the
+/// remote CQ data of the next message sent to the sender, or, once
+/// [`CreditAttr::threshold()`] credits are owed, in an empty message of their own. Receivers
+/// keep a window of receives posted per peer, so the provider always has a buffer for the
+/// messages in flight, instead of failing them with `FI_EAGAIN` or retrying them on RNR.
+///
+/// It runs over any [`Transport`] and [`Cq`], so over an RDM [`Endpoint`] opened with
+/// `Caps::TAGGED | Caps::SOURCE`, or a [`MockEndpoint`](crate::mock::MockEndpoint): messages
+/// are tagged, and their source identifies the peer to grant credits to. Messages from
+/// sources not in the address vector, which cannot be granted credits, are dropped.
+///
+/// [`Endpoint`]: crate::Endpoint
+pub struct FlowControl<T: Transport, C: Cq> {
+    // First, see `CqHandler`.
+    ep: T,
+    cq: C,
+    attr: CreditAttr,
+    // The receive buffers, for messages then for grants.
+    slots: Vec<Box<[u8]>>,
+    data_slots: usize,
+    unposted: Vec<usize>,
+    // Credits left towards each peer, the window for those never sent to.
+    credits: HashMap<Addr, u32>,
+    // Credits owed to each peer, for the messages the application received.
+    owed: HashMap<Addr, u32>,
+    backlog: VecDeque<Outgoing>,
+    inbox: VecDeque<(Addr, Vec<u8>)>,
+    // The buffers of posted sends, by context.
+    sends: HashMap<usize, Outgoing>,
+    next_send: usize,
+    errors: VecDeque<Error>,
+}
+
+impl<T: Transport, C: Cq> FlowControl<T, C> {
+    /// Post the receives of the flow control on `ep`, whose completions are read from `cq`.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Transport`] documentation.
+    pub unsafe fn new(ep: T, cq: C, attr: &CreditAttr) -> Result<Self> {
+        // Grants are sent once `threshold` credits are owed, so at most as many grants as the
+        // window holds thresholds are in flight from each peer.
+        let data_slots = attr.window as usize * attr.peers;
+        let grant_slots = (attr.window / attr.threshold_or_default()) as usize * attr.peers;
+        let slots = (0..data_slots + grant_slots)
+            .map(|slot| match slot < data_slots {
+                true => vec![0u8; attr.max_size].into_boxed_slice(),
+                false => Box::default(),
+            })
+            .collect();
+        let mut flow = FlowControl {
+            ep,
+            cq,
+            attr: attr.clone(),
+            slots,
+            data_slots,
+            unposted: (0..data_slots + grant_slots).rev().collect(),
+            credits: HashMap::new(),
+            owed: HashMap::new(),
+            backlog: VecDeque::new(),
+            inbox: VecDeque::new(),
+            sends: HashMap::new(),
+            next_send: 0,
+            errors: VecDeque::new(),
+        };
+        flow.flush()?;
+        Ok(flow)
+    }
+
+    pub fn endpoint(&self) -> &T {
+        &self.ep
+    }
+
+    /// Send `buf` to `dest` once a credit towards it is left, queuing it until then. Never
+    /// fails with `FI_EAGAIN`: messages the provider has no room for are queued as well.
+    pub fn send(&mut self, dest: Addr, buf: &[u8]) -> Result<()> {
+        if buf.len() > self.attr.max_size {
+            return Err(Error::invalid(format!(
+                "{} bytes message over the {} bytes maximum",
+                buf.len(),
+                self.attr.max_size
+            )));
+        }
+        self.backlog.push_back(Outgoing::new(dest, buf.to_vec()));
+        self.flush()
+    }
+
+    /// The next message received, and its source, granting a credit back for it.
+    pub fn recv(&mut self) -> Result<Option<(Addr, Vec<u8>)>> {
+        if self.inbox.is_empty() {
+            self.poll()?;
+        }
+        let Some((src, buf)) = self.inbox.pop_front() else {
+            return Ok(None);
+        };
+        *self.owed.entry(src).or_default() += 1;
+        self.flush()?;
+        Ok(Some((src, buf)))
+    }
+
+    /// Credits left towards `dest`.
+    pub fn credits(&self, dest: Addr) -> u32 {
+        *self.credits.get(&dest).unwrap_or(&self.attr.window)
+    }
+
+    /// Messages queued, waiting for credits or for room to be sent.
+    pub fn backlog(&self) -> usize {
+        self.backlog.len()
+    }
+
+    /// Read the completions available, take in the messages and credits received, and send
+    /// what the credits allow. Fails with the error of a message which failed to be sent,
+    /// whose credit is restored, and the credits it granted owed to the peer again.
+    pub fn poll(&mut self) -> Result<()> {
+        poll_cq(self)?;
+        self.flush()?;
+        match self.errors.pop_front() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    // Repost the receives, then post the messages the credits allow, and the grants owed,
+    // until the provider runs out of room.
+    fn flush(&mut self) -> Result<()> {
+        while let Some(slot) = self.unposted.pop() {
+            let tag = match slot < self.data_slots {
+                true => DATA,
+                false => GRANT,
+            };
+            // SAFETY: the slot is not read until the receive completes, and outlives the
+            // endpoint, see `new()`.
+            let posted = unsafe {
+                self.ep
+                    .trecv(&mut self.slots[slot], None, Addr::UNSPEC, tag, 0, slot)
+            };
+            match posted {
+                Err(err) if err.is_again() => {
+                    self.unposted.push(slot);
+                    break;
+                }
+                other => other?,
+            }
+        }
+
+        // Messages to a peer without credits wait, without holding those to other peers.
+        let mut waiting = VecDeque::new();
+        let mut result = Ok(());
+        while let Some(out) = self.backlog.pop_front() {
+            if self.credits(out.dest) == 0 || waiting.iter().any(|w: &Outgoing| w.dest == out.dest)
+            {
+                waiting.push_back(out);
+                continue;
+            }
+            let (dest, granted) = (out.dest, self.owed.remove(&out.dest).unwrap_or(0));
+            match self.post(out, DATA, granted) {
+                Ok(None) => {}
+                Ok(Some(out)) => {
+                    self.owed.insert(dest, granted);
+                    waiting.push_back(out);
+                    break;
+                }
+                // The message is dropped, like a lost one.
+                Err(err) => {
+                    self.owed.insert(dest, granted);
+                    result = Err(err);
+                    break;
+                }
+            }
+        }
+        waiting.extend(self.backlog.drain(..));
+        self.backlog = waiting;
+        result?;
+
+        let threshold = self.attr.threshold_or_default();
+        let due: Vec<_> = self
+            .owed
+            .iter()
+            .filter(|(_, owed)| **owed >= threshold)
+            .map(|(dest, owed)| (*dest, *owed))
+            .collect();
+        for (dest, granted) in due {
+            let grant = Outgoing::new(dest, Vec::new());
+            if self.post(grant, GRANT, granted)?.is_some() {
+                break;
+            }
+            self.owed.remove(&dest);
+        }
+        Ok(())
+    }
+
+    // Post a message, spending a credit for those carrying a payload, or hand it back when
+    // the provider has no room for it.
+    fn post(&mut self, mut out: Outgoing, tag: u64, granted: u32) -> Result<Option<Outgoing>> {
+        let context = self.slots.len().wrapping_add(self.next_send);
+        // SAFETY: the buffer is kept in `sends` until the send completes.
+        let posted = unsafe {
+            self.ep
+                .tsenddata(&out.buf, None, granted as u64, out.dest, tag, context)
+        };
+        match posted {
+            Ok(()) => {
+                if tag == DATA {
+                    *self.credits.entry(out.dest).or_insert(self.attr.window) -= 1;
+                }
+                self.next_send = self.next_send.wrapping_add(1);
+                (out.tag, out.granted) = (tag, granted);
+                self.sends.insert(context, out);
+                Ok(None)
+            }
+            Err(err) if err.is_again() => Ok(Some(out)),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl<T: Transport, C: Cq> CqHandler for FlowControl<T, C> {
+    type Cq = C;
+
+    fn cq(&self) -> &C {
+        &self.cq
+    }
+
+    fn complete(&mut self, completion: &Completion, src: Addr) {
+        let slot = completion.context();
+        if slot >= self.slots.len() {
+            self.sends.remove(&slot);
+            return;
+        }
+        self.unposted.push(slot);
+        if src == Addr::NOTAVAIL {
+            return;
+        }
+        let granted = completion.data() as u32;
+        if granted > 0 {
+            *self.credits.entry(src).or_insert(self.attr.window) += granted;
+        }
+        if slot < self.data_slots {
+            let payload = self.slots[slot][..completion.len()].to_vec();
+            self.inbox.push_back((src, payload));
+        }
+    }
+
+    fn failed(&mut self, entry: CqErrEntry) {
+        if entry.context >= self.slots.len() {
+            // The credit a message spent, and those it granted, which the peer never received.
+            if let Some(out) = self.sends.remove(&entry.context) {
+                if out.tag == DATA {
+                    *self.credits.entry(out.dest).or_insert(self.attr.window) += 1;
+                }
+                if out.granted > 0 {
+                    *self.owed.entry(out.dest).or_default() += out.granted;
+                }
+                self.errors.push_back(entry.error);
+            }
+            return;
+        }
+        // A truncated receive, whose credit the sender spent all the same.
+        self.unposted.push(entry.context);
+        if entry.context < self.data_slots && entry.src_addr != Addr::NOTAVAIL {
+            *self.owed.entry(entry.src_addr).or_default() += 1;
+        }
+    }
+}
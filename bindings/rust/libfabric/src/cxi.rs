@@ -0,0 +1,358 @@
+//! Knobs specific to the CXI provider of HPE Slingshot: the operations of its extension
+//! header, `fi_cxi_ext.h`, its NIC attributes and authorization keys, and its domain and
+//! endpoint controls.
+//!
+//! Every call fails with `FI_ENOSYS`, or `FI_EINVAL` for the controls, on objects of other
+//! providers. The domain operations are those of `FI_CXI_DOM_OPS_6`, which providers older
+//! than it do not return.
+//!
+//! ```no_run
+//! use libfabric::cxi::{self, AuthKey};
+//! use libfabric::{Caps, EndpointType, Info};
+//!
+//! # fn main() -> libfabric::Result<()> {
+//! // The service and VNI the workload manager granted the job.
+//! let key = AuthKey { svc_id: 5, vni: 1024 };
+//! let entries = Info::new()
+//!     .caps(Caps::TAGGED)
+//!     .ep_type(EndpointType::Rdm)
+//!     .provider("cxi")
+//!     .auth_key(&key.to_bytes())
+//!     .get()?;
+//! if let Some(nic) = cxi::nic_attr(&entries[0]) {
+//!     println!("NIC {:#x}, default VNI {}", nic.addr, nic.default_vni);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::cntr::Counter;
+use crate::domain::Domain;
+use crate::ep::{Endpoint, EndpointState};
+use crate::error::{Error, Result, check};
+use crate::fid::AsRawFid;
+use crate::info::InfoEntry;
+use crate::threading::ThreadSafe;
+use ofi_libfabric_sys::bindgen as ffi;
+use std::marker::PhantomData;
+use std::ptr;
+use std::sync::atomic::AtomicU64;
+use std::time::Duration;
+
+/// The attributes of a CXI NIC, which `fi_getinfo()` reports in the provider attributes of
+/// its `fid_nic`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NicAttr {
+    /// The address of the NIC on the fabric.
+    pub addr: u32,
+    /// The resource group picked for the process, from `SLINGSHOT_SVC_ID` or its credentials.
+    pub default_rgroup_id: u32,
+    /// The VNI of the default resource group.
+    pub default_vni: u32,
+}
+
+/// The CXI attributes of the NIC of `entry`, if it is a CXI entry.
+pub fn nic_attr(entry: &InfoEntry) -> Option<NicAttr> {
+    let nic = unsafe { (*entry.as_raw()).nic.as_ref()? };
+    let attr = unsafe { nic.prov_attr.cast::<ffi::cxip_nic_attr>().as_ref()? };
+    if attr.version != ffi::FI_CXI_NIC_ATTR_VER {
+        return None;
+    }
+    Some(NicAttr {
+        addr: attr.addr,
+        default_rgroup_id: attr.default_rgroup_id,
+        default_vni: attr.default_vni,
+    })
+}
+
+/// A CXI authorization key (`struct cxi_auth_key`): the CXI service of a domain, which sets
+/// its resource limits and traffic classes, and the VNI of its endpoints, which must match
+/// for them to communicate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct AuthKey {
+    pub svc_id: u32,
+    pub vni: u16,
+}
+
+impl AuthKey {
+    /// Encode the key, ex: for [`Info::auth_key()`](crate::Info::auth_key).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let raw = ffi::cxi_auth_key {
+            svc_id: self.svc_id,
+            vni: self.vni,
+        };
+        let raw = (&raw as *const ffi::cxi_auth_key).cast::<u8>();
+        unsafe { std::slice::from_raw_parts(raw, size_of::<ffi::cxi_auth_key>()) }.to_vec()
+    }
+
+    /// The key of `entry`, if it is a CXI entry with one.
+    pub fn of(entry: &InfoEntry) -> Option<Self> {
+        let domain_attr = unsafe { (*entry.as_raw()).domain_attr.as_ref()? };
+        if domain_attr.auth_key_size < size_of::<ffi::cxi_auth_key>() {
+            return None;
+        }
+        let raw = unsafe {
+            domain_attr
+                .auth_key
+                .cast::<ffi::cxi_auth_key>()
+                .read_unaligned()
+        };
+        Some(AuthKey {
+            svc_id: raw.svc_id,
+            vni: raw.vni,
+        })
+    }
+}
+
+/// Where the NIC of a domain is in the dragonfly topology of the fabric.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Topology {
+    pub group_id: u32,
+    pub switch_id: u32,
+    pub port_id: u32,
+}
+
+/// The position of the NIC of `domain`, via the `topology()` domain operation.
+pub fn topology(domain: &Domain) -> Result<Topology> {
+    let topology = dom_ops(domain)?.topology.ok_or_else(unsupported)?;
+    let (mut group_id, mut switch_id, mut port_id) = (0, 0, 0);
+    check("topology", unsafe {
+        topology(
+            domain.as_raw_fid(),
+            &mut group_id,
+            &mut switch_id,
+            &mut port_id,
+        )
+    })?;
+    Ok(Topology {
+        group_id,
+        switch_id,
+        port_id,
+    })
+}
+
+/// The value of the NIC telemetry counter `cntr` (ex: `C_CNTR_...` of the Cassini headers),
+/// and when it was sampled, via the `cntr_read()` domain operation.
+pub fn read_counter(domain: &Domain, cntr: u32) -> Result<(u64, Duration)> {
+    let cntr_read = dom_ops(domain)?.cntr_read.ok_or_else(unsupported)?;
+    let mut value = 0;
+    let mut ts = ffi::timespec::default();
+    check("cntr_read", unsafe {
+        cntr_read(domain.as_raw_fid(), cntr, &mut value, &mut ts)
+    })?;
+    Ok((value, Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)))
+}
+
+/// Let the local operations of the endpoints opened afterwards pass memory descriptors,
+/// rather than have the provider register their buffers, via the `enable_hybrid_mr_desc()`
+/// domain operation.
+pub fn set_hybrid_mr_desc(domain: &Domain, enable: bool) -> Result<()> {
+    let enable_hybrid = dom_ops(domain)?
+        .enable_hybrid_mr_desc
+        .ok_or_else(unsupported)?;
+    check("enable_hybrid_mr_desc", unsafe {
+        enable_hybrid(domain.as_raw_fid(), enable)
+    })
+}
+
+/// The triggered operations the hardware can queue, for all the processes of the CXI service
+/// of `domain`, via the `get_dwq_depth()` domain operation.
+pub fn dwq_depth(domain: &Domain) -> Result<usize> {
+    let get_dwq_depth = dom_ops(domain)?.get_dwq_depth.ok_or_else(unsupported)?;
+    let mut depth = 0;
+    check("get_dwq_depth", unsafe {
+        get_dwq_depth(domain.as_raw_fid(), &mut depth)
+    })?;
+    Ok(depth)
+}
+
+/// The messages `ep` received that no receive matched yet, via the `ep_get_unexp_msgs()`
+/// domain operation.
+pub fn unexpected_messages(ep: &Endpoint) -> Result<usize> {
+    let get_unexp = dom_ops(ep.domain())?
+        .ep_get_unexp_msgs
+        .ok_or_else(unsupported)?;
+    let mut count = 0;
+    let ret = unsafe { get_unexp(ep.as_raw(), ptr::null_mut(), 0, ptr::null_mut(), &mut count) };
+    // Errors come back as negative values.
+    check("ep_get_unexp_msgs", ret as isize as i32)?;
+    Ok(count)
+}
+
+/// Whether memory regions with provider keys use optimized, per-key hardware resources
+/// (`FI_OPT_CXI_GET_OPTIMIZED_MRS`).
+pub fn optimized_mrs(domain: &Domain) -> Result<bool> {
+    let mut enable = false;
+    control(domain, ffi::FI_OPT_CXI_GET_OPTIMIZED_MRS, &mut enable)?;
+    Ok(enable)
+}
+
+/// Set [`optimized_mrs()`], before memory is registered.
+pub fn set_optimized_mrs(domain: &Domain, mut enable: bool) -> Result<()> {
+    control(domain, ffi::FI_OPT_CXI_SET_OPTIMIZED_MRS, &mut enable)
+}
+
+/// Whether remote accesses to memory regions generate match events, which counting their
+/// accesses requires (`FI_OPT_CXI_GET_MR_MATCH_EVENTS`).
+pub fn mr_match_events(domain: &Domain) -> Result<bool> {
+    let mut enable = false;
+    control(domain, ffi::FI_OPT_CXI_GET_MR_MATCH_EVENTS, &mut enable)?;
+    Ok(enable)
+}
+
+/// Set [`mr_match_events()`], before memory is registered.
+pub fn set_mr_match_events(domain: &Domain, mut enable: bool) -> Result<()> {
+    control(domain, ffi::FI_OPT_CXI_SET_MR_MATCH_EVENTS, &mut enable)
+}
+
+/// Whether provider keys are cached along with the registrations
+/// (`FI_OPT_CXI_GET_PROV_KEY_CACHE`).
+pub fn prov_key_cache(domain: &Domain) -> Result<bool> {
+    let mut enable = false;
+    control(domain, ffi::FI_OPT_CXI_GET_PROV_KEY_CACHE, &mut enable)?;
+    Ok(enable)
+}
+
+/// Set [`prov_key_cache()`].
+pub fn set_prov_key_cache(domain: &Domain, mut enable: bool) -> Result<()> {
+    control(domain, ffi::FI_OPT_CXI_SET_PROV_KEY_CACHE, &mut enable)
+}
+
+/// Set the traffic class of the sends of `ep`, ex: `FI_TC_LOW_LATENCY`
+/// (`FI_OPT_CXI_SET_TCLASS`).
+pub fn set_tclass<S: EndpointState>(ep: &Endpoint<ThreadSafe, S>, mut tclass: u32) -> Result<()> {
+    set_val(ep, ffi::FI_OPT_CXI_SET_TCLASS, &mut tclass)
+}
+
+/// Set the ordering of the messages of `ep`, a combination of `FI_ORDER_*` flags
+/// (`FI_OPT_CXI_SET_MSG_ORDER`).
+pub fn set_msg_order<S: EndpointState>(ep: &Endpoint<ThreadSafe, S>, mut order: u64) -> Result<()> {
+    set_val(ep, ffi::FI_OPT_CXI_SET_MSG_ORDER, &mut order)
+}
+
+/// Set how long the sends of `ep` are retried while the peer has no receive for them, on
+/// endpoints of the `FI_PROTO_CXI_RNR` protocol (`FI_OPT_CXI_SET_RNR_MAX_RETRY_TIME`).
+pub fn set_rnr_max_retry_time<S: EndpointState>(
+    ep: &Endpoint<ThreadSafe, S>,
+    time: Duration,
+) -> Result<()> {
+    let mut us = time.as_micros() as u64;
+    set_val(ep, ffi::FI_OPT_CXI_SET_RNR_MAX_RETRY_TIME, &mut us)
+}
+
+/// Have the NIC write the value of `cntr` to `buf` as it changes, via the `set_wb_buffer()`
+/// counter operation. [`writeback_value()`] decodes what `buf` holds.
+///
+/// # Safety
+///
+/// `buf` must outlive the counter, whose last handle may be one held elsewhere.
+pub unsafe fn set_writeback_buffer(cntr: &Counter, buf: &AtomicU64) -> Result<()> {
+    let ops = cntr.open_ops::<ffi::fi_cxi_cntr_ops>()?;
+    let set_wb_buffer = ops.set_wb_buffer.ok_or_else(unsupported)?;
+    check("set_wb_buffer", unsafe {
+        set_wb_buffer(
+            cntr.as_raw_fid(),
+            buf.as_ptr().cast(),
+            size_of::<AtomicU64>(),
+        )
+    })
+}
+
+/// The successes and failures counted in a value written back by the NIC, see
+/// [`set_writeback_buffer()`].
+pub fn writeback_value(value: u64) -> (u64, u64) {
+    (
+        value & ffi::FI_CXI_CNTR_SUCCESS_MAX,
+        (value >> 48) & ffi::FI_CXI_CNTR_FAILURE_MAX as u64,
+    )
+}
+
+/// The MMIO registers of a counter, which update it from the CPU without a call into the
+/// provider.
+pub struct CounterMmio<'a> {
+    regs: *mut u64,
+    _cntr: PhantomData<&'a Counter>,
+}
+
+impl<'a> CounterMmio<'a> {
+    /// The registers of `cntr`, via the `get_mmio_addr()` counter operation.
+    pub fn new(cntr: &'a Counter) -> Result<Self> {
+        let ops = cntr.open_ops::<ffi::fi_cxi_cntr_ops>()?;
+        let get_mmio_addr = ops.get_mmio_addr.ok_or_else(unsupported)?;
+        let (mut addr, mut len) = (ptr::null_mut(), 0);
+        check("get_mmio_addr", unsafe {
+            get_mmio_addr(cntr.as_raw_fid(), &mut addr, &mut len)
+        })?;
+        // The success, failure and their reset registers, 8 words apart.
+        if addr.is_null() || len < 25 * size_of::<u64>() {
+            return Err(unsupported());
+        }
+        Ok(CounterMmio {
+            regs: addr.cast(),
+            _cntr: PhantomData,
+        })
+    }
+
+    /// Add `value` to the successes, up to `FI_CXI_CNTR_SUCCESS_MAX`.
+    pub fn add(&self, value: u64) -> Result<()> {
+        self.write(0, value, ffi::FI_CXI_CNTR_SUCCESS_MAX)
+    }
+
+    /// Add `value` to the failures, up to `FI_CXI_CNTR_FAILURE_MAX`.
+    pub fn add_err(&self, value: u64) -> Result<()> {
+        self.write(8, value, ffi::FI_CXI_CNTR_FAILURE_MAX as u64)
+    }
+
+    /// Reset the successes to 0, the only value the registers set.
+    pub fn reset(&self) -> Result<()> {
+        self.write(16, 0, 0)
+    }
+
+    /// Reset the failures to 0.
+    pub fn reset_err(&self) -> Result<()> {
+        self.write(24, 0, 0)
+    }
+
+    fn write(&self, reg: usize, value: u64, max: u64) -> Result<()> {
+        if value > max {
+            return Err(Error::invalid(format!("counter value {value} over {max}")));
+        }
+        // SAFETY: the registers are mapped while the counter is open, see `new()`.
+        unsafe { self.regs.add(reg).write_volatile(value) };
+        Ok(())
+    }
+}
+
+fn dom_ops(domain: &Domain) -> Result<&ffi::fi_cxi_dom_ops> {
+    domain.open_ops::<ffi::fi_cxi_dom_ops>()
+}
+
+// A CXI domain control, through `fi_control()`.
+fn control<T>(domain: &Domain, command: i32, arg: &mut T) -> Result<()> {
+    check("fi_control", unsafe {
+        ffi::fi_control(domain.as_raw_fid(), command, (arg as *mut T).cast())
+    })
+}
+
+// A CXI endpoint variable, through `fi_control(FI_SET_VAL)`.
+fn set_val<S: EndpointState, T>(
+    ep: &Endpoint<ThreadSafe, S>,
+    name: i32,
+    val: &mut T,
+) -> Result<()> {
+    let mut var = ffi::fi_fid_var {
+        name,
+        val: (val as *mut T).cast(),
+    };
+    check("fi_control", unsafe {
+        ffi::fi_control(
+            ep.as_raw_fid(),
+            ffi::FI_SET_VAL as i32,
+            (&mut var as *mut ffi::fi_fid_var).cast(),
+        )
+    })
+}
+
+fn unsupported() -> Error {
+    Error::fabric("fi_open_ops", ffi::FI_ENOSYS as i64)
+}
@@ -0,0 +1,363 @@
+use crate::av::Addr;
+use crate::cq::{Completion, CqErrEntry};
+use crate::error::{Error, Result};
+use crate::transport::Cq;
+use ofi_libfabric_sys::bindgen as ffi;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, MutexGuard};
+
+// The id of the endpoint of an operation is kept in the top bits of its context.
+const ID_BITS: u32 = 16;
+const ID_SHIFT: u32 = usize::BITS - ID_BITS;
+const CONTEXT_MASK: usize = (1 << ID_SHIFT) - 1;
+
+/// Attributes of a [`SharedCq`].
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct SharedCqAttr {
+    batch: usize,
+    quota: usize,
+}
+
+impl Default for SharedCqAttr {
+    fn default() -> Self {
+        SharedCqAttr {
+            batch: 64,
+            quota: 16,
+        }
+    }
+}
+
+impl SharedCqAttr {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The completions read from the queue at once, then queued to their endpoints, by
+    /// default 64.
+    pub fn batch(mut self, batch: usize) -> Self {
+        self.batch = batch.max(1);
+        self
+    }
+
+    /// The most entries handed to one endpoint in each round of [`SharedCq::poll()`], or
+    /// returned by one read of a [`DemuxCq`], by default 16, so that a busy endpoint does not
+    /// hold the others up.
+    pub fn quota(mut self, quota: usize) -> Self {
+        self.quota = quota.max(1);
+        self
+    }
+}
+
+/// An entry of a [`SharedCq`], given back to its endpoint.
+#[derive(Debug, Clone)]
+pub enum Demuxed {
+    /// A completion, with its context untagged, and the source address when reported.
+    Completion { completion: Completion, src: Addr },
+    /// An error completion, with its context untagged.
+    Error(CqErrEntry),
+}
+
+#[derive(Default)]
+struct Backlog {
+    completions: VecDeque<(Completion, Addr)>,
+    errors: VecDeque<CqErrEntry>,
+}
+
+impl Backlog {
+    fn is_empty(&self) -> bool {
+        self.completions.is_empty() && self.errors.is_empty()
+    }
+}
+
+struct State {
+    backlogs: HashMap<u16, Backlog>,
+    next_id: u16,
+    // Ids of detached endpoints, reused oldest first, once all others were given out.
+    free: VecDeque<u16>,
+    // The entries of no attached endpoint.
+    strays: VecDeque<Demuxed>,
+    // The endpoint the next round of poll() starts from.
+    cursor: u16,
+}
+
+/// A completion queue bound by many endpoints, its completions demultiplexed back to the
+/// [`DemuxCq`] of the endpoint which posted the operation, as servers with many connections
+/// share a queue rather than poll one per connection.
+///
+/// Each endpoint attached gets an id, which it tags the contexts of its operations with
+/// through [`DemuxCq::tag()`]. Reading the completions of any endpoint reads a batch of the
+/// queue, and queues the completions and error completions of the others to them, the tag
+/// removed from their context, so that each endpoint reads as from a queue of its own: a
+/// [`DemuxCq`] is a [`Cq`], passed wherever one is. Completions of contexts left untagged, or
+/// of endpoints detached since, are kept apart, for [`take_strays()`](Self::take_strays).
+///
+/// [`poll()`](Self::poll) instead hands the completions of all endpoints to a dispatcher, in
+/// rounds over them which start from the next endpoint each time, each endpoint getting up to
+/// the [quota](SharedCqAttr::quota) of a round.
+///
+/// ```no_run
+/// use libfabric::{Addr, CompletionQueue, Cq, Demuxed, Endpoint, SharedCq, SharedCqAttr};
+///
+/// # fn run(cq: CompletionQueue, ep: Endpoint, peer: Addr) -> libfabric::Result<()> {
+/// let cq = SharedCq::with_attr(cq, SharedCqAttr::new().quota(8));
+/// let conn = cq.attach()?;
+/// let mut buf = [0u8; 64];
+/// unsafe { ep.recv(&mut buf, None, peer, conn.tag(7))? };
+/// cq.poll(|id, entry| match entry {
+///     Demuxed::Completion { completion, .. } => {
+///         assert_eq!((id, completion.context()), (conn.id(), 7));
+///     }
+///     Demuxed::Error(err) => eprintln!("endpoint {id}: {}", err.error),
+/// })?;
+/// # Ok(())
+/// # }
+/// ```
+#[must_use]
+pub struct SharedCq<C: Cq> {
+    cq: C,
+    attr: SharedCqAttr,
+    state: Mutex<State>,
+}
+
+impl<C: Cq> SharedCq<C> {
+    pub fn new(cq: C) -> Self {
+        Self::with_attr(cq, SharedCqAttr::default())
+    }
+
+    pub fn with_attr(cq: C, attr: SharedCqAttr) -> Self {
+        SharedCq {
+            cq,
+            attr,
+            state: Mutex::new(State {
+                backlogs: HashMap::new(),
+                next_id: 1,
+                free: VecDeque::new(),
+                strays: VecDeque::new(),
+                cursor: 0,
+            }),
+        }
+    }
+
+    pub fn get_ref(&self) -> &C {
+        &self.cq
+    }
+
+    /// Attach an endpoint, whose operations are then posted with contexts tagged by the
+    /// returned queue. Fails once 65535 endpoints are attached.
+    ///
+    /// An endpoint is detached when its queue is dropped, after its operations completed or
+    /// were canceled, as its id goes to another endpoint eventually.
+    pub fn attach(&self) -> Result<DemuxCq<'_, C>> {
+        let mut state = self.lock();
+        let id = if state.next_id != 0 {
+            let id = state.next_id;
+            state.next_id = id.wrapping_add(1);
+            id
+        } else {
+            match state.free.pop_front() {
+                Some(id) => id,
+                None => return Err(Error::invalid("shared CQ: too many endpoints attached")),
+            }
+        };
+        state.backlogs.insert(id, Backlog::default());
+        Ok(DemuxCq { shared: self, id })
+    }
+
+    /// How many endpoints are attached.
+    pub fn attached(&self) -> usize {
+        self.lock().backlogs.len()
+    }
+
+    /// Read a batch of the queue, then hand up to the quota of entries of each endpoint to
+    /// `f`, with the id of the endpoint, in a round starting from the endpoint after the one
+    /// the previous round started from. Returns how many were handed, 0 when none.
+    ///
+    /// Entries left over stay queued, for the next round or the reads of their endpoints. `f`
+    /// runs with the queue locked, thus must not call back into it.
+    pub fn poll(&self, mut f: impl FnMut(u16, Demuxed)) -> Result<usize> {
+        let mut state = self.lock();
+        self.pump(&mut state)?;
+        let mut ids: Vec<u16> = (state.backlogs.iter())
+            .filter(|(_, backlog)| !backlog.is_empty())
+            .map(|(&id, _)| id)
+            .collect();
+        ids.sort_unstable();
+        let start = ids.partition_point(|&id| id <= state.cursor);
+        ids.rotate_left(start);
+        if let Some(&first) = ids.first() {
+            state.cursor = first;
+        }
+        let mut handed = 0;
+        for id in ids {
+            let backlog = state.backlogs.get_mut(&id).unwrap();
+            for _ in 0..self.attr.quota {
+                let Some(entry) = next_entry(backlog) else {
+                    break;
+                };
+                f(id, entry);
+                handed += 1;
+            }
+        }
+        Ok(handed)
+    }
+
+    /// The entries of no attached endpoint, oldest first.
+    pub fn take_strays(&self) -> Vec<Demuxed> {
+        self.lock().strays.drain(..).collect()
+    }
+
+    // Read a batch of entries, errors included, and queue them to their endpoints.
+    fn pump(&self, state: &mut State) -> Result<()> {
+        let mut completions = vec![Completion::default(); self.attr.batch];
+        let mut src = vec![Addr::UNSPEC; self.attr.batch];
+        loop {
+            match self.cq.read_from(&mut completions, &mut src) {
+                Ok(n) => {
+                    for (&(mut completion), &src) in completions.iter().zip(&src).take(n) {
+                        let (id, context) = untag(completion.context());
+                        completion.set_context(context);
+                        match state.backlogs.get_mut(&id) {
+                            Some(backlog) => backlog.completions.push_back((completion, src)),
+                            None => state
+                                .strays
+                                .push_back(Demuxed::Completion { completion, src }),
+                        }
+                    }
+                    return Ok(());
+                }
+                Err(err) if err.is_again() => return Ok(()),
+                // The completions behind the errors are read on, once they are queued.
+                Err(err) if err.is_avail() => {
+                    while let Some(mut entry) = self.cq.read_err()? {
+                        let (id, context) = untag(entry.context);
+                        entry.context = context;
+                        match state.backlogs.get_mut(&id) {
+                            Some(backlog) => backlog.errors.push_back(entry),
+                            None => state.strays.push_back(Demuxed::Error(entry)),
+                        }
+                    }
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    // Read the completions of endpoint `id`, reading a batch of the queue first if it has
+    // none queued.
+    fn read_for(
+        &self,
+        id: u16,
+        out: &mut [Completion],
+        mut src: Option<&mut [Addr]>,
+    ) -> Result<usize> {
+        let mut state = self.lock();
+        if state.backlogs[&id].is_empty() {
+            self.pump(&mut state)?;
+        }
+        let backlog = state.backlogs.get_mut(&id).unwrap();
+        let count = src
+            .as_deref()
+            .map_or(out.len(), |src| out.len().min(src.len()))
+            .min(self.attr.quota);
+        let mut n = 0;
+        while n < count {
+            let Some((completion, addr)) = backlog.completions.pop_front() else {
+                break;
+            };
+            out[n] = completion;
+            if let Some(src) = src.as_deref_mut() {
+                src[n] = addr;
+            }
+            n += 1;
+        }
+        match (n, backlog.errors.is_empty()) {
+            (0, false) => Err(Error::fabric("fi_cq_read", ffi::FI_EAVAIL as i64)),
+            (0, true) => Err(Error::fabric("fi_cq_read", ffi::FI_EAGAIN as i64)),
+            _ => Ok(n),
+        }
+    }
+
+    fn read_err_for(&self, id: u16) -> Result<Option<CqErrEntry>> {
+        let mut state = self.lock();
+        if state.backlogs[&id].errors.is_empty() {
+            self.pump(&mut state)?;
+        }
+        Ok(state.backlogs.get_mut(&id).unwrap().errors.pop_front())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+}
+
+// Completions first, as the errors of an endpoint are reported once its completions are read.
+fn next_entry(backlog: &mut Backlog) -> Option<Demuxed> {
+    match backlog.completions.pop_front() {
+        Some((completion, src)) => Some(Demuxed::Completion { completion, src }),
+        None => backlog.errors.pop_front().map(Demuxed::Error),
+    }
+}
+
+fn untag(context: usize) -> (u16, usize) {
+    ((context >> ID_SHIFT) as u16, context & CONTEXT_MASK)
+}
+
+/// The completions of one endpoint of a [`SharedCq`], detaching the endpoint when dropped.
+pub struct DemuxCq<'a, C: Cq> {
+    shared: &'a SharedCq<C>,
+    id: u16,
+}
+
+impl<C: Cq> DemuxCq<'_, C> {
+    /// The id of the endpoint, never 0.
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
+    /// Tag `context` with the id of the endpoint, for the operations it posts. `context` must
+    /// leave the top 16 bits clear, as the addresses of user space do on 64 bit targets, and
+    /// is given back untagged by the completions.
+    pub fn tag(&self, context: usize) -> usize {
+        debug_assert_eq!(
+            context & !CONTEXT_MASK,
+            0,
+            "context {context:#x} too wide to tag"
+        );
+        (self.id as usize) << ID_SHIFT | context & CONTEXT_MASK
+    }
+
+    /// How many completions and error completions are queued to the endpoint.
+    pub fn pending(&self) -> usize {
+        let state = self.shared.lock();
+        let backlog = &state.backlogs[&self.id];
+        backlog.completions.len() + backlog.errors.len()
+    }
+}
+
+impl<C: Cq> Cq for DemuxCq<'_, C> {
+    fn read(&self, out: &mut [Completion]) -> Result<usize> {
+        self.shared.read_for(self.id, out, None)
+    }
+
+    fn read_from(&self, out: &mut [Completion], src: &mut [Addr]) -> Result<usize> {
+        self.shared.read_for(self.id, out, Some(src))
+    }
+
+    fn read_err(&self) -> Result<Option<CqErrEntry>> {
+        self.shared.read_err_for(self.id)
+    }
+}
+
+impl<C: Cq> Drop for DemuxCq<'_, C> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        if let Some(backlog) = state.backlogs.remove(&self.id) {
+            let strays = (backlog.completions.into_iter())
+                .map(|(completion, src)| Demuxed::Completion { completion, src })
+                .chain(backlog.errors.into_iter().map(Demuxed::Error));
+            state.strays.extend(strays);
+        }
+        state.free.push_back(self.id);
+    }
+}
@@ -0,0 +1,349 @@
+use crate::av::{Addr, AddressVector, AvAttr, EndpointAddress};
+use crate::cq::{Completion, CompletionQueue, CqAttr};
+use crate::domain::Domain;
+use crate::ep::Endpoint;
+use crate::error::{Error, Result};
+use crate::flags::BindFlags;
+use crate::info::{EndpointType, InfoEntry};
+use ofi_libfabric_sys::bindgen as ffi;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// A datagram endpoint (`FI_EP_DGRAM`), used like a UDP socket: unreliable and unordered, and
+/// without connections.
+///
+/// Operations block until they complete, so their buffers are plain slices, and may be called
+/// from several threads at once. Datagrams are copied through buffers owned by the endpoint.
+/// If the completion queue fails to be read while an operation is still posted, the endpoint
+/// keeps its buffer until it is closed. Datagrams are up to [`mtu()`](Self::mtu) bytes long,
+/// and their source is only reported by providers supporting
+/// [`Caps::SOURCE`](crate::Caps::SOURCE), as [`Addr::NOTAVAIL`] otherwise.
+///
+/// With the `async` feature, [`send_to_async()`](Self::send_to_async) and
+/// [`recv_from_async()`](Self::recv_from_async) work on owned buffers instead.
+pub struct DgramEndpoint {
+    // Not handed out, so the endpoint is closed, and its operations canceled, before the
+    // buffers of the state below are freed.
+    ep: Endpoint,
+    cq: CompletionQueue,
+    av: AddressVector,
+    mtu: usize,
+    read_timeout: Mutex<Option<Duration>>,
+    next_context: AtomicUsize,
+    state: Mutex<State>,
+}
+
+// The length and source of a datagram sent or received.
+type Outcome = Result<(usize, Addr)>;
+
+// Completions read by one operation on behalf of another, by context.
+#[derive(Default)]
+struct State {
+    done: HashMap<usize, Outcome>,
+    // Buffers of the operations whose future was dropped, or whose wait failed, before they
+    // completed, freed once they do.
+    orphans: HashMap<usize, Box<[u8]>>,
+}
+
+impl DgramEndpoint {
+    /// Open a datagram endpoint from `entry`, along with its completion queue and address
+    /// vector.
+    pub fn open(domain: &Domain, entry: &InfoEntry) -> Result<Self> {
+        if entry.ep_type() != EndpointType::Dgram {
+            return Err(Error::invalid(format!(
+                "{:?} is not a datagram endpoint type",
+                entry.ep_type()
+            )));
+        }
+        let cq = domain.cq(&CqAttr::new())?;
+        let av = domain.av(&AvAttr::new())?;
+        let ep = domain
+            .endpoint(entry)?
+            .bind_cq(&cq, BindFlags::TRANSMIT | BindFlags::RECV)?
+            .bind_av(&av)?
+            .enable()?;
+        Ok(DgramEndpoint {
+            ep,
+            cq,
+            av,
+            mtu: entry.max_msg_size(),
+            read_timeout: Mutex::new(None),
+            next_context: AtomicUsize::new(1),
+            state: Mutex::new(State::default()),
+        })
+    }
+
+    /// The address peers insert to reach this endpoint.
+    pub fn name(&self) -> Result<EndpointAddress> {
+        self.ep.name()
+    }
+
+    /// Resolve the address of a peer, to send to it.
+    pub fn insert(&self, addr: &EndpointAddress) -> Result<Addr> {
+        self.av.insert(addr)
+    }
+
+    /// The largest datagram that may be sent, from the `max_msg_size` of the endpoint.
+    pub fn mtu(&self) -> usize {
+        self.mtu
+    }
+
+    /// How long [`recv_from()`](Self::recv_from) waits for a datagram before failing with
+    /// `FI_ETIMEDOUT`, forever with `None`, the default.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) {
+        *self.read_timeout.lock().unwrap() = timeout;
+    }
+
+    pub fn read_timeout(&self) -> Option<Duration> {
+        *self.read_timeout.lock().unwrap()
+    }
+
+    /// Send `buf` as one datagram to `dest`, returning its length once it left.
+    pub fn send_to(&self, buf: &[u8], dest: Addr) -> Result<usize> {
+        self.check_mtu(buf.len())?;
+        self.run(buf.into(), Some(dest), None)?.1?;
+        Ok(buf.len())
+    }
+
+    /// Receive one datagram into `buf`, returning its length and source.
+    pub fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, Addr)> {
+        let deadline = self.read_timeout().map(|timeout| Instant::now() + timeout);
+        let owned = vec![0; buf.len()].into_boxed_slice();
+        match self.run(owned, None, deadline)? {
+            (owned, Ok((len, src))) => {
+                buf[..len].copy_from_slice(&owned[..len]);
+                Ok((len, src))
+            }
+            (_, Err(err)) if err.code() == ffi::FI_ECANCELED as i32 => {
+                Err(Error::fabric("recv_from", ffi::FI_ETIMEDOUT as i64))
+            }
+            (_, Err(err)) => Err(err),
+        }
+    }
+
+    // Post a send to `dest`, or a receive, over `buf`, and wait for it, canceling it past
+    // `deadline`. Returns the buffer along with the outcome, unless the queue fails to be read
+    // before the operation completed, in which case the buffer is orphaned.
+    fn run(
+        &self,
+        mut buf: Box<[u8]>,
+        dest: Option<Addr>,
+        deadline: Option<Instant>,
+    ) -> Result<(Box<[u8]>, Outcome)> {
+        let context = self.context();
+        // SAFETY: the buffer is kept until the operation completed, here or, once orphaned, by
+        // the endpoint.
+        self.post(|ep| unsafe {
+            match dest {
+                Some(dest) => ep.send(&buf, None, dest, context),
+                None => ep.recv(&mut buf, None, Addr::UNSPEC, context),
+            }
+        })?;
+        let outcome = match self.wait(context, deadline) {
+            Ok(Some(outcome)) => Ok(outcome),
+            // A datagram may still have arrived in the meantime. Should canceling fail, the
+            // receive is waited for regardless, as it still uses the buffer.
+            Ok(None) => {
+                let _ = self.ep.cancel(context);
+                self.wait(context, None)
+                    .map(|outcome| outcome.expect("operations without a deadline complete"))
+            }
+            Err(err) => Err(err),
+        };
+        match outcome {
+            Ok(outcome) => Ok((buf, outcome)),
+            Err(err) => {
+                self.orphan(context, buf);
+                Err(err)
+            }
+        }
+    }
+
+    // Cancel the operation of `context` and keep its buffer until it completes, unless it
+    // already did.
+    fn orphan(&self, context: usize, buf: Box<[u8]>) {
+        let mut state = self.state.lock().unwrap();
+        if state.done.remove(&context).is_none() {
+            let _ = self.ep.cancel(context);
+            state.orphans.insert(context, buf);
+        }
+    }
+
+    fn check_mtu(&self, len: usize) -> Result<()> {
+        match len <= self.mtu {
+            true => Ok(()),
+            false => Err(Error::fabric("send_to", ffi::FI_EMSGSIZE as i64)),
+        }
+    }
+
+    fn context(&self) -> usize {
+        self.next_context.fetch_add(1, Ordering::Relaxed)
+    }
+
+    // Post an operation, progressing the queue while the endpoint is out of resources.
+    fn post(&self, mut post: impl FnMut(&Endpoint) -> Result<()>) -> Result<()> {
+        loop {
+            match post(&self.ep) {
+                Err(err) if err.is_again() => self.progress(&mut self.state.lock().unwrap())?,
+                other => return other,
+            }
+        }
+    }
+
+    // Wait for the completion of `context`, up to `deadline`.
+    fn wait(&self, context: usize, deadline: Option<Instant>) -> Result<Option<Outcome>> {
+        loop {
+            if let Some(outcome) = self.poll(context)? {
+                return Ok(Some(outcome));
+            }
+            if deadline.is_some_and(|deadline| Instant::now() > deadline) {
+                return Ok(None);
+            }
+            std::thread::yield_now();
+        }
+    }
+
+    // The outcome of `context`, if it completed.
+    fn poll(&self, context: usize) -> Result<Option<Outcome>> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(outcome) = state.done.remove(&context) {
+            return Ok(Some(outcome));
+        }
+        self.progress(&mut state)?;
+        Ok(state.done.remove(&context))
+    }
+
+    // Read the completions available, successful or not.
+    fn progress(&self, state: &mut State) -> Result<()> {
+        let mut completions = [Completion::default(); 8];
+        let mut src = [Addr::NOTAVAIL; 8];
+        let mut complete = |context: usize, outcome| {
+            if state.orphans.remove(&context).is_none() {
+                state.done.insert(context, outcome);
+            }
+        };
+        match self.cq.read_from(&mut completions, &mut src) {
+            Ok(n) => {
+                for (completion, &src) in completions[..n].iter().zip(&src) {
+                    complete(completion.context(), Ok((completion.len(), src)));
+                }
+            }
+            Err(err) if err.is_again() => {}
+            Err(err) if err.is_avail() => {
+                if let Some(entry) = self.cq.read_err()? {
+                    complete(entry.context, Err(entry.error));
+                }
+            }
+            Err(err) => return Err(err),
+        }
+        Ok(())
+    }
+}
+
+/// Datagrams over owned buffers, enabled by the `async` feature.
+///
+/// The futures do not depend on a runtime: they poll the completion queue each time they are
+/// polled, and wake themselves up until their operation completed, which keeps the executor
+/// busy while they wait. Dropping a future before it completed cancels its operation.
+#[cfg(feature = "async")]
+impl DgramEndpoint {
+    /// Like [`send_to()`](Self::send_to), returning the buffer once sent.
+    pub async fn send_to_async(&self, buf: Vec<u8>, dest: Addr) -> Result<Vec<u8>> {
+        self.check_mtu(buf.len())?;
+        let (buf, _, _) = Op::new(self, buf, Some(dest)).await?;
+        Ok(buf)
+    }
+
+    /// Receive one datagram into a buffer of [`mtu()`](Self::mtu) bytes, returned truncated to
+    /// its length along with its source.
+    pub async fn recv_from_async(&self) -> Result<(Vec<u8>, Addr)> {
+        let (mut buf, len, src) = Op::new(self, vec![0; self.mtu], None).await?;
+        buf.truncate(len);
+        Ok((buf, src))
+    }
+}
+
+// A send, with a destination, or a receive, posted on first poll.
+#[cfg(feature = "async")]
+struct Op<'a> {
+    dgram: &'a DgramEndpoint,
+    context: usize,
+    dest: Option<Addr>,
+    buf: Option<Box<[u8]>>,
+    posted: bool,
+}
+
+#[cfg(feature = "async")]
+impl<'a> Op<'a> {
+    fn new(dgram: &'a DgramEndpoint, buf: Vec<u8>, dest: Option<Addr>) -> Self {
+        Op {
+            dgram,
+            context: dgram.context(),
+            dest,
+            buf: Some(buf.into_boxed_slice()),
+            posted: false,
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl std::future::Future for Op<'_> {
+    type Output = Result<(Vec<u8>, usize, Addr)>;
+
+    fn poll(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Self::Output> {
+        use std::task::Poll;
+
+        let op = &mut *self;
+        let Some(buf) = op.buf.as_mut() else {
+            panic!("polled after completion");
+        };
+        if !op.posted {
+            // SAFETY: the buffer is kept until the operation completed, by the future or, once
+            // dropped, by the endpoint.
+            let ret = unsafe {
+                match op.dest {
+                    Some(dest) => op.dgram.ep.send(buf, None, dest, op.context),
+                    None => op.dgram.ep.recv(buf, None, Addr::UNSPEC, op.context),
+                }
+            };
+            match ret {
+                Ok(()) => op.posted = true,
+                Err(err) if err.is_again() => {
+                    if let Err(err) = op.dgram.progress(&mut op.dgram.state.lock().unwrap()) {
+                        return Poll::Ready(Err(err));
+                    }
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
+                Err(err) => return Poll::Ready(Err(err)),
+            }
+        }
+        match op.dgram.poll(op.context) {
+            Ok(Some(outcome)) => {
+                let buf = op.buf.take().unwrap().into_vec();
+                Poll::Ready(outcome.map(|(len, src)| (buf, len, src)))
+            }
+            Ok(None) => {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+            // The operation is still pending, and its buffer is handed over on drop.
+            Err(err) => Poll::Ready(Err(err)),
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl Drop for Op<'_> {
+    fn drop(&mut self) {
+        let Some(buf) = self.buf.take() else { return };
+        if self.posted {
+            self.dgram.orphan(self.context, buf);
+        }
+    }
+}
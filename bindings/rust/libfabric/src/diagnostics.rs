@@ -0,0 +1,158 @@
+use crate::attr::Progress;
+use crate::av::AddrFormat;
+use crate::flags::{Caps, Mode, MrMode};
+use crate::info::{EndpointType, InfoEntry, Nic, Version, available_providers};
+use std::fmt;
+
+// The environment variables reported: those of libfabric and its providers, and those of the
+// verbs libraries some of these load.
+const ENV_PREFIXES: &[&str] = &["FI_", "RDMAV_", "IBV_", "MLX5_"];
+
+/// What was found out about an info entry, for a [`Diagnostics`] report.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct EntryDiagnostics {
+    pub provider: String,
+    pub provider_version: Version,
+    pub fabric: String,
+    pub domain: String,
+    pub ep_type: EndpointType,
+    pub caps: Caps,
+    pub mode: Mode,
+    pub mr_mode: MrMode,
+    pub addr_format: AddrFormat,
+    pub max_msg_size: usize,
+    pub inject_size: usize,
+    pub data_progress: Progress,
+    pub nic: Option<Nic>,
+}
+
+impl EntryDiagnostics {
+    fn new(entry: &InfoEntry) -> Self {
+        EntryDiagnostics {
+            provider: entry.provider_name().to_owned(),
+            provider_version: entry.provider_version(),
+            fabric: entry.fabric_name().to_owned(),
+            domain: entry.domain_name().to_owned(),
+            ep_type: entry.ep_type(),
+            caps: entry.caps(),
+            mode: entry.mode(),
+            mr_mode: entry.mr_mode(),
+            addr_format: entry.addr_format(),
+            max_msg_size: entry.max_msg_size(),
+            inject_size: entry.tx_attr().inject_size,
+            data_progress: entry.domain_attr().data_progress,
+            nic: entry.nic(),
+        }
+    }
+}
+
+/// A report of the libfabric a process runs with, from [`diagnostics()`], to attach to bug
+/// reports or to check nodes before running a job on them.
+///
+/// It displays as text, and, with the `serde` feature, serializes to JSON or any other format.
+/// Environment variables are reported as they are set, any secret they hold included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Diagnostics {
+    /// The version of the headers the bindings were built with.
+    pub header_version: Version,
+    /// The version of the library loaded at runtime.
+    pub linked_version: Version,
+    /// The providers available on this node, see [`available_providers()`].
+    pub providers: Vec<String>,
+    /// The entry the application selected, with [`diagnostics_for()`].
+    pub entry: Option<EntryDiagnostics>,
+    /// The `FI_*` environment variables, and those of the verbs libraries, sorted by name.
+    pub env: Vec<(String, String)>,
+    /// What could not be found out, ex: providers failing discovery.
+    pub errors: Vec<String>,
+}
+
+/// Report the versions of libfabric, the providers available and the environment variables
+/// configuring them.
+///
+/// ```no_run
+/// let report = libfabric::diagnostics();
+/// eprintln!("{report}");
+/// ```
+pub fn diagnostics() -> Diagnostics {
+    let mut errors = Vec::new();
+    let providers = available_providers().unwrap_or_else(|err| {
+        errors.push(format!("provider discovery: {err}"));
+        Vec::new()
+    });
+    let mut env: Vec<_> = std::env::vars_os()
+        .filter_map(|(name, value)| Some((name.into_string().ok()?, value)))
+        .filter(|(name, _)| ENV_PREFIXES.iter().any(|prefix| name.starts_with(prefix)))
+        .map(|(name, value)| (name, value.to_string_lossy().into_owned()))
+        .collect();
+    env.sort();
+    Diagnostics {
+        header_version: Version::HEADER,
+        linked_version: Version::linked(),
+        providers,
+        entry: None,
+        env,
+        errors,
+    }
+}
+
+/// Like [`diagnostics()`], also reporting the attributes and NIC of `entry`, ex: the one the
+/// application opened its fabric from.
+pub fn diagnostics_for(entry: &InfoEntry) -> Diagnostics {
+    Diagnostics {
+        entry: Some(EntryDiagnostics::new(entry)),
+        ..diagnostics()
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "libfabric {} (headers {})",
+            self.linked_version, self.header_version
+        )?;
+        writeln!(f, "providers: {}", self.providers.join(", "))?;
+        if let Some(entry) = &self.entry {
+            writeln!(
+                f,
+                "entry: {} {} (fabric {}, domain {}, {:?})",
+                entry.provider, entry.provider_version, entry.fabric, entry.domain, entry.ep_type
+            )?;
+            writeln!(f, "  caps: {:?}", entry.caps)?;
+            writeln!(f, "  mode: {:?}", entry.mode)?;
+            writeln!(f, "  mr_mode: {:?}", entry.mr_mode)?;
+            writeln!(
+                f,
+                "  addr_format: {:?}, max_msg_size: {}, inject_size: {}, data_progress: {:?}",
+                entry.addr_format, entry.max_msg_size, entry.inject_size, entry.data_progress
+            )?;
+            if let Some(nic) = &entry.nic {
+                write!(f, "  nic: {} ({})", nic.name, nic.driver)?;
+                if let Some(pci) = nic.pci {
+                    write!(f, " at {pci}")?;
+                }
+                writeln!(
+                    f,
+                    ", mtu {}, {} bit/s, link {}",
+                    nic.mtu,
+                    nic.speed,
+                    match nic.link_up {
+                        Some(true) => "up",
+                        Some(false) => "down",
+                        None => "unknown",
+                    }
+                )?;
+            }
+        }
+        for (name, value) in &self.env {
+            writeln!(f, "{name}={value}")?;
+        }
+        for error in &self.errors {
+            writeln!(f, "error: {error}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,353 @@
+use crate::av::Addr;
+use crate::cq::Completion;
+use crate::error::Result;
+use crate::transport::Cq;
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Keeps the positions of producers and consumers on cache lines of their own.
+#[repr(align(64))]
+struct Padded<T>(T);
+
+// A slot of the ring: `seq` equals the position it may be written at when free, and that
+// position plus one once written.
+struct Slot<T> {
+    seq: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+struct Shared<T> {
+    slots: Box<[Slot<T>]>,
+    mask: usize,
+    tail: Padded<AtomicUsize>,
+    head: Padded<AtomicUsize>,
+}
+
+// SAFETY: A slot is written by the one producer which claimed its position, then read by the
+// one consumer which claimed it, each handing it over with the release of `seq`.
+unsafe impl<T: Send> Send for Shared<T> {}
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+impl<T: Copy> Shared<T> {
+    fn new(capacity: usize) -> Arc<Self> {
+        let capacity = capacity.max(2).next_power_of_two();
+        let slots = (0..capacity)
+            .map(|seq| Slot {
+                seq: AtomicUsize::new(seq),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            })
+            .collect();
+        Arc::new(Shared {
+            slots,
+            mask: capacity - 1,
+            tail: Padded(AtomicUsize::new(0)),
+            head: Padded(AtomicUsize::new(0)),
+        })
+    }
+
+    fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    fn len(&self) -> usize {
+        let head = self.head.0.load(Ordering::Acquire);
+        let tail = self.tail.0.load(Ordering::Acquire);
+        tail.saturating_sub(head).min(self.capacity())
+    }
+
+    fn push(&self, value: T) -> Result<(), T> {
+        let mut pos = self.tail.0.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos & self.mask];
+            let seq = slot.seq.load(Ordering::Acquire);
+            match seq.wrapping_sub(pos) as isize {
+                0 => match self.tail.0.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        unsafe { (*slot.value.get()).write(value) };
+                        slot.seq.store(pos.wrapping_add(1), Ordering::Release);
+                        return Ok(());
+                    }
+                    Err(current) => pos = current,
+                },
+                // The slot still holds the value of the previous lap.
+                dif if dif < 0 => return Err(value),
+                _ => pos = self.tail.0.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    // Push, waiting for the consumers to make room.
+    fn push_spin(&self, mut value: T) {
+        while let Err(back) = self.push(value) {
+            value = back;
+            std::hint::spin_loop();
+        }
+    }
+
+    fn pop(&self) -> Option<T> {
+        let mut pos = self.head.0.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos & self.mask];
+            let seq = slot.seq.load(Ordering::Acquire);
+            match seq.wrapping_sub(pos.wrapping_add(1)) as isize {
+                0 => match self.head.0.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        let value = unsafe { (*slot.value.get()).assume_init() };
+                        slot.seq
+                            .store(pos.wrapping_add(self.mask + 1), Ordering::Release);
+                        return Some(value);
+                    }
+                    Err(current) => pos = current,
+                },
+                // The slot was not written since the previous lap.
+                dif if dif < 0 => return None,
+                _ => pos = self.head.0.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    fn pop_batch(&self, out: &mut [T]) -> usize {
+        let mut n = 0;
+        while n < out.len() {
+            match self.pop() {
+                Some(value) => out[n] = value,
+                None => break,
+            }
+            n += 1;
+        }
+        n
+    }
+}
+
+/// A bounded lock-free ring of completions, for a completion queue shared by several worker
+/// threads: a poller fills it in batches with [`fill()`](Self::fill), and the workers drain it
+/// with [`pop()`](Self::pop), none of them waiting on a lock held by another.
+///
+/// Any number of threads may push and pop at once, on clones of the ring. Workers find out
+/// which operation completed from the [`context()`](Completion::context) of the completion.
+///
+/// ```no_run
+/// # fn run(cq: libfabric::CompletionQueue) -> libfabric::Result<()> {
+/// use libfabric::{Completion, CompletionRing};
+///
+/// let ring = CompletionRing::new(4096);
+/// for _ in 0..4 {
+///     let ring = ring.clone();
+///     std::thread::spawn(move || loop {
+///         match ring.pop() {
+///             Some(completion) => println!("{:#x} done", completion.context()),
+///             None => std::hint::spin_loop(),
+///         }
+///     });
+/// }
+/// let mut batch = [Completion::default(); 64];
+/// loop {
+///     ring.fill(&cq, &mut batch)?;
+/// }
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct CompletionRing {
+    shared: Arc<Shared<Completion>>,
+}
+
+impl CompletionRing {
+    /// A ring holding `capacity` completions, rounded up to a power of two.
+    pub fn new(capacity: usize) -> Self {
+        CompletionRing {
+            shared: Shared::new(capacity),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity()
+    }
+
+    /// The completions in the ring, which may have changed by the time it returns.
+    pub fn len(&self) -> usize {
+        self.shared.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Add `completion` to the ring, handing it back if the ring is full.
+    pub fn push(&self, completion: Completion) -> Result<(), Completion> {
+        self.shared.push(completion)
+    }
+
+    /// Take the oldest completion out of the ring, if any.
+    pub fn pop(&self) -> Option<Completion> {
+        self.shared.pop()
+    }
+
+    /// Take up to `out.len()` completions out of the ring, returning how many were taken.
+    pub fn pop_batch(&self, out: &mut [Completion]) -> usize {
+        self.shared.pop_batch(out)
+    }
+
+    /// Read a batch of completions from `cq` into the ring, through `batch`, returning how
+    /// many were read. Only as many as the ring has room for are read, none while it is full,
+    /// so that no completion is lost.
+    ///
+    /// An empty queue reads none. Fails like [`Cq::read()`] otherwise, with `FI_EAVAIL` for
+    /// an error completion, which the poller reads with [`Cq::read_err()`].
+    pub fn fill<C: Cq>(&self, cq: &C, batch: &mut [Completion]) -> Result<usize> {
+        let room = batch.len().min(self.capacity() - self.len());
+        if room == 0 {
+            return Ok(0);
+        }
+        let n = match cq.read(&mut batch[..room]) {
+            Err(err) if err.is_again() => return Ok(0),
+            other => other?,
+        };
+        for completion in &batch[..n] {
+            // Other producers may have taken the room in between; the workers free it again.
+            self.shared.push_spin(*completion);
+        }
+        Ok(n)
+    }
+}
+
+// A completion and the peer it was steered by.
+type Steered = (Completion, Addr);
+
+/// Completions steered to shards by peer, for servers whose state is sharded across threads
+/// by peer: a poller fills it with [`fill()`](Self::fill), and the worker of each shard drains
+/// its ring with [`pop()`](Self::pop), seeing every completion of the peers of its shard, in
+/// the order of the queue, without queuing them again between threads.
+///
+/// Completions are steered by their source, as `fi_cq_readfrom()` reports it: the address of
+/// the peer with [`Caps::SOURCE`](crate::Caps::SOURCE), or its ID with
+/// [`AvAttr::user_id()`](crate::AvAttr::user_id), hashed to the index of a shard by
+/// [`shard_of()`](Self::shard_of). Completions the queue reports no source for, ex: those of
+/// sends and RMA operations, are steered by the peer `fill()` is told for them, typically
+/// from their context.
+///
+/// ```no_run
+/// # fn run(cq: libfabric::CompletionQueue) -> libfabric::Result<()> {
+/// use libfabric::{Addr, Completion, SteeredRing};
+///
+/// let ring = SteeredRing::new(4, 1024);
+/// for shard in 0..ring.shards() {
+///     let ring = ring.clone();
+///     std::thread::spawn(move || loop {
+///         match ring.pop(shard) {
+///             Some((completion, peer)) => println!("{peer:?}: {:#x} done", completion.context()),
+///             None => std::hint::spin_loop(),
+///         }
+///     });
+/// }
+/// let (mut batch, mut src) = ([Completion::default(); 64], [Addr::UNSPEC; 64]);
+/// loop {
+///     // The contexts of sends are the indices of their peers here.
+///     ring.fill(&cq, &mut batch, &mut src, |completion| {
+///         Some(Addr::from_raw(completion.context() as u64))
+///     })?;
+/// }
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct SteeredRing {
+    shards: Arc<[Arc<Shared<Steered>>]>,
+}
+
+impl SteeredRing {
+    /// Rings for `shards` shards, holding `capacity` completions each, rounded up to a power
+    /// of two.
+    pub fn new(shards: usize, capacity: usize) -> Self {
+        SteeredRing {
+            shards: (0..shards.max(1)).map(|_| Shared::new(capacity)).collect(),
+        }
+    }
+
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// The shard the completions of `peer` are steered to, the same for as long as the
+    /// rings live.
+    pub fn shard_of(&self, peer: Addr) -> usize {
+        // The finalizer of splitmix64, as user IDs and the handles of FI_AV_TABLE are small
+        // consecutive integers.
+        let mut hash = peer.as_raw();
+        hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d049bb133111eb);
+        hash ^= hash >> 31;
+        (hash % self.shards.len() as u64) as usize
+    }
+
+    /// The completions in the ring of `shard`, which may have changed by the time it returns.
+    pub fn len(&self, shard: usize) -> usize {
+        self.shards[shard].len()
+    }
+
+    /// Whether the rings of all shards are empty.
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| shard.len() == 0)
+    }
+
+    /// Take the oldest completion out of the ring of `shard`, with the peer it was steered by.
+    pub fn pop(&self, shard: usize) -> Option<(Completion, Addr)> {
+        self.shards[shard].pop()
+    }
+
+    /// Take up to `out.len()` completions out of the ring of `shard`, returning how many were
+    /// taken.
+    pub fn pop_batch(&self, shard: usize, out: &mut [(Completion, Addr)]) -> usize {
+        self.shards[shard].pop_batch(out)
+    }
+
+    /// Read a batch of completions and their sources from `cq` into the rings of their
+    /// shards, through `batch` and `src`, returning how many were read. `peer_of` tells the
+    /// peer of completions without a source, those it has none for being steered as
+    /// [`Addr::NOTAVAIL`]. Only as many as the fullest ring has room for are read, so that no
+    /// completion is lost.
+    ///
+    /// An empty queue reads none. Fails like [`Cq::read_from()`] otherwise, with `FI_EAVAIL`
+    /// for an error completion, which the poller reads with [`Cq::read_err()`] and steers by
+    /// its `src_addr`.
+    pub fn fill<C: Cq>(
+        &self,
+        cq: &C,
+        batch: &mut [Completion],
+        src: &mut [Addr],
+        peer_of: impl Fn(&Completion) -> Option<Addr>,
+    ) -> Result<usize> {
+        let room = self
+            .shards
+            .iter()
+            .map(|shard| shard.capacity() - shard.len())
+            .min()
+            .unwrap_or(0);
+        let room = batch.len().min(src.len()).min(room);
+        if room == 0 {
+            return Ok(0);
+        }
+        let n = match cq.read_from(&mut batch[..room], &mut src[..room]) {
+            Err(err) if err.is_again() => return Ok(0),
+            other => other?,
+        };
+        for (completion, &src) in batch[..n].iter().zip(&src[..n]) {
+            let peer = match src {
+                Addr::NOTAVAIL => peer_of(completion).unwrap_or(Addr::NOTAVAIL),
+                src => src,
+            };
+            self.shards[self.shard_of(peer)].push_spin((*completion, peer));
+        }
+        Ok(n)
+    }
+}
@@ -0,0 +1,386 @@
+use crate::attr::DomainConfig;
+use crate::av::{AddressVector, AvAttr};
+use crate::checks::{Level, ValidationLevel};
+use crate::cntr::{CntrAttr, Counter};
+use crate::cq::{CompletionQueue, CqAttr};
+use crate::ep::{Created, Endpoint, ScalableEndpoint};
+use crate::error::{Error, Result};
+use crate::fabric::Fabric;
+use crate::fid::{AsRawFid, OwnedFid};
+use crate::flags::Access;
+use crate::info::InfoEntry;
+use crate::mr::{MemoryRegion, MrAttr};
+use crate::peer::{PeerCounter, PeerCq};
+use crate::resources::{Accounting, Claim, Reservation, Resource, ResourceUsage};
+use crate::threading::{ThreadSafe, ThreadingModel};
+use ofi_libfabric_sys::bindgen as ffi;
+use std::collections::HashSet;
+use std::ffi::c_void;
+use std::marker::PhantomData;
+use std::mem;
+use std::ptr;
+use std::sync::{Arc, Mutex};
+
+/// An open access domain (`fid_domain`), usually one per NIC.
+///
+/// Queues, counters, address vectors, memory regions and endpoints are opened from a domain,
+/// and keep it alive. They all share its [`ThreadingModel`], by default [`ThreadSafe`].
+#[derive(Clone)]
+pub struct Domain<M: ThreadingModel = ThreadSafe> {
+    inner: Arc<DomainInner>,
+    threading: PhantomData<M>,
+}
+
+struct DomainInner {
+    fid: OwnedFid<ffi::fid_domain>,
+    info: InfoEntry,
+    fabric: Fabric,
+    // The owner of a peer domain, kept alive until the domain is closed.
+    #[allow(dead_code)]
+    owner: Option<Domain>,
+    // The keys requested by the open regions of the domain.
+    keys: Mutex<HashSet<u64>>,
+    validation: Level,
+    resources: Arc<Accounting>,
+}
+
+impl Domain {
+    /// Open the domain described by `info`, via `fi_domain()`.
+    pub fn open(fabric: &Fabric, info: &InfoEntry) -> Result<Self> {
+        // SAFETY: No flags, and no context.
+        unsafe { Self::open_with_flags(fabric, info, 0, ptr::null_mut()) }
+    }
+
+    /// Open the domain described by `info` as a peer of `owner`, typically a domain of another
+    /// provider, via `fi_domain2()` with `FI_PEER` (see `fi_peer(3)`).
+    ///
+    /// The peer's objects are then imported from the owner, for instance with
+    /// [`peer_cq()`](Self::peer_cq).
+    pub fn open_peer(fabric: &Fabric, info: &InfoEntry, owner: &Domain) -> Result<Self> {
+        let mut context = ffi::fi_peer_domain_context {
+            size: mem::size_of::<ffi::fi_peer_domain_context>(),
+            domain: owner.as_raw(),
+        };
+        let mut domain = unsafe {
+            Self::open_with_flags(
+                fabric,
+                info,
+                ffi::FI_PEER,
+                ptr::from_mut(&mut context).cast(),
+            )
+        }?;
+        Arc::get_mut(&mut domain.inner).unwrap().owner = Some(owner.clone());
+        Ok(domain)
+    }
+
+    /// Open the domain described by `info`, via `fi_domain2()` with `flags`, for the flags of
+    /// libfabric 2.0 and providers which the other constructors do not cover. Without flags,
+    /// this is `fi_domain()`.
+    ///
+    /// # Safety
+    ///
+    /// `context` must be what `flags` require, ex: a `struct fi_peer_domain_context` for
+    /// `FI_PEER`, and stay valid for as long as they require, ex: the objects it points to for
+    /// the whole life of the domain. Without flags, it is the opaque context of the domain.
+    pub unsafe fn open_with_flags(
+        fabric: &Fabric,
+        info: &InfoEntry,
+        flags: u64,
+        context: *mut c_void,
+    ) -> Result<Self> {
+        unsafe { Self::open_raw(fabric, info, flags, context) }
+    }
+}
+
+impl<M: ThreadingModel> Domain<M> {
+    // The handles to the domain, this one included.
+    pub(crate) fn handles(&self) -> usize {
+        Arc::strong_count(&self.inner)
+    }
+
+    // Close the domain, via `fi_close()`, failing unless this is the last handle to it.
+    pub(crate) fn close(self) -> Result<()> {
+        crate::fid::close_last(self.inner, |inner| &mut inner.fid)
+    }
+
+    /// Open the domain described by `info` under the threading model `M`, ex:
+    /// [`ThreadDomain`](crate::ThreadDomain) for an entry found with
+    /// [`Info::threading()`](crate::Info::threading) set to [`Threading::Domain`]. Fails when
+    /// the threading level of the entry is not one the model allows.
+    ///
+    /// ```no_run
+    /// use libfabric::{Domain, Fabric, Info, ThreadDomain, Threading};
+    ///
+    /// let entries = Info::new().threading(Threading::Domain).get()?;
+    /// let fabric = Fabric::open(&entries[0])?;
+    /// let domain: Domain<ThreadDomain> = Domain::open_with_threading(&fabric, &entries[0])?;
+    /// # Ok::<(), libfabric::Error>(())
+    /// ```
+    ///
+    /// [`Threading::Domain`]: crate::Threading::Domain
+    pub fn open_with_threading(fabric: &Fabric, info: &InfoEntry) -> Result<Self> {
+        // SAFETY: No flags, and no context.
+        unsafe { Self::open_raw(fabric, info, 0, ptr::null_mut()) }
+    }
+
+    /// Open the domain described by `info`, found with [`Info::domain_config()`], under the
+    /// threading model of `config`, then check that its attributes meet `config`.
+    ///
+    /// [`Info::domain_config()`]: crate::Info::domain_config
+    pub fn open_with_config(
+        fabric: &Fabric,
+        info: &InfoEntry,
+        config: &DomainConfig<M>,
+    ) -> Result<Self> {
+        let domain = Self::open_with_threading(fabric, info)?;
+        config.validate(&domain.info().domain_attr())?;
+        if let Some(level) = config.validation_level() {
+            domain.set_validation(level);
+        }
+        domain.set_limits_enforced(config.limits_enforced());
+        Ok(domain)
+    }
+
+    /// Take ownership of a domain of `fabric` opened elsewhere, ex: by C code, from `info`,
+    /// under the threading model `M`. Fails like
+    /// [`open_with_threading()`](Self::open_with_threading) when the threading level of `info`
+    /// is not one `M` allows, and with an invalid argument error if `domain` is null.
+    ///
+    /// # Safety
+    ///
+    /// `domain` must be an open domain of `fabric`, described by `info`, which is closed by the
+    /// returned value only: once all handles to it are dropped, or released by
+    /// [`into_raw()`](Self::into_raw).
+    pub unsafe fn from_raw(
+        fabric: &Fabric,
+        domain: *mut ffi::fid_domain,
+        info: &InfoEntry,
+    ) -> Result<Self> {
+        Self::check_threading(info)?;
+        Ok(Domain {
+            inner: Arc::new(DomainInner {
+                fid: unsafe { OwnedFid::from_raw(domain) }?,
+                info: info.clone(),
+                fabric: fabric.clone(),
+                owner: None,
+                keys: Mutex::new(HashSet::new()),
+                validation: Level::new(ValidationLevel::default()),
+                resources: Accounting::new(&info.domain_attr()),
+            }),
+            threading: PhantomData,
+        })
+    }
+
+    /// Hand the domain over, ex: to C code, whose `fi_close()` then closes it. Fails unless
+    /// this is the last handle to it, which objects opened from it hold too. The fabric is
+    /// released: it must be kept open, by a handle or otherwise, until the domain is closed.
+    pub fn into_raw(self) -> Result<*mut ffi::fid_domain> {
+        crate::fid::release_last(self.inner, |inner| &mut inner.fid)
+    }
+
+    fn check_threading(info: &InfoEntry) -> Result<()> {
+        let threading = info.domain_attr().threading;
+        if !M::allows(threading) {
+            return Err(Error::invalid(format!(
+                "domain {} has threading {threading:?}, which {} does not allow",
+                info.domain_name(),
+                std::any::type_name::<M>()
+            )));
+        }
+        Ok(())
+    }
+
+    // SAFETY: `context` must be what `flags` require, see `Domain::open_with_flags()`.
+    unsafe fn open_raw(
+        fabric: &Fabric,
+        info: &InfoEntry,
+        flags: u64,
+        context: *mut c_void,
+    ) -> Result<Self> {
+        Self::check_threading(info)?;
+        let fid = OwnedFid::open("fi_domain2", |domain| unsafe {
+            ffi::fi_domain2(fabric.as_raw(), info.as_raw(), domain, flags, context)
+        })?;
+        Ok(Domain {
+            inner: Arc::new(DomainInner {
+                fid,
+                info: info.clone(),
+                fabric: fabric.clone(),
+                owner: None,
+                keys: Mutex::new(HashSet::new()),
+                validation: Level::new(ValidationLevel::default()),
+                resources: Accounting::new(&info.domain_attr()),
+            }),
+            threading: PhantomData,
+        })
+    }
+
+    /// The entry the domain was opened from.
+    pub fn info(&self) -> &InfoEntry {
+        &self.inner.info
+    }
+
+    pub fn fabric(&self) -> &Fabric {
+        &self.inner.fabric
+    }
+
+    /// How much the operations of the endpoints of the domain check their arguments.
+    pub fn validation(&self) -> ValidationLevel {
+        self.inner.validation.get()
+    }
+
+    /// Change how much the operations of the endpoints of the domain check their arguments,
+    /// from their next operation on, ex: to [`ValidationLevel::Full`] while debugging a job.
+    pub fn set_validation(&self, level: ValidationLevel) {
+        self.inner.validation.set(level);
+    }
+
+    /// The objects of `resource` the domain has open, opened through this crate or taken over
+    /// with `from_raw()`, against the count of its [`DomainAttr`](crate::DomainAttr).
+    pub fn usage(&self, resource: Resource) -> ResourceUsage {
+        self.inner.resources.usage(resource)
+    }
+
+    /// Reserve room for `count` objects of `resource`, ex: the endpoints of a job before it
+    /// opens them, failing with [`Error::LimitExceeded`] if the domain does not have as much
+    /// left. The objects opened take their room from the reservations of their resource first,
+    /// which opening them then never fails for, and the room left is released once the
+    /// reservation is dropped.
+    pub fn reserve(&self, resource: Resource, count: usize) -> Result<Reservation> {
+        self.inner.resources.reserve(resource, count)
+    }
+
+    /// Whether opening objects past the counts of the attributes of the domain fails.
+    pub fn limits_enforced(&self) -> bool {
+        self.inner.resources.enforced()
+    }
+
+    /// Fail with [`Error::LimitExceeded`], before calling the provider, to open objects past
+    /// the counts of the attributes of the domain, ex: to find which part of a large job
+    /// exhausts them. Off by default: most counts are those the domain is optimized for, past
+    /// which the provider shares its resources between the objects, rather than failing.
+    pub fn set_limits_enforced(&self, enforced: bool) {
+        self.inner.resources.set_enforced(enforced);
+    }
+
+    // Count an object opened from the domain, failing past its limits if they are enforced.
+    pub(crate) fn claim(&self, resources: &[Resource]) -> Result<Claim> {
+        self.inner.resources.open(resources, true)
+    }
+
+    // Count an object opened elsewhere, which its limits do not apply to.
+    pub(crate) fn adopt(&self, resources: &[Resource]) -> Claim {
+        match self.inner.resources.open(resources, false) {
+            Ok(claim) => claim,
+            Err(_) => unreachable!("unchecked claims do not fail"),
+        }
+    }
+
+    /// Open an endpoint for the given entry, typically the domain's own entry or the one
+    /// delivered with a connection request. It is then bound, and enabled, see [`Endpoint`].
+    pub fn endpoint(&self, info: &InfoEntry) -> Result<Endpoint<M, Created>> {
+        // SAFETY: No flags, and no context.
+        unsafe { Endpoint::open(self, info, 0, ptr::null_mut()) }
+    }
+
+    /// Open an endpoint for the given entry, via `fi_endpoint2()` with `flags`, ex: `FI_PEER`
+    /// for the endpoints of peer providers (see `fi_peer(3)`). Without flags, this is
+    /// [`endpoint()`](Self::endpoint).
+    ///
+    /// # Safety
+    ///
+    /// `context` must be what `flags` and the provider require, and stay valid for as long as
+    /// they require. Without flags, it is the opaque context of the endpoint.
+    pub unsafe fn endpoint_with_flags(
+        &self,
+        info: &InfoEntry,
+        flags: u64,
+        context: *mut c_void,
+    ) -> Result<Endpoint<M, Created>> {
+        unsafe { Endpoint::open(self, info, flags, context) }
+    }
+
+    /// Open a scalable endpoint for the given entry, via `fi_scalable_ep()`.
+    pub fn scalable_endpoint(&self, info: &InfoEntry) -> Result<ScalableEndpoint<M>> {
+        ScalableEndpoint::open(self, info)
+    }
+
+    pub fn cq(&self, attr: &CqAttr) -> Result<CompletionQueue<M>> {
+        CompletionQueue::open(self, attr, None)
+    }
+
+    /// Open a queue whose completions are written to `owner`, via `fi_cq_open()` with
+    /// `FI_PEER`. Reading the returned queue only drives the provider's progress.
+    pub fn peer_cq(&self, attr: &CqAttr, owner: &PeerCq) -> Result<CompletionQueue<M>> {
+        CompletionQueue::open(self, attr, Some(owner))
+    }
+
+    pub fn counter(&self, attr: &CntrAttr) -> Result<Counter<M>> {
+        Counter::open(self, attr, None)
+    }
+
+    /// Open a counter whose updates are forwarded to `owner`, via `fi_cntr_open()` with
+    /// `FI_PEER`.
+    pub fn peer_counter(&self, attr: &CntrAttr, owner: &PeerCounter) -> Result<Counter<M>> {
+        Counter::open(self, attr, Some(owner))
+    }
+
+    pub fn av(&self, attr: &AvAttr) -> Result<AddressVector<M>> {
+        AddressVector::open(self, attr)
+    }
+
+    /// Attach read-only to the vector shared as the [`AvAttr::name()`] of `attr` if it exists,
+    /// or else create it, owning it: the one process which creates the vector inserts the
+    /// addresses of the job, which the others resolve through the handles it hands them. The
+    /// vector lives on until every process sharing it closed it.
+    ///
+    /// [`AddressVector::is_read_only()`] tells which of the two happened.
+    pub fn shared_av(&self, attr: &AvAttr) -> Result<AddressVector<M>> {
+        AddressVector::open_shared(self, attr)
+    }
+
+    /// Register `len` bytes at `buf` for use in data transfers, via `fi_mr_reg()`.
+    ///
+    /// # Safety
+    ///
+    /// The memory must stay allocated for as long as the region, or any operation using it, is
+    /// alive. With remote access rights, peers may write to it at any time.
+    pub unsafe fn register(
+        &self,
+        buf: *mut u8,
+        len: usize,
+        access: Access,
+    ) -> Result<MemoryRegion<M>> {
+        unsafe { MemoryRegion::register(self, buf, len, &MrAttr::new(access)) }
+    }
+
+    /// Register `len` bytes at `buf` as [`register()`](Self::register) does, with the
+    /// attributes of `attr`, ex: the key of the region.
+    ///
+    /// # Safety
+    ///
+    /// See [`register()`](Self::register).
+    pub unsafe fn register_with(
+        &self,
+        buf: *mut u8,
+        len: usize,
+        attr: &MrAttr,
+    ) -> Result<MemoryRegion<M>> {
+        unsafe { MemoryRegion::register(self, buf, len, attr) }
+    }
+
+    // The keys requested by the open regions of the domain.
+    pub(crate) fn keys(&self) -> &Mutex<HashSet<u64>> {
+        &self.inner.keys
+    }
+
+    pub fn as_raw(&self) -> *mut ffi::fid_domain {
+        self.inner.fid.as_ptr()
+    }
+}
+
+impl<M: ThreadingModel> AsRawFid for Domain<M> {
+    fn as_raw_fid(&self) -> *mut ffi::fid {
+        self.inner.fid.as_fid()
+    }
+}
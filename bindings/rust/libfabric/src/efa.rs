@@ -0,0 +1,135 @@
+//! Knobs specific to the EFA provider of AWS: the operations of its extension header,
+//! `fi_ext_efa.h`, and its `FI_OPT_EFA_*` endpoint options.
+//!
+//! Every call fails with `FI_ENOSYS`, or `FI_ENOPROTOOPT` for the options, on objects of other
+//! providers.
+//!
+//! ```no_run
+//! use libfabric::efa;
+//!
+//! # fn run(ep: libfabric::Endpoint, mr: libfabric::MemoryRegion) -> libfabric::Result<()> {
+//! // Large transfers are better done with RMA reads, when the device does them.
+//! let rdma_read = !efa::emulated_read(&ep)?;
+//! let attr = efa::query_mr(&mr)?;
+//! println!("rdma read: {rdma_read}, interconnect: {:?}", attr.rdma_read_ic_id);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::av::Addr;
+use crate::ep::{Endpoint, EndpointState, Setup};
+use crate::error::{Error, Result, check};
+use crate::fid::AsRawFid;
+use crate::mr::MemoryRegion;
+use crate::threading::ThreadSafe;
+use ofi_libfabric_sys::bindgen as ffi;
+
+/// The attributes of a memory region, from the `query_mr()` domain operation: the
+/// interconnects the device reaches the memory over, for each kind of access, when valid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MrAttr {
+    pub recv_ic_id: Option<u16>,
+    pub rdma_read_ic_id: Option<u16>,
+    pub rdma_recv_ic_id: Option<u16>,
+}
+
+/// The attributes of `mr`, via the `query_mr()` operation of its domain.
+pub fn query_mr(mr: &MemoryRegion) -> Result<MrAttr> {
+    let ops = mr.domain().open_ops::<ffi::fi_efa_ops_domain>()?;
+    let query_mr = ops.query_mr.ok_or_else(unsupported)?;
+    let mut raw = ffi::fi_efa_mr_attr::default();
+    check("query_mr", unsafe { query_mr(mr.as_raw(), &mut raw) })?;
+    let valid = |bit: u32, id: u16| (raw.ic_id_validity as u32 & bit != 0).then_some(id);
+    Ok(MrAttr {
+        recv_ic_id: valid(ffi::FI_EFA_MR_ATTR_RECV_IC_ID, raw.recv_ic_id),
+        rdma_read_ic_id: valid(ffi::FI_EFA_MR_ATTR_RDMA_READ_IC_ID, raw.rdma_read_ic_id),
+        rdma_recv_ic_id: valid(ffi::FI_EFA_MR_ATTR_RDMA_RECV_IC_ID, raw.rdma_recv_ic_id),
+    })
+}
+
+/// The device address of a peer, from the `query_addr()` operation of the endpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerAddr {
+    /// The address handle number.
+    pub ahn: u16,
+    pub qpn: u16,
+    pub qkey: u32,
+}
+
+/// The device address of `addr`, via the `query_addr()` operation of `ep`.
+pub fn query_addr(ep: &Endpoint, addr: Addr) -> Result<PeerAddr> {
+    let ops = ep.open_ops::<ffi::fi_efa_ops_gda>()?;
+    let query_addr = ops.query_addr.ok_or_else(unsupported)?;
+    let (mut ahn, mut qpn, mut qkey) = (0, 0, 0);
+    check("query_addr", unsafe {
+        query_addr(ep.as_raw(), addr.as_raw(), &mut ahn, &mut qpn, &mut qkey)
+    })?;
+    Ok(PeerAddr { ahn, qpn, qkey })
+}
+
+/// The local key of `mr` on the device, via the `get_mr_lkey()` operation of `ep`.
+pub fn mr_lkey(ep: &Endpoint, mr: &MemoryRegion) -> Result<u64> {
+    let ops = ep.open_ops::<ffi::fi_efa_ops_gda>()?;
+    let get_mr_lkey = ops.get_mr_lkey.ok_or_else(unsupported)?;
+    Ok(unsafe { get_mr_lkey(mr.as_raw()) })
+}
+
+/// Whether RMA reads are emulated with messages, rather than done by the device
+/// (`FI_OPT_EFA_EMULATED_READ`).
+pub fn emulated_read<S: EndpointState>(ep: &Endpoint<ThreadSafe, S>) -> Result<bool> {
+    ep.getopt(ffi::FI_OPT_EFA_EMULATED_READ)
+}
+
+/// Whether RMA writes are emulated with messages (`FI_OPT_EFA_EMULATED_WRITE`).
+pub fn emulated_write<S: EndpointState>(ep: &Endpoint<ThreadSafe, S>) -> Result<bool> {
+    ep.getopt(ffi::FI_OPT_EFA_EMULATED_WRITE)
+}
+
+/// Whether atomics are emulated with messages (`FI_OPT_EFA_EMULATED_ATOMICS`).
+pub fn emulated_atomics<S: EndpointState>(ep: &Endpoint<ThreadSafe, S>) -> Result<bool> {
+    ep.getopt(ffi::FI_OPT_EFA_EMULATED_ATOMICS)
+}
+
+/// Have the device do the RMA of `ep`, failing if it cannot, or have it emulated
+/// (`FI_OPT_EFA_USE_DEVICE_RDMA`). Set before the endpoint is enabled.
+pub fn set_use_device_rdma<S: Setup>(ep: &Endpoint<ThreadSafe, S>, enable: bool) -> Result<()> {
+    ep.setopt(ffi::FI_OPT_EFA_USE_DEVICE_RDMA, &enable)
+}
+
+/// The times the device retries a send the peer had no receive for (`FI_OPT_EFA_RNR_RETRY`).
+pub fn rnr_retry<S: EndpointState>(ep: &Endpoint<ThreadSafe, S>) -> Result<usize> {
+    ep.getopt(ffi::FI_OPT_EFA_RNR_RETRY)
+}
+
+/// Set [`rnr_retry()`], before the endpoint is enabled, 7 meaning retrying forever.
+pub fn set_rnr_retry<S: Setup>(ep: &Endpoint<ThreadSafe, S>, retries: usize) -> Result<()> {
+    ep.setopt(ffi::FI_OPT_EFA_RNR_RETRY, &retries)
+}
+
+/// Require sends and receives to land in order, in aligned 128 byte units, failing if the
+/// device does not (`FI_OPT_EFA_SENDRECV_IN_ORDER_ALIGNED_128_BYTES`).
+pub fn set_sendrecv_in_order_aligned_128_bytes<S: Setup>(
+    ep: &Endpoint<ThreadSafe, S>,
+    enable: bool,
+) -> Result<()> {
+    ep.setopt(ffi::FI_OPT_EFA_SENDRECV_IN_ORDER_ALIGNED_128_BYTES, &enable)
+}
+
+/// Require RMA writes to land in order, in aligned 128 byte units, failing if the device does
+/// not (`FI_OPT_EFA_WRITE_IN_ORDER_ALIGNED_128_BYTES`).
+pub fn set_write_in_order_aligned_128_bytes<S: Setup>(
+    ep: &Endpoint<ThreadSafe, S>,
+    enable: bool,
+) -> Result<()> {
+    ep.setopt(ffi::FI_OPT_EFA_WRITE_IN_ORDER_ALIGNED_128_BYTES, &enable)
+}
+
+/// Promise that every peer runs with the same settings, which skips the handshake with them
+/// before RMA (`FI_OPT_EFA_HOMOGENEOUS_PEERS`).
+pub fn set_homogeneous_peers<S: Setup>(ep: &Endpoint<ThreadSafe, S>, enable: bool) -> Result<()> {
+    ep.setopt(ffi::FI_OPT_EFA_HOMOGENEOUS_PEERS, &enable)
+}
+
+fn unsupported() -> Error {
+    Error::fabric("fi_open_ops", ffi::FI_ENOSYS as i64)
+}
@@ -0,0 +1,1109 @@
+use crate::attr::{RxQueueAttr, TxQueueAttr};
+use crate::av::{Addr, AddressVector};
+use crate::caps::{AnyCaps, CapProfile, HasMsg};
+use crate::checks::{Kind, Limits, Op, ValidationLevel};
+use crate::cntr::Counter;
+use crate::cq::{CompletionQueue, Rooms};
+use crate::domain::Domain;
+use crate::eq::EventQueue;
+use crate::error::{Error, Result, check, check_len};
+use crate::fabric::Fabric;
+use crate::fid::{AsRawFid, OwnedFid};
+use crate::flags::{BindFlags, OpFlags};
+use crate::info::InfoEntry;
+use crate::mr::{MemoryRegion, desc};
+use crate::quiesce::Object;
+use crate::resources::{Claim, Resource};
+use crate::rma::{descs, iovec};
+use crate::threading::{ThreadSafe, ThreadingModel};
+use crate::trace;
+use ofi_libfabric_sys::bindgen as ffi;
+use std::ffi::c_void;
+use std::marker::PhantomData;
+use std::ptr;
+use std::sync::{Arc, Mutex, OnceLock};
+
+// Objects bound to an endpoint, kept alive until the endpoint itself is closed.
+#[allow(dead_code)]
+enum BoundFid<M: ThreadingModel> {
+    Cq(CompletionQueue<M>),
+    Eq(EventQueue),
+    Av(AddressVector<M>),
+    Cntr(Counter<M>),
+    Aliased(Endpoint<M>),
+    Scalable(ScalableEndpoint<M>),
+}
+
+mod sealed {
+    pub trait Sealed {}
+
+    impl Sealed for super::Created {}
+    impl Sealed for super::Bound {}
+    impl Sealed for super::Enabled {}
+}
+
+/// The stage of the lifecycle of an [`Endpoint`], as a type parameter: [`Created`], then
+/// [`Bound`] to a completion queue or counter, then [`Enabled`].
+pub trait EndpointState: sealed::Sealed + 'static {}
+
+/// The states of endpoints which may still be bound, before they are enabled.
+pub trait Setup: EndpointState {}
+
+/// An endpoint just opened, which needs a completion queue, or a counter, before it can be
+/// enabled.
+pub enum Created {}
+
+/// An endpoint bound to a completion queue or counter, which may be bound to more objects and
+/// enabled.
+pub enum Bound {}
+
+/// An enabled endpoint, the default, which transfers data.
+pub enum Enabled {}
+
+impl EndpointState for Created {}
+impl EndpointState for Bound {}
+impl EndpointState for Enabled {}
+impl Setup for Created {}
+impl Setup for Bound {}
+
+/// An active endpoint (`fid_ep`).
+///
+/// Data transfers are `unsafe`: libfabric keeps using the buffers, and the descriptor of their
+/// memory region, after the call returns. Every buffer must stay valid, and must not be
+/// otherwise accessed, until the operation's completion has been read. The `context` of an
+/// operation is an opaque value handed back in its completion.
+///
+/// The objects bound to the endpoint, and the regions of its buffers, belong to its domain, and
+/// share its [`ThreadingModel`].
+///
+/// The [`EndpointState`] of the endpoint follows its lifecycle: [`Domain::endpoint()`] opens it
+/// [`Created`], binding a completion queue or counter makes it [`Bound`], and [`enable()`]
+/// makes it [`Enabled`]. Objects are only bound before, and data only transferred after,
+/// enabling it, so an endpoint missing its completion queue does not compile, rather than fail
+/// with `-FI_ENOCQ`. Only enabled endpoints are `Clone`.
+///
+/// ```no_run
+/// # fn run(domain: &libfabric::Domain, entry: &libfabric::InfoEntry) -> libfabric::Result<()> {
+/// use libfabric::{AvAttr, BindFlags, CqAttr};
+///
+/// let (cq, av) = (domain.cq(&CqAttr::new())?, domain.av(&AvAttr::new())?);
+/// let ep = domain
+///     .endpoint(entry)?
+///     .bind_cq(&cq, BindFlags::TRANSMIT | BindFlags::RECV)?
+///     .bind_av(&av)?
+///     .enable()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// ```compile_fail
+/// # fn run(domain: &libfabric::Domain, entry: &libfabric::InfoEntry) -> libfabric::Result<()> {
+/// // Without a completion queue, the endpoint cannot be enabled.
+/// let ep = domain.endpoint(entry)?.enable()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// The [`CapProfile`] of the endpoint, [`AnyCaps`] unless it takes another with
+/// [`with_profile()`], leaves out the operations of the capabilities it was not opened with,
+/// see the [`caps`](crate::caps) module.
+///
+/// [`enable()`]: Self::enable
+/// [`with_profile()`]: Self::with_profile
+pub struct Endpoint<
+    M: ThreadingModel = ThreadSafe,
+    S: EndpointState = Enabled,
+    P: CapProfile = AnyCaps,
+> {
+    inner: Arc<EpInner<M>>,
+    state: PhantomData<(S, P)>,
+}
+
+/// An enabled endpoint of the profile `P`, ex: `ProfiledEndpoint<(Msg, Rma)>`.
+pub type ProfiledEndpoint<P, M = ThreadSafe> = Endpoint<M, Enabled, P>;
+
+struct EpInner<M: ThreadingModel> {
+    // Declared first, so the endpoint is closed before the objects bound to it.
+    fid: OwnedFid<ffi::fid_ep>,
+    bound: Mutex<Vec<BoundFid<M>>>,
+    // The buffers of operations left posted when their completion queue failed to be read,
+    // kept until the endpoint is closed.
+    orphans: Mutex<Vec<Box<dyn Send>>>,
+    info: InfoEntry,
+    domain: Domain<M>,
+    limits: OnceLock<Limits>,
+    // The queues the completions of the operations posted are reserved in.
+    rooms: Rooms,
+    // The resources of the domain the endpoint counts for, none for aliases.
+    #[allow(dead_code)]
+    claim: Option<Claim>,
+}
+
+#[cfg(feature = "debug-validate")]
+impl<M: ThreadingModel> Drop for EpInner<M> {
+    fn drop(&mut self) {
+        crate::validate::closed(crate::fid::FidId::from_ptr(self.fid.as_fid()));
+    }
+}
+
+impl<M: ThreadingModel, P: CapProfile> Clone for Endpoint<M, Enabled, P> {
+    fn clone(&self) -> Self {
+        Endpoint {
+            inner: self.inner.clone(),
+            state: PhantomData,
+        }
+    }
+}
+
+impl<M: ThreadingModel> Endpoint<M, Created> {
+    // SAFETY: `context` must be what `flags` require, see `Domain::endpoint_with_flags()`.
+    pub(crate) unsafe fn open(
+        domain: &Domain<M>,
+        info: &InfoEntry,
+        flags: u64,
+        context: *mut c_void,
+    ) -> Result<Self> {
+        let claim = domain.claim(&resources(info))?;
+        let fid = OwnedFid::open("fi_endpoint2", |ep| unsafe {
+            ffi::fi_endpoint2(domain.as_raw(), info.as_raw(), ep, flags, context)
+        })?;
+        Ok(Endpoint {
+            inner: Arc::new(EpInner {
+                fid,
+                bound: Mutex::new(Vec::new()),
+                orphans: Mutex::new(Vec::new()),
+                info: info.clone(),
+                domain: domain.clone(),
+                limits: OnceLock::new(),
+                rooms: Rooms::default(),
+                claim: Some(claim),
+            }),
+            state: PhantomData,
+        })
+    }
+}
+
+impl<M: ThreadingModel, S: EndpointState> Endpoint<M, S> {
+    /// Take ownership of an endpoint of `domain` opened elsewhere, ex: by C code, from `info`,
+    /// in the state `S`: [`Created`], or [`Enabled`] once its queues are bound and it is
+    /// enabled. Fails with an invalid argument error if `ep` is null.
+    ///
+    /// The objects bound to it before are not kept alive by the returned value, and must stay
+    /// open until the endpoint is closed.
+    ///
+    /// # Safety
+    ///
+    /// `ep` must be an open endpoint of `domain`, described by `info`, in the state `S`, which
+    /// is closed by the returned value only: once all handles to it are dropped, or released by
+    /// [`into_raw()`](Self::into_raw).
+    pub unsafe fn from_raw(
+        domain: &Domain<M>,
+        ep: *mut ffi::fid_ep,
+        info: &InfoEntry,
+    ) -> Result<Self> {
+        Ok(Endpoint {
+            inner: Arc::new(EpInner {
+                fid: unsafe { OwnedFid::from_raw(ep) }?,
+                bound: Mutex::new(Vec::new()),
+                orphans: Mutex::new(Vec::new()),
+                info: info.clone(),
+                domain: domain.clone(),
+                limits: OnceLock::new(),
+                rooms: Rooms::default(),
+                claim: Some(domain.adopt(&resources(info))),
+            }),
+            state: PhantomData,
+        })
+    }
+}
+
+impl<M: ThreadingModel, S: EndpointState, P: CapProfile> Endpoint<M, S, P> {
+    // The handles to the endpoint, this one included.
+    pub(crate) fn handles(&self) -> usize {
+        Arc::strong_count(&self.inner)
+    }
+
+    // Close the endpoint, via `fi_close()`, failing unless this is the last handle to it.
+    pub(crate) fn close(self) -> Result<()> {
+        crate::fid::close_last(self.inner, |inner| &mut inner.fid)
+    }
+
+    /// Hand the endpoint over, ex: to C code, whose `fi_close()` then closes it. Fails unless
+    /// this is the last handle to it. The domain and the objects bound to the endpoint are
+    /// released: they must be kept open until the endpoint is closed.
+    pub fn into_raw(self) -> Result<*mut ffi::fid_ep> {
+        crate::fid::release_last(self.inner, |inner| &mut inner.fid)
+    }
+
+    /// The entry the endpoint was opened from.
+    pub fn info(&self) -> &InfoEntry {
+        &self.inner.info
+    }
+
+    pub fn domain(&self) -> &Domain<M> {
+        &self.inner.domain
+    }
+
+    /// The endpoint, of the profile `Q`, which leaves out the operations of the capabilities
+    /// it lacks. Fails with an invalid argument error unless the entry of the endpoint has
+    /// the capabilities of `Q`.
+    pub fn with_profile<Q: CapProfile>(self) -> Result<Endpoint<M, S, Q>> {
+        let caps = self.inner.info.caps();
+        if !caps.contains(Q::CAPS) {
+            return Err(Error::invalid(format!(
+                "endpoint of {caps:?} given a profile of {:?}",
+                Q::CAPS
+            )));
+        }
+        Ok(self.into_profile())
+    }
+
+    /// The endpoint, of [`AnyCaps`], for the code which takes endpoints of any capabilities.
+    pub fn into_any_caps(self) -> Endpoint<M, S> {
+        self.into_profile()
+    }
+
+    fn into_state<T: EndpointState>(self) -> Endpoint<M, T, P> {
+        Endpoint {
+            inner: self.inner,
+            state: PhantomData,
+        }
+    }
+
+    fn into_profile<Q: CapProfile>(self) -> Endpoint<M, S, Q> {
+        Endpoint {
+            inner: self.inner,
+            state: PhantomData,
+        }
+    }
+
+    // The event queue bound to the endpoint, if any.
+    pub(crate) fn bound_eq(&self) -> Option<EventQueue> {
+        let bound = self.inner.bound.lock().unwrap();
+        bound.iter().find_map(|bound| match bound {
+            BoundFid::Eq(eq) => Some(eq.clone()),
+            BoundFid::Aliased(ep) => ep.bound_eq(),
+            _ => None,
+        })
+    }
+
+    // The objects bound to the endpoint, closed after it.
+    pub(crate) fn bound_objects(&self) -> Vec<Object<M>> {
+        bound_objects(&self.inner)
+    }
+
+    // The completion queues bound to the endpoint.
+    pub(crate) fn bound_cqs(&self) -> Vec<CompletionQueue<M>> {
+        let bound = self.inner.bound.lock().unwrap();
+        bound
+            .iter()
+            .flat_map(|bound| match bound {
+                BoundFid::Cq(cq) => vec![cq.clone()],
+                BoundFid::Aliased(ep) => ep.bound_cqs(),
+                _ => Vec::new(),
+            })
+            .collect()
+    }
+
+    fn bind(
+        &self,
+        op: &'static str,
+        fid: *mut ffi::fid,
+        flags: u64,
+        bound: BoundFid<M>,
+    ) -> Result<()> {
+        check(op, unsafe { ffi::fi_ep_bind(self.as_raw(), fid, flags) })?;
+        self.inner.bound.lock().unwrap().push(bound);
+        Ok(())
+    }
+
+    /// The space left in multi-receive buffers under which the provider releases them, via
+    /// `fi_getopt(FI_OPT_MIN_MULTI_RECV)`.
+    pub fn min_multi_recv(&self) -> Result<usize> {
+        self.getopt(ffi::FI_OPT_MIN_MULTI_RECV as i32)
+    }
+
+    /// Set the space left in multi-receive buffers under which the provider releases them,
+    /// via `fi_setopt(FI_OPT_MIN_MULTI_RECV)`. No message larger than this is truncated.
+    pub fn set_min_multi_recv(&self, len: usize) -> Result<()> {
+        self.setopt(ffi::FI_OPT_MIN_MULTI_RECV as i32, &len)
+    }
+
+    // An endpoint option of type `T`, via `fi_getopt()`.
+    pub(crate) fn getopt<T: Copy + Default>(&self, name: i32) -> Result<T> {
+        let mut value = T::default();
+        let mut len = std::mem::size_of::<T>();
+        check("fi_getopt", unsafe {
+            ffi::fi_getopt(
+                self.as_raw_fid(),
+                ffi::FI_OPT_ENDPOINT as i32,
+                name,
+                (&mut value as *mut T).cast(),
+                &mut len,
+            )
+        })?;
+        Ok(value)
+    }
+
+    // Set an endpoint option of type `T`, via `fi_setopt()`.
+    pub(crate) fn setopt<T: Copy>(&self, name: i32, value: &T) -> Result<()> {
+        check("fi_setopt", unsafe {
+            ffi::fi_setopt(
+                self.as_raw_fid(),
+                ffi::FI_OPT_ENDPOINT as i32,
+                name,
+                (value as *const T).cast(),
+                std::mem::size_of::<T>(),
+            )
+        })
+    }
+
+    pub fn as_raw(&self) -> *mut ffi::fid_ep {
+        self.inner.fid.as_ptr()
+    }
+}
+
+impl<M: ThreadingModel, S: Setup, P: CapProfile> Endpoint<M, S, P> {
+    /// Bind a completion queue for the completions selected by `flags`.
+    ///
+    /// The message, tagged, RMA and atomic operations posted then reserve a completion in the
+    /// queue, and are refused with `FI_EAGAIN` once it is full, see
+    /// [`CqAttr::track_outstanding()`]. Those of a context bound with
+    /// [`BindFlags::SELECTIVE_COMPLETION`] do not, as most do not complete.
+    ///
+    /// [`CqAttr::track_outstanding()`]: crate::CqAttr::track_outstanding
+    pub fn bind_cq(
+        self,
+        cq: &CompletionQueue<M>,
+        flags: BindFlags,
+    ) -> Result<Endpoint<M, Bound, P>> {
+        self.bind(
+            "fi_ep_bind",
+            cq.as_raw_fid(),
+            flags.bits(),
+            BoundFid::Cq(cq.clone()),
+        )?;
+        if let Some(room) = cq
+            .room()
+            .filter(|_| !flags.contains(BindFlags::SELECTIVE_COMPLETION))
+        {
+            let rooms = &self.inner.rooms;
+            if flags.contains(BindFlags::TRANSMIT) {
+                let _ = rooms.tx.set(room.clone());
+            }
+            if flags.contains(BindFlags::RECV) {
+                let _ = rooms.rx.set(room);
+            }
+        }
+        #[cfg(feature = "debug-validate")]
+        crate::validate::bound_cq(self.id(), flags.bits());
+        Ok(self.into_state())
+    }
+
+    /// Bind a counter for the events selected by `flags`.
+    ///
+    /// A counter tracks the operations in bulk: with a completion queue bound with
+    /// [`BindFlags::SELECTIVE_COMPLETION`], or none at all where the provider allows it, only
+    /// the operations posted with [`OpFlags::COMPLETION`] write a completion, the others only
+    /// incrementing the counter, which [`Counter::wait()`] waits on.
+    pub fn bind_counter(
+        self,
+        cntr: &Counter<M>,
+        flags: BindFlags,
+    ) -> Result<Endpoint<M, Bound, P>> {
+        self.bind(
+            "fi_ep_bind",
+            cntr.as_raw_fid(),
+            flags.bits(),
+            BoundFid::Cntr(cntr.clone()),
+        )?;
+        Ok(self.into_state())
+    }
+
+    /// Bind the event queue which reports connection management events.
+    pub fn bind_eq(self, eq: &EventQueue) -> Result<Self> {
+        self.bind("fi_ep_bind", eq.as_raw_fid(), 0, BoundFid::Eq(eq.clone()))?;
+        Ok(self)
+    }
+
+    /// Bind the address vector used to resolve peer addresses of connectionless endpoints.
+    pub fn bind_av(self, av: &AddressVector<M>) -> Result<Self> {
+        self.bind("fi_ep_bind", av.as_raw_fid(), 0, BoundFid::Av(av.clone()))?;
+        Ok(self)
+    }
+}
+
+impl<M: ThreadingModel, P: CapProfile> Endpoint<M, Bound, P> {
+    /// Enable the endpoint once all of its queues are bound, via `fi_enable()`.
+    pub fn enable(self) -> Result<Endpoint<M, Enabled, P>> {
+        check("fi_enable", unsafe { ffi::fi_enable(self.as_raw()) })?;
+        Ok(self.into_state())
+    }
+}
+
+impl<M: ThreadingModel, P: CapProfile> Endpoint<M, Enabled, P> {
+    /// Open an alias of the endpoint, via `fi_ep_alias()`: another handle on its contexts and
+    /// bound objects, whose operations default to `op_flags` on the contexts selected by
+    /// `flags`, [`BindFlags::TRANSMIT`] and [`BindFlags::RECV`]. The alias keeps the endpoint
+    /// alive.
+    ///
+    /// With the completion queue bound with [`BindFlags::SELECTIVE_COMPLETION`], the operations
+    /// of an alias with [`OpFlags::COMPLETION`] all complete, while those of the endpoint only
+    /// complete when asked to.
+    pub fn alias(&self, flags: BindFlags, op_flags: OpFlags) -> Result<Self> {
+        let contexts = BindFlags::TRANSMIT | BindFlags::RECV;
+        if flags.is_empty() || !contexts.contains(flags) {
+            return Err(Error::invalid(format!(
+                "endpoint aliases apply to transmit or receive contexts, not {flags:?}"
+            )));
+        }
+        let fid = OwnedFid::open("fi_ep_alias", |alias| unsafe {
+            ffi::fi_ep_alias(self.as_raw(), alias, flags.bits() | op_flags.bits())
+        })?;
+        Ok(Endpoint {
+            inner: Arc::new(EpInner {
+                fid,
+                bound: Mutex::new(vec![BoundFid::Aliased(self.clone().into_profile())]),
+                orphans: Mutex::new(Vec::new()),
+                info: self.inner.info.clone(),
+                domain: self.inner.domain.clone(),
+                limits: OnceLock::new(),
+                rooms: self.inner.rooms.clone(),
+                claim: None,
+            }),
+            state: PhantomData,
+        })
+    }
+
+    /// Cancel the outstanding operation posted with `context`.
+    pub fn cancel(&self, context: usize) -> Result<()> {
+        check_len("fi_cancel", unsafe {
+            ffi::fi_cancel(self.as_raw_fid(), context as *mut _)
+        })
+        .map(|_| ())
+    }
+
+    // Keep `buf` until the endpoint is closed, for operations which may still be posted over
+    // it, ex: once their cancellation failed to be waited for.
+    pub(crate) fn orphan(&self, buf: impl Send + 'static) {
+        self.inner.orphans.lock().unwrap().push(Box::new(buf));
+    }
+
+    // Check the arguments of an operation, at the validation level of the domain.
+    #[inline]
+    pub(crate) fn check_op(&self, op: impl FnOnce() -> Op) -> Result<()> {
+        let level = self.inner.domain.validation();
+        if level == ValidationLevel::Off {
+            return Ok(());
+        }
+        let limits = self
+            .inner
+            .limits
+            .get_or_init(|| Limits::of(&self.inner.info));
+        op().check(level, limits)
+    }
+
+    // Post an operation, via `post`, whose completion is written to the queue bound for the
+    // `context`, [`BindFlags::TRANSMIT`] or [`BindFlags::RECV`]. Fails with `FI_EAGAIN` without
+    // posting it while that queue is full, see `CqAttr::track_outstanding()`.
+    #[inline]
+    pub(crate) fn post_op(&self, context: BindFlags, post: impl FnOnce() -> isize) -> isize {
+        let rooms = &self.inner.rooms;
+        let room = match context == BindFlags::RECV {
+            true => rooms.rx.get(),
+            false => rooms.tx.get(),
+        };
+        let Some(room) = room else {
+            return post();
+        };
+        if !room.reserve() {
+            return -(ffi::FI_EAGAIN as isize);
+        }
+        let ret = post();
+        if ret < 0 {
+            room.release(1);
+        }
+        ret
+    }
+
+    /// Number of operations that can still be posted to the transmit queue.
+    pub fn tx_size_left(&self) -> Result<usize> {
+        check_len("fi_tx_size_left", unsafe {
+            ffi::fi_tx_size_left(self.as_raw())
+        })
+    }
+
+    /// Number of operations that can still be posted to the receive queue.
+    pub fn rx_size_left(&self) -> Result<usize> {
+        check_len("fi_rx_size_left", unsafe {
+            ffi::fi_rx_size_left(self.as_raw())
+        })
+    }
+}
+
+/// Messages (`fi_msg(3)`), matched with the receives posted in order of arrival. The endpoint
+/// must have been opened with [`Caps::MSG`](crate::Caps::MSG).
+impl<M: ThreadingModel, P: HasMsg> Endpoint<M, Enabled, P> {
+    /// Post a receive buffer, via `fi_recv()`.
+    ///
+    /// # Safety
+    ///
+    /// See the type level documentation.
+    pub unsafe fn recv(
+        &self,
+        buf: &mut [u8],
+        mr: Option<&MemoryRegion<M>>,
+        src: Addr,
+        context: usize,
+    ) -> Result<()> {
+        self.check_op(|| Op::new("fi_recv", Kind::Recv, buf.len()))?;
+        trace::data_op!(self, "fi_recv", size = buf.len());
+        let ret = self.post_op(BindFlags::RECV, || {
+            trace::tracked!(self, "fi_recv", context, None, [writes(buf)], unsafe {
+                ffi::fi_recv(
+                    self.as_raw(),
+                    buf.as_mut_ptr().cast(),
+                    buf.len(),
+                    desc(mr),
+                    src.as_raw(),
+                    context as *mut _,
+                )
+            })
+        });
+        check_len("fi_recv", ret).map(|_| ())
+    }
+
+    /// Post a receive buffer with the flags of the operation, via `fi_recvmsg()`, ex:
+    /// [`OpFlags::COMPLETION`] for a completion on a queue bound with
+    /// [`BindFlags::SELECTIVE_COMPLETION`].
+    ///
+    /// # Safety
+    ///
+    /// See the type level documentation.
+    pub unsafe fn recv_with_flags(
+        &self,
+        buf: &mut [u8],
+        mr: Option<&MemoryRegion<M>>,
+        src: Addr,
+        context: usize,
+        flags: OpFlags,
+    ) -> Result<()> {
+        self.check_op(|| Op::new("fi_recvmsg", Kind::Recv, buf.len()).flags(flags))?;
+        trace::data_op!(self, "fi_recvmsg", size = buf.len());
+        let iov = ffi::iovec {
+            iov_base: buf.as_mut_ptr().cast(),
+            iov_len: buf.len(),
+        };
+        let mut desc = desc(mr);
+        let msg = ffi::fi_msg {
+            msg_iov: &iov,
+            desc: &mut desc,
+            iov_count: 1,
+            addr: src.as_raw(),
+            context: context as *mut _,
+            data: 0,
+        };
+        let ret = self.post_op(BindFlags::RECV, || {
+            trace::tracked!(
+                self,
+                "fi_recvmsg",
+                context,
+                Some(flags),
+                [writes(buf)],
+                unsafe { ffi::fi_recvmsg(self.as_raw(), &msg, flags.bits()) }
+            )
+        });
+        check_len("fi_recvmsg", ret).map(|_| ())
+    }
+
+    /// Post a receive scattered into `bufs`, filled one after the other, via `fi_recvv()`. At
+    /// most [`RxAttr::iov_limit`](crate::RxAttr::iov_limit) buffers are taken.
+    ///
+    /// # Safety
+    ///
+    /// See the type level documentation.
+    pub unsafe fn recvv(
+        &self,
+        bufs: &mut [&mut [u8]],
+        mrs: &[&MemoryRegion<M>],
+        src: Addr,
+        context: usize,
+    ) -> Result<()> {
+        let iov: Vec<_> = bufs
+            .iter_mut()
+            .map(|buf| iovec(buf.as_mut_ptr(), buf.len()))
+            .collect();
+        let mut desc = descs(mrs, bufs.len())?;
+        self.check_op(|| Op::new("fi_recvv", Kind::Recv, crate::rma::total(&iov)).iov(iov.len()))?;
+        trace::data_op!(self, "fi_recvv", size = crate::rma::total(&iov));
+        let ret = self.post_op(BindFlags::RECV, || {
+            trace::tracked!(
+                self,
+                "fi_recvv",
+                context,
+                None,
+                [writes_iov(&iov)],
+                unsafe {
+                    ffi::fi_recvv(
+                        self.as_raw(),
+                        iov.as_ptr(),
+                        desc.as_mut_ptr(),
+                        iov.len(),
+                        src.as_raw(),
+                        context as *mut _,
+                    )
+                }
+            )
+        });
+        check_len("fi_recvv", ret).map(|_| ())
+    }
+
+    /// Post a multi-receive buffer, via `fi_recvmsg()` with `FI_MULTI_RECV`: messages land one
+    /// after the other in it, each with a completion whose [`buf()`](crate::Completion::buf)
+    /// points at it, until less than the
+    /// [minimum](Self::set_min_multi_recv) is left. The provider then releases the buffer,
+    /// flagging the last completion with `FI_MULTI_RECV`. Requires `Caps::MULTI_RECV`.
+    ///
+    /// # Safety
+    ///
+    /// See the type level documentation, the buffer being in use until released.
+    pub unsafe fn recv_multi(
+        &self,
+        buf: &mut [u8],
+        mr: Option<&MemoryRegion<M>>,
+        src: Addr,
+        context: usize,
+    ) -> Result<()> {
+        unsafe { self.recv_with_flags(buf, mr, src, context, OpFlags::MULTI_RECV) }
+    }
+
+    /// Send a message, via `fi_send()`.
+    ///
+    /// # Safety
+    ///
+    /// See the type level documentation.
+    pub unsafe fn send(
+        &self,
+        buf: &[u8],
+        mr: Option<&MemoryRegion<M>>,
+        dest: Addr,
+        context: usize,
+    ) -> Result<()> {
+        self.check_op(|| Op::new("fi_send", Kind::Send, buf.len()))?;
+        trace::data_op!(self, "fi_send", size = buf.len());
+        let ret = self.post_op(BindFlags::TRANSMIT, || {
+            trace::tracked!(self, "fi_send", context, None, [reads(buf)], unsafe {
+                ffi::fi_send(
+                    self.as_raw(),
+                    buf.as_ptr().cast(),
+                    buf.len(),
+                    desc(mr),
+                    dest.as_raw(),
+                    context as *mut _,
+                )
+            })
+        });
+        check_len("fi_send", ret).map(|_| ())
+    }
+
+    /// Send a message with the flags of the operation, via `fi_sendmsg()`, ex:
+    /// [`OpFlags::COMPLETION`] for a completion on a queue bound with
+    /// [`BindFlags::SELECTIVE_COMPLETION`].
+    ///
+    /// # Safety
+    ///
+    /// See the type level documentation.
+    pub unsafe fn send_with_flags(
+        &self,
+        buf: &[u8],
+        mr: Option<&MemoryRegion<M>>,
+        dest: Addr,
+        context: usize,
+        flags: OpFlags,
+    ) -> Result<()> {
+        self.check_op(|| Op::new("fi_sendmsg", Kind::Send, buf.len()).flags(flags))?;
+        trace::data_op!(self, "fi_sendmsg", size = buf.len());
+        let iov = ffi::iovec {
+            iov_base: buf.as_ptr() as *mut _,
+            iov_len: buf.len(),
+        };
+        let mut desc = desc(mr);
+        let msg = ffi::fi_msg {
+            msg_iov: &iov,
+            desc: &mut desc,
+            iov_count: 1,
+            addr: dest.as_raw(),
+            context: context as *mut _,
+            data: 0,
+        };
+        let ret = self.post_op(BindFlags::TRANSMIT, || {
+            trace::tracked!(
+                self,
+                "fi_sendmsg",
+                context,
+                Some(flags),
+                [reads(buf)],
+                unsafe { ffi::fi_sendmsg(self.as_raw(), &msg, flags.bits()) }
+            )
+        });
+        check_len("fi_sendmsg", ret).map(|_| ())
+    }
+
+    /// Send a message gathered from `bufs`, one after the other, via `fi_sendv()`. At most
+    /// [`TxAttr::iov_limit`](crate::TxAttr::iov_limit) buffers are taken.
+    ///
+    /// # Safety
+    ///
+    /// See the type level documentation.
+    pub unsafe fn sendv(
+        &self,
+        bufs: &[&[u8]],
+        mrs: &[&MemoryRegion<M>],
+        dest: Addr,
+        context: usize,
+    ) -> Result<()> {
+        let iov: Vec<_> = bufs
+            .iter()
+            .map(|buf| iovec(buf.as_ptr(), buf.len()))
+            .collect();
+        let mut desc = descs(mrs, bufs.len())?;
+        self.check_op(|| Op::new("fi_sendv", Kind::Send, crate::rma::total(&iov)).iov(iov.len()))?;
+        trace::data_op!(self, "fi_sendv", size = crate::rma::total(&iov));
+        let ret = self.post_op(BindFlags::TRANSMIT, || {
+            trace::tracked!(self, "fi_sendv", context, None, [reads_iov(&iov)], unsafe {
+                ffi::fi_sendv(
+                    self.as_raw(),
+                    iov.as_ptr(),
+                    desc.as_mut_ptr(),
+                    iov.len(),
+                    dest.as_raw(),
+                    context as *mut _,
+                )
+            })
+        });
+        check_len("fi_sendv", ret).map(|_| ())
+    }
+
+    /// Send a message with remote CQ data, via `fi_senddata()`.
+    ///
+    /// # Safety
+    ///
+    /// See the type level documentation.
+    pub unsafe fn senddata(
+        &self,
+        buf: &[u8],
+        mr: Option<&MemoryRegion<M>>,
+        data: u64,
+        dest: Addr,
+        context: usize,
+    ) -> Result<()> {
+        self.check_op(|| Op::new("fi_senddata", Kind::Send, buf.len()).data())?;
+        trace::data_op!(self, "fi_senddata", size = buf.len());
+        let ret = self.post_op(BindFlags::TRANSMIT, || {
+            trace::tracked!(self, "fi_senddata", context, None, [reads(buf)], unsafe {
+                ffi::fi_senddata(
+                    self.as_raw(),
+                    buf.as_ptr().cast(),
+                    buf.len(),
+                    desc(mr),
+                    data,
+                    dest.as_raw(),
+                    context as *mut _,
+                )
+            })
+        });
+        check_len("fi_senddata", ret).map(|_| ())
+    }
+
+    /// Send a small message, via `fi_inject()`. The buffer may be reused once this returns, and
+    /// no completion is generated.
+    pub fn inject(&self, buf: &[u8], dest: Addr) -> Result<()> {
+        self.check_op(|| Op::new("fi_inject", Kind::Send, buf.len()).inject())?;
+        trace::data_op!(self, "fi_inject", size = buf.len());
+        let ret =
+            unsafe { ffi::fi_inject(self.as_raw(), buf.as_ptr().cast(), buf.len(), dest.as_raw()) };
+        check_len("fi_inject", ret).map(|_| ())
+    }
+
+    /// Like [`inject()`](Self::inject), with remote CQ data.
+    pub fn injectdata(&self, buf: &[u8], data: u64, dest: Addr) -> Result<()> {
+        self.check_op(|| {
+            Op::new("fi_injectdata", Kind::Send, buf.len())
+                .inject()
+                .data()
+        })?;
+        trace::data_op!(self, "fi_injectdata", size = buf.len());
+        let ret = unsafe {
+            ffi::fi_injectdata(
+                self.as_raw(),
+                buf.as_ptr().cast(),
+                buf.len(),
+                data,
+                dest.as_raw(),
+            )
+        };
+        check_len("fi_injectdata", ret).map(|_| ())
+    }
+}
+
+impl<M: ThreadingModel, S: EndpointState, P: CapProfile> AsRawFid for Endpoint<M, S, P> {
+    fn as_raw_fid(&self) -> *mut ffi::fid {
+        self.inner.fid.as_fid()
+    }
+}
+
+/// A passive endpoint (`fid_pep`), listening for connection requests.
+pub struct PassiveEndpoint {
+    fid: OwnedFid<ffi::fid_pep>,
+    eq: Mutex<Option<EventQueue>>,
+    fabric: Fabric,
+}
+
+impl PassiveEndpoint {
+    pub(crate) fn open(fabric: &Fabric, info: &InfoEntry) -> Result<Self> {
+        let fid = OwnedFid::open("fi_passive_ep", |pep| unsafe {
+            ffi::fi_passive_ep(fabric.as_raw(), info.as_raw(), pep, ptr::null_mut())
+        })?;
+        Ok(PassiveEndpoint {
+            fid,
+            eq: Mutex::new(None),
+            fabric: fabric.clone(),
+        })
+    }
+
+    pub fn fabric(&self) -> &Fabric {
+        &self.fabric
+    }
+
+    /// Bind the event queue which receives `FI_CONNREQ` events.
+    pub fn bind_eq(&self, eq: &EventQueue) -> Result<()> {
+        check("fi_pep_bind", unsafe {
+            ffi::fi_pep_bind(self.as_raw(), eq.as_raw_fid(), 0)
+        })?;
+        *self.eq.lock().unwrap() = Some(eq.clone());
+        Ok(())
+    }
+
+    pub fn as_raw(&self) -> *mut ffi::fid_pep {
+        self.fid.as_ptr()
+    }
+}
+
+impl AsRawFid for PassiveEndpoint {
+    fn as_raw_fid(&self) -> *mut ffi::fid {
+        self.fid.as_fid()
+    }
+}
+
+/// A scalable endpoint (`fi_scalable_ep()`), which transfers data through transmit and receive
+/// contexts opened as endpoints of their own, up to the
+/// [`tx_ctx_cnt`](crate::EpAttr::tx_ctx_cnt) and [`rx_ctx_cnt`](crate::EpAttr::rx_ctx_cnt) of
+/// its entry, requested with [`Info::contexts()`](crate::Info::contexts). Each context is
+/// bound to queues of its own, so that threads posting on different contexts do not contend,
+/// while peers reach all of them at the one address of the scalable endpoint.
+///
+/// ```no_run
+/// # fn run(fabric: &libfabric::Fabric) -> libfabric::Result<()> {
+/// use libfabric::{AvAttr, BindFlags, CqAttr, Domain, EndpointType, Info};
+///
+/// let entries = Info::new().ep_type(EndpointType::Rdm).contexts(4, 4).get()?;
+/// let domain = Domain::open(fabric, &entries[0])?;
+/// let sep = domain.scalable_endpoint(&entries[0])?;
+/// sep.bind_av(&domain.av(&AvAttr::new())?)?;
+/// let cq = domain.cq(&CqAttr::new())?;
+/// let tx = sep.tx_context(0, None)?.bind_cq(&cq, BindFlags::TRANSMIT)?.enable()?;
+/// sep.enable()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ScalableEndpoint<M: ThreadingModel = ThreadSafe> {
+    inner: Arc<EpInner<M>>,
+}
+
+impl<M: ThreadingModel> Clone for ScalableEndpoint<M> {
+    fn clone(&self) -> Self {
+        ScalableEndpoint {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<M: ThreadingModel> ScalableEndpoint<M> {
+    // The handles to the endpoint, this one included.
+    pub(crate) fn handles(&self) -> usize {
+        Arc::strong_count(&self.inner)
+    }
+
+    // The objects bound to the endpoint, closed after it.
+    pub(crate) fn bound_objects(&self) -> Vec<Object<M>> {
+        bound_objects(&self.inner)
+    }
+
+    // Close the endpoint, via `fi_close()`, failing unless this is the last handle to it.
+    pub(crate) fn close(self) -> Result<()> {
+        crate::fid::close_last(self.inner, |inner| &mut inner.fid)
+    }
+
+    pub(crate) fn open(domain: &Domain<M>, info: &InfoEntry) -> Result<Self> {
+        let claim = domain.claim(&[Resource::Endpoint])?;
+        let fid = OwnedFid::open("fi_scalable_ep", |sep| unsafe {
+            ffi::fi_scalable_ep(domain.as_raw(), info.as_raw(), sep, ptr::null_mut())
+        })?;
+        Ok(ScalableEndpoint {
+            inner: Arc::new(EpInner {
+                fid,
+                bound: Mutex::new(Vec::new()),
+                orphans: Mutex::new(Vec::new()),
+                info: info.clone(),
+                domain: domain.clone(),
+                limits: OnceLock::new(),
+                rooms: Rooms::default(),
+                claim: Some(claim),
+            }),
+        })
+    }
+
+    /// The entry the endpoint was opened from.
+    pub fn info(&self) -> &InfoEntry {
+        &self.inner.info
+    }
+
+    pub fn domain(&self) -> &Domain<M> {
+        &self.inner.domain
+    }
+
+    /// The transmit contexts which may be opened.
+    pub fn tx_ctx_cnt(&self) -> usize {
+        self.inner.info.ep_attr().tx_ctx_cnt
+    }
+
+    /// The receive contexts which may be opened.
+    pub fn rx_ctx_cnt(&self) -> usize {
+        self.inner.info.ep_attr().rx_ctx_cnt
+    }
+
+    /// Bind the address vector used to resolve peer addresses, shared by the contexts.
+    pub fn bind_av(&self, av: &AddressVector<M>) -> Result<()> {
+        check("fi_scalable_ep_bind", unsafe {
+            ffi::fi_scalable_ep_bind(self.as_raw(), av.as_raw_fid(), 0)
+        })?;
+        self.inner
+            .bound
+            .lock()
+            .unwrap()
+            .push(BoundFid::Av(av.clone()));
+        Ok(())
+    }
+
+    /// Enable the endpoint once its address vector is bound, via `fi_enable()`.
+    pub fn enable(&self) -> Result<()> {
+        check("fi_enable", unsafe { ffi::fi_enable(self.as_raw()) })
+    }
+
+    /// Open the transmit context `index`, via `fi_tx_context()`, with the settings of `attr`
+    /// over those of the entry. It keeps the scalable endpoint alive, and is bound and
+    /// enabled like any endpoint.
+    pub fn tx_context(
+        &self,
+        index: usize,
+        attr: Option<&TxQueueAttr>,
+    ) -> Result<Endpoint<M, Created>> {
+        let index = self.context_index("transmit", index, self.tx_ctx_cnt())?;
+        let mut raw = attr.map(|attr| {
+            let mut raw = unsafe { *(*self.info().as_raw()).tx_attr };
+            attr.apply(&mut raw);
+            raw
+        });
+        let raw = raw.as_mut().map_or(ptr::null_mut(), ptr::from_mut);
+        let claim = self.domain().claim(&[Resource::TxContext])?;
+        let fid = OwnedFid::open("fi_tx_context", |tx| unsafe {
+            ffi::fi_tx_context(self.as_raw(), index, raw, tx, ptr::null_mut())
+        })?;
+        Ok(self.context(fid, claim))
+    }
+
+    /// Open the receive context `index`, via `fi_rx_context()`, like
+    /// [`tx_context()`](Self::tx_context).
+    pub fn rx_context(
+        &self,
+        index: usize,
+        attr: Option<&RxQueueAttr>,
+    ) -> Result<Endpoint<M, Created>> {
+        let index = self.context_index("receive", index, self.rx_ctx_cnt())?;
+        let mut raw = attr.map(|attr| {
+            let mut raw = unsafe { *(*self.info().as_raw()).rx_attr };
+            attr.apply(&mut raw);
+            raw
+        });
+        let raw = raw.as_mut().map_or(ptr::null_mut(), ptr::from_mut);
+        let claim = self.domain().claim(&[Resource::RxContext])?;
+        let fid = OwnedFid::open("fi_rx_context", |rx| unsafe {
+            ffi::fi_rx_context(self.as_raw(), index, raw, rx, ptr::null_mut())
+        })?;
+        Ok(self.context(fid, claim))
+    }
+
+    fn context_index(&self, what: &str, index: usize, count: usize) -> Result<i32> {
+        if index >= count {
+            return Err(Error::invalid(format!(
+                "{what} context {index} of a scalable endpoint with {count}"
+            )));
+        }
+        i32::try_from(index).map_err(|_| Error::invalid(format!("{what} context {index}")))
+    }
+
+    fn context(&self, fid: OwnedFid<ffi::fid_ep>, claim: Claim) -> Endpoint<M, Created> {
+        Endpoint {
+            inner: Arc::new(EpInner {
+                fid,
+                bound: Mutex::new(vec![BoundFid::Scalable(self.clone())]),
+                orphans: Mutex::new(Vec::new()),
+                info: self.inner.info.clone(),
+                domain: self.inner.domain.clone(),
+                limits: OnceLock::new(),
+                rooms: Rooms::default(),
+                claim: Some(claim),
+            }),
+            state: PhantomData,
+        }
+    }
+
+    pub fn as_raw(&self) -> *mut ffi::fid_ep {
+        self.inner.fid.as_ptr()
+    }
+}
+
+// The resources an endpoint of `info` counts for: itself, and its contexts unless it shares them.
+fn resources(info: &InfoEntry) -> Vec<Resource> {
+    let attr = info.ep_attr();
+    let shared = ffi::FI_SHARED_CONTEXT as usize;
+    [
+        Some(Resource::Endpoint),
+        (attr.tx_ctx_cnt != shared).then_some(Resource::TxContext),
+        (attr.rx_ctx_cnt != shared).then_some(Resource::RxContext),
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+fn bound_objects<M: ThreadingModel>(inner: &EpInner<M>) -> Vec<Object<M>> {
+    let bound = inner.bound.lock().unwrap();
+    bound
+        .iter()
+        .map(|bound| match bound {
+            BoundFid::Cq(cq) => Object::Cq(cq.clone()),
+            BoundFid::Eq(eq) => Object::Eq(eq.clone()),
+            BoundFid::Av(av) => Object::Av(av.clone()),
+            BoundFid::Cntr(cntr) => Object::Cntr(cntr.clone()),
+            BoundFid::Aliased(ep) => Object::Ep(ep.clone()),
+            BoundFid::Scalable(sep) => Object::Scalable(sep.clone()),
+        })
+        .collect()
+}
+
+impl<M: ThreadingModel> AsRawFid for ScalableEndpoint<M> {
+    fn as_raw_fid(&self) -> *mut ffi::fid {
+        self.inner.fid.as_fid()
+    }
+}
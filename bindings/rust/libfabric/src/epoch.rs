@@ -0,0 +1,187 @@
+use crate::av::Addr;
+use crate::cntr::Counter;
+use crate::ep::Endpoint;
+use crate::error::{Error, Result};
+use crate::flags::OpFlags;
+use crate::mr::MemoryRegion;
+use crate::threading::{ThreadSafe, ThreadingModel};
+use ofi_libfabric_sys::bindgen as ffi;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Epochs of one-sided operations, as with `MPI_Win_fence()`: the reads and writes of an
+/// epoch are posted through it and counted on a counter, and the epoch completes once the
+/// counter counts them all, after which the next one starts.
+///
+/// The counter must be [blocking](crate::CntrAttr::blocking), and bound to the endpoint with
+/// [`BindFlags::READ`](crate::BindFlags::READ) and [`BindFlags::WRITE`](crate::BindFlags::WRITE).
+/// The operations go without a completion queue entry where it is bound with
+/// [`BindFlags::SELECTIVE_COMPLETION`](crate::BindFlags::SELECTIVE_COMPLETION), which the
+/// operations of the endpoint outside of epochs then request with [`OpFlags::COMPLETION`].
+///
+/// [`complete_epoch()`](Self::complete_epoch) waits for the local completion of the
+/// operations: the buffers of the writes may be reused, and those of the reads hold their
+/// data. [`remote_complete_epoch()`](Self::remote_complete_epoch) also waits for the writes to
+/// be visible at their targets, through a fenced read of each target, or at once for an epoch
+/// posting its writes with [`OpFlags::DELIVERY_COMPLETE`].
+///
+/// ```no_run
+/// use libfabric::{Addr, Counter, Endpoint, RmaEpoch};
+///
+/// # fn run(ep: Endpoint, cntr: Counter, peers: &[(Addr, u64, u64)]) -> libfabric::Result<()> {
+/// let halo = [0u8; 4096];
+/// let mut epoch = RmaEpoch::new(&ep, &cntr);
+/// for &(peer, addr, key) in peers {
+///     unsafe { epoch.write(&halo, None, peer, addr, key)? };
+/// }
+/// // The peers may read their halo once they learn that this returned.
+/// epoch.remote_complete_epoch(None)?;
+/// # Ok(())
+/// # }
+/// ```
+#[must_use]
+pub struct RmaEpoch<'e, M: ThreadingModel = ThreadSafe> {
+    ep: &'e Endpoint<M>,
+    cntr: &'e Counter<M>,
+    delivery_complete: bool,
+    // The counts of the counter when the epoch started.
+    base: u64,
+    errors: u64,
+    issued: u64,
+    // A segment of each target written in the epoch, which its flush reads.
+    written: HashMap<Addr, (u64, u64)>,
+    epochs: u64,
+}
+
+impl<'e, M: ThreadingModel> RmaEpoch<'e, M> {
+    /// Start an epoch of the operations of `ep`, counted by `cntr`.
+    pub fn new(ep: &'e Endpoint<M>, cntr: &'e Counter<M>) -> Self {
+        RmaEpoch {
+            ep,
+            cntr,
+            delivery_complete: false,
+            base: cntr.read(),
+            errors: cntr.read_err(),
+            issued: 0,
+            written: HashMap::new(),
+            epochs: 0,
+        }
+    }
+
+    /// Post the writes with [`OpFlags::DELIVERY_COMPLETE`], such that they complete once
+    /// visible at their target, and remote completion needs no flush. Writes complete later,
+    /// but epochs writing to many targets complete sooner than after a read of each.
+    pub fn delivery_complete(mut self) -> Self {
+        self.delivery_complete = true;
+        self
+    }
+
+    /// Write `buf` to remote memory within the epoch.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation: `buf` must stay valid until the epoch completes.
+    pub unsafe fn write(
+        &mut self,
+        buf: &[u8],
+        mr: Option<&MemoryRegion<M>>,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+    ) -> Result<()> {
+        let flags = match self.delivery_complete {
+            true => OpFlags::DELIVERY_COMPLETE,
+            false => OpFlags::empty(),
+        };
+        unsafe { self.ep.write_with_flags(buf, mr, dest, addr, key, 0, flags) }?;
+        self.issued += 1;
+        self.written.insert(dest, (addr, key));
+        Ok(())
+    }
+
+    /// Read remote memory into `buf` within the epoch.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation: `buf` must stay valid until the epoch completes.
+    pub unsafe fn read(
+        &mut self,
+        buf: &mut [u8],
+        mr: Option<&MemoryRegion<M>>,
+        src: Addr,
+        addr: u64,
+        key: u64,
+    ) -> Result<()> {
+        unsafe {
+            self.ep
+                .read_with_flags(buf, mr, src, addr, key, 0, OpFlags::empty())
+        }?;
+        self.issued += 1;
+        Ok(())
+    }
+
+    /// The operations posted in the epoch.
+    pub fn issued(&self) -> u64 {
+        self.issued
+    }
+
+    /// The operations of the epoch not completed yet.
+    pub fn pending(&self) -> u64 {
+        let done = (self.cntr.read() - self.base) + (self.cntr.read_err() - self.errors);
+        self.issued.saturating_sub(done)
+    }
+
+    /// The epochs completed so far.
+    pub fn epochs(&self) -> u64 {
+        self.epochs
+    }
+
+    /// Block until the operations of the epoch completed locally, then start the next one.
+    /// Fails with `FI_EIO` if any of them failed, once all are done, and with `FI_ETIMEDOUT`
+    /// if they are not done within `timeout`, the epoch going on then.
+    pub fn complete_epoch(&mut self, timeout: Option<Duration>) -> Result<()> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            let errors = self.cntr.read_err() - self.errors;
+            let threshold = self.base + self.issued.saturating_sub(errors);
+            let left = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            match self.cntr.wait(threshold, left) {
+                Ok(()) => break,
+                // Errors counted since unblock the wait, which goes on for the others.
+                Err(_) if self.cntr.read_err() - self.errors > errors => {}
+                Err(err) => return Err(err),
+            }
+        }
+        let failed = self.cntr.read_err() - self.errors;
+        self.base = self.cntr.read();
+        self.errors = self.cntr.read_err();
+        self.issued = 0;
+        self.written.clear();
+        self.epochs += 1;
+        match failed {
+            0 => Ok(()),
+            _ => Err(Error::fabric("fi_cntr_readerr", ffi::FI_EIO as i64)),
+        }
+    }
+
+    /// Like [`complete_epoch()`](Self::complete_epoch), also waiting for the writes to be
+    /// visible at their targets, by reading nothing from each target written with
+    /// [`OpFlags::FENCE`], which requires [`Caps::FENCE`](crate::Caps::FENCE): the read
+    /// only starts once the writes before it completed, and completes from the target.
+    pub fn remote_complete_epoch(&mut self, timeout: Option<Duration>) -> Result<()> {
+        if !self.delivery_complete {
+            // Targets whose flush fails to post stay written, for the next call.
+            let written: Vec<_> = self.written.iter().map(|(&t, &seg)| (t, seg)).collect();
+            for (target, (addr, key)) in written {
+                // SAFETY: The read is of no bytes.
+                unsafe {
+                    self.ep
+                        .read_with_flags(&mut [], None, target, addr, key, 0, OpFlags::FENCE)
+                }?;
+                self.written.remove(&target);
+                self.issued += 1;
+            }
+        }
+        self.complete_epoch(timeout)
+    }
+}
@@ -0,0 +1,428 @@
+use crate::error::{Error, Result, check_len};
+use crate::fabric::Fabric;
+use crate::fid::{AsRawFid, FidId, OwnedFid};
+use crate::info::InfoEntry;
+use crate::util::{cstr, timeout_ms};
+use crate::wait::{WaitObj, WaitTarget};
+use ofi_libfabric_sys::bindgen as ffi;
+use std::mem;
+use std::ptr;
+use std::sync::Arc;
+use std::time::Duration;
+
+// Room for a CM entry plus its private data; providers cap private data well below this.
+const EQ_BUF_WORDS: usize = 64;
+
+/// The event number [`EventQueue::write()`] writes the events of applications at, offset by
+/// theirs, above those of libfabric.
+pub const EQ_USER_EVENT_BASE: u32 = 1 << 16;
+
+/// The largest payload of the events written with [`EventQueue::write()`], as much as is read.
+pub const EQ_USER_EVENT_MAX: usize = EQ_BUF_WORDS * 8;
+
+/// Attributes for opening an event queue.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[must_use]
+pub struct EqAttr {
+    size: usize,
+    blocking: bool,
+    pollable: bool,
+    writable: bool,
+    wait: Option<WaitObj>,
+    signaling_vector: Option<i32>,
+}
+
+impl EqAttr {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Minimum number of entries, 0 lets the provider pick.
+    pub fn size(mut self, size: usize) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// Open the queue with a wait object, so [`EventQueue::sread()`] can block.
+    pub fn blocking(mut self, blocking: bool) -> Self {
+        self.blocking = blocking;
+        self
+    }
+
+    /// Open the queue with a file descriptor wait object, so it can be polled along with other
+    /// descriptors through [`EventQueue::wait_fd()`]. This also allows blocking as above.
+    #[cfg(unix)]
+    pub fn pollable(mut self, pollable: bool) -> Self {
+        self.pollable = pollable;
+        self
+    }
+
+    /// Open the queue for the events of the application (`FI_WRITE`), written with
+    /// [`EventQueue::write()`].
+    pub fn writable(mut self, writable: bool) -> Self {
+        self.writable = writable;
+        self
+    }
+
+    /// Open the queue with `obj` as its wait object, rather than the one picked from
+    /// [`blocking()`](Self::blocking) and [`pollable()`](Self::pollable). Opening fails with an
+    /// argument error if the provider is known not to support it, see [`WaitObj::supported()`].
+    pub fn wait_obj(mut self, obj: WaitObj) -> Self {
+        self.wait = Some(obj);
+        self
+    }
+
+    // The wait object to open the queue with.
+    pub(crate) fn wait_object(&self) -> WaitObj {
+        self.wait
+            .unwrap_or_else(|| WaitObj::pick(self.blocking, self.pollable, false))
+    }
+
+    /// Signal the wait object of the queue through `vector` (`FI_AFFINITY`), ex: the interrupt
+    /// vector of the core the thread reading the queue is pinned to.
+    pub fn signaling_vector(mut self, vector: i32) -> Self {
+        self.signaling_vector = Some(vector);
+        self
+    }
+}
+
+/// An event read from an event queue.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum EqEvent {
+    /// A connection request arrived on a passive endpoint. Accept it by opening an endpoint
+    /// from `info`, or reject it through the passive endpoint.
+    ConnReq {
+        fid: FidId,
+        info: InfoEntry,
+        data: Vec<u8>,
+    },
+    /// A connection with the endpoint `fid` has been established.
+    Connected { fid: FidId, data: Vec<u8> },
+    /// The connection of endpoint `fid` has been shut down.
+    Shutdown { fid: FidId },
+    /// An asynchronous memory registration completed.
+    MrComplete {
+        fid: FidId,
+        context: usize,
+        data: u64,
+    },
+    /// An asynchronous address vector insertion completed, `data` entries were inserted.
+    AvComplete {
+        fid: FidId,
+        context: usize,
+        data: u64,
+    },
+    /// A multicast join completed.
+    JoinComplete {
+        fid: FidId,
+        context: usize,
+        data: u64,
+    },
+    /// An event of the application, written with [`EventQueue::write()`], with its number
+    /// and payload.
+    User { event: u32, data: Vec<u8> },
+    /// An event these bindings do not decode (ex: `FI_NOTIFY`, or one introduced by a newer
+    /// libfabric), along with the raw bytes of its entry.
+    Other { event: u32, data: Vec<u8> },
+}
+
+/// An error event, read with `fi_eq_readerr()`.
+#[derive(Debug, Clone)]
+pub struct EqErrEntry {
+    pub fid: FidId,
+    pub context: usize,
+    pub data: u64,
+    pub error: Error,
+    pub prov_errno: i32,
+    /// Provider specific description, from `fi_eq_strerror()`.
+    pub message: String,
+}
+
+/// An event queue (`fid_eq`).
+#[derive(Clone)]
+pub struct EventQueue {
+    inner: Arc<EqInner>,
+}
+
+struct EqInner {
+    fid: OwnedFid<ffi::fid_eq>,
+    fabric: Fabric,
+    wait: WaitObj,
+}
+
+impl EventQueue {
+    // The handles to the queue, this one included.
+    pub(crate) fn handles(&self) -> usize {
+        Arc::strong_count(&self.inner)
+    }
+
+    // Close the queue, via `fi_close()`, failing unless this is the last handle to it.
+    pub(crate) fn close(self) -> Result<()> {
+        crate::fid::close_last(self.inner, |inner| &mut inner.fid)
+    }
+
+    pub(crate) fn open(fabric: &Fabric, attr: &EqAttr) -> Result<Self> {
+        let wait = attr.wait_object();
+        let mut raw = ffi::fi_eq_attr {
+            size: attr.size,
+            flags: attr.signaling_vector.map_or(0, |_| ffi::FI_AFFINITY as u64)
+                | match attr.writable {
+                    true => ffi::FI_WRITE as u64,
+                    false => 0,
+                },
+            wait_obj: wait.check(fabric.info().provider_name(), WaitTarget::Eq)?,
+            signaling_vector: attr.signaling_vector.unwrap_or(0),
+            ..Default::default()
+        };
+        let fid = OwnedFid::open("fi_eq_open", |eq| unsafe {
+            ffi::fi_eq_open(fabric.as_raw(), &mut raw, eq, ptr::null_mut())
+        })?;
+        Ok(EventQueue {
+            inner: Arc::new(EqInner {
+                fid,
+                fabric: fabric.clone(),
+                wait: WaitObj::from_raw(raw.wait_obj).unwrap_or(wait),
+            }),
+        })
+    }
+
+    /// Take ownership of an event queue of `fabric` opened elsewhere, ex: by C code. Fails
+    /// with an invalid argument error if `eq` is null.
+    ///
+    /// # Safety
+    ///
+    /// `eq` must be an open event queue of `fabric`, which is closed by the returned value
+    /// only: once all handles to it are dropped, or released by [`into_raw()`](Self::into_raw).
+    pub unsafe fn from_raw(fabric: &Fabric, eq: *mut ffi::fid_eq) -> Result<Self> {
+        let fid = unsafe { OwnedFid::from_raw(eq) }?;
+        Ok(EventQueue {
+            inner: Arc::new(EqInner {
+                wait: crate::wait::wait_obj_of(fid.as_fid()),
+                fid,
+                fabric: fabric.clone(),
+            }),
+        })
+    }
+
+    /// Hand the queue over, ex: to C code, whose `fi_close()` then closes it. Fails unless this
+    /// is the last handle to it, which the endpoints bound to it hold too. The fabric is
+    /// released: it must be kept open until the queue is closed.
+    pub fn into_raw(self) -> Result<*mut ffi::fid_eq> {
+        crate::fid::release_last(self.inner, |inner| &mut inner.fid)
+    }
+
+    pub fn fabric(&self) -> &Fabric {
+        &self.inner.fabric
+    }
+
+    /// The wait object of the queue, as the provider resolved [`WaitObj::Unspec`] if it did.
+    pub fn wait_obj(&self) -> WaitObj {
+        self.inner.wait
+    }
+
+    /// Write an event of the application, read back as [`EqEvent::User`] along with those of
+    /// libfabric, ex: for the control plane of the application to be served by the loop reading
+    /// connection events. Requires a queue opened with [`EqAttr::writable()`]. Fails with an
+    /// argument error when `event` overflows past [`EQ_USER_EVENT_BASE`], or `data` is longer
+    /// than [`EQ_USER_EVENT_MAX`].
+    pub fn write(&self, event: u32, data: &[u8]) -> Result<()> {
+        let raw = EQ_USER_EVENT_BASE
+            .checked_add(event)
+            .ok_or_else(|| Error::invalid(format!("user event {event} is out of range")))?;
+        if data.len() > EQ_USER_EVENT_MAX {
+            return Err(Error::invalid(format!(
+                "user event of {} bytes exceeds {EQ_USER_EVENT_MAX}",
+                data.len()
+            )));
+        }
+        let ret =
+            unsafe { ffi::fi_eq_write(self.as_raw(), raw, data.as_ptr().cast(), data.len(), 0) };
+        check_len("fi_eq_write", ret).map(drop)
+    }
+
+    /// Read one event without blocking.
+    ///
+    /// Fails with an error for which [`Error::is_avail()`] holds when an error event is pending,
+    /// which must then be consumed with [`read_err()`](Self::read_err).
+    pub fn read(&self) -> Result<Option<EqEvent>> {
+        let mut event = 0;
+        let mut buf = [0u64; EQ_BUF_WORDS];
+        let ret = unsafe {
+            ffi::fi_eq_read(
+                self.as_raw(),
+                &mut event,
+                buf.as_mut_ptr().cast(),
+                mem::size_of_val(&buf),
+                0,
+            )
+        };
+        self.decode("fi_eq_read", ret, event, &buf)
+    }
+
+    /// Block until an event is available, or the timeout expires.
+    ///
+    /// Requires a queue opened with [`EqAttr::blocking()`].
+    pub fn sread(&self, timeout: Option<Duration>) -> Result<Option<EqEvent>> {
+        let mut event = 0;
+        let mut buf = [0u64; EQ_BUF_WORDS];
+        let ret = unsafe {
+            ffi::fi_eq_sread(
+                self.as_raw(),
+                &mut event,
+                buf.as_mut_ptr().cast(),
+                mem::size_of_val(&buf),
+                timeout_ms(timeout),
+                0,
+            )
+        };
+        self.decode("fi_eq_sread", ret, event, &buf)
+    }
+
+    fn decode(
+        &self,
+        op: &'static str,
+        ret: isize,
+        event: u32,
+        buf: &[u64],
+    ) -> Result<Option<EqEvent>> {
+        let len = match check_len(op, ret) {
+            Err(err) if err.is_again() => return Ok(None),
+            Err(err) => return Err(err),
+            Ok(len) => len,
+        };
+        let cm = || {
+            let entry = unsafe { &*(buf.as_ptr() as *const ffi::fi_eq_cm_entry) };
+            let header = mem::size_of::<ffi::fi_eq_cm_entry>();
+            let data = unsafe {
+                std::slice::from_raw_parts(
+                    (buf.as_ptr() as *const u8).add(header),
+                    len.saturating_sub(header),
+                )
+            };
+            (entry, data.to_vec())
+        };
+        let entry = || unsafe { &*(buf.as_ptr() as *const ffi::fi_eq_entry) };
+        let event = match event {
+            ffi::FI_CONNREQ => {
+                let (entry, data) = cm();
+                let info = unsafe { InfoEntry::from_raw(entry.info) }
+                    .ok_or_else(|| Error::fabric(op, ffi::FI_EOTHER as i64))?;
+                EqEvent::ConnReq {
+                    fid: FidId::from_ptr(entry.fid),
+                    info,
+                    data,
+                }
+            }
+            ffi::FI_CONNECTED => {
+                let (entry, data) = cm();
+                EqEvent::Connected {
+                    fid: FidId::from_ptr(entry.fid),
+                    data,
+                }
+            }
+            ffi::FI_SHUTDOWN => EqEvent::Shutdown {
+                fid: FidId::from_ptr(cm().0.fid),
+            },
+            ffi::FI_MR_COMPLETE | ffi::FI_AV_COMPLETE | ffi::FI_JOIN_COMPLETE => {
+                let e = entry();
+                let (fid, context, data) = (FidId::from_ptr(e.fid), e.context as usize, e.data);
+                match event {
+                    ffi::FI_MR_COMPLETE => EqEvent::MrComplete { fid, context, data },
+                    ffi::FI_AV_COMPLETE => EqEvent::AvComplete { fid, context, data },
+                    _ => EqEvent::JoinComplete { fid, context, data },
+                }
+            }
+            event if event >= EQ_USER_EVENT_BASE => EqEvent::User {
+                event: event - EQ_USER_EVENT_BASE,
+                data: unsafe {
+                    std::slice::from_raw_parts(
+                        buf.as_ptr().cast::<u8>(),
+                        len.min(mem::size_of_val(buf)),
+                    )
+                }
+                .to_vec(),
+            },
+            event => EqEvent::Other {
+                event,
+                data: unsafe {
+                    std::slice::from_raw_parts(
+                        buf.as_ptr().cast::<u8>(),
+                        len.min(mem::size_of_val(buf)),
+                    )
+                }
+                .to_vec(),
+            },
+        };
+        Ok(Some(event))
+    }
+
+    /// Read one error event, if any.
+    pub fn read_err(&self) -> Result<Option<EqErrEntry>> {
+        let mut raw = ffi::fi_eq_err_entry::default();
+        match check_len("fi_eq_readerr", unsafe {
+            ffi::fi_eq_readerr(self.as_raw(), &mut raw, 0)
+        }) {
+            Err(err) if err.is_again() => return Ok(None),
+            Err(err) => return Err(err),
+            Ok(_) => {}
+        }
+        let mut buf = [0 as std::os::raw::c_char; 256];
+        let message = unsafe {
+            cstr(ffi::fi_eq_strerror(
+                self.as_raw(),
+                raw.prov_errno,
+                raw.err_data,
+                buf.as_mut_ptr(),
+                buf.len(),
+            ))
+        }
+        .to_owned();
+        Ok(Some(EqErrEntry {
+            fid: FidId::from_ptr(raw.fid),
+            context: raw.context as usize,
+            data: raw.data,
+            error: Error::fabric("fi_eq_read", raw.err as i64),
+            prov_errno: raw.prov_errno,
+            message,
+        }))
+    }
+
+    /// The descriptor of a [pollable](EqAttr::pollable) queue, readable once events may be
+    /// available. Call [`Fabric::trywait()`](crate::Fabric::trywait) before blocking on it.
+    #[cfg(unix)]
+    pub fn wait_fd(&self) -> Result<std::os::fd::RawFd> {
+        crate::wait::wait_fd(self.as_raw_fid(), self.inner.wait)
+    }
+
+    /// The descriptors of a queue opened with [`WaitObj::PollFd`], any of which is readable
+    /// once events may be available, see [`PollFdSet::refresh()`](crate::PollFdSet::refresh).
+    #[cfg(unix)]
+    pub fn wait_pollfd(&self) -> Result<crate::PollFdSet> {
+        crate::PollFdSet::fetch(self.as_raw_fid(), self.inner.wait)
+    }
+
+    /// Wait until the queue may have events to read, returning `false` if the timeout expired
+    /// first, for queues opened with a [pollable](WaitObj::is_pollable) wait object.
+    /// [`Fabric::trywait()`](crate::Fabric::trywait) is called first: the future is ready at
+    /// once if the queue has events to read already.
+    ///
+    /// Like the other futures of the `async` feature, it does not depend on a runtime: a
+    /// thread polls the descriptors of the wait object, which are fetched again by each
+    /// call for [`WaitObj::PollFd`], and wakes up the task.
+    #[cfg(all(unix, feature = "async"))]
+    pub async fn readable_async(&self, timeout: Option<Duration>) -> Result<bool> {
+        crate::wait::readable(self.fabric(), self, self.inner.wait, timeout)?.await
+    }
+
+    pub fn as_raw(&self) -> *mut ffi::fid_eq {
+        self.inner.fid.as_ptr()
+    }
+}
+
+impl AsRawFid for EventQueue {
+    fn as_raw_fid(&self) -> *mut ffi::fid {
+        self.inner.fid.as_fid()
+    }
+}
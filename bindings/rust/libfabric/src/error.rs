@@ -0,0 +1,118 @@
+use crate::resources::Resource;
+use ofi_libfabric_sys::bindgen as ffi;
+use std::ffi::CStr;
+use std::os::raw::c_int;
+use std::{fmt, io};
+
+/// Result type used throughout the safe API.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Errors returned by the safe API.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// A libfabric call returned a negative error code.
+    ///
+    /// `code` is stored as the positive `FI_E*` value, e.g. `FI_EAGAIN`.
+    Fabric { op: &'static str, code: i32 },
+    /// An argument was rejected before reaching libfabric.
+    InvalidArgument(String),
+    /// Opening an object would exceed the count of its resource in the attributes of the
+    /// domain, see [`Domain::set_limits_enforced()`](crate::Domain::set_limits_enforced).
+    LimitExceeded { resource: Resource, limit: usize },
+    /// An out of band channel failed, ex: the connections of the [`bootstrap`](crate::bootstrap).
+    Io {
+        kind: io::ErrorKind,
+        message: String,
+    },
+}
+
+impl Error {
+    pub(crate) fn fabric(op: &'static str, code: i64) -> Self {
+        Error::Fabric {
+            op,
+            code: code.unsigned_abs() as i32,
+        }
+    }
+
+    pub(crate) fn invalid(msg: impl Into<String>) -> Self {
+        Error::InvalidArgument(msg.into())
+    }
+
+    /// The positive `FI_E*` code of the error, `FI_EINVAL` for argument errors, `FI_ENOSPC`
+    /// for exceeded limits and `FI_EIO` for I/O errors.
+    pub fn code(&self) -> i32 {
+        match self {
+            Error::Fabric { code, .. } => *code,
+            Error::InvalidArgument(_) => ffi::FI_EINVAL as i32,
+            Error::LimitExceeded { .. } => ffi::FI_ENOSPC as i32,
+            Error::Io { .. } => ffi::FI_EIO as i32,
+        }
+    }
+
+    /// Whether the operation failed with `-FI_EAGAIN`, and should be retried after progress.
+    pub fn is_again(&self) -> bool {
+        self.code() == ffi::FI_EAGAIN as i32
+    }
+
+    /// Whether an error entry is waiting to be read from the queue (`-FI_EAVAIL`).
+    pub fn is_avail(&self) -> bool {
+        self.code() == ffi::FI_EAVAIL as i32
+    }
+
+    /// Whether the provider does not support the operation, or its datatype (`-FI_EOPNOTSUPP`
+    /// or `-FI_ENOSYS`).
+    pub fn is_unsupported(&self) -> bool {
+        matches!(self.code() as u32, ffi::FI_EOPNOTSUPP | ffi::FI_ENOSYS)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Fabric { op, code } => write!(f, "{op} failed: {} ({code})", strerror(*code)),
+            Error::InvalidArgument(msg) => write!(f, "invalid argument: {msg}"),
+            Error::LimitExceeded { resource, limit } => {
+                write!(f, "limit exceeded: the domain supports {limit} {resource}")
+            }
+            Error::Io { message, .. } => write!(f, "i/o error: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io {
+            kind: err.kind(),
+            message: err.to_string(),
+        }
+    }
+}
+
+/// Returns libfabric's description of an `FI_E*` code, via `fi_strerror()`.
+pub fn strerror(code: i32) -> String {
+    // SAFETY: fi_strerror always returns a pointer to a static, NUL terminated string.
+    unsafe { CStr::from_ptr(ffi::fi_strerror(code)) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+// Map the `int` return convention of libfabric control calls into a Result.
+pub(crate) fn check(op: &'static str, ret: c_int) -> Result<()> {
+    if ret < 0 {
+        Err(Error::fabric(op, ret as i64))
+    } else {
+        Ok(())
+    }
+}
+
+// Map the `ssize_t` return convention of libfabric data calls into a Result.
+pub(crate) fn check_len(op: &'static str, ret: isize) -> Result<usize> {
+    if ret < 0 {
+        Err(Error::fabric(op, ret as i64))
+    } else {
+        Ok(ret as usize)
+    }
+}
@@ -0,0 +1,87 @@
+use crate::error::{Error, Result, check};
+use ofi_libfabric_sys::bindgen as ffi;
+use std::ffi::CStr;
+use std::ptr;
+
+/// A table of provider specific operations, returned by `fi_open_ops()` under a well known name
+/// (ex: `FI_EFA_DOMAIN_OPS`). See [`AsRawFid::open_ops()`](crate::AsRawFid::open_ops).
+///
+/// The tables of the provider extension headers enabled through the crate features (`cxi`,
+/// `efa`, `usnic`) implement this trait. Tables of other providers, or of newer versions than
+/// the headers in use, are declared as `#[repr(C)]` structs matching their C layout, and looked
+/// up the same way: the reference returned borrows the object, so the table cannot be used
+/// once the object is closed.
+///
+/// ```no_run
+/// # use libfabric::Domain;
+/// # fn run(domain: &Domain) -> libfabric::Result<()> {
+/// use libfabric::{AsRawFid, Ops};
+/// use std::ffi::{CStr, c_int};
+/// use std::os::raw::c_void;
+///
+/// #[repr(C)]
+/// struct MyDomainOps {
+///     query: Option<unsafe extern "C" fn(domain: *mut c_void, value: *mut u64) -> c_int>,
+/// }
+///
+/// // SAFETY: the provider returns a `struct my_domain_ops` for "my domain ops".
+/// unsafe impl Ops for MyDomainOps {
+///     const NAME: &'static CStr = c"my domain ops";
+/// }
+///
+/// let ops = domain.open_ops::<MyDomainOps>()?;
+/// if let Some(query) = ops.query {
+///     let mut value = 0;
+///     unsafe { query(domain.as_raw_fid().cast(), &mut value) };
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Safety
+///
+/// Providers must return a pointer to a `Self` when `fi_open_ops()` is given `NAME`.
+pub unsafe trait Ops {
+    const NAME: &'static CStr;
+}
+
+macro_rules! ops {
+    ($($(#[$meta:meta])* $ty:ident => $name:ident),* $(,)?) => {
+        $($(#[$meta])*
+        unsafe impl Ops for ffi::$ty {
+            const NAME: &'static CStr = match CStr::from_bytes_with_nul(ffi::$name) {
+                Ok(name) => name,
+                Err(_) => panic!(concat!(stringify!($name), " is not NUL terminated")),
+            };
+        })*
+    };
+}
+
+ops! {
+    #[cfg(feature = "cxi")]
+    fi_cxi_dom_ops => FI_CXI_DOM_OPS_6,
+    #[cfg(feature = "cxi")]
+    fi_cxi_cntr_ops => FI_CXI_COUNTER_OPS,
+    #[cfg(feature = "efa")]
+    fi_efa_ops_domain => FI_EFA_DOMAIN_OPS,
+    #[cfg(feature = "efa")]
+    fi_efa_ops_gda => FI_EFA_GDA_OPS,
+    #[cfg(feature = "usnic")]
+    fi_usnic_ops_fabric => FI_USNIC_FABRIC_OPS_1,
+    #[cfg(feature = "usnic")]
+    fi_usnic_ops_av => FI_USNIC_AV_OPS_1,
+}
+
+// Look up the `T` operations of an object.
+//
+// SAFETY: The table is owned by the provider, and stays valid while the object is open.
+pub(crate) fn open_ops<'a, T: Ops>(fid: *mut ffi::fid) -> Result<&'a T> {
+    let mut ops = ptr::null_mut();
+    check("fi_open_ops", unsafe {
+        ffi::fi_open_ops(fid, T::NAME.as_ptr(), 0, &mut ops, ptr::null_mut())
+    })?;
+    if ops.is_null() {
+        return Err(Error::fabric("fi_open_ops", ffi::FI_ENOSYS as i64));
+    }
+    Ok(unsafe { &*ops.cast::<T>() })
+}
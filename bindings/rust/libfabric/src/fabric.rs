@@ -0,0 +1,134 @@
+use crate::domain::Domain;
+use crate::ep::PassiveEndpoint;
+use crate::eq::{EqAttr, EventQueue};
+use crate::error::{Error, Result};
+use crate::fid::{AsRawFid, OwnedFid};
+use crate::info::InfoEntry;
+use ofi_libfabric_sys::bindgen as ffi;
+use std::ptr;
+use std::sync::Arc;
+
+/// An open fabric (`fid_fabric`), the root of every other object.
+///
+/// Cloning is cheap and shares the same fabric, which is closed once the last clone, and every
+/// object opened from it, has been dropped.
+#[derive(Clone)]
+pub struct Fabric {
+    inner: Arc<FabricInner>,
+}
+
+struct FabricInner {
+    fid: OwnedFid<ffi::fid_fabric>,
+    info: InfoEntry,
+}
+
+impl Fabric {
+    // The handles to the fabric, this one included.
+    pub(crate) fn handles(&self) -> usize {
+        Arc::strong_count(&self.inner)
+    }
+
+    // Close the fabric, via `fi_close()`, failing unless this is the last handle to it.
+    pub(crate) fn close(self) -> Result<()> {
+        crate::fid::close_last(self.inner, |inner| &mut inner.fid)
+    }
+
+    /// Open the fabric described by a discovered entry, via `fi_fabric2()` with libfabric 2.0
+    /// and later, which hands the provider the whole entry, or else `fi_fabric()`.
+    pub fn open(info: &InfoEntry) -> Result<Self> {
+        #[cfg(libfabric_ge_2_0)]
+        let fid = OwnedFid::open("fi_fabric2", |fabric| unsafe {
+            ffi::fi_fabric2(info.as_raw(), fabric, 0, ptr::null_mut())
+        })?;
+        #[cfg(not(libfabric_ge_2_0))]
+        let fid = OwnedFid::open("fi_fabric", |fabric| unsafe {
+            ffi::fi_fabric((*info.as_raw()).fabric_attr, fabric, ptr::null_mut())
+        })?;
+        Ok(Fabric {
+            inner: Arc::new(FabricInner {
+                fid,
+                info: info.clone(),
+            }),
+        })
+    }
+
+    /// Open the fabric named `name` (`fabric_attr.name`, ex: `"172.16.0.0/16"`) from the first
+    /// of `entries` on it, for hosts on several fabrics, ex: separate storage and compute
+    /// fabrics. Fails with `FI_ENODATA` when none is.
+    pub fn open_named(entries: &[InfoEntry], name: &str) -> Result<Self> {
+        let entry = entries
+            .iter()
+            .find(|entry| entry.fabric_name() == name)
+            .ok_or(Error::fabric("fi_fabric", ffi::FI_ENODATA as i64))?;
+        Self::open(entry)
+    }
+
+    /// Take ownership of a fabric opened elsewhere, ex: by C code, from `info`. Fails with an
+    /// invalid argument error if `fabric` is null.
+    ///
+    /// # Safety
+    ///
+    /// `fabric` must be an open fabric, described by `info`, which is closed by the returned
+    /// value only: once all handles to it are dropped, or released by
+    /// [`into_raw()`](Self::into_raw).
+    pub unsafe fn from_raw(fabric: *mut ffi::fid_fabric, info: &InfoEntry) -> Result<Self> {
+        Ok(Fabric {
+            inner: Arc::new(FabricInner {
+                fid: unsafe { OwnedFid::from_raw(fabric) }?,
+                info: info.clone(),
+            }),
+        })
+    }
+
+    /// Hand the fabric over, ex: to C code, whose `fi_close()` then closes it. Fails unless
+    /// this is the last handle to it, which objects opened from it hold too.
+    pub fn into_raw(self) -> Result<*mut ffi::fid_fabric> {
+        crate::fid::release_last(self.inner, |inner| &mut inner.fid)
+    }
+
+    /// The entry the fabric was opened from.
+    pub fn info(&self) -> &InfoEntry {
+        &self.inner.info
+    }
+
+    /// Open a domain of this fabric.
+    pub fn domain(&self, info: &InfoEntry) -> Result<Domain> {
+        Domain::open(self, info)
+    }
+
+    /// Open the domain named `name` (`domain_attr.name`, ex: `"mlx5_1"`) of this fabric, from
+    /// the first of `entries` of the provider and fabric this one was opened from on it. Fails
+    /// with `FI_ENODATA` when none is.
+    pub fn domain_named(&self, entries: &[InfoEntry], name: &str) -> Result<Domain> {
+        let info = self.info();
+        let entry = entries
+            .iter()
+            .find(|entry| {
+                entry.provider_name() == info.provider_name()
+                    && entry.fabric_name() == info.fabric_name()
+                    && entry.domain_name() == name
+            })
+            .ok_or(Error::fabric("fi_domain", ffi::FI_ENODATA as i64))?;
+        Domain::open(self, entry)
+    }
+
+    /// Open an event queue, used for connection management and asynchronous control events.
+    pub fn eq(&self, attr: &EqAttr) -> Result<EventQueue> {
+        EventQueue::open(self, attr)
+    }
+
+    /// Open a passive endpoint, which listens for connection requests on MSG endpoints.
+    pub fn passive_endpoint(&self, info: &InfoEntry) -> Result<PassiveEndpoint> {
+        PassiveEndpoint::open(self, info)
+    }
+
+    pub fn as_raw(&self) -> *mut ffi::fid_fabric {
+        self.inner.fid.as_ptr()
+    }
+}
+
+impl AsRawFid for Fabric {
+    fn as_raw_fid(&self) -> *mut ffi::fid {
+        self.inner.fid.as_fid()
+    }
+}
@@ -0,0 +1,518 @@
+use crate::av::{Addr, AddressVector, AvAttr, EndpointAddress};
+use crate::bootstrap::Bootstrap;
+use crate::cq::{Completion, CompletionQueue, CqAttr};
+use crate::domain::Domain;
+use crate::ep::Endpoint;
+use crate::error::{Error, Result};
+use crate::fabric::Fabric;
+use crate::flags::{BindFlags, Caps};
+use crate::info::{EndpointType, Info, InfoEntry};
+use crate::mr::MemoryRegion;
+use crate::transport::Transport;
+use ofi_libfabric_sys::bindgen as ffi;
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const RECV_CONTEXT: usize = 1;
+
+/// Attributes of a [`FallbackTransport`]: the providers to try, in order, and what each must
+/// support.
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct FallbackAttr {
+    providers: Vec<String>,
+    caps: Caps,
+    ep_type: EndpointType,
+    probe: Option<Duration>,
+}
+
+impl FallbackAttr {
+    /// Try `providers`, the first one first, ex: `["cxi", "verbs;ofi_rxm", "tcp"]`, for
+    /// reliable unconnected endpoints with [`Caps::MSG`] and [`Caps::TAGGED`], checked by a
+    /// first message within 10 seconds.
+    pub fn new<S: AsRef<str>>(providers: impl IntoIterator<Item = S>) -> Self {
+        FallbackAttr {
+            providers: providers
+                .into_iter()
+                .map(|name| name.as_ref().to_owned())
+                .collect(),
+            caps: Caps::MSG | Caps::TAGGED,
+            ep_type: EndpointType::Rdm,
+            probe: Some(Duration::from_secs(10)),
+        }
+    }
+
+    /// The capabilities endpoints are requested with.
+    pub fn caps(mut self, caps: Caps) -> Self {
+        self.caps = caps;
+        self
+    }
+
+    /// The type of the endpoints, datagrams or reliable unconnected ones.
+    pub fn ep_type(mut self, ep_type: EndpointType) -> Self {
+        self.ep_type = ep_type;
+        self
+    }
+
+    /// Check each provider set up by a round of messages, every member sending to the next
+    /// rank, failing over to the next provider unless every message arrives within `timeout`.
+    /// `None` skips the check, leaving providers which set up but do not communicate, ex:
+    /// through a firewall, to fail the application later.
+    pub fn probe(mut self, timeout: Option<Duration>) -> Self {
+        self.probe = timeout;
+        self
+    }
+}
+
+/// The step at which a provider of a [`FallbackTransport`] failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FallbackStage {
+    /// Opening the fabric, domain, queues or endpoint.
+    Setup,
+    /// Inserting the names of the members, or the first round of messages between them.
+    Probe,
+}
+
+/// A provider a [`FallbackTransport`] failed over from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FallbackAttempt {
+    pub provider: String,
+    pub stage: FallbackStage,
+    /// The first member which failed.
+    pub rank: usize,
+    /// Its error, as it reported it.
+    pub error: String,
+}
+
+impl fmt::Display for FallbackAttempt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stage = match self.stage {
+            FallbackStage::Setup => "setup",
+            FallbackStage::Probe => "probe",
+        };
+        write!(
+            f,
+            "{} failed {stage} on rank {}: {}",
+            self.provider, self.rank, self.error
+        )
+    }
+}
+
+// The objects of a provider, closed in order once dropped.
+struct Setup {
+    ep: Endpoint,
+    cq: CompletionQueue,
+    av: AddressVector,
+    domain: Domain,
+    fabric: Fabric,
+    entry: InfoEntry,
+}
+
+impl Setup {
+    fn open(provider: &str, attr: &FallbackAttr) -> Result<Self> {
+        let hints = Info::new()
+            .provider(provider)
+            .caps(attr.caps)
+            .ep_type(attr.ep_type);
+        let entry = hints
+            .get()?
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::fabric("fi_getinfo", ffi::FI_ENODATA as i64))?;
+        let fabric = Fabric::open(&entry)?;
+        let domain = Domain::open(&fabric, &entry)?;
+        let cq = domain.cq(&CqAttr::new())?;
+        let av = domain.av(&AvAttr::new())?;
+        let ep = domain
+            .endpoint(&entry)?
+            .bind_cq(&cq, BindFlags::TRANSMIT | BindFlags::RECV)?
+            .bind_av(&av)?
+            .enable()?;
+        Ok(Setup {
+            ep,
+            cq,
+            av,
+            domain,
+            fabric,
+            entry,
+        })
+    }
+
+    // Inject the rank of this member to the next, and receive that of the previous one into
+    // `received`, which outlives the endpoint, should the receive time out.
+    fn probe(
+        &self,
+        peers: &[Addr],
+        rank: usize,
+        timeout: Duration,
+        received: &mut [u8; 8],
+    ) -> Result<()> {
+        let size = peers.len();
+        let deadline = Instant::now() + timeout;
+        let mut done = false;
+        // SAFETY: `received` outlives the endpoint, see above.
+        unsafe { self.ep.recv(received, None, Addr::UNSPEC, RECV_CONTEXT)? };
+        let next = peers[(rank + 1) % size];
+        let result = loop {
+            match self.ep.inject(&(rank as u64).to_le_bytes(), next) {
+                Err(err) if err.is_again() && Instant::now() < deadline => {}
+                other => break other,
+            }
+            if let Err(err) = self.progress(&mut done) {
+                break Err(err);
+            }
+        }
+        .and_then(|()| {
+            while !done {
+                if Instant::now() > deadline {
+                    return Err(Error::fabric("fi_cq_read", ffi::FI_ETIMEDOUT as i64));
+                }
+                self.progress(&mut done)?;
+                thread::yield_now();
+            }
+            Ok(())
+        });
+        if result.is_err() && !done {
+            let _ = self.ep.cancel(RECV_CONTEXT);
+            let deadline = Instant::now() + Duration::from_secs(1);
+            // Canceled receives complete in error.
+            while !done && Instant::now() < deadline && self.progress(&mut done).is_ok() {}
+        }
+        result?;
+        let from = u64::from_le_bytes(*received) as usize;
+        if from != (rank + size - 1) % size {
+            return Err(Error::invalid(format!(
+                "probe of rank {rank} received from rank {from}"
+            )));
+        }
+        Ok(())
+    }
+
+    // Read the completions available, marking the probe received, or failing with the error
+    // of its receive.
+    fn progress(&self, done: &mut bool) -> Result<()> {
+        let mut completions = [Completion::default(); 1];
+        match self.cq.read(&mut completions) {
+            Ok(n) => {
+                *done |= completions[..n].iter().any(|c| c.context() == RECV_CONTEXT);
+                Ok(())
+            }
+            Err(err) if err.is_again() => Ok(()),
+            Err(err) if err.is_avail() => match self.cq.read_err()? {
+                Some(entry) => {
+                    *done |= entry.context == RECV_CONTEXT;
+                    Err(entry.error)
+                }
+                None => Ok(()),
+            },
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// The endpoint of the first provider of a list which sets up, and communicates, on every
+/// member of a job, for binaries deployed across clusters of different fabrics.
+///
+/// [`open()`](Self::open) tries the providers of its [`FallbackAttr`] in order, every member
+/// in step over a [`Bootstrap`]: each opens a fabric, domain, queues and endpoint of the
+/// provider, and the members exchange their names, each inserting all of them, and, unless
+/// disabled, send each other a first message. If any member fails, every member closes the
+/// objects of the provider and moves on to the next, so that the job agrees on one, and each
+/// failure is kept as a [`FallbackAttempt`].
+///
+/// Operations through [`Transport`] go to the endpoint, whose completions are read from
+/// [`cq()`](Self::cq), and the members are addressed by rank with [`peer()`](Self::peer).
+///
+/// ```no_run
+/// use libfabric::bootstrap::Tcp;
+/// use libfabric::{FallbackAttr, FallbackTransport, Transport};
+/// use std::time::Duration;
+///
+/// # fn run() -> libfabric::Result<()> {
+/// let mut job = Tcp::connect("node0:4000", Duration::from_secs(30))?;
+/// let attr = FallbackAttr::new(["cxi", "verbs;ofi_rxm", "tcp"]);
+/// let transport = FallbackTransport::open(&mut job, &attr)?;
+/// for attempt in transport.attempts() {
+///     eprintln!("fell back: {attempt}");
+/// }
+/// transport.inject(b"hello", transport.peer(0)?)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct FallbackTransport {
+    setup: Setup,
+    peers: Vec<Addr>,
+    rank: usize,
+    attempts: Vec<FallbackAttempt>,
+}
+
+impl FallbackTransport {
+    /// Set up the first provider of `attr` which every member of `job` sets up, and
+    /// communicates over. Fails with an invalid argument error listing the attempts if none
+    /// does, and with the error of `job` if the bootstrap fails.
+    pub fn open(job: &mut impl Bootstrap, attr: &FallbackAttr) -> Result<Self> {
+        let (rank, size) = (job.rank(), job.size());
+        let mut attempts = Vec::new();
+        for provider in &attr.providers {
+            // Declared first, so that the endpoint is closed before the buffer is dropped.
+            let mut received = [0u8; 8];
+            let setup = Setup::open(provider, attr);
+            let local = match &setup {
+                Ok(setup) => setup.ep.name().map(|name| name.as_bytes().to_vec()),
+                Err(err) => Err(err.clone()),
+            };
+            let (setup, names) = match (setup, agree(job, local)?) {
+                (Ok(setup), Outcome::Agreed(names)) => (setup, names),
+                (_, Outcome::Failed(failed, error)) => {
+                    attempts.push(attempt(provider, FallbackStage::Setup, failed, error));
+                    continue;
+                }
+                (Err(_), Outcome::Agreed(_)) => {
+                    unreachable!("a member failed without reporting it")
+                }
+            };
+            let checked = names
+                .into_iter()
+                .map(|name| setup.av.insert(&EndpointAddress::from_bytes(name)))
+                .collect::<Result<Vec<_>>>()
+                .and_then(|peers| {
+                    if let Some(timeout) = attr.probe {
+                        setup.probe(&peers, rank, timeout, &mut received)?;
+                    }
+                    Ok(peers)
+                });
+            let local = checked.as_ref().map(|_| Vec::new()).map_err(Clone::clone);
+            match (checked, agree(job, local)?) {
+                (Ok(peers), Outcome::Agreed(_)) => {
+                    return Ok(FallbackTransport {
+                        setup,
+                        peers,
+                        rank,
+                        attempts,
+                    });
+                }
+                (_, Outcome::Failed(failed, error)) => {
+                    attempts.push(attempt(provider, FallbackStage::Probe, failed, error));
+                }
+                (Err(_), Outcome::Agreed(_)) => {
+                    unreachable!("a member failed without reporting it")
+                }
+            }
+        }
+        let attempts: Vec<_> = attempts.iter().map(ToString::to_string).collect();
+        Err(Error::invalid(format!(
+            "no provider set up on all {size} members: {}",
+            attempts.join("; ")
+        )))
+    }
+
+    /// The provider set up.
+    pub fn provider(&self) -> &str {
+        self.setup.entry.provider_name()
+    }
+
+    /// The providers failed over from, in order.
+    pub fn attempts(&self) -> &[FallbackAttempt] {
+        &self.attempts
+    }
+
+    /// The rank of this member, as given by the bootstrap.
+    pub fn rank(&self) -> usize {
+        self.rank
+    }
+
+    pub fn size(&self) -> usize {
+        self.peers.len()
+    }
+
+    /// The address of the member `rank`.
+    pub fn peer(&self, rank: usize) -> Result<Addr> {
+        self.peers.get(rank).copied().ok_or_else(|| {
+            Error::invalid(format!("rank {rank} is out of a group of {}", self.size()))
+        })
+    }
+
+    pub fn info(&self) -> &InfoEntry {
+        &self.setup.entry
+    }
+
+    pub fn fabric(&self) -> &Fabric {
+        &self.setup.fabric
+    }
+
+    pub fn domain(&self) -> &Domain {
+        &self.setup.domain
+    }
+
+    pub fn endpoint(&self) -> &Endpoint {
+        &self.setup.ep
+    }
+
+    /// The queue of the transmit and receive completions of the endpoint.
+    pub fn cq(&self) -> &CompletionQueue {
+        &self.setup.cq
+    }
+
+    pub fn av(&self) -> &AddressVector {
+        &self.setup.av
+    }
+}
+
+fn attempt(provider: &str, stage: FallbackStage, rank: usize, error: String) -> FallbackAttempt {
+    FallbackAttempt {
+        provider: provider.to_owned(),
+        stage,
+        rank,
+        error,
+    }
+}
+
+// What the members of a job agreed on: the data of each by rank, or the first failure.
+enum Outcome {
+    Agreed(Vec<Vec<u8>>),
+    Failed(usize, String),
+}
+
+// Gather the outcome of every member, a status byte followed by its data or error.
+fn agree(job: &mut impl Bootstrap, local: Result<Vec<u8>>) -> Result<Outcome> {
+    let msg = match local {
+        Ok(data) => [&[1u8][..], &data].concat(),
+        Err(err) => [&[0u8][..], err.to_string().as_bytes()].concat(),
+    };
+    let all = job.allgather(&msg)?;
+    let mut data = Vec::with_capacity(all.len());
+    for (rank, msg) in all.into_iter().enumerate() {
+        match msg.split_first() {
+            Some((1, rest)) => data.push(rest.to_vec()),
+            Some((_, error)) => {
+                return Ok(Outcome::Failed(rank, String::from_utf8_lossy(error).into()));
+            }
+            None => return Ok(Outcome::Failed(rank, "no outcome reported".to_owned())),
+        }
+    }
+    Ok(Outcome::Agreed(data))
+}
+
+impl Transport for FallbackTransport {
+    type Mr = MemoryRegion;
+
+    fn name(&self) -> Result<EndpointAddress> {
+        self.setup.ep.name()
+    }
+
+    unsafe fn recv(
+        &self,
+        buf: &mut [u8],
+        mr: Option<&MemoryRegion>,
+        src: Addr,
+        context: usize,
+    ) -> Result<()> {
+        unsafe { self.setup.ep.recv(buf, mr, src, context) }
+    }
+
+    unsafe fn send(
+        &self,
+        buf: &[u8],
+        mr: Option<&MemoryRegion>,
+        dest: Addr,
+        context: usize,
+    ) -> Result<()> {
+        unsafe { self.setup.ep.send(buf, mr, dest, context) }
+    }
+
+    unsafe fn senddata(
+        &self,
+        buf: &[u8],
+        mr: Option<&MemoryRegion>,
+        data: u64,
+        dest: Addr,
+        context: usize,
+    ) -> Result<()> {
+        unsafe { self.setup.ep.senddata(buf, mr, data, dest, context) }
+    }
+
+    fn inject(&self, buf: &[u8], dest: Addr) -> Result<()> {
+        self.setup.ep.inject(buf, dest)
+    }
+
+    unsafe fn trecv(
+        &self,
+        buf: &mut [u8],
+        mr: Option<&MemoryRegion>,
+        src: Addr,
+        tag: u64,
+        ignore: u64,
+        context: usize,
+    ) -> Result<()> {
+        unsafe { self.setup.ep.trecv(buf, mr, src, tag, ignore, context) }
+    }
+
+    unsafe fn tsend(
+        &self,
+        buf: &[u8],
+        mr: Option<&MemoryRegion>,
+        dest: Addr,
+        tag: u64,
+        context: usize,
+    ) -> Result<()> {
+        unsafe { self.setup.ep.tsend(buf, mr, dest, tag, context) }
+    }
+
+    unsafe fn tsenddata(
+        &self,
+        buf: &[u8],
+        mr: Option<&MemoryRegion>,
+        data: u64,
+        dest: Addr,
+        tag: u64,
+        context: usize,
+    ) -> Result<()> {
+        unsafe { self.setup.ep.tsenddata(buf, mr, data, dest, tag, context) }
+    }
+
+    fn tinject(&self, buf: &[u8], dest: Addr, tag: u64) -> Result<()> {
+        self.setup.ep.tinject(buf, dest, tag)
+    }
+
+    unsafe fn read(
+        &self,
+        buf: &mut [u8],
+        mr: Option<&MemoryRegion>,
+        src: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()> {
+        unsafe { self.setup.ep.read(buf, mr, src, addr, key, context) }
+    }
+
+    unsafe fn write(
+        &self,
+        buf: &[u8],
+        mr: Option<&MemoryRegion>,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()> {
+        unsafe { self.setup.ep.write(buf, mr, dest, addr, key, context) }
+    }
+
+    unsafe fn writedata(
+        &self,
+        buf: &[u8],
+        mr: Option<&MemoryRegion>,
+        data: u64,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()> {
+        unsafe {
+            self.setup
+                .ep
+                .writedata(buf, mr, data, dest, addr, key, context)
+        }
+    }
+}
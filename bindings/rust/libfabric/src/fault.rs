@@ -0,0 +1,570 @@
+//! Fault injection over the [transport traits](crate::Transport), enabled by the `fault`
+//! feature, to exercise the retry and cleanup paths of an application without a faulty
+//! network.
+//!
+//! A [`FaultInjector`] wraps endpoints, completion queues and event queues, which then behave
+//! as the originals but for the faults it injects at random, at the rates it is configured
+//! with:
+//!
+//! - bursts of `FI_EAGAIN` failures of the operations posted on an endpoint, none of which
+//!   reaches the endpoint,
+//! - completions held back for a number of reads of their queue, which may then be read out
+//!   of order,
+//! - successful completions reported in error instead, as if the operation had failed,
+//! - connection management events (connection requests, connections and shutdowns) that are
+//!   read from their queue, but never returned.
+//!
+//! Wrappers made by one injector share its state, and all of the randomness comes from its
+//! seed: a single threaded run making the same calls with the same seed sees the same faults.
+//!
+//! ```no_run
+//! use libfabric::fault::FaultInjector;
+//! use libfabric::{CompletionQueue, Endpoint, EventQueue};
+//!
+//! # fn run(ep: Endpoint, cq: CompletionQueue, eq: EventQueue) {
+//! let faults = FaultInjector::new(7)
+//!     .eagain_rate(0.05)
+//!     .max_burst(8)
+//!     .error_rate(0.01)
+//!     .cm_drop_rate(0.1);
+//! let (ep, cq, eq) = (faults.endpoint(ep), faults.cq(cq), faults.eq(&eq));
+//! // Run the protocol under test over `ep`, `cq` and `eq`, then check what was injected.
+//! println!("{:?}", faults.stats());
+//! # }
+//! ```
+
+use crate::av::{Addr, EndpointAddress};
+use crate::cq::{Completion, CqErrEntry};
+use crate::eq::{EqErrEntry, EqEvent, EventQueue};
+use crate::error::{Error, Result};
+use crate::transport::{Cq, Transport};
+use ofi_libfabric_sys::bindgen as ffi;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// What a [`FaultInjector`] injected so far.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FaultStats {
+    /// Posts failed with `FI_EAGAIN`.
+    pub eagain: u64,
+    /// Completions held back.
+    pub delayed: u64,
+    /// Completions reported in error.
+    pub errors: u64,
+    /// Connection management events dropped.
+    pub cm_dropped: u64,
+}
+
+/// The faults injected into the endpoints and queues it wraps, see the [module](self)
+/// documentation.
+///
+/// No fault is injected until their rates are set. Clones share the same state.
+#[derive(Clone)]
+#[must_use]
+pub struct FaultInjector {
+    inner: Arc<Mutex<State>>,
+}
+
+struct State {
+    // SplitMix64 state.
+    rng: u64,
+    eagain_rate: f64,
+    max_burst: u64,
+    delay_rate: f64,
+    max_delay: u64,
+    error_rate: f64,
+    error_code: i32,
+    cm_drop_rate: f64,
+    // Posts left to fail in the current burst.
+    burst: u64,
+    stats: FaultStats,
+}
+
+impl State {
+    fn next(&mut self) -> u64 {
+        self.rng = self.rng.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn chance(&mut self, rate: f64) -> bool {
+        // The top 53 bits, as a uniform float in [0, 1).
+        rate > 0.0 && ((self.next() >> 11) as f64 / (1u64 << 53) as f64) < rate
+    }
+
+    // Whether to fail the current post.
+    fn fails_post(&mut self) -> bool {
+        if self.burst == 0 && self.chance(self.eagain_rate) {
+            self.burst = 1 + self.next() % self.max_burst;
+        }
+        if self.burst == 0 {
+            return false;
+        }
+        self.burst -= 1;
+        self.stats.eagain += 1;
+        true
+    }
+
+    // The reads of its queue a completion is held back for, 0 to deliver it now.
+    fn delay(&mut self) -> u64 {
+        if !self.chance(self.delay_rate) {
+            return 0;
+        }
+        self.stats.delayed += 1;
+        1 + self.next() % self.max_delay
+    }
+
+    fn fails_completion(&mut self) -> bool {
+        let fails = self.chance(self.error_rate);
+        self.stats.errors += fails as u64;
+        fails
+    }
+
+    fn drops_cm(&mut self) -> bool {
+        let drops = self.chance(self.cm_drop_rate);
+        self.stats.cm_dropped += drops as u64;
+        drops
+    }
+}
+
+impl FaultInjector {
+    /// An injector of no faults, which `seed` then randomizes.
+    pub fn new(seed: u64) -> Self {
+        FaultInjector {
+            inner: Arc::new(Mutex::new(State {
+                rng: seed,
+                eagain_rate: 0.0,
+                max_burst: 1,
+                delay_rate: 0.0,
+                max_delay: 1,
+                error_rate: 0.0,
+                error_code: ffi::FI_EIO as i32,
+                cm_drop_rate: 0.0,
+                burst: 0,
+                stats: FaultStats::default(),
+            })),
+        }
+    }
+
+    /// Start a burst of `FI_EAGAIN` failures on each post with probability `rate`, from 0.0 to
+    /// 1.0.
+    pub fn eagain_rate(self, rate: f64) -> Self {
+        self.lock().eagain_rate = rate;
+        self
+    }
+
+    /// Fail 1 to `posts` consecutive posts, picked at random, in each burst of `FI_EAGAIN`
+    /// failures. Defaults to 1.
+    pub fn max_burst(self, posts: u64) -> Self {
+        self.lock().max_burst = posts.max(1);
+        self
+    }
+
+    /// Hold back each completion with probability `rate`.
+    pub fn delay_rate(self, rate: f64) -> Self {
+        self.lock().delay_rate = rate;
+        self
+    }
+
+    /// Hold back delayed completions for 1 to `reads` reads of their queue, picked at random.
+    /// Defaults to 1.
+    pub fn max_delay(self, reads: u64) -> Self {
+        self.lock().max_delay = reads.max(1);
+        self
+    }
+
+    /// Report each successful completion in error with probability `rate`.
+    pub fn error_rate(self, rate: f64) -> Self {
+        self.lock().error_rate = rate;
+        self
+    }
+
+    /// The `FI_E*` code of the completions reported in error. Defaults to `FI_EIO`.
+    pub fn error_code(self, code: i32) -> Self {
+        self.lock().error_code = code;
+        self
+    }
+
+    /// Drop each connection management event with probability `rate`.
+    pub fn cm_drop_rate(self, rate: f64) -> Self {
+        self.lock().cm_drop_rate = rate;
+        self
+    }
+
+    pub fn stats(&self) -> FaultStats {
+        self.lock().stats
+    }
+
+    /// Inject `FI_EAGAIN` bursts into the operations posted on `ep`.
+    pub fn endpoint<T: Transport>(&self, ep: T) -> FaultyEndpoint<T> {
+        FaultyEndpoint {
+            inner: ep,
+            faults: self.clone(),
+        }
+    }
+
+    /// Delay completions read from `cq`, and report some in error.
+    pub fn cq<C: Cq>(&self, cq: C) -> FaultyCq<C> {
+        FaultyCq {
+            inner: cq,
+            faults: self.clone(),
+            queue: Arc::new(Mutex::new(Pending::default())),
+        }
+    }
+
+    /// Drop connection management events read from `eq`.
+    pub fn eq(&self, eq: &EventQueue) -> FaultyEq {
+        FaultyEq {
+            inner: eq.clone(),
+            faults: self.clone(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.inner.lock().unwrap()
+    }
+
+    fn post(&self, op: &'static str) -> Result<()> {
+        match self.lock().fails_post() {
+            true => Err(Error::fabric(op, ffi::FI_EAGAIN as i64)),
+            false => Ok(()),
+        }
+    }
+}
+
+/// An endpoint whose posts fail with `FI_EAGAIN` in bursts, made by
+/// [`FaultInjector::endpoint()`].
+#[derive(Clone)]
+pub struct FaultyEndpoint<T> {
+    inner: T,
+    faults: FaultInjector,
+}
+
+impl<T> FaultyEndpoint<T> {
+    /// The wrapped endpoint, to which faults are not injected.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: Transport> Transport for FaultyEndpoint<T> {
+    type Mr = T::Mr;
+
+    fn name(&self) -> Result<EndpointAddress> {
+        self.inner.name()
+    }
+
+    unsafe fn recv(
+        &self,
+        buf: &mut [u8],
+        mr: Option<&T::Mr>,
+        src: Addr,
+        context: usize,
+    ) -> Result<()> {
+        self.faults.post("fi_recv")?;
+        unsafe { self.inner.recv(buf, mr, src, context) }
+    }
+
+    unsafe fn send(
+        &self,
+        buf: &[u8],
+        mr: Option<&T::Mr>,
+        dest: Addr,
+        context: usize,
+    ) -> Result<()> {
+        self.faults.post("fi_send")?;
+        unsafe { self.inner.send(buf, mr, dest, context) }
+    }
+
+    unsafe fn senddata(
+        &self,
+        buf: &[u8],
+        mr: Option<&T::Mr>,
+        data: u64,
+        dest: Addr,
+        context: usize,
+    ) -> Result<()> {
+        self.faults.post("fi_senddata")?;
+        unsafe { self.inner.senddata(buf, mr, data, dest, context) }
+    }
+
+    fn inject(&self, buf: &[u8], dest: Addr) -> Result<()> {
+        self.faults.post("fi_inject")?;
+        self.inner.inject(buf, dest)
+    }
+
+    unsafe fn trecv(
+        &self,
+        buf: &mut [u8],
+        mr: Option<&T::Mr>,
+        src: Addr,
+        tag: u64,
+        ignore: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.faults.post("fi_trecv")?;
+        unsafe { self.inner.trecv(buf, mr, src, tag, ignore, context) }
+    }
+
+    unsafe fn tsend(
+        &self,
+        buf: &[u8],
+        mr: Option<&T::Mr>,
+        dest: Addr,
+        tag: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.faults.post("fi_tsend")?;
+        unsafe { self.inner.tsend(buf, mr, dest, tag, context) }
+    }
+
+    unsafe fn tsenddata(
+        &self,
+        buf: &[u8],
+        mr: Option<&T::Mr>,
+        data: u64,
+        dest: Addr,
+        tag: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.faults.post("fi_tsenddata")?;
+        unsafe { self.inner.tsenddata(buf, mr, data, dest, tag, context) }
+    }
+
+    fn tinject(&self, buf: &[u8], dest: Addr, tag: u64) -> Result<()> {
+        self.faults.post("fi_tinject")?;
+        self.inner.tinject(buf, dest, tag)
+    }
+
+    unsafe fn read(
+        &self,
+        buf: &mut [u8],
+        mr: Option<&T::Mr>,
+        src: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.faults.post("fi_read")?;
+        unsafe { self.inner.read(buf, mr, src, addr, key, context) }
+    }
+
+    unsafe fn write(
+        &self,
+        buf: &[u8],
+        mr: Option<&T::Mr>,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.faults.post("fi_write")?;
+        unsafe { self.inner.write(buf, mr, dest, addr, key, context) }
+    }
+
+    unsafe fn writedata(
+        &self,
+        buf: &[u8],
+        mr: Option<&T::Mr>,
+        data: u64,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.faults.post("fi_writedata")?;
+        unsafe {
+            self.inner
+                .writedata(buf, mr, data, dest, addr, key, context)
+        }
+    }
+}
+
+type Entry = std::result::Result<(Completion, Addr), CqErrEntry>;
+
+// The completions read from the wrapped queue and not yet from the wrapper.
+#[derive(Default)]
+struct Pending {
+    // In order, up to the first error entry, which stops reads as on a queue.
+    ready: VecDeque<Entry>,
+    // Delayed entries, along with the read they are due on.
+    held: Vec<(u64, Entry)>,
+    reads: u64,
+}
+
+/// A completion queue whose completions are delayed or reported in error at times, made by
+/// [`FaultInjector::cq()`].
+///
+/// Completions are only taken from the wrapped queue as it is read, such that holding some
+/// back does not keep those after them. Those in error are reported as they are on the
+/// queue: [`read()`](Cq::read) fails with `FI_EAVAIL` when one is next, and
+/// [`read_err()`](Cq::read_err) then takes it, with the context, flags, length, data and tag
+/// of the completion it replaces.
+#[derive(Clone)]
+pub struct FaultyCq<C> {
+    inner: C,
+    faults: FaultInjector,
+    queue: Arc<Mutex<Pending>>,
+}
+
+impl<C> FaultyCq<C> {
+    /// The wrapped queue, to which faults are not injected.
+    pub fn get_ref(&self) -> &C {
+        &self.inner
+    }
+}
+
+impl<C: Cq> FaultyCq<C> {
+    // Entries are held as they are read, errors included.
+    #[allow(clippy::result_large_err)]
+    fn read_entries(&self, out: &mut [Completion], mut src: Option<&mut [Addr]>) -> Result<usize> {
+        let count = src
+            .as_deref()
+            .map_or(out.len(), |src| out.len().min(src.len()));
+        let mut queue = self.queue.lock().unwrap();
+        queue.reads += 1;
+        let reads = queue.reads;
+        let (due, held) = std::mem::take(&mut queue.held)
+            .into_iter()
+            .partition(|(at, _)| *at <= reads);
+        queue.held = held;
+        queue.ready.extend(due.into_iter().map(|(_, entry)| entry));
+
+        // The errors of the wrapped queue are only read through read_err().
+        let mut avail = false;
+        if queue.ready.len() < count {
+            let want = count - queue.ready.len();
+            let mut buf = vec![Completion::default(); want];
+            let read = match src {
+                Some(_) => {
+                    let mut addrs = vec![Addr::UNSPEC; want];
+                    self.inner.read_from(&mut buf, &mut addrs).map(|n| {
+                        addrs.truncate(n);
+                        addrs
+                    })
+                }
+                None => self.inner.read(&mut buf).map(|n| vec![Addr::UNSPEC; n]),
+            };
+            match read {
+                Ok(addrs) => {
+                    let mut faults = self.faults.lock();
+                    for (completion, addr) in buf.into_iter().zip(addrs) {
+                        let entry = match faults.fails_completion() {
+                            true => Err(error_entry(&completion, faults.error_code)),
+                            false => Ok((completion, addr)),
+                        };
+                        match faults.delay() {
+                            0 => queue.ready.push_back(entry),
+                            delay => queue.held.push((reads + delay, entry)),
+                        }
+                    }
+                }
+                Err(err) if err.is_again() => {}
+                Err(err) if err.is_avail() => avail = true,
+                Err(err) => return Err(err),
+            }
+        }
+
+        let mut n = 0;
+        while n < count
+            && let Some(Ok((completion, addr))) = queue.ready.front()
+        {
+            out[n] = *completion;
+            if let Some(src) = src.as_deref_mut() {
+                src[n] = *addr;
+            }
+            queue.ready.pop_front();
+            n += 1;
+        }
+        match (n, queue.ready.front()) {
+            (0, Some(Err(_))) => Err(Error::fabric("fi_cq_read", ffi::FI_EAVAIL as i64)),
+            (0, _) if avail => Err(Error::fabric("fi_cq_read", ffi::FI_EAVAIL as i64)),
+            (0, _) => Err(Error::fabric("fi_cq_read", ffi::FI_EAGAIN as i64)),
+            _ => Ok(n),
+        }
+    }
+}
+
+impl<C: Cq> Cq for FaultyCq<C> {
+    fn read(&self, out: &mut [Completion]) -> Result<usize> {
+        self.read_entries(out, None)
+    }
+
+    fn read_from(&self, out: &mut [Completion], src: &mut [Addr]) -> Result<usize> {
+        self.read_entries(out, Some(src))
+    }
+
+    fn read_err(&self) -> Result<Option<CqErrEntry>> {
+        let mut queue = self.queue.lock().unwrap();
+        match queue.ready.front() {
+            Some(Err(_)) => Ok(queue.ready.pop_front().and_then(|entry| entry.err())),
+            _ => self.inner.read_err(),
+        }
+    }
+}
+
+fn error_entry(completion: &Completion, code: i32) -> CqErrEntry {
+    CqErrEntry {
+        context: completion.context(),
+        flags: completion.flags(),
+        len: completion.len(),
+        data: completion.data(),
+        tag: completion.tag(),
+        olen: 0,
+        error: Error::fabric("fi_cq_read", code as i64),
+        prov_errno: 0,
+        message: "injected fault".to_owned(),
+        src_addr: Addr::NOTAVAIL,
+        src_name: None,
+    }
+}
+
+/// An event queue whose connection management events are dropped at times, made by
+/// [`FaultInjector::eq()`].
+///
+/// Dropped events are read from the queue like any other, then discarded: a connection
+/// request is left unanswered, and a connection or shutdown goes unnoticed.
+#[derive(Clone)]
+pub struct FaultyEq {
+    inner: EventQueue,
+    faults: FaultInjector,
+}
+
+impl FaultyEq {
+    /// The wrapped queue, to which faults are not injected.
+    pub fn get_ref(&self) -> &EventQueue {
+        &self.inner
+    }
+
+    /// Like [`EventQueue::read()`].
+    pub fn read(&self) -> Result<Option<EqEvent>> {
+        Ok(self.inner.read()?.filter(|event| !self.drops(event)))
+    }
+
+    /// Like [`EventQueue::sread()`], returning as on a timeout when the event it waited for
+    /// is dropped.
+    pub fn sread(&self, timeout: Option<Duration>) -> Result<Option<EqEvent>> {
+        Ok(self
+            .inner
+            .sread(timeout)?
+            .filter(|event| !self.drops(event)))
+    }
+
+    pub fn read_err(&self) -> Result<Option<EqErrEntry>> {
+        self.inner.read_err()
+    }
+
+    fn drops(&self, event: &EqEvent) -> bool {
+        matches!(
+            event,
+            EqEvent::ConnReq { .. } | EqEvent::Connected { .. } | EqEvent::Shutdown { .. }
+        ) && self.faults.lock().drops_cm()
+    }
+}
@@ -0,0 +1,144 @@
+use crate::error::{Error, Result, check};
+use crate::ext::Ops;
+use ofi_libfabric_sys::bindgen as ffi;
+use std::os::raw::c_int;
+use std::ptr::{self, NonNull};
+use std::sync::Arc;
+
+/// Owned pointer to a libfabric object, closed with `fi_close()` on drop.
+///
+/// Every `struct fid_*` starts with a `struct fid`, so the pointer can always be viewed as a fid.
+pub(crate) struct OwnedFid<T> {
+    ptr: NonNull<T>,
+    // Whether close() closed the object already, or release() handed it over.
+    closed: bool,
+}
+
+// SAFETY: The wrappers only hand out the pointer to libfabric calls. Domains of the
+// `ThreadSafe` model are only opened at FI_THREAD_SAFE, which allows concurrent calls on every
+// object. Those of `ThreadDomain`, opened at FI_THREAD_DOMAIN or a lesser level, allow no
+// concurrent calls, but their objects carry the model as a type parameter, which withholds
+// `Send` and `Sync` from the wrappers whatever this fid allows: they stay on the thread which
+// opened the domain.
+unsafe impl<T> Send for OwnedFid<T> {}
+unsafe impl<T> Sync for OwnedFid<T> {}
+
+impl<T> OwnedFid<T> {
+    /// Opens a libfabric object by handing `open` the out-pointer it should fill.
+    pub(crate) fn open(op: &'static str, open: impl FnOnce(*mut *mut T) -> c_int) -> Result<Self> {
+        let mut raw = ptr::null_mut();
+        check(op, open(&mut raw))?;
+        NonNull::new(raw)
+            .map(|ptr| OwnedFid { ptr, closed: false })
+            .ok_or(crate::Error::fabric(op, ffi::FI_EOTHER as i64))
+    }
+
+    /// Take ownership of an object opened elsewhere, failing with an invalid argument error
+    /// for a null pointer.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be an open object, which nothing else closes.
+    pub(crate) unsafe fn from_raw(ptr: *mut T) -> Result<Self> {
+        NonNull::new(ptr)
+            .map(|ptr| OwnedFid { ptr, closed: false })
+            .ok_or_else(|| Error::invalid("null object pointer"))
+    }
+
+    pub(crate) fn as_ptr(&self) -> *mut T {
+        self.ptr.as_ptr()
+    }
+
+    pub(crate) fn as_fid(&self) -> *mut ffi::fid {
+        self.ptr.as_ptr().cast()
+    }
+
+    /// Close the object now, surfacing the error of `fi_close()`, ex: `-FI_EBUSY` while other
+    /// objects still refer to it, which leaves it to be closed on drop.
+    pub(crate) fn close(&mut self) -> Result<()> {
+        check("fi_close", unsafe { ffi::fi_close(self.as_fid()) })?;
+        self.closed = true;
+        Ok(())
+    }
+}
+
+/// Hand over the object of `inner`, the last handle to it, without closing it. The objects it
+/// keeps alive are released, and only stay open while other handles to them do.
+pub(crate) fn release_last<I, T>(
+    inner: Arc<I>,
+    fid: impl FnOnce(&mut I) -> &mut OwnedFid<T>,
+) -> Result<*mut T> {
+    let mut inner = Arc::try_unwrap(inner)
+        .map_err(|_| Error::invalid("the object has other handles, which keep it open"))?;
+    let fid = fid(&mut inner);
+    fid.closed = true;
+    Ok(fid.as_ptr())
+}
+
+/// Close the object of `inner`, the last handle to it, along with the objects it keeps alive.
+pub(crate) fn close_last<I, T>(
+    inner: Arc<I>,
+    fid: impl FnOnce(&mut I) -> &mut OwnedFid<T>,
+) -> Result<()> {
+    let mut inner = Arc::try_unwrap(inner)
+        .map_err(|_| Error::invalid("the object has other handles, which keep it open"))?;
+    fid(&mut inner).close()
+}
+
+impl<T> Drop for OwnedFid<T> {
+    fn drop(&mut self) {
+        // Errors cannot be surfaced from drop; the object is gone either way.
+        if !self.closed {
+            let _ = unsafe { ffi::fi_close(self.as_fid()) };
+        }
+    }
+}
+
+/// Opaque identity of a libfabric object.
+///
+/// Events such as `FI_CONNECTED` or `FI_SHUTDOWN` only carry the fid they relate to, so this is
+/// what applications compare against [`AsRawFid::id()`] to route them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FidId(usize);
+
+impl FidId {
+    pub(crate) fn from_ptr(ptr: *const ffi::fid) -> Self {
+        FidId(ptr as usize)
+    }
+}
+
+/// Access to the raw `struct fid` behind a wrapper.
+pub trait AsRawFid {
+    /// The raw fid. It stays valid for as long as the wrapper is alive.
+    fn as_raw_fid(&self) -> *mut ffi::fid;
+
+    /// The identity of the object, as reported in events.
+    fn id(&self) -> FidId {
+        FidId::from_ptr(self.as_raw_fid())
+    }
+
+    /// Look up provider specific operations of the object, via `fi_open_ops()`.
+    ///
+    /// Fails with `FI_ENOSYS` if the provider does not implement `T` for this kind of object.
+    fn open_ops<T: Ops>(&self) -> Result<&T>
+    where
+        Self: Sized,
+    {
+        crate::ext::open_ops(self.as_raw_fid())
+    }
+
+    /// Import an object created by the application into this one, via `fi_import_fid()`.
+    ///
+    /// # Safety
+    ///
+    /// `fid` must be of a kind the provider accepts for this object, and must stay valid until
+    /// this object is closed.
+    unsafe fn import_fid(&self, fid: *mut ffi::fid, flags: u64) -> Result<()>
+    where
+        Self: Sized,
+    {
+        check("fi_import_fid", unsafe {
+            ffi::fi_import_fid(self.as_raw_fid(), fid, flags)
+        })
+    }
+}
@@ -0,0 +1,182 @@
+// bindgen types each FI_* macro by its value, u32 or u64, so some of the casts below are no-ops.
+#![allow(clippy::unnecessary_cast)]
+
+use bitflags::bitflags;
+use ofi_libfabric_sys::bindgen as ffi;
+
+bitflags! {
+    /// Capabilities requested in hints, or granted by a provider (`fi_info.caps`).
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(transparent)
+    )]
+    pub struct Caps: u64 {
+        const MSG = ffi::FI_MSG as u64;
+        const RMA = ffi::FI_RMA as u64;
+        const TAGGED = ffi::FI_TAGGED as u64;
+        const ATOMIC = ffi::FI_ATOMIC as u64;
+        const MULTICAST = ffi::FI_MULTICAST as u64;
+        const COLLECTIVE = ffi::FI_COLLECTIVE as u64;
+        const READ = ffi::FI_READ as u64;
+        const WRITE = ffi::FI_WRITE as u64;
+        const RECV = ffi::FI_RECV as u64;
+        const SEND = ffi::FI_SEND as u64;
+        const REMOTE_READ = ffi::FI_REMOTE_READ as u64;
+        const REMOTE_WRITE = ffi::FI_REMOTE_WRITE as u64;
+        const MULTI_RECV = ffi::FI_MULTI_RECV as u64;
+        const REMOTE_CQ_DATA = ffi::FI_REMOTE_CQ_DATA as u64;
+        const TRIGGER = ffi::FI_TRIGGER as u64;
+        const FENCE = ffi::FI_FENCE as u64;
+        const AV_USER_ID = ffi::FI_AV_USER_ID as u64;
+        const PEER = ffi::FI_PEER as u64;
+        const TAGGED_DIRECTED_RECV = ffi::FI_TAGGED_DIRECTED_RECV as u64;
+        const TAGGED_MULTI_RECV = ffi::FI_TAGGED_MULTI_RECV as u64;
+        const HMEM = ffi::FI_HMEM as u64;
+        const EXACT_DIRECTED_RECV = ffi::FI_EXACT_DIRECTED_RECV as u64;
+        const RMA_PMEM = ffi::FI_RMA_PMEM as u64;
+        const SOURCE_ERR = ffi::FI_SOURCE_ERR as u64;
+        const LOCAL_COMM = ffi::FI_LOCAL_COMM as u64;
+        const REMOTE_COMM = ffi::FI_REMOTE_COMM as u64;
+        const SHARED_AV = ffi::FI_SHARED_AV as u64;
+        const RMA_EVENT = ffi::FI_RMA_EVENT as u64;
+        const SOURCE = ffi::FI_SOURCE as u64;
+        const NAMED_RX_CTX = ffi::FI_NAMED_RX_CTX as u64;
+        const DIRECTED_RECV = ffi::FI_DIRECTED_RECV as u64;
+    }
+}
+
+bitflags! {
+    /// Operational modes an application supports (`fi_info.mode`).
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(transparent)
+    )]
+    pub struct Mode: u64 {
+        const CONTEXT = ffi::FI_CONTEXT as u64;
+        const MSG_PREFIX = ffi::FI_MSG_PREFIX as u64;
+        const ASYNC_IOV = ffi::FI_ASYNC_IOV as u64;
+        const RX_CQ_DATA = ffi::FI_RX_CQ_DATA as u64;
+        const CONTEXT2 = ffi::FI_CONTEXT2 as u64;
+        /// Receives buffered by the provider, reported as
+        /// [`BufferedRecv`](crate::BufferedRecv)s, removed from libfabric 2.0.
+        #[cfg(not(libfabric_ge_2_0))]
+        const BUFFERED_RECV = ffi::FI_BUFFERED_RECV as u64;
+    }
+}
+
+bitflags! {
+    /// Memory registration modes (`fi_domain_attr.mr_mode`).
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(transparent)
+    )]
+    pub struct MrMode: u32 {
+        const LOCAL = ffi::FI_MR_LOCAL;
+        const RAW = ffi::FI_MR_RAW;
+        const VIRT_ADDR = ffi::FI_MR_VIRT_ADDR;
+        const ALLOCATED = ffi::FI_MR_ALLOCATED;
+        const PROV_KEY = ffi::FI_MR_PROV_KEY;
+        const MMU_NOTIFY = ffi::FI_MR_MMU_NOTIFY;
+        const RMA_EVENT = ffi::FI_MR_RMA_EVENT;
+        const ENDPOINT = ffi::FI_MR_ENDPOINT;
+        const HMEM = ffi::FI_MR_HMEM;
+        const COLLECTIVE = ffi::FI_MR_COLLECTIVE;
+    }
+}
+
+bitflags! {
+    /// Access rights of a memory registration.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(transparent)
+    )]
+    pub struct Access: u64 {
+        const SEND = ffi::FI_SEND as u64;
+        const RECV = ffi::FI_RECV as u64;
+        const READ = ffi::FI_READ as u64;
+        const WRITE = ffi::FI_WRITE as u64;
+        const REMOTE_READ = ffi::FI_REMOTE_READ as u64;
+        const REMOTE_WRITE = ffi::FI_REMOTE_WRITE as u64;
+        const COLLECTIVE = ffi::FI_COLLECTIVE as u64;
+    }
+}
+
+bitflags! {
+    /// Flags for binding a queue or counter to an endpoint (`fi_ep_bind()`).
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(transparent)
+    )]
+    pub struct BindFlags: u64 {
+        const TRANSMIT = ffi::FI_TRANSMIT as u64;
+        const RECV = ffi::FI_RECV as u64;
+        const READ = ffi::FI_READ as u64;
+        const WRITE = ffi::FI_WRITE as u64;
+        const REMOTE_READ = ffi::FI_REMOTE_READ as u64;
+        const REMOTE_WRITE = ffi::FI_REMOTE_WRITE as u64;
+        const SELECTIVE_COMPLETION = ffi::FI_SELECTIVE_COMPLETION as u64;
+    }
+}
+
+bitflags! {
+    /// The orderings of messages, or of their completions, on a transmit or receive context
+    /// (`fi_tx_attr.msg_order`): `RAW` means that a read is not carried out before a write posted
+    /// ahead of it, and so on for sends, writes and reads.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(transparent)
+    )]
+    pub struct MsgOrder: u64 {
+        const RAR = ffi::FI_ORDER_RAR as u64;
+        const RAW = ffi::FI_ORDER_RAW as u64;
+        const RAS = ffi::FI_ORDER_RAS as u64;
+        const WAR = ffi::FI_ORDER_WAR as u64;
+        const WAW = ffi::FI_ORDER_WAW as u64;
+        const WAS = ffi::FI_ORDER_WAS as u64;
+        const SAR = ffi::FI_ORDER_SAR as u64;
+        const SAW = ffi::FI_ORDER_SAW as u64;
+        const SAS = ffi::FI_ORDER_SAS as u64;
+        const RMA_RAR = ffi::FI_ORDER_RMA_RAR as u64;
+        const RMA_RAW = ffi::FI_ORDER_RMA_RAW as u64;
+        const RMA_WAR = ffi::FI_ORDER_RMA_WAR as u64;
+        const RMA_WAW = ffi::FI_ORDER_RMA_WAW as u64;
+        const ATOMIC_RAR = ffi::FI_ORDER_ATOMIC_RAR as u64;
+        const ATOMIC_RAW = ffi::FI_ORDER_ATOMIC_RAW as u64;
+        const ATOMIC_WAR = ffi::FI_ORDER_ATOMIC_WAR as u64;
+        const ATOMIC_WAW = ffi::FI_ORDER_ATOMIC_WAW as u64;
+    }
+}
+
+bitflags! {
+    /// The default flags of the operations of a transmit or receive context
+    /// (`fi_tx_attr.op_flags`).
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(transparent)
+    )]
+    pub struct OpFlags: u64 {
+        const COMPLETION = ffi::FI_COMPLETION as u64;
+        const INJECT = ffi::FI_INJECT as u64;
+        const INJECT_COMPLETE = ffi::FI_INJECT_COMPLETE as u64;
+        const TRANSMIT_COMPLETE = ffi::FI_TRANSMIT_COMPLETE as u64;
+        const DELIVERY_COMPLETE = ffi::FI_DELIVERY_COMPLETE as u64;
+        const COMMIT_COMPLETE = ffi::FI_COMMIT_COMPLETE as u64;
+        const MULTI_RECV = ffi::FI_MULTI_RECV as u64;
+        const FENCE = ffi::FI_FENCE as u64;
+        const TRIGGER = ffi::FI_TRIGGER as u64;
+    }
+}
@@ -0,0 +1,516 @@
+use crate::av::{Addr, EndpointAddress};
+use crate::cq::{Completion, CqErrEntry};
+use crate::error::{Error, Result};
+use crate::progress::{ProgressDriver, SystemProgress};
+use crate::record::OpKind;
+use crate::transport::{Cq, Transport};
+use ofi_libfabric_sys::bindgen as ffi;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// A transmit operation about to be posted, as its [`TxGovernor`] sees it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TxPost {
+    pub kind: OpKind,
+    /// The bytes sent, written or read.
+    pub len: usize,
+    pub peer: Addr,
+    /// Transmit operations of the endpoint posted, whose completion was not read yet.
+    pub depth: usize,
+}
+
+/// What a [`TxGovernor`] makes of a post.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxVerdict {
+    Admit,
+    /// Fail the post with `FI_EAGAIN`, to be posted again once this long passed, or after
+    /// completions were read for no time.
+    Delay(Duration),
+    /// Fail the post with `FI_ECANCELED`.
+    Reject,
+}
+
+/// Decides on the transmit operations of a [`GovernedEndpoint`] before they are posted, to
+/// cap the bandwidth or the depth of a flow, ex: that of a tenant sharing the fabric.
+///
+/// Closures taking a [`TxPost`] are governors, and [`TokenBucket`] is the governor capping
+/// the bandwidth of a flow.
+pub trait TxGovernor {
+    fn admit(&mut self, post: &TxPost) -> TxVerdict;
+
+    /// The outcome of a post admitted, ex: to give back what a post the provider failed took.
+    /// Does nothing by default.
+    fn posted(&mut self, post: &TxPost, result: &Result<()>) {
+        let _ = (post, result);
+    }
+}
+
+impl<F: FnMut(&TxPost) -> TxVerdict> TxGovernor for F {
+    fn admit(&mut self, post: &TxPost) -> TxVerdict {
+        self(post)
+    }
+}
+
+/// Attributes of a [`TokenBucket`].
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct TokenBucketAttr {
+    rate: u64,
+    burst: u64,
+    per_peer: bool,
+    max_depth: Option<usize>,
+}
+
+impl TokenBucketAttr {
+    /// Admit `rate` bytes per second, in bursts of up to `burst` bytes.
+    pub fn new(rate: u64, burst: u64) -> Self {
+        TokenBucketAttr {
+            rate: rate.max(1),
+            burst: burst.max(1),
+            per_peer: false,
+            max_depth: None,
+        }
+    }
+
+    /// Keep a bucket per peer, each admitting the rate, rather than one for all of them.
+    pub fn per_peer(mut self, per_peer: bool) -> Self {
+        self.per_peer = per_peer;
+        self
+    }
+
+    /// Delay the posts while this many operations are in flight, unbounded by default.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth.max(1));
+        self
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+/// A [`TxGovernor`] capping the bytes posted per second: each post takes its length in
+/// tokens from a bucket refilled at the rate, up to the burst, and is delayed until the
+/// bucket holds as many tokens, or a full burst for posts larger than that. Tokens taken by
+/// posts the provider failed are given back.
+pub struct TokenBucket {
+    attr: TokenBucketAttr,
+    // By peer, or under `None` for all of them.
+    buckets: HashMap<Option<Addr>, Bucket>,
+    driver: Box<dyn ProgressDriver + Send>,
+}
+
+impl TokenBucket {
+    /// A bucket full of tokens, refilled on the system clock.
+    pub fn new(attr: &TokenBucketAttr) -> Self {
+        Self::with_driver(attr, SystemProgress::default())
+    }
+
+    /// Like [`new()`](Self::new), refilled on the clock of `driver`.
+    pub fn with_driver(
+        attr: &TokenBucketAttr,
+        driver: impl ProgressDriver + Send + 'static,
+    ) -> Self {
+        TokenBucket {
+            attr: attr.clone(),
+            buckets: HashMap::new(),
+            driver: Box::new(driver),
+        }
+    }
+
+    /// The tokens left for `peer`.
+    pub fn tokens(&mut self, peer: Addr) -> f64 {
+        self.bucket(peer).tokens
+    }
+
+    // The bucket of `peer`, refilled up to now.
+    fn bucket(&mut self, peer: Addr) -> &mut Bucket {
+        let now = self.driver.now();
+        let (rate, burst) = (self.attr.rate as f64, self.attr.burst as f64);
+        let key = self.attr.per_peer.then_some(peer);
+        let bucket = self.buckets.entry(key).or_insert(Bucket {
+            tokens: burst,
+            last: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.last);
+        bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() * rate).min(burst);
+        bucket.last = now;
+        bucket
+    }
+}
+
+impl TxGovernor for TokenBucket {
+    fn admit(&mut self, post: &TxPost) -> TxVerdict {
+        if self.attr.max_depth.is_some_and(|max| post.depth >= max) {
+            return TxVerdict::Delay(Duration::ZERO);
+        }
+        let (rate, burst) = (self.attr.rate as f64, self.attr.burst as f64);
+        let bucket = self.bucket(post.peer);
+        let needed = (post.len as f64).min(burst);
+        if bucket.tokens < needed {
+            return TxVerdict::Delay(Duration::from_secs_f64((needed - bucket.tokens) / rate));
+        }
+        // Posts over a burst take the bucket below zero, for the time they take at the rate.
+        bucket.tokens -= post.len as f64;
+        TxVerdict::Admit
+    }
+
+    fn posted(&mut self, post: &TxPost, result: &Result<()>) {
+        if result.is_err() {
+            let burst = self.attr.burst as f64;
+            let bucket = self.bucket(post.peer);
+            bucket.tokens = (bucket.tokens + post.len as f64).min(burst);
+        }
+    }
+}
+
+struct State<G> {
+    governor: G,
+    // The transmit operations in flight, by context.
+    pending: HashMap<usize, usize>,
+    depth: usize,
+    delayed: u64,
+    rejected: u64,
+    last_delay: Option<Duration>,
+}
+
+impl<G> State<G> {
+    fn complete(&mut self, context: usize) {
+        if let Some(count) = self.pending.get_mut(&context) {
+            *count -= 1;
+            if *count == 0 {
+                self.pending.remove(&context);
+            }
+            self.depth -= 1;
+        }
+    }
+}
+
+/// An endpoint whose transmit operations are decided on by a [`TxGovernor`] before they are
+/// posted: sends, injects, reads and writes, receives being posted as they come. What the
+/// governor delays fails with `FI_EAGAIN`, which the callers of the
+/// [transport traits](crate::Transport) retry as when the provider has no room, and what it
+/// rejects with `FI_ECANCELED`.
+///
+/// The depth of the endpoint passed to the governor counts the operations posted until their
+/// completion is read from the queue wrapped by [`cq()`](Self::cq).
+///
+/// ```no_run
+/// use libfabric::{CompletionQueue, Endpoint, GovernedEndpoint, TokenBucket, TokenBucketAttr};
+///
+/// # fn run(ep: Endpoint, cq: CompletionQueue) {
+/// // 1 GB/s in bursts of 1 MB, 64 operations deep at most.
+/// let attr = TokenBucketAttr::new(1 << 30, 1 << 20).max_depth(64);
+/// let ep = GovernedEndpoint::new(ep, TokenBucket::new(&attr));
+/// let cq = ep.cq(cq);
+/// // Run over `ep` and `cq` through the transport traits.
+/// # }
+/// ```
+pub struct GovernedEndpoint<T, G> {
+    inner: T,
+    state: Arc<Mutex<State<G>>>,
+}
+
+impl<T: Clone, G> Clone for GovernedEndpoint<T, G> {
+    fn clone(&self) -> Self {
+        GovernedEndpoint {
+            inner: self.inner.clone(),
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<T, G: TxGovernor> GovernedEndpoint<T, G> {
+    pub fn new(ep: T, governor: G) -> Self {
+        GovernedEndpoint {
+            inner: ep,
+            state: Arc::new(Mutex::new(State {
+                governor,
+                pending: HashMap::new(),
+                depth: 0,
+                delayed: 0,
+                rejected: 0,
+                last_delay: None,
+            })),
+        }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Count the completions of the endpoint read from `cq`, its transmit queue.
+    pub fn cq<C: Cq>(&self, cq: C) -> GovernedCq<C, G> {
+        GovernedCq {
+            inner: cq,
+            state: self.state.clone(),
+        }
+    }
+
+    /// Run `f` on the governor, ex: to change its rates.
+    pub fn with_governor<R>(&self, f: impl FnOnce(&mut G) -> R) -> R {
+        f(&mut self.lock().governor)
+    }
+
+    /// Transmit operations posted, whose completion was not read yet.
+    pub fn depth(&self) -> usize {
+        self.lock().depth
+    }
+
+    /// Posts delayed, and rejected, so far.
+    pub fn delayed(&self) -> u64 {
+        self.lock().delayed
+    }
+
+    pub fn rejected(&self) -> u64 {
+        self.lock().rejected
+    }
+
+    /// How long the last post delayed was to wait.
+    pub fn last_delay(&self) -> Option<Duration> {
+        self.lock().last_delay
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State<G>> {
+        self.state.lock().unwrap()
+    }
+
+    // Post an operation of `len` bytes if admitted, in flight until its completion unless
+    // injected. The state stays locked meanwhile, so its completion cannot be read before it
+    // is pending.
+    fn govern(
+        &self,
+        kind: OpKind,
+        len: usize,
+        peer: Addr,
+        context: Option<usize>,
+        post: impl FnOnce() -> Result<()>,
+    ) -> Result<()> {
+        let mut state = self.lock();
+        let tx = TxPost {
+            kind,
+            len,
+            peer,
+            depth: state.depth,
+        };
+        match state.governor.admit(&tx) {
+            TxVerdict::Admit => {}
+            TxVerdict::Delay(delay) => {
+                state.delayed += 1;
+                state.last_delay = Some(delay);
+                return Err(Error::fabric(kind.name(), ffi::FI_EAGAIN as i64));
+            }
+            TxVerdict::Reject => {
+                state.rejected += 1;
+                return Err(Error::fabric(kind.name(), ffi::FI_ECANCELED as i64));
+            }
+        }
+        let posted = post();
+        state.governor.posted(&tx, &posted);
+        if let (Ok(()), Some(context)) = (&posted, context) {
+            *state.pending.entry(context).or_default() += 1;
+            state.depth += 1;
+        }
+        posted
+    }
+}
+
+impl<T: Transport, G: TxGovernor> Transport for GovernedEndpoint<T, G> {
+    type Mr = T::Mr;
+
+    fn name(&self) -> Result<EndpointAddress> {
+        self.inner.name()
+    }
+
+    unsafe fn recv(
+        &self,
+        buf: &mut [u8],
+        mr: Option<&T::Mr>,
+        src: Addr,
+        context: usize,
+    ) -> Result<()> {
+        unsafe { self.inner.recv(buf, mr, src, context) }
+    }
+
+    unsafe fn send(
+        &self,
+        buf: &[u8],
+        mr: Option<&T::Mr>,
+        dest: Addr,
+        context: usize,
+    ) -> Result<()> {
+        self.govern(OpKind::Send, buf.len(), dest, Some(context), || unsafe {
+            self.inner.send(buf, mr, dest, context)
+        })
+    }
+
+    unsafe fn senddata(
+        &self,
+        buf: &[u8],
+        mr: Option<&T::Mr>,
+        data: u64,
+        dest: Addr,
+        context: usize,
+    ) -> Result<()> {
+        self.govern(
+            OpKind::SendData,
+            buf.len(),
+            dest,
+            Some(context),
+            || unsafe { self.inner.senddata(buf, mr, data, dest, context) },
+        )
+    }
+
+    fn inject(&self, buf: &[u8], dest: Addr) -> Result<()> {
+        self.govern(OpKind::Inject, buf.len(), dest, None, || {
+            self.inner.inject(buf, dest)
+        })
+    }
+
+    unsafe fn trecv(
+        &self,
+        buf: &mut [u8],
+        mr: Option<&T::Mr>,
+        src: Addr,
+        tag: u64,
+        ignore: u64,
+        context: usize,
+    ) -> Result<()> {
+        unsafe { self.inner.trecv(buf, mr, src, tag, ignore, context) }
+    }
+
+    unsafe fn tsend(
+        &self,
+        buf: &[u8],
+        mr: Option<&T::Mr>,
+        dest: Addr,
+        tag: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.govern(OpKind::TSend, buf.len(), dest, Some(context), || unsafe {
+            self.inner.tsend(buf, mr, dest, tag, context)
+        })
+    }
+
+    unsafe fn tsenddata(
+        &self,
+        buf: &[u8],
+        mr: Option<&T::Mr>,
+        data: u64,
+        dest: Addr,
+        tag: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.govern(
+            OpKind::TSendData,
+            buf.len(),
+            dest,
+            Some(context),
+            || unsafe { self.inner.tsenddata(buf, mr, data, dest, tag, context) },
+        )
+    }
+
+    fn tinject(&self, buf: &[u8], dest: Addr, tag: u64) -> Result<()> {
+        self.govern(OpKind::TInject, buf.len(), dest, None, || {
+            self.inner.tinject(buf, dest, tag)
+        })
+    }
+
+    unsafe fn read(
+        &self,
+        buf: &mut [u8],
+        mr: Option<&T::Mr>,
+        src: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()> {
+        let len = buf.len();
+        self.govern(OpKind::Read, len, src, Some(context), || unsafe {
+            self.inner.read(buf, mr, src, addr, key, context)
+        })
+    }
+
+    unsafe fn write(
+        &self,
+        buf: &[u8],
+        mr: Option<&T::Mr>,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.govern(OpKind::Write, buf.len(), dest, Some(context), || unsafe {
+            self.inner.write(buf, mr, dest, addr, key, context)
+        })
+    }
+
+    unsafe fn writedata(
+        &self,
+        buf: &[u8],
+        mr: Option<&T::Mr>,
+        data: u64,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.govern(
+            OpKind::WriteData,
+            buf.len(),
+            dest,
+            Some(context),
+            || unsafe {
+                self.inner
+                    .writedata(buf, mr, data, dest, addr, key, context)
+            },
+        )
+    }
+}
+
+/// The transmit queue of a [`GovernedEndpoint`], made by [`GovernedEndpoint::cq()`].
+pub struct GovernedCq<C, G> {
+    inner: C,
+    state: Arc<Mutex<State<G>>>,
+}
+
+impl<C, G> GovernedCq<C, G> {
+    pub fn get_ref(&self) -> &C {
+        &self.inner
+    }
+
+    fn completed(&self, completions: &[Completion]) {
+        let mut state = self.state.lock().unwrap();
+        for completion in completions {
+            state.complete(completion.context());
+        }
+    }
+}
+
+impl<C: Cq, G> Cq for GovernedCq<C, G> {
+    fn read(&self, out: &mut [Completion]) -> Result<usize> {
+        let n = self.inner.read(out)?;
+        self.completed(&out[..n]);
+        Ok(n)
+    }
+
+    fn read_from(&self, out: &mut [Completion], src: &mut [Addr]) -> Result<usize> {
+        let n = self.inner.read_from(out, src)?;
+        self.completed(&out[..n]);
+        Ok(n)
+    }
+
+    fn read_err(&self) -> Result<Option<CqErrEntry>> {
+        let entry = self.inner.read_err()?;
+        if let Some(entry) = &entry {
+            self.state.lock().unwrap().complete(entry.context);
+        }
+        Ok(entry)
+    }
+}
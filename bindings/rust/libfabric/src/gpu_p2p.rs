@@ -0,0 +1,322 @@
+//! Transfers between the GPU buffers of two nodes, without staging them in host memory
+//! (GPUDirect RDMA), enabled by the `cuda` or `ze` feature.
+//!
+//! A [`GpuBuffer`] registers device memory with its interface and device (`fi_mr_regattr()`),
+//! [`GpuP2p::connect()`] exchanges the keys of the buffers of all members over a
+//! [`Bootstrap`], and [`GpuP2p::write()`] and [`GpuP2p::read()`] then move ranges of bytes
+//! between the local buffer and that of a peer with RMA, blocking until they completed.
+//! [`GpuP2p::verify()`] reads a range of the buffer of a peer back into host memory, and
+//! compares it with what it should hold, which checks the whole path on a new setup.
+//!
+//! Endpoints must be RDM ones opened with [`Caps::RMA`] and [`Caps::HMEM`], from a provider
+//! able to reach device memory, ex: verbs or efa with the peer memory modules of the GPU
+//! loaded; those without fail the registration or the transfers with their own error.
+//!
+//! ```no_run
+//! # use libfabric::{AddressVector, CompletionQueue, Endpoint};
+//! # fn run(ep: Endpoint, cq: CompletionQueue, av: &AddressVector, gpu: *mut u8) -> libfabric::Result<()> {
+//! use libfabric::bootstrap::Tcp;
+//! use libfabric::gpu_p2p::{GpuBuffer, GpuP2p};
+//! use libfabric::xpu::XpuDevice;
+//! use std::time::Duration;
+//!
+//! // 1 MiB allocated with cudaMalloc() on device 0, filled with 0xab by a kernel.
+//! let len = 1 << 20;
+//! let local = unsafe { GpuBuffer::register(ep.domain(), gpu, len, XpuDevice::Cuda(0)) }?;
+//! let mut job = Tcp::connect("node0:4000", Duration::from_secs(30))?;
+//! let mut p2p = GpuP2p::connect(ep, cq, av, &mut job, local)?;
+//! let peer = 1 - p2p.rank();
+//! p2p.write(peer, 0, len)?;
+//! p2p.verify(peer, 0, &vec![0xab; len])?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::av::{Addr, AddressVector};
+use crate::bootstrap::{Bootstrap, PeerInfo, RemoteRegion};
+use crate::cq::{Completion, CompletionQueue};
+use crate::domain::Domain;
+use crate::ep::Endpoint;
+use crate::error::{Error, Result, check_len};
+use crate::flags::{Access, Caps, MrMode};
+use crate::mr::MemoryRegion;
+use crate::threading::{ThreadSafe, ThreadingModel};
+use crate::xpu::XpuDevice;
+use ofi_libfabric_sys::bindgen as ffi;
+use std::ffi::c_void;
+
+// The RMA operations, whose values are the contexts they are posted with.
+#[derive(Clone, Copy)]
+enum Op {
+    Write = 1,
+    Read = 2,
+}
+
+/// Device memory registered for local and remote access.
+pub struct GpuBuffer<M: ThreadingModel = ThreadSafe> {
+    mr: MemoryRegion<M>,
+    device: XpuDevice,
+}
+
+impl<M: ThreadingModel> GpuBuffer<M> {
+    /// Register the `len` bytes of device memory at `buf`, allocated on `device`. Fails with
+    /// `FI_EOPNOTSUPP` if `domain` was not opened with [`Caps::HMEM`].
+    ///
+    /// # Safety
+    ///
+    /// `buf` must be device memory of `device`, valid for as long as the buffer, which peers
+    /// read and write at any time.
+    pub unsafe fn register(
+        domain: &Domain<M>,
+        buf: *mut u8,
+        len: usize,
+        device: XpuDevice,
+    ) -> Result<Self> {
+        if !domain.info().caps().contains(Caps::HMEM) {
+            return Err(Error::fabric("fi_mr_regattr", ffi::FI_EOPNOTSUPP as i64));
+        }
+        let access = Access::READ | Access::WRITE | Access::REMOTE_READ | Access::REMOTE_WRITE;
+        let mr =
+            unsafe { MemoryRegion::register_device(domain, buf, len, access, device.mr_device()) }?;
+        Ok(GpuBuffer { mr, device })
+    }
+
+    pub fn region(&self) -> &MemoryRegion<M> {
+        &self.mr
+    }
+
+    pub fn device(&self) -> XpuDevice {
+        self.device
+    }
+
+    pub fn len(&self) -> usize {
+        self.mr.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mr.is_empty()
+    }
+}
+
+/// A member of a [`GpuP2p`], and the GPU buffer it exposed.
+#[derive(Debug, Clone, Copy)]
+pub struct GpuPeer {
+    pub addr: Addr,
+    pub region: RemoteRegion,
+}
+
+/// The GPU buffers of the members of a job, moved between with RMA.
+///
+/// Operations block until they complete, reading the completion queue of the endpoint, which
+/// must not be used elsewhere.
+pub struct GpuP2p {
+    ep: Endpoint,
+    cq: CompletionQueue,
+    local: GpuBuffer,
+    peers: Vec<GpuPeer>,
+    rank: usize,
+}
+
+impl GpuP2p {
+    /// Exchange the name of `ep` and the key of `local` with every member of `job`, inserting
+    /// their names into `av`. `ep` must be enabled, with `cq` bound for transmits, and `av`
+    /// bound. Fails with `FI_EOPNOTSUPP` if its domain lacks [`Caps::RMA`].
+    pub fn connect<B: Bootstrap>(
+        ep: Endpoint,
+        cq: CompletionQueue,
+        av: &AddressVector,
+        job: &mut B,
+        local: GpuBuffer,
+    ) -> Result<Self> {
+        let info = ep.domain().info();
+        if !info.caps().contains(Caps::RMA) {
+            return Err(Error::fabric("fi_write", ffi::FI_EOPNOTSUPP as i64));
+        }
+        let virt_addr = info.mr_mode().contains(MrMode::VIRT_ADDR);
+        let contribution = PeerInfo {
+            name: ep.name()?,
+            regions: vec![RemoteRegion::new(local.region(), virt_addr)],
+            data: Vec::new(),
+        };
+        let peers = job
+            .exchange(av, &contribution)?
+            .into_iter()
+            .enumerate()
+            .map(|(rank, peer)| match peer.info.regions[..] {
+                [region] => Ok(GpuPeer {
+                    addr: peer.addr,
+                    region,
+                }),
+                _ => Err(Error::invalid(format!(
+                    "member {rank} exposed {} buffers",
+                    peer.info.regions.len()
+                ))),
+            })
+            .collect::<Result<_>>()?;
+        Ok(GpuP2p {
+            ep,
+            cq,
+            local,
+            peers,
+            rank: job.rank(),
+        })
+    }
+
+    /// The rank of this member in the job.
+    pub fn rank(&self) -> usize {
+        self.rank
+    }
+
+    /// The members, by rank, this one included.
+    pub fn peers(&self) -> &[GpuPeer] {
+        &self.peers
+    }
+
+    pub fn local(&self) -> &GpuBuffer {
+        &self.local
+    }
+
+    /// Write the `len` bytes at `offset` of the local buffer to the same range of the buffer
+    /// of the member `rank`.
+    pub fn write(&mut self, rank: usize, offset: usize, len: usize) -> Result<()> {
+        let peer = self.range(rank, offset, len)?;
+        let (buf, desc) = (
+            self.local.mr.addr().wrapping_add(offset),
+            self.local.mr.desc(),
+        );
+        self.transfer(Op::Write, buf, len, desc, peer, offset)
+    }
+
+    /// Read the `len` bytes at `offset` of the buffer of the member `rank` into the same range
+    /// of the local buffer.
+    pub fn read(&mut self, rank: usize, offset: usize, len: usize) -> Result<()> {
+        let peer = self.range(rank, offset, len)?;
+        let (buf, desc) = (
+            self.local.mr.addr().wrapping_add(offset),
+            self.local.mr.desc(),
+        );
+        self.transfer(Op::Read, buf, len, desc, peer, offset)
+    }
+
+    /// Read the bytes at `offset` of the buffer of the member `rank` into host memory, failing
+    /// with `FI_EINVAL` at the first byte which differs from `expected`.
+    pub fn verify(&mut self, rank: usize, offset: usize, expected: &[u8]) -> Result<()> {
+        let peer = self.range(rank, offset, expected.len())?;
+        let mut host = vec![0u8; expected.len()];
+        let domain = self.ep.domain().clone();
+        // SAFETY: `host` outlives the region, dropped before it.
+        let mr = unsafe { domain.register(host.as_mut_ptr(), host.len(), Access::READ) }?;
+        let read = self.transfer(
+            Op::Read,
+            host.as_mut_ptr(),
+            host.len(),
+            mr.desc(),
+            peer,
+            offset,
+        );
+        drop(mr);
+        read?;
+        match host
+            .iter()
+            .zip(expected)
+            .position(|(got, want)| got != want)
+        {
+            Some(at) => Err(Error::invalid(format!(
+                "byte {} of member {rank} is {:#04x}, expected {:#04x}",
+                offset + at,
+                host[at],
+                expected[at]
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    // The member `rank`, once checked that both buffers span the range.
+    fn range(&self, rank: usize, offset: usize, len: usize) -> Result<GpuPeer> {
+        let peer = *self.peers.get(rank).ok_or_else(|| {
+            Error::invalid(format!(
+                "rank {rank} is out of a job of {}",
+                self.peers.len()
+            ))
+        })?;
+        let end = offset.checked_add(len);
+        if end.is_none_or(|end| end > self.local.len() || end as u64 > peer.region.len) {
+            return Err(Error::invalid(format!(
+                "{len} bytes at {offset} past the buffer of {} bytes, or that of member {rank} of {}",
+                self.local.len(),
+                peer.region.len
+            )));
+        }
+        Ok(peer)
+    }
+
+    // Post a write from, or a read into, the `len` bytes at `buf` of the range at `offset` of
+    // the buffer of `peer`, progressing the queue while the endpoint is out of resources, then
+    // wait for its completion.
+    fn transfer(
+        &mut self,
+        op: Op,
+        buf: *mut u8,
+        len: usize,
+        desc: *mut c_void,
+        peer: GpuPeer,
+        offset: usize,
+    ) -> Result<()> {
+        let (addr, key) = (peer.region.addr + offset as u64, peer.region.key);
+        let context = op as usize;
+        loop {
+            // SAFETY: both buffers are registered and span the range, and stay borrowed until
+            // the operation completed.
+            let (name, ret) = unsafe {
+                match op {
+                    Op::Write => (
+                        "fi_write",
+                        ffi::fi_write(
+                            self.ep.as_raw(),
+                            buf.cast(),
+                            len,
+                            desc,
+                            peer.addr.as_raw(),
+                            addr,
+                            key,
+                            context as *mut c_void,
+                        ),
+                    ),
+                    Op::Read => (
+                        "fi_read",
+                        ffi::fi_read(
+                            self.ep.as_raw(),
+                            buf.cast(),
+                            len,
+                            desc,
+                            peer.addr.as_raw(),
+                            addr,
+                            key,
+                            context as *mut c_void,
+                        ),
+                    ),
+                }
+            };
+            match check_len(name, ret) {
+                Err(err) if err.is_again() => self.progress().map(|_| ())?,
+                other => break other.map(|_| ())?,
+            }
+        }
+        while !self.progress()?.contains(&context) {}
+        Ok(())
+    }
+
+    // The contexts of the completions available, failing with the error of a failed one.
+    fn progress(&self) -> Result<Vec<usize>> {
+        let mut completions = [Completion::default(); 4];
+        match self.cq.read(&mut completions) {
+            Ok(n) => Ok(completions[..n].iter().map(|c| c.context()).collect()),
+            Err(err) if err.is_again() => Ok(Vec::new()),
+            Err(err) if err.is_avail() => match self.cq.read_err()? {
+                Some(entry) => Err(entry.error),
+                None => Ok(Vec::new()),
+            },
+            Err(err) => Err(err),
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use crate::error::{Error, Result};
+
+/// A hooking provider, which intercepts the calls of the applications to the fabrics it is
+/// installed on (see `fi_hook(7)`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Hook {
+    /// Gathers the count and cost, in the unit of `FI_PERF_CNTR`, of the data operations and
+    /// queue reads, reported when the fabric is closed (`ofi_hook_perf`). With the `log`
+    /// feature, the reports are returned by `perf_reports()`.
+    Perf,
+    /// Logs every call with its arguments (`ofi_hook_trace`).
+    Trace,
+    /// Exposes its counters through the profiling interface (`ofi_hook_profile`).
+    Profile,
+    /// Checks the usage of the API, ex: completions never read (`ofi_hook_debug`).
+    Debug,
+    /// Writes call statistics to a file periodically (`ofi_hook_monitor`).
+    Monitor,
+    /// Copies device memory through bounce buffers (`ofi_hook_hmem`).
+    Hmem,
+    /// Registers device memory through dma-buf (`ofi_hook_dmabuf_peer_mem`).
+    DmabufPeerMem,
+    /// A hook by name, or the name of its provider.
+    Other(String),
+}
+
+impl Hook {
+    /// The name `FI_HOOK` lists the hook by.
+    pub fn name(&self) -> &str {
+        match self {
+            Hook::Perf => "perf",
+            Hook::Trace => "trace",
+            Hook::Profile => "profile",
+            Hook::Debug => "debug",
+            Hook::Monitor => "monitor",
+            Hook::Hmem => "hmem",
+            Hook::DmabufPeerMem => "dmabuf_peer_mem",
+            Hook::Other(name) => name,
+        }
+    }
+
+    // The provider of the hook, whose parameters are prefixed by its name.
+    fn provider(&self) -> String {
+        match self {
+            Hook::Other(name) if name.starts_with("ofi_hook_") => name.clone(),
+            hook => format!("ofi_hook_{}", hook.name()),
+        }
+    }
+}
+
+/// The hooks installed on every fabric the process opens, and their parameters.
+///
+/// libfabric reads both from the environment on its initialization, which [`install()`]
+/// sets: it takes effect only when called before any other call into libfabric, and hooks
+/// cannot be removed afterwards.
+///
+/// ```no_run
+/// use libfabric::{Hook, HookConfig};
+///
+/// let hooks = HookConfig::new()
+///     .hook(Hook::Perf)
+///     .hook(Hook::Monitor)
+///     .param(Hook::Monitor, "tick_max", "5");
+/// // SAFETY: no other thread runs yet.
+/// unsafe { hooks.install() }.unwrap();
+/// ```
+///
+/// [`install()`]: Self::install
+#[derive(Debug, Clone, Default)]
+#[must_use]
+pub struct HookConfig {
+    hooks: Vec<Hook>,
+    // Environment variables and their values.
+    params: Vec<(String, String)>,
+}
+
+impl HookConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Install `hook`, after those added before it, the first one being the closest to the
+    /// application.
+    pub fn hook(mut self, hook: Hook) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    /// Set the parameter `name` of `hook`, ex: `linger` for `FI_OFI_HOOK_MONITOR_LINGER`.
+    pub fn param(mut self, hook: Hook, name: &str, value: &str) -> Self {
+        let var = format!("FI_{}_{}", hook.provider(), name).to_uppercase();
+        self.params.push((var, value.to_owned()));
+        self
+    }
+
+    /// Set `FI_HOOK`, and the parameters of the hooks, in the environment of the process.
+    ///
+    /// # Safety
+    ///
+    /// Setting the environment is only sound while no other thread reads or writes it, see
+    /// [`std::env::set_var()`].
+    pub unsafe fn install(&self) -> Result<()> {
+        if let Some(hook) = self
+            .hooks
+            .iter()
+            .find(|hook| hook.name().is_empty() || hook.name().contains([';', '\0']))
+        {
+            return Err(Error::invalid(format!(
+                "invalid hook name {:?}",
+                hook.name()
+            )));
+        }
+        let names: Vec<_> = self.hooks.iter().map(Hook::name).collect();
+        unsafe { std::env::set_var("FI_HOOK", names.join(";")) };
+        for (var, value) in &self.params {
+            unsafe { std::env::set_var(var, value) };
+        }
+        Ok(())
+    }
+}
+
+/// A counter of the perf hook: one of the intercepted calls.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PerfCounter {
+    /// The name of the call, ex: `perf_tsend`.
+    pub name: String,
+    /// The average cost of a call, in the unit of the report.
+    pub average: f64,
+    pub events: u64,
+}
+
+/// What the perf hook gathered on a fabric, reported once it is closed.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PerfReport {
+    /// What the costs are measured in, ex: `cpu_instr` or `cpu_cycles`, as set by
+    /// `FI_PERF_CNTR`.
+    pub unit: String,
+    /// The calls made at least once.
+    pub counters: Vec<PerfCounter>,
+}
+
+impl PerfReport {
+    pub fn counter(&self, name: &str) -> Option<&PerfCounter> {
+        self.counters.iter().find(|counter| counter.name == name)
+    }
+}
+
+#[cfg(feature = "log")]
+static PERF: std::sync::Mutex<Vec<PerfReport>> = std::sync::Mutex::new(Vec::new());
+
+/// Take the reports of the perf hook for the fabrics closed since the last call, in order.
+///
+/// The perf hook logs its report, which is intercepted once logging is routed with
+/// [`route_logging()`](crate::route_logging), rather than handed to the `log` crate. Enabled
+/// by the `log` feature.
+#[cfg(feature = "log")]
+pub fn perf_reports() -> Vec<PerfReport> {
+    std::mem::take(&mut *PERF.lock().unwrap())
+}
+
+// Whether a message is part of a report of the perf hook, which logs it in trace messages of
+// the core subsystem.
+#[cfg(feature = "log")]
+pub(crate) fn is_perf_report(
+    prov: &str,
+    level: ofi_libfabric_sys::bindgen::fi_log_level,
+    subsys: ofi_libfabric_sys::bindgen::fi_log_subsys,
+) -> bool {
+    use ofi_libfabric_sys::bindgen as ffi;
+
+    prov == "ofi_hook_perf"
+        && level == ffi::fi_log_level_FI_LOG_TRACE
+        && subsys == ffi::fi_log_subsys_FI_LOG_CORE
+}
+
+// Add a line of a report of the perf hook: an empty line, then `PERF: {unit}`, a header, and
+// one `{name} {average} {events}` line per counter.
+#[cfg(feature = "log")]
+pub(crate) fn perf_line(line: &str) {
+    let mut reports = PERF.lock().unwrap();
+    if let Some(unit) = line.trim().strip_prefix("PERF:") {
+        reports.push(PerfReport {
+            unit: unit.trim().to_owned(),
+            counters: Vec::new(),
+        });
+        return;
+    }
+    let Some(report) = reports.last_mut() else {
+        return;
+    };
+    if let [name, average, events] = line.split_whitespace().collect::<Vec<_>>()[..]
+        && let (Ok(average), Ok(events)) = (average.parse(), events.parse())
+    {
+        report.counters.push(PerfCounter {
+            name: name.to_owned(),
+            average,
+            events,
+        });
+    }
+}
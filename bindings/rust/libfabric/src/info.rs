@@ -0,0 +1,839 @@
+use crate::attr::{
+    DomainAttr, DomainConfig, EpAttr, FabricAttr, Protocol, RxAttr, RxQueueAttr, TrafficClass,
+    TxAttr, TxQueueAttr,
+};
+use crate::av::{AddrFormat, EndpointAddress};
+use crate::error::{Error, Result, check};
+use crate::flags::{Caps, Mode, MrMode};
+use crate::threading::{Threading, ThreadingModel};
+use crate::util::{cstr, read_enum, write_enum};
+use ofi_libfabric_sys::bindgen as ffi;
+use std::collections::BTreeSet;
+use std::ffi::CString;
+use std::fmt;
+use std::ptr::{self, NonNull};
+
+/// Libfabric API version, as encoded by `FI_VERSION(major, minor)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Version {
+    pub major: u16,
+    pub minor: u16,
+}
+
+impl Version {
+    /// The version of the headers the bindings were generated from.
+    pub const HEADER: Version =
+        Version::new(ffi::FI_MAJOR_VERSION as u16, ffi::FI_MINOR_VERSION as u16);
+
+    pub const fn new(major: u16, minor: u16) -> Self {
+        Version { major, minor }
+    }
+
+    /// Decodes a version packed as `(major << 16) | minor`.
+    pub const fn from_raw(raw: u32) -> Self {
+        Version::new((raw >> 16) as u16, (raw & 0xFFFF) as u16)
+    }
+
+    pub const fn as_raw(self) -> u32 {
+        ((self.major as u32) << 16) | self.minor as u32
+    }
+
+    /// The version of the libfabric library linked at runtime, via `fi_version()`.
+    pub fn linked() -> Self {
+        Version::from_raw(unsafe { ffi::fi_version() })
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// Endpoint types (`enum fi_ep_type`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EndpointType {
+    Unspec,
+    /// Reliable, connection oriented.
+    Msg,
+    /// Unreliable datagrams.
+    Dgram,
+    /// Reliable datagrams, connectionless.
+    Rdm,
+    /// A raw value these bindings do not know about, ex: from a newer libfabric.
+    Other(u32),
+}
+
+impl EndpointType {
+    pub(crate) fn from_raw(raw: u32) -> Self {
+        match ffi::fi_ep_type::try_from(raw) {
+            Ok(ffi::fi_ep_type::FI_EP_UNSPEC) => EndpointType::Unspec,
+            Ok(ffi::fi_ep_type::FI_EP_MSG) => EndpointType::Msg,
+            Ok(ffi::fi_ep_type::FI_EP_DGRAM) => EndpointType::Dgram,
+            Ok(ffi::fi_ep_type::FI_EP_RDM) => EndpointType::Rdm,
+            _ => EndpointType::Other(raw),
+        }
+    }
+
+    pub(crate) fn as_raw(self) -> u32 {
+        match self {
+            EndpointType::Unspec => ffi::fi_ep_type::FI_EP_UNSPEC as u32,
+            EndpointType::Msg => ffi::fi_ep_type::FI_EP_MSG as u32,
+            EndpointType::Dgram => ffi::fi_ep_type::FI_EP_DGRAM as u32,
+            EndpointType::Rdm => ffi::fi_ep_type::FI_EP_RDM as u32,
+            EndpointType::Other(raw) => raw,
+        }
+    }
+}
+
+/// Hints for `fi_getinfo()`, built up with chained setters.
+///
+/// ```no_run
+/// use libfabric::{Caps, EndpointType, Info};
+///
+/// let entries = Info::new()
+///     .caps(Caps::MSG | Caps::TAGGED)
+///     .ep_type(EndpointType::Rdm)
+///     .provider("tcp")
+///     .get()?;
+/// println!("{}", entries[0].provider_name());
+/// # Ok::<(), libfabric::Error>(())
+/// ```
+///
+/// Threading defaults to `FI_THREAD_SAFE`, which the wrappers rely on to be shareable across
+/// threads, see [`threading()`](Self::threading) for lesser levels.
+///
+/// Providers are never told the application supports `FI_CONTEXT`/`FI_CONTEXT2`, so the
+/// context of an operation is an opaque value handed back in its completion.
+#[must_use]
+pub struct Info {
+    hints: NonNull<ffi::fi_info>,
+    // Strings referenced from `hints`. They are detached again before fi_freeinfo() runs.
+    prov_name: Option<CString>,
+    fabric_name: Option<CString>,
+    domain_name: Option<CString>,
+    node: Option<CString>,
+    service: Option<CString>,
+    src_addr: Option<Vec<u8>>,
+    auth_key: Option<Vec<u8>>,
+    flags: u64,
+    version: Version,
+    error: Option<Error>,
+}
+
+// SAFETY: The hints are exclusively owned, and only read by libfabric during get().
+unsafe impl Send for Info {}
+
+impl Default for Info {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Info {
+    /// Allocate empty hints with `fi_allocinfo()`.
+    pub fn new() -> Self {
+        let hints =
+            NonNull::new(unsafe { ffi::fi_allocinfo() }).expect("fi_allocinfo() out of memory");
+        unsafe { (*(*hints.as_ptr()).domain_attr).threading = ffi::fi_threading::FI_THREAD_SAFE };
+        Info {
+            hints,
+            prov_name: None,
+            fabric_name: None,
+            domain_name: None,
+            node: None,
+            service: None,
+            src_addr: None,
+            auth_key: None,
+            flags: 0,
+            version: Version::HEADER,
+            error: None,
+        }
+    }
+
+    fn raw(&mut self) -> &mut ffi::fi_info {
+        unsafe { self.hints.as_mut() }
+    }
+
+    fn string(&mut self, what: &str, value: &str) -> Option<CString> {
+        match CString::new(value) {
+            Ok(s) => Some(s),
+            Err(_) => {
+                self.error = Some(Error::invalid(format!("{what} contains a NUL byte")));
+                None
+            }
+        }
+    }
+
+    /// Required capabilities.
+    pub fn caps(mut self, caps: Caps) -> Self {
+        self.raw().caps = caps.bits();
+        self
+    }
+
+    /// Modes the application is able to support.
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.raw().mode = mode.bits();
+        self
+    }
+
+    pub fn ep_type(mut self, ep_type: EndpointType) -> Self {
+        unsafe { write_enum(&raw mut (*self.raw().ep_attr).type_, ep_type.as_raw()) };
+        self
+    }
+
+    /// Memory registration modes the application is able to support.
+    pub fn mr_mode(mut self, mr_mode: MrMode) -> Self {
+        unsafe { (*self.raw().domain_attr).mr_mode = mr_mode.bits() as i32 };
+        self
+    }
+
+    /// The thread safety the application requires: domains of entries with a level below
+    /// [`Threading::Safe`] are opened with
+    /// [`Domain::open_with_threading()`](crate::Domain::open_with_threading), under a
+    /// [`ThreadingModel`](crate::ThreadingModel) which keeps their objects on one thread.
+    pub fn threading(mut self, threading: Threading) -> Self {
+        unsafe {
+            write_enum(
+                &raw mut (*self.raw().domain_attr).threading,
+                threading.as_raw(),
+            )
+        };
+        self
+    }
+
+    /// Request the threading, progress and resource management of `config`, overriding
+    /// [`threading()`](Self::threading).
+    pub fn domain_config<M: ThreadingModel>(mut self, config: &DomainConfig<M>) -> Self {
+        config.apply(unsafe { &mut *self.raw().domain_attr });
+        self
+    }
+
+    /// Request scalable endpoints with `tx` transmit and `rx` receive contexts, see
+    /// [`ScalableEndpoint`](crate::ScalableEndpoint).
+    pub fn contexts(mut self, tx: usize, rx: usize) -> Self {
+        let ep_attr = unsafe { &mut *self.raw().ep_attr };
+        ep_attr.tx_ctx_cnt = tx;
+        ep_attr.rx_ctx_cnt = rx;
+        self
+    }
+
+    /// Require the wire protocol of the endpoints.
+    pub fn protocol(mut self, protocol: Protocol) -> Self {
+        unsafe { (*self.raw().ep_attr).protocol = protocol.as_raw() };
+        self
+    }
+
+    /// Request a traffic class for the domain, which its endpoints default to, and for their
+    /// transmit contexts.
+    pub fn tclass(mut self, tclass: TrafficClass) -> Self {
+        let raw = self.raw();
+        unsafe {
+            (*raw.domain_attr).tclass = tclass.as_raw();
+            (*raw.tx_attr).tclass = tclass.as_raw();
+        }
+        self
+    }
+
+    /// Require the transmit contexts of the entries to meet the settings of `attr`.
+    pub fn tx_attr(mut self, attr: &TxQueueAttr) -> Self {
+        attr.apply(unsafe { &mut *self.raw().tx_attr });
+        self
+    }
+
+    /// Require the receive contexts of the entries to meet the settings of `attr`.
+    pub fn rx_attr(mut self, attr: &RxQueueAttr) -> Self {
+        attr.apply(unsafe { &mut *self.raw().rx_attr });
+        self
+    }
+
+    /// Restrict discovery to one provider, e.g. `"tcp"` or `"efa"`.
+    pub fn provider(mut self, name: &str) -> Self {
+        self.prov_name = self.string("provider name", name);
+        let ptr = self.prov_name.as_ref().map_or(ptr::null(), |s| s.as_ptr());
+        unsafe { (*self.raw().fabric_attr).prov_name = ptr as *mut _ };
+        self
+    }
+
+    pub fn fabric_name(mut self, name: &str) -> Self {
+        self.fabric_name = self.string("fabric name", name);
+        let ptr = self
+            .fabric_name
+            .as_ref()
+            .map_or(ptr::null(), |s| s.as_ptr());
+        unsafe { (*self.raw().fabric_attr).name = ptr as *mut _ };
+        self
+    }
+
+    pub fn domain_name(mut self, name: &str) -> Self {
+        self.domain_name = self.string("domain name", name);
+        let ptr = self
+            .domain_name
+            .as_ref()
+            .map_or(ptr::null(), |s| s.as_ptr());
+        unsafe { (*self.raw().domain_attr).name = ptr as *mut _ };
+        self
+    }
+
+    /// Node (host name or address) passed to `fi_getinfo()`.
+    pub fn node(mut self, node: &str) -> Self {
+        self.node = self.string("node", node);
+        self
+    }
+
+    /// Service (port) passed to `fi_getinfo()`.
+    pub fn service(mut self, service: &str) -> Self {
+        self.service = self.string("service", service);
+        self
+    }
+
+    /// Treat node/service as the local address to bind to (`FI_SOURCE`), e.g. for a server.
+    pub fn source(mut self) -> Self {
+        self.flags |= ffi::FI_SOURCE;
+        self
+    }
+
+    // The local address to bind to, in `format` (ex: FI_SOCKADDR_IB).
+    pub(crate) fn src_addr(mut self, format: u32, addr: &[u8]) -> Self {
+        self.src_addr = Some(addr.to_vec());
+        let addr = self.src_addr.as_mut().unwrap();
+        let (ptr, len) = (addr.as_mut_ptr(), addr.len());
+        let raw = self.raw();
+        raw.addr_format = format;
+        raw.src_addr = ptr.cast();
+        raw.src_addrlen = len;
+        self
+    }
+
+    /// The authorization key of the domain, which its endpoints default to, in the format of
+    /// the provider (ex: `struct cxi_auth_key`).
+    pub fn auth_key(mut self, key: &[u8]) -> Self {
+        self.auth_key = Some(key.to_vec());
+        let key = self.auth_key.as_mut().unwrap();
+        let (ptr, len) = (key.as_mut_ptr(), key.len());
+        let domain_attr = unsafe { &mut *self.raw().domain_attr };
+        domain_attr.auth_key = ptr;
+        domain_attr.auth_key_size = len;
+        self
+    }
+
+    /// API version to request, defaulting to the version of the headers.
+    pub fn version(mut self, version: Version) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// The raw hints, for fields not covered by the setters.
+    pub fn as_raw_mut(&mut self) -> *mut ffi::fi_info {
+        self.hints.as_ptr()
+    }
+
+    pub(crate) fn hints(&self) -> &ffi::fi_info {
+        unsafe { self.hints.as_ref() }
+    }
+
+    /// Run `fi_getinfo()`, returning every matching entry ordered by provider preference.
+    pub fn get(&self) -> Result<Vec<InfoEntry>> {
+        if let Some(err) = &self.error {
+            return Err(err.clone());
+        }
+        let as_ptr = |s: &Option<CString>| s.as_ref().map_or(ptr::null(), |s| s.as_ptr());
+        let mut list = ptr::null_mut();
+        check("fi_getinfo", unsafe {
+            ffi::fi_getinfo(
+                self.version.as_raw(),
+                as_ptr(&self.node),
+                as_ptr(&self.service),
+                self.flags,
+                self.hints.as_ptr(),
+                &mut list,
+            )
+        })?;
+        Ok(unsafe { take_list(list) })
+    }
+
+    // Discover with a copy of the hints as changed by `edit`, none if nothing matches. The
+    // edit may change scalars and clear names, but not point the copy at memory of its own.
+    pub(crate) fn get_edited(
+        &self,
+        edit: impl FnOnce(&mut ffi::fi_info),
+    ) -> Result<Vec<InfoEntry>> {
+        if let Some(err) = &self.error {
+            return Err(err.clone());
+        }
+        let copy = NonNull::new(unsafe { ffi::fi_dupinfo(self.hints.as_ptr()) })
+            .ok_or_else(|| Error::fabric("fi_dupinfo", ffi::FI_ENOMEM as i64))?;
+        // SAFETY: The copy is ours, with its attributes, until freed below.
+        let raw = unsafe { &mut *copy.as_ptr() };
+        let names = unsafe {
+            (
+                (*raw.fabric_attr).name,
+                (*raw.fabric_attr).prov_name,
+                (*raw.domain_attr).name,
+            )
+        };
+        edit(raw);
+        let as_ptr = |s: &Option<CString>| s.as_ref().map_or(ptr::null(), |s| s.as_ptr());
+        let mut list = ptr::null_mut();
+        let ret = unsafe {
+            ffi::fi_getinfo(
+                self.version.as_raw(),
+                as_ptr(&self.node),
+                as_ptr(&self.service),
+                self.flags,
+                copy.as_ptr(),
+                &mut list,
+            )
+        };
+        // Restored, so that fi_freeinfo() frees the names fi_dupinfo() copied.
+        unsafe {
+            (*raw.fabric_attr).name = names.0;
+            (*raw.fabric_attr).prov_name = names.1;
+            (*raw.domain_attr).name = names.2;
+            ffi::fi_freeinfo(copy.as_ptr());
+        }
+        if ret == -(ffi::FI_ENODATA as i32) {
+            return Ok(Vec::new());
+        }
+        check("fi_getinfo", ret)?;
+        Ok(unsafe { take_list(list) })
+    }
+}
+
+// Copy the entries of a list returned by `fi_getinfo()`, then free it.
+//
+// SAFETY: `list` must be NULL or a list allocated by libfabric, not used afterwards.
+unsafe fn take_list(list: *mut ffi::fi_info) -> Vec<InfoEntry> {
+    let mut entries = Vec::new();
+    let mut cur = list;
+    while !cur.is_null() {
+        // fi_dupinfo() copies a single entry, leaving its `next` pointer NULL.
+        if let Some(entry) = NonNull::new(unsafe { ffi::fi_dupinfo(cur) }) {
+            entries.push(InfoEntry { ptr: entry });
+        }
+        cur = unsafe { (*cur).next };
+    }
+    unsafe { ffi::fi_freeinfo(list) };
+    entries
+}
+
+impl Drop for Info {
+    fn drop(&mut self) {
+        // The strings are owned by Rust, and must not be passed to free().
+        let hints = self.raw();
+        unsafe {
+            (*hints.fabric_attr).prov_name = ptr::null_mut();
+            (*hints.fabric_attr).name = ptr::null_mut();
+            (*hints.domain_attr).name = ptr::null_mut();
+            (*hints.domain_attr).auth_key = ptr::null_mut();
+            hints.src_addr = ptr::null_mut();
+            ffi::fi_freeinfo(hints);
+        }
+    }
+}
+
+/// Description of a network interface (`struct fid_nic`).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Nic {
+    pub name: String,
+    pub device_id: String,
+    pub device_version: String,
+    pub vendor_id: String,
+    pub driver: String,
+    pub firmware: String,
+    /// The PCI address of PCI devices.
+    pub pci: Option<PciAddress>,
+    pub link_address: String,
+    pub mtu: usize,
+    /// Link speed, in bits per second.
+    pub speed: usize,
+    /// Whether the link is up, if known.
+    pub link_up: Option<bool>,
+    pub network_type: String,
+}
+
+impl Nic {
+    // SAFETY: The attributes of `nic` must be NULL or valid.
+    unsafe fn from_raw(nic: &ffi::fid_nic) -> Self {
+        let mut desc = Nic::default();
+        if let Some(device) = unsafe { nic.device_attr.as_ref() } {
+            desc.name = unsafe { cstr(device.name) }.to_owned();
+            desc.device_id = unsafe { cstr(device.device_id) }.to_owned();
+            desc.device_version = unsafe { cstr(device.device_version) }.to_owned();
+            desc.vendor_id = unsafe { cstr(device.vendor_id) }.to_owned();
+            desc.driver = unsafe { cstr(device.driver) }.to_owned();
+            desc.firmware = unsafe { cstr(device.firmware) }.to_owned();
+        }
+        if let Some(bus) = unsafe { nic.bus_attr.as_ref() }
+            && unsafe { read_enum(&raw const bus.bus_type) } == ffi::fi_bus_type_FI_BUS_PCI
+        {
+            let pci = unsafe { bus.attr.pci };
+            desc.pci = Some(PciAddress {
+                domain: pci.domain_id,
+                bus: pci.bus_id,
+                device: pci.device_id,
+                function: pci.function_id,
+            });
+        }
+        if let Some(link) = unsafe { nic.link_attr.as_ref() } {
+            desc.link_address = unsafe { cstr(link.address) }.to_owned();
+            desc.mtu = link.mtu;
+            desc.speed = link.speed;
+            desc.link_up = match unsafe { read_enum(&raw const link.state) } {
+                ffi::fi_link_state_FI_LINK_UP => Some(true),
+                ffi::fi_link_state_FI_LINK_DOWN => Some(false),
+                _ => None,
+            };
+            desc.network_type = unsafe { cstr(link.network_type) }.to_owned();
+        }
+        desc
+    }
+
+    /// The NUMA node of a PCI device, from sysfs on Linux.
+    pub fn numa_node(&self) -> Option<u32> {
+        let path = format!("/sys/bus/pci/devices/{}/numa_node", self.pci?);
+        // An unknown node reads as -1, which does not parse.
+        std::fs::read_to_string(path).ok()?.trim().parse().ok()
+    }
+
+    /// The CPUs local to a PCI device, closest to it for the threads polling its queues, from
+    /// sysfs on Linux. See [`pin_thread()`](crate::pin_thread).
+    pub fn local_cpus(&self) -> Option<Vec<usize>> {
+        let path = format!("/sys/bus/pci/devices/{}/local_cpulist", self.pci?);
+        cpu_list(std::fs::read_to_string(path).ok()?.trim())
+    }
+
+    /// The CPUs the interrupts of a PCI device are delivered to, from its MSI vectors in sysfs
+    /// and their affinity in procfs on Linux, sorted.
+    pub fn irq_cpus(&self) -> Option<Vec<usize>> {
+        let path = format!("/sys/bus/pci/devices/{}/msi_irqs", self.pci?);
+        let mut cpus = Vec::new();
+        for irq in std::fs::read_dir(path).ok()? {
+            let path = format!(
+                "/proc/irq/{}/smp_affinity_list",
+                irq.ok()?.file_name().to_str()?
+            );
+            // Vectors may be torn down in between.
+            if let Ok(list) = std::fs::read_to_string(path) {
+                cpus.extend(cpu_list(list.trim())?);
+            }
+        }
+        cpus.sort_unstable();
+        cpus.dedup();
+        (!cpus.is_empty()).then_some(cpus)
+    }
+}
+
+// The CPUs of NUMA node `node`, from sysfs on Linux.
+pub(crate) fn numa_cpus(node: u32) -> Option<Vec<usize>> {
+    let path = format!("/sys/devices/system/node/node{node}/cpulist");
+    cpu_list(std::fs::read_to_string(path).ok()?.trim())
+}
+
+// Parse a list of CPUs in the format of sysfs and cpusets, ex: `0-3,8,10-11`.
+fn cpu_list(list: &str) -> Option<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for range in list.split(',').filter(|range| !range.is_empty()) {
+        match range.split_once('-') {
+            Some((first, last)) => cpus.extend(first.parse::<usize>().ok()?..=last.parse().ok()?),
+            None => cpus.push(range.parse().ok()?),
+        }
+    }
+    Some(cpus)
+}
+
+/// The address of a PCI device (`struct fi_pci_attr`), displayed as
+/// `domain:bus:device.function`, ex: `0000:3b:00.1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PciAddress {
+    pub domain: u16,
+    pub bus: u8,
+    /// The slot of the device on its bus.
+    pub device: u8,
+    pub function: u8,
+}
+
+impl fmt::Display for PciAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04x}:{:02x}:{:02x}.{:x}",
+            self.domain, self.bus, self.device, self.function
+        )
+    }
+}
+
+/// The providers the linked libfabric exposes on this node (ex: `"tcp"`, `"verbs"`), sorted, via
+/// `fi_getinfo()` without hints.
+///
+/// Layered providers are listed under each of their names, e.g. `"tcp;ofi_rxm"` yields both
+/// `"tcp"` and `"ofi_rxm"`. This is the runtime counterpart of the `libfabric_provider_{name}`
+/// cfgs set for vendored builds.
+pub fn available_providers() -> Result<Vec<String>> {
+    let names: BTreeSet<_> = discover_all()?
+        .iter()
+        .flat_map(|entry| entry.provider_name().split(';').map(str::to_owned))
+        .collect();
+    Ok(names.into_iter().collect())
+}
+
+// Every entry of every provider, via `fi_getinfo()` without hints, none if there are none.
+pub(crate) fn discover_all() -> Result<Vec<InfoEntry>> {
+    let mut list = ptr::null_mut();
+    let ret = unsafe {
+        ffi::fi_getinfo(
+            Version::HEADER.as_raw(),
+            ptr::null(),
+            ptr::null(),
+            0,
+            ptr::null_mut(),
+            &mut list,
+        )
+    };
+    if ret == -(ffi::FI_ENODATA as i32) {
+        return Ok(Vec::new());
+    }
+    check("fi_getinfo", ret)?;
+    Ok(unsafe { take_list(list) })
+}
+
+/// A single `fi_info` entry returned by discovery, describing one usable configuration.
+///
+/// Entries are owned, and cloned with `fi_dupinfo()`.
+pub struct InfoEntry {
+    ptr: NonNull<ffi::fi_info>,
+}
+
+// SAFETY: The entry is never mutated once returned by libfabric.
+unsafe impl Send for InfoEntry {}
+unsafe impl Sync for InfoEntry {}
+
+impl InfoEntry {
+    /// Take ownership of an entry allocated by libfabric, e.g. from an `FI_CONNREQ` event.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a valid `fi_info` that is freed with `fi_freeinfo()` and not otherwise used.
+    pub unsafe fn from_raw(ptr: *mut ffi::fi_info) -> Option<Self> {
+        NonNull::new(ptr).map(|ptr| InfoEntry { ptr })
+    }
+
+    /// The raw entry. It stays valid for as long as `self` is alive.
+    pub fn as_raw(&self) -> *mut ffi::fi_info {
+        self.ptr.as_ptr()
+    }
+
+    fn raw(&self) -> &ffi::fi_info {
+        unsafe { self.ptr.as_ref() }
+    }
+
+    pub fn caps(&self) -> Caps {
+        Caps::from_bits_retain(self.raw().caps)
+    }
+
+    pub fn mode(&self) -> Mode {
+        Mode::from_bits_retain(self.raw().mode)
+    }
+
+    pub fn ep_type(&self) -> EndpointType {
+        EndpointType::from_raw(unsafe { read_enum(&raw const (*self.raw().ep_attr).type_) })
+    }
+
+    pub fn mr_mode(&self) -> MrMode {
+        MrMode::from_bits_retain(unsafe { (*self.raw().domain_attr).mr_mode } as u32)
+    }
+
+    pub fn provider_name(&self) -> &str {
+        unsafe { cstr((*self.raw().fabric_attr).prov_name) }
+    }
+
+    pub fn fabric_name(&self) -> &str {
+        unsafe { cstr((*self.raw().fabric_attr).name) }
+    }
+
+    pub fn domain_name(&self) -> &str {
+        unsafe { cstr((*self.raw().domain_attr).name) }
+    }
+
+    /// The version of the provider implementation (`fi_fabric_attr.prov_version`).
+    pub fn provider_version(&self) -> Version {
+        Version::from_raw(unsafe { (*self.raw().fabric_attr).prov_version })
+    }
+
+    /// The largest message the endpoint may send (`fi_ep_attr.max_msg_size`).
+    pub fn max_msg_size(&self) -> usize {
+        unsafe { (*self.raw().ep_attr).max_msg_size }
+    }
+
+    pub fn fabric_attr(&self) -> FabricAttr {
+        unsafe { FabricAttr::from_raw(&*self.raw().fabric_attr) }
+    }
+
+    pub fn domain_attr(&self) -> DomainAttr {
+        unsafe { DomainAttr::from_raw(&*self.raw().domain_attr) }
+    }
+
+    pub fn ep_attr(&self) -> EpAttr {
+        EpAttr::from_raw(unsafe { &*self.raw().ep_attr })
+    }
+
+    pub fn tx_attr(&self) -> TxAttr {
+        TxAttr::from_raw(unsafe { &*self.raw().tx_attr })
+    }
+
+    pub fn rx_attr(&self) -> RxAttr {
+        RxAttr::from_raw(unsafe { &*self.raw().rx_attr })
+    }
+
+    /// A copy of the entry with the settings of `attr` applied to its transmit context, for
+    /// [`Domain::endpoint()`](crate::Domain::endpoint). Fails if they exceed what the entry
+    /// reports.
+    pub fn with_tx_attr(&self, attr: &TxQueueAttr) -> Result<InfoEntry> {
+        attr.validate(&self.tx_attr())?;
+        let entry = self.clone();
+        attr.apply(unsafe { &mut *(*entry.as_raw()).tx_attr });
+        Ok(entry)
+    }
+
+    /// A copy of the entry with the settings of `attr` applied to its receive context.
+    pub fn with_rx_attr(&self, attr: &RxQueueAttr) -> Result<InfoEntry> {
+        attr.validate(&self.rx_attr())?;
+        let entry = self.clone();
+        attr.apply(unsafe { &mut *(*entry.as_raw()).rx_attr });
+        Ok(entry)
+    }
+
+    /// The format of the addresses of the entry and of the endpoints opened from it.
+    pub fn addr_format(&self) -> AddrFormat {
+        AddrFormat::from_raw(self.raw().addr_format)
+    }
+
+    /// The local address of the entry, which endpoints opened from it bind to.
+    pub fn src_addr(&self) -> Option<EndpointAddress> {
+        let raw = self.raw();
+        if raw.src_addr.is_null() {
+            return None;
+        }
+        let bytes =
+            unsafe { std::slice::from_raw_parts(raw.src_addr.cast::<u8>(), raw.src_addrlen) };
+        Some(EndpointAddress::from_bytes(bytes))
+    }
+
+    /// The peer address the entry resolved from the node and service of the hints, which
+    /// connection oriented endpoints connect to.
+    pub fn dest_addr(&self) -> Option<EndpointAddress> {
+        let raw = self.raw();
+        if raw.dest_addr.is_null() {
+            return None;
+        }
+        let bytes =
+            unsafe { std::slice::from_raw_parts(raw.dest_addr.cast::<u8>(), raw.dest_addrlen) };
+        Some(EndpointAddress::from_bytes(bytes))
+    }
+
+    /// The NIC the entry goes through, for providers which report it.
+    pub fn nic(&self) -> Option<Nic> {
+        let nic = unsafe { self.raw().nic.as_ref()? };
+        Some(unsafe { Nic::from_raw(nic) })
+    }
+
+    // The connection request handle of an entry delivered with FI_CONNREQ, if any.
+    pub(crate) fn handle(&self) -> ffi::fid_t {
+        self.raw().handle
+    }
+}
+
+impl Clone for InfoEntry {
+    fn clone(&self) -> Self {
+        let ptr = NonNull::new(unsafe { ffi::fi_dupinfo(self.ptr.as_ptr()) })
+            .expect("fi_dupinfo() out of memory");
+        InfoEntry { ptr }
+    }
+}
+
+impl Drop for InfoEntry {
+    fn drop(&mut self) {
+        unsafe { ffi::fi_freeinfo(self.ptr.as_ptr()) };
+    }
+}
+
+impl fmt::Debug for InfoEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InfoEntry")
+            .field("provider", &self.provider_name())
+            .field("fabric", &self.fabric_name())
+            .field("domain", &self.domain_name())
+            .field("ep_type", &self.ep_type())
+            .field("caps", &self.caps())
+            .finish()
+    }
+}
+
+/// The portable part of an [`InfoEntry`], as (de)serialized with the `serde` feature.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct InfoDesc {
+    provider: String,
+    fabric: String,
+    domain: String,
+    ep_type: EndpointType,
+    caps: Caps,
+    mode: Mode,
+    mr_mode: MrMode,
+}
+
+/// Entries serialize to the names and attributes identifying them.
+#[cfg(feature = "serde")]
+impl serde::Serialize for InfoEntry {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        InfoDesc {
+            provider: self.provider_name().to_owned(),
+            fabric: self.fabric_name().to_owned(),
+            domain: self.domain_name().to_owned(),
+            ep_type: self.ep_type(),
+            caps: self.caps(),
+            mode: self.mode(),
+            mr_mode: self.mr_mode(),
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Entries deserialize by running discovery again with the serialized names and attributes as
+/// hints, so they describe the local fabric and can be opened. This fails if the local node has
+/// no such fabric and domain.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for InfoEntry {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        let desc = InfoDesc::deserialize(deserializer)?;
+        Info::new()
+            .provider(&desc.provider)
+            .fabric_name(&desc.fabric)
+            .domain_name(&desc.domain)
+            .ep_type(desc.ep_type)
+            .caps(desc.caps)
+            .mode(desc.mode)
+            .mr_mode(desc.mr_mode)
+            .get()
+            .and_then(|entries| {
+                entries
+                    .into_iter()
+                    .find(|entry| {
+                        entry.provider_name() == desc.provider
+                            && entry.fabric_name() == desc.fabric
+                            && entry.domain_name() == desc.domain
+                    })
+                    .ok_or(Error::fabric("fi_getinfo", ffi::FI_ENODATA as i64))
+            })
+            .map_err(serde::de::Error::custom)
+    }
+}
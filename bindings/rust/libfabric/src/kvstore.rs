@@ -0,0 +1,452 @@
+//! A distributed key-value cache over RMA and atomics, enabled by the `kvstore` feature: a
+//! bulletin board every rank of a job reads and writes by key, without the CPU of the rank
+//! holding the key taking part.
+//!
+//! The table is sharded over the ranks. A key hashes to its owner, and to a slot of the table
+//! of the owner, from which [`KvAttr::probes()`] consecutive slots are probed. Each slot is a
+//! sequence lock: [`get()`](KvStore::get) reads the slot with RMA, then its sequence word
+//! again, and retries if a writer came in between; [`put()`](KvStore::put) takes the lock
+//! with a compare and swap of the sequence word, writes the key and value with RMA, then
+//! releases it with another compare and swap. Keys are never removed, so a rank owns at most
+//! [`KvAttr::slots()`] keys, fewer once probes collide; `put()` fails with `FI_ENOSPC` when all
+//! the slots probed hold other keys.
+//!
+//! A slot is laid out as its sequence word, which is 0 while the slot is empty, odd while it is
+//! being written, and even otherwise; the hash of the key; the lengths of the key and value, as
+//! 32-bit words; then the key and value, padded to 8 bytes. Words are native endian, as the
+//! atomics which operate on them, so ranks must share the endianness.
+//!
+//! The store runs over any [`AtomicTransport`] and [`Cq`], so over an RDM
+//! [`Endpoint`](crate::Endpoint) opened with `Caps::RMA | Caps::ATOMIC`, or a
+//! [`MockEndpoint`](crate::mock::MockEndpoint). Its queue must be its own: operations are
+//! waited for one at a time, and any completion is taken as theirs. The write of a value must
+//! land before the release of its slot, which endpoints ensure with
+//! [`OpFlags::DELIVERY_COMPLETE`](crate::OpFlags::DELIVERY_COMPLETE) as the default flags of
+//! their transmit context, unless the provider orders atomics after writes. Buffers are not
+//! registered, so providers requiring `FI_MR_LOCAL` are not supported.
+//!
+//! It is a consumer of the RMA and atomic layers from end to end, and a template to fork, ex:
+//! for removals with tombstones, or caches of the values read. The tables are the memory of
+//! the application, registered for remote reads and writes, zeroed before the first access,
+//! and of [`KvAttr::table_size()`] bytes, such as an allocation of a
+//! [`SymmetricHeap`](crate::SymmetricHeap):
+//!
+//! ```no_run
+//! use libfabric::bootstrap::Bootstrap;
+//! use libfabric::kvstore::{KvAttr, KvStore};
+//! use libfabric::{Addr, CompletionQueue, Domain, Endpoint, SymmetricHeap, SymmetricHeapAttr};
+//!
+//! # fn run(
+//! #     domain: Domain,
+//! #     ep: Endpoint,
+//! #     cq: CompletionQueue,
+//! #     job: &mut impl Bootstrap,
+//! #     peers: Vec<Addr>,
+//! # ) -> libfabric::Result<()> {
+//! let attr = KvAttr::new().slots(4096).max_value(64);
+//! let mut heap = SymmetricHeap::new(&domain, job, &SymmetricHeapAttr::new())?;
+//! let table = heap.alloc(attr.table_size(), 8)?;
+//! let tables = (0..job.size()).map(|rank| heap.remote(table, rank)).collect();
+//! // With the AV handles of the ranks in `peers`.
+//! let mut kv = KvStore::new(ep, cq, peers, tables, &attr)?;
+//! kv.put(format!("rank{}/port", job.rank()).as_bytes(), b"7000")?;
+//! job.barrier()?;
+//! assert_eq!(kv.get(b"rank0/port")?.as_deref(), Some(&b"7000"[..]));
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::av::Addr;
+pub use crate::bootstrap::RemoteRegion;
+use crate::cq::Completion;
+use crate::error::{Error, Result};
+use crate::transport::{AtomicTransport, Cq};
+use ofi_libfabric_sys::bindgen as ffi;
+use std::time::{Duration, Instant};
+
+// The bytes of the sequence word, hash and lengths at the start of a slot.
+const HEADER: usize = 24;
+
+/// Attributes of a [`KvStore`], which must be the same on all ranks.
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct KvAttr {
+    slots: usize,
+    max_key: usize,
+    max_value: usize,
+    probes: usize,
+    lock_timeout: Duration,
+}
+
+impl Default for KvAttr {
+    fn default() -> Self {
+        KvAttr {
+            slots: 1024,
+            max_key: 64,
+            max_value: 256,
+            probes: 8,
+            lock_timeout: Duration::from_secs(1),
+        }
+    }
+}
+
+impl KvAttr {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Slots of the table of each rank, 1024 by default.
+    pub fn slots(mut self, slots: usize) -> Self {
+        self.slots = slots.max(1);
+        self
+    }
+
+    /// Largest key, 64 bytes by default.
+    pub fn max_key(mut self, len: usize) -> Self {
+        self.max_key = len;
+        self
+    }
+
+    /// Largest value, 256 bytes by default.
+    pub fn max_value(mut self, len: usize) -> Self {
+        self.max_value = len;
+        self
+    }
+
+    /// Slots probed for a key, from the one it hashes to, 8 by default.
+    pub fn probes(mut self, probes: usize) -> Self {
+        self.probes = probes.max(1);
+        self
+    }
+
+    /// How long to wait for a slot locked by another writer, 1 second by default, after which
+    /// operations fail with `FI_ETIMEDOUT`, ex: when the writer died holding it.
+    pub fn lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = timeout;
+        self
+    }
+
+    /// Bytes of a slot.
+    pub fn slot_size(&self) -> usize {
+        (HEADER + self.max_key + self.max_value).div_ceil(8) * 8
+    }
+
+    /// Bytes of the table of each rank.
+    pub fn table_size(&self) -> usize {
+        self.slots * self.slot_size()
+    }
+}
+
+/// The hash of `key` the store places it by, FNV-1a, the same in every process.
+pub fn kv_hash(key: &[u8]) -> u64 {
+    key.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+// A slot as read, with its sequence word.
+struct Slot<'a> {
+    seq: u64,
+    hash: u64,
+    key: &'a [u8],
+    value: &'a [u8],
+}
+
+/// A rank of a distributed key-value cache, see the [module](self) documentation.
+pub struct KvStore<T: AtomicTransport, C: Cq> {
+    // Declared first, so that the endpoint is closed before the buffers of its operations
+    // are freed.
+    ep: T,
+    cq: C,
+    peers: Vec<Addr>,
+    tables: Vec<RemoteRegion>,
+    attr: KvAttr,
+    // The slot read by the operation in flight, as words for the alignment of the header.
+    buf: Box<[u64]>,
+    // The result of the compare and swap in flight.
+    result: Box<u64>,
+    context: usize,
+}
+
+impl<T: AtomicTransport, C: Cq> KvStore<T, C> {
+    /// A store reaching rank `i` at `peers[i]`, whose table is `tables[i]`, with completions
+    /// read from `cq`. Fails with an argument error unless there are as many tables as peers,
+    /// each of at least [`KvAttr::table_size()`] bytes.
+    pub fn new(
+        ep: T,
+        cq: C,
+        peers: Vec<Addr>,
+        tables: Vec<RemoteRegion>,
+        attr: &KvAttr,
+    ) -> Result<Self> {
+        if peers.is_empty() || peers.len() != tables.len() {
+            return Err(Error::invalid(format!(
+                "{} peers for {} tables",
+                peers.len(),
+                tables.len()
+            )));
+        }
+        if let Some(rank) = tables
+            .iter()
+            .position(|table| table.len < attr.table_size() as u64)
+        {
+            return Err(Error::invalid(format!(
+                "the table of rank {rank} is of {} bytes, not {}",
+                tables[rank].len,
+                attr.table_size()
+            )));
+        }
+        Ok(KvStore {
+            ep,
+            cq,
+            peers,
+            tables,
+            buf: vec![0; attr.slot_size() / 8].into_boxed_slice(),
+            attr: attr.clone(),
+            result: Box::new(0),
+            context: 0,
+        })
+    }
+
+    pub fn endpoint(&self) -> &T {
+        &self.ep
+    }
+
+    /// The ranks sharing the store.
+    pub fn ranks(&self) -> usize {
+        self.peers.len()
+    }
+
+    /// The rank holding `key`.
+    pub fn owner(&self, key: &[u8]) -> usize {
+        (kv_hash(key) % self.peers.len() as u64) as usize
+    }
+
+    /// The value of `key`, if it was put.
+    pub fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.check(key, &[])?;
+        let hash = kv_hash(key);
+        for probe in 0..self.attr.probes {
+            let (rank, addr) = self.slot_addr(hash, probe);
+            let deadline = Instant::now() + self.attr.lock_timeout;
+            loop {
+                self.read(rank, addr, self.attr.slot_size())?;
+                let slot = self.slot()?;
+                let (seq, found) = match slot {
+                    Slot { seq: 0, .. } => return Ok(None),
+                    Slot { seq, .. } if !seq.is_multiple_of(2) => {
+                        self.locked(deadline)?;
+                        continue;
+                    }
+                    Slot {
+                        seq,
+                        hash: slot_hash,
+                        key: slot_key,
+                        value,
+                    } => (
+                        seq,
+                        (slot_hash == hash && slot_key == key).then(|| value.to_vec()),
+                    ),
+                };
+                let Some(value) = found else { break };
+                // Unchanged while it was read, or a writer came in between.
+                self.read(rank, addr, 8)?;
+                if self.buf[0] == seq {
+                    return Ok(Some(value));
+                }
+                self.locked(deadline)?;
+            }
+        }
+        Ok(None)
+    }
+
+    /// Set the value of `key`. Fails with `FI_ENOSPC` when the slots probed for the key hold
+    /// others, and with an argument error for keys and values larger than the attributes.
+    pub fn put(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.check(key, value)?;
+        let hash = kv_hash(key);
+        for probe in 0..self.attr.probes {
+            let (rank, addr) = self.slot_addr(hash, probe);
+            let deadline = Instant::now() + self.attr.lock_timeout;
+            loop {
+                self.read(rank, addr, HEADER + key.len())?;
+                let slot = self.slot()?;
+                let seq = match slot {
+                    Slot { seq, .. } if !seq.is_multiple_of(2) => {
+                        // Being written, maybe with this key.
+                        self.locked(deadline)?;
+                        continue;
+                    }
+                    Slot { seq: 0, .. } => 0,
+                    Slot {
+                        seq,
+                        hash: slot_hash,
+                        key: slot_key,
+                        ..
+                    } if slot_hash == hash && slot_key == key => seq,
+                    _ => break,
+                };
+                // Another writer may have taken the slot since it was read: read it again.
+                if self.compare_swap(rank, addr, seq, seq + 1)? != seq {
+                    continue;
+                }
+                let written = self.write_slot(rank, addr, hash, key, value);
+                // Released even if the write failed, with the previous value or a torn one.
+                let released = self.compare_swap(rank, addr, seq + 1, seq + 2);
+                written?;
+                released?;
+                return Ok(());
+            }
+        }
+        Err(Error::fabric("kvstore put", ffi::FI_ENOSPC as i64))
+    }
+
+    fn check(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        if key.len() > self.attr.max_key || value.len() > self.attr.max_value {
+            return Err(Error::invalid(format!(
+                "a key of {} bytes and value of {} bytes, past the {} and {} of the store",
+                key.len(),
+                value.len(),
+                self.attr.max_key,
+                self.attr.max_value
+            )));
+        }
+        Ok(())
+    }
+
+    // The owner of a key, and the address of its slot at `probe`.
+    fn slot_addr(&self, hash: u64, probe: usize) -> (usize, u64) {
+        let ranks = self.peers.len() as u64;
+        let rank = (hash % ranks) as usize;
+        let index = ((hash / ranks) as usize).wrapping_add(probe) % self.attr.slots;
+        let addr = self.tables[rank].addr + (index * self.attr.slot_size()) as u64;
+        (rank, addr)
+    }
+
+    // The slot in the buffer, the key and value cut to what was read of them.
+    fn slot(&self) -> Result<Slot<'_>> {
+        let bytes: &[u8] =
+            unsafe { std::slice::from_raw_parts(self.buf.as_ptr().cast(), self.buf.len() * 8) };
+        let word = |at: usize| u32::from_ne_bytes(bytes[at..at + 4].try_into().unwrap()) as usize;
+        let (key_len, value_len) = (word(16), word(20));
+        let seq = self.buf[0];
+        if seq != 0
+            && seq.is_multiple_of(2)
+            && (key_len > self.attr.max_key || value_len > self.attr.max_value)
+        {
+            return Err(Error::invalid(format!(
+                "a slot holds a key of {key_len} bytes and value of {value_len} bytes, past the \
+                 attributes of this store: are they the same on all ranks?"
+            )));
+        }
+        let key = &bytes[HEADER..][..key_len];
+        let value = &bytes[HEADER + self.attr.max_key..][..value_len];
+        Ok(Slot {
+            seq,
+            hash: self.buf[1],
+            key,
+            value,
+        })
+    }
+
+    // Give way to the writer holding a slot, failing once the deadline passed.
+    fn locked(&self, deadline: Instant) -> Result<()> {
+        if Instant::now() >= deadline {
+            return Err(Error::fabric("kvstore lock", ffi::FI_ETIMEDOUT as i64));
+        }
+        std::thread::yield_now();
+        Ok(())
+    }
+
+    // Read the first `len` bytes of the slot at `addr` of `rank` into the buffer.
+    fn read(&mut self, rank: usize, addr: u64, len: usize) -> Result<()> {
+        let context = self.next_context();
+        let (dest, key) = (self.peers[rank], self.tables[rank].key);
+        let buf: &mut [u8] = unsafe {
+            std::slice::from_raw_parts_mut(
+                self.buf.as_mut_ptr().cast(),
+                len.min(self.buf.len() * 8),
+            )
+        };
+        let buf: *mut [u8] = buf;
+        // SAFETY: the buffer is owned by the store, which waits for the read to complete, and
+        // outlives its endpoint.
+        self.post(|ep| unsafe { ep.read(&mut *buf, None, dest, addr, key, context) })?;
+        self.wait(context)
+    }
+
+    // Write everything after the sequence word of a slot.
+    fn write_slot(
+        &mut self,
+        rank: usize,
+        addr: u64,
+        hash: u64,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<()> {
+        let mut data = vec![0u8; self.attr.slot_size() - 8];
+        data[..8].copy_from_slice(&hash.to_ne_bytes());
+        data[8..12].copy_from_slice(&(key.len() as u32).to_ne_bytes());
+        data[12..16].copy_from_slice(&(value.len() as u32).to_ne_bytes());
+        data[16..][..key.len()].copy_from_slice(key);
+        data[16 + self.attr.max_key..][..value.len()].copy_from_slice(value);
+        let context = self.next_context();
+        let (dest, rkey) = (self.peers[rank], self.tables[rank].key);
+        // SAFETY: `data` is kept until the write completed.
+        self.post(|ep| unsafe { ep.write(&data, None, dest, addr + 8, rkey, context) })?;
+        self.wait(context)
+    }
+
+    // Swap the sequence word of a slot, returning its previous value.
+    fn compare_swap(&mut self, rank: usize, addr: u64, compare: u64, value: u64) -> Result<u64> {
+        let context = self.next_context();
+        let (dest, key) = (self.peers[rank], self.tables[rank].key);
+        let result: *mut u64 = &mut *self.result;
+        // SAFETY: as for reads.
+        self.post(|ep| unsafe {
+            ep.compare_swap(value, compare, &mut *result, None, dest, addr, key, context)
+        })?;
+        self.wait(context)?;
+        Ok(*self.result)
+    }
+
+    fn next_context(&mut self) -> usize {
+        self.context = self.context.wrapping_add(1);
+        self.context
+    }
+
+    // Post an operation, reading the queue while the endpoint is out of room.
+    fn post(&mut self, mut op: impl FnMut(&T) -> Result<()>) -> Result<()> {
+        loop {
+            match op(&self.ep) {
+                Err(err) if err.is_again() => {
+                    self.read_cq()?;
+                }
+                other => return other,
+            }
+        }
+    }
+
+    fn wait(&mut self, context: usize) -> Result<()> {
+        loop {
+            if self.read_cq()? == Some(context) {
+                return Ok(());
+            }
+        }
+    }
+
+    // Read a completion, returning its context.
+    fn read_cq(&mut self) -> Result<Option<usize>> {
+        let mut completions = [Completion::default(); 1];
+        match self.cq.read(&mut completions) {
+            Ok(0) => Ok(None),
+            Ok(_) => Ok(Some(completions[0].context())),
+            Err(err) if err.is_again() => Ok(None),
+            Err(err) if err.is_avail() => match self.cq.read_err()? {
+                Some(entry) => Err(entry.error),
+                None => Ok(None),
+            },
+            Err(err) => Err(err),
+        }
+    }
+}
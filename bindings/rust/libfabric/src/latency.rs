@@ -0,0 +1,638 @@
+//! Latency histograms of the operations posted over the [transport traits](crate::Transport),
+//! enabled by the `latency` feature, to measure tail latencies without timing every call.
+//!
+//! A [`LatencyTracker`] wraps endpoints and completion queues, which then behave as the
+//! originals, but for the time at which each operation is posted, and at which its completion
+//! is read. The time from one to the other is recorded in a [`Histogram`] of the endpoint the
+//! operation was posted on, by kind of operation, and in one of the queue it completed on.
+//!
+//! Completions are matched with the oldest pending operation of the same context, on any of
+//! the endpoints of the tracker, as with a [`Recorder`](crate::Recorder). Injected
+//! operations, which have no completion, are not timed.
+//!
+//! Times are read from a [`ClockSource`], [`MonotonicClock`] by default: the time stamp
+//! counter with [`TscClock`] for cycle accurate benchmarks, `CLOCK_MONOTONIC_RAW` with
+//! [`MonotonicRawClock`] to leave NTP adjustments out, or the virtual time of a simulation,
+//! ex: that of a [`MockProgress`](crate::mock::MockProgress) with the `mock` feature.
+//!
+//! ```no_run
+//! use libfabric::latency::LatencyTracker;
+//! use libfabric::{CompletionQueue, Endpoint, OpKind};
+//!
+//! # fn run(ep: Endpoint, cq: CompletionQueue) {
+//! let tracker = LatencyTracker::new();
+//! let (ep, cq) = (tracker.endpoint(ep), tracker.cq(cq));
+//! // Run over `ep` and `cq` through the transport traits, then:
+//! let sends = ep.latencies_of(OpKind::TSend);
+//! println!("p50 {:?}, p99.9 {:?}", sends.percentile(50.0), sends.percentile(99.9));
+//! println!("{:?}", cq.latencies().max());
+//! # }
+//! ```
+
+use crate::av::{Addr, EndpointAddress};
+use crate::cq::{Completion, CqErrEntry};
+use crate::error::Result;
+use crate::record::OpKind;
+use crate::transport::{Cq, Transport};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// Where a [`LatencyTracker`] reads the time operations are posted and completed at.
+/// Closures returning nanoseconds are clocks too.
+pub trait ClockSource: Send + Sync {
+    /// The time, in nanoseconds since an origin of the clock, which never goes back.
+    fn now(&self) -> u64;
+}
+
+impl<F: Fn() -> u64 + Send + Sync> ClockSource for F {
+    fn now(&self) -> u64 {
+        self()
+    }
+}
+
+/// The monotonic clock of [`Instant`], `CLOCK_MONOTONIC` on Linux, the default.
+#[derive(Debug, Clone, Copy)]
+pub struct MonotonicClock {
+    origin: Instant,
+}
+
+impl MonotonicClock {
+    pub fn new() -> Self {
+        MonotonicClock {
+            origin: Instant::now(),
+        }
+    }
+}
+
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClockSource for MonotonicClock {
+    fn now(&self) -> u64 {
+        self.origin.elapsed().as_nanos() as u64
+    }
+}
+
+/// `CLOCK_MONOTONIC_RAW`, which NTP does not slew, so that latencies measured while the
+/// clock is adjusted are not off by its adjustment.
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MonotonicRawClock;
+
+#[cfg(target_os = "linux")]
+impl ClockSource for MonotonicRawClock {
+    fn now(&self) -> u64 {
+        use std::os::raw::{c_int, c_long};
+
+        // From time.h.
+        const CLOCK_MONOTONIC_RAW: c_int = 4;
+        #[repr(C)]
+        struct Timespec {
+            tv_sec: c_long,
+            tv_nsec: c_long,
+        }
+        unsafe extern "C" {
+            fn clock_gettime(clock: c_int, tp: *mut Timespec) -> c_int;
+        }
+        let mut ts = Timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        // The clock exists since Linux 2.6.28, and the pointer is valid.
+        unsafe { clock_gettime(CLOCK_MONOTONIC_RAW, &mut ts) };
+        ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+    }
+}
+
+/// The time stamp counter of x86-64 processors, read with `rdtsc` in a few cycles, converted
+/// to nanoseconds at the frequency measured against [`MonotonicClock`] when created.
+///
+/// Cores must share an invariant counter, as those of processors reporting the `constant_tsc`
+/// and `nonstop_tsc` flags do, for times read on different cores to compare.
+#[cfg(target_arch = "x86_64")]
+#[derive(Debug, Clone, Copy)]
+pub struct TscClock {
+    origin: u64,
+    // Counter ticks per second.
+    hz: u64,
+}
+
+#[cfg(target_arch = "x86_64")]
+impl TscClock {
+    /// A clock whose frequency is measured over 10 ms.
+    pub fn new() -> Self {
+        Self::calibrate(Duration::from_millis(10))
+    }
+
+    /// A clock whose frequency is measured over `interval`: the longer, the more accurate.
+    pub fn calibrate(interval: Duration) -> Self {
+        let start = (Instant::now(), Self::cycles());
+        std::thread::sleep(interval);
+        let (elapsed, cycles) = (start.0.elapsed(), Self::cycles() - start.1);
+        TscClock {
+            origin: start.1,
+            hz: (cycles as u128 * 1_000_000_000 / elapsed.as_nanos().max(1)) as u64,
+        }
+    }
+
+    /// The counter, in cycles.
+    pub fn cycles() -> u64 {
+        // SAFETY: every x86-64 processor has rdtsc.
+        unsafe { std::arch::x86_64::_rdtsc() }
+    }
+
+    /// The frequency of the counter measured, in Hz.
+    pub fn frequency(&self) -> u64 {
+        self.hz
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+impl Default for TscClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+impl ClockSource for TscClock {
+    fn now(&self) -> u64 {
+        let cycles = Self::cycles().saturating_sub(self.origin) as u128;
+        (cycles * 1_000_000_000 / self.hz.max(1) as u128) as u64
+    }
+}
+
+/// The virtual time of the progress driver, as moved forward by
+/// [`advance()`](crate::mock::MockProgress::advance).
+#[cfg(feature = "mock")]
+impl ClockSource for crate::mock::MockProgress {
+    fn now(&self) -> u64 {
+        self.elapsed().as_nanos() as u64
+    }
+}
+
+// Values under 2^BITS nanoseconds are counted exactly, and those above in buckets of
+// 2^(BITS-1) per power of two, within 1/2^(BITS-1) of their value.
+const BITS: u32 = 7;
+const HALF: u64 = 1 << (BITS - 1);
+
+fn bucket(nanos: u64) -> usize {
+    if nanos < 1 << BITS {
+        return nanos as usize;
+    }
+    let shift = 64 - nanos.leading_zeros() - BITS;
+    (HALF * shift as u64 + (nanos >> shift)) as usize
+}
+
+// The lowest value counted in a bucket, past the last one for the one after it.
+fn lowest(bucket: usize) -> u64 {
+    let bucket = bucket as u64;
+    if bucket < 1 << BITS {
+        return bucket;
+    }
+    let shift = bucket / HALF - 1;
+    (bucket - HALF * shift) << shift
+}
+
+/// A high dynamic range histogram of latencies, from nanoseconds to hours, with a relative
+/// precision of 1/64 (two significant digits), in a few KiB at most.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    len: u64,
+    min: u64,
+    max: u64,
+    sum: u128,
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, latency: Duration) {
+        let nanos = u64::try_from(latency.as_nanos()).unwrap_or(u64::MAX);
+        let index = bucket(nanos);
+        if index >= self.counts.len() {
+            self.counts.resize(index + 1, 0);
+        }
+        self.counts[index] += 1;
+        self.min = if self.len == 0 {
+            nanos
+        } else {
+            self.min.min(nanos)
+        };
+        self.max = self.max.max(nanos);
+        self.len += 1;
+        self.sum += nanos as u128;
+    }
+
+    /// Add the latencies of `other`, ex: to aggregate those of several endpoints.
+    pub fn merge(&mut self, other: &Histogram) {
+        if other.len == 0 {
+            return;
+        }
+        if other.counts.len() > self.counts.len() {
+            self.counts.resize(other.counts.len(), 0);
+        }
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+        self.min = if self.len == 0 {
+            other.min
+        } else {
+            self.min.min(other.min)
+        };
+        self.max = self.max.max(other.max);
+        self.len += other.len;
+        self.sum += other.sum;
+    }
+
+    /// The number of latencies recorded.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn min(&self) -> Duration {
+        Duration::from_nanos(self.min)
+    }
+
+    pub fn max(&self) -> Duration {
+        Duration::from_nanos(self.max)
+    }
+
+    pub fn mean(&self) -> Duration {
+        match self.len {
+            0 => Duration::ZERO,
+            len => Duration::from_nanos((self.sum / len as u128) as u64),
+        }
+    }
+
+    /// The latency below which `percentile` percent of them fall, ex: 99.9, within the
+    /// precision of the histogram. Zero when empty.
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        if self.len == 0 {
+            return Duration::ZERO;
+        }
+        let rank = ((percentile / 100.0 * self.len as f64).ceil() as u64).clamp(1, self.len);
+        let mut seen = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                let highest = lowest(index + 1).saturating_sub(1);
+                return Duration::from_nanos(highest.clamp(self.min, self.max));
+            }
+        }
+        self.max()
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Times the operations of the endpoints and of the completion queues it wraps, see the
+/// [module documentation](self).
+#[derive(Clone)]
+pub struct LatencyTracker {
+    inner: Arc<Mutex<State>>,
+    clock: Arc<dyn ClockSource>,
+}
+
+impl Default for LatencyTracker {
+    fn default() -> Self {
+        Self::with_clock(MonotonicClock::new())
+    }
+}
+
+#[derive(Default)]
+struct State {
+    // The pending operations of each context, oldest first, with their endpoint and kind.
+    pending: HashMap<usize, VecDeque<(usize, OpKind, u64)>>,
+    endpoints: Vec<HashMap<OpKind, Histogram>>,
+    cqs: Vec<Histogram>,
+}
+
+impl State {
+    fn complete(&mut self, cq: usize, context: usize, at: u64) {
+        let Some(pending) = self.pending.get_mut(&context) else {
+            return;
+        };
+        let Some((ep, kind, posted)) = pending.pop_front() else {
+            return;
+        };
+        if pending.is_empty() {
+            self.pending.remove(&context);
+        }
+        let latency = Duration::from_nanos(at.saturating_sub(posted));
+        self.endpoints[ep].entry(kind).or_default().record(latency);
+        self.cqs[cq].record(latency);
+    }
+}
+
+impl LatencyTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A tracker reading the time from `clock`, rather than from a [`MonotonicClock`].
+    pub fn with_clock(clock: impl ClockSource + 'static) -> Self {
+        LatencyTracker {
+            inner: Arc::default(),
+            clock: Arc::new(clock),
+        }
+    }
+
+    /// Time the operations posted on `ep`.
+    pub fn endpoint<T: Transport>(&self, ep: T) -> TimedEndpoint<T> {
+        let mut state = self.lock();
+        state.endpoints.push(HashMap::new());
+        TimedEndpoint {
+            inner: ep,
+            tracker: self.clone(),
+            index: state.endpoints.len() - 1,
+        }
+    }
+
+    /// Time the operations completed on `cq`.
+    pub fn cq<C: Cq>(&self, cq: C) -> TimedCq<C> {
+        let mut state = self.lock();
+        state.cqs.push(Histogram::new());
+        TimedCq {
+            inner: cq,
+            tracker: self.clone(),
+            index: state.cqs.len() - 1,
+        }
+    }
+
+    /// The latencies of the operations of all of the endpoints.
+    pub fn latencies(&self) -> Histogram {
+        let mut all = Histogram::new();
+        for histograms in &self.lock().endpoints {
+            histograms.values().for_each(|h| all.merge(h));
+        }
+        all
+    }
+
+    /// Forget the latencies recorded so far, ex: those of a warm up. Operations still pending
+    /// are timed all the same.
+    pub fn clear(&self) {
+        let mut state = self.lock();
+        state.endpoints.iter_mut().for_each(HashMap::clear);
+        state.cqs.iter_mut().for_each(Histogram::clear);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.inner.lock().unwrap()
+    }
+}
+
+/// An endpoint whose operations are timed, made by [`LatencyTracker::endpoint()`].
+#[derive(Clone)]
+pub struct TimedEndpoint<T> {
+    inner: T,
+    tracker: LatencyTracker,
+    index: usize,
+}
+
+impl<T> TimedEndpoint<T> {
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// The latencies of the operations of the endpoint, of all kinds.
+    pub fn latencies(&self) -> Histogram {
+        let mut all = Histogram::new();
+        let state = self.tracker.lock();
+        state.endpoints[self.index]
+            .values()
+            .for_each(|h| all.merge(h));
+        all
+    }
+
+    /// The latencies of the operations of the endpoint of one kind, ex: [`OpKind::TSend`].
+    pub fn latencies_of(&self, kind: OpKind) -> Histogram {
+        let state = self.tracker.lock();
+        state.endpoints[self.index]
+            .get(&kind)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    // Post an operation, timing it from now if posted. The tracker stays locked meanwhile, so
+    // its completion cannot be read before it is pending.
+    fn time(&self, kind: OpKind, context: usize, post: impl FnOnce() -> Result<()>) -> Result<()> {
+        let mut state = self.tracker.lock();
+        let posted = self.tracker.clock.now();
+        post()?;
+        state
+            .pending
+            .entry(context)
+            .or_default()
+            .push_back((self.index, kind, posted));
+        Ok(())
+    }
+}
+
+impl<T: Transport> Transport for TimedEndpoint<T> {
+    type Mr = T::Mr;
+
+    fn name(&self) -> Result<EndpointAddress> {
+        self.inner.name()
+    }
+
+    unsafe fn recv(
+        &self,
+        buf: &mut [u8],
+        mr: Option<&T::Mr>,
+        src: Addr,
+        context: usize,
+    ) -> Result<()> {
+        self.time(OpKind::Recv, context, || unsafe {
+            self.inner.recv(buf, mr, src, context)
+        })
+    }
+
+    unsafe fn send(
+        &self,
+        buf: &[u8],
+        mr: Option<&T::Mr>,
+        dest: Addr,
+        context: usize,
+    ) -> Result<()> {
+        self.time(OpKind::Send, context, || unsafe {
+            self.inner.send(buf, mr, dest, context)
+        })
+    }
+
+    unsafe fn senddata(
+        &self,
+        buf: &[u8],
+        mr: Option<&T::Mr>,
+        data: u64,
+        dest: Addr,
+        context: usize,
+    ) -> Result<()> {
+        self.time(OpKind::SendData, context, || unsafe {
+            self.inner.senddata(buf, mr, data, dest, context)
+        })
+    }
+
+    fn inject(&self, buf: &[u8], dest: Addr) -> Result<()> {
+        self.inner.inject(buf, dest)
+    }
+
+    unsafe fn trecv(
+        &self,
+        buf: &mut [u8],
+        mr: Option<&T::Mr>,
+        src: Addr,
+        tag: u64,
+        ignore: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.time(OpKind::TRecv, context, || unsafe {
+            self.inner.trecv(buf, mr, src, tag, ignore, context)
+        })
+    }
+
+    unsafe fn tsend(
+        &self,
+        buf: &[u8],
+        mr: Option<&T::Mr>,
+        dest: Addr,
+        tag: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.time(OpKind::TSend, context, || unsafe {
+            self.inner.tsend(buf, mr, dest, tag, context)
+        })
+    }
+
+    unsafe fn tsenddata(
+        &self,
+        buf: &[u8],
+        mr: Option<&T::Mr>,
+        data: u64,
+        dest: Addr,
+        tag: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.time(OpKind::TSendData, context, || unsafe {
+            self.inner.tsenddata(buf, mr, data, dest, tag, context)
+        })
+    }
+
+    fn tinject(&self, buf: &[u8], dest: Addr, tag: u64) -> Result<()> {
+        self.inner.tinject(buf, dest, tag)
+    }
+
+    unsafe fn read(
+        &self,
+        buf: &mut [u8],
+        mr: Option<&T::Mr>,
+        src: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.time(OpKind::Read, context, || unsafe {
+            self.inner.read(buf, mr, src, addr, key, context)
+        })
+    }
+
+    unsafe fn write(
+        &self,
+        buf: &[u8],
+        mr: Option<&T::Mr>,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.time(OpKind::Write, context, || unsafe {
+            self.inner.write(buf, mr, dest, addr, key, context)
+        })
+    }
+
+    unsafe fn writedata(
+        &self,
+        buf: &[u8],
+        mr: Option<&T::Mr>,
+        data: u64,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.time(OpKind::WriteData, context, || unsafe {
+            self.inner
+                .writedata(buf, mr, data, dest, addr, key, context)
+        })
+    }
+}
+
+/// A completion queue whose completions are timed, made by [`LatencyTracker::cq()`].
+#[derive(Clone)]
+pub struct TimedCq<C> {
+    inner: C,
+    tracker: LatencyTracker,
+    index: usize,
+}
+
+impl<C> TimedCq<C> {
+    pub fn get_ref(&self) -> &C {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+
+    /// The latencies of the operations completed on the queue, error completions included.
+    pub fn latencies(&self) -> Histogram {
+        self.tracker.lock().cqs[self.index].clone()
+    }
+
+    fn completed(&self, contexts: impl IntoIterator<Item = usize>) {
+        let at = self.tracker.clock.now();
+        let mut state = self.tracker.lock();
+        for context in contexts {
+            state.complete(self.index, context, at);
+        }
+    }
+}
+
+impl<C: Cq> Cq for TimedCq<C> {
+    fn read(&self, out: &mut [Completion]) -> Result<usize> {
+        let n = self.inner.read(out)?;
+        self.completed(out[..n].iter().map(Completion::context));
+        Ok(n)
+    }
+
+    fn read_from(&self, out: &mut [Completion], src: &mut [Addr]) -> Result<usize> {
+        let n = self.inner.read_from(out, src)?;
+        self.completed(out[..n].iter().map(Completion::context));
+        Ok(n)
+    }
+
+    fn read_err(&self) -> Result<Option<CqErrEntry>> {
+        let entry = self.inner.read_err()?;
+        if let Some(entry) = &entry {
+            self.completed([entry.context]);
+        }
+        Ok(entry)
+    }
+}
@@ -0,0 +1,219 @@
+use crate::av::{Addr, EndpointAddress};
+use crate::error::{Error, Result};
+use crate::transport::Av;
+use ofi_libfabric_sys::bindgen as ffi;
+use std::collections::{BTreeMap, HashMap};
+
+/// Attributes of a [`LazyAv`].
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct LazyAvAttr {
+    capacity: usize,
+}
+
+impl Default for LazyAvAttr {
+    fn default() -> Self {
+        LazyAvAttr { capacity: 65536 }
+    }
+}
+
+impl LazyAvAttr {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Peers inserted into the address vector at once, 65536 by default, past which the
+    /// least recently used is removed.
+    pub fn capacity(mut self, peers: usize) -> Self {
+        self.capacity = peers.max(1);
+        self
+    }
+}
+
+// A peer inserted into the address vector.
+struct Resident {
+    addr: Addr,
+    // When the peer was last used, its key in the LRU order.
+    used: u64,
+    pins: usize,
+}
+
+/// An address vector of peers addressed by rank, inserted on first use rather than up front,
+/// for jobs of more peers than the address vector of the provider holds comfortably.
+///
+/// The names of all peers are kept, and [`addr()`](Self::addr) inserts the peer of a rank the
+/// first time it is communicated with, so that the address vector only holds the peers in
+/// use, up to [`LazyAvAttr::capacity()`]. Past it, the least recently used peer is removed:
+/// its handle is no longer valid, and a later [`addr()`](Self::addr) inserts it again, likely
+/// under another handle. Peers with operations in flight are [pinned](Self::pin) until they
+/// complete, so that they are not removed under them.
+///
+/// It runs over any [`Av`], as [`Liveness`](crate::Liveness) does.
+///
+/// ```no_run
+/// use libfabric::{AddressVector, Endpoint, EndpointAddress, LazyAv, LazyAvAttr};
+///
+/// # unsafe fn run(ep: &Endpoint, av: AddressVector, names: Vec<EndpointAddress>) -> libfabric::Result<()> {
+/// let mut peers = LazyAv::new(av, names, &LazyAvAttr::new().capacity(4096));
+/// let dest = peers.pin(12345)?;
+/// unsafe { ep.send(b"hello", None, dest, 0)? };
+/// // Once the send completed.
+/// peers.unpin(12345);
+/// # Ok(())
+/// # }
+/// ```
+pub struct LazyAv<A: Av> {
+    av: A,
+    capacity: usize,
+    names: Vec<EndpointAddress>,
+    resident: HashMap<usize, Resident>,
+    // The ranks of the peers inserted, least recently used first.
+    lru: BTreeMap<u64, usize>,
+    ranks: HashMap<Addr, usize>,
+    clock: u64,
+    inserts: u64,
+    evictions: u64,
+}
+
+impl<A: Av> LazyAv<A> {
+    /// Address the peers named by `names`, where the rank of a peer is its index, through
+    /// `av`, inserting none yet.
+    pub fn new(av: A, names: Vec<EndpointAddress>, attr: &LazyAvAttr) -> Self {
+        LazyAv {
+            av,
+            capacity: attr.capacity,
+            names,
+            resident: HashMap::new(),
+            lru: BTreeMap::new(),
+            ranks: HashMap::new(),
+            clock: 0,
+            inserts: 0,
+            evictions: 0,
+        }
+    }
+
+    pub fn av(&self) -> &A {
+        &self.av
+    }
+
+    /// The peers addressed, inserted or not.
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// The peers inserted into the address vector.
+    pub fn resident(&self) -> usize {
+        self.resident.len()
+    }
+
+    /// Insertions into the address vector, first ones and reinsertions of removed peers.
+    pub fn inserts(&self) -> u64 {
+        self.inserts
+    }
+
+    /// Peers removed to make room for others.
+    pub fn evictions(&self) -> u64 {
+        self.evictions
+    }
+
+    /// The handle of the peer of `rank`, inserting it into the address vector first if it is
+    /// not, and removing the least recently used unpinned peer if the address vector is full.
+    /// Fails with an argument error if `rank` is not that of a peer, and with `FI_ENOSPC`
+    /// when every peer inserted is pinned.
+    ///
+    /// The handle is valid until the peer is removed, at the next call of another rank at the
+    /// earliest, unless the peer is [pinned](Self::pin).
+    pub fn addr(&mut self, rank: usize) -> Result<Addr> {
+        self.clock += 1;
+        if let Some(resident) = self.resident.get_mut(&rank) {
+            self.lru.remove(&resident.used);
+            resident.used = self.clock;
+            self.lru.insert(self.clock, rank);
+            return Ok(resident.addr);
+        }
+        if rank >= self.names.len() {
+            return Err(Error::invalid(format!(
+                "rank {rank} is out of a group of {}",
+                self.names.len()
+            )));
+        }
+        if self.resident.len() >= self.capacity {
+            self.evict_lru()?;
+        }
+        let addr = self.av.insert(&self.names[rank])?;
+        self.inserts += 1;
+        self.resident.insert(
+            rank,
+            Resident {
+                addr,
+                used: self.clock,
+                pins: 0,
+            },
+        );
+        self.lru.insert(self.clock, rank);
+        self.ranks.insert(addr, rank);
+        Ok(addr)
+    }
+
+    /// The handle of the peer of `rank`, if it is inserted, without using it.
+    pub fn peek(&self, rank: usize) -> Option<Addr> {
+        self.resident.get(&rank).map(|resident| resident.addr)
+    }
+
+    /// The rank of the peer inserted under `addr`, ex: the source of a completion.
+    pub fn rank_of(&self, addr: Addr) -> Option<usize> {
+        self.ranks.get(&addr).copied()
+    }
+
+    /// Like [`addr()`](Self::addr), keeping the peer inserted until as many
+    /// [`unpin()`](Self::unpin) as calls of `pin()`.
+    pub fn pin(&mut self, rank: usize) -> Result<Addr> {
+        let addr = self.addr(rank)?;
+        self.resident.get_mut(&rank).unwrap().pins += 1;
+        Ok(addr)
+    }
+
+    /// Release a pin of the peer of `rank`.
+    pub fn unpin(&mut self, rank: usize) {
+        if let Some(resident) = self.resident.get_mut(&rank) {
+            resident.pins = resident.pins.saturating_sub(1);
+        }
+    }
+
+    /// Remove the peer of `rank` from the address vector, if it is inserted and not pinned,
+    /// returning whether it was.
+    pub fn evict(&mut self, rank: usize) -> Result<bool> {
+        match self.resident.get(&rank) {
+            Some(resident) if resident.pins == 0 => {
+                self.remove(rank)?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    fn evict_lru(&mut self) -> Result<()> {
+        let rank = self
+            .lru
+            .values()
+            .copied()
+            .find(|rank| self.resident[rank].pins == 0)
+            .ok_or_else(|| Error::fabric("fi_av_insert", ffi::FI_ENOSPC as i64))?;
+        self.remove(rank)?;
+        self.evictions += 1;
+        Ok(())
+    }
+
+    fn remove(&mut self, rank: usize) -> Result<()> {
+        let resident = &self.resident[&rank];
+        self.av.remove(resident.addr)?;
+        let resident = self.resident.remove(&rank).unwrap();
+        self.lru.remove(&resident.used);
+        self.ranks.remove(&resident.addr);
+        Ok(())
+    }
+}
@@ -0,0 +1,280 @@
+//! Safe Rust wrappers for Libfabric.
+//!
+//! The raw bindgen output lives in the `ofi-libfabric-sys` crate, re-exported here as [`sys`]
+//! for anything the safe layer does not cover yet. Every wrapper exposes its raw pointer through
+//! `as_raw()`, so the two can be mixed freely.
+//!
+//! Objects form the usual libfabric hierarchy: an [`Info`] query yields [`InfoEntry`]s, which
+//! open a [`Fabric`], then a [`Domain`], from which queues, counters, address vectors, memory
+//! regions and [`Endpoint`]s are opened. Children keep their parents alive, and each object is
+//! closed with `fi_close()` once its last handle is dropped.
+//!
+//! ```no_run
+//! use libfabric::{AvAttr, BindFlags, Caps, CqAttr, Domain, EndpointType, Fabric, Info};
+//!
+//! # fn main() -> libfabric::Result<()> {
+//! let entries = Info::new()
+//!     .caps(Caps::MSG)
+//!     .ep_type(EndpointType::Rdm)
+//!     .provider("tcp")
+//!     .get()?;
+//! let entry = &entries[0];
+//!
+//! let fabric = Fabric::open(entry)?;
+//! let domain = Domain::open(&fabric, entry)?;
+//! let cq = domain.cq(&CqAttr::new())?;
+//! let av = domain.av(&AvAttr::new())?;
+//! let ep = domain
+//!     .endpoint(entry)?
+//!     .bind_cq(&cq, BindFlags::TRANSMIT | BindFlags::RECV)?
+//!     .bind_av(&av)?
+//!     .enable()?;
+//!
+//! // Talk to ourselves.
+//! let me = av.insert(&ep.name()?)?;
+//! ep.inject(b"hello", me)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Failures come back as a [`Result`], which is `#[must_use]`, as are the builders, attributes
+//! and policies whose methods return `Self`, so that a setting or an error dropped by mistake
+//! warns. Objects hold their fid as a non-null pointer, checked when opened or taken over with
+//! `from_raw()`.
+
+// Kept by the type itself, ex: `#[must_use] pub struct CqAttr`, rather than by each method.
+#![warn(clippy::return_self_not_must_use)]
+
+pub use ofi_libfabric_sys as sys;
+
+mod addr;
+mod arena;
+mod atomic;
+mod attr;
+mod av;
+#[cfg(feature = "bench")]
+pub mod bench;
+pub mod bootstrap;
+mod buffered;
+pub mod caps;
+#[cfg(feature = "channel")]
+pub mod channel;
+mod checks;
+pub mod client;
+mod cm;
+mod cntr;
+mod coalesce;
+#[cfg(any(feature = "rpc", feature = "channel"))]
+pub mod codec;
+mod collective;
+mod communicator;
+mod counted;
+mod cq;
+mod credit;
+#[cfg(feature = "cxi")]
+pub mod cxi;
+mod demux;
+mod dgram;
+mod diagnostics;
+mod dispatch;
+mod domain;
+#[cfg(feature = "efa")]
+pub mod efa;
+mod ep;
+mod epoch;
+mod eq;
+mod error;
+mod ext;
+mod fabric;
+mod fallback;
+#[cfg(feature = "fault")]
+pub mod fault;
+mod fid;
+mod flags;
+mod governor;
+#[cfg(any(feature = "cuda", feature = "ze"))]
+pub mod gpu_p2p;
+mod hook;
+mod info;
+#[cfg(feature = "kvstore")]
+pub mod kvstore;
+#[cfg(feature = "latency")]
+pub mod latency;
+mod lazy_av;
+mod lifecycle;
+mod liveness;
+#[cfg(feature = "log")]
+mod logging;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "mock")]
+pub mod mock;
+mod mr;
+mod multirail;
+mod mux;
+mod negotiate;
+mod notify;
+mod omnipath;
+mod ordering;
+mod peer;
+#[cfg(feature = "pmi")]
+mod pmi;
+pub mod preflight;
+#[cfg(libfabric_ge_1_20)]
+mod profile;
+mod progress;
+mod quiesce;
+mod record;
+mod registry;
+mod rendezvous;
+mod resources;
+mod retry;
+mod ring;
+mod rma;
+#[cfg(feature = "rpc")]
+pub mod rpc;
+mod runtime;
+mod scheduler;
+mod select;
+mod selftest;
+mod semaphore;
+pub mod server;
+mod session;
+mod shm;
+#[cfg(target_os = "linux")]
+mod shm_region;
+#[cfg(feature = "mock")]
+pub mod sim;
+#[cfg(feature = "sink")]
+mod sink;
+mod sizing;
+mod stats;
+mod strided;
+mod supervisor;
+mod symmetric;
+mod tag;
+mod tagged;
+mod threading;
+mod timeline;
+mod topology;
+mod trace;
+mod transport;
+mod triage;
+mod txpool;
+mod util;
+#[cfg(feature = "debug-validate")]
+pub mod validate;
+mod verbs;
+mod wait;
+mod work;
+#[cfg(any(feature = "cuda", feature = "ze"))]
+pub mod xpu;
+
+pub use addr::{AddressFormat, EfaAddr, IbUdAddr, PsmxAddr};
+pub use arena::{ARENA_IOV_LIMIT, OpArena};
+pub use atomic::{AtomicDatatype, AtomicKind, AtomicMsg, AtomicOp, Complex, LongDouble};
+pub use attr::{
+    DomainAttr, DomainConfig, EpAttr, FabricAttr, Progress, Protocol, ResourceMgmt, RxAttr,
+    RxQueueAttr, TrafficClass, TxAttr, TxQueueAttr,
+};
+pub use av::{Addr, AddrFormat, AddressVector, AvAttr, AvType, EndpointAddress};
+pub use buffered::BufferedRecv;
+pub use checks::ValidationLevel;
+pub use cm::{AcceptQueue, ConnRequest, Overflow, PeerAddress, ShutdownReport};
+pub use cntr::{CntrAttr, CntrEvents, Counter};
+pub use coalesce::{CoalesceAttr, Coalescer};
+pub use collective::{
+    AvSet, CollectiveAttr, CollectiveDatatype, CollectivePlan, CollectiveTicket, Multicast,
+    ReduceOp,
+};
+pub use communicator::Communicator;
+pub use counted::{CountedRma, CountedRmaAttr, RmaTicket};
+pub use cq::{
+    Completion, CompletionQueue, CqAttr, CqEntry, CqErrEntry, CqFormat, CtxCompletion,
+    DataCompletion, MsgCompletion, TaggedCompletion,
+};
+pub use credit::{CreditAttr, FlowControl};
+pub use demux::{DemuxCq, Demuxed, SharedCq, SharedCqAttr};
+pub use dgram::DgramEndpoint;
+pub use diagnostics::{Diagnostics, EntryDiagnostics, diagnostics, diagnostics_for};
+pub use dispatch::{CompletionRing, SteeredRing};
+pub use domain::Domain;
+pub use ep::{
+    Bound, Created, Enabled, Endpoint, EndpointState, PassiveEndpoint, ProfiledEndpoint,
+    ScalableEndpoint, Setup,
+};
+pub use epoch::RmaEpoch;
+pub use eq::{EQ_USER_EVENT_BASE, EQ_USER_EVENT_MAX, EqAttr, EqErrEntry, EqEvent, EventQueue};
+pub use error::{Error, Result, strerror};
+pub use ext::Ops;
+pub use fabric::Fabric;
+pub use fallback::{FallbackAttempt, FallbackAttr, FallbackStage, FallbackTransport};
+pub use fid::{AsRawFid, FidId};
+pub use flags::{Access, BindFlags, Caps, Mode, MrMode, MsgOrder, OpFlags};
+pub use governor::{
+    GovernedCq, GovernedEndpoint, TokenBucket, TokenBucketAttr, TxGovernor, TxPost, TxVerdict,
+};
+#[cfg(feature = "log")]
+pub use hook::perf_reports;
+pub use hook::{Hook, HookConfig, PerfCounter, PerfReport};
+pub use info::{EndpointType, Info, InfoEntry, Nic, PciAddress, Version, available_providers};
+pub use lazy_av::{LazyAv, LazyAvAttr};
+pub use lifecycle::{Lifecycle, LifecycleEvent};
+pub use liveness::{Liveness, LivenessAttr};
+#[cfg(feature = "log")]
+pub use logging::route_logging;
+pub use mr::{MemoryRegion, MrAttr};
+pub use multirail::{DEFAULT_STRIPE_THRESHOLD, MultiRailEndpoint};
+pub use mux::{Multiplexer, MuxAttr, Stream};
+pub use negotiate::{CapsReport, GetinfoReport, Hint, HintImpact, explain_getinfo, validate_caps};
+pub use notify::NotifiedRegion;
+pub use omnipath::{ContextCounts, NicSelection, OpxConfig, Psm3Config, context_counts};
+pub use ordering::{AccessPattern, RmaOrdering};
+pub use peer::{PeerCounter, PeerCq};
+#[cfg(libfabric_ge_1_20)]
+pub use profile::{Profile, ProfileDatatype, ProfileDesc};
+pub use progress::{
+    ProgressAffinity, ProgressAttr, ProgressDriver, ProgressEngine, ProgressModel, ProgressStats,
+    SystemProgress,
+};
+pub use quiesce::{Quiesce, QuiesceReport};
+pub use record::{OpKind, OpRecord, OpStatus, Recorder, RecordingCq, RecordingEndpoint};
+pub use registry::{FabricDomain, ProviderQuery, ProviderRegistry};
+pub use rendezvous::{Rendezvous, RendezvousAttr, SendPath};
+pub use resources::{Reservation, Resource, ResourceUsage};
+#[cfg(feature = "async")]
+pub use retry::post_with_retry_async;
+pub use retry::{RetryPolicy, post_with_retry, post_with_retry_on};
+pub use ring::{RecvRing, RecvRingAttr, RecvSlot};
+pub use rma::{Notify, NotifyOrder, RmaCompletions, RmaIov};
+pub use runtime::FabricRuntime;
+pub use scheduler::{SchedulePolicy, SchedulerAttr, SendScheduler};
+pub use select::{SelectionPolicy, select_provider};
+pub use selftest::{SelftestCheck, SelftestReport, selftest, selftest_provider};
+pub use semaphore::{RemoteSemaphore, SemaphorePoster};
+pub use shm::{HybridEndpoint, NodeId, ShmConfig, shm_hints, shm_name};
+#[cfg(target_os = "linux")]
+pub use shm_region::{ShmHandle, ShmRegion, ShmRegionAttr};
+#[cfg(feature = "sink")]
+pub use sink::MsgSink;
+pub use sizing::{Concurrency, QueueSizing, SizingWarning};
+pub use stats::{EndpointStats, OpStats, StatsTracker, TrackedCq, TrackedEndpoint};
+pub use strided::{Gather, Scatter, Strided};
+pub use supervisor::{PendingOps, ReconnectPolicy, Supervisor, SupervisorEvent};
+pub use symmetric::{SymmetricAlloc, SymmetricHeap, SymmetricHeapAttr};
+pub use tag::{TagField, TagMatch, TagSpace};
+pub use threading::{ThreadDomain, ThreadSafe, Threading, ThreadingModel};
+pub use timeline::TraceFormat;
+pub use topology::RankSet;
+#[cfg(feature = "tracing")]
+pub use trace::trace_data_ops;
+pub use transport::{AtomicTransport, Av, Cq, Mr, Transport};
+pub use triage::{ErrorClass, ErrorOrigin, ErrorSeverity, ErrorTriage, TriagedError};
+pub use txpool::{TxContext, TxContextPool};
+pub use verbs::{ForkSafety, IbAddr, VerbsDomain, shared_domains, verbs_domains, verbs_hints};
+#[cfg(unix)]
+pub use wait::{PollFd, PollFdSet};
+pub use wait::{WaitObj, WaitTarget};
+#[cfg(target_os = "linux")]
+pub use wait::{pin_thread, thread_affinity};
+pub use work::{DeferredWork, WorkGraph, WorkNode};
@@ -0,0 +1,109 @@
+use crate::eq::{EqErrEntry, EqEvent, EventQueue};
+use crate::error::Result;
+use crate::fid::FidId;
+use crate::triage::TriagedError;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+/// An event of the lifecycle of the objects of an application, broadcast by a [`Lifecycle`].
+#[derive(Debug, Clone)]
+pub enum LifecycleEvent {
+    /// The connection of the endpoint `fid` was shut down, by its peer or locally.
+    PeerShutdown { fid: FidId },
+    /// The domain `fid` is about to be closed by a [`Quiesce`](crate::Quiesce).
+    DomainClosing { fid: FidId },
+    /// A fatal error completion, which fails the same way once posted again.
+    CqError(TriagedError),
+    /// An error event of an event queue, ex: an asynchronous error of the provider reported on
+    /// the queue of the fabric.
+    AsyncError(EqErrEntry),
+}
+
+/// A broadcast of [`LifecycleEvent`]s to the tasks of an application, so that each learns of
+/// a peer shut down, of a domain closing, or of a fatal error, without polling a queue of its
+/// own.
+///
+/// Events are published from where they are read: the event queue polled by one task with
+/// [`poll_eq()`](Self::poll_eq), the hook of [`cq_errors()`](Self::cq_errors) on an
+/// [`ErrorTriage`](crate::ErrorTriage), and the domains a [`Quiesce`](crate::Quiesce) closes
+/// once given the broadcast. Each [subscriber](Self::subscribe) receives every event
+/// published after it subscribed, in order, until it drops its receiver. Handles are cheap to
+/// clone and share one broadcast.
+///
+/// ```no_run
+/// use libfabric::{CompletionQueue, ErrorTriage, EventQueue, Lifecycle, LifecycleEvent};
+///
+/// # fn run(eq: EventQueue, cq: CompletionQueue) -> libfabric::Result<()> {
+/// let lifecycle = Lifecycle::new();
+/// let events = lifecycle.subscribe();
+/// std::thread::spawn(move || {
+///     for event in events {
+///         if let LifecycleEvent::PeerShutdown { fid } = event {
+///             eprintln!("{fid:?} shut down");
+///         }
+///     }
+/// });
+/// let cq = ErrorTriage::new(cq).hook(lifecycle.cq_errors());
+/// while let Some(event) = lifecycle.poll_eq(&eq)? {
+///     // Handle connection requests and the like.
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct Lifecycle {
+    subscribers: Arc<Mutex<Vec<Sender<LifecycleEvent>>>>,
+}
+
+impl Lifecycle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Receive the events published from now on.
+    pub fn subscribe(&self) -> Receiver<LifecycleEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// The subscribers which have not dropped their receiver, as of the last event published.
+    pub fn subscribers(&self) -> usize {
+        self.subscribers.lock().unwrap().len()
+    }
+
+    /// Send `event` to every subscriber, forgetting those which dropped their receiver.
+    pub fn publish(&self, event: LifecycleEvent) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+
+    /// Read one event of `eq` without blocking, as [`EventQueue::read()`], publishing the
+    /// shutdowns, which are returned too, and the error events, which are consumed: the read
+    /// then returns `None`.
+    pub fn poll_eq(&self, eq: &EventQueue) -> Result<Option<EqEvent>> {
+        match eq.read() {
+            Err(err) if err.is_avail() => {
+                if let Some(entry) = eq.read_err()? {
+                    self.publish(LifecycleEvent::AsyncError(entry));
+                }
+                Ok(None)
+            }
+            Ok(Some(EqEvent::Shutdown { fid })) => {
+                self.publish(LifecycleEvent::PeerShutdown { fid });
+                Ok(Some(EqEvent::Shutdown { fid }))
+            }
+            other => other,
+        }
+    }
+
+    /// A hook of an [`ErrorTriage`](crate::ErrorTriage), publishing its fatal errors.
+    pub fn cq_errors(&self) -> impl FnMut(&TriagedError) + Send + 'static {
+        let lifecycle = self.clone();
+        move |err| {
+            if !err.class.is_transient() {
+                lifecycle.publish(LifecycleEvent::CqError(err.clone()));
+            }
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use crate::av::Addr;
+use crate::cq::{Completion, CqErrEntry};
+use crate::error::Result;
+use crate::progress::{ProgressDriver, SystemProgress};
+use crate::transport::{Av, Cq, CqHandler, Transport, poll_cq};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Attributes of a [`Liveness`] tracker, which must be the same on all peers.
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct LivenessAttr {
+    interval: Duration,
+    misses: u32,
+    tag: u64,
+    depth: usize,
+}
+
+impl Default for LivenessAttr {
+    fn default() -> Self {
+        LivenessAttr {
+            interval: Duration::from_secs(1),
+            misses: 3,
+            tag: 0,
+            depth: 16,
+        }
+    }
+}
+
+impl LivenessAttr {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Time between two heartbeats sent to a peer, 1 s by default.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Heartbeats in a row a peer may miss before it is declared dead, 3 by default.
+    pub fn misses(mut self, misses: u32) -> Self {
+        self.misses = misses.max(1);
+        self
+    }
+
+    /// Tag of the heartbeats, 0 by default.
+    pub fn tag(mut self, tag: u64) -> Self {
+        self.tag = tag;
+        self
+    }
+
+    /// Receives kept posted for the heartbeats of all peers, 16 by default.
+    pub fn depth(mut self, depth: usize) -> Self {
+        self.depth = depth.max(1);
+        self
+    }
+
+    fn timeout(&self) -> Duration {
+        self.interval * self.misses
+    }
+}
+
+struct Peer {
+    heard: Instant,
+    sent: Option<Instant>,
+}
+
+/// Liveness tracking of the peers of an RDM endpoint, which, unlike a connected one, reports
+/// nothing when a peer goes away: sends to it are accepted, and at best fail later on.
+///
+/// Each peer being watched is sent an empty tagged message every
+/// [`LivenessAttr::interval()`], and is declared dead once nothing was heard from it for
+/// [`LivenessAttr::misses()`] intervals. [`poll()`](Self::poll) then removes it from the
+/// address vector, and returns it so the application can give up on its operations. Anything
+/// received from a peer may be reported with [`heard()`](Self::heard), to keep it alive
+/// without heartbeats getting through, ex: under heavy traffic.
+///
+/// It runs over any [`Transport`], [`Cq`] and [`Av`], so over an RDM [`Endpoint`] opened
+/// with `Caps::TAGGED | Caps::SOURCE`, or a [`MockEndpoint`](crate::mock::MockEndpoint).
+/// The endpoint is dedicated to heartbeats, whose receives are posted on it and completions
+/// read from `cq`; the addresses are those of the heartbeat endpoints of the peers. Time is
+/// that of the system clock, or of the [`ProgressDriver`] given to
+/// [`with_driver()`](Self::with_driver).
+///
+/// [`Endpoint`]: crate::Endpoint
+pub struct Liveness<T: Transport, C: Cq, A: Av> {
+    ep: T,
+    cq: C,
+    av: A,
+    attr: LivenessAttr,
+    peers: HashMap<Addr, Peer>,
+    posted: usize,
+    driver: Box<dyn ProgressDriver + Send>,
+}
+
+impl<T: Transport, C: Cq, A: Av> Liveness<T, C, A> {
+    /// Post the heartbeat receives on `ep`, whose completions are read from `cq`, and evict
+    /// dead peers from `av`.
+    pub fn new(ep: T, cq: C, av: A, attr: &LivenessAttr) -> Result<Self> {
+        Self::with_driver(ep, cq, av, attr, SystemProgress::default())
+    }
+
+    /// Like [`new()`](Self::new), telling the time from the clock of `driver`, ex: the
+    /// virtual one of a [`MockProgress`](crate::mock::MockProgress).
+    pub fn with_driver(
+        ep: T,
+        cq: C,
+        av: A,
+        attr: &LivenessAttr,
+        driver: impl ProgressDriver + Send + 'static,
+    ) -> Result<Self> {
+        let mut liveness = Liveness {
+            ep,
+            cq,
+            av,
+            attr: attr.clone(),
+            peers: HashMap::new(),
+            posted: 0,
+            driver: Box::new(driver),
+        };
+        liveness.post_recvs()?;
+        Ok(liveness)
+    }
+
+    pub fn endpoint(&self) -> &T {
+        &self.ep
+    }
+
+    /// Start watching `addr`, as if just heard from.
+    pub fn watch(&mut self, addr: Addr) {
+        self.peers.insert(
+            addr,
+            Peer {
+                heard: self.driver.now(),
+                sent: None,
+            },
+        );
+    }
+
+    /// Stop watching `addr`, leaving it in the address vector.
+    pub fn unwatch(&mut self, addr: Addr) {
+        self.peers.remove(&addr);
+    }
+
+    /// Whether `addr` is watched, that is, neither unwatched nor declared dead.
+    pub fn is_watched(&self, addr: Addr) -> bool {
+        self.peers.contains_key(&addr)
+    }
+
+    /// Time since something was last heard from `addr`, if watched.
+    pub fn silence(&self, addr: Addr) -> Option<Duration> {
+        let now = self.driver.now();
+        (self.peers.get(&addr)).map(|peer| now.saturating_duration_since(peer.heard))
+    }
+
+    /// Report that something was received from `addr`, other than a heartbeat.
+    pub fn heard(&mut self, addr: Addr) {
+        if let Some(peer) = self.peers.get_mut(&addr) {
+            peer.heard = self.driver.now();
+        }
+    }
+
+    /// Take in the heartbeats received, send those due, and evict the peers declared dead
+    /// from the address vector, which are returned and no longer watched. Fails with the
+    /// error of a heartbeat which failed to be sent, or of a peer which failed to be evicted,
+    /// which is tried again on the next call.
+    pub fn poll(&mut self) -> Result<Vec<Addr>> {
+        poll_cq(self)?;
+        self.post_recvs()?;
+
+        let now = self.driver.now();
+        let timeout = self.attr.timeout();
+        let mut dead: Vec<Addr> = self
+            .peers
+            .iter()
+            .filter(|(_, peer)| now.duration_since(peer.heard) >= timeout)
+            .map(|(addr, _)| *addr)
+            .collect();
+        dead.sort_by_key(|addr| addr.as_raw());
+        for addr in &dead {
+            self.av.remove(*addr)?;
+            self.peers.remove(addr);
+        }
+
+        for (addr, peer) in &mut self.peers {
+            if peer
+                .sent
+                .is_some_and(|sent| now.duration_since(sent) < self.attr.interval)
+            {
+                continue;
+            }
+            match self.ep.tinject(&[], *addr, self.attr.tag) {
+                Ok(()) => peer.sent = Some(now),
+                Err(err) if err.is_again() => break,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(dead)
+    }
+
+    // Keep `depth` empty receives posted, as far as the provider has room for them.
+    fn post_recvs(&mut self) -> Result<()> {
+        while self.posted < self.attr.depth {
+            // SAFETY: the buffers are empty, so the provider never writes to them.
+            let posted = unsafe {
+                self.ep
+                    .trecv(&mut [], None, Addr::UNSPEC, self.attr.tag, 0, 0)
+            };
+            match posted {
+                Ok(()) => self.posted += 1,
+                Err(err) if err.is_again() => break,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: Transport, C: Cq, A: Av> CqHandler for Liveness<T, C, A> {
+    type Cq = C;
+
+    fn cq(&self) -> &C {
+        &self.cq
+    }
+
+    fn complete(&mut self, _completion: &Completion, src: Addr) {
+        self.posted -= 1;
+        self.heard(src);
+    }
+
+    // A truncated heartbeat is one all the same.
+    fn failed(&mut self, entry: CqErrEntry) {
+        self.posted -= 1;
+        self.heard(entry.src_addr);
+    }
+}
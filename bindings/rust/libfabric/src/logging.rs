@@ -0,0 +1,117 @@
+use crate::error::{Result, check};
+use crate::hook;
+use crate::info::Version;
+use crate::util::cstr;
+use ofi_libfabric_sys::bindgen as ffi;
+use std::os::raw::{c_char, c_int};
+use std::sync::OnceLock;
+
+/// Route the log messages of libfabric and its providers to the `log` crate, instead of
+/// stderr, via the logging import extension (`fi_import_log()`). Enabled by the `log` feature.
+///
+/// Messages are logged under the `libfabric::{provider}::{subsystem}` target, ex:
+/// `libfabric::tcp::ep_ctrl`, with `FI_LOG_WARN`, `FI_LOG_INFO` and `FI_LOG_DEBUG` mapped to
+/// the levels of the same name, and `FI_LOG_TRACE`, which traces API calls, to
+/// [`log::Level::Trace`]. The filters of the logger then apply in place of `FI_LOG_LEVEL`
+/// and `FI_LOG_SUBSYS`, while `FI_LOG_PROV` still drops other providers. With `tracing`,
+/// messages reach subscribers through the `LogTracer` of the `tracing-log` crate.
+///
+/// The reports of the [perf hook](crate::Hook::Perf) are intercepted, and returned by
+/// [`perf_reports()`](crate::perf_reports) instead.
+///
+/// Logging is routed for the rest of the process. Fails with `FI_EALREADY` if another
+/// component of the process already imported its own logging; calling it again is a no-op.
+pub fn route_logging() -> Result<()> {
+    static ROUTED: OnceLock<Result<()>> = OnceLock::new();
+    ROUTED.get_or_init(import).clone()
+}
+
+fn import() -> Result<()> {
+    // Kept by libfabric until the end of the process, as there is no unrouting.
+    let ops = Box::leak(Box::new(ffi::fi_ops_log {
+        size: std::mem::size_of::<ffi::fi_ops_log>(),
+        enabled: Some(enabled),
+        ready: None,
+        log: Some(log),
+    }));
+    let fid = Box::leak(Box::new(ffi::fid_logging {
+        ops,
+        ..Default::default()
+    }));
+    check("fi_import_log", unsafe {
+        ffi::fi_import_log(Version::HEADER.as_raw(), 0, fid)
+    })
+}
+
+fn level(level: ffi::fi_log_level) -> log::Level {
+    match level {
+        ffi::fi_log_level_FI_LOG_WARN => log::Level::Warn,
+        ffi::fi_log_level_FI_LOG_INFO => log::Level::Info,
+        ffi::fi_log_level_FI_LOG_DEBUG => log::Level::Debug,
+        _ => log::Level::Trace,
+    }
+}
+
+fn provider<'a>(prov: *const ffi::fi_provider) -> &'a str {
+    match unsafe { prov.as_ref() } {
+        Some(prov) => unsafe { cstr(prov.name) },
+        None => "core",
+    }
+}
+
+fn target(prov: *const ffi::fi_provider, subsys: ffi::fi_log_subsys) -> String {
+    let prov = provider(prov);
+    let subsys = match subsys {
+        ffi::fi_log_subsys_FI_LOG_CORE => "core",
+        ffi::fi_log_subsys_FI_LOG_FABRIC => "fabric",
+        ffi::fi_log_subsys_FI_LOG_DOMAIN => "domain",
+        ffi::fi_log_subsys_FI_LOG_EP_CTRL => "ep_ctrl",
+        ffi::fi_log_subsys_FI_LOG_EP_DATA => "ep_data",
+        ffi::fi_log_subsys_FI_LOG_AV => "av",
+        ffi::fi_log_subsys_FI_LOG_CQ => "cq",
+        ffi::fi_log_subsys_FI_LOG_EQ => "eq",
+        ffi::fi_log_subsys_FI_LOG_MR => "mr",
+        ffi::fi_log_subsys_FI_LOG_CNTR => "cntr",
+        _ => "other",
+    };
+    format!("libfabric::{prov}::{subsys}")
+}
+
+unsafe extern "C" fn enabled(
+    prov: *const ffi::fi_provider,
+    level: ffi::fi_log_level,
+    subsys: ffi::fi_log_subsys,
+    flags: u64,
+) -> c_int {
+    if hook::is_perf_report(provider(prov), level, subsys) {
+        return 1;
+    }
+    if flags & ffi::FI_LOG_PROV_FILTERED as u64 != 0 {
+        return 0;
+    }
+    log::log_enabled!(target: &target(prov, subsys), self::level(level)) as c_int
+}
+
+unsafe extern "C" fn log(
+    prov: *const ffi::fi_provider,
+    level: ffi::fi_log_level,
+    subsys: ffi::fi_log_subsys,
+    func: *const c_char,
+    line: c_int,
+    msg: *const c_char,
+) {
+    // Messages are formatted by libfabric, a newline included.
+    let msg = unsafe { cstr(msg) }.trim_end();
+    if hook::is_perf_report(provider(prov), level, subsys) {
+        return hook::perf_line(msg);
+    }
+    let target = target(prov, subsys);
+    log::logger().log(
+        &log::Record::builder()
+            .args(format_args!("{}(): {msg}", unsafe { cstr(func) }))
+            .level(self::level(level))
+            .target(&target)
+            .line(u32::try_from(line).ok())
+            .build(),
+    );
+}
@@ -0,0 +1,318 @@
+//! Fabric statistics in the OpenMetrics text format, enabled by the `metrics` feature, for
+//! Prometheus or any compatible scraper.
+//!
+//! A [`Registry`] samples the counters, completion queues and, with libfabric 1.20 and later,
+//! profiles added to it, along with the memory regions registered by the process. It renders
+//! them on demand with [`Registry::render()`], or periodically from a background thread with
+//! [`Registry::spawn()`], whose latest rendering the application serves over HTTP as it sees
+//! fit.
+//!
+//! ```no_run
+//! use libfabric::metrics::Registry;
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! # fn run(cq: libfabric::CompletionQueue, cntr: libfabric::Counter) {
+//! let registry = Arc::new(Registry::new());
+//! registry.cq("tx", &cq);
+//! registry.counter("rma", &cntr);
+//! let sampler = registry.spawn(Duration::from_secs(10));
+//! // Served as text/plain at /metrics, ex:
+//! // fabric_cq_completions_total{name="tx"} 1234
+//! let body = sampler.latest();
+//! # }
+//! ```
+
+use crate::cntr::Counter;
+use crate::cq::CompletionQueue;
+#[cfg(libfabric_ge_1_20)]
+use crate::profile::Profile;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// The memory regions registered by the process, and their total length.
+static MR_COUNT: AtomicUsize = AtomicUsize::new(0);
+static MR_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) fn mr_registered(len: usize) {
+    MR_COUNT.fetch_add(1, Ordering::Relaxed);
+    MR_BYTES.fetch_add(len, Ordering::Relaxed);
+}
+
+pub(crate) fn mr_closed(len: usize) {
+    MR_COUNT.fetch_sub(1, Ordering::Relaxed);
+    MR_BYTES.fetch_sub(len, Ordering::Relaxed);
+}
+
+// What a completion queue read so far, counted by the queue itself.
+#[derive(Default)]
+pub(crate) struct CqStats {
+    pub(crate) completions: AtomicU64,
+    pub(crate) errors: AtomicU64,
+}
+
+/// Whether a metric only goes up, or may go both ways.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricKind {
+    Counter,
+    Gauge,
+}
+
+/// One value of a metric family, for a given object.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    /// The name of the family, ex: `fabric_cq_errors`, without the `_total` suffix of the
+    /// samples of counters.
+    pub family: String,
+    pub help: String,
+    pub kind: MetricKind,
+    /// The name the object was added with, empty for process wide metrics.
+    pub name: String,
+    pub value: u64,
+}
+
+enum Source {
+    Counter(Counter),
+    Cq(CompletionQueue),
+    #[cfg(libfabric_ge_1_20)]
+    Profile(Profile),
+}
+
+/// The objects sampled, each under a name reported as the `name` label of its metrics.
+///
+/// The registry keeps the objects open until it is dropped.
+#[derive(Default)]
+pub struct Registry {
+    sources: Mutex<Vec<(String, Source)>>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sample the events and errors of `cntr`.
+    pub fn counter(&self, name: &str, cntr: &Counter) {
+        self.add(name, Source::Counter(cntr.clone()));
+    }
+
+    /// Sample the completions and error completions read from `cq`, and its size when set by
+    /// [`CqAttr::size()`](crate::CqAttr::size).
+    pub fn cq(&self, name: &str, cq: &CompletionQueue) {
+        self.add(name, Source::Cq(cq.clone()));
+    }
+
+    /// Sample the `FI_UINT64` variables of `profile`, as gauges named after them, ex:
+    /// `fabric_profile_fi_var_unexp_msg_cnt`.
+    #[cfg(libfabric_ge_1_20)]
+    pub fn profile(&self, name: &str, profile: &Profile) {
+        self.add(name, Source::Profile(profile.clone()));
+    }
+
+    fn add(&self, name: &str, source: Source) {
+        self.sources.lock().unwrap().push((name.to_owned(), source));
+    }
+
+    /// The current value of every metric.
+    pub fn sample(&self) -> Vec<Sample> {
+        let mut samples = vec![
+            sample(
+                "fabric_mr_regions",
+                "Memory regions registered by the process.",
+                MetricKind::Gauge,
+                "",
+                MR_COUNT.load(Ordering::Relaxed) as u64,
+            ),
+            sample(
+                "fabric_mr_bytes",
+                "Bytes of memory registered by the process.",
+                MetricKind::Gauge,
+                "",
+                MR_BYTES.load(Ordering::Relaxed) as u64,
+            ),
+        ];
+        for (name, source) in self.sources.lock().unwrap().iter() {
+            match source {
+                Source::Counter(cntr) => {
+                    samples.push(sample(
+                        "fabric_counter_events",
+                        "Events of the counter.",
+                        MetricKind::Counter,
+                        name,
+                        cntr.read(),
+                    ));
+                    samples.push(sample(
+                        "fabric_counter_errors",
+                        "Errors of the counter.",
+                        MetricKind::Counter,
+                        name,
+                        cntr.read_err(),
+                    ));
+                }
+                Source::Cq(cq) => {
+                    let stats = cq.stats();
+                    samples.push(sample(
+                        "fabric_cq_completions",
+                        "Completions read from the queue.",
+                        MetricKind::Counter,
+                        name,
+                        stats.completions.load(Ordering::Relaxed),
+                    ));
+                    samples.push(sample(
+                        "fabric_cq_errors",
+                        "Error completions read from the queue.",
+                        MetricKind::Counter,
+                        name,
+                        stats.errors.load(Ordering::Relaxed),
+                    ));
+                    if cq.size() > 0 {
+                        samples.push(sample(
+                            "fabric_cq_size",
+                            "Entries the queue was opened with.",
+                            MetricKind::Gauge,
+                            name,
+                            cq.size() as u64,
+                        ));
+                    }
+                }
+                #[cfg(libfabric_ge_1_20)]
+                Source::Profile(profile) => {
+                    // Profiles the provider does not support are left out.
+                    let Ok(vars) = profile.vars() else { continue };
+                    profile.snapshot(|profile| {
+                        for var in vars.iter().filter(|var| var.is_u64()) {
+                            if let Ok(value) = profile.read_u64(var.id) {
+                                samples.push(sample(
+                                    &format!("fabric_profile_{}", sanitize(&var.name)),
+                                    &var.description,
+                                    MetricKind::Gauge,
+                                    name,
+                                    value,
+                                ));
+                            }
+                        }
+                    });
+                }
+            }
+        }
+        samples
+    }
+
+    /// The current value of every metric, in the OpenMetrics text format.
+    pub fn render(&self) -> String {
+        let mut samples = self.sample();
+        // Samples of a family are grouped, in the order of their first appearance.
+        let mut families: Vec<String> = Vec::new();
+        for sample in &samples {
+            if !families.contains(&sample.family) {
+                families.push(sample.family.clone());
+            }
+        }
+        samples.sort_by_key(|sample| families.iter().position(|f| *f == sample.family));
+
+        let mut out = String::new();
+        let mut last = None;
+        for sample in &samples {
+            if last != Some(&sample.family) {
+                let kind = match sample.kind {
+                    MetricKind::Counter => "counter",
+                    MetricKind::Gauge => "gauge",
+                };
+                let _ = writeln!(out, "# TYPE {} {kind}", sample.family);
+                let _ = writeln!(out, "# HELP {} {}", sample.family, escape(&sample.help));
+                last = Some(&sample.family);
+            }
+            let suffix = match sample.kind {
+                MetricKind::Counter => "_total",
+                MetricKind::Gauge => "",
+            };
+            let _ = match sample.name.is_empty() {
+                true => writeln!(out, "{}{suffix} {}", sample.family, sample.value),
+                false => writeln!(
+                    out,
+                    "{}{suffix}{{name=\"{}\"}} {}",
+                    sample.family,
+                    escape(&sample.name),
+                    sample.value
+                ),
+            };
+        }
+        out.push_str("# EOF\n");
+        out
+    }
+
+    /// Render the metrics every `period` from a background thread, until the returned sampler
+    /// is dropped.
+    pub fn spawn(self: &Arc<Self>, period: Duration) -> Sampler {
+        let latest = Arc::new(Mutex::new(self.render()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = thread::spawn({
+            let (registry, latest, stop) = (self.clone(), latest.clone(), stop.clone());
+            move || {
+                while !stop.load(Ordering::Acquire) {
+                    thread::park_timeout(period);
+                    *latest.lock().unwrap() = registry.render();
+                }
+            }
+        });
+        Sampler {
+            latest,
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+/// The background thread of [`Registry::spawn()`], stopped on drop.
+pub struct Sampler {
+    latest: Arc<Mutex<String>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Sampler {
+    /// The last rendering of the metrics.
+    pub fn latest(&self) -> String {
+        self.latest.lock().unwrap().clone()
+    }
+}
+
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+fn sample(family: &str, help: &str, kind: MetricKind, name: &str, value: u64) -> Sample {
+    Sample {
+        family: family.to_owned(),
+        help: help.to_owned(),
+        kind,
+        name: name.to_owned(),
+        value,
+    }
+}
+
+// Metric names are made of ASCII letters, digits and underscores.
+#[cfg(libfabric_ge_1_20)]
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| match c.is_ascii_alphanumeric() {
+            true => c.to_ascii_lowercase(),
+            false => '_',
+        })
+        .collect()
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,1062 @@
+//! An in-memory fabric implementing [`Transport`], [`AtomicTransport`], [`Cq`], [`Av`] and
+//! [`Mr`], enabled by the `mock` feature.
+//!
+//! Applications written against those traits can unit test their protocols over a
+//! [`MockFabric`], without fabric hardware, a provider, or privileges. Operations are moved
+//! between endpoints when a completion queue is read, after a configurable delay, and failures
+//! may be injected both when posting operations and in their completions.
+//! [`sim`](crate::sim) builds seeded simulations of lossy, reordering networks on top of it.
+//! [`MockProgress`] drives progress on a virtual clock, for the timeouts, retries and
+//! heartbeats built on a [`ProgressDriver`].
+//!
+//! ```
+//! use libfabric::mock::MockFabric;
+//! use libfabric::{Addr, Av, Completion, Cq, Transport};
+//!
+//! let fabric = MockFabric::new();
+//! let (client, server) = (fabric.endpoint(), fabric.endpoint());
+//! let server_addr = fabric.av().insert(&server.name()?)?;
+//!
+//! let mut buf = [0u8; 5];
+//! unsafe { server.recv(&mut buf, None, Addr::UNSPEC, 1)? };
+//! client.inject(b"hello", server_addr)?;
+//!
+//! let mut completions = [Completion::default(); 1];
+//! assert_eq!(server.cq().read(&mut completions)?, 1);
+//! assert_eq!(&buf, b"hello");
+//! # Ok::<(), libfabric::Error>(())
+//! ```
+//!
+//! The mock follows the semantics of a reliable, connectionless (RDM) endpoint whose memory
+//! regions use virtual addresses (`FI_MR_VIRT_ADDR`), with these simplifications:
+//!
+//! - All endpoints are in the same domain, so any of them may access any region, given its key.
+//! - The initiator of an operation is notified once it is delivered to the peer.
+//! - Remote CQ data of RMA writes is reported at the target without consuming a receive.
+
+use crate::av::{Addr, EndpointAddress};
+use crate::cq::{Completion, CqErrEntry};
+use crate::error::{Error, Result};
+use crate::flags::Access;
+use crate::progress::ProgressDriver;
+use crate::sim::Scheduler;
+use crate::trace;
+use crate::transport::{AtomicTransport, Av, Cq, Mr, Transport};
+use ofi_libfabric_sys::bindgen as ffi;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use std::{mem, ptr};
+
+/// An in-memory network, connecting the endpoints opened from it.
+#[derive(Clone, Default)]
+pub struct MockFabric {
+    net: Arc<Mutex<Network>>,
+}
+
+#[derive(Default)]
+struct Network {
+    endpoints: Vec<EpState>,
+    regions: HashMap<u64, Region>,
+    next_key: u64,
+    in_flight: VecDeque<InFlight>,
+    delay: Duration,
+    // Injected failures, as a count of operations left to fail and the code they fail with.
+    post_failures: (usize, i32),
+    completion_failures: (usize, i32),
+    // Replaces wall clock delays in simulated fabrics.
+    scheduler: Option<Scheduler>,
+}
+
+// SAFETY: The buffer pointers of posted operations are only dereferenced under the contract of
+// the `unsafe` calls which posted them, from whichever thread happens to progress the network.
+unsafe impl Send for Network {}
+
+#[derive(Default)]
+struct EpState {
+    recvs: VecDeque<PostedRecv>,
+    // Messages delivered to the endpoint, not matched by a receive yet.
+    unexpected: VecDeque<Message>,
+    // Successful completions with their source address, and error completions, in the order
+    // they were generated.
+    completions: VecDeque<Result<(Completion, Addr), CqErrEntry>>,
+    closed: bool,
+}
+
+struct PostedRecv {
+    buf: *mut u8,
+    len: usize,
+    src: Addr,
+    // The tag and ignored bits of tagged receives.
+    tag: Option<(u64, u64)>,
+    context: usize,
+}
+
+struct Message {
+    src: usize,
+    data: Vec<u8>,
+    cq_data: Option<u64>,
+    tag: Option<u64>,
+}
+
+impl Message {
+    const EMPTY: Message = Message {
+        src: 0,
+        data: Vec::new(),
+        cq_data: None,
+        tag: None,
+    };
+}
+
+struct InFlight {
+    src: usize,
+    dest: usize,
+    op: Op,
+    // None for injected operations, which have no completion.
+    context: Option<usize>,
+    due: Due,
+}
+
+enum Due {
+    Time(Instant),
+    // A tick of the virtual clock of a simulated fabric.
+    Tick(u64),
+}
+
+enum Op {
+    Msg(Message),
+    Write {
+        data: Vec<u8>,
+        addr: u64,
+        key: u64,
+        cq_data: Option<u64>,
+    },
+    Read {
+        buf: *mut u8,
+        len: usize,
+        addr: u64,
+        key: u64,
+    },
+    CompareSwap {
+        value: u64,
+        compare: u64,
+        result: *mut u64,
+        addr: u64,
+        key: u64,
+    },
+}
+
+#[cfg(feature = "tracing")]
+impl Op {
+    // The bytes moved, as data operations are traced.
+    fn len(&self) -> usize {
+        match self {
+            Op::Msg(msg) => msg.data.len(),
+            Op::Write { data, .. } => data.len(),
+            Op::Read { len, .. } => *len,
+            Op::CompareSwap { .. } => mem::size_of::<u64>(),
+        }
+    }
+
+    fn tag(&self) -> Option<u64> {
+        match self {
+            Op::Msg(msg) => msg.tag,
+            _ => None,
+        }
+    }
+}
+
+struct Region {
+    addr: usize,
+    len: usize,
+    access: Access,
+}
+
+// A successful completion, carrying the remote CQ data and tag of received messages.
+fn completion(context: usize, flags: u64, len: usize, buf: *mut u8, msg: &Message) -> Completion {
+    let cq_data = msg.cq_data;
+    Completion::from_raw(ffi::fi_cq_tagged_entry {
+        op_context: context as *mut _,
+        flags: flags | cq_data.map_or(0, |_| ffi::FI_REMOTE_CQ_DATA as u64),
+        len,
+        buf: buf.cast(),
+        data: cq_data.unwrap_or(0),
+        tag: msg.tag.unwrap_or(0),
+    })
+}
+
+fn error_entry(context: usize, flags: u64, code: i32, message: impl Into<String>) -> CqErrEntry {
+    CqErrEntry {
+        context,
+        flags,
+        len: 0,
+        data: 0,
+        tag: 0,
+        olen: 0,
+        error: Error::fabric("fi_cq_read", code as i64),
+        prov_errno: 0,
+        message: message.into(),
+        src_addr: Addr::NOTAVAIL,
+        src_name: None,
+    }
+}
+
+impl MockFabric {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn simulated(scheduler: Scheduler) -> Self {
+        let net = Network {
+            scheduler: Some(scheduler),
+            ..Network::default()
+        };
+        MockFabric {
+            net: Arc::new(Mutex::new(net)),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Network> {
+        self.net.lock().unwrap()
+    }
+
+    /// Open an endpoint, along with its completion queue.
+    pub fn endpoint(&self) -> MockEndpoint {
+        let mut net = self.lock();
+        net.endpoints.push(EpState::default());
+        MockEndpoint {
+            inner: Arc::new(EpInner {
+                fabric: self.clone(),
+                index: net.endpoints.len() - 1,
+            }),
+        }
+    }
+
+    /// An address vector resolving the names of the endpoints of this fabric.
+    pub fn av(&self) -> MockAv {
+        MockAv {
+            fabric: self.clone(),
+        }
+    }
+
+    /// Register memory, accessed by peers through the key of the returned region.
+    ///
+    /// # Safety
+    ///
+    /// As for [`Domain::register()`](crate::Domain::register), the memory must stay valid while
+    /// the region is registered.
+    pub unsafe fn register(&self, buf: *mut u8, len: usize, access: Access) -> Result<MockMr> {
+        let mut net = self.lock();
+        net.next_key += 1;
+        let key = net.next_key;
+        net.regions.insert(
+            key,
+            Region {
+                addr: buf as usize,
+                len,
+                access,
+            },
+        );
+        Ok(MockMr {
+            inner: Arc::new(MrInner {
+                fabric: self.clone(),
+                key,
+                addr: buf,
+                len,
+            }),
+        })
+    }
+
+    /// Delay the delivery of the operations posted from now on.
+    pub fn set_delay(&self, delay: Duration) {
+        self.lock().delay = delay;
+    }
+
+    /// Fail the next `count` operations posted on any endpoint with the `FI_E*` `code`, ex:
+    /// `FI_EAGAIN` to exercise retries.
+    pub fn fail_posts(&self, count: usize, code: i32) {
+        self.lock().post_failures = (count, code);
+    }
+
+    /// Complete the next `count` operations delivered in error with the `FI_E*` `code`, rather
+    /// than delivering them.
+    pub fn fail_completions(&self, count: usize, code: i32) {
+        self.lock().completion_failures = (count, code);
+    }
+
+    /// Deliver the operations whose delay elapsed. This is also done when reading any
+    /// completion queue.
+    pub fn progress(&self) {
+        self.lock().progress();
+    }
+}
+
+impl Network {
+    fn take_failure(failures: &mut (usize, i32)) -> Option<i32> {
+        match failures.0 {
+            0 => None,
+            _ => {
+                failures.0 -= 1;
+                Some(failures.1)
+            }
+        }
+    }
+
+    // Forget about the buffers of the endpoint, which may be freed from now on.
+    fn close(&mut self, index: usize) {
+        let ep = &mut self.endpoints[index];
+        ep.closed = true;
+        ep.recvs.clear();
+        ep.unexpected.clear();
+        self.in_flight.retain(|op| op.src != index);
+    }
+
+    fn peer(&self, op: &'static str, addr: Addr) -> Result<usize> {
+        match usize::try_from(addr.as_raw()) {
+            Ok(index) if index < self.endpoints.len() => Ok(index),
+            _ => Err(Error::fabric(op, ffi::FI_EADDRNOTAVAIL as i64)),
+        }
+    }
+
+    // The memory of `len` bytes at `addr` in the region of `key`, if it allows `access`.
+    fn resolve(&self, key: u64, addr: u64, len: usize, access: Access) -> Option<*mut u8> {
+        let region = self.regions.get(&key)?;
+        let offset = usize::try_from(addr).ok()?.checked_sub(region.addr)?;
+        match region.access.contains(access) && offset.checked_add(len)? <= region.len {
+            true => Some((region.addr + offset) as *mut u8),
+            false => None,
+        }
+    }
+
+    fn progress(&mut self) {
+        let now = Instant::now();
+        let tick = self.scheduler.as_mut().map(Scheduler::tick);
+        let (mut due, in_flight) = mem::take(&mut self.in_flight)
+            .into_iter()
+            .partition::<Vec<_>, _>(|op| match op.due {
+                Due::Time(time) => time <= now,
+                Due::Tick(due) => tick.is_some_and(|tick| due <= tick),
+            });
+        self.in_flight = in_flight.into();
+        // In the order they were due, then posted.
+        due.sort_by_key(|op| match op.due {
+            Due::Time(_) => 0,
+            Due::Tick(tick) => tick,
+        });
+        for op in due {
+            self.deliver(op);
+        }
+        for ep in &mut self.endpoints {
+            ep.match_recvs();
+        }
+    }
+
+    fn deliver(&mut self, op: InFlight) {
+        let (flags, len) = match &op.op {
+            Op::Msg(msg) if msg.tag.is_some() => (ffi::FI_SEND | ffi::FI_TAGGED, msg.data.len()),
+            Op::Msg(msg) => (ffi::FI_SEND | ffi::FI_MSG, msg.data.len()),
+            Op::Write { data, .. } => (ffi::FI_WRITE | ffi::FI_RMA, data.len()),
+            Op::Read { len, .. } => (ffi::FI_READ | ffi::FI_RMA, *len),
+            Op::CompareSwap { .. } => (ffi::FI_ATOMIC | ffi::FI_READ, 8),
+        };
+        let flags = flags as u64;
+        let failure = match Self::take_failure(&mut self.completion_failures) {
+            Some(code) => Some((code, "injected failure")),
+            None => self
+                .scheduler
+                .as_mut()
+                .is_some_and(Scheduler::drops)
+                .then_some((ffi::FI_EIO as i32, "dropped by the simulation")),
+        };
+        let result = match failure {
+            Some((code, message)) => Err(error_entry(0, flags, code, message)),
+            None if self.endpoints[op.dest].closed => Err(error_entry(
+                0,
+                flags,
+                ffi::FI_EHOSTUNREACH as i32,
+                "the peer endpoint is closed",
+            )),
+            None => self.apply(op.src, op.dest, op.op),
+        };
+        let Some(context) = op.context else {
+            return;
+        };
+        let src = &mut self.endpoints[op.src];
+        match result {
+            Ok(()) => {
+                let entry = completion(context, flags, len, ptr::null_mut(), &Message::EMPTY);
+                src.completions.push_back(Ok((entry, Addr::NOTAVAIL)));
+            }
+            Err(entry) => src
+                .completions
+                .push_back(Err(CqErrEntry { context, ..entry })),
+        }
+    }
+
+    // Carry out an operation at its target.
+    #[allow(clippy::result_large_err)]
+    fn apply(&mut self, src: usize, dest: usize, op: Op) -> Result<(), CqErrEntry> {
+        let denied = |flags: u32| {
+            error_entry(
+                0,
+                flags as u64,
+                ffi::FI_EACCES as i32,
+                "no registered region at this address and key",
+            )
+        };
+        match op {
+            Op::Msg(msg) => self.endpoints[dest].unexpected.push_back(msg),
+            Op::Write {
+                data,
+                addr,
+                key,
+                cq_data,
+            } => {
+                let target = self
+                    .resolve(key, addr, data.len(), Access::REMOTE_WRITE)
+                    .ok_or_else(|| denied(ffi::FI_WRITE | ffi::FI_RMA))?;
+                // SAFETY: the target is registered, see `MockFabric::register()`.
+                unsafe { ptr::copy_nonoverlapping(data.as_ptr(), target, data.len()) };
+                if cq_data.is_some() {
+                    let msg = Message {
+                        cq_data,
+                        ..Message::EMPTY
+                    };
+                    let flags = (ffi::FI_REMOTE_WRITE | ffi::FI_RMA) as u64;
+                    let entry = completion(0, flags, 0, ptr::null_mut(), &msg);
+                    let src = Addr::from_raw(src as u64);
+                    self.endpoints[dest].completions.push_back(Ok((entry, src)));
+                }
+            }
+            Op::Read {
+                buf,
+                len,
+                addr,
+                key,
+            } => {
+                let source = self
+                    .resolve(key, addr, len, Access::REMOTE_READ)
+                    .ok_or_else(|| denied(ffi::FI_READ | ffi::FI_RMA))?;
+                // SAFETY: the source is registered, and `buf` valid until the completion.
+                unsafe { ptr::copy(source, buf, len) };
+            }
+            Op::CompareSwap {
+                value,
+                compare,
+                result,
+                addr,
+                key,
+            } => {
+                let target = self
+                    .resolve(key, addr, 8, Access::REMOTE_READ | Access::REMOTE_WRITE)
+                    .ok_or_else(|| denied(ffi::FI_ATOMIC | ffi::FI_READ))?
+                    .cast::<u64>();
+                // SAFETY: the target is registered, and `result` valid until the completion.
+                // Operations are applied under the lock of the network, one at a time.
+                unsafe {
+                    let previous = target.read_unaligned();
+                    if previous == compare {
+                        target.write_unaligned(value);
+                    }
+                    result.write_unaligned(previous);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl PostedRecv {
+    fn matches(&self, msg: &Message) -> bool {
+        let src = self.src == Addr::UNSPEC || self.src.as_raw() == msg.src as u64;
+        src && match (self.tag, msg.tag) {
+            (None, None) => true,
+            (Some((tag, ignore)), Some(msg_tag)) => (tag & !ignore) == (msg_tag & !ignore),
+            _ => false,
+        }
+    }
+}
+
+impl EpState {
+    fn match_recvs(&mut self) {
+        let mut i = 0;
+        while i < self.unexpected.len() {
+            let Some(j) = self
+                .recvs
+                .iter()
+                .position(|r| r.matches(&self.unexpected[i]))
+            else {
+                i += 1;
+                continue;
+            };
+            let msg = self.unexpected.remove(i).unwrap();
+            let recv = self.recvs.remove(j).unwrap();
+            self.complete_recv(recv, msg);
+        }
+    }
+
+    fn complete_recv(&mut self, recv: PostedRecv, msg: Message) {
+        let len = msg.data.len().min(recv.len);
+        // SAFETY: the receive buffer is valid until its completion, which is written below.
+        unsafe { ptr::copy_nonoverlapping(msg.data.as_ptr(), recv.buf, len) };
+        let flags = match msg.tag {
+            Some(_) => ffi::FI_RECV | ffi::FI_TAGGED,
+            None => ffi::FI_RECV | ffi::FI_MSG,
+        } as u64;
+        if msg.data.len() > recv.len {
+            self.completions.push_back(Err(CqErrEntry {
+                len,
+                data: msg.cq_data.unwrap_or(0),
+                tag: msg.tag.unwrap_or(0),
+                olen: msg.data.len() - recv.len,
+                src_addr: Addr::from_raw(msg.src as u64),
+                ..error_entry(
+                    recv.context,
+                    flags,
+                    ffi::FI_ETRUNC as i32,
+                    format!(
+                        "{} bytes message truncated to {} bytes",
+                        msg.data.len(),
+                        recv.len
+                    ),
+                )
+            }));
+            return;
+        }
+        let entry = completion(recv.context, flags, len, recv.buf, &msg);
+        let src = Addr::from_raw(msg.src as u64);
+        self.completions.push_back(Ok((entry, src)));
+    }
+}
+
+/// An endpoint of a [`MockFabric`], with its own completion queue for both directions.
+///
+/// The endpoint is closed once the last clone is dropped: its pending operations are discarded,
+/// and those sent to it complete in error with `FI_EHOSTUNREACH`.
+#[derive(Clone)]
+pub struct MockEndpoint {
+    inner: Arc<EpInner>,
+}
+
+struct EpInner {
+    fabric: MockFabric,
+    index: usize,
+}
+
+impl Drop for EpInner {
+    fn drop(&mut self) {
+        self.fabric.lock().close(self.index);
+    }
+}
+
+impl MockEndpoint {
+    /// The completion queue of the endpoint.
+    pub fn cq(&self) -> MockCq {
+        MockCq {
+            fabric: self.inner.fabric.clone(),
+            index: self.inner.index,
+        }
+    }
+
+    /// Cancel the receives posted with `context`, which complete in error with `FI_ECANCELED`.
+    /// Operations already sent to a peer are delivered all the same.
+    pub fn cancel(&self, context: usize) -> Result<()> {
+        let mut net = self.inner.fabric.lock();
+        let ep = &mut net.endpoints[self.inner.index];
+        let (cancelled, recvs) = mem::take(&mut ep.recvs)
+            .into_iter()
+            .partition::<Vec<_>, _>(|recv| recv.context == context);
+        ep.recvs = recvs.into();
+        for recv in cancelled {
+            let flags = match recv.tag {
+                Some(_) => ffi::FI_RECV | ffi::FI_TAGGED,
+                None => ffi::FI_RECV | ffi::FI_MSG,
+            } as u64;
+            let entry = error_entry(context, flags, ffi::FI_ECANCELED as i32, "cancelled");
+            ep.completions.push_back(Err(entry));
+        }
+        Ok(())
+    }
+
+    fn post(&self, name: &'static str, dest: Addr, op: Op, context: Option<usize>) -> Result<()> {
+        trace::mock_op!(name, self.inner.index, size = op.len(), tag = ?op.tag());
+        let mut net = self.inner.fabric.lock();
+        if let Some(code) = Network::take_failure(&mut net.post_failures) {
+            return Err(Error::fabric(name, code as i64));
+        }
+        let dest = net.peer(name, dest)?;
+        let due = match &mut net.scheduler {
+            Some(scheduler) => Due::Tick(scheduler.due()),
+            None => Due::Time(Instant::now() + net.delay),
+        };
+        net.in_flight.push_back(InFlight {
+            src: self.inner.index,
+            dest,
+            op,
+            context,
+            due,
+        });
+        Ok(())
+    }
+
+    fn post_recv(
+        &self,
+        name: &'static str,
+        buf: &mut [u8],
+        src: Addr,
+        tag: Option<(u64, u64)>,
+        context: usize,
+    ) -> Result<()> {
+        trace::mock_op!(name, self.inner.index, size = buf.len(), tag = ?tag.map(|(tag, _)| tag));
+        let mut net = self.inner.fabric.lock();
+        if let Some(code) = Network::take_failure(&mut net.post_failures) {
+            return Err(Error::fabric(name, code as i64));
+        }
+        if src != Addr::UNSPEC {
+            net.peer(name, src)?;
+        }
+        net.endpoints[self.inner.index].recvs.push_back(PostedRecv {
+            buf: buf.as_mut_ptr(),
+            len: buf.len(),
+            src,
+            tag,
+            context,
+        });
+        Ok(())
+    }
+
+    fn message(&self, buf: &[u8], cq_data: Option<u64>, tag: Option<u64>) -> Op {
+        Op::Msg(Message {
+            src: self.inner.index,
+            data: buf.to_vec(),
+            cq_data,
+            tag,
+        })
+    }
+}
+
+// The data of sends and writes is copied when they are posted, and that of receives and reads
+// when they are delivered.
+impl Transport for MockEndpoint {
+    type Mr = MockMr;
+
+    fn name(&self) -> Result<EndpointAddress> {
+        Ok(EndpointAddress::from_bytes(
+            (self.inner.index as u64).to_le_bytes(),
+        ))
+    }
+
+    unsafe fn recv(
+        &self,
+        buf: &mut [u8],
+        _mr: Option<&MockMr>,
+        src: Addr,
+        context: usize,
+    ) -> Result<()> {
+        self.post_recv("fi_recv", buf, src, None, context)
+    }
+
+    unsafe fn send(
+        &self,
+        buf: &[u8],
+        _mr: Option<&MockMr>,
+        dest: Addr,
+        context: usize,
+    ) -> Result<()> {
+        let op = self.message(buf, None, None);
+        self.post("fi_send", dest, op, Some(context))
+    }
+
+    unsafe fn senddata(
+        &self,
+        buf: &[u8],
+        _mr: Option<&MockMr>,
+        data: u64,
+        dest: Addr,
+        context: usize,
+    ) -> Result<()> {
+        let op = self.message(buf, Some(data), None);
+        self.post("fi_senddata", dest, op, Some(context))
+    }
+
+    fn inject(&self, buf: &[u8], dest: Addr) -> Result<()> {
+        let op = self.message(buf, None, None);
+        self.post("fi_inject", dest, op, None)
+    }
+
+    unsafe fn trecv(
+        &self,
+        buf: &mut [u8],
+        _mr: Option<&MockMr>,
+        src: Addr,
+        tag: u64,
+        ignore: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.post_recv("fi_trecv", buf, src, Some((tag, ignore)), context)
+    }
+
+    unsafe fn tsend(
+        &self,
+        buf: &[u8],
+        _mr: Option<&MockMr>,
+        dest: Addr,
+        tag: u64,
+        context: usize,
+    ) -> Result<()> {
+        let op = self.message(buf, None, Some(tag));
+        self.post("fi_tsend", dest, op, Some(context))
+    }
+
+    unsafe fn tsenddata(
+        &self,
+        buf: &[u8],
+        _mr: Option<&MockMr>,
+        data: u64,
+        dest: Addr,
+        tag: u64,
+        context: usize,
+    ) -> Result<()> {
+        let op = self.message(buf, Some(data), Some(tag));
+        self.post("fi_tsenddata", dest, op, Some(context))
+    }
+
+    fn tinject(&self, buf: &[u8], dest: Addr, tag: u64) -> Result<()> {
+        let op = self.message(buf, None, Some(tag));
+        self.post("fi_tinject", dest, op, None)
+    }
+
+    unsafe fn read(
+        &self,
+        buf: &mut [u8],
+        _mr: Option<&MockMr>,
+        src: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()> {
+        let op = Op::Read {
+            buf: buf.as_mut_ptr(),
+            len: buf.len(),
+            addr,
+            key,
+        };
+        self.post("fi_read", src, op, Some(context))
+    }
+
+    unsafe fn write(
+        &self,
+        buf: &[u8],
+        _mr: Option<&MockMr>,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()> {
+        let op = Op::Write {
+            data: buf.to_vec(),
+            addr,
+            key,
+            cq_data: None,
+        };
+        self.post("fi_write", dest, op, Some(context))
+    }
+
+    unsafe fn writedata(
+        &self,
+        buf: &[u8],
+        _mr: Option<&MockMr>,
+        data: u64,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()> {
+        let op = Op::Write {
+            data: buf.to_vec(),
+            addr,
+            key,
+            cq_data: Some(data),
+        };
+        self.post("fi_writedata", dest, op, Some(context))
+    }
+}
+
+impl AtomicTransport for MockEndpoint {
+    unsafe fn compare_swap(
+        &self,
+        value: u64,
+        compare: u64,
+        result: &mut u64,
+        _mr: Option<&MockMr>,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()> {
+        let op = Op::CompareSwap {
+            value,
+            compare,
+            result,
+            addr,
+            key,
+        };
+        self.post("fi_compare_atomic", dest, op, Some(context))
+    }
+}
+
+/// The completion queue of a [`MockEndpoint`].
+#[derive(Clone)]
+pub struct MockCq {
+    fabric: MockFabric,
+    index: usize,
+}
+
+impl MockCq {
+    fn read_entries(&self, out: &mut [Completion], mut src: Option<&mut [Addr]>) -> Result<usize> {
+        let mut net = self.fabric.lock();
+        net.progress();
+        let ep = &mut net.endpoints[self.index];
+        let count = src
+            .as_deref()
+            .map_or(out.len(), |src| out.len().min(src.len()));
+        // Successful completions are read up to the first error, which is then reported.
+        let mut n = 0;
+        while n < count
+            && let Some(Ok((completion, addr))) = ep.completions.front()
+        {
+            out[n] = *completion;
+            if let Some(src) = src.as_deref_mut() {
+                src[n] = *addr;
+            }
+            ep.completions.pop_front();
+            n += 1;
+        }
+        match (n, ep.completions.front()) {
+            (0, Some(Err(_))) => Err(Error::fabric("fi_cq_read", ffi::FI_EAVAIL as i64)),
+            (0, _) => Err(Error::fabric("fi_cq_read", ffi::FI_EAGAIN as i64)),
+            _ => Ok(n),
+        }
+    }
+}
+
+impl Cq for MockCq {
+    fn read(&self, out: &mut [Completion]) -> Result<usize> {
+        self.read_entries(out, None)
+    }
+
+    /// Sources are reported for received messages and remote CQ data only.
+    fn read_from(&self, out: &mut [Completion], src: &mut [Addr]) -> Result<usize> {
+        self.read_entries(out, Some(src))
+    }
+
+    fn read_err(&self) -> Result<Option<CqErrEntry>> {
+        let completions = &mut self.fabric.lock().endpoints[self.index].completions;
+        match completions.front() {
+            Some(Err(_)) => Ok(completions.pop_front().and_then(|entry| entry.err())),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// An address vector of a [`MockFabric`].
+#[derive(Clone)]
+pub struct MockAv {
+    fabric: MockFabric,
+}
+
+impl Av for MockAv {
+    fn insert(&self, addr: &EndpointAddress) -> Result<Addr> {
+        let _span = trace::span!("fi_av_insert", provider = "mock");
+        let index = <[u8; 8]>::try_from(addr.as_bytes())
+            .map(u64::from_le_bytes)
+            .map_err(|_| Error::fabric("fi_av_insert", ffi::FI_EINVAL as i64))?;
+        let addr = Addr::from_raw(index);
+        self.fabric.lock().peer("fi_av_insert", addr)?;
+        Ok(addr)
+    }
+
+    /// The handles of the mock name their endpoint, which still reach it once removed.
+    fn remove(&self, addr: Addr) -> Result<()> {
+        self.fabric.lock().peer("fi_av_remove", addr).map(drop)
+    }
+}
+
+/// A memory region of a [`MockFabric`], deregistered once the last clone is dropped.
+#[derive(Clone)]
+pub struct MockMr {
+    inner: Arc<MrInner>,
+}
+
+struct MrInner {
+    fabric: MockFabric,
+    key: u64,
+    addr: *mut u8,
+    len: usize,
+}
+
+// SAFETY: `addr` is only reported back to the application, never dereferenced through here.
+unsafe impl Send for MrInner {}
+unsafe impl Sync for MrInner {}
+
+impl Drop for MrInner {
+    fn drop(&mut self) {
+        self.fabric.lock().regions.remove(&self.key);
+    }
+}
+
+impl Mr for MockMr {
+    fn key(&self) -> u64 {
+        self.inner.key
+    }
+
+    fn addr(&self) -> *mut u8 {
+        self.inner.addr
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len
+    }
+}
+
+type Poller = Box<dyn FnMut() -> Result<()> + Send>;
+
+/// A [`ProgressDriver`] on a virtual clock, for unit tests of timeouts, retries and heartbeats
+/// without real time: the clock only moves when the test [advances](Self::advance) it, or
+/// when the code under test sleeps, which returns at once, [recorded](Self::slept).
+///
+/// Each progress polls the pollers registered with [`poller()`](Self::poller), such as the
+/// progress of a [`MockFabric`], in the order they were registered, or in that of
+/// [`set_order()`](Self::set_order). Clones share the clock and the pollers, so that the test
+/// keeps one while the code under test owns another.
+///
+/// ```
+/// use libfabric::mock::MockProgress;
+/// use libfabric::{Error, ProgressDriver, RetryPolicy, post_with_retry_on};
+/// use std::time::Duration;
+///
+/// let progress = MockProgress::new();
+/// let policy = RetryPolicy::new().backoff(Duration::from_secs(1), Duration::from_secs(60));
+/// let start = progress.now();
+/// let mut attempts = 0;
+/// post_with_retry_on(&policy, &progress, || match attempts {
+///     3 => Ok(()),
+///     _ => {
+///         attempts += 1;
+///         let code = libfabric::sys::bindgen::FI_EAGAIN as i32;
+///         Err(Error::Fabric { op: "fi_send", code })
+///     }
+/// })?;
+/// // Seven seconds of backoff, slept in no time.
+/// assert_eq!(progress.now() - start, Duration::from_secs(7));
+/// assert_eq!(progress.passes(), 3);
+/// # Ok::<(), libfabric::Error>(())
+/// ```
+#[derive(Clone)]
+pub struct MockProgress {
+    clock: Arc<Mutex<Clock>>,
+    // Apart from the clock, which pollers may read.
+    pollers: Arc<Mutex<Vec<Poller>>>,
+}
+
+struct Clock {
+    start: Instant,
+    elapsed: Duration,
+    // The pollers of each pass, by index, all of them in order if unset.
+    order: Option<Vec<usize>>,
+    passes: u64,
+    slept: Vec<Duration>,
+}
+
+impl Default for MockProgress {
+    fn default() -> Self {
+        MockProgress {
+            clock: Arc::new(Mutex::new(Clock {
+                start: Instant::now(),
+                elapsed: Duration::ZERO,
+                order: None,
+                passes: 0,
+                slept: Vec::new(),
+            })),
+            pollers: Arc::default(),
+        }
+    }
+}
+
+impl MockProgress {
+    /// A clock stopped at the current time, without pollers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn clock(&self) -> MutexGuard<'_, Clock> {
+        self.clock.lock().unwrap()
+    }
+
+    /// Poll `poller` on each progress, after those registered before, returning its index.
+    /// Pollers must not register others, nor progress the driver themselves.
+    pub fn poller(&self, poller: impl FnMut() -> Result<()> + Send + 'static) -> usize {
+        let mut pollers = self.pollers.lock().unwrap();
+        pollers.push(Box::new(poller));
+        pollers.len() - 1
+    }
+
+    /// Poll the pollers of `order`, by index, on each progress, rather than all of them in the
+    /// order they were registered: left out, a poller starves what it progresses, and listed
+    /// twice, it is polled twice per pass.
+    pub fn set_order(&self, order: &[usize]) {
+        self.clock().order = Some(order.to_vec());
+    }
+
+    /// Move the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        self.clock().elapsed += duration;
+    }
+
+    /// Time passed on the clock since it was created.
+    pub fn elapsed(&self) -> Duration {
+        self.clock().elapsed
+    }
+
+    /// Progress passes so far.
+    pub fn passes(&self) -> u64 {
+        self.clock().passes
+    }
+
+    /// What the code under test slept, in order.
+    pub fn slept(&self) -> Vec<Duration> {
+        self.clock().slept.clone()
+    }
+}
+
+impl ProgressDriver for MockProgress {
+    fn now(&self) -> Instant {
+        let clock = self.clock();
+        clock.start + clock.elapsed
+    }
+
+    /// Fails with the error of a poller, or if the order names one which does not exist,
+    /// skipping the pollers after it.
+    fn progress(&self) -> Result<()> {
+        let order = {
+            let mut clock = self.clock();
+            clock.passes += 1;
+            clock.order.clone()
+        };
+        let mut pollers = self.pollers.lock().unwrap();
+        let order = order.unwrap_or_else(|| (0..pollers.len()).collect());
+        for index in order {
+            let poller = pollers
+                .get_mut(index)
+                .ok_or_else(|| Error::invalid(format!("no poller {index}")))?;
+            poller()?;
+        }
+        Ok(())
+    }
+
+    fn sleep(&self, duration: Duration) {
+        let mut clock = self.clock();
+        clock.elapsed += duration;
+        clock.slept.push(duration);
+    }
+}
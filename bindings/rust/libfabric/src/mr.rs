@@ -0,0 +1,301 @@
+use crate::cntr::Counter;
+use crate::domain::Domain;
+use crate::ep::{Endpoint, EndpointState};
+use crate::error::{Error, Result, check};
+use crate::fid::{AsRawFid, OwnedFid};
+use crate::flags::{Access, MrMode};
+use crate::resources::{Claim, Resource};
+use crate::threading::{ThreadSafe, ThreadingModel};
+use crate::trace;
+use ofi_libfabric_sys::bindgen as ffi;
+use std::os::raw::c_void;
+use std::ptr;
+use std::sync::Arc;
+
+/// Attributes of a memory registration, for
+/// [`Domain::register_with()`](crate::Domain::register_with).
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct MrAttr {
+    access: Access,
+    requested_key: Option<u64>,
+}
+
+impl MrAttr {
+    /// A registration with the access rights of `access`, and a key chosen by the provider.
+    pub fn new(access: Access) -> Self {
+        MrAttr {
+            access,
+            requested_key: None,
+        }
+    }
+
+    /// Request `key` as the remote key of the region, rather than one chosen by the provider,
+    /// ex: derived from the id of the object it holds, so that every node registers the same
+    /// object under the same key and peers need not exchange keys.
+    ///
+    /// Registration fails with an invalid argument error if the domain requires
+    /// [`MrMode::PROV_KEY`], or if `key` does not fit in its `mr_key_size`, and with
+    /// `FI_ENOKEY` if an open region of the domain was registered with the same key.
+    pub fn requested_key(mut self, key: u64) -> Self {
+        self.requested_key = Some(key);
+        self
+    }
+}
+
+/// A registered memory region (`fid_mr`).
+#[derive(Clone)]
+pub struct MemoryRegion<M: ThreadingModel = ThreadSafe> {
+    inner: Arc<MrInner<M>>,
+}
+
+struct MrInner<M: ThreadingModel> {
+    fid: OwnedFid<ffi::fid_mr>,
+    addr: *mut u8,
+    len: usize,
+    domain: Domain<M>,
+    // Declared after the region, so that the key is released once it is closed.
+    #[allow(dead_code)]
+    key: Option<KeyClaim<M>>,
+    #[allow(dead_code)]
+    claim: Claim,
+}
+
+// A key requested by a region, which no other region of the domain may request while it is
+// held.
+struct KeyClaim<M: ThreadingModel> {
+    domain: Domain<M>,
+    key: u64,
+}
+
+impl<M: ThreadingModel> KeyClaim<M> {
+    fn new(domain: &Domain<M>, key: u64) -> Result<Self> {
+        if domain.info().mr_mode().contains(MrMode::PROV_KEY) {
+            return Err(Error::invalid(
+                "requested key on a domain with FI_MR_PROV_KEY",
+            ));
+        }
+        let size = domain.info().domain_attr().mr_key_size;
+        if size < 8 && key >> (8 * size) != 0 {
+            return Err(Error::invalid(format!(
+                "key {key:#x} over the {size} bytes keys of the domain"
+            )));
+        }
+        if !domain.keys().lock().unwrap().insert(key) {
+            return Err(Error::fabric("fi_mr_reg", ffi::FI_ENOKEY as i64));
+        }
+        Ok(KeyClaim {
+            domain: domain.clone(),
+            key,
+        })
+    }
+}
+
+impl<M: ThreadingModel> Drop for KeyClaim<M> {
+    fn drop(&mut self) {
+        self.domain.keys().lock().unwrap().remove(&self.key);
+    }
+}
+
+// SAFETY: `addr` is only reported back to the application, never dereferenced by the crate. The
+// region is otherwise as thread safe as its domain.
+unsafe impl<M: ThreadingModel> Send for MrInner<M> where Domain<M>: Send {}
+unsafe impl<M: ThreadingModel> Sync for MrInner<M> where Domain<M>: Sync {}
+
+#[cfg(feature = "metrics")]
+impl<M: ThreadingModel> Drop for MrInner<M> {
+    fn drop(&mut self) {
+        crate::metrics::mr_closed(self.len);
+    }
+}
+
+impl<M: ThreadingModel> MemoryRegion<M> {
+    // The handles to the region, this one included.
+    pub(crate) fn handles(&self) -> usize {
+        Arc::strong_count(&self.inner)
+    }
+
+    // Close the region, via `fi_close()`, failing unless this is the last handle to it.
+    pub(crate) fn close(self) -> Result<()> {
+        crate::fid::close_last(self.inner, |inner| &mut inner.fid)
+    }
+
+    pub(crate) unsafe fn register(
+        domain: &Domain<M>,
+        buf: *mut u8,
+        len: usize,
+        attr: &MrAttr,
+    ) -> Result<Self> {
+        let _span = trace::span!(
+            "fi_mr_reg",
+            provider = domain.info().provider_name(),
+            size = len
+        );
+        let key = match attr.requested_key {
+            Some(key) => Some(KeyClaim::new(domain, key)?),
+            None => None,
+        };
+        let claim = domain.claim(&[Resource::Mr])?;
+        let fid = OwnedFid::open("fi_mr_reg", |mr| unsafe {
+            ffi::fi_mr_reg(
+                domain.as_raw(),
+                buf as *const c_void,
+                len,
+                attr.access.bits(),
+                0,
+                attr.requested_key.unwrap_or(0),
+                0,
+                mr,
+                ptr::null_mut(),
+            )
+        })?;
+        let mut region = Self::registered(domain, fid, buf, len, claim);
+        Arc::get_mut(&mut region.inner).unwrap().key = key;
+        Ok(region)
+    }
+
+    // Register the device memory at `buf`, of the device `device` of `iface`, via
+    // `fi_mr_regattr()`.
+    #[cfg(any(feature = "cuda", feature = "ze"))]
+    pub(crate) unsafe fn register_device(
+        domain: &Domain<M>,
+        buf: *mut u8,
+        len: usize,
+        access: Access,
+        (iface, device): (ffi::fi_hmem_iface, ffi::fi_mr_attr__bindgen_ty_2),
+    ) -> Result<Self> {
+        let _span = trace::span!(
+            "fi_mr_regattr",
+            provider = domain.info().provider_name(),
+            size = len
+        );
+        let iov = ffi::iovec {
+            iov_base: buf.cast(),
+            iov_len: len,
+        };
+        let attr = ffi::fi_mr_attr {
+            __bindgen_anon_1: ffi::fi_mr_attr__bindgen_ty_1 { mr_iov: &iov },
+            iov_count: 1,
+            access: access.bits(),
+            iface,
+            device,
+            ..Default::default()
+        };
+        let claim = domain.claim(&[Resource::Mr])?;
+        let fid = OwnedFid::open("fi_mr_regattr", |mr| unsafe {
+            ffi::fi_mr_regattr(domain.as_raw(), &attr, 0, mr)
+        })?;
+        Ok(Self::registered(domain, fid, buf, len, claim))
+    }
+
+    fn registered(
+        domain: &Domain<M>,
+        fid: OwnedFid<ffi::fid_mr>,
+        buf: *mut u8,
+        len: usize,
+        claim: Claim,
+    ) -> Self {
+        #[cfg(feature = "metrics")]
+        crate::metrics::mr_registered(len);
+        MemoryRegion {
+            inner: Arc::new(MrInner {
+                fid,
+                addr: buf,
+                len,
+                domain: domain.clone(),
+                key: None,
+                claim,
+            }),
+        }
+    }
+
+    /// Take ownership of a region of `domain` registered elsewhere, ex: by C code, over the
+    /// `len` bytes at `buf`. Fails with an invalid argument error if `mr` is null.
+    ///
+    /// # Safety
+    ///
+    /// `mr` must be an open region of `domain`, registering the `len` bytes at `buf`, which is
+    /// closed by the returned value only: once all handles to it are dropped, or released by
+    /// [`into_raw()`](Self::into_raw).
+    pub unsafe fn from_raw(
+        domain: &Domain<M>,
+        mr: *mut ffi::fid_mr,
+        buf: *mut u8,
+        len: usize,
+    ) -> Result<Self> {
+        let fid = unsafe { OwnedFid::from_raw(mr) }?;
+        let claim = domain.adopt(&[Resource::Mr]);
+        Ok(Self::registered(domain, fid, buf, len, claim))
+    }
+
+    /// Hand the region over, ex: to C code, whose `fi_close()` then closes it. Fails unless
+    /// this is the last handle to it. The domain is released: it must be kept open until the
+    /// region is closed.
+    pub fn into_raw(self) -> Result<*mut ffi::fid_mr> {
+        crate::fid::release_last(self.inner, |inner| &mut inner.fid)
+    }
+
+    pub fn domain(&self) -> &Domain<M> {
+        &self.inner.domain
+    }
+
+    /// Start of the registered memory.
+    pub fn addr(&self) -> *mut u8 {
+        self.inner.addr
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.len == 0
+    }
+
+    /// The remote key peers use to access the region, via `fi_mr_key()`.
+    pub fn key(&self) -> u64 {
+        unsafe { ffi::fi_mr_key(self.as_raw()) }
+    }
+
+    /// The local descriptor passed along with buffers in data transfers, via `fi_mr_desc()`.
+    pub fn desc(&self) -> *mut c_void {
+        unsafe { ffi::fi_mr_desc(self.as_raw()) }
+    }
+
+    /// Bind the region to an endpoint, required by providers with `FI_MR_ENDPOINT`.
+    pub fn bind_endpoint<S: EndpointState>(&self, ep: &Endpoint<M, S>) -> Result<()> {
+        check("fi_mr_bind", unsafe {
+            ffi::fi_mr_bind(self.as_raw(), ep.as_raw_fid(), 0)
+        })
+    }
+
+    /// Bind the region to a counter, counting the remote accesses of `access`, its
+    /// [`Access::REMOTE_READ`] and [`Access::REMOTE_WRITE`] bits, into the region. Requires
+    /// [`Caps::RMA_EVENT`](crate::Caps::RMA_EVENT).
+    pub fn bind_counter(&self, cntr: &Counter<M>, access: Access) -> Result<()> {
+        let flags = access & (Access::REMOTE_READ | Access::REMOTE_WRITE);
+        check("fi_mr_bind", unsafe {
+            ffi::fi_mr_bind(self.as_raw(), cntr.as_raw_fid(), flags.bits())
+        })
+    }
+
+    /// Enable the region after binding, required by providers with `FI_MR_ENDPOINT`.
+    pub fn enable(&self) -> Result<()> {
+        check("fi_mr_enable", unsafe { ffi::fi_mr_enable(self.as_raw()) })
+    }
+
+    pub fn as_raw(&self) -> *mut ffi::fid_mr {
+        self.inner.fid.as_ptr()
+    }
+}
+
+impl<M: ThreadingModel> AsRawFid for MemoryRegion<M> {
+    fn as_raw_fid(&self) -> *mut ffi::fid {
+        self.inner.fid.as_fid()
+    }
+}
+
+// The descriptor of an optional region, as passed to data transfer calls.
+pub(crate) fn desc<M: ThreadingModel>(mr: Option<&MemoryRegion<M>>) -> *mut c_void {
+    mr.map_or(ptr::null_mut(), MemoryRegion::desc)
+}
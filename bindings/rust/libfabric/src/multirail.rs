@@ -0,0 +1,320 @@
+use crate::av::{Addr, AddressVector, AvAttr, EndpointAddress};
+use crate::cq::{Completion, CompletionQueue, CqAttr};
+use crate::domain::Domain;
+use crate::ep::Endpoint;
+use crate::error::{Error, Result};
+use crate::fabric::Fabric;
+use crate::flags::BindFlags;
+use crate::info::{InfoEntry, Nic};
+
+/// Messages from this many bytes up are striped over every rail, by default.
+pub const DEFAULT_STRIPE_THRESHOLD: usize = 64 * 1024;
+
+/// An endpoint over several NICs, or ports of a NIC, each a rail with its own domain and
+/// endpoint, to aggregate their bandwidth.
+///
+/// Messages are tagged with a sequence number per peer and direction. Small ones go over the
+/// rails in turn, and those from the [stripe threshold](Self::set_stripe_threshold) up are
+/// split into one chunk per rail, sent at once. The receiver splits its buffer the same way,
+/// so a striped message must be received into a buffer of its exact length, and a smaller one
+/// into a buffer below the threshold too. Both sides must use as many rails, and the same
+/// threshold.
+///
+/// Operations block until every chunk completed, so their buffers are plain slices, each chunk
+/// copied through a buffer owned by its rail. Should the completion queue of a rail fail to be
+/// read, the chunks left on every rail are canceled and waited for before failing, and if a
+/// queue still fails to be read, each rail keeps the buffers of the chunks left on it until its
+/// endpoint is closed. No memory descriptors are passed, which rules out providers requiring
+/// [`MrMode::LOCAL`](crate::MrMode::LOCAL). With several peers, receives only tell them apart
+/// if the entries have [`Caps::DIRECTED_RECV`](crate::Caps::DIRECTED_RECV).
+pub struct MultiRailEndpoint {
+    rails: Vec<Rail>,
+    peers: Vec<Peer>,
+    stripe_threshold: usize,
+    // Completions read while waiting for others, by rail and context.
+    done: Vec<(usize, usize, Result<usize>)>,
+    // The context of the next operation. Each operation takes its own, so that the completions
+    // of the chunks orphaned after a failure are not mistaken for those of later ones.
+    next_context: usize,
+}
+
+struct Rail {
+    ep: Endpoint,
+    cq: CompletionQueue,
+    av: AddressVector,
+    nic: Option<Nic>,
+}
+
+struct Peer {
+    // The address of the peer on each rail.
+    addrs: Vec<Addr>,
+    next_send: u64,
+    next_recv: u64,
+}
+
+impl MultiRailEndpoint {
+    /// Open a rail for each distinct NIC among `entries`, up to `max_rails`, in the order of
+    /// the entries. Entries not reporting their NIC are told apart by their domain name.
+    ///
+    /// The entries are those of a single provider, resolved with tagged, RDM endpoints.
+    pub fn open(entries: &[InfoEntry], max_rails: usize) -> Result<Self> {
+        let mut seen = Vec::new();
+        let mut rails = Vec::new();
+        for entry in entries {
+            if rails.len() == max_rails {
+                break;
+            }
+            let nic = entry.nic();
+            let device = match &nic {
+                Some(nic) if !nic.name.is_empty() => nic.name.clone(),
+                _ => entry.domain_name().to_owned(),
+            };
+            if seen.contains(&device) {
+                continue;
+            }
+            seen.push(device);
+            rails.push(Rail::open(entry, nic)?);
+        }
+        if rails.is_empty() {
+            return Err(Error::invalid("no entry to open a rail from"));
+        }
+        Ok(MultiRailEndpoint {
+            rails,
+            peers: Vec::new(),
+            stripe_threshold: DEFAULT_STRIPE_THRESHOLD,
+            done: Vec::new(),
+            next_context: 1,
+        })
+    }
+
+    pub fn rails(&self) -> usize {
+        self.rails.len()
+    }
+
+    /// The NIC of each rail, for providers which report it.
+    pub fn nics(&self) -> Vec<Option<&Nic>> {
+        self.rails.iter().map(|rail| rail.nic.as_ref()).collect()
+    }
+
+    /// The names of the endpoints of the rails, in order, which peers pass to
+    /// [`insert()`](Self::insert).
+    pub fn names(&self) -> Result<Vec<EndpointAddress>> {
+        self.rails.iter().map(|rail| rail.ep.name()).collect()
+    }
+
+    /// Add a peer from the names of its rails, returning the index it is addressed by.
+    pub fn insert(&mut self, names: &[EndpointAddress]) -> Result<usize> {
+        if names.len() != self.rails.len() {
+            return Err(Error::invalid(format!(
+                "{} names for {} rails",
+                names.len(),
+                self.rails.len()
+            )));
+        }
+        let addrs = self
+            .rails
+            .iter()
+            .zip(names)
+            .map(|(rail, name)| rail.av.insert(name))
+            .collect::<Result<_>>()?;
+        self.peers.push(Peer {
+            addrs,
+            next_send: 0,
+            next_recv: 0,
+        });
+        Ok(self.peers.len() - 1)
+    }
+
+    pub fn stripe_threshold(&self) -> usize {
+        self.stripe_threshold
+    }
+
+    pub fn set_stripe_threshold(&mut self, threshold: usize) {
+        self.stripe_threshold = threshold;
+    }
+
+    /// Send `buf` to `peer`.
+    pub fn send(&mut self, buf: &[u8], peer: usize) -> Result<()> {
+        let (seq, addrs) = self.sequence(peer, true)?;
+        let chunks = self.split(buf.len(), seq);
+        let chunks = chunks
+            .into_iter()
+            .map(|(rail, range)| (rail, buf[range].into()));
+        // SAFETY: each chunk is owned until it completed, see `wait()`.
+        self.run(chunks, |ep, chunk, rail, context| unsafe {
+            ep.tsend(chunk, None, addrs[rail], seq, context)
+        })
+        .map(|_| ())
+    }
+
+    /// Receive the next message from `peer` into `buf`, returning its length.
+    pub fn recv(&mut self, buf: &mut [u8], peer: usize) -> Result<usize> {
+        let (seq, addrs) = self.sequence(peer, false)?;
+        let ranges = self.split(buf.len(), seq);
+        let chunks = ranges
+            .iter()
+            .map(|(rail, range)| (*rail, vec![0; range.len()].into_boxed_slice()));
+        // SAFETY: each chunk is owned until it completed, see `wait()`.
+        let received = self.run(chunks, |ep, chunk, rail, context| unsafe {
+            ep.trecv(chunk, None, addrs[rail], seq, 0, context)
+        })?;
+        let mut total = 0;
+        for ((_, range), (len, chunk)) in ranges.into_iter().zip(received) {
+            buf[range.start..range.start + len].copy_from_slice(&chunk[..len]);
+            total += len;
+        }
+        Ok(total)
+    }
+
+    // The sequence number of the next message to or from `peer`, and the addresses of the
+    // peer.
+    fn sequence(&mut self, peer: usize, send: bool) -> Result<(u64, Vec<Addr>)> {
+        let peer = self
+            .peers
+            .get_mut(peer)
+            .ok_or_else(|| Error::invalid(format!("no peer {peer}")))?;
+        let next = match send {
+            true => &mut peer.next_send,
+            false => &mut peer.next_recv,
+        };
+        let seq = *next;
+        *next += 1;
+        Ok((seq, peer.addrs.clone()))
+    }
+
+    // The rail and range of each chunk of a message of `len` bytes.
+    fn split(&self, len: usize, seq: u64) -> Vec<(usize, std::ops::Range<usize>)> {
+        let rails = self.rails.len();
+        if len < self.stripe_threshold || rails == 1 {
+            return vec![(seq as usize % rails, 0..len)];
+        }
+        let chunk = len.div_ceil(rails);
+        (0..rails)
+            .map(|rail| (rail, (rail * chunk).min(len)..((rail + 1) * chunk).min(len)))
+            .collect()
+    }
+
+    // Post each of `chunks` on its rail, with a new context, and wait until those posted
+    // completed, returning the length of each along with its buffer, or the first error.
+    fn run(
+        &mut self,
+        chunks: impl IntoIterator<Item = (usize, Box<[u8]>)>,
+        mut post: impl FnMut(&Endpoint, &mut [u8], usize, usize) -> Result<()>,
+    ) -> Result<Vec<(usize, Box<[u8]>)>> {
+        let context = self.next_context;
+        self.next_context += 1;
+        let mut posted = Vec::new();
+        let mut outcome = Ok(());
+        for (rail, mut chunk) in chunks {
+            outcome = self.post(rail, |ep| post(ep, &mut chunk, rail, context));
+            if outcome.is_err() {
+                break;
+            }
+            posted.push((rail, chunk));
+        }
+        let done = self.wait(posted, context);
+        outcome.and(done)
+    }
+
+    // Post an operation on `rail`, progressing the rails while it is out of resources.
+    fn post(&mut self, rail: usize, mut post: impl FnMut(&Endpoint) -> Result<()>) -> Result<()> {
+        loop {
+            match post(&self.rails[rail].ep) {
+                Err(err) if err.is_again() => self.progress()?,
+                other => return other,
+            }
+        }
+    }
+
+    // Wait until the chunk of `context` posted on each rail of `posted` completed, returning
+    // the length of each along with its buffer, or the first error. Every chunk is waited for
+    // even after one failed, or a queue failed to be read, the chunks left being canceled
+    // then. Should a queue fail to be read again, the chunks left are orphaned on their rail.
+    fn wait(
+        &mut self,
+        mut posted: Vec<(usize, Box<[u8]>)>,
+        context: usize,
+    ) -> Result<Vec<(usize, Box<[u8]>)>> {
+        let rails: Vec<_> = posted.iter().map(|(rail, _)| *rail).collect();
+        let mut failed = Ok(());
+        while let Some(pending) = self.pending(&rails, context) {
+            let Err(err) = self.progress() else {
+                continue;
+            };
+            if failed.is_err() {
+                for (rail, chunk) in std::mem::take(&mut posted) {
+                    if pending.contains(&rail) {
+                        self.rails[rail].ep.orphan(chunk);
+                    }
+                }
+                break;
+            }
+            for &rail in &pending {
+                let _ = self.rails[rail].ep.cancel(context);
+            }
+            failed = Err(err);
+        }
+        // The completions left are those of `context`, or of chunks orphaned before.
+        let mut done = std::mem::take(&mut self.done);
+        failed?;
+        posted
+            .into_iter()
+            .map(|(rail, chunk)| {
+                let at = done.iter().position(|&(r, c, _)| (r, c) == (rail, context));
+                Ok((done.swap_remove(at.unwrap()).2?, chunk))
+            })
+            .collect()
+    }
+
+    // The rails among `rails` whose chunk of `context` has not completed yet, if any.
+    fn pending(&self, rails: &[usize], context: usize) -> Option<Vec<usize>> {
+        let pending: Vec<_> = rails
+            .iter()
+            .copied()
+            .filter(|&rail| self.done.iter().all(|&(r, c, _)| (r, c) != (rail, context)))
+            .collect();
+        (!pending.is_empty()).then_some(pending)
+    }
+
+    // Read the completions available on every rail, successful or not, failing with the error
+    // of the first queue which failed to be read, once the others were.
+    fn progress(&mut self) -> Result<()> {
+        let mut completions = [Completion::default(); 8];
+        let mut outcome = Ok(());
+        for (i, rail) in self.rails.iter().enumerate() {
+            let read = match rail.cq.read(&mut completions) {
+                Ok(n) => {
+                    let done = completions[..n]
+                        .iter()
+                        .map(|c| (i, c.context(), Ok(c.len())));
+                    self.done.extend(done);
+                    Ok(())
+                }
+                Err(err) if err.is_again() => Ok(()),
+                Err(err) if err.is_avail() => rail.cq.read_err().map(|entry| {
+                    if let Some(entry) = entry {
+                        self.done.push((i, entry.context, Err(entry.error)));
+                    }
+                }),
+                Err(err) => Err(err),
+            };
+            outcome = outcome.and(read);
+        }
+        outcome
+    }
+}
+
+impl Rail {
+    fn open(entry: &InfoEntry, nic: Option<Nic>) -> Result<Self> {
+        let fabric = Fabric::open(entry)?;
+        let domain = Domain::open(&fabric, entry)?;
+        let cq = domain.cq(&CqAttr::new())?;
+        let av = domain.av(&AvAttr::new())?;
+        let ep = domain
+            .endpoint(entry)?
+            .bind_cq(&cq, BindFlags::TRANSMIT | BindFlags::RECV)?
+            .bind_av(&av)?
+            .enable()?;
+        Ok(Rail { ep, cq, av, nic })
+    }
+}
@@ -0,0 +1,478 @@
+use crate::av::Addr;
+use crate::cq::{Completion, CqErrEntry};
+use crate::error::{Error, Result};
+use crate::tag::{TagField, TagSpace};
+use crate::transport::{Cq, CqHandler, Transport, poll_cq};
+use std::collections::{HashMap, VecDeque};
+
+// The tag of messages: their stream, then whether they only grant credits.
+const TAGS: TagSpace<2> = TagSpace::new([32, 1]);
+const STREAM: TagField = TAGS.field(0);
+const GRANT: TagField = TAGS.field(1);
+// The remote CQ data of messages: the credits granted back on the stream, then the sequence
+// number of the message in it.
+const DATA: TagSpace<2> = TagSpace::new([32, 32]);
+
+/// Attributes of a [`Multiplexer`], which must be the same on all peers.
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct MuxAttr {
+    window: u32,
+    threshold: Option<u32>,
+    max_size: usize,
+    depth: usize,
+}
+
+impl Default for MuxAttr {
+    fn default() -> Self {
+        MuxAttr {
+            window: 8,
+            threshold: None,
+            max_size: 4096,
+            depth: 16,
+        }
+    }
+}
+
+impl MuxAttr {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Messages a sender may have sent on a stream that the application of the peer has not
+    /// received yet, 8 by default.
+    pub fn window(mut self, credits: u32) -> Self {
+        self.window = credits.max(1);
+        self
+    }
+
+    /// Credits a receiver owes on a stream before granting them in a message of their own,
+    /// rather than along with the next message sent on the stream. Half the window by default.
+    pub fn threshold(mut self, credits: u32) -> Self {
+        self.threshold = Some(credits.max(1));
+        self
+    }
+
+    /// Largest payload, 4 KiB by default.
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Receives kept posted, shared by all streams, 16 by default.
+    pub fn depth(mut self, depth: usize) -> Self {
+        self.depth = depth.max(1);
+        self
+    }
+
+    fn threshold_or_default(&self) -> u32 {
+        self.threshold
+            .unwrap_or(self.window.div_ceil(2))
+            .min(self.window)
+    }
+}
+
+/// A logical stream of a [`Multiplexer`]: the peer at the other end, and the id both ends
+/// know it by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Stream {
+    pub peer: Addr,
+    pub id: u32,
+}
+
+impl Stream {
+    pub fn new(peer: Addr, id: u32) -> Self {
+        Stream { peer, id }
+    }
+}
+
+struct StreamState {
+    // Sending side.
+    credits: u32,
+    next_seq: u32,
+    backlog: VecDeque<Vec<u8>>,
+    failed: Option<Error>,
+    // Receiving side.
+    expected: u32,
+    early: HashMap<u32, Vec<u8>>,
+    inbox: VecDeque<Vec<u8>>,
+    owed: u32,
+}
+
+impl StreamState {
+    fn new(window: u32) -> Self {
+        StreamState {
+            credits: window,
+            next_seq: 0,
+            backlog: VecDeque::new(),
+            failed: None,
+            expected: 0,
+            early: HashMap::new(),
+            inbox: VecDeque::new(),
+            owed: 0,
+        }
+    }
+}
+
+/// Many logical streams of messages over one endpoint, each delivered in order and flow
+/// controlled on its own, so that applications need not open an endpoint per connection.
+///
+/// Messages are tagged with the id of their stream, and carry their sequence number in the
+/// stream as remote CQ data, by which the receiver puts them back in order whatever the
+/// ordering of the provider. Each stream has a window of credits, as in [`FlowControl`], one
+/// spent per message and granted back once the application received it: a stream whose
+/// receiver falls behind queues its messages, without holding back the other streams, to the
+/// same peer or not. The receives are shared by all streams, and messages are copied out of
+/// them as they arrive.
+///
+/// It runs over any [`Transport`] and [`Cq`], so over an RDM [`Endpoint`] opened with
+/// `Caps::TAGGED | Caps::SOURCE`, where streams are told apart by peer and id, or over a MSG
+/// endpoint, whose completions carry no source, where the peer of all streams is
+/// [`Addr::UNSPEC`].
+///
+/// A message which fails to be sent fails its stream: [`poll()`](Self::poll) reports the
+/// error, later sends on the stream fail with it, and the receiver holds back the messages
+/// after the lost one.
+///
+/// ```no_run
+/// # use libfabric::{Addr, CompletionQueue, Endpoint};
+/// # fn run(ep: Endpoint, cq: CompletionQueue, peer: Addr) -> libfabric::Result<()> {
+/// use libfabric::{Multiplexer, MuxAttr, Stream};
+///
+/// let mut mux = unsafe { Multiplexer::new(ep, cq, &MuxAttr::new()) }?;
+/// let (control, bulk) = (Stream::new(peer, 0), Stream::new(peer, 1));
+/// mux.send(bulk, &[0; 4096])?;
+/// mux.send(control, b"stop")?;
+/// loop {
+///     if let Some((stream, message)) = mux.recv_any()? {
+///         println!("{} bytes on stream {}", message.len(), stream.id);
+///     }
+/// }
+/// # }
+/// ```
+///
+/// [`Endpoint`]: crate::Endpoint
+/// [`FlowControl`]: crate::FlowControl
+pub struct Multiplexer<T: Transport, C: Cq> {
+    // First, see `CqHandler`.
+    ep: T,
+    cq: C,
+    attr: MuxAttr,
+    slots: Vec<Box<[u8]>>,
+    unposted: Vec<usize>,
+    streams: HashMap<Stream, StreamState>,
+    // The streams of the messages received, in order of arrival, for `recv_any()`.
+    ready: VecDeque<Stream>,
+    // Streams with messages queued, and streams owing a grant.
+    sending: VecDeque<Stream>,
+    due: VecDeque<Stream>,
+    // The buffers of posted sends, by context.
+    sends: HashMap<usize, (Stream, Vec<u8>)>,
+    next_send: usize,
+    errors: VecDeque<Error>,
+}
+
+impl<T: Transport, C: Cq> Multiplexer<T, C> {
+    /// Post the receives of the multiplexer on `ep`, whose completions are read from `cq`.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Transport`] documentation.
+    pub unsafe fn new(ep: T, cq: C, attr: &MuxAttr) -> Result<Self> {
+        let slots = (0..attr.depth)
+            .map(|_| vec![0u8; attr.max_size].into_boxed_slice())
+            .collect();
+        let mut mux = Multiplexer {
+            ep,
+            cq,
+            attr: attr.clone(),
+            slots,
+            unposted: (0..attr.depth).rev().collect(),
+            streams: HashMap::new(),
+            ready: VecDeque::new(),
+            sending: VecDeque::new(),
+            due: VecDeque::new(),
+            sends: HashMap::new(),
+            next_send: 0,
+            errors: VecDeque::new(),
+        };
+        mux.flush()?;
+        Ok(mux)
+    }
+
+    pub fn endpoint(&self) -> &T {
+        &self.ep
+    }
+
+    /// Send `buf` on `stream` once a credit of the stream is left, queuing it until then.
+    /// Never fails with `FI_EAGAIN`: messages the provider has no room for are queued as well.
+    pub fn send(&mut self, stream: Stream, buf: &[u8]) -> Result<()> {
+        if buf.len() > self.attr.max_size {
+            return Err(Error::invalid(format!(
+                "{} bytes message over the {} bytes maximum",
+                buf.len(),
+                self.attr.max_size
+            )));
+        }
+        let state = self.state(stream);
+        if let Some(err) = &state.failed {
+            return Err(err.clone());
+        }
+        state.backlog.push_back(buf.to_vec());
+        if state.backlog.len() == 1 {
+            self.sending.push_back(stream);
+        }
+        self.flush()
+    }
+
+    /// The next message of `stream`, granting a credit back for it.
+    pub fn recv(&mut self, stream: Stream) -> Result<Option<Vec<u8>>> {
+        if self
+            .streams
+            .get(&stream)
+            .is_none_or(|state| state.inbox.is_empty())
+        {
+            self.poll()?;
+        }
+        let Some(buf) = self
+            .streams
+            .get_mut(&stream)
+            .and_then(|state| state.inbox.pop_front())
+        else {
+            return Ok(None);
+        };
+        self.received(stream)?;
+        Ok(Some(buf))
+    }
+
+    /// The next message of any stream, in order of arrival, and its stream, granting a credit
+    /// back for it. Streams not sent on yet show up here first.
+    pub fn recv_any(&mut self) -> Result<Option<(Stream, Vec<u8>)>> {
+        if self.ready.is_empty() {
+            self.poll()?;
+        }
+        while let Some(stream) = self.ready.pop_front() {
+            // Messages taken by `recv()` already leave their stream behind.
+            let Some(buf) = self.state(stream).inbox.pop_front() else {
+                continue;
+            };
+            self.received(stream)?;
+            return Ok(Some((stream, buf)));
+        }
+        Ok(None)
+    }
+
+    /// Credits left on `stream`.
+    pub fn credits(&self, stream: Stream) -> u32 {
+        self.streams
+            .get(&stream)
+            .map_or(self.attr.window, |state| state.credits)
+    }
+
+    /// Messages of `stream` queued, waiting for credits or for room to be sent.
+    pub fn backlog(&self, stream: Stream) -> usize {
+        self.streams
+            .get(&stream)
+            .map_or(0, |state| state.backlog.len())
+    }
+
+    /// Forget `stream`, its queued and received messages, once the application is done with
+    /// it. Once both ends closed it, the stream starts over from its first message.
+    pub fn close(&mut self, stream: Stream) {
+        self.streams.remove(&stream);
+    }
+
+    /// Read the completions available, take in the messages and credits received, and send
+    /// what the credits allow. Fails with the error of a message which failed to be sent.
+    pub fn poll(&mut self) -> Result<()> {
+        poll_cq(self)?;
+        self.flush()?;
+        match self.errors.pop_front() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    fn state(&mut self, stream: Stream) -> &mut StreamState {
+        let window = self.attr.window;
+        self.streams
+            .entry(stream)
+            .or_insert_with(|| StreamState::new(window))
+    }
+
+    // Owe a credit for a message the application received, granted once enough are owed.
+    fn received(&mut self, stream: Stream) -> Result<()> {
+        let threshold = self.attr.threshold_or_default();
+        let state = self.state(stream);
+        state.owed += 1;
+        if state.owed == threshold {
+            self.due.push_back(stream);
+        }
+        self.flush()
+    }
+
+    // Repost the receives, then post the messages the credits of their streams allow, and the
+    // grants owed, until the provider runs out of room.
+    fn flush(&mut self) -> Result<()> {
+        let recv = TAGS.matching().exact(&TAGS);
+        while let Some(slot) = self.unposted.pop() {
+            // SAFETY: the slot is not read until the receive completes, and outlives the
+            // endpoint, see `new()`.
+            let posted = unsafe {
+                self.ep.trecv(
+                    &mut self.slots[slot],
+                    None,
+                    Addr::UNSPEC,
+                    recv.tag(),
+                    recv.ignore(),
+                    slot,
+                )
+            };
+            match posted {
+                Err(err) if err.is_again() => {
+                    self.unposted.push(slot);
+                    break;
+                }
+                other => other?,
+            }
+        }
+
+        // Streams out of credits leave the queue until granted some.
+        while let Some(&stream) = self.sending.front() {
+            let Some(state) = self.streams.get_mut(&stream) else {
+                self.sending.pop_front();
+                continue;
+            };
+            let Some(buf) = state.backlog.pop_front() else {
+                self.sending.pop_front();
+                continue;
+            };
+            if state.credits == 0 {
+                state.backlog.push_front(buf);
+                self.sending.pop_front();
+                continue;
+            }
+            let data = DATA.tag([state.owed as u64, state.next_seq as u64]);
+            match self.post(stream, buf, STREAM.encode(stream.id as u64), data) {
+                Ok(None) => {
+                    let state = self.state(stream);
+                    state.credits -= 1;
+                    state.next_seq = state.next_seq.wrapping_add(1);
+                    state.owed = 0;
+                }
+                Ok(Some(buf)) => {
+                    self.state(stream).backlog.push_front(buf);
+                    return Ok(());
+                }
+                // The message is dropped, before it took a sequence number.
+                Err(err) => return Err(err),
+            }
+        }
+
+        let threshold = self.attr.threshold_or_default();
+        while let Some(&stream) = self.due.front() {
+            let owed = self.streams.get(&stream).map_or(0, |state| state.owed);
+            if owed >= threshold {
+                let tag = TAGS.tag([stream.id as u64, 1]);
+                match self.post(stream, Vec::new(), tag, DATA.tag([owed as u64, 0]))? {
+                    None => self.state(stream).owed = 0,
+                    Some(_) => break,
+                }
+            }
+            self.due.pop_front();
+        }
+        Ok(())
+    }
+
+    // Post a message, or hand it back when the provider has no room for it.
+    fn post(
+        &mut self,
+        stream: Stream,
+        buf: Vec<u8>,
+        tag: u64,
+        data: u64,
+    ) -> Result<Option<Vec<u8>>> {
+        let context = self.slots.len().wrapping_add(self.next_send);
+        // SAFETY: the buffer is kept in `sends` until the send completes.
+        let posted = unsafe {
+            self.ep
+                .tsenddata(&buf, None, data, stream.peer, tag, context)
+        };
+        match posted {
+            Ok(()) => {
+                self.next_send = self.next_send.wrapping_add(1);
+                self.sends.insert(context, (stream, buf));
+                Ok(None)
+            }
+            Err(err) if err.is_again() => Ok(Some(buf)),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl<T: Transport, C: Cq> CqHandler for Multiplexer<T, C> {
+    type Cq = C;
+
+    fn cq(&self) -> &C {
+        &self.cq
+    }
+
+    fn complete(&mut self, completion: &Completion, src: Addr) {
+        let slot = completion.context();
+        if slot >= self.slots.len() {
+            self.sends.remove(&slot);
+            return;
+        }
+        self.unposted.push(slot);
+        let peer = match src {
+            Addr::NOTAVAIL => Addr::UNSPEC,
+            src => src,
+        };
+        let tag = completion.tag();
+        let stream = Stream::new(peer, STREAM.get(tag) as u32);
+        let [granted, seq] = DATA.fields(completion.data());
+        let payload = match GRANT.get(tag) {
+            0 => Some(self.slots[slot][..completion.len()].to_vec()),
+            _ => None,
+        };
+        let window = self.attr.window;
+        let state = self
+            .streams
+            .entry(stream)
+            .or_insert_with(|| StreamState::new(window));
+        if granted > 0 {
+            state.credits += granted as u32;
+            if state.credits == granted as u32 && !state.backlog.is_empty() {
+                self.sending.push_back(stream);
+            }
+        }
+        let Some(payload) = payload else {
+            return;
+        };
+        if seq as u32 != state.expected {
+            state.early.insert(seq as u32, payload);
+            return;
+        }
+        let mut next = Some(payload);
+        while let Some(payload) = next {
+            state.inbox.push_back(payload);
+            self.ready.push_back(stream);
+            state.expected = state.expected.wrapping_add(1);
+            next = state.early.remove(&state.expected);
+        }
+    }
+
+    fn failed(&mut self, entry: CqErrEntry) {
+        if entry.context >= self.slots.len() {
+            if let Some((stream, _)) = self.sends.remove(&entry.context) {
+                let state = self.state(stream);
+                state.failed = Some(entry.error.clone());
+                state.backlog.clear();
+                self.errors.push_back(entry.error);
+            }
+            return;
+        }
+        // A truncated receive, of a peer with a larger maximum size.
+        self.unposted.push(entry.context);
+    }
+}
@@ -0,0 +1,412 @@
+use crate::attr::{RxAttr, TxAttr};
+use crate::error::{Error, Result};
+use crate::flags::{Caps, Mode, MrMode, MsgOrder};
+use crate::info::{EndpointType, Info, InfoEntry};
+use crate::threading::Threading;
+use crate::util::{cstr, read_enum, write_enum};
+use ofi_libfabric_sys::bindgen as ffi;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::ptr;
+
+/// What an entry grants short of the hints it was requested with, from [`validate_caps()`].
+///
+/// Providers are free to return entries lacking secondary capabilities and orderings the hints
+/// asked for, ex: `FI_RMA_EVENT`, which then go unnoticed until the application relies on
+/// them. Every field is empty when the entry grants everything requested.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CapsReport {
+    pub provider: String,
+    /// The requested capabilities the entry lacks.
+    pub missing_caps: Caps,
+    /// The modes the entry requires, which the hints did not declare supported.
+    pub unsupported_modes: Mode,
+    /// The message and completion orderings of the transmit context the entry lacks.
+    pub missing_tx_msg_order: MsgOrder,
+    pub missing_tx_comp_order: MsgOrder,
+    /// The message and completion orderings of the receive context the entry lacks.
+    pub missing_rx_msg_order: MsgOrder,
+    pub missing_rx_comp_order: MsgOrder,
+}
+
+impl CapsReport {
+    /// Whether the entry grants everything requested.
+    pub fn is_exact(&self) -> bool {
+        self.missing_caps.is_empty()
+            && self.unsupported_modes.is_empty()
+            && self.missing_tx_msg_order.is_empty()
+            && self.missing_tx_comp_order.is_empty()
+            && self.missing_rx_msg_order.is_empty()
+            && self.missing_rx_comp_order.is_empty()
+    }
+
+    /// Fail with an invalid argument error listing the downgrades, if any, for applications
+    /// which cannot do without what they requested.
+    pub fn strict(self) -> Result<Self> {
+        if self.is_exact() {
+            Ok(self)
+        } else {
+            Err(Error::invalid(self.to_string()))
+        }
+    }
+}
+
+impl fmt::Display for CapsReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_exact() {
+            return write!(f, "{} grants every requested capability", self.provider);
+        }
+        let mut parts = Vec::new();
+        if !self.missing_caps.is_empty() {
+            parts.push(format!("caps {:?}", self.missing_caps));
+        }
+        if !self.unsupported_modes.is_empty() {
+            parts.push(format!("modes {:?}", self.unsupported_modes));
+        }
+        let orders = [
+            ("tx msg_order", self.missing_tx_msg_order),
+            ("tx comp_order", self.missing_tx_comp_order),
+            ("rx msg_order", self.missing_rx_msg_order),
+            ("rx comp_order", self.missing_rx_comp_order),
+        ];
+        for (name, order) in orders.iter().filter(|(_, order)| !order.is_empty()) {
+            parts.push(format!("{name} {order:?}"));
+        }
+        write!(f, "{} downgraded: {}", self.provider, parts.join(", "))
+    }
+}
+
+/// Compare the capabilities, modes and orderings `requested` from discovery with those of
+/// `granted`, one of the entries it returned.
+///
+/// ```no_run
+/// use libfabric::{Caps, EndpointType, Info, validate_caps};
+///
+/// # fn run() -> libfabric::Result<()> {
+/// let hints = Info::new()
+///     .caps(Caps::MSG | Caps::RMA | Caps::RMA_EVENT)
+///     .ep_type(EndpointType::Rdm);
+/// let entry = &hints.get()?[0];
+/// let report = validate_caps(&hints, entry);
+/// if !report.is_exact() {
+///     eprintln!("{report}");
+/// }
+/// // Or give up on the entry.
+/// validate_caps(&hints, entry).strict()?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn validate_caps(requested: &Info, granted: &InfoEntry) -> CapsReport {
+    let hints = requested.hints();
+    // Hints always carry their attributes, allocated by fi_allocinfo().
+    let (tx, rx) = unsafe {
+        (
+            TxAttr::from_raw(&*hints.tx_attr),
+            RxAttr::from_raw(&*hints.rx_attr),
+        )
+    };
+    let (granted_tx, granted_rx) = (granted.tx_attr(), granted.rx_attr());
+    CapsReport {
+        provider: granted.provider_name().to_owned(),
+        missing_caps: Caps::from_bits_retain(hints.caps).difference(granted.caps()),
+        unsupported_modes: granted
+            .mode()
+            .difference(Mode::from_bits_retain(hints.mode)),
+        missing_tx_msg_order: tx.msg_order.difference(granted_tx.msg_order),
+        missing_tx_comp_order: tx.comp_order.difference(granted_tx.comp_order),
+        missing_rx_msg_order: rx.msg_order.difference(granted_rx.msg_order),
+        missing_rx_comp_order: rx.comp_order.difference(granted_rx.comp_order),
+    }
+}
+
+/// A requested hint of discovery, as [`explain_getinfo()`] analyzes it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Hint {
+    EpType(EndpointType),
+    /// One of the requested capabilities.
+    Cap(Caps),
+    /// The modes declared supported.
+    Mode(Mode),
+    /// The memory registration modes declared supported.
+    MrMode(MrMode),
+    Threading(Threading),
+    Provider(String),
+    Fabric(String),
+    Domain(String),
+}
+
+impl Hint {
+    // The hints of `hints` which may eliminate providers.
+    fn requested(hints: &ffi::fi_info) -> Vec<Hint> {
+        // Hints always carry their attributes, allocated by fi_allocinfo().
+        let (ep_attr, domain_attr, fabric_attr) =
+            unsafe { (&*hints.ep_attr, &*hints.domain_attr, &*hints.fabric_attr) };
+        let name =
+            |ptr: *mut std::ffi::c_char| (!ptr.is_null()).then(|| unsafe { cstr(ptr) }.to_owned());
+        let mut requested = Vec::new();
+        let ep_type = EndpointType::from_raw(unsafe { read_enum(&raw const ep_attr.type_) });
+        if ep_type != EndpointType::Unspec {
+            requested.push(Hint::EpType(ep_type));
+        }
+        requested.extend(Caps::from_bits_retain(hints.caps).iter().map(Hint::Cap));
+        let mode = Mode::from_bits_retain(hints.mode);
+        if !mode.contains(Mode::all()) {
+            requested.push(Hint::Mode(mode));
+        }
+        let mr_mode = MrMode::from_bits_retain(domain_attr.mr_mode as u32);
+        if !mr_mode.contains(MrMode::all()) {
+            requested.push(Hint::MrMode(mr_mode));
+        }
+        let threading = Threading::from_raw(unsafe { read_enum(&raw const domain_attr.threading) });
+        if threading != Threading::Unspec {
+            requested.push(Hint::Threading(threading));
+        }
+        requested.extend(name(fabric_attr.prov_name).map(Hint::Provider));
+        requested.extend(name(fabric_attr.name).map(Hint::Fabric));
+        requested.extend(name(domain_attr.name).map(Hint::Domain));
+        requested
+    }
+
+    // Drop the hint from `info`, or declare every mode supported.
+    fn relax(&self, info: &mut ffi::fi_info) {
+        let (ep_attr, domain_attr, fabric_attr) = unsafe {
+            (
+                &mut *info.ep_attr,
+                &mut *info.domain_attr,
+                &mut *info.fabric_attr,
+            )
+        };
+        match self {
+            Hint::EpType(_) => unsafe {
+                write_enum(&raw mut ep_attr.type_, EndpointType::Unspec.as_raw())
+            },
+            Hint::Cap(cap) => info.caps &= !cap.bits(),
+            Hint::Mode(_) => info.mode = Mode::all().bits(),
+            Hint::MrMode(_) => domain_attr.mr_mode = MrMode::all().bits() as i32,
+            Hint::Threading(_) => unsafe {
+                write_enum(&raw mut domain_attr.threading, Threading::Unspec.as_raw())
+            },
+            Hint::Provider(_) => fabric_attr.prov_name = ptr::null_mut(),
+            Hint::Fabric(_) => fabric_attr.name = ptr::null_mut(),
+            Hint::Domain(_) => domain_attr.name = ptr::null_mut(),
+        }
+    }
+
+    // The value of the hint the `entries` matching without it would accept, if it has one.
+    fn accepted(&self, entries: &[InfoEntry]) -> Option<Hint> {
+        if entries.is_empty() {
+            return None;
+        }
+        match self {
+            Hint::EpType(_) => {
+                let ep_type = entries[0].ep_type();
+                entries
+                    .iter()
+                    .all(|entry| entry.ep_type() == ep_type)
+                    .then_some(Hint::EpType(ep_type))
+            }
+            Hint::Mode(mode) => {
+                let required = entries
+                    .iter()
+                    .fold(*mode, |mode, entry| mode | entry.mode());
+                Some(Hint::Mode(required))
+            }
+            Hint::MrMode(mr_mode) => {
+                let required = entries
+                    .iter()
+                    .fold(*mr_mode, |mr_mode, entry| mr_mode | entry.mr_mode());
+                Some(Hint::MrMode(required))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Hint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Hint::EpType(ep_type) => write!(f, "ep_type {ep_type:?}"),
+            Hint::Cap(cap) => write!(f, "cap {cap:?}"),
+            Hint::Mode(mode) => write!(f, "mode {mode:?}"),
+            Hint::MrMode(mr_mode) => write!(f, "mr_mode {mr_mode:?}"),
+            Hint::Threading(threading) => write!(f, "threading {threading:?}"),
+            Hint::Provider(name) => write!(f, "provider {name:?}"),
+            Hint::Fabric(name) => write!(f, "fabric {name:?}"),
+            Hint::Domain(name) => write!(f, "domain {name:?}"),
+        }
+    }
+}
+
+/// How one hint narrows discovery, in a [`GetinfoReport`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct HintImpact {
+    pub hint: Hint,
+    /// The providers matching this hint with every other one relaxed, none if it eliminates
+    /// them all by itself.
+    pub alone: Vec<String>,
+    /// The providers matching every other hint with this one relaxed, some if it is all that
+    /// stands in the way.
+    pub without: Vec<String>,
+    /// The value of the hint those providers accept, for modes, memory registration modes and
+    /// endpoint types: the modes they require on top of those declared, or their type.
+    pub accepted: Option<Hint>,
+}
+
+impl HintImpact {
+    /// Whether the hint eliminates every provider by itself.
+    pub fn is_culprit(&self) -> bool {
+        self.alone.is_empty()
+    }
+
+    /// Whether relaxing the hint alone lets some provider match.
+    pub fn is_fix(&self) -> bool {
+        !self.without.is_empty()
+    }
+}
+
+/// Why discovery found nothing, from [`explain_getinfo()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct GetinfoReport {
+    /// The providers matching the hints, none when `fi_getinfo()` fails with `FI_ENODATA`.
+    pub matches: Vec<String>,
+    /// The providers matching with every hint relaxed, for the node and service of the hints.
+    pub available: Vec<String>,
+    /// The requested hints, in the order of [`Hint`], analyzed when no provider matches.
+    pub hints: Vec<HintImpact>,
+}
+
+impl GetinfoReport {
+    /// The hints eliminating every provider by themselves.
+    pub fn culprits(&self) -> impl Iterator<Item = &HintImpact> {
+        self.hints.iter().filter(|impact| impact.is_culprit())
+    }
+
+    /// The hints relaxing which alone lets some provider match.
+    pub fn fixes(&self) -> impl Iterator<Item = &HintImpact> {
+        self.hints.iter().filter(|impact| impact.is_fix())
+    }
+}
+
+impl fmt::Display for GetinfoReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.matches.is_empty() {
+            return write!(f, "the hints match {}", self.matches.join(", "));
+        }
+        if self.available.is_empty() {
+            return write!(
+                f,
+                "no provider is available, whatever the hints: check the node and service, \
+                 FI_PROVIDER and the libfabric installed"
+            );
+        }
+        write!(
+            f,
+            "no provider matches the hints, of {}",
+            self.available.join(", ")
+        )?;
+        for impact in self.culprits() {
+            write!(f, "\n  {} eliminates every provider", impact.hint)?;
+        }
+        for impact in self.fixes() {
+            write!(
+                f,
+                "\n  without {}, {} match",
+                impact.hint,
+                impact.without.join(", ")
+            )?;
+            if let Some(accepted) = &impact.accepted {
+                write!(f, ": request {accepted}")?;
+            }
+        }
+        if self.fixes().next().is_none() {
+            // Several hints eliminating every provider, or hints conflicting with each other.
+            let several = match self.culprits().next() {
+                Some(_) => self.culprits().collect::<Vec<_>>(),
+                None => self.hints.iter().collect(),
+            };
+            let several: Vec<_> = several
+                .iter()
+                .map(|impact| impact.hint.to_string())
+                .collect();
+            write!(
+                f,
+                "\n  relaxing no single hint is enough: relax several of {}",
+                several.join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Explain why discovery with `hints` finds no provider, the most common dead end of
+/// `fi_getinfo()`, failing with `FI_ENODATA`: each requested endpoint type, capability,
+/// mode, memory registration mode, threading level and name is tried alone, then relaxed
+/// alone, to report which of them eliminate every provider and which to relax for some to
+/// match.
+///
+/// Relaxing drops the hint, or declares every mode supported for modes. This takes two
+/// `fi_getinfo()` calls per hint, and is meant for the error path.
+///
+/// ```no_run
+/// use libfabric::{Caps, EndpointType, Info, explain_getinfo};
+///
+/// # fn run() -> libfabric::Result<()> {
+/// let hints = Info::new()
+///     .caps(Caps::MSG | Caps::ATOMIC)
+///     .ep_type(EndpointType::Rdm);
+/// let entries = match hints.get() {
+///     Ok(entries) => entries,
+///     Err(err) => {
+///         // ex: "no provider matches the hints, of shm, tcp
+///         //        without cap Caps(ATOMIC), shm, tcp match"
+///         eprintln!("{}", explain_getinfo(&hints)?);
+///         return Err(err);
+///     }
+/// };
+/// # Ok(())
+/// # }
+/// ```
+pub fn explain_getinfo(hints: &Info) -> Result<GetinfoReport> {
+    let names = |entries: Vec<InfoEntry>| {
+        let names: BTreeSet<_> = entries
+            .iter()
+            .map(|entry| entry.provider_name().to_owned())
+            .collect();
+        names.into_iter().collect::<Vec<_>>()
+    };
+    let requested = Hint::requested(hints.hints());
+    let relax = |skip: Option<usize>| {
+        let requested = &requested;
+        move |info: &mut ffi::fi_info| {
+            for (_, hint) in requested
+                .iter()
+                .enumerate()
+                .filter(|&(i, _)| Some(i) != skip)
+            {
+                hint.relax(info);
+            }
+        }
+    };
+    let mut report = GetinfoReport {
+        matches: names(hints.get_edited(|_| {})?),
+        available: names(hints.get_edited(relax(None))?),
+        hints: Vec::new(),
+    };
+    if !report.matches.is_empty() {
+        return Ok(report);
+    }
+    for (i, hint) in requested.iter().enumerate() {
+        let without = hints.get_edited(|info| hint.relax(info))?;
+        report.hints.push(HintImpact {
+            hint: hint.clone(),
+            alone: names(hints.get_edited(relax(Some(i)))?),
+            accepted: hint.accepted(&without),
+            without: names(without),
+        });
+    }
+    Ok(report)
+}
@@ -0,0 +1,131 @@
+use crate::cntr::{CntrAttr, Counter};
+use crate::ep::{Endpoint, EndpointState};
+use crate::error::{Error, Result};
+use crate::flags::{Access, Caps, MrMode};
+use crate::mr::MemoryRegion;
+use crate::threading::{ThreadSafe, ThreadingModel};
+use ofi_libfabric_sys::bindgen as ffi;
+use std::time::Duration;
+
+/// A region peers write into, counting their writes (`FI_RMA_EVENT`), so that the target
+/// learns of them without any message from the initiators.
+///
+/// [`new()`](Self::new) takes the steps the pattern needs: it checks the domain was opened
+/// with [`Caps::RMA_EVENT`], registers the buffer for remote writes, opens a blocking counter
+/// and binds the region to it for `FI_REMOTE_WRITE`, binds the region to the endpoint where
+/// the provider requires [`MrMode::ENDPOINT`], and enables it where
+/// [`MrMode::RMA_EVENT`] or [`MrMode::ENDPOINT`] require that.
+/// [`wait_for_writes()`](Self::wait_for_writes) then blocks until peers wrote the region a
+/// number of times since the previous wait.
+///
+/// ```no_run
+/// # use libfabric::Endpoint;
+/// # fn run(ep: &Endpoint) -> libfabric::Result<()> {
+/// use libfabric::NotifiedRegion;
+///
+/// let mut buf = vec![0u8; 4096];
+/// let mut region = unsafe { NotifiedRegion::new(ep, buf.as_mut_ptr(), buf.len()) }?;
+/// // Hand `region.region().key()` to the peers, then wait for each of them to write once.
+/// region.wait_for_writes(4, None)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct NotifiedRegion<M: ThreadingModel = ThreadSafe> {
+    mr: MemoryRegion<M>,
+    cntr: Counter<M>,
+    // The writes waited for so far, and the write errors counted when the region was opened.
+    seen: u64,
+    errors: u64,
+}
+
+impl<M: ThreadingModel> NotifiedRegion<M> {
+    /// Register the `len` bytes at `buf` for the remote writes of the peers of `ep`, counted
+    /// by a counter of its own. Fails with `FI_EOPNOTSUPP` if the domain of `ep` was not
+    /// opened with [`Caps::RMA_EVENT`].
+    ///
+    /// # Safety
+    ///
+    /// As for [`Domain::register()`](crate::Domain::register): the memory must stay valid for
+    /// as long as the region, which peers write at any time.
+    pub unsafe fn new<S: EndpointState>(
+        ep: &Endpoint<M, S>,
+        buf: *mut u8,
+        len: usize,
+    ) -> Result<Self> {
+        let domain = ep.domain();
+        let info = domain.info();
+        if !info.caps().contains(Caps::RMA_EVENT) {
+            return Err(Error::fabric("fi_mr_bind", ffi::FI_EOPNOTSUPP as i64));
+        }
+        let cntr = domain.counter(&CntrAttr::new().blocking(true))?;
+        let mr = unsafe { domain.register(buf, len, Access::REMOTE_WRITE) }?;
+        mr.bind_counter(&cntr, Access::REMOTE_WRITE)?;
+        let mr_mode = info.mr_mode();
+        if mr_mode.contains(MrMode::ENDPOINT) {
+            mr.bind_endpoint(ep)?;
+        }
+        if mr_mode.intersects(MrMode::RMA_EVENT | MrMode::ENDPOINT) {
+            mr.enable()?;
+        }
+        let errors = cntr.read_err();
+        Ok(NotifiedRegion {
+            mr,
+            cntr,
+            seen: 0,
+            errors,
+        })
+    }
+
+    /// The registered region, whose key and address peers write with.
+    pub fn region(&self) -> &MemoryRegion<M> {
+        &self.mr
+    }
+
+    /// The counter of the writes into the region.
+    pub fn counter(&self) -> &Counter<M> {
+        &self.cntr
+    }
+
+    /// The writes completed into the region, in all.
+    pub fn writes(&self) -> u64 {
+        self.cntr.read()
+    }
+
+    /// The writes completed since the previous wait, which are not waited for yet.
+    pub fn unseen(&self) -> u64 {
+        self.writes().saturating_sub(self.seen)
+    }
+
+    /// Block until the peers wrote the region `n` times since the previous wait, an error is
+    /// counted, or the timeout expires, failing with `FI_ETIMEDOUT` then.
+    pub fn wait_for_writes(&mut self, n: u64, timeout: Option<Duration>) -> Result<()> {
+        self.cntr.wait(self.seen + n, timeout)?;
+        self.check()?;
+        self.seen += n;
+        Ok(())
+    }
+
+    /// Like [`wait_for_writes()`](Self::wait_for_writes), yielding to the executor while the
+    /// writes are not there, enabled by the `async` feature.
+    ///
+    /// Like [`post_with_retry_async()`](crate::post_with_retry_async), the future does not
+    /// depend on a runtime: it polls the counter each time the executor gets to it.
+    #[cfg(feature = "async")]
+    pub async fn wait_for_writes_async(&mut self, n: u64) -> Result<()> {
+        while self.writes() < self.seen + n {
+            self.check()?;
+            crate::retry::YieldNow(false).await;
+        }
+        self.check()?;
+        self.seen += n;
+        Ok(())
+    }
+
+    // Fails once the counter counts write errors.
+    fn check(&self) -> Result<()> {
+        match self.cntr.read_err() > self.errors {
+            true => Err(Error::fabric("fi_cntr_readerr", ffi::FI_EIO as i64)),
+            false => Ok(()),
+        }
+    }
+}
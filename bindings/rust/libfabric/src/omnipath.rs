@@ -0,0 +1,312 @@
+use crate::error::{Error, Result};
+use crate::info::InfoEntry;
+
+/// How PSM3 picks the NICs of a process (`PSM3_MULTIRAIL`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NicSelection {
+    /// The application picks one NIC per process, ex: with [`Psm3Config::nic()`].
+    Application,
+    /// PSM3 picks one NIC per process, the default.
+    One,
+    /// Every process stripes over all the NICs of the node.
+    All,
+    /// Every process stripes over the NICs of its NUMA node, or all of them if it has none.
+    NumaLocal,
+    /// Every process stripes over the NICs closest to its CPU and GPU.
+    Affinity,
+}
+
+impl NicSelection {
+    fn value(self) -> i32 {
+        match self {
+            NicSelection::Application => -1,
+            NicSelection::One => 0,
+            NicSelection::All => 1,
+            NicSelection::NumaLocal => 2,
+            NicSelection::Affinity => 3,
+        }
+    }
+}
+
+/// The tunables of the psm3 provider, and of the PSM3 library underneath it.
+///
+/// Both read them from the environment on their initialization, which [`install()`] sets: it
+/// takes effect only when called before any other call into libfabric. Tunables left unset keep
+/// the defaults of the provider.
+///
+/// ```no_run
+/// use libfabric::{NicSelection, Psm3Config};
+///
+/// let psm3 = Psm3Config::new()
+///     .multi_ep(true)
+///     .nic_selection(NicSelection::NumaLocal)
+///     .uuid("4a1f3c3e-5b8a-4a0e-9b4e-1f2c3d4e5f60");
+/// // SAFETY: no other thread runs yet.
+/// unsafe { psm3.install() }?;
+/// # Ok::<(), libfabric::Error>(())
+/// ```
+///
+/// [`install()`]: Self::install
+#[derive(Debug, Clone, Default)]
+#[must_use]
+pub struct Psm3Config {
+    multi_ep: Option<bool>,
+    nic: Option<String>,
+    nic_selection: Option<NicSelection>,
+    uuid: Option<String>,
+    lock_level: Option<u8>,
+    lazy_conn: Option<bool>,
+}
+
+impl Psm3Config {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Let a process open several endpoints, each with its own PSM3 endpoint and hardware
+    /// contexts, rather than one (`PSM3_MULTI_EP`).
+    pub fn multi_ep(mut self, enable: bool) -> Self {
+        self.multi_ep = Some(enable);
+        self
+    }
+
+    /// The NIC of the process, by name or unit number, ex: `irdma0` or `0` (`PSM3_NIC`).
+    pub fn nic(mut self, nic: &str) -> Self {
+        self.nic = Some(nic.to_owned());
+        self
+    }
+
+    /// How the NICs of the process are picked (`PSM3_MULTIRAIL`).
+    pub fn nic_selection(mut self, selection: NicSelection) -> Self {
+        self.nic_selection = Some(selection);
+        self
+    }
+
+    /// The id of the job, the same in each of its processes, which keeps the traffic and the
+    /// shared memory of other jobs apart (`FI_PSM3_UUID`).
+    pub fn uuid(mut self, uuid: &str) -> Self {
+        self.uuid = Some(uuid.to_owned());
+        self
+    }
+
+    /// The locking of the provider, from 0, no locks at all, to 2, locks around every object
+    /// (`FI_PSM3_LOCK_LEVEL`).
+    pub fn lock_level(mut self, level: u8) -> Self {
+        self.lock_level = Some(level);
+        self
+    }
+
+    /// Connect to peers on their first message, rather than on their insertion in the address
+    /// vector (`FI_PSM3_LAZY_CONN`).
+    pub fn lazy_conn(mut self, enable: bool) -> Self {
+        self.lazy_conn = Some(enable);
+        self
+    }
+
+    /// The variables of the tunables, and their values.
+    pub fn vars(&self) -> Result<Vec<(&'static str, String)>> {
+        if let Some(level) = self.lock_level.filter(|&level| level > 2) {
+            return Err(Error::invalid(format!("invalid psm3 lock level {level}")));
+        }
+        let mut vars = Vec::new();
+        if let Some(multi_ep) = self.multi_ep {
+            vars.push(("PSM3_MULTI_EP", flag(multi_ep)));
+        }
+        if let Some(nic) = &self.nic {
+            vars.push(("PSM3_NIC", nic.clone()));
+        }
+        if let Some(selection) = self.nic_selection {
+            vars.push(("PSM3_MULTIRAIL", selection.value().to_string()));
+        }
+        if let Some(uuid) = &self.uuid {
+            vars.push(("FI_PSM3_UUID", uuid.clone()));
+        }
+        if let Some(level) = self.lock_level {
+            vars.push(("FI_PSM3_LOCK_LEVEL", level.to_string()));
+        }
+        if let Some(lazy_conn) = self.lazy_conn {
+            vars.push(("FI_PSM3_LAZY_CONN", flag(lazy_conn)));
+        }
+        Ok(vars)
+    }
+
+    /// Set the variables of the tunables in the environment of the process.
+    ///
+    /// # Safety
+    ///
+    /// Setting the environment is only sound while no other thread reads or writes it, see
+    /// [`std::env::set_var()`].
+    pub unsafe fn install(&self) -> Result<()> {
+        unsafe { set_vars(self.vars()?) };
+        Ok(())
+    }
+}
+
+/// The tunables of the opx provider, for Omni-Path HFIs.
+///
+/// The provider reads them from the environment on its initialization, which [`install()`]
+/// sets: it takes effect only when called before any other call into libfabric. Tunables left
+/// unset keep the defaults of the provider.
+///
+/// ```no_run
+/// use libfabric::OpxConfig;
+///
+/// // Four endpoints per hardware context, for more ranks per node than the HFI has contexts.
+/// let opx = OpxConfig::new().endpoints_per_context(4).hfi("0");
+/// // SAFETY: no other thread runs yet.
+/// unsafe { opx.install() }?;
+/// # Ok::<(), libfabric::Error>(())
+/// ```
+///
+/// [`install()`]: Self::install
+#[derive(Debug, Clone, Default)]
+#[must_use]
+pub struct OpxConfig {
+    context_sharing: Option<bool>,
+    endpoints_per_context: Option<u8>,
+    hfi: Option<String>,
+    port: Option<u8>,
+    uuid: Option<String>,
+    sdma: Option<bool>,
+    tid: Option<bool>,
+}
+
+impl OpxConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Let several endpoints share a hardware context of the HFI, rather than take one each
+    /// (`FI_OPX_CONTEXT_SHARING`).
+    pub fn context_sharing(mut self, enable: bool) -> Self {
+        self.context_sharing = Some(enable);
+        self
+    }
+
+    /// Share each hardware context between `count` endpoints, from 2 to 8, which turns on
+    /// [`context_sharing()`](Self::context_sharing) (`FI_OPX_ENDPOINTS_PER_HFI_CONTEXT`).
+    pub fn endpoints_per_context(mut self, count: u8) -> Self {
+        self.context_sharing = Some(true);
+        self.endpoints_per_context = Some(count);
+        self
+    }
+
+    /// The HFI of the process, overriding the selection of the provider, ex: `0`, or `numa:0:0`
+    /// for the unit of each NUMA node (`FI_OPX_HFI_SELECT`).
+    pub fn hfi(mut self, select: &str) -> Self {
+        self.hfi = Some(select.to_owned());
+        self
+    }
+
+    /// The port of the HFI, from 1 (`FI_OPX_PORT`).
+    pub fn port(mut self, port: u8) -> Self {
+        self.port = Some(port);
+        self
+    }
+
+    /// The id of the job, the same in each of its processes, which keeps the traffic and the
+    /// shared memory of other jobs apart (`FI_OPX_UUID`).
+    pub fn uuid(mut self, uuid: &str) -> Self {
+        self.uuid = Some(uuid.to_owned());
+        self
+    }
+
+    /// Offload large sends to the SDMA engines of the HFI (`FI_OPX_SDMA_DISABLE`).
+    pub fn sdma(mut self, enable: bool) -> Self {
+        self.sdma = Some(enable);
+        self
+    }
+
+    /// Place large receives directly in the buffers of the application, with expected receive
+    /// (TID) entries (`FI_OPX_TID_DISABLE`).
+    pub fn tid(mut self, enable: bool) -> Self {
+        self.tid = Some(enable);
+        self
+    }
+
+    /// The variables of the tunables, and their values.
+    pub fn vars(&self) -> Result<Vec<(&'static str, String)>> {
+        if let Some(count) = self
+            .endpoints_per_context
+            .filter(|count| !(2..=8).contains(count))
+        {
+            return Err(Error::invalid(format!(
+                "invalid opx endpoints per context {count}"
+            )));
+        }
+        if self.port == Some(0) {
+            return Err(Error::invalid("invalid opx port 0"));
+        }
+        let mut vars = Vec::new();
+        if let Some(sharing) = self.context_sharing {
+            vars.push(("FI_OPX_CONTEXT_SHARING", flag(sharing)));
+        }
+        if let Some(count) = self.endpoints_per_context {
+            vars.push(("FI_OPX_ENDPOINTS_PER_HFI_CONTEXT", count.to_string()));
+        }
+        if let Some(hfi) = &self.hfi {
+            vars.push(("FI_OPX_HFI_SELECT", hfi.clone()));
+        }
+        if let Some(port) = self.port {
+            vars.push(("FI_OPX_PORT", port.to_string()));
+        }
+        if let Some(uuid) = &self.uuid {
+            vars.push(("FI_OPX_UUID", uuid.clone()));
+        }
+        if let Some(sdma) = self.sdma {
+            vars.push(("FI_OPX_SDMA_DISABLE", flag(!sdma)));
+        }
+        if let Some(tid) = self.tid {
+            vars.push(("FI_OPX_TID_DISABLE", flag(!tid)));
+        }
+        Ok(vars)
+    }
+
+    /// Set the variables of the tunables in the environment of the process.
+    ///
+    /// # Safety
+    ///
+    /// Setting the environment is only sound while no other thread reads or writes it, see
+    /// [`std::env::set_var()`].
+    pub unsafe fn install(&self) -> Result<()> {
+        unsafe { set_vars(self.vars()?) };
+        Ok(())
+    }
+}
+
+/// The endpoints and contexts of a domain (`fi_domain_attr`), which bound the endpoints of a
+/// process, and the scalable endpoints they split into, on providers such as psm3 and opx
+/// that give each of them hardware contexts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContextCounts {
+    /// The endpoints the domain is optimized for.
+    pub ep_cnt: usize,
+    /// The transmit and receive contexts of the domain, over all its endpoints.
+    pub tx_ctx_cnt: usize,
+    pub rx_ctx_cnt: usize,
+    /// The transmit and receive contexts of a scalable endpoint.
+    pub max_ep_tx_ctx: usize,
+    pub max_ep_rx_ctx: usize,
+}
+
+/// The endpoint and context counts of the domain of `entry`.
+pub fn context_counts(entry: &InfoEntry) -> ContextCounts {
+    let attr = entry.domain_attr();
+    ContextCounts {
+        ep_cnt: attr.ep_cnt,
+        tx_ctx_cnt: attr.tx_ctx_cnt,
+        rx_ctx_cnt: attr.rx_ctx_cnt,
+        max_ep_tx_ctx: attr.max_ep_tx_ctx,
+        max_ep_rx_ctx: attr.max_ep_rx_ctx,
+    }
+}
+
+fn flag(enable: bool) -> String {
+    if enable { "1" } else { "0" }.to_owned()
+}
+
+unsafe fn set_vars(vars: Vec<(&str, String)>) {
+    for (var, value) in vars {
+        unsafe { std::env::set_var(var, value) };
+    }
+}
@@ -0,0 +1,157 @@
+use crate::attr::{EpAttr, TxAttr};
+use crate::error::{Error, Result};
+use crate::flags::MsgOrder;
+use crate::info::InfoEntry;
+use std::fmt;
+
+/// The ordering of the data of RMA and atomic operations at their targets, from
+/// [`InfoEntry::rma_ordering()`]: the message orderings of the transmit context, and the sizes
+/// up to which the data of an operation is placed in memory after that of the operations
+/// posted before it (`max_order_raw_size`, `max_order_war_size` and `max_order_waw_size`).
+///
+/// Message ordering alone orders when operations are processed, not when their data reaches
+/// memory: two writes are ordered at a target when `msg_order` has the ordering and both are
+/// no larger than its size. A size of 0 orders no data, `usize::MAX` (`-1` in C) data of any
+/// size.
+///
+/// [`check()`](Self::check) validates an access pattern against these, for patterns such as
+/// polling on a flag the initiator writes after its data, which break silently at a target
+/// which does not order the two writes.
+///
+/// ```no_run
+/// use libfabric::{AccessPattern, InfoEntry};
+///
+/// # fn run(entry: &InfoEntry) -> libfabric::Result<()> {
+/// // The target polls on an 8 byte flag written after 4 KiB of data.
+/// let ordering = entry.rma_ordering();
+/// ordering.check(AccessPattern::WriteThenFlag {
+///     len: 4096,
+///     flag_len: 8,
+/// })?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RmaOrdering {
+    pub msg_order: MsgOrder,
+    pub max_order_raw_size: usize,
+    pub max_order_war_size: usize,
+    pub max_order_waw_size: usize,
+}
+
+impl RmaOrdering {
+    pub fn from_attrs(tx: &TxAttr, ep: &EpAttr) -> Self {
+        RmaOrdering {
+            msg_order: tx.msg_order,
+            max_order_raw_size: ep.max_order_raw_size,
+            max_order_war_size: ep.max_order_war_size,
+            max_order_waw_size: ep.max_order_waw_size,
+        }
+    }
+
+    /// The largest operations whose reads are ordered after the writes before them, 0 if
+    /// `msg_order` has neither [`MsgOrder::RAW`] nor [`MsgOrder::RMA_RAW`].
+    pub fn raw(&self) -> usize {
+        self.ordered(MsgOrder::RAW | MsgOrder::RMA_RAW, self.max_order_raw_size)
+    }
+
+    /// The largest operations whose writes are ordered after the reads before them, 0 if
+    /// `msg_order` has neither [`MsgOrder::WAR`] nor [`MsgOrder::RMA_WAR`].
+    pub fn war(&self) -> usize {
+        self.ordered(MsgOrder::WAR | MsgOrder::RMA_WAR, self.max_order_war_size)
+    }
+
+    /// The largest operations whose writes are ordered after the writes before them, 0 if
+    /// `msg_order` has neither [`MsgOrder::WAW`] nor [`MsgOrder::RMA_WAW`].
+    pub fn waw(&self) -> usize {
+        self.ordered(MsgOrder::WAW | MsgOrder::RMA_WAW, self.max_order_waw_size)
+    }
+
+    fn ordered(&self, order: MsgOrder, size: usize) -> usize {
+        match self.msg_order.intersects(order) {
+            true => size,
+            false => 0,
+        }
+    }
+
+    /// Check that `pattern` is safe under this ordering. Fails with an argument error telling
+    /// what the pattern relies on, what the provider orders, and what to do instead.
+    pub fn check(&self, pattern: AccessPattern) -> Result<()> {
+        let (kind, ordered, largest) = match pattern {
+            AccessPattern::PollLastByte { len } => {
+                return Err(Error::invalid(format!(
+                    "polling on the last byte of a {len} byte write is unsafe on every \
+                     provider: the bytes of one write are not placed in order, so the last \
+                     may land before the others; write a flag after the data \
+                     (AccessPattern::WriteThenFlag), or write with remote CQ data or \
+                     FI_DELIVERY_COMPLETE and wait for the completion instead"
+                )));
+            }
+            AccessPattern::WriteThenFlag { len, flag_len } => {
+                ("WAW", self.waw(), len.max(flag_len))
+            }
+            AccessPattern::ReadAfterWrite { len } => ("RAW", self.raw(), len),
+            AccessPattern::WriteAfterRead { len } => ("WAR", self.war(), len),
+            AccessPattern::WriteAfterWrite { len } => ("WAW", self.waw(), len),
+        };
+        if largest <= ordered {
+            return Ok(());
+        }
+        let negotiated = match ordered {
+            0 => format!("no {kind} ordering of data was negotiated"),
+            _ => format!("{kind} data ordering is limited to {ordered} bytes"),
+        };
+        Err(Error::invalid(format!(
+            "{pattern} relies on {kind} ordering of {largest} bytes, but {negotiated}; request \
+             MsgOrder::RMA_{kind} in the hints, keep the operations within the ordered size, \
+             or wait for the completion of the first before posting the second"
+        )))
+    }
+}
+
+impl InfoEntry {
+    /// The ordering of the data of RMA and atomic operations at their targets, from the
+    /// transmit and endpoint attributes.
+    pub fn rma_ordering(&self) -> RmaOrdering {
+        RmaOrdering::from_attrs(&self.tx_attr(), &self.ep_attr())
+    }
+}
+
+/// An access pattern relying on the ordering of RMA operations at their target, checked by
+/// [`RmaOrdering::check()`]. Lengths are those of the operations, in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessPattern {
+    /// The target polls on the last byte of a write to see the write complete, which no
+    /// ordering makes safe.
+    PollLastByte { len: usize },
+    /// The initiator writes data, then a flag, and the target polls on the flag to read the
+    /// data, which needs write after write ordering of both.
+    WriteThenFlag { len: usize, flag_len: usize },
+    /// The initiator reads back memory it wrote, without waiting for the write to complete.
+    ReadAfterWrite { len: usize },
+    /// The initiator overwrites memory it read, without waiting for the read to complete.
+    WriteAfterRead { len: usize },
+    /// The initiator writes memory twice, relying on the second write landing last.
+    WriteAfterWrite { len: usize },
+}
+
+impl fmt::Display for AccessPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AccessPattern::PollLastByte { len } => {
+                write!(f, "polling on the last byte of a {len} byte write")
+            }
+            AccessPattern::WriteThenFlag { len, flag_len } => {
+                write!(
+                    f,
+                    "a {flag_len} byte flag written after {len} bytes of data"
+                )
+            }
+            AccessPattern::ReadAfterWrite { len } => write!(f, "a {len} byte read after a write"),
+            AccessPattern::WriteAfterRead { len } => write!(f, "a {len} byte write after a read"),
+            AccessPattern::WriteAfterWrite { len } => {
+                write!(f, "a {len} byte write after a write")
+            }
+        }
+    }
+}
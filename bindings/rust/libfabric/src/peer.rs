@@ -0,0 +1,200 @@
+use crate::cq::{Completion, CqErrEntry};
+use crate::error::strerror;
+use ofi_libfabric_sys::bindgen as ffi;
+use std::cell::UnsafeCell;
+use std::collections::VecDeque;
+use std::mem;
+use std::os::raw::c_void;
+use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+// Build the `struct fid` header of an object owned by the application. Peers only look at its
+// class, the application being the one closing it.
+fn owner_fid(fclass: u32) -> ffi::fid {
+    ffi::fid {
+        fclass: fclass as usize,
+        ..Default::default()
+    }
+}
+
+/// A completion queue owned by the application, into which peer providers write their
+/// completions (`fid_peer_cq`, see `fi_peer(3)`).
+///
+/// This lets one queue collect the completions of several providers, e.g. shm for local peers
+/// and a network provider for remote ones. Open each provider's side with
+/// [`Domain::peer_cq()`](crate::Domain::peer_cq), then read completions from here. Reading the
+/// providers' queues, even with an empty buffer, drives their progress.
+#[derive(Clone)]
+pub struct PeerCq {
+    inner: Arc<PeerCqInner>,
+}
+
+// The fid_peer_cq comes first, so the callbacks recover the whole object from the pointer they
+// are handed. Peers write through that pointer, hence the UnsafeCell.
+#[repr(C)]
+struct PeerCqInner {
+    peer: UnsafeCell<ffi::fid_peer_cq>,
+    ops: UnsafeCell<ffi::fi_ops_cq_owner>,
+    completions: Mutex<VecDeque<Completion>>,
+    errors: Mutex<VecDeque<CqErrEntry>>,
+}
+
+// SAFETY: The raw parts are only handed to providers, the queues are behind mutexes.
+unsafe impl Send for PeerCqInner {}
+unsafe impl Sync for PeerCqInner {}
+
+impl PeerCq {
+    pub fn new() -> Self {
+        let inner = Arc::new(PeerCqInner {
+            peer: UnsafeCell::new(ffi::fid_peer_cq {
+                fid: owner_fid(ffi::FI_CLASS_PEER_CQ),
+                owner_ops: ptr::null_mut(),
+            }),
+            ops: UnsafeCell::new(ffi::fi_ops_cq_owner {
+                size: mem::size_of::<ffi::fi_ops_cq_owner>(),
+                write: Some(peer_cq_write),
+                writeerr: Some(peer_cq_writeerr),
+            }),
+            completions: Mutex::default(),
+            errors: Mutex::default(),
+        });
+        // The Arc never moves its contents, so the table can be linked in place.
+        unsafe { (*inner.peer.get()).owner_ops = inner.ops.get() };
+        PeerCq { inner }
+    }
+
+    /// Pop up to `out.len()` completions written by the peers, returning how many were read.
+    pub fn read(&self, out: &mut [Completion]) -> usize {
+        let mut completions = self.inner.completions.lock().unwrap();
+        let count = out.len().min(completions.len());
+        for (slot, completion) in out.iter_mut().zip(completions.drain(..count)) {
+            *slot = completion;
+        }
+        count
+    }
+
+    /// Pop one error completion written by the peers, if any.
+    pub fn read_err(&self) -> Option<CqErrEntry> {
+        self.inner.errors.lock().unwrap().pop_front()
+    }
+
+    pub fn as_raw(&self) -> *mut ffi::fid_peer_cq {
+        self.inner.peer.get()
+    }
+}
+
+impl Default for PeerCq {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe extern "C" fn peer_cq_write(
+    cq: *mut ffi::fid_peer_cq,
+    context: *mut c_void,
+    flags: u64,
+    len: usize,
+    buf: *mut c_void,
+    data: u64,
+    tag: u64,
+    _src: ffi::fi_addr_t,
+) -> isize {
+    let inner = unsafe { &*cq.cast::<PeerCqInner>() };
+    inner
+        .completions
+        .lock()
+        .unwrap()
+        .push_back(Completion::from_raw(ffi::fi_cq_tagged_entry {
+            op_context: context,
+            flags,
+            len,
+            buf,
+            data,
+            tag,
+        }));
+    0
+}
+
+unsafe extern "C" fn peer_cq_writeerr(
+    cq: *mut ffi::fid_peer_cq,
+    err_entry: *const ffi::fi_cq_err_entry,
+) -> isize {
+    let inner = unsafe { &*cq.cast::<PeerCqInner>() };
+    let raw = unsafe { &*err_entry };
+    let entry = CqErrEntry::from_raw(raw, strerror(raw.err));
+    inner.errors.lock().unwrap().push_back(entry);
+    0
+}
+
+/// A counter owned by the application, which peer providers increment (`fid_peer_cntr`, see
+/// `fi_peer(3)`). Open each provider's side with
+/// [`Domain::peer_counter()`](crate::Domain::peer_counter).
+#[derive(Clone)]
+pub struct PeerCounter {
+    inner: Arc<PeerCntrInner>,
+}
+
+// Laid out like PeerCqInner, see there.
+#[repr(C)]
+struct PeerCntrInner {
+    peer: UnsafeCell<ffi::fid_peer_cntr>,
+    ops: UnsafeCell<ffi::fi_ops_cntr_owner>,
+    value: AtomicU64,
+    errors: AtomicU64,
+}
+
+// SAFETY: The raw parts are only handed to providers, the values are atomics.
+unsafe impl Send for PeerCntrInner {}
+unsafe impl Sync for PeerCntrInner {}
+
+impl PeerCounter {
+    pub fn new() -> Self {
+        let inner = Arc::new(PeerCntrInner {
+            peer: UnsafeCell::new(ffi::fid_peer_cntr {
+                fid: owner_fid(ffi::FI_CLASS_PEER_CNTR),
+                owner_ops: ptr::null_mut(),
+            }),
+            ops: UnsafeCell::new(ffi::fi_ops_cntr_owner {
+                size: mem::size_of::<ffi::fi_ops_cntr_owner>(),
+                inc: Some(peer_cntr_inc),
+                incerr: Some(peer_cntr_incerr),
+            }),
+            value: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+        });
+        unsafe { (*inner.peer.get()).owner_ops = inner.ops.get() };
+        PeerCounter { inner }
+    }
+
+    /// Number of successful events reported by the peers.
+    pub fn read(&self) -> u64 {
+        self.inner.value.load(Ordering::Acquire)
+    }
+
+    /// Number of events completed in error reported by the peers.
+    pub fn read_err(&self) -> u64 {
+        self.inner.errors.load(Ordering::Acquire)
+    }
+
+    pub fn as_raw(&self) -> *mut ffi::fid_peer_cntr {
+        self.inner.peer.get()
+    }
+}
+
+impl Default for PeerCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe extern "C" fn peer_cntr_inc(cntr: *mut ffi::fid_peer_cntr) {
+    let inner = unsafe { &*cntr.cast::<PeerCntrInner>() };
+    inner.value.fetch_add(1, Ordering::AcqRel);
+}
+
+unsafe extern "C" fn peer_cntr_incerr(cntr: *mut ffi::fid_peer_cntr) {
+    let inner = unsafe { &*cntr.cast::<PeerCntrInner>() };
+    inner.errors.fetch_add(1, Ordering::AcqRel);
+}
@@ -0,0 +1,189 @@
+use crate::bootstrap::Bootstrap;
+use crate::error::{Error, Result};
+use crate::util::cstring;
+use std::ffi::{CStr, CString};
+use std::io::ErrorKind;
+use std::os::raw::{c_char, c_int};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// From pmi2.h, which libpmi2 of Slurm, MPICH's hydra and OpenPMIx's compatibility layer share.
+const PMI2_SUCCESS: c_int = 0;
+const PMI2_MAX_VALLEN: usize = 1024;
+
+unsafe extern "C" {
+    fn PMI2_Init(
+        spawned: *mut c_int,
+        size: *mut c_int,
+        rank: *mut c_int,
+        appnum: *mut c_int,
+    ) -> c_int;
+    fn PMI2_Finalize() -> c_int;
+    fn PMI2_Job_GetId(jobid: *mut c_char, jobid_size: c_int) -> c_int;
+    fn PMI2_KVS_Put(key: *const c_char, value: *const c_char) -> c_int;
+    fn PMI2_KVS_Fence() -> c_int;
+    fn PMI2_KVS_Get(
+        jobid: *const c_char,
+        src_pmi_id: c_int,
+        key: *const c_char,
+        value: *mut c_char,
+        maxvalue: c_int,
+        vallen: *mut c_int,
+    ) -> c_int;
+}
+
+// Values are hex encoded strings, so each one holds up to this many bytes.
+const CHUNK: usize = (PMI2_MAX_VALLEN - 1) / 2;
+
+// PMI may only be initialized once at a time in a process.
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+/// A [`Bootstrap`] over the key-value store of the job launcher, through the PMI-2 interface,
+/// enabled by the `pmi` feature.
+///
+/// Ranks and the size of the job are those the launcher assigned, ex: with `srun --mpi=pmi2`
+/// under Slurm, or through the PMI-2 compatibility library of OpenPMIx. Each
+/// [`allgather()`](Bootstrap::allgather) puts the contribution of this member, fences, then gets
+/// those of the others, so no socket is opened.
+pub struct Pmi {
+    rank: usize,
+    size: usize,
+    jobid: CString,
+    // Keys may not be put twice, so each allgather uses its own.
+    round: u64,
+}
+
+impl Pmi {
+    /// Initialize PMI, which is finalized once the returned value is dropped. Fails if it was
+    /// already initialized by this process.
+    pub fn init() -> Result<Self> {
+        if INITIALIZED.swap(true, Ordering::AcqRel) {
+            return Err(Error::invalid("PMI is already initialized"));
+        }
+        let (mut spawned, mut size, mut rank, mut appnum) = (0, 0, 0, 0);
+        if let Err(err) = check("PMI2_Init", unsafe {
+            PMI2_Init(&mut spawned, &mut size, &mut rank, &mut appnum)
+        }) {
+            INITIALIZED.store(false, Ordering::Release);
+            return Err(err);
+        }
+        // Finalized by drop from now on, including when getting the job id fails.
+        let mut pmi = Pmi {
+            rank: rank as usize,
+            size: size as usize,
+            jobid: CString::default(),
+            round: 0,
+        };
+        let mut jobid = [0 as c_char; PMI2_MAX_VALLEN];
+        check("PMI2_Job_GetId", unsafe {
+            PMI2_Job_GetId(jobid.as_mut_ptr(), jobid.len() as c_int)
+        })?;
+        pmi.jobid = unsafe { CStr::from_ptr(jobid.as_ptr()) }.to_owned();
+        Ok(pmi)
+    }
+
+    fn put(&self, key: &str, value: &str) -> Result<()> {
+        let (key, value) = (cstring(key)?, cstring(value)?);
+        check("PMI2_KVS_Put", unsafe {
+            PMI2_KVS_Put(key.as_ptr(), value.as_ptr())
+        })
+    }
+
+    fn get(&self, rank: usize, key: &str) -> Result<String> {
+        let key = cstring(key)?;
+        let mut value = [0 as c_char; PMI2_MAX_VALLEN];
+        let mut len = 0;
+        check("PMI2_KVS_Get", unsafe {
+            PMI2_KVS_Get(
+                self.jobid.as_ptr(),
+                rank as c_int,
+                key.as_ptr(),
+                value.as_mut_ptr(),
+                value.len() as c_int,
+                &mut len,
+            )
+        })?;
+        Ok(unsafe { CStr::from_ptr(value.as_ptr()) }
+            .to_string_lossy()
+            .into_owned())
+    }
+}
+
+impl Bootstrap for Pmi {
+    fn rank(&self) -> usize {
+        self.rank
+    }
+
+    fn size(&self) -> usize {
+        self.size
+    }
+
+    // The contribution of each member is split into chunks of `ofi-{round}-{rank}-{chunk}`
+    // keys, of which `ofi-{round}-{rank}` holds the count.
+    fn allgather(&mut self, local: &[u8]) -> Result<Vec<Vec<u8>>> {
+        let round = self.round;
+        self.round += 1;
+        let prefix = |rank: usize| format!("ofi-{round}-{rank}");
+        let chunks: Vec<_> = local.chunks(CHUNK).collect();
+        self.put(&prefix(self.rank), &chunks.len().to_string())?;
+        for (i, chunk) in chunks.iter().enumerate() {
+            self.put(&format!("{}-{i}", prefix(self.rank)), &hex(chunk))?;
+        }
+        check("PMI2_KVS_Fence", unsafe { PMI2_KVS_Fence() })?;
+
+        (0..self.size)
+            .map(|rank| {
+                if rank == self.rank {
+                    return Ok(local.to_vec());
+                }
+                let count: usize = self
+                    .get(rank, &prefix(rank))?
+                    .parse()
+                    .map_err(|_| malformed())?;
+                let mut buf = Vec::new();
+                for i in 0..count {
+                    buf.extend(unhex(&self.get(rank, &format!("{}-{i}", prefix(rank)))?)?);
+                }
+                Ok(buf)
+            })
+            .collect()
+    }
+}
+
+impl Drop for Pmi {
+    fn drop(&mut self) {
+        unsafe { PMI2_Finalize() };
+        INITIALIZED.store(false, Ordering::Release);
+    }
+}
+
+fn check(op: &str, ret: c_int) -> Result<()> {
+    match ret {
+        PMI2_SUCCESS => Ok(()),
+        ret => Err(Error::Io {
+            kind: ErrorKind::Other,
+            message: format!("{op} failed with {ret}"),
+        }),
+    }
+}
+
+fn malformed() -> Error {
+    Error::invalid("malformed PMI value")
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn unhex(s: &str) -> Result<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return Err(malformed());
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| {
+            s.get(i..i + 2)
+                .and_then(|b| u8::from_str_radix(b, 16).ok())
+                .ok_or_else(malformed)
+        })
+        .collect()
+}
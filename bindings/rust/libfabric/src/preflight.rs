@@ -0,0 +1,425 @@
+//! Preflight checks of a host, before running fabric services on it: the locked memory limit,
+//! the permissions of the devices, huge pages, kernel modules and providers, each reported
+//! with what to do about it.
+//!
+//! [`check()`] runs every check and returns a [`PreflightReport`], which fails only on what
+//! the [`PreflightAttr`] requires, while warning about what commonly breaks RDMA providers
+//! later, ex: a locked memory limit of a few megabytes. The checks read `/proc`, `/sys` and
+//! `/dev` as Linux lays them out, and are skipped where these are missing.
+//!
+//! ```no_run
+//! use libfabric::preflight::{self, PreflightAttr};
+//!
+//! let attr = PreflightAttr::new().require_provider("verbs").min_hugepages(512);
+//! let report = preflight::check(&attr);
+//! if !report.passed() {
+//!     eprintln!("{report}");
+//!     std::process::exit(1);
+//! }
+//! ```
+
+use crate::info::available_providers;
+use std::ffi::CString;
+use std::fmt;
+use std::fs;
+use std::os::raw::{c_char, c_int};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+// From unistd.h.
+const R_OK: c_int = 4;
+const W_OK: c_int = 2;
+
+unsafe extern "C" {
+    fn access(path: *const c_char, mode: c_int) -> c_int;
+}
+
+// The kernel modules the providers need, along with the user space libraries they load.
+const PROVIDER_MODULES: &[(&str, &[&str])] = &[
+    ("verbs", &["ib_uverbs"]),
+    ("psm3", &["ib_uverbs"]),
+    ("efa", &["ib_uverbs", "efa"]),
+    ("cxi", &["cxi_ss1"]),
+    ("opx", &["hfi1"]),
+    ("psm2", &["hfi1"]),
+    ("usnic", &["usnic_verbs"]),
+];
+
+// The device directories of RDMA and Slingshot NICs.
+const DEVICE_DIRS: &[&str] = &["/dev/infiniband"];
+const DEVICE_PREFIXES: &[&str] = &["cxi"];
+
+/// What a [`check()`] requires of the host.
+#[derive(Debug, Clone, Default)]
+#[must_use]
+pub struct PreflightAttr {
+    providers: Vec<String>,
+    modules: Vec<String>,
+    min_memlock: Option<u64>,
+    min_hugepages: u64,
+}
+
+impl PreflightAttr {
+    /// Require nothing, so that the checks only warn, but on a `/dev/shm` which may not be
+    /// written to.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fail unless `provider` is available, and the kernel modules it is known to need are
+    /// loaded. Layered providers are required layer by layer, ex: `"verbs;ofi_rxm"`.
+    pub fn require_provider(mut self, provider: &str) -> Self {
+        self.providers
+            .extend(provider.split(';').map(str::to_owned));
+        self
+    }
+
+    /// Fail unless the kernel module `name` is loaded, or built in.
+    pub fn require_module(mut self, name: &str) -> Self {
+        self.modules.push(name.to_owned());
+        self
+    }
+
+    /// Fail if the locked memory limit is below `bytes`. Limits other than unlimited are
+    /// warned about otherwise, as pinning buffers of RDMA providers counts against them.
+    pub fn min_memlock(mut self, bytes: u64) -> Self {
+        self.min_memlock = Some(bytes);
+        self
+    }
+
+    /// Fail if fewer than `pages` huge pages are free, of the default size.
+    pub fn min_hugepages(mut self, pages: u64) -> Self {
+        self.min_hugepages = pages;
+        self
+    }
+}
+
+/// What a [`PreflightCheck`] verifies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum CheckKind {
+    /// The `RLIMIT_MEMLOCK` limit of the process.
+    Memlock,
+    /// Read and write access to the devices of the NICs, and to `/dev/shm`.
+    Devices,
+    /// The huge pages of the default size free.
+    HugePages,
+    /// The kernel modules required, and those of the providers required.
+    KernelModules,
+    /// The providers of the linked libfabric.
+    Providers,
+}
+
+impl CheckKind {
+    /// The name of the check in reports, ex: `memlock`.
+    pub fn name(self) -> &'static str {
+        match self {
+            CheckKind::Memlock => "memlock",
+            CheckKind::Devices => "devices",
+            CheckKind::HugePages => "hugepages",
+            CheckKind::KernelModules => "modules",
+            CheckKind::Providers => "providers",
+        }
+    }
+}
+
+/// How a [`PreflightCheck`] went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum CheckStatus {
+    Pass,
+    /// Not checked, ex: on hosts without `/proc`.
+    Skipped,
+    /// Not required, but likely to fail some providers.
+    Warn,
+    /// Short of what the [`PreflightAttr`] requires.
+    Fail,
+}
+
+/// The outcome of one check of a [`PreflightReport`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PreflightCheck {
+    pub kind: CheckKind,
+    pub status: CheckStatus,
+    /// What was found, ex: `locked memory limited to 65536 bytes`.
+    pub detail: String,
+    /// What to do about a warning or failure.
+    pub remediation: Option<String>,
+}
+
+impl PreflightCheck {
+    fn new(kind: CheckKind, status: CheckStatus, detail: impl Into<String>) -> Self {
+        PreflightCheck {
+            kind,
+            status,
+            detail: detail.into(),
+            remediation: None,
+        }
+    }
+
+    fn hint(mut self, remediation: impl Into<String>) -> Self {
+        self.remediation = Some(remediation.into());
+        self
+    }
+}
+
+/// What [`check()`] found about the host, one check of each [`CheckKind`], in order.
+///
+/// It displays as text, and, with the `serde` feature, serializes to JSON or any other format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PreflightReport {
+    pub checks: Vec<PreflightCheck>,
+}
+
+impl PreflightReport {
+    /// Whether no check failed, warnings allowed.
+    pub fn passed(&self) -> bool {
+        self.checks
+            .iter()
+            .all(|check| check.status != CheckStatus::Fail)
+    }
+
+    /// The worst status of the checks.
+    pub fn status(&self) -> CheckStatus {
+        let statuses = self.checks.iter().map(|check| check.status);
+        statuses.max().unwrap_or(CheckStatus::Pass)
+    }
+
+    /// The check of `kind`.
+    pub fn get(&self, kind: CheckKind) -> Option<&PreflightCheck> {
+        self.checks.iter().find(|check| check.kind == kind)
+    }
+}
+
+impl fmt::Display for PreflightReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            let status = match check.status {
+                CheckStatus::Pass => "ok",
+                CheckStatus::Skipped => "skipped",
+                CheckStatus::Warn => "WARN",
+                CheckStatus::Fail => "FAILED",
+            };
+            writeln!(f, "{:<10} {status:<7} {}", check.kind.name(), check.detail)?;
+            if let Some(remediation) = &check.remediation {
+                writeln!(f, "{:<18} hint: {remediation}", "")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check the host, failing on what `attr` requires, see the [module documentation](self).
+pub fn check(attr: &PreflightAttr) -> PreflightReport {
+    PreflightReport {
+        checks: vec![
+            memlock(attr),
+            devices(),
+            hugepages(attr),
+            modules(attr),
+            providers(attr),
+        ],
+    }
+}
+
+fn memlock(attr: &PreflightAttr) -> PreflightCheck {
+    const HINT: &str = "raise the limit with `ulimit -l unlimited`, `memlock unlimited` in \
+                        /etc/security/limits.conf, or `LimitMEMLOCK=infinity` for systemd \
+                        services";
+    let kind = CheckKind::Memlock;
+    let Some(limit) = read_memlock() else {
+        return PreflightCheck::new(kind, CheckStatus::Skipped, "no /proc/self/limits");
+    };
+    match (limit, attr.min_memlock) {
+        (None, _) => PreflightCheck::new(kind, CheckStatus::Pass, "locked memory unlimited"),
+        (Some(bytes), Some(min)) if bytes < min => PreflightCheck::new(
+            kind,
+            CheckStatus::Fail,
+            format!("locked memory limited to {bytes} bytes, below {min}"),
+        )
+        .hint(HINT),
+        (Some(bytes), Some(_)) => PreflightCheck::new(
+            kind,
+            CheckStatus::Pass,
+            format!("locked memory limited to {bytes} bytes"),
+        ),
+        (Some(bytes), None) => PreflightCheck::new(
+            kind,
+            CheckStatus::Warn,
+            format!("locked memory limited to {bytes} bytes, which registrations count against"),
+        )
+        .hint(HINT),
+    }
+}
+
+// The soft locked memory limit, None when unlimited, from the `Max locked memory` line of
+// /proc/self/limits.
+fn read_memlock() -> Option<Option<u64>> {
+    let limits = fs::read_to_string("/proc/self/limits").ok()?;
+    let line = limits
+        .lines()
+        .find(|line| line.starts_with("Max locked memory"))?;
+    let soft = line["Max locked memory".len()..]
+        .split_whitespace()
+        .next()?;
+    match soft {
+        "unlimited" => Some(None),
+        soft => soft.parse().ok().map(Some),
+    }
+}
+
+fn devices() -> PreflightCheck {
+    let kind = CheckKind::Devices;
+    let mut devices = Vec::new();
+    for dir in DEVICE_DIRS {
+        if let Ok(entries) = fs::read_dir(dir) {
+            devices.extend(entries.flatten().map(|entry| entry.path()));
+        }
+    }
+    if let Ok(entries) = fs::read_dir("/dev") {
+        let named = entries.flatten().filter(|entry| {
+            let name = entry.file_name();
+            DEVICE_PREFIXES
+                .iter()
+                .any(|prefix| name.as_bytes().starts_with(prefix.as_bytes()))
+        });
+        devices.extend(named.map(|entry| entry.path()));
+    }
+    devices.sort();
+    let denied: Vec<_> = devices
+        .iter()
+        .filter(|path| !accessible(path))
+        .map(|path| path.display().to_string())
+        .collect();
+    if !accessible(Path::new("/dev/shm")) {
+        return PreflightCheck::new(kind, CheckStatus::Fail, "/dev/shm may not be written to")
+            .hint("mount a tmpfs on /dev/shm writable by all, as `mount -t tmpfs -o mode=1777`");
+    }
+    if !denied.is_empty() {
+        return PreflightCheck::new(
+            kind,
+            CheckStatus::Warn,
+            format!("no read and write access to {}", denied.join(", ")),
+        )
+        .hint("add the user to the group owning the devices, ex: `rdma`, or fix their udev rules");
+    }
+    let detail = match devices.len() {
+        0 => "no RDMA or Slingshot device, /dev/shm writable".to_owned(),
+        n => format!("{n} devices and /dev/shm accessible"),
+    };
+    PreflightCheck::new(kind, CheckStatus::Pass, detail)
+}
+
+// Whether this process may read and write `path`, with its real user and groups.
+fn accessible(path: &Path) -> bool {
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    // SAFETY: the path is a valid C string.
+    unsafe { access(path.as_ptr(), R_OK | W_OK) == 0 }
+}
+
+fn hugepages(attr: &PreflightAttr) -> PreflightCheck {
+    let kind = CheckKind::HugePages;
+    let Ok(meminfo) = fs::read_to_string("/proc/meminfo") else {
+        return PreflightCheck::new(kind, CheckStatus::Skipped, "no /proc/meminfo");
+    };
+    let field = |name: &str| {
+        meminfo
+            .lines()
+            .find_map(|line| line.strip_prefix(name))
+            .and_then(|rest| rest.split_whitespace().next()?.parse::<u64>().ok())
+    };
+    let (total, free) = (
+        field("HugePages_Total:").unwrap_or(0),
+        field("HugePages_Free:").unwrap_or(0),
+    );
+    let size = field("Hugepagesize:").unwrap_or(0);
+    let detail = format!("{free} of {total} huge pages of {size} kB free");
+    if free < attr.min_hugepages {
+        let hint = format!(
+            "reserve them with `sysctl vm.nr_hugepages={}`, or the `hugepages=` kernel parameter",
+            total - free + attr.min_hugepages
+        );
+        return PreflightCheck::new(kind, CheckStatus::Fail, detail).hint(hint);
+    }
+    PreflightCheck::new(kind, CheckStatus::Pass, detail)
+}
+
+fn modules(attr: &PreflightAttr) -> PreflightCheck {
+    let kind = CheckKind::KernelModules;
+    if !Path::new("/sys/module").is_dir() {
+        return PreflightCheck::new(kind, CheckStatus::Skipped, "no /sys/module");
+    }
+    let mut required: Vec<&str> = attr.modules.iter().map(String::as_str).collect();
+    for provider in &attr.providers {
+        if let Some((_, modules)) = PROVIDER_MODULES.iter().find(|(p, _)| p == provider) {
+            required.extend(modules.iter());
+        }
+    }
+    required.sort();
+    required.dedup();
+    // Loaded modules, and those built in with parameters, have a directory.
+    let missing: Vec<_> = required
+        .iter()
+        .filter(|name| !Path::new("/sys/module").join(name).exists())
+        .collect();
+    if missing.is_empty() {
+        let detail = match required.len() {
+            0 => "none required".to_owned(),
+            _ => format!("{} loaded", required.join(", ")),
+        };
+        return PreflightCheck::new(kind, CheckStatus::Pass, detail);
+    }
+    let missing: Vec<_> = missing.into_iter().copied().collect();
+    PreflightCheck::new(
+        kind,
+        CheckStatus::Fail,
+        format!("{} not loaded", missing.join(", ")),
+    )
+    .hint(format!("load them with `modprobe {}`", missing.join(" ")))
+}
+
+fn providers(attr: &PreflightAttr) -> PreflightCheck {
+    let kind = CheckKind::Providers;
+    let available = match available_providers() {
+        Ok(available) => available,
+        Err(err) => {
+            return PreflightCheck::new(
+                kind,
+                CheckStatus::Fail,
+                format!("provider discovery failed: {err}"),
+            )
+            .hint("run `fi_info` to see why, with `FI_LOG_LEVEL=warn`");
+        }
+    };
+    let missing: Vec<_> = attr
+        .providers
+        .iter()
+        .filter(|provider| !available.contains(provider))
+        .map(String::as_str)
+        .collect();
+    if !missing.is_empty() {
+        return PreflightCheck::new(
+            kind,
+            CheckStatus::Fail,
+            format!(
+                "{} unavailable, of {}",
+                missing.join(", "),
+                available.join(", ")
+            ),
+        )
+        .hint(
+            "check that libfabric was built with them, that `FI_PROVIDER` does not leave them \
+             out, and that `FI_PROVIDER_PATH` points at their libraries, with `fi_info -l` and \
+             `FI_LOG_LEVEL=warn`",
+        );
+    }
+    if available.is_empty() {
+        return PreflightCheck::new(kind, CheckStatus::Warn, "no provider available")
+            .hint("check `FI_PROVIDER` and `FI_PROVIDER_PATH`, with `fi_info -l`");
+    }
+    PreflightCheck::new(kind, CheckStatus::Pass, available.join(", "))
+}
@@ -0,0 +1,240 @@
+use crate::domain::Domain;
+use crate::ep::Endpoint;
+use crate::error::{Result, check, check_len};
+use crate::fid::{AsRawFid, OwnedFid};
+use crate::util::cstr;
+use ofi_libfabric_sys::bindgen as ffi;
+use std::os::raw::{c_int, c_void};
+use std::ptr;
+use std::sync::{Arc, Mutex};
+
+/// The type of a profiling variable, or of the data passed along an event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileDatatype {
+    /// A scalar (`enum fi_datatype`), ex: `FI_UINT64` for counters.
+    Primitive(ffi::fi_datatype),
+    /// A libfabric structure (`enum fi_type`), ex: `FI_TYPE_CQ_ERR_ENTRY`.
+    Defined(ffi::fi_type),
+    /// A primitive type these bindings do not know about.
+    Unknown(u32),
+}
+
+/// Description of a profiling variable or event (`struct fi_profile_desc`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileDesc {
+    /// Identifier to read the variable with, or to register a callback for the event.
+    pub id: u32,
+    pub datatype: ProfileDatatype,
+    pub flags: u64,
+    /// Size in bytes of the variable, or of the event data.
+    pub size: usize,
+    pub name: String,
+    pub description: String,
+}
+
+impl ProfileDesc {
+    fn from_raw(raw: &ffi::fi_profile_desc) -> Self {
+        // Both union members are 32 bit enums, read the raw value to validate the primitive one.
+        let value = unsafe { raw.datatype.defined };
+        let datatype = if raw.datatype_sel == ffi::fi_profile_type_fi_defined_type {
+            ProfileDatatype::Defined(value)
+        } else {
+            ffi::fi_datatype::try_from(value)
+                .map(ProfileDatatype::Primitive)
+                .unwrap_or(ProfileDatatype::Unknown(value))
+        };
+        ProfileDesc {
+            id: raw.id,
+            datatype,
+            flags: raw.flags,
+            size: raw.size,
+            name: unsafe { cstr(raw.name) }.to_owned(),
+            description: unsafe { cstr(raw.desc) }.to_owned(),
+        }
+    }
+
+    /// Whether the variable is a counter, readable with [`Profile::read_u64()`].
+    pub fn is_u64(&self) -> bool {
+        self.datatype == ProfileDatatype::Primitive(ffi::fi_datatype::FI_UINT64)
+    }
+}
+
+type Callback = Box<dyn Fn(u32, &[u8]) + Send + Sync>;
+
+/// A profile of a domain or endpoint (`fid_profile`, see `fi_profile(3)`), exposing the
+/// provider's internal variables (ex: `FI_VAR_UNEXP_MSG_CNT`, retransmit counts) and events.
+///
+/// Opened with [`Domain::profile()`] or [`Endpoint::profile()`], which fail with `FI_ENOSYS`
+/// when the provider does not support profiling.
+#[derive(Clone)]
+pub struct Profile {
+    inner: Arc<ProfileInner>,
+}
+
+struct ProfileInner {
+    fid: OwnedFid<ffi::fid_profile>,
+    // Registered callbacks, boxed again so a thin pointer to them can be handed to the
+    // provider. Dropped once the profile is closed, as they may be called until then.
+    #[allow(clippy::vec_box)]
+    callbacks: Mutex<Vec<Box<Callback>>>,
+    // The profiled object, kept alive until the profile is closed.
+    #[allow(dead_code)]
+    target: Target,
+}
+
+enum Target {
+    Domain(Domain),
+    Endpoint(Endpoint),
+}
+
+impl Target {
+    fn as_raw_fid(&self) -> *mut ffi::fid {
+        match self {
+            Target::Domain(domain) => domain.as_raw_fid(),
+            Target::Endpoint(ep) => ep.as_raw_fid(),
+        }
+    }
+}
+
+impl Profile {
+    fn open(target: Target) -> Result<Self> {
+        let fid = OwnedFid::open("fi_profile_open", |raw| unsafe {
+            ffi::fi_profile_open(target.as_raw_fid(), 0, raw, ptr::null_mut())
+        })?;
+        Ok(Profile {
+            inner: Arc::new(ProfileInner {
+                fid,
+                callbacks: Mutex::default(),
+                target,
+            }),
+        })
+    }
+
+    /// The variables the provider exposes.
+    pub fn vars(&self) -> Result<Vec<ProfileDesc>> {
+        self.query("fi_profile_query_vars", ffi::fi_profile_query_vars)
+    }
+
+    /// The events callbacks can be registered for.
+    pub fn events(&self) -> Result<Vec<ProfileDesc>> {
+        self.query("fi_profile_query_events", ffi::fi_profile_query_events)
+    }
+
+    /// Look up a variable by name, as names are stable across providers while ids are not.
+    pub fn var(&self, name: &str) -> Result<Option<ProfileDesc>> {
+        Ok(self.vars()?.into_iter().find(|var| var.name == name))
+    }
+
+    fn query(
+        &self,
+        op: &'static str,
+        query: unsafe extern "C" fn(
+            *mut ffi::fid_profile,
+            *mut ffi::fi_profile_desc,
+            *mut usize,
+        ) -> isize,
+    ) -> Result<Vec<ProfileDesc>> {
+        // A first call without a buffer only reports the count.
+        let mut count = 0;
+        check_len(op, unsafe {
+            query(self.as_raw(), ptr::null_mut(), &mut count)
+        })?;
+        let mut raw: Vec<ffi::fi_profile_desc> = vec![unsafe { std::mem::zeroed() }; count];
+        let len = check_len(op, unsafe {
+            query(self.as_raw(), raw.as_mut_ptr(), &mut count)
+        })?;
+        Ok(raw[..len.min(raw.len())]
+            .iter()
+            .map(ProfileDesc::from_raw)
+            .collect())
+    }
+
+    /// Read the current value of an `FI_UINT64` variable.
+    pub fn read_u64(&self, var_id: u32) -> Result<u64> {
+        let mut value = 0;
+        check_len("fi_profile_read_u64", unsafe {
+            ffi::fi_profile_read_u64(self.as_raw(), var_id, &mut value)
+        })?;
+        Ok(value)
+    }
+
+    /// Run `f` between `fi_profile_start_reads()` and `fi_profile_end_reads()`, such that the
+    /// variables it reads come from a single snapshot.
+    pub fn snapshot<R>(&self, f: impl FnOnce(&Self) -> R) -> R {
+        unsafe { ffi::fi_profile_start_reads(self.as_raw(), 0) };
+        let ret = f(self);
+        unsafe { ffi::fi_profile_end_reads(self.as_raw(), 0) };
+        ret
+    }
+
+    /// Reset the variables of the profile.
+    pub fn reset(&self) {
+        unsafe { ffi::fi_profile_reset(self.as_raw(), 0) };
+    }
+
+    /// Call `callback` with the event id and data (if any) every time the event occurs.
+    ///
+    /// Callbacks run inline with the provider's progress, so they should be short, and must
+    /// not call back into the profile except to read variables. They stay registered until the
+    /// profile is closed.
+    pub fn register_callback(
+        &self,
+        event_id: u32,
+        callback: impl Fn(u32, &[u8]) + Send + Sync + 'static,
+    ) -> Result<()> {
+        let callback: Box<Callback> = Box::new(Box::new(callback));
+        let context = &*callback as *const Callback as *mut c_void;
+        let mut callbacks = self.inner.callbacks.lock().unwrap();
+        check("fi_profile_register_callback", unsafe {
+            ffi::fi_profile_register_callback(
+                self.as_raw(),
+                event_id,
+                Some(profile_callback),
+                context,
+            )
+        })?;
+        callbacks.push(callback);
+        Ok(())
+    }
+
+    pub fn as_raw(&self) -> *mut ffi::fid_profile {
+        self.inner.fid.as_ptr()
+    }
+}
+
+unsafe extern "C" fn profile_callback(
+    _prof: *mut ffi::fid_profile,
+    event: *mut ffi::fi_profile_desc,
+    param: *mut c_void,
+    size: usize,
+    context: *mut c_void,
+) -> c_int {
+    let callback = unsafe { &*context.cast::<Callback>() };
+    let data = if param.is_null() {
+        &[][..]
+    } else {
+        unsafe { std::slice::from_raw_parts(param.cast::<u8>(), size) }
+    };
+    callback(unsafe { (*event).id }, data);
+    0
+}
+
+impl AsRawFid for Profile {
+    fn as_raw_fid(&self) -> *mut ffi::fid {
+        self.inner.fid.as_fid()
+    }
+}
+
+impl Domain {
+    /// Open a profile of the domain, via `fi_profile_open()`.
+    pub fn profile(&self) -> Result<Profile> {
+        Profile::open(Target::Domain(self.clone()))
+    }
+}
+
+impl Endpoint {
+    /// Open a profile of the endpoint, via `fi_profile_open()`.
+    pub fn profile(&self) -> Result<Profile> {
+        Profile::open(Target::Endpoint(self.clone()))
+    }
+}
@@ -0,0 +1,405 @@
+use crate::attr::Progress;
+use crate::cq::CompletionQueue;
+use crate::domain::Domain;
+use crate::error::{Error, Result};
+use crate::info::{Nic, numa_cpus};
+use crate::threading::ThreadingModel;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How the provider of a domain progresses control and data operations, from
+/// [`Domain::progress_model()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProgressModel {
+    /// Connection management and address vector operations.
+    pub control: Progress,
+    /// Data transfers.
+    pub data: Progress,
+}
+
+impl ProgressModel {
+    /// Whether data transfers or control operations only progress from within calls into the
+    /// provider.
+    pub fn is_manual(&self) -> bool {
+        self.data == Progress::Manual || self.control == Progress::Manual
+    }
+}
+
+impl<M: ThreadingModel> Domain<M> {
+    /// How the provider progresses operations, from the domain attributes it was opened with:
+    /// manual with efa, tcp or shm, automatic with verbs, ex.
+    pub fn progress_model(&self) -> ProgressModel {
+        let attr = self.info().domain_attr();
+        ProgressModel {
+            control: attr.control_progress,
+            data: attr.data_progress,
+        }
+    }
+}
+
+impl Domain {
+    /// Progress `cqs` every `period` from a background thread, but only if the provider needs
+    /// it, per [`progress_model()`](Self::progress_model): with manual progress, operations
+    /// otherwise stall while the application does not read its queues, ex: from a thread
+    /// blocked on something else. Returns `None` with automatic progress, so the same code
+    /// works with both kinds of providers.
+    ///
+    /// The thread only drives progress, via [`CompletionQueue::progress()`], leaving the
+    /// completions to the application. It runs until the returned engine is dropped.
+    ///
+    /// ```no_run
+    /// # use libfabric::{CompletionQueue, Domain};
+    /// # fn run(domain: &Domain, cq: &CompletionQueue) -> libfabric::Result<()> {
+    /// use std::time::Duration;
+    ///
+    /// let _progress = domain.setup_progress(&[cq], Duration::from_micros(100));
+    /// // Post and wait for operations, progressed in the background if need be.
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn setup_progress(
+        &self,
+        cqs: &[&CompletionQueue],
+        period: Duration,
+    ) -> Option<ProgressEngine> {
+        self.progress_model()
+            .is_manual()
+            .then(|| ProgressEngine::spawn(cqs, period))
+    }
+
+    /// [`setup_progress()`](Self::setup_progress), with the thread placed per `attr`: next to
+    /// the NIC of the domain with [`ProgressAffinity::Nic`], unless `attr` names another.
+    ///
+    /// ```no_run
+    /// # use libfabric::{CompletionQueue, Domain};
+    /// # fn run(domain: &Domain, cq: &CompletionQueue) -> libfabric::Result<()> {
+    /// use libfabric::{ProgressAffinity, ProgressAttr};
+    ///
+    /// let attr = ProgressAttr::new().affinity(ProgressAffinity::Nic);
+    /// if let Some(progress) = domain.setup_progress_with(&[cq], &attr)? {
+    ///     println!("progressing on CPUs {:?}", progress.stats().cpus);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn setup_progress_with(
+        &self,
+        cqs: &[&CompletionQueue],
+        attr: &ProgressAttr,
+    ) -> Result<Option<ProgressEngine>> {
+        if !self.progress_model().is_manual() {
+            return Ok(None);
+        }
+        match (&attr.nic, self.info().nic()) {
+            (None, Some(nic)) => ProgressEngine::spawn_with(cqs, &attr.clone().nic(&nic)),
+            _ => ProgressEngine::spawn_with(cqs, attr),
+        }
+        .map(Some)
+    }
+}
+
+/// What progresses operations, and tells the time, as the timeouts, retries and heartbeats
+/// built on it see them, ex: [`post_with_retry_on()`](crate::post_with_retry_on) and
+/// [`Liveness::with_driver()`](crate::Liveness::with_driver).
+///
+/// [`SystemProgress`] progresses completion queues on the system clock. With the `mock`
+/// feature, [`MockProgress`](crate::mock::MockProgress) runs on a virtual clock, which only
+/// moves when the test advances it or the code under test sleeps, and polls in the order the
+/// test sets, so that such logic is unit tested without real time or hardware.
+pub trait ProgressDriver {
+    /// The time it is.
+    fn now(&self) -> Instant;
+
+    /// Progress the queues once.
+    fn progress(&self) -> Result<()>;
+
+    /// Let `duration` pass before going on.
+    fn sleep(&self, duration: Duration);
+}
+
+/// The progress of completion queues, via [`CompletionQueue::progress()`], on the system
+/// clock. Without queues, the default, it is only a clock.
+#[derive(Clone, Default)]
+pub struct SystemProgress {
+    cqs: Vec<CompletionQueue>,
+}
+
+impl SystemProgress {
+    pub fn new(cqs: &[&CompletionQueue]) -> Self {
+        SystemProgress {
+            cqs: cqs.iter().map(|&cq| cq.clone()).collect(),
+        }
+    }
+}
+
+impl ProgressDriver for SystemProgress {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn progress(&self) -> Result<()> {
+        self.cqs.iter().try_for_each(CompletionQueue::progress)
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+/// The CPUs the thread of a [`ProgressEngine`] is pinned to, on Linux.
+///
+/// Polling from another socket than that of the NIC crosses the interconnect on every
+/// completion, which shows in the latency: the NIC variants keep the thread next to it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ProgressAffinity {
+    /// Not pinned, running wherever the scheduler puts it.
+    #[default]
+    Any,
+    Cpus(Vec<usize>),
+    /// The CPUs of a NUMA node.
+    NumaNode(u32),
+    /// The CPUs local to the NIC, per [`Nic::local_cpus()`], or else those of its NUMA node.
+    Nic,
+    /// The CPUs the interrupts of the NIC are delivered to, per [`Nic::irq_cpus()`], whose
+    /// caches its completions are already hot in.
+    NicIrqs,
+}
+
+/// The settings of the thread of a [`ProgressEngine`].
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct ProgressAttr {
+    period: Duration,
+    affinity: ProgressAffinity,
+    nic: Option<Nic>,
+}
+
+impl Default for ProgressAttr {
+    fn default() -> Self {
+        ProgressAttr {
+            period: Duration::from_micros(100),
+            affinity: ProgressAffinity::Any,
+            nic: None,
+        }
+    }
+}
+
+impl ProgressAttr {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Progress the queues every `period`, 100µs by default.
+    pub fn period(mut self, period: Duration) -> Self {
+        self.period = period;
+        self
+    }
+
+    pub fn affinity(mut self, affinity: ProgressAffinity) -> Self {
+        self.affinity = affinity;
+        self
+    }
+
+    /// The NIC of the NIC affinities, that of the domain with
+    /// [`Domain::setup_progress_with()`].
+    pub fn nic(mut self, nic: &Nic) -> Self {
+        self.nic = Some(nic.clone());
+        self
+    }
+
+    // The CPUs to pin the thread to, none for any.
+    fn cpus(&self) -> Result<Vec<usize>> {
+        let nic = || {
+            self.nic
+                .as_ref()
+                .ok_or_else(|| Error::invalid("no NIC to place the progress thread next to"))
+        };
+        let unknown = |what: String| Error::invalid(format!("no CPU is known for {what}"));
+        let cpus = match &self.affinity {
+            ProgressAffinity::Any => return Ok(Vec::new()),
+            ProgressAffinity::Cpus(cpus) => cpus.clone(),
+            ProgressAffinity::NumaNode(node) => {
+                numa_cpus(*node).ok_or_else(|| unknown(format!("NUMA node {node}")))?
+            }
+            ProgressAffinity::Nic => {
+                let nic = nic()?;
+                nic.local_cpus()
+                    .or_else(|| nic.numa_node().and_then(numa_cpus))
+                    .ok_or_else(|| unknown(format!("NIC {}", nic.name)))?
+            }
+            ProgressAffinity::NicIrqs => {
+                let nic = nic()?;
+                (nic.irq_cpus())
+                    .ok_or_else(|| unknown(format!("the interrupts of {}", nic.name)))?
+            }
+        };
+        if cpus.is_empty() {
+            return Err(Error::invalid("no CPU to pin the progress thread to"));
+        }
+        Ok(cpus)
+    }
+}
+
+/// What the thread of a [`ProgressEngine`] did so far, from [`ProgressEngine::stats()`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProgressStats {
+    /// Times the thread progressed its queues.
+    pub passes: u64,
+    /// Time spent progressing the queues, the rest of the time being spent parked.
+    pub busy: Duration,
+    /// The CPUs the thread is pinned to, none if it is not.
+    pub cpus: Vec<usize>,
+    /// The CPU of the last pass, on Linux.
+    pub last_cpu: Option<usize>,
+    /// Passes which ran on another CPU than the previous one.
+    pub migrations: u64,
+}
+
+// Updated by the thread on every pass.
+#[derive(Default)]
+struct Counters {
+    passes: AtomicU64,
+    busy_ns: AtomicU64,
+    // usize::MAX while unknown.
+    last_cpu: AtomicUsize,
+    migrations: AtomicU64,
+}
+
+impl Counters {
+    fn pass(&self, busy: Duration) {
+        self.busy_ns
+            .fetch_add(busy.as_nanos() as u64, Ordering::Relaxed);
+        self.passes.fetch_add(1, Ordering::Relaxed);
+        #[cfg(target_os = "linux")]
+        if let Some(cpu) = crate::wait::current_cpu() {
+            let last = self.last_cpu.swap(cpu, Ordering::Relaxed);
+            if last != usize::MAX && last != cpu {
+                self.migrations.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// The background thread of [`Domain::setup_progress()`], stopped on drop.
+pub struct ProgressEngine {
+    stop: Arc<AtomicBool>,
+    // The first error of the queues, which stops the thread.
+    error: Arc<Mutex<Option<Error>>>,
+    thread: Option<JoinHandle<()>>,
+    counters: Arc<Counters>,
+    cpus: Vec<usize>,
+}
+
+impl ProgressEngine {
+    /// Progress `cqs` every `period` from a background thread, whatever the provider.
+    pub fn spawn(cqs: &[&CompletionQueue], period: Duration) -> Self {
+        Self::start(SystemProgress::new(cqs), period, Vec::new())
+            .expect("only pinning the thread fails")
+    }
+
+    /// [`spawn()`](Self::spawn), with the thread placed per `attr`. Fails when the CPUs of
+    /// its affinity are unknown, or the thread cannot be pinned to them.
+    pub fn spawn_with(cqs: &[&CompletionQueue], attr: &ProgressAttr) -> Result<Self> {
+        Self::start(SystemProgress::new(cqs), attr.period, attr.cpus()?)
+    }
+
+    /// [`spawn_with()`](Self::spawn_with), each pass progressing `driver` rather than
+    /// completion queues, ex: to progress other objects along with them.
+    pub fn spawn_driver(
+        driver: impl ProgressDriver + Send + 'static,
+        attr: &ProgressAttr,
+    ) -> Result<Self> {
+        Self::start(driver, attr.period, attr.cpus()?)
+    }
+
+    fn start(
+        driver: impl ProgressDriver + Send + 'static,
+        period: Duration,
+        cpus: Vec<usize>,
+    ) -> Result<Self> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let error = Arc::new(Mutex::new(None));
+        let counters = Arc::new(Counters {
+            last_cpu: AtomicUsize::new(usize::MAX),
+            ..Counters::default()
+        });
+        let (pinned, pinning) = mpsc::channel();
+        let thread = thread::spawn({
+            let (stop, error, counters) = (stop.clone(), error.clone(), counters.clone());
+            let cpus = cpus.clone();
+            move || {
+                let pinning = pin(&cpus);
+                let failed = pinning.is_err();
+                if pinned.send(pinning).is_err() || failed {
+                    return;
+                }
+                while !stop.load(Ordering::Acquire) {
+                    let start = Instant::now();
+                    if let Err(err) = driver.progress() {
+                        *error.lock().unwrap() = Some(err);
+                        return;
+                    }
+                    counters.pass(start.elapsed());
+                    thread::park_timeout(period);
+                }
+            }
+        });
+        let engine = ProgressEngine {
+            stop,
+            error,
+            thread: Some(thread),
+            counters,
+            cpus,
+        };
+        // Dropping the engine on failure joins the thread, which returns after pinning.
+        pinning.recv().expect("the thread reports its pinning")?;
+        Ok(engine)
+    }
+
+    /// The statistics of the thread so far.
+    pub fn stats(&self) -> ProgressStats {
+        let counters = &*self.counters;
+        let last_cpu = counters.last_cpu.load(Ordering::Relaxed);
+        ProgressStats {
+            passes: counters.passes.load(Ordering::Relaxed),
+            busy: Duration::from_nanos(counters.busy_ns.load(Ordering::Relaxed)),
+            cpus: self.cpus.clone(),
+            last_cpu: (last_cpu != usize::MAX).then_some(last_cpu),
+            migrations: counters.migrations.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Fails with the error which stopped the thread, if any.
+    pub fn check(&self) -> Result<()> {
+        match self.error.lock().unwrap().clone() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for ProgressEngine {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+// Pin the calling thread to `cpus`, if any.
+fn pin(cpus: &[usize]) -> Result<()> {
+    if cpus.is_empty() {
+        return Ok(());
+    }
+    #[cfg(target_os = "linux")]
+    return crate::wait::pin_thread(cpus);
+    #[cfg(not(target_os = "linux"))]
+    Err(Error::invalid("progress threads are only pinned on Linux"))
+}
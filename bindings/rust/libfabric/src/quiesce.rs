@@ -0,0 +1,386 @@
+use crate::av::AddressVector;
+use crate::cntr::Counter;
+use crate::cq::{
+    Completion, CompletionQueue, CqEntry, CqErrEntry, CqFormat, CtxCompletion, DataCompletion,
+    MsgCompletion,
+};
+use crate::domain::Domain;
+use crate::ep::{Endpoint, ScalableEndpoint};
+use crate::eq::EventQueue;
+use crate::error::{Error, Result};
+use crate::fabric::Fabric;
+use crate::fid::{AsRawFid, FidId};
+use crate::lifecycle::{Lifecycle, LifecycleEvent};
+use crate::mr::MemoryRegion;
+use crate::threading::{ThreadSafe, ThreadingModel};
+use ofi_libfabric_sys::bindgen as ffi;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// An object of the wrapper graph, closed by a Quiesce in the order of its stage.
+pub(crate) enum Object<M: ThreadingModel> {
+    Mr(MemoryRegion<M>),
+    Ep(Endpoint<M>),
+    Scalable(ScalableEndpoint<M>),
+    Cq(CompletionQueue<M>),
+    Cntr(Counter<M>),
+    Eq(EventQueue),
+    Av(AddressVector<M>),
+    Domain(Domain<M>),
+    Fabric(Fabric),
+}
+
+impl<M: ThreadingModel> Object<M> {
+    // Regions, then endpoints, then the objects bound to them, then domains, then fabrics.
+    fn stage(&self) -> usize {
+        match self {
+            Object::Mr(_) => 0,
+            Object::Ep(_) | Object::Scalable(_) => 1,
+            Object::Cq(_) | Object::Cntr(_) | Object::Eq(_) | Object::Av(_) => 2,
+            Object::Domain(_) => 3,
+            Object::Fabric(_) => 4,
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            Object::Mr(_) => "memory region",
+            Object::Ep(_) => "endpoint",
+            Object::Scalable(_) => "scalable endpoint",
+            Object::Cq(_) => "completion queue",
+            Object::Cntr(_) => "counter",
+            Object::Eq(_) => "event queue",
+            Object::Av(_) => "address vector",
+            Object::Domain(_) => "domain",
+            Object::Fabric(_) => "fabric",
+        }
+    }
+
+    fn id(&self) -> FidId {
+        match self {
+            Object::Mr(mr) => mr.id(),
+            Object::Ep(ep) => ep.id(),
+            Object::Scalable(sep) => sep.id(),
+            Object::Cq(cq) => cq.id(),
+            Object::Cntr(cntr) => cntr.id(),
+            Object::Eq(eq) => eq.id(),
+            Object::Av(av) => av.id(),
+            Object::Domain(domain) => domain.id(),
+            Object::Fabric(fabric) => fabric.id(),
+        }
+    }
+
+    fn handles(&self) -> usize {
+        match self {
+            Object::Mr(mr) => mr.handles(),
+            Object::Ep(ep) => ep.handles(),
+            Object::Scalable(sep) => sep.handles(),
+            Object::Cq(cq) => cq.handles(),
+            Object::Cntr(cntr) => cntr.handles(),
+            Object::Eq(eq) => eq.handles(),
+            Object::Av(av) => av.handles(),
+            Object::Domain(domain) => domain.handles(),
+            Object::Fabric(fabric) => fabric.handles(),
+        }
+    }
+
+    fn close(self) -> Result<()> {
+        match self {
+            Object::Mr(mr) => mr.close(),
+            Object::Ep(ep) => ep.close(),
+            Object::Scalable(sep) => sep.close(),
+            Object::Cq(cq) => cq.close(),
+            Object::Cntr(cntr) => cntr.close(),
+            Object::Eq(eq) => eq.close(),
+            Object::Av(av) => av.close(),
+            Object::Domain(domain) => domain.close(),
+            Object::Fabric(fabric) => fabric.close(),
+        }
+    }
+
+    // The objects this one keeps alive, closed after it.
+    fn parents(&self) -> Vec<Object<M>> {
+        let domain = |domain: &Domain<M>| vec![Object::Domain(domain.clone())];
+        match self {
+            Object::Mr(mr) => domain(mr.domain()),
+            Object::Ep(ep) => {
+                let mut parents = ep.bound_objects();
+                parents.extend(domain(ep.domain()));
+                parents
+            }
+            Object::Scalable(sep) => {
+                let mut parents = sep.bound_objects();
+                parents.extend(domain(sep.domain()));
+                parents
+            }
+            Object::Cq(cq) => domain(cq.domain()),
+            Object::Cntr(cntr) => domain(cntr.domain()),
+            Object::Eq(eq) => vec![Object::Fabric(eq.fabric().clone())],
+            Object::Av(av) => domain(av.domain()),
+            Object::Domain(domain) => vec![Object::Fabric(domain.fabric().clone())],
+            Object::Fabric(_) => Vec::new(),
+        }
+    }
+}
+
+/// What [`Quiesce::run()`] drained and closed.
+#[derive(Debug, Default)]
+pub struct QuiesceReport {
+    /// The contexts of the operations which completed while draining, in flight or not.
+    pub completed: Vec<usize>,
+    /// The errors of the operations which failed meanwhile, with `FI_ECANCELED` for those
+    /// the provider cancelled.
+    pub errors: Vec<CqErrEntry>,
+    /// The operations in flight still unaccounted for when the timeout expired. Their buffers
+    /// are no longer accessed once their endpoint is closed.
+    pub abandoned: Vec<usize>,
+    /// The successes and errors of each counter, read once the queues were drained.
+    pub counters: Vec<(FidId, u64, u64)>,
+    /// The objects closed, in order.
+    pub closed: Vec<FidId>,
+}
+
+/// A checked shutdown of a set of objects: their operations in flight are cancelled and
+/// drained, their counters read, and the objects closed in the order libfabric requires,
+/// memory regions, then endpoints, then queues, counters and address vectors, then domains,
+/// then fabrics.
+///
+/// Objects are closed with `fi_close()` as soon as their last handle is dropped, in whichever
+/// order the application drops them, and the errors of `fi_close()` are lost, ex: a domain
+/// whose regions are still open. A quiesce surfaces them instead, and fails naming the first
+/// object which cannot be closed, when the application still holds other handles to it, or to
+/// the objects opened from it.
+///
+/// The objects bound to an endpoint, and the parents of every object, are closed along with
+/// it, so that quiescing an endpoint closes everything up to its fabric.
+///
+/// ```no_run
+/// # use libfabric::{Endpoint, MemoryRegion};
+/// # fn run(ep: Endpoint, mr: MemoryRegion, recvs: &[usize]) -> libfabric::Result<()> {
+/// use libfabric::Quiesce;
+/// use std::time::Duration;
+///
+/// let report = Quiesce::new()
+///     .timeout(Duration::from_secs(1))
+///     .mr(mr)
+///     .endpoint(ep, recvs)
+///     .run()?;
+/// assert!(report.abandoned.is_empty());
+/// # Ok(())
+/// # }
+/// ```
+#[must_use]
+pub struct Quiesce<M: ThreadingModel = ThreadSafe> {
+    timeout: Duration,
+    objects: Vec<Object<M>>,
+    // The operations in flight, by endpoint.
+    in_flight: Vec<(FidId, usize)>,
+    lifecycle: Option<Lifecycle>,
+}
+
+impl<M: ThreadingModel> Default for Quiesce<M> {
+    fn default() -> Self {
+        Quiesce {
+            timeout: Duration::from_secs(1),
+            objects: Vec::new(),
+            in_flight: Vec::new(),
+            lifecycle: None,
+        }
+    }
+}
+
+impl<M: ThreadingModel> Quiesce<M> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How long to drain the operations in flight for, 1s by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Close `ep`, cancelling the operations posted with the contexts `in_flight` and
+    /// draining their completions first.
+    pub fn endpoint(mut self, ep: Endpoint<M>, in_flight: &[usize]) -> Self {
+        let id = ep.id();
+        self.in_flight
+            .extend(in_flight.iter().map(|&context| (id, context)));
+        self.add(Object::Ep(ep));
+        self
+    }
+
+    /// Close `sep` once its contexts, which keep it alive, are closed.
+    pub fn scalable_endpoint(mut self, sep: ScalableEndpoint<M>) -> Self {
+        self.add(Object::Scalable(sep));
+        self
+    }
+
+    pub fn mr(mut self, mr: MemoryRegion<M>) -> Self {
+        self.add(Object::Mr(mr));
+        self
+    }
+
+    pub fn cq(mut self, cq: CompletionQueue<M>) -> Self {
+        self.add(Object::Cq(cq));
+        self
+    }
+
+    pub fn counter(mut self, cntr: Counter<M>) -> Self {
+        self.add(Object::Cntr(cntr));
+        self
+    }
+
+    pub fn eq(mut self, eq: EventQueue) -> Self {
+        self.add(Object::Eq(eq));
+        self
+    }
+
+    pub fn av(mut self, av: AddressVector<M>) -> Self {
+        self.add(Object::Av(av));
+        self
+    }
+
+    pub fn domain(mut self, domain: Domain<M>) -> Self {
+        self.add(Object::Domain(domain));
+        self
+    }
+
+    pub fn fabric(mut self, fabric: Fabric) -> Self {
+        self.add(Object::Fabric(fabric));
+        self
+    }
+
+    /// Publish a [`LifecycleEvent::DomainClosing`] on `lifecycle` before each domain is
+    /// closed.
+    pub fn lifecycle(mut self, lifecycle: &Lifecycle) -> Self {
+        self.lifecycle = Some(lifecycle.clone());
+        self
+    }
+
+    // Add `object` and its parents, dropping the handles to objects added already.
+    fn add(&mut self, object: Object<M>) {
+        if self.objects.iter().any(|added| added.id() == object.id()) {
+            return;
+        }
+        let parents = object.parents();
+        self.objects.push(object);
+        for parent in parents {
+            self.add(parent);
+        }
+    }
+
+    /// Cancel and drain the operations in flight, read the counters, then close every object.
+    ///
+    /// Fails with the errors of the queues, and of `fi_close()`, or when an object still has
+    /// handles besides those of the quiesce, once the objects of the previous stages are
+    /// closed; the objects left are then closed as their handles are dropped.
+    pub fn run(self) -> Result<QuiesceReport> {
+        let Quiesce {
+            timeout,
+            mut objects,
+            in_flight,
+            lifecycle,
+        } = self;
+        let mut report = QuiesceReport::default();
+
+        let mut pending = Vec::new();
+        for (id, context) in in_flight {
+            let ep = objects.iter().find_map(|object| match object {
+                Object::Ep(ep) if ep.id() == id => Some(ep),
+                _ => None,
+            });
+            match ep.map(|ep| ep.cancel(context)) {
+                // The operation completed already.
+                Some(Err(err)) if err.code() == ffi::FI_ENOENT as i32 => {}
+                Some(Err(err)) => return Err(err),
+                _ => pending.push(context),
+            }
+        }
+        let cqs: Vec<CompletionQueue<M>> = objects
+            .iter()
+            .filter_map(|object| match object {
+                Object::Cq(cq) => Some(cq.clone()),
+                _ => None,
+            })
+            .collect();
+        let deadline = Instant::now() + timeout;
+        loop {
+            for cq in &cqs {
+                drain(cq, &mut report)?;
+            }
+            pending.retain(|context| {
+                !report.completed.contains(context)
+                    && !report.errors.iter().any(|entry| entry.context == *context)
+            });
+            if pending.is_empty() || Instant::now() >= deadline {
+                break;
+            }
+            thread::yield_now();
+        }
+        report.abandoned = pending;
+        drop(cqs);
+        for object in &objects {
+            if let Object::Cntr(cntr) = object {
+                report
+                    .counters
+                    .push((cntr.id(), cntr.read(), cntr.read_err()));
+            }
+        }
+
+        // Objects of a stage may keep others of the same alive, ex: aliases their endpoint,
+        // so each stage closes those with no other handle until none is left.
+        objects.sort_by_key(Object::stage);
+        while let Some(stage) = objects.first().map(Object::stage) {
+            let end = objects.partition_point(|object| object.stage() == stage);
+            let Some(last) = objects[..end]
+                .iter()
+                .position(|object| object.handles() == 1)
+            else {
+                let object = &objects[0];
+                return Err(Error::invalid(format!(
+                    "cannot close the {} {:?}: {} other handles to it, or to objects opened \
+                     from it, are still alive",
+                    object.kind(),
+                    object.id(),
+                    object.handles() - 1
+                )));
+            };
+            let object = objects.remove(last);
+            let id = object.id();
+            if let (Object::Domain(_), Some(lifecycle)) = (&object, &lifecycle) {
+                lifecycle.publish(LifecycleEvent::DomainClosing { fid: id });
+            }
+            object.close()?;
+            report.closed.push(id);
+        }
+        Ok(report)
+    }
+}
+
+// Read the completions of `cq` until it is empty, in its format.
+fn drain<M: ThreadingModel>(cq: &CompletionQueue<M>, report: &mut QuiesceReport) -> Result<()> {
+    match cq.format() {
+        CqFormat::Context => drain_as::<M, CtxCompletion>(cq, report),
+        CqFormat::Msg => drain_as::<M, MsgCompletion>(cq, report),
+        CqFormat::Data => drain_as::<M, DataCompletion>(cq, report),
+        CqFormat::Tagged => drain_as::<M, Completion>(cq, report),
+    }
+}
+
+fn drain_as<M: ThreadingModel, E: CqEntry>(
+    cq: &CompletionQueue<M>,
+    report: &mut QuiesceReport,
+) -> Result<()> {
+    let mut buf = [E::default(); 16];
+    loop {
+        match cq.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(n) => report
+                .completed
+                .extend(buf[..n].iter().map(CqEntry::context)),
+            Err(err) if err.is_avail() => report.errors.extend(cq.read_err()?),
+            Err(err) => return Err(err),
+        }
+    }
+}
@@ -0,0 +1,582 @@
+use crate::av::{Addr, EndpointAddress};
+use crate::cq::{Completion, CqErrEntry};
+use crate::error::{Error, Result};
+use crate::timeline::{self, TraceFormat};
+use crate::transport::{Cq, Transport};
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// The data transfer operation of an [`OpRecord`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OpKind {
+    Recv,
+    Send,
+    SendData,
+    Inject,
+    TRecv,
+    TSend,
+    TSendData,
+    TInject,
+    Read,
+    Write,
+    WriteData,
+}
+
+impl OpKind {
+    /// The libfabric call, ex: `fi_tsend`.
+    pub fn name(self) -> &'static str {
+        match self {
+            OpKind::Recv => "fi_recv",
+            OpKind::Send => "fi_send",
+            OpKind::SendData => "fi_senddata",
+            OpKind::Inject => "fi_inject",
+            OpKind::TRecv => "fi_trecv",
+            OpKind::TSend => "fi_tsend",
+            OpKind::TSendData => "fi_tsenddata",
+            OpKind::TInject => "fi_tinject",
+            OpKind::Read => "fi_read",
+            OpKind::Write => "fi_write",
+            OpKind::WriteData => "fi_writedata",
+        }
+    }
+}
+
+/// Where an [`OpRecord`] stands.
+#[derive(Debug, Clone)]
+pub enum OpStatus {
+    /// Posted, with no completion read yet.
+    Pending,
+    /// Completed successfully, with the flags of its completion. Injected operations, which
+    /// have none, are completed as soon as posted, with no flags.
+    Completed { at: Duration, flags: u64 },
+    /// Completed in error.
+    Failed { at: Duration, error: Error },
+    /// Not posted, the call failed, ex: with `FI_EAGAIN`.
+    Rejected(Error),
+}
+
+/// An operation posted on a [`RecordingEndpoint`], and its completion.
+#[derive(Debug, Clone)]
+pub struct OpRecord {
+    /// The order of the operation among those of the recorder.
+    pub seq: u64,
+    /// The endpoint, numbered in the order the recorder wrapped them.
+    pub endpoint: usize,
+    pub kind: OpKind,
+    /// The length of the buffer.
+    pub len: usize,
+    /// The destination, or the source a receive or read is posted for.
+    pub peer: Addr,
+    pub tag: Option<u64>,
+    pub context: usize,
+    /// When the operation was posted, since the recorder was created.
+    pub posted: Duration,
+    pub status: OpStatus,
+}
+
+impl OpRecord {
+    /// The time from posting to completion, if completed.
+    pub fn latency(&self) -> Option<Duration> {
+        match self.status {
+            OpStatus::Completed { at, .. } | OpStatus::Failed { at, .. } => {
+                Some(at.saturating_sub(self.posted))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for OpRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "#{} {:.6}s ep{} {} len={} peer={:#x} ctx={:#x}",
+            self.seq,
+            self.posted.as_secs_f64(),
+            self.endpoint,
+            self.kind.name(),
+            self.len,
+            self.peer.as_raw(),
+            self.context
+        )?;
+        if let Some(tag) = self.tag {
+            write!(f, " tag={tag:#x}")?;
+        }
+        match &self.status {
+            OpStatus::Pending => write!(f, " pending"),
+            OpStatus::Completed { at, flags } => {
+                write!(f, " completed {:.6}s flags={flags:#x}", at.as_secs_f64())
+            }
+            OpStatus::Failed { at, error } => {
+                write!(f, " failed {:.6}s: {error}", at.as_secs_f64())
+            }
+            OpStatus::Rejected(error) => write!(f, " rejected: {error}"),
+        }
+    }
+}
+
+/// A record of the operations posted on the endpoints it wraps, and of their completions read
+/// from the queues it wraps, to diagnose hangs and ordering bugs.
+///
+/// The last operations are kept in a ring buffer, also written, as they are posted and
+/// completed, to the file set by [`log_to()`](Self::log_to). With
+/// [`dump_on_error()`](Self::dump_on_error), the ring buffer is written out on each error
+/// completion, or failed post other than `FI_EAGAIN`.
+///
+/// Completions are matched with the oldest pending operation of the same context, on any of
+/// the endpoints of the recorder: contexts unique across them match exactly.
+///
+/// ```no_run
+/// use libfabric::{CompletionQueue, Endpoint, Recorder};
+///
+/// # fn run(ep: Endpoint, cq: CompletionQueue) -> libfabric::Result<()> {
+/// let recorder = Recorder::new(1024).dump_on_error("/tmp/fabric-ops.txt");
+/// let (ep, cq) = (recorder.endpoint(ep), recorder.cq(cq));
+/// // Run over `ep` and `cq` through the transport traits, then, on a hang:
+/// for op in recorder.pending() {
+///     eprintln!("{op}");
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+#[must_use]
+pub struct Recorder {
+    inner: Arc<Mutex<State>>,
+}
+
+struct State {
+    start: Instant,
+    // The time of `start` on CLOCK_MONOTONIC, in nanoseconds.
+    epoch: u64,
+    capacity: usize,
+    ops: VecDeque<OpRecord>,
+    seq: u64,
+    endpoints: usize,
+    log: Option<File>,
+    dump: Option<PathBuf>,
+}
+
+impl State {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn push(&mut self, op: OpRecord) {
+        if self.ops.len() == self.capacity {
+            self.ops.pop_front();
+        }
+        self.log(&op);
+        let failed = matches!(&op.status, OpStatus::Rejected(err) if !err.is_again());
+        self.ops.push_back(op);
+        if failed {
+            self.dump();
+        }
+    }
+
+    fn complete(&mut self, context: usize, status: OpStatus) {
+        let failed = matches!(status, OpStatus::Failed { .. });
+        let Some(index) = self
+            .ops
+            .iter()
+            .position(|op| matches!(op.status, OpStatus::Pending) && op.context == context)
+        else {
+            return;
+        };
+        self.ops[index].status = status;
+        let op = self.ops[index].clone();
+        self.log(&op);
+        if failed {
+            self.dump();
+        }
+    }
+
+    // Errors writing the record are ignored, rather than failing the operations.
+    fn log(&mut self, op: &OpRecord) {
+        if let Some(file) = &mut self.log {
+            let _ = writeln!(file, "{op}");
+        }
+    }
+
+    fn dump(&self) {
+        if let Some(path) = &self.dump {
+            let _ = File::create(path).and_then(|file| write_ops(file, &self.ops));
+        }
+    }
+}
+
+fn write_ops<'a>(mut w: impl Write, ops: impl IntoIterator<Item = &'a OpRecord>) -> io::Result<()> {
+    for op in ops {
+        writeln!(w, "{op}")?;
+    }
+    w.flush()
+}
+
+impl Recorder {
+    /// Keep the last `capacity` operations.
+    pub fn new(capacity: usize) -> Self {
+        Recorder {
+            inner: Arc::new(Mutex::new(State {
+                start: Instant::now(),
+                epoch: timeline::monotonic_ns(),
+                capacity: capacity.max(1),
+                ops: VecDeque::new(),
+                seq: 0,
+                endpoints: 0,
+                log: None,
+                dump: None,
+            })),
+        }
+    }
+
+    /// Append a line to `path` each time an operation is posted, and each time it completes.
+    pub fn log_to(self, path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::options().create(true).append(true).open(path)?;
+        self.lock().log = Some(file);
+        Ok(self)
+    }
+
+    /// Write the operations kept to `path`, replacing its content, on each error.
+    pub fn dump_on_error(self, path: impl Into<PathBuf>) -> Self {
+        self.lock().dump = Some(path.into());
+        self
+    }
+
+    /// Record the operations posted on `ep`.
+    pub fn endpoint<T: Transport>(&self, ep: T) -> RecordingEndpoint<T> {
+        let mut state = self.lock();
+        state.endpoints += 1;
+        RecordingEndpoint {
+            inner: ep,
+            recorder: self.clone(),
+            index: state.endpoints - 1,
+        }
+    }
+
+    /// Record the completions read from `cq`.
+    pub fn cq<C: Cq>(&self, cq: C) -> RecordingCq<C> {
+        RecordingCq {
+            inner: cq,
+            recorder: self.clone(),
+        }
+    }
+
+    /// The operations kept, oldest first.
+    pub fn ops(&self) -> Vec<OpRecord> {
+        self.lock().ops.iter().cloned().collect()
+    }
+
+    /// The operations kept whose completion was not read yet, oldest first.
+    pub fn pending(&self) -> Vec<OpRecord> {
+        let state = self.lock();
+        let pending = state
+            .ops
+            .iter()
+            .filter(|op| matches!(op.status, OpStatus::Pending));
+        pending.cloned().collect()
+    }
+
+    /// Write the operations kept to `w`, one line each, oldest first.
+    pub fn dump(&self, w: impl Write) -> Result<()> {
+        Ok(write_ops(w, &self.lock().ops)?)
+    }
+
+    /// Write the operations kept to `w` as a trace of `format`, so that they show on a
+    /// timeline next to a CPU profile of the application.
+    ///
+    /// Each operation is a slice from its post to its completion, named after its call, ex:
+    /// `fi_tsend`, with its peer, length, tag, context and status as arguments. Pending
+    /// operations last until now, and rejected posts are instants. The operations of each
+    /// endpoint are laid out on the tracks `libfabric ep<n>` of the process, as many as they
+    /// overlap, at times of `CLOCK_MONOTONIC` on Linux, which perf and Perfetto also read.
+    pub fn export_trace(&self, w: impl Write, format: TraceFormat) -> Result<()> {
+        let state = self.lock();
+        let ops: Vec<_> = state.ops.iter().cloned().collect();
+        let (epoch, now) = (state.epoch, state.now());
+        drop(state);
+        Ok(timeline::write_trace(w, format, &ops, epoch, now)?)
+    }
+
+    pub fn clear(&self) {
+        self.lock().ops.clear();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.inner.lock().unwrap()
+    }
+}
+
+/// An endpoint whose operations are recorded, made by [`Recorder::endpoint()`].
+#[derive(Clone)]
+pub struct RecordingEndpoint<T> {
+    inner: T,
+    recorder: Recorder,
+    index: usize,
+}
+
+impl<T> RecordingEndpoint<T> {
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    // Post an operation and record it, completing injected ones at once. The recorder stays
+    // locked meanwhile, so its completion cannot be read before it is recorded.
+    fn record(
+        &self,
+        kind: OpKind,
+        len: usize,
+        peer: Addr,
+        tag: Option<u64>,
+        context: usize,
+        post: impl FnOnce() -> Result<()>,
+    ) -> Result<()> {
+        let mut state = self.recorder.lock();
+        let posted = state.now();
+        let result = post();
+        let status = match &result {
+            Err(err) => OpStatus::Rejected(err.clone()),
+            Ok(()) if matches!(kind, OpKind::Inject | OpKind::TInject) => OpStatus::Completed {
+                at: posted,
+                flags: 0,
+            },
+            Ok(()) => OpStatus::Pending,
+        };
+        state.seq += 1;
+        let op = OpRecord {
+            seq: state.seq - 1,
+            endpoint: self.index,
+            kind,
+            len,
+            peer,
+            tag,
+            context,
+            posted,
+            status,
+        };
+        state.push(op);
+        result
+    }
+}
+
+impl<T: Transport> Transport for RecordingEndpoint<T> {
+    type Mr = T::Mr;
+
+    fn name(&self) -> Result<EndpointAddress> {
+        self.inner.name()
+    }
+
+    unsafe fn recv(
+        &self,
+        buf: &mut [u8],
+        mr: Option<&T::Mr>,
+        src: Addr,
+        context: usize,
+    ) -> Result<()> {
+        let len = buf.len();
+        self.record(OpKind::Recv, len, src, None, context, || unsafe {
+            self.inner.recv(buf, mr, src, context)
+        })
+    }
+
+    unsafe fn send(
+        &self,
+        buf: &[u8],
+        mr: Option<&T::Mr>,
+        dest: Addr,
+        context: usize,
+    ) -> Result<()> {
+        self.record(OpKind::Send, buf.len(), dest, None, context, || unsafe {
+            self.inner.send(buf, mr, dest, context)
+        })
+    }
+
+    unsafe fn senddata(
+        &self,
+        buf: &[u8],
+        mr: Option<&T::Mr>,
+        data: u64,
+        dest: Addr,
+        context: usize,
+    ) -> Result<()> {
+        self.record(
+            OpKind::SendData,
+            buf.len(),
+            dest,
+            None,
+            context,
+            || unsafe { self.inner.senddata(buf, mr, data, dest, context) },
+        )
+    }
+
+    fn inject(&self, buf: &[u8], dest: Addr) -> Result<()> {
+        self.record(OpKind::Inject, buf.len(), dest, None, 0, || {
+            self.inner.inject(buf, dest)
+        })
+    }
+
+    unsafe fn trecv(
+        &self,
+        buf: &mut [u8],
+        mr: Option<&T::Mr>,
+        src: Addr,
+        tag: u64,
+        ignore: u64,
+        context: usize,
+    ) -> Result<()> {
+        let len = buf.len();
+        self.record(OpKind::TRecv, len, src, Some(tag), context, || unsafe {
+            self.inner.trecv(buf, mr, src, tag, ignore, context)
+        })
+    }
+
+    unsafe fn tsend(
+        &self,
+        buf: &[u8],
+        mr: Option<&T::Mr>,
+        dest: Addr,
+        tag: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.record(
+            OpKind::TSend,
+            buf.len(),
+            dest,
+            Some(tag),
+            context,
+            || unsafe { self.inner.tsend(buf, mr, dest, tag, context) },
+        )
+    }
+
+    unsafe fn tsenddata(
+        &self,
+        buf: &[u8],
+        mr: Option<&T::Mr>,
+        data: u64,
+        dest: Addr,
+        tag: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.record(
+            OpKind::TSendData,
+            buf.len(),
+            dest,
+            Some(tag),
+            context,
+            || unsafe { self.inner.tsenddata(buf, mr, data, dest, tag, context) },
+        )
+    }
+
+    fn tinject(&self, buf: &[u8], dest: Addr, tag: u64) -> Result<()> {
+        self.record(OpKind::TInject, buf.len(), dest, Some(tag), 0, || {
+            self.inner.tinject(buf, dest, tag)
+        })
+    }
+
+    unsafe fn read(
+        &self,
+        buf: &mut [u8],
+        mr: Option<&T::Mr>,
+        src: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()> {
+        let len = buf.len();
+        self.record(OpKind::Read, len, src, None, context, || unsafe {
+            self.inner.read(buf, mr, src, addr, key, context)
+        })
+    }
+
+    unsafe fn write(
+        &self,
+        buf: &[u8],
+        mr: Option<&T::Mr>,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.record(OpKind::Write, buf.len(), dest, None, context, || unsafe {
+            self.inner.write(buf, mr, dest, addr, key, context)
+        })
+    }
+
+    unsafe fn writedata(
+        &self,
+        buf: &[u8],
+        mr: Option<&T::Mr>,
+        data: u64,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.record(
+            OpKind::WriteData,
+            buf.len(),
+            dest,
+            None,
+            context,
+            || unsafe {
+                self.inner
+                    .writedata(buf, mr, data, dest, addr, key, context)
+            },
+        )
+    }
+}
+
+/// A completion queue whose completions are recorded, made by [`Recorder::cq()`].
+#[derive(Clone)]
+pub struct RecordingCq<C> {
+    inner: C,
+    recorder: Recorder,
+}
+
+impl<C> RecordingCq<C> {
+    pub fn get_ref(&self) -> &C {
+        &self.inner
+    }
+
+    fn completed(&self, completions: &[Completion]) {
+        let mut state = self.recorder.lock();
+        let at = state.now();
+        for completion in completions {
+            let flags = completion.flags();
+            state.complete(completion.context(), OpStatus::Completed { at, flags });
+        }
+    }
+}
+
+impl<C: Cq> Cq for RecordingCq<C> {
+    fn read(&self, out: &mut [Completion]) -> Result<usize> {
+        let n = self.inner.read(out)?;
+        self.completed(&out[..n]);
+        Ok(n)
+    }
+
+    fn read_from(&self, out: &mut [Completion], src: &mut [Addr]) -> Result<usize> {
+        let n = self.inner.read_from(out, src)?;
+        self.completed(&out[..n]);
+        Ok(n)
+    }
+
+    fn read_err(&self) -> Result<Option<CqErrEntry>> {
+        let entry = self.inner.read_err()?;
+        if let Some(entry) = &entry {
+            let mut state = self.recorder.lock();
+            let at = state.now();
+            let error = entry.error.clone();
+            state.complete(entry.context, OpStatus::Failed { at, error });
+        }
+        Ok(entry)
+    }
+}
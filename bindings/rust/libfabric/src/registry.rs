@@ -0,0 +1,233 @@
+use crate::error::Result;
+use crate::flags::Caps;
+use crate::info::{EndpointType, Info, InfoEntry, discover_all};
+use crate::select::{SelectionPolicy, layers};
+use std::sync::{Mutex, OnceLock};
+
+static GLOBAL: OnceLock<ProviderRegistry> = OnceLock::new();
+
+/// Which entries of a [`ProviderRegistry`] [`ProviderRegistry::query()`] returns.
+///
+/// Every criterion set must match; none are set by default, matching every entry.
+#[derive(Debug, Clone, Default)]
+#[must_use]
+pub struct ProviderQuery {
+    caps: Caps,
+    ep_type: Option<EndpointType>,
+    provider: Option<String>,
+    fabric_name: Option<String>,
+    domain_name: Option<String>,
+    nic: Option<String>,
+}
+
+impl ProviderQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Entries with all of `caps`.
+    pub fn caps(mut self, caps: Caps) -> Self {
+        self.caps |= caps;
+        self
+    }
+
+    pub fn ep_type(mut self, ep_type: EndpointType) -> Self {
+        self.ep_type = Some(ep_type);
+        self
+    }
+
+    /// Entries of `provider`, be it their core provider, one of their layers or their whole
+    /// name, e.g. `"tcp"` matches `"tcp;ofi_rxm"` entries too.
+    pub fn provider(mut self, provider: &str) -> Self {
+        self.provider = Some(provider.to_owned());
+        self
+    }
+
+    pub fn fabric_name(mut self, name: &str) -> Self {
+        self.fabric_name = Some(name.to_owned());
+        self
+    }
+
+    pub fn domain_name(mut self, name: &str) -> Self {
+        self.domain_name = Some(name.to_owned());
+        self
+    }
+
+    /// Entries going through the NIC named `name`, ex: `"eth0"` or `"mlx5_0"`.
+    pub fn nic(mut self, name: &str) -> Self {
+        self.nic = Some(name.to_owned());
+        self
+    }
+
+    fn matches(&self, entry: &InfoEntry) -> bool {
+        let is = |wanted: &Option<String>, name: &str| wanted.as_ref().is_none_or(|w| w == name);
+        entry.caps().contains(self.caps)
+            && self
+                .ep_type
+                .is_none_or(|ep_type| entry.ep_type() == ep_type)
+            && self
+                .provider
+                .as_ref()
+                .is_none_or(|p| entry.provider_name() == p || layers(entry).any(|layer| layer == p))
+            && is(&self.fabric_name, entry.fabric_name())
+            && is(&self.domain_name, entry.domain_name())
+            && self
+                .nic
+                .as_ref()
+                .is_none_or(|name| entry.nic().is_some_and(|nic| &nic.name == name))
+    }
+}
+
+/// A fabric and one of its domains, as [`ProviderRegistry::fabric_domains()`] lists them.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FabricDomain {
+    pub provider: String,
+    pub fabric: String,
+    pub domain: String,
+}
+
+impl FabricDomain {
+    /// The query for the entries of the domain.
+    pub fn query(&self) -> ProviderQuery {
+        ProviderQuery::new()
+            .provider(&self.provider)
+            .fabric_name(&self.fabric)
+            .domain_name(&self.domain)
+    }
+}
+
+/// The entries of a single run of `fi_getinfo()`, queried any number of times without running
+/// discovery again, which may take long: providers probe their devices, and some resolve
+/// addresses. Applications opening many domains, or picking entries in several places, query
+/// a registry instead.
+///
+/// Queries return clones of the entries, independent of the registry. Entries describe the
+/// node as it was at discovery: [`refresh()`](Self::refresh) runs it again, ex: once a link
+/// came up.
+///
+/// ```no_run
+/// use libfabric::{Caps, EndpointType, ProviderQuery, ProviderRegistry};
+/// # fn run() -> libfabric::Result<()> {
+/// let registry = ProviderRegistry::global()?;
+/// let query = ProviderQuery::new()
+///     .ep_type(EndpointType::Rdm)
+///     .caps(Caps::TAGGED)
+///     .provider("tcp");
+/// for entry in registry.query(&query) {
+///     println!("{} {}", entry.provider_name(), entry.domain_name());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct ProviderRegistry {
+    // Info is not Sync, which the global registry must be.
+    hints: Option<Mutex<Info>>,
+    entries: Vec<InfoEntry>,
+}
+
+impl ProviderRegistry {
+    /// Discover every entry of every provider, via `fi_getinfo()` without hints.
+    pub fn discover() -> Result<Self> {
+        Ok(ProviderRegistry {
+            hints: None,
+            entries: discover_all()?,
+        })
+    }
+
+    /// Discover the entries matching `hints`, ex: to request the modes and registration modes
+    /// the application supports, which discovery without hints leaves out.
+    pub fn with_hints(hints: Info) -> Result<Self> {
+        let entries = hints.get()?;
+        Ok(ProviderRegistry {
+            hints: Some(Mutex::new(hints)),
+            entries,
+        })
+    }
+
+    /// The registry of the process, discovering every entry on first use.
+    pub fn global() -> Result<&'static ProviderRegistry> {
+        if let Some(registry) = GLOBAL.get() {
+            return Ok(registry);
+        }
+        // Racing threads discover as well, and all but the first registry are dropped.
+        let _ = GLOBAL.set(Self::discover()?);
+        Ok(GLOBAL.get().unwrap())
+    }
+
+    /// Run discovery again, with the same hints.
+    pub fn refresh(&mut self) -> Result<()> {
+        self.entries = match &mut self.hints {
+            Some(hints) => hints.get_mut().unwrap().get()?,
+            None => discover_all()?,
+        };
+        Ok(())
+    }
+
+    /// Every entry, in the order of `fi_getinfo()`.
+    pub fn entries(&self) -> &[InfoEntry] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The providers of the entries, as [`available_providers()`](crate::available_providers)
+    /// lists them.
+    pub fn providers(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .entries
+            .iter()
+            .flat_map(|entry| layers(entry).map(str::to_owned))
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// The fabrics and domains of the entries, sorted, each once: on hosts on several fabrics,
+    /// the names to open them by, with [`Fabric::open_named()`](crate::Fabric::open_named) and
+    /// [`Fabric::domain_named()`](crate::Fabric::domain_named).
+    pub fn fabric_domains(&self) -> Vec<FabricDomain> {
+        let mut pairs: Vec<_> = self
+            .entries
+            .iter()
+            .map(|entry| FabricDomain {
+                provider: entry.provider_name().to_owned(),
+                fabric: entry.fabric_name().to_owned(),
+                domain: entry.domain_name().to_owned(),
+            })
+            .collect();
+        pairs.sort();
+        pairs.dedup();
+        pairs
+    }
+
+    /// Clones of the entries matching `query`, in the order of `fi_getinfo()`.
+    pub fn query(&self, query: &ProviderQuery) -> Vec<InfoEntry> {
+        let entries = self.entries.iter().filter(|entry| query.matches(entry));
+        entries.cloned().collect()
+    }
+
+    /// The first entry matching `query`, if any.
+    pub fn first(&self, query: &ProviderQuery) -> Option<InfoEntry> {
+        self.entries
+            .iter()
+            .find(|entry| query.matches(entry))
+            .cloned()
+    }
+
+    /// The entries matching `query`, filtered and ranked by `policy` as by
+    /// [`select_provider()`](crate::select_provider), which they fail like when none is left.
+    pub fn select(
+        &self,
+        query: &ProviderQuery,
+        policy: &SelectionPolicy,
+    ) -> Result<Vec<InfoEntry>> {
+        policy.apply(self.query(query))
+    }
+}
@@ -0,0 +1,495 @@
+use crate::av::Addr;
+use crate::cq::{Completion, CqErrEntry};
+use crate::error::{Error, Result};
+use crate::flags::{Caps, MrMode};
+use crate::info::InfoEntry;
+use crate::transport::{Cq, CqHandler, Mr, Transport, poll_cq};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+// The tags of eager messages, of the descriptors of rendezvous messages, and of the
+// notifications of their receivers once they have read them.
+const EAGER: u64 = 0;
+const RTS: u64 = 1;
+const FIN: u64 = 2;
+// The bits receives match on.
+const KIND: u64 = 0x3;
+
+// The address, key and length of the buffer of a rendezvous message.
+const DESCRIPTOR_LEN: usize = 24;
+
+/// Attributes of a [`Rendezvous`], which must be the same on all peers.
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct RendezvousAttr {
+    threshold: usize,
+    depth: usize,
+    virt_addr: bool,
+    inject_size: usize,
+    rma: bool,
+}
+
+impl Default for RendezvousAttr {
+    fn default() -> Self {
+        RendezvousAttr {
+            threshold: 8192,
+            depth: 16,
+            virt_addr: true,
+            inject_size: 0,
+            rma: true,
+        }
+    }
+}
+
+impl RendezvousAttr {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Defaults tuned to the endpoints opened from `entry`: messages up to the inject size of
+    /// its transmit context are injected, peers address registered memory as its memory
+    /// registration mode has it, and, without [`Caps::RMA`], messages from the threshold on
+    /// are refused, rather than sent by rendezvous.
+    pub fn for_entry(entry: &InfoEntry) -> Self {
+        Self::default()
+            .inject_size(entry.tx_attr().inject_size)
+            .virt_addr(entry.mr_mode().contains(MrMode::VIRT_ADDR))
+            .rma(entry.caps().contains(Caps::RMA))
+    }
+
+    /// The length from which messages are sent by rendezvous, rather than eagerly, 8 KiB by
+    /// default.
+    pub fn threshold(mut self, len: usize) -> Self {
+        self.threshold = len.max(DESCRIPTOR_LEN);
+        self
+    }
+
+    /// Number of receives kept posted, 16 by default.
+    pub fn depth(mut self, depth: usize) -> Self {
+        self.depth = depth.max(1);
+        self
+    }
+
+    /// Whether peers address registered memory by its virtual address, as with
+    /// [`MrMode::VIRT_ADDR`](crate::MrMode::VIRT_ADDR), the default, or by its offset in the
+    /// region.
+    pub fn virt_addr(mut self, virt_addr: bool) -> Self {
+        self.virt_addr = virt_addr;
+        self
+    }
+
+    /// The length up to which messages are injected, when under the threshold too: copied by
+    /// the provider and completed at once, without a completion to read. None are by default.
+    pub fn inject_size(mut self, len: usize) -> Self {
+        self.inject_size = len;
+        self
+    }
+
+    /// Whether messages from the threshold on may be sent by rendezvous, which the endpoints
+    /// of both peers need RMA reads for, the default.
+    pub fn rma(mut self, rma: bool) -> Self {
+        self.rma = rma;
+        self
+    }
+
+    /// The path a message of `len` bytes takes.
+    pub fn path(&self, len: usize) -> SendPath {
+        if len >= self.threshold {
+            SendPath::Rendezvous
+        } else if self.inject_size > 0 && len <= self.inject_size {
+            SendPath::Inject
+        } else {
+            SendPath::Eager
+        }
+    }
+}
+
+/// How [`Rendezvous::smart_send()`] sends a message, by length, see [`RendezvousAttr::path()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SendPath {
+    /// Injected, up to [`RendezvousAttr::inject_size()`].
+    Inject,
+    /// Copied and sent, under [`RendezvousAttr::threshold()`].
+    Eager,
+    /// Read by the receiver from the registered buffer of the sender.
+    Rendezvous,
+}
+
+// An operation posted, or waiting for room to be posted.
+enum Op {
+    // An eager message or the descriptor of a rendezvous one, which completes on its FIN.
+    Send { id: u32, rendezvous: bool },
+    // The read of a rendezvous message into its buffer.
+    Read { src: Addr, id: u32, buf: Vec<u8> },
+    Fin,
+}
+
+// A rendezvous message announced, to be read.
+struct Rts {
+    src: Addr,
+    id: u32,
+    addr: u64,
+    key: u64,
+    len: usize,
+}
+
+// A send waiting for room to be posted.
+struct Outgoing {
+    dest: Addr,
+    tag: u64,
+    data: u64,
+    buf: Vec<u8>,
+    op: Op,
+}
+
+/// Messages of any length over tagged messages, those under the threshold sent eagerly, and
+/// those above by rendezvous: only a descriptor of their buffer is sent, which the receiver
+/// reads with RMA into a buffer of the right size, then notifies the sender.
+///
+/// Eager messages are copied, and complete once sent. Rendezvous messages are not, and
+/// complete once read by the receiver, from memory registered with
+/// [`Access::REMOTE_READ`](crate::Access::REMOTE_READ). Completions of sends are returned by
+/// [`sent()`](Self::sent), with the id [`send()`](Self::send) returned.
+/// [`smart_send()`](Self::smart_send) injects small messages too, sent from several buffers.
+///
+/// It runs over any [`Transport`] and [`Cq`], so over an RDM [`Endpoint`] opened with
+/// `Caps::TAGGED | Caps::RMA | Caps::SOURCE`, or a
+/// [`MockEndpoint`](crate::mock::MockEndpoint). Buffers of eager messages and of reads are
+/// not registered, so providers requiring `FI_MR_LOCAL` are not supported.
+///
+/// [`Endpoint`]: crate::Endpoint
+pub struct Rendezvous<T: Transport, C: Cq> {
+    // First, see `CqHandler`.
+    ep: T,
+    cq: C,
+    attr: RendezvousAttr,
+    slots: Vec<Box<[u8]>>,
+    unposted: Vec<usize>,
+    outbox: VecDeque<Outgoing>,
+    // The posted operations, with their buffer, by context.
+    posted: HashMap<usize, (Vec<u8>, Op)>,
+    next_context: usize,
+    next_id: u32,
+    // Rendezvous messages sent, waiting for their FIN.
+    waiting: HashSet<u32>,
+    // Rendezvous messages to read, waiting for room to be posted.
+    reads: VecDeque<Rts>,
+    inbox: VecDeque<(Addr, Vec<u8>)>,
+    sent: VecDeque<(u32, Result<()>)>,
+}
+
+impl<T: Transport, C: Cq> Rendezvous<T, C> {
+    /// Post the receives of the protocol on `ep`, whose completions are read from `cq`.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Transport`] documentation.
+    pub unsafe fn new(ep: T, cq: C, attr: &RendezvousAttr) -> Result<Self> {
+        let slots = (0..attr.depth)
+            .map(|_| vec![0u8; attr.threshold].into_boxed_slice())
+            .collect();
+        let mut rdv = Rendezvous {
+            ep,
+            cq,
+            attr: attr.clone(),
+            slots,
+            unposted: (0..attr.depth).rev().collect(),
+            outbox: VecDeque::new(),
+            posted: HashMap::new(),
+            next_context: 0,
+            next_id: 0,
+            waiting: HashSet::new(),
+            reads: VecDeque::new(),
+            inbox: VecDeque::new(),
+            sent: VecDeque::new(),
+        };
+        rdv.flush()?;
+        Ok(rdv)
+    }
+
+    pub fn endpoint(&self) -> &T {
+        &self.ep
+    }
+
+    /// Send `buf` to `dest`, eagerly under the threshold, or by rendezvous from `mr`, returning
+    /// the id of the send.
+    ///
+    /// # Safety
+    ///
+    /// Over the threshold, `buf` must stay valid and unchanged until the send is returned by
+    /// [`sent()`](Self::sent), and `mr` must register it with `Access::REMOTE_READ`.
+    pub unsafe fn send(&mut self, dest: Addr, buf: &[u8], mr: Option<&T::Mr>) -> Result<u32> {
+        unsafe { self.smart_send(dest, &[buf], mr) }.map(|(id, _)| id)
+    }
+
+    /// Send `bufs`, one after the other, to `dest` by the path their length calls for, see
+    /// [`RendezvousAttr::path()`], returning the id of the send and the path taken: injected,
+    /// sent eagerly from a copy gathering them, or by rendezvous from `mr`, which takes a
+    /// single buffer. Injected messages are returned by [`sent()`](Self::sent) at once, or
+    /// sent eagerly once the provider has room, or earlier sends were posted.
+    ///
+    /// # Safety
+    ///
+    /// See [`send()`](Self::send).
+    pub unsafe fn smart_send(
+        &mut self,
+        dest: Addr,
+        bufs: &[&[u8]],
+        mr: Option<&T::Mr>,
+    ) -> Result<(u32, SendPath)> {
+        let id = self.next_id;
+        let path = self.attr.path(bufs.iter().map(|buf| buf.len()).sum());
+        let out = match path {
+            SendPath::Inject | SendPath::Eager => Outgoing {
+                dest,
+                tag: EAGER,
+                data: id as u64,
+                buf: bufs.concat(),
+                op: Op::Send {
+                    id,
+                    rendezvous: false,
+                },
+            },
+            SendPath::Rendezvous => {
+                if !self.attr.rma {
+                    return Err(Error::invalid(
+                        "messages over the threshold need RMA to be sent by rendezvous",
+                    ));
+                }
+                let &[buf] = bufs else {
+                    return Err(Error::invalid(
+                        "rendezvous messages must be sent from a single buffer",
+                    ));
+                };
+                let mr = mr.ok_or_else(|| {
+                    Error::invalid("rendezvous messages must be sent from a registered region")
+                })?;
+                let start = mr.addr() as usize;
+                let offset = (buf.as_ptr() as usize).wrapping_sub(start);
+                if offset
+                    .checked_add(buf.len())
+                    .is_none_or(|end| end > mr.len())
+                {
+                    return Err(Error::invalid("the message is outside of its region"));
+                }
+                let addr = match self.attr.virt_addr {
+                    true => buf.as_ptr() as u64,
+                    false => offset as u64,
+                };
+                let mut descriptor = Vec::with_capacity(DESCRIPTOR_LEN);
+                descriptor.extend(addr.to_le_bytes());
+                descriptor.extend(mr.key().to_le_bytes());
+                descriptor.extend((buf.len() as u64).to_le_bytes());
+                self.waiting.insert(id);
+                Outgoing {
+                    dest,
+                    tag: RTS,
+                    data: id as u64,
+                    buf: descriptor,
+                    op: Op::Send {
+                        id,
+                        rendezvous: true,
+                    },
+                }
+            }
+        };
+        self.next_id = self.next_id.wrapping_add(1);
+        // Injecting past queued sends would reorder them.
+        if path == SendPath::Inject && self.outbox.is_empty() {
+            match self.ep.tinject(&out.buf, dest, EAGER) {
+                Ok(()) => {
+                    self.sent.push_back((id, Ok(())));
+                    return Ok((id, path));
+                }
+                Err(err) if err.is_again() => {}
+                Err(err) => {
+                    self.sent.push_back((id, Err(err)));
+                    return Ok((id, path));
+                }
+            }
+        }
+        self.outbox.push_back(out);
+        self.flush()?;
+        Ok((id, path))
+    }
+
+    /// The next message received, and its source.
+    pub fn recv(&mut self) -> Result<Option<(Addr, Vec<u8>)>> {
+        if self.inbox.is_empty() {
+            self.poll()?;
+        }
+        Ok(self.inbox.pop_front())
+    }
+
+    /// The next send completed, and its result.
+    pub fn sent(&mut self) -> Result<Option<(u32, Result<()>)>> {
+        if self.sent.is_empty() {
+            self.poll()?;
+        }
+        Ok(self.sent.pop_front())
+    }
+
+    /// Read the completions available, read the rendezvous messages announced, and post the
+    /// operations waiting for room.
+    pub fn poll(&mut self) -> Result<()> {
+        poll_cq(self)?;
+        self.flush()
+    }
+
+    // Notify the sender of a rendezvous message that it was read, or failed to be with the
+    // `FI_E*` code.
+    fn fin(&mut self, dest: Addr, id: u32, code: i32) {
+        self.outbox.push_back(Outgoing {
+            dest,
+            tag: FIN,
+            data: ((code.unsigned_abs() as u64) << 32) | id as u64,
+            buf: Vec::new(),
+            op: Op::Fin,
+        });
+    }
+
+    // Repost the receives, then post the reads and sends waiting for room, until the provider
+    // runs out.
+    fn flush(&mut self) -> Result<()> {
+        while let Some(slot) = self.unposted.pop() {
+            // SAFETY: the slot is not read until the receive completes, and outlives the
+            // endpoint, see `new()`.
+            let posted = unsafe {
+                self.ep
+                    .trecv(&mut self.slots[slot], None, Addr::UNSPEC, EAGER, KIND, slot)
+            };
+            match posted {
+                Err(err) if err.is_again() => {
+                    self.unposted.push(slot);
+                    break;
+                }
+                other => other?,
+            }
+        }
+        while let Some(rts) = self.reads.pop_front() {
+            let context = self.slots.len().wrapping_add(self.next_context);
+            let mut buf = vec![0u8; rts.len];
+            // SAFETY: the buffer is kept in `posted` until the read completes, and its heap
+            // memory does not move along with it.
+            let posted = unsafe {
+                self.ep
+                    .read(&mut buf, None, rts.src, rts.addr, rts.key, context)
+            };
+            match posted {
+                Ok(()) => {
+                    self.next_context = self.next_context.wrapping_add(1);
+                    let (src, id) = (rts.src, rts.id);
+                    self.posted
+                        .insert(context, (Vec::new(), Op::Read { src, id, buf }));
+                }
+                Err(err) if err.is_again() => {
+                    self.reads.push_front(rts);
+                    break;
+                }
+                // The sender is notified of the failure, and may send again.
+                Err(err) => self.fin(rts.src, rts.id, err.code()),
+            }
+        }
+        while let Some(out) = self.outbox.pop_front() {
+            let context = self.slots.len().wrapping_add(self.next_context);
+            // SAFETY: the buffer is kept in `posted` until the send completes.
+            let posted = unsafe {
+                self.ep
+                    .tsenddata(&out.buf, None, out.data, out.dest, out.tag, context)
+            };
+            match posted {
+                Ok(()) => {
+                    self.next_context = self.next_context.wrapping_add(1);
+                    self.posted.insert(context, (out.buf, out.op));
+                }
+                Err(err) if err.is_again() => {
+                    self.outbox.push_front(out);
+                    break;
+                }
+                Err(err) => {
+                    if let Op::Send { id, rendezvous } = out.op {
+                        if rendezvous {
+                            self.waiting.remove(&id);
+                        }
+                        self.sent.push_back((id, Err(err)));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: Transport, C: Cq> CqHandler for Rendezvous<T, C> {
+    type Cq = C;
+
+    fn cq(&self) -> &C {
+        &self.cq
+    }
+
+    fn complete(&mut self, completion: &Completion, src: Addr) {
+        let context = completion.context();
+        if context >= self.slots.len() {
+            match self.posted.remove(&context) {
+                Some((
+                    _,
+                    Op::Send {
+                        id,
+                        rendezvous: false,
+                    },
+                )) => self.sent.push_back((id, Ok(()))),
+                Some((_, Op::Read { src, id, buf })) => {
+                    self.inbox.push_back((src, buf));
+                    self.fin(src, id, 0);
+                }
+                _ => {}
+            }
+            return;
+        }
+        self.unposted.push(context);
+        let payload = &self.slots[context][..completion.len()];
+        match completion.tag() & KIND {
+            EAGER if src != Addr::NOTAVAIL => self.inbox.push_back((src, payload.to_vec())),
+            RTS if src != Addr::NOTAVAIL && payload.len() == DESCRIPTOR_LEN => {
+                let word =
+                    |i: usize| u64::from_le_bytes(payload[8 * i..8 * i + 8].try_into().unwrap());
+                self.reads.push_back(Rts {
+                    src,
+                    id: completion.data() as u32,
+                    addr: word(0),
+                    key: word(1),
+                    len: word(2) as usize,
+                });
+            }
+            FIN => {
+                let id = completion.data() as u32;
+                if self.waiting.remove(&id) {
+                    let result = match completion.data() >> 32 {
+                        0 => Ok(()),
+                        code => Err(Error::fabric("rendezvous send", code as i64)),
+                    };
+                    self.sent.push_back((id, result));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn failed(&mut self, entry: CqErrEntry) {
+        if entry.context < self.slots.len() {
+            // A truncated receive, of a message over the threshold sent eagerly by a peer
+            // with another one.
+            self.unposted.push(entry.context);
+            return;
+        }
+        match self.posted.remove(&entry.context) {
+            Some((_, Op::Send { id, rendezvous })) => {
+                if rendezvous {
+                    self.waiting.remove(&id);
+                }
+                self.sent.push_back((id, Err(entry.error)));
+            }
+            Some((_, Op::Read { src, id, .. })) => self.fin(src, id, entry.error.code()),
+            _ => {}
+        }
+    }
+}
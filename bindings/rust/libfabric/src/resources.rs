@@ -0,0 +1,237 @@
+use crate::attr::DomainAttr;
+use crate::error::{Error, Result};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// The objects whose count a domain reports in its [`DomainAttr`], which it accounts for as
+/// they are opened and closed, see [`Domain::usage()`](crate::Domain::usage).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Resource {
+    /// Endpoints, scalable ones included (`ep_cnt`).
+    Endpoint,
+    /// Completion queues (`cq_cnt`).
+    Cq,
+    /// Counters (`cntr_cnt`).
+    Counter,
+    /// Memory regions (`mr_cnt`).
+    Mr,
+    /// Transmit contexts, one for each endpoint which does not share one, and those of scalable
+    /// endpoints (`tx_ctx_cnt`).
+    TxContext,
+    /// Receive contexts, as the transmit ones (`rx_ctx_cnt`).
+    RxContext,
+}
+
+impl Resource {
+    pub const ALL: [Resource; 6] = [
+        Resource::Endpoint,
+        Resource::Cq,
+        Resource::Counter,
+        Resource::Mr,
+        Resource::TxContext,
+        Resource::RxContext,
+    ];
+
+    /// The count of the resource in `attr`, 0 when the provider does not report one.
+    pub fn limit(self, attr: &DomainAttr) -> usize {
+        match self {
+            Resource::Endpoint => attr.ep_cnt,
+            Resource::Cq => attr.cq_cnt,
+            Resource::Counter => attr.cntr_cnt,
+            Resource::Mr => attr.mr_cnt,
+            Resource::TxContext => attr.tx_ctx_cnt,
+            Resource::RxContext => attr.rx_ctx_cnt,
+        }
+    }
+}
+
+impl fmt::Display for Resource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Resource::Endpoint => "endpoints",
+            Resource::Cq => "completion queues",
+            Resource::Counter => "counters",
+            Resource::Mr => "memory regions",
+            Resource::TxContext => "transmit contexts",
+            Resource::RxContext => "receive contexts",
+        })
+    }
+}
+
+/// The objects of a resource a domain has open, and the room reserved for more, against the
+/// count of its attributes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceUsage {
+    pub resource: Resource,
+    pub open: usize,
+    /// Left in the [reservations](crate::Domain::reserve) of the resource.
+    pub reserved: usize,
+    /// 0 when the provider does not report one.
+    pub limit: usize,
+}
+
+impl ResourceUsage {
+    /// The objects which can still be opened without reservations, `None` without a limit.
+    pub fn available(&self) -> Option<usize> {
+        (self.limit != 0).then(|| self.limit.saturating_sub(self.open + self.reserved))
+    }
+}
+
+// The accounting of the resources of a domain, shared with the objects opened from it.
+pub(crate) struct Accounting {
+    limits: [usize; Resource::ALL.len()],
+    enforced: AtomicBool,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    open: [usize; Resource::ALL.len()],
+    reserved: [usize; Resource::ALL.len()],
+    // The room left in each reservation, by id.
+    reservations: HashMap<u64, (Resource, usize)>,
+    next: u64,
+}
+
+impl Accounting {
+    pub(crate) fn new(attr: &DomainAttr) -> Arc<Self> {
+        Arc::new(Accounting {
+            limits: Resource::ALL.map(|resource| resource.limit(attr)),
+            enforced: AtomicBool::new(false),
+            state: Mutex::default(),
+        })
+    }
+
+    pub(crate) fn enforced(&self) -> bool {
+        self.enforced.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_enforced(&self, enforced: bool) {
+        self.enforced.store(enforced, Ordering::Relaxed);
+    }
+
+    pub(crate) fn usage(&self, resource: Resource) -> ResourceUsage {
+        let state = self.state.lock().unwrap();
+        let i = resource as usize;
+        ResourceUsage {
+            resource,
+            open: state.open[i],
+            reserved: state.reserved[i],
+            limit: self.limits[i],
+        }
+    }
+
+    fn exceeded(&self, state: &State, resource: Resource, count: usize) -> Result<()> {
+        let (i, limit) = (resource as usize, self.limits[resource as usize]);
+        if limit != 0 && state.open[i] + state.reserved[i] + count > limit {
+            return Err(Error::LimitExceeded { resource, limit });
+        }
+        Ok(())
+    }
+
+    // Count an object of each of `resources`, taking its room from the reservations of the
+    // resource first. Fails past the limits of the domain if they are enforced and `check`.
+    pub(crate) fn open(self: &Arc<Self>, resources: &[Resource], check: bool) -> Result<Claim> {
+        let mut state = self.state.lock().unwrap();
+        if check && self.enforced() {
+            for &resource in resources {
+                if state.reserved[resource as usize] == 0 {
+                    self.exceeded(&state, resource, 1)?;
+                }
+            }
+        }
+        for &resource in resources {
+            let i = resource as usize;
+            if state.reserved[i] != 0 {
+                let left = state
+                    .reservations
+                    .values_mut()
+                    .find_map(|(of, left)| (*of == resource && *left != 0).then_some(left));
+                if let Some(left) = left {
+                    *left -= 1;
+                    state.reserved[i] -= 1;
+                }
+            }
+            state.open[i] += 1;
+        }
+        Ok(Claim {
+            accounting: self.clone(),
+            resources: resources.to_vec(),
+        })
+    }
+
+    pub(crate) fn reserve(
+        self: &Arc<Self>,
+        resource: Resource,
+        count: usize,
+    ) -> Result<Reservation> {
+        let mut state = self.state.lock().unwrap();
+        self.exceeded(&state, resource, count)?;
+        let id = state.next;
+        state.next += 1;
+        state.reservations.insert(id, (resource, count));
+        state.reserved[resource as usize] += count;
+        Ok(Reservation {
+            accounting: self.clone(),
+            resource,
+            id,
+        })
+    }
+}
+
+// The resources an object counts for, released once it is closed.
+pub(crate) struct Claim {
+    accounting: Arc<Accounting>,
+    resources: Vec<Resource>,
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        let mut state = self.accounting.state.lock().unwrap();
+        for &resource in &self.resources {
+            state.open[resource as usize] -= 1;
+        }
+    }
+}
+
+/// Room for objects of a resource of a domain, from [`Domain::reserve()`](crate::Domain::reserve),
+/// which the objects opened in the meantime take from, and which is released once dropped.
+#[must_use]
+pub struct Reservation {
+    accounting: Arc<Accounting>,
+    resource: Resource,
+    id: u64,
+}
+
+impl Reservation {
+    pub fn resource(&self) -> Resource {
+        self.resource
+    }
+
+    /// The room the objects opened did not take yet.
+    pub fn left(&self) -> usize {
+        let state = self.accounting.state.lock().unwrap();
+        state.reservations[&self.id].1
+    }
+}
+
+impl fmt::Debug for Reservation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Reservation")
+            .field("resource", &self.resource)
+            .field("left", &self.left())
+            .finish()
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        let mut state = self.accounting.state.lock().unwrap();
+        if let Some((resource, left)) = state.reservations.remove(&self.id) {
+            state.reserved[resource as usize] -= left;
+        }
+    }
+}
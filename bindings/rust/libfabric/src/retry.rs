@@ -0,0 +1,211 @@
+use crate::error::Result;
+use crate::progress::ProgressDriver;
+use std::cell::RefCell;
+use std::time::{Duration, Instant};
+
+/// How [`post_with_retry()`] retries an operation failing with `-FI_EAGAIN`, as a full transmit
+/// or receive queue does until completions are read from its completion queue.
+#[derive(Debug, Clone, Default)]
+#[must_use]
+pub struct RetryPolicy {
+    max_retries: Option<u32>,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    timeout: Option<Duration>,
+}
+
+impl RetryPolicy {
+    /// Retry forever, right after driving progress: spin on progress.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Give up after `retries` retries, returning the last `-FI_EAGAIN`.
+    pub fn max_retries(mut self, retries: u32) -> Self {
+        self.max_retries = Some(retries);
+        self
+    }
+
+    /// Wait `initial` after driving progress for the first retry, then twice as long before
+    /// each of the next ones, up to `max`.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Give up once `timeout` passed since the first attempt, returning the last `-FI_EAGAIN`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    fn delay(&self, retries: u32) -> Duration {
+        let factor = 1u32
+            .checked_shl(retries.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+// The retries of one operation under a policy.
+struct Retries<'a> {
+    policy: &'a RetryPolicy,
+    retries: u32,
+    deadline: Option<Instant>,
+}
+
+impl<'a> Retries<'a> {
+    fn new(policy: &'a RetryPolicy, now: Instant) -> Self {
+        Retries {
+            policy,
+            retries: 0,
+            deadline: policy.timeout.map(|timeout| now + timeout),
+        }
+    }
+
+    // The wait before the next retry, or None once the policy gives up.
+    fn next(&mut self, now: Instant) -> Option<Duration> {
+        if self
+            .policy
+            .max_retries
+            .is_some_and(|max| self.retries >= max)
+        {
+            return None;
+        }
+        if self.deadline.is_some_and(|deadline| now >= deadline) {
+            return None;
+        }
+        self.retries += 1;
+        let delay = self.policy.delay(self.retries);
+        Some(
+            self.deadline
+                .map_or(delay, |deadline| delay.min(deadline - now)),
+        )
+    }
+}
+
+/// Post an operation with `op` until it no longer fails with `-FI_EAGAIN`, calling `progress`
+/// before each retry, as it is only from reading completions that the provider frees the
+/// entries of its queues.
+///
+/// `progress` typically drives the [`CompletionQueue`](crate::CompletionQueue) bound to the
+/// endpoint with [`progress()`](crate::CompletionQueue::progress), or reads the completions of
+/// the application. Errors of either are returned right away, as is the `-FI_EAGAIN` on which
+/// the `policy` gives up.
+///
+/// ```no_run
+/// # use libfabric::{Addr, CompletionQueue, Endpoint};
+/// # fn run(ep: &Endpoint, cq: &CompletionQueue, dest: Addr) -> libfabric::Result<()> {
+/// use libfabric::{RetryPolicy, post_with_retry};
+/// use std::time::Duration;
+///
+/// let policy = RetryPolicy::new().timeout(Duration::from_secs(1));
+/// post_with_retry(&policy, || cq.progress(), || ep.inject(b"hello", dest))?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn post_with_retry<T>(
+    policy: &RetryPolicy,
+    progress: impl FnMut() -> Result<()>,
+    op: impl FnMut() -> Result<T>,
+) -> Result<T> {
+    post_with_retry_on(policy, &WithProgress(RefCell::new(progress)), op)
+}
+
+/// Like [`post_with_retry()`], progressing `driver` before each retry and sleeping on its
+/// clock, such that the retries of code taking a [`ProgressDriver`] are tested on the virtual
+/// clock of a [`MockProgress`](crate::mock::MockProgress).
+pub fn post_with_retry_on<T>(
+    policy: &RetryPolicy,
+    driver: &impl ProgressDriver,
+    mut op: impl FnMut() -> Result<T>,
+) -> Result<T> {
+    let mut retries = Retries::new(policy, driver.now());
+    loop {
+        match op() {
+            Err(err) if err.is_again() => {
+                let Some(delay) = retries.next(driver.now()) else {
+                    return Err(err);
+                };
+                driver.progress()?;
+                if !delay.is_zero() {
+                    driver.sleep(delay);
+                }
+            }
+            other => return other,
+        }
+    }
+}
+
+// The progress of `post_with_retry()`, on the system clock.
+struct WithProgress<F>(RefCell<F>);
+
+impl<F: FnMut() -> Result<()>> ProgressDriver for WithProgress<F> {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn progress(&self) -> Result<()> {
+        (self.0.borrow_mut())()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// Like [`post_with_retry()`], yielding to the executor between attempts rather than blocking
+/// the thread, enabled by the `async` feature.
+///
+/// The future does not depend on a runtime: it wakes itself up to be polled again, past the
+/// backoff of the policy, which keeps the executor busy in the meantime.
+#[cfg(feature = "async")]
+pub async fn post_with_retry_async<T>(
+    policy: &RetryPolicy,
+    mut progress: impl FnMut() -> Result<()>,
+    mut op: impl FnMut() -> Result<T>,
+) -> Result<T> {
+    let mut retries = Retries::new(policy, Instant::now());
+    loop {
+        match op() {
+            Err(err) if err.is_again() => {
+                let Some(delay) = retries.next(Instant::now()) else {
+                    return Err(err);
+                };
+                progress()?;
+                let until = Instant::now() + delay;
+                loop {
+                    YieldNow(false).await;
+                    if Instant::now() >= until {
+                        break;
+                    }
+                }
+            }
+            other => return other,
+        }
+    }
+}
+
+// Pending once, waking itself up, so that the executor runs other tasks before the next poll.
+#[cfg(feature = "async")]
+pub(crate) struct YieldNow(pub(crate) bool);
+
+#[cfg(feature = "async")]
+impl std::future::Future for YieldNow {
+    type Output = ();
+
+    fn poll(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<()> {
+        if self.0 {
+            return std::task::Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        std::task::Poll::Pending
+    }
+}
@@ -0,0 +1,501 @@
+use crate::av::Addr;
+use crate::cq::{Completion, CompletionQueue};
+use crate::ep::Endpoint;
+use crate::error::{Error, Result};
+use crate::flags::Access;
+use crate::mr::MemoryRegion;
+use ofi_libfabric_sys::bindgen as ffi;
+use std::collections::{BTreeMap, VecDeque};
+use std::mem::MaybeUninit;
+
+/// Attributes of a [`RecvRing`].
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct RecvRingAttr {
+    segments: usize,
+    segment_len: usize,
+    max_message: usize,
+    source: bool,
+}
+
+impl Default for RecvRingAttr {
+    fn default() -> Self {
+        RecvRingAttr {
+            segments: 4,
+            segment_len: 1 << 20,
+            max_message: 8192,
+            source: false,
+        }
+    }
+}
+
+impl RecvRingAttr {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of multi-receive buffers the ring is split into, 4 by default. Messages land in
+    /// the others while the application holds on to those of one. A ring in an arena of the
+    /// application has as many as fit in the arena instead.
+    pub fn segments(mut self, segments: usize) -> Self {
+        self.segments = segments.max(2);
+        self
+    }
+
+    /// Length of each multi-receive buffer, 1 MiB by default, rounded up to a multiple of
+    /// the [maximum message](Self::max_message): a buffer filled with messages of the maximum
+    /// is then released once full, rather than with a trailing fragment too short for one.
+    pub fn segment_len(mut self, len: usize) -> Self {
+        self.segment_len = len;
+        self
+    }
+
+    /// Largest message received, 8 KiB by default: buffers with less space left are released
+    /// by the provider, as set on the endpoint with
+    /// [`set_min_multi_recv()`](Endpoint::set_min_multi_recv). At most the `max_msg_size` of
+    /// the endpoint, and not 0, with which the provider truncates the last message of a
+    /// buffer instead.
+    pub fn max_message(mut self, len: usize) -> Self {
+        self.max_message = len;
+        self
+    }
+
+    /// Report the source of messages, which requires `FI_SOURCE`.
+    pub fn source(mut self, source: bool) -> Self {
+        self.source = source;
+        self
+    }
+
+    // The length of segments, rounded up to a multiple of the maximum message.
+    fn rounded_segment_len(&self) -> usize {
+        match self.max_message {
+            0 => self.segment_len,
+            max => self.segment_len.next_multiple_of(max),
+        }
+    }
+}
+
+/// A message received in a [`RecvRing`], or a frame of one, whose bytes are read in place
+/// with [`RecvRing::bytes()`] until it is [released](RecvRing::release).
+#[derive(Debug)]
+pub struct RecvSlot {
+    segment: usize,
+    offset: usize,
+    len: usize,
+    src: Addr,
+    data: u64,
+    flags: u64,
+}
+
+impl RecvSlot {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The source of the message, [`Addr::UNSPEC`] unless enabled by
+    /// [`RecvRingAttr::source()`].
+    pub fn src(&self) -> Addr {
+        self.src
+    }
+
+    /// Remote CQ data, valid with `FI_REMOTE_CQ_DATA`.
+    pub fn data(&self) -> u64 {
+        self.data
+    }
+
+    /// Raw completion flags.
+    pub fn flags(&self) -> u64 {
+        self.flags
+    }
+}
+
+#[derive(Default)]
+struct Segment {
+    // Whether the provider holds the buffer, from its post until its release.
+    posted: bool,
+    // The ranges of the segment not yet released by the application, from start to end,
+    // disjoint.
+    held: BTreeMap<usize, usize>,
+}
+
+impl Segment {
+    fn hold(&mut self, offset: usize, len: usize) {
+        if len > 0 {
+            self.held.insert(offset, offset + len);
+        }
+    }
+
+    // Release a range held, splitting the one holding it.
+    fn unhold(&mut self, offset: usize, len: usize) -> Result<()> {
+        if len == 0 {
+            return Ok(());
+        }
+        let end = offset + len;
+        let (start, held_end) = match self.held.range(..=offset).next_back() {
+            Some((&start, &held_end)) if held_end >= end => (start, held_end),
+            _ => {
+                return Err(Error::invalid(format!(
+                    "{len} bytes at {offset} released, not held"
+                )));
+            }
+        };
+        self.held.remove(&start);
+        if start < offset {
+            self.held.insert(start, offset);
+        }
+        if end < held_end {
+            self.held.insert(end, held_end);
+        }
+        Ok(())
+    }
+}
+
+/// Zero-copy receives into a large registered ring, split into segments posted as
+/// multi-receive buffers (`FI_MULTI_RECV`).
+///
+/// Messages are returned by [`recv()`](Self::recv) as slots of the ring, read in place, and
+/// handed back with [`release()`](Self::release), in any order. The ring reclaims segments in
+/// order, as a cursor: a segment is reposted once the provider released it, and the
+/// application released all of its messages, so holding a message back only holds back the
+/// reposting of its own segment and those after it.
+///
+/// Framed protocols, carrying several frames per message, parse them in place with
+/// [`recv_frame()`](Self::recv_frame): a parser reads the length of the next frame off the
+/// rest of a message, and the frame is returned as a slot of its own. Slots hold ranges of
+/// their segment, released in any order, the bytes between frames with the rest of the
+/// message: a segment is reclaimed once no range of it is held.
+///
+/// The ring is allocated by [`new()`](Self::new), or is an arena of the application, given
+/// to [`with_arena()`](Self::with_arena) uninitialized, and taken back with
+/// [`into_arena()`](Self::into_arena).
+///
+/// The endpoint must be opened with `Caps::MULTI_RECV`, and `cq` only report its receives,
+/// which all go to the ring.
+///
+/// ```no_run
+/// use libfabric::{CompletionQueue, Endpoint, RecvRing, RecvRingAttr};
+///
+/// # fn run(ep: Endpoint, cq: CompletionQueue) -> libfabric::Result<()> {
+/// let mut ring = unsafe { RecvRing::new(ep, cq, &RecvRingAttr::new()) }?;
+/// // Frames of a 4 byte length, then as many bytes.
+/// let frame_len = |rest: &[u8]| match rest {
+///     [a, b, c, d, ..] => Ok(Some(4 + u32::from_le_bytes([*a, *b, *c, *d]) as usize)),
+///     _ => Ok(None),
+/// };
+/// while let Some(frame) = ring.recv_frame(frame_len)? {
+///     println!("{:?}", &ring.bytes(&frame)[4..]);
+///     ring.release(frame)?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct RecvRing {
+    // Declared first, so that when they are the last handles, the endpoint and its queue are
+    // closed, then the ring deregistered, before the ring is freed.
+    ep: Endpoint,
+    cq: CompletionQueue,
+    mr: MemoryRegion,
+    // Initialized where the provider received messages.
+    ring: Box<[MaybeUninit<u8>]>,
+    attr: RecvRingAttr,
+    segments: Vec<Segment>,
+    // The next segment to repost.
+    reclaim: usize,
+    ready: VecDeque<RecvSlot>,
+    // The rest of the message whose frames are being parsed.
+    parsing: Option<RecvSlot>,
+}
+
+impl RecvRing {
+    /// Register the ring in the domain of `ep`, and post all of its segments.
+    ///
+    /// # Safety
+    ///
+    /// The ring is owned by the returned value, so no other handle of `ep` may outlive it.
+    /// Other receives of `ep`, and completions of `cq`, must not be used elsewhere either.
+    pub unsafe fn new(ep: Endpoint, cq: CompletionQueue, attr: &RecvRingAttr) -> Result<Self> {
+        let ring = Box::new_uninit_slice(attr.segments * attr.rounded_segment_len());
+        unsafe { Self::with_arena(ep, cq, attr, ring) }
+    }
+
+    /// Receive into `arena`, split into as many segments of [`RecvRingAttr::segment_len()`],
+    /// rounded up, as fit, at least 2, for memory the application allocated itself, ex:
+    /// pinned or near the NIC. The arena is not read until messages are received into it.
+    ///
+    /// # Safety
+    ///
+    /// As for [`new()`](Self::new).
+    pub unsafe fn with_arena(
+        ep: Endpoint,
+        cq: CompletionQueue,
+        attr: &RecvRingAttr,
+        mut arena: Box<[MaybeUninit<u8>]>,
+    ) -> Result<Self> {
+        if attr.max_message == 0 {
+            return Err(Error::invalid(
+                "multi-receive ring of a 0 bytes maximum message",
+            ));
+        }
+        let max_msg_size = ep.info().ep_attr().max_msg_size;
+        if max_msg_size != 0 && attr.max_message > max_msg_size {
+            return Err(Error::invalid(format!(
+                "{} bytes maximum message past the {max_msg_size} bytes max_msg_size of the {} \
+                 endpoint",
+                attr.max_message,
+                ep.info().provider_name()
+            )));
+        }
+        if attr.segment_len < attr.max_message {
+            return Err(Error::invalid(format!(
+                "{} bytes segments under the {} bytes maximum message",
+                attr.segment_len, attr.max_message
+            )));
+        }
+        let segment_len = attr.rounded_segment_len();
+        let segments = arena.len() / segment_len;
+        if segments < 2 {
+            return Err(Error::invalid(format!(
+                "{} bytes arena under 2 segments of {segment_len} bytes",
+                arena.len()
+            )));
+        }
+        // SAFETY: the arena is freed after the region, see the field order, and only written
+        // by the provider.
+        let mr = unsafe {
+            ep.domain().register(
+                arena.as_mut_ptr().cast::<u8>(),
+                segments * segment_len,
+                Access::RECV,
+            )?
+        };
+        ep.set_min_multi_recv(attr.max_message)?;
+        let mut recv_ring = RecvRing {
+            ep,
+            cq,
+            mr,
+            ring: arena,
+            attr: attr.clone().segments(segments).segment_len(segment_len),
+            segments: (0..segments).map(|_| Segment::default()).collect(),
+            reclaim: 0,
+            ready: VecDeque::new(),
+            parsing: None,
+        };
+        for segment in 0..segments {
+            recv_ring.post(segment)?;
+        }
+        Ok(recv_ring)
+    }
+
+    /// Close the endpoint, its queue and the region of the ring, and hand the arena back,
+    /// uninitialized where nothing was received.
+    pub fn into_arena(self) -> Box<[MaybeUninit<u8>]> {
+        let RecvRing {
+            ep, cq, mr, ring, ..
+        } = self;
+        drop(ep);
+        drop(cq);
+        drop(mr);
+        ring
+    }
+
+    pub fn endpoint(&self) -> &Endpoint {
+        &self.ep
+    }
+
+    /// The length of each segment, that of the attributes rounded up to a multiple of the
+    /// maximum message.
+    pub fn segment_len(&self) -> usize {
+        self.attr.segment_len
+    }
+
+    /// The maximum message, which the endpoint releases segments with less space left than.
+    pub fn min_multi_recv(&self) -> usize {
+        self.attr.max_message
+    }
+
+    /// The next message received, reading the queue when none is waiting.
+    ///
+    /// Fails with the error of a failed receive, ex: `FI_ETRUNC` for a message over the
+    /// maximum.
+    pub fn recv(&mut self) -> Result<Option<RecvSlot>> {
+        if self.ready.is_empty() {
+            self.poll()?;
+        }
+        Ok(self.ready.pop_front())
+    }
+
+    /// The next frame of the messages received, `parse` reading the length of the frame at
+    /// the start of the rest of a message, or `None` once none is left. Frames are read in
+    /// place and released as slots of their own, in any order; the rest of a message without
+    /// frames is released once parsed.
+    ///
+    /// Fails with the error of `parse`, or if a frame is empty or runs past its message,
+    /// releasing the rest of the message.
+    pub fn recv_frame(
+        &mut self,
+        mut parse: impl FnMut(&[u8]) -> Result<Option<usize>>,
+    ) -> Result<Option<RecvSlot>> {
+        loop {
+            let rest = match self.parsing.take() {
+                Some(rest) => rest,
+                None => match self.recv()? {
+                    Some(slot) => slot,
+                    None => return Ok(None),
+                },
+            };
+            if rest.is_empty() {
+                self.release(rest)?;
+                continue;
+            }
+            let len = match parse(self.bytes(&rest)) {
+                Ok(Some(len)) if len > 0 && len <= rest.len => len,
+                Ok(None) => {
+                    self.release(rest)?;
+                    continue;
+                }
+                Ok(Some(len)) => {
+                    let err = Error::invalid(format!(
+                        "{len} bytes frame in the {} bytes left of a message",
+                        rest.len
+                    ));
+                    self.release(rest)?;
+                    return Err(err);
+                }
+                Err(err) => {
+                    self.release(rest)?;
+                    return Err(err);
+                }
+            };
+            let frame = RecvSlot { len, ..rest };
+            if len < rest.len {
+                self.parsing = Some(RecvSlot {
+                    offset: rest.offset + len,
+                    len: rest.len - len,
+                    ..rest
+                });
+            }
+            return Ok(Some(frame));
+        }
+    }
+
+    /// The bytes of `slot`, in the ring.
+    pub fn bytes(&self, slot: &RecvSlot) -> &[u8] {
+        let start = slot.segment * self.attr.segment_len + slot.offset;
+        let bytes = &self.ring[start..start + slot.len];
+        // SAFETY: the provider received the message into these bytes.
+        unsafe { &*(bytes as *const [MaybeUninit<u8>] as *const [u8]) }
+    }
+
+    /// Hand `slot` back to the ring, reposting the segments reclaimed.
+    ///
+    /// Fails if its bytes were released already, which slots of another ring may claim.
+    pub fn release(&mut self, slot: RecvSlot) -> Result<()> {
+        match self.segments.get_mut(slot.segment) {
+            Some(segment) => segment.unhold(slot.offset, slot.len)?,
+            None => return Err(Error::invalid(format!("no segment {}", slot.segment))),
+        }
+        self.reclaim()
+    }
+
+    /// Bytes of the ring held by the application, received and not released.
+    pub fn held(&self) -> usize {
+        let held = self.segments.iter().flat_map(|segment| &segment.held);
+        held.map(|(start, end)| end - start).sum()
+    }
+
+    /// Segments posted, which the provider may receive messages in.
+    pub fn posted(&self) -> usize {
+        self.segments
+            .iter()
+            .filter(|segment| segment.posted)
+            .count()
+    }
+
+    fn poll(&mut self) -> Result<()> {
+        let mut completions = [Completion::default(); 16];
+        let mut src = [Addr::UNSPEC; 16];
+        let read = match self.attr.source {
+            true => self.cq.read_from(&mut completions, &mut src),
+            false => self.cq.read(&mut completions),
+        };
+        let n = match read {
+            Err(err) if err.is_avail() => {
+                let Some(entry) = self.cq.read_err()? else {
+                    return Ok(());
+                };
+                if entry.flags & ffi::FI_MULTI_RECV as u64 != 0 {
+                    self.released(entry.context)?;
+                }
+                return Err(entry.error);
+            }
+            Err(err) if err.is_again() => 0,
+            other => other?,
+        };
+        for (completion, src) in completions[..n].iter().zip(src) {
+            let segment = completion.context();
+            if segment >= self.segments.len() {
+                continue;
+            }
+            if completion.is_recv() {
+                let start = self.ring.as_ptr() as usize + segment * self.attr.segment_len;
+                let offset = (completion.buf() as usize).wrapping_sub(start);
+                self.segments[segment].hold(offset, completion.len());
+                self.ready.push_back(RecvSlot {
+                    segment,
+                    offset,
+                    len: completion.len(),
+                    src,
+                    data: completion.data(),
+                    flags: completion.flags(),
+                });
+            }
+            if completion.is_multi_recv() {
+                self.released(segment)?;
+            }
+        }
+        Ok(())
+    }
+
+    // The provider released `segment`.
+    fn released(&mut self, segment: usize) -> Result<()> {
+        if let Some(segment) = self.segments.get_mut(segment) {
+            segment.posted = false;
+        }
+        self.reclaim()
+    }
+
+    // Repost the segments released by both the provider and the application, in order from
+    // the cursor.
+    fn reclaim(&mut self) -> Result<()> {
+        loop {
+            let segment = &self.segments[self.reclaim];
+            if segment.posted || !segment.held.is_empty() {
+                return Ok(());
+            }
+            match self.post(self.reclaim) {
+                Err(err) if err.is_again() => return Ok(()),
+                other => other?,
+            }
+            self.reclaim = (self.reclaim + 1) % self.segments.len();
+        }
+    }
+
+    fn post(&mut self, segment: usize) -> Result<()> {
+        let len = self.attr.segment_len;
+        let buf = &mut self.ring[segment * len..(segment + 1) * len];
+        // SAFETY: the segment is only handed to the provider, which writes it, and is not
+        // read until the provider releases it, only where it received messages. The ring
+        // outlives the endpoint, see `new()`.
+        let buf = unsafe { &mut *(buf as *mut [MaybeUninit<u8>] as *mut [u8]) };
+        unsafe {
+            self.ep
+                .recv_multi(buf, Some(&self.mr), Addr::UNSPEC, segment)?
+        };
+        self.segments[segment].posted = true;
+        Ok(())
+    }
+}
@@ -0,0 +1,793 @@
+use crate::av::Addr;
+use crate::caps::{HasMsg, HasRma};
+use crate::checks::{Kind, Op};
+use crate::cq::CompletionQueue;
+use crate::ep::{Enabled, Endpoint};
+use crate::error::{Error, Result, check_len};
+use crate::flags::{BindFlags, Caps, MsgOrder, OpFlags};
+use crate::mr::{MemoryRegion, desc};
+use crate::retry::{RetryPolicy, post_with_retry};
+use crate::threading::ThreadingModel;
+use crate::trace;
+use ofi_libfabric_sys::bindgen as ffi;
+use std::ffi::c_void;
+use std::mem;
+
+/// A segment of remote memory, in a region registered by the peer, as [`Endpoint::read()`]
+/// takes its `addr` and `key`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RmaIov {
+    pub addr: u64,
+    pub len: usize,
+    pub key: u64,
+}
+
+/// The completions of an operation split into several posts by [`Endpoint::write_split()`] or
+/// [`Endpoint::read_split()`], each completing with the context of the operation, which is done
+/// once all of them completed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RmaCompletions {
+    context: usize,
+    posts: usize,
+    completed: usize,
+}
+
+impl RmaCompletions {
+    /// The context of the operation, that of each of its completions.
+    pub fn context(&self) -> usize {
+        self.context
+    }
+
+    /// How many operations were posted, and so how many completions are expected.
+    pub fn posts(&self) -> usize {
+        self.posts
+    }
+
+    /// Count a completion with the given context, those of other operations being ignored,
+    /// returning whether the operation is done.
+    pub fn complete(&mut self, context: usize) -> bool {
+        if context == self.context && self.completed < self.posts {
+            self.completed += 1;
+        }
+        self.is_done()
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.completed == self.posts
+    }
+}
+
+/// The notification following the payload of [`Endpoint::write_with_notification()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Notify<'a> {
+    /// Remote CQ data of the write itself, which the target reads once the payload is placed,
+    /// so no ordering is needed. Requires a provider with a
+    /// [`DomainAttr::cq_data_size`](crate::DomainAttr::cq_data_size).
+    Data(u64),
+    /// A message, injected after the write and matched by a receive of the target, at most
+    /// [`TxAttr::inject_size`](crate::TxAttr::inject_size) bytes.
+    Send(&'a [u8]),
+}
+
+/// How the notification message of [`Endpoint::write_with_notification()`] is kept from
+/// passing its payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotifyOrder {
+    /// The endpoint transmits sends after the writes before them ([`MsgOrder::SAW`]), so both
+    /// are posted as they are.
+    Ordered,
+    /// The write is posted with [`OpFlags::DELIVERY_COMPLETE`], completing once its data is
+    /// visible at the target, and the send with [`OpFlags::FENCE`] ([`Caps::FENCE`]), which
+    /// holds it until then. A fence alone would not do: a write completing at the default
+    /// [`OpFlags::TRANSMIT_COMPLETE`] may not be placed yet.
+    Fenced,
+}
+
+impl NotifyOrder {
+    /// The ordering of endpoints transmitting in `msg_order`, with `caps`. Fails with
+    /// `FI_EOPNOTSUPP` when they neither order sends after writes nor support fences.
+    pub fn new(msg_order: MsgOrder, caps: Caps) -> Result<Self> {
+        if msg_order.contains(MsgOrder::SAW) {
+            Ok(NotifyOrder::Ordered)
+        } else if caps.contains(Caps::FENCE) {
+            Ok(NotifyOrder::Fenced)
+        } else {
+            Err(Error::fabric("fi_sendmsg", ffi::FI_EOPNOTSUPP as i64))
+        }
+    }
+}
+
+/// Remote memory access (`fi_rma(3)`). The target is given by the remote `addr` and `key` of a
+/// memory region registered by the peer, see [`MemoryRegion::key()`]. Depending on the
+/// provider's [`MrMode`](crate::MrMode), `addr` is either a virtual address or an offset into
+/// the region.
+impl<M: ThreadingModel, P: HasRma> Endpoint<M, Enabled, P> {
+    /// Read remote memory into `buf`.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    pub unsafe fn read(
+        &self,
+        buf: &mut [u8],
+        mr: Option<&MemoryRegion<M>>,
+        src: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.check_op(|| Op::new("fi_read", Kind::Read, buf.len()).rma_iov(1))?;
+        trace::data_op!(self, "fi_read", size = buf.len());
+        let ret = self.post_op(BindFlags::TRANSMIT, || {
+            trace::tracked!(self, "fi_read", context, None, [writes(buf)], unsafe {
+                ffi::fi_read(
+                    self.as_raw(),
+                    buf.as_mut_ptr().cast(),
+                    buf.len(),
+                    desc(mr),
+                    src.as_raw(),
+                    addr,
+                    key,
+                    context as *mut _,
+                )
+            })
+        });
+        check_len("fi_read", ret).map(|_| ())
+    }
+
+    /// Like [`read()`](Self::read), with the flags of the operation, via `fi_readmsg()`, ex:
+    /// [`OpFlags::COMPLETION`] for a completion on a queue bound with
+    /// [`BindFlags::SELECTIVE_COMPLETION`](crate::BindFlags::SELECTIVE_COMPLETION).
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn read_with_flags(
+        &self,
+        buf: &mut [u8],
+        mr: Option<&MemoryRegion<M>>,
+        src: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+        flags: OpFlags,
+    ) -> Result<()> {
+        self.check_op(|| {
+            Op::new("fi_readmsg", Kind::Read, buf.len())
+                .rma_iov(1)
+                .flags(flags)
+        })?;
+        trace::data_op!(self, "fi_readmsg", size = buf.len());
+        let iov = ffi::iovec {
+            iov_base: buf.as_mut_ptr().cast(),
+            iov_len: buf.len(),
+        };
+        let rma_iov = ffi::fi_rma_iov {
+            addr,
+            len: buf.len(),
+            key,
+        };
+        let mut desc = desc(mr);
+        let msg = rma_msg(&iov, &mut desc, src, &rma_iov, context);
+        let ret = self.post_op(BindFlags::TRANSMIT, || {
+            trace::tracked!(
+                self,
+                "fi_readmsg",
+                context,
+                Some(flags),
+                [writes(buf)],
+                unsafe { ffi::fi_readmsg(self.as_raw(), &msg, flags.bits()) }
+            )
+        });
+        check_len("fi_readmsg", ret).map(|_| ())
+    }
+
+    /// Write `buf` to remote memory.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    pub unsafe fn write(
+        &self,
+        buf: &[u8],
+        mr: Option<&MemoryRegion<M>>,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.check_op(|| Op::new("fi_write", Kind::Write, buf.len()).rma_iov(1))?;
+        trace::data_op!(self, "fi_write", size = buf.len());
+        let ret = self.post_op(BindFlags::TRANSMIT, || {
+            trace::tracked!(self, "fi_write", context, None, [reads(buf)], unsafe {
+                ffi::fi_write(
+                    self.as_raw(),
+                    buf.as_ptr().cast(),
+                    buf.len(),
+                    desc(mr),
+                    dest.as_raw(),
+                    addr,
+                    key,
+                    context as *mut _,
+                )
+            })
+        });
+        check_len("fi_write", ret).map(|_| ())
+    }
+
+    /// Like [`write()`](Self::write), with the flags of the operation, via `fi_writemsg()`, ex:
+    /// [`OpFlags::COMPLETION`] for a completion on a queue bound with
+    /// [`BindFlags::SELECTIVE_COMPLETION`](crate::BindFlags::SELECTIVE_COMPLETION), the writes
+    /// posted without it only counted by a counter bound with
+    /// [`BindFlags::WRITE`](crate::BindFlags::WRITE).
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn write_with_flags(
+        &self,
+        buf: &[u8],
+        mr: Option<&MemoryRegion<M>>,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+        flags: OpFlags,
+    ) -> Result<()> {
+        self.check_op(|| {
+            Op::new("fi_writemsg", Kind::Write, buf.len())
+                .rma_iov(1)
+                .flags(flags)
+        })?;
+        trace::data_op!(self, "fi_writemsg", size = buf.len());
+        let iov = ffi::iovec {
+            iov_base: buf.as_ptr() as *mut _,
+            iov_len: buf.len(),
+        };
+        let rma_iov = ffi::fi_rma_iov {
+            addr,
+            len: buf.len(),
+            key,
+        };
+        let mut desc = desc(mr);
+        let msg = rma_msg(&iov, &mut desc, dest, &rma_iov, context);
+        let ret = self.post_op(BindFlags::TRANSMIT, || {
+            trace::tracked!(
+                self,
+                "fi_writemsg",
+                context,
+                Some(flags),
+                [reads(buf)],
+                unsafe { ffi::fi_writemsg(self.as_raw(), &msg, flags.bits()) }
+            )
+        });
+        check_len("fi_writemsg", ret).map(|_| ())
+    }
+
+    /// Write `buf` to remote memory, with remote CQ data.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn writedata(
+        &self,
+        buf: &[u8],
+        mr: Option<&MemoryRegion<M>>,
+        data: u64,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.check_op(|| {
+            Op::new("fi_writedata", Kind::Write, buf.len())
+                .rma_iov(1)
+                .data()
+        })?;
+        trace::data_op!(self, "fi_writedata", size = buf.len());
+        let ret = self.post_op(BindFlags::TRANSMIT, || {
+            trace::tracked!(self, "fi_writedata", context, None, [reads(buf)], unsafe {
+                ffi::fi_writedata(
+                    self.as_raw(),
+                    buf.as_ptr().cast(),
+                    buf.len(),
+                    desc(mr),
+                    data,
+                    dest.as_raw(),
+                    addr,
+                    key,
+                    context as *mut _,
+                )
+            })
+        });
+        check_len("fi_writedata", ret).map(|_| ())
+    }
+
+    /// Write a small buffer to remote memory, without a completion.
+    pub fn inject_write(&self, buf: &[u8], dest: Addr, addr: u64, key: u64) -> Result<()> {
+        self.check_op(|| {
+            Op::new("fi_inject_write", Kind::Write, buf.len())
+                .rma_iov(1)
+                .inject()
+        })?;
+        trace::data_op!(self, "fi_inject_write", size = buf.len());
+        let ret = unsafe {
+            ffi::fi_inject_write(
+                self.as_raw(),
+                buf.as_ptr().cast(),
+                buf.len(),
+                dest.as_raw(),
+                addr,
+                key,
+            )
+        };
+        check_len("fi_inject_write", ret).map(|_| ())
+    }
+
+    /// Like [`inject_write()`](Self::inject_write), with remote CQ data.
+    pub fn inject_writedata(
+        &self,
+        buf: &[u8],
+        data: u64,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+    ) -> Result<()> {
+        self.check_op(|| {
+            Op::new("fi_inject_writedata", Kind::Write, buf.len())
+                .rma_iov(1)
+                .inject()
+                .data()
+        })?;
+        trace::data_op!(self, "fi_inject_writedata", size = buf.len());
+        let ret = unsafe {
+            ffi::fi_inject_writedata(
+                self.as_raw(),
+                buf.as_ptr().cast(),
+                buf.len(),
+                data,
+                dest.as_raw(),
+                addr,
+                key,
+            )
+        };
+        check_len("fi_inject_writedata", ret).map(|_| ())
+    }
+}
+
+/// Writes followed by a notification of the target.
+impl<M: ThreadingModel, P: HasRma + HasMsg> Endpoint<M, Enabled, P> {
+    /// Write `buf` to remote memory, then notify the target such that the notification never
+    /// reaches it before the data: with remote CQ data of the write itself, or with a message
+    /// posted after it as the [`NotifyOrder`] of the endpoint requires. All the operations
+    /// complete with `context`, as the returned [`RmaCompletions`] counts.
+    ///
+    /// A send failing with `-FI_EAGAIN` is retried after driving the progress of the completion
+    /// queues of the endpoint, since the write is posted already. Should it fail otherwise, the
+    /// error is returned and the write still completes. Fails with `FI_EOPNOTSUPP` for a
+    /// message on endpoints which can order it neither way.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation, `buf` being in use until the operations completed.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn write_with_notification(
+        &self,
+        buf: &[u8],
+        mr: Option<&MemoryRegion<M>>,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        notify: Notify<'_>,
+        context: usize,
+    ) -> Result<RmaCompletions> {
+        let message = match notify {
+            Notify::Data(data) => {
+                unsafe { self.writedata(buf, mr, data, dest, addr, key, context) }?;
+                return Ok(RmaCompletions {
+                    context,
+                    posts: 1,
+                    completed: 0,
+                });
+            }
+            Notify::Send(message) => message,
+        };
+        let info = self.info();
+        let (write_flags, send_flags) =
+            match NotifyOrder::new(info.tx_attr().msg_order, info.caps())? {
+                NotifyOrder::Ordered => (OpFlags::empty(), OpFlags::empty()),
+                NotifyOrder::Fenced => (OpFlags::DELIVERY_COMPLETE, OpFlags::FENCE),
+            };
+        unsafe {
+            self.write_with_flags(
+                buf,
+                mr,
+                dest,
+                addr,
+                key,
+                context,
+                OpFlags::COMPLETION | write_flags,
+            )
+        }?;
+        let cqs = self.bound_cqs();
+        post_with_retry(
+            &RetryPolicy::new(),
+            || cqs.iter().try_for_each(CompletionQueue::progress),
+            // SAFETY: the message is injected, so not used once posted.
+            || unsafe {
+                self.send_with_flags(
+                    message,
+                    None,
+                    dest,
+                    context,
+                    OpFlags::COMPLETION | OpFlags::INJECT | send_flags,
+                )
+            },
+        )?;
+        Ok(RmaCompletions {
+            context,
+            posts: 2,
+            completed: 0,
+        })
+    }
+}
+
+fn rma_msg(
+    iov: &ffi::iovec,
+    desc: &mut *mut std::ffi::c_void,
+    peer: Addr,
+    rma_iov: &ffi::fi_rma_iov,
+    context: usize,
+) -> ffi::fi_msg_rma {
+    ffi::fi_msg_rma {
+        msg_iov: iov,
+        desc,
+        iov_count: 1,
+        addr: peer.as_raw(),
+        rma_iov,
+        rma_iov_count: 1,
+        context: context as *mut _,
+        data: 0,
+    }
+}
+
+/// Scatter-gather remote memory access, between several local buffers and one remote segment,
+/// or any list of segments when split into as many posts as the limits of the provider need.
+///
+/// The memory regions of the buffers are given as `mrs`, either one for each buffer or none at
+/// all.
+impl<M: ThreadingModel, P: HasRma> Endpoint<M, Enabled, P> {
+    /// Write `bufs` one after the other to remote memory at `addr`, via `fi_writev()`. At most
+    /// [`TxAttr::iov_limit`](crate::TxAttr::iov_limit) buffers are taken.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn writev(
+        &self,
+        bufs: &[&[u8]],
+        mrs: &[&MemoryRegion<M>],
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()> {
+        let iov: Vec<_> = bufs
+            .iter()
+            .map(|buf| iovec(buf.as_ptr(), buf.len()))
+            .collect();
+        let mut desc = descs(mrs, bufs.len())?;
+        self.check_op(|| {
+            Op::new("fi_writev", Kind::Write, total(&iov))
+                .iov(iov.len())
+                .rma_iov(1)
+        })?;
+        trace::data_op!(self, "fi_writev", size = total(&iov));
+        let ret = self.post_op(BindFlags::TRANSMIT, || {
+            trace::tracked!(
+                self,
+                "fi_writev",
+                context,
+                None,
+                [reads_iov(&iov)],
+                unsafe {
+                    ffi::fi_writev(
+                        self.as_raw(),
+                        iov.as_ptr(),
+                        desc.as_mut_ptr(),
+                        iov.len(),
+                        dest.as_raw(),
+                        addr,
+                        key,
+                        context as *mut _,
+                    )
+                }
+            )
+        });
+        check_len("fi_writev", ret).map(|_| ())
+    }
+
+    /// Read remote memory at `addr` into `bufs`, filled one after the other, via `fi_readv()`.
+    /// At most [`TxAttr::iov_limit`](crate::TxAttr::iov_limit) buffers are taken.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn readv(
+        &self,
+        bufs: &mut [&mut [u8]],
+        mrs: &[&MemoryRegion<M>],
+        src: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()> {
+        let iov: Vec<_> = bufs
+            .iter_mut()
+            .map(|buf| iovec(buf.as_mut_ptr(), buf.len()))
+            .collect();
+        let mut desc = descs(mrs, bufs.len())?;
+        self.check_op(|| {
+            Op::new("fi_readv", Kind::Read, total(&iov))
+                .iov(iov.len())
+                .rma_iov(1)
+        })?;
+        trace::data_op!(self, "fi_readv", size = total(&iov));
+        let ret = self.post_op(BindFlags::TRANSMIT, || {
+            trace::tracked!(
+                self,
+                "fi_readv",
+                context,
+                None,
+                [writes_iov(&iov)],
+                unsafe {
+                    ffi::fi_readv(
+                        self.as_raw(),
+                        iov.as_ptr(),
+                        desc.as_mut_ptr(),
+                        iov.len(),
+                        src.as_raw(),
+                        addr,
+                        key,
+                        context as *mut _,
+                    )
+                }
+            )
+        });
+        check_len("fi_readv", ret).map(|_| ())
+    }
+
+    /// Write `bufs` to the `remote` segments, as many bytes in total, with as many
+    /// `fi_writemsg()` as it takes to keep each within
+    /// [`TxAttr::iov_limit`](crate::TxAttr::iov_limit) buffers and
+    /// [`TxAttr::rma_iov_limit`](crate::TxAttr::rma_iov_limit) segments. Each completes with
+    /// `context`, as the returned [`RmaCompletions`] counts.
+    ///
+    /// Posts failing with `-FI_EAGAIN` are retried after driving the progress of the completion
+    /// queues of the endpoint. Should one fail otherwise, the error is returned and the posts
+    /// before it still complete.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation, the buffers being in use until all the posts
+    /// completed.
+    pub unsafe fn write_split(
+        &self,
+        bufs: &[&[u8]],
+        mrs: &[&MemoryRegion<M>],
+        dest: Addr,
+        remote: &[RmaIov],
+        context: usize,
+    ) -> Result<RmaCompletions> {
+        let iov: Vec<_> = bufs
+            .iter()
+            .map(|buf| iovec(buf.as_ptr(), buf.len()))
+            .collect();
+        let desc = descs(mrs, bufs.len())?;
+        trace::data_op!(self, "fi_writemsg", size = total(&iov));
+        self.post_split(
+            "fi_writemsg",
+            &iov,
+            &desc,
+            dest,
+            remote,
+            context,
+            |ep, msg| unsafe { ffi::fi_writemsg(ep, msg, ffi::FI_COMPLETION as u64) },
+        )
+    }
+
+    /// Read the `remote` segments into `bufs`, as many bytes in total, split like
+    /// [`write_split()`](Self::write_split) into `fi_readmsg()` calls.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation, the buffers being in use until all the posts
+    /// completed.
+    pub unsafe fn read_split(
+        &self,
+        bufs: &mut [&mut [u8]],
+        mrs: &[&MemoryRegion<M>],
+        src: Addr,
+        remote: &[RmaIov],
+        context: usize,
+    ) -> Result<RmaCompletions> {
+        let iov: Vec<_> = bufs
+            .iter_mut()
+            .map(|buf| iovec(buf.as_mut_ptr(), buf.len()))
+            .collect();
+        let desc = descs(mrs, bufs.len())?;
+        trace::data_op!(self, "fi_readmsg", size = total(&iov));
+        self.post_split(
+            "fi_readmsg",
+            &iov,
+            &desc,
+            src,
+            remote,
+            context,
+            |ep, msg| unsafe { ffi::fi_readmsg(ep, msg, ffi::FI_COMPLETION as u64) },
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn post_split(
+        &self,
+        op: &'static str,
+        iov: &[ffi::iovec],
+        desc: &[*mut c_void],
+        peer: Addr,
+        remote: &[RmaIov],
+        context: usize,
+        post: impl Fn(*mut ffi::fid_ep, *const ffi::fi_msg_rma) -> isize,
+    ) -> Result<RmaCompletions> {
+        let local = total(iov);
+        let len: usize = remote.iter().map(|segment| segment.len).sum();
+        if local != len {
+            return Err(Error::invalid(format!(
+                "{local} bytes of local buffers for {len} bytes of remote segments"
+            )));
+        }
+        let attr = self.info().tx_attr();
+        let parts = split(iov, remote, attr.iov_limit, attr.rma_iov_limit);
+        let cqs = self.bound_cqs();
+        let policy = RetryPolicy::new();
+        for part in &parts {
+            let part_iov: Vec<_> = part
+                .local
+                .iter()
+                .map(|&(i, offset, len)| {
+                    iovec(unsafe { iov[i].iov_base.cast::<u8>().add(offset) }, len)
+                })
+                .collect();
+            let mut part_desc: Vec<_> = part.local.iter().map(|&(i, ..)| desc[i]).collect();
+            let rma_iov: Vec<_> = part
+                .remote
+                .iter()
+                .map(|segment| ffi::fi_rma_iov {
+                    addr: segment.addr,
+                    len: segment.len,
+                    key: segment.key,
+                })
+                .collect();
+            let msg = ffi::fi_msg_rma {
+                msg_iov: part_iov.as_ptr(),
+                desc: part_desc.as_mut_ptr(),
+                iov_count: part_iov.len(),
+                addr: peer.as_raw(),
+                rma_iov: rma_iov.as_ptr(),
+                rma_iov_count: rma_iov.len(),
+                context: context as *mut _,
+                data: 0,
+            };
+            post_with_retry(
+                &policy,
+                || cqs.iter().try_for_each(CompletionQueue::progress),
+                || {
+                    check_len(
+                        op,
+                        self.post_op(BindFlags::TRANSMIT, || post(self.as_raw(), &msg)),
+                    )
+                },
+            )?;
+        }
+        Ok(RmaCompletions {
+            context,
+            posts: parts.len(),
+            completed: 0,
+        })
+    }
+}
+
+// The local pieces, as (buffer, offset, length), and remote segments of one post.
+#[derive(Default)]
+struct Part {
+    local: Vec<(usize, usize, usize)>,
+    remote: Vec<RmaIov>,
+}
+
+// Split the transfer between the local buffers and remote segments, which hold as many bytes,
+// into posts of at most `iov_limit` pieces of buffers and `rma_iov_limit` segments, cutting
+// them where they no longer fit.
+fn split(
+    local: &[ffi::iovec],
+    remote: &[RmaIov],
+    iov_limit: usize,
+    rma_iov_limit: usize,
+) -> Vec<Part> {
+    let (iov_limit, rma_iov_limit) = (iov_limit.max(1), rma_iov_limit.max(1));
+    let mut parts = Vec::new();
+    let mut part = Part::default();
+    let (mut l, mut l_offset, mut r, mut r_offset) = (0, 0, 0, 0);
+    loop {
+        while l < local.len() && l_offset == local[l].iov_len {
+            (l, l_offset) = (l + 1, 0);
+        }
+        while r < remote.len() && r_offset == remote[r].len {
+            (r, r_offset) = (r + 1, 0);
+        }
+        if l == local.len() || r == remote.len() {
+            break;
+        }
+        let len = (local[l].iov_len - l_offset).min(remote[r].len - r_offset);
+        let addr = remote[r].addr + r_offset as u64;
+        let mut extends_local = part
+            .local
+            .last()
+            .is_some_and(|&(i, offset, len)| i == l && offset + len == l_offset);
+        let mut extends_remote = part.remote.last().is_some_and(|segment| {
+            segment.key == remote[r].key && segment.addr + segment.len as u64 == addr
+        });
+        if (!extends_local && part.local.len() == iov_limit)
+            || (!extends_remote && part.remote.len() == rma_iov_limit)
+        {
+            parts.push(mem::take(&mut part));
+            (extends_local, extends_remote) = (false, false);
+        }
+        match part.local.last_mut() {
+            Some(piece) if extends_local => piece.2 += len,
+            _ => part.local.push((l, l_offset, len)),
+        }
+        match part.remote.last_mut() {
+            Some(segment) if extends_remote => segment.len += len,
+            _ => part.remote.push(RmaIov {
+                addr,
+                len,
+                key: remote[r].key,
+            }),
+        }
+        (l_offset, r_offset) = (l_offset + len, r_offset + len);
+    }
+    if !part.local.is_empty() {
+        parts.push(part);
+    }
+    parts
+}
+
+pub(crate) fn iovec<T>(base: *const T, len: usize) -> ffi::iovec {
+    ffi::iovec {
+        iov_base: base as *mut _,
+        iov_len: len,
+    }
+}
+
+pub(crate) fn total(iov: &[ffi::iovec]) -> usize {
+    iov.iter().map(|iov| iov.iov_len).sum()
+}
+
+// The descriptors of the buffers, none or one region each.
+pub(crate) fn descs<M: ThreadingModel>(
+    mrs: &[&MemoryRegion<M>],
+    bufs: usize,
+) -> Result<Vec<*mut c_void>> {
+    match mrs.len() {
+        0 => Ok(vec![std::ptr::null_mut(); bufs]),
+        len if len == bufs => Ok(mrs.iter().map(|mr| mr.desc()).collect()),
+        len => Err(Error::invalid(format!(
+            "{len} memory regions for {bufs} buffers"
+        ))),
+    }
+}
@@ -0,0 +1,401 @@
+//! Remote procedure calls over tagged messages, enabled by the `rpc` feature.
+//!
+//! An [`Rpc`] is both a client and a server: it serves the methods registered on it whenever it
+//! is polled, including while waiting for the response of one of its own calls, so peers may
+//! call each other. It runs over any [`Transport`] and [`Cq`], so over an RDM [`Endpoint`]
+//! opened with `Caps::TAGGED | Caps::SOURCE`, or a [`MockEndpoint`](crate::mock::MockEndpoint).
+//!
+//! Requests are tagged messages whose tag is the method, and whose remote CQ data is the call
+//! id. Responses are tagged with the call id and the high bit of the tag set, and carry the
+//! status as remote CQ data: 0, or the `FI_E*` code the call failed with. Servers reply to the
+//! source address of requests, so clients must be in their address vector; clients which are
+//! not are ignored, and their calls time out.
+//!
+//! Payloads are raw bytes, or values encoded by a [`Codec`], and are at most
+//! [`RpcAttr::max_size()`] bytes. Their buffers are not registered, so providers requiring
+//! `FI_MR_LOCAL` are not supported. Requests are handed to handlers in their receive buffer,
+//! aligned to 16 bytes, which [`register_archived()`](Rpc::register_archived) reads archives
+//! from in place.
+//!
+//! [`Endpoint`]: crate::Endpoint
+
+use crate::av::Addr;
+#[cfg(feature = "json")]
+pub use crate::codec::Json;
+#[cfg(feature = "rkyv")]
+use crate::codec::Rkyv;
+pub use crate::codec::{Codec, Raw};
+use crate::cq::{Completion, CqErrEntry};
+use crate::error::{Error, Result};
+use crate::transport::{Cq, CqHandler, Transport, poll_cq};
+use ofi_libfabric_sys::bindgen as ffi;
+use std::collections::{HashMap, VecDeque};
+use std::slice;
+use std::time::{Duration, Instant};
+
+// Set in the tag of responses, and the only bit receives match on.
+const RESPONSE: u64 = 1 << 63;
+
+/// Attributes of an [`Rpc`].
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct RpcAttr {
+    max_size: usize,
+    depth: usize,
+}
+
+impl Default for RpcAttr {
+    fn default() -> Self {
+        RpcAttr {
+            max_size: 4096,
+            depth: 16,
+        }
+    }
+}
+
+impl RpcAttr {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Largest request or response payload, 4 KiB by default.
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Number of receives kept posted for requests, and as many for responses, 16 by default.
+    pub fn depth(mut self, depth: usize) -> Self {
+        self.depth = depth.max(1);
+        self
+    }
+}
+
+type Handler = Box<dyn FnMut(Addr, &[u8]) -> Result<Vec<u8>> + Send>;
+
+// The unit of receive buffers, for rkyv archives to be aligned in them.
+#[derive(Clone, Copy)]
+#[repr(C, align(16))]
+struct Block([u8; 16]);
+
+fn bytes(slot: &[Block], len: usize) -> &[u8] {
+    // SAFETY: Blocks are plain bytes, of which the slot holds at least `len`.
+    unsafe { slice::from_raw_parts(slot.as_ptr().cast(), len) }
+}
+
+fn bytes_mut(slot: &mut [Block], len: usize) -> &mut [u8] {
+    // SAFETY: As for `bytes()`.
+    unsafe { slice::from_raw_parts_mut(slot.as_mut_ptr().cast(), len) }
+}
+
+// A request or response waiting for room to be sent.
+struct Outgoing {
+    dest: Addr,
+    tag: u64,
+    data: u64,
+    buf: Vec<u8>,
+    // The call of requests, failed along with their send.
+    call: Option<u32>,
+}
+
+/// A client and server of remote procedure calls, see the [module](self) documentation.
+pub struct Rpc<T: Transport, C: Cq> {
+    // First, see `CqHandler`.
+    ep: T,
+    cq: C,
+    attr: RpcAttr,
+    // The receive buffers, for requests then for responses, each a separate allocation which
+    // is only read once its receive completed.
+    slots: Vec<Box<[Block]>>,
+    unposted: Vec<usize>,
+    handlers: HashMap<u32, Handler>,
+    next_call: u32,
+    // Calls waiting for their response, which is set once it arrives.
+    pending: HashMap<u32, Option<Result<Vec<u8>>>>,
+    outbox: VecDeque<Outgoing>,
+    // The buffers of posted sends, by context.
+    sends: HashMap<usize, (Vec<u8>, Option<u32>)>,
+    next_send: usize,
+    // The requests served by the current poll.
+    served: usize,
+}
+
+impl<T: Transport, C: Cq> Rpc<T, C> {
+    /// Post the receives of the RPC layer on `ep`, whose completions are read from `cq`.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Transport`] documentation.
+    pub unsafe fn new(ep: T, cq: C, attr: &RpcAttr) -> Result<Self> {
+        let slots = (0..2 * attr.depth)
+            .map(|_| vec![Block([0; 16]); attr.max_size.div_ceil(16)].into_boxed_slice())
+            .collect();
+        let mut rpc = Rpc {
+            ep,
+            cq,
+            attr: attr.clone(),
+            slots,
+            unposted: (0..2 * attr.depth).rev().collect(),
+            handlers: HashMap::new(),
+            next_call: 0,
+            pending: HashMap::new(),
+            outbox: VecDeque::new(),
+            sends: HashMap::new(),
+            next_send: 0,
+            served: 0,
+        };
+        rpc.flush()?;
+        Ok(rpc)
+    }
+
+    pub fn endpoint(&self) -> &T {
+        &self.ep
+    }
+
+    /// Serve `method` with `handler`, called with the address of the caller and the request.
+    /// Errors are returned to the caller as their code.
+    pub fn register(
+        &mut self,
+        method: u32,
+        handler: impl FnMut(Addr, &[u8]) -> Result<Vec<u8>> + Send + 'static,
+    ) {
+        self.handlers.insert(method, Box::new(handler));
+    }
+
+    /// Like [`register()`](Self::register), for requests and responses encoded by `K`.
+    pub fn register_with<K, Req, Resp>(
+        &mut self,
+        method: u32,
+        mut handler: impl FnMut(Addr, Req) -> Result<Resp> + Send + 'static,
+    ) where
+        K: Codec<Req> + Codec<Resp>,
+    {
+        self.register(method, move |src, bytes| {
+            let request = <K as Codec<Req>>::decode(bytes)?;
+            <K as Codec<Resp>>::encode(&handler(src, request)?)
+        });
+    }
+
+    /// Like [`register()`](Self::register), for requests encoded by [`Rkyv`], which `handler`
+    /// reads in place in their receive buffer, once validated, and responses encoded by it.
+    /// Requires the `rkyv` feature.
+    #[cfg(feature = "rkyv")]
+    pub fn register_archived<Req, Resp>(
+        &mut self,
+        method: u32,
+        mut handler: impl FnMut(Addr, &rkyv::Archived<Req>) -> Result<Resp> + Send + 'static,
+    ) where
+        Req: rkyv::Archive,
+        Req::Archived: for<'a> rkyv::bytecheck::CheckBytes<
+                rkyv::api::high::HighValidator<'a, rkyv::rancor::Error>,
+            >,
+        Rkyv: Codec<Resp>,
+    {
+        self.register(method, move |src, bytes| {
+            let request = Rkyv::access::<Req>(bytes)?;
+            <Rkyv as Codec<Resp>>::encode(&handler(src, request)?)
+        });
+    }
+
+    /// Call `method` on the peer at `dest`, waiting up to `timeout` for its response. Requests
+    /// to this endpoint are served in the meantime.
+    pub fn call(
+        &mut self,
+        dest: Addr,
+        method: u32,
+        request: &[u8],
+        timeout: Duration,
+    ) -> Result<Vec<u8>> {
+        if request.len() > self.attr.max_size {
+            return Err(Error::invalid(format!(
+                "{} bytes request over the {} bytes maximum",
+                request.len(),
+                self.attr.max_size
+            )));
+        }
+        let call = self.next_call;
+        self.next_call = self.next_call.wrapping_add(1);
+        self.pending.insert(call, None);
+        self.outbox.push_back(Outgoing {
+            dest,
+            tag: method as u64,
+            data: call as u64,
+            buf: request.to_vec(),
+            call: Some(call),
+        });
+        let deadline = Instant::now() + timeout;
+        loop {
+            let polled = self.poll();
+            if let Some(Some(_)) = self.pending.get(&call) {
+                return self.pending.remove(&call).flatten().unwrap();
+            }
+            if let Err(err) = polled {
+                self.pending.remove(&call);
+                return Err(err);
+            }
+            if Instant::now() >= deadline {
+                // A late response is dropped once it arrives.
+                self.pending.remove(&call);
+                return Err(Error::fabric("rpc call", ffi::FI_ETIMEDOUT as i64));
+            }
+        }
+    }
+
+    /// Like [`call()`](Self::call), with the request and response encoded by `K`.
+    pub fn call_with<K, Req, Resp>(
+        &mut self,
+        dest: Addr,
+        method: u32,
+        request: &Req,
+        timeout: Duration,
+    ) -> Result<Resp>
+    where
+        K: Codec<Req> + Codec<Resp>,
+    {
+        let request = <K as Codec<Req>>::encode(request)?;
+        let response = self.call(dest, method, &request, timeout)?;
+        <K as Codec<Resp>>::decode(&response)
+    }
+
+    /// Read the completions available, serve the requests among them, and post the responses.
+    /// Returns the number of requests served.
+    pub fn poll(&mut self) -> Result<usize> {
+        self.served = 0;
+        poll_cq(self)?;
+        self.flush()?;
+        Ok(self.served)
+    }
+
+    // Set the result of a call still waiting for it.
+    fn respond(&mut self, call: u32, result: Result<Vec<u8>>) {
+        if let Some(pending) = self.pending.get_mut(&call)
+            && pending.is_none()
+        {
+            *pending = Some(result);
+        }
+    }
+
+    // Repost the receives and post the sends waiting for room, until the provider runs out.
+    fn flush(&mut self) -> Result<()> {
+        while let Some(slot) = self.unposted.pop() {
+            let tag = match slot < self.attr.depth {
+                true => 0,
+                false => RESPONSE,
+            };
+            // SAFETY: the slot is not read until the receive completes, and outlives the
+            // endpoint, see `new()`.
+            let posted = unsafe {
+                self.ep.trecv(
+                    bytes_mut(&mut self.slots[slot], self.attr.max_size),
+                    None,
+                    Addr::UNSPEC,
+                    tag,
+                    !RESPONSE,
+                    slot,
+                )
+            };
+            match posted {
+                Err(err) if err.is_again() => {
+                    self.unposted.push(slot);
+                    break;
+                }
+                other => other?,
+            }
+        }
+        while let Some(out) = self.outbox.pop_front() {
+            let context = self.slots.len().wrapping_add(self.next_send);
+            // SAFETY: the buffer is kept in `sends` until the send completes.
+            let posted = unsafe {
+                self.ep
+                    .tsenddata(&out.buf, None, out.data, out.dest, out.tag, context)
+            };
+            match posted {
+                Ok(()) => {
+                    self.next_send = self.next_send.wrapping_add(1);
+                    self.sends.insert(context, (out.buf, out.call));
+                }
+                Err(err) if err.is_again() => {
+                    self.outbox.push_front(out);
+                    break;
+                }
+                // Responses which fail to be sent are dropped, like lost ones.
+                Err(err) => {
+                    if let Some(call) = out.call {
+                        self.respond(call, Err(err));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: Transport, C: Cq> CqHandler for Rpc<T, C> {
+    type Cq = C;
+
+    fn cq(&self) -> &C {
+        &self.cq
+    }
+
+    fn complete(&mut self, completion: &Completion, src: Addr) {
+        let slot = completion.context();
+        if slot >= self.slots.len() {
+            self.sends.remove(&slot);
+            return;
+        }
+        self.unposted.push(slot);
+        let payload = bytes(&self.slots[slot], completion.len());
+        let tag = completion.tag();
+        if tag & RESPONSE != 0 {
+            let result = match completion.data() {
+                0 => Ok(payload.to_vec()),
+                code => Err(Error::fabric("rpc call", code as i64)),
+            };
+            self.respond(tag as u32, result);
+            return;
+        }
+        if src == Addr::NOTAVAIL {
+            return;
+        }
+        let (status, response) = match self.handlers.get_mut(&(tag as u32)) {
+            None => (ffi::FI_ENOSYS as u64, Vec::new()),
+            Some(handler) => match handler(src, payload) {
+                Ok(response) if response.len() > self.attr.max_size => {
+                    (ffi::FI_EMSGSIZE as u64, Vec::new())
+                }
+                Ok(response) => (0, response),
+                Err(err) => (err.code() as u64, Vec::new()),
+            },
+        };
+        self.outbox.push_back(Outgoing {
+            dest: src,
+            tag: RESPONSE | completion.data() as u32 as u64,
+            data: status,
+            buf: response,
+            call: None,
+        });
+        self.served += 1;
+    }
+
+    fn failed(&mut self, entry: CqErrEntry) {
+        if entry.context >= self.slots.len() {
+            if let Some((_, Some(call))) = self.sends.remove(&entry.context) {
+                self.respond(call, Err(entry.error));
+            }
+            return;
+        }
+        // A failed receive, which is truncated with a payload over the maximum size.
+        self.unposted.push(entry.context);
+        if entry.tag & RESPONSE != 0 {
+            self.respond(entry.tag as u32, Err(entry.error));
+        } else if entry.src_addr != Addr::NOTAVAIL {
+            self.outbox.push_back(Outgoing {
+                dest: entry.src_addr,
+                tag: RESPONSE | entry.data as u32 as u64,
+                data: ffi::FI_EMSGSIZE as u64,
+                buf: Vec::new(),
+                call: None,
+            });
+        }
+    }
+}
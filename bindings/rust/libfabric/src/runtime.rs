@@ -0,0 +1,182 @@
+use crate::av::{AddressVector, AvAttr};
+use crate::domain::Domain;
+use crate::error::{Error, Result};
+use crate::fabric::Fabric;
+use crate::info::InfoEntry;
+use crate::lifecycle::Lifecycle;
+use crate::quiesce::{Quiesce, QuiesceReport};
+use crate::registry::FabricDomain;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+
+// The runtime of the process, alive while a handle to it is.
+static RUNTIME: Mutex<Weak<RuntimeInner>> = Mutex::new(Weak::new());
+
+#[derive(Default)]
+struct Objects {
+    // By provider and fabric name.
+    fabrics: HashMap<(String, String), Fabric>,
+    domains: HashMap<FabricDomain, Domain>,
+    // By domain and name.
+    avs: HashMap<(FabricDomain, String), AddressVector>,
+}
+
+struct RuntimeInner {
+    objects: Mutex<Objects>,
+    lifecycle: Lifecycle,
+}
+
+/// The fabrics, domains and address vectors of the process, opened once and shared by its
+/// subsystems, ex: the libraries of a large application which each open the same domain
+/// otherwise, at the cost of its resources, or close it under the others.
+///
+/// [`get()`](Self::get) opts in: it returns a handle to the runtime of the process, created
+/// on first use. Handles keep the runtime alive, and it is torn down once the last is dropped,
+/// or checked by [`shutdown()`](Self::shutdown): the next `get()` then creates another. The
+/// objects are opened on first request, and keyed by the names of their entry, so that an
+/// entry of the same provider, fabric and domain, from any query, returns the same domain.
+///
+/// ```no_run
+/// use libfabric::{AvAttr, FabricRuntime, Info};
+///
+/// # fn run() -> libfabric::Result<()> {
+/// let entry = &Info::new().provider("tcp").get()?[0];
+/// // In one subsystem.
+/// let runtime = FabricRuntime::get();
+/// let domain = runtime.domain(entry)?;
+/// // In another, the same domain and address vector.
+/// let av = FabricRuntime::get().av(entry, "peers", &AvAttr::new())?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct FabricRuntime {
+    inner: Arc<RuntimeInner>,
+}
+
+impl FabricRuntime {
+    /// A handle to the runtime of the process, created if none is alive.
+    pub fn get() -> Self {
+        let mut runtime = RUNTIME.lock().unwrap();
+        if let Some(inner) = runtime.upgrade() {
+            return FabricRuntime { inner };
+        }
+        let inner = Arc::new(RuntimeInner {
+            objects: Mutex::default(),
+            lifecycle: Lifecycle::new(),
+        });
+        *runtime = Arc::downgrade(&inner);
+        FabricRuntime { inner }
+    }
+
+    /// Whether a handle to the runtime of the process is alive.
+    pub fn is_running() -> bool {
+        RUNTIME.lock().unwrap().strong_count() > 0
+    }
+
+    /// The handles to the runtime, this one included.
+    pub fn handles(&self) -> usize {
+        Arc::strong_count(&self.inner)
+    }
+
+    /// The broadcast of the runtime, on which [`shutdown()`](Self::shutdown) publishes the
+    /// domains it closes.
+    pub fn lifecycle(&self) -> &Lifecycle {
+        &self.inner.lifecycle
+    }
+
+    /// The fabric of `entry`, opened on first request.
+    pub fn fabric(&self, entry: &InfoEntry) -> Result<Fabric> {
+        fabric(&mut self.objects(), entry)
+    }
+
+    /// The domain of `entry`, opened on first request, along with its fabric.
+    pub fn domain(&self, entry: &InfoEntry) -> Result<Domain> {
+        domain(&mut self.objects(), entry)
+    }
+
+    /// The address vector `name` of the domain of `entry`, opened with `attr` on first
+    /// request, so that the subsystems all insert their peers in one table.
+    pub fn av(&self, entry: &InfoEntry, name: &str, attr: &AvAttr) -> Result<AddressVector> {
+        let mut objects = self.objects();
+        let key = (key(entry), name.to_owned());
+        if let Some(av) = objects.avs.get(&key) {
+            return Ok(av.clone());
+        }
+        let av = domain(&mut objects, entry)?.av(attr)?;
+        objects.avs.insert(key, av.clone());
+        Ok(av)
+    }
+
+    /// The domains opened, by name.
+    pub fn domains(&self) -> Vec<FabricDomain> {
+        let mut domains: Vec<_> = self.objects().domains.keys().cloned().collect();
+        domains.sort();
+        domains
+    }
+
+    /// Tear the runtime down, closing its address vectors, domains and fabrics with a
+    /// [`Quiesce`], which reports them.
+    ///
+    /// Fails with an invalid argument error, keeping the runtime, while other handles to it
+    /// are alive, and as a quiesce does while the application holds objects opened from it,
+    /// which are then closed once dropped.
+    pub fn shutdown(self) -> Result<QuiesceReport> {
+        let inner = match Arc::try_unwrap(self.inner) {
+            Ok(inner) => inner,
+            Err(inner) => {
+                return Err(Error::invalid(format!(
+                    "fabric runtime shut down with {} other handles alive",
+                    Arc::strong_count(&inner) - 1
+                )));
+            }
+        };
+        let objects = inner.objects.into_inner().unwrap();
+        let mut quiesce = Quiesce::new().lifecycle(&inner.lifecycle);
+        for av in objects.avs.into_values() {
+            quiesce = quiesce.av(av);
+        }
+        for domain in objects.domains.into_values() {
+            quiesce = quiesce.domain(domain);
+        }
+        for fabric in objects.fabrics.into_values() {
+            quiesce = quiesce.fabric(fabric);
+        }
+        quiesce.run()
+    }
+
+    fn objects(&self) -> MutexGuard<'_, Objects> {
+        self.inner.objects.lock().unwrap()
+    }
+}
+
+fn key(entry: &InfoEntry) -> FabricDomain {
+    FabricDomain {
+        provider: entry.provider_name().to_owned(),
+        fabric: entry.fabric_name().to_owned(),
+        domain: entry.domain_name().to_owned(),
+    }
+}
+
+fn fabric(objects: &mut Objects, entry: &InfoEntry) -> Result<Fabric> {
+    let key = (
+        entry.provider_name().to_owned(),
+        entry.fabric_name().to_owned(),
+    );
+    if let Some(fabric) = objects.fabrics.get(&key) {
+        return Ok(fabric.clone());
+    }
+    let fabric = Fabric::open(entry)?;
+    objects.fabrics.insert(key, fabric.clone());
+    Ok(fabric)
+}
+
+fn domain(objects: &mut Objects, entry: &InfoEntry) -> Result<Domain> {
+    let key = key(entry);
+    if let Some(domain) = objects.domains.get(&key) {
+        return Ok(domain.clone());
+    }
+    let domain = fabric(objects, entry)?.domain(entry)?;
+    objects.domains.insert(key, domain.clone());
+    Ok(domain)
+}
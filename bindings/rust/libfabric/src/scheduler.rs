@@ -0,0 +1,314 @@
+use crate::av::Addr;
+use crate::cq::{Completion, CqErrEntry};
+use crate::error::{Error, Result};
+use crate::transport::{Cq, CqHandler, Transport, poll_cq};
+use ofi_libfabric_sys::bindgen as ffi;
+use std::collections::{HashMap, VecDeque};
+
+/// How a [`SendScheduler`] takes turns between the peers with messages queued.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SchedulePolicy {
+    /// One message per peer and turn, whatever its size.
+    #[default]
+    RoundRobin,
+    /// Deficit round robin: each turn, a peer may send [`SchedulerAttr::quantum()`] bytes
+    /// times its [weight](SendScheduler::set_weight), so that peers share the transmit context
+    /// by bytes, in proportion to their weights, however large their messages.
+    Weighted,
+}
+
+/// Attributes of a [`SendScheduler`].
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct SchedulerAttr {
+    policy: SchedulePolicy,
+    quantum: usize,
+    max_in_flight: Option<usize>,
+    max_queued: usize,
+}
+
+impl Default for SchedulerAttr {
+    fn default() -> Self {
+        SchedulerAttr {
+            policy: SchedulePolicy::RoundRobin,
+            quantum: 8192,
+            max_in_flight: None,
+            max_queued: 1024,
+        }
+    }
+}
+
+impl SchedulerAttr {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Round robin by default.
+    pub fn policy(mut self, policy: SchedulePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Bytes a peer of weight 1 may send per turn under [`SchedulePolicy::Weighted`], 8 KiB by
+    /// default.
+    pub fn quantum(mut self, bytes: usize) -> Self {
+        self.quantum = bytes.max(1);
+        self
+    }
+
+    /// Sends posted at once, as many as the provider takes by default. Keeping the transmit
+    /// context shallow leaves the order of the messages to the scheduler rather than to the
+    /// queue of the provider.
+    pub fn max_in_flight(mut self, sends: usize) -> Self {
+        self.max_in_flight = Some(sends.max(1));
+        self
+    }
+
+    /// Messages queued per peer before [`SendScheduler::send()`] fails with `FI_EAGAIN`,
+    /// 1024 by default.
+    pub fn max_queued(mut self, messages: usize) -> Self {
+        self.max_queued = messages.max(1);
+        self
+    }
+}
+
+/// A message waiting for its turn.
+struct Queued {
+    buf: Vec<u8>,
+    tag: Option<u64>,
+}
+
+struct PeerQueue {
+    messages: VecDeque<Queued>,
+    weight: u32,
+    // What the peer may still send this turn, in messages or bytes, and whether its turn
+    // started.
+    deficit: usize,
+    in_turn: bool,
+}
+
+/// An outbound scheduler keeping a queue of messages per destination, and draining them into
+/// the transmit context in turns, round robin or weighted, so that a peer sent much to does
+/// not starve the others when the transmit context is what holds the sends back.
+///
+/// Messages are copied into the queue of their destination, and posted as the provider has
+/// room for them, or as sends complete under [`SchedulerAttr::max_in_flight()`]. The queues
+/// of the peers are drained in turns, in the order they got messages queued: under
+/// [`SchedulePolicy::RoundRobin`] one message per turn, under [`SchedulePolicy::Weighted`] as
+/// many bytes as the weight of the peer allows, carried over to its next turn when its next
+/// message is larger. Messages to one peer are sent in order.
+///
+/// It runs over any [`Transport`] and [`Cq`], as [`FlowControl`](crate::FlowControl) does,
+/// and reads the transmit completions of the endpoint from its queue, which is its own.
+///
+/// ```no_run
+/// use libfabric::{Addr, CompletionQueue, Endpoint, SchedulePolicy, SchedulerAttr, SendScheduler};
+///
+/// # fn run(ep: Endpoint, cq: CompletionQueue, hot: Addr, peers: &[Addr]) -> libfabric::Result<()> {
+/// let attr = SchedulerAttr::new()
+///     .policy(SchedulePolicy::Weighted)
+///     .max_in_flight(64);
+/// let mut sched = unsafe { SendScheduler::new(ep, cq, &attr) };
+/// sched.set_weight(hot, 4);
+/// for peer in peers {
+///     sched.send(*peer, b"update")?;
+/// }
+/// while sched.backlog() + sched.in_flight() > 0 {
+///     sched.poll()?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct SendScheduler<T: Transport, C: Cq> {
+    // First, see `CqHandler`.
+    ep: T,
+    cq: C,
+    attr: SchedulerAttr,
+    queues: HashMap<Addr, PeerQueue>,
+    // The peers with messages queued, the one whose turn it is first.
+    turns: VecDeque<Addr>,
+    // The buffers of posted sends, by context.
+    sends: HashMap<usize, Queued>,
+    next_send: usize,
+    errors: VecDeque<Error>,
+}
+
+impl<T: Transport, C: Cq> SendScheduler<T, C> {
+    /// Schedule the sends of `ep`, whose transmit completions are read from `cq`.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Transport`] documentation.
+    pub unsafe fn new(ep: T, cq: C, attr: &SchedulerAttr) -> Self {
+        SendScheduler {
+            ep,
+            cq,
+            attr: attr.clone(),
+            queues: HashMap::new(),
+            turns: VecDeque::new(),
+            sends: HashMap::new(),
+            next_send: 0,
+            errors: VecDeque::new(),
+        }
+    }
+
+    pub fn endpoint(&self) -> &T {
+        &self.ep
+    }
+
+    /// Set the share of `dest` under [`SchedulePolicy::Weighted`], 1 by default.
+    pub fn set_weight(&mut self, dest: Addr, weight: u32) {
+        self.queue(dest).weight = weight.max(1);
+    }
+
+    /// Queue `buf` for `dest`, and send what the turns allow. Fails with `FI_EAGAIN` when
+    /// [`SchedulerAttr::max_queued()`] messages are queued for `dest` already.
+    pub fn send(&mut self, dest: Addr, buf: &[u8]) -> Result<()> {
+        self.enqueue(dest, buf, None)
+    }
+
+    /// Queue `buf` for `dest`, to be sent with `tag`.
+    pub fn tsend(&mut self, dest: Addr, buf: &[u8], tag: u64) -> Result<()> {
+        self.enqueue(dest, buf, Some(tag))
+    }
+
+    fn enqueue(&mut self, dest: Addr, buf: &[u8], tag: Option<u64>) -> Result<()> {
+        let max_queued = self.attr.max_queued;
+        let queue = self.queue(dest);
+        if queue.messages.len() >= max_queued {
+            return Err(Error::fabric("fi_send", ffi::FI_EAGAIN as i64));
+        }
+        queue.messages.push_back(Queued {
+            buf: buf.to_vec(),
+            tag,
+        });
+        if queue.messages.len() == 1 {
+            self.turns.push_back(dest);
+        }
+        self.drain()
+    }
+
+    fn queue(&mut self, dest: Addr) -> &mut PeerQueue {
+        self.queues.entry(dest).or_insert_with(|| PeerQueue {
+            messages: VecDeque::new(),
+            weight: 1,
+            deficit: 0,
+            in_turn: false,
+        })
+    }
+
+    /// Messages queued for `dest`.
+    pub fn queued(&self, dest: Addr) -> usize {
+        self.queues
+            .get(&dest)
+            .map_or(0, |queue| queue.messages.len())
+    }
+
+    /// Messages queued for every peer.
+    pub fn backlog(&self) -> usize {
+        self.queues.values().map(|queue| queue.messages.len()).sum()
+    }
+
+    /// Sends posted, not completed yet.
+    pub fn in_flight(&self) -> usize {
+        self.sends.len()
+    }
+
+    /// Read the completions available, then send what the turns allow. Fails with the error
+    /// of a send which failed.
+    pub fn poll(&mut self) -> Result<()> {
+        poll_cq(self)?;
+        self.drain()?;
+        match self.errors.pop_front() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    // Post the messages of the peers in turn, until the provider runs out of room or sends
+    // reach the maximum in flight.
+    fn drain(&mut self) -> Result<()> {
+        let (policy, quantum) = (self.attr.policy, self.attr.quantum);
+        while let Some(&dest) = self.turns.front() {
+            if self
+                .attr
+                .max_in_flight
+                .is_some_and(|max| self.sends.len() >= max)
+            {
+                break;
+            }
+            let queue = self.queues.get_mut(&dest).unwrap();
+            if !queue.in_turn {
+                queue.deficit += match policy {
+                    SchedulePolicy::RoundRobin => 1,
+                    SchedulePolicy::Weighted => quantum * queue.weight as usize,
+                };
+                queue.in_turn = true;
+            }
+            let message = queue.messages.front().unwrap();
+            let cost = match policy {
+                SchedulePolicy::RoundRobin => 1,
+                SchedulePolicy::Weighted => message.buf.len(),
+            };
+            if cost > queue.deficit {
+                queue.in_turn = false;
+                self.turns.rotate_left(1);
+                continue;
+            }
+            let context = self.next_send;
+            // SAFETY: the buffer is kept in `sends` until the send completes.
+            let posted = unsafe {
+                match message.tag {
+                    Some(tag) => self.ep.tsend(&message.buf, None, dest, tag, context),
+                    None => self.ep.send(&message.buf, None, dest, context),
+                }
+            };
+            match posted {
+                Err(err) if err.is_again() => break,
+                // The message is dropped, like a lost one.
+                Err(err) => {
+                    self.next(dest, cost);
+                    return Err(err);
+                }
+                Ok(()) => {
+                    let message = self.next(dest, cost);
+                    self.next_send = self.next_send.wrapping_add(1);
+                    self.sends.insert(context, message);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Take the message of `dest` at the head of the turns off its queue, ending the turn of
+    // the peer once it has nothing left.
+    fn next(&mut self, dest: Addr, cost: usize) -> Queued {
+        let queue = self.queues.get_mut(&dest).unwrap();
+        let message = queue.messages.pop_front().unwrap();
+        queue.deficit -= cost;
+        if queue.messages.is_empty() {
+            queue.deficit = 0;
+            queue.in_turn = false;
+            self.turns.pop_front();
+        }
+        message
+    }
+}
+
+impl<T: Transport, C: Cq> CqHandler for SendScheduler<T, C> {
+    type Cq = C;
+
+    fn cq(&self) -> &C {
+        &self.cq
+    }
+
+    fn complete(&mut self, completion: &Completion, _src: Addr) {
+        self.sends.remove(&completion.context());
+    }
+
+    fn failed(&mut self, entry: CqErrEntry) {
+        if self.sends.remove(&entry.context).is_some() {
+            self.errors.push_back(entry.error);
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use crate::error::{Error, Result};
+use crate::flags::{Caps, MrMode};
+use crate::info::{Info, InfoEntry};
+use ofi_libfabric_sys::bindgen as ffi;
+use std::cmp::Reverse;
+
+// Core providers implementing the transport in software, over the kernel network stack or
+// shared memory, rather than offloading RMA to the NIC.
+const SOFTWARE_PROVIDERS: &[&str] = &["tcp", "udp", "sockets", "shm", "sm2", "net", "lnx"];
+
+/// How [`select_provider()`] filters and ranks the entries of `fi_getinfo()`.
+///
+/// Filters drop entries; preferences then rank those left, in the order the methods below are
+/// listed, with ties keeping the order of `fi_getinfo()`.
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct SelectionPolicy {
+    require_caps: Caps,
+    require_hmem: bool,
+    exclude: Vec<String>,
+    prefer_rdma: bool,
+    numa_node: Option<u32>,
+    prefer_speed: bool,
+    prefer: Vec<String>,
+}
+
+impl Default for SelectionPolicy {
+    fn default() -> Self {
+        SelectionPolicy {
+            require_caps: Caps::empty(),
+            require_hmem: false,
+            exclude: Vec::new(),
+            prefer_rdma: false,
+            numa_node: None,
+            prefer_speed: false,
+            prefer: Vec::new(),
+        }
+    }
+}
+
+impl SelectionPolicy {
+    /// Keep every entry, in order.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drop the entries missing any of `caps`.
+    pub fn require_caps(mut self, caps: Caps) -> Self {
+        self.require_caps |= caps;
+        self
+    }
+
+    /// Drop the entries which do not support device memory: without the
+    /// [`Caps::HMEM`] capability, or without the [`MrMode::HMEM`]
+    /// registration mode.
+    pub fn require_hmem(mut self) -> Self {
+        self.require_hmem = true;
+        self
+    }
+
+    /// Drop the entries of `provider`, be it their core provider or one of their layers, e.g.
+    /// `"tcp"` drops `"tcp;ofi_rxm"` entries too.
+    pub fn exclude(mut self, provider: &str) -> Self {
+        self.exclude.push(provider.to_owned());
+        self
+    }
+
+    /// Rank first the entries whose NIC offloads RMA, that is, those with [`Caps::RMA`] whose
+    /// core provider does not implement it in software.
+    pub fn prefer_rdma(mut self) -> Self {
+        self.prefer_rdma = true;
+        self
+    }
+
+    /// Rank first the entries whose NIC is attached to NUMA node `node`, e.g. that of the
+    /// cores the application runs on.
+    pub fn prefer_numa_node(mut self, node: u32) -> Self {
+        self.numa_node = Some(node);
+        self
+    }
+
+    /// Rank first the entries whose NIC has the fastest link, counting links known to be down as
+    /// the slowest.
+    pub fn prefer_speed(mut self) -> Self {
+        self.prefer_speed = true;
+        self
+    }
+
+    /// Rank the entries of the given providers first, in the order given, calling it once per
+    /// provider.
+    pub fn prefer(mut self, provider: &str) -> Self {
+        self.prefer.push(provider.to_owned());
+        self
+    }
+
+    fn keeps(&self, entry: &InfoEntry) -> bool {
+        let hmem = entry.caps().contains(Caps::HMEM) && entry.mr_mode().contains(MrMode::HMEM);
+        entry.caps().contains(self.require_caps)
+            && (!self.require_hmem || hmem)
+            && !layers(entry).any(|layer| self.exclude.iter().any(|p| p == layer))
+    }
+
+    // Lower ranks first.
+    fn rank(&self, entry: &InfoEntry) -> (bool, bool, Reverse<usize>, usize) {
+        let rdma = self.prefer_rdma && is_rdma(entry);
+        let nic = entry.nic();
+        let numa = self.numa_node.is_some()
+            && nic.as_ref().and_then(|nic| nic.numa_node()) == self.numa_node;
+        let speed = match nic {
+            Some(nic) if self.prefer_speed && nic.link_up != Some(false) => nic.speed,
+            _ => 0,
+        };
+        let preferred = self
+            .prefer
+            .iter()
+            .position(|p| layers(entry).any(|layer| layer == p))
+            .unwrap_or(self.prefer.len());
+        (!rdma, !numa, Reverse(speed), preferred)
+    }
+}
+
+/// Resolve `hints`, then filter and rank the entries by `policy`, best first.
+///
+/// Fails with `FI_ENODATA`, as `fi_getinfo()` does, when no entry is left.
+///
+/// ```no_run
+/// use libfabric::{Caps, Info, SelectionPolicy, select_provider};
+/// # fn run() -> libfabric::Result<()> {
+/// let policy = SelectionPolicy::new()
+///     .require_caps(Caps::RMA)
+///     .exclude("tcp")
+///     .prefer_rdma();
+/// let entries = select_provider(&Info::new().caps(Caps::MSG), &policy)?;
+/// let entry = &entries[0];
+/// # Ok(())
+/// # }
+/// ```
+pub fn select_provider(hints: &Info, policy: &SelectionPolicy) -> Result<Vec<InfoEntry>> {
+    policy.apply(hints.get()?)
+}
+
+impl SelectionPolicy {
+    // Filter and rank `entries`, failing when none is left.
+    pub(crate) fn apply(&self, entries: Vec<InfoEntry>) -> Result<Vec<InfoEntry>> {
+        let mut entries: Vec<_> = entries
+            .into_iter()
+            .filter(|entry| self.keeps(entry))
+            .collect();
+        if entries.is_empty() {
+            return Err(Error::fabric("select_provider", ffi::FI_ENODATA as i64));
+        }
+        // Stable, so ties keep their order.
+        entries.sort_by_cached_key(|entry| self.rank(entry));
+        Ok(entries)
+    }
+}
+
+pub(crate) fn layers(entry: &InfoEntry) -> impl Iterator<Item = &str> {
+    entry.provider_name().split(';')
+}
+
+fn is_rdma(entry: &InfoEntry) -> bool {
+    let core = layers(entry).next().unwrap_or_default();
+    entry.caps().contains(Caps::RMA) && !SOFTWARE_PROVIDERS.contains(&core)
+}
@@ -0,0 +1,342 @@
+use crate::av::{Addr, AvAttr};
+use crate::cq::{Completion, CompletionQueue, CqAttr};
+use crate::domain::Domain;
+use crate::ep::Endpoint;
+use crate::error::{Error, Result};
+use crate::fabric::Fabric;
+use crate::flags::{Access, BindFlags, Caps, MrMode};
+use crate::info::{EndpointType, Info, InfoEntry};
+use crate::mr::MemoryRegion;
+use ofi_libfabric_sys::bindgen as ffi;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Providers tried by [`selftest()`], in order: shared memory, then tcp over loopback.
+const LOCAL_PROVIDERS: &[&str] = &["shm", "tcp"];
+
+// Size of each of the four areas of the test buffer, and how long a check may take.
+const AREA: usize = 4096;
+const TIMEOUT: Duration = Duration::from_secs(5);
+const TX_CONTEXT: usize = 1;
+const RX_CONTEXT: usize = 2;
+
+/// The outcome of one check of a [`SelftestReport`].
+#[derive(Debug, Clone)]
+pub struct SelftestCheck {
+    /// What was exercised, ex: `"rma write"`.
+    pub name: &'static str,
+    /// How long the check took, or why it failed.
+    pub result: Result<Duration>,
+}
+
+/// What [`selftest()`] ran, and how each check went.
+#[derive(Debug, Clone)]
+pub struct SelftestReport {
+    pub provider: String,
+    pub fabric: String,
+    pub domain: String,
+    pub checks: Vec<SelftestCheck>,
+}
+
+impl SelftestReport {
+    /// Whether every check succeeded.
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.result.is_ok())
+    }
+}
+
+impl fmt::Display for SelftestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} (fabric {}, domain {})",
+            self.provider, self.fabric, self.domain
+        )?;
+        for check in &self.checks {
+            match &check.result {
+                Ok(elapsed) => writeln!(f, "  {:<10} ok ({elapsed:?})", check.name)?,
+                Err(err) => writeln!(f, "  {:<10} FAILED: {err}", check.name)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check that this node can move data through libfabric, with no peer or configuration.
+///
+/// This opens two endpoints of a local provider (shm, or else tcp) in this process, then has
+/// them exchange messages, tagged messages, and RMA reads and writes, verifying the data that
+/// arrives. An error is only returned when no local provider could be set up at all; failed
+/// transfers are reported as failed checks of the report.
+///
+/// ```no_run
+/// let report = libfabric::selftest()?;
+/// assert!(report.passed(), "{report}");
+/// # Ok::<(), libfabric::Error>(())
+/// ```
+pub fn selftest() -> Result<SelftestReport> {
+    let mut last = Error::fabric("fi_getinfo", ffi::FI_ENODATA as i64);
+    for provider in LOCAL_PROVIDERS {
+        match selftest_provider(provider) {
+            Ok(report) => return Ok(report),
+            Err(err) => last = err,
+        }
+    }
+    Err(last)
+}
+
+/// Like [`selftest()`], on the given provider.
+pub fn selftest_provider(provider: &str) -> Result<SelftestReport> {
+    let entries = Info::new()
+        .provider(provider)
+        .ep_type(EndpointType::Rdm)
+        .caps(Caps::MSG | Caps::TAGGED | Caps::RMA)
+        .mr_mode(MrMode::LOCAL | MrMode::VIRT_ADDR | MrMode::ALLOCATED | MrMode::PROV_KEY)
+        .get()?;
+    let entry = &entries[0];
+    let mut pair = Pair::open(entry)?;
+
+    let checks = [
+        (
+            "send/recv",
+            Pair::check_msg as fn(&mut Pair, u8) -> Result<()>,
+        ),
+        ("tagged", Pair::check_tagged),
+        ("rma write", Pair::check_write),
+        ("rma read", Pair::check_read),
+    ];
+    Ok(SelftestReport {
+        provider: entry.provider_name().to_owned(),
+        fabric: entry.fabric_name().to_owned(),
+        domain: entry.domain_name().to_owned(),
+        checks: (1..)
+            .zip(checks)
+            .map(|(pattern, (name, check))| {
+                let start = Instant::now();
+                let result = check(&mut pair, pattern).map(|()| start.elapsed());
+                SelftestCheck { name, result }
+            })
+            .collect(),
+    })
+}
+
+struct Side {
+    ep: Endpoint,
+    cq: CompletionQueue,
+    // The address of the other side.
+    peer: Addr,
+    // Successful completions read, by context.
+    tx: usize,
+    rx: usize,
+}
+
+// Two endpoints of one domain, sharing a registered buffer laid out as the send and receive
+// areas of the first endpoint, then of the second.
+struct Pair {
+    sides: [Side; 2],
+    // Closed before the buffer below is freed.
+    mr: MemoryRegion,
+    buf: Vec<u8>,
+    virt_addr: bool,
+}
+
+impl Pair {
+    fn open(entry: &InfoEntry) -> Result<Self> {
+        let fabric = Fabric::open(entry)?;
+        let domain = Domain::open(&fabric, entry)?;
+        let av = domain.av(&AvAttr::new())?;
+        let open = || -> Result<Side> {
+            let cq = domain.cq(&CqAttr::new())?;
+            let ep = domain
+                .endpoint(entry)?
+                .bind_cq(&cq, BindFlags::TRANSMIT | BindFlags::RECV)?
+                .bind_av(&av)?
+                .enable()?;
+            Ok(Side {
+                ep,
+                cq,
+                peer: Addr::UNSPEC,
+                tx: 0,
+                rx: 0,
+            })
+        };
+        let mut sides = [open()?, open()?];
+        sides[0].peer = av.insert(&sides[1].ep.name()?)?;
+        sides[1].peer = av.insert(&sides[0].ep.name()?)?;
+
+        let mut buf = vec![0u8; 4 * AREA];
+        let access = Access::SEND
+            | Access::RECV
+            | Access::READ
+            | Access::WRITE
+            | Access::REMOTE_READ
+            | Access::REMOTE_WRITE;
+        let mr = unsafe { domain.register(buf.as_mut_ptr(), buf.len(), access)? };
+        Ok(Pair {
+            sides,
+            mr,
+            buf,
+            virt_addr: entry.mr_mode().contains(MrMode::VIRT_ADDR),
+        })
+    }
+
+    fn offset(side: usize, rx: bool) -> usize {
+        (2 * side + rx as usize) * AREA
+    }
+
+    // Fill the send area of `side` with `pattern`, and clear the receive area of the other.
+    fn prepare(&mut self, side: usize, pattern: u8) {
+        let tx = Self::offset(side, false);
+        let rx = Self::offset(1 - side, true);
+        self.buf[tx..tx + AREA].fill(pattern);
+        self.buf[rx..rx + AREA].fill(0);
+    }
+
+    fn verify(&self, side: usize, pattern: u8) -> Result<()> {
+        let rx = Self::offset(side, true);
+        match self.buf[rx..rx + AREA].iter().all(|&b| b == pattern) {
+            true => Ok(()),
+            false => Err(Error::invalid("received data does not match what was sent")),
+        }
+    }
+
+    // Where RMA targets the receive area of `side`.
+    fn remote_addr(&self, side: usize) -> u64 {
+        let offset = Self::offset(side, true);
+        match self.virt_addr {
+            true => self.buf.as_ptr() as u64 + offset as u64,
+            false => offset as u64,
+        }
+    }
+
+    // Progress both sides until `side` has `tx` send and `rx` receive completions.
+    fn wait(&mut self, side: usize, tx: usize, rx: usize) -> Result<()> {
+        let deadline = Instant::now() + TIMEOUT;
+        while self.sides[side].tx < tx || self.sides[side].rx < rx {
+            if Instant::now() > deadline {
+                return Err(Error::fabric("fi_cq_read", ffi::FI_ETIMEDOUT as i64));
+            }
+            for side in &mut self.sides {
+                side.progress()?;
+            }
+        }
+        self.sides[side].tx -= tx;
+        self.sides[side].rx -= rx;
+        Ok(())
+    }
+
+    // SAFETY (of the transfers below): the buffer outlives the endpoints, and is only read back
+    // once the transfers completed.
+    fn check_msg(&mut self, pattern: u8) -> Result<()> {
+        self.prepare(0, pattern);
+        let (tx, rx) = (Self::offset(0, false), Self::offset(1, true));
+        let Pair { sides, mr, buf, .. } = self;
+        let (head, tail) = buf.split_at_mut(rx);
+        unsafe {
+            sides[1]
+                .ep
+                .recv(&mut tail[..AREA], Some(mr), sides[1].peer, RX_CONTEXT)?;
+            sides[0]
+                .ep
+                .send(&head[tx..tx + AREA], Some(mr), sides[0].peer, TX_CONTEXT)?;
+        }
+        self.wait(0, 1, 0)?;
+        self.wait(1, 0, 1)?;
+        self.verify(1, pattern)
+    }
+
+    fn check_tagged(&mut self, pattern: u8) -> Result<()> {
+        const TAG: u64 = 0x5e1f;
+        self.prepare(1, pattern);
+        let (tx, rx) = (Self::offset(1, false), Self::offset(0, true));
+        let Pair { sides, mr, buf, .. } = self;
+        let (head, tail) = buf.split_at_mut(tx);
+        unsafe {
+            sides[0].ep.trecv(
+                &mut head[rx..rx + AREA],
+                Some(mr),
+                sides[0].peer,
+                TAG,
+                0,
+                RX_CONTEXT,
+            )?;
+            sides[1]
+                .ep
+                .tsend(&tail[..AREA], Some(mr), sides[1].peer, TAG, TX_CONTEXT)?;
+        }
+        self.wait(1, 1, 0)?;
+        self.wait(0, 0, 1)?;
+        self.verify(0, pattern)
+    }
+
+    // Write into the receive area of the second side. The write completing only means the data
+    // left the send buffer, so the target area is polled until it shows up.
+    fn check_write(&mut self, pattern: u8) -> Result<()> {
+        self.prepare(0, pattern);
+        let tx = Self::offset(0, false);
+        let (addr, key) = (self.remote_addr(1), self.mr.key());
+        let side = &self.sides[0];
+        unsafe {
+            side.ep.write(
+                &self.buf[tx..tx + AREA],
+                Some(&self.mr),
+                side.peer,
+                addr,
+                key,
+                TX_CONTEXT,
+            )?
+        };
+        self.wait(0, 1, 0)?;
+        let deadline = Instant::now() + TIMEOUT;
+        while self.verify(1, pattern).is_err() && Instant::now() < deadline {
+            for side in &mut self.sides {
+                side.progress()?;
+            }
+        }
+        self.verify(1, pattern)
+    }
+
+    // Read the send area of the second side into the receive area of the first.
+    fn check_read(&mut self, pattern: u8) -> Result<()> {
+        self.prepare(1, pattern);
+        let rx = Self::offset(0, true);
+        let offset = Self::offset(1, false);
+        let addr = match self.virt_addr {
+            true => self.buf.as_ptr() as u64 + offset as u64,
+            false => offset as u64,
+        };
+        let key = self.mr.key();
+        let Pair { sides, mr, buf, .. } = self;
+        unsafe {
+            sides[0].ep.read(
+                &mut buf[rx..rx + AREA],
+                Some(mr),
+                sides[0].peer,
+                addr,
+                key,
+                TX_CONTEXT,
+            )?
+        };
+        self.wait(0, 1, 0)?;
+        self.verify(0, pattern)
+    }
+}
+
+impl Side {
+    fn progress(&mut self) -> Result<()> {
+        let mut completions = [Completion::default(); 8];
+        let n = match self.cq.read(&mut completions) {
+            Err(err) if err.is_avail() => {
+                return Err(self.cq.read_err()?.map_or(err, |entry| entry.error));
+            }
+            other => other?,
+        };
+        for completion in &completions[..n] {
+            match completion.context() {
+                TX_CONTEXT => self.tx += 1,
+                _ => self.rx += 1,
+            }
+        }
+        Ok(())
+    }
+}
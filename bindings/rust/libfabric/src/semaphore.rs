@@ -0,0 +1,146 @@
+use crate::atomic::AtomicOp;
+use crate::av::Addr;
+use crate::bootstrap::RemoteRegion;
+use crate::ep::{Endpoint, EndpointState};
+use crate::error::Result;
+use crate::flags::MrMode;
+use crate::notify::NotifiedRegion;
+use crate::threading::{ThreadSafe, ThreadingModel};
+use std::sync::atomic::AtomicU64;
+use std::time::Duration;
+
+/// A counting semaphore posted to by peers and waited on by its owner, over remote atomics
+/// and a counter, for producer/consumer handoffs between nodes without message traffic.
+///
+/// The semaphore is a word of its owner, registered as a [`NotifiedRegion`]: each post of a
+/// [`SemaphorePoster`] adds 1 to the word with an atomic `FI_SUM`, which the counter of the
+/// region counts, and each wait takes one of the posts counted, blocking on the counter while
+/// none is left. Posts of several peers add up, so that the word holds the posts in all.
+///
+/// The domain of the owner needs [`Caps::RMA_EVENT`](crate::Caps::RMA_EVENT) and
+/// [`Caps::ATOMIC`](crate::Caps::ATOMIC), those of the posters
+/// [`Caps::ATOMIC`](crate::Caps::ATOMIC) with 64 bit sums.
+///
+/// ```no_run
+/// use libfabric::{Addr, Endpoint, RemoteSemaphore, SemaphorePoster};
+///
+/// # fn run(consumer: &Endpoint, producer: Endpoint, to_consumer: Addr) -> libfabric::Result<()> {
+/// let mut ready = RemoteSemaphore::new(consumer)?;
+/// // Hand `ready.remote()` to the producer, which posts once a buffer is filled.
+/// let mut poster = SemaphorePoster::new(producer, to_consumer, ready.remote())?;
+/// poster.post()?;
+/// ready.wait(None)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct RemoteSemaphore<M: ThreadingModel = ThreadSafe> {
+    // Declared first, so that the word is deregistered before it is freed.
+    region: NotifiedRegion<M>,
+    word: Box<AtomicU64>,
+    virt_addr: bool,
+}
+
+impl<M: ThreadingModel> RemoteSemaphore<M> {
+    /// Register a semaphore for the posts of the peers of `ep`, with none posted. Fails with
+    /// `FI_EOPNOTSUPP` if the domain of `ep` was not opened with
+    /// [`Caps::RMA_EVENT`](crate::Caps::RMA_EVENT).
+    pub fn new<S: EndpointState>(ep: &Endpoint<M, S>) -> Result<Self> {
+        let word = Box::new(AtomicU64::new(0));
+        // SAFETY: the word is freed after the region, see the field order.
+        let region = unsafe { NotifiedRegion::new(ep, word.as_ptr().cast(), size_of::<u64>()) }?;
+        let virt_addr = ep.domain().info().mr_mode().contains(MrMode::VIRT_ADDR);
+        Ok(RemoteSemaphore {
+            region,
+            word,
+            virt_addr,
+        })
+    }
+
+    /// The word the posters target, to hand to them.
+    pub fn remote(&self) -> RemoteRegion {
+        RemoteRegion {
+            addr: match self.virt_addr {
+                true => self.word.as_ptr() as u64,
+                false => 0,
+            },
+            len: size_of::<u64>() as u64,
+            key: self.region.region().key(),
+        }
+    }
+
+    /// The posts counted, not waited for yet.
+    pub fn available(&self) -> u64 {
+        self.region.unseen()
+    }
+
+    /// The posts counted, in all.
+    pub fn posts(&self) -> u64 {
+        self.region.writes()
+    }
+
+    /// Take a post, if one was counted, without blocking.
+    pub fn try_wait(&mut self) -> Result<bool> {
+        if self.available() == 0 {
+            return Ok(false);
+        }
+        self.region.wait_for_writes(1, None)?;
+        Ok(true)
+    }
+
+    /// Block until a post is counted, and take it. Fails with `FI_EIO` once an error is
+    /// counted, and with `FI_ETIMEDOUT` when the timeout expires.
+    pub fn wait(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.region.wait_for_writes(1, timeout)
+    }
+
+    /// Like [`wait()`](Self::wait), yielding to the executor while no post is counted,
+    /// enabled by the `async` feature. As
+    /// [`NotifiedRegion::wait_for_writes_async()`] does, the future polls the counter each
+    /// time the executor gets to it.
+    #[cfg(feature = "async")]
+    pub async fn wait_async(&mut self) -> Result<()> {
+        self.region.wait_for_writes_async(1).await
+    }
+}
+
+/// The posting side of a [`RemoteSemaphore`] of a peer, adding to its word with injected
+/// atomics, which complete without entries in the queues of the poster.
+pub struct SemaphorePoster<M: ThreadingModel = ThreadSafe> {
+    ep: Endpoint<M>,
+    dest: Addr,
+    remote: RemoteRegion,
+    posts: u64,
+}
+
+impl<M: ThreadingModel> SemaphorePoster<M> {
+    /// Post to the semaphore at `remote` of `dest` through `ep`. Fails with `FI_EOPNOTSUPP`
+    /// if `ep` has no 64 bit atomic sums.
+    pub fn new(ep: Endpoint<M>, dest: Addr, remote: RemoteRegion) -> Result<Self> {
+        ep.atomic_valid::<u64>(AtomicOp::Sum)?;
+        Ok(SemaphorePoster {
+            ep,
+            dest,
+            remote,
+            posts: 0,
+        })
+    }
+
+    pub fn endpoint(&self) -> &Endpoint<M> {
+        &self.ep
+    }
+
+    /// Post once, waking a wait of the owner. Fails with `FI_EAGAIN` while the provider has
+    /// no room for the atomic, to be posted again once it progressed.
+    pub fn post(&mut self) -> Result<()> {
+        let (addr, key) = (self.remote.addr, self.remote.key);
+        self.ep
+            .inject_atomic(&[1u64], self.dest, addr, key, AtomicOp::Sum)?;
+        self.posts += 1;
+        Ok(())
+    }
+
+    /// The posts so far.
+    pub fn posts(&self) -> u64 {
+        self.posts
+    }
+}
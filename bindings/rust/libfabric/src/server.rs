@@ -0,0 +1,96 @@
+//! The server side of sessions: a [`Server`] listens at an address, and hands the peers which
+//! connect, their messages and their RMA events to a [`Handler`].
+//!
+//! Sessions wrap provider selection, endpoint setup, the joining of peers, their state and the
+//! dispatch of completions, for applications which need no more; the lower layers stay
+//! available through [`Session::domain()`] and [`Session::endpoint()`]. Over MSG endpoints,
+//! peers connect through the passive endpoint of the server. Over RDM endpoints, which stay
+//! the default, a client joins by sending its name in a message of the session, inserted
+//! into the address vector of the server, which answers it; the first byte of each message
+//! tells these apart, so both sides must be sessions.
+//!
+//! ```no_run
+//! use libfabric::server::{Handler, PeerId, Server, Session, SessionAttr};
+//!
+//! struct Echo;
+//!
+//! impl Handler for Echo {
+//!     // The messages of each peer.
+//!     type Peer = u64;
+//!
+//!     fn on_connect(&mut self, _: &mut Session, _: PeerId) -> libfabric::Result<u64> {
+//!         Ok(0)
+//!     }
+//!
+//!     fn on_message(
+//!         &mut self,
+//!         session: &mut Session,
+//!         peer: PeerId,
+//!         count: &mut u64,
+//!         msg: &[u8],
+//!     ) -> libfabric::Result<()> {
+//!         *count += 1;
+//!         session.send(peer, msg)
+//!     }
+//! }
+//!
+//! # fn run() -> libfabric::Result<()> {
+//! let mut server = Server::bind("0.0.0.0", "9228", &SessionAttr::new().provider("tcp"), Echo)?;
+//! loop {
+//!     server.poll()?;
+//! }
+//! # }
+//! ```
+
+use crate::av::EndpointAddress;
+use crate::error::Result;
+use crate::session::dispatch;
+pub use crate::session::{Handler, PeerId, RmaEvent, Session, SessionAttr};
+use std::collections::HashMap;
+
+/// A server of sessions, see the [module](self) documentation.
+pub struct Server<H: Handler> {
+    session: Session,
+    handler: H,
+    states: HashMap<PeerId, H::Peer>,
+}
+
+impl<H: Handler> Server<H> {
+    /// Listen at `node` and `service`, ex: an address of the host and a port, serving the
+    /// peers with `handler`.
+    pub fn bind(node: &str, service: &str, attr: &SessionAttr, handler: H) -> Result<Self> {
+        Ok(Server {
+            session: Session::listen(attr.hints(node, service), attr)?,
+            handler,
+            states: HashMap::new(),
+        })
+    }
+
+    /// The address clients connect to.
+    pub fn name(&self) -> Result<EndpointAddress> {
+        self.session.name()
+    }
+
+    pub fn session(&mut self) -> &mut Session {
+        &mut self.session
+    }
+
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    pub fn handler_mut(&mut self) -> &mut H {
+        &mut self.handler
+    }
+
+    /// The state of `peer`, while it is connected.
+    pub fn peer(&self, peer: PeerId) -> Option<&H::Peer> {
+        self.states.get(&peer)
+    }
+
+    /// Make progress, calling the handler for what happened, and returning how many events
+    /// it was called for.
+    pub fn poll(&mut self) -> Result<usize> {
+        dispatch(&mut self.session, &mut self.handler, &mut self.states)
+    }
+}
@@ -0,0 +1,799 @@
+use crate::av::{Addr, AddressVector, AvAttr, EndpointAddress};
+use crate::cq::{Completion, CompletionQueue, CqAttr};
+use crate::domain::Domain;
+use crate::ep::{Endpoint, PassiveEndpoint};
+use crate::eq::{EqAttr, EqEvent, EventQueue};
+use crate::error::{Error, Result};
+use crate::fabric::Fabric;
+use crate::fid::{AsRawFid, FidId};
+use crate::flags::{BindFlags, Caps};
+use crate::info::{EndpointType, Info, InfoEntry};
+use ofi_libfabric_sys::bindgen as ffi;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+// The first byte of the messages of a session: a peer joining an RDM session with its name,
+// and answered with an empty one, a message of the application, and a peer leaving.
+const HELLO: u8 = 0;
+const DATA: u8 = 1;
+const BYE: u8 = 2;
+
+// Set in the contexts of sends, those of receives being the index of their slot.
+const SEND: usize = 1 << (usize::BITS - 1);
+
+/// Attributes of the [`Session`] of a [`Server`](crate::server::Server) or a
+/// [`Client`](crate::client::Client), which must agree between them.
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct SessionAttr {
+    provider: Option<String>,
+    ep_type: EndpointType,
+    caps: Caps,
+    max_size: usize,
+    depth: usize,
+}
+
+impl Default for SessionAttr {
+    fn default() -> Self {
+        SessionAttr {
+            provider: None,
+            ep_type: EndpointType::Rdm,
+            caps: Caps::empty(),
+            max_size: 4096,
+            depth: 16,
+        }
+    }
+}
+
+impl SessionAttr {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The provider to open, the first one `fi_getinfo()` returns by default.
+    pub fn provider(mut self, name: &str) -> Self {
+        self.provider = Some(name.to_owned());
+        self
+    }
+
+    /// RDM endpoints by default, on which clients join by a message of the session, or MSG
+    /// endpoints, accepted from the connection requests of a passive endpoint.
+    pub fn ep_type(mut self, ep_type: EndpointType) -> Self {
+        self.ep_type = ep_type;
+        self
+    }
+
+    /// Capabilities requested on top of [`Caps::MSG`], ex: [`Caps::RMA`] and
+    /// [`Caps::RMA_EVENT`] for the RMA events of [`Handler::on_rma_event()`].
+    pub fn caps(mut self, caps: Caps) -> Self {
+        self.caps = caps;
+        self
+    }
+
+    /// Largest message, 4 KiB by default.
+    pub fn max_size(mut self, bytes: usize) -> Self {
+        self.max_size = bytes;
+        self
+    }
+
+    /// Receives kept posted per endpoint, 16 by default.
+    pub fn depth(mut self, receives: usize) -> Self {
+        self.depth = receives.max(1);
+        self
+    }
+
+    pub(crate) fn hints(&self, node: &str, service: &str) -> Info {
+        let caps = match self.ep_type {
+            EndpointType::Rdm => Caps::MSG | Caps::SOURCE,
+            _ => Caps::MSG,
+        };
+        let hints = Info::new()
+            .ep_type(self.ep_type)
+            .caps(caps | self.caps)
+            .node(node)
+            .service(service);
+        match &self.provider {
+            Some(name) => hints.provider(name),
+            None => hints,
+        }
+    }
+}
+
+/// A peer of a [`Session`], numbered in the order it joined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PeerId(u64);
+
+impl PeerId {
+    pub fn index(&self) -> u64 {
+        self.0
+    }
+}
+
+/// A remote memory access of a peer, reported to [`Handler::on_rma_event()`]: a write with
+/// remote CQ data, or any access to memory the domain reports events of, with
+/// [`Caps::RMA_EVENT`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RmaEvent {
+    /// The peer, when known: always over MSG endpoints, over RDM ones when the provider
+    /// reports the source of the access.
+    pub peer: Option<PeerId>,
+    /// Whether the peer wrote, rather than read.
+    pub write: bool,
+    pub len: usize,
+    /// The remote CQ data of the write, if any.
+    pub data: Option<u64>,
+}
+
+/// The callbacks of a [`Server`](crate::server::Server) or a
+/// [`Client`](crate::client::Client), called from their `poll()` with the [`Session`] to
+/// answer through and the state of the peer.
+///
+/// Errors returned by callbacks are returned by `poll()`, once the event is handled. A peer
+/// whose [`on_connect()`](Self::on_connect) fails is disconnected.
+pub trait Handler {
+    /// The state kept per peer, created on connection and handed back on disconnection.
+    type Peer;
+
+    /// A peer connected.
+    fn on_connect(&mut self, session: &mut Session, peer: PeerId) -> Result<Self::Peer>;
+
+    /// A peer sent `msg`.
+    fn on_message(
+        &mut self,
+        session: &mut Session,
+        peer: PeerId,
+        state: &mut Self::Peer,
+        msg: &[u8],
+    ) -> Result<()>;
+
+    /// A peer accessed the memory of the session, with the state of the peer when it is
+    /// known. Nothing is done by default.
+    fn on_rma_event(
+        &mut self,
+        session: &mut Session,
+        event: RmaEvent,
+        state: Option<&mut Self::Peer>,
+    ) -> Result<()> {
+        let _ = (session, event, state);
+        Ok(())
+    }
+
+    /// A peer disconnected, or was disconnected. Nothing is done by default.
+    fn on_disconnect(&mut self, session: &mut Session, peer: PeerId, state: Self::Peer) {
+        let _ = (session, peer, state);
+    }
+}
+
+/// What happened to a session, handed to the handler by [`dispatch()`].
+enum Event {
+    Connected(PeerId),
+    Message(PeerId, Vec<u8>),
+    Rma(RmaEvent),
+    Disconnected(PeerId),
+}
+
+/// The fabric objects, peers and buffers behind a [`Server`](crate::server::Server) or a
+/// [`Client`](crate::client::Client), through which handlers send and reach the lower layers.
+///
+/// Messages are copied, into buffers of the session kept until their send completes, and
+/// queued while the provider has no room for them. Over RDM endpoints, every peer is reached
+/// through the one endpoint of the session; over MSG ones, each through its connection, read
+/// in turn.
+pub struct Session {
+    // Declared first, so that the endpoints are closed before the buffers of their operations
+    // are freed, and before the objects they were opened from.
+    link: Link,
+    max_size: usize,
+    depth: usize,
+    next_peer: u64,
+    events: VecDeque<Event>,
+    entry: InfoEntry,
+    eq: EventQueue,
+    domain: Domain,
+    fabric: Fabric,
+}
+
+enum Link {
+    Msg {
+        conns: HashMap<PeerId, Channel>,
+        // Endpoints waiting for FI_CONNECTED, by fid.
+        connecting: HashMap<FidId, (PeerId, Channel)>,
+        pep: Option<PassiveEndpoint>,
+    },
+    Rdm {
+        chan: Channel,
+        av: AddressVector,
+        addrs: HashMap<Addr, PeerId>,
+        peers: HashMap<PeerId, Addr>,
+        // The server a client sent its name to, until it answers.
+        joining: HashSet<PeerId>,
+    },
+}
+
+// An endpoint, its queue, and the buffers of its operations.
+struct Channel {
+    ep: Endpoint,
+    cq: CompletionQueue,
+    slots: Vec<Box<[u8]>>,
+    unposted: Vec<usize>,
+    sends: HashMap<usize, Vec<u8>>,
+    outbox: VecDeque<(Addr, Vec<u8>)>,
+    next_send: usize,
+}
+
+// A completion of a channel.
+enum Incoming {
+    Recv(Addr, Vec<u8>),
+    Rma(Addr, RmaEvent),
+}
+
+impl Channel {
+    fn open(
+        domain: &Domain,
+        entry: &InfoEntry,
+        eq: Option<&EventQueue>,
+        av: Option<&AddressVector>,
+        slot_len: usize,
+        depth: usize,
+    ) -> Result<Self> {
+        let cq = domain.cq(&CqAttr::new())?;
+        let mut ep = domain
+            .endpoint(entry)?
+            .bind_cq(&cq, BindFlags::TRANSMIT | BindFlags::RECV)?;
+        if let Some(eq) = eq {
+            ep = ep.bind_eq(eq)?;
+        }
+        if let Some(av) = av {
+            ep = ep.bind_av(av)?;
+        }
+        let mut chan = Channel {
+            ep: ep.enable()?,
+            cq,
+            slots: (0..depth)
+                .map(|_| vec![0; slot_len].into_boxed_slice())
+                .collect(),
+            unposted: (0..depth).rev().collect(),
+            sends: HashMap::new(),
+            outbox: VecDeque::new(),
+            next_send: 0,
+        };
+        chan.post()?;
+        Ok(chan)
+    }
+
+    fn send(&mut self, dest: Addr, kind: u8, msg: &[u8]) -> Result<()> {
+        let mut buf = Vec::with_capacity(1 + msg.len());
+        buf.push(kind);
+        buf.extend_from_slice(msg);
+        self.outbox.push_back((dest, buf));
+        self.post()
+    }
+
+    // Post the receives taken back and the queued sends, until the provider has no room.
+    fn post(&mut self) -> Result<()> {
+        while let Some(&slot) = self.unposted.last() {
+            // SAFETY: the slot is only read once its receive completed, and outlives the
+            // endpoint, see the field order.
+            match unsafe {
+                self.ep
+                    .recv(&mut self.slots[slot], None, Addr::UNSPEC, slot)
+            } {
+                Err(err) if err.is_again() => break,
+                other => other?,
+            }
+            self.unposted.pop();
+        }
+        while let Some((dest, buf)) = self.outbox.front() {
+            let context = SEND | self.next_send;
+            // SAFETY: the buffer is kept in `sends` until the send completes.
+            match unsafe { self.ep.send(buf, None, *dest, context) } {
+                Err(err) if err.is_again() => break,
+                other => other?,
+            }
+            let (_, buf) = self.outbox.pop_front().unwrap();
+            self.sends.insert(context, buf);
+            self.next_send = (self.next_send + 1) & !SEND;
+        }
+        Ok(())
+    }
+
+    // Read the available completions, with their source when `source` is set.
+    fn poll(&mut self, source: bool, out: &mut Vec<Incoming>) -> Result<()> {
+        let mut completions = [Completion::default(); 16];
+        let mut srcs = [Addr::UNSPEC; 16];
+        loop {
+            let read = match source {
+                true => self.cq.read_from(&mut completions, &mut srcs),
+                false => self.cq.read(&mut completions),
+            };
+            let n = match read {
+                Ok(0) => break,
+                Err(err) if err.is_again() => break,
+                Err(err) if err.is_avail() => {
+                    let Some(entry) = self.cq.read_err()? else {
+                        break;
+                    };
+                    if self.sends.remove(&entry.context).is_some() {
+                        return Err(entry.error);
+                    }
+                    // Receives are canceled as the endpoint shuts down.
+                    if entry.error.code() != ffi::FI_ECANCELED as i32 {
+                        return Err(entry.error);
+                    }
+                    continue;
+                }
+                other => other?,
+            };
+            for (completion, &src) in completions[..n].iter().zip(&srcs) {
+                let context = completion.context();
+                if completion.is_remote_write() || completion.is_remote_read() {
+                    let event = RmaEvent {
+                        peer: None,
+                        write: completion.is_remote_write(),
+                        len: completion.len(),
+                        data: completion.has_data().then(|| completion.data()),
+                    };
+                    out.push(Incoming::Rma(src, event));
+                } else if context & SEND != 0 {
+                    self.sends.remove(&context);
+                } else if let Some(slot) = self.slots.get(context) {
+                    out.push(Incoming::Recv(src, slot[..completion.len()].to_vec()));
+                    self.unposted.push(context);
+                }
+            }
+        }
+        self.post()
+    }
+}
+
+impl Session {
+    /// Open the session of a server, listening at the source address `hints` resolve to.
+    pub(crate) fn listen(hints: Info, attr: &SessionAttr) -> Result<Self> {
+        let (entry, fabric, eq, domain) = Self::open(hints.source())?;
+        let link = match entry.ep_type() {
+            EndpointType::Rdm => {
+                let av = domain.av(&AvAttr::new())?;
+                let chan = Channel::open(
+                    &domain,
+                    &entry,
+                    None,
+                    Some(&av),
+                    attr.max_size + 1,
+                    attr.depth,
+                )?;
+                Link::Rdm {
+                    chan,
+                    av,
+                    addrs: HashMap::new(),
+                    peers: HashMap::new(),
+                    joining: HashSet::new(),
+                }
+            }
+            _ => {
+                let pep = fabric.passive_endpoint(&entry)?;
+                pep.bind_eq(&eq)?;
+                pep.listen()?;
+                Link::Msg {
+                    conns: HashMap::new(),
+                    connecting: HashMap::new(),
+                    pep: Some(pep),
+                }
+            }
+        };
+        Ok(Self::new(link, attr, entry, eq, domain, fabric))
+    }
+
+    /// Open the session of a client, connecting to the server `hints` resolve to, returning
+    /// the session and the id of the server.
+    pub(crate) fn connect(hints: Info, attr: &SessionAttr) -> Result<(Self, PeerId)> {
+        let (entry, fabric, eq, domain) = Self::open(hints)?;
+        let dest = entry
+            .dest_addr()
+            .ok_or_else(|| Error::invalid("the hints do not resolve to a server address"))?;
+        let server = PeerId(0);
+        let link = match entry.ep_type() {
+            EndpointType::Rdm => {
+                let av = domain.av(&AvAttr::new())?;
+                let mut chan = Channel::open(
+                    &domain,
+                    &entry,
+                    None,
+                    Some(&av),
+                    attr.max_size + 1,
+                    attr.depth,
+                )?;
+                let addr = av.insert(&dest)?;
+                let name = chan.ep.name()?;
+                chan.send(addr, HELLO, name.as_bytes())?;
+                Link::Rdm {
+                    chan,
+                    av,
+                    addrs: HashMap::from([(addr, server)]),
+                    peers: HashMap::from([(server, addr)]),
+                    joining: HashSet::from([server]),
+                }
+            }
+            _ => {
+                let chan = Channel::open(
+                    &domain,
+                    &entry,
+                    Some(&eq),
+                    None,
+                    attr.max_size + 1,
+                    attr.depth,
+                )?;
+                chan.ep.connect(&dest, &[])?;
+                Link::Msg {
+                    conns: HashMap::new(),
+                    connecting: HashMap::from([(chan.ep.id(), (server, chan))]),
+                    pep: None,
+                }
+            }
+        };
+        let mut session = Self::new(link, attr, entry, eq, domain, fabric);
+        session.next_peer = 1;
+        Ok((session, server))
+    }
+
+    fn open(hints: Info) -> Result<(InfoEntry, Fabric, EventQueue, Domain)> {
+        let entries = hints.get()?;
+        let entry = entries
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::fabric("fi_getinfo", ffi::FI_ENODATA as i64))?;
+        let fabric = Fabric::open(&entry)?;
+        let eq = fabric.eq(&EqAttr::new())?;
+        let domain = Domain::open(&fabric, &entry)?;
+        Ok((entry, fabric, eq, domain))
+    }
+
+    fn new(
+        link: Link,
+        attr: &SessionAttr,
+        entry: InfoEntry,
+        eq: EventQueue,
+        domain: Domain,
+        fabric: Fabric,
+    ) -> Self {
+        Session {
+            link,
+            max_size: attr.max_size,
+            depth: attr.depth,
+            next_peer: 0,
+            events: VecDeque::new(),
+            entry,
+            eq,
+            domain,
+            fabric,
+        }
+    }
+
+    /// The entry the session was opened from.
+    pub fn info(&self) -> &InfoEntry {
+        &self.entry
+    }
+
+    pub fn fabric(&self) -> &Fabric {
+        &self.fabric
+    }
+
+    /// The domain of the session, to register the memory peers access with.
+    pub fn domain(&self) -> &Domain {
+        &self.domain
+    }
+
+    /// The address clients connect to: that of the passive endpoint of a server over MSG
+    /// endpoints, of the endpoint of the session over RDM ones. Clients of MSG endpoints have
+    /// none.
+    pub fn name(&self) -> Result<EndpointAddress> {
+        match &self.link {
+            Link::Rdm { chan, .. } => chan.ep.name(),
+            Link::Msg { pep: Some(pep), .. } => pep.name(),
+            Link::Msg { pep: None, .. } => Err(Error::fabric("fi_getname", ffi::FI_ENODATA as i64)),
+        }
+    }
+
+    /// The peers connected.
+    pub fn peers(&self) -> Vec<PeerId> {
+        let mut peers: Vec<PeerId> = match &self.link {
+            Link::Msg { conns, .. } => conns.keys().copied().collect(),
+            Link::Rdm { peers, joining, .. } => peers
+                .keys()
+                .filter(|peer| !joining.contains(peer))
+                .copied()
+                .collect(),
+        };
+        peers.sort();
+        peers
+    }
+
+    pub fn is_connected(&self, peer: PeerId) -> bool {
+        match &self.link {
+            Link::Msg { conns, .. } => conns.contains_key(&peer),
+            Link::Rdm { peers, joining, .. } => {
+                peers.contains_key(&peer) && !joining.contains(&peer)
+            }
+        }
+    }
+
+    /// The endpoint `peer` is reached through, and its address there, for the operations of
+    /// the lower layers, such as RMA. Receives of the endpoint, and completions of its queue,
+    /// are the session's own.
+    pub fn endpoint(&self, peer: PeerId) -> Option<(&Endpoint, Addr)> {
+        match &self.link {
+            Link::Msg { conns, .. } => conns.get(&peer).map(|chan| (&chan.ep, Addr::UNSPEC)),
+            Link::Rdm {
+                chan,
+                peers,
+                joining,
+                ..
+            } if !joining.contains(&peer) => peers.get(&peer).map(|&addr| (&chan.ep, addr)),
+            Link::Rdm { .. } => None,
+        }
+    }
+
+    /// Send `msg` to `peer`, copied and queued while the provider has no room. Fails with
+    /// `FI_ENOTCONN` if `peer` is not connected, with an argument error past
+    /// [`SessionAttr::max_size()`].
+    pub fn send(&mut self, peer: PeerId, msg: &[u8]) -> Result<()> {
+        if msg.len() > self.max_size {
+            return Err(Error::invalid(format!(
+                "message of {} bytes exceeds the {} of the session",
+                msg.len(),
+                self.max_size
+            )));
+        }
+        if !self.is_connected(peer) {
+            return Err(Error::fabric("fi_send", ffi::FI_ENOTCONN as i64));
+        }
+        match &mut self.link {
+            Link::Msg { conns, .. } => conns.get_mut(&peer).unwrap().send(Addr::UNSPEC, DATA, msg),
+            Link::Rdm { chan, peers, .. } => chan.send(peers[&peer], DATA, msg),
+        }
+    }
+
+    /// Disconnect `peer`, whose state is handed to [`Handler::on_disconnect()`]. Does nothing
+    /// if `peer` is not connected.
+    pub fn disconnect(&mut self, peer: PeerId) -> Result<()> {
+        match &mut self.link {
+            Link::Msg { conns, .. } => {
+                let Some(chan) = conns.remove(&peer) else {
+                    return Ok(());
+                };
+                self.events.push_back(Event::Disconnected(peer));
+                chan.ep.shutdown()
+            }
+            Link::Rdm {
+                chan,
+                addrs,
+                peers,
+                joining,
+                ..
+            } => {
+                let Some(addr) = peers.remove(&peer) else {
+                    return Ok(());
+                };
+                addrs.remove(&addr);
+                if !joining.remove(&peer) {
+                    self.events.push_back(Event::Disconnected(peer));
+                }
+                // The address stays in the address vector for the message to be sent.
+                chan.send(addr, BYE, &[])
+            }
+        }
+    }
+
+    fn next_id(&mut self) -> PeerId {
+        let peer = PeerId(self.next_peer);
+        self.next_peer += 1;
+        peer
+    }
+
+    /// Read the events and completions of the session, queueing what the handler is told.
+    fn progress(&mut self) -> Result<()> {
+        if let Link::Msg { .. } = self.link {
+            self.progress_cm()?;
+        }
+        let mut read = Vec::new();
+        let mut incoming = Vec::new();
+        match &mut self.link {
+            Link::Rdm { chan, addrs, .. } => {
+                chan.poll(true, &mut read)?;
+                for read in read {
+                    let src = match &read {
+                        Incoming::Recv(src, _) | Incoming::Rma(src, _) => *src,
+                    };
+                    incoming.push((addrs.get(&src).copied(), read));
+                }
+            }
+            Link::Msg { conns, .. } => {
+                for (&peer, chan) in conns.iter_mut() {
+                    chan.poll(false, &mut read)?;
+                    // Connected endpoints hear from their peer only.
+                    incoming.extend(read.drain(..).map(|read| (Some(peer), read)));
+                }
+            }
+        }
+        incoming
+            .into_iter()
+            .try_for_each(|(peer, read)| self.received(peer, read))
+    }
+
+    // Read the connection events of MSG endpoints.
+    fn progress_cm(&mut self) -> Result<()> {
+        let slot_len = self.max_size + 1;
+        loop {
+            let event = match self.eq.read() {
+                Ok(Some(event)) => event,
+                Ok(None) => return Ok(()),
+                Err(err) if err.is_avail() => {
+                    let Some(entry) = self.eq.read_err()? else {
+                        return Ok(());
+                    };
+                    let Link::Msg { connecting, .. } = &mut self.link else {
+                        unreachable!();
+                    };
+                    // A connection which failed to establish is dropped, other errors are
+                    // the caller's.
+                    if connecting.remove(&entry.fid).is_none() {
+                        return Err(entry.error);
+                    }
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            match event {
+                EqEvent::ConnReq { info, .. } => {
+                    let peer = self.next_id();
+                    let chan = Channel::open(
+                        &self.domain,
+                        &info,
+                        Some(&self.eq),
+                        None,
+                        slot_len,
+                        self.depth,
+                    )?;
+                    chan.ep.accept(&[])?;
+                    let Link::Msg { connecting, .. } = &mut self.link else {
+                        unreachable!();
+                    };
+                    connecting.insert(chan.ep.id(), (peer, chan));
+                }
+                EqEvent::Connected { fid, .. } => {
+                    let Link::Msg {
+                        conns, connecting, ..
+                    } = &mut self.link
+                    else {
+                        unreachable!();
+                    };
+                    if let Some((peer, chan)) = connecting.remove(&fid) {
+                        conns.insert(peer, chan);
+                        self.events.push_back(Event::Connected(peer));
+                    }
+                }
+                EqEvent::Shutdown { fid } => {
+                    let Link::Msg { conns, .. } = &mut self.link else {
+                        unreachable!();
+                    };
+                    let peer = conns
+                        .iter()
+                        .find(|(_, chan)| chan.ep.id() == fid)
+                        .map(|(&peer, _)| peer);
+                    if let Some(peer) = peer {
+                        conns.remove(&peer);
+                        self.events.push_back(Event::Disconnected(peer));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Handle what a channel read, from `peer` if known.
+    fn received(&mut self, peer: Option<PeerId>, incoming: Incoming) -> Result<()> {
+        let (src, msg) = match incoming {
+            Incoming::Rma(_, event) => {
+                self.events
+                    .push_back(Event::Rma(RmaEvent { peer, ..event }));
+                return Ok(());
+            }
+            Incoming::Recv(src, msg) => (src, msg),
+        };
+        let Some((&kind, payload)) = msg.split_first() else {
+            return Ok(());
+        };
+        match (kind, peer) {
+            (DATA, Some(peer)) if self.is_connected(peer) => {
+                self.events
+                    .push_back(Event::Message(peer, payload.to_vec()));
+            }
+            (HELLO, peer) => {
+                let next = PeerId(self.next_peer);
+                let Link::Rdm {
+                    chan,
+                    av,
+                    addrs,
+                    peers,
+                    joining,
+                } = &mut self.link
+                else {
+                    return Ok(());
+                };
+                match peer {
+                    // A client is answered by its server.
+                    Some(peer) if joining.remove(&peer) => {
+                        self.events.push_back(Event::Connected(peer));
+                    }
+                    // A peer joining a server, with its name.
+                    None if !payload.is_empty() => {
+                        let addr = av.insert(&EndpointAddress::from_bytes(payload))?;
+                        addrs.insert(addr, next);
+                        peers.insert(next, addr);
+                        chan.send(addr, HELLO, &[])?;
+                        self.next_peer += 1;
+                        self.events.push_back(Event::Connected(next));
+                    }
+                    _ => {}
+                }
+            }
+            (BYE, Some(peer)) => {
+                let Link::Rdm {
+                    addrs,
+                    peers,
+                    joining,
+                    ..
+                } = &mut self.link
+                else {
+                    return Ok(());
+                };
+                addrs.remove(&src);
+                peers.remove(&peer);
+                if !joining.remove(&peer) {
+                    self.events.push_back(Event::Disconnected(peer));
+                }
+            }
+            // Messages of peers unknown or gone.
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Make progress on `session`, then hand its events to `handler`, with the states of the
+/// peers kept in `states`. Returns how many events were handled.
+pub(crate) fn dispatch<H: Handler>(
+    session: &mut Session,
+    handler: &mut H,
+    states: &mut HashMap<PeerId, H::Peer>,
+) -> Result<usize> {
+    session.progress()?;
+    let mut handled = 0;
+    while let Some(event) = session.events.pop_front() {
+        handled += 1;
+        match event {
+            Event::Connected(peer) => match handler.on_connect(session, peer) {
+                Ok(state) => {
+                    states.insert(peer, state);
+                }
+                Err(err) => {
+                    session.disconnect(peer)?;
+                    return Err(err);
+                }
+            },
+            Event::Message(peer, msg) => {
+                if let Some(state) = states.get_mut(&peer) {
+                    handler.on_message(session, peer, state, &msg)?;
+                }
+            }
+            Event::Rma(event) => {
+                let state = event.peer.and_then(|peer| states.get_mut(&peer));
+                handler.on_rma_event(session, event, state)?;
+            }
+            Event::Disconnected(peer) => {
+                if let Some(state) = states.remove(&peer) {
+                    handler.on_disconnect(session, peer, state);
+                }
+            }
+        }
+    }
+    Ok(handled)
+}
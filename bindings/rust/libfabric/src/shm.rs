@@ -0,0 +1,406 @@
+use crate::av::{Addr, AddressVector, AvAttr, EndpointAddress};
+use crate::bootstrap::{Bootstrap, put_bytes, take_bytes};
+use crate::cq::{Completion, CompletionQueue, CqAttr};
+use crate::domain::Domain;
+use crate::ep::Endpoint;
+use crate::error::{Error, Result};
+use crate::fabric::Fabric;
+use crate::flags::BindFlags;
+use crate::info::{Info, InfoEntry};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// The context of the operations of `HybridEndpoint`, the others being those posted over
+// `route()`.
+const CONTEXT: usize = usize::MAX;
+
+/// The mechanisms the shm provider copies large messages with, besides its bounce buffers.
+///
+/// libfabric reads them from the environment on its initialization, which [`install()`]
+/// sets: it takes effect only when called before any other call into libfabric. Mechanisms
+/// left unset keep the defaults of the provider.
+///
+/// ```no_run
+/// use libfabric::ShmConfig;
+///
+/// // Containers without CAP_SYS_PTRACE cannot use CMA.
+/// let shm = ShmConfig::new().cma(false).xpmem(true);
+/// // SAFETY: no other thread runs yet.
+/// unsafe { shm.install() };
+/// ```
+///
+/// [`install()`]: Self::install
+#[derive(Debug, Clone, Default)]
+#[must_use]
+pub struct ShmConfig {
+    cma: Option<bool>,
+    xpmem: Option<bool>,
+}
+
+impl ShmConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Copy between the address spaces of processes with Cross Memory Attach, which requires
+    /// them to be allowed to ptrace each other (`FI_SHM_DISABLE_CMA`).
+    pub fn cma(mut self, enable: bool) -> Self {
+        self.cma = Some(enable);
+        self
+    }
+
+    /// Map the memory of peers with XPMEM, when its kernel module is loaded
+    /// (`FI_SHM_USE_XPMEM`).
+    pub fn xpmem(mut self, enable: bool) -> Self {
+        self.xpmem = Some(enable);
+        self
+    }
+
+    /// Set the variables of the mechanisms in the environment of the process.
+    ///
+    /// # Safety
+    ///
+    /// Setting the environment is only sound while no other thread reads or writes it, see
+    /// [`std::env::set_var()`].
+    pub unsafe fn install(&self) {
+        let flag = |enable: bool| if enable { "1" } else { "0" };
+        if let Some(cma) = self.cma {
+            unsafe { std::env::set_var("FI_SHM_DISABLE_CMA", flag(!cma)) };
+        }
+        if let Some(xpmem) = self.xpmem {
+            unsafe { std::env::set_var("FI_SHM_USE_XPMEM", flag(xpmem)) };
+        }
+    }
+}
+
+/// A name for a shm endpoint, unique to the node: the provider names the shared memory
+/// region of the endpoint after it, which peers on the node map.
+pub fn shm_name() -> String {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    format!(
+        "fi_shm_{}_{}",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// Hints for shm endpoints named `name`, ex: from [`shm_name()`], to add capabilities and an
+/// endpoint type to.
+pub fn shm_hints(name: &str) -> Info {
+    Info::new().provider("shm").node(name).source()
+}
+
+/// The identity of a node: the processes with the same one may reach each other with the
+/// shm provider.
+///
+/// It is made of the host name, and of the boot id of the kernel where available, so that
+/// containers sharing a host name across hosts are not mistaken for one node.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NodeId(Vec<u8>);
+
+impl NodeId {
+    /// The identity of the node this process runs on.
+    pub fn local() -> Result<Self> {
+        let read = |path| std::fs::read_to_string(path).map(|s| s.trim().to_owned());
+        let host = match read("/proc/sys/kernel/hostname") {
+            Ok(host) => host,
+            Err(err) => std::env::var("HOSTNAME").map_err(|_| err)?,
+        };
+        let boot = read("/proc/sys/kernel/random/boot_id").unwrap_or_default();
+        Ok(NodeId(format!("{host}/{boot}").into_bytes()))
+    }
+
+    pub fn from_bytes(bytes: impl Into<Vec<u8>>) -> Self {
+        NodeId(bytes.into())
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// An endpoint reaching the peers on the same node over the shm provider, and the others
+/// over a network provider.
+///
+/// Each peer is reached over one of the two endpoints, picked by [`insert()`](Self::insert)
+/// from the [`NodeId`] of the peer, so both sides must be opened on the same pair of
+/// providers. Operations block until they complete, so their buffers are plain slices, copied
+/// through buffers owned by the endpoint. Should a completion queue fail to be read, the
+/// operation is canceled and waited for before failing, and if the queue still fails to be
+/// read, the endpoint keeps its buffer until it is closed. No memory descriptors are passed,
+/// which rules out providers requiring
+/// [`MrMode::LOCAL`](crate::MrMode::LOCAL). With several peers on a side, receives only tell
+/// them apart if its entry has [`Caps::DIRECTED_RECV`](crate::Caps::DIRECTED_RECV).
+///
+/// ```no_run
+/// use libfabric::bootstrap::Tcp;
+/// use libfabric::{Caps, EndpointType, HybridEndpoint, Info, shm_hints, shm_name};
+/// use std::time::Duration;
+///
+/// # fn main() -> libfabric::Result<()> {
+/// let shm = shm_hints(&shm_name())
+///     .caps(Caps::MSG)
+///     .ep_type(EndpointType::Rdm)
+///     .get()?;
+/// let net = Info::new()
+///     .caps(Caps::MSG)
+///     .ep_type(EndpointType::Rdm)
+///     .provider("tcp")
+///     .get()?;
+/// let mut ep = HybridEndpoint::open(&shm[0], &net[0])?;
+/// let mut job = Tcp::connect("node0:4000", Duration::from_secs(30))?;
+/// // Each member of the job, by rank.
+/// let peers = ep.exchange(&mut job)?;
+/// ep.send(b"hello", peers[0])?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct HybridEndpoint {
+    node: NodeId,
+    shm: Side,
+    net: Side,
+    peers: Vec<Peer>,
+}
+
+struct Side {
+    ep: Endpoint,
+    cq: CompletionQueue,
+    av: AddressVector,
+    // The completions of the operations posted over `route()`, by context.
+    done: Vec<(usize, Result<usize>)>,
+    // The operations of `CONTEXT` orphaned after a failure, whose completions are dropped as
+    // they are read rather than mistaken for those of later ones.
+    orphaned: usize,
+}
+
+struct Peer {
+    local: bool,
+    addr: Addr,
+}
+
+impl HybridEndpoint {
+    /// Open an endpoint for the peers on the node from `shm`, and one for the others from
+    /// `net`, both RDM entries.
+    pub fn open(shm: &InfoEntry, net: &InfoEntry) -> Result<Self> {
+        Ok(HybridEndpoint {
+            node: NodeId::local()?,
+            shm: Side::open(shm)?,
+            net: Side::open(net)?,
+            peers: Vec::new(),
+        })
+    }
+
+    /// The node this endpoint is on.
+    pub fn node(&self) -> &NodeId {
+        &self.node
+    }
+
+    /// The names of the shm and network endpoints, which peers pass to
+    /// [`insert()`](Self::insert).
+    pub fn names(&self) -> Result<(EndpointAddress, EndpointAddress)> {
+        Ok((self.shm.ep.name()?, self.net.ep.name()?))
+    }
+
+    /// Add a peer on `node`, from the names of its endpoints, returning the index it is
+    /// addressed by. Only the name of the endpoint it is reached over is inserted.
+    pub fn insert(
+        &mut self,
+        node: &NodeId,
+        shm: &EndpointAddress,
+        net: &EndpointAddress,
+    ) -> Result<usize> {
+        let local = *node == self.node;
+        let addr = match local {
+            true => self.shm.av.insert(shm)?,
+            false => self.net.av.insert(net)?,
+        };
+        self.peers.push(Peer { local, addr });
+        Ok(self.peers.len() - 1)
+    }
+
+    /// Exchange the node and names of this endpoint with every member of `job`, inserting
+    /// them all, this one included. Returns the index of each member, by rank.
+    pub fn exchange(&mut self, job: &mut impl Bootstrap) -> Result<Vec<usize>> {
+        let (shm, net) = self.names()?;
+        let mut local = Vec::new();
+        for field in [self.node.as_bytes(), shm.as_bytes(), net.as_bytes()] {
+            put_bytes(&mut local, field);
+        }
+        job.allgather(&local)?
+            .iter()
+            .map(|buf| {
+                let mut buf = buf.as_slice();
+                let node = NodeId::from_bytes(take_bytes(&mut buf)?);
+                let shm = EndpointAddress::from_bytes(take_bytes(&mut buf)?);
+                let net = EndpointAddress::from_bytes(take_bytes(&mut buf)?);
+                self.insert(&node, &shm, &net)
+            })
+            .collect()
+    }
+
+    /// Whether `peer` is reached over shm.
+    pub fn is_local(&self, peer: usize) -> Result<bool> {
+        Ok(self.peer(peer)?.local)
+    }
+
+    /// The endpoint `peer` is reached over, and its address there, for operations beyond
+    /// those below, of any context but `usize::MAX`. Their completions are read by
+    /// [`completions()`](Self::completions), along with those read while
+    /// [`send()`](Self::send) or [`recv()`](Self::recv) wait for their own.
+    pub fn route(&self, peer: usize) -> Result<(&Endpoint, Addr)> {
+        let peer = self.peer(peer)?;
+        Ok((&self.side(peer.local).ep, peer.addr))
+    }
+
+    /// Send `buf` to `peer`.
+    pub fn send(&mut self, buf: &[u8], peer: usize) -> Result<()> {
+        let peer = self.peer(peer)?;
+        let (local, addr) = (peer.local, peer.addr);
+        // SAFETY: the buffer is owned until the send completed, see `Side::wait()`.
+        self.side_mut(local)
+            .run(buf.into(), |ep, buf| unsafe {
+                ep.send(buf, None, addr, CONTEXT)
+            })
+            .map(|_| ())
+    }
+
+    /// Receive the next message from `peer` into `buf`, returning its length.
+    pub fn recv(&mut self, buf: &mut [u8], peer: usize) -> Result<usize> {
+        let peer = self.peer(peer)?;
+        let (local, addr) = (peer.local, peer.addr);
+        let staged = vec![0; buf.len()].into_boxed_slice();
+        // SAFETY: the buffer is owned until the receive completed, see `Side::wait()`.
+        let (len, staged) = self.side_mut(local).run(staged, |ep, buf| unsafe {
+            ep.recv(buf, None, addr, CONTEXT)
+        })?;
+        buf[..len].copy_from_slice(&staged[..len]);
+        Ok(len)
+    }
+
+    /// Read the completions available on both sides, returning the context and length, or
+    /// error, of each operation posted over [`route()`](Self::route) which completed.
+    pub fn completions(&mut self) -> Result<Vec<(usize, Result<usize>)>> {
+        let shm = self.shm.progress();
+        let net = self.net.progress();
+        shm.and(net)?;
+        let mut done = std::mem::take(&mut self.shm.done);
+        done.append(&mut self.net.done);
+        Ok(done)
+    }
+
+    fn peer(&self, peer: usize) -> Result<&Peer> {
+        self.peers
+            .get(peer)
+            .ok_or_else(|| Error::invalid(format!("no peer {peer}")))
+    }
+
+    fn side(&self, local: bool) -> &Side {
+        match local {
+            true => &self.shm,
+            false => &self.net,
+        }
+    }
+
+    fn side_mut(&mut self, local: bool) -> &mut Side {
+        match local {
+            true => &mut self.shm,
+            false => &mut self.net,
+        }
+    }
+}
+
+impl Side {
+    fn open(entry: &InfoEntry) -> Result<Self> {
+        let fabric = Fabric::open(entry)?;
+        let domain = Domain::open(&fabric, entry)?;
+        let cq = domain.cq(&CqAttr::new())?;
+        let av = domain.av(&AvAttr::new())?;
+        let ep = domain
+            .endpoint(entry)?
+            .bind_cq(&cq, BindFlags::TRANSMIT | BindFlags::RECV)?
+            .bind_av(&av)?
+            .enable()?;
+        Ok(Side {
+            ep,
+            cq,
+            av,
+            done: Vec::new(),
+            orphaned: 0,
+        })
+    }
+
+    // Post an operation of `CONTEXT` over `buf` and wait for it, returning its length along
+    // with `buf`.
+    fn run(
+        &mut self,
+        mut buf: Box<[u8]>,
+        mut post: impl FnMut(&Endpoint, &mut [u8]) -> Result<()>,
+    ) -> Result<(usize, Box<[u8]>)> {
+        self.post(|ep| post(ep, &mut buf))?;
+        let len = self.wait(&mut buf)?;
+        Ok((len, buf))
+    }
+
+    // Post an operation, progressing the endpoint while it is out of resources.
+    fn post(&self, mut post: impl FnMut(&Endpoint) -> Result<()>) -> Result<()> {
+        loop {
+            match post(&self.ep) {
+                Err(err) if err.is_again() => self.cq.progress()?,
+                other => return other,
+            }
+        }
+    }
+
+    // Wait for the completion of the operation posted over `buf`, returning its length. Should
+    // the queue fail to be read, the operation is canceled and waited for all the same before
+    // failing, as `buf` is only released on return, and should it fail to be read again, `buf`
+    // is left to the endpoint.
+    fn wait(&mut self, buf: &mut Box<[u8]>) -> Result<usize> {
+        let mut failed = None;
+        loop {
+            if let Some(at) = self.done.iter().position(|(c, _)| *c == CONTEXT) {
+                let outcome = self.done.swap_remove(at).1;
+                return match failed {
+                    Some(err) => Err(err),
+                    None => outcome,
+                };
+            }
+            if let Err(err) = self.progress() {
+                if let Some(failed) = failed {
+                    self.ep.orphan(std::mem::take(buf));
+                    self.orphaned += 1;
+                    return Err(failed);
+                }
+                let _ = self.ep.cancel(CONTEXT);
+                failed = Some(err);
+            }
+        }
+    }
+
+    // Record the completion of `context`, unless it is that of an orphaned operation.
+    fn complete(&mut self, context: usize, outcome: Result<usize>) {
+        match context == CONTEXT && self.orphaned > 0 {
+            true => self.orphaned -= 1,
+            false => self.done.push((context, outcome)),
+        }
+    }
+
+    // Read the completions available, successful or not, of every context.
+    fn progress(&mut self) -> Result<()> {
+        let mut completions = [Completion::default(); 8];
+        match self.cq.read(&mut completions) {
+            Ok(n) => {
+                for completion in &completions[..n] {
+                    self.complete(completion.context(), Ok(completion.len()));
+                }
+            }
+            Err(err) if err.is_again() => {}
+            Err(err) if err.is_avail() => {
+                if let Some(entry) = self.cq.read_err()? {
+                    self.complete(entry.context, Err(entry.error));
+                }
+            }
+            Err(err) => return Err(err),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,361 @@
+use crate::bootstrap::{RemoteRegion, put_bytes, take, take_bytes};
+use crate::domain::Domain;
+use crate::error::{Error, Result};
+use crate::flags::{Access, MrMode};
+use crate::mr::MemoryRegion;
+use crate::threading::{ThreadSafe, ThreadingModel};
+use std::ffi::c_void;
+use std::fs::{File, OpenOptions};
+use std::os::fd::AsRawFd;
+use std::os::raw::{c_int, c_long};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use std::ptr;
+
+// From sys/mman.h, the same on the architectures Linux runs on.
+const PROT_READ: c_int = 0x1;
+const PROT_WRITE: c_int = 0x2;
+const MAP_SHARED: c_int = 0x1;
+const MAP_FAILED: *mut c_void = !0 as *mut c_void;
+
+unsafe extern "C" {
+    fn mmap(
+        addr: *mut c_void,
+        len: usize,
+        prot: c_int,
+        flags: c_int,
+        fd: c_int,
+        offset: c_long,
+    ) -> *mut c_void;
+    fn munmap(addr: *mut c_void, len: usize) -> c_int;
+}
+
+/// Attributes of a [`ShmRegion`], created or opened.
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct ShmRegionAttr {
+    access: Access,
+    mode: u32,
+    read_only: bool,
+}
+
+impl ShmRegionAttr {
+    /// A region registered with the access rights of `access`, whose name only the user may
+    /// open.
+    pub fn new(access: Access) -> Self {
+        ShmRegionAttr {
+            access,
+            mode: 0o600,
+            read_only: false,
+        }
+    }
+
+    /// The permissions of the name of a created region, `0o600` by default: `0o660` lets the
+    /// processes of the group of the user open it too, ex: those of another user of a job.
+    pub fn mode(mut self, mode: u32) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Map an opened region read-only, for siblings which only send from it, or are the
+    /// source of reads, or whose permissions do not allow writing. Its access rights are then
+    /// checked not to write to it: neither [`Access::RECV`], [`Access::READ`] nor
+    /// [`Access::REMOTE_WRITE`].
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+}
+
+/// What a sibling process on the node needs to map a [`ShmRegion`], and target the
+/// registration of its creator with RMA: small enough to hand over through any channel, ex:
+/// the `data` of a [`PeerInfo`](crate::PeerInfo) or a pipe, as the memory itself never is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShmHandle {
+    /// The name of the shared memory object, under `/dev/shm`.
+    pub name: String,
+    pub len: usize,
+    /// The registration of the creator of the region.
+    pub remote: RemoteRegion,
+}
+
+impl ShmHandle {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        put_bytes(&mut buf, self.name.as_bytes());
+        let remote = &self.remote;
+        for field in [self.len as u64, remote.addr, remote.len, remote.key] {
+            buf.extend(field.to_le_bytes());
+        }
+        buf
+    }
+
+    /// Decode a handle from [`to_bytes()`](Self::to_bytes), failing with an invalid argument
+    /// error if `buf` is truncated.
+    pub fn from_bytes(mut buf: &[u8]) -> Result<Self> {
+        let name = String::from_utf8(take_bytes(&mut buf)?.to_vec())
+            .map_err(|_| Error::invalid("shared memory name is not UTF-8"))?;
+        let mut next = || take(&mut buf).map(u64::from_le_bytes);
+        Ok(ShmHandle {
+            name,
+            len: next()? as usize,
+            remote: RemoteRegion {
+                addr: next()?,
+                len: next()?,
+                key: next()?,
+            },
+        })
+    }
+}
+
+// A shared mapping, unmapped once dropped.
+struct Mapping {
+    addr: *mut u8,
+    len: usize,
+    writable: bool,
+}
+
+impl Mapping {
+    fn new(file: &File, len: usize, writable: bool) -> Result<Self> {
+        let prot = if writable {
+            PROT_READ | PROT_WRITE
+        } else {
+            PROT_READ
+        };
+        let addr = unsafe { mmap(ptr::null_mut(), len, prot, MAP_SHARED, file.as_raw_fd(), 0) };
+        if addr == MAP_FAILED {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(Mapping {
+            addr: addr.cast(),
+            len,
+            writable,
+        })
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe { munmap(self.addr.cast(), self.len) };
+    }
+}
+
+/// A registered region of shared memory, which the processes of the node map without copies,
+/// ex: the stages of a pipeline over the shm provider, and target with RMA.
+///
+/// The creator names the memory, under `/dev/shm` with the permissions of its
+/// [`mode()`](ShmRegionAttr::mode), and hands its [`handle()`](Self::handle) to its
+/// siblings, which [`open()`](Self::open) it, each registering its mapping in its own domain.
+/// The memory lives until the last process unmaps it, while the name lives until its creator
+/// drops the region, or [`unlinks`](Self::unlink) it once its siblings opened it. The
+/// registration of the creator, which siblings target with RMA, lives as long as its region.
+///
+/// ```no_run
+/// use libfabric::{Access, Domain, ShmHandle, ShmRegion, ShmRegionAttr};
+///
+/// # fn run(domain: &Domain, handle: &[u8]) -> libfabric::Result<()> {
+/// let attr = ShmRegionAttr::new(Access::SEND | Access::RECV | Access::REMOTE_WRITE);
+/// // In the creator.
+/// let region = ShmRegion::create(domain, "pipeline-stage-0", 1 << 20, &attr)?;
+/// let bytes = region.handle().to_bytes();
+/// // In a sibling, once it received the bytes.
+/// let sibling = ShmRegion::open(domain, &ShmHandle::from_bytes(handle)?, &attr)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ShmRegion<M: ThreadingModel = ThreadSafe> {
+    // Declared first, so that the region is closed before the memory is unmapped.
+    mr: MemoryRegion<M>,
+    map: Mapping,
+    name: String,
+    remote: RemoteRegion,
+    // Whether the name is unlinked once the region is dropped.
+    owned: bool,
+}
+
+// SAFETY: the mapping is only reported back to the application, never dereferenced by the
+// crate. The region is otherwise as thread safe as its domain.
+unsafe impl<M: ThreadingModel> Send for ShmRegion<M> where Domain<M>: Send {}
+unsafe impl<M: ThreadingModel> Sync for ShmRegion<M> where Domain<M>: Sync {}
+
+impl<M: ThreadingModel> ShmRegion<M> {
+    /// Create `len` bytes of zeroed shared memory named `name`, and register them in `domain`.
+    /// Fails with an I/O error of [`AlreadyExists`](std::io::ErrorKind::AlreadyExists) if
+    /// the name is taken, ex: by a region a crashed job did not unlink.
+    pub fn create(
+        domain: &Domain<M>,
+        name: &str,
+        len: usize,
+        attr: &ShmRegionAttr,
+    ) -> Result<Self> {
+        if len == 0 {
+            return Err(Error::invalid("shared memory regions of 0 bytes"));
+        }
+        if attr.read_only {
+            return Err(Error::invalid("read-only shared memory region created"));
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .mode(attr.mode)
+            .open(path(name)?)?;
+        let region = file
+            .set_len(len as u64)
+            .map_err(Error::from)
+            .and_then(|()| Self::map(domain, &file, name, len, attr, None));
+        if region.is_err() {
+            let _ = std::fs::remove_file(path(name)?);
+        }
+        region
+    }
+
+    /// Map the region of `handle`, created by a sibling process, and register it in `domain`.
+    /// Fails with an I/O error of [`NotFound`](std::io::ErrorKind::NotFound) once its name is
+    /// unlinked, and of [`PermissionDenied`](std::io::ErrorKind::PermissionDenied) if its
+    /// mode does not allow this process to open it.
+    pub fn open(domain: &Domain<M>, handle: &ShmHandle, attr: &ShmRegionAttr) -> Result<Self> {
+        let writes = Access::RECV | Access::READ | Access::REMOTE_WRITE;
+        if attr.read_only && attr.access.intersects(writes) {
+            return Err(Error::invalid(format!(
+                "read-only shared memory region registered with {:?}",
+                attr.access & writes
+            )));
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(!attr.read_only)
+            .open(path(&handle.name)?)?;
+        let size = file.metadata()?.len();
+        if size < handle.len as u64 {
+            return Err(Error::invalid(format!(
+                "shared memory {} of {size} bytes, not {}",
+                handle.name, handle.len
+            )));
+        }
+        Self::map(
+            domain,
+            &file,
+            &handle.name,
+            handle.len,
+            attr,
+            Some(handle.remote),
+        )
+    }
+
+    fn map(
+        domain: &Domain<M>,
+        file: &File,
+        name: &str,
+        len: usize,
+        attr: &ShmRegionAttr,
+        remote: Option<RemoteRegion>,
+    ) -> Result<Self> {
+        let map = Mapping::new(file, len, !attr.read_only)?;
+        // SAFETY: the mapping outlives the region, see the order of the fields.
+        let mr = unsafe { domain.register(map.addr, len, attr.access) }?;
+        let virt_addr = domain.info().mr_mode().contains(MrMode::VIRT_ADDR);
+        Ok(ShmRegion {
+            remote: remote.unwrap_or(RemoteRegion {
+                addr: if virt_addr { map.addr as u64 } else { 0 },
+                len: len as u64,
+                key: mr.key(),
+            }),
+            owned: remote.is_none(),
+            mr,
+            map,
+            name: name.to_string(),
+        })
+    }
+
+    /// The handle siblings open the region with, whose remote region is that of its creator.
+    pub fn handle(&self) -> ShmHandle {
+        ShmHandle {
+            name: self.name.clone(),
+            len: self.map.len,
+            remote: self.remote,
+        }
+    }
+
+    /// The registration of the mapping of this process, local buffers of operations.
+    pub fn region(&self) -> &MemoryRegion<M> {
+        &self.mr
+    }
+
+    /// The registration of the creator of the region, which RMA operations target.
+    pub fn remote(&self) -> RemoteRegion {
+        self.remote
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether this process created the region, and unlinks its name once it is dropped.
+    pub fn is_owner(&self) -> bool {
+        self.owned
+    }
+
+    /// Remove the name of the region, so that no other process may open it, while those which
+    /// did keep their mapping. Dropping the region of its creator unlinks it too.
+    pub fn unlink(&mut self) -> Result<()> {
+        self.owned = false;
+        std::fs::remove_file(path(&self.name)?)?;
+        Ok(())
+    }
+
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.map.addr
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.len == 0
+    }
+
+    /// The memory of the region.
+    ///
+    /// # Safety
+    ///
+    /// No other process, nor any operation, may write to it while the slice is in use.
+    pub unsafe fn as_slice(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.map.addr, self.map.len) }
+    }
+
+    /// The memory of the region, to write to. Fails with an invalid argument error if it is
+    /// mapped read-only.
+    ///
+    /// # Safety
+    ///
+    /// No other process, nor any operation, may access it while the slice is in use.
+    pub unsafe fn as_mut_slice(&mut self) -> Result<&mut [u8]> {
+        if !self.map.writable {
+            return Err(Error::invalid(format!(
+                "shared memory region {} mapped read-only",
+                self.name
+            )));
+        }
+        Ok(unsafe { std::slice::from_raw_parts_mut(self.map.addr, self.map.len) })
+    }
+}
+
+impl<M: ThreadingModel> Drop for ShmRegion<M> {
+    fn drop(&mut self) {
+        if self.owned
+            && let Ok(path) = path(&self.name)
+        {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+// The path of the shared memory object `name`, as `shm_open()` names it.
+fn path(name: &str) -> Result<PathBuf> {
+    if name.is_empty() || name.len() > 255 || name.contains(['/', '\0']) {
+        return Err(Error::invalid(format!("shared memory name {name:?}")));
+    }
+    Ok(PathBuf::from("/dev/shm").join(name))
+}
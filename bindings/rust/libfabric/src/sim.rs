@@ -0,0 +1,129 @@
+//! Deterministic simulation of a lossy, reordering network over the [`mock`](crate::mock)
+//! fabric, enabled by the `mock` feature.
+//!
+//! A simulated [`MockFabric`] runs on a virtual clock, ticking once on each progress of the
+//! network, rather than on wall clock delays. Each operation is delivered after a random
+//! number of ticks, such that operations posted back to back may complete out of order, and
+//! may be dropped. All of the randomness comes from the seed, so a run is reproduced exactly
+//! by replaying the same calls with the same seed, which is what makes failures found by
+//! [`Simulation::check()`] debuggable.
+//!
+//! Dropped operations never reach their target, and complete in error with `FI_EIO` at the
+//! initiator, as on a reliable endpoint giving up on a peer. Dropped injected operations,
+//! which have no completion, vanish silently.
+//!
+//! ```
+//! use libfabric::sim::Simulation;
+//! use libfabric::{Av, Completion, Cq, Transport};
+//!
+//! Simulation::new(0).max_delay(4).drop_rate(0.1).check(0..32, |fabric| {
+//!     let (a, b) = (fabric.endpoint(), fabric.endpoint());
+//!     let to_b = fabric.av().insert(&b.name()?)?;
+//!     a.inject(b"ping", to_b)?;
+//!     // ... drive the protocol under test, returning an error when it misbehaves.
+//!     Ok(())
+//! });
+//! ```
+
+use crate::error::Result;
+use crate::mock::MockFabric;
+use std::ops::Range;
+
+/// The parameters of a simulated network.
+#[derive(Debug, Clone, Copy)]
+#[must_use]
+pub struct Simulation {
+    seed: u64,
+    drop_rate: f64,
+    max_delay: u64,
+}
+
+impl Simulation {
+    /// A simulation with neither delays nor drops, which `seed` then randomizes.
+    pub fn new(seed: u64) -> Self {
+        Simulation {
+            seed,
+            drop_rate: 0.0,
+            max_delay: 0,
+        }
+    }
+
+    /// Drop each operation with probability `rate`, from 0.0 to 1.0.
+    pub fn drop_rate(mut self, rate: f64) -> Self {
+        self.drop_rate = rate;
+        self
+    }
+
+    /// Deliver each operation after 0 to `ticks` ticks of the virtual clock, picked at random.
+    /// Operations posted less than `ticks` apart may then be delivered out of order.
+    pub fn max_delay(mut self, ticks: u64) -> Self {
+        self.max_delay = ticks;
+        self
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Open a simulated fabric. Failures may still be injected into it, and wall clock delays
+    /// set through [`MockFabric::set_delay()`] are ignored.
+    pub fn fabric(&self) -> MockFabric {
+        MockFabric::simulated(Scheduler {
+            rng: self.seed,
+            drop_rate: self.drop_rate,
+            max_delay: self.max_delay,
+            now: 0,
+        })
+    }
+
+    /// Run `test` on a fresh fabric for each seed of `seeds`, panicking with the seed of the
+    /// first failed run.
+    pub fn check(&self, seeds: Range<u64>, mut test: impl FnMut(&MockFabric) -> Result<()>) {
+        for seed in seeds {
+            let fabric = Simulation { seed, ..*self }.fabric();
+            if let Err(err) = test(&fabric) {
+                panic!("simulation failed with seed {seed}: {err}");
+            }
+        }
+    }
+}
+
+// The virtual clock and random choices of a simulated fabric.
+pub(crate) struct Scheduler {
+    // SplitMix64 state, which is fast, and good enough for picking delays.
+    rng: u64,
+    drop_rate: f64,
+    max_delay: u64,
+    now: u64,
+}
+
+impl Scheduler {
+    fn next(&mut self) -> u64 {
+        self.rng = self.rng.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Advance the clock by one tick, returning the new time.
+    pub(crate) fn tick(&mut self) -> u64 {
+        self.now += 1;
+        self.now
+    }
+
+    /// The tick at which an operation posted now is due.
+    pub(crate) fn due(&mut self) -> u64 {
+        let delay = match self.max_delay {
+            0 => 0,
+            max => self.next() % (max + 1),
+        };
+        self.now + delay
+    }
+
+    /// Whether to drop the operation being delivered.
+    pub(crate) fn drops(&mut self) -> bool {
+        // The top 53 bits, as a uniform float in [0, 1).
+        self.drop_rate > 0.0 && ((self.next() >> 11) as f64 / (1u64 << 53) as f64) < self.drop_rate
+    }
+}
@@ -0,0 +1,186 @@
+use crate::av::Addr;
+use crate::cq::{Completion, CqErrEntry};
+use crate::error::{Error, Result};
+use crate::transport::{Cq, CqHandler, Transport, poll_cq};
+use futures_sink::Sink;
+use std::collections::{HashMap, VecDeque};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// The messages sent to a peer, as a [`Sink`] of owned buffers, enabled by the `sink`
+/// feature, so that `SinkExt::send_all()` of a stream waits for room instead of spinning on
+/// `FI_EAGAIN`.
+///
+/// The sink is ready while fewer than its depth of sends are in flight, which bounds the
+/// transmit context used, and a send the provider has no room for stays queued in the sink,
+/// posted again on the next poll. Flushing waits for the completion of every send, and fails
+/// with the error of the first which failed since the last flush.
+///
+/// It runs over any [`Transport`] and [`Cq`], as [`FlowControl`](crate::FlowControl) does,
+/// whose completions are its own. As the futures of the `async` feature, it does not depend on
+/// a runtime: it polls the queue each time it is polled, and wakes itself up while it waits,
+/// which keeps the executor busy in the meantime. For the credits of the receiver to hold the
+/// sender back too, see the `Sink` of the [channels](crate::channel::Sender).
+///
+/// ```no_run
+/// use futures::SinkExt;
+/// use libfabric::{Addr, CompletionQueue, Endpoint, MsgSink};
+///
+/// # async fn run(ep: Endpoint, cq: CompletionQueue, peer: Addr) -> libfabric::Result<()> {
+/// let mut sink = unsafe { MsgSink::new(ep, cq, peer, 64) };
+/// let mut messages = futures::stream::iter((0..1000u32).map(|i| Ok(i.to_le_bytes().to_vec())));
+/// sink.send_all(&mut messages).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct MsgSink<T: Transport, C: Cq> {
+    // First, see `CqHandler`.
+    ep: T,
+    cq: C,
+    dest: Addr,
+    tag: Option<u64>,
+    depth: usize,
+    // Sends the provider had no room for, in order.
+    unposted: VecDeque<Vec<u8>>,
+    // The buffers of posted sends, by context.
+    sends: HashMap<usize, Vec<u8>>,
+    next_send: usize,
+    error: Option<Error>,
+}
+
+impl<T: Transport, C: Cq> MsgSink<T, C> {
+    /// A sink of the messages sent over `ep` to `dest`, whose completions are read from `cq`,
+    /// with at most `depth` sends in flight.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Transport`] documentation.
+    pub unsafe fn new(ep: T, cq: C, dest: Addr, depth: usize) -> Self {
+        MsgSink {
+            ep,
+            cq,
+            dest,
+            tag: None,
+            depth: depth.max(1),
+            unposted: VecDeque::new(),
+            sends: HashMap::new(),
+            next_send: 0,
+            error: None,
+        }
+    }
+
+    /// Send tagged messages of `tag`, rather than untagged ones.
+    #[must_use]
+    pub fn tagged(mut self, tag: u64) -> Self {
+        self.tag = Some(tag);
+        self
+    }
+
+    pub fn endpoint(&self) -> &T {
+        &self.ep
+    }
+
+    /// The peer messages are sent to.
+    pub fn dest(&self) -> Addr {
+        self.dest
+    }
+
+    /// Sends posted or waiting for room, not completed yet.
+    pub fn in_flight(&self) -> usize {
+        self.sends.len() + self.unposted.len()
+    }
+
+    // Read the completions available, then post the sends waiting for room.
+    fn progress(&mut self) -> Result<()> {
+        poll_cq(self)?;
+        while let Some(buf) = self.unposted.pop_front() {
+            let context = self.next_send;
+            // SAFETY: the buffer is kept until the send completes, and outlives the endpoint,
+            // see `new()`.
+            let posted = unsafe {
+                match self.tag {
+                    Some(tag) => self.ep.tsend(&buf, None, self.dest, tag, context),
+                    None => self.ep.send(&buf, None, self.dest, context),
+                }
+            };
+            match posted {
+                Ok(()) => {
+                    self.next_send = self.next_send.wrapping_add(1);
+                    self.sends.insert(context, buf);
+                }
+                Err(err) if err.is_again() => {
+                    self.unposted.push_front(buf);
+                    break;
+                }
+                // The message is dropped, like a failed send.
+                Err(err) => {
+                    self.error.get_or_insert(err);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Ready once `done` holds, waking the task up to poll again otherwise.
+    fn poll_until(
+        &mut self,
+        cx: &mut Context<'_>,
+        done: impl Fn(&Self) -> bool,
+    ) -> Poll<Result<()>> {
+        if let Err(err) = self.progress() {
+            return Poll::Ready(Err(err));
+        }
+        if let Some(err) = self.error.take() {
+            return Poll::Ready(Err(err));
+        }
+        if done(self) {
+            return Poll::Ready(Ok(()));
+        }
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+// The sink holds no self-references.
+impl<T: Transport, C: Cq> Unpin for MsgSink<T, C> {}
+
+impl<T: Transport, C: Cq> CqHandler for MsgSink<T, C> {
+    type Cq = C;
+
+    fn cq(&self) -> &C {
+        &self.cq
+    }
+
+    fn complete(&mut self, completion: &Completion, _src: Addr) {
+        self.sends.remove(&completion.context());
+    }
+
+    fn failed(&mut self, entry: CqErrEntry) {
+        self.sends.remove(&entry.context);
+        self.error.get_or_insert(entry.error);
+    }
+}
+
+impl<T: Transport, C: Cq> Sink<Vec<u8>> for MsgSink<T, C> {
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let depth = self.depth;
+        self.get_mut()
+            .poll_until(cx, |sink| sink.in_flight() < depth)
+    }
+
+    fn start_send(self: Pin<&mut Self>, msg: Vec<u8>) -> Result<()> {
+        let sink = self.get_mut();
+        sink.unposted.push_back(msg);
+        sink.progress()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.get_mut().poll_until(cx, |sink| sink.in_flight() == 0)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.poll_flush(cx)
+    }
+}
@@ -0,0 +1,188 @@
+use crate::attr::{RxQueueAttr, TxQueueAttr};
+use crate::cq::CqAttr;
+use crate::error::{Error, Result};
+use crate::info::InfoEntry;
+use std::fmt;
+
+/// The concurrency of an application, from which [`advise()`](Self::advise) derives the
+/// depths of its queues: the operations in flight towards each peer, times the peers.
+///
+/// ```no_run
+/// # fn run(entry: &libfabric::InfoEntry) -> libfabric::Result<()> {
+/// use libfabric::{Concurrency, Fabric};
+///
+/// let sizing = Concurrency::new(64).sends(8).recvs(4).advise(entry);
+/// for warning in &sizing.warnings {
+///     eprintln!("{warning}");
+/// }
+/// let entry = entry
+///     .with_tx_attr(&sizing.tx_attr())?
+///     .with_rx_attr(&sizing.rx_attr())?;
+/// let domain = Fabric::open(&entry)?.domain(&entry)?;
+/// let cq = domain.cq(&sizing.cq_attr())?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub struct Concurrency {
+    peers: usize,
+    sends: usize,
+    recvs: usize,
+    endpoints: usize,
+    counters: usize,
+    shared_cq: bool,
+}
+
+impl Concurrency {
+    /// An endpoint exchanging with `peers` peers, one operation in flight each way per peer.
+    pub fn new(peers: usize) -> Self {
+        Concurrency {
+            peers: peers.max(1),
+            sends: 1,
+            recvs: 1,
+            endpoints: 1,
+            counters: 0,
+            shared_cq: true,
+        }
+    }
+
+    /// Sends, writes, reads and atomics in flight towards each peer, 1 by default.
+    pub fn sends(mut self, sends: usize) -> Self {
+        self.sends = sends;
+        self
+    }
+
+    /// Receives kept posted for each peer, 1 by default.
+    pub fn recvs(mut self, recvs: usize) -> Self {
+        self.recvs = recvs;
+        self
+    }
+
+    /// Endpoints sharing the completion queues, 1 by default.
+    pub fn endpoints(mut self, endpoints: usize) -> Self {
+        self.endpoints = endpoints.max(1);
+        self
+    }
+
+    /// Counters opened in the domain, none by default.
+    pub fn counters(mut self, counters: usize) -> Self {
+        self.counters = counters;
+        self
+    }
+
+    /// Whether transmits and receives complete on the same queue, the default, or on a queue
+    /// each.
+    pub fn shared_cq(mut self, shared: bool) -> Self {
+        self.shared_cq = shared;
+        self
+    }
+
+    /// The depths of the queues for this concurrency on the endpoints of `entry`, clamped to
+    /// the maxima of the provider, with a warning for each depth clamped or count exceeded.
+    pub fn advise(&self, entry: &InfoEntry) -> QueueSizing {
+        let (tx_max, rx_max, domain) = (entry.tx_attr(), entry.rx_attr(), entry.domain_attr());
+        let mut warnings = Vec::new();
+        let mut check = |what, requested: usize, max: usize| match max {
+            // Left unspecified by the provider.
+            0 => requested,
+            max if requested > max => {
+                warnings.push(SizingWarning {
+                    what,
+                    requested,
+                    max,
+                });
+                max
+            }
+            _ => requested,
+        };
+
+        let tx_size = check("tx depth", self.peers * self.sends, tx_max.size);
+        let rx_size = check("rx depth", self.peers * self.recvs, rx_max.size);
+        check("endpoints", self.endpoints, domain.ep_cnt);
+        let cqs = match self.shared_cq {
+            true => 1,
+            false => 2,
+        };
+        check("completion queues", cqs, domain.cq_cnt);
+        let counters = check("counters", self.counters, domain.cntr_cnt);
+
+        // Every operation in flight may complete before any completion is read, errors
+        // included, so the queues hold them all, rounded up for headroom.
+        let per_endpoint = match self.shared_cq {
+            true => tx_size + rx_size,
+            false => tx_size.max(rx_size),
+        };
+        let cq_size = (self.endpoints * per_endpoint).next_power_of_two();
+        QueueSizing {
+            tx_size,
+            rx_size,
+            cq_size,
+            cqs,
+            counters,
+            warnings,
+        }
+    }
+}
+
+/// The depths of queues derived by [`Concurrency::advise()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueueSizing {
+    /// The operations the transmit context of each endpoint queues.
+    pub tx_size: usize,
+    /// The receives the receive context of each endpoint queues.
+    pub rx_size: usize,
+    /// The entries of each completion queue.
+    pub cq_size: usize,
+    /// The completion queues, 1 shared by transmits and receives, or 2.
+    pub cqs: usize,
+    pub counters: usize,
+    /// The depths and counts past the maxima of the provider, clamped to them.
+    pub warnings: Vec<SizingWarning>,
+}
+
+impl QueueSizing {
+    /// The settings of the transmit contexts, for
+    /// [`InfoEntry::with_tx_attr()`](crate::InfoEntry::with_tx_attr).
+    pub fn tx_attr(&self) -> TxQueueAttr {
+        TxQueueAttr::new().size(self.tx_size)
+    }
+
+    /// The settings of the receive contexts, for
+    /// [`InfoEntry::with_rx_attr()`](crate::InfoEntry::with_rx_attr).
+    pub fn rx_attr(&self) -> RxQueueAttr {
+        RxQueueAttr::new().size(self.rx_size)
+    }
+
+    pub fn cq_attr(&self) -> CqAttr {
+        CqAttr::new().size(self.cq_size)
+    }
+
+    /// Fail with an invalid argument error listing the warnings, if any, for applications
+    /// which cannot run with less concurrency than they declared.
+    pub fn strict(self) -> Result<Self> {
+        if self.warnings.is_empty() {
+            return Ok(self);
+        }
+        let warnings: Vec<_> = self.warnings.iter().map(|w| w.to_string()).collect();
+        Err(Error::invalid(warnings.join(", ")))
+    }
+}
+
+/// A depth or count past the maximum of a provider.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SizingWarning {
+    pub what: &'static str,
+    pub requested: usize,
+    pub max: usize,
+}
+
+impl fmt::Display for SizingWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} over the provider maximum of {}",
+            self.what, self.requested, self.max
+        )
+    }
+}
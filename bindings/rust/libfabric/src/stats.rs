@@ -0,0 +1,484 @@
+use crate::av::{Addr, EndpointAddress};
+use crate::cq::{Completion, CqErrEntry};
+use crate::error::{Error, Result};
+use crate::record::OpKind;
+use crate::transport::{Cq, Transport};
+use ofi_libfabric_sys::bindgen as ffi;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// The operations of one kind of an endpoint, see [`EndpointStats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OpStats {
+    pub posted: u64,
+    /// Completed successfully. Injected operations, which have none, are once posted.
+    pub completed: u64,
+    /// Completed in error.
+    pub failed: u64,
+    /// Posted, with no completion read yet, whenever they were posted.
+    pub in_flight: u64,
+}
+
+/// What the operations of an endpoint did, from [`TrackedEndpoint::stats()`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EndpointStats {
+    /// By kind of operation, for the kinds posted at least once.
+    pub ops: HashMap<OpKind, OpStats>,
+    /// The bytes of the sends, injects and writes posted.
+    pub bytes_sent: u64,
+    /// The bytes of the messages received, and of the reads completed.
+    pub bytes_received: u64,
+    /// Posts failed with `FI_EAGAIN`, which the provider had no room for.
+    pub again: u64,
+    /// Error completions, of all kinds.
+    pub errors: u64,
+}
+
+impl EndpointStats {
+    /// The operations of `kind`, zero if none was posted.
+    pub fn of(&self, kind: OpKind) -> OpStats {
+        self.ops.get(&kind).copied().unwrap_or_default()
+    }
+
+    /// The operations in flight, of all kinds.
+    pub fn in_flight(&self) -> u64 {
+        self.ops.values().map(|op| op.in_flight).sum()
+    }
+
+    fn merge(&mut self, other: &EndpointStats) {
+        for (kind, op) in &other.ops {
+            let total = self.ops.entry(*kind).or_default();
+            total.posted += op.posted;
+            total.completed += op.completed;
+            total.failed += op.failed;
+            total.in_flight += op.in_flight;
+        }
+        self.bytes_sent += other.bytes_sent;
+        self.bytes_received += other.bytes_received;
+        self.again += other.again;
+        self.errors += other.errors;
+    }
+}
+
+/// Counts the operations of the endpoints, and the completions of the queues, it wraps, for
+/// the monitoring of an application, or for tuning it as it runs, ex: posting less while the
+/// provider keeps failing posts with `FI_EAGAIN`.
+///
+/// The wrapped endpoints and queues behave as the originals, but for counting. Completions are
+/// matched with the oldest pending operation of the same context, on any of the endpoints of
+/// the tracker, as with a [`Recorder`](crate::Recorder), so they are counted on the endpoint
+/// which posted them even when queues are shared.
+///
+/// ```no_run
+/// use libfabric::{CompletionQueue, Endpoint, OpKind, StatsTracker};
+///
+/// # fn run(ep: Endpoint, cq: CompletionQueue) {
+/// let tracker = StatsTracker::new();
+/// let (ep, cq) = (tracker.endpoint(ep), tracker.cq(cq));
+/// // Run over `ep` and `cq` through the transport traits, then:
+/// let stats = ep.stats();
+/// println!("{} sends in flight, {} EAGAIN", stats.of(OpKind::Send).in_flight, stats.again);
+/// ep.reset();
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct StatsTracker {
+    inner: Arc<Mutex<State>>,
+}
+
+// An operation waiting for its completion.
+struct Pending {
+    ep: usize,
+    kind: OpKind,
+    len: usize,
+    // The peer of the operation, unspecified for receives from any.
+    peer: Addr,
+}
+
+#[derive(Default)]
+struct State {
+    // The pending operations of each context, oldest first.
+    pending: HashMap<usize, VecDeque<Pending>>,
+    endpoints: Vec<EndpointStats>,
+}
+
+impl State {
+    // Count the completion of the oldest operation pending with `context`, of `len` bytes
+    // for receives, failed or not.
+    fn complete(&mut self, context: usize, len: usize, failed: bool) {
+        let Some(pending) = self.pending.get_mut(&context) else {
+            return;
+        };
+        let Some(op) = pending.pop_front() else {
+            return;
+        };
+        if pending.is_empty() {
+            self.pending.remove(&context);
+        }
+        let stats = &mut self.endpoints[op.ep];
+        let counts = stats.ops.entry(op.kind).or_default();
+        if failed {
+            counts.failed += 1;
+            stats.errors += 1;
+            return;
+        }
+        counts.completed += 1;
+        stats.bytes_received += match op.kind {
+            OpKind::Recv | OpKind::TRecv => len as u64,
+            OpKind::Read => op.len as u64,
+            _ => 0,
+        };
+    }
+
+    fn stats(&self, ep: usize) -> EndpointStats {
+        let mut stats = self.endpoints[ep].clone();
+        for op in self.pending.values().flatten().filter(|op| op.ep == ep) {
+            stats.ops.entry(op.kind).or_default().in_flight += 1;
+        }
+        stats
+    }
+}
+
+impl StatsTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count the operations posted on `ep`.
+    pub fn endpoint<T: Transport>(&self, ep: T) -> TrackedEndpoint<T> {
+        let mut state = self.lock();
+        state.endpoints.push(EndpointStats::default());
+        TrackedEndpoint {
+            inner: ep,
+            tracker: self.clone(),
+            index: state.endpoints.len() - 1,
+        }
+    }
+
+    /// Count the completions read from `cq`.
+    pub fn cq<C: Cq>(&self, cq: C) -> TrackedCq<C> {
+        TrackedCq {
+            inner: cq,
+            tracker: self.clone(),
+        }
+    }
+
+    /// The operations of all of the endpoints.
+    pub fn stats(&self) -> EndpointStats {
+        let state = self.lock();
+        let mut all = EndpointStats::default();
+        for ep in 0..state.endpoints.len() {
+            all.merge(&state.stats(ep));
+        }
+        all
+    }
+
+    /// Zero the counts of all of the endpoints. Operations still pending are counted in
+    /// flight all the same, and completed once their completion is read.
+    pub fn reset(&self) {
+        let mut state = self.lock();
+        state.endpoints.fill_with(EndpointStats::default);
+    }
+
+    /// The operations in flight to or from the peer at `addr`, on all of the endpoints.
+    /// Receives from any peer are not counted.
+    pub fn in_flight_to(&self, addr: Addr) -> u64 {
+        let state = self.lock();
+        state
+            .pending
+            .values()
+            .flatten()
+            .filter(|op| op.peer == addr)
+            .count() as u64
+    }
+
+    // Run `f` unless operations are in flight to or from `peers`, failing with `FI_EBUSY`
+    // then. The tracker stays locked meanwhile, so that none is posted before `f` returns.
+    pub(crate) fn while_idle<R>(&self, peers: &[Addr], f: impl FnOnce() -> Result<R>) -> Result<R> {
+        let state = self.lock();
+        if state
+            .pending
+            .values()
+            .flatten()
+            .any(|op| peers.contains(&op.peer))
+        {
+            return Err(Error::fabric("fi_av_remove", ffi::FI_EBUSY as i64));
+        }
+        f()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.inner.lock().unwrap()
+    }
+}
+
+/// An endpoint whose operations are counted, made by [`StatsTracker::endpoint()`].
+#[derive(Clone)]
+pub struct TrackedEndpoint<T> {
+    inner: T,
+    tracker: StatsTracker,
+    index: usize,
+}
+
+impl<T> TrackedEndpoint<T> {
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// The operations of the endpoint since it was wrapped, or since the last reset.
+    pub fn stats(&self) -> EndpointStats {
+        self.tracker.lock().stats(self.index)
+    }
+
+    /// Zero the counts of the endpoint, but for its operations in flight.
+    pub fn reset(&self) {
+        self.tracker.lock().endpoints[self.index] = EndpointStats::default();
+    }
+
+    // Post an operation of `len` bytes, pending until its completion unless injected. The
+    // tracker stays locked meanwhile, so its completion cannot be read before it is pending.
+    fn count(
+        &self,
+        kind: OpKind,
+        len: usize,
+        peer: Addr,
+        context: Option<usize>,
+        post: impl FnOnce() -> Result<()>,
+    ) -> Result<()> {
+        let mut guard = self.tracker.lock();
+        let posted = post();
+        let state = &mut *guard;
+        let stats = &mut state.endpoints[self.index];
+        match &posted {
+            Err(err) if err.is_again() => stats.again += 1,
+            Err(_) => {}
+            Ok(()) => {
+                let counts = stats.ops.entry(kind).or_default();
+                counts.posted += 1;
+                if !matches!(kind, OpKind::Recv | OpKind::TRecv | OpKind::Read) {
+                    stats.bytes_sent += len as u64;
+                }
+                match context {
+                    Some(context) => state
+                        .pending
+                        .entry(context)
+                        .or_default()
+                        .push_back(Pending {
+                            ep: self.index,
+                            kind,
+                            len,
+                            peer,
+                        }),
+                    None => counts.completed += 1,
+                }
+            }
+        }
+        posted
+    }
+}
+
+impl<T: Transport> Transport for TrackedEndpoint<T> {
+    type Mr = T::Mr;
+
+    fn name(&self) -> Result<EndpointAddress> {
+        self.inner.name()
+    }
+
+    unsafe fn recv(
+        &self,
+        buf: &mut [u8],
+        mr: Option<&T::Mr>,
+        src: Addr,
+        context: usize,
+    ) -> Result<()> {
+        self.count(OpKind::Recv, buf.len(), src, Some(context), || unsafe {
+            self.inner.recv(buf, mr, src, context)
+        })
+    }
+
+    unsafe fn send(
+        &self,
+        buf: &[u8],
+        mr: Option<&T::Mr>,
+        dest: Addr,
+        context: usize,
+    ) -> Result<()> {
+        self.count(OpKind::Send, buf.len(), dest, Some(context), || unsafe {
+            self.inner.send(buf, mr, dest, context)
+        })
+    }
+
+    unsafe fn senddata(
+        &self,
+        buf: &[u8],
+        mr: Option<&T::Mr>,
+        data: u64,
+        dest: Addr,
+        context: usize,
+    ) -> Result<()> {
+        self.count(
+            OpKind::SendData,
+            buf.len(),
+            dest,
+            Some(context),
+            || unsafe { self.inner.senddata(buf, mr, data, dest, context) },
+        )
+    }
+
+    fn inject(&self, buf: &[u8], dest: Addr) -> Result<()> {
+        self.count(OpKind::Inject, buf.len(), dest, None, || {
+            self.inner.inject(buf, dest)
+        })
+    }
+
+    unsafe fn trecv(
+        &self,
+        buf: &mut [u8],
+        mr: Option<&T::Mr>,
+        src: Addr,
+        tag: u64,
+        ignore: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.count(OpKind::TRecv, buf.len(), src, Some(context), || unsafe {
+            self.inner.trecv(buf, mr, src, tag, ignore, context)
+        })
+    }
+
+    unsafe fn tsend(
+        &self,
+        buf: &[u8],
+        mr: Option<&T::Mr>,
+        dest: Addr,
+        tag: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.count(OpKind::TSend, buf.len(), dest, Some(context), || unsafe {
+            self.inner.tsend(buf, mr, dest, tag, context)
+        })
+    }
+
+    unsafe fn tsenddata(
+        &self,
+        buf: &[u8],
+        mr: Option<&T::Mr>,
+        data: u64,
+        dest: Addr,
+        tag: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.count(
+            OpKind::TSendData,
+            buf.len(),
+            dest,
+            Some(context),
+            || unsafe { self.inner.tsenddata(buf, mr, data, dest, tag, context) },
+        )
+    }
+
+    fn tinject(&self, buf: &[u8], dest: Addr, tag: u64) -> Result<()> {
+        self.count(OpKind::TInject, buf.len(), dest, None, || {
+            self.inner.tinject(buf, dest, tag)
+        })
+    }
+
+    unsafe fn read(
+        &self,
+        buf: &mut [u8],
+        mr: Option<&T::Mr>,
+        src: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.count(OpKind::Read, buf.len(), src, Some(context), || unsafe {
+            self.inner.read(buf, mr, src, addr, key, context)
+        })
+    }
+
+    unsafe fn write(
+        &self,
+        buf: &[u8],
+        mr: Option<&T::Mr>,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.count(OpKind::Write, buf.len(), dest, Some(context), || unsafe {
+            self.inner.write(buf, mr, dest, addr, key, context)
+        })
+    }
+
+    unsafe fn writedata(
+        &self,
+        buf: &[u8],
+        mr: Option<&T::Mr>,
+        data: u64,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.count(
+            OpKind::WriteData,
+            buf.len(),
+            dest,
+            Some(context),
+            || unsafe {
+                self.inner
+                    .writedata(buf, mr, data, dest, addr, key, context)
+            },
+        )
+    }
+}
+
+/// A completion queue whose completions are counted, made by [`StatsTracker::cq()`].
+#[derive(Clone)]
+pub struct TrackedCq<C> {
+    inner: C,
+    tracker: StatsTracker,
+}
+
+impl<C> TrackedCq<C> {
+    pub fn get_ref(&self) -> &C {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+
+    fn completed(&self, completions: &[Completion]) {
+        let mut state = self.tracker.lock();
+        for completion in completions {
+            state.complete(completion.context(), completion.len(), false);
+        }
+    }
+}
+
+impl<C: Cq> Cq for TrackedCq<C> {
+    fn read(&self, out: &mut [Completion]) -> Result<usize> {
+        let n = self.inner.read(out)?;
+        self.completed(&out[..n]);
+        Ok(n)
+    }
+
+    fn read_from(&self, out: &mut [Completion], src: &mut [Addr]) -> Result<usize> {
+        let n = self.inner.read_from(out, src)?;
+        self.completed(&out[..n]);
+        Ok(n)
+    }
+
+    fn read_err(&self) -> Result<Option<CqErrEntry>> {
+        let entry = self.inner.read_err()?;
+        if let Some(entry) = &entry {
+            self.tracker.lock().complete(entry.context, 0, true);
+        }
+        Ok(entry)
+    }
+}
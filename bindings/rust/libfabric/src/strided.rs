@@ -0,0 +1,279 @@
+use crate::error::{Error, Result};
+use crate::rma::RmaIov;
+use std::mem::size_of;
+
+/// A strided layout within a buffer: `count` blocks of `block` bytes, each `stride` bytes after
+/// the previous one, the first at `offset`. Ex: a column of a row-major matrix, or a field of
+/// an array of structs.
+///
+/// [`gather()`](Self::gather) and [`scatter()`](Self::scatter) turn a buffer of the layout into
+/// the buffers of vectored operations, such as [`Endpoint::sendv()`](crate::Endpoint::sendv)
+/// or [`Endpoint::readv()`](crate::Endpoint::readv), one per block, or into a packed staging
+/// copy when there are more blocks than the provider takes in one operation. Contiguous
+/// layouts, whose stride is their block, are a single buffer.
+///
+/// ```no_run
+/// # use libfabric::{Addr, Endpoint, InfoEntry};
+/// # fn run(ep: &Endpoint, entry: &InfoEntry, dest: Addr) -> libfabric::Result<()> {
+/// use libfabric::Strided;
+///
+/// // Column 3 of a 100 x 100 matrix of doubles.
+/// let matrix = vec![0u8; 100 * 100 * 8];
+/// let column = Strided::column::<f64>(100, 100, 3);
+/// let gather = column.gather(&matrix, entry.tx_attr().iov_limit)?;
+/// unsafe { ep.sendv(&gather.bufs(), &[], dest, 1) }?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[must_use]
+pub struct Strided {
+    offset: usize,
+    block: usize,
+    stride: usize,
+    count: usize,
+}
+
+impl Strided {
+    pub fn new(block: usize, stride: usize, count: usize) -> Self {
+        Strided {
+            offset: 0,
+            block,
+            stride,
+            count,
+        }
+    }
+
+    /// The layout starting `offset` bytes into the buffer.
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Column `col` of a row-major matrix of `rows` rows of `cols` elements of `T`.
+    pub fn column<T>(rows: usize, cols: usize, col: usize) -> Self {
+        let size = size_of::<T>();
+        Strided::new(size, cols * size, rows).offset(col * size)
+    }
+
+    /// The `rows` by `width` submatrix at `(row, col)` of a row-major matrix of `cols`
+    /// elements of `T` per row, a block per row.
+    pub fn submatrix<T>(
+        cols: usize,
+        (row, col): (usize, usize),
+        (rows, width): (usize, usize),
+    ) -> Self {
+        let size = size_of::<T>();
+        Strided::new(width * size, cols * size, rows).offset((row * cols + col) * size)
+    }
+
+    /// The field of `len` bytes at `offset` of `count` structs `S` laid out in an array, ex:
+    /// `Strided::field::<S>(offset_of!(S, x), size_of::<f32>(), n)`.
+    pub fn field<S>(offset: usize, len: usize, count: usize) -> Self {
+        Strided::new(len, size_of::<S>(), count).offset(offset)
+    }
+
+    /// The bytes of all the blocks.
+    pub fn len(&self) -> usize {
+        self.block * self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The bytes of buffer the layout spans, from its start.
+    pub fn extent(&self) -> usize {
+        match self.count {
+            0 => 0,
+            count => self.offset + (count - 1) * self.stride + self.block,
+        }
+    }
+
+    /// The buffers the layout takes in vectored operations: 1 when contiguous, one per block
+    /// otherwise.
+    pub fn blocks(&self) -> usize {
+        match self.contiguous() {
+            true => self.count.min(1),
+            false => self.count,
+        }
+    }
+
+    /// The blocks of `buf`.
+    pub fn slices<'a>(&self, buf: &'a [u8]) -> Result<Vec<&'a [u8]>> {
+        self.check(buf.len())?;
+        Ok(self.ranges().map(|range| &buf[range]).collect())
+    }
+
+    /// The blocks of `buf`, mutably.
+    pub fn slices_mut<'a>(&self, buf: &'a mut [u8]) -> Result<Vec<&'a mut [u8]>> {
+        self.check(buf.len())?;
+        let mut slices = Vec::with_capacity(self.blocks());
+        let mut rest = buf;
+        let mut start = 0;
+        for range in self.ranges() {
+            let (_, tail) = std::mem::take(&mut rest).split_at_mut(range.start - start);
+            let (slice, tail) = tail.split_at_mut(range.len());
+            slices.push(slice);
+            (rest, start) = (tail, range.end);
+        }
+        Ok(slices)
+    }
+
+    /// The blocks of `buf`, one after the other.
+    pub fn pack(&self, buf: &[u8]) -> Result<Vec<u8>> {
+        Ok(self.slices(buf)?.concat())
+    }
+
+    /// Copy `packed`, the blocks one after the other, into the blocks of `buf`.
+    pub fn unpack(&self, packed: &[u8], buf: &mut [u8]) -> Result<()> {
+        if packed.len() != self.len() {
+            return Err(Error::invalid(format!(
+                "{} bytes packed for a layout of {}",
+                packed.len(),
+                self.len()
+            )));
+        }
+        let mut packed = packed;
+        for slice in self.slices_mut(buf)? {
+            let (chunk, rest) = packed.split_at(slice.len());
+            slice.copy_from_slice(chunk);
+            packed = rest;
+        }
+        Ok(())
+    }
+
+    /// The segments of the layout in remote memory at `addr`, registered with `key`, ex: for
+    /// [`Endpoint::write_split()`](crate::Endpoint::write_split), which posts as many
+    /// operations as the segment limits of the provider need.
+    pub fn rma_iov(&self, addr: u64, key: u64) -> Vec<RmaIov> {
+        self.ranges()
+            .map(|range| RmaIov {
+                addr: addr + range.start as u64,
+                len: range.len(),
+                key,
+            })
+            .collect()
+    }
+
+    /// The buffers to send or write the layout of `buf` from: its blocks when there are at
+    /// most `iov_limit`, ex: the [`TxAttr::iov_limit`](crate::TxAttr::iov_limit) of the
+    /// endpoint, or a packed copy of them otherwise.
+    pub fn gather<'a>(&self, buf: &'a [u8], iov_limit: usize) -> Result<Gather<'a>> {
+        match self.blocks() <= iov_limit.max(1) {
+            true => Ok(Gather::Blocks(self.slices(buf)?)),
+            false => Ok(Gather::Packed(self.pack(buf)?)),
+        }
+    }
+
+    /// The buffers to receive or read the layout of `buf` into: its blocks when there are at
+    /// most `iov_limit`, ex: the [`RxAttr::iov_limit`](crate::RxAttr::iov_limit) of the
+    /// endpoint, or a staging buffer otherwise, which [`Scatter::unpack()`] copies into them
+    /// once the operation completed.
+    pub fn scatter<'a>(&self, buf: &'a mut [u8], iov_limit: usize) -> Result<Scatter<'a>> {
+        let bufs = match self.blocks() <= iov_limit.max(1) {
+            true => ScatterBufs::Blocks(self.slices_mut(buf)?),
+            false => {
+                self.check(buf.len())?;
+                ScatterBufs::Staged {
+                    buf,
+                    staging: vec![0; self.len()],
+                }
+            }
+        };
+        Ok(Scatter {
+            layout: *self,
+            bufs,
+        })
+    }
+
+    fn contiguous(&self) -> bool {
+        self.stride == self.block || self.count <= 1
+    }
+
+    // The byte ranges of the blocks, merged into one for contiguous layouts.
+    fn ranges(&self) -> impl Iterator<Item = std::ops::Range<usize>> + '_ {
+        let (block, count) = match self.contiguous() {
+            true => (self.len(), self.count.min(1)),
+            false => (self.block, self.count),
+        };
+        (0..count).map(move |i| {
+            let start = self.offset + i * self.stride;
+            start..start + block
+        })
+    }
+
+    fn check(&self, len: usize) -> Result<()> {
+        if self.count > 1 && self.stride < self.block {
+            return Err(Error::invalid(format!(
+                "blocks of {} bytes overlap at a stride of {}",
+                self.block, self.stride
+            )));
+        }
+        if self.extent() > len {
+            return Err(Error::invalid(format!(
+                "layout of {} bytes over a buffer of {len}",
+                self.extent()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// The buffers a strided layout is sent or written from, see [`Strided::gather()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Gather<'a> {
+    /// The blocks, in the buffer.
+    Blocks(Vec<&'a [u8]>),
+    /// A copy of the blocks, one after the other.
+    Packed(Vec<u8>),
+}
+
+impl Gather<'_> {
+    /// The buffers of the vectored operation.
+    pub fn bufs(&self) -> Vec<&[u8]> {
+        match self {
+            Gather::Blocks(blocks) => blocks.clone(),
+            Gather::Packed(packed) => vec![packed],
+        }
+    }
+
+    /// Whether the blocks were copied, into memory no region covers.
+    pub fn is_packed(&self) -> bool {
+        matches!(self, Gather::Packed(_))
+    }
+}
+
+/// The buffers a strided layout is received or read into, see [`Strided::scatter()`].
+pub struct Scatter<'a> {
+    layout: Strided,
+    bufs: ScatterBufs<'a>,
+}
+
+enum ScatterBufs<'a> {
+    Blocks(Vec<&'a mut [u8]>),
+    Staged { buf: &'a mut [u8], staging: Vec<u8> },
+}
+
+impl Scatter<'_> {
+    /// The buffers of the vectored operation, which stay in use until it completed.
+    pub fn bufs(&mut self) -> Vec<&mut [u8]> {
+        match &mut self.bufs {
+            ScatterBufs::Blocks(blocks) => blocks.iter_mut().map(|block| &mut **block).collect(),
+            ScatterBufs::Staged { staging, .. } => vec![staging],
+        }
+    }
+
+    /// Whether the blocks are received into a staging buffer, which no region covers.
+    pub fn is_staged(&self) -> bool {
+        matches!(self.bufs, ScatterBufs::Staged { .. })
+    }
+
+    /// Once the operation completed, copy the staging buffer into the blocks, if any.
+    pub fn unpack(self) -> Result<()> {
+        match self.bufs {
+            ScatterBufs::Blocks(_) => Ok(()),
+            ScatterBufs::Staged { buf, staging } => self.layout.unpack(&staging, buf),
+        }
+    }
+}
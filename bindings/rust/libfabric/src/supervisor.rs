@@ -0,0 +1,410 @@
+use crate::av::Addr;
+use crate::cq::{Completion, CompletionQueue, CqAttr};
+use crate::domain::Domain;
+use crate::ep::Endpoint;
+use crate::eq::{EqAttr, EqEvent, EventQueue};
+use crate::error::{Error, Result};
+use crate::fabric::Fabric;
+use crate::flags::BindFlags;
+use crate::info::Info;
+use ofi_libfabric_sys::bindgen as ffi;
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// What a [`Supervisor`] does with the operations pending when its connection is lost.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PendingOps {
+    /// Post them again once reconnected, along with those issued in the meantime. Sends whose
+    /// completion was lost are then delivered at least once, rather than exactly once.
+    #[default]
+    Replay,
+    /// Fail them with the error that broke the connection, and fail new operations until
+    /// reconnected.
+    Fail,
+}
+
+/// How a [`Supervisor`] reconnects.
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct ReconnectPolicy {
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    max_attempts: Option<u32>,
+    pending: PendingOps,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            max_attempts: None,
+            pending: PendingOps::Replay,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Retry forever, waiting from 100ms up to 10s between attempts, replaying operations.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wait `initial` after the first failed attempt, then twice as long after each of the
+    /// next ones, up to `max`.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Give up after `attempts` consecutive failed attempts.
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = Some(attempts);
+        self
+    }
+
+    pub fn pending(mut self, pending: PendingOps) -> Self {
+        self.pending = pending;
+        self
+    }
+
+    fn delay(&self, attempts: u32) -> Duration {
+        let factor = 1u32
+            .checked_shl(attempts.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+/// What happened to a [`Supervisor`], as returned by [`Supervisor::poll()`].
+#[derive(Debug)]
+pub enum SupervisorEvent {
+    /// The send of the given id completed.
+    Sent(u64),
+    /// The receive of the given id completed, with the message received.
+    Received(u64, Vec<u8>),
+    /// The operation of the given id failed.
+    Failed(u64, Error),
+    /// The connection was established for the first time.
+    Connected,
+    /// The connection was lost, and is being reestablished.
+    Disconnected(Error),
+    /// The connection was reestablished, after the given number of failed attempts.
+    Reconnected { attempts: u32 },
+    /// The last attempt allowed by the policy failed, with the given error. Operations fail
+    /// from now on.
+    GaveUp(Error),
+}
+
+/// A MSG endpoint which reconnects on its own.
+///
+/// The supervisor resolves its hints, whose node and service name the server, connects, and
+/// watches for `FI_SHUTDOWN` and connection errors. When the connection is lost, it resolves
+/// the hints again and reconnects on a new endpoint, waiting longer after each failed attempt
+/// as set by its [`ReconnectPolicy`], which also decides what becomes of the pending
+/// operations.
+///
+/// Operations are issued with owned buffers, kept until they complete or fail, so that they
+/// can be posted again on the new endpoint. No memory descriptors are passed, which rules out
+/// providers requiring [`MrMode::LOCAL`](crate::MrMode::LOCAL). Nothing happens but from
+/// [`poll()`](Self::poll), which must be called until the operations of interest complete.
+pub struct Supervisor {
+    hints: Info,
+    policy: ReconnectPolicy,
+    state: State,
+    link: Option<Link>,
+    // Operations which did not complete yet, by id, posted on the current link or not.
+    ops: BTreeMap<u64, Op>,
+    next_id: u64,
+    events: VecDeque<SupervisorEvent>,
+    // Whether a connection was ever established, to tell reconnections apart.
+    connected_once: bool,
+}
+
+enum State {
+    // Waiting for FI_CONNECTED on the link, after `attempts` failed attempts.
+    Connecting { attempts: u32 },
+    Connected,
+    // Waiting before the next attempt.
+    Backoff { attempts: u32, until: Instant },
+    Failed(Error),
+}
+
+// The objects of one connection. The endpoint is closed first, so the buffers of its
+// operations are no longer used once the link is dropped.
+struct Link {
+    ep: Endpoint,
+    cq: CompletionQueue,
+    eq: EventQueue,
+}
+
+struct Op {
+    send: bool,
+    buf: Vec<u8>,
+    posted: bool,
+}
+
+impl Supervisor {
+    /// Start connecting to the server `hints` resolve to.
+    pub fn connect(hints: Info, policy: ReconnectPolicy) -> Self {
+        let mut supervisor = Supervisor {
+            hints,
+            policy,
+            state: State::Backoff {
+                attempts: 0,
+                until: Instant::now(),
+            },
+            link: None,
+            ops: BTreeMap::new(),
+            next_id: 0,
+            events: VecDeque::new(),
+            connected_once: false,
+        };
+        supervisor.attempt(0);
+        supervisor
+    }
+
+    pub fn is_connected(&self) -> bool {
+        matches!(self.state, State::Connected)
+    }
+
+    /// Send `buf`, returning the id its completion is reported with.
+    pub fn send(&mut self, buf: Vec<u8>) -> Result<u64> {
+        self.issue(true, buf)
+    }
+
+    /// Receive a message of up to `len` bytes, returning the id its completion is reported
+    /// with.
+    pub fn recv(&mut self, len: usize) -> Result<u64> {
+        self.issue(false, vec![0; len])
+    }
+
+    /// Make progress, returning the next event if any.
+    pub fn poll(&mut self) -> Option<SupervisorEvent> {
+        if self.events.is_empty() {
+            self.step();
+        }
+        self.events.pop_front()
+    }
+
+    fn issue(&mut self, send: bool, buf: Vec<u8>) -> Result<u64> {
+        match &self.state {
+            State::Failed(err) => return Err(err.clone()),
+            State::Connected => {}
+            _ if self.policy.pending == PendingOps::Fail => {
+                return Err(Error::fabric("supervisor", ffi::FI_ENOTCONN as i64));
+            }
+            _ => {}
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.ops.insert(
+            id,
+            Op {
+                send,
+                buf,
+                posted: false,
+            },
+        );
+        if self.is_connected()
+            && let Err(err) = self.post_pending()
+        {
+            self.disconnected(err);
+        }
+        Ok(id)
+    }
+
+    fn step(&mut self) {
+        match self.state {
+            State::Backoff { attempts, until } if Instant::now() >= until => self.attempt(attempts),
+            State::Connecting { .. } | State::Connected => {
+                if let Err(err) = self.progress() {
+                    self.disconnected(err);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Open a new link, after `attempts` failed attempts.
+    fn attempt(&mut self, attempts: u32) {
+        match Link::open(&self.hints) {
+            Ok(link) => {
+                self.link = Some(link);
+                self.state = State::Connecting { attempts };
+            }
+            Err(err) => self.retry(attempts + 1, err),
+        }
+    }
+
+    fn retry(&mut self, attempts: u32, err: Error) {
+        if self.policy.max_attempts.is_some_and(|max| attempts >= max) {
+            self.state = State::Failed(err.clone());
+            self.fail_all(&err);
+            self.events.push_back(SupervisorEvent::GaveUp(err));
+            return;
+        }
+        self.state = State::Backoff {
+            attempts,
+            until: Instant::now() + self.policy.delay(attempts),
+        };
+    }
+
+    // Drop the link, then fail or keep its operations for the next one.
+    fn disconnected(&mut self, err: Error) {
+        self.link = None;
+        let attempts = match self.state {
+            State::Connecting { attempts } => attempts + 1,
+            _ => {
+                self.events
+                    .push_back(SupervisorEvent::Disconnected(err.clone()));
+                1
+            }
+        };
+        match self.policy.pending {
+            PendingOps::Replay => self.ops.values_mut().for_each(|op| op.posted = false),
+            PendingOps::Fail => self.fail_all(&err),
+        }
+        self.retry(attempts, err);
+    }
+
+    fn fail_all(&mut self, err: &Error) {
+        for id in std::mem::take(&mut self.ops).into_keys() {
+            self.events
+                .push_back(SupervisorEvent::Failed(id, err.clone()));
+        }
+    }
+
+    // Post the operations not posted on the current link yet, in order, until the endpoint is
+    // out of resources.
+    fn post_pending(&mut self) -> Result<()> {
+        let Some(link) = &self.link else {
+            return Ok(());
+        };
+        for (&id, op) in self.ops.iter_mut().filter(|(_, op)| !op.posted) {
+            // SAFETY: the buffer stays in place, and untouched, until the operation completes,
+            // or the link is dropped, closing the endpoint.
+            let ret = unsafe {
+                match op.send {
+                    true => link.ep.send(&op.buf, None, Addr::UNSPEC, id as usize),
+                    false => link.ep.recv(&mut op.buf, None, Addr::UNSPEC, id as usize),
+                }
+            };
+            match ret {
+                Ok(()) => op.posted = true,
+                Err(err) if err.is_again() => break,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    // Read the events and completions of the link, failing with connection errors.
+    fn progress(&mut self) -> Result<()> {
+        let Some(link) = &self.link else {
+            return Ok(());
+        };
+        match link.eq.read() {
+            Ok(Some(EqEvent::Connected { .. })) => {
+                let event = match self.state {
+                    State::Connecting { attempts } if self.connected_once => {
+                        SupervisorEvent::Reconnected { attempts }
+                    }
+                    _ => SupervisorEvent::Connected,
+                };
+                self.state = State::Connected;
+                self.connected_once = true;
+                self.events.push_back(event);
+            }
+            Ok(Some(EqEvent::Shutdown { .. })) => {
+                return Err(Error::fabric("fi_eq_read", ffi::FI_ECONNRESET as i64));
+            }
+            Ok(_) => {}
+            Err(err) if err.is_avail() => {
+                return Err(link.eq.read_err()?.map_or(err, |entry| entry.error));
+            }
+            Err(err) => return Err(err),
+        }
+        if !self.is_connected() {
+            return Ok(());
+        }
+
+        let link = self.link.as_ref().unwrap();
+        let mut completions = [Completion::default(); 8];
+        match link.cq.read(&mut completions) {
+            Ok(n) => {
+                for completion in &completions[..n] {
+                    let id = completion.context() as u64;
+                    let Some(op) = self.ops.remove(&id) else {
+                        continue;
+                    };
+                    self.events.push_back(match op.send {
+                        true => SupervisorEvent::Sent(id),
+                        false => {
+                            let mut buf = op.buf;
+                            buf.truncate(completion.len());
+                            SupervisorEvent::Received(id, buf)
+                        }
+                    });
+                }
+            }
+            Err(err) if err.is_again() => {}
+            Err(err) if err.is_avail() => {
+                let Some(entry) = link.cq.read_err()? else {
+                    return Ok(());
+                };
+                if is_connection_error(entry.error.code()) {
+                    return Err(entry.error);
+                }
+                let id = entry.context as u64;
+                if self.ops.remove(&id).is_some() {
+                    self.events
+                        .push_back(SupervisorEvent::Failed(id, entry.error));
+                }
+            }
+            Err(err) => return Err(err),
+        }
+        self.post_pending()
+    }
+}
+
+impl Link {
+    fn open(hints: &Info) -> Result<Self> {
+        let entries = hints.get()?;
+        let entry = entries
+            .first()
+            .ok_or_else(|| Error::fabric("fi_getinfo", ffi::FI_ENODATA as i64))?;
+        let dest = entry
+            .dest_addr()
+            .ok_or_else(|| Error::invalid("the hints do not resolve to a server address"))?;
+        let fabric = Fabric::open(entry)?;
+        let eq = fabric.eq(&EqAttr::new())?;
+        let domain = Domain::open(&fabric, entry)?;
+        let cq = domain.cq(&CqAttr::new())?;
+        let ep = domain
+            .endpoint(entry)?
+            .bind_cq(&cq, BindFlags::TRANSMIT | BindFlags::RECV)?
+            .bind_eq(&eq)?
+            .enable()?;
+        ep.connect(&dest, &[])?;
+        Ok(Link { ep, cq, eq })
+    }
+}
+
+// Errors reported for the operations of a broken connection.
+fn is_connection_error(code: i32) -> bool {
+    [
+        ffi::FI_ECONNRESET,
+        ffi::FI_ECONNABORTED,
+        ffi::FI_ECONNREFUSED,
+        ffi::FI_ENOTCONN,
+        ffi::FI_EHOSTUNREACH,
+        ffi::FI_ECANCELED,
+        ffi::FI_EIO,
+    ]
+    .iter()
+    .any(|&err| err as i32 == code)
+}
@@ -0,0 +1,291 @@
+use crate::bootstrap::{Bootstrap, RemoteRegion};
+use crate::domain::Domain;
+use crate::error::{Error, Result};
+use crate::flags::{Access, MrMode};
+use crate::mr::{MemoryRegion, MrAttr};
+use crate::threading::{ThreadSafe, ThreadingModel};
+use ofi_libfabric_sys::bindgen as ffi;
+use std::alloc::{self, Layout};
+use std::collections::BTreeMap;
+use std::ffi::c_void;
+use std::ptr::NonNull;
+
+/// Attributes of a [`SymmetricHeap`], which must be the same on all members.
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct SymmetricHeapAttr {
+    size: usize,
+    key: u64,
+    access: Access,
+}
+
+impl Default for SymmetricHeapAttr {
+    fn default() -> Self {
+        SymmetricHeapAttr {
+            size: 64 << 20,
+            key: 0x5348,
+            access: Access::READ | Access::WRITE | Access::REMOTE_READ | Access::REMOTE_WRITE,
+        }
+    }
+}
+
+impl SymmetricHeapAttr {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bytes of the heap, 64 MiB by default, rounded up to whole pages.
+    pub fn size(mut self, size: usize) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// The key the heap is registered with on every member, `0x5348` by default, which no
+    /// other region of the domain may request. Unused on domains requiring
+    /// [`MrMode::PROV_KEY`], whose keys are exchanged instead.
+    pub fn key(mut self, key: u64) -> Self {
+        self.key = key;
+        self
+    }
+
+    /// The access rights of the heap, local and remote reads and writes by default.
+    pub fn access(mut self, access: Access) -> Self {
+        self.access = access;
+        self
+    }
+}
+
+/// An allocation of a [`SymmetricHeap`], at the same offset of the heap of every member.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SymmetricAlloc {
+    offset: usize,
+    len: usize,
+}
+
+impl SymmetricAlloc {
+    /// The offset of the allocation in the heap.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+// The memory of the heap, freed once the region is closed.
+struct Backing {
+    ptr: NonNull<u8>,
+    layout: Layout,
+}
+
+impl Drop for Backing {
+    fn drop(&mut self) {
+        // SAFETY: Allocated with this layout by `SymmetricHeap::new()`.
+        unsafe { alloc::dealloc(self.ptr.as_ptr(), self.layout) };
+    }
+}
+
+/// A heap registered by every member of a job, as the symmetric heap of OpenSHMEM: members
+/// allocate from it in the same order, so every allocation lands at the same offset on each
+/// of them, and its remote address on any member is computed locally, with no exchange of
+/// descriptors per allocation.
+///
+/// Creating the heap is collective: every member registers a heap of the same size, with the
+/// same [key](SymmetricHeapAttr::key) unless the domain requires [`MrMode::PROV_KEY`], and
+/// the base address and key of each member are exchanged once, through a [`Bootstrap`]. The
+/// remote address of an allocation is then its offset, added to the base of the member on
+/// domains with [`MrMode::VIRT_ADDR`]. [`alloc()`](Self::alloc) and [`free()`](Self::free)
+/// are not collective, but must be called with the same arguments in the same order on all
+/// members, which they do not check.
+///
+/// Domains requiring [`MrMode::ENDPOINT`] are not supported.
+///
+/// ```no_run
+/// use libfabric::bootstrap::Bootstrap;
+/// use libfabric::{Domain, Endpoint, SymmetricHeap, SymmetricHeapAttr};
+///
+/// # fn run(domain: Domain, ep: Endpoint, job: &mut impl Bootstrap, peers: &[libfabric::Addr]) -> libfabric::Result<()> {
+/// let mut heap = SymmetricHeap::new(&domain, job, &SymmetricHeapAttr::new())?;
+/// let counts = heap.alloc(8 * job.size(), 8)?;
+/// let next = (job.rank() + 1) % job.size();
+/// let target = heap.remote(counts, next);
+/// let value = (job.rank() as u64).to_ne_bytes();
+/// unsafe { ep.write(&value, None, peers[next], target.addr + 8 * job.rank() as u64, target.key, 0)? };
+/// # Ok(())
+/// # }
+/// ```
+pub struct SymmetricHeap<M: ThreadingModel = ThreadSafe> {
+    // Declared first, so that the region is closed before its memory is freed.
+    mr: MemoryRegion<M>,
+    backing: Backing,
+    rank: usize,
+    // The heap of each member, by rank.
+    peers: Vec<RemoteRegion>,
+    // The free ranges of the heap, by offset.
+    free: BTreeMap<usize, usize>,
+}
+
+// SAFETY: The memory of the heap is only read and written by the application, through
+// pointers it is handed, and by the provider.
+unsafe impl<M: ThreadingModel> Send for SymmetricHeap<M> where MemoryRegion<M>: Send {}
+unsafe impl<M: ThreadingModel> Sync for SymmetricHeap<M> where MemoryRegion<M>: Sync {}
+
+impl<M: ThreadingModel> SymmetricHeap<M> {
+    /// Allocate and register the heap of this member in `domain`, then exchange it with the
+    /// other members of `job`, which must all call it. Fails with an invalid argument error if
+    /// the members registered heaps of different sizes.
+    pub fn new(
+        domain: &Domain<M>,
+        job: &mut impl Bootstrap,
+        attr: &SymmetricHeapAttr,
+    ) -> Result<Self> {
+        let page = 4096;
+        let size = attr.size.max(1).div_ceil(page) * page;
+        let layout =
+            Layout::from_size_align(size, page).map_err(|err| Error::invalid(err.to_string()))?;
+        // SAFETY: The layout has a non-zero size.
+        let ptr = NonNull::new(unsafe { alloc::alloc_zeroed(layout) })
+            .ok_or_else(|| Error::fabric("symmetric heap", ffi::FI_ENOMEM as i64))?;
+        let backing = Backing { ptr, layout };
+        let mode = domain.info().mr_mode();
+        let mr = match mode.contains(MrMode::PROV_KEY) {
+            true => unsafe { domain.register(ptr.as_ptr(), size, attr.access) },
+            false => {
+                let mr_attr = MrAttr::new(attr.access).requested_key(attr.key);
+                unsafe { domain.register_with(ptr.as_ptr(), size, &mr_attr) }
+            }
+        }?;
+        let local = RemoteRegion {
+            addr: if mode.contains(MrMode::VIRT_ADDR) {
+                ptr.as_ptr() as u64
+            } else {
+                0
+            },
+            len: size as u64,
+            key: mr.key(),
+        };
+        let mut buf = Vec::with_capacity(24);
+        for field in [local.addr, local.len, local.key] {
+            buf.extend(field.to_le_bytes());
+        }
+        let peers = job
+            .allgather(&buf)?
+            .iter()
+            .map(|buf| decode(buf, size))
+            .collect::<Result<_>>()?;
+        Ok(SymmetricHeap {
+            mr,
+            backing,
+            rank: job.rank(),
+            peers,
+            free: BTreeMap::from([(0, size)]),
+        })
+    }
+
+    /// The rank of this member.
+    pub fn rank(&self) -> usize {
+        self.rank
+    }
+
+    /// The members sharing the heap.
+    pub fn members(&self) -> usize {
+        self.peers.len()
+    }
+
+    /// Bytes of the heap.
+    pub fn capacity(&self) -> usize {
+        self.backing.layout.size()
+    }
+
+    /// Allocate `len` bytes aligned to `align`, a power of two, from the first free range
+    /// they fit in. Fails with `FI_ENOMEM` when none is large enough.
+    pub fn alloc(&mut self, len: usize, align: usize) -> Result<SymmetricAlloc> {
+        if !align.is_power_of_two() {
+            return Err(Error::invalid(format!(
+                "alignment {align} not a power of two"
+            )));
+        }
+        let len = len.max(1);
+        let (start, end, offset) = self
+            .free
+            .iter()
+            .map(|(&start, &size)| (start, start + size, start.next_multiple_of(align)))
+            .find(|&(_, end, offset)| offset + len <= end)
+            .ok_or_else(|| Error::fabric("symmetric heap alloc", ffi::FI_ENOMEM as i64))?;
+        self.free.remove(&start);
+        if offset > start {
+            self.free.insert(start, offset - start);
+        }
+        if offset + len < end {
+            self.free.insert(offset + len, end - offset - len);
+        }
+        Ok(SymmetricAlloc { offset, len })
+    }
+
+    /// Return `alloc` to the heap, merging it with the free ranges around it.
+    pub fn free(&mut self, alloc: SymmetricAlloc) {
+        let (mut start, mut end) = (alloc.offset, alloc.offset + alloc.len);
+        if let Some((&before, &size)) = self.free.range(..start).next_back()
+            && before + size == start
+        {
+            self.free.remove(&before);
+            start = before;
+        }
+        if let Some(size) = self.free.remove(&end) {
+            end += size;
+        }
+        self.free.insert(start, end - start);
+    }
+
+    /// The local memory of `alloc`, which peers may read and write at any time.
+    pub fn ptr(&self, alloc: SymmetricAlloc) -> *mut u8 {
+        // SAFETY: Allocations are within the heap.
+        unsafe { self.backing.ptr.as_ptr().add(alloc.offset) }
+    }
+
+    /// The address and key RMA and atomic operations target `alloc` on the member `rank`
+    /// with.
+    ///
+    /// # Panics
+    ///
+    /// If `rank` is not that of a member.
+    pub fn remote(&self, alloc: SymmetricAlloc, rank: usize) -> RemoteRegion {
+        let heap = &self.peers[rank];
+        RemoteRegion {
+            addr: heap.addr + alloc.offset as u64,
+            len: alloc.len as u64,
+            key: heap.key,
+        }
+    }
+
+    /// The local descriptor of the heap, passed along with buffers of it in data transfers.
+    pub fn desc(&self) -> *mut c_void {
+        self.mr.desc()
+    }
+
+    /// The key of the heap of this member.
+    pub fn key(&self) -> u64 {
+        self.mr.key()
+    }
+}
+
+fn decode(buf: &[u8], size: usize) -> Result<RemoteRegion> {
+    let field = |i: usize| {
+        buf.get(8 * i..8 * i + 8)
+            .map(|field| u64::from_le_bytes(field.try_into().unwrap()))
+    };
+    let (Some(addr), Some(len), Some(key)) = (field(0), field(1), field(2)) else {
+        return Err(Error::invalid("truncated symmetric heap"));
+    };
+    if len != size as u64 {
+        return Err(Error::invalid(format!(
+            "symmetric heap of {len} bytes on a member, {size} bytes here"
+        )));
+    }
+    Ok(RemoteRegion { addr, len, key })
+}
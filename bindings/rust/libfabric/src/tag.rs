@@ -0,0 +1,238 @@
+use std::fmt;
+
+/// A field of a [`TagSpace`], some contiguous bits of the tags of messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TagField {
+    shift: u32,
+    width: u32,
+}
+
+impl TagField {
+    /// The lowest bit of the field.
+    pub const fn shift(self) -> u32 {
+        self.shift
+    }
+
+    /// The number of bits of the field.
+    pub const fn width(self) -> u32 {
+        self.width
+    }
+
+    /// The bits of the field, in place.
+    pub const fn mask(self) -> u64 {
+        self.max() << self.shift
+    }
+
+    /// The largest value the field holds.
+    pub const fn max(self) -> u64 {
+        u64::MAX >> (64 - self.width)
+    }
+
+    /// `value` in place in the field, if it fits.
+    pub const fn try_encode(self, value: u64) -> Option<u64> {
+        match value <= self.max() {
+            true => Some(value << self.shift),
+            false => None,
+        }
+    }
+
+    /// `value` in place in the field.
+    ///
+    /// # Panics
+    ///
+    /// When `value` does not fit, as arithmetic overflows do, silently truncating it being
+    /// how messages end up matching the wrong receives.
+    pub const fn encode(self, value: u64) -> u64 {
+        match self.try_encode(value) {
+            Some(bits) => bits,
+            None => panic!("the value overflows its tag field"),
+        }
+    }
+
+    /// The value of the field in `tag`.
+    pub const fn get(self, tag: u64) -> u64 {
+        (tag >> self.shift) & self.max()
+    }
+
+    /// `tag` with the field set to `value`.
+    ///
+    /// # Panics
+    ///
+    /// Like [`encode()`](Self::encode).
+    pub const fn set(self, tag: u64, value: u64) -> u64 {
+        (tag & !self.mask()) | self.encode(value)
+    }
+}
+
+/// The 64 bit tag of messages partitioned into fields, such as the peer, channel and sequence
+/// number of a message, from the least significant bits up.
+///
+/// The widths are checked when the space is built, which for `const` spaces is at compile
+/// time: fields of no bits, or more than 64 bits in all, fail to compile. Bits above the
+/// fields are left clear, for protocols using the top bits for themselves, ex: the responses of
+/// `libfabric::rpc`.
+///
+/// ```
+/// use libfabric::{TagField, TagSpace};
+///
+/// // Peer id, channel and sequence number.
+/// const TAGS: TagSpace<3> = TagSpace::new([20, 4, 32]);
+/// const PEER: TagField = TAGS.field(0);
+/// const SEQ: TagField = TAGS.field(2);
+///
+/// let tag = TAGS.tag([7, 1, 1234]);
+/// assert_eq!(PEER.get(tag), 7);
+/// assert_eq!(TAGS.fields(tag), [7, 1, 1234]);
+///
+/// // Receive message 1234 of peer 7, on any channel.
+/// let recv = TAGS.matching().field(PEER, 7).field(SEQ, 1234);
+/// assert!(recv.matches(tag));
+/// assert!(!recv.matches(PEER.set(tag, 8)));
+/// ```
+///
+/// A width too large is rejected by the compiler:
+///
+/// ```compile_fail
+/// const TAGS: libfabric::TagSpace<2> = libfabric::TagSpace::new([32, 40]);
+/// let _ = TAGS.tag([0, 0]);
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TagSpace<const N: usize> {
+    fields: [TagField; N],
+}
+
+impl<const N: usize> TagSpace<N> {
+    /// Fields of `widths` bits, the first one starting at bit 0.
+    ///
+    /// # Panics
+    ///
+    /// When a width is 0, or the widths add up to more than 64 bits.
+    pub const fn new(widths: [u32; N]) -> Self {
+        let mut fields = [TagField { shift: 0, width: 0 }; N];
+        let mut shift = 0;
+        let mut i = 0;
+        while i < N {
+            let width = widths[i];
+            assert!(width > 0, "tag fields have at least one bit");
+            assert!(
+                width <= 64 - shift,
+                "tag fields add up to more than 64 bits"
+            );
+            fields[i] = TagField { shift, width };
+            shift += width;
+            i += 1;
+        }
+        TagSpace { fields }
+    }
+
+    /// The field at `index`, in the order of the widths.
+    pub const fn field(&self, index: usize) -> TagField {
+        self.fields[index]
+    }
+
+    /// The bits taken by the fields.
+    pub const fn bits(&self) -> u32 {
+        match N {
+            0 => 0,
+            _ => self.fields[N - 1].shift + self.fields[N - 1].width,
+        }
+    }
+
+    /// The bits of all the fields, in place.
+    pub const fn mask(&self) -> u64 {
+        match self.bits() {
+            0 => 0,
+            bits => u64::MAX >> (64 - bits),
+        }
+    }
+
+    /// The tag whose fields hold `values`.
+    ///
+    /// # Panics
+    ///
+    /// When a value does not fit its field.
+    pub const fn tag(&self, values: [u64; N]) -> u64 {
+        let mut tag = 0;
+        let mut i = 0;
+        while i < N {
+            tag |= self.fields[i].encode(values[i]);
+            i += 1;
+        }
+        tag
+    }
+
+    /// The values of the fields of `tag`.
+    pub const fn fields(&self, tag: u64) -> [u64; N] {
+        let mut values = [0; N];
+        let mut i = 0;
+        while i < N {
+            values[i] = self.fields[i].get(tag);
+            i += 1;
+        }
+        values
+    }
+
+    /// A receive matching any tag, to narrow down by field.
+    pub const fn matching(&self) -> TagMatch {
+        TagMatch {
+            tag: 0,
+            ignore: u64::MAX,
+        }
+    }
+}
+
+impl<const N: usize> fmt::Debug for TagSpace<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.fields.iter().map(|field| field.width))
+            .finish()
+    }
+}
+
+/// The tag and ignored bits of a tagged receive, as
+/// [`Endpoint::trecv()`](crate::Endpoint::trecv) takes them, matching the fields given values
+/// and ignoring the others.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[must_use]
+pub struct TagMatch {
+    tag: u64,
+    ignore: u64,
+}
+
+impl TagMatch {
+    /// Only match tags whose `field` holds `value`.
+    ///
+    /// # Panics
+    ///
+    /// When `value` does not fit the field.
+    pub const fn field(self, field: TagField, value: u64) -> Self {
+        TagMatch {
+            tag: field.set(self.tag, value),
+            ignore: self.ignore & !field.mask(),
+        }
+    }
+
+    /// Only match tags whose bits outside of the fields are clear, where a protocol keeps
+    /// flags of its own.
+    pub const fn exact<const N: usize>(self, space: &TagSpace<N>) -> Self {
+        TagMatch {
+            tag: self.tag,
+            ignore: self.ignore & space.mask(),
+        }
+    }
+
+    /// The tag to pass a receive, the values of the fields matched.
+    pub const fn tag(self) -> u64 {
+        self.tag
+    }
+
+    /// The ignored bits to pass a receive, those of the fields not matched.
+    pub const fn ignore(self) -> u64 {
+        self.ignore
+    }
+
+    /// Whether a message tagged `tag` matches, as providers match them.
+    pub const fn matches(self, tag: u64) -> bool {
+        tag & !self.ignore == self.tag & !self.ignore
+    }
+}
@@ -0,0 +1,148 @@
+use crate::av::Addr;
+use crate::caps::HasTagged;
+use crate::checks::{Kind, Op};
+use crate::ep::{Enabled, Endpoint};
+use crate::error::{Result, check_len};
+use crate::flags::BindFlags;
+use crate::mr::{MemoryRegion, desc};
+use crate::threading::ThreadingModel;
+use crate::trace;
+use ofi_libfabric_sys::bindgen as ffi;
+
+/// Tagged messages (`fi_tagged(3)`), matched against posted receives by tag instead of by
+/// arrival order. The endpoint must have been opened with [`Caps::TAGGED`](crate::Caps::TAGGED).
+impl<M: ThreadingModel, P: HasTagged> Endpoint<M, Enabled, P> {
+    /// Post a tagged receive, matching any tag `t` for which `t & !ignore == tag & !ignore`.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    pub unsafe fn trecv(
+        &self,
+        buf: &mut [u8],
+        mr: Option<&MemoryRegion<M>>,
+        src: Addr,
+        tag: u64,
+        ignore: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.check_op(|| Op::new("fi_trecv", Kind::Trecv, buf.len()))?;
+        trace::data_op!(self, "fi_trecv", size = buf.len(), tag);
+        let ret = self.post_op(BindFlags::RECV, || {
+            trace::tracked!(self, "fi_trecv", context, None, [writes(buf)], unsafe {
+                ffi::fi_trecv(
+                    self.as_raw(),
+                    buf.as_mut_ptr().cast(),
+                    buf.len(),
+                    desc(mr),
+                    src.as_raw(),
+                    tag,
+                    ignore,
+                    context as *mut _,
+                )
+            })
+        });
+        check_len("fi_trecv", ret).map(|_| ())
+    }
+
+    /// Send a tagged message.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    pub unsafe fn tsend(
+        &self,
+        buf: &[u8],
+        mr: Option<&MemoryRegion<M>>,
+        dest: Addr,
+        tag: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.check_op(|| Op::new("fi_tsend", Kind::Tsend, buf.len()))?;
+        trace::data_op!(self, "fi_tsend", size = buf.len(), tag);
+        let ret = self.post_op(BindFlags::TRANSMIT, || {
+            trace::tracked!(self, "fi_tsend", context, None, [reads(buf)], unsafe {
+                ffi::fi_tsend(
+                    self.as_raw(),
+                    buf.as_ptr().cast(),
+                    buf.len(),
+                    desc(mr),
+                    dest.as_raw(),
+                    tag,
+                    context as *mut _,
+                )
+            })
+        });
+        check_len("fi_tsend", ret).map(|_| ())
+    }
+
+    /// Send a tagged message with remote CQ data.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    pub unsafe fn tsenddata(
+        &self,
+        buf: &[u8],
+        mr: Option<&MemoryRegion<M>>,
+        data: u64,
+        dest: Addr,
+        tag: u64,
+        context: usize,
+    ) -> Result<()> {
+        self.check_op(|| Op::new("fi_tsenddata", Kind::Tsend, buf.len()).data())?;
+        trace::data_op!(self, "fi_tsenddata", size = buf.len(), tag);
+        let ret = self.post_op(BindFlags::TRANSMIT, || {
+            trace::tracked!(self, "fi_tsenddata", context, None, [reads(buf)], unsafe {
+                ffi::fi_tsenddata(
+                    self.as_raw(),
+                    buf.as_ptr().cast(),
+                    buf.len(),
+                    desc(mr),
+                    data,
+                    dest.as_raw(),
+                    tag,
+                    context as *mut _,
+                )
+            })
+        });
+        check_len("fi_tsenddata", ret).map(|_| ())
+    }
+
+    /// Send a small tagged message, without a completion.
+    pub fn tinject(&self, buf: &[u8], dest: Addr, tag: u64) -> Result<()> {
+        self.check_op(|| Op::new("fi_tinject", Kind::Tsend, buf.len()).inject())?;
+        trace::data_op!(self, "fi_tinject", size = buf.len(), tag);
+        let ret = unsafe {
+            ffi::fi_tinject(
+                self.as_raw(),
+                buf.as_ptr().cast(),
+                buf.len(),
+                dest.as_raw(),
+                tag,
+            )
+        };
+        check_len("fi_tinject", ret).map(|_| ())
+    }
+
+    /// Like [`tinject()`](Self::tinject), with remote CQ data.
+    pub fn tinjectdata(&self, buf: &[u8], data: u64, dest: Addr, tag: u64) -> Result<()> {
+        self.check_op(|| {
+            Op::new("fi_tinjectdata", Kind::Tsend, buf.len())
+                .inject()
+                .data()
+        })?;
+        trace::data_op!(self, "fi_tinjectdata", size = buf.len(), tag);
+        let ret = unsafe {
+            ffi::fi_tinjectdata(
+                self.as_raw(),
+                buf.as_ptr().cast(),
+                buf.len(),
+                data,
+                dest.as_raw(),
+                tag,
+            )
+        };
+        check_len("fi_tinjectdata", ret).map(|_| ())
+    }
+}
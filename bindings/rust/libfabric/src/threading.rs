@@ -0,0 +1,113 @@
+use ofi_libfabric_sys::bindgen as ffi;
+use std::marker::PhantomData;
+
+/// The thread safety a provider guarantees for the objects of a domain (`enum fi_threading`),
+/// as negotiated in [`DomainAttr::threading`](crate::DomainAttr::threading), or required in
+/// hints with [`Info::threading()`](crate::Info::threading).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Threading {
+    /// Any level, in hints.
+    #[default]
+    Unspec,
+    /// Every object may be used from any thread at any time.
+    Safe,
+    /// Each object may be used from one thread at a time.
+    Fid,
+    /// The objects of the domain may be used from one thread at a time.
+    Domain,
+    /// The endpoints sharing a completion queue, and the queue, may be used from one thread at a
+    /// time.
+    Completion,
+    /// Each endpoint, and the queues bound to it only, may be used from one thread at a time.
+    Endpoint,
+    /// A raw value these bindings do not know about, ex: from a newer libfabric.
+    Other(u32),
+}
+
+impl Threading {
+    pub(crate) fn from_raw(raw: u32) -> Self {
+        match ffi::fi_threading::try_from(raw) {
+            Ok(ffi::fi_threading::FI_THREAD_UNSPEC) => Threading::Unspec,
+            Ok(ffi::fi_threading::FI_THREAD_SAFE) => Threading::Safe,
+            Ok(ffi::fi_threading::FI_THREAD_FID) => Threading::Fid,
+            Ok(ffi::fi_threading::FI_THREAD_DOMAIN) => Threading::Domain,
+            Ok(ffi::fi_threading::FI_THREAD_COMPLETION) => Threading::Completion,
+            Ok(ffi::fi_threading::FI_THREAD_ENDPOINT) => Threading::Endpoint,
+            _ => Threading::Other(raw),
+        }
+    }
+
+    pub(crate) fn as_raw(self) -> u32 {
+        match self {
+            Threading::Unspec => ffi::fi_threading::FI_THREAD_UNSPEC as u32,
+            Threading::Safe => ffi::fi_threading::FI_THREAD_SAFE as u32,
+            Threading::Fid => ffi::fi_threading::FI_THREAD_FID as u32,
+            Threading::Domain => ffi::fi_threading::FI_THREAD_DOMAIN as u32,
+            Threading::Completion => ffi::fi_threading::FI_THREAD_COMPLETION as u32,
+            Threading::Endpoint => ffi::fi_threading::FI_THREAD_ENDPOINT as u32,
+            Threading::Other(raw) => raw,
+        }
+    }
+}
+
+mod sealed {
+    pub trait Sealed {}
+
+    impl Sealed for super::ThreadSafe {}
+    impl Sealed for super::ThreadDomain {}
+}
+
+/// The threading model of a [`Domain`](crate::Domain), as a type parameter shared by the
+/// endpoints, queues, counters, address vectors and memory regions opened from it. It decides
+/// whether they are `Send` and `Sync`, so sharing the objects of a domain across threads only
+/// compiles when the provider allows it.
+///
+/// ```compile_fail
+/// use libfabric::{Endpoint, ThreadDomain};
+///
+/// fn share(ep: Endpoint<ThreadDomain>) {
+///     std::thread::spawn(move || drop(ep));
+/// }
+/// ```
+pub trait ThreadingModel: sealed::Sealed + Copy + 'static {
+    /// Whether the objects of a domain of the given level may be used under the model.
+    fn allows(threading: Threading) -> bool;
+
+    /// The level hints request for domains of the model, with a
+    /// [`DomainConfig`](crate::DomainConfig).
+    fn requested() -> Threading;
+}
+
+/// The model of domains with `FI_THREAD_SAFE`, the default, whose objects are `Send` and
+/// `Sync`.
+#[derive(Debug, Clone, Copy)]
+pub enum ThreadSafe {}
+
+impl ThreadingModel for ThreadSafe {
+    fn allows(threading: Threading) -> bool {
+        threading == Threading::Safe
+    }
+
+    fn requested() -> Threading {
+        Threading::Safe
+    }
+}
+
+/// The model of domains with `FI_THREAD_DOMAIN`, or any lesser level, whose objects are neither
+/// `Send` nor `Sync`: the whole domain stays on the thread which opened it, which serializes
+/// every call into it without locks.
+#[derive(Debug, Clone, Copy)]
+pub struct ThreadDomain {
+    _not_send: PhantomData<*const ()>,
+}
+
+impl ThreadingModel for ThreadDomain {
+    fn allows(threading: Threading) -> bool {
+        threading != Threading::Unspec
+    }
+
+    fn requested() -> Threading {
+        Threading::Domain
+    }
+}
@@ -0,0 +1,353 @@
+use crate::record::{OpRecord, OpStatus};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// The format of the trace [`Recorder::export_trace()`](crate::Recorder::export_trace) writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// The JSON of the Chrome trace event format, opened by `chrome://tracing`, the Perfetto UI
+    /// and most profilers.
+    ChromeJson,
+    /// The protobuf of Perfetto traces, `.perfetto-trace` or `.pftrace` files.
+    Perfetto,
+}
+
+// CLOCK_MONOTONIC, which `Instant` reads on Linux, as do perf and Perfetto.
+#[cfg(target_os = "linux")]
+pub(crate) fn monotonic_ns() -> u64 {
+    use std::os::raw::{c_int, c_long};
+
+    const CLOCK_MONOTONIC: c_int = 1;
+    #[repr(C)]
+    struct Timespec {
+        tv_sec: c_long,
+        tv_nsec: c_long,
+    }
+    unsafe extern "C" {
+        fn clock_gettime(clock: c_int, tp: *mut Timespec) -> c_int;
+    }
+    let mut ts = Timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // The clock always exists, and the pointer is valid.
+    unsafe { clock_gettime(CLOCK_MONOTONIC, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+// Elsewhere, times are relative to the recorder.
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn monotonic_ns() -> u64 {
+    0
+}
+
+// An operation laid out on a track, which it shares with the operations of its endpoint it
+// does not overlap.
+struct Slice<'a> {
+    op: &'a OpRecord,
+    track: usize,
+    begin: u64,
+    // None for posts which failed, drawn as instants.
+    end: Option<u64>,
+}
+
+struct Timeline<'a> {
+    slices: Vec<Slice<'a>>,
+    // The names of the tracks, by index.
+    tracks: Vec<String>,
+}
+
+// Lay `ops`, oldest first, out on tracks, as of `now`, at times in nanoseconds of
+// CLOCK_MONOTONIC.
+fn layout(ops: &[OpRecord], epoch: u64, now: Duration) -> Timeline<'_> {
+    let ns = |at: Duration| epoch + at.as_nanos() as u64;
+    let mut tracks = Vec::new();
+    // The tracks of each endpoint, with the end of their last slice.
+    let mut lanes: HashMap<usize, Vec<(usize, u64)>> = HashMap::new();
+    let mut slices = Vec::with_capacity(ops.len());
+    for op in ops {
+        let begin = ns(op.posted);
+        let end = match &op.status {
+            OpStatus::Pending => Some(ns(now)),
+            OpStatus::Completed { at, .. } | OpStatus::Failed { at, .. } => Some(ns(*at)),
+            OpStatus::Rejected(_) => None,
+        };
+        let lanes = lanes.entry(op.endpoint).or_default();
+        let free = match end {
+            Some(_) => lanes.iter().position(|&(_, last)| last < begin),
+            None => (!lanes.is_empty()).then_some(0),
+        };
+        let lane = free.unwrap_or_else(|| {
+            let name = match lanes.len() {
+                0 => format!("libfabric ep{}", op.endpoint),
+                n => format!("libfabric ep{} ({})", op.endpoint, n + 1),
+            };
+            tracks.push(name);
+            lanes.push((tracks.len() - 1, 0));
+            lanes.len() - 1
+        });
+        if let Some(end) = end {
+            lanes[lane].1 = end;
+        }
+        slices.push(Slice {
+            op,
+            track: lanes[lane].0,
+            begin,
+            end,
+        });
+    }
+    Timeline { slices, tracks }
+}
+
+// The arguments of an operation, shown with its slice.
+enum Arg {
+    Uint(u64),
+    Str(String),
+}
+
+fn args(op: &OpRecord) -> Vec<(&'static str, Arg)> {
+    let mut args = vec![
+        ("seq", Arg::Uint(op.seq)),
+        ("endpoint", Arg::Uint(op.endpoint as u64)),
+        ("len", Arg::Uint(op.len as u64)),
+        ("peer", Arg::Str(format!("{:#x}", op.peer.as_raw()))),
+        ("context", Arg::Str(format!("{:#x}", op.context))),
+    ];
+    if let Some(tag) = op.tag {
+        args.push(("tag", Arg::Str(format!("{tag:#x}"))));
+    }
+    let status = match &op.status {
+        OpStatus::Pending => "pending",
+        OpStatus::Completed { flags, .. } => {
+            args.push(("flags", Arg::Str(format!("{flags:#x}"))));
+            "completed"
+        }
+        OpStatus::Failed { error, .. } => {
+            args.push(("error", Arg::Str(error.to_string())));
+            "failed"
+        }
+        OpStatus::Rejected(error) => {
+            args.push(("error", Arg::Str(error.to_string())));
+            "rejected"
+        }
+    };
+    args.push(("status", Arg::Str(status.to_string())));
+    args
+}
+
+pub(crate) fn write_trace(
+    w: impl Write,
+    format: TraceFormat,
+    ops: &[OpRecord],
+    epoch: u64,
+    now: Duration,
+) -> io::Result<()> {
+    let timeline = layout(ops, epoch, now);
+    match format {
+        TraceFormat::ChromeJson => write_json(w, &timeline),
+        TraceFormat::Perfetto => write_perfetto(w, &timeline),
+    }
+}
+
+// Times in microseconds, with the nanoseconds as decimals.
+fn micros(ns: u64) -> String {
+    format!("{}.{:03}", ns / 1000, ns % 1000)
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+// Tracks are threads of the process, numbered from 1.
+fn write_json(mut w: impl Write, timeline: &Timeline) -> io::Result<()> {
+    let pid = std::process::id();
+    write!(w, "{{\"displayTimeUnit\":\"ns\",\"traceEvents\":[")?;
+    let mut sep = "";
+    for (track, name) in timeline.tracks.iter().enumerate() {
+        write!(
+            w,
+            "{sep}\n{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":{pid},\"tid\":{},\
+             \"args\":{{\"name\":{}}}}}",
+            track + 1,
+            json_string(name)
+        )?;
+        sep = ",";
+    }
+    for slice in &timeline.slices {
+        let mut fields = String::new();
+        for (name, arg) in args(slice.op) {
+            let value = match arg {
+                Arg::Uint(v) => v.to_string(),
+                Arg::Str(s) => json_string(&s),
+            };
+            let comma = if fields.is_empty() { "" } else { "," };
+            let _ = write!(fields, "{comma}\"{name}\":{value}");
+        }
+        let phase = match slice.end {
+            Some(end) => format!("\"ph\":\"X\",\"dur\":{}", micros(end - slice.begin)),
+            None => "\"ph\":\"i\",\"s\":\"t\"".to_string(),
+        };
+        write!(
+            w,
+            "{sep}\n{{\"name\":\"{}\",\"cat\":\"libfabric\",{phase},\"ts\":{},\"pid\":{pid},\
+             \"tid\":{},\"args\":{{{fields}}}}}",
+            slice.op.kind.name(),
+            micros(slice.begin),
+            slice.track + 1
+        )?;
+        sep = ",";
+    }
+    writeln!(w, "\n]}}")?;
+    w.flush()
+}
+
+// A protobuf message, of the fields written so far.
+#[derive(Default)]
+struct Proto(Vec<u8>);
+
+impl Proto {
+    fn varint(&mut self, mut v: u64) {
+        while v >= 0x80 {
+            self.0.push(v as u8 | 0x80);
+            v >>= 7;
+        }
+        self.0.push(v as u8);
+    }
+
+    fn uint(&mut self, field: u32, v: u64) -> &mut Self {
+        self.varint(u64::from(field) << 3);
+        self.varint(v);
+        self
+    }
+
+    fn bytes(&mut self, field: u32, v: &[u8]) -> &mut Self {
+        self.varint(u64::from(field) << 3 | 2);
+        self.varint(v.len() as u64);
+        self.0.extend_from_slice(v);
+        self
+    }
+
+    fn string(&mut self, field: u32, v: &str) -> &mut Self {
+        self.bytes(field, v.as_bytes())
+    }
+
+    fn message(&mut self, field: u32, v: &Proto) -> &mut Self {
+        self.bytes(field, &v.0)
+    }
+}
+
+// The fields of perfetto/trace/trace_packet.proto and those it includes.
+const TRACE_PACKET: u32 = 1;
+const PACKET_TIMESTAMP: u32 = 8;
+const PACKET_SEQUENCE_ID: u32 = 10;
+const PACKET_TRACK_EVENT: u32 = 11;
+const PACKET_SEQUENCE_FLAGS: u32 = 13;
+const PACKET_CLOCK_ID: u32 = 58;
+const PACKET_TRACK_DESCRIPTOR: u32 = 60;
+const TRACK_UUID: u32 = 1;
+const TRACK_NAME: u32 = 2;
+const TRACK_PROCESS: u32 = 3;
+const TRACK_PARENT_UUID: u32 = 5;
+const PROCESS_PID: u32 = 1;
+const EVENT_ANNOTATIONS: u32 = 4;
+const EVENT_TYPE: u32 = 9;
+const EVENT_TRACK_UUID: u32 = 11;
+const EVENT_CATEGORIES: u32 = 22;
+const EVENT_NAME: u32 = 23;
+const ANNOTATION_UINT: u32 = 3;
+const ANNOTATION_STRING: u32 = 6;
+const ANNOTATION_NAME: u32 = 10;
+const SEQ_INCREMENTAL_STATE_CLEARED: u64 = 1;
+const BUILTIN_CLOCK_MONOTONIC: u64 = 3;
+const TYPE_SLICE_BEGIN: u64 = 1;
+const TYPE_SLICE_END: u64 = 2;
+const TYPE_INSTANT: u64 = 3;
+// The uuid of the track of the process, "lf" in its top bytes, those of its threads following.
+const PROCESS_UUID: u64 = 0x6c66 << 48;
+
+// Tracks are children of that of the process, merged with the tracks of its CPU profile.
+fn write_perfetto(mut w: impl Write, timeline: &Timeline) -> io::Result<()> {
+    let mut trace = Proto::default();
+    let mut packet = |fill: &mut dyn FnMut(&mut Proto)| {
+        let mut p = Proto::default();
+        p.uint(PACKET_SEQUENCE_ID, 1);
+        fill(&mut p);
+        trace.message(TRACE_PACKET, &p);
+    };
+    packet(&mut |p| {
+        let mut process = Proto::default();
+        process.uint(PROCESS_PID, u64::from(std::process::id()));
+        let mut track = Proto::default();
+        track
+            .uint(TRACK_UUID, PROCESS_UUID)
+            .message(TRACK_PROCESS, &process);
+        p.uint(PACKET_SEQUENCE_FLAGS, SEQ_INCREMENTAL_STATE_CLEARED)
+            .message(PACKET_TRACK_DESCRIPTOR, &track);
+    });
+    for (index, name) in timeline.tracks.iter().enumerate() {
+        packet(&mut |p| {
+            let mut track = Proto::default();
+            track
+                .uint(TRACK_UUID, PROCESS_UUID + 1 + index as u64)
+                .uint(TRACK_PARENT_UUID, PROCESS_UUID)
+                .string(TRACK_NAME, name);
+            p.message(PACKET_TRACK_DESCRIPTOR, &track);
+        });
+    }
+    for slice in &timeline.slices {
+        let uuid = PROCESS_UUID + 1 + slice.track as u64;
+        packet(&mut |p| {
+            let mut event = Proto::default();
+            let kind = match slice.end {
+                Some(_) => TYPE_SLICE_BEGIN,
+                None => TYPE_INSTANT,
+            };
+            event
+                .uint(EVENT_TYPE, kind)
+                .uint(EVENT_TRACK_UUID, uuid)
+                .string(EVENT_CATEGORIES, "libfabric")
+                .string(EVENT_NAME, slice.op.kind.name());
+            for (name, arg) in args(slice.op) {
+                let mut annotation = Proto::default();
+                annotation.string(ANNOTATION_NAME, name);
+                match arg {
+                    Arg::Uint(v) => annotation.uint(ANNOTATION_UINT, v),
+                    Arg::Str(s) => annotation.string(ANNOTATION_STRING, &s),
+                };
+                event.message(EVENT_ANNOTATIONS, &annotation);
+            }
+            p.uint(PACKET_TIMESTAMP, slice.begin)
+                .uint(PACKET_CLOCK_ID, BUILTIN_CLOCK_MONOTONIC)
+                .message(PACKET_TRACK_EVENT, &event);
+        });
+        if let Some(end) = slice.end {
+            packet(&mut |p| {
+                let mut event = Proto::default();
+                event
+                    .uint(EVENT_TYPE, TYPE_SLICE_END)
+                    .uint(EVENT_TRACK_UUID, uuid);
+                p.uint(PACKET_TIMESTAMP, end)
+                    .uint(PACKET_CLOCK_ID, BUILTIN_CLOCK_MONOTONIC)
+                    .message(PACKET_TRACK_EVENT, &event);
+            });
+        }
+    }
+    w.write_all(&trace.0)?;
+    w.flush()
+}
@@ -0,0 +1,130 @@
+use crate::error::{Error, Result};
+
+/// The ranks of a subgroup of a group, ex: of a [`Communicator`](crate::Communicator), from
+/// which [`AddressVector::av_set_ranks()`](crate::AddressVector::av_set_ranks) and
+/// [`Communicator::av_set()`](crate::Communicator::av_set) create the [`AvSet`](crate::AvSet) a
+/// collective subgroup is joined over.
+///
+/// The constructors build the subgroups of common topologies, each including the member it is
+/// built for, so that the member may join it. Ranks are kept in order and once each, the
+/// index of a rank being its rank in the subgroup, see [`position()`](Self::position).
+///
+/// ```
+/// use libfabric::RankSet;
+///
+/// # fn main() -> libfabric::Result<()> {
+/// // The members of the node of rank 5, with 4 ranks per node, out of 16.
+/// let node = RankSet::node_local(5, 4, 16)?;
+/// assert_eq!(node.ranks(), [4, 5, 6, 7]);
+/// // Rank 5 and its partners across each dimension of a hypercube of 16.
+/// let cube = RankSet::hypercube_partners(5, 16)?;
+/// assert_eq!(cube.ranks(), [5, 4, 7, 1, 13]);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RankSet {
+    ranks: Vec<usize>,
+}
+
+impl RankSet {
+    /// The ranks of `ranks`, in order, those repeated kept once.
+    pub fn list(ranks: impl IntoIterator<Item = usize>) -> Self {
+        let mut set = RankSet { ranks: Vec::new() };
+        for rank in ranks {
+            if !set.contains(rank) {
+                set.ranks.push(rank);
+            }
+        }
+        set
+    }
+
+    /// The ranks from `start` to `end` included, `stride` apart, as the range of an
+    /// `fi_av_set_attr`. Fails with an invalid argument error if `stride` is 0 or `end` is
+    /// before `start`.
+    pub fn strided(start: usize, end: usize, stride: usize) -> Result<Self> {
+        if stride == 0 || end < start {
+            return Err(Error::invalid(format!(
+                "ranks from {start} to {end} by {stride}"
+            )));
+        }
+        Ok(RankSet {
+            ranks: (start..=end).step_by(stride).collect(),
+        })
+    }
+
+    /// `rank` between its neighbors on a ring of `size` members: the previous rank, `rank`,
+    /// then the next, fewer on rings of 1 or 2 members.
+    pub fn ring_neighbors(rank: usize, size: usize) -> Result<Self> {
+        check_rank(rank, size)?;
+        let (prev, next) = ((rank + size - 1) % size, (rank + 1) % size);
+        Ok(Self::list([prev, rank, next]))
+    }
+
+    /// `rank` followed by its partner across each dimension of a hypercube, from the lowest:
+    /// the ranks differing from it by one bit, those of `size` members or more left out when
+    /// `size` is not a power of 2.
+    pub fn hypercube_partners(rank: usize, size: usize) -> Result<Self> {
+        check_rank(rank, size)?;
+        let partners = (0..usize::BITS)
+            .map(|dim| 1usize << dim)
+            .take_while(|&bit| bit < size)
+            .map(|bit| rank ^ bit)
+            .filter(|&partner| partner < size);
+        Ok(Self::list(std::iter::once(rank).chain(partners)))
+    }
+
+    /// The members of the node of `rank`, with ranks placed `per_node` to a node in order,
+    /// as launchers place them by default: the last node holds fewer if `size` is not a
+    /// multiple of `per_node`.
+    pub fn node_local(rank: usize, per_node: usize, size: usize) -> Result<Self> {
+        check_rank(rank, size)?;
+        if per_node == 0 {
+            return Err(Error::invalid("0 ranks per node"));
+        }
+        let first = rank / per_node * per_node;
+        Ok(RankSet {
+            ranks: (first..size.min(first + per_node)).collect(),
+        })
+    }
+
+    /// The members of the place of `rank` on each node, `rank % per_node`, ex: the first
+    /// member of each for the node leaders, which exchange across nodes on behalf of theirs.
+    pub fn node_leaders(rank: usize, per_node: usize, size: usize) -> Result<Self> {
+        check_rank(rank, size)?;
+        if per_node == 0 {
+            return Err(Error::invalid("0 ranks per node"));
+        }
+        Self::strided(rank % per_node, size - 1, per_node)
+    }
+
+    pub fn ranks(&self) -> &[usize] {
+        &self.ranks
+    }
+
+    pub fn len(&self) -> usize {
+        self.ranks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranks.is_empty()
+    }
+
+    pub fn contains(&self, rank: usize) -> bool {
+        self.ranks.contains(&rank)
+    }
+
+    /// The rank of `rank` in the subgroup, if a member.
+    pub fn position(&self, rank: usize) -> Option<usize> {
+        self.ranks.iter().position(|&r| r == rank)
+    }
+}
+
+fn check_rank(rank: usize, size: usize) -> Result<()> {
+    if rank >= size {
+        return Err(Error::invalid(format!(
+            "rank {rank} is out of a group of {size}"
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,123 @@
+// Instrumentation through the `tracing` crate, enabled by the `tracing` feature. Without it,
+// the macros below expand to nothing, and their fields are not evaluated.
+//
+// Connection management is reported as debug events, registrations and address insertions as
+// debug spans around the call, and data operations, once enabled by `trace_data_ops()`, as
+// trace events for one in so many of them, all under the `libfabric` target. The mock reports
+// its address insertions and data operations the same way, with `mock` as the provider.
+
+#[cfg(feature = "tracing")]
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[cfg(feature = "tracing")]
+static DATA_OPS_EVERY: AtomicU64 = AtomicU64::new(0);
+#[cfg(feature = "tracing")]
+static DATA_OPS: AtomicU64 = AtomicU64::new(0);
+
+/// Report one in `every` data operation (sends, receives, RMA and atomics) as a trace event of
+/// the `libfabric::data` target, with its provider, endpoint, size and, if any, tag. Data
+/// operations are not reported with `every` 0, the default, which keeps them free of any
+/// overhead but an atomic load.
+///
+/// Enabled by the `tracing` feature.
+#[cfg(feature = "tracing")]
+pub fn trace_data_ops(every: u64) {
+    DATA_OPS_EVERY.store(every, Ordering::Relaxed);
+}
+
+// Whether to report the current data operation.
+#[cfg(feature = "tracing")]
+pub(crate) fn sampled() -> bool {
+    match DATA_OPS_EVERY.load(Ordering::Relaxed) {
+        0 => false,
+        every => DATA_OPS
+            .fetch_add(1, Ordering::Relaxed)
+            .is_multiple_of(every),
+    }
+}
+
+// A debug event, ex: `event!(endpoint = ?ep.id(), "fi_connect")`.
+macro_rules! event {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        ::tracing::debug!(target: "libfabric", $($arg)*);
+    };
+}
+
+// What `span!` returns without the feature.
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct NoSpan;
+
+// A debug span, entered until the returned guard is dropped.
+macro_rules! span {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        let guard = ::tracing::debug_span!(target: "libfabric", $($arg)*).entered();
+        #[cfg(not(feature = "tracing"))]
+        let guard = $crate::trace::NoSpan;
+        guard
+    }};
+}
+
+// The trace event of a data operation of endpoint `$ep`, if sampled, ex:
+// `data_op!(self, "fi_send", size = buf.len())`.
+macro_rules! data_op {
+    ($ep:expr, $op:literal $(, $($field:tt)*)?) => {
+        #[cfg(feature = "tracing")]
+        if $crate::trace::sampled() {
+            ::tracing::trace!(
+                target: "libfabric::data",
+                provider = $ep.info().provider_name(),
+                endpoint = ?$crate::fid::AsRawFid::id($ep)
+                $(, $($field)*)?,
+                $op
+            );
+        }
+    };
+}
+
+// The trace event of a data operation of `$endpoint` of the mock, if sampled, like those of
+// `data_op!`, ex: `mock_op!(name, index, size = len)`.
+#[cfg(feature = "mock")]
+macro_rules! mock_op {
+    ($op:expr, $endpoint:expr $(, $($field:tt)*)?) => {
+        #[cfg(feature = "tracing")]
+        if $crate::trace::sampled() {
+            ::tracing::trace!(
+                target: "libfabric::data",
+                provider = "mock",
+                endpoint = $endpoint
+                $(, $($field)*)?,
+                "{}",
+                $op
+            );
+        }
+    };
+}
+
+// Run `$post`, the post of an operation of endpoint `$ep` with `$context` on the buffers
+// `$bufs`, ex: `tracked!(self, "fi_send", context, None, [reads(buf)], unsafe { ... })`,
+// tracking them until its completion with the `debug-validate` feature (see `validate.rs`).
+// `$flags` are the operation flags of the `*msg` calls.
+macro_rules! tracked {
+    ($ep:expr, $op:literal, $context:expr, $flags:expr, [$($access:ident($buf:expr)),*], $post:expr) => {{
+        #[cfg(feature = "debug-validate")]
+        let token = $crate::validate::track(
+            $crate::fid::AsRawFid::id($ep),
+            $op,
+            $context,
+            $flags,
+            [$($crate::validate::$access($buf)),*].concat(),
+        );
+        let ret = $post;
+        #[cfg(feature = "debug-validate")]
+        if ret != 0 {
+            $crate::validate::untrack(token);
+        }
+        ret
+    }};
+}
+
+#[cfg(feature = "mock")]
+pub(crate) use mock_op;
+pub(crate) use {data_op, event, span, tracked};
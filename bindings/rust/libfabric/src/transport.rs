@@ -0,0 +1,436 @@
+use crate::atomic::AtomicOp;
+use crate::av::{Addr, AddressVector, EndpointAddress};
+use crate::cq::{Completion, CompletionQueue, CqErrEntry};
+use crate::ep::Endpoint;
+use crate::error::Result;
+use crate::mr::MemoryRegion;
+
+/// The data transfer operations of an endpoint, implemented by [`Endpoint`] and, with the
+/// `mock` feature, by [`MockEndpoint`](crate::mock::MockEndpoint).
+///
+/// Protocols written against this trait, along with [`Cq`], [`Av`] and [`Mr`], run unchanged
+/// over a fabric or in memory. The methods are those of [`Endpoint`], with the same safety
+/// requirements: buffers must stay valid until the completion of their operation is read.
+///
+/// # Protocols
+///
+/// The protocols of the crate, ex: [`FlowControl`](crate::FlowControl), take the endpoint
+/// and completion queue they run over, and own the buffers of the operations they post, which
+/// are freed along with them. So creating one is `unsafe`: no other handle of the endpoint
+/// may outlive the protocol, and neither the other operations of the endpoint nor the
+/// completions of the queue may be used elsewhere, as the protocol takes every completion it
+/// reads for its own.
+pub trait Transport {
+    /// The memory regions whose descriptors go along with the buffers.
+    type Mr: Mr;
+
+    /// The address peers insert to reach this endpoint.
+    fn name(&self) -> Result<EndpointAddress>;
+
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    unsafe fn recv(
+        &self,
+        buf: &mut [u8],
+        mr: Option<&Self::Mr>,
+        src: Addr,
+        context: usize,
+    ) -> Result<()>;
+
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    unsafe fn send(
+        &self,
+        buf: &[u8],
+        mr: Option<&Self::Mr>,
+        dest: Addr,
+        context: usize,
+    ) -> Result<()>;
+
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    unsafe fn senddata(
+        &self,
+        buf: &[u8],
+        mr: Option<&Self::Mr>,
+        data: u64,
+        dest: Addr,
+        context: usize,
+    ) -> Result<()>;
+
+    fn inject(&self, buf: &[u8], dest: Addr) -> Result<()>;
+
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    unsafe fn trecv(
+        &self,
+        buf: &mut [u8],
+        mr: Option<&Self::Mr>,
+        src: Addr,
+        tag: u64,
+        ignore: u64,
+        context: usize,
+    ) -> Result<()>;
+
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    unsafe fn tsend(
+        &self,
+        buf: &[u8],
+        mr: Option<&Self::Mr>,
+        dest: Addr,
+        tag: u64,
+        context: usize,
+    ) -> Result<()>;
+
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    unsafe fn tsenddata(
+        &self,
+        buf: &[u8],
+        mr: Option<&Self::Mr>,
+        data: u64,
+        dest: Addr,
+        tag: u64,
+        context: usize,
+    ) -> Result<()>;
+
+    fn tinject(&self, buf: &[u8], dest: Addr, tag: u64) -> Result<()>;
+
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    unsafe fn read(
+        &self,
+        buf: &mut [u8],
+        mr: Option<&Self::Mr>,
+        src: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()>;
+
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    unsafe fn write(
+        &self,
+        buf: &[u8],
+        mr: Option<&Self::Mr>,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()>;
+
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation.
+    #[allow(clippy::too_many_arguments)]
+    unsafe fn writedata(
+        &self,
+        buf: &[u8],
+        mr: Option<&Self::Mr>,
+        data: u64,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()>;
+}
+
+/// The 64-bit atomics of an endpoint, implemented by [`Endpoint`] and, with the `mock`
+/// feature, by [`MockEndpoint`](crate::mock::MockEndpoint), for protocols built on atomics,
+/// ex: locks in remote memory. Endpoints need [`Caps::ATOMIC`](crate::Caps::ATOMIC).
+pub trait AtomicTransport: Transport {
+    /// Swap the `u64` at `addr` of `dest` for `value` if it equals `compare`
+    /// (`FI_CSWAP`), writing its previous value to `result` either way.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation; `result` is written when the operation completes.
+    #[allow(clippy::too_many_arguments)]
+    unsafe fn compare_swap(
+        &self,
+        value: u64,
+        compare: u64,
+        result: &mut u64,
+        result_mr: Option<&Self::Mr>,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()>;
+}
+
+/// A completion queue, implemented by [`CompletionQueue`].
+pub trait Cq {
+    /// Read completions into `out`, returning how many were read. Returns 0, or fails with
+    /// `FI_EAGAIN`, when there are none, and fails with `FI_EAVAIL` when an error entry is
+    /// waiting.
+    fn read(&self, out: &mut [Completion]) -> Result<usize>;
+
+    /// Like [`read()`](Self::read), also reporting the source address of each completion.
+    fn read_from(&self, out: &mut [Completion], src: &mut [Addr]) -> Result<usize>;
+
+    /// Read the next error entry, if any.
+    fn read_err(&self) -> Result<Option<CqErrEntry>>;
+}
+
+/// An address vector, implemented by [`AddressVector`].
+pub trait Av {
+    fn insert(&self, addr: &EndpointAddress) -> Result<Addr>;
+
+    fn remove(&self, addr: Addr) -> Result<()>;
+}
+
+/// A registered memory region, implemented by [`MemoryRegion`].
+pub trait Mr {
+    /// The remote key peers use to access the region.
+    fn key(&self) -> u64;
+
+    /// Start of the registered memory.
+    fn addr(&self) -> *mut u8;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// A protocol over a `Cq`, whose completions `poll_cq()` reads. Protocols declare the
+// endpoint and its queue as their first fields, so that when they are the last handles, these
+// are closed before the buffers of the protocol are freed.
+pub(crate) trait CqHandler {
+    type Cq: Cq;
+
+    fn cq(&self) -> &Self::Cq;
+
+    // An operation completed, a receive from `src`, or `Addr::NOTAVAIL` when the provider does
+    // not report sources.
+    fn complete(&mut self, completion: &Completion, src: Addr);
+
+    // An operation failed, or a receive was truncated.
+    fn failed(&mut self, entry: CqErrEntry);
+}
+
+// Read the completions of the queue of `handler`, successful or not, until there are none.
+// Fails only if the queue cannot be read, the errors of operations going to `failed()`.
+pub(crate) fn poll_cq(handler: &mut impl CqHandler) -> Result<()> {
+    let mut completions = [Completion::default(); 16];
+    let mut src = [Addr::UNSPEC; 16];
+    loop {
+        // Empty queues read nothing, or fail with FI_EAGAIN as the mock does.
+        let n = match handler.cq().read_from(&mut completions, &mut src) {
+            Ok(0) => return Ok(()),
+            Err(err) if err.is_again() => return Ok(()),
+            Err(err) if err.is_avail() => match handler.cq().read_err()? {
+                Some(entry) => {
+                    handler.failed(entry);
+                    continue;
+                }
+                None => return Ok(()),
+            },
+            other => other?,
+        };
+        for (completion, src) in completions[..n].iter().zip(&src[..n]) {
+            handler.complete(completion, *src);
+        }
+    }
+}
+
+impl Transport for Endpoint {
+    type Mr = MemoryRegion;
+
+    fn name(&self) -> Result<EndpointAddress> {
+        Endpoint::name(self)
+    }
+
+    unsafe fn recv(
+        &self,
+        buf: &mut [u8],
+        mr: Option<&MemoryRegion>,
+        src: Addr,
+        context: usize,
+    ) -> Result<()> {
+        unsafe { Endpoint::recv(self, buf, mr, src, context) }
+    }
+
+    unsafe fn send(
+        &self,
+        buf: &[u8],
+        mr: Option<&MemoryRegion>,
+        dest: Addr,
+        context: usize,
+    ) -> Result<()> {
+        unsafe { Endpoint::send(self, buf, mr, dest, context) }
+    }
+
+    unsafe fn senddata(
+        &self,
+        buf: &[u8],
+        mr: Option<&MemoryRegion>,
+        data: u64,
+        dest: Addr,
+        context: usize,
+    ) -> Result<()> {
+        unsafe { Endpoint::senddata(self, buf, mr, data, dest, context) }
+    }
+
+    fn inject(&self, buf: &[u8], dest: Addr) -> Result<()> {
+        Endpoint::inject(self, buf, dest)
+    }
+
+    unsafe fn trecv(
+        &self,
+        buf: &mut [u8],
+        mr: Option<&MemoryRegion>,
+        src: Addr,
+        tag: u64,
+        ignore: u64,
+        context: usize,
+    ) -> Result<()> {
+        unsafe { Endpoint::trecv(self, buf, mr, src, tag, ignore, context) }
+    }
+
+    unsafe fn tsend(
+        &self,
+        buf: &[u8],
+        mr: Option<&MemoryRegion>,
+        dest: Addr,
+        tag: u64,
+        context: usize,
+    ) -> Result<()> {
+        unsafe { Endpoint::tsend(self, buf, mr, dest, tag, context) }
+    }
+
+    unsafe fn tsenddata(
+        &self,
+        buf: &[u8],
+        mr: Option<&MemoryRegion>,
+        data: u64,
+        dest: Addr,
+        tag: u64,
+        context: usize,
+    ) -> Result<()> {
+        unsafe { Endpoint::tsenddata(self, buf, mr, data, dest, tag, context) }
+    }
+
+    fn tinject(&self, buf: &[u8], dest: Addr, tag: u64) -> Result<()> {
+        Endpoint::tinject(self, buf, dest, tag)
+    }
+
+    unsafe fn read(
+        &self,
+        buf: &mut [u8],
+        mr: Option<&MemoryRegion>,
+        src: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()> {
+        unsafe { Endpoint::read(self, buf, mr, src, addr, key, context) }
+    }
+
+    unsafe fn write(
+        &self,
+        buf: &[u8],
+        mr: Option<&MemoryRegion>,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()> {
+        unsafe { Endpoint::write(self, buf, mr, dest, addr, key, context) }
+    }
+
+    unsafe fn writedata(
+        &self,
+        buf: &[u8],
+        mr: Option<&MemoryRegion>,
+        data: u64,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()> {
+        unsafe { Endpoint::writedata(self, buf, mr, data, dest, addr, key, context) }
+    }
+}
+
+impl AtomicTransport for Endpoint {
+    unsafe fn compare_swap(
+        &self,
+        value: u64,
+        compare: u64,
+        result: &mut u64,
+        result_mr: Option<&MemoryRegion>,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<()> {
+        // The operands are injected, only the result is written at completion.
+        unsafe {
+            Endpoint::compare_atomic(
+                self,
+                &[value],
+                None,
+                &[compare],
+                None,
+                std::slice::from_mut(result),
+                result_mr,
+                dest,
+                addr,
+                key,
+                AtomicOp::Cswap,
+                context,
+            )
+        }
+    }
+}
+
+impl Cq for CompletionQueue {
+    fn read(&self, out: &mut [Completion]) -> Result<usize> {
+        CompletionQueue::read(self, out)
+    }
+
+    fn read_from(&self, out: &mut [Completion], src: &mut [Addr]) -> Result<usize> {
+        CompletionQueue::read_from(self, out, src)
+    }
+
+    fn read_err(&self) -> Result<Option<CqErrEntry>> {
+        CompletionQueue::read_err(self)
+    }
+}
+
+impl Av for AddressVector {
+    fn insert(&self, addr: &EndpointAddress) -> Result<Addr> {
+        AddressVector::insert(self, addr)
+    }
+
+    fn remove(&self, addr: Addr) -> Result<()> {
+        AddressVector::remove(self, addr)
+    }
+}
+
+impl Mr for MemoryRegion {
+    fn key(&self) -> u64 {
+        MemoryRegion::key(self)
+    }
+
+    fn addr(&self) -> *mut u8 {
+        MemoryRegion::addr(self)
+    }
+
+    fn len(&self) -> usize {
+        MemoryRegion::len(self)
+    }
+}
@@ -0,0 +1,281 @@
+use crate::av::Addr;
+use crate::cq::{Completion, CqErrEntry};
+use crate::error::{Error, Result};
+use crate::transport::{Av, Cq};
+use ofi_libfabric_sys::bindgen as ffi;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Mutex, MutexGuard};
+
+/// Where the cause of an error completion lies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorOrigin {
+    /// The operation, its buffers or the local endpoint, ex: a truncated receive.
+    Local,
+    /// The peer or the network towards it, ex: an unreachable host or a rejected key.
+    Remote,
+}
+
+/// Whether the operation of an error completion may succeed once posted again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorSeverity {
+    /// Ex: a peer with no receive posted yet, or a provider out of resources.
+    Transient,
+    /// Ex: a peer gone, or a buffer too small, which fail the same way again.
+    Fatal,
+}
+
+/// The classification of an error, from its code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ErrorClass {
+    pub origin: ErrorOrigin,
+    pub severity: ErrorSeverity,
+    /// Whether the peer cannot be reached any more, connection errors included, for which
+    /// its operations are better given up on.
+    pub unreachable: bool,
+}
+
+impl ErrorClass {
+    /// The class of `error`, from its code.
+    pub fn of(error: &Error) -> Self {
+        Self::of_code(error.code())
+    }
+
+    /// The class of the `FI_E*` `code`. Codes not known to be remote or transient are local
+    /// and fatal.
+    pub fn of_code(code: i32) -> Self {
+        let (origin, severity, unreachable) = match code.unsigned_abs() {
+            ffi::FI_EHOSTUNREACH
+            | ffi::FI_EHOSTDOWN
+            | ffi::FI_ENETUNREACH
+            | ffi::FI_ENETDOWN
+            | ffi::FI_ECONNREFUSED
+            | ffi::FI_ECONNRESET
+            | ffi::FI_ECONNABORTED
+            | ffi::FI_ENOTCONN
+            | ffi::FI_ESHUTDOWN => (ErrorOrigin::Remote, ErrorSeverity::Fatal, true),
+            ffi::FI_EKEYREJECTED | ffi::FI_EACCES | ffi::FI_EPERM | ffi::FI_EREMOTEIO => {
+                (ErrorOrigin::Remote, ErrorSeverity::Fatal, false)
+            }
+            ffi::FI_ENORX | ffi::FI_ETIMEDOUT | ffi::FI_EADDRNOTAVAIL => {
+                (ErrorOrigin::Remote, ErrorSeverity::Transient, false)
+            }
+            ffi::FI_EAGAIN | ffi::FI_ENOMEM | ffi::FI_ENOBUFS | ffi::FI_ECANCELED => {
+                (ErrorOrigin::Local, ErrorSeverity::Transient, false)
+            }
+            _ => (ErrorOrigin::Local, ErrorSeverity::Fatal, false),
+        };
+        ErrorClass {
+            origin,
+            severity,
+            unreachable,
+        }
+    }
+
+    pub fn is_transient(&self) -> bool {
+        self.severity == ErrorSeverity::Transient
+    }
+
+    pub fn is_remote(&self) -> bool {
+        self.origin == ErrorOrigin::Remote
+    }
+}
+
+/// An error completion drained by an [`ErrorTriage`], with its class and peer.
+#[derive(Debug, Clone)]
+pub struct TriagedError {
+    pub entry: CqErrEntry,
+    pub class: ErrorClass,
+    /// The peer of the operation, when known, see [`ErrorTriage::peer_of()`].
+    pub peer: Option<Addr>,
+}
+
+type Hook = Box<dyn FnMut(&TriagedError) + Send>;
+type PeerOf = Box<dyn Fn(&CqErrEntry) -> Option<Addr> + Send>;
+
+struct State {
+    errors: VecDeque<TriagedError>,
+    subscriber: Option<Sender<TriagedError>>,
+    hooks: Vec<Hook>,
+    peer_of: PeerOf,
+    per_peer: HashMap<Addr, usize>,
+}
+
+/// A completion queue whose error completions are drained as they come, classified, handed to
+/// policy hooks, then queued for [`take_errors()`](Self::take_errors) or sent to a
+/// [subscriber](Self::subscribe), so that error handling lives in one place rather than after
+/// each read.
+///
+/// Reads of the queue never fail with `FI_EAVAIL`: they drain the errors pending and read on,
+/// failing with `FI_EAGAIN` as usual once no completion is left.
+/// [`Cq::read_err()`] triages an error as well, and returns it besides. It runs over any [`Cq`], so
+/// over a [`CompletionQueue`](crate::CompletionQueue), or a
+/// [`MockCq`](crate::mock::MockCq).
+///
+/// ```no_run
+/// use libfabric::{AddressVector, Completion, CompletionQueue, Cq, ErrorTriage};
+///
+/// # fn run(cq: CompletionQueue, av: AddressVector) -> libfabric::Result<()> {
+/// let cq = ErrorTriage::new(cq)
+///     .evict_unreachable(av)
+///     .hook(|err| eprintln!("{:?} from {:?}: {}", err.class, err.peer, err.entry.error));
+/// let errors = cq.subscribe();
+/// let mut completions = [Completion::default(); 16];
+/// cq.read(&mut completions)?;
+/// for err in errors.try_iter().filter(|err| !err.class.is_transient()) {
+///     // Give up on the operation of `err.entry.context`.
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[must_use]
+pub struct ErrorTriage<C: Cq> {
+    cq: C,
+    state: Mutex<State>,
+}
+
+impl<C: Cq> ErrorTriage<C> {
+    pub fn new(cq: C) -> Self {
+        ErrorTriage {
+            cq,
+            state: Mutex::new(State {
+                errors: VecDeque::new(),
+                subscriber: None,
+                hooks: Vec::new(),
+                peer_of: Box::new(|entry| {
+                    (entry.src_addr != Addr::UNSPEC && entry.src_addr != Addr::NOTAVAIL)
+                        .then_some(entry.src_addr)
+                }),
+                per_peer: HashMap::new(),
+            }),
+        }
+    }
+
+    pub fn get_ref(&self) -> &C {
+        &self.cq
+    }
+
+    pub fn into_inner(self) -> C {
+        self.cq
+    }
+
+    /// Run `hook` on each error, in the order they were added, before it is queued.
+    pub fn hook(self, hook: impl FnMut(&TriagedError) + Send + 'static) -> Self {
+        self.lock().hooks.push(Box::new(hook));
+        self
+    }
+
+    /// Remove the peers found unreachable from `av`, once each, so that the application no
+    /// longer posts to them.
+    pub fn evict_unreachable<A: Av + Send + 'static>(self, av: A) -> Self {
+        let mut evicted = HashSet::new();
+        self.hook(move |err| match err.peer {
+            Some(peer) if err.class.unreachable && evicted.insert(peer) => {
+                let _ = av.remove(peer);
+            }
+            _ => {}
+        })
+    }
+
+    /// How the peer of an error is found, by default its source address when the provider
+    /// reports it, which is for receives with `FI_SOURCE`. Applications knowing the peer of
+    /// each context map it here, for the peers of failed sends and writes.
+    pub fn peer_of(self, peer_of: impl Fn(&CqErrEntry) -> Option<Addr> + Send + 'static) -> Self {
+        self.lock().peer_of = Box::new(peer_of);
+        self
+    }
+
+    /// Send the errors to the returned receiver from now on, rather than queuing them. Errors
+    /// are queued again once it is dropped.
+    pub fn subscribe(&self) -> Receiver<TriagedError> {
+        let (sender, receiver) = mpsc::channel();
+        self.lock().subscriber = Some(sender);
+        receiver
+    }
+
+    /// The errors queued, oldest first.
+    pub fn take_errors(&self) -> Vec<TriagedError> {
+        self.lock().errors.drain(..).collect()
+    }
+
+    /// The errors of each peer, since the queue was wrapped.
+    pub fn per_peer(&self) -> HashMap<Addr, usize> {
+        self.lock().per_peer.clone()
+    }
+
+    // Read and triage the pending errors, returning how many there were.
+    fn drain(&self) -> Result<usize> {
+        let mut drained = 0;
+        while self.triage_one()?.is_some() {
+            drained += 1;
+        }
+        Ok(drained)
+    }
+
+    fn triage_one(&self) -> Result<Option<CqErrEntry>> {
+        let Some(entry) = self.cq.read_err()? else {
+            return Ok(None);
+        };
+        let mut state = self.lock();
+        let state = &mut *state;
+        let err = TriagedError {
+            class: ErrorClass::of(&entry.error),
+            peer: (state.peer_of)(&entry),
+            entry: entry.clone(),
+        };
+        if let Some(peer) = err.peer {
+            *state.per_peer.entry(peer).or_default() += 1;
+        }
+        for hook in &mut state.hooks {
+            hook(&err);
+        }
+        let err = match &state.subscriber {
+            Some(subscriber) => match subscriber.send(err) {
+                Ok(()) => return Ok(Some(entry)),
+                Err(mpsc::SendError(err)) => {
+                    state.subscriber = None;
+                    err
+                }
+            },
+            None => err,
+        };
+        state.errors.push_back(err);
+        Ok(Some(entry))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+}
+
+impl<C: Cq> Cq for ErrorTriage<C> {
+    fn read(&self, out: &mut [Completion]) -> Result<usize> {
+        loop {
+            match self.cq.read(out) {
+                Err(err) if err.is_avail() => {
+                    if self.drain()? == 0 {
+                        return Err(Error::fabric("fi_cq_read", ffi::FI_EAGAIN as i64));
+                    }
+                }
+                other => return other,
+            }
+        }
+    }
+
+    fn read_from(&self, out: &mut [Completion], src: &mut [Addr]) -> Result<usize> {
+        loop {
+            match self.cq.read_from(out, src) {
+                Err(err) if err.is_avail() => {
+                    if self.drain()? == 0 {
+                        return Err(Error::fabric("fi_cq_readfrom", ffi::FI_EAGAIN as i64));
+                    }
+                }
+                other => return other,
+            }
+        }
+    }
+
+    fn read_err(&self) -> Result<Option<CqErrEntry>> {
+        self.triage_one()
+    }
+}
@@ -0,0 +1,154 @@
+use crate::cq::{CompletionQueue, CqAttr};
+use crate::ep::{Endpoint, ScalableEndpoint};
+use crate::error::Result;
+use crate::flags::BindFlags;
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+static NEXT_POOL: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    // The context each pool assigned the thread, by pool. Pools are never reused, so the
+    // entries of dropped ones are merely never looked up again.
+    static ASSIGNED: RefCell<Vec<(usize, usize)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// A transmit context of a [`TxContextPool`], enabled and bound to its completion queue.
+pub struct TxContext {
+    index: usize,
+    ep: Endpoint,
+    cq: CompletionQueue,
+}
+
+impl TxContext {
+    /// The index of the context in its scalable endpoint.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The context, posting sends, writes and the other transmit operations.
+    pub fn endpoint(&self) -> &Endpoint {
+        &self.ep
+    }
+
+    /// The queue the operations of the context complete on.
+    pub fn cq(&self) -> &CompletionQueue {
+        &self.cq
+    }
+}
+
+enum Cqs {
+    Shared(CompletionQueue),
+    Own(CqAttr),
+}
+
+/// The transmit contexts of a [`ScalableEndpoint`], each thread calling [`get()`](Self::get)
+/// being assigned one of its own, opened on first use. Threads then send in parallel without
+/// locks, and without the application keeping track of context indices.
+///
+/// Threads are assigned the contexts in turn, and keep theirs in a thread local, so that
+/// later calls look it up without synchronizing. Threads beyond the
+/// [`tx_ctx_cnt`](ScalableEndpoint::tx_ctx_cnt) of the endpoint share the contexts, round
+/// robin, as the threading level of `FI_THREAD_SAFE` domains allows. Contexts complete on a
+/// shared queue, with [`new()`](Self::new), or on one of their own, with
+/// [`with_cqs()`](Self::with_cqs), which their thread reads without contending either.
+///
+/// ```no_run
+/// # use libfabric::{Addr, ScalableEndpoint};
+/// # fn run(sep: &ScalableEndpoint, dest: Addr) -> libfabric::Result<()> {
+/// use libfabric::{CqAttr, TxContextPool};
+/// use std::sync::Arc;
+///
+/// let pool = Arc::new(TxContextPool::with_cqs(sep, &CqAttr::new()));
+/// let workers: Vec<_> = (0..4)
+///     .map(|_| {
+///         let pool = pool.clone();
+///         std::thread::spawn(move || {
+///             let tx = pool.get()?;
+///             tx.endpoint().inject(b"hello", dest)
+///         })
+///     })
+///     .collect();
+/// for worker in workers {
+///     worker.join().unwrap()?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct TxContextPool {
+    id: usize,
+    sep: ScalableEndpoint,
+    cqs: Cqs,
+    contexts: Box<[OnceLock<TxContext>]>,
+    next: AtomicUsize,
+    // Serializes the opening of contexts, the only time the pool locks.
+    opening: Mutex<()>,
+}
+
+impl TxContextPool {
+    /// Contexts of `sep` completing on `cq`.
+    pub fn new(sep: &ScalableEndpoint, cq: &CompletionQueue) -> Self {
+        Self::open(sep, Cqs::Shared(cq.clone()))
+    }
+
+    /// Contexts of `sep` each completing on a queue of its own, opened with `attr`.
+    pub fn with_cqs(sep: &ScalableEndpoint, attr: &CqAttr) -> Self {
+        Self::open(sep, Cqs::Own(attr.clone()))
+    }
+
+    fn open(sep: &ScalableEndpoint, cqs: Cqs) -> Self {
+        TxContextPool {
+            id: NEXT_POOL.fetch_add(1, Ordering::Relaxed),
+            sep: sep.clone(),
+            cqs,
+            contexts: (0..sep.tx_ctx_cnt().max(1))
+                .map(|_| OnceLock::new())
+                .collect(),
+            next: AtomicUsize::new(0),
+            opening: Mutex::new(()),
+        }
+    }
+
+    pub fn scalable_endpoint(&self) -> &ScalableEndpoint {
+        &self.sep
+    }
+
+    /// The context of the calling thread, assigned and opened on its first call. Fails when
+    /// the context fails to open, which the next call tries again.
+    pub fn get(&self) -> Result<&TxContext> {
+        let index = ASSIGNED.with(|assigned| {
+            let mut assigned = assigned.borrow_mut();
+            match assigned.iter().find(|(pool, _)| *pool == self.id) {
+                Some(&(_, index)) => index,
+                None => {
+                    let index = self.next.fetch_add(1, Ordering::Relaxed) % self.contexts.len();
+                    assigned.push((self.id, index));
+                    index
+                }
+            }
+        });
+        if let Some(context) = self.contexts[index].get() {
+            return Ok(context);
+        }
+        let _opening = self.opening.lock().unwrap();
+        if let Some(context) = self.contexts[index].get() {
+            return Ok(context);
+        }
+        let cq = match &self.cqs {
+            Cqs::Shared(cq) => cq.clone(),
+            Cqs::Own(attr) => self.sep.domain().cq(attr)?,
+        };
+        let ep = self
+            .sep
+            .tx_context(index, None)?
+            .bind_cq(&cq, BindFlags::TRANSMIT)?
+            .enable()?;
+        Ok(self.contexts[index].get_or_init(|| TxContext { index, ep, cq }))
+    }
+
+    /// The contexts opened so far, ex: for a thread reading all of their queues.
+    pub fn opened(&self) -> impl Iterator<Item = &TxContext> {
+        self.contexts.iter().filter_map(OnceLock::get)
+    }
+}
@@ -0,0 +1,47 @@
+use crate::error::{Error, Result};
+use std::ffi::{CStr, CString};
+use std::mem;
+use std::os::raw::{c_char, c_int};
+use std::time::Duration;
+
+// Convert an optional timeout into libfabric's milliseconds, where -1 waits forever.
+pub(crate) fn timeout_ms(timeout: Option<Duration>) -> c_int {
+    match timeout {
+        Some(t) => t.as_millis().min(c_int::MAX as u128) as c_int,
+        None => -1,
+    }
+}
+
+// Borrow a C string owned by libfabric, treating NULL as empty.
+//
+// SAFETY: `ptr` must be NULL or point to a NUL terminated string that outlives 'a.
+pub(crate) unsafe fn cstr<'a>(ptr: *const c_char) -> &'a str {
+    if ptr.is_null() {
+        return "";
+    }
+    unsafe { CStr::from_ptr(ptr) }.to_str().unwrap_or("")
+}
+
+// A string for libfabric, which must not hold NUL bytes.
+pub(crate) fn cstring(s: &str) -> Result<CString> {
+    CString::new(s).map_err(|_| Error::invalid(format!("{s:?} contains a NUL byte")))
+}
+
+// Read an enum field of a libfabric structure as its raw value.
+//
+// A newer library may store values the generated Rust enum has no variant for, and reading
+// those as the enum itself would be undefined behavior.
+//
+// SAFETY: `ptr` must be valid for reads, and point to a C enum (which are 32 bit wide).
+pub(crate) unsafe fn read_enum<T>(ptr: *const T) -> u32 {
+    debug_assert_eq!(mem::size_of::<T>(), mem::size_of::<u32>());
+    unsafe { ptr.cast::<u32>().read() }
+}
+
+// Write the raw value of an enum field, which may have no variant in the generated Rust enum.
+//
+// SAFETY: `ptr` must be valid for writes, and point to a C enum.
+pub(crate) unsafe fn write_enum<T>(ptr: *mut T, raw: u32) {
+    debug_assert_eq!(mem::size_of::<T>(), mem::size_of::<u32>());
+    unsafe { ptr.cast::<u32>().write(raw) }
+}
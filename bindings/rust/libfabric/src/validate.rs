@@ -0,0 +1,419 @@
+//! A sanitizer of the buffers of the operations in flight, enabled by the `debug-validate`
+//! feature, for the `unsafe` data transfers of [`Endpoint`](crate::Endpoint).
+//!
+//! The buffers handed to an operation are tracked from its post until its completion, or error
+//! completion, is read from a completion queue. Meanwhile, the process aborts with a
+//! diagnostic naming the operation, its context, its buffer and, with `RUST_BACKTRACE` set,
+//! where it was posted, when the buffer is:
+//!
+//! - posted to another operation while either of them writes it, as receives, reads and the
+//!   results of atomics do, which is how a mutable borrow outliving the operation shows,
+//! - modified before the completion of an operation reading it, as sends, writes and the
+//!   sources of atomics do: their buffers are checksummed at post and checked at completion,
+//!   which catches the buffers mutably re-borrowed, and those dropped or moved whose memory was
+//!   reused,
+//! - freed, for buffers of the heap, with [`ValidatingAlloc`] as the global allocator.
+//!
+//! Without the allocator, the checksums read the buffers dropped while in flight, even if
+//! their memory was returned to the system. [`set_violation_handler()`] replaces the abort,
+//! ex: in tests.
+//!
+//! ```no_run
+//! use libfabric::validate::ValidatingAlloc;
+//! use std::alloc::System;
+//!
+//! #[global_allocator]
+//! static ALLOC: ValidatingAlloc<System> = ValidatingAlloc(System);
+//! ```
+//!
+//! Only the operations whose completion is read are tracked: those of endpoints with a
+//! completion queue bound for their direction, with `FI_COMPLETION` if it was bound with
+//! `FI_SELECTIVE_COMPLETION`. Injects, the operations split into several posts and the atomic
+//! message operations are not tracked either. Every post, completion and, with the allocator,
+//! free takes a global lock, which is only meant for debug builds.
+
+use crate::fid::FidId;
+use crate::flags::OpFlags;
+use ofi_libfabric_sys::bindgen as ffi;
+use std::alloc::{GlobalAlloc, Layout};
+use std::backtrace::Backtrace;
+use std::cell::Cell;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+static STATE: Mutex<State> = Mutex::new(State {
+    in_flight: Vec::new(),
+    endpoints: Vec::new(),
+});
+// The buffers in flight, for the allocator to skip the lock while there are none.
+static TRACKED: AtomicUsize = AtomicUsize::new(0);
+static NEXT_TOKEN: AtomicU64 = AtomicU64::new(0);
+static HANDLER: Mutex<fn(&Violation)> = Mutex::new(abort);
+
+thread_local! {
+    // Whether the thread holds the lock of STATE, and so frees without checking.
+    static LOCKED: Cell<bool> = const { Cell::new(false) };
+}
+
+struct State {
+    in_flight: Vec<InFlight>,
+    // The bind flags of the completion queues of each endpoint.
+    endpoints: Vec<(FidId, u64)>,
+}
+
+// The lock of STATE, flagging the thread as holding it.
+struct Locked(MutexGuard<'static, State>);
+
+impl Drop for Locked {
+    fn drop(&mut self) {
+        LOCKED.set(false);
+    }
+}
+
+fn lock() -> Locked {
+    LOCKED.set(true);
+    Locked(STATE.lock().unwrap_or_else(PoisonError::into_inner))
+}
+
+struct InFlight {
+    token: u64,
+    ep: FidId,
+    op: &'static str,
+    context: usize,
+    // The completion flags of the operation, ex: FI_SEND.
+    kind: u64,
+    // Whether the operation is a multi-receive, in flight until the provider releases it.
+    multi: bool,
+    buffers: Vec<Buffer>,
+    posted_at: Backtrace,
+}
+
+// A buffer handed to an operation.
+#[derive(Clone, Copy)]
+pub(crate) struct Buffer {
+    addr: usize,
+    len: usize,
+    writes: bool,
+    // The checksum of the buffers the operation reads.
+    checksum: u64,
+}
+
+impl Buffer {
+    fn overlaps(&self, other: &Buffer) -> bool {
+        self.addr < other.addr + other.len && other.addr < self.addr + self.len
+    }
+
+    // SAFETY: The buffer must be readable.
+    unsafe fn checksum(&self) -> u64 {
+        let bytes = unsafe { std::slice::from_raw_parts(self.addr as *const u8, self.len) };
+        // FNV-1a.
+        bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        })
+    }
+}
+
+// A buffer the operation reads.
+pub(crate) fn reads<T>(buf: &[T]) -> Vec<Buffer> {
+    let mut buffer = Buffer {
+        addr: buf.as_ptr() as usize,
+        len: std::mem::size_of_val(buf),
+        writes: false,
+        checksum: 0,
+    };
+    buffer.checksum = unsafe { buffer.checksum() };
+    vec![buffer]
+}
+
+// A buffer the operation writes.
+pub(crate) fn writes<T>(buf: &[T]) -> Vec<Buffer> {
+    vec![Buffer {
+        addr: buf.as_ptr() as usize,
+        len: std::mem::size_of_val(buf),
+        writes: true,
+        checksum: 0,
+    }]
+}
+
+// The buffers of `iov`, which the operation reads.
+pub(crate) fn reads_iov(iov: &[ffi::iovec]) -> Vec<Buffer> {
+    let bufs = iov
+        .iter()
+        .map(|iov| unsafe { std::slice::from_raw_parts(iov.iov_base as *const u8, iov.iov_len) });
+    bufs.flat_map(reads).collect()
+}
+
+// The buffers of `iov`, which the operation writes.
+pub(crate) fn writes_iov(iov: &[ffi::iovec]) -> Vec<Buffer> {
+    let bufs = iov
+        .iter()
+        .map(|iov| unsafe { std::slice::from_raw_parts(iov.iov_base as *const u8, iov.iov_len) });
+    bufs.flat_map(writes).collect()
+}
+
+// The flags of the completion of `op`.
+fn kind(op: &str) -> u64 {
+    let kind = match op {
+        "fi_recv" | "fi_recvmsg" | "fi_recvv" | "fi_trecv" | "fi_trecvmsg" => ffi::FI_RECV,
+        "fi_read" | "fi_readmsg" | "fi_readv" => ffi::FI_READ,
+        "fi_write" | "fi_writemsg" | "fi_writedata" | "fi_writev" => ffi::FI_WRITE,
+        op if op.contains("atomic") => ffi::FI_ATOMIC,
+        _ => ffi::FI_SEND,
+    };
+    kind as u64
+}
+
+/// How a buffer in flight was misused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// Posted to the operation `op` as well, while one of them writes it.
+    Reposted { op: &'static str },
+    /// Modified before the completion of the operation, which reads it.
+    Modified,
+    /// Freed before the completion of the operation.
+    Freed,
+}
+
+/// The misuse of a buffer in flight, reported to the [handler](set_violation_handler).
+#[derive(Debug)]
+pub struct Violation {
+    pub kind: ViolationKind,
+    /// The operation the buffer is in flight with, ex: `"fi_recv"`.
+    pub op: &'static str,
+    pub context: usize,
+    /// The address and the length of the buffer.
+    pub addr: usize,
+    pub len: usize,
+    /// Where the operation was posted, captured with `RUST_BACKTRACE` set.
+    pub posted_at: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the buffer {:#x}+{} of {} (context {:#x}) ",
+            self.addr, self.len, self.op, self.context
+        )?;
+        match self.kind {
+            ViolationKind::Reposted { op } => write!(f, "was posted to {op}")?,
+            ViolationKind::Modified => write!(f, "was modified")?,
+            ViolationKind::Freed => write!(f, "was freed")?,
+        }
+        write!(f, " before its completion, posted at:\n{}", self.posted_at)
+    }
+}
+
+// The default handler.
+fn abort(violation: &Violation) {
+    eprintln!("libfabric: {violation}");
+    std::process::abort();
+}
+
+/// Report violations to `handler` instead of aborting the process. The operation goes on once
+/// it returns, as if the buffer had not been misused.
+pub fn set_violation_handler(handler: fn(&Violation)) {
+    *HANDLER.lock().unwrap_or_else(PoisonError::into_inner) = handler;
+}
+
+// Report `violations`, with the lock released, so that the handler may post.
+fn report(violations: Vec<Violation>) {
+    if violations.is_empty() {
+        return;
+    }
+    let handler = *HANDLER.lock().unwrap_or_else(PoisonError::into_inner);
+    for violation in &violations {
+        handler(violation);
+    }
+}
+
+fn violation(kind: ViolationKind, op: &InFlight, buffer: &Buffer) -> Violation {
+    Violation {
+        kind,
+        op: op.op,
+        context: op.context,
+        addr: buffer.addr,
+        len: buffer.len,
+        posted_at: op.posted_at.to_string(),
+    }
+}
+
+/// A buffer of an operation in flight, from [`in_flight()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InFlightBuffer {
+    pub op: &'static str,
+    pub context: usize,
+    pub addr: usize,
+    pub len: usize,
+    /// Whether the operation writes the buffer.
+    pub writes: bool,
+}
+
+/// The buffers of the operations in flight, in the order they were posted.
+pub fn in_flight() -> Vec<InFlightBuffer> {
+    let state = lock();
+    (state.0.in_flight.iter())
+        .flat_map(|op| {
+            op.buffers.iter().map(|buffer| InFlightBuffer {
+                op: op.op,
+                context: op.context,
+                addr: buffer.addr,
+                len: buffer.len,
+                writes: buffer.writes,
+            })
+        })
+        .collect()
+}
+
+// Record the bind flags of a completion queue of `ep`.
+pub(crate) fn bound_cq(ep: FidId, flags: u64) {
+    lock().0.endpoints.push((ep, flags));
+}
+
+// Forget the operations of `ep`, which was closed.
+pub(crate) fn closed(ep: FidId) {
+    let mut state = lock();
+    state.0.endpoints.retain(|&(id, _)| id != ep);
+    let before = state.0.in_flight.len();
+    state.0.in_flight.retain(|op| op.ep != ep);
+    TRACKED.fetch_sub(before - state.0.in_flight.len(), Ordering::Relaxed);
+}
+
+// Check the buffers of an operation about to be posted, and track them until its completion if
+// it is reported on a queue, returning the token to untrack them with if the post fails.
+pub(crate) fn track(
+    ep: FidId,
+    op: &'static str,
+    context: usize,
+    flags: Option<OpFlags>,
+    buffers: Vec<Buffer>,
+) -> Option<u64> {
+    let kind = kind(op);
+    let mut state = lock();
+    let mut violations = Vec::new();
+    for posted in &state.0.in_flight {
+        for theirs in &posted.buffers {
+            if let Some(ours) = buffers
+                .iter()
+                .find(|ours| ours.overlaps(theirs) && (ours.writes || theirs.writes))
+            {
+                let kind = ViolationKind::Reposted { op };
+                violations.push(violation(kind, posted, ours));
+            }
+        }
+    }
+    let side = match kind == ffi::FI_RECV as u64 {
+        true => ffi::FI_RECV,
+        false => ffi::FI_TRANSMIT,
+    } as u64;
+    let completes = state.0.endpoints.iter().any(|&(id, bind)| {
+        id == ep
+            && bind & side != 0
+            && (bind & ffi::FI_SELECTIVE_COMPLETION == 0
+                || flags.is_some_and(|flags| flags.contains(OpFlags::COMPLETION)))
+    });
+    let token = completes.then(|| {
+        let token = NEXT_TOKEN.fetch_add(1, Ordering::Relaxed);
+        TRACKED.fetch_add(1, Ordering::Relaxed);
+        state.0.in_flight.push(InFlight {
+            token,
+            ep,
+            op,
+            context,
+            kind,
+            multi: flags.is_some_and(|flags| flags.contains(OpFlags::MULTI_RECV)),
+            buffers,
+            posted_at: Backtrace::capture(),
+        });
+        token
+    });
+    drop(state);
+    report(violations);
+    token
+}
+
+// Untrack the buffers of an operation which failed to post.
+pub(crate) fn untrack(token: Option<u64>) {
+    let Some(token) = token else { return };
+    let mut state = lock();
+    if let Some(i) = state.0.in_flight.iter().position(|op| op.token == token) {
+        state.0.in_flight.remove(i);
+        TRACKED.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// Untrack the oldest operation posted with `context` matching the completion `flags`, if any,
+// checking that the buffers it reads were left untouched.
+pub(crate) fn completed(context: usize, flags: u64) {
+    let mut state = lock();
+    let Some(i) = state
+        .0
+        .in_flight
+        .iter()
+        .position(|op| op.context == context && (flags == 0 || flags & op.kind != 0))
+    else {
+        return;
+    };
+    if state.0.in_flight[i].multi && flags & ffi::FI_MULTI_RECV as u64 == 0 {
+        return;
+    }
+    let op = state.0.in_flight.remove(i);
+    TRACKED.fetch_sub(1, Ordering::Relaxed);
+    drop(state);
+    let modified = op
+        .buffers
+        .iter()
+        .filter(|buffer| !buffer.writes && unsafe { buffer.checksum() } != buffer.checksum);
+    let modified = modified.map(|buffer| violation(ViolationKind::Modified, &op, buffer));
+    report(modified.collect());
+}
+
+// Check that the memory freed at `addr` holds no buffer in flight.
+fn freed(addr: usize, len: usize) {
+    if TRACKED.load(Ordering::Relaxed) == 0 || LOCKED.try_with(Cell::get).unwrap_or(true) {
+        return;
+    }
+    let region = Buffer {
+        addr,
+        len,
+        writes: true,
+        checksum: 0,
+    };
+    let mut state = lock();
+    let mut violations = Vec::new();
+    state.0.in_flight.retain(|op| {
+        let freed = op.buffers.iter().find(|buffer| buffer.overlaps(&region));
+        if let Some(buffer) = freed {
+            violations.push(violation(ViolationKind::Freed, op, buffer));
+        }
+        freed.is_none()
+    });
+    TRACKED.fetch_sub(violations.len(), Ordering::Relaxed);
+    drop(state);
+    report(violations);
+}
+
+/// A global allocator checking that the memory it frees holds no buffer in flight, over the
+/// allocator `A`, ex: [`std::alloc::System`].
+pub struct ValidatingAlloc<A>(pub A);
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for ValidatingAlloc<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        unsafe { self.0.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        freed(ptr as usize, layout.size());
+        unsafe { self.0.dealloc(ptr, layout) }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        unsafe { self.0.alloc_zeroed(layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        freed(ptr as usize, layout.size());
+        unsafe { self.0.realloc(ptr, layout, new_size) }
+    }
+}
@@ -0,0 +1,244 @@
+use crate::error::{Error, Result};
+use crate::info::{Info, InfoEntry, Nic};
+use ofi_libfabric_sys::bindgen as ffi;
+use std::fmt;
+use std::net::Ipv6Addr;
+
+// The address family of InfiniBand addresses on Linux.
+const AF_IB: u16 = 27;
+// The length of `struct sockaddr_ib`.
+const SOCKADDR_IB_LEN: usize = 48;
+// The service id of an unbound InfiniBand address, as libfabric sets it: the RDMA_PS_IB port
+// space, port 0, under a full mask.
+const RDMA_PS_IB_SID: u64 = 0x013f << 16;
+
+/// An InfiniBand address (`struct sockaddr_ib`, `FI_SOCKADDR_IB`): a port of an HCA, through
+/// one of its GIDs, in one partition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IbAddr {
+    pub gid: Ipv6Addr,
+    /// The partition key, with its full membership bit.
+    pub pkey: u16,
+    /// The port of the HCA, from 1.
+    pub port: u8,
+    sid: u64,
+    sid_mask: u64,
+}
+
+impl IbAddr {
+    pub fn new(gid: Ipv6Addr, pkey: u16, port: u8) -> Self {
+        IbAddr {
+            gid,
+            pkey,
+            port,
+            sid: RDMA_PS_IB_SID,
+            sid_mask: u64::MAX,
+        }
+    }
+
+    /// Decode a `struct sockaddr_ib`, ex: the [`src_addr()`](InfoEntry::src_addr) of a verbs
+    /// entry. Addresses of other families, such as those of IPoIB entries, yield `None`.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < SOCKADDR_IB_LEN {
+            return None;
+        }
+        if u16::from_ne_bytes([bytes[0], bytes[1]]) != AF_IB {
+            return None;
+        }
+        let field = |at: usize| <[u8; 8]>::try_from(&bytes[at..at + 8]).unwrap();
+        let gid: [u8; 16] = bytes[8..24].try_into().unwrap();
+        Some(IbAddr {
+            gid: Ipv6Addr::from(gid),
+            pkey: u16::from_be_bytes([bytes[2], bytes[3]]),
+            port: u64::from_ne_bytes(field(40)) as u8,
+            sid: u64::from_be_bytes(field(24)),
+            sid_mask: u64::from_be_bytes(field(32)),
+        })
+    }
+
+    /// Encode the address as a `struct sockaddr_ib`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(SOCKADDR_IB_LEN);
+        bytes.extend(AF_IB.to_ne_bytes());
+        bytes.extend(self.pkey.to_be_bytes());
+        // The flow information.
+        bytes.extend(0u32.to_be_bytes());
+        bytes.extend(self.gid.octets());
+        bytes.extend(self.sid.to_be_bytes());
+        bytes.extend(self.sid_mask.to_be_bytes());
+        bytes.extend((self.port as u64).to_ne_bytes());
+        bytes
+    }
+}
+
+impl fmt::Display for IbAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} port {} pkey {:#06x}", self.gid, self.port, self.pkey)
+    }
+}
+
+/// A verbs domain, as listed by [`verbs_domains()`].
+#[derive(Debug, Clone)]
+pub struct VerbsDomain {
+    /// The name of the domain, ex: `mlx5_0`, or `mlx5_0-dgram` for datagram endpoints.
+    pub domain: String,
+    /// The HCA, ex: `mlx5_0`.
+    pub device: String,
+    /// The port, GID and partition of entries bound to an InfiniBand address, rather than to
+    /// an IP address of the device.
+    pub addr: Option<IbAddr>,
+    pub nic: Option<Nic>,
+    /// The first entry of the domain, to open it from.
+    pub entry: InfoEntry,
+}
+
+/// The verbs domains matching `hints`, which are restricted to the verbs provider, one per
+/// domain name and local address, in the order of `fi_getinfo()`.
+///
+/// On machines with several HCAs, or several ports, this tells which device, port and GID
+/// each domain sends through, to pick those close to the application, ex: with
+/// [`Nic::numa_node()`], then pin the endpoints to them with [`verbs_hints()`].
+pub fn verbs_domains(hints: Info) -> Result<Vec<VerbsDomain>> {
+    let mut domains: Vec<VerbsDomain> = Vec::new();
+    for entry in hints.provider("verbs").get()? {
+        let src = entry.src_addr();
+        let addr = src
+            .as_ref()
+            .and_then(|src| IbAddr::from_bytes(src.as_bytes()));
+        let seen = domains
+            .iter()
+            .any(|domain| domain.domain == entry.domain_name() && domain.entry.src_addr() == src);
+        if seen {
+            continue;
+        }
+        let domain = entry.domain_name().to_owned();
+        let device = match domain.rsplit_once('-') {
+            Some((device, "dgram" | "xrc")) => device.to_owned(),
+            _ => domain.clone(),
+        };
+        domains.push(VerbsDomain {
+            domain,
+            device,
+            addr,
+            nic: entry.nic(),
+            entry,
+        });
+    }
+    Ok(domains)
+}
+
+/// Hints for the verbs domain `domain`, bound to the HCA port, GID and partition of `addr`:
+/// add capabilities and an endpoint type to them.
+pub fn verbs_hints(domain: &str, addr: &IbAddr) -> Info {
+    Info::new()
+        .provider("verbs")
+        .domain_name(domain)
+        .src_addr(ffi::FI_SOCKADDR_IB, &addr.to_bytes())
+}
+
+/// The verbs domains matching `hints` on the HCA `device`, ex: one the application holds
+/// through the ibverbs crate (`Device::name()`), as listed by [`verbs_domains()`].
+///
+/// Both libraries then open the device in the process through the same libibverbs, so that
+/// they share its limits, such as the queue pairs and memory registrations it allows, but
+/// not their protection domains: memory registered through one is not usable by the other.
+///
+/// ```no_run
+/// use libfabric::{ForkSafety, Info, shared_domains};
+///
+/// # fn run(device: &str, port: u8, gid: [u8; 16]) -> libfabric::Result<()> {
+/// // Before either library opens a device.
+/// if !ForkSafety::from_env().is_requested() {
+///     unsafe { ForkSafety::request(false) };
+/// }
+/// // The device, port and GID the application uses through ibverbs
+/// // (`Context::gid()`), which libfabric must send through too.
+/// for domain in shared_domains(Info::new(), device)? {
+///     domain.check_gid(port, gid.into())?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn shared_domains(hints: Info, device: &str) -> Result<Vec<VerbsDomain>> {
+    let mut domains = verbs_domains(hints)?;
+    domains.retain(|domain| domain.device == device);
+    Ok(domains)
+}
+
+impl VerbsDomain {
+    /// Check that the domain sends through `port` and `gid`, ex: those the application uses
+    /// through ibverbs, failing with an argument error telling both apart otherwise. Libfabric
+    /// picks the GID of index `FI_VERBS_GID_IDX`, 0 by default, which must then match the
+    /// index of the application. Domains bound to an IP address rather than a GID fail too:
+    /// open them with [`verbs_hints()`] to pin them to the GID.
+    pub fn check_gid(&self, port: u8, gid: Ipv6Addr) -> Result<()> {
+        let Some(addr) = &self.addr else {
+            return Err(Error::invalid(format!(
+                "domain {} is bound to an IP address rather than a GID, pin it to {gid} port \
+                 {port} with verbs_hints()",
+                self.domain
+            )));
+        };
+        if addr.port == port && addr.gid == gid {
+            return Ok(());
+        }
+        let index = std::env::var("FI_VERBS_GID_IDX").unwrap_or("0".to_owned());
+        Err(Error::invalid(format!(
+            "domain {} sends through {addr}, not {gid} port {port}: libfabric picks GID index \
+             {index} (FI_VERBS_GID_IDX)",
+            self.domain
+        )))
+    }
+}
+
+// The variables libibverbs, and the efa provider, enable fork support with.
+const FORK_SAFE_VARS: [&str; 3] = ["RDMAV_FORK_SAFE", "IBV_FORK_SAFE", "FI_EFA_FORK_SAFE"];
+
+// Whether `var` requests what it stands for, being set to neither an empty value nor `0`.
+fn is_set(var: &str) -> bool {
+    std::env::var_os(var).is_some_and(|value| !value.is_empty() && value != "0")
+}
+
+/// Whether `fork()` is made safe for the memory registered through libibverbs, from
+/// [`ForkSafety::from_env()`].
+///
+/// Fork support is set once per process, by libibverbs, when the first device is opened, for
+/// libfabric and the ibverbs crate alike: both must agree on it before either opens one.
+/// Without it, children of the process may corrupt the registered memory of their parent,
+/// unless the kernel makes it unneeded (Linux 5.13 on, with rdma-core 35).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ForkSafety {
+    /// The variable requesting it, `RDMAV_FORK_SAFE`, `IBV_FORK_SAFE` or `FI_EFA_FORK_SAFE`.
+    pub requested_by: Option<&'static str>,
+    /// Whether memory backed by huge pages is made safe too (`RDMAV_HUGEPAGES_SAFE`), at a
+    /// cost. The efa provider stops using huge pages when fork support is requested instead.
+    pub hugepages_safe: bool,
+}
+
+impl ForkSafety {
+    /// The fork safety the environment of the process requests.
+    pub fn from_env() -> Self {
+        ForkSafety {
+            requested_by: FORK_SAFE_VARS.into_iter().find(|var| is_set(var)),
+            hugepages_safe: is_set("RDMAV_HUGEPAGES_SAFE"),
+        }
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested_by.is_some()
+    }
+
+    /// Request fork safety for the process, through `RDMAV_FORK_SAFE`, and for memory backed
+    /// by huge pages with `hugepages`.
+    ///
+    /// # Safety
+    ///
+    /// As for [`std::env::set_var()`]. Neither libfabric nor ibverbs may have opened a device
+    /// yet, after which the variables take no effect.
+    pub unsafe fn request(hugepages: bool) {
+        unsafe { std::env::set_var("RDMAV_FORK_SAFE", "1") };
+        if hugepages {
+            unsafe { std::env::set_var("RDMAV_HUGEPAGES_SAFE", "1") };
+        }
+    }
+}
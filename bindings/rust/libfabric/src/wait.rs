@@ -0,0 +1,571 @@
+use crate::error::{Error, Result};
+use crate::fabric::Fabric;
+use crate::fid::AsRawFid;
+use ofi_libfabric_sys::bindgen as ffi;
+use std::fmt;
+#[cfg(any(target_os = "linux", all(unix, feature = "async")))]
+use std::io;
+#[cfg(target_os = "linux")]
+use std::mem;
+use std::os::raw::c_int;
+
+/// The object a queue or counter blocks on (`enum fi_wait_obj`), selected with
+/// [`CqAttr::wait_obj()`](crate::CqAttr::wait_obj),
+/// [`EqAttr::wait_obj()`](crate::EqAttr::wait_obj) and
+/// [`CntrAttr::wait_obj()`](crate::CntrAttr::wait_obj), or picked from their `blocking()`,
+/// `pollable()` and `spin()` otherwise.
+///
+/// Providers support a few of them each, see [`supported()`](Self::supported), and fail to
+/// open queues with others. The object decides how to wait: in `sread()` or `wait()` for all
+/// but [`None`](Self::None), through [`wait_fd()`](crate::CompletionQueue::wait_fd) for
+/// [`Fd`](Self::Fd), and [`wait_pollfd()`](crate::CompletionQueue::wait_pollfd) for
+/// [`PollFd`](Self::PollFd).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum WaitObj {
+    /// No wait object: the queue is only read, the default.
+    #[default]
+    None,
+    /// Whichever the provider suits best, ex: a file descriptor or a mutex/condition.
+    Unspec,
+    /// A file descriptor, to block on along with others.
+    Fd,
+    /// A mutex and condition variable, signaled by the provider.
+    MutexCond,
+    /// Yielding the thread in a loop: the lowest wakeup latency, for a busy core.
+    Yield,
+    /// A set of file descriptors, which the provider may change over time.
+    PollFd,
+}
+
+/// What a wait object is opened for, as providers support different ones for each.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WaitTarget {
+    Cq,
+    Eq,
+    Cntr,
+}
+
+impl WaitObj {
+    pub(crate) fn as_raw(self) -> ffi::fi_wait_obj {
+        match self {
+            WaitObj::None => ffi::fi_wait_obj::FI_WAIT_NONE,
+            WaitObj::Unspec => ffi::fi_wait_obj::FI_WAIT_UNSPEC,
+            WaitObj::Fd => ffi::fi_wait_obj::FI_WAIT_FD,
+            WaitObj::MutexCond => ffi::fi_wait_obj::FI_WAIT_MUTEX_COND,
+            WaitObj::Yield => ffi::fi_wait_obj::FI_WAIT_YIELD,
+            WaitObj::PollFd => ffi::fi_wait_obj::FI_WAIT_POLLFD,
+        }
+    }
+
+    // The object a provider wrote back into the attributes, if one of ours (not FI_WAIT_SET).
+    pub(crate) fn from_raw(raw: ffi::fi_wait_obj) -> Option<Self> {
+        [
+            WaitObj::None,
+            WaitObj::Unspec,
+            WaitObj::Fd,
+            WaitObj::MutexCond,
+            WaitObj::Yield,
+            WaitObj::PollFd,
+        ]
+        .into_iter()
+        .find(|obj| obj.as_raw() == raw)
+    }
+
+    // Pick the wait object from the attributes of a queue or counter. A pollable object is
+    // backed by a file descriptor, a spinning one yields the thread in a loop, while a
+    // blocking one lets the provider pick whatever suits it best (ex: a mutex/condition).
+    pub(crate) fn pick(blocking: bool, pollable: bool, spin: bool) -> Self {
+        match (blocking, pollable, spin) {
+            (_, true, _) => WaitObj::Fd,
+            (_, false, true) => WaitObj::Yield,
+            (true, false, false) => WaitObj::Unspec,
+            (false, false, false) => WaitObj::None,
+        }
+    }
+
+    /// Whether threads may block on the object, in `sread()` or `wait()`.
+    pub fn can_block(self) -> bool {
+        self != WaitObj::None
+    }
+
+    /// Whether the object is backed by file descriptors, to poll along with others.
+    pub fn is_pollable(self) -> bool {
+        matches!(self, WaitObj::Fd | WaitObj::PollFd)
+    }
+
+    /// The wait objects `provider` opens `target` with, `None` for providers not known here.
+    /// For layered providers, ex: `tcp;ofi_rxm`, those of the last, which opens the queues.
+    pub fn supported(provider: &str, target: WaitTarget) -> Option<&'static [WaitObj]> {
+        use WaitObj::*;
+        // Those of the utility queues and counters, which most providers build on.
+        const UTIL: &[WaitObj] = &[None, Unspec, Fd, Yield, PollFd];
+        const UTIL_EQ: &[WaitObj] = &[None, Unspec, Fd, MutexCond, Yield, PollFd];
+        const KNOWN: &[&str] = &[
+            "verbs", "efa", "shm", "sm2", "cxi", "psm2", "psm3", "sockets", "tcp", "udp", "net",
+            "ofi_rxm", "ofi_rxd",
+        ];
+        let provider = provider.rsplit(';').next().unwrap_or(provider);
+        if !KNOWN.contains(&provider) {
+            return Option::None;
+        }
+        Some(match (provider, target) {
+            ("verbs", WaitTarget::Cq | WaitTarget::Eq) => &[None, Unspec, Fd, PollFd],
+            ("efa", WaitTarget::Cq) => &[None, Unspec, Fd],
+            ("shm" | "sm2", WaitTarget::Cq | WaitTarget::Cntr) => &[None, Unspec, Yield],
+            ("cxi", WaitTarget::Cq) => &[None, Unspec, Fd, PollFd],
+            ("cxi", WaitTarget::Eq | WaitTarget::Cntr) => &[None, Unspec, Yield],
+            ("psm2" | "psm3", WaitTarget::Cq | WaitTarget::Cntr) => &[None, Unspec, Fd, MutexCond],
+            ("sockets", _) => &[None, Unspec, Fd, MutexCond],
+            (_, WaitTarget::Eq) => UTIL_EQ,
+            _ => UTIL,
+        })
+    }
+
+    // The raw object to open `target` of `provider` with, failing with an argument error
+    // when the provider is known not to support it.
+    pub(crate) fn check(self, provider: &str, target: WaitTarget) -> Result<ffi::fi_wait_obj> {
+        match WaitObj::supported(provider, target) {
+            Some(supported) if !supported.contains(&self) => Err(Error::invalid(format!(
+                "{provider} does not support {self} for {target}, only {}",
+                supported
+                    .iter()
+                    .map(|obj| obj.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))),
+            _ => Ok(self.as_raw()),
+        }
+    }
+}
+
+impl fmt::Display for WaitObj {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            WaitObj::None => "FI_WAIT_NONE",
+            WaitObj::Unspec => "FI_WAIT_UNSPEC",
+            WaitObj::Fd => "FI_WAIT_FD",
+            WaitObj::MutexCond => "FI_WAIT_MUTEX_COND",
+            WaitObj::Yield => "FI_WAIT_YIELD",
+            WaitObj::PollFd => "FI_WAIT_POLLFD",
+        })
+    }
+}
+
+impl fmt::Display for WaitTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            WaitTarget::Cq => "completion queues",
+            WaitTarget::Eq => "event queues",
+            WaitTarget::Cntr => "counters",
+        })
+    }
+}
+
+// The wait object of a queue or counter opened elsewhere, via FI_GETWAITOBJ, taken as
+// FI_WAIT_UNSPEC if the provider does not tell.
+pub(crate) fn wait_obj_of(fid: *mut ffi::fid) -> WaitObj {
+    let mut raw = ffi::fi_wait_obj::FI_WAIT_UNSPEC;
+    let ret = unsafe {
+        ffi::fi_control(
+            fid,
+            ffi::FI_GETWAITOBJ as c_int,
+            (&mut raw as *mut ffi::fi_wait_obj).cast(),
+        )
+    };
+    match ret {
+        0 => WaitObj::from_raw(raw).unwrap_or(WaitObj::Unspec),
+        _ => WaitObj::Unspec,
+    }
+}
+
+// Fetch the file descriptor behind an FI_WAIT_FD wait object, via FI_GETWAIT. Objects of
+// other kinds are refused, rather than reading what FI_GETWAIT writes for them as a descriptor.
+//
+// This is an epoll descriptor on Linux, and a kqueue descriptor on macOS. Both report readable
+// to poll(), epoll or kqueue once the object may have something to read.
+#[cfg(unix)]
+pub(crate) fn wait_fd(fid: *mut ffi::fid, obj: WaitObj) -> Result<std::os::fd::RawFd> {
+    match obj {
+        WaitObj::Fd | WaitObj::Unspec => {}
+        WaitObj::PollFd => {
+            return Err(Error::invalid(
+                "the wait object is FI_WAIT_POLLFD, whose descriptors wait_pollfd() returns",
+            ));
+        }
+        obj => {
+            return Err(Error::invalid(format!(
+                "the wait object is {obj}, not a file descriptor"
+            )));
+        }
+    }
+    let mut fd: c_int = -1;
+    crate::error::check("fi_control", unsafe {
+        ffi::fi_control(
+            fid,
+            ffi::FI_GETWAIT as c_int,
+            (&mut fd as *mut c_int).cast(),
+        )
+    })?;
+    Ok(fd)
+}
+
+/// A descriptor of an [`FI_WAIT_POLLFD`](WaitObj::PollFd) wait object, with the `poll(2)`
+/// events to wait for on it.
+#[cfg(unix)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PollFd {
+    pub fd: std::os::fd::RawFd,
+    pub events: i16,
+}
+
+/// The descriptors of an [`FI_WAIT_POLLFD`](WaitObj::PollFd) wait object, from
+/// `wait_pollfd()`, ex: [`CompletionQueue::wait_pollfd()`](crate::CompletionQueue::wait_pollfd).
+///
+/// Unlike the single descriptor of [`WaitObj::Fd`], the set changes as the provider adds and
+/// removes descriptors, ex: as endpoints are bound to a queue, which it counts in a change
+/// index. Applications keeping the set, ex: registered with a reactor, call
+/// [`refresh()`](Self::refresh) before each wait, which only fetches the descriptors again
+/// when the index moved, and update their registrations when it returns `true`.
+///
+/// ```no_run
+/// # fn run(cq: &libfabric::CompletionQueue) -> libfabric::Result<()> {
+/// let mut fds = cq.wait_pollfd()?;
+/// loop {
+///     if cq.domain().fabric().trywait(&[cq])? {
+///         if fds.refresh(cq)? {
+///             // Register fds.fds() with the reactor again.
+///         }
+///         // Wait for one of them to be readable.
+///     }
+///     // Read the queue.
+/// }
+/// # }
+/// ```
+#[cfg(unix)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PollFdSet {
+    change_index: u64,
+    fds: Vec<PollFd>,
+}
+
+#[cfg(unix)]
+impl PollFdSet {
+    // Fetch the descriptors of `fid` via FI_GETWAIT, refusing objects of other kinds, rather
+    // than reading what FI_GETWAIT writes for them as a set.
+    pub(crate) fn fetch(fid: *mut ffi::fid, obj: WaitObj) -> Result<Self> {
+        if obj != WaitObj::PollFd {
+            return Err(Error::invalid(format!(
+                "the wait object is {obj}, not FI_WAIT_POLLFD"
+            )));
+        }
+        let mut set = PollFdSet::default();
+        set.fetch_from(fid)?;
+        Ok(set)
+    }
+
+    // Fetch the descriptors, growing the array until they fit, as the set may grow in between.
+    fn fetch_from(&mut self, fid: *mut ffi::fid) -> Result<()> {
+        let mut fds = vec![ffi::pollfd::default(); self.fds.len()];
+        loop {
+            let mut pollfd = ffi::fi_wait_pollfd {
+                change_index: 0,
+                nfds: fds.len(),
+                fd: fds.as_mut_ptr(),
+            };
+            let ret = unsafe { get_wait(fid, &mut pollfd) };
+            if ret == -(ffi::FI_ETOOSMALL as c_int) && pollfd.nfds > fds.len() {
+                fds.resize(pollfd.nfds, ffi::pollfd::default());
+                continue;
+            }
+            crate::error::check("fi_control", ret)?;
+            self.change_index = pollfd.change_index;
+            self.fds = fds[..pollfd.nfds]
+                .iter()
+                .map(|pollfd| PollFd {
+                    fd: pollfd.fd,
+                    events: pollfd.events,
+                })
+                .collect();
+            return Ok(());
+        }
+    }
+
+    /// Fetch the descriptors of `obj`, the object they were fetched from, again if they
+    /// changed since, returning whether they did.
+    pub fn refresh(&mut self, obj: &dyn AsRawFid) -> Result<bool> {
+        // No room for descriptors: the provider only reports the change index.
+        let mut pollfd = ffi::fi_wait_pollfd::default();
+        let ret = unsafe { get_wait(obj.as_raw_fid(), &mut pollfd) };
+        if ret != 0 && ret != -(ffi::FI_ETOOSMALL as c_int) {
+            crate::error::check("fi_control", ret)?;
+        }
+        if pollfd.change_index == self.change_index && pollfd.nfds == self.fds.len() {
+            return Ok(false);
+        }
+        self.fetch_from(obj.as_raw_fid())?;
+        Ok(true)
+    }
+
+    /// The changes to the set the provider counted, as of the last fetch.
+    pub fn change_index(&self) -> u64 {
+        self.change_index
+    }
+
+    pub fn fds(&self) -> &[PollFd] {
+        &self.fds
+    }
+
+    pub fn len(&self) -> usize {
+        self.fds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fds.is_empty()
+    }
+}
+
+// FI_GETWAIT into a set of descriptors.
+#[cfg(unix)]
+unsafe fn get_wait(fid: *mut ffi::fid, pollfd: &mut ffi::fi_wait_pollfd) -> c_int {
+    unsafe {
+        ffi::fi_control(
+            fid,
+            ffi::FI_GETWAIT as c_int,
+            (pollfd as *mut ffi::fi_wait_pollfd).cast(),
+        )
+    }
+}
+
+// The descriptors of a wait object, to poll(2) for readability.
+#[cfg(all(unix, feature = "async"))]
+fn pollfds(fid: *mut ffi::fid, obj: WaitObj) -> Result<Vec<ffi::pollfd>> {
+    // From poll.h, the same on Linux and macOS.
+    const POLLIN: i16 = 1;
+    Ok(match obj {
+        WaitObj::PollFd => PollFdSet::fetch(fid, obj)?
+            .fds
+            .iter()
+            .map(|fd| ffi::pollfd {
+                fd: fd.fd,
+                events: fd.events,
+                revents: 0,
+            })
+            .collect(),
+        WaitObj::Fd | WaitObj::Unspec => vec![ffi::pollfd {
+            fd: wait_fd(fid, obj)?,
+            events: POLLIN,
+            revents: 0,
+        }],
+        obj => {
+            return Err(Error::invalid(format!(
+                "the wait object is {obj}, which has no descriptor to wait on asynchronously"
+            )));
+        }
+    })
+}
+
+// Wait for the wait object of `obj` to be readable, with `fi_trywait()` first: a future ready
+// at once if the object has entries to read already, waiting on its descriptors otherwise.
+#[cfg(all(unix, feature = "async"))]
+pub(crate) fn readable(
+    fabric: &Fabric,
+    obj: &dyn AsRawFid,
+    wait: WaitObj,
+    timeout: Option<std::time::Duration>,
+) -> Result<Readable> {
+    let fds = pollfds(obj.as_raw_fid(), wait)?;
+    if !fabric.trywait(&[obj])? {
+        return Ok(Readable::ready());
+    }
+    Ok(Readable::spawn(fds, timeout))
+}
+
+#[cfg(all(unix, feature = "async"))]
+unsafe extern "C" {
+    fn poll(fds: *mut ffi::pollfd, nfds: std::os::raw::c_ulong, timeout: c_int) -> c_int;
+}
+
+// The wait of a thread on descriptors, which wakes the task awaiting it once one is readable
+// or the timeout expired.
+#[cfg(all(unix, feature = "async"))]
+pub(crate) struct Readable {
+    state: std::sync::Arc<std::sync::Mutex<ReadableState>>,
+}
+
+#[cfg(all(unix, feature = "async"))]
+#[derive(Default)]
+struct ReadableState {
+    // Whether a descriptor was readable, false on timeout.
+    readable: Option<Result<bool>>,
+    waker: Option<std::task::Waker>,
+    // Set once the future is dropped, for the thread to stop waiting.
+    dropped: bool,
+}
+
+#[cfg(all(unix, feature = "async"))]
+impl Readable {
+    fn ready() -> Self {
+        let state = ReadableState {
+            readable: Some(Ok(true)),
+            ..Default::default()
+        };
+        Readable {
+            state: std::sync::Arc::new(std::sync::Mutex::new(state)),
+        }
+    }
+
+    fn spawn(mut fds: Vec<ffi::pollfd>, timeout: Option<std::time::Duration>) -> Self {
+        use std::time::{Duration, Instant};
+
+        // How long the thread waits at a time, before checking whether the future was dropped.
+        const SLICE: Duration = Duration::from_millis(100);
+        let state = std::sync::Arc::new(std::sync::Mutex::new(ReadableState::default()));
+        std::thread::spawn({
+            let state = state.clone();
+            move || {
+                let deadline = timeout.map(|timeout| Instant::now() + timeout);
+                let readable = loop {
+                    if state.lock().unwrap().dropped {
+                        return;
+                    }
+                    let left = deadline.map_or(SLICE, |deadline| {
+                        deadline
+                            .saturating_duration_since(Instant::now())
+                            .min(SLICE)
+                    });
+                    let ret = unsafe {
+                        poll(
+                            fds.as_mut_ptr(),
+                            fds.len() as _,
+                            crate::util::timeout_ms(Some(left)),
+                        )
+                    };
+                    match ret {
+                        0 if deadline.is_some_and(|deadline| Instant::now() >= deadline) => {
+                            break Ok(false);
+                        }
+                        ret if ret > 0 => break Ok(true),
+                        ret if ret < 0 => {
+                            let err = io::Error::last_os_error();
+                            if err.kind() != io::ErrorKind::Interrupted {
+                                break Err(err.into());
+                            }
+                        }
+                        _ => {}
+                    }
+                };
+                let mut state = state.lock().unwrap();
+                state.readable = Some(readable);
+                if let Some(waker) = state.waker.take() {
+                    waker.wake();
+                }
+            }
+        });
+        Readable { state }
+    }
+}
+
+#[cfg(all(unix, feature = "async"))]
+impl std::future::Future for Readable {
+    type Output = Result<bool>;
+
+    fn poll(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Self::Output> {
+        let mut state = self.state.lock().unwrap();
+        match state.readable.take() {
+            Some(readable) => std::task::Poll::Ready(readable),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                std::task::Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(all(unix, feature = "async"))]
+impl Drop for Readable {
+    fn drop(&mut self) {
+        self.state.lock().unwrap().dropped = true;
+    }
+}
+
+impl Fabric {
+    /// Check whether the wait file descriptors of `objects` may be blocked on, via
+    /// `fi_trywait()`.
+    ///
+    /// Returns `false` when one of them already has entries to read, in which case they must be
+    /// drained before trying again. Blocking on a descriptor without this check can deadlock,
+    /// as providers may only make progress from within read calls.
+    pub fn trywait(&self, objects: &[&dyn AsRawFid]) -> Result<bool> {
+        let mut fids: Vec<_> = objects.iter().map(|o| o.as_raw_fid()).collect();
+        let ret = unsafe { ffi::fi_trywait(self.as_raw(), fids.as_mut_ptr(), fids.len() as c_int) };
+        match ret {
+            0 => Ok(true),
+            ret if ret == -(ffi::FI_EAGAIN as c_int) => Ok(false),
+            ret => Err(Error::fabric("fi_trywait", ret as i64)),
+        }
+    }
+}
+
+// From sched.h: the affinity masks of glibc and musl hold CPU_SETSIZE (1024) CPUs.
+#[cfg(target_os = "linux")]
+const CPU_SETSIZE: usize = 1024;
+
+#[cfg(target_os = "linux")]
+unsafe extern "C" {
+    fn sched_setaffinity(pid: c_int, size: usize, mask: *const u64) -> c_int;
+    fn sched_getaffinity(pid: c_int, size: usize, mask: *mut u64) -> c_int;
+    fn sched_getcpu() -> c_int;
+}
+
+// The CPU the calling thread runs on, via `sched_getcpu(3)`.
+#[cfg(target_os = "linux")]
+pub(crate) fn current_cpu() -> Option<usize> {
+    usize::try_from(unsafe { sched_getcpu() }).ok()
+}
+
+/// Pin the calling thread to `cpus`, via `sched_setaffinity(2)`: a thread polling completions
+/// is best kept on the cores local to its NIC, its [`Nic::local_cpus()`](crate::Nic::local_cpus),
+/// away from the migrations which add to the jitter of latency critical loops.
+///
+/// ```no_run
+/// # fn run(entry: &libfabric::InfoEntry) -> libfabric::Result<()> {
+/// if let Some(cpus) = entry.nic().and_then(|nic| nic.local_cpus()) {
+///     libfabric::pin_thread(&cpus)?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(target_os = "linux")]
+pub fn pin_thread(cpus: &[usize]) -> Result<()> {
+    if cpus.is_empty() {
+        return Err(Error::invalid("no CPU to pin the thread to"));
+    }
+    let mut mask = [0u64; CPU_SETSIZE / 64];
+    for &cpu in cpus {
+        if cpu >= CPU_SETSIZE {
+            return Err(Error::invalid(format!("CPU {cpu} is past {CPU_SETSIZE}")));
+        }
+        mask[cpu / 64] |= 1 << (cpu % 64);
+    }
+    if unsafe { sched_setaffinity(0, mem::size_of_val(&mask), mask.as_ptr()) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+/// The CPUs the calling thread may run on, via `sched_getaffinity(2)`, ex: to restore them
+/// after [`pin_thread()`].
+#[cfg(target_os = "linux")]
+pub fn thread_affinity() -> Result<Vec<usize>> {
+    let mut mask = [0u64; CPU_SETSIZE / 64];
+    if unsafe { sched_getaffinity(0, mem::size_of_val(&mask), mask.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok((0..CPU_SETSIZE)
+        .filter(|cpu| mask[cpu / 64] & (1 << (cpu % 64)) != 0)
+        .collect())
+}
@@ -0,0 +1,657 @@
+use crate::atomic::{AtomicDatatype, AtomicOp};
+use crate::av::Addr;
+use crate::cntr::{CntrAttr, Counter};
+use crate::domain::Domain;
+use crate::ep::Endpoint;
+use crate::error::{Error, Result, check};
+use crate::fid::AsRawFid;
+use crate::mr::MemoryRegion;
+use ofi_libfabric_sys::bindgen as ffi;
+use std::ffi::c_void;
+use std::mem;
+use std::ptr;
+
+/// An operation the provider runs once a counter reaches a threshold (`struct
+/// fi_deferred_work`), queued with [`Domain::queue_work()`]: a data transfer, or the update of
+/// another counter, which is how chains of deferred work trigger each other.
+///
+/// Transfers read and write registered memory, ranges of a [`MemoryRegion`] given by their
+/// offset and length. Work is built with its operation, then given the counter it is
+/// [`triggered_by()`](Self::triggered_by), and optionally the counter
+/// [`completion()`](Self::completion) increments once it ran. It is canceled when dropped, if
+/// still queued. Requires [`Caps::TRIGGER`](crate::Caps::TRIGGER).
+///
+/// ```no_run
+/// # use libfabric::{Addr, Domain, Endpoint, MemoryRegion};
+/// # fn run(domain: &Domain, ep: &Endpoint, mr: &MemoryRegion, dest: Addr) -> libfabric::Result<()> {
+/// use libfabric::{CntrAttr, DeferredWork};
+///
+/// // Send the region once the application counted 4 events.
+/// let events = domain.counter(&CntrAttr::new())?;
+/// let mut work = DeferredWork::send(ep, mr, 0, mr.len(), dest, 1)?.triggered_by(&events, 4);
+/// unsafe { domain.queue_work(&mut work) }?;
+/// for _ in 0..4 {
+///     events.add(1)?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[must_use]
+pub struct DeferredWork {
+    // Boxed, along with the operation, for the provider to point into while the work is queued.
+    raw: Box<ffi::fi_deferred_work>,
+    _op: Op,
+    trigger: Option<Counter>,
+    // The objects the operation refers to, kept alive with the work.
+    _completion: Option<Counter>,
+    _ep: Option<Endpoint>,
+    _mr: Option<MemoryRegion>,
+    _cntr: Option<Counter>,
+    // The domain the work was last queued on, to cancel it when dropped.
+    queued: Option<Domain>,
+}
+
+// An operation with the arrays it points to, boxed so that they do not move.
+enum Op {
+    Msg(Box<MsgWork>),
+    Rma(Box<RmaWork>),
+    Atomic(Box<AtomicWork>),
+    Cntr(Box<ffi::fi_op_cntr>),
+}
+
+struct MsgWork {
+    iov: ffi::iovec,
+    desc: *mut c_void,
+    op: ffi::fi_op_msg,
+}
+
+struct RmaWork {
+    iov: ffi::iovec,
+    desc: *mut c_void,
+    rma_iov: ffi::fi_rma_iov,
+    op: ffi::fi_op_rma,
+}
+
+struct AtomicWork {
+    ioc: ffi::fi_ioc,
+    desc: *mut c_void,
+    rma_ioc: ffi::fi_rma_ioc,
+    op: ffi::fi_op_atomic,
+}
+
+impl DeferredWork {
+    fn new(
+        op_type: ffi::fi_op_type,
+        op: Op,
+        ep: Option<&Endpoint>,
+        mr: Option<&MemoryRegion>,
+    ) -> Self {
+        let mut raw = Box::new(ffi::fi_deferred_work {
+            op_type,
+            ..Default::default()
+        });
+        match &op {
+            Op::Msg(msg) => raw.op.msg = ptr::from_ref(&msg.op).cast_mut(),
+            Op::Rma(rma) => raw.op.rma = ptr::from_ref(&rma.op).cast_mut(),
+            Op::Atomic(atomic) => raw.op.atomic = ptr::from_ref(&atomic.op).cast_mut(),
+            Op::Cntr(cntr) => raw.op.cntr = ptr::from_ref(&**cntr).cast_mut(),
+        }
+        DeferredWork {
+            raw,
+            _op: op,
+            trigger: None,
+            _completion: None,
+            _ep: ep.cloned(),
+            _mr: mr.cloned(),
+            _cntr: None,
+            queued: None,
+        }
+    }
+
+    /// Receive `len` bytes at `offset` in `mr` from `src`.
+    pub fn recv(
+        ep: &Endpoint,
+        mr: &MemoryRegion,
+        offset: usize,
+        len: usize,
+        src: Addr,
+        context: usize,
+    ) -> Result<Self> {
+        let op = msg_work(ep, range(mr, offset, len)?, len, mr, src, context);
+        Ok(Self::new(
+            ffi::fi_op_type_FI_OP_RECV,
+            Op::Msg(op),
+            Some(ep),
+            Some(mr),
+        ))
+    }
+
+    /// Send `len` bytes at `offset` in `mr` to `dest`.
+    pub fn send(
+        ep: &Endpoint,
+        mr: &MemoryRegion,
+        offset: usize,
+        len: usize,
+        dest: Addr,
+        context: usize,
+    ) -> Result<Self> {
+        let op = msg_work(ep, range(mr, offset, len)?, len, mr, dest, context);
+        Ok(Self::new(
+            ffi::fi_op_type_FI_OP_SEND,
+            Op::Msg(op),
+            Some(ep),
+            Some(mr),
+        ))
+    }
+
+    /// Read remote memory into `len` bytes at `offset` in `mr`, like [`Endpoint::read()`].
+    #[allow(clippy::too_many_arguments)]
+    pub fn read(
+        ep: &Endpoint,
+        mr: &MemoryRegion,
+        offset: usize,
+        len: usize,
+        src: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<Self> {
+        let remote = ffi::fi_rma_iov { addr, len, key };
+        let op = rma_work(ep, range(mr, offset, len)?, mr, src, remote, context);
+        Ok(Self::new(
+            ffi::fi_op_type_FI_OP_READ,
+            Op::Rma(op),
+            Some(ep),
+            Some(mr),
+        ))
+    }
+
+    /// Write `len` bytes at `offset` in `mr` to remote memory, like [`Endpoint::write()`].
+    #[allow(clippy::too_many_arguments)]
+    pub fn write(
+        ep: &Endpoint,
+        mr: &MemoryRegion,
+        offset: usize,
+        len: usize,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+    ) -> Result<Self> {
+        let remote = ffi::fi_rma_iov { addr, len, key };
+        let op = rma_work(ep, range(mr, offset, len)?, mr, dest, remote, context);
+        Ok(Self::new(
+            ffi::fi_op_type_FI_OP_WRITE,
+            Op::Rma(op),
+            Some(ep),
+            Some(mr),
+        ))
+    }
+
+    /// Apply `op` with `count` elements at `offset` in `mr` to remote memory, like
+    /// [`Endpoint::atomic()`].
+    #[allow(clippy::too_many_arguments)]
+    pub fn atomic<T: AtomicDatatype>(
+        ep: &Endpoint,
+        mr: &MemoryRegion,
+        offset: usize,
+        count: usize,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        op: AtomicOp,
+        context: usize,
+    ) -> Result<Self> {
+        let len = count
+            .checked_mul(mem::size_of::<T>())
+            .ok_or_else(|| Error::invalid("atomic count overflows"))?;
+        let mut work = Box::new(AtomicWork {
+            ioc: ffi::fi_ioc {
+                addr: range(mr, offset, len)?.cast(),
+                count,
+            },
+            desc: mr.desc(),
+            rma_ioc: ffi::fi_rma_ioc { addr, count, key },
+            op: Default::default(),
+        });
+        work.op = ffi::fi_op_atomic {
+            ep: ep.as_raw(),
+            msg: ffi::fi_msg_atomic {
+                msg_iov: &work.ioc,
+                desc: &mut work.desc,
+                iov_count: 1,
+                addr: dest.as_raw(),
+                rma_iov: &work.rma_ioc,
+                rma_iov_count: 1,
+                datatype: T::DATATYPE,
+                op: op.as_raw(),
+                context: context as *mut _,
+                data: 0,
+            },
+            flags: 0,
+        };
+        Ok(Self::new(
+            ffi::fi_op_type_FI_OP_ATOMIC,
+            Op::Atomic(work),
+            Some(ep),
+            Some(mr),
+        ))
+    }
+
+    /// Add `value` to `cntr`.
+    pub fn counter_add(cntr: &Counter, value: u64) -> Self {
+        Self::counter(ffi::fi_op_type_FI_OP_CNTR_ADD, cntr, value)
+    }
+
+    /// Set `cntr` to `value`.
+    pub fn counter_set(cntr: &Counter, value: u64) -> Self {
+        Self::counter(ffi::fi_op_type_FI_OP_CNTR_SET, cntr, value)
+    }
+
+    fn counter(op_type: ffi::fi_op_type, cntr: &Counter, value: u64) -> Self {
+        let op = Box::new(ffi::fi_op_cntr {
+            cntr: cntr.as_raw(),
+            value,
+        });
+        let mut work = Self::new(op_type, Op::Cntr(op), None, None);
+        work._cntr = Some(cntr.clone());
+        work
+    }
+
+    /// Run the work once `cntr` reaches `threshold`, which is required before queueing it.
+    pub fn triggered_by(mut self, cntr: &Counter, threshold: u64) -> Self {
+        self.set_trigger(cntr, threshold);
+        self
+    }
+
+    /// Increment `cntr` once the work ran.
+    pub fn completion(mut self, cntr: &Counter) -> Self {
+        self.raw.completion_cntr = cntr.as_raw();
+        self._completion = Some(cntr.clone());
+        self
+    }
+
+    fn set_trigger(&mut self, cntr: &Counter, threshold: u64) {
+        self.raw.triggering_cntr = cntr.as_raw();
+        self.raw.threshold = threshold;
+        self.trigger = Some(cntr.clone());
+    }
+
+    /// The counter triggering the work, if set.
+    pub fn trigger(&self) -> Option<&Counter> {
+        self.trigger.as_ref()
+    }
+
+    pub fn threshold(&self) -> u64 {
+        self.raw.threshold
+    }
+
+    pub fn as_raw(&mut self) -> *mut ffi::fi_deferred_work {
+        &mut *self.raw
+    }
+}
+
+impl Drop for DeferredWork {
+    fn drop(&mut self) {
+        if let Some(domain) = self.queued.take() {
+            let _ = control(&domain, ffi::FI_CANCEL_WORK, self.as_raw());
+        }
+    }
+}
+
+impl Domain {
+    /// Queue `work`, run by the provider once its trigger reaches its threshold, via
+    /// `fi_control(FI_QUEUE_WORK)`.
+    ///
+    /// # Safety
+    ///
+    /// See the [`Endpoint`] documentation: the memory of the operation is in use until it
+    /// completes. The work must not be queued again before it ran, or was canceled.
+    pub unsafe fn queue_work(&self, work: &mut DeferredWork) -> Result<()> {
+        if work.trigger.is_none() {
+            return Err(Error::invalid("deferred work has no triggering counter"));
+        }
+        control(self, ffi::FI_QUEUE_WORK, work.as_raw())?;
+        work.queued = Some(self.clone());
+        Ok(())
+    }
+
+    /// Cancel `work` if it did not run yet, via `fi_control(FI_CANCEL_WORK)`. Work which was not
+    /// queued is left alone.
+    pub fn cancel_work(&self, work: &mut DeferredWork) -> Result<()> {
+        if work.queued.take().is_none() {
+            return Ok(());
+        }
+        control(self, ffi::FI_CANCEL_WORK, work.as_raw())
+    }
+
+    /// Cancel all the work queued on the domain which did not run yet, via
+    /// `fi_control(FI_FLUSH_WORK)`.
+    pub fn flush_work(&self) -> Result<()> {
+        control(self, ffi::FI_FLUSH_WORK, ptr::null_mut())
+    }
+}
+
+/// A node of a [`WorkGraph`], returned when adding its operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WorkNode(usize);
+
+/// A graph of data operations, each started by the provider once the operations it depends on
+/// completed, as [`DeferredWork`]: the dependencies run on the NIC, without the host in
+/// between, ex: a receive, then the reduction of its data into remote memory, then a send.
+///
+/// Each node counts its completion on a counter of its own, which its dependents are triggered
+/// by, through counters joining them when they depend on several nodes.
+/// [`queue()`](Self::queue) queues the whole graph, then starts the nodes depending on none;
+/// the completions of the operations are also reported as usual, with their contexts, on the
+/// completion queues of their endpoints. Requires [`Caps::TRIGGER`](crate::Caps::TRIGGER).
+///
+/// Operations read and write registered memory, ranges of a [`MemoryRegion`] given by their
+/// offset and length, so that the buffer a receive writes may be the one a later node reads.
+///
+/// ```no_run
+/// # use libfabric::{Addr, Domain, Endpoint, MemoryRegion};
+/// # fn run(domain: &Domain, ep: &Endpoint, mr: &MemoryRegion, peer: Addr, addr: u64, key: u64) -> libfabric::Result<()> {
+/// use libfabric::{AtomicOp, WorkGraph};
+/// use std::time::Duration;
+///
+/// // Receive 8 values into the region, add them to the memory of the peer, then notify it.
+/// let mut graph = WorkGraph::new(domain)?;
+/// let recv = graph.recv(ep, mr, 0, 64, peer, 1, &[])?;
+/// let reduce = graph.atomic::<u64>(ep, mr, 0, 8, peer, addr, key, AtomicOp::Sum, 2, &[recv])?;
+/// let notify = graph.send(ep, mr, 64, 1, peer, 3, &[reduce])?;
+/// unsafe { graph.queue() }?;
+/// graph.counter(notify).wait(1, Some(Duration::from_secs(1)))?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct WorkGraph {
+    domain: Domain,
+    nodes: Vec<Node>,
+    // Started once the graph is queued, triggering the nodes depending on none.
+    start: Counter,
+    // The additions to the joins, as last queued.
+    adds: Vec<DeferredWork>,
+}
+
+struct Node {
+    work: DeferredWork,
+    deps: Vec<usize>,
+    // The completion of the operation, and the dependencies of one depending on several nodes.
+    done: Counter,
+    join: Option<Counter>,
+}
+
+impl WorkGraph {
+    /// An empty graph of operations on the endpoints of `domain`.
+    pub fn new(domain: &Domain) -> Result<Self> {
+        Ok(WorkGraph {
+            domain: domain.clone(),
+            nodes: Vec::new(),
+            start: domain.counter(&CntrAttr::new())?,
+            adds: Vec::new(),
+        })
+    }
+
+    /// Receive `len` bytes at `offset` in `mr` from `src`, once the nodes of `after` completed.
+    #[allow(clippy::too_many_arguments)]
+    pub fn recv(
+        &mut self,
+        ep: &Endpoint,
+        mr: &MemoryRegion,
+        offset: usize,
+        len: usize,
+        src: Addr,
+        context: usize,
+        after: &[WorkNode],
+    ) -> Result<WorkNode> {
+        self.check(after)?;
+        self.add(
+            DeferredWork::recv(ep, mr, offset, len, src, context)?,
+            after,
+        )
+    }
+
+    /// Send `len` bytes at `offset` in `mr` to `dest`, once the nodes of `after` completed.
+    #[allow(clippy::too_many_arguments)]
+    pub fn send(
+        &mut self,
+        ep: &Endpoint,
+        mr: &MemoryRegion,
+        offset: usize,
+        len: usize,
+        dest: Addr,
+        context: usize,
+        after: &[WorkNode],
+    ) -> Result<WorkNode> {
+        self.check(after)?;
+        self.add(
+            DeferredWork::send(ep, mr, offset, len, dest, context)?,
+            after,
+        )
+    }
+
+    /// Read remote memory into `len` bytes at `offset` in `mr`, like [`Endpoint::read()`], once
+    /// the nodes of `after` completed.
+    #[allow(clippy::too_many_arguments)]
+    pub fn read(
+        &mut self,
+        ep: &Endpoint,
+        mr: &MemoryRegion,
+        offset: usize,
+        len: usize,
+        src: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+        after: &[WorkNode],
+    ) -> Result<WorkNode> {
+        self.check(after)?;
+        let work = DeferredWork::read(ep, mr, offset, len, src, addr, key, context)?;
+        self.add(work, after)
+    }
+
+    /// Write `len` bytes at `offset` in `mr` to remote memory, like [`Endpoint::write()`], once
+    /// the nodes of `after` completed.
+    #[allow(clippy::too_many_arguments)]
+    pub fn write(
+        &mut self,
+        ep: &Endpoint,
+        mr: &MemoryRegion,
+        offset: usize,
+        len: usize,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        context: usize,
+        after: &[WorkNode],
+    ) -> Result<WorkNode> {
+        self.check(after)?;
+        let work = DeferredWork::write(ep, mr, offset, len, dest, addr, key, context)?;
+        self.add(work, after)
+    }
+
+    /// Apply `op` with `count` elements at `offset` in `mr` to remote memory, like
+    /// [`Endpoint::atomic()`], once the nodes of `after` completed: the reduction of received
+    /// data, for one.
+    #[allow(clippy::too_many_arguments)]
+    pub fn atomic<T: AtomicDatatype>(
+        &mut self,
+        ep: &Endpoint,
+        mr: &MemoryRegion,
+        offset: usize,
+        count: usize,
+        dest: Addr,
+        addr: u64,
+        key: u64,
+        op: AtomicOp,
+        context: usize,
+        after: &[WorkNode],
+    ) -> Result<WorkNode> {
+        self.check(after)?;
+        let work = DeferredWork::atomic::<T>(ep, mr, offset, count, dest, addr, key, op, context)?;
+        self.add(work, after)
+    }
+
+    fn check(&self, after: &[WorkNode]) -> Result<()> {
+        match after.iter().find(|node| node.0 >= self.nodes.len()) {
+            Some(node) => Err(Error::invalid(format!("{node:?} is not in the graph"))),
+            None => Ok(()),
+        }
+    }
+
+    fn add(&mut self, work: DeferredWork, after: &[WorkNode]) -> Result<WorkNode> {
+        let join = match after.len() {
+            0 | 1 => None,
+            _ => Some(self.domain.counter(&CntrAttr::new())?),
+        };
+        let done = self.domain.counter(&CntrAttr::new())?;
+        self.nodes.push(Node {
+            work: work.completion(&done),
+            deps: after.iter().map(|node| node.0).collect(),
+            done,
+            join,
+        });
+        Ok(WorkNode(self.nodes.len() - 1))
+    }
+
+    /// The counter of the completion of `node`, 1 once its operation completed.
+    pub fn counter(&self, node: WorkNode) -> &Counter {
+        &self.nodes[node.0].done
+    }
+
+    /// Queue the operations of the graph, dependencies first, each triggered by the counter of
+    /// the node it depends on, or by one joining those it depends on; then start the nodes
+    /// depending on none. The counters are reset first, so the graph may be queued again once
+    /// all of its operations completed.
+    ///
+    /// # Safety
+    ///
+    /// See [`Domain::queue_work()`]: the graph must not be queued again before all of its
+    /// operations completed.
+    pub unsafe fn queue(&mut self) -> Result<()> {
+        self.adds.clear();
+        self.start.set(0)?;
+        for node in &self.nodes {
+            node.done.set(0)?;
+            if let Some(join) = &node.join {
+                join.set(0)?;
+            }
+        }
+        for i in 0..self.nodes.len() {
+            let (trigger, threshold) = match (&self.nodes[i].deps[..], &self.nodes[i].join) {
+                ([], _) => (self.start.clone(), 1),
+                (&[dep], _) => (self.nodes[dep].done.clone(), 1),
+                (deps, Some(join)) => {
+                    // Each dependency adds to the join once it completed.
+                    for &dep in deps {
+                        let mut add = DeferredWork::counter_add(join, 1)
+                            .triggered_by(&self.nodes[dep].done, 1);
+                        unsafe { self.domain.queue_work(&mut add) }?;
+                        self.adds.push(add);
+                    }
+                    (join.clone(), deps.len() as u64)
+                }
+                (_, None) => unreachable!("nodes with several dependencies have a join"),
+            };
+            let node = &mut self.nodes[i];
+            node.work.set_trigger(&trigger, threshold);
+            unsafe { self.domain.queue_work(&mut node.work) }?;
+        }
+        self.start.add(1)
+    }
+
+    /// Cancel the queued operations which did not run yet, via `fi_control(FI_CANCEL_WORK)`.
+    pub fn cancel(&mut self) -> Result<()> {
+        for node in self.nodes.iter_mut().rev() {
+            self.domain.cancel_work(&mut node.work)?;
+        }
+        for add in &mut self.adds {
+            self.domain.cancel_work(add)?;
+        }
+        Ok(())
+    }
+}
+
+// The address of `len` bytes at `offset` in `mr`, which must hold them.
+fn range(mr: &MemoryRegion, offset: usize, len: usize) -> Result<*mut u8> {
+    match offset.checked_add(len) {
+        Some(end) if end <= mr.len() => Ok(mr.addr().wrapping_add(offset)),
+        _ => Err(Error::invalid(format!(
+            "{len} bytes at offset {offset} are past the {} bytes of the region",
+            mr.len()
+        ))),
+    }
+}
+
+fn msg_work(
+    ep: &Endpoint,
+    buf: *mut u8,
+    len: usize,
+    mr: &MemoryRegion,
+    peer: Addr,
+    context: usize,
+) -> Box<MsgWork> {
+    let mut work = Box::new(MsgWork {
+        iov: ffi::iovec {
+            iov_base: buf.cast(),
+            iov_len: len,
+        },
+        desc: mr.desc(),
+        op: Default::default(),
+    });
+    work.op = ffi::fi_op_msg {
+        ep: ep.as_raw(),
+        msg: ffi::fi_msg {
+            msg_iov: &work.iov,
+            desc: &mut work.desc,
+            iov_count: 1,
+            addr: peer.as_raw(),
+            context: context as *mut _,
+            data: 0,
+        },
+        flags: 0,
+    };
+    work
+}
+
+fn rma_work(
+    ep: &Endpoint,
+    buf: *mut u8,
+    mr: &MemoryRegion,
+    peer: Addr,
+    remote: ffi::fi_rma_iov,
+    context: usize,
+) -> Box<RmaWork> {
+    let mut work = Box::new(RmaWork {
+        iov: ffi::iovec {
+            iov_base: buf.cast(),
+            iov_len: remote.len,
+        },
+        desc: mr.desc(),
+        rma_iov: remote,
+        op: Default::default(),
+    });
+    work.op = ffi::fi_op_rma {
+        ep: ep.as_raw(),
+        msg: ffi::fi_msg_rma {
+            msg_iov: &work.iov,
+            desc: &mut work.desc,
+            iov_count: 1,
+            addr: peer.as_raw(),
+            rma_iov: &work.rma_iov,
+            rma_iov_count: 1,
+            context: context as *mut _,
+            data: 0,
+        },
+        flags: 0,
+    };
+    work
+}
+
+// A deferred work control of the domain, through `fi_control()`.
+fn control(domain: &Domain, command: u32, work: *mut ffi::fi_deferred_work) -> Result<()> {
+    check("fi_control", unsafe {
+        ffi::fi_control(domain.as_raw_fid(), command as i32, work.cast())
+    })
+}
@@ -0,0 +1,191 @@
+//! Operations triggered by a GPU: posted by the host ahead of time, then started by a kernel
+//! writing to a trigger variable, without returning control to the host (`FI_TRIGGER_XPU`).
+//!
+//! An [`XpuTrigger`] is the triggered context an operation is posted with, along with
+//! [`OpFlags::TRIGGER`](crate::OpFlags::TRIGGER), through any of the `*_with_flags()` posts.
+//! The provider fills in its [variables](XpuTrigger::vars) during the post: the address of
+//! each, in memory the device can write, and the value to write there. Once a kernel wrote all
+//! of them, the operation starts, and completes as usual, with the
+//! [`context()`](XpuTrigger::context) of the trigger.
+//!
+//! Endpoints must have been opened with XPU trigger support, which no provider of libfabric
+//! 2.x advertises any longer (`FI_XPU_TRIGGER` is a reserved capability bit): posts then fail
+//! with the error of the provider, typically `FI_EINVAL` or `FI_ENOSYS`.
+//!
+//! ```no_run
+//! # use libfabric::{Addr, Endpoint, MemoryRegion};
+//! # fn run(ep: &Endpoint, buf: &[u8], mr: &MemoryRegion, dest: Addr) -> libfabric::Result<()> {
+//! use libfabric::OpFlags;
+//! use libfabric::xpu::{XpuDevice, XpuTrigger};
+//!
+//! # let device: XpuDevice = unimplemented!();
+//! let trigger = XpuTrigger::new(device, 1);
+//! unsafe { ep.send_with_flags(buf, Some(mr), dest, trigger.context(), OpFlags::TRIGGER) }?;
+//! for var in trigger.vars() {
+//!     // Handed to the kernel, which writes `var.value()` to `var.addr()` when done.
+//!     println!("{:?} <- {:?}", var.addr(), var.value());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use ofi_libfabric_sys::bindgen as ffi;
+use std::ffi::c_void;
+use std::ptr;
+
+/// The device whose kernels write the trigger variables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XpuDevice {
+    /// A CUDA device, by ordinal.
+    #[cfg(feature = "cuda")]
+    Cuda(i32),
+    /// A Level Zero device, by index within the devices of its driver.
+    #[cfg(feature = "ze")]
+    Ze { driver: u16, device: u16 },
+}
+
+impl XpuDevice {
+    fn raw(self) -> (ffi::fi_hmem_iface, ffi::fi_trigger_xpu__bindgen_ty_1) {
+        match self {
+            #[cfg(feature = "cuda")]
+            XpuDevice::Cuda(ordinal) => (
+                ffi::fi_hmem_iface::FI_HMEM_CUDA,
+                ffi::fi_trigger_xpu__bindgen_ty_1 { cuda: ordinal },
+            ),
+            // As fi_hmem_ze_device() encodes them.
+            #[cfg(feature = "ze")]
+            XpuDevice::Ze { driver, device } => (
+                ffi::fi_hmem_iface::FI_HMEM_ZE,
+                ffi::fi_trigger_xpu__bindgen_ty_1 {
+                    ze: (i32::from(driver) << 16) | i32::from(device),
+                },
+            ),
+        }
+    }
+
+    // The interface and device of memory registrations.
+    pub(crate) fn mr_device(self) -> (ffi::fi_hmem_iface, ffi::fi_mr_attr__bindgen_ty_2) {
+        match self {
+            #[cfg(feature = "cuda")]
+            XpuDevice::Cuda(ordinal) => (
+                ffi::fi_hmem_iface::FI_HMEM_CUDA,
+                ffi::fi_mr_attr__bindgen_ty_2 { cuda: ordinal },
+            ),
+            #[cfg(feature = "ze")]
+            XpuDevice::Ze { .. } => (
+                ffi::fi_hmem_iface::FI_HMEM_ZE,
+                ffi::fi_mr_attr__bindgen_ty_2 {
+                    ze: unsafe { self.raw().1.ze },
+                },
+            ),
+        }
+    }
+}
+
+/// The triggered context of an operation started by a device, and its trigger variables.
+///
+/// The context is read by the provider until the operation completes, so the trigger must not
+/// be dropped, nor posted with again, before that.
+pub struct XpuTrigger {
+    // Boxed, so that their addresses, which the provider keeps, are stable.
+    raw: Box<ffi::fi_triggered_context2>,
+    vars: Box<[ffi::fi_trigger_var]>,
+}
+
+// SAFETY: The raw pointers are to the variables the trigger owns, and to device memory only
+// the provider and the device access.
+unsafe impl Send for XpuTrigger {}
+unsafe impl Sync for XpuTrigger {}
+
+impl XpuTrigger {
+    /// A trigger of `count` variables, all written by kernels of `device`.
+    pub fn new(device: XpuDevice, count: usize) -> Self {
+        let mut vars: Box<[ffi::fi_trigger_var]> = (0..count).map(|_| Default::default()).collect();
+        let (iface, device) = device.raw();
+        let mut raw = Box::new(ffi::fi_triggered_context2 {
+            event_type: ffi::fi_trigger_event_FI_TRIGGER_XPU,
+            ..Default::default()
+        });
+        raw.trigger.xpu = ffi::fi_trigger_xpu {
+            count: count as i32,
+            iface,
+            device,
+            var: if count == 0 {
+                ptr::null_mut()
+            } else {
+                vars.as_mut_ptr()
+            },
+        };
+        XpuTrigger { raw, vars }
+    }
+
+    /// The context to post the operation with, which its completion then reports.
+    pub fn context(&self) -> usize {
+        ptr::from_ref(&*self.raw) as usize
+    }
+
+    /// The trigger variables, filled in by the provider once the operation is posted.
+    pub fn vars(&self) -> impl ExactSizeIterator<Item = TriggerVar<'_>> {
+        self.vars.iter().map(|raw| TriggerVar { raw })
+    }
+}
+
+/// A variable an [`XpuTrigger`] waits for the device to write.
+#[derive(Clone, Copy)]
+pub struct TriggerVar<'a> {
+    raw: &'a ffi::fi_trigger_var,
+}
+
+impl TriggerVar<'_> {
+    /// Where the device writes the value, null until the operation is posted.
+    pub fn addr(&self) -> *mut c_void {
+        self.raw.addr
+    }
+
+    pub fn datatype(&self) -> ffi::fi_datatype {
+        self.raw.datatype
+    }
+
+    /// The elements of the datatype the value is made of.
+    pub fn count(&self) -> usize {
+        self.raw.count.max(0) as usize
+    }
+
+    /// The bytes the device writes, in the representation of their datatype. Values of up to 8
+    /// bytes are held by the variable, larger ones by the provider, which the slice borrows.
+    pub fn value(&self) -> &[u8] {
+        let len = datatype_size(self.raw.datatype).unwrap_or(0) * self.count();
+        if len <= 8 {
+            // The fields of the union all start at its first byte.
+            unsafe { std::slice::from_raw_parts(ptr::from_ref(&self.raw.value).cast(), len) }
+        } else if unsafe { self.raw.value.data }.is_null() {
+            &[]
+        } else {
+            unsafe { std::slice::from_raw_parts(self.raw.value.data, len) }
+        }
+    }
+}
+
+impl std::fmt::Debug for TriggerVar<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TriggerVar")
+            .field("addr", &self.addr())
+            .field("datatype", &self.datatype())
+            .field("count", &self.count())
+            .field("value", &self.value())
+            .finish()
+    }
+}
+
+// The size of the integer and floating point datatypes trigger variables are made of.
+fn datatype_size(datatype: ffi::fi_datatype) -> Option<usize> {
+    use ffi::fi_datatype::*;
+    Some(match datatype {
+        FI_INT8 | FI_UINT8 => 1,
+        FI_INT16 | FI_UINT16 => 2,
+        FI_INT32 | FI_UINT32 | FI_FLOAT => 4,
+        FI_INT64 | FI_UINT64 | FI_DOUBLE => 8,
+        FI_INT128 | FI_UINT128 => 16,
+        _ => return None,
+    })
+}
@@ -0,0 +1,175 @@
+//! Wire compatibility of the pingpong binary with the C fabtests run with `-E`: the Rust client
+//! runs against the servers of fi_rdm_pingpong and fi_rdm_bw, and their clients against the
+//! Rust server, over the tcp and shm providers.
+//!
+//! The tests run once `FABTESTS_BIN` names the directory of the fabtests binaries, and pass
+//! without running otherwise:
+//!
+//!     FABTESTS_BIN=/usr/local/bin cargo test --test fabtests
+//!
+//! `FABTESTS_PROVIDERS` overrides the providers, a comma separated list, `tcp,shm` by default.
+
+#[cfg(test)]
+mod fabtests_tests {
+    use std::net::TcpListener;
+    use std::path::{Path, PathBuf};
+    use std::process::{Child, Command, Stdio};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    const PINGPONG: &str = env!("CARGO_BIN_EXE_pingpong");
+    const SERVER: &str = "127.0.0.1";
+    const ITERATIONS: &str = "100";
+    const WARMUP: &str = "10";
+
+    #[derive(Clone, Copy)]
+    enum Test {
+        Pingpong,
+        Bandwidth,
+    }
+
+    impl Test {
+        fn fabtest(self) -> &'static str {
+            match self {
+                Test::Pingpong => "fi_rdm_pingpong",
+                Test::Bandwidth => "fi_rdm_bw",
+            }
+        }
+
+        fn size(self) -> &'static str {
+            match self {
+                Test::Pingpong => "64",
+                Test::Bandwidth => "8192",
+            }
+        }
+    }
+
+    /// The directory of the fabtests binaries, and the providers to test, if enabled.
+    fn fabtests() -> Option<(PathBuf, Vec<String>)> {
+        let Some(dir) = std::env::var_os("FABTESTS_BIN") else {
+            eprintln!("FABTESTS_BIN not set, skipping");
+            return None;
+        };
+        let providers = std::env::var("FABTESTS_PROVIDERS").unwrap_or("tcp,shm".to_owned());
+        Some((
+            PathBuf::from(dir),
+            providers.split(',').map(str::to_owned).collect(),
+        ))
+    }
+
+    /// A port nothing listens on, for the out of band exchange.
+    fn free_port() -> u16 {
+        let listener = TcpListener::bind((SERVER, 0)).unwrap();
+        listener.local_addr().unwrap().port()
+    }
+
+    fn rust(test: Test, provider: &str, port: u16, client: bool) -> Command {
+        let mut command = Command::new(PINGPONG);
+        command.args(["-E", "-p", provider, "-B", &port.to_string()]);
+        command.args(["-S", test.size(), "-I", ITERATIONS, "-W", WARMUP]);
+        if let Test::Bandwidth = test {
+            command.args(["-t", "bw", "-w", "16"]);
+        }
+        if client {
+            command.arg(SERVER);
+        }
+        command
+    }
+
+    fn c(dir: &Path, test: Test, provider: &str, port: u16, client: bool) -> Command {
+        let mut command = Command::new(dir.join(test.fabtest()));
+        command.args(["-p", provider, &format!("-E={port}")]);
+        command.args(["-S", test.size(), "-I", ITERATIONS, "-w", WARMUP]);
+        if let Test::Bandwidth = test {
+            command.args(["-W", "16"]);
+        }
+        if client {
+            command.arg(SERVER);
+        }
+        command
+    }
+
+    // Wait for `child`, killing it once `deadline` passed.
+    fn finish(name: &str, mut child: Child, deadline: Instant) -> String {
+        while child.try_wait().unwrap().is_none() {
+            if Instant::now() > deadline {
+                child.kill().unwrap();
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let output = child.wait_with_output().unwrap();
+        let report = format!(
+            "{name}: {}\n{}{}",
+            output.status,
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert!(output.status.success(), "{report}");
+        report
+    }
+
+    /// Run `server`, then `client` once the server had time to listen, both to completion.
+    fn run_pair(mut server: Command, mut client: Command) {
+        let piped = |command: &mut Command| {
+            command
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .unwrap()
+        };
+        let server = piped(&mut server);
+        // The C clients connect out of band once, without retrying.
+        thread::sleep(Duration::from_secs(1));
+        let client = piped(&mut client);
+        let deadline = Instant::now() + Duration::from_secs(60);
+        let client = finish("client", client, deadline);
+        let server = finish("server", server, deadline);
+        eprintln!("{client}{server}");
+    }
+
+    fn run(test: Test, rust_client: bool) {
+        let Some((dir, providers)) = fabtests() else {
+            return;
+        };
+        for provider in &providers {
+            let port = free_port();
+            let (server, client) = match rust_client {
+                true => (
+                    c(&dir, test, provider, port, false),
+                    rust(test, provider, port, true),
+                ),
+                false => (
+                    rust(test, provider, port, false),
+                    c(&dir, test, provider, port, true),
+                ),
+            };
+            run_pair(server, client);
+        }
+    }
+
+    /// The Rust client completes the round trips of fi_rdm_pingpong.
+    #[test]
+    fn test_pingpong_rust_client() {
+        run(Test::Pingpong, true);
+    }
+
+    /// The client of fi_rdm_pingpong completes its round trips with the Rust server.
+    #[test]
+    fn test_pingpong_c_client() {
+        run(Test::Pingpong, false);
+    }
+
+    /// The Rust client streams windows of messages to fi_rdm_bw, which acknowledges them.
+    #[test]
+    fn test_bw_rust_client() {
+        run(Test::Bandwidth, true);
+    }
+
+    /// The client of fi_rdm_bw streams windows of messages to the Rust server, which
+    /// acknowledges them.
+    #[test]
+    fn test_bw_c_client() {
+        run(Test::Bandwidth, false);
+    }
+}
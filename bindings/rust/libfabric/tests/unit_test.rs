@@ -0,0 +1,5421 @@
+#[cfg(test)]
+mod unit_tests {
+    use libfabric::*;
+
+    // Counts the allocations of each thread, for the tests of allocation free paths.
+    struct CountingAlloc;
+
+    thread_local! {
+        static ALLOCATIONS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    }
+
+    unsafe impl std::alloc::GlobalAlloc for CountingAlloc {
+        unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+            ALLOCATIONS.with(|n| n.set(n.get() + 1));
+            unsafe { std::alloc::System.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+            unsafe { std::alloc::System.dealloc(ptr, layout) }
+        }
+    }
+
+    #[global_allocator]
+    static GLOBAL: CountingAlloc = CountingAlloc;
+
+    fn allocations() -> usize {
+        ALLOCATIONS.with(|n| n.get())
+    }
+
+    /// Hints for the tcp provider, which is available on every Linux and macOS host.
+    fn tcp_hints() -> Info {
+        Info::new()
+            .caps(Caps::MSG)
+            .ep_type(EndpointType::Rdm)
+            .provider("tcp")
+    }
+
+    /// The first entry of [`tcp_hints()`].
+    fn tcp_entry() -> InfoEntry {
+        tcp_hints().get().unwrap()[0].clone()
+    }
+
+    // An enabled endpoint, with the domain it was opened in and the objects bound to it.
+    struct MsgEndpoint {
+        ep: Endpoint,
+        cq: CompletionQueue,
+        av: AddressVector,
+        domain: Domain,
+    }
+
+    /// An endpoint of the first entry of [`tcp_hints()`], enabled.
+    fn enabled_msg_endpoint() -> MsgEndpoint {
+        enabled_endpoint(tcp_hints())
+    }
+
+    /// An endpoint of the first entry of `hints`, enabled in a domain of its own.
+    fn enabled_endpoint(hints: Info) -> MsgEndpoint {
+        let entries = hints.get().unwrap();
+        let fabric = Fabric::open(&entries[0]).unwrap();
+        let domain = fabric.domain(&entries[0]).unwrap();
+        let (ep, cq, av) = bound_endpoint(&domain, &entries[0]);
+        MsgEndpoint { ep, cq, av, domain }
+    }
+
+    /// An endpoint of `entry` in `domain`, enabled with a completion queue for transmits and
+    /// receives, and an address vector.
+    fn bound_endpoint(
+        domain: &Domain,
+        entry: &InfoEntry,
+    ) -> (Endpoint, CompletionQueue, AddressVector) {
+        let cq = domain.cq(&CqAttr::new()).unwrap();
+        let av = domain.av(&AvAttr::new()).unwrap();
+        let ep = domain
+            .endpoint(entry)
+            .unwrap()
+            .bind_cq(&cq, BindFlags::TRANSMIT | BindFlags::RECV)
+            .unwrap()
+            .bind_av(&av)
+            .unwrap()
+            .enable()
+            .unwrap();
+        (ep, cq, av)
+    }
+
+    // A subscriber recording the target, name and fields of every span and event, events being
+    // named by their message.
+    #[cfg(feature = "tracing")]
+    #[derive(Clone, Default)]
+    struct Capture(std::sync::Arc<std::sync::Mutex<Vec<Traced>>>);
+
+    #[cfg(feature = "tracing")]
+    #[derive(Debug, Default)]
+    struct Traced {
+        target: String,
+        name: String,
+        fields: std::collections::HashMap<&'static str, String>,
+    }
+
+    #[cfg(feature = "tracing")]
+    impl Capture {
+        // The spans and events recorded under `target` named `name`.
+        fn find(
+            &self,
+            target: &str,
+            name: &str,
+        ) -> Vec<std::collections::HashMap<&'static str, String>> {
+            let traced = self.0.lock().unwrap();
+            traced
+                .iter()
+                .filter(|t| t.target == target && t.name == name)
+                .map(|t| t.fields.clone())
+                .collect()
+        }
+
+        fn push(&self, metadata: &tracing::Metadata<'_>, mut traced: Traced) {
+            traced.target = metadata.target().to_string();
+            if let Some(message) = traced.fields.remove("message") {
+                traced.name = message;
+            }
+            self.0.lock().unwrap().push(traced);
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    impl tracing::field::Visit for Traced {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.fields.insert(field.name(), format!("{value:?}"));
+        }
+
+        fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+            self.fields.insert(field.name(), value.to_string());
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    impl tracing::Subscriber for Capture {
+        fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            let mut traced = Traced {
+                name: span.metadata().name().to_string(),
+                ..Traced::default()
+            };
+            span.record(&mut traced);
+            self.push(span.metadata(), traced);
+            tracing::span::Id::from_u64(self.0.lock().unwrap().len() as u64)
+        }
+
+        fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record<'_>) {}
+
+        fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+
+        fn event(&self, event: &tracing::Event<'_>) {
+            let mut traced = Traced::default();
+            event.record(&mut traced);
+            self.push(event.metadata(), traced);
+        }
+
+        fn enter(&self, _: &tracing::span::Id) {}
+
+        fn exit(&self, _: &tracing::span::Id) {}
+    }
+
+    /// The packed version round-trips, and the linked library is at least as new as the headers'
+    /// major version.
+    #[test]
+    fn test_version() {
+        let version = Version::new(1, 22);
+        assert_eq!(Version::from_raw(version.as_raw()), version);
+        assert_eq!(version.to_string(), "1.22");
+        assert_eq!(Version::linked().major, Version::HEADER.major);
+    }
+
+    /// Flags keep the bit values of their C counterparts.
+    #[test]
+    fn test_flag_bits() {
+        assert_eq!(Caps::MSG.bits(), sys::bindgen::FI_MSG as u64);
+        assert_eq!(
+            (Caps::SEND | Caps::RECV).bits(),
+            (sys::bindgen::FI_SEND | sys::bindgen::FI_RECV) as u64
+        );
+        assert_eq!(MrMode::LOCAL.bits(), sys::bindgen::FI_MR_LOCAL);
+    }
+
+    /// Errors carry the positive code, and libfabric's description.
+    #[test]
+    fn test_error() {
+        let again = sys::bindgen::FI_EAGAIN as i32;
+        assert!(!strerror(again).is_empty());
+
+        let err = Info::new().provider("tcp\0").get().unwrap_err();
+        assert!(matches!(err, Error::InvalidArgument(_)));
+        assert_eq!(err.code(), sys::bindgen::FI_EINVAL as i32);
+    }
+
+    /// IP addresses round-trip through their sockaddr encoding.
+    #[test]
+    fn test_socket_addr() {
+        for addr in ["192.168.0.1:4242", "[fe80::1%3]:4242"] {
+            let addr: std::net::SocketAddr = addr.parse().unwrap();
+            let encoded = EndpointAddress::from(addr);
+            assert_eq!(encoded.to_socket_addr(), Some(addr));
+        }
+        assert_eq!(EndpointAddress::from_bytes([0u8; 4]).to_socket_addr(), None);
+    }
+
+    /// Typed addresses round-trip through their encoding, and decode only from their format.
+    #[test]
+    fn test_address_formats() {
+        fn round_trip<F: AddressFormat + PartialEq + std::fmt::Debug>(addr: F) {
+            let encoded = EndpointAddress::encode(&addr);
+            assert_eq!(encoded.decode::<F>(addr.format()), Some(addr));
+        }
+
+        let gid: std::net::Ipv6Addr = "fe80::1".parse().unwrap();
+        let ud = IbUdAddr {
+            gid,
+            qpn: 0x12,
+            lid: 3,
+            pkey: 0xffff,
+            service: 0,
+            sl: 0,
+        };
+        assert_eq!(EndpointAddress::encode(&ud).as_bytes().len(), 32);
+        assert_eq!(ud.to_string(), "fi_addr_ib_ud://fe80::1:12/3/ffff/0");
+        round_trip(ud);
+        let efa = EfaAddr {
+            gid,
+            qpn: 12,
+            qkey: 3,
+        };
+        assert_eq!(efa.to_string(), "fi_addr_efa://[fe80::1]:12:3");
+        round_trip(efa);
+        round_trip(PsmxAddr::Psmx2([0x1a, 0x2b]));
+        assert_eq!(
+            PsmxAddr::Psmx2([0x1a, 0x2b]).to_string(),
+            "fi_addr_psmx2://1a:2b"
+        );
+        round_trip(PsmxAddr::Psmx3([1, 2, 3, 4]));
+        round_trip("fi_sockaddr_in://10.0.0.1:4000".to_string());
+        round_trip("10.0.0.1:4000".parse::<std::net::SocketAddr>().unwrap());
+        round_trip(IbAddr::new(gid, 0xffff, 1));
+
+        let encoded = EndpointAddress::encode(&efa);
+        assert_eq!(encoded.decode::<IbUdAddr>(AddrFormat::Efa), None);
+        assert_eq!(encoded.decode::<EfaAddr>(AddrFormat::SockAddrIn), None);
+        assert_eq!(
+            EndpointAddress::from_bytes([0u8; 8]).decode::<PsmxAddr>(AddrFormat::Psmx2),
+            None
+        );
+    }
+
+    /// Peer providers report to application owned counters through the owner operations.
+    #[test]
+    fn test_peer_counter() {
+        let counter = PeerCounter::new();
+        unsafe {
+            let ops = &*(*counter.as_raw()).owner_ops;
+            ops.inc.unwrap()(counter.as_raw());
+            ops.inc.unwrap()(counter.as_raw());
+            ops.incerr.unwrap()(counter.as_raw());
+        }
+        assert_eq!((counter.read(), counter.read_err()), (2, 1));
+    }
+
+    /// Example use case of the library.
+    #[test]
+    fn test_get_info() {
+        let entries = tcp_hints().get().unwrap();
+        assert!(!entries.is_empty());
+        for entry in &entries {
+            assert_eq!(entry.provider_name(), "tcp");
+            assert_eq!(entry.ep_type(), EndpointType::Rdm);
+            assert!(entry.caps().contains(Caps::MSG));
+        }
+    }
+
+    /// The typed attributes of an entry agree with its other accessors, and give usable limits.
+    #[test]
+    fn test_entry_attrs() {
+        let entry = &tcp_entry();
+        let fabric = entry.fabric_attr();
+        assert_eq!(fabric.provider, entry.provider_name());
+        assert_eq!(fabric.provider_version, entry.provider_version());
+        let domain = entry.domain_attr();
+        assert_eq!(domain.name, entry.domain_name());
+        assert_eq!(domain.mr_mode, entry.mr_mode());
+        let ep = entry.ep_attr();
+        assert_eq!(ep.ep_type, EndpointType::Rdm);
+        assert_eq!(ep.max_msg_size, entry.max_msg_size());
+        let (tx, rx) = (entry.tx_attr(), entry.rx_attr());
+        assert!(tx.caps.contains(Caps::MSG) && rx.caps.contains(Caps::MSG));
+        assert!(tx.iov_limit >= 1 && rx.iov_limit >= 1);
+        assert!(tx.inject_size <= ep.max_msg_size);
+    }
+
+    /// Queue settings within the maxima of an entry are applied to a copy of it, and those
+    /// beyond them are rejected.
+    #[test]
+    fn test_queue_attrs() {
+        let entry = &tcp_entry();
+        let (max_tx, max_rx) = (entry.tx_attr(), entry.rx_attr());
+        let tx = TxQueueAttr::new()
+            .size(max_tx.size / 2)
+            .op_flags(OpFlags::DELIVERY_COMPLETE);
+        let tuned = entry.with_tx_attr(&tx).unwrap();
+        assert_eq!(tuned.tx_attr().size, max_tx.size / 2);
+        assert!(
+            tuned
+                .tx_attr()
+                .op_flags
+                .contains(OpFlags::DELIVERY_COMPLETE)
+        );
+        assert_eq!(tuned.tx_attr().iov_limit, max_tx.iov_limit);
+        assert_eq!(entry.tx_attr(), max_tx);
+
+        let tx = TxQueueAttr::new().iov_limit(max_tx.iov_limit + 1);
+        assert!(matches!(
+            entry.with_tx_attr(&tx),
+            Err(Error::InvalidArgument(_))
+        ));
+        let order = MsgOrder::all().difference(max_rx.msg_order);
+        if !order.is_empty() {
+            let rx = RxQueueAttr::new().msg_order(order);
+            assert!(entry.with_rx_attr(&rx).is_err());
+        }
+
+        let rx = RxQueueAttr::new().size(max_rx.size.min(64));
+        let tuned = entry.with_rx_attr(&rx).unwrap();
+        let domain = Domain::open(&Fabric::open(&tuned).unwrap(), &tuned).unwrap();
+        let ep = domain.endpoint(&tuned).unwrap();
+        assert_eq!(ep.info().rx_attr().size, max_rx.size.min(64));
+        let hints = tcp_hints().tx_attr(&TxQueueAttr::new().size(max_tx.size));
+        assert!(!hints.get().unwrap().is_empty());
+    }
+
+    /// Wait objects are checked against those of the provider, layered providers by the last
+    /// of them, and those of unknown providers are left to them.
+    #[test]
+    fn test_wait_obj() {
+        let shm = WaitObj::supported("shm", WaitTarget::Cq).unwrap();
+        assert!(shm.contains(&WaitObj::Yield) && !shm.contains(&WaitObj::Fd));
+        let rxm = WaitObj::supported("verbs;ofi_rxm", WaitTarget::Cq).unwrap();
+        assert!(rxm.contains(&WaitObj::Yield) && rxm.contains(&WaitObj::PollFd));
+        let psm3 = WaitObj::supported("psm3", WaitTarget::Cntr).unwrap();
+        assert!(psm3.contains(&WaitObj::MutexCond) && !psm3.contains(&WaitObj::Yield));
+        assert_eq!(WaitObj::supported("opx", WaitTarget::Eq), None);
+        assert!(!WaitObj::None.can_block() && WaitObj::Yield.can_block());
+        assert!(WaitObj::PollFd.is_pollable() && !WaitObj::MutexCond.is_pollable());
+        assert_eq!(WaitObj::MutexCond.to_string(), "FI_WAIT_MUTEX_COND");
+    }
+
+    /// Queues keep the wait object they were opened with, refusing to hand out descriptors
+    /// they have none of, unsupported ones fail to open, and pollfd sets are only fetched
+    /// again once changed.
+    #[test]
+    fn test_cq_wait_obj() {
+        let entry = &tcp_entry();
+        let domain = Domain::open(&Fabric::open(entry).unwrap(), entry).unwrap();
+        let cq = domain.cq(&CqAttr::new().wait_obj(WaitObj::Yield)).unwrap();
+        assert_eq!(cq.wait_obj(), WaitObj::Yield);
+        assert!(matches!(cq.wait_fd(), Err(Error::InvalidArgument(_))));
+        let attr = CqAttr::new().wait_obj(WaitObj::MutexCond);
+        assert!(matches!(domain.cq(&attr), Err(Error::InvalidArgument(_))));
+
+        let cq = domain.cq(&CqAttr::new().wait_obj(WaitObj::PollFd)).unwrap();
+        assert!(matches!(cq.wait_fd(), Err(Error::InvalidArgument(_))));
+        let mut fds = cq.wait_pollfd().unwrap();
+        assert!(!fds.refresh(&cq).unwrap());
+        assert!(fds.fds().iter().all(|fd| fd.fd >= 0));
+    }
+
+    /// Hints keep the requested protocol and traffic class, and so do the entries they match.
+    #[test]
+    fn test_protocol_tclass() {
+        let entry = tcp_entry();
+        let protocol = entry.ep_attr().protocol;
+        assert_ne!(protocol, Protocol::Unspec);
+        for entry in tcp_hints().protocol(protocol).get().unwrap().iter() {
+            assert_eq!(entry.ep_attr().protocol, protocol);
+        }
+
+        let tclass = TrafficClass::Dscp(46);
+        for entry in tcp_hints().tclass(tclass).get().unwrap().iter() {
+            assert_eq!(entry.tx_attr().tclass, tclass);
+        }
+    }
+
+    /// The tcp provider is listed, both at runtime and, for vendored builds, as a cfg.
+    #[test]
+    fn test_available_providers() {
+        let providers = available_providers().unwrap();
+        assert!(providers.iter().any(|name| name == "tcp"));
+        assert!(providers.is_sorted());
+        #[cfg(feature = "vendored")]
+        assert!(cfg!(libfabric_provider_tcp));
+    }
+
+    /// The fi-info-rs tool finds the tcp provider, in both output formats.
+    #[cfg(feature = "cli")]
+    #[test]
+    fn test_cli() {
+        let run = |args: &[&str]| {
+            let out = std::process::Command::new(env!("CARGO_BIN_EXE_fi-info-rs"))
+                .args(args)
+                .output()
+                .unwrap();
+            assert!(
+                out.status.success(),
+                "{}",
+                String::from_utf8_lossy(&out.stderr)
+            );
+            String::from_utf8(out.stdout).unwrap()
+        };
+        assert!(run(&["-p", "tcp", "-v"]).contains("provider: tcp"));
+        assert!(run(&["-p", "tcp", "--json"]).contains("\"provider\": \"tcp\""));
+        assert!(run(&["-l"]).lines().any(|name| name == "tcp"));
+    }
+
+    /// The pingpong example completes a few round trips over loopback, in every mode.
+    #[test]
+    fn test_pingpong() {
+        let pingpong = env!("CARGO_BIN_EXE_pingpong");
+        for (port, (ep_type, mode)) in (47600..).zip([
+            ("rdm", "msg"),
+            ("rdm", "tagged"),
+            ("rdm", "rma"),
+            ("msg", "msg"),
+        ]) {
+            let port = port.to_string();
+            let args = ["-e", ep_type, "-m", mode, "-I", "10", "-B", &port];
+            let server = std::process::Command::new(pingpong)
+                .args(args)
+                .spawn()
+                .unwrap();
+            let client = std::process::Command::new(pingpong)
+                .args(args)
+                .arg("127.0.0.1")
+                .output()
+                .unwrap();
+            assert!(
+                client.status.success(),
+                "{ep_type} {mode}: {}",
+                String::from_utf8_lossy(&client.stderr)
+            );
+            assert!(server.wait_with_output().unwrap().status.success());
+        }
+    }
+
+    /// Both APIs of the benchmarks complete round trips and streams over loopback.
+    #[cfg(feature = "bench")]
+    #[test]
+    fn test_bench() {
+        use libfabric::bench::{self, Api, Loopback};
+
+        let mut loopback = Loopback::open("tcp", 64).unwrap();
+        for api in [Api::Safe, Api::Raw] {
+            let latencies = bench::latency(&mut loopback, 64, 1, 10, api).unwrap();
+            assert!(latencies.min() <= latencies.percentile(50.0));
+            assert!(latencies.percentile(50.0) <= latencies.max());
+            let throughput = bench::throughput(&mut loopback, 64, 8, 1, 2, api).unwrap();
+            assert!(throughput.messages_per_sec > 0.0);
+        }
+        assert!(loopback.round_trip(65, Api::Safe).is_err());
+    }
+
+    /// Every check of the self-test passes over tcp loopback.
+    #[test]
+    fn test_selftest() {
+        let report = libfabric::selftest_provider("tcp").unwrap();
+        assert_eq!(report.provider, "tcp");
+        assert_eq!(report.checks.len(), 4);
+        assert!(report.passed(), "{report}");
+    }
+
+    /// Events written by the application are read back in order as user events, and those out
+    /// of range are refused before reaching the queue.
+    #[test]
+    fn test_eq_write() {
+        let entry = tcp_entry();
+        let fabric = Fabric::open(&entry).unwrap();
+        let eq = fabric.eq(&EqAttr::new().writable(true)).unwrap();
+        eq.write(7, b"drain").unwrap();
+        eq.write(0, &[]).unwrap();
+        let event = eq.read().unwrap();
+        assert!(
+            matches!(&event, Some(EqEvent::User { event: 7, data }) if data == b"drain"),
+            "{event:?}"
+        );
+        let event = eq.read().unwrap();
+        assert!(
+            matches!(event, Some(EqEvent::User { event: 0, .. })),
+            "{event:?}"
+        );
+        assert!(eq.read().unwrap().is_none());
+
+        assert!(eq.write(u32::MAX, &[]).is_err());
+        assert!(eq.write(1, &[0; EQ_USER_EVENT_MAX + 1]).is_err());
+    }
+
+    /// Rank sets of the common topologies include the member they are built for, with its
+    /// neighbors or partners, once each, and out of range ranks are refused.
+    #[test]
+    fn test_rank_set() {
+        assert_eq!(RankSet::list([3, 1, 3, 0]).ranks(), [3, 1, 0]);
+        assert_eq!(RankSet::strided(1, 9, 4).unwrap().ranks(), [1, 5, 9]);
+        assert_eq!(RankSet::strided(2, 2, 1).unwrap().ranks(), [2]);
+        assert!(RankSet::strided(0, 4, 0).is_err());
+        assert!(RankSet::strided(4, 0, 1).is_err());
+
+        assert_eq!(RankSet::ring_neighbors(0, 8).unwrap().ranks(), [7, 0, 1]);
+        assert_eq!(RankSet::ring_neighbors(1, 2).unwrap().ranks(), [0, 1]);
+        assert_eq!(RankSet::ring_neighbors(0, 1).unwrap().ranks(), [0]);
+        assert!(RankSet::ring_neighbors(8, 8).is_err());
+
+        assert_eq!(
+            RankSet::hypercube_partners(3, 8).unwrap().ranks(),
+            [3, 2, 1, 7]
+        );
+        // Partners past the end of a group of 6 are left out.
+        assert_eq!(
+            RankSet::hypercube_partners(3, 6).unwrap().ranks(),
+            [3, 2, 1]
+        );
+
+        let node = RankSet::node_local(9, 4, 10).unwrap();
+        assert_eq!(node.ranks(), [8, 9]);
+        assert_eq!((node.position(9), node.position(3)), (Some(1), None));
+        assert!(RankSet::node_local(0, 0, 4).is_err());
+        assert_eq!(RankSet::node_leaders(6, 4, 10).unwrap().ranks(), [2, 6]);
+        assert_eq!(RankSet::node_leaders(0, 4, 10).unwrap().ranks(), [0, 4, 8]);
+    }
+
+    /// A communicator refuses a rank outside of its group before joining anything.
+    #[test]
+    fn test_communicator_rank() {
+        let entries = tcp_hints().caps(Caps::MSG | Caps::TAGGED).get().unwrap();
+        let entry = &entries[0];
+        let fabric = Fabric::open(entry).unwrap();
+        let domain = Domain::open(&fabric, entry).unwrap();
+        let eq = fabric.eq(&EqAttr::new()).unwrap();
+        let (ep, cq, av) = bound_endpoint(&domain, entry);
+        let names = [EndpointAddress::from_bytes([0u8; 16])];
+        let err = Communicator::join(ep, cq, &eq, &av, &names, 1)
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::InvalidArgument(_)));
+    }
+
+    /// Datagrams go from one udp endpoint to another within the MTU, and receives time out.
+    #[test]
+    fn test_dgram() {
+        use std::time::Duration;
+
+        let entries = Info::new()
+            .caps(Caps::MSG)
+            .ep_type(EndpointType::Dgram)
+            .provider("udp")
+            .get()
+            .unwrap();
+        let entry = &entries[0];
+        let fabric = Fabric::open(entry).unwrap();
+        let domain = Domain::open(&fabric, entry).unwrap();
+        let (a, b) = (
+            DgramEndpoint::open(&domain, entry).unwrap(),
+            DgramEndpoint::open(&domain, entry).unwrap(),
+        );
+        assert_eq!(a.mtu(), entry.max_msg_size());
+        let to_b = a.insert(&b.name().unwrap()).unwrap();
+
+        let receiver = std::thread::scope(|s| {
+            let receiver = s.spawn(|| {
+                let mut buf = [0u8; 16];
+                let (len, _) = b.recv_from(&mut buf).unwrap();
+                buf[..len].to_vec()
+            });
+            // Datagrams sent before the receive is posted may be dropped, so keep sending.
+            while !receiver.is_finished() {
+                assert_eq!(a.send_to(b"datagram", to_b).unwrap(), 8);
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            receiver.join().unwrap()
+        });
+        assert_eq!(receiver, b"datagram");
+
+        let too_long = vec![0u8; a.mtu() + 1];
+        assert!(a.send_to(&too_long, to_b).is_err());
+        a.set_read_timeout(Some(Duration::from_millis(20)));
+        let err = a.recv_from(&mut [0u8; 16]).unwrap_err();
+        assert_eq!(err.code(), sys::bindgen::FI_ETIMEDOUT as i32);
+
+        // The futures complete when polled by any executor, here a plain loop.
+        #[cfg(feature = "async")]
+        {
+            use std::future::Future;
+            use std::task::{Context, Poll, Waker};
+
+            let c = DgramEndpoint::open(&domain, entry).unwrap();
+            let to_c = a.insert(&c.name().unwrap()).unwrap();
+            let mut cx = Context::from_waker(Waker::noop());
+            let mut recv = std::pin::pin!(c.recv_from_async());
+            assert!(recv.as_mut().poll(&mut cx).is_pending());
+            let received = loop {
+                let mut send = std::pin::pin!(a.send_to_async(b"async".to_vec(), to_c));
+                let sent = loop {
+                    if let Poll::Ready(sent) = send.as_mut().poll(&mut cx) {
+                        break sent;
+                    }
+                };
+                assert_eq!(sent.unwrap(), b"async");
+                std::thread::sleep(Duration::from_millis(10));
+                if let Poll::Ready(received) = recv.as_mut().poll(&mut cx) {
+                    break received.unwrap().0;
+                }
+            };
+            assert_eq!(received, b"async");
+        }
+    }
+
+    /// A full accept queue rejects the requests past its capacity, and hands out the others.
+    #[test]
+    fn test_accept_queue() {
+        use std::time::{Duration, Instant};
+
+        let entries = tcp_hints().ep_type(EndpointType::Msg).get().unwrap();
+        let entry = &entries[0];
+        let fabric = Fabric::open(entry).unwrap();
+        let eq = fabric.eq(&EqAttr::new()).unwrap();
+        let pep = fabric.passive_endpoint(entry).unwrap();
+        pep.bind_eq(&eq).unwrap();
+        pep.set_backlog(8).unwrap();
+        pep.listen().unwrap();
+        let server = pep.name().unwrap();
+        let mut queue = AcceptQueue::new(pep, eq, 1, Overflow::Reject(b"busy".to_vec()));
+
+        let domain = Domain::open(&fabric, entry).unwrap();
+        let clients: Vec<_> = (0..2)
+            .map(|_| {
+                let eq = fabric.eq(&EqAttr::new()).unwrap();
+                let cq = domain.cq(&CqAttr::new()).unwrap();
+                let ep = domain
+                    .endpoint(entry)
+                    .unwrap()
+                    .bind_cq(&cq, BindFlags::TRANSMIT | BindFlags::RECV)
+                    .unwrap()
+                    .bind_eq(&eq)
+                    .unwrap()
+                    .enable()
+                    .unwrap();
+                ep.connect(&server, &[]).unwrap();
+                (ep, cq, eq)
+            })
+            .collect();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while queue.rejected() < 1 && Instant::now() < deadline {
+            queue.progress().unwrap();
+        }
+        assert_eq!((queue.len(), queue.rejected()), (1, 1));
+        let request = queue.pop().unwrap();
+        assert!(queue.is_empty());
+        queue.reject(&request, &[]).unwrap();
+        drop(clients);
+    }
+
+    /// A graceful shutdown hands back the completions still unread, once the peer shut the
+    /// connection down too.
+    #[test]
+    fn test_shutdown_graceful() {
+        use std::time::Duration;
+
+        let entries = tcp_hints().ep_type(EndpointType::Msg).get().unwrap();
+        let entry = &entries[0];
+        let fabric = Fabric::open(entry).unwrap();
+        let domain = Domain::open(&fabric, entry).unwrap();
+        let server_eq = fabric.eq(&EqAttr::new()).unwrap();
+        let pep = fabric.passive_endpoint(entry).unwrap();
+        pep.bind_eq(&server_eq).unwrap();
+        pep.listen().unwrap();
+
+        let (client_eq, client_cq) = (
+            fabric.eq(&EqAttr::new()).unwrap(),
+            domain.cq(&CqAttr::new()).unwrap(),
+        );
+        let client = domain
+            .endpoint(entry)
+            .unwrap()
+            .bind_cq(&client_cq, BindFlags::TRANSMIT | BindFlags::RECV)
+            .unwrap()
+            .bind_eq(&client_eq)
+            .unwrap()
+            .enable()
+            .unwrap();
+        client.connect(&pep.name().unwrap(), &[]).unwrap();
+        let info = loop {
+            if let Some(EqEvent::ConnReq { info, .. }) = server_eq.read().unwrap() {
+                break info;
+            }
+        };
+        let server_cq = domain.cq(&CqAttr::new()).unwrap();
+        let server = domain
+            .endpoint(&info)
+            .unwrap()
+            .bind_cq(&server_cq, BindFlags::TRANSMIT | BindFlags::RECV)
+            .unwrap()
+            .bind_eq(&server_eq)
+            .unwrap()
+            .enable()
+            .unwrap();
+        let mut buf = [0u8; 16];
+        unsafe { server.recv(&mut buf, None, Addr::UNSPEC, 1).unwrap() };
+        server.accept(&[]).unwrap();
+        for (ep, eq) in [(&client, &client_eq), (&server, &server_eq)] {
+            loop {
+                match eq.read().unwrap() {
+                    Some(EqEvent::Connected { fid, .. }) if fid == ep.id() => break,
+                    _ => {}
+                }
+            }
+        }
+
+        unsafe { client.send(b"bye", None, Addr::UNSPEC, 2).unwrap() };
+        let mut completions = [Completion::default(); 4];
+        while client_cq.read(&mut completions).unwrap() == 0 {}
+        let client = std::thread::spawn(move || client.shutdown_graceful(Duration::from_secs(5)));
+        let report = server.shutdown_graceful(Duration::from_secs(5)).unwrap();
+        assert!(report.peer_closed);
+        assert!(report.completions.iter().any(|c| c.context() == 1));
+        assert_eq!(&buf[..3], b"bye");
+        client.join().unwrap().unwrap();
+    }
+
+    /// Listening and connecting are reported as debug events, with the provider, endpoint and
+    /// peer.
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_trace_connect() {
+        let entries = tcp_hints().ep_type(EndpointType::Msg).get().unwrap();
+        let entry = &entries[0];
+        let fabric = Fabric::open(entry).unwrap();
+        let domain = Domain::open(&fabric, entry).unwrap();
+        let capture = Capture::default();
+        tracing::subscriber::with_default(capture.clone(), || {
+            let server_eq = fabric.eq(&EqAttr::new()).unwrap();
+            let pep = fabric.passive_endpoint(entry).unwrap();
+            pep.bind_eq(&server_eq).unwrap();
+            pep.listen().unwrap();
+            let client_eq = fabric.eq(&EqAttr::new()).unwrap();
+            let client_cq = domain.cq(&CqAttr::new()).unwrap();
+            let client = domain
+                .endpoint(entry)
+                .unwrap()
+                .bind_cq(&client_cq, BindFlags::TRANSMIT | BindFlags::RECV)
+                .unwrap()
+                .bind_eq(&client_eq)
+                .unwrap()
+                .enable()
+                .unwrap();
+            client.connect(&pep.name().unwrap(), &[]).unwrap();
+
+            let listened = capture.find("libfabric", "fi_listen");
+            assert_eq!(listened.len(), 1);
+            assert_eq!(listened[0]["endpoint"], format!("{:?}", pep.id()));
+            let connected = capture.find("libfabric", "fi_connect");
+            assert_eq!(connected.len(), 1);
+            assert_eq!(connected[0]["provider"], entry.provider_name());
+            assert_eq!(connected[0]["endpoint"], format!("{:?}", client.id()));
+            assert!(connected[0].contains_key("peer"));
+        });
+    }
+
+    /// Selection keeps the entries matching the policy, and fails like `fi_getinfo()` when
+    /// none is left.
+    #[test]
+    fn test_select_provider() {
+        let policy = SelectionPolicy::new()
+            .require_caps(Caps::MSG)
+            .prefer_rdma()
+            .prefer("tcp");
+        let entries = select_provider(&tcp_hints(), &policy).unwrap();
+        assert!(
+            entries
+                .iter()
+                .all(|entry| entry.provider_name().starts_with("tcp"))
+        );
+
+        let err = select_provider(&tcp_hints(), &policy.exclude("tcp")).unwrap_err();
+        assert_eq!(err.code(), sys::bindgen::FI_ENODATA as i32);
+    }
+
+    /// PCI addresses display as sysfs names them, and order by their domain, bus, device and
+    /// function.
+    #[test]
+    fn test_pci_address() {
+        let addr = PciAddress {
+            domain: 0,
+            bus: 0x3b,
+            device: 0,
+            function: 1,
+        };
+        assert_eq!(addr.to_string(), "0000:3b:00.1");
+        assert!(
+            addr < PciAddress {
+                bus: 0x5e,
+                function: 0,
+                ..addr
+            }
+        );
+        let nic = Nic {
+            pci: Some(addr),
+            ..Nic::default()
+        };
+        assert_eq!(nic.pci.unwrap().to_string(), "0000:3b:00.1");
+        assert_eq!(Nic::default().numa_node(), None);
+    }
+
+    /// InfiniBand addresses round-trip through `struct sockaddr_ib`, and those of other
+    /// families are not mistaken for them.
+    #[test]
+    fn test_ib_addr() {
+        let gid = "fe80::248a:703:9c:a1b2".parse().unwrap();
+        let addr = IbAddr::new(gid, 0xffff, 1);
+        let bytes = addr.to_bytes();
+        assert_eq!(bytes.len(), 48);
+        assert_eq!(IbAddr::from_bytes(&bytes), Some(addr));
+        assert_eq!(
+            addr.to_string(),
+            "fe80::248a:703:9c:a1b2 port 1 pkey 0xffff"
+        );
+        assert_eq!(IbAddr::from_bytes(&bytes[..16]), None);
+        let mut inet = bytes.clone();
+        inet[..2].copy_from_slice(&2u16.to_ne_bytes());
+        assert_eq!(IbAddr::from_bytes(&inet), None);
+    }
+
+    /// Fork safety requested for the process is seen by the next reading of the environment,
+    /// as libibverbs reads it when the first device is opened, and variables set to `0` request
+    /// nothing.
+    #[test]
+    fn test_fork_safety() {
+        for var in [
+            "RDMAV_FORK_SAFE",
+            "IBV_FORK_SAFE",
+            "FI_EFA_FORK_SAFE",
+            "RDMAV_HUGEPAGES_SAFE",
+        ] {
+            unsafe { std::env::set_var(var, "0") };
+        }
+        let safety = ForkSafety::from_env();
+        assert!(!safety.is_requested());
+        assert!(!safety.hugepages_safe);
+
+        unsafe { ForkSafety::request(true) };
+        let safety = ForkSafety::from_env();
+        assert!(safety.is_requested());
+        assert!(safety.hugepages_safe);
+        assert_eq!(safety.requested_by, Some("RDMAV_FORK_SAFE"));
+    }
+
+    /// The psm3 and opx tunables map to the variables of the providers, and values out of their
+    /// range are rejected before the environment is touched.
+    #[test]
+    fn test_omnipath_config() {
+        let psm3 = Psm3Config::new()
+            .multi_ep(false)
+            .nic_selection(NicSelection::Application)
+            .lock_level(1);
+        assert_eq!(
+            psm3.vars().unwrap(),
+            [
+                ("PSM3_MULTI_EP", "0".to_owned()),
+                ("PSM3_MULTIRAIL", "-1".to_owned()),
+                ("FI_PSM3_LOCK_LEVEL", "1".to_owned()),
+            ]
+        );
+        let opx = OpxConfig::new().endpoints_per_context(4).sdma(false);
+        assert_eq!(
+            opx.vars().unwrap(),
+            [
+                ("FI_OPX_CONTEXT_SHARING", "1".to_owned()),
+                ("FI_OPX_ENDPOINTS_PER_HFI_CONTEXT", "4".to_owned()),
+                ("FI_OPX_SDMA_DISABLE", "1".to_owned()),
+            ]
+        );
+
+        let psm3 = Psm3Config::new().uuid("job").lock_level(3);
+        assert!(matches!(
+            unsafe { psm3.install() },
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(std::env::var_os("FI_PSM3_UUID").is_none());
+        let opx = OpxConfig::new().endpoints_per_context(9);
+        assert!(matches!(opx.vars(), Err(Error::InvalidArgument(_))));
+        assert!(OpxConfig::new().port(0).vars().is_err());
+    }
+
+    /// The domains of the psm3 and opx providers, when the node has them, give each endpoint
+    /// at least one context.
+    #[test]
+    fn test_omnipath_context_counts() {
+        let providers = available_providers().unwrap();
+        for provider in ["psm3", "opx"] {
+            if !providers.iter().any(|name| name == provider) {
+                continue;
+            }
+            let entries = Info::new()
+                .caps(Caps::MSG | Caps::TAGGED)
+                .ep_type(EndpointType::Rdm)
+                .provider(provider)
+                .get()
+                .unwrap();
+            for entry in &entries {
+                let counts = context_counts(entry);
+                assert!(counts.ep_cnt >= 1, "{provider}: {counts:?}");
+                assert!(counts.tx_ctx_cnt >= 1 && counts.rx_ctx_cnt >= 1);
+                assert!(counts.max_ep_tx_ctx >= 1 && counts.max_ep_rx_ctx >= 1);
+            }
+        }
+    }
+
+    /// Messages between multi-rail endpoints arrive in order, striped or not, and peers are
+    /// added with a name per rail.
+    #[test]
+    fn test_multirail() {
+        let entries = tcp_hints().caps(Caps::MSG | Caps::TAGGED).get().unwrap();
+        let mut a = MultiRailEndpoint::open(&entries, 2).unwrap();
+        let mut b = MultiRailEndpoint::open(&entries, 2).unwrap();
+        assert!(matches!(a.insert(&[]), Err(Error::InvalidArgument(_))));
+        let to_b = a.insert(&b.names().unwrap()).unwrap();
+        let to_a = b.insert(&a.names().unwrap()).unwrap();
+        a.set_stripe_threshold(16);
+        b.set_stripe_threshold(16);
+
+        let large: Vec<u8> = (0..64).collect();
+        a.send(b"small", to_b).unwrap();
+        a.send(&large, to_b).unwrap();
+        let mut small = [0u8; 8];
+        assert_eq!(b.recv(&mut small, to_a).unwrap(), 5);
+        assert_eq!(&small[..5], b"small");
+        let mut buf = vec![0u8; large.len()];
+        assert_eq!(b.recv(&mut buf, to_a).unwrap(), large.len());
+        assert_eq!(buf, large);
+    }
+
+    /// Hybrid endpoints reach the peers on their node over their shm side, the others over
+    /// their network side, and peers on both sides exchange messages. The completions of
+    /// operations posted over a route are kept for `completions()`, rather than taken for
+    /// those of the endpoint.
+    #[test]
+    fn test_hybrid_endpoint() {
+        assert_ne!(shm_name(), shm_name());
+        let entry = tcp_entry();
+        let mut a = HybridEndpoint::open(&entry, &entry).unwrap();
+        let mut b = HybridEndpoint::open(&entry, &entry).unwrap();
+        assert_eq!(a.node(), &NodeId::local().unwrap());
+        let elsewhere = NodeId::from_bytes("elsewhere");
+
+        let (shm, net) = b.names().unwrap();
+        let near_b = a.insert(b.node(), &shm, &net).unwrap();
+        let far_b = a.insert(&elsewhere, &shm, &net).unwrap();
+        assert!(a.is_local(near_b).unwrap());
+        assert!(!a.is_local(far_b).unwrap());
+        assert!(a.is_local(2).is_err());
+        let (shm, net) = a.names().unwrap();
+        let near_a = b.insert(a.node(), &shm, &net).unwrap();
+        let far_a = b.insert(&elsewhere, &shm, &net).unwrap();
+
+        let mut buf = [0u8; 8];
+        let (ep, addr) = a.route(near_b).unwrap();
+        // SAFETY: the buffer is static.
+        unsafe { ep.send(b"routed", None, addr, 7) }.unwrap();
+        assert_eq!(b.recv(&mut buf, near_a).unwrap(), 6);
+        a.send(b"near", near_b).unwrap();
+        assert_eq!(b.recv(&mut buf, near_a).unwrap(), 4);
+        assert_eq!(&buf[..4], b"near");
+        let mut done = Vec::new();
+        while done.is_empty() {
+            done = a.completions().unwrap();
+        }
+        assert!(matches!(done[..], [(7, Ok(6))]), "{done:?}");
+        a.send(b"far", far_b).unwrap();
+        assert_eq!(b.recv(&mut buf, far_a).unwrap(), 3);
+        assert_eq!(&buf[..3], b"far");
+    }
+
+    /// A supervisor gives up on a server which is not listening after the attempts the policy
+    /// allows, and then fails operations.
+    #[test]
+    fn test_supervisor_gives_up() {
+        use std::time::{Duration, Instant};
+
+        let hints = tcp_hints()
+            .ep_type(EndpointType::Msg)
+            .node("127.0.0.1")
+            .service("47702");
+        let policy = ReconnectPolicy::new()
+            .backoff(Duration::from_millis(1), Duration::from_millis(10))
+            .max_attempts(2)
+            .pending(PendingOps::Fail);
+        let mut supervisor = Supervisor::connect(hints, policy);
+        assert!(!supervisor.is_connected());
+        assert!(supervisor.send(b"early".to_vec()).is_err());
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let event = loop {
+            match supervisor.poll() {
+                Some(event) => break event,
+                None => assert!(Instant::now() < deadline, "no event"),
+            }
+        };
+        assert!(matches!(event, SupervisorEvent::GaveUp(_)), "{event:?}");
+        assert!(supervisor.recv(8).is_err());
+    }
+
+    /// A client joins a server of RDM sessions, whose handler echoes its message with the
+    /// count of messages of the peer, then leaves it, handing the state back.
+    #[test]
+    fn test_session() {
+        use libfabric::client::Client;
+        use libfabric::server::{Handler, PeerId, Server, Session, SessionAttr};
+        use std::time::{Duration, Instant};
+
+        #[derive(Default)]
+        struct Echo {
+            server: bool,
+            replies: Vec<Vec<u8>>,
+            left: Option<u32>,
+        }
+
+        impl Handler for Echo {
+            type Peer = u32;
+
+            fn on_connect(&mut self, _: &mut Session, _: PeerId) -> libfabric::Result<u32> {
+                Ok(0)
+            }
+
+            fn on_message(
+                &mut self,
+                session: &mut Session,
+                peer: PeerId,
+                count: &mut u32,
+                msg: &[u8],
+            ) -> libfabric::Result<()> {
+                *count += 1;
+                match self.server {
+                    true => session.send(peer, &[msg, &count.to_le_bytes()].concat()),
+                    false => {
+                        self.replies.push(msg.to_vec());
+                        Ok(())
+                    }
+                }
+            }
+
+            fn on_disconnect(&mut self, _: &mut Session, _: PeerId, count: u32) {
+                self.left = Some(count);
+            }
+        }
+
+        fn poll_until(
+            server: &mut Server<Echo>,
+            client: &mut Client<Echo>,
+            until: impl Fn(&Server<Echo>, &Client<Echo>) -> bool,
+        ) {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while !until(server, client) {
+                server.poll().unwrap();
+                client.poll().unwrap();
+                assert!(Instant::now() < deadline, "timed out");
+            }
+        }
+
+        let attr = SessionAttr::new().provider("tcp").max_size(64);
+        let echo = Echo {
+            server: true,
+            ..Echo::default()
+        };
+        let mut server = Server::bind("127.0.0.1", "47229", &attr, echo).unwrap();
+        let mut client = Client::connect("127.0.0.1", "47229", &attr, Echo::default()).unwrap();
+        assert!(!client.is_connected());
+        assert!(client.send(b"early").is_err());
+
+        poll_until(&mut server, &mut client, |_, client| client.is_connected());
+        client.send(b"ping").unwrap();
+        assert!(client.send(&[0; 65]).is_err());
+        poll_until(&mut server, &mut client, |_, client| {
+            !client.handler().replies.is_empty()
+        });
+        assert_eq!(client.handler().replies, [b"ping\x01\0\0\0".to_vec()]);
+        assert_eq!(server.session().peers().len(), 1);
+
+        client.disconnect().unwrap();
+        poll_until(&mut server, &mut client, |server, client| {
+            server.handler().left.is_some() && client.handler().left.is_some()
+        });
+        assert_eq!(
+            (server.handler().left, client.handler().left),
+            (Some(1), Some(1))
+        );
+        assert!(server.session().peers().is_empty());
+    }
+
+    /// The registry renders the counters added to it, and the memory registered by the
+    /// process, in the OpenMetrics text format.
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_metrics() {
+        use libfabric::metrics::{MetricKind, Registry};
+
+        let entry = tcp_entry();
+        let fabric = Fabric::open(&entry).unwrap();
+        let domain = Domain::open(&fabric, &entry).unwrap();
+        let cntr = domain.counter(&CntrAttr::new()).unwrap();
+        cntr.add(3).unwrap();
+
+        let registry = Registry::new();
+        registry.counter("rma", &cntr);
+        let events = registry
+            .sample()
+            .into_iter()
+            .find(|sample| sample.family == "fabric_counter_events")
+            .unwrap();
+        assert_eq!((events.kind, events.value), (MetricKind::Counter, 3));
+        let text = registry.render();
+        assert!(text.contains("# TYPE fabric_mr_regions gauge\n"));
+        assert!(text.contains("fabric_counter_events_total{name=\"rma\"} 3\n"));
+        assert!(text.ends_with("# EOF\n"));
+    }
+
+    /// Regions registered with a requested key get that key, which no other open region of
+    /// the domain may request until the first is dropped.
+    #[test]
+    fn test_requested_key() {
+        use sys::bindgen as ffi;
+
+        let entry = tcp_entry();
+        let fabric = Fabric::open(&entry).unwrap();
+        let domain = Domain::open(&fabric, &entry).unwrap();
+        let mut buf = [0u8; 64];
+        let attr = MrAttr::new(Access::REMOTE_READ).requested_key(0x2a);
+        let register =
+            |buf: &mut [u8]| unsafe { domain.register_with(buf.as_mut_ptr(), buf.len(), &attr) };
+        if entry.mr_mode().contains(MrMode::PROV_KEY) {
+            assert_eq!(
+                register(&mut buf).err().unwrap().code(),
+                ffi::FI_EINVAL as i32
+            );
+            return;
+        }
+        let mr = register(&mut buf).unwrap();
+        assert_eq!(mr.key(), 0x2a);
+        let err = register(&mut buf).err().unwrap();
+        assert_eq!(err.code(), ffi::FI_ENOKEY as i32);
+        drop(mr);
+        assert_eq!(register(&mut buf).unwrap().key(), 0x2a);
+    }
+
+    /// Allocations of a symmetric heap of a job of one member fill it first fit, and free
+    /// ranges merge back into one, each allocation being targeted at its offset.
+    #[test]
+    fn test_symmetric_heap() {
+        use libfabric::bootstrap::Bootstrap;
+
+        struct Alone;
+
+        impl Bootstrap for Alone {
+            fn rank(&self) -> usize {
+                0
+            }
+
+            fn size(&self) -> usize {
+                1
+            }
+
+            fn allgather(&mut self, local: &[u8]) -> Result<Vec<Vec<u8>>> {
+                Ok(vec![local.to_vec()])
+            }
+        }
+
+        let entries = tcp_hints().caps(Caps::MSG | Caps::RMA).get().unwrap();
+        let fabric = Fabric::open(&entries[0]).unwrap();
+        let domain = Domain::open(&fabric, &entries[0]).unwrap();
+        let attr = SymmetricHeapAttr::new().size(3000).key(0x77);
+        let mut heap = SymmetricHeap::new(&domain, &mut Alone, &attr).unwrap();
+        assert_eq!((heap.rank(), heap.members(), heap.capacity()), (0, 1, 4096));
+
+        let a = heap.alloc(10, 8).unwrap();
+        let b = heap.alloc(100, 64).unwrap();
+        assert_eq!((a.offset(), b.offset()), (0, 64));
+        assert!(heap.alloc(4096, 8).is_err());
+        heap.free(a);
+        assert_eq!(heap.alloc(16, 8).unwrap().offset(), 0);
+        let remote = heap.remote(b, 0);
+        assert_eq!((remote.len, remote.key), (100, heap.key()));
+        assert_eq!(remote.addr % 64, 0);
+        assert_eq!(heap.ptr(b) as usize - heap.ptr(a) as usize, 64);
+    }
+
+    /// The EFA operations and options are refused on the endpoints and regions of other
+    /// providers.
+    #[cfg(feature = "efa")]
+    #[test]
+    fn test_efa_elsewhere() {
+        use libfabric::efa;
+
+        let entry = tcp_entry();
+        let fabric = Fabric::open(&entry).unwrap();
+        let domain = Domain::open(&fabric, &entry).unwrap();
+        let ep = domain.endpoint(&entry).unwrap();
+        let mut buf = [0u8; 64];
+        let mr = unsafe { domain.register(buf.as_mut_ptr(), buf.len(), Access::RECV) }.unwrap();
+        assert!(efa::query_mr(&mr).is_err());
+        assert!(efa::emulated_read(&ep).is_err());
+        assert!(efa::set_rnr_retry(&ep, 3).is_err());
+    }
+
+    /// CXI authorization keys encode as a `struct cxi_auth_key`, and the values the NIC writes
+    /// back split into successes and failures.
+    #[cfg(feature = "cxi")]
+    #[test]
+    fn test_cxi_values() {
+        use libfabric::cxi::{self, AuthKey};
+
+        let key = AuthKey {
+            svc_id: 5,
+            vni: 1024,
+        };
+        let bytes = key.to_bytes();
+        assert_eq!(bytes.len(), 8);
+        assert_eq!(&bytes[..4], &5u32.to_ne_bytes());
+        assert_eq!(&bytes[4..6], &1024u16.to_ne_bytes());
+        assert_eq!(cxi::writeback_value((3 << 48) | 42), (42, 3));
+    }
+
+    /// Logging is routed once for the process, later calls being no-ops.
+    #[cfg(feature = "log")]
+    #[test]
+    fn test_route_logging() {
+        route_logging().unwrap();
+        route_logging().unwrap();
+        // Messages now go through the `log` crate, which drops them without a logger.
+        assert!(tcp_hints().get().is_ok());
+    }
+
+    /// Hooks are listed by name, and invalid names are rejected before the environment is
+    /// touched.
+    #[test]
+    fn test_hook_config() {
+        assert_eq!(Hook::DmabufPeerMem.name(), "dmabuf_peer_mem");
+        let hooks = HookConfig::new()
+            .hook(Hook::Perf)
+            .hook(Hook::Other("a;b".into()));
+        assert!(matches!(
+            unsafe { hooks.install() },
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(std::env::var_os("FI_HOOK").is_none());
+    }
+
+    /// Injected faults are reproducible from their seed: posts fail with EAGAIN in bursts, and
+    /// completions are held back or reported in error.
+    #[cfg(all(feature = "fault", feature = "mock"))]
+    #[test]
+    fn test_fault_injector() {
+        use libfabric::fault::FaultInjector;
+        use libfabric::mock::MockFabric;
+        use sys::bindgen as ffi;
+
+        let fabric = MockFabric::new();
+        let b = fabric.endpoint();
+        let to_b = fabric.av().insert(&b.name().unwrap()).unwrap();
+        let mut completions = [Completion::default(); 4];
+
+        // Every message gets through with retries, after the same failures for the same seed.
+        let failures = |seed| {
+            let faults = FaultInjector::new(seed).eagain_rate(0.3).max_burst(4);
+            let a = faults.endpoint(fabric.endpoint());
+            let mut failed = Vec::new();
+            for i in 0..32u8 {
+                while let Err(err) = a.inject(&[i], to_b) {
+                    assert!(err.is_again());
+                    failed.push(i);
+                }
+            }
+            assert_eq!(faults.stats().eagain, failed.len() as u64);
+            failed
+        };
+        assert!(!failures(1).is_empty());
+        assert_eq!(failures(1), failures(1));
+        assert_ne!(failures(1), failures(2));
+
+        // Successful completions are reported in error, with their context.
+        let faults = FaultInjector::new(0)
+            .error_rate(1.0)
+            .error_code(ffi::FI_ECANCELED as i32);
+        let a = faults.endpoint(fabric.endpoint());
+        let cq = faults.cq(a.get_ref().cq());
+        unsafe { a.send(b"ping", None, to_b, 1).unwrap() };
+        assert!(cq.read(&mut completions).unwrap_err().is_avail());
+        let entry = cq.read_err().unwrap().unwrap();
+        assert_eq!(
+            (entry.context, entry.error.code()),
+            (1, ffi::FI_ECANCELED as i32)
+        );
+        assert!(cq.read(&mut completions).unwrap_err().is_again());
+        assert_eq!(faults.stats().errors, 1);
+
+        // Held back completions are delivered within the maximum delay.
+        let faults = FaultInjector::new(0).delay_rate(1.0).max_delay(3);
+        let a = faults.endpoint(fabric.endpoint());
+        let cq = faults.cq(a.get_ref().cq());
+        unsafe { a.send(b"ping", None, to_b, 2).unwrap() };
+        assert!(cq.read(&mut completions).unwrap_err().is_again());
+        let reads = (0..3)
+            .position(|_| cq.read(&mut completions).is_ok())
+            .expect("completion held back too long");
+        assert!(reads < 3);
+        assert_eq!(completions[0].context(), 2);
+        assert_eq!(faults.stats().delayed, 1);
+    }
+
+    /// Recorded operations are matched with their completions, and the record is dumped on
+    /// errors.
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_recorder() {
+        use libfabric::mock::MockFabric;
+        use libfabric::{OpKind, OpStatus, Recorder};
+        use sys::bindgen as ffi;
+
+        let dump = std::env::temp_dir().join(format!("fabric-ops-{}.txt", std::process::id()));
+        let _ = std::fs::remove_file(&dump);
+        let recorder = Recorder::new(4).dump_on_error(&dump);
+        let fabric = MockFabric::new();
+        let (a, b) = (
+            recorder.endpoint(fabric.endpoint()),
+            recorder.endpoint(fabric.endpoint()),
+        );
+        let (cq_a, cq_b) = (recorder.cq(a.get_ref().cq()), recorder.cq(b.get_ref().cq()));
+        let to_b = fabric.av().insert(&b.name().unwrap()).unwrap();
+        let mut completions = [Completion::default(); 4];
+        let mut buf = [0u8; 8];
+
+        unsafe {
+            b.trecv(&mut buf, None, Addr::UNSPEC, 0x5, 0, 1).unwrap();
+            b.recv(&mut buf, None, Addr::UNSPEC, 2).unwrap();
+            a.tsend(b"ping", None, to_b, 0x5, 3).unwrap();
+        }
+        assert_eq!(cq_a.read(&mut completions).unwrap(), 1);
+        assert_eq!(cq_b.read(&mut completions).unwrap(), 1);
+        let ops = recorder.ops();
+        assert_eq!(ops.len(), 3);
+        assert_eq!(
+            (ops[0].endpoint, ops[0].kind, ops[0].tag),
+            (1, OpKind::TRecv, Some(5))
+        );
+        assert_eq!((ops[2].endpoint, ops[2].len), (0, 4));
+        assert!(ops[0].latency().is_some() && ops[2].latency().is_some());
+        let pending = recorder.pending();
+        assert_eq!((pending.len(), pending[0].kind), (1, OpKind::Recv));
+        assert!(!dump.exists());
+
+        // Failed posts are recorded, the oldest operations dropped, and errors dumped.
+        fabric.fail_posts(1, ffi::FI_EAGAIN as i32);
+        assert!(a.inject(b"ping", to_b).is_err());
+        fabric.fail_completions(1, ffi::FI_EIO as i32);
+        unsafe { a.send(b"lost", None, to_b, 4).unwrap() };
+        assert!(cq_a.read(&mut completions).unwrap_err().is_avail());
+        cq_a.read_err().unwrap().unwrap();
+        let ops = recorder.ops();
+        assert_eq!(ops.len(), 4);
+        assert!(matches!(&ops[2].status, OpStatus::Rejected(err) if err.is_again()));
+        assert!(matches!(&ops[3].status, OpStatus::Failed { .. }));
+        let dumped = std::fs::read_to_string(&dump).unwrap();
+        assert_eq!(dumped.lines().count(), 4);
+        assert!(dumped.lines().last().unwrap().contains("fi_send len=4"));
+        std::fs::remove_file(&dump).unwrap();
+    }
+
+    /// Recorded operations are exported as slices of Chrome trace JSON and Perfetto protobuf,
+    /// those in flight together laid out on tracks of their own.
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_recorder_export_trace() {
+        use libfabric::mock::MockFabric;
+        use libfabric::{Recorder, TraceFormat};
+        use sys::bindgen as ffi;
+
+        let recorder = Recorder::new(16);
+        let fabric = MockFabric::new();
+        let (a, b) = (
+            recorder.endpoint(fabric.endpoint()),
+            recorder.endpoint(fabric.endpoint()),
+        );
+        let cq_a = recorder.cq(a.get_ref().cq());
+        let to_b = fabric.av().insert(&b.name().unwrap()).unwrap();
+        let mut completions = [Completion::default(); 4];
+        let mut buf = [0u8; 8];
+
+        unsafe {
+            b.recv(&mut buf, None, Addr::UNSPEC, 1).unwrap();
+            b.trecv(&mut buf, None, Addr::UNSPEC, 0x5, 0, 2).unwrap();
+            a.tsend(b"ping", None, to_b, 0x5, 3).unwrap();
+        }
+        assert_eq!(cq_a.read(&mut completions).unwrap(), 1);
+        fabric.fail_posts(1, ffi::FI_EAGAIN as i32);
+        assert!(a.inject(b"ping", to_b).is_err());
+
+        let mut json = Vec::new();
+        recorder
+            .export_trace(&mut json, TraceFormat::ChromeJson)
+            .unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.starts_with('{') && json.trim_end().ends_with("]}"));
+        // Both receives of b are in flight together, on two tracks.
+        for name in ["libfabric ep0", "libfabric ep1", "libfabric ep1 (2)"] {
+            assert!(json.contains(&format!("\"args\":{{\"name\":\"{name}\"}}")));
+        }
+        assert_eq!(json.matches("\"ph\":\"X\"").count(), 3);
+        assert_eq!(json.matches("\"ph\":\"i\"").count(), 1);
+        assert!(json.contains("\"name\":\"fi_tsend\""));
+        assert!(json.contains("\"len\":4,"));
+        assert!(json.contains("\"tag\":\"0x5\""));
+        assert!(json.contains("\"status\":\"rejected\""));
+        assert_eq!(json.matches("\"status\":\"pending\"").count(), 2);
+
+        let mut proto = Vec::new();
+        recorder
+            .export_trace(&mut proto, TraceFormat::Perfetto)
+            .unwrap();
+        // The packets of the trace: the process, 3 tracks, 3 slices of 2 and an instant.
+        let mut packets = 0;
+        let mut rest = &proto[..];
+        while !rest.is_empty() {
+            assert_eq!(rest[0], 0x0a);
+            let (mut len, mut shift, mut i) = (0usize, 0, 1);
+            loop {
+                len |= ((rest[i] & 0x7f) as usize) << shift;
+                shift += 7;
+                i += 1;
+                if rest[i - 1] < 0x80 {
+                    break;
+                }
+            }
+            rest = &rest[i + len..];
+            packets += 1;
+        }
+        assert_eq!(packets, 1 + 3 + 3 * 2 + 1);
+        let contains = |s: &str| proto.windows(s.len()).any(|w| w == s.as_bytes());
+        assert!(contains("fi_tsend") && contains("libfabric ep1 (2)"));
+    }
+
+    /// Tracked endpoints count their operations by kind, their bytes, EAGAIN posts and error
+    /// completions, on whichever queue the completions are read.
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_endpoint_stats() {
+        use libfabric::mock::MockFabric;
+        use libfabric::{OpKind, StatsTracker};
+        use sys::bindgen as ffi;
+
+        let tracker = StatsTracker::new();
+        let fabric = MockFabric::new();
+        let (a, b) = (
+            tracker.endpoint(fabric.endpoint()),
+            tracker.endpoint(fabric.endpoint()),
+        );
+        let (cq_a, cq_b) = (tracker.cq(a.get_ref().cq()), tracker.cq(b.get_ref().cq()));
+        let to_b = fabric.av().insert(&b.name().unwrap()).unwrap();
+        let mut completions = [Completion::default(); 4];
+        let mut buf = [0u8; 8];
+
+        unsafe {
+            b.recv(&mut buf, None, Addr::UNSPEC, 1).unwrap();
+            b.recv(&mut buf, None, Addr::UNSPEC, 2).unwrap();
+            a.send(b"ping", None, to_b, 3).unwrap();
+        }
+        a.inject(b"hi", to_b).unwrap();
+        assert_eq!(b.stats().of(OpKind::Recv).in_flight, 2);
+        assert_eq!(cq_a.read(&mut completions).unwrap(), 1);
+        assert_eq!(cq_b.read(&mut completions).unwrap(), 2);
+        let (stats_a, stats_b) = (a.stats(), b.stats());
+        assert_eq!(stats_a.of(OpKind::Send).completed, 1);
+        assert_eq!(stats_a.of(OpKind::Inject).completed, 1);
+        assert_eq!((stats_a.bytes_sent, stats_a.in_flight()), (6, 0));
+        assert_eq!(
+            (stats_b.of(OpKind::Recv).completed, stats_b.bytes_received),
+            (2, 6)
+        );
+
+        fabric.fail_posts(1, ffi::FI_EAGAIN as i32);
+        assert!(a.inject(b"ping", to_b).is_err());
+        fabric.fail_completions(1, ffi::FI_EIO as i32);
+        unsafe { a.send(b"lost", None, to_b, 4).unwrap() };
+        assert!(cq_a.read(&mut completions).unwrap_err().is_avail());
+        cq_a.read_err().unwrap().unwrap();
+        let stats = a.stats();
+        assert_eq!((stats.again, stats.errors), (1, 1));
+        assert_eq!(stats.of(OpKind::Send).failed, 1);
+        assert_eq!(tracker.stats().of(OpKind::Send).posted, 2);
+
+        // Operations in flight stay so across resets.
+        unsafe { b.recv(&mut buf, None, Addr::UNSPEC, 5).unwrap() };
+        tracker.reset();
+        assert_eq!(a.stats(), Default::default());
+        assert_eq!(b.stats().in_flight(), 1);
+    }
+
+    /// Operations are in flight to their peer until their completion is read, receives from
+    /// any peer counting for none.
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_in_flight_to() {
+        use libfabric::StatsTracker;
+        use libfabric::mock::MockFabric;
+
+        let tracker = StatsTracker::new();
+        let fabric = MockFabric::new();
+        let (a, b) = (
+            tracker.endpoint(fabric.endpoint()),
+            tracker.endpoint(fabric.endpoint()),
+        );
+        let cq_a = tracker.cq(a.get_ref().cq());
+        let to_b = fabric.av().insert(&b.name().unwrap()).unwrap();
+        let mut buf = [0u8; 8];
+        unsafe {
+            b.recv(&mut buf, None, Addr::UNSPEC, 1).unwrap();
+            a.send(b"ping", None, to_b, 2).unwrap();
+        }
+        assert_eq!(tracker.in_flight_to(to_b), 1);
+        assert_eq!(tracker.in_flight_to(Addr::UNSPEC), 1);
+        let mut completions = [Completion::default(); 1];
+        assert_eq!(cq_a.read(&mut completions).unwrap(), 1);
+        assert_eq!(tracker.in_flight_to(to_b), 0);
+    }
+
+    /// Peers with operations in flight on the endpoints of a tracker are not removed, until
+    /// their completions are read.
+    #[test]
+    fn test_remove_idle() {
+        use sys::bindgen as ffi;
+
+        let MsgEndpoint { ep, cq, av, .. } = enabled_msg_endpoint();
+        let me = av.insert(&ep.name().unwrap()).unwrap();
+        let tracker = StatsTracker::new();
+        let (ep, cq) = (tracker.endpoint(ep), tracker.cq(cq));
+
+        let mut buf = [0u8; 4];
+        unsafe {
+            ep.recv(&mut buf, None, Addr::UNSPEC, 1).unwrap();
+            ep.send(b"ping", None, me, 2).unwrap();
+        }
+        let err = av.remove_idle(&[me], &tracker).unwrap_err();
+        assert_eq!(err.code(), ffi::FI_EBUSY as i32);
+        let mut completions = [Completion::default(); 2];
+        let mut done = 0;
+        while done < 2 {
+            match cq.read(&mut completions) {
+                Err(err) if err.is_again() => {}
+                other => done += other.unwrap(),
+            }
+        }
+        av.remove_idle(&[me], &tracker).unwrap();
+        assert_eq!(av.get(0), Some(Addr::NOTAVAIL));
+    }
+
+    /// Error completions are drained by reads, classified, counted per peer and handed to the
+    /// hooks, which evict unreachable peers, then queued or sent to a subscriber.
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_error_triage() {
+        use libfabric::mock::MockFabric;
+        use libfabric::{ErrorClass, ErrorOrigin, ErrorTriage};
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use sys::bindgen as ffi;
+
+        let class = ErrorClass::of_code(ffi::FI_ENORX as i32);
+        assert!(class.is_transient() && class.is_remote() && !class.unreachable);
+        let class = ErrorClass::of_code(ffi::FI_ETRUNC as i32);
+        assert_eq!(class.origin, ErrorOrigin::Local);
+        assert!(!class.is_transient());
+
+        let fabric = MockFabric::new();
+        let (a, b) = (fabric.endpoint(), fabric.endpoint());
+        let to_b = fabric.av().insert(&b.name().unwrap()).unwrap();
+        let hooked = Arc::new(AtomicUsize::new(0));
+        let counted = hooked.clone();
+        let cq = ErrorTriage::new(a.cq())
+            .peer_of(move |_| Some(to_b))
+            .evict_unreachable(fabric.av())
+            .hook(move |_| {
+                counted.fetch_add(1, Ordering::Relaxed);
+            });
+        let mut completions = [Completion::default(); 4];
+        let mut buf = [0u8; 8];
+
+        fabric.fail_completions(2, ffi::FI_EHOSTUNREACH as i32);
+        unsafe {
+            a.send(b"lost", None, to_b, 1).unwrap();
+            a.send(b"lost", None, to_b, 2).unwrap();
+        }
+        assert!(cq.read(&mut completions).unwrap_err().is_again());
+        let errors = cq.take_errors();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].entry.context, 1);
+        assert!(errors[0].class.unreachable && errors[0].peer == Some(to_b));
+        assert_eq!(hooked.load(Ordering::Relaxed), 2);
+        assert_eq!(cq.per_peer()[&to_b], 2);
+
+        // Completions read on once the errors are drained, and errors go to the subscriber.
+        let subscriber = cq.subscribe();
+        fabric.fail_completions(1, ffi::FI_EIO as i32);
+        unsafe {
+            b.recv(&mut buf, None, Addr::UNSPEC, 3).unwrap();
+            a.send(b"lost", None, to_b, 4).unwrap();
+            a.send(b"ping", None, to_b, 5).unwrap();
+        }
+        assert_eq!(cq.read(&mut completions).unwrap(), 1);
+        assert_eq!(completions[0].context(), 5);
+        assert_eq!(subscriber.try_recv().unwrap().entry.context, 4);
+        assert!(cq.take_errors().is_empty());
+        drop(subscriber);
+        fabric.fail_completions(1, ffi::FI_EIO as i32);
+        unsafe { a.send(b"lost", None, to_b, 6).unwrap() };
+        fabric.progress();
+        assert_eq!(cq.read_err().unwrap().unwrap().context, 6);
+        assert_eq!(cq.take_errors().len(), 1);
+    }
+
+    /// Fatal error completions reach every subscriber of a lifecycle broadcast, transient ones
+    /// none, and subscribers which dropped their receiver are forgotten.
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_lifecycle() {
+        use libfabric::mock::MockFabric;
+        use libfabric::{ErrorTriage, Lifecycle, LifecycleEvent};
+        use sys::bindgen as ffi;
+
+        let fabric = MockFabric::new();
+        let (a, b) = (fabric.endpoint(), fabric.endpoint());
+        let to_b = fabric.av().insert(&b.name().unwrap()).unwrap();
+        let lifecycle = Lifecycle::new();
+        let (first, second) = (lifecycle.subscribe(), lifecycle.subscribe());
+        let cq = ErrorTriage::new(a.cq()).hook(lifecycle.cq_errors());
+        let mut completions = [Completion::default(); 4];
+
+        for (code, context) in [(ffi::FI_ENORX, 1), (ffi::FI_EHOSTUNREACH, 2)] {
+            fabric.fail_completions(1, code as i32);
+            unsafe { a.send(b"lost", None, to_b, context).unwrap() };
+            assert!(cq.read(&mut completions).unwrap_err().is_again());
+        }
+        let mut published = Vec::new();
+        for events in [&first, &second] {
+            let event = events.try_recv().unwrap();
+            assert!(matches!(&event, LifecycleEvent::CqError(err) if err.entry.context == 2));
+            assert!(events.try_recv().is_err());
+            published.push(event);
+        }
+
+        drop(second);
+        lifecycle.publish(published.remove(0));
+        assert!(matches!(first.try_recv(), Ok(LifecycleEvent::CqError(_))));
+        assert_eq!(lifecycle.subscribers(), 1);
+    }
+
+    /// Only completions flagged with `FI_CLAIM` are buffered messages.
+    #[test]
+    fn test_buffered_recv() {
+        use libfabric::BufferedRecv;
+
+        assert!(unsafe { BufferedRecv::from_completion(&Completion::default()) }.is_none());
+    }
+
+    /// Completions of a queue shared by endpoints go back to the endpoint whose tag their
+    /// context bears, untagged, errors included, and poll() rounds start from the next
+    /// endpoint each time, under the quota.
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_shared_cq() {
+        use libfabric::mock::MockFabric;
+        use libfabric::{Demuxed, SharedCq, SharedCqAttr};
+        use sys::bindgen as ffi;
+
+        let fabric = MockFabric::new();
+        let (server, a, b) = (fabric.endpoint(), fabric.endpoint(), fabric.endpoint());
+        let to_a = fabric.av().insert(&a.name().unwrap()).unwrap();
+        let to_b = fabric.av().insert(&b.name().unwrap()).unwrap();
+        let cq = SharedCq::with_attr(server.cq(), SharedCqAttr::new().quota(2));
+        let (conn_a, conn_b) = (cq.attach().unwrap(), cq.attach().unwrap());
+        assert_ne!(conn_a.id(), conn_b.id());
+        let mut completions = [Completion::default(); 4];
+
+        fabric.fail_completions(1, ffi::FI_ECONNRESET as i32);
+        unsafe {
+            server.send(b"lost", None, to_b, conn_b.tag(20)).unwrap();
+            for context in 1..=3 {
+                server.send(b"a", None, to_a, conn_a.tag(context)).unwrap();
+            }
+            server.send(b"b", None, to_b, conn_b.tag(10)).unwrap();
+            server.send(b"untagged", None, to_b, 99).unwrap();
+        }
+        // The read of one endpoint queues the entries of the others, under the quota.
+        assert_eq!(conn_a.read(&mut completions).unwrap(), 2);
+        assert_eq!((completions[0].context(), completions[1].context()), (1, 2));
+        assert_eq!(conn_b.pending(), 2);
+        assert_eq!(conn_b.read(&mut completions).unwrap(), 1);
+        assert_eq!(completions[0].context(), 10);
+        assert!(conn_b.read(&mut completions).unwrap_err().is_avail());
+        let entry = conn_b.read_err().unwrap().unwrap();
+        assert_eq!(
+            (entry.context, entry.error.code()),
+            (20, ffi::FI_ECONNRESET as i32)
+        );
+        assert!(conn_b.read(&mut completions).unwrap_err().is_again());
+        match &cq.take_strays()[..] {
+            [Demuxed::Completion { completion, .. }] => assert_eq!(completion.context(), 99),
+            strays => panic!("strays: {strays:?}"),
+        }
+
+        let mut handed = Vec::new();
+        assert_eq!(cq.poll(|id, _| handed.push(id)).unwrap(), 1);
+        unsafe {
+            for context in 4..=6 {
+                server.send(b"a", None, to_a, conn_a.tag(context)).unwrap();
+                server.send(b"b", None, to_b, conn_b.tag(context)).unwrap();
+            }
+        }
+        assert_eq!(cq.poll(|id, _| handed.push(id)).unwrap(), 4);
+        assert_eq!(cq.poll(|id, _| handed.push(id)).unwrap(), 2);
+        let (a_id, b_id) = (conn_a.id(), conn_b.id());
+        assert_eq!(handed, [a_id, b_id, b_id, a_id, a_id, a_id, b_id]);
+
+        // The entries of detached endpoints are strays.
+        unsafe { server.send(b"a", None, to_a, conn_a.tag(7)).unwrap() };
+        assert!(conn_b.read(&mut completions).unwrap_err().is_again());
+        drop(conn_a);
+        assert_eq!(cq.attached(), 1);
+        assert_eq!(cq.take_strays().len(), 1);
+    }
+
+    /// Senders stop at the window of credits their peer granted, and resume as the peer
+    /// receives, with credits granted back in messages of their own or along with replies.
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_flow_control() {
+        use libfabric::mock::MockFabric;
+        use libfabric::{CreditAttr, FlowControl};
+
+        let fabric = MockFabric::new();
+        let (a, b) = (fabric.endpoint(), fabric.endpoint());
+        let av = fabric.av();
+        let (to_a, to_b) = (
+            av.insert(&a.name().unwrap()).unwrap(),
+            av.insert(&b.name().unwrap()).unwrap(),
+        );
+        let attr = CreditAttr::new().window(2).max_size(16);
+        let (cq_a, cq_b) = (a.cq(), b.cq());
+        let mut a = unsafe { FlowControl::new(a, cq_a, &attr) }.unwrap();
+        let mut b = unsafe { FlowControl::new(b, cq_b, &attr) }.unwrap();
+
+        for i in 0..5u8 {
+            a.send(to_b, &[i]).unwrap();
+        }
+        assert_eq!((a.credits(to_b), a.backlog()), (0, 3));
+        assert!(a.send(to_b, &[0; 17]).is_err());
+
+        // Each message received grants a credit back, the threshold being one here.
+        let mut received = Vec::new();
+        while received.len() < 5 {
+            a.poll().unwrap();
+            if let Some((src, buf)) = b.recv().unwrap() {
+                assert_eq!(src, to_a);
+                received.extend(buf);
+            }
+        }
+        assert_eq!(received, [0, 1, 2, 3, 4]);
+        a.poll().unwrap();
+        assert_eq!((a.credits(to_b), a.backlog()), (2, 0));
+
+        // Replies carry the credits owed, with no grant of their own.
+        let attr = attr.threshold(2);
+        let fabric = MockFabric::new();
+        let (a, b) = (fabric.endpoint(), fabric.endpoint());
+        let to_b = fabric.av().insert(&b.name().unwrap()).unwrap();
+        let to_a = fabric.av().insert(&a.name().unwrap()).unwrap();
+        let (cq_a, cq_b) = (a.cq(), b.cq());
+        let mut a = unsafe { FlowControl::new(a, cq_a, &attr) }.unwrap();
+        let mut b = unsafe { FlowControl::new(b, cq_b, &attr) }.unwrap();
+        a.send(to_b, b"ping").unwrap();
+        while b.recv().unwrap().is_none() {}
+        assert_eq!(a.credits(to_b), 1);
+        b.send(to_a, b"pong").unwrap();
+        let (_, pong) = loop {
+            if let Some(message) = a.recv().unwrap() {
+                break message;
+            }
+        };
+        assert_eq!((pong.as_slice(), a.credits(to_b)), (&b"pong"[..], 2));
+
+        // The credits granted along with a failed message are owed again, and granted with the
+        // next one, such that the window of the peer recovers.
+        a.send(to_b, b"ping").unwrap();
+        while b.recv().unwrap().is_none() {}
+        fabric.fail_completions(1, sys::bindgen::FI_EIO as i32);
+        b.send(to_a, b"lost").unwrap();
+        let err = loop {
+            if let Err(err) = b.poll() {
+                break err;
+            }
+        };
+        assert_eq!(err.code(), sys::bindgen::FI_EIO as i32);
+        assert_eq!((b.credits(to_a), a.credits(to_b)), (2, 1));
+        b.send(to_a, b"pong").unwrap();
+        let (_, pong) = loop {
+            if let Some(message) = a.recv().unwrap() {
+                break message;
+            }
+        };
+        assert_eq!((pong.as_slice(), a.credits(to_b)), (&b"pong"[..], 2));
+    }
+
+    /// Peers take turns in the transmit context, one message each round robin, or in
+    /// proportion to their weights.
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_send_scheduler() {
+        use libfabric::mock::MockFabric;
+        use libfabric::{SchedulePolicy, SchedulerAttr, SendScheduler};
+
+        let run = |attr: &SchedulerAttr, hot: usize, cold: usize| {
+            let fabric = MockFabric::new();
+            let (a, b, c) = (fabric.endpoint(), fabric.endpoint(), fabric.endpoint());
+            let av = fabric.av();
+            let (to_b, to_c) = (
+                av.insert(&b.name().unwrap()).unwrap(),
+                av.insert(&c.name().unwrap()).unwrap(),
+            );
+            let cq = a.cq();
+            let mut sched = unsafe { SendScheduler::new(a, cq, &attr.clone().max_in_flight(1)) };
+            sched.set_weight(to_c, 2);
+            for _ in 0..hot {
+                sched.send(to_b, &[0; 4]).unwrap();
+            }
+            for _ in 0..cold {
+                sched.send(to_c, &[0; 4]).unwrap();
+            }
+            assert_eq!(sched.in_flight(), 1);
+            let mut order = Vec::new();
+            while sched.backlog() > 0 {
+                let queued = (sched.queued(to_b), sched.queued(to_c));
+                sched.poll().unwrap();
+                if sched.queued(to_b) < queued.0 {
+                    order.push('b');
+                }
+                if sched.queued(to_c) < queued.1 {
+                    order.push('c');
+                }
+            }
+            order.into_iter().collect::<String>()
+        };
+        // The first message to b is posted at once.
+        assert_eq!(run(&SchedulerAttr::new(), 4, 2), "bcbcb");
+        let weighted = SchedulerAttr::new()
+            .policy(SchedulePolicy::Weighted)
+            .quantum(4);
+        assert_eq!(run(&weighted, 4, 4), "bccbccb");
+
+        // Peers with full queues push back, and messages the provider has no room for wait.
+        let fabric = MockFabric::new();
+        let (a, b) = (fabric.endpoint(), fabric.endpoint());
+        let to_b = fabric.av().insert(&b.name().unwrap()).unwrap();
+        let cq = a.cq();
+        let attr = SchedulerAttr::new().max_queued(2);
+        let mut sched = unsafe { SendScheduler::new(a, cq, &attr) };
+        fabric.fail_posts(usize::MAX, sys::bindgen::FI_EAGAIN as i32);
+        sched.send(to_b, b"one").unwrap();
+        sched.tsend(to_b, b"two", 7).unwrap();
+        let err = sched.send(to_b, b"three").unwrap_err();
+        assert!(err.is_again());
+        assert_eq!((sched.queued(to_b), sched.in_flight()), (2, 0));
+        fabric.fail_posts(0, 0);
+        sched.poll().unwrap();
+        assert_eq!(sched.backlog(), 0);
+    }
+
+    /// Values sent over a fabric channel arrive in order, the sender blocking once the window
+    /// of credits is spent until the receiver takes them in.
+    #[cfg(all(feature = "mock", feature = "channel"))]
+    #[test]
+    fn test_fabric_channel() {
+        use libfabric::CreditAttr;
+        use libfabric::channel::fabric_channel;
+        use libfabric::mock::MockFabric;
+
+        let fabric = MockFabric::new();
+        let (a, b) = (fabric.endpoint(), fabric.endpoint());
+        let av = fabric.av();
+        let (to_a, to_b) = (
+            av.insert(&a.name().unwrap()).unwrap(),
+            av.insert(&b.name().unwrap()).unwrap(),
+        );
+        let attr = CreditAttr::new().window(2).threshold(1).max_size(64);
+        let (cq_a, cq_b) = (a.cq(), b.cq());
+        let (tx, _) = unsafe { fabric_channel::<(u32, String), _, _>(a, cq_a, &attr) }.unwrap();
+        let (_, rx) = unsafe { fabric_channel::<(u32, String), _, _>(b, cq_b, &attr) }.unwrap();
+        assert!(tx.send(&(0, String::new())).is_err());
+
+        #[cfg(feature = "sink")]
+        let tx_sink = tx.clone();
+        let tx = tx.to(to_b);
+        let sender = std::thread::spawn(move || {
+            for i in 0..5 {
+                tx.send(&(i, i.to_string())).unwrap();
+            }
+            while tx.backlog() > 0 {
+                tx.flush().unwrap();
+            }
+        });
+        for i in 0..5 {
+            assert_eq!(rx.recv_from().unwrap(), (to_a, (i, i.to_string())));
+        }
+        sender.join().unwrap();
+        assert!(rx.try_recv().unwrap().is_none());
+
+        // As a sink, the sender is not ready while values wait for credits, once those
+        // granted for the values above are taken in.
+        #[cfg(feature = "sink")]
+        {
+            use futures_sink::Sink;
+            use std::pin::Pin;
+            use std::task::{Context, Waker};
+
+            let mut cx = Context::from_waker(Waker::noop());
+            let mut tx = tx_sink.to(to_b);
+            tx.flush().unwrap();
+            let mut tx = Pin::new(&mut tx);
+            for i in 0..3 {
+                assert!(tx.as_mut().poll_ready(&mut cx).is_ready());
+                tx.as_mut().start_send((i, String::new())).unwrap();
+            }
+            assert!(tx.as_mut().poll_ready(&mut cx).is_pending());
+            let mut received = Vec::new();
+            while received.len() < 3 {
+                if let Some((i, _)) = rx.try_recv().unwrap() {
+                    received.push(i);
+                }
+                let _ = tx.as_mut().poll_flush(&mut cx);
+            }
+            assert_eq!(received, [0, 1, 2]);
+            assert!(tx.as_mut().poll_flush(&mut cx).is_ready());
+        }
+    }
+
+    /// A message sink is ready while fewer sends than its depth are in flight, queues those
+    /// the provider has no room for, and reports the failed ones on flush.
+    #[cfg(all(feature = "mock", feature = "sink"))]
+    #[test]
+    fn test_msg_sink() {
+        use futures_sink::Sink;
+        use libfabric::mock::MockFabric;
+        use std::pin::Pin;
+        use std::task::{Context, Poll, Waker};
+        use std::time::Duration;
+
+        let fabric = MockFabric::new();
+        let (a, b) = (fabric.endpoint(), fabric.endpoint());
+        let to_b = fabric.av().insert(&b.name().unwrap()).unwrap();
+        let (cq_a, cq_b) = (a.cq(), b.cq());
+        let mut sink = unsafe { MsgSink::new(a, cq_a, to_b, 2) };
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut sink = Pin::new(&mut sink);
+
+        fabric.set_delay(Duration::from_millis(20));
+        for i in 0..2u8 {
+            assert!(sink.as_mut().poll_ready(&mut cx).is_ready());
+            sink.as_mut().start_send(vec![i]).unwrap();
+        }
+        assert!(sink.as_mut().poll_ready(&mut cx).is_pending());
+        fabric.set_delay(Duration::ZERO);
+        while sink.as_mut().poll_ready(&mut cx).is_pending() {}
+
+        fabric.fail_posts(1, sys::bindgen::FI_EAGAIN as i32);
+        sink.as_mut().start_send(vec![2]).unwrap();
+        assert_eq!(sink.in_flight(), 1);
+        while sink.as_mut().poll_flush(&mut cx).is_pending() {}
+        assert_eq!(sink.in_flight(), 0);
+
+        let mut received = [0u8; 3];
+        for (i, byte) in received.iter_mut().enumerate() {
+            unsafe { b.recv(std::slice::from_mut(byte), None, Addr::UNSPEC, i) }.unwrap();
+        }
+        let mut completions = [Completion::default(); 3];
+        let mut n = 0;
+        while n < 3 {
+            n += cq_b.read(&mut completions[n..]).unwrap_or(0);
+        }
+        assert_eq!(received, [0, 1, 2]);
+
+        fabric.fail_posts(1, sys::bindgen::FI_EIO as i32);
+        sink.as_mut().start_send(vec![3]).unwrap();
+        let Poll::Ready(Err(err)) = sink.as_mut().poll_flush(&mut cx) else {
+            panic!("the failed send was not reported");
+        };
+        assert_eq!(err.code(), sys::bindgen::FI_EIO as i32);
+        assert!(sink.as_mut().poll_close(&mut cx).is_ready());
+    }
+
+    /// Streams of a multiplexer are flow controlled on their own: one out of credits queues its
+    /// messages without holding back the others.
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_multiplexer() {
+        use libfabric::mock::MockFabric;
+        use libfabric::{Multiplexer, MuxAttr, Stream};
+
+        let fabric = MockFabric::new();
+        let (a, b) = (fabric.endpoint(), fabric.endpoint());
+        let av = fabric.av();
+        let (to_a, to_b) = (
+            av.insert(&a.name().unwrap()).unwrap(),
+            av.insert(&b.name().unwrap()).unwrap(),
+        );
+        let attr = MuxAttr::new().window(2).threshold(1).max_size(16);
+        let (cq_a, cq_b) = (a.cq(), b.cq());
+        let mut a = unsafe { Multiplexer::new(a, cq_a, &attr) }.unwrap();
+        let mut b = unsafe { Multiplexer::new(b, cq_b, &attr) }.unwrap();
+
+        let (bulk, control) = (Stream::new(to_b, 1), Stream::new(to_b, 0));
+        for i in 0..4u8 {
+            a.send(bulk, &[i]).unwrap();
+        }
+        a.send(control, b"stop").unwrap();
+        assert_eq!((a.credits(bulk), a.backlog(bulk)), (0, 2));
+        assert_eq!((a.credits(control), a.backlog(control)), (1, 0));
+        assert!(a.send(bulk, &[0; 17]).is_err());
+
+        let control = Stream::new(to_a, 0);
+        let stop = loop {
+            if let Some(message) = b.recv(control).unwrap() {
+                break message;
+            }
+        };
+        assert_eq!(stop, b"stop");
+
+        // Each message received grants a credit back, letting the next one out.
+        let mut received = Vec::new();
+        while received.len() < 4 {
+            a.poll().unwrap();
+            if let Some((stream, message)) = b.recv_any().unwrap() {
+                assert_eq!(stream, Stream::new(to_a, 1));
+                received.extend(message);
+            }
+        }
+        assert_eq!(received, [0, 1, 2, 3]);
+        a.poll().unwrap();
+        assert_eq!((a.credits(bulk), a.backlog(bulk)), (2, 0));
+    }
+
+    /// Small messages to a peer go out as one batch, once the next one does not fit or once
+    /// flushed, and are unpacked in order by the receiver.
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_coalescer() {
+        use libfabric::mock::MockFabric;
+        use libfabric::{CoalesceAttr, Coalescer};
+        use std::time::Duration;
+
+        let fabric = MockFabric::new();
+        let (a, b) = (fabric.endpoint(), fabric.endpoint());
+        let av = fabric.av();
+        let (to_a, to_b) = (
+            av.insert(&a.name().unwrap()).unwrap(),
+            av.insert(&b.name().unwrap()).unwrap(),
+        );
+        let attr = CoalesceAttr::new()
+            .max_size(8)
+            .delay(Duration::from_secs(60))
+            .inject_size(4);
+        let (cq_a, cq_b) = (a.cq(), b.cq());
+        let mut a = unsafe { Coalescer::new(a, cq_a, &attr) }.unwrap();
+        let mut b = unsafe { Coalescer::new(b, cq_b, &attr) }.unwrap();
+
+        a.send(to_b, b"ab").unwrap();
+        a.send(to_b, b"c").unwrap();
+        assert_eq!(a.pending(), 2);
+        assert!(b.recv().unwrap().is_none());
+        assert!(a.send(to_b, &[0; 7]).is_err());
+
+        // The third message does not fit, closing the batch of the first two.
+        a.send(to_b, b"de").unwrap();
+        assert_eq!(a.pending(), 1);
+        a.flush().unwrap();
+        assert_eq!(a.pending(), 0);
+
+        let mut received = Vec::new();
+        while received.len() < 3 {
+            a.poll().unwrap();
+            if let Some((src, message)) = b.recv().unwrap() {
+                assert_eq!(src, to_a);
+                received.push(message);
+            }
+        }
+        assert_eq!(received, [&b"ab"[..], b"c", b"de"]);
+    }
+
+    /// Messages under the threshold are sent eagerly, and those above are read by the receiver
+    /// from the registered buffer of the sender, which completes once notified.
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_rendezvous() {
+        use libfabric::mock::MockFabric;
+        use libfabric::{Rendezvous, RendezvousAttr};
+
+        let fabric = MockFabric::new();
+        let (a, b) = (fabric.endpoint(), fabric.endpoint());
+        let av = fabric.av();
+        let (to_a, to_b) = (
+            av.insert(&a.name().unwrap()).unwrap(),
+            av.insert(&b.name().unwrap()).unwrap(),
+        );
+        let attr = RendezvousAttr::new().threshold(64);
+        let (cq_a, cq_b) = (a.cq(), b.cq());
+        let mut a = unsafe { Rendezvous::new(a, cq_a, &attr) }.unwrap();
+        let mut b = unsafe { Rendezvous::new(b, cq_b, &attr) }.unwrap();
+
+        let mut large: Vec<u8> = (0..200u8).collect();
+        let mr = unsafe {
+            fabric
+                .register(large.as_mut_ptr(), large.len(), Access::REMOTE_READ)
+                .unwrap()
+        };
+        assert!(unsafe { a.send(to_b, &large, None) }.is_err());
+        let small = unsafe { a.send(to_b, b"small", None) }.unwrap();
+        let big = unsafe { a.send(to_b, &large[100..], Some(&mr)) }.unwrap();
+
+        let mut received = Vec::new();
+        let mut sent = Vec::new();
+        while received.len() < 2 || sent.len() < 2 {
+            if let Some(message) = b.recv().unwrap() {
+                received.push(message);
+            }
+            if let Some(send) = a.sent().unwrap() {
+                sent.push(send);
+            }
+        }
+        assert_eq!(received[0], (to_a, b"small".to_vec()));
+        assert_eq!(received[1], (to_a, large[100..].to_vec()));
+        assert_eq!(
+            sent.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            [small, big]
+        );
+        assert!(sent.iter().all(|(_, result)| result.is_ok()));
+    }
+
+    /// Messages, tagged messages and RMA move between mock endpoints, with the completions a
+    /// provider would report.
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_mock() {
+        use libfabric::mock::MockFabric;
+        use sys::bindgen as ffi;
+
+        let fabric = MockFabric::new();
+        let (a, b) = (fabric.endpoint(), fabric.endpoint());
+        let av = fabric.av();
+        let (to_a, to_b) = (
+            av.insert(&a.name().unwrap()).unwrap(),
+            av.insert(&b.name().unwrap()).unwrap(),
+        );
+        let mut completions = [Completion::default(); 4];
+
+        // An unexpected message waits for its receive, and tags are matched.
+        let mut buf = [0u8; 8];
+        let mut tagged = [0u8; 8];
+        unsafe {
+            a.senddata(b"untagged", None, 7, to_b, 1).unwrap();
+            a.tsend(b"tagged!!", None, to_b, 0x12, 2).unwrap();
+            b.trecv(&mut tagged, None, to_a, 0x10, 0xf, 3).unwrap();
+        }
+        assert_eq!(a.cq().read(&mut completions).unwrap(), 2);
+        assert_eq!(b.cq().read(&mut completions).unwrap(), 1);
+        assert_eq!((completions[0].context(), completions[0].tag()), (3, 0x12));
+        unsafe { b.recv(&mut buf, None, Addr::UNSPEC, 4).unwrap() };
+        assert_eq!(b.cq().read(&mut completions).unwrap(), 1);
+        assert_eq!(completions[0].data(), 7);
+        assert!(completions[0].has_data());
+        assert_eq!((&buf, &tagged), (b"untagged", b"tagged!!"));
+
+        // RMA goes through registered regions only.
+        let mut region = vec![0u8; 16];
+        let access = Access::REMOTE_READ | Access::REMOTE_WRITE;
+        let mr = unsafe {
+            fabric
+                .register(region.as_mut_ptr(), region.len(), access)
+                .unwrap()
+        };
+        let addr = mr.addr() as u64;
+        unsafe {
+            a.writedata(b"written", None, 9, to_b, addr + 8, mr.key(), 5)
+                .unwrap();
+            a.read(&mut buf[..4], None, to_b, addr, mr.key(), 6)
+                .unwrap();
+        }
+        assert_eq!(a.cq().read(&mut completions).unwrap(), 2);
+        assert_eq!(b.cq().read(&mut completions).unwrap(), 1);
+        assert_eq!(completions[0].data(), 9);
+        assert_eq!(&region[8..15], b"written");
+        assert_eq!(&buf[..4], &[0; 4]);
+        unsafe { a.write(b"!", None, to_b, addr + 16, mr.key(), 7).unwrap() };
+        assert!(a.cq().read(&mut completions).unwrap_err().is_avail());
+        let entry = a.cq().read_err().unwrap().unwrap();
+        assert_eq!(
+            (entry.context, entry.error.code()),
+            (7, ffi::FI_EACCES as i32)
+        );
+
+        // Truncated receives complete in error, with the overflow.
+        unsafe {
+            b.recv(&mut buf[..2], None, to_a, 8).unwrap();
+            a.inject(b"abc", to_b).unwrap();
+        }
+        assert!(b.cq().read(&mut completions).unwrap_err().is_avail());
+        let entry = b.cq().read_err().unwrap().unwrap();
+        assert_eq!((entry.len, entry.olen), (2, 1));
+        assert_eq!(entry.error.code(), ffi::FI_ETRUNC as i32);
+        assert!(
+            av.insert(&EndpointAddress::from_bytes(9u64.to_le_bytes()))
+                .is_err()
+        );
+
+        // Cancelled receives complete in error, and are no longer matched.
+        unsafe { b.recv(&mut buf, None, Addr::UNSPEC, 9).unwrap() };
+        b.cancel(9).unwrap();
+        assert!(b.cq().read(&mut completions).unwrap_err().is_avail());
+        let entry = b.cq().read_err().unwrap().unwrap();
+        assert_eq!(
+            (entry.context, entry.error.code()),
+            (9, ffi::FI_ECANCELED as i32)
+        );
+        unsafe { b.recv(&mut buf, None, Addr::UNSPEC, 10).unwrap() };
+        a.inject(b"late", to_b).unwrap();
+        assert_eq!(b.cq().read(&mut completions).unwrap(), 1);
+        let raw = completions[0].as_raw();
+        assert_eq!((raw.op_context as usize, raw.len), (10, 4));
+    }
+
+    /// The mock reports address insertions as debug spans, and data operations as trace events
+    /// once sampled by `trace_data_ops()`.
+    #[cfg(all(feature = "tracing", feature = "mock"))]
+    #[test]
+    fn test_trace_mock() {
+        use libfabric::mock::MockFabric;
+
+        let fabric = MockFabric::new();
+        let (a, b) = (fabric.endpoint(), fabric.endpoint());
+        let av = fabric.av();
+        let capture = Capture::default();
+        tracing::subscriber::with_default(capture.clone(), || {
+            let to_b = av.insert(&b.name().unwrap()).unwrap();
+            let inserted = capture.find("libfabric", "fi_av_insert");
+            assert_eq!(inserted.len(), 1);
+            assert_eq!(inserted[0]["provider"], "mock");
+
+            // Data operations are only traced once sampled.
+            let mut buf = [0u8; 8];
+            unsafe { a.send(b"unsampled", None, to_b, 1).unwrap() };
+            assert!(capture.find("libfabric::data", "fi_send").is_empty());
+            trace_data_ops(1);
+            unsafe {
+                a.tsend(b"sampled!", None, to_b, 0x12, 2).unwrap();
+                b.trecv(&mut buf, None, Addr::UNSPEC, 0x12, 0, 3).unwrap();
+            }
+            trace_data_ops(0);
+            let (sent, received) = (
+                capture.find("libfabric::data", "fi_tsend"),
+                capture.find("libfabric::data", "fi_trecv"),
+            );
+            assert_eq!((sent.len(), received.len()), (1, 1));
+            for fields in [&sent[0], &received[0]] {
+                assert_eq!(fields["provider"], "mock");
+                assert_eq!(fields["size"], "8");
+                assert_eq!(fields["tag"], "Some(18)");
+            }
+            let mut completions = [Completion::default(); 4];
+            assert_eq!(b.cq().read(&mut completions).unwrap(), 1);
+            assert_eq!(&buf, b"sampled!");
+        });
+    }
+
+    /// Injected delays hold operations back, and injected failures surface when posting and in
+    /// completions.
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_mock_faults() {
+        use libfabric::mock::MockFabric;
+        use std::time::Duration;
+        use sys::bindgen as ffi;
+
+        let fabric = MockFabric::new();
+        let (a, b) = (fabric.endpoint(), fabric.endpoint());
+        let to_b = fabric.av().insert(&b.name().unwrap()).unwrap();
+        let mut completions = [Completion::default(); 1];
+        let mut buf = [0u8; 4];
+
+        fabric.fail_posts(2, ffi::FI_EAGAIN as i32);
+        assert!(a.inject(b"ping", to_b).unwrap_err().is_again());
+        assert!(
+            unsafe { b.recv(&mut buf, None, Addr::UNSPEC, 1) }
+                .unwrap_err()
+                .is_again()
+        );
+        unsafe { b.recv(&mut buf, None, Addr::UNSPEC, 1).unwrap() };
+
+        fabric.set_delay(Duration::from_millis(50));
+        unsafe { a.send(b"ping", None, to_b, 2).unwrap() };
+        assert!(b.cq().read(&mut completions).unwrap_err().is_again());
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(b.cq().read(&mut completions).unwrap(), 1);
+        assert_eq!(&buf, b"ping");
+
+        fabric.set_delay(Duration::ZERO);
+        fabric.fail_completions(1, ffi::FI_EIO as i32);
+        unsafe { a.send(b"lost", None, to_b, 3).unwrap() };
+        assert_eq!(a.cq().read(&mut completions).unwrap(), 1);
+        assert!(a.cq().read(&mut completions).unwrap_err().is_avail());
+        let entry = a.cq().read_err().unwrap().unwrap();
+        assert_eq!((entry.context, entry.error.code()), (3, ffi::FI_EIO as i32));
+        assert!(b.cq().read(&mut completions).unwrap_err().is_again());
+    }
+
+    /// Simulated runs are reproducible from their seed, and reorder and drop messages as
+    /// configured.
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_sim() {
+        use libfabric::sim::Simulation;
+
+        // The messages, numbered from 1, in the order they were received, 0 for dropped ones.
+        let run = |sim: Simulation| {
+            let fabric = sim.fabric();
+            let (a, b) = (fabric.endpoint(), fabric.endpoint());
+            let to_b = fabric.av().insert(&b.name().unwrap()).unwrap();
+            let mut received = [[0u8; 1]; 16];
+            for buf in &mut received {
+                unsafe { b.recv(buf, None, Addr::UNSPEC, 0).unwrap() };
+            }
+            for i in 1..=16u8 {
+                a.inject(&[i], to_b).unwrap();
+            }
+            let mut completions = [Completion::default(); 16];
+            for _ in 0..64 {
+                let _ = b.cq().read(&mut completions);
+            }
+            received.map(|[i]| i)
+        };
+        let in_order: Vec<u8> = (1..=16).collect();
+        assert_eq!(run(Simulation::new(1)).to_vec(), in_order);
+
+        let delayed = Simulation::new(0).max_delay(4);
+        assert_eq!(run(delayed), run(delayed));
+        assert!((0..8).any(|seed| run(Simulation::new(seed).max_delay(4)).to_vec() != in_order));
+        for seed in 0..8 {
+            let mut received = run(Simulation::new(seed).max_delay(4)).to_vec();
+            received.sort();
+            assert_eq!(received, in_order);
+        }
+
+        let dropped = |sim| run(sim).iter().filter(|&&i| i == 0).count();
+        assert!((0..8).any(|seed| dropped(Simulation::new(seed).drop_rate(0.5)) > 0));
+        assert_eq!(dropped(Simulation::new(0).drop_rate(1.0)), 16);
+
+        let mut runs = 0;
+        let failed = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            Simulation::new(0).check(5..10, |_| {
+                runs += 1;
+                match runs {
+                    3 => Err(Error::InvalidArgument("third run".into())),
+                    _ => Ok(()),
+                }
+            })
+        }));
+        let message = failed.unwrap_err().downcast::<String>().unwrap();
+        assert!(message.contains("seed 7"), "{message}");
+    }
+
+    /// Calls reach the handler registered for their method, including while the caller is
+    /// itself waiting, and report handler errors, unknown methods and timeouts.
+    #[cfg(all(feature = "mock", feature = "rpc"))]
+    #[test]
+    fn test_rpc() {
+        use libfabric::mock::MockFabric;
+        use libfabric::rpc::{Raw, Rpc, RpcAttr};
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::time::Duration;
+        use sys::bindgen as ffi;
+
+        const ECHO: u32 = 1;
+        const FAIL: u32 = 2;
+        let timeout = Duration::from_secs(5);
+        let fabric = MockFabric::new();
+        let (client_ep, server_ep) = (fabric.endpoint(), fabric.endpoint());
+        let av = fabric.av();
+        let server_addr = av.insert(&server_ep.name().unwrap()).unwrap();
+        let client_addr = av.insert(&client_ep.name().unwrap()).unwrap();
+        let attr = RpcAttr::new().max_size(64).depth(4);
+        // The RPC layers own the only handles of their endpoints.
+        let (client_cq, server_cq) = (client_ep.cq(), server_ep.cq());
+        let mut client = unsafe { Rpc::new(client_ep, client_cq, &attr).unwrap() };
+        let mut server = unsafe { Rpc::new(server_ep, server_cq, &attr).unwrap() };
+        server.register(ECHO, move |src, request| {
+            assert_eq!(src, client_addr);
+            Ok(request.to_ascii_uppercase())
+        });
+        server.register(FAIL, |_, _| Err(Error::InvalidArgument("no".into())));
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let serving = {
+            let stop = stop.clone();
+            std::thread::spawn(move || {
+                let mut served = 0;
+                while !stop.load(Ordering::Relaxed) {
+                    served += server.poll().unwrap();
+                }
+                (served, server)
+            })
+        };
+        for i in 0..32 {
+            let request = format!("call {i}");
+            let response = client
+                .call(server_addr, ECHO, request.as_bytes(), timeout)
+                .unwrap();
+            assert_eq!(response, request.to_uppercase().into_bytes());
+        }
+        let response: Vec<u8> = client
+            .call_with::<Raw, _, _>(server_addr, ECHO, &b"typed".to_vec(), timeout)
+            .unwrap();
+        assert_eq!(response, b"TYPED");
+        let err = client.call(server_addr, FAIL, b"", timeout).unwrap_err();
+        assert_eq!(err.code(), ffi::FI_EINVAL as i32);
+        let err = client.call(server_addr, 3, b"", timeout).unwrap_err();
+        assert_eq!(err.code(), ffi::FI_ENOSYS as i32);
+        let err = client
+            .call(server_addr, ECHO, &[0; 65], timeout)
+            .unwrap_err();
+        assert_eq!(err.code(), ffi::FI_EINVAL as i32);
+        stop.store(true, Ordering::Relaxed);
+        let (served, _server) = serving.join().unwrap();
+        assert_eq!(served, 35);
+
+        // The server is no longer polled.
+        let err = client
+            .call(server_addr, ECHO, b"late", Duration::from_millis(10))
+            .unwrap_err();
+        assert_eq!(err.code(), ffi::FI_ETIMEDOUT as i32);
+    }
+
+    /// Requests reach handlers in their receive buffer, aligned for archives to be read in
+    /// place, through those registered with a codec as through raw ones, here of an RPC layer
+    /// calling itself, which serves its requests while waiting for their response.
+    #[cfg(all(feature = "mock", feature = "rpc"))]
+    #[test]
+    fn test_rpc_codec() {
+        use libfabric::codec::{Codec, Raw};
+        use libfabric::mock::MockFabric;
+        use libfabric::rpc::{Rpc, RpcAttr};
+        use std::time::Duration;
+
+        const LEN: u32 = 1;
+        let fabric = MockFabric::new();
+        let ep = fabric.endpoint();
+        let addr = fabric.av().insert(&ep.name().unwrap()).unwrap();
+        let cq = ep.cq();
+        let attr = RpcAttr::new().max_size(40).depth(2);
+        let mut rpc = unsafe { Rpc::new(ep, cq, &attr).unwrap() };
+        rpc.register_with::<Raw, Vec<u8>, Vec<u8>>(LEN, |_, request| Ok(vec![request.len() as u8]));
+        rpc.register(2, |_, request| {
+            assert_eq!(request.as_ptr() as usize % 16, 0);
+            Ok(request.to_vec())
+        });
+
+        let timeout = Duration::from_secs(5);
+        let request = vec![7u8; 40];
+        let response: Vec<u8> = rpc
+            .call_with::<Raw, _, _>(addr, LEN, &request, timeout)
+            .unwrap();
+        assert_eq!(response, [40]);
+        let response = rpc.call(addr, 2, &request, timeout).unwrap();
+        assert_eq!(
+            Raw::decode(&response).unwrap(),
+            Raw::encode(&request).unwrap()
+        );
+    }
+
+    /// Values put by any rank are read by every other from the table of the owner of their
+    /// key, overwritten in place, and refused once the slots probed for a key hold others.
+    #[cfg(all(feature = "mock", feature = "kvstore"))]
+    #[test]
+    fn test_kvstore() {
+        use libfabric::kvstore::{KvAttr, KvStore, RemoteRegion};
+        use libfabric::mock::MockFabric;
+
+        let fabric = MockFabric::new();
+        let attr = KvAttr::new().slots(16).max_key(16).max_value(32).probes(2);
+        let endpoints: Vec<_> = (0..3).map(|_| fabric.endpoint()).collect();
+        let peers: Vec<_> = endpoints
+            .iter()
+            .map(|ep| fabric.av().insert(&ep.name().unwrap()).unwrap())
+            .collect();
+        let mut tables = vec![vec![0u64; attr.table_size() / 8]; 3];
+        let access = Access::REMOTE_READ | Access::REMOTE_WRITE;
+        let mrs: Vec<_> = tables
+            .iter_mut()
+            .map(|table| {
+                unsafe { fabric.register(table.as_mut_ptr().cast(), table.len() * 8, access) }
+                    .unwrap()
+            })
+            .collect();
+        let regions: Vec<_> = mrs
+            .iter()
+            .map(|mr| RemoteRegion {
+                addr: mr.addr() as u64,
+                len: attr.table_size() as u64,
+                key: mr.key(),
+            })
+            .collect();
+        let mut stores: Vec<_> = endpoints
+            .into_iter()
+            .map(|ep| {
+                let cq = ep.cq();
+                KvStore::new(ep, cq, peers.clone(), regions.clone(), &attr).unwrap()
+            })
+            .collect();
+
+        for i in 0..12 {
+            let key = format!("key{i}");
+            stores[i % 3]
+                .put(key.as_bytes(), format!("value{i}").as_bytes())
+                .unwrap();
+        }
+        for i in 0..12 {
+            let key = format!("key{i}");
+            let value = stores[(i + 1) % 3].get(key.as_bytes()).unwrap();
+            assert_eq!(value, Some(format!("value{i}").into_bytes()));
+        }
+        stores[2].put(b"key0", b"again").unwrap();
+        assert_eq!(
+            stores[0].get(b"key0").unwrap().as_deref(),
+            Some(&b"again"[..])
+        );
+        assert_eq!(stores[1].get(b"missing").unwrap(), None);
+        assert!(stores[0].owner(b"key0") < 3);
+        assert!(matches!(
+            stores[0].put(&[0; 17], b""),
+            Err(Error::InvalidArgument(_))
+        ));
+
+        // Keys past the slots of their owner.
+        let code = (0..100)
+            .map(|i| stores[0].put(format!("more{i}").as_bytes(), b"x"))
+            .find_map(Result::err)
+            .unwrap()
+            .code();
+        assert_eq!(code, sys::bindgen::FI_ENOSPC as i32);
+        let short = RemoteRegion {
+            len: 8,
+            ..regions[0]
+        };
+        let ep = fabric.endpoint();
+        let cq = ep.cq();
+        assert!(KvStore::new(ep, cq, peers.clone(), vec![short; 3], &attr).is_err());
+    }
+
+    /// Members exchange names, regions and metadata through the root of the job, and through a
+    /// rendezvous server, each ending up with every member by rank.
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_bootstrap() {
+        use libfabric::bootstrap::{self, Bootstrap, PeerInfo, RemoteRegion, Tcp};
+        use libfabric::mock::MockFabric;
+        use std::thread;
+        use std::time::Duration;
+
+        let fabric = MockFabric::new();
+        let run = move |mut job: Tcp| {
+            let (ep, av) = (fabric.endpoint(), fabric.av());
+            let rank = job.rank();
+            let local = PeerInfo {
+                name: ep.name().unwrap(),
+                regions: vec![RemoteRegion {
+                    addr: 0,
+                    len: 8,
+                    key: rank as u64,
+                }],
+                data: vec![rank as u8],
+            };
+            let peers = job.exchange(&av, &local).unwrap();
+            assert_eq!(peers.len(), job.size());
+            assert_eq!(peers[rank].info, local);
+            assert_eq!(peers[rank].addr, av.insert(&local.name).unwrap());
+            for (rank, peer) in peers.iter().enumerate() {
+                assert_eq!(peer.info.data, [rank as u8]);
+                assert_eq!(peer.info.regions[0].key, rank as u64);
+            }
+            let job_data = job.broadcast(b"config", job.size() - 1).unwrap();
+            assert_eq!(job_data, b"config");
+            job.barrier().unwrap();
+            rank
+        };
+        let connect = |port: u16| {
+            let run = run.clone();
+            thread::spawn(move || {
+                run(Tcp::connect(("127.0.0.1", port), Duration::from_secs(10)).unwrap())
+            })
+        };
+
+        // A root and two members.
+        let members = [connect(47700), connect(47700)];
+        assert_eq!(run(Tcp::root(("127.0.0.1", 47700), 3).unwrap()), 0);
+        let mut ranks: Vec<_> = members.map(|member| member.join().unwrap()).into();
+        ranks.sort();
+        assert_eq!(ranks, [1, 2]);
+
+        // Two members and a server.
+        let server = thread::spawn(|| bootstrap::serve(("127.0.0.1", 47701), 2));
+        let mut ranks: Vec<_> = [connect(47701), connect(47701)]
+            .map(|member| member.join().unwrap())
+            .into();
+        ranks.sort();
+        assert_eq!(ranks, [0, 1]);
+        server.join().unwrap().unwrap();
+    }
+
+    /// A fallback transport skips a provider no member sets up, agrees on the next one across
+    /// the job, and reports the attempts which failed.
+    #[test]
+    fn test_fallback_transport() {
+        use libfabric::bootstrap::Tcp;
+        use libfabric::{FallbackAttr, FallbackStage, FallbackTransport};
+        use std::thread;
+        use std::time::Duration;
+
+        let attr = FallbackAttr::new(["no-such-provider", "tcp"])
+            .caps(Caps::MSG)
+            .probe(Some(Duration::from_secs(10)));
+        let run = move |mut job: Tcp| {
+            let transport = FallbackTransport::open(&mut job, &attr).unwrap();
+            assert!(transport.provider().contains("tcp"));
+            assert_eq!(transport.size(), 2);
+            let attempts = transport.attempts();
+            assert_eq!(attempts.len(), 1);
+            assert_eq!(attempts[0].provider, "no-such-provider");
+            assert_eq!(
+                (attempts[0].stage, attempts[0].rank),
+                (FallbackStage::Setup, 0)
+            );
+            assert!(transport.peer(1).is_ok() && transport.peer(2).is_err());
+            transport.rank()
+        };
+        let member = {
+            let run = run.clone();
+            thread::spawn(move || {
+                run(Tcp::connect(("127.0.0.1", 47702), Duration::from_secs(10)).unwrap())
+            })
+        };
+        assert_eq!(run(Tcp::root(("127.0.0.1", 47702), 2).unwrap()), 0);
+        assert_eq!(member.join().unwrap(), 1);
+
+        // With no provider left, the attempts make the error.
+        let mut job = Tcp::root(("127.0.0.1", 47703), 1).unwrap();
+        let err = FallbackTransport::open(&mut job, &FallbackAttr::new(["no-such-provider"]))
+            .err()
+            .unwrap();
+        assert!(matches!(&err, Error::InvalidArgument(msg) if msg.contains("no-such-provider")));
+    }
+
+    /// Objects handed over as raw pointers are taken back without being closed, once the
+    /// other handles to them are dropped.
+    #[test]
+    fn test_raw_round_trip() {
+        let entry = &tcp_entry();
+
+        let fabric = Fabric::open(entry).unwrap();
+        let domain = fabric.domain(entry).unwrap();
+        let cq = domain.cq(&CqAttr::new()).unwrap();
+        // Fails while another handle is open, which keeps the queue open.
+        assert!(cq.clone().into_raw().is_err());
+        let raw = cq.into_raw().unwrap();
+        let cq = unsafe { CompletionQueue::from_raw(&domain, raw, &CqAttr::new()) }.unwrap();
+        let mut completions = [Completion::default(); 1];
+        assert!(cq.read(&mut completions).unwrap_err().is_again());
+
+        // The queue holds the domain.
+        assert!(domain.clone().into_raw().is_err());
+        assert!(unsafe { Counter::from_raw(&domain, std::ptr::null_mut()) }.is_err());
+    }
+
+    /// Pollable queues export their wait descriptor, an epoll fd on Linux and a kqueue fd on
+    /// macOS, which may be blocked on while empty.
+    #[cfg(unix)]
+    #[test]
+    fn test_wait_fd() {
+        let entry = &tcp_entry();
+
+        let fabric = Fabric::open(entry).unwrap();
+        let domain = fabric.domain(entry).unwrap();
+        let cq = domain.cq(&CqAttr::new().pollable(true)).unwrap();
+        assert!(cq.wait_fd().unwrap() >= 0);
+        assert!(fabric.trywait(&[&cq]).unwrap());
+    }
+
+    /// Profiles of tcp endpoints expose their counters, when the provider was built with
+    /// profiling support.
+    #[cfg(libfabric_ge_1_20)]
+    #[test]
+    fn test_profile() {
+        let MsgEndpoint { ep, .. } = enabled_msg_endpoint();
+        let profile = match ep.profile() {
+            Err(err) if err.code() == sys::bindgen::FI_ENOSYS as i32 => return,
+            profile => profile.unwrap(),
+        };
+        for var in profile.vars().unwrap().iter().filter(|var| var.is_u64()) {
+            profile.read_u64(var.id).unwrap();
+        }
+    }
+
+    /// Messages are read in place from the multi-receive segments of a ring, which are
+    /// reposted once released.
+    #[test]
+    fn test_recv_ring() {
+        let entries = tcp_hints()
+            .caps(Caps::MSG | Caps::MULTI_RECV)
+            .get()
+            .unwrap();
+        let entry = &entries[0];
+        let fabric = Fabric::open(entry).unwrap();
+        let domain = fabric.domain(entry).unwrap();
+        let (tx_cq, rx_cq) = (
+            domain.cq(&CqAttr::new()).unwrap(),
+            domain.cq(&CqAttr::new()).unwrap(),
+        );
+        let av = domain.av(&AvAttr::new()).unwrap();
+        let ep = domain
+            .endpoint(entry)
+            .unwrap()
+            .bind_cq(&tx_cq, BindFlags::TRANSMIT)
+            .unwrap()
+            .bind_cq(&rx_cq, BindFlags::RECV)
+            .unwrap()
+            .bind_av(&av)
+            .unwrap()
+            .enable()
+            .unwrap();
+        let me = av.insert(&ep.name().unwrap()).unwrap();
+
+        let attr = RecvRingAttr::new()
+            .segments(2)
+            .segment_len(1024)
+            .max_message(128);
+        let mut ring = unsafe { RecvRing::new(ep, rx_cq, &attr) }.unwrap();
+        assert_eq!(ring.posted(), 2);
+        // Enough messages to go around the ring a few times, released as they come.
+        for i in 0..64u8 {
+            loop {
+                match ring.endpoint().inject(&[i; 100], me) {
+                    Err(err) if err.is_again() => {
+                        tx_cq.read::<Completion>(&mut []).map(|_| ()).unwrap()
+                    }
+                    other => break other.unwrap(),
+                }
+            }
+            let slot = loop {
+                if let Some(slot) = ring.recv().unwrap() {
+                    break slot;
+                }
+            };
+            assert_eq!(ring.bytes(&slot), &[i; 100]);
+            ring.release(slot).unwrap();
+        }
+        assert_eq!(ring.posted(), 2);
+    }
+
+    /// Frames parsed off the messages of a ring in an arena are released out of order, and
+    /// the segments reposted once all of their frames are.
+    #[test]
+    fn test_recv_ring_frames() {
+        let entries = tcp_hints()
+            .caps(Caps::MSG | Caps::MULTI_RECV)
+            .get()
+            .unwrap();
+        let entry = &entries[0];
+        let fabric = Fabric::open(entry).unwrap();
+        let domain = fabric.domain(entry).unwrap();
+        let (tx_cq, rx_cq) = (
+            domain.cq(&CqAttr::new()).unwrap(),
+            domain.cq(&CqAttr::new()).unwrap(),
+        );
+        let av = domain.av(&AvAttr::new()).unwrap();
+        let ep = domain
+            .endpoint(entry)
+            .unwrap()
+            .bind_cq(&tx_cq, BindFlags::TRANSMIT)
+            .unwrap()
+            .bind_cq(&rx_cq, BindFlags::RECV)
+            .unwrap()
+            .bind_av(&av)
+            .unwrap()
+            .enable()
+            .unwrap();
+        let me = av.insert(&ep.name().unwrap()).unwrap();
+
+        let attr = RecvRingAttr::new().segment_len(1024).max_message(128);
+        let arena = Box::new_uninit_slice(3 * 1024 + 100);
+        let mut ring = unsafe { RecvRing::with_arena(ep, rx_cq, &attr, arena) }.unwrap();
+        assert_eq!(ring.posted(), 3);
+        // Frames of a 1 byte length, then as many bytes, the last of each message padding.
+        let parse = |rest: &[u8]| {
+            Ok(rest
+                .first()
+                .filter(|&&len| len > 0)
+                .map(|&len| len as usize))
+        };
+        for i in 0..64u8 {
+            let mut message = Vec::new();
+            for len in [3u8, 5, 7] {
+                message.push(len);
+                message.extend(vec![i; len as usize - 1]);
+            }
+            message.extend([0; 4]);
+            loop {
+                match ring.endpoint().inject(&message, me) {
+                    Err(err) if err.is_again() => {
+                        tx_cq.read::<Completion>(&mut []).map(|_| ()).unwrap()
+                    }
+                    other => break other.unwrap(),
+                }
+            }
+            let mut frames = Vec::new();
+            while frames.len() < 3 {
+                if let Some(frame) = ring.recv_frame(parse).unwrap() {
+                    frames.push(frame);
+                }
+            }
+            for (frame, len) in frames.iter().zip([3, 5, 7]) {
+                assert_eq!(ring.bytes(frame)[0], len as u8);
+                assert_eq!(ring.bytes(frame)[1..], vec![i; len - 1]);
+            }
+            // The padding is released with the rest of the message once parsed.
+            assert_eq!(ring.held(), 15 + 4);
+            ring.release(frames.remove(1)).unwrap();
+            ring.release(frames.remove(0)).unwrap();
+            assert!(ring.recv_frame(parse).unwrap().is_none());
+            assert_eq!(ring.held(), 7);
+            ring.release(frames.remove(0)).unwrap();
+            assert_eq!(ring.held(), 0);
+        }
+        assert_eq!(ring.posted(), 3);
+        assert_eq!(ring.into_arena().len(), 3 * 1024 + 100);
+    }
+
+    /// Segments are sized as a multiple of the maximum message, which the endpoint is set to
+    /// release buffers under, and maximum messages of 0 or past `max_msg_size` are refused.
+    #[test]
+    fn test_recv_ring_min_multi_recv() {
+        let entries = tcp_hints()
+            .caps(Caps::MSG | Caps::MULTI_RECV)
+            .get()
+            .unwrap();
+        let entry = &entries[0];
+        let fabric = Fabric::open(entry).unwrap();
+        let domain = fabric.domain(entry).unwrap();
+        let open = || {
+            let (ep, cq, _) = bound_endpoint(&domain, entry);
+            (ep, cq)
+        };
+
+        let attr = RecvRingAttr::new()
+            .segments(2)
+            .segment_len(1000)
+            .max_message(128);
+        let (ep, cq) = open();
+        let ring = unsafe { RecvRing::new(ep, cq, &attr) }.unwrap();
+        assert_eq!((ring.segment_len(), ring.min_multi_recv()), (1024, 128));
+        assert_eq!(ring.endpoint().min_multi_recv().unwrap(), 128);
+        assert_eq!(ring.into_arena().len(), 2 * 1024);
+
+        let max_msg_size = entry.ep_attr().max_msg_size;
+        for max_message in [0, max_msg_size.saturating_add(1)] {
+            let attr = attr.clone().max_message(max_message);
+            let (ep, cq) = open();
+            let arena = Box::new_uninit_slice(4096);
+            let err = unsafe { RecvRing::with_arena(ep, cq, &attr, arena) }.err();
+            assert!(matches!(err, Some(Error::InvalidArgument(_))));
+        }
+    }
+
+    /// Receives from a peer inserted with a user ID report that ID as their source.
+    #[test]
+    fn test_av_user_id() {
+        let entry = &tcp_entry();
+        let fabric = Fabric::open(entry).unwrap();
+        let domain = fabric.domain(entry).unwrap();
+        let (a, a_cq, a_av) = bound_endpoint(&domain, entry);
+        let (b, b_cq, b_av) = bound_endpoint(&domain, entry);
+        let to_b = a_av.insert(&b.name().unwrap()).unwrap();
+        let from_a = b_av.insert_with_id(&a.name().unwrap(), 7).unwrap();
+        assert_ne!(from_a, Addr::from_raw(7));
+
+        let mut buf = [0u8; 16];
+        unsafe { b.recv(&mut buf, None, Addr::UNSPEC, 1).unwrap() };
+        loop {
+            match a.inject(b"rank 7", to_b) {
+                Err(err) if err.is_again() => a_cq.read::<Completion>(&mut []).map(|_| ()).unwrap(),
+                other => break other.unwrap(),
+            }
+        }
+        let mut completions = [Completion::default(); 1];
+        let mut src = [Addr::NOTAVAIL; 1];
+        while b_cq.read_from(&mut completions, &mut src).unwrap() == 0 {
+            a_cq.read::<Completion>(&mut []).unwrap();
+        }
+        assert_eq!(completions[0].context(), 1);
+        assert_eq!(src[0], Addr::from_raw(7));
+    }
+
+    /// The flag taking constructors open the same objects as the others without flags.
+    #[test]
+    fn test_open_with_flags() {
+        let entry = &tcp_entry();
+        let fabric = Fabric::open(entry).unwrap();
+        let domain =
+            unsafe { Domain::open_with_flags(&fabric, entry, 0, std::ptr::null_mut()) }.unwrap();
+        let ep = unsafe { domain.endpoint_with_flags(entry, 0, std::ptr::null_mut()) }.unwrap();
+        assert_eq!(ep.info().provider_name(), "tcp");
+        assert_eq!(domain.info().domain_name(), entry.domain_name());
+    }
+
+    /// The objects of thread safe domains can be shared across threads.
+    #[test]
+    fn test_thread_safe_objects() {
+        fn shareable<T: Send + Sync>() {}
+        shareable::<Domain>();
+        shareable::<Endpoint>();
+        shareable::<CompletionQueue>();
+        shareable::<Counter>();
+        shareable::<AddressVector>();
+        shareable::<MemoryRegion>();
+    }
+
+    /// Entries found with a lesser threading level only open under the ThreadDomain model.
+    #[test]
+    fn test_thread_domain() {
+        let entries = tcp_hints().threading(Threading::Domain).get().unwrap();
+        let entry = &entries[0];
+        let threading = entry.domain_attr().threading;
+        assert_ne!(threading, Threading::Unspec);
+        let fabric = Fabric::open(entry).unwrap();
+        if threading != Threading::Safe {
+            assert!(matches!(
+                Domain::open(&fabric, entry),
+                Err(Error::InvalidArgument(_))
+            ));
+        }
+        let domain: Domain<ThreadDomain> = Domain::open_with_threading(&fabric, entry).unwrap();
+        let cq = domain.cq(&CqAttr::new()).unwrap();
+        let ep = domain.endpoint(entry).unwrap();
+        ep.bind_cq(&cq, BindFlags::TRANSMIT | BindFlags::RECV)
+            .unwrap()
+            .enable()
+            .unwrap();
+    }
+
+    /// An alias with completions reports the operations of an endpoint which only reports those
+    /// asked for, and aliases take only transmit and receive flags.
+    #[test]
+    fn test_ep_alias() {
+        let entry = &tcp_entry();
+
+        let fabric = Fabric::open(entry).unwrap();
+        let domain = fabric.domain(entry).unwrap();
+        let cq = domain.cq(&CqAttr::new()).unwrap();
+        let av = domain.av(&AvAttr::new()).unwrap();
+        let flags = BindFlags::TRANSMIT | BindFlags::RECV | BindFlags::SELECTIVE_COMPLETION;
+        let ep = domain
+            .endpoint(entry)
+            .unwrap()
+            .bind_cq(&cq, flags)
+            .unwrap()
+            .bind_av(&av)
+            .unwrap()
+            .enable()
+            .unwrap();
+        assert!(matches!(
+            ep.alias(BindFlags::READ, OpFlags::COMPLETION),
+            Err(Error::InvalidArgument(_))
+        ));
+        let alias = match ep.alias(BindFlags::TRANSMIT | BindFlags::RECV, OpFlags::COMPLETION) {
+            Err(err) if err.code() == sys::bindgen::FI_ENOSYS as i32 => return,
+            alias => alias.unwrap(),
+        };
+        drop(ep);
+
+        let me = av.insert(&alias.name().unwrap()).unwrap();
+        let mut buf = [0u8; 16];
+        unsafe { alias.recv(&mut buf, None, Addr::UNSPEC, 1).unwrap() };
+        let msg = b"hello";
+        unsafe { alias.send(msg, None, me, 2).unwrap() };
+        let mut completions = [Completion::default(); 4];
+        let mut contexts = Vec::new();
+        while contexts.len() < 2 {
+            let n = cq.read(&mut completions).unwrap();
+            contexts.extend(completions[..n].iter().map(Completion::context));
+        }
+        contexts.sort();
+        assert_eq!(contexts, [1, 2]);
+    }
+
+    /// Endpoints take the profiles of the capabilities of their entry only, and keep them
+    /// through their lifecycle, while transferring data as the default profile.
+    #[test]
+    fn test_ep_profile() {
+        use libfabric::caps::{Atomic, Msg, Tagged};
+
+        let entries = tcp_hints().caps(Caps::MSG | Caps::TAGGED).get().unwrap();
+        let entry = &entries[0];
+
+        let fabric = Fabric::open(entry).unwrap();
+        let domain = fabric.domain(entry).unwrap();
+        let cq = domain.cq(&CqAttr::new()).unwrap();
+        let av = domain.av(&AvAttr::new()).unwrap();
+        let ep = domain.endpoint(entry).unwrap();
+        let atomic = domain
+            .endpoint(entry)
+            .unwrap()
+            .with_profile::<(Msg, Atomic)>();
+        assert_eq!(atomic.is_ok(), entry.caps().contains(Caps::ATOMIC));
+        let ep: ProfiledEndpoint<(Msg, Tagged)> = ep
+            .with_profile()
+            .unwrap()
+            .bind_cq(&cq, BindFlags::TRANSMIT | BindFlags::RECV)
+            .unwrap()
+            .bind_av(&av)
+            .unwrap()
+            .enable()
+            .unwrap();
+
+        let me = av.insert(&ep.name().unwrap()).unwrap();
+        let mut buf = [0u8; 16];
+        unsafe { ep.trecv(&mut buf, None, Addr::UNSPEC, 7, 0, 1).unwrap() };
+        unsafe { ep.tsend(b"hello", None, me, 7, 2).unwrap() };
+        let mut completions = [Completion::default(); 4];
+        let mut contexts = Vec::new();
+        while contexts.len() < 2 {
+            let n = cq.read(&mut completions).unwrap();
+            contexts.extend(completions[..n].iter().map(Completion::context));
+        }
+        contexts.sort();
+        assert_eq!(contexts, [1, 2]);
+        let ep: Endpoint = ep.into_any_caps();
+        ep.inject(b"hello", me).unwrap();
+    }
+
+    /// With selective completions, only the operations posted with `FI_COMPLETION` write a
+    /// completion, while a counter tracks all of them.
+    #[test]
+    fn test_selective_completion() {
+        let entry = &tcp_entry();
+
+        let fabric = Fabric::open(entry).unwrap();
+        let domain = fabric.domain(entry).unwrap();
+        let cq = domain.cq(&CqAttr::new()).unwrap();
+        let cntr = domain.counter(&CntrAttr::new()).unwrap();
+        let av = domain.av(&AvAttr::new()).unwrap();
+        let flags = BindFlags::TRANSMIT | BindFlags::RECV | BindFlags::SELECTIVE_COMPLETION;
+        let ep = domain
+            .endpoint(entry)
+            .unwrap()
+            .bind_counter(&cntr, BindFlags::TRANSMIT)
+            .unwrap()
+            .bind_cq(&cq, flags)
+            .unwrap()
+            .bind_av(&av)
+            .unwrap()
+            .enable()
+            .unwrap();
+
+        let me = av.insert(&ep.name().unwrap()).unwrap();
+        let mut bufs = [[0u8; 16]; 3];
+        for (context, buf) in (1..).zip(&mut bufs) {
+            unsafe {
+                ep.recv_with_flags(buf, None, Addr::UNSPEC, context, OpFlags::COMPLETION)
+                    .unwrap()
+            };
+        }
+        let msg = b"hello";
+        for (context, flags) in [(10, OpFlags::empty()), (11, OpFlags::empty())] {
+            unsafe { ep.send_with_flags(msg, None, me, context, flags).unwrap() };
+        }
+        unsafe {
+            ep.send_with_flags(msg, None, me, 12, OpFlags::COMPLETION)
+                .unwrap()
+        };
+
+        let mut completions = [Completion::default(); 8];
+        let mut contexts = Vec::new();
+        while contexts.len() < 4 || cntr.read() < 3 {
+            let n = cq.read(&mut completions).unwrap();
+            contexts.extend(completions[..n].iter().map(Completion::context));
+        }
+        contexts.sort();
+        assert_eq!(contexts, [1, 2, 3, 12]);
+        assert_eq!((cntr.read(), cntr.read_err()), (3, 0));
+    }
+
+    /// Notifications follow their write in the order of the endpoint when it orders sends
+    /// after writes, behind a fence otherwise, and cannot be ordered without either.
+    #[test]
+    fn test_notify_order() {
+        let ordered = NotifyOrder::new(MsgOrder::SAW | MsgOrder::SAS, Caps::empty());
+        assert_eq!(ordered.unwrap(), NotifyOrder::Ordered);
+        let fenced = NotifyOrder::new(MsgOrder::WAW, Caps::RMA | Caps::FENCE);
+        assert_eq!(fenced.unwrap(), NotifyOrder::Fenced);
+        let err = NotifyOrder::new(MsgOrder::SAS, Caps::RMA).unwrap_err();
+        assert_eq!(err.code(), sys::bindgen::FI_EOPNOTSUPP as i32);
+    }
+
+    /// Access patterns are checked against the ordered sizes of the endpoint, which order
+    /// nothing without the ordering in `msg_order`, and polling on the last byte of a write
+    /// never passes.
+    #[test]
+    fn test_rma_ordering() {
+        let ordering = RmaOrdering {
+            msg_order: MsgOrder::RMA_WAW | MsgOrder::RAR,
+            max_order_raw_size: usize::MAX,
+            max_order_war_size: 0,
+            max_order_waw_size: 4096,
+        };
+        assert_eq!(
+            (ordering.raw(), ordering.war(), ordering.waw()),
+            (0, 0, 4096)
+        );
+        let flag = AccessPattern::WriteThenFlag {
+            len: 4096,
+            flag_len: 8,
+        };
+        assert!(ordering.check(flag).is_ok());
+        assert!(
+            ordering
+                .check(AccessPattern::WriteAfterWrite { len: 64 })
+                .is_ok()
+        );
+
+        let err = ordering
+            .check(AccessPattern::WriteThenFlag {
+                len: 8192,
+                flag_len: 8,
+            })
+            .unwrap_err();
+        assert_eq!(err.code(), sys::bindgen::FI_EINVAL as i32);
+        assert!(err.to_string().contains("limited to 4096 bytes"));
+        let err = ordering.check(AccessPattern::ReadAfterWrite { len: 8 });
+        assert!(err.unwrap_err().to_string().contains("no RAW ordering"));
+        let err = ordering.check(AccessPattern::PollLastByte { len: 8 });
+        assert!(
+            err.unwrap_err()
+                .to_string()
+                .contains("unsafe on every provider")
+        );
+    }
+
+    /// Split RMA operations cover any lists of local buffers and remote segments, completing
+    /// once per post.
+    #[test]
+    fn test_rma_split() {
+        let hints = Info::new()
+            .caps(Caps::MSG | Caps::RMA)
+            .ep_type(EndpointType::Rdm)
+            .provider("tcp");
+        let entries = hints.get().unwrap();
+        let entry = &entries[0];
+
+        let fabric = Fabric::open(entry).unwrap();
+        let domain = fabric.domain(entry).unwrap();
+        let (ep, cq, av) = bound_endpoint(&domain, entry);
+        let me = av.insert(&ep.name().unwrap()).unwrap();
+
+        let mut region = vec![0u8; 32];
+        let access = Access::REMOTE_READ | Access::REMOTE_WRITE;
+        let mr = unsafe { domain.register(region.as_mut_ptr(), region.len(), access) }.unwrap();
+        let base = match entry.domain_attr().mr_mode.contains(MrMode::VIRT_ADDR) {
+            true => mr.addr() as u64,
+            false => 0,
+        };
+        let key = mr.key();
+        let remote = [
+            RmaIov {
+                addr: base,
+                len: 10,
+                key,
+            },
+            RmaIov {
+                addr: base + 16,
+                len: 14,
+                key,
+            },
+        ];
+        let data: Vec<u8> = (1..=24).collect();
+        let bufs: Vec<&[u8]> = [3, 5, 1, 7, 2, 6]
+            .iter()
+            .scan(0, |start, &len| {
+                *start += len;
+                Some(&data[*start - len..*start])
+            })
+            .collect();
+
+        let wait = |mut pending: RmaCompletions| {
+            let mut completions = [Completion::default(); 4];
+            while !pending.is_done() {
+                let n = cq.read(&mut completions).unwrap();
+                for completion in &completions[..n] {
+                    pending.complete(completion.context());
+                }
+            }
+        };
+        let written = unsafe { ep.write_split(&bufs, &[], me, &remote, 1) }.unwrap();
+        assert!(written.posts() >= 1 && written.context() == 1);
+        wait(written);
+        assert_eq!(&region[..10], &data[..10]);
+        assert_eq!(&region[10..16], &[0; 6]);
+        assert_eq!(&region[16..30], &data[10..]);
+
+        let (mut head, mut tail) = ([0u8; 20], [0u8; 4]);
+        let mut bufs: [&mut [u8]; 2] = [&mut head, &mut tail];
+        let read = unsafe { ep.read_split(&mut bufs, &[], me, &remote, 2) }.unwrap();
+        wait(read);
+        assert_eq!((&head[..], &tail[..]), (&data[..20], &data[20..]));
+
+        let short = [RmaIov {
+            len: 4,
+            ..remote[0]
+        }];
+        assert!(matches!(
+            unsafe { ep.write_split(&[&data], &[], me, &short, 3) },
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            unsafe { ep.write_split(&[&data[..2], &data[2..4]], &[&mr], me, &short, 3) },
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+
+    /// Epochs complete once the counter counts their operations, locally, or remotely with
+    /// delivery complete writes, and the next one starts from there.
+    #[test]
+    fn test_rma_epoch() {
+        let hints = Info::new()
+            .caps(Caps::MSG | Caps::RMA)
+            .ep_type(EndpointType::Rdm)
+            .provider("tcp");
+        let entries = hints.get().unwrap();
+        let entry = &entries[0];
+
+        let fabric = Fabric::open(entry).unwrap();
+        let domain = fabric.domain(entry).unwrap();
+        let cq = domain.cq(&CqAttr::new()).unwrap();
+        let cntr = domain.counter(&CntrAttr::new().blocking(true)).unwrap();
+        let av = domain.av(&AvAttr::new()).unwrap();
+        let flags = BindFlags::TRANSMIT | BindFlags::RECV | BindFlags::SELECTIVE_COMPLETION;
+        let ep = domain
+            .endpoint(entry)
+            .unwrap()
+            .bind_counter(&cntr, BindFlags::READ | BindFlags::WRITE)
+            .unwrap()
+            .bind_cq(&cq, flags)
+            .unwrap()
+            .bind_av(&av)
+            .unwrap()
+            .enable()
+            .unwrap();
+        let me = av.insert(&ep.name().unwrap()).unwrap();
+
+        let mut region = vec![0u8; 32];
+        let access = Access::REMOTE_READ | Access::REMOTE_WRITE;
+        let mr = unsafe { domain.register(region.as_mut_ptr(), region.len(), access) }.unwrap();
+        let base = match entry.domain_attr().mr_mode.contains(MrMode::VIRT_ADDR) {
+            true => mr.addr() as u64,
+            false => 0,
+        };
+        let timeout = Some(std::time::Duration::from_secs(5));
+
+        let mut epoch = RmaEpoch::new(&ep, &cntr);
+        unsafe {
+            epoch.write(b"head", None, me, base, mr.key()).unwrap();
+            epoch.write(b"tail", None, me, base + 28, mr.key()).unwrap();
+        }
+        assert_eq!(epoch.issued(), 2);
+        epoch.complete_epoch(timeout).unwrap();
+        assert_eq!((epoch.epochs(), epoch.issued(), epoch.pending()), (1, 0, 0));
+        assert_eq!((&region[..4], &region[28..]), (&b"head"[..], &b"tail"[..]));
+
+        let mut buf = [0u8; 4];
+        unsafe { epoch.read(&mut buf, None, me, base + 28, mr.key()).unwrap() };
+        epoch.complete_epoch(timeout).unwrap();
+        assert_eq!(&buf, b"tail");
+
+        let mut epoch = RmaEpoch::new(&ep, &cntr).delivery_complete();
+        unsafe { epoch.write(b"next", None, me, base + 8, mr.key()).unwrap() };
+        epoch.remote_complete_epoch(timeout).unwrap();
+        assert_eq!(&region[8..12], b"next");
+        assert_eq!(cntr.read_err(), 0);
+    }
+
+    /// Counted operations are done once a notification counts them: every few operations, or
+    /// once none is in flight, or when their ticket is waited for.
+    #[test]
+    fn test_counted_rma() {
+        let hints = Info::new()
+            .caps(Caps::MSG | Caps::RMA)
+            .ep_type(EndpointType::Rdm)
+            .provider("tcp");
+        let entries = hints.get().unwrap();
+        let entry = &entries[0];
+
+        let fabric = Fabric::open(entry).unwrap();
+        let domain = fabric.domain(entry).unwrap();
+        let cq = domain.cq(&CqAttr::new()).unwrap();
+        let cntr = domain.counter(&CntrAttr::new().blocking(true)).unwrap();
+        let av = domain.av(&AvAttr::new()).unwrap();
+        let flags = BindFlags::TRANSMIT | BindFlags::RECV | BindFlags::SELECTIVE_COMPLETION;
+        let ep = domain
+            .endpoint(entry)
+            .unwrap()
+            .bind_counter(&cntr, BindFlags::READ | BindFlags::WRITE)
+            .unwrap()
+            .bind_cq(&cq, flags)
+            .unwrap()
+            .bind_av(&av)
+            .unwrap()
+            .enable()
+            .unwrap();
+        let me = av.insert(&ep.name().unwrap()).unwrap();
+
+        let mut region = vec![0u8; 64];
+        let access = Access::REMOTE_READ | Access::REMOTE_WRITE;
+        let mr = unsafe { domain.register(region.as_mut_ptr(), region.len(), access) }.unwrap();
+        let base = match entry.domain_attr().mr_mode.contains(MrMode::VIRT_ADDR) {
+            true => mr.addr() as u64,
+            false => 0,
+        };
+        let timeout = Some(std::time::Duration::from_secs(5));
+
+        let attr = CountedRmaAttr::new()
+            .every(4)
+            .interval(std::time::Duration::from_secs(60));
+        let rma = CountedRma::new(ep, cntr.clone(), &attr);
+        let data = [7u8; 8];
+        let tickets: Vec<_> = (0..8)
+            .map(|i| unsafe { rma.write(&data, None, me, base + 8 * i, mr.key()) }.unwrap())
+            .collect();
+        assert_eq!(tickets[7].threshold(), 8);
+        tickets[7].wait(timeout).unwrap();
+        assert!(tickets.iter().all(|ticket| ticket.is_done().unwrap()));
+        assert_eq!((rma.issued(), rma.notified()), (8, 8));
+        assert!(!rma.progress());
+        assert_eq!(region, [7u8; 64]);
+        assert_eq!(cntr.read_err(), 0);
+    }
+
+    /// Collective plans check their buffers once, report the context their completions carry,
+    /// that of their trigger when they have one, and only issue tickets with a counter.
+    #[test]
+    fn test_collective_plan() {
+        let MsgEndpoint { ep, domain, .. } = enabled_msg_endpoint();
+
+        let (mut buf, mut result) = ([1u32; 4], [0u32; 2]);
+        let plan = ep.allreduce_plan(
+            &mut buf,
+            None,
+            &mut result,
+            None,
+            Addr::UNSPEC,
+            AtomicOp::Sum,
+        );
+        assert!(matches!(plan, Err(Error::InvalidArgument(_))));
+        let mut result = [0u32; 4];
+        let mut plan = ep
+            .allreduce_plan(
+                &mut buf,
+                None,
+                &mut result,
+                None,
+                Addr::UNSPEC,
+                AtomicOp::Sum,
+            )
+            .unwrap()
+            .with_context(7);
+        assert_eq!((plan.context(), plan.starts()), (7, 0));
+        plan.buf()[0] = 2;
+        assert_eq!(plan.result(), &[0; 4]);
+        let issued = unsafe { plan.issue() };
+        assert!(matches!(issued, Err(Error::InvalidArgument(_))));
+
+        let cntr = domain.counter(&CntrAttr::new()).unwrap();
+        let plan = ep
+            .barrier_plan(Addr::UNSPEC)
+            .with_context(7)
+            .trigger(&cntr, 1, 1);
+        assert_ne!(plan.context(), 7);
+        assert_eq!(plan.context(), plan.context());
+    }
+
+    /// Notified regions need a domain opened with FI_RMA_EVENT, which tcp does not support.
+    #[test]
+    fn test_notified_region() {
+        use libfabric::NotifiedRegion;
+
+        let entries = tcp_hints().caps(Caps::MSG | Caps::RMA).get().unwrap();
+        let entry = &entries[0];
+        let fabric = Fabric::open(entry).unwrap();
+        let domain = fabric.domain(entry).unwrap();
+        let ep = domain.endpoint(entry).unwrap();
+        let mut buf = [0u8; 64];
+        let region = unsafe { NotifiedRegion::new(&ep, buf.as_mut_ptr(), buf.len()) };
+        assert!(region.err().unwrap().is_unsupported());
+    }
+
+    /// Remote semaphores are notified regions, which tcp does not support, and posted to with
+    /// atomics, which it does not support either.
+    #[test]
+    fn test_remote_semaphore() {
+        use libfabric::bootstrap::RemoteRegion;
+        use libfabric::{RemoteSemaphore, SemaphorePoster};
+
+        let MsgEndpoint { ep, av, .. } = enabled_endpoint(tcp_hints().caps(Caps::MSG | Caps::RMA));
+        let semaphore = RemoteSemaphore::new(&ep);
+        assert!(semaphore.err().unwrap().is_unsupported());
+        let me = av.insert(&ep.name().unwrap()).unwrap();
+        let poster = SemaphorePoster::new(ep, me, RemoteRegion::default());
+        assert!(poster.err().unwrap().is_unsupported());
+    }
+
+    /// The arrays of atomic messages are checked against each other before posting.
+    #[test]
+    fn test_atomic_msg() {
+        let MsgEndpoint { ep, av, .. } = enabled_msg_endpoint();
+        let me = av.insert(&ep.name().unwrap()).unwrap();
+
+        let (ones, mut result) = ([1u64; 4], [0u64; 3]);
+        let msg = AtomicMsg::new()
+            .buf(&ones[..2], None)
+            .buf(&ones[2..], None)
+            .result(&mut result, None);
+        let fetched = unsafe { ep.fetch_atomicv(&msg, me, 0, 0, AtomicOp::Sum, 1) };
+        assert!(matches!(fetched, Err(Error::InvalidArgument(_))));
+
+        let msg = AtomicMsg::new().buf(&ones, None);
+        let sent = unsafe { ep.atomicmsg(&msg, me, AtomicOp::Sum, 2, OpFlags::empty()) };
+        assert!(matches!(sent, Err(Error::InvalidArgument(_))));
+        let msg = msg.compare(&ones[..1], None).remote(0, 4, 0);
+        let swapped =
+            unsafe { ep.compare_atomicmsg(&msg, me, AtomicOp::Cswap, 3, OpFlags::empty()) };
+        assert!(matches!(swapped, Err(Error::InvalidArgument(_))));
+    }
+
+    /// The extended datatypes are laid out as their C counterparts, and size mismatches are
+    /// reported as unsupported.
+    #[test]
+    fn test_atomic_datatypes() {
+        use libfabric::{AtomicDatatype, Complex, LongDouble};
+        use std::mem::{align_of, size_of};
+        use sys::bindgen::fi_datatype;
+
+        assert_eq!(i128::DATATYPE, fi_datatype::FI_INT128);
+        assert_eq!(u128::DATATYPE, fi_datatype::FI_UINT128);
+        assert_eq!(LongDouble::DATATYPE, fi_datatype::FI_LONG_DOUBLE);
+        assert_eq!(Complex::<f32>::DATATYPE, fi_datatype::FI_FLOAT_COMPLEX);
+        assert_eq!(Complex::<f64>::DATATYPE, fi_datatype::FI_DOUBLE_COMPLEX);
+        assert_eq!(
+            Complex::<LongDouble>::DATATYPE,
+            fi_datatype::FI_LONG_DOUBLE_COMPLEX
+        );
+        assert_eq!(
+            (size_of::<LongDouble>(), align_of::<LongDouble>()),
+            (16, 16)
+        );
+        assert_eq!(size_of::<Complex<f64>>(), 16);
+        assert_eq!(size_of::<Complex<LongDouble>>(), 32);
+
+        let unsupported = |code| Error::Fabric {
+            op: "fi_query_atomic",
+            code: code as i32,
+        };
+        assert!(unsupported(sys::bindgen::FI_EOPNOTSUPP).is_unsupported());
+        assert!(unsupported(sys::bindgen::FI_ENOSYS).is_unsupported());
+        assert!(!unsupported(sys::bindgen::FI_EINVAL).is_unsupported());
+    }
+
+    /// Queue sizings turn into queue settings, and strict ones fail with their warnings.
+    #[test]
+    fn test_queue_sizing() {
+        use libfabric::{QueueSizing, RxQueueAttr, SizingWarning, TxQueueAttr};
+
+        let sizing = QueueSizing {
+            tx_size: 512,
+            rx_size: 256,
+            cq_size: 1024,
+            cqs: 1,
+            counters: 0,
+            warnings: vec![SizingWarning {
+                what: "tx depth",
+                requested: 1024,
+                max: 512,
+            }],
+        };
+        assert_eq!(sizing.tx_attr(), TxQueueAttr::new().size(512));
+        assert_eq!(sizing.rx_attr(), RxQueueAttr::new().size(256));
+        assert_eq!(
+            sizing.warnings[0].to_string(),
+            "tx depth of 1024 over the provider maximum of 512"
+        );
+        assert!(matches!(sizing.strict(), Err(Error::InvalidArgument(_))));
+    }
+
+    /// Reductions map onto the atomic operations, the bitwise ones applying to integers only.
+    #[test]
+    fn test_reduce_op() {
+        use libfabric::{CollectiveDatatype, ReduceOp};
+
+        assert_eq!(AtomicOp::from(ReduceOp::Sum), AtomicOp::Sum);
+        assert_eq!(AtomicOp::from(ReduceOp::Bxor), AtomicOp::Bxor);
+        assert!(ReduceOp::Band.is_bitwise());
+        assert!(!ReduceOp::Max.is_bitwise());
+        let bitwise = [u64::BITWISE, i8::BITWISE, f64::BITWISE];
+        assert_eq!(bitwise, [true, true, false]);
+    }
+
+    /// Operations failing with EAGAIN are retried after progress, until the policy gives up.
+    #[test]
+    fn test_post_with_retry() {
+        use std::time::{Duration, Instant};
+
+        let again = || Error::Fabric {
+            op: "fi_send",
+            code: sys::bindgen::FI_EAGAIN as i32,
+        };
+        let (mut attempts, mut progress) = (0, 0);
+        let posted = post_with_retry(
+            &RetryPolicy::new(),
+            || {
+                progress += 1;
+                Ok(())
+            },
+            || {
+                attempts += 1;
+                if attempts < 4 {
+                    Err(again())
+                } else {
+                    Ok(attempts)
+                }
+            },
+        );
+        assert_eq!((posted, progress), (Ok(4), 3));
+
+        let (mut attempts, mut progress) = (0, 0);
+        let policy = RetryPolicy::new()
+            .max_retries(2)
+            .backoff(Duration::from_millis(1), Duration::from_millis(2));
+        let posted = post_with_retry(
+            &policy,
+            || {
+                progress += 1;
+                Ok(())
+            },
+            || -> Result<()> {
+                attempts += 1;
+                Err(again())
+            },
+        );
+        assert!(posted.unwrap_err().is_again());
+        assert_eq!((attempts, progress), (3, 2));
+
+        let policy = RetryPolicy::new().timeout(Duration::from_millis(10));
+        let started = Instant::now();
+        let posted = post_with_retry(&policy, || Ok(()), || -> Result<()> { Err(again()) });
+        assert!(posted.unwrap_err().is_again());
+        assert!(started.elapsed() >= Duration::from_millis(10));
+
+        // Errors of the progress are returned right away.
+        let posted = post_with_retry(
+            &RetryPolicy::new(),
+            || Err(Error::InvalidArgument("progress".into())),
+            || -> Result<()> { Err(again()) },
+        );
+        assert!(matches!(posted, Err(Error::InvalidArgument(_))));
+
+        #[cfg(feature = "async")]
+        {
+            use std::future::Future;
+            use std::task::{Context, Poll, Waker};
+
+            let mut attempts = 0;
+            let policy = RetryPolicy::new();
+            let mut post = std::pin::pin!(post_with_retry_async(
+                &policy,
+                || Ok(()),
+                || {
+                    attempts += 1;
+                    if attempts < 3 { Err(again()) } else { Ok(()) }
+                }
+            ));
+            let mut cx = Context::from_waker(Waker::noop());
+            // Each retry yields once to the executor.
+            assert!(post.as_mut().poll(&mut cx).is_pending());
+            assert!(post.as_mut().poll(&mut cx).is_pending());
+            assert_eq!(post.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        }
+    }
+
+    /// Open the whole object hierarchy, and send a message to ourselves.
+    #[test]
+    fn test_loopback() {
+        let MsgEndpoint { ep, cq, av, .. } = enabled_msg_endpoint();
+
+        let me = av.insert(&ep.name().unwrap()).unwrap();
+        let mut buf = [0u8; 16];
+        unsafe { ep.recv(&mut buf, None, Addr::UNSPEC, 1).unwrap() };
+        loop {
+            match ep.inject(b"hello", me) {
+                Err(err) if err.is_again() => cq.read::<Completion>(&mut []).map(|_| ()).unwrap(),
+                other => break other.unwrap(),
+            }
+        }
+
+        let mut completions = [Completion::default(); 4];
+        let completion = loop {
+            let n = cq.read(&mut completions).unwrap();
+            if let Some(c) = completions[..n].iter().find(|c| c.context() == 1) {
+                break *c;
+            }
+        };
+        assert_eq!(completion.len(), 5);
+        assert_eq!(&buf[..5], b"hello");
+    }
+
+    /// A queue opened in another format reads its own entries, with their flags, and refuses
+    /// those of others.
+    #[test]
+    fn test_cq_format() {
+        let entry = &tcp_entry();
+
+        let fabric = Fabric::open(entry).unwrap();
+        let domain = fabric.domain(entry).unwrap();
+        let cq = domain.cq(&CqAttr::new().format(CqFormat::Msg)).unwrap();
+        assert_eq!(cq.format(), CqFormat::Msg);
+        let av = domain.av(&AvAttr::new()).unwrap();
+        let ep = domain
+            .endpoint(entry)
+            .unwrap()
+            .bind_cq(&cq, BindFlags::TRANSMIT | BindFlags::RECV)
+            .unwrap()
+            .bind_av(&av)
+            .unwrap()
+            .enable()
+            .unwrap();
+        assert!(matches!(
+            cq.read(&mut [Completion::default(); 1]),
+            Err(Error::InvalidArgument(_))
+        ));
+
+        let me = av.insert(&ep.name().unwrap()).unwrap();
+        let mut buf = [0u8; 16];
+        unsafe { ep.recv(&mut buf, None, Addr::UNSPEC, 1).unwrap() };
+        unsafe { ep.send(b"hello", None, me, 2).unwrap() };
+        let mut completions = [MsgCompletion::default(); 4];
+        let mut seen = Vec::new();
+        while seen.len() < 2 {
+            let n = cq.read(&mut completions).unwrap();
+            seen.extend_from_slice(&completions[..n]);
+        }
+        seen.sort_by_key(MsgCompletion::context);
+        assert!(seen[0].is_recv() && !seen[0].is_send());
+        assert_eq!(seen[0].len(), 5);
+        assert!(seen[1].is_send() && !seen[1].is_rma());
+    }
+
+    /// Work graph nodes are checked against their region and the nodes added before them, and
+    /// count their completions on counters of their own.
+    #[test]
+    fn test_work_graph() {
+        let MsgEndpoint { ep, domain, .. } = enabled_msg_endpoint();
+        let mut region = vec![0u8; 64];
+        let access = Access::SEND | Access::RECV;
+        let mr = unsafe { domain.register(region.as_mut_ptr(), region.len(), access) }.unwrap();
+
+        let mut graph = WorkGraph::new(&domain).unwrap();
+        let recv = graph.recv(&ep, &mr, 0, 32, Addr::UNSPEC, 1, &[]).unwrap();
+        let past = graph.send(&ep, &mr, 48, 32, Addr::UNSPEC, 2, &[recv]);
+        assert!(matches!(past, Err(Error::InvalidArgument(_))));
+        let send = graph
+            .send(&ep, &mr, 32, 32, Addr::UNSPEC, 2, &[recv])
+            .unwrap();
+
+        // Nodes of other graphs are unknown past the nodes of this one.
+        let mut other = WorkGraph::new(&domain).unwrap();
+        let mut last = recv;
+        for context in 0..3 {
+            last = other
+                .recv(&ep, &mr, 0, 8, Addr::UNSPEC, context, &[])
+                .unwrap();
+        }
+        let unknown = graph.send(&ep, &mr, 0, 8, Addr::UNSPEC, 3, &[send, last]);
+        assert!(matches!(unknown, Err(Error::InvalidArgument(_))));
+        graph
+            .send(&ep, &mr, 0, 8, Addr::UNSPEC, 3, &[recv, send])
+            .unwrap();
+        assert_ne!(recv, send);
+        assert_ne!(graph.counter(recv).as_raw(), graph.counter(send).as_raw());
+        assert_eq!(graph.counter(send).read(), 0);
+    }
+
+    /// Deferred work needs a triggering counter to be queued, and canceling work which was not
+    /// queued leaves it alone.
+    #[test]
+    fn test_deferred_work() {
+        let MsgEndpoint { ep, domain, .. } =
+            enabled_endpoint(tcp_hints().caps(Caps::MSG | Caps::TRIGGER));
+        let mut region = vec![0u8; 64];
+        let access = Access::SEND | Access::RECV;
+        let mr = unsafe { domain.register(region.as_mut_ptr(), region.len(), access) }.unwrap();
+        let past = DeferredWork::send(&ep, &mr, 1, 64, Addr::UNSPEC, 1);
+        assert!(matches!(past, Err(Error::InvalidArgument(_))));
+
+        let cntr = domain.counter(&CntrAttr::new()).unwrap();
+        let mut work = DeferredWork::counter_add(&cntr, 1);
+        let queued = unsafe { domain.queue_work(&mut work) };
+        assert!(matches!(queued, Err(Error::InvalidArgument(_))));
+        domain.cancel_work(&mut work).unwrap();
+
+        let work = DeferredWork::send(&ep, &mr, 0, 64, Addr::UNSPEC, 1)
+            .unwrap()
+            .triggered_by(&cntr, 4);
+        assert_eq!(work.threshold(), 4);
+        assert_eq!(work.trigger().map(Counter::as_raw), Some(cntr.as_raw()));
+    }
+
+    /// Pinning the thread to the CPUs it may already run on leaves its affinity as it was, and
+    /// an empty set of CPUs is rejected.
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_pin_thread() {
+        std::thread::spawn(|| {
+            let cpus = thread_affinity().unwrap();
+            assert!(!cpus.is_empty());
+            pin_thread(&cpus).unwrap();
+            assert_eq!(thread_affinity().unwrap(), cpus);
+            pin_thread(&cpus[..1]).unwrap();
+            assert_eq!(thread_affinity().unwrap(), &cpus[..1]);
+            assert!(matches!(pin_thread(&[]), Err(Error::InvalidArgument(_))));
+            assert!(matches!(
+                pin_thread(&[4096]),
+                Err(Error::InvalidArgument(_))
+            ));
+        })
+        .join()
+        .unwrap();
+    }
+
+    /// Background progress is only set up for providers with manual progress, which tcp is.
+    #[test]
+    fn test_progress_model() {
+        use std::time::Duration;
+
+        let auto = ProgressModel {
+            control: Progress::Auto,
+            data: Progress::Auto,
+        };
+        assert!(!auto.is_manual());
+        assert!(
+            ProgressModel {
+                data: Progress::Manual,
+                ..auto
+            }
+            .is_manual()
+        );
+        assert!(
+            ProgressModel {
+                control: Progress::Manual,
+                ..auto
+            }
+            .is_manual()
+        );
+
+        let entry = &tcp_entry();
+        let fabric = Fabric::open(entry).unwrap();
+        let domain = fabric.domain(entry).unwrap();
+        let cq = domain.cq(&CqAttr::new()).unwrap();
+        assert_eq!(domain.progress_model().data, Progress::Manual);
+        let engine = domain.setup_progress(&[&cq], Duration::from_millis(1));
+        std::thread::sleep(Duration::from_millis(10));
+        engine.unwrap().check().unwrap();
+    }
+
+    /// Progress threads pin to the CPUs of their affinity, or fail to spawn when those are
+    /// unknown, and count their passes.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_progress_affinity() {
+        use std::time::Duration;
+
+        let entry = &tcp_entry();
+        let fabric = Fabric::open(entry).unwrap();
+        let domain = fabric.domain(entry).unwrap();
+        let cq = domain.cq(&CqAttr::new()).unwrap();
+
+        let cpu = thread_affinity().unwrap()[0];
+        let attr = ProgressAttr::new()
+            .period(Duration::from_millis(1))
+            .affinity(ProgressAffinity::Cpus(vec![cpu]));
+        let engine = ProgressEngine::spawn_with(&[&cq], &attr).unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+        let stats = engine.stats();
+        assert_eq!(stats.cpus, [cpu]);
+        assert!(stats.passes > 0 && stats.busy > Duration::ZERO);
+        assert_eq!((stats.last_cpu, stats.migrations), (Some(cpu), 0));
+
+        let nic = attr.clone().affinity(ProgressAffinity::Nic);
+        assert!(ProgressEngine::spawn_with(&[&cq], &nic).is_err());
+        let far = attr.affinity(ProgressAffinity::NumaNode(u32::MAX));
+        assert!(ProgressEngine::spawn_with(&[&cq], &far).is_err());
+        let attr = ProgressAttr::new().affinity(ProgressAffinity::Nic);
+        // Fails on NICs without a PCI address, ex: loopback.
+        if let Ok(Some(engine)) = domain.setup_progress_with(&[&cq], &attr) {
+            assert!(!engine.stats().cpus.is_empty());
+        }
+    }
+
+    /// Addresses inserted at once report their handles one by one, which the vector also
+    /// finds by index, or are all rolled back once one fails.
+    #[test]
+    fn test_av_insert_all() {
+        let MsgEndpoint { ep, av, .. } = enabled_msg_endpoint();
+        assert_ne!(av.av_type(), AvType::Unspec);
+        assert!(av.insert_all(&[]).unwrap().is_empty());
+
+        let name = ep.name().unwrap();
+        let short = EndpointAddress::from_bytes(&name.as_bytes()[1..]);
+        let mixed = av.insert_all(&[name.clone(), short]);
+        assert!(matches!(mixed, Err(Error::InvalidArgument(_))));
+        assert!(av.is_empty());
+
+        let first = av.insert(&name).unwrap();
+        let all = av.insert_all(&[name.clone(), name]).unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(av.len(), 3);
+        assert_eq!(av.get(0), Some(first));
+        assert_eq!(av.get(1), Some(*all[0].as_ref().unwrap()));
+        assert_eq!(av.get(2), Some(*all[1].as_ref().unwrap()));
+        assert_eq!(av.get(3), None);
+
+        // An invalid address rolls back the valid one inserted along with it.
+        let name = ep.name().unwrap();
+        let invalid = EndpointAddress::from_bytes(vec![0u8; name.as_bytes().len()]);
+        let err = av
+            .insert_all_or_nothing(&[name.clone(), invalid])
+            .unwrap_err();
+        assert_eq!(err.code(), sys::bindgen::FI_EADDRNOTAVAIL as i32);
+        assert_eq!(av.len(), 5);
+        assert_eq!(av.get(3), Some(Addr::NOTAVAIL));
+        assert_eq!(av.get(4), Some(Addr::NOTAVAIL));
+        assert_eq!(av.insert_all_or_nothing(&[name]).unwrap().len(), 1);
+    }
+
+    /// Handles look up to the addresses they were inserted from, as socket addresses with tcp.
+    #[test]
+    fn test_av_lookup() {
+        let MsgEndpoint { ep, av, .. } = enabled_msg_endpoint();
+        let name = ep.name().unwrap();
+        let me = av.insert(&name).unwrap();
+
+        assert_eq!(av.lookup(me).unwrap(), name);
+        let socket = av.lookup_socket_addr(me).unwrap().unwrap();
+        assert_eq!(Some(socket), name.to_socket_addr());
+        assert!(av.straddr(&name).contains(&socket.port().to_string()));
+    }
+
+    /// Peers inserted by host and service resolve to the same addresses as those inserted from
+    /// their raw addresses, symmetric insertion numbering them node by node.
+    #[test]
+    fn test_av_insert_service() {
+        let MsgEndpoint { ep, av, .. } = enabled_msg_endpoint();
+        let me = ep.name().unwrap().to_socket_addr().unwrap();
+        let (host, port) = (me.ip().to_string(), me.port().to_string());
+
+        let addr = av.insert_service(&host, &port).unwrap();
+        assert_eq!(av.lookup_socket_addr(addr).unwrap(), Some(me));
+        let nul = av.insert_service("local\0host", &port);
+        assert!(matches!(nul, Err(Error::InvalidArgument(_))));
+
+        let peers = av.insert_symmetric(&host, 1, &port, 2).unwrap();
+        assert_eq!(peers.len(), 2);
+        let next = av.lookup_socket_addr(*peers[1].as_ref().unwrap());
+        assert_eq!(next.unwrap().map(|addr| addr.port()), Some(me.port() + 1));
+        assert_eq!(av.get(2), Some(*peers[1].as_ref().unwrap()));
+
+        #[cfg(feature = "async")]
+        {
+            use std::future::Future;
+            use std::task::{Context, Poll, Waker};
+
+            let mut insert = std::pin::pin!(av.insert_host_async(&host, me.port()));
+            let mut cx = Context::from_waker(Waker::noop());
+            let addr = loop {
+                match insert.as_mut().poll(&mut cx) {
+                    Poll::Ready(addr) => break addr.unwrap(),
+                    Poll::Pending => std::thread::yield_now(),
+                }
+            };
+            assert_eq!(av.lookup_socket_addr(addr).unwrap(), Some(me));
+        }
+    }
+
+    /// Shared address vectors are found by name, and vectors of a process of its own are
+    /// writable.
+    #[test]
+    fn test_shared_av() {
+        let entry = &tcp_entry();
+
+        let fabric = Fabric::open(entry).unwrap();
+        let domain = fabric.domain(entry).unwrap();
+        let unnamed = domain.shared_av(&AvAttr::new());
+        assert!(matches!(unnamed, Err(Error::InvalidArgument(_))));
+        let av = domain.av(&AvAttr::new().ep_per_node(4)).unwrap();
+        assert!(!av.is_read_only());
+    }
+
+    /// Error completions from unknown senders turn into completions from a known peer once
+    /// the sender is inserted, and others are left as they are.
+    #[test]
+    fn test_unknown_source() {
+        let MsgEndpoint { ep, av, .. } = enabled_msg_endpoint();
+        let unknown = CqErrEntry {
+            context: 7,
+            flags: sys::bindgen::FI_RECV as u64 | sys::bindgen::FI_MSG as u64,
+            len: 5,
+            data: 0,
+            tag: 0,
+            olen: 0,
+            error: Error::Fabric {
+                op: "fi_cq_read",
+                code: sys::bindgen::FI_EADDRNOTAVAIL as i32,
+            },
+            prov_errno: 0,
+            message: String::new(),
+            src_addr: Addr::NOTAVAIL,
+            src_name: Some(ep.name().unwrap()),
+        };
+        assert!(unknown.is_unknown_source());
+        let truncated = CqErrEntry {
+            error: Error::Fabric {
+                op: "fi_cq_read",
+                code: sys::bindgen::FI_ETRUNC as i32,
+            },
+            ..unknown.clone()
+        };
+        assert!(!truncated.is_unknown_source());
+        let retried = truncated.insert_and_retry(&av);
+        assert!(matches!(retried, Err(Error::InvalidArgument(_))));
+        assert!(av.is_empty());
+
+        let (completion, src) = unknown.insert_and_retry(&av).unwrap();
+        assert_eq!((completion.context(), completion.len()), (7, 5));
+        assert!(completion.is_recv());
+        assert_eq!(av.get(0), Some(src));
+    }
+
+    /// Both ends of a connection find the address of the other one.
+    #[test]
+    fn test_peer_address() {
+        let entries = tcp_hints().ep_type(EndpointType::Msg).get().unwrap();
+        let entry = &entries[0];
+        let fabric = Fabric::open(entry).unwrap();
+        let domain = Domain::open(&fabric, entry).unwrap();
+        let eq = fabric.eq(&EqAttr::new()).unwrap();
+        let pep = fabric.passive_endpoint(entry).unwrap();
+        pep.bind_eq(&eq).unwrap();
+        pep.listen().unwrap();
+
+        let cq = domain.cq(&CqAttr::new()).unwrap();
+        let client = domain
+            .endpoint(entry)
+            .unwrap()
+            .bind_cq(&cq, BindFlags::TRANSMIT | BindFlags::RECV)
+            .unwrap()
+            .bind_eq(&eq)
+            .unwrap()
+            .enable()
+            .unwrap();
+        client.connect(&pep.name().unwrap(), &[]).unwrap();
+        let info = loop {
+            if let Some(EqEvent::ConnReq { info, .. }) = eq.read().unwrap() {
+                break info;
+            }
+        };
+        let server = domain
+            .endpoint(&info)
+            .unwrap()
+            .bind_cq(&cq, BindFlags::TRANSMIT | BindFlags::RECV)
+            .unwrap()
+            .bind_eq(&eq)
+            .unwrap()
+            .enable()
+            .unwrap();
+        server.accept(&[]).unwrap();
+        let mut connected = 0;
+        while connected < 2 {
+            if let Some(EqEvent::Connected { .. }) = eq.read().unwrap() {
+                connected += 1;
+            }
+        }
+
+        let peer = server.peer_address().unwrap();
+        assert_eq!(peer.format, entry.addr_format());
+        assert_eq!(peer.addr, client.name().unwrap());
+        assert_eq!(peer.socket_addr(), client.name().unwrap().to_socket_addr());
+        assert!(peer.socket_addr().is_some());
+        let peer = client.peer_address().unwrap();
+        assert_eq!(peer.addr, server.name().unwrap());
+    }
+
+    /// Peers are inserted on first use, and the least recently used one unpinned is removed
+    /// once the capacity is reached, to be inserted again when used.
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_lazy_av() {
+        use libfabric::mock::MockFabric;
+        use libfabric::{LazyAv, LazyAvAttr};
+
+        let fabric = MockFabric::new();
+        let eps: Vec<_> = (0..4).map(|_| fabric.endpoint()).collect();
+        let names = eps.iter().map(|ep| ep.name().unwrap()).collect();
+        let mut av = LazyAv::new(fabric.av(), names, &LazyAvAttr::new().capacity(2));
+        assert_eq!((av.len(), av.resident()), (4, 0));
+
+        let first = av.addr(0).unwrap();
+        av.addr(1).unwrap();
+        assert_eq!(av.addr(0).unwrap(), first);
+        assert_eq!((av.resident(), av.inserts()), (2, 2));
+        // 1 is the least recently used.
+        av.addr(2).unwrap();
+        assert_eq!((av.peek(1), av.peek(0)), (None, Some(first)));
+        assert_eq!((av.evictions(), av.rank_of(first)), (1, Some(0)));
+
+        av.pin(2).unwrap();
+        av.addr(3).unwrap();
+        assert_eq!(av.peek(0), None);
+        av.pin(3).unwrap();
+        let err = av.addr(0).unwrap_err();
+        assert_eq!(err.code(), sys::bindgen::FI_ENOSPC as i32);
+        av.unpin(3);
+        assert!(av.evict(3).unwrap() && !av.evict(2).unwrap());
+        assert_eq!(av.addr(0).unwrap(), first);
+        assert_eq!((av.inserts(), av.evictions()), (5, 2));
+        assert!(av.addr(4).is_err());
+    }
+
+    /// Peers exchanging heartbeats stay alive, and one which went away is declared dead and
+    /// evicted once it missed enough of them.
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_liveness() {
+        use libfabric::mock::MockFabric;
+        use libfabric::{Liveness, LivenessAttr};
+        use std::time::{Duration, Instant};
+
+        let fabric = MockFabric::new();
+        let (a, b) = (fabric.endpoint(), fabric.endpoint());
+        let av = fabric.av();
+        let (to_a, to_b) = (
+            av.insert(&a.name().unwrap()).unwrap(),
+            av.insert(&b.name().unwrap()).unwrap(),
+        );
+        let attr = LivenessAttr::new()
+            .interval(Duration::from_millis(5))
+            .misses(4);
+        let (cq_a, cq_b) = (a.cq(), b.cq());
+        let mut a = Liveness::new(a, cq_a, fabric.av(), &attr).unwrap();
+        let mut b = Liveness::new(b, cq_b, fabric.av(), &attr).unwrap();
+        a.watch(to_b);
+        b.watch(to_a);
+
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_millis(60) {
+            assert!(a.poll().unwrap().is_empty());
+            assert!(b.poll().unwrap().is_empty());
+        }
+        assert!(a.silence(to_b).unwrap() < Duration::from_millis(20));
+        b.unwatch(to_a);
+        assert!(!b.is_watched(to_a) && b.silence(to_a).is_none());
+
+        drop(b);
+        let dead = loop {
+            let dead = a.poll().unwrap();
+            if !dead.is_empty() {
+                break dead;
+            }
+            assert!(start.elapsed() < Duration::from_secs(5));
+        };
+        assert_eq!(dead, [to_b]);
+        assert!(!a.is_watched(to_b));
+    }
+
+    /// On a virtual clock, a peer which went away is declared dead once exactly enough time
+    /// passed without heartbeats, whatever the wall clock, and retries sleep their backoff
+    /// on that clock.
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_mock_progress() {
+        use libfabric::mock::{MockFabric, MockProgress};
+        use libfabric::{Liveness, LivenessAttr, ProgressDriver, post_with_retry_on};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::{Arc, Mutex};
+        use std::time::Duration;
+
+        let fabric = MockFabric::new();
+        let (a, b) = (fabric.endpoint(), fabric.endpoint());
+        let av = fabric.av();
+        let (to_a, to_b) = (
+            av.insert(&a.name().unwrap()).unwrap(),
+            av.insert(&b.name().unwrap()).unwrap(),
+        );
+        let attr = LivenessAttr::new()
+            .interval(Duration::from_secs(10))
+            .misses(3);
+        let progress = MockProgress::new();
+        let (cq_a, cq_b) = (a.cq(), b.cq());
+        let mut a = Liveness::with_driver(a, cq_a, fabric.av(), &attr, progress.clone()).unwrap();
+        let mut b = Liveness::with_driver(b, cq_b, fabric.av(), &attr, progress.clone()).unwrap();
+        a.watch(to_b);
+        b.watch(to_a);
+        for _ in 0..10 {
+            assert!(a.poll().unwrap().is_empty());
+            assert!(b.poll().unwrap().is_empty());
+            progress.advance(Duration::from_secs(10));
+        }
+        assert_eq!(a.silence(to_b), Some(Duration::from_secs(10)));
+        // The last heartbeat of b.
+        assert!(a.poll().unwrap().is_empty());
+        assert_eq!(a.silence(to_b), Some(Duration::ZERO));
+
+        drop(b);
+        progress.advance(Duration::from_secs(29));
+        assert!(a.poll().unwrap().is_empty());
+        assert_eq!(a.silence(to_b), Some(Duration::from_secs(29)));
+        progress.advance(Duration::from_secs(1));
+        assert_eq!(a.poll().unwrap(), [to_b]);
+
+        // Pollers run in the order set, omitted ones starving.
+        let polls = Arc::new(Mutex::new(Vec::new()));
+        for name in ["cq", "eq"] {
+            let polls = polls.clone();
+            progress.poller(move || {
+                polls.lock().unwrap().push(name);
+                Ok(())
+            });
+        }
+        progress.set_order(&[1, 1]);
+        let start = progress.now();
+        let attempts = AtomicUsize::new(0);
+        let policy = RetryPolicy::new()
+            .backoff(Duration::from_millis(100), Duration::from_millis(300))
+            .timeout(Duration::from_secs(1));
+        let posted = post_with_retry_on(&policy, &progress, || -> Result<()> {
+            attempts.fetch_add(1, Ordering::Relaxed);
+            Err(Error::Fabric {
+                op: "fi_send",
+                code: sys::bindgen::FI_EAGAIN as i32,
+            })
+        });
+        assert!(posted.unwrap_err().is_again());
+        let slept = progress.slept();
+        let ms = Duration::from_millis;
+        assert_eq!(slept, [ms(100), ms(200), ms(300), ms(300), ms(100)]);
+        assert_eq!(progress.now() - start, Duration::from_secs(1));
+        assert_eq!(attempts.load(Ordering::Relaxed), 6);
+        assert_eq!(*polls.lock().unwrap(), ["eq"; 10]);
+        progress.set_order(&[2]);
+        assert!(progress.progress().is_err());
+    }
+
+    /// A token bucket delays sends past its burst until the clock refilled it, per peer if so
+    /// set, and posts past its depth until completions are read, while governors reject
+    /// what they refuse.
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_governed_endpoint() {
+        use libfabric::mock::{MockFabric, MockProgress};
+        use libfabric::{
+            Completion, Cq, GovernedEndpoint, OpKind, TokenBucket, TokenBucketAttr, TxPost,
+            TxVerdict,
+        };
+        use std::time::Duration;
+
+        let fabric = MockFabric::new();
+        let (a, b, c) = (fabric.endpoint(), fabric.endpoint(), fabric.endpoint());
+        let (to_b, to_c) = (
+            fabric.av().insert(&b.name().unwrap()).unwrap(),
+            fabric.av().insert(&c.name().unwrap()).unwrap(),
+        );
+        let progress = MockProgress::new();
+        let cq = a.cq();
+        let attr = TokenBucketAttr::new(1000, 1000).per_peer(true).max_depth(2);
+        let bucket = TokenBucket::with_driver(&attr, progress.clone());
+        let ep = GovernedEndpoint::new(a, bucket);
+        let cq = ep.cq(cq);
+        let buf = [0u8; 600];
+
+        unsafe { ep.send(&buf, None, to_b, 1) }.unwrap();
+        let delayed = unsafe { ep.send(&buf, None, to_b, 2) };
+        assert!(delayed.unwrap_err().is_again());
+        assert_eq!(ep.last_delay(), Some(Duration::from_millis(200)));
+        // The bucket of another peer is full.
+        unsafe { ep.send(&buf, None, to_c, 3) }.unwrap();
+        progress.advance(Duration::from_millis(200));
+        assert!(
+            unsafe { ep.send(&buf, None, to_b, 2) }
+                .unwrap_err()
+                .is_again()
+        );
+        assert_eq!(ep.last_delay(), Some(Duration::ZERO));
+        assert_eq!((ep.depth(), ep.delayed()), (2, 2));
+
+        let mut completions = [Completion::default(); 4];
+        assert_eq!(cq.read(&mut completions).unwrap(), 2);
+        assert_eq!(ep.depth(), 0);
+        unsafe { ep.send(&buf, None, to_b, 2) }.unwrap();
+        assert!(ep.with_governor(|bucket| bucket.tokens(to_b)).abs() < 1e-6);
+
+        // Posts over a burst wait for a full bucket, and take it below zero.
+        let large = [0u8; 1500];
+        ep.inject(&[], to_c).unwrap();
+        progress.advance(Duration::from_secs(1));
+        ep.inject(&large, to_c).unwrap();
+        assert!(ep.with_governor(|bucket| bucket.tokens(to_c)) < -499.0);
+
+        let governed = GovernedEndpoint::new(b, |post: &TxPost| match post.kind {
+            OpKind::Write => TxVerdict::Reject,
+            _ => TxVerdict::Admit,
+        });
+        let rejected = unsafe { governed.write(&buf, None, to_c, 0, 0, 4) };
+        assert_eq!(
+            rejected.unwrap_err().code(),
+            sys::bindgen::FI_ECANCELED as i32
+        );
+        assert_eq!(governed.rejected(), 1);
+        governed.inject(b"ok", to_c).unwrap();
+    }
+
+    /// Percentiles are found within the precision of the histogram, and merged histograms
+    /// count the latencies of both.
+    #[cfg(feature = "latency")]
+    #[test]
+    fn test_latency_histogram() {
+        use libfabric::latency::Histogram;
+        use std::time::Duration;
+
+        let mut histogram = Histogram::new();
+        assert_eq!(histogram.percentile(99.0), Duration::ZERO);
+        for micros in 1..=1000 {
+            histogram.record(Duration::from_micros(micros));
+        }
+        assert_eq!(histogram.len(), 1000);
+        assert_eq!(histogram.min(), Duration::from_micros(1));
+        assert_eq!(histogram.max(), Duration::from_micros(1000));
+        for (percentile, expected) in [(50.0, 500.0), (99.0, 990.0), (99.9, 999.0)] {
+            let found = histogram.percentile(percentile).as_secs_f64() * 1e6;
+            assert!(
+                (found - expected).abs() <= expected / 64.0,
+                "{percentile}: {found}"
+            );
+        }
+        assert_eq!(histogram.percentile(100.0), histogram.max());
+        assert_eq!(histogram.mean(), Duration::from_nanos(500_500));
+
+        let mut other = Histogram::new();
+        other.record(Duration::from_secs(3600));
+        histogram.merge(&other);
+        assert_eq!(
+            (histogram.len(), histogram.max()),
+            (1001, Duration::from_secs(3600))
+        );
+        histogram.clear();
+        assert!(histogram.is_empty());
+    }
+
+    /// Operations are timed from their post to the read of their completion, on the endpoint
+    /// by kind and on the queue, and injected ones not at all.
+    #[cfg(all(feature = "latency", feature = "mock"))]
+    #[test]
+    fn test_latency_tracker() {
+        use libfabric::latency::LatencyTracker;
+        use libfabric::mock::MockFabric;
+        use std::time::Duration;
+
+        let fabric = MockFabric::new();
+        fabric.set_delay(Duration::from_millis(2));
+        let (a, b) = (fabric.endpoint(), fabric.endpoint());
+        let to_b = fabric.av().insert(&b.name().unwrap()).unwrap();
+        let tracker = LatencyTracker::new();
+        let (cq_a, cq_b) = (tracker.cq(a.cq()), tracker.cq(b.cq()));
+        let (a, b) = (tracker.endpoint(a), tracker.endpoint(b));
+
+        let mut buf = [0u8; 4];
+        unsafe { b.recv(&mut buf, None, Addr::UNSPEC, 1).unwrap() };
+        fabric.fail_posts(1, sys::bindgen::FI_EAGAIN as i32);
+        unsafe { a.tsend(b"ping", None, to_b, 0, 2).unwrap_err() };
+        unsafe { a.send(b"ping", None, to_b, 2).unwrap() };
+        a.inject(b"ping", to_b).unwrap();
+        let mut completions = [Completion::default(); 2];
+        for cq in [&cq_a, &cq_b] {
+            while !matches!(cq.read(&mut completions), Ok(1..)) {}
+        }
+
+        let sends = a.latencies_of(OpKind::Send);
+        assert_eq!(sends.len(), 1);
+        assert!(sends.min() >= Duration::from_millis(2));
+        assert_eq!(a.latencies(), sends);
+        assert!(a.latencies_of(OpKind::TSend).is_empty());
+        assert_eq!(b.latencies_of(OpKind::Recv).len(), 1);
+        assert_eq!((cq_a.latencies().len(), cq_b.latencies().len()), (1, 1));
+        assert_eq!(tracker.latencies().len(), 2);
+        tracker.clear();
+        assert!(tracker.latencies().is_empty() && cq_a.latencies().is_empty());
+    }
+
+    /// Latencies are those of the clock of the tracker: exact on a virtual one, and never
+    /// going back on the hardware ones.
+    #[cfg(all(feature = "latency", feature = "mock"))]
+    #[test]
+    fn test_latency_clock() {
+        use libfabric::latency::{ClockSource, LatencyTracker, MonotonicClock};
+        use libfabric::mock::{MockFabric, MockProgress};
+        use std::time::Duration;
+
+        let fabric = MockFabric::new();
+        let (a, b) = (fabric.endpoint(), fabric.endpoint());
+        let to_b = fabric.av().insert(&b.name().unwrap()).unwrap();
+        let progress = MockProgress::new();
+        let tracker = LatencyTracker::with_clock(progress.clone());
+        let cq = tracker.cq(a.cq());
+        let a = tracker.endpoint(a);
+
+        unsafe { a.send(b"ping", None, to_b, 1).unwrap() };
+        progress.advance(Duration::from_micros(3));
+        let mut completions = [Completion::default(); 1];
+        while !matches!(cq.read(&mut completions), Ok(1..)) {}
+        let sends = a.latencies_of(OpKind::Send);
+        assert_eq!(
+            (sends.min(), sends.max()),
+            (Duration::from_micros(3), Duration::from_micros(3))
+        );
+
+        let ticks = std::sync::atomic::AtomicU64::new(0);
+        let clock = move || ticks.fetch_add(500, std::sync::atomic::Ordering::Relaxed);
+        assert_eq!((clock.now(), clock.now()), (0, 500));
+        let monotonic = MonotonicClock::new();
+        let earlier = monotonic.now();
+        assert!(monotonic.now() >= earlier);
+        #[cfg(target_os = "linux")]
+        {
+            let raw = libfabric::latency::MonotonicRawClock;
+            let earlier = raw.now();
+            assert!(raw.now() >= earlier);
+        }
+        #[cfg(target_arch = "x86_64")]
+        {
+            let tsc = libfabric::latency::TscClock::calibrate(Duration::from_millis(1));
+            assert!(tsc.frequency() > 0);
+            let earlier = tsc.now();
+            assert!(tsc.now() >= earlier);
+        }
+    }
+
+    /// Messages take the path their length calls for: injected, eager from several buffers,
+    /// or by rendezvous, which is refused without RMA.
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_smart_send() {
+        use libfabric::mock::MockFabric;
+        use libfabric::{Rendezvous, RendezvousAttr, SendPath};
+
+        let fabric = MockFabric::new();
+        let (a, b) = (fabric.endpoint(), fabric.endpoint());
+        let av = fabric.av();
+        let (to_a, to_b) = (
+            av.insert(&a.name().unwrap()).unwrap(),
+            av.insert(&b.name().unwrap()).unwrap(),
+        );
+        let attr = RendezvousAttr::new().threshold(64).inject_size(8);
+        assert_eq!(attr.path(8), SendPath::Inject);
+        assert_eq!(attr.path(9), SendPath::Eager);
+        assert_eq!(attr.path(64), SendPath::Rendezvous);
+        assert_eq!(attr.clone().inject_size(100).path(64), SendPath::Rendezvous);
+        let (cq_a, cq_b) = (a.cq(), b.cq());
+        let mut a = unsafe { Rendezvous::new(a, cq_a, &attr) }.unwrap();
+        let mut b = unsafe { Rendezvous::new(b, cq_b, &attr) }.unwrap();
+
+        let mut large: Vec<u8> = (0..100u8).collect();
+        let mr = unsafe {
+            fabric
+                .register(large.as_mut_ptr(), large.len(), Access::REMOTE_READ)
+                .unwrap()
+        };
+        let (head, tail) = large.split_at(50);
+        let split = unsafe { a.smart_send(to_b, &[head, tail], Some(&mr)) };
+        assert!(matches!(split, Err(Error::InvalidArgument(_))));
+        let sends = [
+            unsafe { a.smart_send(to_b, &[b"tiny"], None) }.unwrap(),
+            unsafe { a.smart_send(to_b, &[b"gathered ", b"message"], None) }.unwrap(),
+            unsafe { a.smart_send(to_b, &[&large], Some(&mr)) }.unwrap(),
+        ];
+        let paths: Vec<_> = sends.iter().map(|(_, path)| *path).collect();
+        assert_eq!(
+            paths,
+            [SendPath::Inject, SendPath::Eager, SendPath::Rendezvous]
+        );
+
+        let mut received = Vec::new();
+        let mut sent = Vec::new();
+        while received.len() < 3 || sent.len() < 3 {
+            if let Some(message) = b.recv().unwrap() {
+                received.push(message);
+            }
+            if let Some(send) = a.sent().unwrap() {
+                sent.push(send);
+            }
+        }
+        assert_eq!(received[0], (to_a, b"tiny".to_vec()));
+        assert_eq!(received[1], (to_a, b"gathered message".to_vec()));
+        assert_eq!(received[2], (to_a, large.clone()));
+        assert!(sent.iter().all(|(_, result)| result.is_ok()));
+
+        let attr = attr.rma(false);
+        let mut c =
+            unsafe { Rendezvous::new(fabric.endpoint(), fabric.endpoint().cq(), &attr) }.unwrap();
+        let refused = unsafe { c.smart_send(to_b, &[&large], Some(&mr)) };
+        assert!(matches!(refused, Err(Error::InvalidArgument(_))));
+    }
+
+    /// Diagnostics report the library, the environment variables of libfabric, and the
+    /// attributes of the entry they are made for.
+    #[test]
+    fn test_diagnostics() {
+        unsafe { std::env::set_var("FI_DIAGNOSTICS_TEST", "1") };
+        let report = diagnostics();
+        assert_eq!(report.header_version, Version::HEADER);
+        assert!(report.entry.is_none());
+        assert!(report.providers.iter().any(|name| name == "tcp"));
+        let var = ("FI_DIAGNOSTICS_TEST".to_owned(), "1".to_owned());
+        assert!(report.env.contains(&var));
+        assert!(report.env.iter().all(|(name, _)| !name.starts_with("PATH")));
+
+        let entry = tcp_entry();
+        let report = diagnostics_for(&entry);
+        let attrs = report.entry.as_ref().unwrap();
+        assert_eq!(attrs.provider, entry.provider_name());
+        assert!(attrs.caps.contains(Caps::MSG));
+        assert!(report.to_string().contains("FI_DIAGNOSTICS_TEST=1"));
+    }
+
+    /// Preflight checks report every kind in order, and fail on a required provider which is
+    /// not available, or a module which is not loaded, with a hint of what to do.
+    #[test]
+    fn test_preflight() {
+        use libfabric::preflight::{self, CheckKind, CheckStatus, PreflightAttr};
+
+        let report = preflight::check(&PreflightAttr::new().require_provider("tcp"));
+        let kinds: Vec<_> = report.checks.iter().map(|check| check.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                CheckKind::Memlock,
+                CheckKind::Devices,
+                CheckKind::HugePages,
+                CheckKind::KernelModules,
+                CheckKind::Providers,
+            ]
+        );
+        let providers = report.get(CheckKind::Providers).unwrap();
+        assert_eq!(providers.status, CheckStatus::Pass, "{report}");
+
+        let attr = PreflightAttr::new()
+            .require_provider("no-such-provider;ofi_rxm")
+            .require_module("no_such_module");
+        let report = preflight::check(&attr);
+        assert!(!report.passed());
+        assert_eq!(report.status(), CheckStatus::Fail);
+        let providers = report.get(CheckKind::Providers).unwrap();
+        assert_eq!(providers.status, CheckStatus::Fail);
+        assert!(providers.detail.contains("no-such-provider"));
+        assert!(providers.remediation.is_some());
+        let modules = report.get(CheckKind::KernelModules).unwrap();
+        if modules.status != CheckStatus::Skipped {
+            assert_eq!(modules.status, CheckStatus::Fail);
+            assert!(
+                modules
+                    .remediation
+                    .as_ref()
+                    .unwrap()
+                    .contains("modprobe no_such_module")
+            );
+        }
+        assert!(report.to_string().contains("hint: "));
+    }
+
+    /// The registry discovers once, and queries filter its entries, as selection does.
+    #[test]
+    fn test_provider_registry() {
+        let registry = ProviderRegistry::discover().unwrap();
+        assert!(registry.providers().iter().any(|name| name == "tcp"));
+
+        let query = ProviderQuery::new()
+            .provider("tcp")
+            .ep_type(EndpointType::Rdm)
+            .caps(Caps::MSG);
+        let entries = registry.query(&query);
+        assert!(!entries.is_empty());
+        assert!(
+            entries
+                .iter()
+                .all(|entry| entry.ep_type() == EndpointType::Rdm
+                    && entry.caps().contains(Caps::MSG)
+                    && entry.provider_name().split(';').any(|name| name == "tcp"))
+        );
+        assert_eq!(
+            registry.first(&query).unwrap().domain_name(),
+            entries[0].domain_name()
+        );
+        assert!(
+            registry
+                .query(&query.clone().fabric_name("no such fabric"))
+                .is_empty()
+        );
+
+        let policy = SelectionPolicy::new().exclude("tcp");
+        let err = registry.select(&query, &policy).unwrap_err();
+        assert_eq!(err.code(), sys::bindgen::FI_ENODATA as i32);
+
+        let global = ProviderRegistry::global().unwrap();
+        assert!(std::ptr::eq(global, ProviderRegistry::global().unwrap()));
+    }
+
+    /// The runtime opens each domain once for all of its handles, and is only shut down by
+    /// the last, once the application released the objects opened from it.
+    #[test]
+    fn test_fabric_runtime() {
+        let entry = &tcp_entry();
+
+        let runtime = FabricRuntime::get();
+        let other = FabricRuntime::get();
+        assert!(FabricRuntime::is_running());
+        assert_eq!(runtime.handles(), 2);
+        let domain = runtime.domain(entry).unwrap();
+        assert_eq!(other.domain(entry).unwrap().id(), domain.id());
+        assert_eq!(runtime.fabric(entry).unwrap().id(), domain.fabric().id());
+        let av = other.av(entry, "peers", &AvAttr::new()).unwrap();
+        assert_eq!(
+            runtime.av(entry, "peers", &AvAttr::new()).unwrap().id(),
+            av.id()
+        );
+        assert_ne!(
+            runtime.av(entry, "other", &AvAttr::new()).unwrap().id(),
+            av.id()
+        );
+        assert_eq!(runtime.domains().len(), 1);
+
+        let err = runtime.shutdown().unwrap_err();
+        assert!(matches!(err, Error::InvalidArgument(_)));
+        let closing = other.lifecycle().subscribe();
+        assert!(other.clone().shutdown().is_err());
+        drop((domain, av));
+        let report = other.shutdown().unwrap();
+        assert_eq!(report.closed.len(), 4);
+        assert!(matches!(
+            closing.try_recv(),
+            Ok(LifecycleEvent::DomainClosing { .. })
+        ));
+        assert!(!FabricRuntime::is_running());
+    }
+
+    /// Every fabric and domain is listed by name, and opened by it.
+    #[test]
+    fn test_fabric_domains() {
+        let registry = ProviderRegistry::with_hints(tcp_hints()).unwrap();
+        let pairs = registry.fabric_domains();
+        assert!(!pairs.is_empty());
+        assert!(pairs.windows(2).all(|pair| pair[0] < pair[1]));
+        for pair in &pairs {
+            let entries = registry.query(&pair.query());
+            assert!(!entries.is_empty());
+            let fabric = Fabric::open_named(&entries, &pair.fabric).unwrap();
+            assert_eq!(fabric.info().fabric_name(), pair.fabric);
+            let domain = fabric
+                .domain_named(registry.entries(), &pair.domain)
+                .unwrap();
+            assert_eq!(domain.info().domain_name(), pair.domain);
+            let err = fabric
+                .domain_named(&entries, "no such domain")
+                .err()
+                .unwrap();
+            assert_eq!(err.code(), sys::bindgen::FI_ENODATA as i32);
+        }
+        let err = Fabric::open_named(registry.entries(), "no such fabric")
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), sys::bindgen::FI_ENODATA as i32);
+    }
+
+    /// Entries report the requested capabilities they lack, and strict validation fails on
+    /// any of them.
+    #[test]
+    fn test_validate_caps() {
+        let report = CapsReport {
+            provider: "tcp".to_owned(),
+            missing_caps: Caps::RMA_EVENT,
+            unsupported_modes: Mode::empty(),
+            missing_tx_msg_order: MsgOrder::SAS,
+            missing_tx_comp_order: MsgOrder::empty(),
+            missing_rx_msg_order: MsgOrder::empty(),
+            missing_rx_comp_order: MsgOrder::empty(),
+        };
+        assert!(!report.is_exact());
+        assert_eq!(
+            report.to_string(),
+            "tcp downgraded: caps Caps(RMA_EVENT), tx msg_order MsgOrder(SAS)"
+        );
+
+        let hints = tcp_hints()
+            .caps(Caps::MSG | Caps::RMA_EVENT)
+            .tx_attr(&TxQueueAttr::new().msg_order(MsgOrder::SAS));
+        for entry in hints.get().unwrap() {
+            let report = validate_caps(&hints, &entry);
+            assert!(!report.missing_caps.contains(Caps::MSG));
+            assert_eq!(report.provider, entry.provider_name());
+            match report.clone().strict() {
+                Ok(_) => assert!(report.is_exact()),
+                Err(err) => {
+                    assert!(!report.is_exact());
+                    assert_eq!(err.code(), sys::bindgen::FI_EINVAL as i32);
+                }
+            }
+        }
+    }
+
+    /// Failed discovery is explained by the hint eliminating every provider, which matches
+    /// once relaxed.
+    #[test]
+    fn test_explain_getinfo() {
+        let report = GetinfoReport {
+            matches: Vec::new(),
+            available: vec!["shm".to_owned(), "tcp".to_owned()],
+            hints: vec![
+                HintImpact {
+                    hint: Hint::Cap(Caps::MSG),
+                    alone: vec!["shm".to_owned(), "tcp".to_owned()],
+                    without: Vec::new(),
+                    accepted: None,
+                },
+                HintImpact {
+                    hint: Hint::MrMode(MrMode::empty()),
+                    alone: Vec::new(),
+                    without: vec!["verbs".to_owned()],
+                    accepted: Some(Hint::MrMode(MrMode::LOCAL)),
+                },
+            ],
+        };
+        assert_eq!(report.culprits().count(), 1);
+        assert_eq!(
+            report.to_string(),
+            "no provider matches the hints, of shm, tcp\n  \
+             mr_mode MrMode(0x0) eliminates every provider\n  \
+             without mr_mode MrMode(0x0), verbs match: request mr_mode MrMode(LOCAL)"
+        );
+
+        let hints = tcp_hints().provider("no-such-provider");
+        let report = explain_getinfo(&hints).unwrap();
+        assert!(report.matches.is_empty());
+        let provider = Hint::Provider("no-such-provider".to_owned());
+        assert!(report.culprits().any(|impact| impact.hint == provider));
+        assert!(report.fixes().any(|impact| impact.hint == provider));
+        assert!(explain_getinfo(&tcp_hints()).unwrap().hints.is_empty());
+    }
+
+    /// A domain config requests the threading of its model, and the domain opened with it
+    /// meets its progress and resource management.
+    #[test]
+    fn test_domain_config() {
+        assert_eq!(
+            DomainConfig::<ThreadSafe>::new().threading(),
+            Threading::Safe
+        );
+        let config = DomainConfig::<ThreadDomain>::new()
+            .data_progress(Progress::Manual)
+            .resource_mgmt(ResourceMgmt::Enabled);
+        assert_eq!(config.threading(), Threading::Domain);
+
+        let entries = tcp_hints().domain_config(&config).get().unwrap();
+        let entry = &entries[0];
+        let attr = entry.domain_attr();
+        assert_eq!(attr.resource_mgmt, ResourceMgmt::Enabled);
+        let fabric = Fabric::open(entry).unwrap();
+        let domain: Domain<ThreadDomain> =
+            Domain::open_with_config(&fabric, entry, &config).unwrap();
+        assert!(domain.progress_model().data != Progress::Unspec);
+
+        if attr.data_progress != Progress::Auto {
+            let auto = config.data_progress(Progress::Auto);
+            assert!(matches!(
+                Domain::open_with_config(&fabric, entry, &auto),
+                Err(Error::InvalidArgument(_))
+            ));
+        }
+    }
+
+    /// Past the limits of the endpoint, or its capabilities, operations fail before they are
+    /// posted at the levels checking them, and reach the provider with checks off.
+    #[test]
+    fn test_validation_level() {
+        assert!(ValidationLevel::Off < ValidationLevel::Cheap);
+        assert_eq!(ValidationLevel::default(), ValidationLevel::Full);
+        let config = DomainConfig::<ThreadSafe>::new().validation(ValidationLevel::Cheap);
+        let entries = tcp_hints().domain_config(&config).get().unwrap();
+        let entry = &entries[0];
+        let fabric = Fabric::open(entry).unwrap();
+        let domain = Domain::open_with_config(&fabric, entry, &config).unwrap();
+        assert_eq!(domain.validation(), ValidationLevel::Cheap);
+        let (ep, _, av) = bound_endpoint(&domain, entry);
+        let dest = av.insert(&ep.name().unwrap()).unwrap();
+
+        let tx = entry.tx_attr();
+        let big = vec![0u8; tx.inject_size + 1];
+        assert!(matches!(
+            ep.inject(&big, dest),
+            Err(Error::InvalidArgument(_))
+        ));
+        // Tagged messages are not a capability of the endpoint, which only the full checks see.
+        let tagged = ep.tinject(b"tag", dest, 1);
+        assert!(!matches!(tagged, Err(Error::InvalidArgument(_))));
+        domain.set_validation(ValidationLevel::Full);
+        assert!(matches!(
+            ep.tinject(b"tag", dest, 1),
+            Err(Error::InvalidArgument(_))
+        ));
+        let mut buf = [0u8; 8];
+        let recv = unsafe { ep.recv_with_flags(&mut buf, None, Addr::UNSPEC, 0, OpFlags::FENCE) };
+        assert!(matches!(recv, Err(Error::InvalidArgument(_))));
+        domain.set_validation(ValidationLevel::Off);
+        assert!(!matches!(
+            ep.inject(&big, dest),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+
+    /// A sibling maps the memory of a shared region from its handle, which no other process
+    /// opens once its creator unlinked it.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_shm_region() {
+        let entry = &tcp_entry();
+        let fabric = Fabric::open(entry).unwrap();
+        let domain = Domain::open(&fabric, entry).unwrap();
+        let name = format!("fi_rs_region_{}", std::process::id());
+        let attr = ShmRegionAttr::new(Access::SEND | Access::RECV);
+        let mut region = ShmRegion::create(&domain, &name, 4096, &attr).unwrap();
+        assert!(region.is_owner());
+        let handle = ShmHandle::from_bytes(&region.handle().to_bytes()).unwrap();
+        assert_eq!(handle, region.handle());
+        assert_eq!(handle.remote.key, region.region().key());
+
+        assert!(matches!(
+            ShmRegion::open(&domain, &handle, &attr.clone().read_only(true)),
+            Err(Error::InvalidArgument(_))
+        ));
+        let mut sibling = ShmRegion::open(
+            &domain,
+            &handle,
+            &ShmRegionAttr::new(Access::SEND).read_only(true),
+        )
+        .unwrap();
+        assert!(!sibling.is_owner());
+        assert!(unsafe { sibling.as_mut_slice() }.is_err());
+        unsafe { region.as_mut_slice() }.unwrap()[..5].copy_from_slice(b"hello");
+        assert_eq!(unsafe { &sibling.as_slice()[..5] }, b"hello");
+
+        region.unlink().unwrap();
+        let err = ShmRegion::open(&domain, &handle, &attr).err().unwrap();
+        assert!(matches!(
+            err,
+            Error::Io {
+                kind: std::io::ErrorKind::NotFound,
+                ..
+            }
+        ));
+        drop(region);
+        // The mapping of the sibling outlives the name and the region of the creator.
+        assert_eq!(unsafe { &sibling.as_slice()[..5] }, b"hello");
+    }
+
+    /// Domains count the objects opened from them against the counts of their attributes, and
+    /// reservations keep room for those opened next.
+    #[test]
+    fn test_domain_resources() {
+        let entry = &tcp_entry();
+        let fabric = Fabric::open(entry).unwrap();
+        let domain = Domain::open(&fabric, entry).unwrap();
+        assert!(!domain.limits_enforced());
+        let usage = domain.usage(Resource::Cq);
+        assert_eq!(usage.limit, entry.domain_attr().cq_cnt);
+        assert_eq!((usage.open, usage.reserved), (0, 0));
+
+        let cq = domain.cq(&CqAttr::new()).unwrap();
+        let ep = domain.endpoint(entry).unwrap();
+        assert_eq!(domain.usage(Resource::Cq).open, 1);
+        assert_eq!(domain.usage(Resource::Endpoint).open, 1);
+        drop(ep);
+        assert_eq!(domain.usage(Resource::Endpoint).open, 0);
+
+        domain.set_limits_enforced(true);
+        if let Some(available) = domain.usage(Resource::Cq).available() {
+            let err = domain.reserve(Resource::Cq, available + 1).unwrap_err();
+            assert!(matches!(
+                err,
+                Error::LimitExceeded {
+                    resource: Resource::Cq,
+                    ..
+                }
+            ));
+            assert_eq!(err.code(), sys::bindgen::FI_ENOSPC as i32);
+        }
+        let reservation = domain.reserve(Resource::Cq, 2).unwrap();
+        assert_eq!(domain.usage(Resource::Cq).reserved, 2);
+        let other = domain.cq(&CqAttr::new()).unwrap();
+        assert_eq!(reservation.left(), 1);
+        drop(reservation);
+        let usage = domain.usage(Resource::Cq);
+        assert_eq!((usage.open, usage.reserved), (2, 0));
+        drop((cq, other));
+        assert_eq!(domain.usage(Resource::Cq).open, 0);
+    }
+
+    /// Posts are refused with `FI_EAGAIN` once their completions would fill the queue, until
+    /// completions are read, unless the queue opts out.
+    #[test]
+    fn test_cq_outstanding() {
+        let entry = &tcp_entry();
+        let fabric = Fabric::open(entry).unwrap();
+        let domain = Domain::open(&fabric, entry).unwrap();
+        let mut bufs = [[0u8; 8]; 5];
+        for (attr, limited) in [
+            (CqAttr::new().size(4), true),
+            (CqAttr::new().size(4).track_outstanding(false), false),
+        ] {
+            let cq = domain.cq(&attr).unwrap();
+            assert_eq!(cq.capacity(), if limited { 4 } else { 0 });
+            let ep = domain
+                .endpoint(entry)
+                .unwrap()
+                .bind_cq(&cq, BindFlags::TRANSMIT | BindFlags::RECV)
+                .unwrap()
+                .enable()
+                .unwrap();
+            let posted: Vec<_> = bufs
+                .iter_mut()
+                .enumerate()
+                .map(|(i, buf)| unsafe { ep.recv(buf, None, Addr::UNSPEC, i) })
+                .collect();
+            if !limited {
+                assert!(posted.iter().all(Result::is_ok));
+                assert_eq!((cq.outstanding(), cq.refused()), (0, 0));
+                continue;
+            }
+            assert!(posted[..4].iter().all(Result::is_ok));
+            assert!(posted[4].as_ref().unwrap_err().is_again());
+            assert_eq!((cq.outstanding(), cq.refused()), (4, 1));
+            // The canceled receive completes with an error, which frees its completion.
+            ep.cancel(0).unwrap();
+            let mut completions = [Completion::default(); 4];
+            let canceled = loop {
+                match cq.read(&mut completions) {
+                    Err(err) if err.is_avail() => break cq.read_err().unwrap().unwrap(),
+                    other => assert_eq!(other.unwrap(), 0),
+                }
+            };
+            assert_eq!(canceled.context, 0);
+            assert_eq!(cq.outstanding(), 3);
+            unsafe { ep.recv(&mut bufs[4], None, Addr::UNSPEC, 4) }.unwrap();
+        }
+    }
+
+    /// Arena operations complete with the context of the application, and fail with
+    /// `FI_EAGAIN` while every descriptor is in flight.
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_op_arena() {
+        use libfabric::mock::MockFabric;
+
+        let fabric = MockFabric::new();
+        let (a, b) = (fabric.endpoint(), fabric.endpoint());
+        let to_b = fabric.av().insert(&b.name().unwrap()).unwrap();
+        let cq_a = a.cq();
+        let mut bufs = [[0u8; 8]; 2];
+        for (i, buf) in bufs.iter_mut().enumerate() {
+            unsafe { b.recv(buf, None, Addr::UNSPEC, i) }.unwrap();
+        }
+
+        let mut arena = OpArena::new(a, 2);
+        unsafe { arena.send(b"one", None, to_b, 7) }.unwrap();
+        unsafe { arena.send(b"two", None, to_b, 8) }.unwrap();
+        assert_eq!(arena.in_flight(), 2);
+        let err = unsafe { arena.send(b"three", None, to_b, 9) }.unwrap_err();
+        assert!(err.is_again());
+
+        let mut completions = [Completion::default(); 4];
+        let mut contexts = Vec::new();
+        while contexts.len() < 2 {
+            match cq_a.read(&mut completions) {
+                Ok(n) => contexts.extend(completions[..n].iter().filter_map(|c| arena.complete(c))),
+                Err(err) if err.is_again() => {}
+                Err(err) => panic!("{err}"),
+            }
+        }
+        contexts.sort();
+        assert_eq!(contexts, [7, 8]);
+        assert_eq!(arena.in_flight(), 0);
+        assert_eq!(arena.complete(&completions[0]), None);
+        assert_eq!(arena.complete_context(0), None);
+    }
+
+    /// Posting and completing through an arena allocates nothing.
+    #[test]
+    fn test_op_arena_allocations() {
+        use std::cell::Cell;
+
+        // A transport accepting every operation, remembering the context of the last one.
+        #[derive(Default)]
+        struct Sink(Cell<usize>);
+
+        impl Transport for Sink {
+            type Mr = MemoryRegion;
+
+            fn name(&self) -> Result<EndpointAddress> {
+                unimplemented!()
+            }
+
+            unsafe fn recv(
+                &self,
+                _: &mut [u8],
+                _: Option<&MemoryRegion>,
+                _: Addr,
+                context: usize,
+            ) -> Result<()> {
+                self.0.set(context);
+                Ok(())
+            }
+
+            unsafe fn send(
+                &self,
+                _: &[u8],
+                _: Option<&MemoryRegion>,
+                _: Addr,
+                context: usize,
+            ) -> Result<()> {
+                self.0.set(context);
+                Ok(())
+            }
+
+            unsafe fn senddata(
+                &self,
+                _: &[u8],
+                _: Option<&MemoryRegion>,
+                _: u64,
+                _: Addr,
+                context: usize,
+            ) -> Result<()> {
+                self.0.set(context);
+                Ok(())
+            }
+
+            fn inject(&self, _: &[u8], _: Addr) -> Result<()> {
+                Ok(())
+            }
+
+            unsafe fn trecv(
+                &self,
+                _: &mut [u8],
+                _: Option<&MemoryRegion>,
+                _: Addr,
+                _: u64,
+                _: u64,
+                context: usize,
+            ) -> Result<()> {
+                self.0.set(context);
+                Ok(())
+            }
+
+            unsafe fn tsend(
+                &self,
+                _: &[u8],
+                _: Option<&MemoryRegion>,
+                _: Addr,
+                _: u64,
+                context: usize,
+            ) -> Result<()> {
+                self.0.set(context);
+                Ok(())
+            }
+
+            unsafe fn tsenddata(
+                &self,
+                _: &[u8],
+                _: Option<&MemoryRegion>,
+                _: u64,
+                _: Addr,
+                _: u64,
+                context: usize,
+            ) -> Result<()> {
+                self.0.set(context);
+                Ok(())
+            }
+
+            fn tinject(&self, _: &[u8], _: Addr, _: u64) -> Result<()> {
+                Ok(())
+            }
+
+            unsafe fn read(
+                &self,
+                _: &mut [u8],
+                _: Option<&MemoryRegion>,
+                _: Addr,
+                _: u64,
+                _: u64,
+                context: usize,
+            ) -> Result<()> {
+                self.0.set(context);
+                Ok(())
+            }
+
+            unsafe fn write(
+                &self,
+                _: &[u8],
+                _: Option<&MemoryRegion>,
+                _: Addr,
+                _: u64,
+                _: u64,
+                context: usize,
+            ) -> Result<()> {
+                self.0.set(context);
+                Ok(())
+            }
+
+            unsafe fn writedata(
+                &self,
+                _: &[u8],
+                _: Option<&MemoryRegion>,
+                _: u64,
+                _: Addr,
+                _: u64,
+                _: u64,
+                context: usize,
+            ) -> Result<()> {
+                self.0.set(context);
+                Ok(())
+            }
+        }
+
+        let mut arena = OpArena::new(Sink::default(), 16);
+        let mut buf = [0u8; 64];
+        let before = allocations();
+        for i in 0..10_000 {
+            unsafe {
+                arena.send(&buf, None, Addr::UNSPEC, i).unwrap();
+                let sent = arena.endpoint().0.get();
+                arena.tsend(&buf, None, Addr::UNSPEC, 1, i + 1).unwrap();
+                let tagged = arena.endpoint().0.get();
+                arena.recv(&mut buf, None, Addr::UNSPEC, i + 2).unwrap();
+                let received = arena.endpoint().0.get();
+                arena.write(&buf, None, Addr::UNSPEC, 0, 0, i + 3).unwrap();
+                let written = arena.endpoint().0.get();
+                assert_eq!(arena.complete_context(tagged), Some(i + 1));
+                assert_eq!(arena.complete_context(sent), Some(i));
+                assert_eq!(arena.complete_context(written), Some(i + 3));
+                assert_eq!(arena.complete_context(received), Some(i + 2));
+            }
+        }
+        assert_eq!(allocations(), before);
+        assert_eq!(arena.in_flight(), 0);
+    }
+
+    /// Completions filled into the ring by a poller are each popped once by the workers.
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_completion_ring() {
+        use libfabric::mock::MockFabric;
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        const OPS: usize = 2000;
+        let fabric = MockFabric::new();
+        let (a, b) = (fabric.endpoint(), fabric.endpoint());
+        let to_b = fabric.av().insert(&b.name().unwrap()).unwrap();
+        let mut bufs = vec![[0u8; 4]; OPS];
+        for (i, buf) in bufs.iter_mut().enumerate() {
+            unsafe { b.recv(buf, None, Addr::UNSPEC, i) }.unwrap();
+            unsafe { a.send(b"ping", None, to_b, i + 1) }.unwrap();
+        }
+
+        let ring = CompletionRing::new(50);
+        assert_eq!(ring.capacity(), 64);
+        let seen: Arc<Vec<AtomicUsize>> =
+            Arc::new((0..=OPS).map(|_| AtomicUsize::new(0)).collect());
+        let popped = Arc::new(AtomicUsize::new(0));
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                let (ring, seen, popped) = (ring.clone(), seen.clone(), popped.clone());
+                std::thread::spawn(move || {
+                    let mut out = [Completion::default(); 8];
+                    while popped.load(Ordering::Acquire) < OPS {
+                        let n = ring.pop_batch(&mut out);
+                        for completion in &out[..n] {
+                            seen[completion.context()].fetch_add(1, Ordering::Relaxed);
+                        }
+                        popped.fetch_add(n, Ordering::AcqRel);
+                    }
+                })
+            })
+            .collect();
+
+        let (cq, mut batch, mut filled) = (a.cq(), [Completion::default(); 16], 0);
+        while filled < OPS {
+            filled += ring.fill(&cq, &mut batch).unwrap();
+            assert!(ring.len() <= ring.capacity());
+        }
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        assert!(ring.is_empty() && ring.pop().is_none());
+        assert_eq!(seen[0].load(Ordering::Relaxed), 0);
+        assert!(seen[1..].iter().all(|n| n.load(Ordering::Relaxed) == 1));
+    }
+
+    /// Completions are steered to the shard of their source, in the order of the queue, and
+    /// those without one to the shard of the peer told for them.
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_steered_ring() {
+        use libfabric::mock::MockFabric;
+
+        const OPS: usize = 40;
+        let fabric = MockFabric::new();
+        let b = fabric.endpoint();
+        let senders: Vec<_> = (0..3).map(|_| fabric.endpoint()).collect();
+        let to_b = fabric.av().insert(&b.name().unwrap()).unwrap();
+        let mut bufs = vec![[0u8; 4]; OPS * senders.len()];
+        for (i, buf) in bufs.iter_mut().enumerate() {
+            unsafe { b.recv(buf, None, Addr::UNSPEC, i) }.unwrap();
+        }
+        for _ in 0..OPS {
+            for (peer, sender) in senders.iter().enumerate() {
+                unsafe { sender.send(b"ping", None, to_b, peer) }.unwrap();
+            }
+        }
+
+        let ring = SteeredRing::new(2, 16);
+        assert_eq!(ring.shards(), 2);
+        let (mut batch, mut src) = ([Completion::default(); 8], [Addr::UNSPEC; 8]);
+        let mut seen = vec![Vec::new(); 2];
+        let mut filled = 0;
+        while filled < bufs.len() {
+            filled += ring.fill(&b.cq(), &mut batch, &mut src, |_| None).unwrap();
+            for (shard, seen) in seen.iter_mut().enumerate() {
+                while let Some((completion, peer)) = ring.pop(shard) {
+                    seen.push((peer, completion.context()));
+                }
+            }
+        }
+        assert!(ring.is_empty());
+        for (shard, seen) in seen.iter().enumerate() {
+            assert!(seen.iter().all(|(peer, _)| ring.shard_of(*peer) == shard));
+            assert!(seen.windows(2).all(|w| w[0].1 < w[1].1));
+        }
+        let peers: std::collections::HashSet<_> = seen.iter().flatten().map(|(p, _)| *p).collect();
+        assert_eq!(peers.len(), senders.len());
+        assert!(!seen[0].is_empty() && !seen[1].is_empty());
+
+        // Sends have no source: they go to the peer told by their context.
+        let cq = senders[1].cq();
+        assert_eq!(
+            ring.fill(&cq, &mut batch, &mut src, |c| {
+                Some(Addr::from_raw(c.context() as u64 + 100))
+            })
+            .unwrap(),
+            8
+        );
+        let shard = ring.shard_of(Addr::from_raw(101));
+        assert_eq!(ring.len(shard), 8);
+        assert_eq!(ring.pop(shard).unwrap().1, Addr::from_raw(101));
+        while ring.pop(shard).is_some() {}
+        ring.fill(&cq, &mut batch, &mut src, |_| None).unwrap();
+        assert_eq!(ring.len(ring.shard_of(Addr::NOTAVAIL)), 8);
+    }
+
+    /// Each thread keeps the context it was first assigned, threads beyond the context count
+    /// sharing them.
+    #[test]
+    fn test_tx_context_pool() {
+        use std::sync::Arc;
+
+        let entries = tcp_hints().contexts(2, 0).get().unwrap();
+        let entry = &entries[0];
+        let fabric = Fabric::open(entry).unwrap();
+        let domain = Domain::open(&fabric, entry).unwrap();
+        let av = domain.av(&AvAttr::new()).unwrap();
+        let sep = domain.scalable_endpoint(entry).unwrap();
+        sep.bind_av(&av).unwrap();
+        sep.enable().unwrap();
+
+        let pool = Arc::new(TxContextPool::with_cqs(&sep, &CqAttr::new()));
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                let pool = pool.clone();
+                std::thread::spawn(move || {
+                    let first = pool.get().unwrap().index();
+                    assert_eq!(pool.get().unwrap().index(), first);
+                    first
+                })
+            })
+            .collect();
+        let mut indices: Vec<usize> = workers.into_iter().map(|w| w.join().unwrap()).collect();
+        indices.sort();
+        assert_eq!(indices, [0, 0, 1, 1]);
+        assert_eq!(pool.opened().count(), 2);
+    }
+
+    /// Quiescing cancels the receives in flight and closes the whole graph in order, and fails
+    /// naming the object the application still holds.
+    #[test]
+    fn test_quiesce() {
+        let open = || {
+            let MsgEndpoint { ep, cq, .. } = enabled_msg_endpoint();
+            (ep, cq)
+        };
+
+        let (ep, cq) = open();
+        let mut buf = [0u8; 64];
+        unsafe { ep.recv(&mut buf, None, Addr::UNSPEC, 7) }.unwrap();
+        drop(cq);
+        let report = Quiesce::new().endpoint(ep, &[7]).run().unwrap();
+        assert!(report.abandoned.is_empty());
+        assert!(
+            report.errors.iter().any(|entry| entry.context == 7
+                && entry.error.code() == sys::bindgen::FI_ECANCELED as i32)
+        );
+        // The endpoint, its queue and address vector, the domain and the fabric.
+        assert_eq!(report.closed.len(), 5);
+
+        let (ep, cq) = open();
+        let err = Quiesce::new().endpoint(ep, &[]).run().err().unwrap();
+        assert!(err.to_string().contains("completion queue"), "{err}");
+        drop(cq);
+    }
+
+    /// Buffers are tracked while in flight, and reposting one to another operation before its
+    /// completion is reported.
+    #[cfg(feature = "debug-validate")]
+    #[test]
+    fn test_debug_validate() {
+        use libfabric::validate::{self, Violation, ViolationKind};
+        use std::sync::Mutex;
+
+        static VIOLATIONS: Mutex<Vec<ViolationKind>> = Mutex::new(Vec::new());
+        fn record(violation: &Violation) {
+            VIOLATIONS.lock().unwrap().push(violation.kind.clone());
+        }
+        validate::set_violation_handler(record);
+
+        let MsgEndpoint { ep, cq, av, .. } = enabled_msg_endpoint();
+        let me = av.insert(&ep.name().unwrap()).unwrap();
+
+        let mut buf = vec![0u8; 64];
+        unsafe { ep.recv(&mut buf, None, Addr::UNSPEC, 1) }.unwrap();
+        let tracked = validate::in_flight();
+        assert_eq!(
+            (tracked.len(), tracked[0].op, tracked[0].writes),
+            (1, "fi_recv", true)
+        );
+        unsafe { ep.send(&buf[..8], None, me, 2) }.unwrap();
+        assert_eq!(
+            *VIOLATIONS.lock().unwrap(),
+            [ViolationKind::Reposted { op: "fi_send" }]
+        );
+
+        let mut out = [Completion::default(); 4];
+        let mut done = 0;
+        while done < 2 {
+            done += cq.read(&mut out).unwrap();
+        }
+        assert!(validate::in_flight().is_empty());
+    }
+
+    /// An XPU trigger is a triggered context of its own address, whose variables the provider
+    /// fills in on post.
+    #[cfg(feature = "cuda")]
+    #[test]
+    fn test_xpu_trigger() {
+        use libfabric::xpu::{XpuDevice, XpuTrigger};
+
+        let trigger = XpuTrigger::new(XpuDevice::Cuda(0), 2);
+        let raw = unsafe { &*(trigger.context() as *const sys::bindgen::fi_triggered_context2) };
+        assert_eq!(
+            raw.event_type,
+            sys::bindgen::fi_trigger_event_FI_TRIGGER_XPU
+        );
+        let xpu = unsafe { raw.trigger.xpu };
+        assert_eq!(xpu.count, 2);
+        assert_eq!(xpu.iface, sys::bindgen::fi_hmem_iface::FI_HMEM_CUDA);
+        assert_eq!(trigger.vars().len(), 2);
+        for var in trigger.vars() {
+            assert!(var.addr().is_null());
+            assert!(var.value().is_empty());
+        }
+    }
+
+    /// GPU buffers need a domain opened with FI_HMEM.
+    #[cfg(feature = "cuda")]
+    #[test]
+    fn test_gpu_buffer() {
+        use libfabric::gpu_p2p::GpuBuffer;
+        use libfabric::xpu::XpuDevice;
+
+        let entries = tcp_hints().caps(Caps::MSG | Caps::RMA).get().unwrap();
+        let entry = &entries[0];
+        let domain = Fabric::open(entry).unwrap().domain(entry).unwrap();
+        let mut buf = [0u8; 64];
+        let registered = unsafe {
+            GpuBuffer::register(&domain, buf.as_mut_ptr(), buf.len(), XpuDevice::Cuda(0))
+        };
+        assert!(registered.err().unwrap().is_unsupported());
+    }
+
+    /// Tag fields are laid out from the low bits up, and receives match on the fields given.
+    #[test]
+    fn test_tag_space() {
+        const TAGS: TagSpace<3> = TagSpace::new([16, 8, 40]);
+        let (peer, channel, seq) = (TAGS.field(0), TAGS.field(1), TAGS.field(2));
+        assert_eq!((channel.shift(), channel.mask()), (16, 0xff << 16));
+        assert_eq!((TAGS.bits(), TAGS.mask()), (64, u64::MAX));
+
+        let tag = TAGS.tag([3, 2, 1]);
+        assert_eq!(tag, 1 << 24 | 2 << 16 | 3);
+        assert_eq!(TAGS.fields(tag), [3, 2, 1]);
+        assert_eq!(seq.set(tag, seq.max()), TAGS.tag([3, 2, (1 << 40) - 1]));
+        assert_eq!(peer.try_encode(1 << 16), None);
+        assert!(std::panic::catch_unwind(|| TAGS.tag([0, 256, 0])).is_err());
+
+        let recv = TAGS.matching().field(peer, 3);
+        assert_eq!((recv.tag(), recv.ignore()), (3, !0xffff));
+        assert!(recv.matches(tag) && !recv.matches(TAGS.tag([4, 2, 1])));
+
+        // Bits above the fields are only matched on when asked to.
+        let small = TagSpace::new([8]);
+        let recv = small.matching().field(small.field(0), 1);
+        assert!(recv.matches(1 << 63 | 1));
+        assert!(!recv.exact(&small).matches(1 << 63 | 1));
+        assert!(std::panic::catch_unwind(|| TagSpace::new([60, 8])).is_err());
+    }
+
+    /// Strided layouts are split into their blocks, merged when contiguous, and packed
+    /// instead when they have more blocks than the limit.
+    #[test]
+    fn test_strided() {
+        // A 3 x 4 matrix of u16, in bytes.
+        let matrix: Vec<u8> = (0..24).collect();
+        let column = Strided::column::<u16>(3, 4, 1);
+        assert_eq!((column.len(), column.extent(), column.blocks()), (6, 20, 3));
+        assert_eq!(
+            column.slices(&matrix).unwrap(),
+            [&[2, 3][..], &[10, 11], &[18, 19]]
+        );
+        assert_eq!(column.pack(&matrix).unwrap(), [2, 3, 10, 11, 18, 19]);
+        let rows = Strided::submatrix::<u16>(4, (1, 0), (2, 4));
+        assert_eq!(rows.slices(&matrix).unwrap(), [&matrix[8..]]);
+
+        let gather = column.gather(&matrix, 4).unwrap();
+        assert!(!gather.is_packed() && gather.bufs().len() == 3);
+        let gather = column.gather(&matrix, 2).unwrap();
+        assert_eq!(gather, Gather::Packed(vec![2, 3, 10, 11, 18, 19]));
+
+        let mut out = vec![0u8; 24];
+        let mut scatter = column.scatter(&mut out, 2).unwrap();
+        assert!(scatter.is_staged());
+        scatter.bufs()[0].copy_from_slice(&[1; 6]);
+        scatter.unpack().unwrap();
+        assert_eq!(&out[..12], [0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 1, 1]);
+        let mut scatter = column.scatter(&mut out, 3).unwrap();
+        scatter.bufs()[2].fill(7);
+        assert_eq!(out[18..20], [7, 7]);
+
+        let remote = column.rma_iov(0x1000, 9);
+        assert_eq!(
+            remote[1],
+            RmaIov {
+                addr: 0x100a,
+                len: 2,
+                key: 9
+            }
+        );
+        assert!(column.slices(&matrix[..19]).is_err());
+        assert!(Strided::new(4, 2, 2).slices(&matrix).is_err());
+    }
+}